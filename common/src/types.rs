@@ -183,8 +183,84 @@ impl PlmnId {
     }
 }
 
+impl From<Qci> for FiveQi {
+    /// Standardized QCI values 1-9 map to the 5QIs with the same value
+    fn from(qci: Qci) -> Self {
+        Self(qci.0)
+    }
+}
+
+/// 5G QoS Identifier (3GPP TS 23.501 section 5.7.2.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FiveQi(pub u8);
+
+/// QoS flow resource type (3GPP TS 23.501 section 5.7.3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QosResourceType {
+    /// Guaranteed bit rate
+    Gbr,
+    /// Non-guaranteed bit rate
+    NonGbr,
+    /// Delay-critical guaranteed bit rate
+    DelayCriticalGbr,
+}
+
+impl FiveQi {
+    /// Conversational voice
+    pub const VOICE: Self = Self(1);
+    /// Conversational video
+    pub const VIDEO: Self = Self(2);
+    /// IMS signalling
+    pub const IMS_SIGNALLING: Self = Self(5);
+    /// Default bearer
+    pub const DEFAULT: Self = Self(9);
+    
+    /// Resource type of a standardized 5QI (3GPP TS 23.501 Table 5.7.4-1),
+    /// None for non-standardized values
+    pub fn resource_type(&self) -> Option<QosResourceType> {
+        match self.0 {
+            1..=4 | 65..=67 | 71..=76 => Some(QosResourceType::Gbr),
+            5..=9 | 69 | 70 | 79 | 80 => Some(QosResourceType::NonGbr),
+            82..=90 => Some(QosResourceType::DelayCriticalGbr),
+            _ => None,
+        }
+    }
+    
+    /// Default priority level of a standardized 5QI (3GPP TS 23.501 Table 5.7.4-1)
+    pub fn default_priority_level(&self) -> Option<u8> {
+        let level = match self.0 {
+            1 => 20,
+            2 => 40,
+            3 => 30,
+            4 => 50,
+            5 => 10,
+            6 => 60,
+            7 => 70,
+            8 => 80,
+            9 => 90,
+            65 => 7,
+            66 => 20,
+            67 => 15,
+            69 => 5,
+            70 => 55,
+            71..=74 | 76 => 56,
+            75 => 25,
+            79 => 65,
+            80 => 68,
+            82 => 19,
+            83 => 22,
+            84 => 24,
+            85 => 21,
+            86 => 18,
+            87..=90 => 25,
+            _ => return None,
+        };
+        Some(level)
+    }
+}
+
 /// S-NSSAI (Single Network Slice Selection Assistance Information)
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SNssai {
     /// Slice/Service Type
     pub sst: u8,
@@ -192,6 +268,87 @@ pub struct SNssai {
     pub sd: Option<u32>,
 }
 
+/// Allocation and Retention Priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocationRetentionPriority {
+    /// Priority level (1 = highest, 15 = lowest)
+    pub priority_level: u8,
+    /// The flow may pre-empt other flows
+    pub may_trigger_pre_emption: bool,
+    /// The flow may be pre-empted by other flows
+    pub pre_emptable: bool,
+}
+
+/// QoS characteristics of a QoS flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QosCharacteristics {
+    /// Standardized or pre-configured 5QI
+    NonDynamic {
+        /// 5QI
+        five_qi: FiveQi,
+        /// Priority level overriding the 5QI default (1-127)
+        priority_level: Option<u8>,
+    },
+    /// Characteristics signalled explicitly
+    Dynamic {
+        /// 5QI, if any
+        five_qi: Option<FiveQi>,
+        /// Priority level (1-127)
+        priority_level: u8,
+        /// Packet delay budget in units of 0.5 ms
+        packet_delay_budget: u16,
+        /// Packet error rate scalar
+        per_scalar: u8,
+        /// Packet error rate exponent (PER = scalar * 10^-exponent)
+        per_exponent: u8,
+    },
+}
+
+/// Bit rates of a GBR QoS flow in bit/s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GbrQosInformation {
+    /// Maximum flow bit rate downlink
+    pub max_bit_rate_dl: u64,
+    /// Maximum flow bit rate uplink
+    pub max_bit_rate_ul: u64,
+    /// Guaranteed flow bit rate downlink
+    pub guaranteed_bit_rate_dl: u64,
+    /// Guaranteed flow bit rate uplink
+    pub guaranteed_bit_rate_ul: u64,
+}
+
+/// QoS flow descriptor (3GPP TS 23.501 section 5.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QosFlowDescriptor {
+    /// QoS flow identifier (0-63)
+    pub qfi: u8,
+    /// QoS characteristics
+    pub characteristics: QosCharacteristics,
+    /// Allocation and retention priority
+    pub arp: AllocationRetentionPriority,
+    /// GBR QoS information (GBR flows only)
+    pub gbr: Option<GbrQosInformation>,
+}
+
+impl QosFlowDescriptor {
+    /// 5QI of the flow, if known
+    pub fn five_qi(&self) -> Option<FiveQi> {
+        match self.characteristics {
+            QosCharacteristics::NonDynamic { five_qi, .. } => Some(five_qi),
+            QosCharacteristics::Dynamic { five_qi, .. } => five_qi,
+        }
+    }
+}
+
+/// Aggregate maximum bit rate (UE-AMBR or Session-AMBR) in bit/s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AggregateMaximumBitRate {
+    /// Downlink
+    pub dl: u64,
+    /// Uplink
+    pub ul: u64,
+}
+
 /// Cell configuration
 #[derive(Debug, Clone)]
 pub struct CellConfig {
@@ -252,4 +409,14 @@ mod tests {
         let encoded = plmn.encode();
         assert_eq!(encoded, [0x02, 0xF8, 0x39]);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_five_qi() {
+        assert_eq!(FiveQi::from(Qci::DEFAULT), FiveQi::DEFAULT);
+        assert_eq!(FiveQi::DEFAULT.resource_type(), Some(QosResourceType::NonGbr));
+        assert_eq!(FiveQi::VOICE.resource_type(), Some(QosResourceType::Gbr));
+        assert_eq!(FiveQi(82).resource_type(), Some(QosResourceType::DelayCriticalGbr));
+        assert_eq!(FiveQi::IMS_SIGNALLING.default_priority_level(), Some(10));
+        assert_eq!(FiveQi(200).resource_type(), None);
+    }
+}
@@ -2,7 +2,7 @@
 //! 
 //! These structures EXACTLY match the sacred gnb_albor.yml format
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
 /// Main configuration structure matching srsRAN YAML format
@@ -22,23 +22,102 @@ pub struct GnbConfig {
     /// PCAP configuration
     #[serde(default)]
     pub pcap: PcapConfig,
+    /// F1 split configuration
+    #[serde(default)]
+    pub f1: F1Config,
+    /// E1 split configuration
+    #[serde(default)]
+    pub e1: E1Config,
+    /// Xn configuration
+    #[serde(default)]
+    pub xn: XnConfig,
+    /// E2 configuration
+    #[serde(default)]
+    pub e2: E2Config,
+    /// FAPI configuration
+    #[serde(default)]
+    pub fapi: FapiConfig,
 }
 
 /// CU-CP (Control Plane) configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CuCpConfig {
-    /// AMF configuration
-    pub amf: AmfConfig,
+    /// AMF configuration: a single AMF, or a list with one NG association per AMF
+    #[serde(deserialize_with = "one_or_many")]
+    pub amf: Vec<AmfConfig>,
     /// Inactivity timer in seconds
     #[serde(default = "default_inactivity_timer")]
     pub inactivity_timer: u32,
+    /// Maximum number of UE contexts, further RRC Setup Requests are rejected
+    #[serde(default = "default_max_ue_contexts")]
+    pub max_ue_contexts: u16,
+    /// Time to wait for the AMF to answer a UE Context Release Request before
+    /// releasing the UE locally, in seconds
+    #[serde(default = "default_release_request_guard_time")]
+    pub release_request_guard_time: u32,
+    /// Suspend inactive UEs to RRC_INACTIVE instead of releasing them
+    #[serde(default)]
+    pub rrc_inactive_enabled: bool,
+    /// RAN paging cycle in radio frames (32, 64, 128 or 256)
+    #[serde(default = "default_ran_paging_cycle")]
+    pub ran_paging_cycle: u16,
+    /// Neighbour cells without Xn, handed over to through the AMF
+    #[serde(default)]
+    pub n2_neighbours: Vec<N2NeighbourConfig>,
+}
+
+/// Neighbour cell reached by N2 handover
+///
+/// As for the served cell, the gNB ID of the neighbour is its PCI.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct N2NeighbourConfig {
+    /// Physical cell ID of the neighbour cell
+    pub pci: u16,
+    /// Tracking area code of the neighbour cell, the served one if not given
+    #[serde(default)]
+    pub tac: Option<u32>,
+}
+
+/// Accept either a single value or a list
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
 }
 
 fn default_inactivity_timer() -> u32 {
     7200
 }
 
+fn default_max_ue_contexts() -> u16 {
+    100
+}
+
+fn default_release_request_guard_time() -> u32 {
+    10
+}
+
+fn default_ran_paging_cycle() -> u16 {
+    128
+}
+
 /// AMF configuration
+///
+/// With several AMFs, the bind address and transport settings of the first
+/// entry apply to all associations, and the tracking areas of all entries are
+/// merged into the NG Setup Request.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AmfConfig {
     /// AMF address
@@ -46,9 +125,42 @@ pub struct AmfConfig {
     /// AMF port
     pub port: u16,
     /// Bind address for gNodeB
+    #[serde(default = "default_amf_bind_addr")]
     pub bind_addr: String,
     /// Supported tracking areas
+    #[serde(default)]
     pub supported_tracking_areas: Vec<TrackingAreaConfig>,
+    /// NG-C transport: "sctp", or "tcp" for length-prefixed frames over TCP
+    #[serde(default = "default_amf_transport")]
+    pub transport: String,
+    /// Number of SCTP streams requested from the AMF
+    #[serde(default = "default_sctp_streams")]
+    pub sctp_streams: u16,
+    /// Additional AMF addresses for SCTP multi-homing
+    #[serde(default)]
+    pub extra_addrs: Vec<String>,
+    /// Additional local addresses for SCTP multi-homing
+    #[serde(default)]
+    pub bind_extra_addrs: Vec<String>,
+    /// Delay between attempts to re-establish a lost NG association in seconds
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+}
+
+fn default_amf_bind_addr() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_amf_transport() -> String {
+    "sctp".to_string()
+}
+
+fn default_sctp_streams() -> u16 {
+    4
+}
+
+fn default_reconnect_interval() -> u64 {
+    5
 }
 
 /// Tracking area configuration
@@ -91,7 +203,7 @@ pub struct CuUpConfig {
 /// RU SDR configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RuSdrConfig {
-    /// Device driver type
+    /// RU device driver: zmq for the ZMQ RF driver, oran_fh for an O-RU over the O-RAN 7.2x fronthaul
     pub device_driver: String,
     /// Device arguments
     pub device_args: String,
@@ -268,6 +380,246 @@ pub struct PcapConfig {
     pub ngap_filename: Option<String>,
 }
 
+/// F1 configuration: which half of the gNB this process runs and how the
+/// gNB-CU and gNB-DU reach each other
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct F1Config {
+    /// Node role: monolithic, cu or du
+    #[serde(default = "default_f1_mode")]
+    pub mode: String,
+    /// gNB-CU F1-C address, the address the DU connects to
+    #[serde(default = "default_loopback_addr")]
+    pub cu_addr: String,
+    /// gNB-CU F1-C port
+    #[serde(default = "default_f1_port")]
+    pub cu_port: u16,
+    /// Local F1-C address: listening address of the CU, source address of the DU
+    #[serde(default = "default_loopback_addr")]
+    pub bind_addr: String,
+    /// F1-C transport: sctp or tcp (length-prefixed frames, for testing)
+    #[serde(default = "default_transport")]
+    pub transport: String,
+    /// Local F1-U GTP-U address
+    #[serde(default = "default_loopback_addr")]
+    pub f1u_bind_addr: String,
+    /// gNB-DU ID announced in F1 Setup
+    #[serde(default = "default_gnb_du_id")]
+    pub gnb_du_id: u64,
+    /// Delay in seconds before the DU retries the association with the CU
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+}
+
+impl Default for F1Config {
+    fn default() -> Self {
+        Self {
+            mode: default_f1_mode(),
+            cu_addr: default_loopback_addr(),
+            cu_port: default_f1_port(),
+            bind_addr: default_loopback_addr(),
+            transport: default_transport(),
+            f1u_bind_addr: default_loopback_addr(),
+            gnb_du_id: default_gnb_du_id(),
+            reconnect_interval: default_reconnect_interval(),
+        }
+    }
+}
+
+fn default_f1_mode() -> String {
+    "monolithic".to_string()
+}
+
+fn default_loopback_addr() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_f1_port() -> u16 {
+    38472
+}
+
+fn default_transport() -> String {
+    "sctp".to_string()
+}
+
+fn default_gnb_du_id() -> u64 {
+    1
+}
+
+/// E1 split configuration: gNB-CU-CP with RRC and NGAP, gNB-CU-UP with PDCP,
+/// SDAP and GTP-U
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct E1Config {
+    /// Node role within the gNB-CU: combined, cu_cp or cu_up
+    #[serde(default = "default_e1_mode")]
+    pub mode: String,
+    /// gNB-CU-CP E1 address, the address the CU-UP connects to
+    #[serde(default = "default_loopback_addr")]
+    pub cu_cp_addr: String,
+    /// gNB-CU-CP E1 port
+    #[serde(default = "default_e1_port")]
+    pub cu_cp_port: u16,
+    /// Local E1 address: listening address of the CU-CP, source address of the CU-UP
+    #[serde(default = "default_loopback_addr")]
+    pub bind_addr: String,
+    /// E1 transport: sctp or tcp (length-prefixed frames, for testing)
+    #[serde(default = "default_transport")]
+    pub transport: String,
+    /// gNB-CU-UP ID announced in GNB-CU-UP E1 Setup
+    #[serde(default = "default_gnb_cu_up_id")]
+    pub gnb_cu_up_id: u64,
+    /// Delay in seconds before the CU-UP retries the association with the CU-CP
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+}
+
+impl Default for E1Config {
+    fn default() -> Self {
+        Self {
+            mode: default_e1_mode(),
+            cu_cp_addr: default_loopback_addr(),
+            cu_cp_port: default_e1_port(),
+            bind_addr: default_loopback_addr(),
+            transport: default_transport(),
+            gnb_cu_up_id: default_gnb_cu_up_id(),
+            reconnect_interval: default_reconnect_interval(),
+        }
+    }
+}
+
+fn default_e1_mode() -> String {
+    "combined".to_string()
+}
+
+fn default_e1_port() -> u16 {
+    38462
+}
+
+fn default_gnb_cu_up_id() -> u64 {
+    1
+}
+
+/// Xn configuration: associations with neighbouring gNBs for handover
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct XnConfig {
+    /// Whether XnAP runs
+    #[serde(default)]
+    pub enabled: bool,
+    /// Local Xn address: listening address and source address of the associations
+    #[serde(default = "default_loopback_addr")]
+    pub bind_addr: String,
+    /// Xn port
+    #[serde(default = "default_xn_port")]
+    pub port: u16,
+    /// Xn transport: sctp or tcp (length-prefixed frames, for testing)
+    #[serde(default = "default_transport")]
+    pub transport: String,
+    /// Neighbour gNBs to connect to, as address:port
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Delay in seconds before an association with a peer is retried
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+}
+
+impl Default for XnConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_addr: default_loopback_addr(),
+            port: default_xn_port(),
+            transport: default_transport(),
+            peers: Vec::new(),
+            reconnect_interval: default_reconnect_interval(),
+        }
+    }
+}
+
+fn default_xn_port() -> u16 {
+    38422
+}
+
+/// E2 configuration: association with a near-RT RIC
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct E2Config {
+    /// Whether the E2 agent runs
+    #[serde(default)]
+    pub enabled: bool,
+    /// Near-RT RIC address
+    #[serde(default = "default_loopback_addr")]
+    pub ric_addr: String,
+    /// Near-RT RIC E2 port
+    #[serde(default = "default_e2_port")]
+    pub ric_port: u16,
+    /// Local address of the association
+    #[serde(default = "default_loopback_addr")]
+    pub bind_addr: String,
+    /// E2 transport: sctp or tcp (length-prefixed frames, for testing)
+    #[serde(default = "default_transport")]
+    pub transport: String,
+    /// Delay in seconds before the association is retried
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+    /// Whether the RIC may steer the gNB through E2SM-RC controls
+    #[serde(default)]
+    pub control_enabled: bool,
+}
+
+impl Default for E2Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ric_addr: default_loopback_addr(),
+            ric_port: default_e2_port(),
+            bind_addr: default_loopback_addr(),
+            transport: default_transport(),
+            reconnect_interval: default_reconnect_interval(),
+            control_enabled: false,
+        }
+    }
+}
+
+fn default_e2_port() -> u16 {
+    36421
+}
+
+/// FAPI configuration: how the MAC reaches the L1
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FapiConfig {
+    /// MAC-L1 interface: none for the PHY called by the MAC directly, in_process
+    /// for the PHY behind FAPI in this process, socket for an L1 in another
+    /// process or on another host
+    #[serde(default = "default_fapi_mode")]
+    pub mode: String,
+    /// Address the L1 listens for the MAC on in socket mode, as address:port
+    #[serde(default = "default_fapi_l1_addr")]
+    pub l1_addr: String,
+    /// Time in microseconds the PHY waits for the requests of a slot in in_process mode
+    #[serde(default = "default_fapi_slot_timeout")]
+    pub slot_timeout_us: u64,
+}
+
+impl Default for FapiConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_fapi_mode(),
+            l1_addr: default_fapi_l1_addr(),
+            slot_timeout_us: default_fapi_slot_timeout(),
+        }
+    }
+}
+
+fn default_fapi_mode() -> String {
+    "none".to_string()
+}
+
+fn default_fapi_l1_addr() -> String {
+    "127.0.0.1:50001".to_string()
+}
+
+fn default_fapi_slot_timeout() -> u64 {
+    500
+}
+
 impl GnbConfig {
     /// Load configuration from YAML file
     pub fn from_yaml_file(path: &str) -> anyhow::Result<Self> {
@@ -305,4 +657,16 @@ mod tests {
         assert_eq!(mcc, 310);
         assert_eq!(mnc, 260);
     }
+    
+    #[test]
+    fn test_cu_cp_defaults() {
+        let amf = "amf:\n  addr: 127.0.0.5\n  port: 38412\n";
+        let cu_cp: CuCpConfig = serde_yaml::from_str(amf).unwrap();
+        assert_eq!(cu_cp.max_ue_contexts, 100);
+        assert_eq!(cu_cp.inactivity_timer, 7200);
+        assert_eq!(cu_cp.release_request_guard_time, 10);
+        
+        let cu_cp: CuCpConfig = serde_yaml::from_str(&format!("{}max_ue_contexts: 8\n", amf)).unwrap();
+        assert_eq!(cu_cp.max_ue_contexts, 8);
+    }
 }
\ No newline at end of file
@@ -9,18 +9,32 @@ use tracing_subscriber::{EnvFilter, fmt};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use common::types::{Pci, CellId, Bandwidth, SubcarrierSpacing};
-use interfaces::zmq_rf::ZmqRfConfig;
-use layers::phy::{EnhancedPhyLayer, PhyConfig, CyclicPrefix, DuplexMode};
+use common::types::{Pci, CellId, Bandwidth, SubcarrierSpacing, SNssai};
+use layers::phy::{EnhancedPhyLayer, PhyConfig, CyclicPrefix, DuplexMode, RuConfig};
 use layers::mac::{EnhancedMacLayer, MacConfig, default_sib1_config};
-use layers::rrc::{RrcLayer, RrcConfig, RrcMacInterface};
+use layers::rrc::{RrcLayer, RrcConfig, RrcCuUpInterface, RrcMacInterface, default_meas_config};
 use layers::ngap::{NgapLayer, NgapConfig};
+use layers::ngap::amf::AmfEndpoint;
+use layers::ngap::association::run_ng_association;
+use layers::ngap::handover::HandoverTarget;
+use layers::ngap::transport::{NgTransportConfig, NgTransportKind};
+use layers::ngap::pdu::{BroadcastPlmnItem, PagingDrx, SupportedTaItem};
+use layers::gtpu::{GtpuConfig, GtpuLayer, run_gtpu_endpoint};
+use layers::gtpu::pdu::GTPU_PORT;
+use layers::f1ap::{run_f1_cu, run_f1_du, F1apCu, F1apCuConfig, F1apDu, F1apDuConfig};
+use layers::f1ap::pdu::{FddInfo, ServedCellInformation};
+use layers::e1ap::{run_e1_cu_cp, run_e1_cu_up, E1apCuCp, E1apCuCpConfig, E1apCuUp, E1apCuUpConfig};
+use layers::ngap::pdu::{GlobalGnbId, NrCgi};
+use layers::xnap::{run_xnap, XnapConfig, XnapNode};
+use layers::xnap::pdu::ServedCellNr;
+use layers::e2ap::{run_e2_agent, E2Agent, E2AgentConfig, StackKpmSource, StackRcControl};
+use layers::fapi::{self, ConfigRequest, FapiL1, FapiL2, FrameDuplexType};
 use layers::ProtocolLayer;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
 mod config;
-use config::GnbConfig;
+use config::{AmfConfig, GnbConfig};
 
 /// Albor Space 5G GNodeB
 #[derive(Parser, Debug)]
@@ -36,13 +50,48 @@ struct Args {
 }
 
 
-/// GNodeB application state
+/// Halves of the gNB run by this process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeMode {
+    /// Every layer in one process
+    Monolithic,
+    /// gNB-CU: RRC, NGAP and GTP-U, reaching the MAC over F1
+    Cu,
+    /// gNB-DU: PHY and MAC, reaching RRC over F1
+    Du,
+    /// gNB-CU-CP: RRC and NGAP, reaching the MAC over F1 and the user plane over E1
+    CuCp,
+    /// gNB-CU-UP: PDCP, SDAP and GTP-U under a gNB-CU-CP
+    CuUp,
+}
+
+/// How the MAC reaches the L1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FapiMode {
+    /// The MAC is called by the PHY directly
+    None,
+    /// The PHY of this process behind FAPI, over in-process channels
+    InProcess,
+    /// An L1 in another process or on another host, over a TCP socket
+    Socket,
+}
+
+/// Capacity of the in-process FAPI channels, in messages
+const FAPI_CHANNEL_CAPACITY: usize = 64;
+
+/// GNodeB application state, with the layers of the halves this process runs
 struct GnbState {
-    phy_layer: Arc<RwLock<EnhancedPhyLayer>>,
-    mac_layer: Arc<EnhancedMacLayer>,
-    rrc_layer: Arc<RwLock<RrcLayer>>,
-    ngap_layer: Arc<RwLock<NgapLayer>>,
-    running: Arc<RwLock<bool>>,
+    phy_layer: Option<Arc<RwLock<EnhancedPhyLayer>>>,
+    fapi_l2: Option<Arc<FapiL2>>,
+    rrc_layer: Option<Arc<RwLock<RrcLayer>>>,
+    ngap_layer: Option<Arc<RwLock<NgapLayer>>>,
+    gtpu_layer: Option<Arc<RwLock<GtpuLayer>>>,
+    f1_cu: Option<Arc<F1apCu>>,
+    f1_du: Option<Arc<F1apDu>>,
+    e1_cu_cp: Option<Arc<E1apCuCp>>,
+    e1_cu_up: Option<Arc<E1apCuUp>>,
+    xnap_node: Option<Arc<XnapNode>>,
+    e2_agent: Option<Arc<E2Agent>>,
 }
 
 #[tokio::main]
@@ -126,30 +175,16 @@ async fn main() -> Result<()> {
     
     // AMF configuration
     info!("AMF configuration:");
-    info!("  Address: {}:{}", config.cu_cp.amf.addr, config.cu_cp.amf.port);
-    info!("  Bind address: {}", config.cu_cp.amf.bind_addr);
-    
-    // Parse PLMN from config (format: "00101" -> [0x00, 0xF1, 0x10])
-    let plmn_str = &config.cell_cfg.plmn;
-    if plmn_str.len() != 5 && plmn_str.len() != 6 {
-        return Err(anyhow::anyhow!("Invalid PLMN format: {}", plmn_str));
+    let Some(first_amf) = config.cu_cp.amf.first() else {
+        return Err(anyhow::anyhow!("At least one AMF must be configured in cu_cp.amf"));
+    };
+    for amf in &config.cu_cp.amf {
+        info!("  Address: {}:{}", amf.addr, amf.port);
     }
+    info!("  Bind address: {}", first_amf.bind_addr);
     
-    let mcc = &plmn_str[0..3];
-    let mnc = &plmn_str[3..];
-    
-    // Convert to BCD format for NGAP
-    let mut plmn_id = [0u8; 3];
-    plmn_id[0] = ((mcc.chars().nth(1).unwrap().to_digit(10).unwrap() as u8) << 4) | 
-                  (mcc.chars().nth(0).unwrap().to_digit(10).unwrap() as u8);
-    plmn_id[1] = ((mnc.chars().nth(0).unwrap().to_digit(10).unwrap() as u8) << 4) | 
-                  (mcc.chars().nth(2).unwrap().to_digit(10).unwrap() as u8);
-    if mnc.len() == 2 {
-        plmn_id[2] = 0xF0 | (mnc.chars().nth(1).unwrap().to_digit(10).unwrap() as u8);
-    } else {
-        plmn_id[2] = ((mnc.chars().nth(2).unwrap().to_digit(10).unwrap() as u8) << 4) | 
-                      (mnc.chars().nth(1).unwrap().to_digit(10).unwrap() as u8);
-    }
+    // Parse PLMN from config (format: "00101" -> [0x00, 0xF1, 0x10])
+    let plmn_id = parse_plmn(&config.cell_cfg.plmn)?;
     
     // Create PRACH configuration from config file
     let prach_config = layers::phy::prach::RachConfigCommon {
@@ -183,10 +218,12 @@ async fn main() -> Result<()> {
         prach_config,
     };
     
-    // Create ZMQ RF configuration from device args in config
-    let mut zmq_config = ZmqRfConfig::from_device_args(&config.ru_sdr.device_args, 1)?;
-    zmq_config.tx_gain = config.ru_sdr.tx_gain;
-    zmq_config.rx_gain = config.ru_sdr.rx_gain;
+    // Create the RU configuration of the device driver from device args in config
+    let mut ru_config = RuConfig::from_device_driver(&config.ru_sdr.device_driver, &config.ru_sdr.device_args)?;
+    if let RuConfig::Zmq(zmq_config) = &mut ru_config {
+        zmq_config.tx_gain = config.ru_sdr.tx_gain;
+        zmq_config.rx_gain = config.ru_sdr.rx_gain;
+    }
     
     // Override sample rate with natural FFT-based sample rate
     // This ensures perfect alignment with OFDM processing
@@ -202,12 +239,49 @@ async fn main() -> Result<()> {
     // REMOVED: Sample rate override that was preventing resampler from working
     // zmq_config.sample_rate = natural_sample_rate;
     
-    info!("ZMQ configuration:");
-    info!("  TX address: {}", zmq_config.tx_address);
-    info!("  RX address: {}", zmq_config.rx_address);
-    info!("  Sample rate: {} MHz (from config)", zmq_config.sample_rate / 1e6);
-    info!("  PHY natural rate: {} MHz (FFT size {} × SCS {} kHz)", 
-          natural_sample_rate / 1e6, fft_size, scs_hz / 1000.0);
+    match &ru_config {
+        RuConfig::Zmq(zmq_config) => {
+            info!("ZMQ configuration:");
+            info!("  TX address: {}", zmq_config.tx_address);
+            info!("  RX address: {}", zmq_config.rx_address);
+            info!("  Sample rate: {} MHz (from config)", zmq_config.sample_rate / 1e6);
+            info!("  PHY natural rate: {} MHz (FFT size {} × SCS {} kHz)", 
+                  natural_sample_rate / 1e6, fft_size, scs_hz / 1000.0);
+        }
+        RuConfig::OranFh(fh_config) => {
+            info!("O-RAN 7.2x fronthaul configuration:");
+            info!("  Local address: {}", fh_config.local_address);
+            info!("  O-RU address: {}", fh_config.ru_address);
+            info!("  Compression: {:?}", fh_config.compression);
+        }
+    }
+
+    // F1 split: the gNB-DU runs PHY and MAC, the gNB-CU runs RRC, NGAP and GTP-U.
+    // E1 split of the gNB-CU: the CU-CP runs RRC and NGAP, the CU-UP GTP-U
+    let node_mode = match (config.f1.mode.as_str(), config.e1.mode.as_str()) {
+        ("monolithic", "combined") => NodeMode::Monolithic,
+        ("cu", "combined") => NodeMode::Cu,
+        ("du", "combined") => NodeMode::Du,
+        ("cu", "cu_cp") => NodeMode::CuCp,
+        (_, "cu_up") => NodeMode::CuUp,
+        (_, "cu_cp") => return Err(anyhow::anyhow!("E1 mode cu_cp needs F1 mode cu")),
+        (other, "combined") => return Err(anyhow::anyhow!("Invalid F1 mode {}, expected monolithic, cu or du", other)),
+        (_, other) => return Err(anyhow::anyhow!("Invalid E1 mode {}, expected combined, cu_cp or cu_up", other)),
+    };
+    info!("Node mode: {:?}", node_mode);
+    
+    let fapi_mode = match config.fapi.mode.as_str() {
+        "none" => FapiMode::None,
+        "in_process" => FapiMode::InProcess,
+        "socket" => FapiMode::Socket,
+        other => return Err(anyhow::anyhow!("Invalid FAPI mode {}, expected none, in_process or socket", other)),
+    };
+    info!("FAPI mode: {:?}", fapi_mode);
+    
+    // NR cell identity: gNB ID followed by the local cell ID (first and only cell)
+    let gnb_id = config.cell_cfg.pci as u32; // Using PCI as gNB ID for now
+    let gnb_id_bits = 22;
+    let nr_cell_identity = ((gnb_id as u64) << (36 - gnb_id_bits)) | 1;
 
     // Create MAC configuration
     let mac_config = MacConfig {
@@ -215,124 +289,685 @@ async fn main() -> Result<()> {
         scs,
         bandwidth,
         max_ues: 32,
-        sib1_config: default_sib1_config(cell_id),
+        sib1_config: default_sib1_config(nr_cell_identity),
         coreset0_index: config.cell_cfg.pdcch.common.coreset0_index,
     };
     
-    // Initialize MAC layer
-    let mut mac_layer = EnhancedMacLayer::new(mac_config.clone())?;
+    // Create channels between MAC (or the F1 endpoint standing in for it) and RRC
+    use bytes::Bytes;
+    let (mac_to_rrc_tx, mut mac_to_rrc_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, Bytes)>(100);
+    let (rrc_to_mac_tx, mut rrc_to_mac_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, layers::rrc::RrcMessageType, Bytes)>(100);
+    let (f1u_to_rrc_tx, mut f1u_to_rrc_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, u8, Bytes)>(1000);
+    let (mac_user_data_tx, mut mac_user_data_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, u8, Bytes)>(1000);
     
-    // Create RRC configuration
-    let rrc_config = RrcConfig {
-        sib_periodicity: 160,
-        max_ue_contexts: 100,
-        cell_id,
-        plmn_id,
-        tac: config.cell_cfg.tac,
+    let (rrc_to_ngap_tx, mut rrc_to_ngap_rx) = tokio::sync::mpsc::channel::<layers::rrc::RrcNgapMessage>(100);
+    let (ngap_to_rrc_tx, mut ngap_to_rrc_rx) = tokio::sync::mpsc::channel::<layers::rrc::NgapRrcMessage>(100);
+    
+    let (rrc_to_gtpu_tx, mut rrc_to_gtpu_rx) = tokio::sync::mpsc::channel::<layers::rrc::RrcGtpuMessage>(1000);
+    let (gtpu_to_rrc_tx, mut gtpu_to_rrc_rx) = tokio::sync::mpsc::channel::<layers::rrc::GtpuRrcMessage>(1000);
+    let (ngap_to_gtpu_tx, mut ngap_to_gtpu_rx) = tokio::sync::mpsc::channel::<layers::gtpu::NgapGtpuMessage>(100);
+    let (ngap_to_xnap_tx, ngap_to_xnap_rx) = tokio::sync::mpsc::channel::<layers::xnap::NgapXnapMessage>(100);
+    let (xnap_to_ngap_tx, mut xnap_to_ngap_rx) = tokio::sync::mpsc::channel::<layers::xnap::XnapNgapMessage>(100);
+    
+    // Initialize MAC and PHY layers (gNB-DU), the PHY behind FAPI or replaced
+    // by an L1 reached over FAPI unless the PHY calls the MAC directly
+    let mut fapi_l1 = None;
+    let mut fapi_l2 = None;
+    let (mac_layer, phy_layer) = if !matches!(node_mode, NodeMode::Monolithic | NodeMode::Du) {
+        (None, None)
+    } else {
+        let mut mac_layer = EnhancedMacLayer::new(mac_config.clone())?;
+        
+        // Set channel before creating Arc
+        mac_layer.set_rrc_channel(mac_to_rrc_tx.clone());
+        mac_layer.set_user_data_channel(mac_user_data_tx);
+        mac_layer.initialize().await?;
+        info!("MAC layer initialized");
+        let mac_layer = Arc::new(mac_layer);
+        let mac_interface: Arc<dyn layers::mac::MacPhyInterface> = mac_layer.clone() as Arc<dyn layers::mac::MacPhyInterface>;
+        
+        let l1_interface = match fapi_mode {
+            FapiMode::None => Some(mac_interface),
+            FapiMode::InProcess => {
+                let (mac_end, (l1_tx, l1_rx)) = fapi::transport::in_process(FAPI_CHANNEL_CAPACITY);
+                let slot_timeout = std::time::Duration::from_micros(config.fapi.slot_timeout_us);
+                let l1 = Arc::new(FapiL1::new(l1_tx, slot_timeout));
+                fapi_l1 = Some((l1.clone(), l1_rx));
+                fapi_l2 = Some((mac_interface, mac_end));
+                Some(l1 as Arc<dyn layers::mac::MacPhyInterface>)
+            }
+            FapiMode::Socket => {
+                let l1_address = SocketAddr::from_str(&config.fapi.l1_addr)
+                    .map_err(|e| anyhow::anyhow!("Invalid FAPI L1 address {}: {}", config.fapi.l1_addr, e))?;
+                fapi_l2 = Some((mac_interface, fapi::transport::connect(l1_address).await?));
+                None
+            }
+        };
+        
+        // Initialize PHY layer, unless the L1 runs elsewhere
+        let phy_layer = match l1_interface {
+            Some(l1_interface) => {
+                let mut enhanced_phy = EnhancedPhyLayer::new(phy_config)?;
+                enhanced_phy.set_mac_interface(l1_interface);
+                enhanced_phy.initialize_with_ru(ru_config).await?;
+                info!("Enhanced PHY layer initialized (full mode)");
+                Some(Arc::new(RwLock::new(enhanced_phy)))
+            }
+            None => None,
+        };
+        (Some(mac_layer), phy_layer)
     };
     
-    // Initialize RRC layer
-    let mut rrc_layer = RrcLayer::new(rrc_config);
+    // Configuration of the L1 driven over FAPI
+    let fapi_l2 = match fapi_l2 {
+        Some((mac_interface, (mac_tx, mac_rx))) => {
+            let l1_config = fapi_config_request(&config, carrier_frequency, k_ssb)?;
+            Some((Arc::new(FapiL2::new(mac_interface, l1_config, mac_tx)), mac_rx))
+        }
+        None => None,
+    };
     
-    // Create channels between MAC and RRC
-    use bytes::Bytes;
-    let (mac_to_rrc_tx, mut mac_to_rrc_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, Bytes)>(100);
-    let (rrc_to_mac_tx, mut rrc_to_mac_rx) = tokio::sync::mpsc::channel::<(common::types::Rnti, layers::rrc::RrcMessageType, Bytes)>(100);
+    // Initialize the F1 endpoint of a split gNB
+    let f1_transport = match config.f1.transport.as_str() {
+        "sctp" => NgTransportKind::Sctp,
+        "tcp" => NgTransportKind::TcpFramed,
+        other => return Err(anyhow::anyhow!("Invalid F1 transport {}, expected sctp or tcp", other)),
+    };
+    let f1_cu_address = SocketAddr::from_str(&format!("{}:{}", config.f1.cu_addr, config.f1.cu_port))
+        .map_err(|e| anyhow::anyhow!("Invalid gNB-CU F1 address: {}", e))?;
+    let f1_bind_address = IpAddr::from_str(&config.f1.bind_addr)
+        .map_err(|e| anyhow::anyhow!("Invalid F1 bind address {}: {}", config.f1.bind_addr, e))?;
+    let f1u_address = IpAddr::from_str(&config.f1.f1u_bind_addr)
+        .map_err(|e| anyhow::anyhow!("Invalid F1-U bind address {}: {}", config.f1.f1u_bind_addr, e))?;
     
-    // Set channel before creating Arc
-    mac_layer.set_rrc_channel(mac_to_rrc_tx);
-    
-    // Now initialize and create Arc
-    mac_layer.initialize().await?;
-    info!("MAC layer initialized");
-    let mac_layer = Arc::new(mac_layer);
-    
-    // Set MAC interface for RRC
-    let mac_interface: Arc<dyn RrcMacInterface> = mac_layer.clone() as Arc<dyn RrcMacInterface>;
-    rrc_layer.set_mac_interface(mac_interface);
-    
-    rrc_layer.initialize().await
-        .map_err(|e| anyhow::anyhow!("Failed to initialize RRC layer: {}", e))?;
-    info!("RRC layer initialized");
-    let rrc_layer = Arc::new(RwLock::new(rrc_layer));
-    
-    // Initialize PHY layer
-    let mut enhanced_phy = EnhancedPhyLayer::new(phy_config)?;
-    
-    // Set MAC interface for PHY - cast to trait object
-    let mac_interface: Arc<dyn layers::mac::MacPhyInterface> = mac_layer.clone() as Arc<dyn layers::mac::MacPhyInterface>;
-    enhanced_phy.set_mac_interface(mac_interface);
-    
-    enhanced_phy.initialize_with_rf(zmq_config).await?;
-    info!("Enhanced PHY layer initialized (full mode)");
-    let phy_layer = Arc::new(RwLock::new(enhanced_phy));
-    
-    // Create NGAP configuration
-    let amf_addr = format!("{}:{}", config.cu_cp.amf.addr, config.cu_cp.amf.port);
-    // Use AMF address from configuration
-    let ngap_config = NgapConfig {
-        amf_address: SocketAddr::from_str(&amf_addr)
-            .map_err(|e| anyhow::anyhow!("Invalid AMF address {}: {}", amf_addr, e))?,
-        local_address: SocketAddr::from_str(&format!("{}:0", config.cu_cp.amf.bind_addr))
-            .map_err(|e| anyhow::anyhow!("Invalid bind address: {}", e))?,
-        gnb_id: config.cell_cfg.pci as u32, // Using PCI as gNB ID for now
-        plmn_id,
+    // Initialize the E1 endpoint of a split gNB-CU
+    let e1_transport = match config.e1.transport.as_str() {
+        "sctp" => NgTransportKind::Sctp,
+        "tcp" => NgTransportKind::TcpFramed,
+        other => return Err(anyhow::anyhow!("Invalid E1 transport {}, expected sctp or tcp", other)),
+    };
+    let e1_cu_cp_address = SocketAddr::from_str(&format!("{}:{}", config.e1.cu_cp_addr, config.e1.cu_cp_port))
+        .map_err(|e| anyhow::anyhow!("Invalid gNB-CU-CP E1 address: {}", e))?;
+    let e1_bind_address = IpAddr::from_str(&config.e1.bind_addr)
+        .map_err(|e| anyhow::anyhow!("Invalid E1 bind address {}: {}", config.e1.bind_addr, e))?;
+    let gtpu_address = IpAddr::from_str(&config.cu_up.gtpu_ext_addr)
+        .map_err(|e| anyhow::anyhow!("Invalid GTP-U address {}: {}", config.cu_up.gtpu_ext_addr, e))?;
+    let gtpu_bind_address = IpAddr::from_str(&config.cu_up.gtpu_bind_addr)
+        .map_err(|e| anyhow::anyhow!("Invalid GTP-U bind address {}: {}", config.cu_up.gtpu_bind_addr, e))?;
+
+    let e1_cu_cp = if node_mode == NodeMode::CuCp {
+        let e1_cu_cp = E1apCuCp::new(E1apCuCpConfig {
+            bind_address: SocketAddr::new(e1_bind_address, config.e1.cu_cp_port),
+            transport: e1_transport,
+            gnb_cu_cp_name: "Albor-gNB-CU-CP".to_string(),
+            ng_u_address: gtpu_address,
+        }).await.map_err(|e| anyhow::anyhow!("Failed to initialize E1 gNB-CU-CP endpoint: {}", e))?;
+        Some(Arc::new(e1_cu_cp))
+    } else {
+        None
+    };
+
+    let f1_cu = if matches!(node_mode, NodeMode::Cu | NodeMode::CuCp) {
+        let mut f1_cu = F1apCu::new(F1apCuConfig {
+            bind_address: SocketAddr::new(f1_bind_address, config.f1.cu_port),
+            transport: f1_transport,
+            f1u_address: SocketAddr::new(f1u_address, GTPU_PORT),
+            gnb_cu_name: "Albor-gNB-CU".to_string(),
+        }).await.map_err(|e| anyhow::anyhow!("Failed to initialize F1 gNB-CU endpoint: {}", e))?;
+        f1_cu.set_rrc_channel(mac_to_rrc_tx.clone());
+        f1_cu.set_user_data_channel(f1u_to_rrc_tx);
+        if let Some(e1_cu_cp) = &e1_cu_cp {
+            f1_cu.set_cu_up_interface(e1_cu_cp.clone() as Arc<dyn RrcCuUpInterface>);
+        }
+        Some(Arc::new(f1_cu))
+    } else {
+        None
     };
     
-    // Initialize NGAP layer
-    let mut ngap_layer = NgapLayer::new(ngap_config);
-    match ngap_layer.initialize().await {
-        Ok(_) => info!("NGAP layer initialized and connected to AMF"),
-        Err(e) => {
-            warn!("Failed to initialize NGAP layer: {}. Continuing without AMF connection.", e);
-            warn!("Cell will broadcast but no core network connectivity.");
+    let f1_du = match (&mac_layer, node_mode) {
+        (Some(mac_layer), NodeMode::Du) => {
+            let nrb = transmission_bandwidth_nrb(config.cell_cfg.channel_bandwidth_mhz, config.cell_cfg.common_scs)?;
+            let served_cell = ServedCellInformation {
+                nr_cgi: NrCgi { plmn_id, nr_cell_identity },
+                nr_pci: config.cell_cfg.pci,
+                tac: Some(config.cell_cfg.tac),
+                served_plmns: vec![plmn_id],
+                fdd_info: FddInfo {
+                    ul_arfcn: calculate_ul_arfcn(config.cell_cfg.dl_arfcn, config.cell_cfg.band)?,
+                    dl_arfcn: config.cell_cfg.dl_arfcn,
+                    band: config.cell_cfg.band,
+                    scs_khz: config.cell_cfg.common_scs,
+                    nrb,
+                },
+                measurement_timing_configuration: Bytes::new(),
+            };
+            let f1_du = F1apDu::new(F1apDuConfig {
+                cu_address: f1_cu_address,
+                local_address: SocketAddr::new(f1_bind_address, 0),
+                transport: f1_transport,
+                f1u_address: SocketAddr::new(f1u_address, GTPU_PORT),
+                gnb_du_id: config.f1.gnb_du_id,
+                gnb_du_name: "Albor-gNB-DU".to_string(),
+                served_cell,
+                reconnect_interval: std::time::Duration::from_secs(config.f1.reconnect_interval),
+            }, mac_layer.clone() as Arc<dyn RrcMacInterface>)
+                .await.map_err(|e| anyhow::anyhow!("Failed to initialize F1 gNB-DU endpoint: {}", e))?;
+            info!("F1 gNB-DU endpoint initialized, gNB-CU at {}", f1_cu_address);
+            Some(Arc::new(f1_du))
         }
-    }
-    let ngap_layer = Arc::new(RwLock::new(ngap_layer));
+        _ => None,
+    };
+    
+    // Initialize the N3 GTP-U endpoint (gNB-CU or gNB-CU-UP); on a gNB-CU-UP
+    // the downlink goes to the E1 endpoint instead of RRC
+    let mut cu_up_downlink_rx = None;
+    let gtpu_layer = if matches!(node_mode, NodeMode::Monolithic | NodeMode::Cu | NodeMode::CuUp) {
+        let mut gtpu_layer = GtpuLayer::new(GtpuConfig {
+            bind_address: SocketAddr::new(gtpu_bind_address, GTPU_PORT),
+            external_address: gtpu_address,
+        });
+        if node_mode == NodeMode::CuUp {
+            let (downlink_tx, downlink_rx) = tokio::sync::mpsc::channel::<layers::rrc::GtpuRrcMessage>(1000);
+            gtpu_layer.set_rrc_channel(downlink_tx);
+            cu_up_downlink_rx = Some(downlink_rx);
+        } else {
+            gtpu_layer.set_rrc_channel(gtpu_to_rrc_tx);
+        }
+        gtpu_layer.initialize().await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize GTP-U endpoint: {}", e))?;
+        info!("GTP-U endpoint initialized");
+        Some(Arc::new(RwLock::new(gtpu_layer)))
+    } else {
+        None
+    };
+
+    let e1_cu_up = match (&gtpu_layer, node_mode) {
+        (Some(gtpu_layer), NodeMode::CuUp) => {
+            let e1_cu_up = E1apCuUp::new(E1apCuUpConfig {
+                cu_cp_address: e1_cu_cp_address,
+                local_address: SocketAddr::new(e1_bind_address, 0),
+                transport: e1_transport,
+                gnb_cu_up_id: config.e1.gnb_cu_up_id,
+                gnb_cu_up_name: "Albor-gNB-CU-UP".to_string(),
+                f1u_address: SocketAddr::new(f1u_address, GTPU_PORT),
+                reconnect_interval: std::time::Duration::from_secs(config.e1.reconnect_interval),
+            }, gtpu_layer.clone())
+                .await.map_err(|e| anyhow::anyhow!("Failed to initialize E1 gNB-CU-UP endpoint: {}", e))?;
+            info!("E1 gNB-CU-UP endpoint initialized, gNB-CU-CP at {}", e1_cu_cp_address);
+            Some(Arc::new(e1_cu_up))
+        }
+        _ => None,
+    };
+
+    // Initialize RRC and NGAP (gNB-CU or gNB-CU-CP)
+    let (rrc_layer, ngap_layer, xnap_node) = if matches!(node_mode, NodeMode::Du | NodeMode::CuUp) {
+        (None, None, None)
+    } else {
+        // Create RRC configuration
+        let rrc_config = RrcConfig {
+            sib_periodicity: 160,
+            max_ue_contexts: config.cu_cp.max_ue_contexts,
+            cell_id,
+            nr_cell_identity,
+            plmn_id,
+            tac: config.cell_cfg.tac,
+            band: config.cell_cfg.band,
+            request_eutra_nr_capability: false,
+            procedure_guard_time_ms: 1000,
+            release_request_guard_time_ms: config.cu_cp.release_request_guard_time.saturating_mul(1000),
+            pci: config.cell_cfg.pci,
+            inactivity_timer_s: config.cu_cp.inactivity_timer,
+            inactive_enabled: config.cu_cp.rrc_inactive_enabled,
+            ran_paging_cycle: config.cu_cp.ran_paging_cycle,
+            // The SSB is placed around the carrier centre
+            meas_config: Some(default_meas_config(config.cell_cfg.dl_arfcn, scs)),
+        };
+        
+        // Initialize RRC layer
+        let mut rrc_layer = RrcLayer::new(rrc_config);
+        rrc_layer.set_ngap_channel(rrc_to_ngap_tx);
+        rrc_layer.set_gtpu_channel(rrc_to_gtpu_tx);
+        
+        // Set MAC interface for RRC: the local MAC, or the gNB-DU over F1
+        let mac_interface: Arc<dyn RrcMacInterface> = match (&mac_layer, &f1_cu) {
+            (Some(mac_layer), _) => mac_layer.clone() as Arc<dyn RrcMacInterface>,
+            (None, Some(f1_cu)) => f1_cu.clone() as Arc<dyn RrcMacInterface>,
+            (None, None) => return Err(anyhow::anyhow!("No MAC interface for RRC")),
+        };
+        rrc_layer.set_mac_interface(mac_interface);
+        if let Some(e1_cu_cp) = &e1_cu_cp {
+            rrc_layer.set_cu_up_interface(e1_cu_cp.clone() as Arc<dyn RrcCuUpInterface>);
+        }
+        
+        rrc_layer.initialize().await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize RRC layer: {}", e))?;
+        info!("RRC layer initialized");
+        let rrc_layer = Arc::new(RwLock::new(rrc_layer));
+        
+        // Create NGAP configuration
+        // Supported TA list for NG Setup, merged over the AMFs
+        let supported_tas = build_supported_tas(&config.cu_cp.amf)?;
+        
+        let amf = first_amf;
+        let transport_kind = match amf.transport.as_str() {
+            "sctp" => NgTransportKind::Sctp,
+            "tcp" => NgTransportKind::TcpFramed,
+            other => return Err(anyhow::anyhow!("Invalid AMF transport {}, expected sctp or tcp", other)),
+        };
+        let parse_addrs = |addrs: &[String]| -> Result<Vec<IpAddr>> {
+            addrs.iter()
+                .map(|addr| IpAddr::from_str(addr).map_err(|e| anyhow::anyhow!("Invalid address {}: {}", addr, e)))
+                .collect()
+        };
+        let transport = NgTransportConfig {
+            kind: transport_kind,
+            num_streams: amf.sctp_streams,
+            extra_local_addresses: parse_addrs(&amf.bind_extra_addrs)?,
+            reconnect_interval: std::time::Duration::from_secs(amf.reconnect_interval),
+            ..Default::default()
+        };
+        
+        let mut amfs = Vec::new();
+        for amf in &config.cu_cp.amf {
+            let amf_addr = format!("{}:{}", amf.addr, amf.port);
+            amfs.push(AmfEndpoint {
+                address: SocketAddr::from_str(&amf_addr)
+                    .map_err(|e| anyhow::anyhow!("Invalid AMF address {}: {}", amf_addr, e))?,
+                extra_addresses: parse_addrs(&amf.extra_addrs)?,
+            });
+        }
+        
+        let ngap_config = NgapConfig {
+            amfs,
+            local_address: SocketAddr::from_str(&format!("{}:0", first_amf.bind_addr))
+                .map_err(|e| anyhow::anyhow!("Invalid bind address: {}", e))?,
+            gnb_id,
+            gnb_id_bits: gnb_id_bits as u8,
+            plmn_id,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: supported_tas.clone(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity,
+            tac: config.cell_cfg.tac,
+            gtpu_address,
+            transport,
+            handover_targets: config.cu_cp.n2_neighbours.iter()
+                .map(|neighbour| HandoverTarget {
+                    pci: neighbour.pci,
+                    gnb_id: neighbour.pci as u32,
+                    gnb_id_bits: gnb_id_bits as u8,
+                    nr_cell_identity: ((neighbour.pci as u64) << (36 - gnb_id_bits)) | 1,
+                    tac: neighbour.tac.unwrap_or(config.cell_cfg.tac),
+                })
+                .collect(),
+        };
+        
+        // Initialize NGAP layer
+        let mut ngap_layer = NgapLayer::new(ngap_config);
+        ngap_layer.set_rrc_channel(ngap_to_rrc_tx);
+        ngap_layer.set_gtpu_channel(ngap_to_gtpu_tx);
+        
+        // Initialize XnAP towards the neighbouring gNBs
+        let xnap_node = if config.xn.enabled {
+            let xn_transport = match config.xn.transport.as_str() {
+                "sctp" => NgTransportKind::Sctp,
+                "tcp" => NgTransportKind::TcpFramed,
+                other => return Err(anyhow::anyhow!("Invalid Xn transport {}, expected sctp or tcp", other)),
+            };
+            let xn_bind_address = IpAddr::from_str(&config.xn.bind_addr)
+                .map_err(|e| anyhow::anyhow!("Invalid Xn bind address {}: {}", config.xn.bind_addr, e))?;
+            let peers = config.xn.peers.iter()
+                .map(|peer| SocketAddr::from_str(peer).map_err(|e| anyhow::anyhow!("Invalid Xn peer {}: {}", peer, e)))
+                .collect::<Result<Vec<_>>>()?;
+            let served_cell = ServedCellNr {
+                pci: config.cell_cfg.pci,
+                nr_cgi: NrCgi { plmn_id, nr_cell_identity },
+                tac: config.cell_cfg.tac,
+                fdd_info: FddInfo {
+                    ul_arfcn: calculate_ul_arfcn(config.cell_cfg.dl_arfcn, config.cell_cfg.band)?,
+                    dl_arfcn: config.cell_cfg.dl_arfcn,
+                    band: config.cell_cfg.band,
+                    scs_khz: config.cell_cfg.common_scs,
+                    nrb: transmission_bandwidth_nrb(config.cell_cfg.channel_bandwidth_mhz, config.cell_cfg.common_scs)?,
+                },
+            };
+            let xnap_node = XnapNode::new(XnapConfig {
+                bind_address: SocketAddr::new(xn_bind_address, config.xn.port),
+                transport: xn_transport,
+                global_gnb_id: GlobalGnbId { plmn_id, gnb_id, gnb_id_bits: gnb_id_bits as u8 },
+                supported_tas,
+                served_cell,
+                peers,
+                reconnect_interval: std::time::Duration::from_secs(config.xn.reconnect_interval),
+            }, xnap_to_ngap_tx).await.map_err(|e| anyhow::anyhow!("Failed to initialize XnAP: {}", e))?;
+            ngap_layer.set_xnap_channel(ngap_to_xnap_tx);
+            info!("XnAP initialized, {} Xn peers", config.xn.peers.len());
+            Some(Arc::new(xnap_node))
+        } else {
+            None
+        };
+        match ngap_layer.initialize().await {
+            Ok(_) => info!("NGAP layer initialized and connected to AMF"),
+            Err(e) => {
+                warn!("Failed to initialize NGAP layer: {}. Continuing without AMF connection.", e);
+                warn!("Cell will broadcast without core network connectivity until the AMF is reachable.");
+            }
+        }
+        let ngap_layer = Arc::new(RwLock::new(ngap_layer));
+        (Some(rrc_layer), Some(ngap_layer), xnap_node)
+    };
     
+    // Initialize the E2 agent reporting the measurements of the layers this process runs
+    let e2_agent = if config.e2.enabled {
+        let e2_transport = match config.e2.transport.as_str() {
+            "sctp" => NgTransportKind::Sctp,
+            "tcp" => NgTransportKind::TcpFramed,
+            other => return Err(anyhow::anyhow!("Invalid E2 transport {}, expected sctp or tcp", other)),
+        };
+        let ric_address = IpAddr::from_str(&config.e2.ric_addr)
+            .map_err(|e| anyhow::anyhow!("Invalid near-RT RIC address {}: {}", config.e2.ric_addr, e))?;
+        let e2_bind_address = IpAddr::from_str(&config.e2.bind_addr)
+            .map_err(|e| anyhow::anyhow!("Invalid E2 bind address {}: {}", config.e2.bind_addr, e))?;
+        let kpm_source = StackKpmSource::new(
+            mac_layer.as_ref().map(|mac_layer| mac_layer.scheduler()),
+            phy_layer.clone(),
+            rrc_layer.clone(),
+        );
+        let mut agent = E2Agent::new(E2AgentConfig {
+            ric_address: SocketAddr::new(ric_address, config.e2.ric_port),
+            bind_address: SocketAddr::new(e2_bind_address, 0),
+            transport: e2_transport,
+            global_gnb_id: GlobalGnbId { plmn_id, gnb_id, gnb_id_bits: gnb_id_bits as u8 },
+            nr_cgi: NrCgi { plmn_id, nr_cell_identity },
+            amf_name: config.cu_cp.amf.first().map_or_else(String::new, |amf| amf.addr.clone()),
+            reconnect_interval: std::time::Duration::from_secs(config.e2.reconnect_interval),
+        }, Arc::new(kpm_source));
+        if config.e2.control_enabled {
+            // Handover targets follow the deployment's cell identity convention: gNB ID = PCI, cell 1
+            let cell_resolver = Box::new(move |target: &NrCgi| {
+                let gnb_id = target.nr_cell_identity >> (36 - gnb_id_bits);
+                (target.plmn_id == plmn_id && target.nr_cell_identity == (gnb_id << (36 - gnb_id_bits)) | 1)
+                    .then(|| u16::try_from(gnb_id).ok())
+                    .flatten()
+            });
+            agent.set_rc_control(Arc::new(StackRcControl::new(
+                plmn_id,
+                mac_layer.as_ref().map(|mac_layer| mac_layer.scheduler()),
+                rrc_layer.clone(),
+                cell_resolver,
+            )));
+            info!("E2SM-RC control enabled");
+        }
+        info!("E2 agent initialized, near-RT RIC at {}:{}", config.e2.ric_addr, config.e2.ric_port);
+        Some(Arc::new(agent))
+    } else {
+        None
+    };
+
     let running = Arc::new(RwLock::new(true));
     
     let state = GnbState {
         phy_layer,
-        mac_layer,
+        fapi_l2: fapi_l2.as_ref().map(|(l2, _)| l2.clone()),
         rrc_layer,
         ngap_layer,
-        running: running.clone(),
+        gtpu_layer,
+        f1_cu,
+        f1_du,
+        e1_cu_cp,
+        e1_cu_up,
+        xnap_node,
+        e2_agent,
     };
 
     info!("GNodeB initialized successfully");
     
+    // Start FAPI tasks: the L1 in front of the PHY, then the MAC side once it
+    // has configured and started the L1
+    if let Some((l1, mut l1_rx)) = fapi_l1 {
+        tokio::spawn(async move {
+            if let Err(e) = l1.run(&mut l1_rx).await {
+                error!("FAPI L1 error: {}", e);
+            }
+        });
+    }
+    let fapi_handle = match fapi_l2 {
+        Some((l2, mut mac_rx)) => {
+            l2.start(&mut mac_rx).await?;
+            Some(tokio::spawn(async move {
+                if let Err(e) = l2.run(&mut mac_rx).await {
+                    error!("FAPI L2 error: {}", e);
+                }
+            }))
+        }
+        None => None,
+    };
+    
     // Start PHY processing in background
-    let phy_handle = {
-        let phy = state.phy_layer.clone();
+    let phy_handle = state.phy_layer.clone().map(|phy| {
         tokio::spawn(async move {
             let phy_guard = phy.read().await;
             if let Err(e) = phy_guard.start_processing().await {
                 error!("Enhanced PHY processing error: {}", e);
             }
         })
+    });
+    
+    // Start F1 task: gNB-DU associations and F1-U on the CU, the association
+    // with the gNB-CU and F1-U on the DU
+    let f1_handle = match (&state.f1_cu, &state.f1_du) {
+        (Some(f1_cu), _) => Some(tokio::spawn(run_f1_cu(f1_cu.clone()))),
+        (None, Some(f1_du)) => Some(tokio::spawn(run_f1_du(f1_du.clone()))),
+        (None, None) => None,
     };
     
-    // Start RRC message processing task
+    // Start E1 task: gNB-CU-UP associations on the CU-CP, the association with
+    // the gNB-CU-CP and the user plane on the CU-UP
+    let e1_handle = match (&state.e1_cu_cp, &state.e1_cu_up) {
+        (Some(e1_cu_cp), _) => Some(tokio::spawn(run_e1_cu_cp(e1_cu_cp.clone()))),
+        (None, Some(e1_cu_up)) => cu_up_downlink_rx.take()
+            .map(|downlink_rx| tokio::spawn(run_e1_cu_up(e1_cu_up.clone(), downlink_rx))),
+        (None, None) => None,
+    };
+    
+    // Start Xn task: associations with the neighbouring gNBs
+    let xn_handle = state.xnap_node.clone().map(|xnap_node| tokio::spawn(run_xnap(xnap_node, ngap_to_xnap_rx)));
+    
+    // Start E2 task: association with the near-RT RIC
+    let e2_handle = state.e2_agent.clone().map(|e2_agent| tokio::spawn(run_e2_agent(e2_agent)));
+    
+    // Start N3 GTP-U receive task
+    let _gtpu_handle = state.gtpu_layer.clone().map(|gtpu| tokio::spawn(run_gtpu_endpoint(gtpu)));
+    
+    // Start RRC message processing task: uplink RRC messages go to RRC, or
+    // to the gNB-CU on a gNB-DU
     let rrc_handle = {
         let rrc = state.rrc_layer.clone();
+        let f1_du = state.f1_du.clone();
         let running = running.clone();
         tokio::spawn(async move {
             while *running.read().await {
                 // Process messages from MAC
                 if let Some((rnti, data)) = mac_to_rrc_rx.recv().await {
-                    let mut rrc_guard = rrc.write().await;
-                    if let Err(e) = rrc_guard.process_uplink(data).await {
-                        error!("RRC uplink processing error: {}", e);
+                    if let Some(rrc) = &rrc {
+                        let mut rrc_guard = rrc.write().await;
+                        if let Err(e) = rrc_guard.handle_uplink_message(rnti, data).await {
+                            error!("RRC uplink processing error: {}", e);
+                        }
+                    } else if let Some(f1_du) = &f1_du {
+                        if let Err(e) = f1_du.handle_uplink_rrc_message(rnti, data).await {
+                            error!("F1 uplink RRC message transfer error: {}", e);
+                        }
                     }
                 }
             }
         })
     };
     
+    // Start MAC uplink user data task: DRB data goes to RRC, or to the
+    // gNB-CU over F1-U on a gNB-DU
+    let _mac_user_data_handle = {
+        let rrc = state.rrc_layer.clone();
+        let f1_du = state.f1_du.clone();
+        tokio::spawn(async move {
+            while let Some((rnti, lcid, data)) = mac_user_data_rx.recv().await {
+                if let Some(rrc) = &rrc {
+                    if let Err(e) = rrc.write().await.handle_uplink_user_data(rnti, lcid, data).await {
+                        warn!("RRC uplink user data error: {}", e);
+                    }
+                } else if let Some(f1_du) = &f1_du {
+                    if let Err(e) = f1_du.send_uplink_user_data(rnti, lcid, data).await {
+                        warn!("F1-U uplink user data error: {}", e);
+                    }
+                }
+            }
+        })
+    };
+    
+    if let (Some(rrc_layer), Some(ngap_layer)) = (&state.rrc_layer, &state.ngap_layer) {
+        // Start RRC procedure timer task
+        let _rrc_timer_handle = {
+            let rrc = rrc_layer.clone();
+            let running = running.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(100));
+                
+                while *running.read().await {
+                    interval.tick().await;
+                    rrc.write().await.handle_timers(tokio::time::Instant::now()).await;
+                }
+            })
+        };
+        
+        // Start RRC to NGAP message processing task
+        let _ngap_handle = {
+            let ngap = ngap_layer.clone();
+            tokio::spawn(async move {
+                while let Some(message) = rrc_to_ngap_rx.recv().await {
+                    let mut ngap_guard = ngap.write().await;
+                    if let Err(e) = ngap_guard.handle_rrc_message(message).await {
+                        error!("NGAP processing error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Start NG association task: AMF messages and reconnection
+        let _ng_association_handle = tokio::spawn(run_ng_association(ngap_layer.clone()));
+        
+        // Start NGAP to RRC message processing task
+        let _ngap_rrc_handle = {
+            let rrc = rrc_layer.clone();
+            tokio::spawn(async move {
+                while let Some(message) = ngap_to_rrc_rx.recv().await {
+                    if let Err(e) = rrc.write().await.handle_ngap_message(message).await {
+                        error!("RRC processing error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Start XnAP to NGAP message processing task
+        let _xnap_ngap_handle = {
+            let ngap = ngap_layer.clone();
+            tokio::spawn(async move {
+                while let Some(message) = xnap_to_ngap_rx.recv().await {
+                    if let Err(e) = ngap.write().await.handle_xnap_message(message).await {
+                        error!("NGAP Xn handover error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Start NGAP to GTP-U tunnel management task: the local endpoint, or
+        // the gNB-CU-UP over E1
+        let _ngap_gtpu_handle = {
+            let gtpu = state.gtpu_layer.clone();
+            let e1_cu_cp = state.e1_cu_cp.clone();
+            tokio::spawn(async move {
+                while let Some(message) = ngap_to_gtpu_rx.recv().await {
+                    let result = match (&e1_cu_cp, &gtpu) {
+                        (Some(e1_cu_cp), _) => e1_cu_cp.handle_ngap_message(message).await,
+                        (None, Some(gtpu)) => gtpu.write().await.handle_ngap_message(message).await,
+                        (None, None) => Ok(()),
+                    };
+                    if let Err(e) = result {
+                        error!("GTP-U tunnel management error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Start RRC to GTP-U uplink task
+        let _rrc_gtpu_handle = state.gtpu_layer.clone().map(|gtpu| {
+            tokio::spawn(async move {
+                while let Some(message) = rrc_to_gtpu_rx.recv().await {
+                    if let Err(e) = gtpu.write().await.handle_rrc_message(message).await {
+                        warn!("GTP-U uplink error: {}", e);
+                    }
+                }
+            })
+        });
+        
+        // Start GTP-U to RRC downlink task
+        let _gtpu_rrc_handle = {
+            let rrc = rrc_layer.clone();
+            tokio::spawn(async move {
+                while let Some(message) = gtpu_to_rrc_rx.recv().await {
+                    if let Err(e) = rrc.write().await.handle_gtpu_message(message).await {
+                        warn!("RRC downlink user data error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Start F1-U to RRC uplink user data task (gNB-CU)
+        let _f1u_rrc_handle = {
+            let rrc = rrc_layer.clone();
+            tokio::spawn(async move {
+                while let Some((rnti, lcid, data)) = f1u_to_rrc_rx.recv().await {
+                    if let Err(e) = rrc.write().await.handle_uplink_user_data(rnti, lcid, data).await {
+                        warn!("RRC uplink user data error: {}", e);
+                    }
+                }
+            })
+        };
+        
+        // Reload the served tracking areas and slices on SIGHUP, announced to the
+        // AMFs with RAN Configuration Update
+        let _config_reload_handle = {
+            let ngap = ngap_layer.clone();
+            let config_path = args.config.clone();
+            tokio::spawn(async move {
+                let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(hangup) => hangup,
+                    Err(e) => {
+                        warn!("Configuration reload on SIGHUP unavailable: {}", e);
+                        return;
+                    }
+                };
+                while hangup.recv().await.is_some() {
+                    info!("Reloading supported tracking areas from: {}", config_path);
+                    let supported_tas = GnbConfig::from_yaml_file(&config_path)
+                        .and_then(|config| build_supported_tas(&config.cu_cp.amf));
+                    match supported_tas {
+                        Ok(supported_tas) => {
+                            if let Err(e) = ngap.write().await.update_supported_tas(supported_tas).await {
+                                error!("Failed to update supported tracking areas: {}", e);
+                            }
+                        }
+                        Err(e) => error!("Configuration not reloaded: {}", e),
+                    }
+                }
+            })
+        };
+    }
+    
     // Start statistics reporting
     let stats_handle = {
         let phy = state.phy_layer.clone();
+        let fapi_l2 = state.fapi_l2.clone();
+        let rrc = state.rrc_layer.clone();
         let running = running.clone();
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
@@ -340,19 +975,49 @@ async fn main() -> Result<()> {
             while *running.read().await {
                 interval.tick().await;
                 
-                let phy_guard = phy.read().await;
-                let stats = phy_guard.get_stats().await;
+                if let Some(phy) = &phy {
+                    let phy_guard = phy.read().await;
+                    let stats = phy_guard.get_stats().await;
+                    
+                    info!("PHY Statistics:");
+                    info!("  Frame: {}, Slot: {}, Symbol: {}", 
+                          stats.frame_number, stats.slot_number, stats.symbol_number);
+                    info!("  Samples processed: {}", stats.sample_count);
+                    
+                    if let Some(rf_stats) = stats.rf_stats {
+                        info!("  TX samples: {}, RX samples: {}", 
+                              rf_stats.tx_samples, rf_stats.rx_samples);
+                        info!("  TX underruns: {}, RX overruns: {}", 
+                              rf_stats.tx_underruns, rf_stats.rx_overruns);
+                    }
+                    if let Some(fh_stats) = stats.fronthaul_stats {
+                        info!("  Fronthaul TX C-plane: {}, U-plane: {}, late: {}", 
+                              fh_stats.tx_cplane, fh_stats.tx_uplane, fh_stats.tx_late);
+                        info!("  Fronthaul RX U-plane: {}, early: {}, late: {}, dropped: {}", 
+                              fh_stats.rx_uplane, fh_stats.rx_early, fh_stats.rx_late, fh_stats.rx_dropped);
+                    }
+                }
                 
-                info!("PHY Statistics:");
-                info!("  Frame: {}, Slot: {}, Symbol: {}", 
-                      stats.frame_number, stats.slot_number, stats.symbol_number);
-                info!("  Samples processed: {}", stats.sample_count);
+                if let Some(fapi_l2) = &fapi_l2 {
+                    let stats = fapi_l2.stats();
+                    info!("FAPI Statistics:");
+                    info!("  SLOT.indication: {}, DL_TTI.request: {}, UL_TTI.request: {}, UL_DCI.request: {}",
+                          stats.slot_indications, stats.dl_tti_requests, stats.ul_tti_requests, stats.ul_dci_requests);
+                    info!("  RACH.indication: {}, RX_Data.indication: {}, CRC failures: {}, L1 errors: {}",
+                          stats.rach_indications, stats.rx_data_indications, stats.crc_failures, stats.error_indications);
+                }
                 
-                if let Some(rf_stats) = stats.rf_stats {
-                    info!("  TX samples: {}, RX samples: {}", 
-                          rf_stats.tx_samples, rf_stats.rx_samples);
-                    info!("  TX underruns: {}, RX overruns: {}", 
-                          rf_stats.tx_underruns, rf_stats.rx_overruns);
+                let Some(rrc) = &rrc else { continue };
+                let measurements = rrc.read().await.get_all_ue_measurements().await;
+                if !measurements.is_empty() {
+                    info!("UE Measurements:");
+                }
+                for (rnti, ue_measurements) in measurements {
+                    if let Some(serving) = ue_measurements.serving {
+                        info!("  RNTI {}: PCI {} RSRP {:?} dBm, RSRQ {:?} dB, SINR {:?} dB, {} neighbours",
+                              rnti.0, serving.pci, serving.rsrp_dbm, serving.rsrq_db, serving.sinr_db,
+                              ue_measurements.neighbours.len());
+                    }
                 }
             }
         })
@@ -363,9 +1028,24 @@ async fn main() -> Result<()> {
         _ = tokio::signal::ctrl_c() => {
             info!("Received shutdown signal");
         }
-        _ = phy_handle => {
+        _ = join_task(phy_handle) => {
             warn!("PHY processing stopped unexpectedly");
         }
+        _ = join_task(fapi_handle) => {
+            warn!("FAPI interface stopped unexpectedly");
+        }
+        _ = join_task(f1_handle) => {
+            warn!("F1 interface stopped unexpectedly");
+        }
+        _ = join_task(e1_handle) => {
+            warn!("E1 interface stopped unexpectedly");
+        }
+        _ = join_task(xn_handle) => {
+            warn!("Xn interface stopped unexpectedly");
+        }
+        _ = join_task(e2_handle) => {
+            warn!("E2 interface stopped unexpectedly");
+        }
         _ = rrc_handle => {
             warn!("RRC processing stopped unexpectedly");
         }
@@ -375,30 +1055,45 @@ async fn main() -> Result<()> {
     info!("Shutting down GNodeB");
     *running.write().await = false;
     
+    // Stop the L1 driven over FAPI
+    if let Some(fapi_l2) = &state.fapi_l2 {
+        if let Err(e) = fapi_l2.stop().await {
+            error!("Error stopping the FAPI L1: {}", e);
+        }
+    }
+    
     // Stop PHY processing
-    {
-        let phy_guard = state.phy_layer.read().await;
+    if let Some(phy_layer) = &state.phy_layer {
+        let phy_guard = phy_layer.read().await;
         if let Err(e) = phy_guard.stop_processing().await {
             error!("Error stopping enhanced PHY: {}", e);
         }
     }
     
     // Shutdown RRC layer
-    {
-        let mut rrc_guard = state.rrc_layer.write().await;
+    if let Some(rrc_layer) = &state.rrc_layer {
+        let mut rrc_guard = rrc_layer.write().await;
         if let Err(e) = rrc_guard.shutdown().await {
             error!("Error shutting down RRC: {}", e);
         }
     }
     
     // Shutdown NGAP layer
-    {
-        let mut ngap_guard = state.ngap_layer.write().await;
+    if let Some(ngap_layer) = &state.ngap_layer {
+        let mut ngap_guard = ngap_layer.write().await;
         if let Err(e) = ngap_guard.shutdown().await {
             error!("Error shutting down NGAP: {}", e);
         }
     }
     
+    // Shutdown GTP-U endpoint
+    if let Some(gtpu_layer) = &state.gtpu_layer {
+        let mut gtpu_guard = gtpu_layer.write().await;
+        if let Err(e) = gtpu_guard.shutdown().await {
+            error!("Error shutting down GTP-U: {}", e);
+        }
+    }
+    
     // Wait for tasks to complete
     let _ = tokio::time::timeout(
         tokio::time::Duration::from_secs(5),
@@ -409,6 +1104,41 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// CONFIG.request of the L1 of the cell, its SSB placed `k_ssb` subcarriers
+/// from the carrier centre as by the PHY of this stack
+fn fapi_config_request(config: &GnbConfig, carrier_frequency: f64, k_ssb: i16) -> Result<ConfigRequest> {
+    let scs_khz = config.cell_cfg.common_scs;
+    let nrb = transmission_bandwidth_nrb(config.cell_cfg.channel_bandwidth_mhz, scs_khz)?;
+    let point_a_khz = (calculate_point_a(carrier_frequency, nrb, scs_khz) / 1e3).round() as u32;
+    // FDD duplex spacing in 5 kHz raster steps
+    let duplex_spacing_khz = (config.cell_cfg.dl_arfcn - calculate_ul_arfcn(config.cell_cfg.dl_arfcn, config.cell_cfg.band)?) * 5;
+    let ssb_first_subcarrier = (nrb as i32 * 6 + k_ssb as i32).max(0) as u32;
+    Ok(ConfigRequest {
+        dl_bandwidth: config.cell_cfg.channel_bandwidth_mhz as u16,
+        dl_frequency: point_a_khz,
+        dl_grid_size: nrb,
+        num_tx_ant: 1,
+        ul_bandwidth: config.cell_cfg.channel_bandwidth_mhz as u16,
+        ul_frequency: point_a_khz - duplex_spacing_khz,
+        ul_grid_size: nrb,
+        num_rx_ant: 1,
+        phy_cell_id: config.cell_cfg.pci,
+        frame_duplex_type: FrameDuplexType::Fdd,
+        scs_common: (scs_khz / 15).trailing_zeros() as u8,
+        // 1.25 kHz of the long preambles
+        prach_sub_c_spacing: 5,
+        prach_root_sequence_index: config.cell_cfg.prach.prach_root_sequence_index,
+        prach_zero_corr_conf: config.cell_cfg.prach.zero_correlation_zone,
+        prach_config_index: config.cell_cfg.prach.prach_config_index,
+        ssb_offset_point_a: (ssb_first_subcarrier / 12) as u16,
+        // 20 ms
+        ssb_period: 2,
+        ssb_subcarrier_offset: (ssb_first_subcarrier % 12) as u8,
+        // First SSB only
+        ssb_mask: 0x8000_0000,
+    })
+}
+
 /// Calculate carrier frequency from ARFCN
 fn calculate_frequency_from_arfcn(arfcn: u32, band: u16) -> Result<f64> {
     // For NR Band n3 (1800 MHz FDD)
@@ -428,6 +1158,52 @@ fn calculate_frequency_from_arfcn(arfcn: u32, band: u16) -> Result<f64> {
     }
 }
 
+/// UL NR-ARFCN paired with a DL NR-ARFCN of an FDD band
+fn calculate_ul_arfcn(dl_arfcn: u32, band: u16) -> Result<u32> {
+    match band {
+        // Band n3: 95 MHz duplex spacing in 5 kHz raster steps
+        3 => Ok(dl_arfcn - 19000),
+        _ => Err(anyhow::anyhow!("Unsupported band: {}", band))
+    }
+}
+
+/// Maximum transmission bandwidth in resource blocks (3GPP TS 38.104 Table 5.3.2-1)
+fn transmission_bandwidth_nrb(bandwidth_mhz: u32, scs_khz: u32) -> Result<u16> {
+    let nrb = match (scs_khz, bandwidth_mhz) {
+        (15, 5) => 25,
+        (15, 10) => 52,
+        (15, 15) => 79,
+        (15, 20) => 106,
+        (15, 25) => 133,
+        (15, 30) => 160,
+        (15, 40) => 216,
+        (15, 50) => 270,
+        (30, 5) => 11,
+        (30, 10) => 24,
+        (30, 15) => 38,
+        (30, 20) => 51,
+        (30, 25) => 65,
+        (30, 30) => 78,
+        (30, 40) => 106,
+        (30, 50) => 133,
+        (30, 60) => 162,
+        (30, 80) => 217,
+        (30, 100) => 273,
+        _ => return Err(anyhow::anyhow!("No transmission bandwidth for {} MHz with {} kHz SCS", bandwidth_mhz, scs_khz)),
+    };
+    Ok(nrb)
+}
+
+/// Wait for a task if it was started
+async fn join_task(handle: Option<tokio::task::JoinHandle<()>>) {
+    match handle {
+        Some(handle) => {
+            let _ = handle.await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
 /// Calculate SSB ARFCN from DL ARFCN and bandwidth for given band
 /// According to 3GPP TS 38.104 and srsRAN implementation
 /// Calculate GSCN (Global Synchronization Channel Number) for Band 3
@@ -516,4 +1292,54 @@ fn calculate_k_ssb(point_a_hz: f64, ssb_first_sc_hz: f64, scs_khz: u32) -> i16 {
           point_a_hz / 1e6, ssb_first_sc_hz / 1e6, freq_diff_hz / 1e6, scs_khz, k_ssb);
     
     k_ssb
-}
\ No newline at end of file
+}
+
+/// Supported TA list for NG Setup and RAN Configuration Update, merged over the AMFs
+fn build_supported_tas(amfs: &[AmfConfig]) -> Result<Vec<SupportedTaItem>> {
+    let mut supported_tas: Vec<SupportedTaItem> = Vec::new();
+    for ta in amfs.iter().flat_map(|amf| &amf.supported_tracking_areas) {
+        let index = match supported_tas.iter().position(|item| item.tac == ta.tac) {
+            Some(index) => index,
+            None => {
+                supported_tas.push(SupportedTaItem { tac: ta.tac, broadcast_plmns: Vec::new() });
+                supported_tas.len() - 1
+            }
+        };
+        let broadcast_plmns = &mut supported_tas[index].broadcast_plmns;
+        for plmn in &ta.plmn_list {
+            let plmn_id = parse_plmn(&plmn.plmn)?;
+            let index = match broadcast_plmns.iter().position(|item| item.plmn_id == plmn_id) {
+                Some(index) => index,
+                None => {
+                    broadcast_plmns.push(BroadcastPlmnItem { plmn_id, slices: Vec::new() });
+                    broadcast_plmns.len() - 1
+                }
+            };
+            for slice in &plmn.tai_slice_support_list {
+                let slice = SNssai { sst: slice.sst, sd: slice.sd };
+                if !broadcast_plmns[index].slices.contains(&slice) {
+                    broadcast_plmns[index].slices.push(slice);
+                }
+            }
+        }
+    }
+    Ok(supported_tas)
+}
+
+/// Parse a PLMN string ("00101") into the 3-byte BCD PLMN identity of TS 38.413
+fn parse_plmn(plmn: &str) -> Result<[u8; 3]> {
+    let digits: Vec<u8> = plmn.chars()
+        .map(|c| c.to_digit(10).map(|d| d as u8))
+        .collect::<Option<_>>()
+        .ok_or_else(|| anyhow::anyhow!("Invalid PLMN format: {}", plmn))?;
+    if digits.len() != 5 && digits.len() != 6 {
+        return Err(anyhow::anyhow!("Invalid PLMN format: {}", plmn));
+    }
+    
+    let mnc3 = if digits.len() == 6 { digits[5] } else { 0x0F };
+    Ok([
+        (digits[1] << 4) | digits[0],
+        (mnc3 << 4) | digits[2],
+        (digits[4] << 4) | digits[3],
+    ])
+}
@@ -1,10 +1,12 @@
 //! ZMQ Communication Interfaces Library
 //! 
-//! This crate provides ZeroMQ-based interfaces for communication with the reference UE.
+//! This crate provides ZeroMQ-based interfaces for communication with the reference UE,
+//! and an O-RAN 7.2x fronthaul for O-RUs.
 
 pub mod zmq_handler;
 pub mod message_types;
 pub mod zmq_rf;
+pub mod oran_fh;
 
 use thiserror::Error;
 
@@ -0,0 +1,240 @@
+//! IQ sample compression
+//!
+//! The PRBs of the U-plane are carried either uncompressed, as 16 bit I and Q
+//! values, or with block floating point compression (O-RAN.WG4.CUS annex
+//! A.1.2): one exponent per PRB followed by the 24 mantissas of its 12 REs.
+//! The PHY's samples are converted to 16 bit fixed point with a configured
+//! scale first.
+
+use crate::InterfaceError;
+use bytes::{BufMut, BytesMut};
+use num_complex::Complex32;
+
+/// Subcarriers of a PRB
+pub const SUBCARRIERS_PER_PRB: usize = 12;
+
+/// udCompMeth: no compression
+const METHOD_NONE: u8 = 0;
+/// udCompMeth: block floating point
+const METHOD_BFP: u8 = 1;
+
+/// Compression of the U-plane IQ samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// 16 bit I and Q values
+    None,
+    /// Block floating point with mantissas of 1 to 16 bits, commonly 9 or 14
+    BlockFloatingPoint { iq_width: u8 },
+}
+
+impl Compression {
+    /// Block floating point compression with the given mantissa width
+    pub fn bfp(iq_width: u8) -> Result<Self, InterfaceError> {
+        if !(1..=16).contains(&iq_width) {
+            return Err(InterfaceError::InvalidConfig(format!("Invalid BFP IQ width {}", iq_width)));
+        }
+        Ok(Compression::BlockFloatingPoint { iq_width })
+    }
+
+    /// Compression header (udCompHdr): IQ width, 0 standing for 16, and method
+    pub fn ud_comp_hdr(&self) -> u8 {
+        match self {
+            Compression::None => METHOD_NONE,
+            Compression::BlockFloatingPoint { iq_width } => (iq_width & 0xF) << 4 | METHOD_BFP,
+        }
+    }
+
+    /// Compression of a compression header
+    pub fn from_ud_comp_hdr(ud_comp_hdr: u8) -> Result<Self, InterfaceError> {
+        let iq_width = match ud_comp_hdr >> 4 {
+            0 => 16,
+            width => width,
+        };
+        match ud_comp_hdr & 0xF {
+            METHOD_NONE if iq_width == 16 => Ok(Compression::None),
+            METHOD_BFP => Ok(Compression::BlockFloatingPoint { iq_width }),
+            _ => Err(InterfaceError::InvalidMessage),
+        }
+    }
+
+    /// Octets of one compressed PRB
+    pub fn prb_len(&self) -> usize {
+        match self {
+            Compression::None => 4 * SUBCARRIERS_PER_PRB,
+            Compression::BlockFloatingPoint { iq_width } => 1 + 2 * SUBCARRIERS_PER_PRB * *iq_width as usize / 8,
+        }
+    }
+
+    /// Compress whole PRBs of samples, each multiplied by `scale` to 16 bit
+    /// fixed point
+    pub fn compress(&self, samples: &[Complex32], scale: f32, buf: &mut BytesMut) {
+        for prb in samples.chunks(SUBCARRIERS_PER_PRB) {
+            let mut values = [0i16; 2 * SUBCARRIERS_PER_PRB];
+            for (i, sample) in prb.iter().enumerate() {
+                values[2 * i] = to_fixed(sample.re, scale);
+                values[2 * i + 1] = to_fixed(sample.im, scale);
+            }
+            match self {
+                Compression::None => {
+                    for value in values {
+                        buf.put_i16(value);
+                    }
+                }
+                Compression::BlockFloatingPoint { iq_width } => {
+                    let exponent = bfp_exponent(&values, *iq_width);
+                    buf.put_u8(exponent);
+                    let mut bits = BitWriter::default();
+                    for value in values {
+                        bits.put((value >> exponent) as u16, *iq_width, buf);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decompress `num_prbs` PRBs of samples, scaled back by `scale`
+    pub fn decompress(&self, data: &[u8], num_prbs: usize, scale: f32) -> Result<Vec<Complex32>, InterfaceError> {
+        if data.len() < num_prbs * self.prb_len() {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let mut samples = Vec::with_capacity(num_prbs * SUBCARRIERS_PER_PRB);
+        for prb in data.chunks(self.prb_len()).take(num_prbs) {
+            let mut values = [0i16; 2 * SUBCARRIERS_PER_PRB];
+            match self {
+                Compression::None => {
+                    for (value, octets) in values.iter_mut().zip(prb.chunks(2)) {
+                        *value = i16::from_be_bytes([octets[0], octets[1]]);
+                    }
+                }
+                Compression::BlockFloatingPoint { iq_width } => {
+                    let exponent = prb[0] & 0xF;
+                    let mut bits = BitReader::new(&prb[1..]);
+                    for value in values.iter_mut() {
+                        // Sign extend the mantissa before restoring its exponent
+                        let shift = 16 - *iq_width;
+                        let mantissa = ((bits.get(*iq_width) << shift) as i16) >> shift;
+                        *value = ((mantissa as i32) << exponent).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                    }
+                }
+            }
+            samples.extend(values.chunks(2).map(|iq| Complex32::new(iq[0] as f32 / scale, iq[1] as f32 / scale)));
+        }
+        Ok(samples)
+    }
+}
+
+fn to_fixed(value: f32, scale: f32) -> i16 {
+    (value * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Shift that makes the largest magnitude of a PRB fit in `iq_width` bits
+fn bfp_exponent(values: &[i16], iq_width: u8) -> u8 {
+    // Magnitude in one's complement, so that -2^n needs as many bits as 2^n - 1
+    let max = values.iter().map(|value| (*value ^ (*value >> 15)) as u16).max().unwrap_or(0);
+    let bits = 16 - max.leading_zeros() as u8 + 1;
+    bits.saturating_sub(iq_width)
+}
+
+/// Packs values MSB first
+#[derive(Default)]
+struct BitWriter {
+    acc: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u16, width: u8, buf: &mut BytesMut) {
+        self.acc = self.acc << width | (value as u32 & ((1 << width) - 1));
+        self.len += width;
+        while self.len >= 8 {
+            self.len -= 8;
+            buf.put_u8((self.acc >> self.len) as u8);
+        }
+    }
+}
+
+/// Unpacks values MSB first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn get(&mut self, width: u8) -> u16 {
+        let mut value = 0u16;
+        for _ in 0..width {
+            let bit = self.data[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = value << 1 | bit as u16;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(Compression::bfp(9).unwrap().prb_len(), 28);
+        assert_eq!(Compression::bfp(14).unwrap().prb_len(), 43);
+        assert_eq!(Compression::None.prb_len(), 48);
+        assert!(Compression::bfp(17).is_err());
+        for compression in [Compression::None, Compression::bfp(9).unwrap(), Compression::bfp(14).unwrap()] {
+            assert_eq!(Compression::from_ud_comp_hdr(compression.ud_comp_hdr()).unwrap(), compression);
+        }
+
+        // A PRB of small values next to one reaching full scale
+        let samples: Vec<_> = (0..24)
+            .map(|i| if i < 12 {
+                Complex32::new(i as f32 / 4000.0, -(i as f32) / 8000.0)
+            } else {
+                Complex32::new(((i - 12) as f32 - 6.0) / 6.0, (6.0 - (i - 12) as f32) / 7.0)
+            })
+            .collect();
+        let scale = 32767.0;
+        for (compression, tolerance) in [(Compression::None, 1e-4), (Compression::bfp(14).unwrap(), 1e-3),
+                                         (Compression::bfp(9).unwrap(), 1e-2)] {
+            let mut buf = BytesMut::new();
+            compression.compress(&samples, scale, &mut buf);
+            assert_eq!(buf.len(), 2 * compression.prb_len());
+            let decompressed = compression.decompress(&buf, 2, scale).unwrap();
+            for (sample, decompressed) in samples.iter().zip(&decompressed) {
+                assert!((sample - decompressed).norm() < tolerance,
+                        "{:?}: {} became {}", compression, sample, decompressed);
+            }
+            assert!(compression.decompress(&buf[1..], 2, scale).is_err());
+        }
+
+        // The small PRB keeps its precision with its own exponent
+        let mut buf = BytesMut::new();
+        Compression::bfp(9).unwrap().compress(&samples, scale, &mut buf);
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf[28], 7);
+    }
+
+    #[test]
+    fn test_compression_errors() {
+        assert!(Compression::bfp(0).is_err());
+        // Methods other than none and BFP, no compression with less than 16 bits
+        for ud_comp_hdr in [0x02, 0x9F, 0x90] {
+            assert!(Compression::from_ud_comp_hdr(ud_comp_hdr).is_err(), "{:02X}", ud_comp_hdr);
+        }
+        assert_eq!(Compression::from_ud_comp_hdr(0x01).unwrap(), Compression::BlockFloatingPoint { iq_width: 16 });
+
+        // Fewer octets than PRBs requested, no PRBs at all
+        let compression = Compression::bfp(9).unwrap();
+        assert!(compression.decompress(&[0; 27], 1, 1.0).is_err());
+        assert!(compression.decompress(&[], 0, 1.0).unwrap().is_empty());
+
+        // Samples beyond the fixed point range saturate
+        let mut buf = BytesMut::new();
+        Compression::None.compress(&[Complex32::new(2.0, -2.0); SUBCARRIERS_PER_PRB], 32767.0, &mut buf);
+        assert_eq!(&buf[..4], &[0x7F, 0xFF, 0x80, 0x00]);
+    }
+}
@@ -0,0 +1,306 @@
+//! C-plane messages
+//!
+//! Real-time control messages telling the O-RU which PRBs and symbols the
+//! U-plane will carry and how to beamform them (O-RAN.WG4.CUS section 7):
+//! section type 1 for the most downlink and uplink channels and section type
+//! 3 for PRACH and mixed numerology channels, with their time offset, frame
+//! structure and frequency offset.
+
+use super::compression::Compression;
+use super::timing::SymbolTime;
+use crate::InterfaceError;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Payload version of the CUS-plane messages
+pub const PAYLOAD_VERSION: u8 = 1;
+/// sectionType of section type 1
+const SECTION_TYPE_1: u8 = 1;
+/// sectionType of section type 3
+const SECTION_TYPE_3: u8 = 3;
+
+/// Direction of the data of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataDirection {
+    Uplink = 0,
+    Downlink = 1,
+}
+
+impl DataDirection {
+    /// dataDirection, payloadVersion and filterIndex octet
+    pub(super) fn first_octet(&self, filter_index: u8) -> u8 {
+        (*self as u8) << 7 | PAYLOAD_VERSION << 4 | filter_index & 0xF
+    }
+
+    /// Direction and filter index of the first octet of a message
+    pub(super) fn from_first_octet(octet: u8) -> Result<(Self, u8), InterfaceError> {
+        if octet >> 4 & 0x7 != PAYLOAD_VERSION {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let direction = if octet & 0x80 != 0 { DataDirection::Downlink } else { DataDirection::Uplink };
+        Ok((direction, octet & 0xF))
+    }
+}
+
+/// Encode sectionId, rb, symInc, startPrb and numPrb, a PRB count not fitting
+/// in the 8 bit field standing for the rest of the carrier
+pub(super) fn encode_section_id(
+    buf: &mut BytesMut,
+    section_id: u16,
+    start_prb: u16,
+    num_prb: u16,
+    num_carrier_prbs: u16,
+) {
+    buf.put_u16((section_id & 0xFFF) << 4 | (start_prb >> 8) & 0x3);
+    buf.put_u8(start_prb as u8);
+    let num_prb = if num_prb > u8::MAX as u16 && start_prb + num_prb == num_carrier_prbs { 0 } else { num_prb as u8 };
+    buf.put_u8(num_prb);
+}
+
+/// Decode the fields written by [`encode_section_id`]: section ID, start PRB
+/// and PRB count
+pub(super) fn decode_section_id(data: &[u8], num_carrier_prbs: u16) -> Result<(u16, u16, u16), InterfaceError> {
+    let [high, middle, low, num_prb, ..] = *data else {
+        return Err(InterfaceError::InvalidMessage);
+    };
+    let section_id = u16::from_be_bytes([high, middle]) >> 4;
+    let start_prb = ((middle as u16 & 0x3) << 8) | low as u16;
+    let num_prb = match num_prb {
+        0 => num_carrier_prbs.checked_sub(start_prb).ok_or(InterfaceError::InvalidMessage)?,
+        num_prb => num_prb as u16,
+    };
+    Ok((section_id, start_prb, num_prb))
+}
+
+/// Section of a C-plane message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CplaneSection {
+    /// Section ID the U-plane sections refer to
+    pub section_id: u16,
+    /// First PRB
+    pub start_prb: u16,
+    /// Number of PRBs
+    pub num_prb: u16,
+    /// REs of each PRB the section applies to, bit 11 being the first
+    pub re_mask: u16,
+    /// Number of symbols from the start symbol
+    pub num_symbols: u8,
+    /// Beam to apply, 0 for no beamforming
+    pub beam_id: u16,
+    /// Frequency offset in half subcarriers, section type 3 only
+    pub frequency_offset: i32,
+}
+
+/// Section type and its common header fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionType {
+    /// Most downlink and uplink channels
+    Type1,
+    /// PRACH and mixed numerology channels
+    Type3 {
+        /// Offset of the start of the channel from the start of the slot, in Ts
+        time_offset: u16,
+        /// log2 of the FFT size of the channel
+        fft_size_log2: u8,
+        /// Numerology of the channel
+        numerology: u8,
+        /// Cyclic prefix length, in Ts
+        cp_length: u16,
+    },
+}
+
+/// C-plane message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CplaneMessage {
+    pub direction: DataDirection,
+    /// Filter of the channel: 0 for the standard channel filter, PRACH filters otherwise
+    pub filter_index: u8,
+    /// Slot and first symbol of the sections
+    pub time: SymbolTime,
+    pub section_type: SectionType,
+    /// Compression of the U-plane of the sections
+    pub compression: Compression,
+    pub sections: Vec<CplaneSection>,
+}
+
+impl CplaneMessage {
+    /// Encode the message for a carrier of `num_carrier_prbs` PRBs
+    pub fn encode(&self, num_carrier_prbs: u16) -> Bytes {
+        let mut buf = BytesMut::with_capacity(12 + 12 * self.sections.len());
+        buf.put_u8(self.direction.first_octet(self.filter_index));
+        self.time.encode(&mut buf);
+        buf.put_u8(self.sections.len() as u8);
+        match self.section_type {
+            SectionType::Type1 => {
+                buf.put_u8(SECTION_TYPE_1);
+                buf.put_u8(self.compression.ud_comp_hdr());
+                buf.put_u8(0);
+            }
+            SectionType::Type3 { time_offset, fft_size_log2, numerology, cp_length } => {
+                buf.put_u8(SECTION_TYPE_3);
+                buf.put_u16(time_offset);
+                buf.put_u8((fft_size_log2 & 0xF) << 4 | numerology & 0xF);
+                buf.put_u16(cp_length);
+                buf.put_u8(self.compression.ud_comp_hdr());
+            }
+        }
+        for section in &self.sections {
+            encode_section_id(&mut buf, section.section_id, section.start_prb, section.num_prb, num_carrier_prbs);
+            buf.put_u16((section.re_mask & 0xFFF) << 4 | section.num_symbols as u16 & 0xF);
+            // ef cleared: no section extensions
+            buf.put_u16(section.beam_id & 0x7FFF);
+            if let SectionType::Type3 { .. } = self.section_type {
+                buf.put_slice(&section.frequency_offset.to_be_bytes()[1..]);
+                buf.put_u8(0);
+            }
+        }
+        buf.freeze()
+    }
+
+    /// Decode a message for a carrier of `num_carrier_prbs` PRBs
+    pub fn decode(data: &[u8], num_carrier_prbs: u16) -> Result<Self, InterfaceError> {
+        if data.len() < 8 {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let (direction, filter_index) = DataDirection::from_first_octet(data[0])?;
+        let time = SymbolTime::decode(&data[1..])?;
+        let num_sections = data[4] as usize;
+        let (section_type, compression, mut offset, section_len) = match data[5] {
+            SECTION_TYPE_1 => (SectionType::Type1, Compression::from_ud_comp_hdr(data[6])?, 8, 8),
+            SECTION_TYPE_3 => {
+                let header = data.get(6..12).ok_or(InterfaceError::InvalidMessage)?;
+                let section_type = SectionType::Type3 {
+                    time_offset: u16::from_be_bytes([header[0], header[1]]),
+                    fft_size_log2: header[2] >> 4,
+                    numerology: header[2] & 0xF,
+                    cp_length: u16::from_be_bytes([header[3], header[4]]),
+                };
+                (section_type, Compression::from_ud_comp_hdr(header[5])?, 12, 12)
+            }
+            _ => return Err(InterfaceError::InvalidMessage),
+        };
+
+        let mut sections = Vec::with_capacity(num_sections);
+        for _ in 0..num_sections {
+            let section = data.get(offset..offset + section_len).ok_or(InterfaceError::InvalidMessage)?;
+            if section[6] & 0x80 != 0 {
+                // Section extensions are not supported
+                return Err(InterfaceError::InvalidMessage);
+            }
+            let (section_id, start_prb, num_prb) = decode_section_id(section, num_carrier_prbs)?;
+            let frequency_offset = match section_type {
+                // Sign extend the 24 bit offset
+                SectionType::Type3 { .. } => i32::from_be_bytes([section[8], section[9], section[10], 0]) >> 8,
+                SectionType::Type1 => 0,
+            };
+            sections.push(CplaneSection {
+                section_id,
+                start_prb,
+                num_prb,
+                re_mask: u16::from_be_bytes([section[4], section[5]]) >> 4,
+                num_symbols: section[5] & 0xF,
+                beam_id: u16::from_be_bytes([section[6], section[7]]),
+                frequency_offset,
+            });
+            offset += section_len;
+        }
+        Ok(Self { direction, filter_index, time, section_type, compression, sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cplane_messages() {
+        let section = CplaneSection {
+            section_id: 0xABC,
+            start_prb: 0,
+            num_prb: 273,
+            re_mask: 0xFFF,
+            num_symbols: 14,
+            beam_id: 0,
+            frequency_offset: 0,
+        };
+        let type1 = CplaneMessage {
+            direction: DataDirection::Downlink,
+            filter_index: 0,
+            time: SymbolTime { frame_id: 12, subframe_id: 3, slot_id: 1, symbol_id: 0 },
+            section_type: SectionType::Type1,
+            compression: Compression::bfp(9).unwrap(),
+            sections: vec![section, CplaneSection { section_id: 1, start_prb: 10, num_prb: 20, num_symbols: 2, ..section }],
+        };
+        let encoded = type1.encode(273);
+        assert_eq!(&encoded[..16], &[0x90, 12, 0x30, 0x40, 2, 1, 0x91, 0, 0xAB, 0xC0, 0, 0, 0xFF, 0xFE, 0, 0]);
+        assert_eq!(CplaneMessage::decode(&encoded, 273).unwrap(), type1);
+
+        // PRACH: 139 subcarriers at 1.25 kHz below the carrier centre
+        let type3 = CplaneMessage {
+            direction: DataDirection::Uplink,
+            filter_index: 1,
+            time: SymbolTime { frame_id: 1, subframe_id: 9, slot_id: 0, symbol_id: 0 },
+            section_type: SectionType::Type3 { time_offset: 0, fft_size_log2: 12, numerology: 0, cp_length: 3168 },
+            compression: Compression::bfp(14).unwrap(),
+            sections: vec![CplaneSection { section_id: 2, start_prb: 0, num_prb: 12, num_symbols: 1,
+                                           beam_id: 5, frequency_offset: -8052, ..section }],
+        };
+        let encoded = type3.encode(52);
+        assert_eq!(encoded.len(), 24);
+        assert_eq!(CplaneMessage::decode(&encoded, 52).unwrap(), type3);
+        assert!(CplaneMessage::decode(&encoded[..23], 52).is_err());
+    }
+
+    #[test]
+    fn test_cplane_decode_errors() {
+        let section = CplaneSection {
+            section_id: 1,
+            start_prb: 10,
+            num_prb: 20,
+            re_mask: 0xFFF,
+            num_symbols: 14,
+            beam_id: 0,
+            frequency_offset: 0,
+        };
+        let message = CplaneMessage {
+            direction: DataDirection::Downlink,
+            filter_index: 0,
+            time: SymbolTime { frame_id: 1, subframe_id: 0, slot_id: 0, symbol_id: 0 },
+            section_type: SectionType::Type1,
+            compression: Compression::bfp(9).unwrap(),
+            sections: vec![section],
+        };
+        let encoded = message.encode(52).to_vec();
+        assert!(CplaneMessage::decode(&encoded[..7], 52).is_err());
+
+        let invalid = |offset: usize, value: u8| {
+            let mut other = encoded.clone();
+            other[offset] = value;
+            CplaneMessage::decode(&other, 52).is_err()
+        };
+        // Other payload version, section types 0 and 5, unknown compression
+        // and no compression with less than 16 bits
+        assert!(invalid(0, 0xA0));
+        assert!(invalid(5, 0));
+        assert!(invalid(5, 5));
+        assert!(invalid(6, 0x92));
+        assert!(invalid(6, 0x90));
+        // More sections than carried, a section extension, a section
+        // starting beyond the carrier taking the rest of it
+        assert!(invalid(4, 2));
+        assert!(invalid(14, 0x80));
+        assert!(CplaneMessage::decode(&CplaneMessage {
+            sections: vec![CplaneSection { start_prb: 60, num_prb: 300, ..section }],
+            ..message.clone()
+        }.encode(360), 52).is_err());
+
+        // Section type 3 with its common header truncated
+        let type3 = CplaneMessage {
+            section_type: SectionType::Type3 { time_offset: 0, fft_size_log2: 12, numerology: 0, cp_length: 3168 },
+            sections: vec![],
+            ..message
+        };
+        let encoded = type3.encode(52);
+        assert_eq!(CplaneMessage::decode(&encoded, 52).unwrap(), type3);
+        assert!(CplaneMessage::decode(&encoded[..11], 52).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! eCPRI transport
+//!
+//! The eCPRI common header (eCPRI v2.0 section 3.1.3.1) followed by the
+//! ecpriRtcid/ecpriPcid and ecpriSeqid fields that O-RAN puts in front of
+//! every C-plane and U-plane message (O-RAN.WG4.CUS section 5.1.3.1): the
+//! eAxC the message belongs to and its sequence ID.
+
+use crate::InterfaceError;
+use bytes::{BufMut, BytesMut};
+use std::collections::HashMap;
+
+/// eCPRI protocol revision
+pub const ECPRI_REVISION: u8 = 1;
+/// Length of the common header and the transport fields
+pub const TRANSPORT_HEADER_LEN: usize = 8;
+/// Length of the eCPRI common header
+const COMMON_HEADER_LEN: usize = 4;
+/// E bit of ecpriSeqid: last (here only) message of the sequence
+const E_BIT: u8 = 0x80;
+
+/// eCPRI message type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EcpriMessageType {
+    /// IQ data, carrying U-plane messages
+    IqData = 0,
+    /// Real-time control data, carrying C-plane messages
+    RealTimeControl = 2,
+}
+
+/// eAxC ID: the antenna carrier a message belongs to, with 4 bits for each of
+/// the DU port, band sector, component carrier and RU port IDs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EaxcId {
+    pub du_port: u8,
+    pub band_sector: u8,
+    pub cc: u8,
+    pub ru_port: u8,
+}
+
+impl EaxcId {
+    /// eAxC ID of an RU port on the first DU port, band sector and carrier
+    pub const fn ru_port(ru_port: u8) -> Self {
+        Self { du_port: 0, band_sector: 0, cc: 0, ru_port }
+    }
+
+    /// 16 bit ecpriRtcid/ecpriPcid value
+    pub fn to_u16(&self) -> u16 {
+        (self.du_port as u16 & 0xF) << 12 | (self.band_sector as u16 & 0xF) << 8
+            | (self.cc as u16 & 0xF) << 4 | self.ru_port as u16 & 0xF
+    }
+
+    /// eAxC ID of an ecpriRtcid/ecpriPcid value
+    pub fn from_u16(value: u16) -> Self {
+        Self {
+            du_port: (value >> 12) as u8,
+            band_sector: (value >> 8) as u8 & 0xF,
+            cc: (value >> 4) as u8 & 0xF,
+            ru_port: value as u8 & 0xF,
+        }
+    }
+}
+
+/// eCPRI common header with the O-RAN transport fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcpriHeader {
+    pub message_type: EcpriMessageType,
+    pub eaxc_id: EaxcId,
+    pub seq_id: u8,
+}
+
+impl EcpriHeader {
+    /// Encode the header for a message of `payload_len` bytes following it
+    pub fn encode(&self, payload_len: usize, buf: &mut BytesMut) {
+        buf.put_u8(ECPRI_REVISION << 4);
+        buf.put_u8(self.message_type as u8);
+        // ecpriPayload counts the transport fields too
+        buf.put_u16((payload_len + TRANSPORT_HEADER_LEN - COMMON_HEADER_LEN) as u16);
+        buf.put_u16(self.eaxc_id.to_u16());
+        buf.put_u8(self.seq_id);
+        buf.put_u8(E_BIT);
+    }
+
+    /// Decode the header, returning the message it carries
+    pub fn decode(data: &[u8]) -> Result<(Self, &[u8]), InterfaceError> {
+        if data.len() < TRANSPORT_HEADER_LEN || data[0] >> 4 != ECPRI_REVISION {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let message_type = match data[1] {
+            0 => EcpriMessageType::IqData,
+            2 => EcpriMessageType::RealTimeControl,
+            _ => return Err(InterfaceError::InvalidMessage),
+        };
+        let payload_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let end = COMMON_HEADER_LEN + payload_len;
+        if payload_len < TRANSPORT_HEADER_LEN - COMMON_HEADER_LEN || data.len() < end {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let header = Self {
+            message_type,
+            eaxc_id: EaxcId::from_u16(u16::from_be_bytes([data[4], data[5]])),
+            seq_id: data[6],
+        };
+        Ok((header, &data[TRANSPORT_HEADER_LEN..end]))
+    }
+}
+
+/// Outcome of checking the sequence ID of a received message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceCheck {
+    /// First message of the stream or the expected one
+    InOrder,
+    /// Messages are missing or arrived out of order, the count skipped
+    Gap(u8),
+}
+
+/// Sequence IDs of the streams, one per message type and eAxC
+#[derive(Debug, Default)]
+pub struct SequenceIds {
+    next: HashMap<(EcpriMessageType, EaxcId), u8>,
+}
+
+impl SequenceIds {
+    /// Sequence ID of the next message sent on a stream
+    pub fn next_tx(&mut self, message_type: EcpriMessageType, eaxc_id: EaxcId) -> u8 {
+        let next = self.next.entry((message_type, eaxc_id)).or_insert(0);
+        let seq_id = *next;
+        *next = next.wrapping_add(1);
+        seq_id
+    }
+
+    /// Check the sequence ID of a received message against the expected one
+    pub fn check_rx(&mut self, header: &EcpriHeader) -> SequenceCheck {
+        let expected = self.next.insert((header.message_type, header.eaxc_id), header.seq_id.wrapping_add(1));
+        match expected {
+            Some(expected) if expected != header.seq_id => SequenceCheck::Gap(header.seq_id.wrapping_sub(expected)),
+            _ => SequenceCheck::InOrder,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecpri_header() {
+        let header = EcpriHeader {
+            message_type: EcpriMessageType::IqData,
+            eaxc_id: EaxcId { du_port: 1, band_sector: 0, cc: 2, ru_port: 3 },
+            seq_id: 200,
+        };
+        let mut buf = BytesMut::new();
+        header.encode(2, &mut buf);
+        buf.put_slice(&[0xAA, 0xBB]);
+        assert_eq!(&buf[..], &[0x10, 0x00, 0x00, 0x06, 0x10, 0x23, 200, 0x80, 0xAA, 0xBB]);
+        assert_eq!(EcpriHeader::decode(&buf).unwrap(), (header, &[0xAA, 0xBB][..]));
+        assert!(EcpriHeader::decode(&buf[..9]).is_err());
+
+        let mut ids = SequenceIds::default();
+        let eaxc_id = EaxcId::ru_port(0);
+        assert_eq!(ids.next_tx(EcpriMessageType::IqData, eaxc_id), 0);
+        assert_eq!(ids.next_tx(EcpriMessageType::IqData, eaxc_id), 1);
+        assert_eq!(ids.next_tx(EcpriMessageType::RealTimeControl, eaxc_id), 0);
+
+        let received = |seq_id| EcpriHeader { message_type: EcpriMessageType::IqData, eaxc_id, seq_id };
+        let mut ids = SequenceIds::default();
+        assert_eq!(ids.check_rx(&received(254)), SequenceCheck::InOrder);
+        assert_eq!(ids.check_rx(&received(255)), SequenceCheck::InOrder);
+        assert_eq!(ids.check_rx(&received(0)), SequenceCheck::InOrder);
+        assert_eq!(ids.check_rx(&received(3)), SequenceCheck::Gap(2));
+    }
+
+    #[test]
+    fn test_ecpri_header_errors() {
+        let header = EcpriHeader { message_type: EcpriMessageType::RealTimeControl, eaxc_id: EaxcId::ru_port(1), seq_id: 0 };
+        let mut buf = BytesMut::new();
+        header.encode(2, &mut buf);
+        buf.put_slice(&[0xAA, 0xBB]);
+
+        // Shorter than the transport fields, other revisions and message types
+        assert!(EcpriHeader::decode(&buf[..TRANSPORT_HEADER_LEN - 1]).is_err());
+        let mut other = buf.clone();
+        other[0] = 0x20;
+        assert!(EcpriHeader::decode(&other).is_err());
+        let mut other = buf.clone();
+        other[1] = 5;
+        assert!(EcpriHeader::decode(&other).is_err());
+
+        // ecpriPayload not covering the transport fields or beyond the data
+        let mut other = buf.clone();
+        other[3] = 3;
+        assert!(EcpriHeader::decode(&other).is_err());
+        let mut other = buf.clone();
+        other[3] = 7;
+        assert!(EcpriHeader::decode(&other).is_err());
+
+        // Padding after the message is not part of it
+        buf.put_slice(&[0, 0]);
+        assert_eq!(EcpriHeader::decode(&buf).unwrap(), (header, &[0xAA, 0xBB][..]));
+        let mut other = buf.clone();
+        other[3] = 4;
+        assert_eq!(EcpriHeader::decode(&other).unwrap(), (header, &[][..]));
+    }
+}
@@ -0,0 +1,483 @@
+//! O-RAN 7.2x Fronthaul
+//!
+//! An alternative to the ZMQ RF driver for O-RUs speaking the O-RAN
+//! control, user and synchronization plane (O-RAN.WG4.CUS): frequency domain
+//! IQ samples in U-plane messages, compressed with block floating point,
+//! announced by C-plane messages of section type 1 or 3, all of them behind
+//! eCPRI headers with sequence IDs and sent over UDP, so that the O-DU can run
+//! against a software O-RU on localhost.
+//!
+//! Messages are sent within the timing windows of the O-DU, waiting for the
+//! start of the window when early, and received uplink U-plane messages are
+//! checked against theirs, both counted in the fronthaul statistics.
+
+pub mod compression;
+pub mod cplane;
+pub mod ecpri;
+pub mod timing;
+pub mod uplane;
+
+pub use compression::Compression;
+pub use cplane::{CplaneMessage, CplaneSection, DataDirection, SectionType};
+pub use ecpri::{EaxcId, EcpriHeader, EcpriMessageType, SequenceCheck, SequenceIds};
+pub use timing::{window_position, AirClock, SymbolTime, TimingWindows, WindowPosition};
+pub use uplane::{UplaneMessage, UplaneSection};
+
+use crate::InterfaceError;
+use bytes::BytesMut;
+use compression::SUBCARRIERS_PER_PRB;
+use num_complex::Complex32;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Default UDP port of the O-DU
+pub const DEFAULT_DU_PORT: u16 = 44000;
+/// Default UDP port of the O-RU
+pub const DEFAULT_RU_PORT: u16 = 44001;
+
+/// Largest datagram received
+const MAX_DATAGRAM_LEN: usize = 9000;
+
+/// O-RAN fronthaul configuration
+#[derive(Debug, Clone)]
+pub struct OranFhConfig {
+    /// Local UDP address
+    pub local_address: String,
+    /// UDP address of the O-RU
+    pub ru_address: String,
+    /// Numerology of the carrier
+    pub numerology: u8,
+    /// PRBs of the carrier
+    pub num_prbs: u16,
+    /// Compression of the U-plane
+    pub compression: Compression,
+    /// Scale from the PHY's samples to 16 bit fixed point
+    pub iq_scale: f32,
+    /// eAxC of the downlink
+    pub dl_eaxc: EaxcId,
+    /// eAxC of the uplink
+    pub ul_eaxc: EaxcId,
+    /// eAxC of PRACH
+    pub prach_eaxc: EaxcId,
+    /// Transmission and reception windows
+    pub timing: TimingWindows,
+    /// Largest UDP payload sent
+    pub mtu: usize,
+}
+
+impl Default for OranFhConfig {
+    fn default() -> Self {
+        Self {
+            local_address: format!("127.0.0.1:{}", DEFAULT_DU_PORT),
+            ru_address: format!("127.0.0.1:{}", DEFAULT_RU_PORT),
+            numerology: 0,
+            num_prbs: 52,
+            compression: Compression::BlockFloatingPoint { iq_width: 9 },
+            iq_scale: 4096.0,
+            dl_eaxc: EaxcId::ru_port(0),
+            ul_eaxc: EaxcId::ru_port(0),
+            prach_eaxc: EaxcId::ru_port(1),
+            timing: TimingWindows::default(),
+            mtu: 1472,
+        }
+    }
+}
+
+impl OranFhConfig {
+    /// Configuration of the comma separated `key=value` device arguments of
+    /// the RU: `local_addr`, `ru_addr`, `iq_width` (16 for no compression),
+    /// `iq_scale` and `mtu`. The numerology and PRBs are left to the PHY
+    /// configuring the carrier.
+    pub fn from_device_args(args: &str) -> Result<Self, InterfaceError> {
+        let mut config = Self::default();
+        for pair in args.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                return Err(InterfaceError::InvalidConfig(format!("Invalid device argument {}", pair)));
+            };
+            let invalid = || InterfaceError::InvalidConfig(format!("Invalid {} {}", key.trim(), value.trim()));
+            match (key.trim(), value.trim()) {
+                ("local_addr", value) => config.local_address = value.to_string(),
+                ("ru_addr", value) => config.ru_address = value.to_string(),
+                ("iq_width", "16") => config.compression = Compression::None,
+                ("iq_width", value) => config.compression = Compression::bfp(value.parse().map_err(|_| invalid())?)?,
+                ("iq_scale", value) => config.iq_scale = value.parse().map_err(|_| invalid())?,
+                ("mtu", value) => config.mtu = value.parse().map_err(|_| invalid())?,
+                (key, _) => debug!("Ignoring fronthaul device argument {}", key),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Fronthaul statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FronthaulStats {
+    pub tx_cplane: u64,
+    pub tx_uplane: u64,
+    pub rx_uplane: u64,
+    /// Messages sent after the end of their window
+    pub tx_late: u64,
+    /// Uplink U-plane messages received before their window
+    pub rx_early: u64,
+    /// Uplink U-plane messages received after their window
+    pub rx_late: u64,
+    /// Uplink U-plane messages received out of sequence
+    pub rx_seq_errors: u64,
+    /// Datagrams dropped as malformed or unexpected
+    pub rx_dropped: u64,
+}
+
+/// O-DU side of an O-RAN 7.2x fronthaul
+pub struct OranFronthaul {
+    config: OranFhConfig,
+    socket: UdpSocket,
+    ru_address: SocketAddr,
+    clock: AirClock,
+    tx_seq: Mutex<SequenceIds>,
+    rx_seq: Mutex<SequenceIds>,
+    stats: Mutex<FronthaulStats>,
+}
+
+impl OranFronthaul {
+    /// Bind the local address, frame 0 of the carrier having started at `epoch`
+    pub async fn bind(config: OranFhConfig, epoch: Instant) -> Result<Self, InterfaceError> {
+        let ru_address = config.ru_address.parse::<SocketAddr>()
+            .map_err(|_| InterfaceError::InvalidConfig(format!("Invalid O-RU address {}", config.ru_address)))?;
+        if config.num_prbs == 0 || config.num_prbs > 1023 {
+            return Err(InterfaceError::InvalidConfig(format!("Invalid number of PRBs {}", config.num_prbs)));
+        }
+        if Self::max_prbs_per_message(&config) == 0 {
+            return Err(InterfaceError::InvalidConfig(format!("MTU {} too small for a PRB", config.mtu)));
+        }
+        let socket = UdpSocket::bind(&config.local_address).await
+            .map_err(|e| InterfaceError::ConnectionFailed(format!("Failed to bind {}: {}", config.local_address, e)))?;
+        info!("O-RAN fronthaul bound to {}, O-RU at {}", config.local_address, ru_address);
+
+        Ok(Self {
+            clock: AirClock::new(epoch, config.numerology),
+            config,
+            socket,
+            ru_address,
+            tx_seq: Mutex::new(SequenceIds::default()),
+            rx_seq: Mutex::new(SequenceIds::default()),
+            stats: Mutex::new(FronthaulStats::default()),
+        })
+    }
+
+    /// Local UDP address
+    pub fn local_address(&self) -> Result<SocketAddr, InterfaceError> {
+        self.socket.local_addr().map_err(|e| InterfaceError::ConnectionFailed(e.to_string()))
+    }
+
+    /// Clock of the carrier
+    pub fn clock(&self) -> &AirClock {
+        &self.clock
+    }
+
+    /// Fronthaul statistics
+    pub fn stats(&self) -> FronthaulStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// C-plane message of section type 1 announcing every PRB of a slot
+    pub fn slot_cplane(&self, direction: DataDirection, time: SymbolTime, section_id: u16) -> CplaneMessage {
+        CplaneMessage {
+            direction,
+            filter_index: 0,
+            time: SymbolTime { symbol_id: 0, ..time },
+            section_type: SectionType::Type1,
+            compression: self.config.compression,
+            sections: vec![CplaneSection {
+                section_id,
+                start_prb: 0,
+                num_prb: self.config.num_prbs,
+                re_mask: 0xFFF,
+                num_symbols: timing::SYMBOLS_PER_SLOT,
+                beam_id: 0,
+                frequency_offset: 0,
+            }],
+        }
+    }
+
+    /// Send a C-plane message in its window, on the PRACH eAxC for section
+    /// type 3 and on the eAxC of its direction otherwise
+    pub async fn send_cplane(&self, message: &CplaneMessage) -> Result<(), InterfaceError> {
+        let eaxc_id = match (message.section_type, message.direction) {
+            (SectionType::Type3 { .. }, _) => self.config.prach_eaxc,
+            (SectionType::Type1, DataDirection::Downlink) => self.config.dl_eaxc,
+            (SectionType::Type1, DataDirection::Uplink) => self.config.ul_eaxc,
+        };
+        let air_time = self.clock.air_time(&message.time, Instant::now());
+        let window = match message.direction {
+            DataDirection::Downlink => self.config.timing.dl_cplane(air_time),
+            DataDirection::Uplink => self.config.timing.ul_cplane(air_time),
+        };
+        self.wait_for_window(window).await;
+
+        let payload = message.encode(self.config.num_prbs);
+        self.send(EcpriMessageType::RealTimeControl, eaxc_id, &payload).await?;
+        self.stats.lock().unwrap().tx_cplane += 1;
+        Ok(())
+    }
+
+    /// Send the samples of every PRB of a downlink symbol in its window, in as
+    /// many U-plane messages as the MTU requires
+    pub async fn send_dl_symbol(&self, time: SymbolTime, section_id: u16, iq: &[Complex32])
+        -> Result<(), InterfaceError> {
+        if iq.len() != self.config.num_prbs as usize * SUBCARRIERS_PER_PRB {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let air_time = self.clock.air_time(&time, Instant::now());
+        self.wait_for_window(self.config.timing.dl_uplane(air_time)).await;
+
+        let max_prbs = Self::max_prbs_per_message(&self.config);
+        for (index, prbs) in iq.chunks(max_prbs * SUBCARRIERS_PER_PRB).enumerate() {
+            let message = UplaneMessage {
+                direction: DataDirection::Downlink,
+                filter_index: 0,
+                time,
+                sections: vec![UplaneSection {
+                    section_id,
+                    start_prb: (index * max_prbs) as u16,
+                    num_prb: (prbs.len() / SUBCARRIERS_PER_PRB) as u16,
+                    iq: prbs.to_vec(),
+                }],
+            };
+            let payload = message.encode(self.config.compression, self.config.iq_scale, self.config.num_prbs);
+            self.send(EcpriMessageType::IqData, self.config.dl_eaxc, &payload).await?;
+            self.stats.lock().unwrap().tx_uplane += 1;
+        }
+        Ok(())
+    }
+
+    /// Receive the next uplink U-plane message, dropping malformed datagrams
+    /// and counting those received out of sequence or out of their window
+    pub async fn recv_uplane(&self) -> Result<(EaxcId, UplaneMessage), InterfaceError> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            let (len, source) = self.socket.recv_from(&mut buf).await
+                .map_err(|e| InterfaceError::ConnectionFailed(format!("Fronthaul receive failed: {}", e)))?;
+            let now = Instant::now();
+            let message = match self.decode_uplane(&buf[..len], source) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Dropping fronthaul datagram from {}: {}", source, e);
+                    self.stats.lock().unwrap().rx_dropped += 1;
+                    continue;
+                }
+            };
+            let (header, message) = message;
+
+            let sequence = self.rx_seq.lock().unwrap().check_rx(&header);
+            let air_time = self.clock.air_time(&message.time, now);
+            let position = window_position(now, self.config.timing.ul_uplane(air_time));
+
+            let mut stats = self.stats.lock().unwrap();
+            stats.rx_uplane += 1;
+            if let SequenceCheck::Gap(skipped) = sequence {
+                debug!("U-plane sequence gap of {} on eAxC {:?}", skipped, header.eaxc_id);
+                stats.rx_seq_errors += 1;
+            }
+            match position {
+                WindowPosition::Early => stats.rx_early += 1,
+                WindowPosition::Late => stats.rx_late += 1,
+                WindowPosition::OnTime => {}
+            }
+            return Ok((header.eaxc_id, message));
+        }
+    }
+
+    fn decode_uplane(&self, data: &[u8], source: SocketAddr)
+        -> Result<(EcpriHeader, UplaneMessage), InterfaceError> {
+        if source != self.ru_address {
+            return Err(InterfaceError::ConnectionFailed(format!("Unknown source {}", source)));
+        }
+        let (header, payload) = EcpriHeader::decode(data)?;
+        if header.message_type != EcpriMessageType::IqData {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let message = UplaneMessage::decode(payload, self.config.compression, self.config.iq_scale,
+                                            self.config.num_prbs)?;
+        if message.direction != DataDirection::Uplink {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        Ok((header, message))
+    }
+
+    async fn send(&self, message_type: EcpriMessageType, eaxc_id: EaxcId, payload: &[u8])
+        -> Result<(), InterfaceError> {
+        let seq_id = self.tx_seq.lock().unwrap().next_tx(message_type, eaxc_id);
+        let mut buf = BytesMut::with_capacity(ecpri::TRANSPORT_HEADER_LEN + payload.len());
+        EcpriHeader { message_type, eaxc_id, seq_id }.encode(payload.len(), &mut buf);
+        buf.extend_from_slice(payload);
+        self.socket.send_to(&buf, self.ru_address).await
+            .map_err(|e| InterfaceError::ConnectionFailed(format!("Fronthaul send failed: {}", e)))?;
+        Ok(())
+    }
+
+    /// Wait for the start of a transmission window, counting a missed one
+    async fn wait_for_window(&self, window: (Instant, Instant)) {
+        match window_position(Instant::now(), window) {
+            WindowPosition::Early => tokio::time::sleep_until(window.0).await,
+            WindowPosition::Late => {
+                warn!("Fronthaul transmission window missed by {:?}", Instant::now() - window.1);
+                self.stats.lock().unwrap().tx_late += 1;
+            }
+            WindowPosition::OnTime => {}
+        }
+    }
+
+    /// PRBs of one U-plane section fitting in the MTU
+    fn max_prbs_per_message(config: &OranFhConfig) -> usize {
+        let overhead = ecpri::TRANSPORT_HEADER_LEN + uplane::HEADER_LEN + uplane::SECTION_HEADER_LEN;
+        (config.mtu.saturating_sub(overhead) / config.compression.prb_len()).min(u8::MAX as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_config_from_device_args() {
+        let config = OranFhConfig::from_device_args("local_addr=10.0.0.1:44000, ru_addr=10.0.0.2:44001,iq_width=14,mtu=8000")
+            .unwrap();
+        assert_eq!((config.local_address.as_str(), config.ru_address.as_str()), ("10.0.0.1:44000", "10.0.0.2:44001"));
+        assert_eq!(config.mtu, 8000);
+        assert_eq!(config.compression, Compression::BlockFloatingPoint { iq_width: 14 });
+
+        let config = OranFhConfig::from_device_args("iq_width=16").unwrap();
+        assert_eq!((config.compression, config.ru_address), (Compression::None, format!("127.0.0.1:{}", DEFAULT_RU_PORT)));
+        assert!(OranFhConfig::from_device_args("iq_width=17").is_err());
+        assert!(OranFhConfig::from_device_args("mtu").is_err());
+        assert!(OranFhConfig::from_device_args("mtu=large").is_err());
+        assert!(OranFhConfig::from_device_args("iq_scale=-").is_err());
+        assert!(OranFhConfig::from_device_args("iq_width=0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fronthaul_with_ru() {
+        let ru = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = OranFhConfig {
+            local_address: "127.0.0.1:0".to_string(),
+            ru_address: ru.local_addr().unwrap().to_string(),
+            compression: Compression::bfp(14).unwrap(),
+            ..Default::default()
+        };
+        let epoch = Instant::now();
+        let du = OranFronthaul::bind(config.clone(), epoch).await.unwrap();
+        let du_address = du.local_address().unwrap();
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+
+        // Downlink C-plane and U-plane of a symbol a few slots ahead
+        let time = du.clock().symbol_at(Instant::now() + Duration::from_millis(3));
+        du.send_cplane(&du.slot_cplane(DataDirection::Downlink, time, 7)).await.unwrap();
+        let (len, _) = ru.recv_from(&mut buf).await.unwrap();
+        assert!(Instant::now() >= du.clock().air_time(&time, Instant::now()) - config.timing.t1a_cp_dl.0);
+        let (header, payload) = EcpriHeader::decode(&buf[..len]).unwrap();
+        assert_eq!((header.message_type, header.eaxc_id, header.seq_id),
+                   (EcpriMessageType::RealTimeControl, config.dl_eaxc, 0));
+        let cplane = CplaneMessage::decode(payload, config.num_prbs).unwrap();
+        assert_eq!(cplane.sections[0].num_prb, 52);
+
+        let iq: Vec<_> = (0..52 * SUBCARRIERS_PER_PRB).map(|i| Complex32::from_polar(0.7, i as f32)).collect();
+        du.send_dl_symbol(time, 7, &iq).await.unwrap();
+        let mut received = Vec::new();
+        for seq_id in 0..2 {
+            let (len, _) = ru.recv_from(&mut buf).await.unwrap();
+            let (header, payload) = EcpriHeader::decode(&buf[..len]).unwrap();
+            assert!(len <= config.mtu);
+            assert_eq!((header.message_type, header.seq_id), (EcpriMessageType::IqData, seq_id));
+            let uplane = UplaneMessage::decode(payload, config.compression, config.iq_scale, 52).unwrap();
+            assert_eq!((uplane.time, uplane.sections[0].section_id), (time, 7));
+            received.extend(uplane.sections[0].iq.iter().copied());
+        }
+        assert_eq!(received.len(), iq.len());
+        assert!(received.iter().zip(&iq).all(|(received, sent)| (received - sent).norm() < 1e-3));
+
+        // Uplink U-plane from the O-RU, late, early and out of sequence
+        let send_ul = |time: SymbolTime, seq_id: u8| {
+            let message = UplaneMessage {
+                direction: DataDirection::Uplink,
+                filter_index: 0,
+                time,
+                sections: vec![UplaneSection { section_id: 3, start_prb: 0, num_prb: 2, iq: iq[..24].to_vec() }],
+            };
+            let payload = message.encode(config.compression, config.iq_scale, 52);
+            let mut datagram = BytesMut::new();
+            EcpriHeader { message_type: EcpriMessageType::IqData, eaxc_id: config.ul_eaxc, seq_id }
+                .encode(payload.len(), &mut datagram);
+            datagram.extend_from_slice(&payload);
+            datagram
+        };
+        let past = du.clock().symbol_at(Instant::now() - Duration::from_millis(10));
+        ru.send_to(&send_ul(past, 0), du_address).await.unwrap();
+        let (eaxc_id, message) = du.recv_uplane().await.unwrap();
+        assert_eq!((eaxc_id, message.time, message.sections[0].num_prb), (config.ul_eaxc, past, 2));
+        assert!((message.sections[0].iq[5] - iq[5]).norm() < 1e-3);
+
+        ru.send_to(&[0x10, 0, 0], du_address).await.unwrap();
+        let future = du.clock().symbol_at(Instant::now() + Duration::from_millis(5));
+        ru.send_to(&send_ul(future, 2), du_address).await.unwrap();
+        du.recv_uplane().await.unwrap();
+
+        let stats = du.stats();
+        assert_eq!((stats.tx_cplane, stats.tx_uplane, stats.rx_uplane), (1, 2, 2));
+        assert_eq!((stats.rx_late, stats.rx_early, stats.rx_seq_errors, stats.rx_dropped), (1, 1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_fronthaul_failures() {
+        // O-RU address, carrier and MTU the fronthaul cannot work with
+        let config = OranFhConfig { local_address: "127.0.0.1:0".to_string(), ..Default::default() };
+        for invalid in [
+            OranFhConfig { ru_address: "ru".to_string(), ..config.clone() },
+            OranFhConfig { num_prbs: 0, ..config.clone() },
+            OranFhConfig { num_prbs: 1024, ..config.clone() },
+            OranFhConfig { mtu: 40, ..config.clone() },
+        ] {
+            assert!(matches!(OranFronthaul::bind(invalid, Instant::now()).await, Err(InterfaceError::InvalidConfig(_))));
+        }
+        assert!(matches!(OranFronthaul::bind(OranFhConfig { local_address: "256.0.0.1:0".to_string(), ..config.clone() },
+                                             Instant::now()).await,
+                         Err(InterfaceError::ConnectionFailed(_))));
+
+        let ru = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = OranFhConfig { ru_address: ru.local_addr().unwrap().to_string(), ..config };
+        let du = OranFronthaul::bind(config.clone(), Instant::now()).await.unwrap();
+        let du_address = du.local_address().unwrap();
+
+        // A symbol of other than the carrier's PRBs
+        let time = du.clock().symbol_at(Instant::now());
+        assert!(du.send_dl_symbol(time, 1, &[Complex32::new(0.0, 0.0); 12]).await.is_err());
+
+        // C-plane and downlink messages from the O-RU and datagrams from
+        // elsewhere are dropped
+        let datagram = |message_type, direction| {
+            let message = UplaneMessage {
+                direction,
+                filter_index: 0,
+                time,
+                sections: vec![UplaneSection { section_id: 3, start_prb: 0, num_prb: 1, iq: vec![Complex32::new(0.1, 0.0); 12] }],
+            };
+            let payload = message.encode(config.compression, config.iq_scale, config.num_prbs);
+            let mut datagram = BytesMut::new();
+            EcpriHeader { message_type, eaxc_id: config.ul_eaxc, seq_id: 0 }.encode(payload.len(), &mut datagram);
+            datagram.extend_from_slice(&payload);
+            datagram
+        };
+        let stranger = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        stranger.send_to(&datagram(EcpriMessageType::IqData, DataDirection::Uplink), du_address).await.unwrap();
+        ru.send_to(&datagram(EcpriMessageType::RealTimeControl, DataDirection::Uplink), du_address).await.unwrap();
+        ru.send_to(&datagram(EcpriMessageType::IqData, DataDirection::Downlink), du_address).await.unwrap();
+        ru.send_to(&datagram(EcpriMessageType::IqData, DataDirection::Uplink), du_address).await.unwrap();
+        let (_, message) = tokio::time::timeout(Duration::from_secs(2), du.recv_uplane()).await.unwrap().unwrap();
+        assert_eq!(message.sections[0].section_id, 3);
+        let stats = du.stats();
+        assert_eq!((stats.rx_uplane, stats.rx_dropped, stats.tx_uplane), (1, 3, 0));
+    }
+}
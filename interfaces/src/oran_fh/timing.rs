@@ -0,0 +1,257 @@
+//! Fronthaul timing
+//!
+//! Radio time as the CUS-plane addresses it (frame, subframe, slot and symbol
+//! IDs), the air time of a symbol relative to the start of the 256 frame cycle
+//! and the windows in which the O-DU has to send and receive the messages of a
+//! symbol (O-RAN.WG4.CUS section 4.4): C-plane messages ahead of the U-plane,
+//! both ahead of the air time in downlink, uplink U-plane messages after it.
+//!
+//! Every symbol of a slot is taken to be of the same length, the longer cyclic
+//! prefix of the first symbol of a half subframe being spread over all of
+//! them, which keeps symbol boundaries within a fraction of a microsecond.
+
+use crate::InterfaceError;
+use bytes::{BufMut, BytesMut};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Symbols of a slot with normal cyclic prefix
+pub const SYMBOLS_PER_SLOT: u8 = 14;
+/// Frames of the cycle the 8 bit frame ID wraps around
+const FRAME_CYCLE: u32 = 256;
+/// Duration of a frame
+const FRAME: Duration = Duration::from_millis(10);
+
+/// Radio time of a symbol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SymbolTime {
+    /// System frame number modulo 256
+    pub frame_id: u8,
+    /// Subframe within the frame, 0 to 9
+    pub subframe_id: u8,
+    /// Slot within the subframe, 0 to 2^numerology - 1
+    pub slot_id: u8,
+    /// Symbol within the slot, 0 to 13
+    pub symbol_id: u8,
+}
+
+impl SymbolTime {
+    /// Radio time of a symbol in the PHY's numbering: SFN and slot within the frame
+    pub fn new(sfn: u32, slot: u32, symbol: u8, numerology: u8) -> Self {
+        let slots_per_subframe = 1u32 << numerology;
+        Self {
+            frame_id: (sfn % FRAME_CYCLE) as u8,
+            subframe_id: (slot / slots_per_subframe) as u8,
+            slot_id: (slot % slots_per_subframe) as u8,
+            symbol_id: symbol,
+        }
+    }
+
+    /// Slot within the frame
+    pub fn slot_in_frame(&self, numerology: u8) -> u32 {
+        ((self.subframe_id as u32) << numerology) + self.slot_id as u32
+    }
+
+    /// Offset of the start of the symbol from the start of the frame cycle
+    pub fn offset(&self, numerology: u8) -> Duration {
+        let slot = FRAME / 10 / (1 << numerology);
+        FRAME * self.frame_id as u32 + slot * self.slot_in_frame(numerology)
+            + slot * self.symbol_id as u32 / SYMBOLS_PER_SLOT as u32
+    }
+
+    /// Symbol at an offset from the start of the frame cycle
+    pub fn at_offset(offset: Duration, numerology: u8) -> Self {
+        let symbol = FRAME / 10 / (1 << numerology) / SYMBOLS_PER_SLOT as u32;
+        let symbols = (offset.as_nanos() / symbol.as_nanos()) as u64;
+        let slots = symbols / SYMBOLS_PER_SLOT as u64;
+        let slots_per_frame = 10u64 << numerology;
+        Self::new((slots / slots_per_frame) as u32, (slots % slots_per_frame) as u32,
+                  (symbols % SYMBOLS_PER_SLOT as u64) as u8, numerology)
+    }
+
+    /// Encode frameId followed by the subframe, slot and symbol IDs
+    pub(super) fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.frame_id);
+        buf.put_u16((self.subframe_id as u16 & 0xF) << 12 | (self.slot_id as u16 & 0x3F) << 6
+            | self.symbol_id as u16 & 0x3F);
+    }
+
+    /// Decode the three octets written by [`SymbolTime::encode`]
+    pub(super) fn decode(data: &[u8]) -> Result<Self, InterfaceError> {
+        let [frame_id, high, low, ..] = *data else {
+            return Err(InterfaceError::InvalidMessage);
+        };
+        let ids = u16::from_be_bytes([high, low]);
+        Ok(Self {
+            frame_id,
+            subframe_id: (ids >> 12) as u8,
+            slot_id: (ids >> 6) as u8 & 0x3F,
+            symbol_id: ids as u8 & 0x3F,
+        })
+    }
+}
+
+/// Clock relating radio time to local time
+#[derive(Debug, Clone, Copy)]
+pub struct AirClock {
+    /// Start of frame 0 of a frame cycle
+    epoch: Instant,
+    numerology: u8,
+}
+
+impl AirClock {
+    /// Clock of a carrier whose frame 0 started at `epoch`
+    pub fn new(epoch: Instant, numerology: u8) -> Self {
+        Self { epoch, numerology }
+    }
+
+    /// Numerology of the carrier
+    pub fn numerology(&self) -> u8 {
+        self.numerology
+    }
+
+    /// Symbol on the air at `now`
+    pub fn symbol_at(&self, now: Instant) -> SymbolTime {
+        let cycle = FRAME * FRAME_CYCLE;
+        let since_epoch = now.saturating_duration_since(self.epoch);
+        SymbolTime::at_offset(Duration::from_nanos((since_epoch.as_nanos() % cycle.as_nanos()) as u64), self.numerology)
+    }
+
+    /// Air time of the occurrence of a symbol closest to `now`, the frame ID
+    /// repeating every 2.56 s
+    pub fn air_time(&self, time: &SymbolTime, now: Instant) -> Instant {
+        let cycle = FRAME * FRAME_CYCLE;
+        let since_epoch = now.saturating_duration_since(self.epoch);
+        let cycle_start = self.epoch + cycle * (since_epoch.as_nanos() / cycle.as_nanos()) as u32;
+        let candidate = cycle_start + time.offset(self.numerology);
+        if candidate > now + cycle / 2 {
+            candidate.checked_sub(cycle).filter(|earlier| *earlier >= self.epoch).unwrap_or(candidate)
+        } else if candidate + cycle / 2 < now {
+            candidate + cycle
+        } else {
+            candidate
+        }
+    }
+}
+
+/// Position of an event relative to its window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPosition {
+    Early,
+    OnTime,
+    Late,
+}
+
+/// Transmission and reception windows of the O-DU, relative to the air time
+/// of the symbol a message is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingWindows {
+    /// Earliest and latest advance of downlink C-plane messages (T1a_max_cp_dl, T1a_min_cp_dl)
+    pub t1a_cp_dl: (Duration, Duration),
+    /// Earliest and latest advance of uplink C-plane messages (T1a_max_cp_ul, T1a_min_cp_ul)
+    pub t1a_cp_ul: (Duration, Duration),
+    /// Earliest and latest advance of downlink U-plane messages (T1a_max_up, T1a_min_up)
+    pub t1a_up: (Duration, Duration),
+    /// Earliest and latest delay of uplink U-plane messages (Ta4_min, Ta4_max)
+    pub ta4: (Duration, Duration),
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        let us = Duration::from_micros;
+        Self {
+            t1a_cp_dl: (us(470), us(258)),
+            t1a_cp_ul: (us(429), us(285)),
+            t1a_up: (us(196), us(80)),
+            ta4: (us(25), us(500)),
+        }
+    }
+}
+
+impl TimingWindows {
+    /// Window to send a downlink C-plane message for a symbol in
+    pub fn dl_cplane(&self, air_time: Instant) -> (Instant, Instant) {
+        advance(air_time, self.t1a_cp_dl)
+    }
+
+    /// Window to send an uplink C-plane message for a symbol in
+    pub fn ul_cplane(&self, air_time: Instant) -> (Instant, Instant) {
+        advance(air_time, self.t1a_cp_ul)
+    }
+
+    /// Window to send a downlink U-plane message for a symbol in
+    pub fn dl_uplane(&self, air_time: Instant) -> (Instant, Instant) {
+        advance(air_time, self.t1a_up)
+    }
+
+    /// Window uplink U-plane messages for a symbol are expected in
+    pub fn ul_uplane(&self, air_time: Instant) -> (Instant, Instant) {
+        (air_time + self.ta4.0, air_time + self.ta4.1)
+    }
+}
+
+fn advance(air_time: Instant, (max, min): (Duration, Duration)) -> (Instant, Instant) {
+    let earliest = air_time.checked_sub(max).unwrap_or(air_time);
+    (earliest, air_time.checked_sub(min).unwrap_or(earliest))
+}
+
+/// Position of `time` relative to a window
+pub fn window_position(time: Instant, (start, end): (Instant, Instant)) -> WindowPosition {
+    if time < start {
+        WindowPosition::Early
+    } else if time > end {
+        WindowPosition::Late
+    } else {
+        WindowPosition::OnTime
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_time() {
+        let time = SymbolTime::new(1023, 13, 7, 1);
+        assert_eq!(time, SymbolTime { frame_id: 255, subframe_id: 6, slot_id: 1, symbol_id: 7 });
+        assert_eq!(time.slot_in_frame(1), 13);
+        assert_eq!(time.offset(1), Duration::from_millis(2556) + Duration::from_micros(500 + 250));
+        assert_eq!(SymbolTime::at_offset(time.offset(1), 1), time);
+
+        let mut buf = BytesMut::new();
+        time.encode(&mut buf);
+        assert_eq!(&buf[..], &[255, 0x60, 0x47]);
+        assert_eq!(SymbolTime::decode(&buf).unwrap(), time);
+
+        // The occurrence closest to now is taken across the frame ID wrap
+        let epoch = Instant::now();
+        let clock = AirClock::new(epoch, 0);
+        let now = epoch + Duration::from_millis(2555);
+        assert_eq!(clock.symbol_at(now), SymbolTime { frame_id: 255, subframe_id: 5, slot_id: 0, symbol_id: 0 });
+        let next = SymbolTime { frame_id: 0, subframe_id: 1, slot_id: 0, symbol_id: 0 };
+        assert_eq!(clock.air_time(&next, now), epoch + Duration::from_millis(2561));
+        let previous = SymbolTime { frame_id: 255, subframe_id: 4, slot_id: 0, symbol_id: 0 };
+        assert_eq!(clock.air_time(&previous, now), epoch + Duration::from_millis(2554));
+
+        let windows = TimingWindows::default();
+        let air_time = epoch + Duration::from_millis(10);
+        let (start, end) = windows.dl_cplane(air_time);
+        assert_eq!((air_time - start, air_time - end), (Duration::from_micros(470), Duration::from_micros(258)));
+        let window = windows.ul_uplane(air_time);
+        assert_eq!(window_position(air_time, window), WindowPosition::Early);
+        assert_eq!(window_position(air_time + Duration::from_micros(100), window), WindowPosition::OnTime);
+        assert_eq!(window_position(air_time + Duration::from_millis(1), window), WindowPosition::Late);
+    }
+
+    #[test]
+    fn test_timing_edges() {
+        assert!(SymbolTime::decode(&[255, 0x60]).is_err());
+
+        // Both ends of a window are on time
+        let start = Instant::now();
+        let end = start + Duration::from_micros(100);
+        assert_eq!(window_position(start, (start, end)), WindowPosition::OnTime);
+        assert_eq!(window_position(end, (start, end)), WindowPosition::OnTime);
+        assert_eq!(window_position(end + Duration::from_nanos(1), (start, end)), WindowPosition::Late);
+    }
+}
@@ -0,0 +1,154 @@
+//! U-plane messages
+//!
+//! IQ data messages (O-RAN.WG4.CUS section 8): the frequency domain samples
+//! of one symbol, in sections of contiguous PRBs. The compression is the one
+//! configured statically for the eAxC, so sections carry no udCompHdr.
+
+use super::compression::{Compression, SUBCARRIERS_PER_PRB};
+use super::cplane::{decode_section_id, encode_section_id, DataDirection};
+use super::timing::SymbolTime;
+use crate::InterfaceError;
+use bytes::{Bytes, BufMut, BytesMut};
+use num_complex::Complex32;
+
+/// Length of the common header of a message
+pub const HEADER_LEN: usize = 4;
+/// Length of the header of a section
+pub const SECTION_HEADER_LEN: usize = 4;
+
+/// Section of a U-plane message
+#[derive(Debug, Clone, PartialEq)]
+pub struct UplaneSection {
+    /// Section ID of the C-plane section describing it
+    pub section_id: u16,
+    /// First PRB
+    pub start_prb: u16,
+    /// Number of PRBs
+    pub num_prb: u16,
+    /// Samples of the PRBs, 12 per PRB
+    pub iq: Vec<Complex32>,
+}
+
+/// U-plane message
+#[derive(Debug, Clone, PartialEq)]
+pub struct UplaneMessage {
+    pub direction: DataDirection,
+    /// Filter of the channel: 0 for the standard channel filter, PRACH filters otherwise
+    pub filter_index: u8,
+    /// Symbol the samples belong to
+    pub time: SymbolTime,
+    pub sections: Vec<UplaneSection>,
+}
+
+impl UplaneMessage {
+    /// Length of an encoded message with sections of `num_prbs` PRBs in total
+    pub fn encoded_len(compression: Compression, num_sections: usize, num_prbs: usize) -> usize {
+        HEADER_LEN + num_sections * SECTION_HEADER_LEN + num_prbs * compression.prb_len()
+    }
+
+    /// Encode the message for a carrier of `num_carrier_prbs` PRBs, samples
+    /// being multiplied by `scale` before compression
+    pub fn encode(&self, compression: Compression, scale: f32, num_carrier_prbs: u16) -> Bytes {
+        let num_prbs = self.sections.iter().map(|section| section.num_prb as usize).sum();
+        let mut buf = BytesMut::with_capacity(Self::encoded_len(compression, self.sections.len(), num_prbs));
+        buf.put_u8(self.direction.first_octet(self.filter_index));
+        self.time.encode(&mut buf);
+        for section in &self.sections {
+            encode_section_id(&mut buf, section.section_id, section.start_prb, section.num_prb, num_carrier_prbs);
+            compression.compress(&section.iq[..section.num_prb as usize * SUBCARRIERS_PER_PRB], scale, &mut buf);
+        }
+        buf.freeze()
+    }
+
+    /// Decode a message for a carrier of `num_carrier_prbs` PRBs, sections
+    /// following each other up to the end of the data
+    pub fn decode(data: &[u8], compression: Compression, scale: f32, num_carrier_prbs: u16)
+        -> Result<Self, InterfaceError> {
+        if data.len() < HEADER_LEN {
+            return Err(InterfaceError::InvalidMessage);
+        }
+        let (direction, filter_index) = DataDirection::from_first_octet(data[0])?;
+        let time = SymbolTime::decode(&data[1..])?;
+
+        let mut sections = Vec::new();
+        let mut offset = HEADER_LEN;
+        while offset < data.len() {
+            let (section_id, start_prb, num_prb) = decode_section_id(&data[offset..], num_carrier_prbs)?;
+            offset += SECTION_HEADER_LEN;
+            let iq = compression.decompress(&data[offset..], num_prb as usize, scale)?;
+            offset += num_prb as usize * compression.prb_len();
+            sections.push(UplaneSection { section_id, start_prb, num_prb, iq });
+        }
+        Ok(Self { direction, filter_index, time, sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uplane_message() {
+        let iq = |num_prb: usize| -> Vec<_> {
+            (0..num_prb * SUBCARRIERS_PER_PRB).map(|i| Complex32::from_polar(0.5, i as f32 * 0.3)).collect()
+        };
+        let message = UplaneMessage {
+            direction: DataDirection::Uplink,
+            filter_index: 0,
+            time: SymbolTime { frame_id: 7, subframe_id: 2, slot_id: 0, symbol_id: 13 },
+            sections: vec![
+                UplaneSection { section_id: 1, start_prb: 0, num_prb: 4, iq: iq(4) },
+                UplaneSection { section_id: 2, start_prb: 4, num_prb: 48, iq: iq(48) },
+            ],
+        };
+        let compression = Compression::bfp(14).unwrap();
+        let encoded = message.encode(compression, 4096.0, 52);
+        assert_eq!(encoded.len(), UplaneMessage::encoded_len(compression, 2, 52));
+        assert_eq!(&encoded[..8], &[0x10, 7, 0x20, 0x0D, 0x00, 0x10, 0x00, 4]);
+
+        let decoded = UplaneMessage::decode(&encoded, compression, 4096.0, 52).unwrap();
+        assert_eq!((decoded.direction, decoded.time), (message.direction, message.time));
+        assert_eq!(decoded.sections.len(), 2);
+        for (decoded, section) in decoded.sections.iter().zip(&message.sections) {
+            assert_eq!((decoded.section_id, decoded.start_prb, decoded.num_prb),
+                       (section.section_id, section.start_prb, section.num_prb));
+            for (decoded, sample) in decoded.iq.iter().zip(&section.iq) {
+                assert!((decoded - sample).norm() < 1e-3);
+            }
+        }
+        assert!(UplaneMessage::decode(&encoded[..encoded.len() - 1], compression, 4096.0, 52).is_err());
+    }
+
+    #[test]
+    fn test_uplane_decode_errors() {
+        let compression = Compression::bfp(9).unwrap();
+        let message = UplaneMessage {
+            direction: DataDirection::Uplink,
+            filter_index: 0,
+            time: SymbolTime { frame_id: 1, subframe_id: 0, slot_id: 0, symbol_id: 2 },
+            sections: vec![UplaneSection { section_id: 1, start_prb: 50, num_prb: 2, iq: vec![Complex32::new(0.1, 0.0); 24] }],
+        };
+        let encoded = message.encode(compression, 4096.0, 52);
+
+        // Header only: a message without sections
+        let empty = UplaneMessage::decode(&encoded[..HEADER_LEN], compression, 4096.0, 52).unwrap();
+        assert!(empty.sections.is_empty());
+        assert!(UplaneMessage::decode(&encoded[..HEADER_LEN - 1], compression, 4096.0, 52).is_err());
+
+        // Other payload version, truncated section header, PRBs beyond the data
+        let mut other = encoded.to_vec();
+        other[0] = 0x20;
+        assert!(UplaneMessage::decode(&other, compression, 4096.0, 52).is_err());
+        assert!(UplaneMessage::decode(&encoded[..HEADER_LEN + 2], compression, 4096.0, 52).is_err());
+        let mut other = encoded.to_vec();
+        other[HEADER_LEN + 3] = 3;
+        assert!(UplaneMessage::decode(&other, compression, 4096.0, 52).is_err());
+
+        // The rest of a carrier the section starts beyond, a compression
+        // other than the configured one
+        let mut other = encoded.to_vec();
+        other[HEADER_LEN + 3] = 0;
+        assert!(UplaneMessage::decode(&other, compression, 4096.0, 49).is_err());
+        assert!(UplaneMessage::decode(&encoded, Compression::None, 4096.0, 52).is_err());
+    }
+}
@@ -0,0 +1,756 @@
+//! Bearer Context Management (3GPP TS 37.483 section 8.3)
+//!
+//! The gNB-CU-CP sets up the PDU sessions and DRBs of a UE in the gNB-CU-UP with
+//! Bearer Context Setup the first time and Bearer Context Modification
+//! afterwards, exchanging the NG-U and F1-U tunnels, and removes the UE with
+//! Bearer Context Release.
+
+use super::cu_cp::E1apCuCp;
+use super::cu_up::{drb_lcid, f1u_ue, CuUpBearerContext, CuUpDrb, E1apCuUp};
+use super::pdu::{
+    self, Cause, DrbFailedItem, DrbSetupItem, DrbToModifyItem, DrbToRemoveItem, DrbToSetupItem, E1apPdu,
+    E1apPduType, GnbCuCpUeE1apId, GnbCuUpUeE1apId, NgRanBearerContext, PduSessionResourceFailedItem,
+    PduSessionResourceModifiedItem, PduSessionResourceSetupItem, PduSessionResourceToModifyItem,
+    PduSessionResourceToRemoveItem, PduSessionResourceToSetupItem, SdapConfiguration,
+};
+use super::transport::UE_STREAM;
+use super::E1apProcedureCode;
+use crate::f1ap::user_plane::F1uBearer;
+use crate::gtpu::NgapGtpuMessage;
+use crate::ngap::pdu::{Criticality, GtpTunnel};
+use crate::pdcp::PdcpLayer;
+use crate::rrc::reconfiguration::SdapConfig;
+use crate::rrc::DrbToAddMod;
+use crate::sdap::SdapEntity;
+use crate::{LayerError, ProtocolLayer};
+use common::types::Rnti;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Time RRC may run ahead of NGAP: the NG-U tunnel of a PDU session is opened
+/// just before RRC sets up its DRBs
+const NG_TUNNEL_WAIT: Duration = Duration::from_millis(500);
+
+/// Error for an unsuccessful outcome of a bearer context procedure
+fn outcome_error(pdu: &E1apPdu) -> LayerError {
+    let cause = pdu.optional_ie::<Cause>(pdu::ID_CAUSE).ok().flatten();
+    LayerError::ProcessingError(format!("{:?} rejected by the gNB-CU-UP: {:?}", pdu.procedure(), cause))
+}
+
+/// SDAP-Config of a DRB from its E1 SDAP configuration and QoS flow changes
+fn sdap_config(pdu_session_id: u8, configuration: &SdapConfiguration, add: Vec<u8>, release: Vec<u8>) -> SdapConfig {
+    SdapConfig {
+        pdu_session_id,
+        default_drb: configuration.default_drb,
+        sdap_header_dl: configuration.sdap_header_dl,
+        sdap_header_ul: configuration.sdap_header_ul,
+        mapped_qos_flows_to_add: add,
+        mapped_qos_flows_to_release: release,
+    }
+}
+
+impl E1apCuCp {
+    /// Wait until NGAP opened the NG-U tunnels of the given PDU sessions
+    async fn wait_for_ng_tunnels(&self, ue_id: u32, pdu_session_ids: &[u8]) -> Result<(), LayerError> {
+        let deadline = Instant::now() + NG_TUNNEL_WAIT;
+        loop {
+            // Registered before checking so that no notification is missed
+            let opened = self.ng_tunnel_opened.notified();
+            let contexts = self.bearer_contexts.lock().await;
+            let missing: Vec<u8> = pdu_session_ids.iter()
+                .filter(|id| !contexts.get(&ue_id).is_some_and(|ctx| ctx.ng_tunnels.contains_key(id)))
+                .copied()
+                .collect();
+            drop(contexts);
+            if missing.is_empty() {
+                return Ok(());
+            }
+            if tokio::time::timeout_at(deadline, opened).await.is_err() {
+                return Err(LayerError::InvalidState(
+                    format!("No NG-U tunnel for PDU sessions {:?} of UE {}", missing, ue_id)));
+            }
+        }
+    }
+
+    /// Set up, modify and remove the DRBs of a UE in the gNB-CU-UP
+    ///
+    /// Returns the uplink F1-U tunnel of each DRB set up.
+    pub(super) async fn setup_or_modify_bearers(
+        &self,
+        ue_id: u32,
+        rnti: Rnti,
+        drbs: Vec<DrbToAddMod>,
+        to_release: Vec<u8>,
+    ) -> Result<Vec<(u8, GtpTunnel)>, LayerError> {
+        let _procedure = self.procedure.lock().await;
+        let mut ctx = self.bearer_contexts.lock().await.get(&ue_id).cloned().unwrap_or_default();
+        ctx.rnti = Some(rnti);
+
+        let mut to_setup: BTreeMap<u8, Vec<DrbToSetupItem>> = BTreeMap::new();
+        let mut to_modify: BTreeMap<u8, PduSessionResourceToModifyItem> = BTreeMap::new();
+        for drb in drbs {
+            if let Some(pdu_session_id) = ctx.drbs.get(&drb.drb_id).copied() {
+                let Some(sdap) = drb.sdap_config else {
+                    continue;
+                };
+                to_modify.entry(pdu_session_id)
+                    .or_insert_with(|| PduSessionResourceToModifyItem { pdu_session_id, ..Default::default() })
+                    .drbs_to_modify
+                    .push(DrbToModifyItem {
+                        drb_id: drb.drb_id,
+                        dl_tunnel: None,
+                        qos_flows_to_add: sdap.mapped_qos_flows_to_add,
+                        qos_flows_to_release: sdap.mapped_qos_flows_to_release,
+                    });
+                continue;
+            }
+            let (Some(sdap), Some(pdcp)) = (drb.sdap_config, drb.pdcp_config) else {
+                warn!("DRB {} of UE {} set up without SDAP or PDCP configuration", drb.drb_id, ue_id);
+                continue;
+            };
+            to_setup.entry(sdap.pdu_session_id).or_default().push(DrbToSetupItem {
+                drb_id: drb.drb_id,
+                sdap_configuration: SdapConfiguration {
+                    default_drb: sdap.default_drb,
+                    sdap_header_ul: sdap.sdap_header_ul,
+                    sdap_header_dl: sdap.sdap_header_dl,
+                },
+                pdcp_configuration: pdcp,
+                qos_flows: sdap.mapped_qos_flows_to_add,
+            });
+        }
+        for drb_id in to_release {
+            if let Some(pdu_session_id) = ctx.drbs.get(&drb_id).copied() {
+                to_modify.entry(pdu_session_id)
+                    .or_insert_with(|| PduSessionResourceToModifyItem { pdu_session_id, ..Default::default() })
+                    .drbs_to_remove
+                    .push(DrbToRemoveItem { drb_id });
+            }
+        }
+
+        // DRBs of sessions already in the CU-UP are added by modifying the session
+        let new_sessions: Vec<u8> = to_setup.keys().filter(|id| !ctx.has_session(**id)).copied().collect();
+        for (pdu_session_id, drbs) in to_setup.extract_if(.., |id, _| ctx.has_session(*id)) {
+            to_modify.entry(pdu_session_id)
+                .or_insert_with(|| PduSessionResourceToModifyItem { pdu_session_id, ..Default::default() })
+                .drbs_to_setup = drbs;
+        }
+        if to_setup.is_empty() && to_modify.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.wait_for_ng_tunnels(ue_id, &new_sessions).await?;
+        if let Some(ng_tunnels) = self.bearer_contexts.lock().await.get(&ue_id).map(|ctx| ctx.ng_tunnels.clone()) {
+            ctx.ng_tunnels = ng_tunnels;
+        }
+        let sessions: Vec<PduSessionResourceToSetupItem> = to_setup.into_iter()
+            .map(|(pdu_session_id, drbs)| {
+                let (dl_teid, ng_ul_tunnel) = ctx.ng_tunnels[&pdu_session_id];
+                PduSessionResourceToSetupItem {
+                    pdu_session_id,
+                    ng_ul_tunnel,
+                    ng_dl_tunnel: GtpTunnel { transport_layer_address: self.config.ng_u_address, teid: dl_teid },
+                    drbs,
+                }
+            })
+            .collect();
+
+        let setup: Vec<PduSessionResourceSetupItem>;
+        let mut modified: Vec<PduSessionResourceModifiedItem> = Vec::new();
+        let failed: Vec<PduSessionResourceFailedItem>;
+        match ctx.cu_up_ue_id {
+            None => {
+                let mut bearer_context = NgRanBearerContext::default();
+                bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST, Criticality::Reject, &sessions)?;
+                let request = E1apPdu::initiating(E1apProcedureCode::BearerContextSetup)
+                    .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(ue_id))?
+                    .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST, Criticality::Reject, &bearer_context)?;
+                let outcome = self.request(ue_id, &request).await?;
+                if outcome.pdu_type != E1apPduType::SuccessfulOutcome {
+                    return Err(outcome_error(&outcome));
+                }
+                ctx.cu_up_ue_id = Some(outcome.ie::<GnbCuUpUeE1apId>(pdu::ID_GNB_CU_UP_UE_E1AP_ID)?.0);
+                let response = outcome.ie::<NgRanBearerContext>(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_RESPONSE)?;
+                setup = response.list(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST)?;
+                failed = response.list(pdu::ID_PDU_SESSION_RESOURCE_FAILED_LIST)?;
+            }
+            Some(cu_up_ue_id) => {
+                let to_modify: Vec<PduSessionResourceToModifyItem> = to_modify.into_values().collect();
+                let mut bearer_context = NgRanBearerContext::default();
+                bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_SETUP_MOD_LIST, Criticality::Reject, &sessions)?;
+                bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST, Criticality::Reject, &to_modify)?;
+                let response = self.modify_bearer_context(ue_id, cu_up_ue_id, &bearer_context).await?;
+                setup = response.list(pdu::ID_PDU_SESSION_RESOURCE_SETUP_MOD_LIST)?;
+                failed = response.list(pdu::ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST)?;
+                modified = response.list(pdu::ID_PDU_SESSION_RESOURCE_MODIFIED_LIST)?;
+                for drb_id in to_modify.iter().flat_map(|session| &session.drbs_to_remove) {
+                    ctx.drbs.remove(&drb_id.drb_id);
+                }
+            }
+        }
+
+        for session in &failed {
+            warn!("gNB-CU-UP failed to set up PDU session {} of UE {}: {:?}", session.pdu_session_id, ue_id, session.cause);
+        }
+        let mut ul_tunnels = Vec::new();
+        let drbs_setup = setup.into_iter()
+            .map(|session| (session.pdu_session_id, session.drbs_setup, session.drbs_failed))
+            .chain(modified.into_iter().map(|session| {
+                (session.pdu_session_id, session.drbs_setup, session.drbs_failed)
+            }));
+        for (pdu_session_id, drbs, drbs_failed) in drbs_setup {
+            for drb in drbs_failed {
+                warn!("gNB-CU-UP failed to set up DRB {} of UE {}: {:?}", drb.drb_id, ue_id, drb.cause);
+            }
+            for drb in drbs {
+                ctx.drbs.insert(drb.drb_id, pdu_session_id);
+                ul_tunnels.push((drb.drb_id, drb.ul_tunnel));
+            }
+        }
+        // NGAP may have opened further tunnels meanwhile
+        let mut contexts = self.bearer_contexts.lock().await;
+        let stored = contexts.entry(ue_id).or_default();
+        stored.cu_up_ue_id = ctx.cu_up_ue_id;
+        stored.rnti = ctx.rnti;
+        stored.drbs = ctx.drbs;
+        Ok(ul_tunnels)
+    }
+
+    /// Run Bearer Context Modification, returning the bearer context of the response
+    async fn modify_bearer_context(
+        &self,
+        cu_cp_ue_id: u32,
+        cu_up_ue_id: u32,
+        bearer_context: &NgRanBearerContext,
+    ) -> Result<NgRanBearerContext, LayerError> {
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextModification)
+            .with_ue_ids(cu_cp_ue_id, cu_up_ue_id)?
+            .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_REQUEST, Criticality::Reject, bearer_context)?;
+        let outcome = self.request(cu_cp_ue_id, &request).await?;
+        if outcome.pdu_type != E1apPduType::SuccessfulOutcome {
+            return Err(outcome_error(&outcome));
+        }
+        Ok(outcome.optional_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_RESPONSE)?.unwrap_or_default())
+    }
+
+    /// Give the gNB-CU-UP the downlink F1-U tunnels of DRBs set up by the gNB-DU
+    pub(super) async fn modify_dl_tunnels(&self, rnti: Rnti, tunnels: Vec<(u8, GtpTunnel)>) -> Result<(), LayerError> {
+        let _procedure = self.procedure.lock().await;
+        let (ue_id, ctx) = self.bearer_contexts.lock().await.iter()
+            .find(|(_, ctx)| ctx.rnti == Some(rnti))
+            .map(|(ue_id, ctx)| (*ue_id, ctx.clone()))
+            .ok_or_else(|| LayerError::InvalidState(format!("No bearer context for RNTI {}", rnti.0)))?;
+        let cu_up_ue_id = ctx.cu_up_ue_id
+            .ok_or_else(|| LayerError::InvalidState(format!("UE {} has no bearer context in the gNB-CU-UP", ue_id)))?;
+
+        let mut to_modify: BTreeMap<u8, PduSessionResourceToModifyItem> = BTreeMap::new();
+        for (drb_id, dl_tunnel) in tunnels {
+            let Some(pdu_session_id) = ctx.drbs.get(&drb_id).copied() else {
+                debug!("DRB {} of RNTI {} set up by the DU is not in the gNB-CU-UP", drb_id, rnti.0);
+                continue;
+            };
+            to_modify.entry(pdu_session_id)
+                .or_insert_with(|| PduSessionResourceToModifyItem { pdu_session_id, ..Default::default() })
+                .drbs_to_modify
+                .push(DrbToModifyItem { drb_id, dl_tunnel: Some(dl_tunnel), ..Default::default() });
+        }
+        if to_modify.is_empty() {
+            return Ok(());
+        }
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST, Criticality::Reject,
+                                &to_modify.into_values().collect())?;
+        self.modify_bearer_context(ue_id, cu_up_ue_id, &bearer_context).await?;
+        Ok(())
+    }
+
+    /// Remove PDU sessions released by NGAP
+    pub(super) async fn remove_pdu_sessions(&self, ue_id: u32, pdu_session_ids: &[u8]) -> Result<(), LayerError> {
+        let _procedure = self.procedure.lock().await;
+        let (cu_up_ue_id, to_remove) = {
+            let mut contexts = self.bearer_contexts.lock().await;
+            let Some(ctx) = contexts.get_mut(&ue_id) else {
+                return Ok(());
+            };
+            let to_remove: Vec<PduSessionResourceToRemoveItem> = pdu_session_ids.iter()
+                .filter(|id| ctx.has_session(**id))
+                .map(|id| PduSessionResourceToRemoveItem { pdu_session_id: *id })
+                .collect();
+            ctx.ng_tunnels.retain(|id, _| !pdu_session_ids.contains(id));
+            ctx.drbs.retain(|_, id| !pdu_session_ids.contains(id));
+            (ctx.cu_up_ue_id, to_remove)
+        };
+        let Some(cu_up_ue_id) = cu_up_ue_id.filter(|_| !to_remove.is_empty()) else {
+            return Ok(());
+        };
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_REMOVE_LIST, Criticality::Reject, &to_remove)?;
+        self.modify_bearer_context(ue_id, cu_up_ue_id, &bearer_context).await?;
+        Ok(())
+    }
+
+    /// Remove the bearer context of a UE released by NGAP
+    pub(super) async fn release_bearer_context(&self, ue_id: u32) -> Result<(), LayerError> {
+        let Some(ctx) = self.bearer_contexts.lock().await.remove(&ue_id) else {
+            return Ok(());
+        };
+        let Some(cu_up_ue_id) = ctx.cu_up_ue_id else {
+            return Ok(());
+        };
+        // Bearer Context Release Complete is only logged, nobody waits for it
+        let command = E1apPdu::initiating(E1apProcedureCode::BearerContextRelease)
+            .with_ue_ids(ue_id, cu_up_ue_id)?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NORMAL_RELEASE)?;
+        self.cu_up_sender().await?.send(UE_STREAM, &command).await
+    }
+}
+
+impl E1apCuUp {
+    /// Instantiate PDCP and SDAP for the DRBs of a PDU session and add their
+    /// F1-U tunnels
+    async fn setup_drbs(
+        &self,
+        cu_up_ue_id: u32,
+        ctx: &mut CuUpBearerContext,
+        sdap: &mut SdapEntity,
+        drbs: Vec<DrbToSetupItem>,
+    ) -> (Vec<DrbSetupItem>, Vec<DrbFailedItem>) {
+        let pdu_session_id = sdap.pdu_session_id();
+        let mut setup = Vec::new();
+        let mut failed = Vec::new();
+        for drb in drbs {
+            if ctx.drbs.contains_key(&drb.drb_id) {
+                failed.push(DrbFailedItem { drb_id: drb.drb_id, cause: Cause::MULTIPLE_DRB_ID_INSTANCES });
+                continue;
+            }
+            let mut pdcp = PdcpLayer::new(drb.pdcp_configuration.clone());
+            if let Err(e) = pdcp.initialize().await {
+                warn!("Failed to create PDCP entity for DRB {}: {}", drb.drb_id, e);
+                failed.push(DrbFailedItem { drb_id: drb.drb_id, cause: Cause::NOT_ENOUGH_USER_PLANE_PROCESSING_RESOURCES });
+                continue;
+            }
+            let bearer = F1uBearer::new(f1u_ue(cu_up_ue_id), drb.drb_id, drb_lcid(drb.drb_id));
+            let ul_tunnel = match self.f1u.add_bearer(bearer).await {
+                Ok(ul_tunnel) => ul_tunnel,
+                Err(e) => {
+                    warn!("Failed to add F1-U tunnel of DRB {}: {}", drb.drb_id, e);
+                    failed.push(DrbFailedItem { drb_id: drb.drb_id, cause: Cause::TRANSPORT_RESOURCE_UNAVAILABLE });
+                    continue;
+                }
+            };
+            sdap.configure_drb(drb.drb_id, &sdap_config(pdu_session_id, &drb.sdap_configuration, drb.qos_flows, Vec::new()));
+            ctx.drbs.insert(drb.drb_id, CuUpDrb {
+                pdu_session_id,
+                sdap_configuration: drb.sdap_configuration,
+                pdcp: Arc::new(Mutex::new(pdcp)),
+            });
+            setup.push(DrbSetupItem { drb_id: drb.drb_id, ul_tunnel, qos_flows: sdap.flows_of(drb.drb_id) });
+        }
+        (setup, failed)
+    }
+
+    /// Set up PDU sessions with their DRBs, noting the N3 tunnels to open
+    async fn setup_pdu_sessions(
+        &self,
+        cu_up_ue_id: u32,
+        ctx: &mut CuUpBearerContext,
+        sessions: Vec<PduSessionResourceToSetupItem>,
+        tunnels: &mut Vec<NgapGtpuMessage>,
+    ) -> (Vec<PduSessionResourceSetupItem>, Vec<PduSessionResourceFailedItem>) {
+        let mut setup = Vec::new();
+        let mut failed = Vec::new();
+        for session in sessions {
+            let pdu_session_id = session.pdu_session_id;
+            if ctx.sdap_entities.contains_key(&pdu_session_id) {
+                failed.push(PduSessionResourceFailedItem { pdu_session_id, cause: Cause::MULTIPLE_PDU_SESSION_ID_INSTANCES });
+                continue;
+            }
+            let mut sdap = SdapEntity::new(pdu_session_id);
+            let (drbs_setup, drbs_failed) = self.setup_drbs(cu_up_ue_id, ctx, &mut sdap, session.drbs).await;
+            if drbs_setup.is_empty() {
+                let cause = drbs_failed.first().map(|drb| drb.cause).unwrap_or(Cause::RADIO_NETWORK_UNSPECIFIED);
+                failed.push(PduSessionResourceFailedItem { pdu_session_id, cause });
+                continue;
+            }
+            tunnels.push(NgapGtpuMessage::CreateTunnel {
+                ue_id: cu_up_ue_id,
+                pdu_session_id,
+                dl_teid: session.ng_dl_tunnel.teid,
+                ul_tunnel: session.ng_ul_tunnel,
+            });
+            ctx.sdap_entities.insert(pdu_session_id, sdap);
+            setup.push(PduSessionResourceSetupItem {
+                pdu_session_id,
+                ng_dl_tunnel: session.ng_dl_tunnel,
+                drbs_setup,
+                drbs_failed,
+            });
+        }
+        (setup, failed)
+    }
+
+    /// Modify the DRBs of a PDU session
+    async fn modify_pdu_session(
+        &self,
+        cu_up_ue_id: u32,
+        ctx: &mut CuUpBearerContext,
+        session: PduSessionResourceToModifyItem,
+    ) -> Result<PduSessionResourceModifiedItem, Cause> {
+        let pdu_session_id = session.pdu_session_id;
+        let mut sdap = ctx.sdap_entities.remove(&pdu_session_id).ok_or(Cause::UNKNOWN_PDU_SESSION_ID)?;
+
+        let removed: Vec<u8> = session.drbs_to_remove.iter()
+            .map(|drb| drb.drb_id)
+            .filter(|drb_id| ctx.drbs.get(drb_id).is_some_and(|drb| drb.pdu_session_id == pdu_session_id))
+            .collect();
+        for drb_id in &removed {
+            ctx.drbs.remove(drb_id);
+            sdap.release_drb(*drb_id);
+        }
+        if !removed.is_empty() {
+            self.f1u.remove_bearers(f1u_ue(cu_up_ue_id), Some(&removed)).await;
+        }
+
+        let (drbs_setup, mut drbs_failed) = self.setup_drbs(cu_up_ue_id, ctx, &mut sdap, session.drbs_to_setup).await;
+        for drb in session.drbs_to_modify {
+            let Some(sdap_configuration) = ctx.drbs.get(&drb.drb_id)
+                .filter(|existing| existing.pdu_session_id == pdu_session_id)
+                .map(|existing| existing.sdap_configuration) else {
+                drbs_failed.push(DrbFailedItem { drb_id: drb.drb_id, cause: Cause::RADIO_NETWORK_UNSPECIFIED });
+                continue;
+            };
+            if let Some(dl_tunnel) = drb.dl_tunnel {
+                self.f1u.set_remote(f1u_ue(cu_up_ue_id), drb.drb_id, dl_tunnel).await;
+            }
+            if !drb.qos_flows_to_add.is_empty() || !drb.qos_flows_to_release.is_empty() {
+                let config = sdap_config(pdu_session_id, &sdap_configuration, drb.qos_flows_to_add, drb.qos_flows_to_release);
+                sdap.configure_drb(drb.drb_id, &config);
+            }
+        }
+        ctx.sdap_entities.insert(pdu_session_id, sdap);
+        Ok(PduSessionResourceModifiedItem { pdu_session_id, drbs_setup, drbs_failed })
+    }
+
+    /// Open or release N3 tunnels in the GTP-U endpoint
+    async fn apply_tunnels(&self, tunnels: Vec<NgapGtpuMessage>) {
+        if tunnels.is_empty() {
+            return;
+        }
+        let mut gtpu = self.gtpu.write().await;
+        for message in tunnels {
+            if let Err(e) = gtpu.handle_ngap_message(message).await {
+                warn!("Failed to update N3 tunnels: {}", e);
+            }
+        }
+    }
+
+    /// Handle Bearer Context Setup Request
+    pub(super) async fn handle_bearer_context_setup_request(&self, pdu: &E1apPdu) -> Result<(), LayerError> {
+        let cu_cp_ue_id = pdu.ie::<GnbCuCpUeE1apId>(pdu::ID_GNB_CU_CP_UE_E1AP_ID)?.0;
+        let request = pdu.ie::<NgRanBearerContext>(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST)?;
+        let sessions = request.list(pdu::ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST)?;
+
+        let cu_up_ue_id = self.next_cu_up_ue_id.fetch_add(1, Ordering::Relaxed) as u32;
+        let mut ctx = CuUpBearerContext { cu_cp_ue_id, sdap_entities: HashMap::new(), drbs: HashMap::new() };
+        let mut tunnels = Vec::new();
+        let (setup, failed) = self.setup_pdu_sessions(cu_up_ue_id, &mut ctx, sessions, &mut tunnels).await;
+        if setup.is_empty() {
+            let cause = failed.first().map(|session| session.cause).unwrap_or(Cause::RADIO_NETWORK_UNSPECIFIED);
+            warn!("Bearer Context Setup for gNB-CU-CP UE E1AP ID {} failed: {:?}", cu_cp_ue_id, cause);
+            self.f1u.remove_bearers(f1u_ue(cu_up_ue_id), None).await;
+            let failure = E1apPdu::unsuccessful(E1apProcedureCode::BearerContextSetup)
+                .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(cu_cp_ue_id))?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+            return self.send(UE_STREAM, &failure).await;
+        }
+
+        info!("Bearer context {} for gNB-CU-CP UE E1AP ID {}: PDU sessions {:?}, DRBs {:?}",
+              cu_up_ue_id, cu_cp_ue_id, ctx.sdap_entities.keys().collect::<Vec<_>>(), ctx.drbs.keys().collect::<Vec<_>>());
+        self.bearer_contexts.lock().await.insert(cu_up_ue_id, ctx);
+        self.apply_tunnels(tunnels).await;
+
+        let mut response = NgRanBearerContext::default();
+        response.add_list(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST, Criticality::Reject, &setup)?;
+        response.add_list(pdu::ID_PDU_SESSION_RESOURCE_FAILED_LIST, Criticality::Ignore, &failed)?;
+        let response = E1apPdu::successful(E1apProcedureCode::BearerContextSetup)
+            .with_ue_ids(cu_cp_ue_id, cu_up_ue_id)?
+            .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_RESPONSE, Criticality::Ignore, &response)?;
+        self.send(UE_STREAM, &response).await
+    }
+
+    /// Handle Bearer Context Modification Request
+    pub(super) async fn handle_bearer_context_modification_request(&self, pdu: &E1apPdu) -> Result<(), LayerError> {
+        let cu_cp_ue_id = pdu.ie::<GnbCuCpUeE1apId>(pdu::ID_GNB_CU_CP_UE_E1AP_ID)?.0;
+        let cu_up_ue_id = pdu.ie::<GnbCuUpUeE1apId>(pdu::ID_GNB_CU_UP_UE_E1AP_ID)?.0;
+        let request: NgRanBearerContext = pdu.optional_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_REQUEST)?
+            .unwrap_or_default();
+
+        let mut contexts = self.bearer_contexts.lock().await;
+        let Some(ctx) = contexts.get_mut(&cu_up_ue_id) else {
+            drop(contexts);
+            let failure = E1apPdu::unsuccessful(E1apProcedureCode::BearerContextModification)
+                .with_ue_ids(cu_cp_ue_id, cu_up_ue_id)?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::UNKNOWN_GNB_CU_UP_UE_E1AP_ID)?;
+            return self.send(UE_STREAM, &failure).await;
+        };
+        // A restarted gNB-CU-CP addresses the bearer context with its own ID
+        ctx.cu_cp_ue_id = cu_cp_ue_id;
+
+        let mut tunnels = Vec::new();
+        for session in request.list::<PduSessionResourceToRemoveItem>(pdu::ID_PDU_SESSION_RESOURCE_TO_REMOVE_LIST)? {
+            let pdu_session_id = session.pdu_session_id;
+            ctx.sdap_entities.remove(&pdu_session_id);
+            let removed: Vec<u8> = ctx.drbs.iter()
+                .filter(|(_, drb)| drb.pdu_session_id == pdu_session_id)
+                .map(|(drb_id, _)| *drb_id)
+                .collect();
+            ctx.drbs.retain(|_, drb| drb.pdu_session_id != pdu_session_id);
+            self.f1u.remove_bearers(f1u_ue(cu_up_ue_id), Some(&removed)).await;
+            tunnels.push(NgapGtpuMessage::ReleaseTunnels { ue_id: cu_up_ue_id, pdu_session_ids: vec![pdu_session_id] });
+            info!("PDU session {} of bearer context {} removed", pdu_session_id, cu_up_ue_id);
+        }
+
+        let sessions = request.list(pdu::ID_PDU_SESSION_RESOURCE_TO_SETUP_MOD_LIST)?;
+        let (setup, mut failed) = self.setup_pdu_sessions(cu_up_ue_id, ctx, sessions, &mut tunnels).await;
+        let mut modified = Vec::new();
+        for session in request.list::<PduSessionResourceToModifyItem>(pdu::ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST)? {
+            let pdu_session_id = session.pdu_session_id;
+            match self.modify_pdu_session(cu_up_ue_id, ctx, session).await {
+                Ok(session) => modified.push(session),
+                Err(cause) => failed.push(PduSessionResourceFailedItem { pdu_session_id, cause }),
+            }
+        }
+        drop(contexts);
+        self.apply_tunnels(tunnels).await;
+
+        let mut response = NgRanBearerContext::default();
+        response.add_list(pdu::ID_PDU_SESSION_RESOURCE_SETUP_MOD_LIST, Criticality::Reject, &setup)?;
+        response.add_list(pdu::ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST, Criticality::Ignore, &failed)?;
+        response.add_list(pdu::ID_PDU_SESSION_RESOURCE_MODIFIED_LIST, Criticality::Reject, &modified)?;
+        let response = E1apPdu::successful(E1apProcedureCode::BearerContextModification)
+            .with_ue_ids(cu_cp_ue_id, cu_up_ue_id)?
+            .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_RESPONSE, Criticality::Ignore, &response)?;
+        self.send(UE_STREAM, &response).await
+    }
+
+    /// Handle Bearer Context Release Command
+    pub(super) async fn handle_bearer_context_release_command(&self, pdu: &E1apPdu) -> Result<(), LayerError> {
+        let cu_cp_ue_id = pdu.ie::<GnbCuCpUeE1apId>(pdu::ID_GNB_CU_CP_UE_E1AP_ID)?.0;
+        let cu_up_ue_id = pdu.ie::<GnbCuUpUeE1apId>(pdu::ID_GNB_CU_UP_UE_E1AP_ID)?.0;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+
+        if self.bearer_contexts.lock().await.remove(&cu_up_ue_id).is_some() {
+            info!("Bearer context {} released: {:?}", cu_up_ue_id, cause);
+            self.f1u.remove_bearers(f1u_ue(cu_up_ue_id), None).await;
+            self.apply_tunnels(vec![NgapGtpuMessage::ReleaseUe { ue_id: cu_up_ue_id }]).await;
+        } else {
+            debug!("Release of unknown bearer context {}", cu_up_ue_id);
+        }
+        let complete = E1apPdu::successful(E1apProcedureCode::BearerContextRelease)
+            .with_ue_ids(cu_cp_ue_id, cu_up_ue_id)?;
+        self.send(UE_STREAM, &complete).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e1ap::cu_cp::CuCpBearerContext;
+    use crate::e1ap::{run_e1_cu_cp, run_e1_cu_up};
+    use crate::rrc::reconfiguration::default_drb_pdcp_config;
+    use crate::test_support::{e1ap_cu_cp, e1ap_cu_up, wait_until};
+    use std::net::IpAddr;
+    use tokio::sync::mpsc;
+
+    fn ng_tunnel(teid: u32) -> GtpTunnel {
+        GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 53]), teid }
+    }
+
+    fn drb(drb_id: u8, pdu_session_id: u8) -> DrbToAddMod {
+        DrbToAddMod {
+            drb_id,
+            sdap_config: Some(SdapConfig {
+                pdu_session_id,
+                default_drb: true,
+                sdap_header_dl: true,
+                sdap_header_ul: true,
+                mapped_qos_flows_to_add: vec![1],
+                mapped_qos_flows_to_release: Vec::new(),
+            }),
+            pdcp_config: Some(default_drb_pdcp_config()),
+            reestablish_pdcp: false,
+        }
+    }
+
+    fn drb_to_setup(drb_id: u8) -> DrbToSetupItem {
+        DrbToSetupItem {
+            drb_id,
+            sdap_configuration: SdapConfiguration { default_drb: true, sdap_header_ul: true, sdap_header_dl: true },
+            pdcp_configuration: default_drb_pdcp_config(),
+            qos_flows: vec![1],
+        }
+    }
+
+    fn session_to_setup(pdu_session_id: u8, drbs: &[u8]) -> PduSessionResourceToSetupItem {
+        PduSessionResourceToSetupItem {
+            pdu_session_id,
+            ng_ul_tunnel: ng_tunnel(0x100 + pdu_session_id as u32),
+            ng_dl_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: pdu_session_id as u32 },
+            drbs: drbs.iter().map(|drb_id| drb_to_setup(*drb_id)).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cu_cp_bearer_context_failures() {
+        let cu_cp = e1ap_cu_cp().await;
+        let rnti = Rnti::new(0x4601);
+
+        // NGAP never opens the NG-U tunnel of the session
+        let started = Instant::now();
+        let result = cu_cp.setup_or_modify_bearers(1, rnti, vec![drb(1, 1)], vec![]).await;
+        assert!(matches!(result, Err(LayerError::InvalidState(_))));
+        assert!(started.elapsed() >= NG_TUNNEL_WAIT);
+
+        // DRBs without PDCP configuration are skipped, nothing is requested
+        let mut incomplete = drb(1, 1);
+        incomplete.pdcp_config = None;
+        assert!(cu_cp.setup_or_modify_bearers(1, rnti, vec![incomplete], vec![9]).await.unwrap().is_empty());
+
+        // The tunnel is there but no gNB-CU-UP is connected
+        cu_cp.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id: 1,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: ng_tunnel(0x101),
+        }).await.unwrap();
+        let result = cu_cp.setup_or_modify_bearers(1, rnti, vec![drb(1, 1)], vec![]).await;
+        assert!(matches!(result, Err(LayerError::InvalidState(_))));
+        assert_eq!(cu_cp.bearer_contexts.lock().await[&1].cu_up_ue_id, None);
+
+        // Downlink tunnels of unknown UEs, UEs not in the CU-UP and unknown DRBs
+        let dl_tunnel = GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 52]), teid: 0x77 };
+        let result = cu_cp.modify_dl_tunnels(rnti, vec![(1, dl_tunnel)]).await;
+        assert!(matches!(result, Err(LayerError::InvalidState(_))));
+        cu_cp.bearer_contexts.lock().await.get_mut(&1).unwrap().rnti = Some(rnti);
+        let result = cu_cp.modify_dl_tunnels(rnti, vec![(1, dl_tunnel)]).await;
+        assert!(matches!(result, Err(LayerError::InvalidState(_))));
+        cu_cp.bearer_contexts.lock().await.get_mut(&1).unwrap().cu_up_ue_id = Some(1);
+        cu_cp.modify_dl_tunnels(rnti, vec![(1, dl_tunnel)]).await.unwrap();
+
+        // Sessions the CU-UP does not run only drop their NG-U tunnel
+        cu_cp.remove_pdu_sessions(2, &[1]).await.unwrap();
+        cu_cp.remove_pdu_sessions(1, &[1]).await.unwrap();
+        assert!(cu_cp.bearer_contexts.lock().await[&1].ng_tunnels.is_empty());
+
+        // Release: unknown UE, UE not in the CU-UP, no CU-UP to tell
+        cu_cp.release_bearer_context(2).await.unwrap();
+        cu_cp.bearer_contexts.lock().await.insert(2, CuCpBearerContext::default());
+        cu_cp.release_bearer_context(2).await.unwrap();
+        assert!(matches!(cu_cp.release_bearer_context(1).await, Err(LayerError::InvalidState(_))));
+        assert!(cu_cp.bearer_contexts.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cu_up_bearer_context_failures() {
+        let cu_cp = Arc::new(e1ap_cu_cp().await);
+        let cu_cp_task = tokio::spawn(run_e1_cu_cp(Arc::clone(&cu_cp)));
+        let cu_up = Arc::new(e1ap_cu_up(cu_cp.local_addr().unwrap()).await);
+        let (_downlink_tx, downlink_rx) = mpsc::channel(8);
+        let cu_up_task = tokio::spawn(run_e1_cu_up(Arc::clone(&cu_up), downlink_rx));
+        wait_until(|| async { cu_cp.connected_cu_up().await == Some(2) }).await;
+
+        // Bearer Context Setup without PDU sessions fails
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextSetup)
+            .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(1)).unwrap()
+            .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST, Criticality::Reject,
+                     &NgRanBearerContext::default()).unwrap();
+        let outcome = cu_cp.request(1, &request).await.unwrap();
+        assert_eq!(outcome.pdu_type, E1apPduType::UnsuccessfulOutcome);
+        assert_eq!(outcome.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::RADIO_NETWORK_UNSPECIFIED);
+        assert!(matches!(outcome_error(&outcome), LayerError::ProcessingError(_)));
+        assert_eq!(cu_up.bearer_context_count().await, 0);
+
+        // Repeated PDU session and DRB: the first session is set up, the others fail
+        let sessions = vec![session_to_setup(1, &[1]), session_to_setup(1, &[2]), session_to_setup(2, &[1])];
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST, Criticality::Reject, &sessions).unwrap();
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextSetup)
+            .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(1)).unwrap()
+            .with_ie(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST, Criticality::Reject, &bearer_context).unwrap();
+        let outcome = cu_cp.request(1, &request).await.unwrap();
+        assert_eq!(outcome.pdu_type, E1apPduType::SuccessfulOutcome);
+        let cu_up_ue_id = outcome.ie::<GnbCuUpUeE1apId>(pdu::ID_GNB_CU_UP_UE_E1AP_ID).unwrap().0;
+        let response: NgRanBearerContext = outcome.ie(pdu::ID_SYSTEM_BEARER_CONTEXT_SETUP_RESPONSE).unwrap();
+        let setup: Vec<PduSessionResourceSetupItem> = response.list(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST).unwrap();
+        assert_eq!(setup.iter().map(|session| session.pdu_session_id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(response.list::<PduSessionResourceFailedItem>(pdu::ID_PDU_SESSION_RESOURCE_FAILED_LIST).unwrap(), vec![
+            PduSessionResourceFailedItem { pdu_session_id: 1, cause: Cause::MULTIPLE_PDU_SESSION_ID_INSTANCES },
+            PduSessionResourceFailedItem { pdu_session_id: 2, cause: Cause::MULTIPLE_DRB_ID_INSTANCES },
+        ]);
+        assert_eq!(cu_up.bearer_context_count().await, 1);
+
+        // Modification of an unknown bearer context
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextModification)
+            .with_ue_ids(1, cu_up_ue_id + 1).unwrap();
+        let outcome = cu_cp.request(1, &request).await.unwrap();
+        assert_eq!(outcome.pdu_type, E1apPduType::UnsuccessfulOutcome);
+        assert_eq!(outcome.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::UNKNOWN_GNB_CU_UP_UE_E1AP_ID);
+
+        // Modification of an unknown session, an unknown DRB and a DRB set up twice
+        let to_modify = vec![
+            PduSessionResourceToModifyItem {
+                pdu_session_id: 1,
+                drbs_to_setup: vec![drb_to_setup(1)],
+                drbs_to_modify: vec![DrbToModifyItem { drb_id: 5, ..Default::default() }],
+                drbs_to_remove: vec![DrbToRemoveItem { drb_id: 6 }],
+            },
+            PduSessionResourceToModifyItem { pdu_session_id: 3, ..Default::default() },
+        ];
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(pdu::ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST, Criticality::Reject, &to_modify).unwrap();
+        let response = cu_cp.modify_bearer_context(1, cu_up_ue_id, &bearer_context).await.unwrap();
+        let modified: Vec<PduSessionResourceModifiedItem> = response.list(pdu::ID_PDU_SESSION_RESOURCE_MODIFIED_LIST).unwrap();
+        assert_eq!(modified, vec![PduSessionResourceModifiedItem {
+            pdu_session_id: 1,
+            drbs_setup: Vec::new(),
+            drbs_failed: vec![
+                DrbFailedItem { drb_id: 1, cause: Cause::MULTIPLE_DRB_ID_INSTANCES },
+                DrbFailedItem { drb_id: 5, cause: Cause::RADIO_NETWORK_UNSPECIFIED },
+            ],
+        }]);
+        assert_eq!(response.list::<PduSessionResourceFailedItem>(pdu::ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST).unwrap(),
+                   vec![PduSessionResourceFailedItem { pdu_session_id: 3, cause: Cause::UNKNOWN_PDU_SESSION_ID }]);
+        assert_eq!(cu_up.bearer_contexts.lock().await[&cu_up_ue_id].drbs.len(), 1);
+
+        // Release of an unknown bearer context is completed all the same
+        let command = E1apPdu::initiating(E1apProcedureCode::BearerContextRelease)
+            .with_ue_ids(1, cu_up_ue_id + 1).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NORMAL_RELEASE).unwrap();
+        let outcome = cu_cp.request(1, &command).await.unwrap();
+        assert_eq!(outcome.pdu_type, E1apPduType::SuccessfulOutcome);
+        assert_eq!(cu_up.bearer_context_count().await, 1);
+
+        // Requests missing mandatory IEs
+        let mut incomplete = command.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_CAUSE);
+        assert!(matches!(cu_up.handle_bearer_context_release_command(&incomplete).await,
+                         Err(LayerError::ProcessingError(_))));
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextSetup)
+            .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(1)).unwrap();
+        assert!(matches!(cu_up.handle_bearer_context_setup_request(&request).await,
+                         Err(LayerError::ProcessingError(_))));
+        let request = E1apPdu::initiating(E1apProcedureCode::BearerContextModification)
+            .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(1)).unwrap();
+        assert!(matches!(cu_up.handle_bearer_context_modification_request(&request).await,
+                         Err(LayerError::ProcessingError(_))));
+        assert_eq!(cu_up.bearer_context_count().await, 1);
+
+        cu_cp_task.abort();
+        cu_up_task.abort();
+    }
+}
@@ -0,0 +1,340 @@
+//! gNB-CU-CP side of E1
+//!
+//! Accepts the E1 association of one gNB-CU-UP and stands in for the GTP-U
+//! endpoint towards NGAP: the NG-U tunnels NGAP opens are noted and given to the
+//! CU-UP once RRC sets up the DRBs of their PDU session. Bearer contexts are kept
+//! when the association is lost, the CU-UP keeps forwarding their packets.
+
+use super::pdu::{self, E1apPdu, E1apPduType, GnbCuCpUeE1apId};
+use super::transport::{E1Listener, E1Receiver, E1Sender, E1TransportEvent, UE_STREAM};
+use super::E1apProcedureCode;
+use crate::gtpu::NgapGtpuMessage;
+use crate::ngap::pdu::GtpTunnel;
+use crate::ngap::transport::NgTransportKind;
+use crate::rrc::{DrbToAddMod, RrcCuUpInterface};
+use crate::LayerError;
+use async_trait::async_trait;
+use common::types::Rnti;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex, Notify, RwLock};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// Time the gNB-CU-UP has to answer a UE-associated request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// gNB-CU-CP configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct E1apCuCpConfig {
+    /// Local address the E1 listener is bound to
+    pub bind_address: SocketAddr,
+    /// E1 transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// gNB-CU-CP name sent in GNB-CU-UP E1 Setup Response
+    pub gnb_cu_cp_name: String,
+    /// NG-U address announced to the AMF, the CU-UP receives the downlink on it
+    pub ng_u_address: IpAddr,
+}
+
+/// gNB-CU-UP served by the CU-CP
+pub(super) struct ConnectedCuUp {
+    /// gNB-CU-UP ID
+    pub(super) gnb_cu_up_id: u64,
+    /// Sending side of its association
+    pub(super) sender: Arc<E1Sender>,
+}
+
+/// Bearer context of a UE as known to the CU-CP
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct CuCpBearerContext {
+    /// gNB-CU-UP UE E1AP ID, once Bearer Context Setup succeeded
+    pub(super) cu_up_ue_id: Option<u32>,
+    /// C-RNTI of the UE in the gNB-DU
+    pub(super) rnti: Option<Rnti>,
+    /// NG-U tunnels opened by NGAP: gNB-side TEID and UPF endpoint per PDU session
+    pub(super) ng_tunnels: HashMap<u8, (u32, GtpTunnel)>,
+    /// DRBs set up in the CU-UP and their PDU session
+    pub(super) drbs: HashMap<u8, u8>,
+}
+
+impl CuCpBearerContext {
+    /// Check if the CU-UP runs a PDU session
+    pub(super) fn has_session(&self, pdu_session_id: u8) -> bool {
+        self.drbs.values().any(|session| *session == pdu_session_id)
+    }
+}
+
+/// gNB-CU-CP E1AP entity
+pub struct E1apCuCp {
+    pub(super) config: E1apCuCpConfig,
+    listener: E1Listener,
+    /// Connected gNB-CU-UP
+    pub(super) cu_up: RwLock<Option<ConnectedCuUp>>,
+    /// Bearer contexts indexed by RAN UE NGAP ID, also the gNB-CU-CP UE E1AP ID
+    pub(super) bearer_contexts: Mutex<HashMap<u32, CuCpBearerContext>>,
+    /// Requests waiting for their outcome, by gNB-CU-CP UE E1AP ID
+    pending: Mutex<HashMap<u32, oneshot::Sender<E1apPdu>>>,
+    /// UE-associated procedures run one at a time so that each outcome matches
+    /// its request
+    pub(super) procedure: Mutex<()>,
+    /// Woken when NGAP opens an NG-U tunnel
+    pub(super) ng_tunnel_opened: Notify,
+}
+
+impl E1apCuCp {
+    /// Bind the E1 listener
+    pub async fn new(config: E1apCuCpConfig) -> Result<Self, LayerError> {
+        let listener = E1Listener::bind(config.transport, config.bind_address).await?;
+        info!("gNB-CU-CP {} listening for E1 on {} ({:?})",
+              config.gnb_cu_cp_name, listener.local_addr().unwrap_or(config.bind_address), config.transport);
+        Ok(Self {
+            config,
+            listener,
+            cu_up: RwLock::new(None),
+            bearer_contexts: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            procedure: Mutex::new(()),
+            ng_tunnel_opened: Notify::new(),
+        })
+    }
+
+    /// Local address of the E1 listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// gNB-CU-UP ID of the connected CU-UP
+    pub async fn connected_cu_up(&self) -> Option<u64> {
+        self.cu_up.read().await.as_ref().map(|cu_up| cu_up.gnb_cu_up_id)
+    }
+
+    /// Sending side of the association with the connected CU-UP
+    pub(super) async fn cu_up_sender(&self) -> Result<Arc<E1Sender>, LayerError> {
+        self.cu_up.read().await.as_ref()
+            .map(|cu_up| Arc::clone(&cu_up.sender))
+            .ok_or_else(|| LayerError::InvalidState("No gNB-CU-UP connected".into()))
+    }
+
+    /// Send a UE-associated request and wait for its outcome
+    pub(super) async fn request(&self, cu_cp_ue_id: u32, request: &E1apPdu) -> Result<E1apPdu, LayerError> {
+        let sender = self.cu_up_sender().await?;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(cu_cp_ue_id, tx);
+        if let Err(e) = sender.send(UE_STREAM, request).await {
+            self.pending.lock().await.remove(&cu_cp_ue_id);
+            return Err(e);
+        }
+        match tokio::time::timeout(RESPONSE_TIMEOUT, rx).await {
+            Ok(Ok(outcome)) => Ok(outcome),
+            Ok(Err(_)) => Err(LayerError::ProcessingError(
+                format!("E1 association lost while waiting for {:?}", request.procedure()))),
+            Err(_) => {
+                self.pending.lock().await.remove(&cu_cp_ue_id);
+                Err(LayerError::ProcessingError(format!("No answer from the gNB-CU-UP to {:?}", request.procedure())))
+            }
+        }
+    }
+
+    /// Hand an outcome to the request waiting for it
+    async fn complete_request(&self, pdu: E1apPdu) -> Result<(), LayerError> {
+        let cu_cp_ue_id = pdu.ie::<GnbCuCpUeE1apId>(pdu::ID_GNB_CU_CP_UE_E1AP_ID)?.0;
+        match self.pending.lock().await.remove(&cu_cp_ue_id) {
+            Some(tx) => {
+                let _ = tx.send(pdu);
+            }
+            None if pdu.procedure() == Some(E1apProcedureCode::BearerContextRelease) => {
+                info!("Bearer Context Release Complete for gNB-CU-CP UE E1AP ID {}", cu_cp_ue_id);
+            }
+            None => debug!("Unexpected {:?} outcome for gNB-CU-CP UE E1AP ID {}", pdu.procedure(), cu_cp_ue_id),
+        }
+        Ok(())
+    }
+
+    /// Handle a PDU received on the association of `sender`
+    async fn handle_pdu(&self, pdu: E1apPdu, sender: &Arc<E1Sender>) -> Result<(), LayerError> {
+        use E1apProcedureCode::*;
+
+        match (pdu.pdu_type, pdu.procedure()) {
+            (E1apPduType::InitiatingMessage, Some(GnbCuUpE1Setup)) => self.handle_e1_setup_request(&pdu, sender).await,
+            (E1apPduType::SuccessfulOutcome | E1apPduType::UnsuccessfulOutcome,
+             Some(BearerContextSetup | BearerContextModification | BearerContextRelease)) => {
+                self.complete_request(pdu).await
+            }
+            (pdu_type, procedure) => {
+                debug!("Unhandled E1AP {:?} of procedure {:?} ({})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the CU-UP once its association is gone, keeping the bearer contexts
+    async fn handle_association_lost(&self, sender: &Arc<E1Sender>, reason: &str) {
+        let mut cu_up = self.cu_up.write().await;
+        if !cu_up.as_ref().is_some_and(|cu_up| Arc::ptr_eq(&cu_up.sender, sender)) {
+            debug!("E1 association with {} closed: {}", sender.peer(), reason);
+            return;
+        }
+        if let Some(cu_up) = cu_up.take() {
+            warn!("E1 association with gNB-CU-UP {} lost: {}", cu_up.gnb_cu_up_id, reason);
+        }
+        drop(cu_up);
+
+        // Waiting requests fail right away
+        self.pending.lock().await.clear();
+    }
+
+    /// Handle a message NGAP sends towards the GTP-U endpoint
+    pub async fn handle_ngap_message(&self, message: NgapGtpuMessage) -> Result<(), LayerError> {
+        match message {
+            NgapGtpuMessage::CreateTunnel { ue_id, pdu_session_id, dl_teid, ul_tunnel } => {
+                debug!("NG-U tunnel for UE {} PDU session {}: TEID {:#x}, UPF {}/{:#x}",
+                       ue_id, pdu_session_id, dl_teid, ul_tunnel.transport_layer_address, ul_tunnel.teid);
+                self.bearer_contexts.lock().await
+                    .entry(ue_id)
+                    .or_default()
+                    .ng_tunnels
+                    .insert(pdu_session_id, (dl_teid, ul_tunnel));
+                self.ng_tunnel_opened.notify_waiters();
+                Ok(())
+            }
+            NgapGtpuMessage::ReleaseTunnels { ue_id, pdu_session_ids } => {
+                self.remove_pdu_sessions(ue_id, &pdu_session_ids).await
+            }
+            NgapGtpuMessage::ReleaseUe { ue_id } => self.release_bearer_context(ue_id).await,
+            NgapGtpuMessage::ForwardTunnel { ue_id, pdu_session_id, .. } => {
+                // Data forwarding would need the Bearer Context Modification of
+                // TS 38.463 section 8.3.2 towards the gNB-CU-UP
+                warn!("Xn-U forwarding of UE {} PDU session {} not supported over E1", ue_id, pdu_session_id);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RrcCuUpInterface for E1apCuCp {
+    async fn configure_bearers(
+        &self,
+        ue_id: u32,
+        rnti: Rnti,
+        drbs: Vec<DrbToAddMod>,
+        to_release: Vec<u8>,
+    ) -> Result<Vec<(u8, GtpTunnel)>, LayerError> {
+        self.setup_or_modify_bearers(ue_id, rnti, drbs, to_release).await
+    }
+
+    async fn set_dl_tunnels(&self, rnti: Rnti, tunnels: Vec<(u8, GtpTunnel)>) -> Result<(), LayerError> {
+        self.modify_dl_tunnels(rnti, tunnels).await
+    }
+}
+
+/// Serve one gNB-CU-UP association until it is lost
+async fn run_association(cu_cp: Arc<E1apCuCp>, sender: E1Sender, mut receiver: E1Receiver) {
+    let sender = Arc::new(sender);
+    info!("E1 association from {}", sender.peer());
+    loop {
+        match receiver.recv().await {
+            E1TransportEvent::Data { stream, payload } => {
+                let pdu = match E1apPdu::decode(&payload) {
+                    Ok(pdu) => pdu,
+                    Err(e) => {
+                        warn!("Dropping undecodable E1AP PDU on stream {}: {}", stream, e);
+                        continue;
+                    }
+                };
+                if let Err(e) = cu_cp.handle_pdu(pdu, &sender).await {
+                    warn!("Failed to handle E1AP PDU from {}: {}", sender.peer(), e);
+                }
+            }
+            E1TransportEvent::AssociationLost(reason) => {
+                cu_cp.handle_association_lost(&sender, &reason).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Accept gNB-CU-UP associations until the task is dropped, which closes them
+pub async fn run_e1_cu_cp(cu_cp: Arc<E1apCuCp>) {
+    let mut associations = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = cu_cp.listener.accept() => match accepted {
+                Ok((sender, receiver)) => {
+                    associations.spawn(run_association(Arc::clone(&cu_cp), sender, receiver));
+                }
+                Err(e) => warn!("{}", e),
+            },
+            Some(_) = associations.join_next() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e1ap::pdu::{Cause, GnbCuUpUeE1apId};
+    use crate::ngap::pdu::Criticality;
+    use crate::test_support::{e1_association, e1ap_cu_cp, wait_until};
+
+    #[tokio::test]
+    async fn test_cu_cp_failures() {
+        let cu_cp = Arc::new(e1ap_cu_cp().await);
+        let release = E1apPdu::initiating(E1apProcedureCode::BearerContextRelease)
+            .with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NORMAL_RELEASE).unwrap();
+
+        // No gNB-CU-UP connected
+        assert!(matches!(cu_cp.request(1, &release).await, Err(LayerError::InvalidState(_))));
+        assert!(cu_cp.pending.lock().await.is_empty());
+
+        // Outcomes nobody waits for, outcomes without UE ID, unhandled messages
+        let ((sender, _cu_cp_rx), (_cu_up_tx, _cu_up_rx)) = e1_association().await;
+        let sender = Arc::new(sender);
+        let complete = E1apPdu::successful(E1apProcedureCode::BearerContextRelease).with_ue_ids(1, 1).unwrap();
+        cu_cp.handle_pdu(complete, &sender).await.unwrap();
+        let failure = E1apPdu::unsuccessful(E1apProcedureCode::BearerContextSetup)
+            .with_ie(pdu::ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(7)).unwrap();
+        cu_cp.handle_pdu(failure, &sender).await.unwrap();
+        let response = E1apPdu::successful(E1apProcedureCode::BearerContextSetup)
+            .with_ie(pdu::ID_GNB_CU_UP_UE_E1AP_ID, Criticality::Reject, &GnbCuUpUeE1apId(1)).unwrap();
+        assert!(matches!(cu_cp.handle_pdu(response, &sender).await, Err(LayerError::ProcessingError(_))));
+        cu_cp.handle_pdu(release.clone(), &sender).await.unwrap();
+        assert_eq!(cu_cp.connected_cu_up().await, None);
+
+        // A CU-UP that never answers
+        *cu_cp.cu_up.write().await = Some(ConnectedCuUp { gnb_cu_up_id: 2, sender: Arc::clone(&sender) });
+        assert!(matches!(cu_cp.request(1, &release).await, Err(LayerError::ProcessingError(_))));
+        assert!(cu_cp.pending.lock().await.is_empty());
+
+        // Losing another association keeps the CU-UP, losing its own fails the
+        // waiting request but keeps the bearer contexts
+        cu_cp.bearer_contexts.lock().await.insert(1, CuCpBearerContext { cu_up_ue_id: Some(1), ..Default::default() });
+        let waiting = tokio::spawn({
+            let cu_cp = Arc::clone(&cu_cp);
+            let release = release.clone();
+            async move { cu_cp.request(1, &release).await }
+        });
+        wait_until(|| async { !cu_cp.pending.lock().await.is_empty() }).await;
+        let ((other, _other_cu_cp_rx), _other_cu_up) = e1_association().await;
+        cu_cp.handle_association_lost(&Arc::new(other), "closed").await;
+        assert_eq!(cu_cp.connected_cu_up().await, Some(2));
+        cu_cp.handle_association_lost(&sender, "closed").await;
+        assert!(matches!(waiting.await.unwrap(), Err(LayerError::ProcessingError(_))));
+        assert_eq!(cu_cp.connected_cu_up().await, None);
+        assert_eq!(cu_cp.bearer_contexts.lock().await.len(), 1);
+
+        // NGAP messages for unknown UEs and unsupported forwarding
+        cu_cp.handle_ngap_message(NgapGtpuMessage::ReleaseTunnels { ue_id: 2, pdu_session_ids: vec![1] }).await.unwrap();
+        cu_cp.handle_ngap_message(NgapGtpuMessage::ReleaseUe { ue_id: 2 }).await.unwrap();
+        cu_cp.handle_ngap_message(NgapGtpuMessage::ForwardTunnel {
+            ue_id: 1,
+            pdu_session_id: 1,
+            forwarding_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 54]), teid: 9 },
+        }).await.unwrap();
+        assert_eq!(cu_cp.bearer_contexts.lock().await[&1], CuCpBearerContext { cu_up_ue_id: Some(1), ..Default::default() });
+    }
+}
@@ -0,0 +1,368 @@
+//! gNB-CU-UP side of E1
+//!
+//! Opens the E1 association towards the gNB-CU-CP and runs the bearer contexts
+//! it sets up: per PDU session an N3 tunnel in the GTP-U endpoint and an SDAP
+//! entity, per DRB a PDCP entity and an F1-U tunnel towards the gNB-DU. The
+//! association is re-established after a loss; unlike the gNB-DU, the CU-UP
+//! keeps its bearer contexts meanwhile so user packets keep flowing.
+
+use super::pdu::{E1apPdu, E1apPduType, SdapConfiguration};
+use super::transport::{self, E1Receiver, E1Sender, E1TransportEvent, NON_UE_STREAM};
+use super::E1apProcedureCode;
+use crate::f1ap::nru::NrUFrame;
+use crate::f1ap::user_plane::{F1uBearer, F1uEndpoint};
+use crate::gtpu::pdu::GtpuPdu;
+use crate::gtpu::GtpuLayer;
+use crate::ngap::transport::NgTransportKind;
+use crate::pdcp::PdcpLayer;
+use crate::rrc::reconfiguration::DRB_LCID_OFFSET;
+use crate::rrc::{GtpuRrcMessage, RrcGtpuMessage};
+use crate::sdap::{SdapEntity, SdapUplink};
+use crate::{LayerError, ProtocolLayer};
+use bytes::Bytes;
+use common::types::Rnti;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU16, AtomicU8};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+/// gNB-CU-UP configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct E1apCuUpConfig {
+    /// E1 address of the gNB-CU-CP
+    pub cu_cp_address: SocketAddr,
+    /// Local E1 address
+    pub local_address: SocketAddr,
+    /// E1 transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// gNB-CU-UP ID (36 bits)
+    pub gnb_cu_up_id: u64,
+    /// gNB-CU-UP name sent in GNB-CU-UP E1 Setup Request
+    pub gnb_cu_up_name: String,
+    /// Local F1-U address, given to the gNB-DU for uplink tunnels
+    pub f1u_address: SocketAddr,
+    /// Delay between attempts to (re-)establish the association
+    pub reconnect_interval: Duration,
+}
+
+/// DRB run by the CU-UP
+#[derive(Debug)]
+pub(super) struct CuUpDrb {
+    /// PDU session carried by the DRB
+    pub(super) pdu_session_id: u8,
+    /// SDAP configuration, kept to apply QoS flow changes
+    pub(super) sdap_configuration: SdapConfiguration,
+    /// PDCP entity
+    pub(super) pdcp: Arc<Mutex<PdcpLayer>>,
+}
+
+/// Bearer context of a UE in the CU-UP
+#[derive(Debug)]
+pub(super) struct CuUpBearerContext {
+    /// gNB-CU-CP UE E1AP ID
+    pub(super) cu_cp_ue_id: u32,
+    /// SDAP entities by PDU session
+    pub(super) sdap_entities: HashMap<u8, SdapEntity>,
+    /// DRBs by DRB identity
+    pub(super) drbs: HashMap<u8, CuUpDrb>,
+}
+
+/// gNB-CU-UP E1AP entity
+pub struct E1apCuUp {
+    pub(super) config: E1apCuUpConfig,
+    /// N3 endpoint, tunnels are indexed by gNB-CU-UP UE E1AP ID
+    pub(super) gtpu: Arc<RwLock<GtpuLayer>>,
+    /// Sending side of the association, once E1 Setup succeeded
+    sender: RwLock<Option<Arc<E1Sender>>>,
+    /// Bearer contexts indexed by gNB-CU-UP UE E1AP ID
+    pub(super) bearer_contexts: Mutex<HashMap<u32, CuUpBearerContext>>,
+    /// Next gNB-CU-UP UE E1AP ID, also the UE key of the F1-U bearers
+    pub(super) next_cu_up_ue_id: AtomicU16,
+    /// Next transaction ID of non UE-associated procedures
+    pub(super) next_transaction_id: AtomicU8,
+    /// F1-U tunnels of the DRBs
+    pub(super) f1u: F1uEndpoint,
+}
+
+/// UE key of the F1-U bearers of a bearer context
+pub(super) fn f1u_ue(cu_up_ue_id: u32) -> Rnti {
+    Rnti(cu_up_ue_id as u16)
+}
+
+impl E1apCuUp {
+    /// Bind the F1-U socket
+    pub async fn new(config: E1apCuUpConfig, gtpu: Arc<RwLock<GtpuLayer>>) -> Result<Self, LayerError> {
+        let f1u = F1uEndpoint::bind(config.f1u_address).await?;
+        Ok(Self {
+            config,
+            gtpu,
+            sender: RwLock::new(None),
+            bearer_contexts: Mutex::new(HashMap::new()),
+            next_cu_up_ue_id: AtomicU16::new(1),
+            next_transaction_id: AtomicU8::new(0),
+            f1u,
+        })
+    }
+
+    /// Whether E1 Setup with the gNB-CU-CP succeeded
+    pub async fn is_connected(&self) -> bool {
+        self.sender.read().await.is_some()
+    }
+
+    /// Number of bearer contexts
+    pub async fn bearer_context_count(&self) -> usize {
+        self.bearer_contexts.lock().await.len()
+    }
+
+    /// Send a PDU to the gNB-CU-CP
+    pub(super) async fn send(&self, stream: u16, pdu: &E1apPdu) -> Result<(), LayerError> {
+        let sender = self.sender.read().await.clone()
+            .ok_or_else(|| LayerError::InvalidState("E1 not set up with the gNB-CU-CP".into()))?;
+        sender.send(stream, pdu).await
+    }
+
+    /// Handle a PDU received from the gNB-CU-CP
+    async fn handle_pdu(&self, pdu: E1apPdu) -> Result<(), LayerError> {
+        use E1apProcedureCode::*;
+
+        match (pdu.pdu_type, pdu.procedure()) {
+            (E1apPduType::InitiatingMessage, Some(BearerContextSetup)) => self.handle_bearer_context_setup_request(&pdu).await,
+            (E1apPduType::InitiatingMessage, Some(BearerContextModification)) => {
+                self.handle_bearer_context_modification_request(&pdu).await
+            }
+            (E1apPduType::InitiatingMessage, Some(BearerContextRelease)) => {
+                self.handle_bearer_context_release_command(&pdu).await
+            }
+            (pdu_type, procedure) => {
+                debug!("Unhandled E1AP {:?} of procedure {:?} ({})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Open the association and run E1 Setup
+    async fn connect(&self) -> Result<E1Receiver, LayerError> {
+        let (sender, mut receiver) = transport::connect(self.config.transport, self.config.local_address,
+                                                        self.config.cu_cp_address).await?;
+        sender.send(NON_UE_STREAM, &self.e1_setup_request()?).await?;
+        loop {
+            match receiver.recv().await {
+                E1TransportEvent::Data { payload, .. } => {
+                    let pdu = E1apPdu::decode(&payload)?;
+                    if pdu.procedure() != Some(E1apProcedureCode::GnbCuUpE1Setup) {
+                        debug!("Ignoring E1AP procedure {} before E1 Setup", pdu.procedure_code);
+                        continue;
+                    }
+                    self.handle_e1_setup_outcome(&pdu)?;
+                    break;
+                }
+                E1TransportEvent::AssociationLost(reason) => {
+                    return Err(LayerError::InitializationFailed(format!("E1 Setup aborted: {}", reason)));
+                }
+            }
+        }
+        *self.sender.write().await = Some(Arc::new(sender));
+        Ok(receiver)
+    }
+
+    /// Forget the association once it is gone, the bearer contexts stay
+    async fn handle_association_lost(&self, reason: &str) {
+        warn!("E1 association with gNB-CU-CP {} lost, keeping {} bearer contexts: {}",
+              self.config.cu_cp_address, self.bearer_contexts.lock().await.len(), reason);
+        *self.sender.write().await = None;
+    }
+
+    /// Keep the association with the gNB-CU-CP up
+    async fn run_control_plane(&self) {
+        loop {
+            match self.connect().await {
+                Ok(mut receiver) => loop {
+                    match receiver.recv().await {
+                        E1TransportEvent::Data { stream, payload } => match E1apPdu::decode(&payload) {
+                            Ok(pdu) => {
+                                if let Err(e) = self.handle_pdu(pdu).await {
+                                    warn!("Failed to handle E1AP PDU from gNB-CU-CP: {}", e);
+                                }
+                            }
+                            Err(e) => warn!("Dropping undecodable E1AP PDU on stream {}: {}", stream, e),
+                        },
+                        E1TransportEvent::AssociationLost(reason) => {
+                            self.handle_association_lost(&reason).await;
+                            break;
+                        }
+                    }
+                },
+                Err(e) => warn!("{}", e),
+            }
+            tokio::time::sleep(self.config.reconnect_interval).await;
+        }
+    }
+
+    /// Pass a downlink packet of an N3 tunnel through SDAP and PDCP to F1-U
+    async fn handle_downlink(&self, message: GtpuRrcMessage) -> Result<(), LayerError> {
+        let GtpuRrcMessage::DownlinkData { ue_id, pdu_session_id, qfi, rqi, data } = message;
+        let mut contexts = self.bearer_contexts.lock().await;
+        let ctx = contexts.get_mut(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("No bearer context {}", ue_id)))?;
+        let sdap = ctx.sdap_entities.get_mut(&pdu_session_id)
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No SDAP entity for PDU session {} of bearer context {}", pdu_session_id, ue_id)))?;
+        let (drb_id, sdap_pdu) = sdap.process_downlink(qfi, rqi, data)?;
+        let pdcp = ctx.drbs.get(&drb_id)
+            .map(|drb| drb.pdcp.clone())
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown DRB {} of bearer context {}", drb_id, ue_id)))?;
+        drop(contexts);
+
+        let pdcp_pdu = pdcp.lock().await.process_downlink(sdap_pdu).await?;
+        self.f1u.send_downlink(f1u_ue(ue_id), drb_lcid(drb_id), pdcp_pdu).await
+    }
+
+    /// Pass an uplink PDCP PDU received on F1-U through PDCP and SDAP to N3
+    async fn handle_uplink(&self, cu_up_ue_id: u32, drb_id: u8, data: Bytes) -> Result<(), LayerError> {
+        let contexts = self.bearer_contexts.lock().await;
+        let drb = contexts.get(&cu_up_ue_id)
+            .and_then(|ctx| ctx.drbs.get(&drb_id))
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown DRB {} of bearer context {}", drb_id, cu_up_ue_id)))?;
+        let (pdu_session_id, pdcp) = (drb.pdu_session_id, drb.pdcp.clone());
+        drop(contexts);
+
+        let sdap_pdu = pdcp.lock().await.process_uplink(data).await?;
+
+        let contexts = self.bearer_contexts.lock().await;
+        let sdap = contexts.get(&cu_up_ue_id)
+            .and_then(|ctx| ctx.sdap_entities.get(&pdu_session_id))
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No SDAP entity for PDU session {} of bearer context {}", pdu_session_id, cu_up_ue_id)))?;
+        let uplink = sdap.process_uplink(drb_id, sdap_pdu)?;
+        drop(contexts);
+
+        match uplink {
+            SdapUplink::Data { qfi, data } => {
+                self.gtpu.write().await
+                    .handle_rrc_message(RrcGtpuMessage::UplinkData { ue_id: cu_up_ue_id, pdu_session_id, qfi, data })
+                    .await
+            }
+            SdapUplink::EndMarker { qfi } => {
+                debug!("Bearer context {} moved QoS flow {} off DRB {}", cu_up_ue_id, qfi, drb_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle a G-PDU received on an F1-U tunnel
+    async fn handle_f1u_pdu(&self, teid: u32, bearer: F1uBearer, pdu: GtpuPdu) {
+        if let Some(container) = &pdu.nr_ran_container {
+            match NrUFrame::decode(container) {
+                Ok(NrUFrame::DlDataDeliveryStatus(status)) => {
+                    debug!("DL Data Delivery Status of DRB {} of bearer context {}: desired buffer size {} bytes",
+                           bearer.drb_id, bearer.rnti.0, status.desired_buffer_size);
+                    self.f1u.set_desired_buffer_size(teid, status.desired_buffer_size).await;
+                }
+                Ok(frame) => debug!("Ignoring uplink NR-U frame {:?}", frame),
+                Err(e) => debug!("Invalid NR-U frame on DRB {} of bearer context {}: {}", bearer.drb_id, bearer.rnti.0, e),
+            }
+        }
+        if pdu.payload.is_empty() {
+            return;
+        }
+        if let Err(e) = self.handle_uplink(bearer.rnti.0 as u32, bearer.drb_id, pdu.payload).await {
+            debug!("Dropping uplink packet of DRB {}: {}", bearer.drb_id, e);
+        }
+    }
+}
+
+/// Logical channel of a DRB, the F1-U bearers are looked up by it
+pub(super) fn drb_lcid(drb_id: u8) -> u8 {
+    drb_id + DRB_LCID_OFFSET
+}
+
+/// Run the association with the gNB-CU-CP and the user plane until the task is
+/// dropped; `downlink_rx` receives the packets of the N3 tunnels
+pub async fn run_e1_cu_up(cu_up: Arc<E1apCuUp>, mut downlink_rx: mpsc::Receiver<GtpuRrcMessage>) {
+    info!("gNB-CU-UP {} connecting to gNB-CU-CP at {} ({:?})",
+          cu_up.config.gnb_cu_up_id, cu_up.config.cu_cp_address, cu_up.config.transport);
+    let downlink = async {
+        while let Some(message) = downlink_rx.recv().await {
+            if let Err(e) = cu_up.handle_downlink(message).await {
+                debug!("Dropping downlink packet: {}", e);
+            }
+        }
+    };
+    let uplink = async {
+        loop {
+            let (teid, bearer, pdu) = cu_up.f1u.recv().await;
+            cu_up.handle_f1u_pdu(teid, bearer, pdu).await;
+        }
+    };
+    tokio::join!(cu_up.run_control_plane(), downlink, uplink);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e1ap::pdu::{self, Cause, TransactionId};
+    use crate::e1ap::transport::{E1Listener, UE_STREAM};
+    use crate::ngap::pdu::{Criticality, TimeToWait};
+    use crate::test_support::e1ap_cu_up;
+
+    #[tokio::test]
+    async fn test_cu_up_failures() {
+        let listener = E1Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let cu_up = e1ap_cu_up(listener.local_addr().unwrap()).await;
+
+        // Nothing to send on, no bearer contexts to forward packets of
+        let release = E1apPdu::initiating(E1apProcedureCode::BearerContextRelease);
+        assert!(matches!(cu_up.send(UE_STREAM, &release).await, Err(LayerError::InvalidState(_))));
+        let downlink = GtpuRrcMessage::DownlinkData { ue_id: 1, pdu_session_id: 1, qfi: Some(1), rqi: false, data: Bytes::new() };
+        assert!(matches!(cu_up.handle_downlink(downlink).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu_up.handle_uplink(1, 1, Bytes::from_static(&[0x80, 0x00])).await,
+                         Err(LayerError::InvalidState(_))));
+        cu_up.handle_pdu(E1apPdu::successful(E1apProcedureCode::BearerContextSetup)).await.unwrap();
+
+        // The gNB-CU-CP rejects E1 Setup after an unrelated message
+        let cu_cp = async {
+            let (sender, mut receiver) = listener.accept().await.unwrap();
+            let E1TransportEvent::Data { payload, .. } = receiver.recv().await else {
+                panic!("E1 association lost");
+            };
+            let request = E1apPdu::decode(&payload).unwrap();
+            let transaction_id = request.ie::<TransactionId>(pdu::ID_TRANSACTION_ID).unwrap();
+            sender.send(UE_STREAM, &release).await.unwrap();
+            let failure = E1apPdu::unsuccessful(E1apProcedureCode::GnbCuUpE1Setup)
+                .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &transaction_id).unwrap()
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::CONTROL_PROCESSING_OVERLOAD).unwrap()
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V60s).unwrap();
+            sender.send(NON_UE_STREAM, &failure).await.unwrap();
+            receiver
+        };
+        let (result, _receiver) = tokio::join!(cu_up.connect(), cu_cp);
+        assert!(matches!(result, Err(LayerError::InitializationFailed(_))));
+        assert!(!cu_up.is_connected().await);
+
+        // The gNB-CU-CP goes away before answering
+        let cu_cp = async {
+            let (sender, mut receiver) = listener.accept().await.unwrap();
+            receiver.recv().await;
+            drop(sender);
+        };
+        let (result, _) = tokio::join!(cu_up.connect(), cu_cp);
+        assert!(matches!(result, Err(LayerError::InitializationFailed(_))));
+
+        // Nobody listening
+        drop(listener);
+        assert!(cu_up.connect().await.is_err());
+
+        // Bearer contexts survive the loss of the association
+        cu_up.bearer_contexts.lock().await.insert(1, CuUpBearerContext {
+            cu_cp_ue_id: 1,
+            sdap_entities: HashMap::new(),
+            drbs: HashMap::new(),
+        });
+        cu_up.handle_association_lost("closed").await;
+        assert!(!cu_up.is_connected().await);
+        assert_eq!(cu_up.bearer_context_count().await, 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! E1 Application Protocol (E1AP) Implementation
+//!
+//! Separates the control plane of a gNB-CU, holding RRC and NGAP, from its user
+//! plane, holding PDCP, SDAP and the NG-U tunnels, according to 3GPP TS 37.483.
+//! The two can then run as separate processes: the user plane scales on its own
+//! and keeps forwarding packets while the control plane is restarted.
+//!
+//! On the CU-CP side [`E1apCuCp`] takes the place of the GTP-U endpoint towards
+//! NGAP and implements [`RrcCuUpInterface`](crate::rrc::RrcCuUpInterface), turning
+//! the DRB changes of RRC into Bearer Context Setup, Modification and Release.
+//! On the CU-UP side [`E1apCuUp`] runs the bearer contexts: SDAP and PDCP per DRB
+//! between the N3 tunnels of the GTP-U endpoint and the F1-U tunnels towards the
+//! gNB-DU. Bearer contexts survive the loss of the E1 association.
+
+pub mod bearer_context;
+pub mod cu_cp;
+pub mod cu_up;
+pub mod pdu;
+pub mod setup;
+pub mod transport;
+
+use crate::ngap::pdu::Criticality;
+
+pub use cu_cp::{run_e1_cu_cp, E1apCuCp, E1apCuCpConfig};
+pub use cu_up::{run_e1_cu_up, E1apCuUp, E1apCuUpConfig};
+
+/// E1AP procedure codes (3GPP TS 37.483 section 9.4.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1apProcedureCode {
+    Reset = 0,
+    ErrorIndication = 1,
+    GnbCuUpE1Setup = 3,
+    BearerContextSetup = 8,
+    BearerContextModification = 9,
+    BearerContextRelease = 11,
+}
+
+impl E1apProcedureCode {
+    /// Look up a procedure code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use E1apProcedureCode::*;
+
+        [Reset, ErrorIndication, GnbCuUpE1Setup, BearerContextSetup, BearerContextModification, BearerContextRelease]
+            .into_iter()
+            .find(|procedure| *procedure as u8 == code)
+    }
+
+    /// Criticality of the procedure (3GPP TS 37.483 section 9.4.4): reject for
+    /// class 1 procedures, ignore for class 2
+    pub fn criticality(&self) -> Criticality {
+        match self {
+            E1apProcedureCode::ErrorIndication => Criticality::Ignore,
+            _ => Criticality::Reject,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::wait_until;
+    use crate::gtpu::pdu::{GtpuPdu, PduSessionInformation, GTPU_PORT};
+    use crate::gtpu::{run_gtpu_endpoint, GtpuConfig, GtpuLayer, NgapGtpuMessage};
+    use crate::ngap::pdu::GtpTunnel;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::rrc::reconfiguration::{default_drb_pdcp_config, SdapConfig};
+    use crate::rrc::{DrbToAddMod, RrcCuUpInterface};
+    use crate::ProtocolLayer;
+    use bytes::Bytes;
+    use common::types::Rnti;
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+    use tokio::sync::{mpsc, RwLock};
+    use tokio::time::timeout;
+
+    async fn recv(socket: &UdpSocket) -> GtpuPdu {
+        let mut buf = vec![0u8; 2048];
+        let (len, _) = timeout(Duration::from_secs(2), socket.recv_from(&mut buf)).await.unwrap().unwrap();
+        GtpuPdu::decode(Bytes::copy_from_slice(&buf[..len])).unwrap()
+    }
+
+    async fn cu_cp(bind_address: SocketAddr) -> Arc<E1apCuCp> {
+        Arc::new(E1apCuCp::new(E1apCuCpConfig {
+            bind_address,
+            transport: NgTransportKind::TcpFramed,
+            gnb_cu_cp_name: "cu-cp".into(),
+            ng_u_address: IpAddr::from([127, 0, 0, 1]),
+        }).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_e1_split() {
+        // UPF and gNB-DU F1-U on their own loopback addresses with the standard port
+        let upf_address = IpAddr::from([127, 0, 0, 53]);
+        let upf = UdpSocket::bind(SocketAddr::new(upf_address, GTPU_PORT)).await.unwrap();
+        let du_address = IpAddr::from([127, 0, 0, 52]);
+        let du = UdpSocket::bind(SocketAddr::new(du_address, GTPU_PORT)).await.unwrap();
+
+        let cu_cp_1 = cu_cp("127.0.0.1:0".parse().unwrap()).await;
+        let cu_cp_address = cu_cp_1.local_addr().unwrap();
+        let cu_cp_task = tokio::spawn(run_e1_cu_cp(Arc::clone(&cu_cp_1)));
+
+        let mut gtpu = GtpuLayer::new(GtpuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            external_address: IpAddr::from([127, 0, 0, 1]),
+        });
+        let (downlink_tx, downlink_rx) = mpsc::channel(8);
+        gtpu.set_rrc_channel(downlink_tx);
+        gtpu.initialize().await.unwrap();
+        let n3_address = gtpu.local_addr().unwrap();
+        let gtpu = Arc::new(RwLock::new(gtpu));
+        let gtpu_task = tokio::spawn(run_gtpu_endpoint(Arc::clone(&gtpu)));
+        let cu_up = Arc::new(E1apCuUp::new(E1apCuUpConfig {
+            cu_cp_address,
+            local_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            gnb_cu_up_id: 2,
+            gnb_cu_up_name: "cu-up".into(),
+            f1u_address: "127.0.0.51:2152".parse().unwrap(),
+            reconnect_interval: Duration::from_millis(100),
+        }, Arc::clone(&gtpu)).await.unwrap());
+        let cu_up_task = tokio::spawn(run_e1_cu_up(Arc::clone(&cu_up), downlink_rx));
+
+        // E1 Setup
+        wait_until(|| async { cu_up.is_connected().await && cu_cp_1.connected_cu_up().await == Some(2) }).await;
+
+        // NGAP opens the NG-U tunnel, RRC sets up the DRB in Bearer Context Setup
+        cu_cp_1.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: GtpTunnel { transport_layer_address: upf_address, teid: 0x101 },
+        }).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+        let drb = DrbToAddMod {
+            drb_id: 1,
+            sdap_config: Some(SdapConfig {
+                pdu_session_id: 1,
+                default_drb: true,
+                sdap_header_dl: true,
+                sdap_header_ul: true,
+                mapped_qos_flows_to_add: vec![1],
+                mapped_qos_flows_to_release: Vec::new(),
+            }),
+            pdcp_config: Some(default_drb_pdcp_config()),
+            reestablish_pdcp: false,
+        };
+        let ul_tunnels = cu_cp_1.configure_bearers(1000, rnti, vec![drb], vec![]).await.unwrap();
+        assert_eq!(ul_tunnels.len(), 1);
+        let (drb_id, ul_tunnel) = ul_tunnels[0];
+        assert_eq!((drb_id, ul_tunnel.transport_layer_address), (1, IpAddr::from([127, 0, 0, 51])));
+        assert_eq!(gtpu.read().await.tunnels().len(), 1);
+
+        // The DU tunnel from UE Context Setup Response goes to the CU-UP
+        let dl_tunnel = GtpTunnel { transport_layer_address: du_address, teid: 0x77 };
+        cu_cp_1.set_dl_tunnels(rnti, vec![(1, dl_tunnel)]).await.unwrap();
+
+        // Downlink: N3 -> SDAP -> PDCP -> F1-U
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14, 0x01, 0x02]);
+        let downlink = GtpuPdu::g_pdu(7, Some(PduSessionInformation::Downlink { qfi: 1, rqi: false }), packet.clone());
+        upf.send_to(&downlink.encode(), n3_address).await.unwrap();
+        let f1u = recv(&du).await;
+        assert_eq!(f1u.teid, 0x77);
+        assert_eq!(&f1u.payload[1..], &packet[..]);
+
+        // Uplink: F1-U -> PDCP -> SDAP -> N3
+        let mut sdap_pdu = vec![0x81];
+        sdap_pdu.extend_from_slice(&packet);
+        du.send_to(&GtpuPdu::g_pdu(ul_tunnel.teid, None, Bytes::from(sdap_pdu)).encode(),
+                   SocketAddr::new(ul_tunnel.transport_layer_address, GTPU_PORT)).await.unwrap();
+        let n3 = recv(&upf).await;
+        assert_eq!(n3.teid, 0x101);
+        assert_eq!(n3.pdu_session_information, Some(PduSessionInformation::Uplink { qfi: 1 }));
+        assert_eq!(n3.payload, packet);
+
+        // The CU-CP goes away: the tunnels keep forwarding
+        cu_cp_task.abort();
+        drop(cu_cp_1);
+        wait_until(|| async { !cu_up.is_connected().await }).await;
+        upf.send_to(&downlink.encode(), n3_address).await.unwrap();
+        assert_eq!(recv(&du).await.teid, 0x77);
+
+        // A new CU-CP on the same address gets the CU-UP back
+        let cu_cp_2 = cu_cp(cu_cp_address).await;
+        let cu_cp_task = tokio::spawn(run_e1_cu_cp(Arc::clone(&cu_cp_2)));
+        wait_until(|| async { cu_up.is_connected().await && cu_cp_2.connected_cu_up().await == Some(2) }).await;
+        assert_eq!(gtpu.read().await.tunnels().len(), 1);
+
+        cu_cp_task.abort();
+        cu_up_task.abort();
+        gtpu_task.abort();
+    }
+}
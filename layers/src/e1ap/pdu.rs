@@ -0,0 +1,1073 @@
+//! E1AP PDU and Information Element encoding
+//!
+//! E1AP-PDU structure and protocol IE containers according to 3GPP TS 37.483
+//! section 9.4, encoded with APER. The container machinery, GTP tunnels and
+//! node names come from the NGAP codec. Only NG-RAN bearer contexts are
+//! supported; their items are reduced to what the gNB-CU-UP needs to run SDAP,
+//! PDCP and the NG-U and F1-U tunnels.
+
+use super::E1apProcedureCode;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{
+    decode_ie_container, decode_list, encode_ie_container, encode_list, find_ie, protocol_ie, skip_ie_extensions,
+    ApPdu, ApPduType, ApProcedureCode, AperCodec, Criticality, GtpTunnel, ProtocolIe,
+};
+use crate::pdcp::PdcpConfig;
+use crate::LayerError;
+
+/// Protocol IE identifiers (3GPP TS 37.483 section 9.4.7)
+pub const ID_CAUSE: u16 = 0;
+pub const ID_GNB_CU_CP_UE_E1AP_ID: u16 = 2;
+pub const ID_GNB_CU_UP_UE_E1AP_ID: u16 = 3;
+pub const ID_GNB_CU_UP_ID: u16 = 7;
+pub const ID_GNB_CU_UP_NAME: u16 = 8;
+pub const ID_GNB_CU_CP_NAME: u16 = 9;
+pub const ID_CN_SUPPORT: u16 = 10;
+pub const ID_TIME_TO_WAIT: u16 = 12;
+pub const ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST: u16 = 15;
+pub const ID_SYSTEM_BEARER_CONTEXT_SETUP_RESPONSE: u16 = 16;
+pub const ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_REQUEST: u16 = 18;
+pub const ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_RESPONSE: u16 = 19;
+pub const ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST: u16 = 35;
+pub const ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST: u16 = 36;
+pub const ID_PDU_SESSION_RESOURCE_TO_REMOVE_LIST: u16 = 37;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST: u16 = 39;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_LIST: u16 = 40;
+pub const ID_PDU_SESSION_RESOURCE_MODIFIED_LIST: u16 = 41;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_MOD_LIST: u16 = 47;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST: u16 = 48;
+pub const ID_PDU_SESSION_RESOURCE_TO_SETUP_MOD_LIST: u16 = 49;
+pub const ID_TRANSACTION_ID: u16 = 57;
+
+/// maxnoofPDUSessionResource
+const MAX_PDU_SESSIONS: usize = 256;
+/// maxnoofDRBs
+const MAX_DRBS: usize = 32;
+/// maxnoofQoSFlows
+const MAX_QOS_FLOWS: usize = 64;
+/// maxnoofUPParameters
+const MAX_UP_PARAMETERS: usize = 8;
+
+impl ApProcedureCode for E1apProcedureCode {
+    const PROTOCOL: &'static str = "E1AP";
+    // initiatingMessage, successfulOutcome, unsuccessfulOutcome and choice-extension
+    const PDU_CHOICE: (usize, bool) = (4, false);
+
+    fn from_u8(code: u8) -> Option<Self> {
+        E1apProcedureCode::from_u8(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn criticality(&self) -> Criticality {
+        E1apProcedureCode::criticality(self)
+    }
+}
+
+/// E1AP-PDU choice
+pub type E1apPduType = ApPduType;
+
+/// E1AP PDU
+pub type E1apPdu = ApPdu<E1apProcedureCode>;
+
+impl E1apPdu {
+    /// Add the gNB-CU-CP and gNB-CU-UP UE E1AP IDs of a UE-associated message
+    pub fn with_ue_ids(self, cu_cp_ue_id: u32, cu_up_ue_id: u32) -> Result<Self, LayerError> {
+        self.with_ie(ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(cu_cp_ue_id))?
+            .with_ie(ID_GNB_CU_UP_UE_E1AP_ID, Criticality::Reject, &GnbCuUpUeE1apId(cu_up_ue_id))
+    }
+}
+
+/// NG-RAN alternative of the System-BearerContext* IEs: a protocol IE container
+/// holding the PDU session lists
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NgRanBearerContext {
+    /// Protocol IEs of the container
+    pub ies: Vec<ProtocolIe>,
+}
+
+impl NgRanBearerContext {
+    /// Append an IE
+    pub fn add_ie<T: AperCodec>(&mut self, id: u16, criticality: Criticality, value: &T) -> Result<(), LayerError> {
+        self.ies.push(protocol_ie(id, criticality, value)?);
+        Ok(())
+    }
+
+    /// Append a list IE unless the list is empty
+    pub fn add_list<T>(&mut self, id: u16, criticality: Criticality, items: &Vec<T>) -> Result<(), LayerError>
+    where
+        Vec<T>: AperCodec,
+    {
+        if items.is_empty() {
+            return Ok(());
+        }
+        self.add_ie(id, criticality, items)
+    }
+
+    /// Decode an optional list IE, empty when absent
+    pub fn list<T>(&self, id: u16) -> Result<Vec<T>, LayerError>
+    where
+        Vec<T>: AperCodec,
+    {
+        Ok(find_ie(&self.ies, id)?.unwrap_or_default())
+    }
+}
+
+impl AperCodec for NgRanBearerContext {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // e-UTRAN, nG-RAN and choice-extension alternatives
+        enc.put_choice(1, 3, false)?;
+        encode_ie_container(enc, &self.ies)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(3, false)? != 1 {
+            return Err(LayerError::ProcessingError("Only NG-RAN bearer contexts are supported".into()));
+        }
+        Ok(Self { ies: decode_ie_container(dec)? })
+    }
+}
+
+/// gNB-CU-CP UE E1AP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbCuCpUeE1apId(pub u32);
+
+impl AperCodec for GnbCuCpUeE1apId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// gNB-CU-UP UE E1AP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbCuUpUeE1apId(pub u32);
+
+impl AperCodec for GnbCuUpUeE1apId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// Transaction ID, INTEGER (0..255, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionId(pub u8);
+
+impl AperCodec for TransactionId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 255, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 255, true)? as u8))
+    }
+}
+
+/// gNB-CU-UP ID, INTEGER (0..2^36-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbCuUpId(pub u64);
+
+impl AperCodec for GnbCuUpId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0, 0, (1 << 36) - 1, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, (1 << 36) - 1, false)?))
+    }
+}
+
+/// CN Support, ENUMERATED {c-epc, c-5gc, both, ...}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CnSupport {
+    Epc = 0,
+    FiveGc = 1,
+    Both = 2,
+}
+
+impl AperCodec for CnSupport {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 3, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(3, true)? {
+            0 => Ok(CnSupport::Epc),
+            1 => Ok(CnSupport::FiveGc),
+            2 => Ok(CnSupport::Both),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// Cause (3GPP TS 37.483 section 9.3.1.2)
+///
+/// Values are the ENUMERATED indices of the respective cause group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    RadioNetwork(u8),
+    Transport(u8),
+    Protocol(u8),
+    Misc(u8),
+}
+
+impl Cause {
+    /// Root values of the radioNetwork, transport, protocol and misc groups
+    const ROOT_COUNTS: [usize; 4] = [25, 2, 7, 5];
+
+    /// Radio network: unspecified
+    pub const RADIO_NETWORK_UNSPECIFIED: Cause = Cause::RadioNetwork(0);
+    /// Radio network: unknown or already allocated gNB-CU-CP UE E1AP ID
+    pub const UNKNOWN_GNB_CU_CP_UE_E1AP_ID: Cause = Cause::RadioNetwork(1);
+    /// Radio network: unknown or already allocated gNB-CU-UP UE E1AP ID
+    pub const UNKNOWN_GNB_CU_UP_UE_E1AP_ID: Cause = Cause::RadioNetwork(2);
+    /// Radio network: multiple PDU session ID instances
+    pub const MULTIPLE_PDU_SESSION_ID_INSTANCES: Cause = Cause::RadioNetwork(12);
+    /// Radio network: unknown PDU session ID
+    pub const UNKNOWN_PDU_SESSION_ID: Cause = Cause::RadioNetwork(13);
+    /// Radio network: multiple DRB ID instances
+    pub const MULTIPLE_DRB_ID_INSTANCES: Cause = Cause::RadioNetwork(16);
+    /// Radio network: normal release
+    pub const NORMAL_RELEASE: Cause = Cause::RadioNetwork(20);
+    /// Transport: transport resource unavailable
+    pub const TRANSPORT_RESOURCE_UNAVAILABLE: Cause = Cause::Transport(1);
+    /// Misc: control processing overload
+    pub const CONTROL_PROCESSING_OVERLOAD: Cause = Cause::Misc(0);
+    /// Misc: not enough user plane processing resources
+    pub const NOT_ENOUGH_USER_PLANE_PROCESSING_RESOURCES: Cause = Cause::Misc(1);
+
+    fn group(&self) -> (usize, u8) {
+        match *self {
+            Cause::RadioNetwork(value) => (0, value),
+            Cause::Transport(value) => (1, value),
+            Cause::Protocol(value) => (2, value),
+            Cause::Misc(value) => (3, value),
+        }
+    }
+}
+
+impl AperCodec for Cause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let (group, value) = self.group();
+        enc.put_choice(group, 5, false)?;
+        enc.put_enumerated(value as usize, Self::ROOT_COUNTS[group], true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let group = dec.get_choice(5, false)?;
+        if group >= Self::ROOT_COUNTS.len() {
+            return Err(LayerError::ProcessingError("Unknown cause group".into()));
+        }
+        let value = dec.get_enumerated(Self::ROOT_COUNTS[group], true)? as u8;
+        Ok(match group {
+            0 => Cause::RadioNetwork(value),
+            1 => Cause::Transport(value),
+            2 => Cause::Protocol(value),
+            _ => Cause::Misc(value),
+        })
+    }
+}
+
+/// QoS flows as a list of QFIs, INTEGER (0..63, ...)
+fn encode_qos_flows(enc: &mut AperEncoder, qos_flows: &[u8]) -> Result<(), LayerError> {
+    enc.put_length(qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+    for qfi in qos_flows {
+        // QoS-Flow-Item: extension and iE-Extensions presence
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(*qfi as u64, 0, 63, true)?;
+    }
+    Ok(())
+}
+
+fn decode_qos_flows(dec: &mut AperDecoder) -> Result<Vec<u8>, LayerError> {
+    let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+    let mut qos_flows = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+        skip_ie_extensions(dec, extensions)?;
+    }
+    Ok(qos_flows)
+}
+
+/// UP Parameters with a single F1-U tunnel of cell group 0
+fn encode_up_parameters(enc: &mut AperEncoder, tunnel: &GtpTunnel) -> Result<(), LayerError> {
+    enc.put_length(1, 1, Some(MAX_UP_PARAMETERS))?;
+    enc.put_bool(false);
+    enc.put_bool(false);
+    tunnel.encode(enc)?;
+    // Cell-Group-ID, INTEGER (0..3, ...)
+    enc.put_integer(0, 0, 3, true)
+}
+
+fn decode_up_parameters(dec: &mut AperDecoder) -> Result<GtpTunnel, LayerError> {
+    let count = dec.get_length(1, Some(MAX_UP_PARAMETERS))?;
+    let mut tunnels = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        tunnels.push(GtpTunnel::decode(dec)?);
+        dec.get_integer(0, 3, true)?;
+        skip_ie_extensions(dec, extensions)?;
+    }
+    Ok(tunnels[0])
+}
+
+/// SDAP Configuration (3GPP TS 37.483 section 9.3.1.39)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdapConfiguration {
+    /// DRB is the default DRB of the PDU session
+    pub default_drb: bool,
+    /// SDAP header present on UL
+    pub sdap_header_ul: bool,
+    /// SDAP header present on DL
+    pub sdap_header_dl: bool,
+}
+
+impl AperCodec for SdapConfiguration {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        // DefaultDRB {true, false}, SDAP-Header-UL/DL {present, absent}
+        enc.put_enumerated(!self.default_drb as usize, 2, true)?;
+        enc.put_enumerated(!self.sdap_header_ul as usize, 2, true)?;
+        enc.put_enumerated(!self.sdap_header_dl as usize, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let default_drb = decode_first_of_two(dec)?;
+        let sdap_header_ul = decode_first_of_two(dec)?;
+        let sdap_header_dl = decode_first_of_two(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { default_drb, sdap_header_ul, sdap_header_dl })
+    }
+}
+
+/// Decode an extensible ENUMERATED of two root values, true for the first one
+fn decode_first_of_two(dec: &mut AperDecoder) -> Result<bool, LayerError> {
+    match dec.get_enumerated(2, true)? {
+        0 => Ok(true),
+        1 => Ok(false),
+        _ => Err(LayerError::InvalidPdu),
+    }
+}
+
+/// PDCP Configuration (3GPP TS 37.483 section 9.3.1.38)
+///
+/// Reduced to one SN size for both directions and the timers in milliseconds;
+/// ciphering and integrity protection stand in for the Security Indication.
+impl AperCodec for PdcpConfig {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        // PDCP-SN-Size {s-12, s-18, ...}
+        let sn_size = match self.sn_size {
+            12 => 0,
+            18 => 1,
+            size => return Err(LayerError::InvalidConfiguration(format!("Unsupported PDCP SN size {}", size))),
+        };
+        enc.put_enumerated(sn_size, 2, true)?;
+        enc.put_integer(self.discard_timer as u64, 0, 65535, false)?;
+        enc.put_integer(self.t_reordering as u64, 0, 65535, false)?;
+        enc.put_bool(self.integrity_protection);
+        enc.put_bool(self.ciphering);
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let sn_size = match dec.get_enumerated(2, true)? {
+            0 => 12,
+            1 => 18,
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        let discard_timer = dec.get_integer(0, 65535, false)? as u32;
+        let t_reordering = dec.get_integer(0, 65535, false)? as u32;
+        let integrity_protection = dec.get_bool()?;
+        let ciphering = dec.get_bool()?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { sn_size, discard_timer, t_reordering, integrity_protection, ciphering })
+    }
+}
+
+/// DRB To Setup Item NG-RAN, also used for DRB To Setup Mod
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrbToSetupItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// SDAP configuration
+    pub sdap_configuration: SdapConfiguration,
+    /// PDCP configuration
+    pub pdcp_configuration: PdcpConfig,
+    /// QoS flows mapped to the DRB
+    pub qos_flows: Vec<u8>,
+}
+
+impl AperCodec for DrbToSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        self.sdap_configuration.encode(enc)?;
+        self.pdcp_configuration.encode(enc)?;
+        encode_qos_flows(enc, &self.qos_flows)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let sdap_configuration = SdapConfiguration::decode(dec)?;
+        let pdcp_configuration = PdcpConfig::decode(dec)?;
+        let qos_flows = decode_qos_flows(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, sdap_configuration, pdcp_configuration, qos_flows })
+    }
+}
+
+impl AperCodec for Vec<DrbToSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+/// DRB Setup Item NG-RAN, also used for DRB Setup Mod
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrbSetupItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// UL F1-U tunnel of the gNB-CU-UP (UL UP Transport Parameters)
+    pub ul_tunnel: GtpTunnel,
+    /// QoS flows set up on the DRB
+    pub qos_flows: Vec<u8>,
+}
+
+impl AperCodec for DrbSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        encode_up_parameters(enc, &self.ul_tunnel)?;
+        encode_qos_flows(enc, &self.qos_flows)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let ul_tunnel = decode_up_parameters(dec)?;
+        let qos_flows = decode_qos_flows(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, ul_tunnel, qos_flows })
+    }
+}
+
+impl AperCodec for Vec<DrbSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+/// DRB Failed Item NG-RAN, also used for DRB Failed Mod
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbFailedItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Failure cause
+    pub cause: Cause,
+}
+
+impl AperCodec for DrbFailedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let cause = Cause::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, cause })
+    }
+}
+
+impl AperCodec for Vec<DrbFailedItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+/// DRB To Modify Item NG-RAN, reduced to the DL F1-U tunnel and the change of
+/// the QoS flow mapping
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DrbToModifyItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// DL F1-U tunnel of the gNB-DU (DL UP Parameters)
+    pub dl_tunnel: Option<GtpTunnel>,
+    /// QoS flows newly mapped to the DRB (Flow Mapping Information)
+    pub qos_flows_to_add: Vec<u8>,
+    /// QoS flows no longer mapped to the DRB (Flow To Remove)
+    pub qos_flows_to_release: Vec<u8>,
+}
+
+impl AperCodec for DrbToModifyItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.dl_tunnel.is_some());
+        enc.put_bool(!self.qos_flows_to_add.is_empty());
+        enc.put_bool(!self.qos_flows_to_release.is_empty());
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        if let Some(dl_tunnel) = &self.dl_tunnel {
+            encode_up_parameters(enc, dl_tunnel)?;
+        }
+        if !self.qos_flows_to_add.is_empty() {
+            encode_qos_flows(enc, &self.qos_flows_to_add)?;
+        }
+        if !self.qos_flows_to_release.is_empty() {
+            encode_qos_flows(enc, &self.qos_flows_to_release)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_dl_tunnel = dec.get_bool()?;
+        let has_flows_to_add = dec.get_bool()?;
+        let has_flows_to_release = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let dl_tunnel = if has_dl_tunnel { Some(decode_up_parameters(dec)?) } else { None };
+        let qos_flows_to_add = if has_flows_to_add { decode_qos_flows(dec)? } else { Vec::new() };
+        let qos_flows_to_release = if has_flows_to_release { decode_qos_flows(dec)? } else { Vec::new() };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, dl_tunnel, qos_flows_to_add, qos_flows_to_release })
+    }
+}
+
+impl AperCodec for Vec<DrbToModifyItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+/// DRB To Remove Item NG-RAN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbToRemoveItem {
+    /// DRB identity
+    pub drb_id: u8,
+}
+
+impl AperCodec for DrbToRemoveItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id })
+    }
+}
+
+impl AperCodec for Vec<DrbToRemoveItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+/// PDU Session Resource To Setup Item, also used for To Setup Mod
+///
+/// The NG DL tunnel is allocated by the gNB-CU-CP, as with Existing Allocated
+/// NG DL UP TNL Information; PDU session type, S-NSSAI and security indication
+/// stay in the CU-CP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PduSessionResourceToSetupItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// UPF endpoint of the NG-U tunnel (NG UL UP Transport Layer Information)
+    pub ng_ul_tunnel: GtpTunnel,
+    /// gNB endpoint of the NG-U tunnel
+    pub ng_dl_tunnel: GtpTunnel,
+    /// DRBs of the PDU session
+    pub drbs: Vec<DrbToSetupItem>,
+}
+
+impl AperCodec for PduSessionResourceToSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.ng_ul_tunnel.encode(enc)?;
+        self.ng_dl_tunnel.encode(enc)?;
+        self.drbs.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let ng_ul_tunnel = GtpTunnel::decode(dec)?;
+        let ng_dl_tunnel = GtpTunnel::decode(dec)?;
+        let drbs = Vec::<DrbToSetupItem>::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, ng_ul_tunnel, ng_dl_tunnel, drbs })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceToSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource Setup Item, also used for Setup Mod
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceSetupItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// gNB endpoint of the NG-U tunnel (NG DL UP Transport Layer Information)
+    pub ng_dl_tunnel: GtpTunnel,
+    /// DRBs set up
+    pub drbs_setup: Vec<DrbSetupItem>,
+    /// DRBs that could not be set up
+    pub drbs_failed: Vec<DrbFailedItem>,
+}
+
+impl AperCodec for PduSessionResourceSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(!self.drbs_failed.is_empty());
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.ng_dl_tunnel.encode(enc)?;
+        self.drbs_setup.encode(enc)?;
+        if !self.drbs_failed.is_empty() {
+            self.drbs_failed.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_failed = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let ng_dl_tunnel = GtpTunnel::decode(dec)?;
+        let drbs_setup = Vec::<DrbSetupItem>::decode(dec)?;
+        let drbs_failed = if has_failed { Vec::<DrbFailedItem>::decode(dec)? } else { Vec::new() };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, ng_dl_tunnel, drbs_setup, drbs_failed })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource Failed Item, also used for Failed Mod
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PduSessionResourceFailedItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// Failure cause
+    pub cause: Cause,
+}
+
+impl AperCodec for PduSessionResourceFailedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let cause = Cause::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, cause })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceFailedItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource To Modify Item
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PduSessionResourceToModifyItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// DRBs to add to the PDU session
+    pub drbs_to_setup: Vec<DrbToSetupItem>,
+    /// DRBs to modify
+    pub drbs_to_modify: Vec<DrbToModifyItem>,
+    /// DRBs to remove
+    pub drbs_to_remove: Vec<DrbToRemoveItem>,
+}
+
+impl AperCodec for PduSessionResourceToModifyItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(!self.drbs_to_setup.is_empty());
+        enc.put_bool(!self.drbs_to_modify.is_empty());
+        enc.put_bool(!self.drbs_to_remove.is_empty());
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        if !self.drbs_to_setup.is_empty() {
+            self.drbs_to_setup.encode(enc)?;
+        }
+        if !self.drbs_to_modify.is_empty() {
+            self.drbs_to_modify.encode(enc)?;
+        }
+        if !self.drbs_to_remove.is_empty() {
+            self.drbs_to_remove.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_setup = dec.get_bool()?;
+        let has_modify = dec.get_bool()?;
+        let has_remove = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let drbs_to_setup = if has_setup { Vec::<DrbToSetupItem>::decode(dec)? } else { Vec::new() };
+        let drbs_to_modify = if has_modify { Vec::<DrbToModifyItem>::decode(dec)? } else { Vec::new() };
+        let drbs_to_remove = if has_remove { Vec::<DrbToRemoveItem>::decode(dec)? } else { Vec::new() };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, drbs_to_setup, drbs_to_modify, drbs_to_remove })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceToModifyItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource Modified Item
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PduSessionResourceModifiedItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// DRBs added to the PDU session
+    pub drbs_setup: Vec<DrbSetupItem>,
+    /// DRBs that could not be added
+    pub drbs_failed: Vec<DrbFailedItem>,
+}
+
+impl AperCodec for PduSessionResourceModifiedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(!self.drbs_setup.is_empty());
+        enc.put_bool(!self.drbs_failed.is_empty());
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        if !self.drbs_setup.is_empty() {
+            self.drbs_setup.encode(enc)?;
+        }
+        if !self.drbs_failed.is_empty() {
+            self.drbs_failed.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_setup = dec.get_bool()?;
+        let has_failed = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let drbs_setup = if has_setup { Vec::<DrbSetupItem>::decode(dec)? } else { Vec::new() };
+        let drbs_failed = if has_failed { Vec::<DrbFailedItem>::decode(dec)? } else { Vec::new() };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, drbs_setup, drbs_failed })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceModifiedItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource To Remove Item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PduSessionResourceToRemoveItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+}
+
+impl AperCodec for PduSessionResourceToRemoveItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id })
+    }
+}
+
+impl AperCodec for Vec<PduSessionResourceToRemoveItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::NodeName;
+    use crate::test_support::{decode_ie, encode_ie};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_e1ap_messages() {
+        let request = E1apPdu::initiating(E1apProcedureCode::GnbCuUpE1Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(3)).unwrap()
+            .with_ie(ID_GNB_CU_UP_ID, Criticality::Reject, &GnbCuUpId(0x9_0000_0002)).unwrap()
+            .with_ie(ID_CN_SUPPORT, Criticality::Reject, &CnSupport::FiveGc).unwrap();
+        let encoded = request.encode().unwrap();
+        // initiatingMessage, procedure code 3, criticality reject
+        assert_eq!(&encoded[..3], &[0x00, 0x03, 0x00]);
+        let decoded = E1apPdu::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.procedure(), Some(E1apProcedureCode::GnbCuUpE1Setup));
+        assert_eq!(decoded.ie::<GnbCuUpId>(ID_GNB_CU_UP_ID).unwrap(), GnbCuUpId(0x9_0000_0002));
+        assert_eq!(decoded.ie::<CnSupport>(ID_CN_SUPPORT).unwrap(), CnSupport::FiveGc);
+        assert!(decoded.optional_ie::<NodeName>(ID_GNB_CU_UP_NAME).unwrap().is_none());
+
+        // Bearer Context Setup with a PDU session, its DRB and tunnels
+        let ng_ul_tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), teid: 0x100 };
+        let ng_dl_tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), teid: 0x200 };
+        let sessions = vec![PduSessionResourceToSetupItem {
+            pdu_session_id: 1,
+            ng_ul_tunnel,
+            ng_dl_tunnel,
+            drbs: vec![DrbToSetupItem {
+                drb_id: 1,
+                sdap_configuration: SdapConfiguration { default_drb: true, sdap_header_ul: true, sdap_header_dl: false },
+                pdcp_configuration: PdcpConfig {
+                    sn_size: 18,
+                    discard_timer: 100,
+                    t_reordering: 40,
+                    integrity_protection: false,
+                    ciphering: true,
+                },
+                qos_flows: vec![1, 5],
+            }],
+        }];
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST, Criticality::Reject, &sessions).unwrap();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST, Criticality::Reject,
+                                &Vec::<PduSessionResourceToModifyItem>::new()).unwrap();
+        let setup = E1apPdu::initiating(E1apProcedureCode::BearerContextSetup)
+            .with_ie(ID_GNB_CU_CP_UE_E1AP_ID, Criticality::Reject, &GnbCuCpUeE1apId(1000)).unwrap()
+            .with_ie(ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST, Criticality::Reject, &bearer_context).unwrap();
+        let decoded = E1apPdu::decode(&setup.encode().unwrap()).unwrap();
+        let bearer_context: NgRanBearerContext = decoded.ie(ID_SYSTEM_BEARER_CONTEXT_SETUP_REQUEST).unwrap();
+        assert_eq!(bearer_context.list::<PduSessionResourceToSetupItem>(ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST).unwrap(),
+                   sessions);
+        assert!(bearer_context.list::<PduSessionResourceToModifyItem>(ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST).unwrap()
+            .is_empty());
+
+        // Bearer Context Modification giving the F1-U tunnel of the DU and removing a DRB
+        let f1u_tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1)), teid: 0x77 };
+        let to_modify = vec![PduSessionResourceToModifyItem {
+            pdu_session_id: 1,
+            drbs_to_modify: vec![DrbToModifyItem {
+                drb_id: 1,
+                dl_tunnel: Some(f1u_tunnel),
+                qos_flows_to_add: vec![2],
+                ..Default::default()
+            }],
+            drbs_to_remove: vec![DrbToRemoveItem { drb_id: 2 }],
+            ..Default::default()
+        }];
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST, Criticality::Reject, &to_modify).unwrap();
+        let modification = E1apPdu::initiating(E1apProcedureCode::BearerContextModification)
+            .with_ue_ids(1000, 1).unwrap()
+            .with_ie(ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_REQUEST, Criticality::Reject, &bearer_context).unwrap();
+        let decoded = E1apPdu::decode(&modification.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<GnbCuUpUeE1apId>(ID_GNB_CU_UP_UE_E1AP_ID).unwrap(), GnbCuUpUeE1apId(1));
+        let bearer_context: NgRanBearerContext = decoded.ie(ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_REQUEST).unwrap();
+        assert_eq!(bearer_context.list::<PduSessionResourceToModifyItem>(ID_PDU_SESSION_RESOURCE_TO_MODIFY_LIST).unwrap(),
+                   to_modify);
+
+        // Response with a set up and a failed DRB, and a failed PDU session
+        let modified = vec![PduSessionResourceModifiedItem {
+            pdu_session_id: 1,
+            drbs_setup: vec![DrbSetupItem { drb_id: 3, ul_tunnel: f1u_tunnel, qos_flows: vec![9] }],
+            drbs_failed: vec![DrbFailedItem { drb_id: 4, cause: Cause::MULTIPLE_DRB_ID_INSTANCES }],
+        }];
+        let failed = vec![PduSessionResourceFailedItem { pdu_session_id: 2, cause: Cause::UNKNOWN_PDU_SESSION_ID }];
+        let mut bearer_context = NgRanBearerContext::default();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_MODIFIED_LIST, Criticality::Reject, &modified).unwrap();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST, Criticality::Ignore, &failed).unwrap();
+        let response = E1apPdu::successful(E1apProcedureCode::BearerContextModification)
+            .with_ue_ids(1000, 1).unwrap()
+            .with_ie(ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_RESPONSE, Criticality::Ignore, &bearer_context).unwrap();
+        let decoded = E1apPdu::decode(&response.encode().unwrap()).unwrap();
+        let bearer_context: NgRanBearerContext = decoded.ie(ID_SYSTEM_BEARER_CONTEXT_MODIFICATION_RESPONSE).unwrap();
+        assert_eq!(bearer_context.list::<PduSessionResourceModifiedItem>(ID_PDU_SESSION_RESOURCE_MODIFIED_LIST).unwrap(),
+                   modified);
+        assert_eq!(bearer_context.list::<PduSessionResourceFailedItem>(ID_PDU_SESSION_RESOURCE_FAILED_MOD_LIST).unwrap(),
+                   failed);
+
+        // Causes of each group
+        for cause in [Cause::NORMAL_RELEASE, Cause::TRANSPORT_RESOURCE_UNAVAILABLE, Cause::Protocol(1),
+                      Cause::CONTROL_PROCESSING_OVERLOAD] {
+            let pdu = E1apPdu::initiating(E1apProcedureCode::BearerContextRelease)
+                .with_ue_ids(1000, 1).unwrap()
+                .with_ie(ID_CAUSE, Criticality::Ignore, &cause).unwrap();
+            let decoded = E1apPdu::decode(&pdu.encode().unwrap()).unwrap();
+            assert_eq!(decoded.ie::<Cause>(ID_CAUSE).unwrap(), cause);
+        }
+    }
+
+    #[test]
+    fn test_e1ap_decode_errors() {
+        // Only the nG-RAN alternative of the System-BearerContext IEs: e-UTRAN, choice-extension
+        assert!(matches!(decode_ie::<NgRanBearerContext>(&[0x00]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<NgRanBearerContext>(&[0x80]), Err(LayerError::ProcessingError(_))));
+
+        // PDU session and DRB lists hold at least one item
+        let drb = DrbToSetupItem {
+            drb_id: 1,
+            sdap_configuration: SdapConfiguration { default_drb: true, sdap_header_ul: false, sdap_header_dl: false },
+            pdcp_configuration: PdcpConfig {
+                sn_size: 12,
+                discard_timer: 100,
+                t_reordering: 35,
+                integrity_protection: false,
+                ciphering: true,
+            },
+            qos_flows: vec![1],
+        };
+        let tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), teid: 0x100 };
+        let session = PduSessionResourceToSetupItem {
+            pdu_session_id: 1,
+            ng_ul_tunnel: tunnel,
+            ng_dl_tunnel: tunnel,
+            drbs: vec![drb.clone()],
+        };
+        assert!(matches!(encode_ie(&Vec::<PduSessionResourceToSetupItem>::new()), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(encode_ie(&vec![PduSessionResourceToSetupItem { drbs: vec![], ..session.clone() }]),
+                         Err(LayerError::ProcessingError(_))));
+        assert!(matches!(encode_ie(&DrbToSetupItem { qos_flows: vec![], ..drb.clone() }),
+                         Err(LayerError::ProcessingError(_))));
+
+        // Bearer context setup lists: absent or empty ones are left out, unknown IEs
+        // next to them are kept, a truncated one fails only when asked for
+        let mut bearer_context = NgRanBearerContext::default();
+        let to_setup = |bearer_context: &NgRanBearerContext| {
+            bearer_context.list::<PduSessionResourceToSetupItem>(ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST)
+        };
+        assert!(to_setup(&bearer_context).unwrap().is_empty());
+        bearer_context.add_list::<PduSessionResourceToSetupItem>(ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST, Criticality::Reject,
+                                                                 &Vec::new()).unwrap();
+        assert!(bearer_context.ies.is_empty());
+        bearer_context.add_ie(0xFFF0, Criticality::Ignore, &TransactionId(9)).unwrap();
+        bearer_context.add_list(ID_PDU_SESSION_RESOURCE_TO_SETUP_LIST, Criticality::Reject, &vec![session.clone()]).unwrap();
+        let decoded = decode_ie::<NgRanBearerContext>(&encode_ie(&bearer_context).unwrap()).unwrap();
+        assert_eq!(decoded, bearer_context);
+        assert_eq!(to_setup(&decoded).unwrap(), vec![session.clone()]);
+        let list = bearer_context.ies[1].value.clone();
+        bearer_context.ies[1].value = list.slice(..list.len() - 1);
+        assert!(matches!(to_setup(&bearer_context), Err(LayerError::InvalidPdu)));
+        assert!(bearer_context.list::<PduSessionResourceSetupItem>(ID_PDU_SESSION_RESOURCE_SETUP_LIST).unwrap().is_empty());
+
+        // PDCP SN sizes and SDAP values outside the E1AP choices
+        let pdcp = PdcpConfig { sn_size: 7, ..drb.pdcp_configuration };
+        assert!(matches!(encode_ie(&pdcp), Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(decode_ie::<PdcpConfig>(&[0x20, 0x00]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<SdapConfiguration>(&[0x20, 0x00]), Err(LayerError::InvalidPdu)));
+
+        // E1AP causes and CN support values outside their root
+        assert!(matches!(decode_ie::<Cause>(&[0x80]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<CnSupport>(&[0x80, 0x00]), Err(LayerError::InvalidPdu)));
+    }
+}
@@ -0,0 +1,153 @@
+//! GNB-CU-UP E1 Setup (3GPP TS 37.483 section 8.2.3)
+//!
+//! The gNB-CU-UP announces itself once the E1 association is up. A gNB-CU-CP
+//! serves a single gNB-CU-UP, further CU-UPs are rejected.
+
+use super::cu_cp::{ConnectedCuUp, E1apCuCp};
+use super::cu_up::E1apCuUp;
+use super::pdu::{self, Cause, CnSupport, E1apPdu, E1apPduType, GnbCuUpId, TransactionId};
+use super::transport::{E1Sender, NON_UE_STREAM};
+use super::E1apProcedureCode;
+use crate::ngap::pdu::{Criticality, NodeName, TimeToWait};
+use crate::LayerError;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+impl E1apCuCp {
+    /// Handle GNB-CU-UP E1 Setup Request
+    pub(super) async fn handle_e1_setup_request(&self, pdu: &E1apPdu, sender: &Arc<E1Sender>) -> Result<(), LayerError> {
+        let transaction_id = pdu.ie::<TransactionId>(pdu::ID_TRANSACTION_ID)?;
+        let gnb_cu_up_id = pdu.ie::<GnbCuUpId>(pdu::ID_GNB_CU_UP_ID)?.0;
+        let name = pdu.optional_ie::<NodeName>(pdu::ID_GNB_CU_UP_NAME)?;
+        let cn_support = pdu.ie::<CnSupport>(pdu::ID_CN_SUPPORT)?;
+
+        let mut cu_up = self.cu_up.write().await;
+        let rejection = match cu_up.as_ref().filter(|cu_up| !Arc::ptr_eq(&cu_up.sender, sender)) {
+            Some(connected) => Some((format!("gNB-CU-UP {} already connected", connected.gnb_cu_up_id),
+                                     Cause::CONTROL_PROCESSING_OVERLOAD)),
+            None if cn_support == CnSupport::Epc => Some(("no 5GC support".to_string(), Cause::Misc(4))),
+            None => None,
+        };
+        if let Some((reason, cause)) = rejection {
+            warn!("Rejecting E1 Setup of gNB-CU-UP {} from {}: {}", gnb_cu_up_id, sender.peer(), reason);
+            let failure = E1apPdu::unsuccessful(E1apProcedureCode::GnbCuUpE1Setup)
+                .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V60s)?;
+            return sender.send(NON_UE_STREAM, &failure).await;
+        }
+
+        info!("E1 Setup from gNB-CU-UP {} ({}) at {}",
+              gnb_cu_up_id, name.map(|name| name.0).unwrap_or_default(), sender.peer());
+        let response = E1apPdu::successful(E1apProcedureCode::GnbCuUpE1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?
+            .with_ie(pdu::ID_GNB_CU_CP_NAME, Criticality::Ignore, &NodeName(self.config.gnb_cu_cp_name.clone()))?;
+        sender.send(NON_UE_STREAM, &response).await?;
+
+        *cu_up = Some(ConnectedCuUp { gnb_cu_up_id, sender: Arc::clone(sender) });
+        Ok(())
+    }
+}
+
+impl E1apCuUp {
+    /// Build GNB-CU-UP E1 Setup Request
+    pub(super) fn e1_setup_request(&self) -> Result<E1apPdu, LayerError> {
+        let transaction_id = self.next_transaction_id.fetch_add(1, Ordering::Relaxed);
+        E1apPdu::initiating(E1apProcedureCode::GnbCuUpE1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(transaction_id))?
+            .with_ie(pdu::ID_GNB_CU_UP_ID, Criticality::Reject, &GnbCuUpId(self.config.gnb_cu_up_id))?
+            .with_ie(pdu::ID_GNB_CU_UP_NAME, Criticality::Ignore, &NodeName(self.config.gnb_cu_up_name.clone()))?
+            .with_ie(pdu::ID_CN_SUPPORT, Criticality::Reject, &CnSupport::FiveGc)
+    }
+
+    /// Handle GNB-CU-UP E1 Setup Response or Failure
+    pub(super) fn handle_e1_setup_outcome(&self, pdu: &E1apPdu) -> Result<(), LayerError> {
+        match pdu.pdu_type {
+            E1apPduType::SuccessfulOutcome => {
+                let name = pdu.optional_ie::<NodeName>(pdu::ID_GNB_CU_CP_NAME)?;
+                info!("E1 Setup with gNB-CU-CP {} done", name.map(|name| name.0).unwrap_or_default());
+                Ok(())
+            }
+            _ => {
+                let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+                let time_to_wait = pdu.optional_ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT)?;
+                Err(LayerError::InitializationFailed(
+                    format!("E1 Setup rejected by the gNB-CU-CP: {:?} (time to wait {:?})", cause, time_to_wait)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e1ap::transport::{E1Receiver, E1TransportEvent};
+    use crate::test_support::{e1_association, e1ap_cu_cp, e1ap_cu_up};
+    use bytes::Bytes;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn recv_pdu(receiver: &mut E1Receiver) -> E1apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            E1TransportEvent::Data { payload, .. } => E1apPdu::decode(&payload).unwrap(),
+            E1TransportEvent::AssociationLost(reason) => panic!("E1 association lost: {}", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e1_setup_failures() {
+        let cu_cp = e1ap_cu_cp().await;
+        let cu_up = e1ap_cu_up(cu_cp.local_addr().unwrap()).await;
+        let ((first_tx, _first_cu_cp_rx), (_first_cu_up_tx, mut first_rx)) = e1_association().await;
+        let first = Arc::new(first_tx);
+
+        // Request without gNB-CU-UP ID
+        let request = cu_up.e1_setup_request().unwrap();
+        let mut incomplete = request.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_GNB_CU_UP_ID);
+        assert!(matches!(cu_cp.handle_e1_setup_request(&incomplete, &first).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(cu_cp.connected_cu_up().await, None);
+
+        // A CU-UP without 5GC support is rejected
+        let mut epc = request.clone();
+        epc.ies.retain(|ie| ie.id != pdu::ID_CN_SUPPORT);
+        epc.add_ie(pdu::ID_CN_SUPPORT, Criticality::Reject, &CnSupport::Epc).unwrap();
+        cu_cp.handle_e1_setup_request(&epc, &first).await.unwrap();
+        let failure = recv_pdu(&mut first_rx).await;
+        assert_eq!(failure.pdu_type, E1apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::Misc(4));
+        assert_eq!(cu_cp.connected_cu_up().await, None);
+
+        // The first gNB-CU-UP is set up and may repeat its setup, a second
+        // association is rejected
+        cu_cp.handle_e1_setup_request(&request, &first).await.unwrap();
+        cu_up.handle_e1_setup_outcome(&recv_pdu(&mut first_rx).await).unwrap();
+        cu_cp.handle_e1_setup_request(&request, &first).await.unwrap();
+        assert_eq!(recv_pdu(&mut first_rx).await.pdu_type, E1apPduType::SuccessfulOutcome);
+        let ((second_tx, _second_cu_cp_rx), (_second_cu_up_tx, mut second_rx)) = e1_association().await;
+        cu_cp.handle_e1_setup_request(&request, &Arc::new(second_tx)).await.unwrap();
+        let failure = recv_pdu(&mut second_rx).await;
+        assert_eq!(failure.pdu_type, E1apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::CONTROL_PROCESSING_OVERLOAD);
+        assert_eq!(failure.ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT).unwrap(), TimeToWait::V60s);
+        assert!(matches!(cu_up.handle_e1_setup_outcome(&failure), Err(LayerError::InitializationFailed(_))));
+        assert_eq!(cu_cp.connected_cu_up().await, Some(2));
+
+        // Failure without Cause, response with an undecodable name
+        let failure = E1apPdu::unsuccessful(E1apProcedureCode::GnbCuUpE1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap();
+        assert!(matches!(cu_up.handle_e1_setup_outcome(&failure), Err(LayerError::ProcessingError(_))));
+        let mut response = E1apPdu::successful(E1apProcedureCode::GnbCuUpE1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap()
+            .with_ie(pdu::ID_GNB_CU_CP_NAME, Criticality::Ignore, &NodeName("cu-cp".into())).unwrap();
+        cu_up.handle_e1_setup_outcome(&response).unwrap();
+        response.ies[1].value = Bytes::new();
+        assert!(cu_up.handle_e1_setup_outcome(&response).is_err());
+
+        // Each request takes a new transaction ID
+        let next = cu_up.e1_setup_request().unwrap();
+        assert_ne!(next.ie::<TransactionId>(pdu::ID_TRANSACTION_ID).unwrap(),
+                   request.ie::<TransactionId>(pdu::ID_TRANSACTION_ID).unwrap());
+    }
+}
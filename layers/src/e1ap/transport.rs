@@ -0,0 +1,55 @@
+//! E1 transport
+//!
+//! Carries E1AP over SCTP as specified by 3GPP TS 37.482: the gNB-CU-UP opens
+//! the association towards the gNB-CU-CP, payload protocol identifier 64,
+//! stream 0 for non UE-associated signalling.
+
+use super::E1apProcedureCode;
+use crate::ngap::transport::{self, ApListener, ApReceiver, ApSender, NgTransportKind, SctpProtocol, TransportEvent};
+use crate::LayerError;
+use std::net::SocketAddr;
+
+/// SCTP payload protocol identifier of E1AP (TS 37.482 section 7)
+pub const E1AP_PPID: u32 = 64;
+/// SCTP stream reserved for non UE-associated signalling (TS 37.482 section 7)
+pub const NON_UE_STREAM: u16 = 0;
+/// SCTP stream used for UE-associated signalling
+pub const UE_STREAM: u16 = 1;
+
+impl SctpProtocol for E1apProcedureCode {
+    const INTERFACE: &'static str = "E1";
+    const PPID: u32 = E1AP_PPID;
+    const NUM_STREAMS: u16 = 2;
+}
+
+/// Event received on the E1 transport
+pub type E1TransportEvent = TransportEvent;
+/// Sending side of an E1 association
+pub type E1Sender = ApSender<E1apProcedureCode>;
+/// Receiving side of an E1 association
+pub type E1Receiver = ApReceiver<E1apProcedureCode>;
+/// Listening socket of the gNB-CU-CP
+pub type E1Listener = ApListener<E1apProcedureCode>;
+
+/// Open the E1 association from the gNB-CU-UP to the gNB-CU-CP
+pub async fn connect(
+    kind: NgTransportKind,
+    local_address: SocketAddr,
+    cu_cp_address: SocketAddr,
+) -> Result<(E1Sender, E1Receiver), LayerError> {
+    transport::connect(kind, local_address, cu_cp_address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_e1_sctp_parameters() {
+        // TS 37.482 section 7, bearer contexts on their own stream
+        assert_eq!(E1apProcedureCode::PPID, 64);
+        assert_eq!(E1apProcedureCode::NUM_STREAMS, 2);
+        assert_ne!(NON_UE_STREAM, UE_STREAM);
+        assert!(UE_STREAM < E1apProcedureCode::NUM_STREAMS);
+    }
+}
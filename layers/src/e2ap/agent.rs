@@ -0,0 +1,329 @@
+//! E2 agent
+//!
+//! Keeps the association with the near-RT RIC up: connects, runs E2 Setup and
+//! serves the RIC's requests until the association is lost, then drops the
+//! subscriptions and starts over after the reconnect interval.
+//!
+//! The KPM service model is always offered, the RC service model when a
+//! [`RcControl`] is set to execute the RIC's controls.
+
+use super::kpm::{encode_e2sm, KpmRanFunctionDescription, KpmSource, KPM_OID, KPM_RAN_FUNCTION_ID, KPM_REVISION};
+use super::pdu::*;
+use super::rc::{RcControl, RcRanFunctionDefinition, RC_OID, RC_RAN_FUNCTION_ID, RC_REVISION};
+use super::subscription::Subscription;
+use super::transport::{self, E2Receiver, E2Sender, E2TransportEvent, E2AP_STREAM};
+use super::E2apProcedureCode;
+use crate::ngap::pdu::{Criticality, GlobalGnbId, NrCgi, TimeToWait};
+use crate::ngap::transport::NgTransportKind;
+use crate::LayerError;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+/// E2 agent configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct E2AgentConfig {
+    /// E2 address of the near-RT RIC
+    pub ric_address: SocketAddr,
+    /// Local address the association is opened from
+    pub bind_address: SocketAddr,
+    /// E2 transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// Global gNB ID of this gNB, its Global E2 node ID
+    pub global_gnb_id: GlobalGnbId,
+    /// Cell served by this gNB
+    pub nr_cgi: NrCgi,
+    /// Name of the AMF, reported with the NG interface as E2 node component
+    pub amf_name: String,
+    /// Delay between attempts to (re-)establish the association
+    pub reconnect_interval: Duration,
+}
+
+/// E2 node function of a gNB
+pub struct E2Agent {
+    pub(super) config: E2AgentConfig,
+    /// Source of the KPM measurements
+    pub(super) kpm: Arc<dyn KpmSource>,
+    /// Executor of the RC controls, the RC service model is not offered without
+    pub(super) rc: Option<Arc<dyn RcControl>>,
+    /// Global RIC ID learnt in E2 Setup, None while not set up
+    ric_id: RwLock<Option<GlobalRicId>>,
+    /// Active subscriptions by RIC request ID
+    pub(super) subscriptions: Mutex<HashMap<RicRequestId, Subscription>>,
+    /// Transaction ID of the next E2 node initiated procedure
+    next_transaction_id: AtomicU8,
+}
+
+impl E2Agent {
+    /// Create the agent, the association is opened by [`run_e2_agent`]
+    pub fn new(config: E2AgentConfig, kpm: Arc<dyn KpmSource>) -> Self {
+        Self {
+            config,
+            kpm,
+            rc: None,
+            ric_id: RwLock::new(None),
+            subscriptions: Mutex::new(HashMap::new()),
+            next_transaction_id: AtomicU8::new(0),
+        }
+    }
+
+    /// Offer the RC service model, executing the RIC's controls with `rc`
+    pub fn set_rc_control(&mut self, rc: Arc<dyn RcControl>) {
+        self.rc = Some(rc);
+    }
+
+    /// Global RIC ID of the near-RT RIC once E2 Setup succeeded
+    pub async fn ric_id(&self) -> Option<GlobalRicId> {
+        *self.ric_id.read().await
+    }
+
+    /// Number of active RIC subscriptions
+    pub async fn subscription_count(&self) -> usize {
+        self.subscriptions.lock().await.len()
+    }
+
+    /// Open the association and run E2 Setup
+    async fn connect(&self) -> Result<(Arc<E2Sender>, E2Receiver), LayerError> {
+        let (sender, mut receiver) =
+            transport::connect(self.config.transport, self.config.bind_address, self.config.ric_address).await?;
+
+        let transaction_id = TransactionId(self.next_transaction_id.fetch_add(1, Ordering::Relaxed));
+        let mut functions = vec![RanFunctionItem {
+            id: KPM_RAN_FUNCTION_ID,
+            definition: encode_e2sm(&KpmRanFunctionDescription::supported())?,
+            revision: KPM_REVISION,
+            oid: KPM_OID.to_string(),
+        }];
+        if self.rc.is_some() {
+            functions.push(RanFunctionItem {
+                id: RC_RAN_FUNCTION_ID,
+                definition: encode_e2sm(&RcRanFunctionDefinition::supported())?,
+                revision: RC_REVISION,
+                oid: RC_OID.to_string(),
+            });
+        }
+        let functions = RanFunctionsList(functions);
+        // The agent does not see the NG Setup exchanged by NGAP, the parts are left empty
+        let components = E2NodeComponentConfigAdditionList(vec![E2NodeComponentConfigAddition {
+            amf_name: self.config.amf_name.clone(),
+            request_part: Bytes::new(),
+            response_part: Bytes::new(),
+        }]);
+        let request = E2apPdu::initiating(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?
+            .with_ie(ID_GLOBAL_E2_NODE_ID, Criticality::Reject, &GlobalE2NodeId(self.config.global_gnb_id))?
+            .with_ie(ID_RAN_FUNCTIONS_ADDED, Criticality::Reject, &functions)?
+            .with_ie(ID_E2_NODE_COMPONENT_CONFIG_ADDITION, Criticality::Reject, &components)?;
+        sender.send(E2AP_STREAM, &request).await?;
+
+        loop {
+            let payload = match receiver.recv().await {
+                E2TransportEvent::Data { payload, .. } => payload,
+                E2TransportEvent::AssociationLost(reason) => {
+                    return Err(LayerError::ProcessingError(format!("E2 association lost during E2 Setup: {}", reason)));
+                }
+            };
+            let pdu = E2apPdu::decode(&payload)?;
+            if pdu.procedure() != Some(E2apProcedureCode::E2Setup) {
+                warn!("Dropping E2AP {:?} received before E2 Setup completed", pdu.procedure());
+                continue;
+            }
+            return match pdu.pdu_type {
+                E2apPduType::SuccessfulOutcome => {
+                    let ric_id = pdu.ie::<GlobalRicId>(ID_GLOBAL_RIC_ID)?;
+                    let accepted = pdu.optional_ie::<RanFunctionsIdList>(ID_RAN_FUNCTIONS_ACCEPTED)?
+                        .map_or(0, |functions| functions.0.len());
+                    if let Some(rejected) = pdu.optional_ie::<RanFunctionsIdCauseList>(ID_RAN_FUNCTIONS_REJECTED)? {
+                        for function in rejected.0 {
+                            warn!("Near-RT RIC rejected RAN function {}: {:?}", function.id, function.cause);
+                        }
+                    }
+                    info!("E2 Setup with near-RT RIC {:05X} succeeded, {} RAN functions accepted",
+                          ric_id.ric_id, accepted);
+                    *self.ric_id.write().await = Some(ric_id);
+                    Ok((Arc::new(sender), receiver))
+                }
+                _ => {
+                    let cause = pdu.ie::<Cause>(ID_CAUSE)?;
+                    if let Some(time_to_wait) = pdu.optional_ie::<TimeToWait>(ID_TIME_TO_WAIT)? {
+                        tokio::time::sleep(Duration::from_secs(time_to_wait.seconds())).await;
+                    }
+                    Err(LayerError::ProcessingError(format!("E2 Setup failed: {:?}", cause)))
+                }
+            };
+        }
+    }
+
+    /// Handle a PDU received from the near-RT RIC
+    async fn handle_pdu(&self, pdu: E2apPdu, sender: &Arc<E2Sender>) -> Result<(), LayerError> {
+        match (pdu.pdu_type, pdu.procedure()) {
+            (E2apPduType::InitiatingMessage, Some(E2apProcedureCode::RicSubscription)) => {
+                self.handle_subscription_request(&pdu, sender).await
+            }
+            (E2apPduType::InitiatingMessage, Some(E2apProcedureCode::RicSubscriptionDelete)) => {
+                self.handle_subscription_delete_request(&pdu, sender).await
+            }
+            (E2apPduType::InitiatingMessage, Some(E2apProcedureCode::RicControl)) => {
+                self.handle_control_request(&pdu, sender).await
+            }
+            (E2apPduType::InitiatingMessage, Some(E2apProcedureCode::Reset)) => {
+                let transaction_id = pdu.ie::<TransactionId>(ID_TRANSACTION_ID)?;
+                let cause = pdu.ie::<Cause>(ID_CAUSE)?;
+                info!("Near-RT RIC reset the E2 interface: {:?}", cause);
+                self.subscriptions.lock().await.clear();
+                let response = E2apPdu::successful(E2apProcedureCode::Reset)
+                    .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?;
+                sender.send(E2AP_STREAM, &response).await
+            }
+            (E2apPduType::InitiatingMessage, Some(E2apProcedureCode::ErrorIndication)) => {
+                warn!("Error Indication from the near-RT RIC: {:?}", pdu.optional_ie::<Cause>(ID_CAUSE)?);
+                Ok(())
+            }
+            (pdu_type, procedure) => {
+                debug!("Ignoring E2AP {:?} {:?} (procedure code {})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Serve the association until it is lost
+    async fn serve(&self, sender: Arc<E2Sender>, mut receiver: E2Receiver) {
+        loop {
+            match receiver.recv().await {
+                E2TransportEvent::Data { payload, .. } => {
+                    let pdu = match E2apPdu::decode(&payload) {
+                        Ok(pdu) => pdu,
+                        Err(e) => {
+                            warn!("Dropping undecodable E2AP PDU: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = self.handle_pdu(pdu, &sender).await {
+                        warn!("Failed to handle E2AP PDU from {}: {}", sender.peer(), e);
+                    }
+                }
+                E2TransportEvent::AssociationLost(reason) => {
+                    warn!("E2 association with {} lost: {}", sender.peer(), reason);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Keep the E2 association with the near-RT RIC up until the task is dropped
+pub async fn run_e2_agent(agent: Arc<E2Agent>) {
+    info!("E2 agent connecting to near-RT RIC at {} ({:?})", agent.config.ric_address, agent.config.transport);
+    loop {
+        match agent.connect().await {
+            Ok((sender, receiver)) => agent.serve(sender, receiver).await,
+            Err(e) => warn!("{}", e),
+        }
+        // Subscriptions do not survive the association
+        agent.subscriptions.lock().await.clear();
+        *agent.ric_id.write().await = None;
+        tokio::time::sleep(agent.config.reconnect_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{e2_agent, XN_PLMN};
+    use transport::{E2Listener, E2TransportEvent};
+    use tokio::time::timeout;
+
+    const RIC_ID: GlobalRicId = GlobalRicId { plmn_id: XN_PLMN, ric_id: 0xABCDE };
+
+    async fn recv(receiver: &mut E2Receiver) -> E2apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            E2TransportEvent::Data { payload, .. } => E2apPdu::decode(&payload).unwrap(),
+            E2TransportEvent::AssociationLost(reason) => panic!("association lost: {}", reason),
+        }
+    }
+
+    /// Run E2 Setup against a near-RT RIC answering the request with `answer`
+    /// or dropping the association when there is none
+    async fn setup(answer: Option<E2apPdu>)
+        -> (E2Agent, Result<(Arc<E2Sender>, E2Receiver), LayerError>, Option<(E2Sender, E2Receiver)>) {
+        let listener = E2Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let agent = e2_agent(listener.local_addr().unwrap());
+        let ric = async {
+            let (ric_tx, mut ric_rx) = listener.accept().await.unwrap();
+            let request = recv(&mut ric_rx).await;
+            assert_eq!(request.procedure(), Some(E2apProcedureCode::E2Setup));
+            assert_eq!(request.ie::<RanFunctionsList>(ID_RAN_FUNCTIONS_ADDED).unwrap().0.len(), 1);
+            match answer {
+                Some(answer) => {
+                    // Anything else the RIC sends before the outcome is dropped
+                    ric_tx.send(E2AP_STREAM, &E2apPdu::initiating(E2apProcedureCode::RicServiceQuery)).await.unwrap();
+                    ric_tx.send(E2AP_STREAM, &answer).await.unwrap();
+                    Some((ric_tx, ric_rx))
+                }
+                None => None,
+            }
+        };
+        let (node, ric) = tokio::join!(agent.connect(), ric);
+        (agent, node, ric)
+    }
+
+    #[tokio::test]
+    async fn test_e2_setup_failures() {
+        // No near-RT RIC listening
+        let listener = E2Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let closed = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(e2_agent(closed).connect().await.is_err());
+
+        // Association lost before the outcome
+        let (agent, node, _ric) = setup(None).await;
+        assert!(matches!(node, Err(LayerError::ProcessingError(_))));
+        assert_eq!(agent.ric_id().await, None);
+
+        // E2 Setup Failure
+        let failure = E2apPdu::unsuccessful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap()
+            .with_ie(ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNSPECIFIED).unwrap();
+        let (agent, node, _ric) = setup(Some(failure)).await;
+        assert!(matches!(node, Err(LayerError::ProcessingError(_))));
+        assert_eq!(agent.ric_id().await, None);
+
+        // Response without the Global RIC ID
+        let incomplete = E2apPdu::successful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap();
+        let (agent, node, _ric) = setup(Some(incomplete)).await;
+        assert!(matches!(node, Err(LayerError::ProcessingError(_))));
+        assert_eq!(agent.ric_id().await, None);
+
+        // After a successful setup, a Reset needs its cause, unknown
+        // procedures and Error Indications are not errors
+        let response = E2apPdu::successful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap()
+            .with_ie(ID_GLOBAL_RIC_ID, Criticality::Reject, &RIC_ID).unwrap();
+        let (agent, node, ric) = setup(Some(response)).await;
+        let (_ric_tx, mut ric_rx) = ric.unwrap();
+        let (sender, _receiver) = node.unwrap();
+        assert_eq!(agent.ric_id().await, Some(RIC_ID));
+
+        let reset = E2apPdu::initiating(E2apProcedureCode::Reset)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(7)).unwrap();
+        assert!(matches!(agent.handle_pdu(reset.clone(), &sender).await, Err(LayerError::ProcessingError(_))));
+        let mut unknown = E2apPdu::initiating(E2apProcedureCode::RicServiceQuery);
+        unknown.procedure_code = 99;
+        agent.handle_pdu(unknown, &sender).await.unwrap();
+        agent.handle_pdu(E2apPdu::initiating(E2apProcedureCode::ErrorIndication), &sender).await.unwrap();
+        agent.handle_pdu(E2apPdu::successful(E2apProcedureCode::RicSubscription), &sender).await.unwrap();
+
+        let reset = reset.with_ie(ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNSPECIFIED).unwrap();
+        agent.handle_pdu(reset, &sender).await.unwrap();
+        let reset_response = recv(&mut ric_rx).await;
+        assert_eq!((reset_response.pdu_type, reset_response.procedure()),
+                   (E2apPduType::SuccessfulOutcome, Some(E2apProcedureCode::Reset)));
+        assert_eq!(reset_response.ie::<TransactionId>(ID_TRANSACTION_ID).unwrap(), TransactionId(7));
+    }
+}
@@ -0,0 +1,163 @@
+//! RIC Control procedure
+//!
+//! RIC Control (O-RAN.WG3.E2AP section 8.2.4) for the RC service model: the
+//! control header and message are decoded into an [`RcControlRequest`], handed
+//! to the agent's [`RcControl`] and the outcome is acknowledged back to the
+//! RIC. A failure is always reported, an acknowledge only when requested.
+
+use super::agent::E2Agent;
+use super::pdu::*;
+use super::rc::{RcControlRequest, RC_RAN_FUNCTION_ID};
+use super::transport::{E2Sender, E2AP_STREAM};
+use super::E2apProcedureCode;
+use crate::ngap::pdu::Criticality;
+use crate::LayerError;
+use bytes::Bytes;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+impl E2Agent {
+    /// Handle a RIC Control Request: execute the control and report the outcome
+    pub(super) async fn handle_control_request(&self, pdu: &E2apPdu, sender: &Arc<E2Sender>) -> Result<(), LayerError> {
+        let request_id = pdu.ie::<RicRequestId>(ID_RIC_REQUEST_ID)?;
+        let function_id = pdu.ie::<RanFunctionId>(ID_RAN_FUNCTION_ID)?;
+        let call_process_id = pdu.optional_ie::<Bytes>(ID_RIC_CALL_PROCESS_ID)?;
+        let header = pdu.ie::<Bytes>(ID_RIC_CONTROL_HEADER)?;
+        let message = pdu.ie::<Bytes>(ID_RIC_CONTROL_MESSAGE)?;
+        let ack_request = pdu.optional_ie::<RicControlAckRequest>(ID_RIC_CONTROL_ACK_REQUEST)?
+            .unwrap_or(RicControlAckRequest::Ack);
+
+        let Some(rc) = self.rc.as_ref().filter(|_| function_id.0 == RC_RAN_FUNCTION_ID) else {
+            return send_control_failure(sender, request_id, function_id, call_process_id, Cause::RAN_FUNCTION_ID_INVALID)
+                .await;
+        };
+        let request = match RcControlRequest::decode_e2sm(&header, &message) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("RIC control {}/{} rejected: {}", request_id.requestor_id, request_id.instance_id, e);
+                return send_control_failure(sender, request_id, function_id, call_process_id,
+                                            Cause::CONTROL_MESSAGE_INVALID).await;
+            }
+        };
+        info!("RIC control {}/{}: {:?}", request_id.requestor_id, request_id.instance_id, request);
+        if let Err(e) = rc.execute(request).await {
+            warn!("RIC control {}/{} failed: {}", request_id.requestor_id, request_id.instance_id, e);
+            return send_control_failure(sender, request_id, function_id, call_process_id,
+                                        Cause::CONTROL_FAILED_TO_EXECUTE).await;
+        }
+
+        if ack_request == RicControlAckRequest::NoAck {
+            return Ok(());
+        }
+        let mut acknowledge = E2apPdu::successful(E2apProcedureCode::RicControl)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?;
+        if let Some(call_process_id) = call_process_id {
+            acknowledge.add_ie(ID_RIC_CALL_PROCESS_ID, Criticality::Reject, &call_process_id)?;
+        }
+        sender.send(E2AP_STREAM, &acknowledge).await
+    }
+}
+
+async fn send_control_failure(
+    sender: &E2Sender,
+    request_id: RicRequestId,
+    function_id: RanFunctionId,
+    call_process_id: Option<Bytes>,
+    cause: Cause,
+) -> Result<(), LayerError> {
+    let mut failure = E2apPdu::unsuccessful(E2apProcedureCode::RicControl)
+        .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+        .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?;
+    if let Some(call_process_id) = call_process_id {
+        failure.add_ie(ID_RIC_CALL_PROCESS_ID, Criticality::Reject, &call_process_id)?;
+    }
+    failure.add_ie(ID_CAUSE, Criticality::Ignore, &cause)?;
+    sender.send(E2AP_STREAM, &failure).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e2ap::rc::{RcControl, RcControlRequest};
+    use crate::e2ap::transport::{E2Receiver, E2TransportEvent};
+    use crate::mac::SchedulingPolicy;
+    use crate::test_support::{e2_agent, e2_association};
+    use async_trait::async_trait;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    struct AcceptingControl;
+
+    #[async_trait]
+    impl RcControl for AcceptingControl {
+        async fn execute(&self, _request: RcControlRequest) -> Result<(), LayerError> {
+            Ok(())
+        }
+    }
+
+    async fn recv(receiver: &mut E2Receiver) -> E2apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            E2TransportEvent::Data { payload, .. } => E2apPdu::decode(&payload).unwrap(),
+            E2TransportEvent::AssociationLost(reason) => panic!("association lost: {}", reason),
+        }
+    }
+
+    fn control_request(instance_id: u16, header: &Bytes, message: &Bytes) -> E2apPdu {
+        E2apPdu::initiating(E2apProcedureCode::RicControl)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &RicRequestId { requestor_id: 100, instance_id }).unwrap()
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(RC_RAN_FUNCTION_ID)).unwrap()
+            .with_ie(ID_RIC_CONTROL_HEADER, Criticality::Reject, header).unwrap()
+            .with_ie(ID_RIC_CONTROL_MESSAGE, Criticality::Reject, message).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_control_failures() {
+        let ((_ric_tx, mut ric_rx), (node_tx, _node_rx)) = e2_association().await;
+        let sender = Arc::new(node_tx);
+        let (header, message) = RcControlRequest::SchedulingPolicy(SchedulingPolicy::MaxCqi).encode().unwrap();
+
+        // Without an RC executor the RC function is not offered
+        let agent = e2_agent(sender.peer());
+        agent.handle_control_request(&control_request(1, &header, &message), &sender).await.unwrap();
+        let failure = recv(&mut ric_rx).await;
+        assert_eq!(failure.pdu_type, E2apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(ID_CAUSE).unwrap(), Cause::RAN_FUNCTION_ID_INVALID);
+        assert_eq!(failure.optional_ie::<Bytes>(ID_RIC_CALL_PROCESS_ID).unwrap(), None);
+
+        let mut agent = e2_agent(sender.peer());
+        agent.set_rc_control(Arc::new(AcceptingControl));
+
+        // Mandatory IEs missing, no outcome is sent for those
+        let mut incomplete = control_request(2, &header, &message);
+        incomplete.ies.retain(|ie| ie.id != ID_RIC_CONTROL_MESSAGE);
+        assert!(matches!(agent.handle_control_request(&incomplete, &sender).await, Err(LayerError::ProcessingError(_))));
+        let mut incomplete = control_request(2, &header, &message);
+        incomplete.ies.retain(|ie| ie.id != ID_RAN_FUNCTION_ID);
+        assert!(matches!(agent.handle_control_request(&incomplete, &sender).await, Err(LayerError::ProcessingError(_))));
+
+        // Undecodable header and message, and a control the service model does not offer
+        let (handover_header, _) = RcControlRequest::Handover {
+            ue_id: 1,
+            target: crate::test_support::E2_CELL,
+        }.encode().unwrap();
+        for (instance_id, header, message) in [
+            (3, Bytes::from_static(&[0x40]), message.clone()),
+            (4, header.clone(), Bytes::from_static(&[0x00])),
+            (5, handover_header, message.clone()),
+        ] {
+            agent.handle_control_request(&control_request(instance_id, &header, &message), &sender).await.unwrap();
+            let failure = recv(&mut ric_rx).await;
+            assert_eq!(failure.pdu_type, E2apPduType::UnsuccessfulOutcome);
+            assert_eq!(failure.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap().instance_id, instance_id);
+            assert_eq!(failure.ie::<Cause>(ID_CAUSE).unwrap(), Cause::CONTROL_MESSAGE_INVALID);
+        }
+
+        // Acknowledged when the RIC does not say otherwise
+        agent.handle_control_request(&control_request(6, &header, &message), &sender).await.unwrap();
+        let acknowledge = recv(&mut ric_rx).await;
+        assert_eq!((acknowledge.pdu_type, acknowledge.procedure()),
+                   (E2apPduType::SuccessfulOutcome, Some(E2apProcedureCode::RicControl)));
+        assert_eq!(acknowledge.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap().instance_id, 6);
+    }
+}
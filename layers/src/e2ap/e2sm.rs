@@ -0,0 +1,182 @@
+//! E2 service model common definitions
+//!
+//! Types shared by the E2 service models (O-RAN.WG3.E2SM section 6): the RAN
+//! function name, the cell and UE identities and the unconstrained INTEGERs the
+//! style and format types are encoded with.
+
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{AperCodec, NrCgi};
+use crate::LayerError;
+
+/// Encode an unconstrained INTEGER: octet count and minimal two's complement
+pub(crate) fn put_unconstrained_integer(enc: &mut AperEncoder, value: i64) -> Result<(), LayerError> {
+    let octets = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 && ((octets[start] == 0x00 && octets[start + 1] & 0x80 == 0)
+        || (octets[start] == 0xFF && octets[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    enc.put_unconstrained_length(8 - start)?;
+    enc.put_octets(&octets[start..]);
+    Ok(())
+}
+
+/// Decode an unconstrained INTEGER
+pub(crate) fn get_unconstrained_integer(dec: &mut AperDecoder) -> Result<i64, LayerError> {
+    let len = dec.get_unconstrained_length()?;
+    if len == 0 || len > 8 {
+        return Err(LayerError::InvalidPdu);
+    }
+    let octets = dec.get_octets(len)?;
+    let sign = if octets[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Ok(octets.iter().fold(sign, |acc, b| (acc << 8) | *b as i64))
+}
+
+/// RAN function name (RANfunction-Name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RanFunctionName {
+    /// Short name of the service model
+    pub short_name: String,
+    /// Object identifier of the service model
+    pub oid: String,
+    /// Description of the RAN function
+    pub description: String,
+    /// Instance of the RAN function when the model is offered more than once
+    pub instance: Option<i64>,
+}
+
+impl AperCodec for RanFunctionName {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.instance.is_some());
+        enc.put_printable_string(&self.short_name, 1, 150, true)?;
+        enc.put_printable_string(&self.oid, 1, 1000, true)?;
+        enc.put_printable_string(&self.description, 1, 150, true)?;
+        if let Some(instance) = self.instance {
+            put_unconstrained_integer(enc, instance)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_instance = dec.get_bool()?;
+        let short_name = dec.get_printable_string(1, 150, true)?;
+        let oid = dec.get_printable_string(1, 1000, true)?;
+        let description = dec.get_printable_string(1, 150, true)?;
+        let instance = if has_instance { Some(get_unconstrained_integer(dec)?) } else { None };
+        Ok(Self { short_name, oid, description, instance })
+    }
+}
+
+/// RIC style: type and name (RIC-EventTriggerStyle-Item and the leading
+/// fields of the report and control style items)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RicStyle {
+    /// Style type
+    pub style_type: i64,
+    /// Style name
+    pub name: String,
+}
+
+impl RicStyle {
+    /// Encode the style type and name
+    pub(crate) fn encode_fields(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        put_unconstrained_integer(enc, self.style_type)?;
+        enc.put_printable_string(&self.name, 1, 150, true)
+    }
+
+    /// Decode the style type and name
+    pub(crate) fn decode_fields(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let style_type = get_unconstrained_integer(dec)?;
+        let name = dec.get_printable_string(1, 150, true)?;
+        Ok(Self { style_type, name })
+    }
+}
+
+/// Encode an NR-CGI, which unlike the NGAP one has no iE-Extensions
+pub(crate) fn put_nr_cgi(enc: &mut AperEncoder, nr_cgi: &NrCgi) -> Result<(), LayerError> {
+    enc.put_bool(false);
+    nr_cgi.plmn_id.encode(enc)?;
+    let value = (nr_cgi.nr_cell_identity & 0xF_FFFF_FFFF) << 28;
+    enc.put_bit_string(&value.to_be_bytes()[..5], 36, 36, Some(36), false)
+}
+
+/// Decode an NR-CGI
+pub(crate) fn get_nr_cgi(dec: &mut AperDecoder) -> Result<NrCgi, LayerError> {
+    dec.get_bool()?;
+    let plmn_id = <[u8; 3]>::decode(dec)?;
+    let (data, _) = dec.get_bit_string(36, Some(36), false)?;
+    let mut padded = [0u8; 8];
+    padded[..data.len()].copy_from_slice(&data);
+    Ok(NrCgi { plmn_id, nr_cell_identity: u64::from_be_bytes(padded) >> 28 })
+}
+
+/// Cell global identity (CGI, NR alternative only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cgi(pub NrCgi);
+
+impl AperCodec for Cgi {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // CGI: nR-CGI
+        enc.put_choice(0, 2, true)?;
+        put_nr_cgi(enc, &self.0)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(2, true)? != 0 {
+            return Err(LayerError::ProcessingError("Only NR cell global identities are supported".into()));
+        }
+        Ok(Self(get_nr_cgi(dec)?))
+    }
+}
+
+/// UE identity (UEID, gNB-DU alternative only)
+///
+/// The gNB identifies a UE towards the RIC by its gNB-CU UE F1AP ID, which is
+/// the RRC UE identifier in a monolithic gNB as in a CU/DU split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UeId(pub u32);
+
+impl AperCodec for UeId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // UEID: gNB-DU-UEID
+        enc.put_choice(1, 7, true)?;
+        // UEID-GNB-DU: extension, ran-UEID absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(7, true)? != 1 {
+            return Err(LayerError::ProcessingError("Only gNB-DU UE IDs are supported".into()));
+        }
+        dec.get_bool()?;
+        let has_ran_ue_id = dec.get_bool()?;
+        let id = dec.get_integer(0, u32::MAX as u64, false)? as u32;
+        if has_ran_ue_id {
+            // RANUEID, OCTET STRING (SIZE(8))
+            dec.get_octet_string(8, Some(8), false)?;
+        }
+        Ok(Self(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unconstrained_integer() {
+        for (value, octets) in [(0i64, vec![0x01, 0x00]), (127, vec![0x01, 0x7F]), (128, vec![0x02, 0x00, 0x80]),
+                                (-1, vec![0x01, 0xFF]), (65536, vec![0x03, 0x01, 0x00, 0x00])] {
+            let mut enc = AperEncoder::new();
+            put_unconstrained_integer(&mut enc, value).unwrap();
+            let encoded = enc.into_bytes();
+            assert_eq!(encoded.to_vec(), octets);
+            assert_eq!(get_unconstrained_integer(&mut AperDecoder::new(&encoded)).unwrap(), value);
+        }
+    }
+}
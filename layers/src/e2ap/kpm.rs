@@ -0,0 +1,868 @@
+//! E2SM-KPM service model
+//!
+//! Key performance measurements according to O-RAN.WG3.E2SM-KPM v3: periodic
+//! reports of cell level measurements (report style 1) and of the measurements
+//! of a single UE (report style 2), both with indication message format 1.
+//!
+//! The measurements follow 3GPP TS 28.552 and are computed from counters
+//! sampled at every granularity period: PRB usage from [`MacScheduler`],
+//! transport block outcomes from the PHY statistics, SDU volumes and the
+//! connected UEs from the RLC entities of the DRBs.
+
+use super::e2sm::{get_unconstrained_integer, put_unconstrained_integer, Cgi, RanFunctionName, RicStyle, UeId};
+use crate::mac::MacScheduler;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{AperCodec, NrCgi};
+use crate::phy::{EnhancedPhyLayer, TransportBlockStats};
+use crate::rlc::RlcStats;
+use crate::rrc::RrcLayer;
+use crate::LayerError;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock};
+
+/// RAN function ID the KPM service model is offered under
+pub const KPM_RAN_FUNCTION_ID: u16 = 2;
+/// Object identifier of E2SM-KPM v3
+pub const KPM_OID: &str = "1.3.6.1.4.1.53148.1.3.2.2";
+/// RAN function revision
+pub const KPM_REVISION: u16 = 0;
+/// Short name of the service model
+const KPM_SHORT_NAME: &str = "ORAN-E2SM-KPM";
+
+/// Report style: E2 node measurement
+pub const STYLE_CELL: i64 = 1;
+/// Report style: E2 node measurement for a single UE
+pub const STYLE_UE: i64 = 2;
+
+/// maxnoofRICStyles
+const MAX_STYLES: usize = 63;
+/// maxnoofMeasurementInfo
+const MAX_MEASUREMENT_INFO: usize = 65535;
+/// maxnoofMeasurementRecord
+const MAX_MEASUREMENT_RECORDS: usize = 65535;
+/// maxnoofLabelInfo and maxnoofMeasurementValue
+const MAX_LABELS_AND_VALUES: usize = 2_147_483_647;
+/// Offset of the NTP era from the UNIX epoch in seconds
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Measurement supported by the E2 node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KpmMeasurement {
+    /// RRU.PrbTotDl: downlink PRB usage in percent
+    PrbTotDl,
+    /// RRU.PrbTotUl: uplink PRB usage in percent
+    PrbTotUl,
+    /// RRU.PrbUsedDl: mean downlink PRBs used per slot
+    PrbUsedDl,
+    /// RRU.PrbUsedUl: mean uplink PRBs used per slot
+    PrbUsedUl,
+    /// DRB.UEThpDl: downlink throughput in kbit/s
+    UeThpDl,
+    /// DRB.UEThpUl: uplink throughput in kbit/s
+    UeThpUl,
+    /// DRB.RlcSduTransmittedVolumeDL: downlink RLC SDU volume in kbit
+    RlcSduVolumeDl,
+    /// DRB.RlcSduTransmittedVolumeUL: uplink RLC SDU volume in kbit
+    RlcSduVolumeUl,
+    /// RRC.ConnMean: connected UEs
+    ConnMean,
+    /// TB.TotNbrDl: downlink transport blocks
+    TbTotNbrDl,
+    /// TB.ErrTotalNbrDl: erroneous downlink transport blocks
+    TbErrTotalNbrDl,
+    /// TB.TotNbrUl: uplink transport blocks
+    TbTotNbrUl,
+    /// TB.ErrTotalNbrUl: erroneous uplink transport blocks
+    TbErrTotalNbrUl,
+}
+
+impl KpmMeasurement {
+    /// Every supported measurement, the position plus one is the measurement ID
+    pub const ALL: [KpmMeasurement; 13] = [
+        KpmMeasurement::PrbTotDl,
+        KpmMeasurement::PrbTotUl,
+        KpmMeasurement::PrbUsedDl,
+        KpmMeasurement::PrbUsedUl,
+        KpmMeasurement::UeThpDl,
+        KpmMeasurement::UeThpUl,
+        KpmMeasurement::RlcSduVolumeDl,
+        KpmMeasurement::RlcSduVolumeUl,
+        KpmMeasurement::ConnMean,
+        KpmMeasurement::TbTotNbrDl,
+        KpmMeasurement::TbErrTotalNbrDl,
+        KpmMeasurement::TbTotNbrUl,
+        KpmMeasurement::TbErrTotalNbrUl,
+    ];
+
+    /// Measurement name of TS 28.552
+    pub fn name(&self) -> &'static str {
+        match self {
+            KpmMeasurement::PrbTotDl => "RRU.PrbTotDl",
+            KpmMeasurement::PrbTotUl => "RRU.PrbTotUl",
+            KpmMeasurement::PrbUsedDl => "RRU.PrbUsedDl",
+            KpmMeasurement::PrbUsedUl => "RRU.PrbUsedUl",
+            KpmMeasurement::UeThpDl => "DRB.UEThpDl",
+            KpmMeasurement::UeThpUl => "DRB.UEThpUl",
+            KpmMeasurement::RlcSduVolumeDl => "DRB.RlcSduTransmittedVolumeDL",
+            KpmMeasurement::RlcSduVolumeUl => "DRB.RlcSduTransmittedVolumeUL",
+            KpmMeasurement::ConnMean => "RRC.ConnMean",
+            KpmMeasurement::TbTotNbrDl => "TB.TotNbrDl",
+            KpmMeasurement::TbErrTotalNbrDl => "TB.ErrTotalNbrDl",
+            KpmMeasurement::TbTotNbrUl => "TB.TotNbrUl",
+            KpmMeasurement::TbErrTotalNbrUl => "TB.ErrTotalNbrUl",
+        }
+    }
+
+    /// Measurement ID announced in the RAN function definition
+    pub fn id(&self) -> u32 {
+        Self::ALL.iter().position(|measurement| measurement == self).map_or(0, |index| index as u32 + 1)
+    }
+
+    /// Look up the measurement a RIC refers to
+    pub fn find(measurement_type: &MeasurementType) -> Option<Self> {
+        match measurement_type {
+            MeasurementType::Name(name) => Self::ALL.into_iter().find(|measurement| measurement.name() == name),
+            MeasurementType::Id(id) => Self::ALL.into_iter().find(|measurement| measurement.id() == *id),
+        }
+    }
+
+    /// Value of a cell level measurement over the period between two samples
+    pub fn cell_value(&self, start: &KpmSnapshot, end: &KpmSnapshot, period_ms: u64) -> Option<u32> {
+        let slots = end.slots.saturating_sub(start.slots);
+        let available = slots * end.prbs_per_slot as u64;
+        let (dl_bytes, ul_bytes) = end.ues.iter().fold((0, 0), |(dl, ul), (ue_id, counters)| {
+            let previous = start.ues.get(ue_id).map(|ue| ue.rlc).unwrap_or_default();
+            (dl + counters.rlc.tx_bytes.saturating_sub(previous.tx_bytes),
+             ul + counters.rlc.rx_bytes.saturating_sub(previous.rx_bytes))
+        });
+        let tbs = |counter: fn(&TransportBlockStats) -> u64| {
+            counter(&end.transport_blocks).saturating_sub(counter(&start.transport_blocks))
+        };
+        let value = match self {
+            KpmMeasurement::PrbTotDl => percentage(end.dl_prbs_used.saturating_sub(start.dl_prbs_used), available)?,
+            KpmMeasurement::PrbTotUl => percentage(end.ul_prbs_used.saturating_sub(start.ul_prbs_used), available)?,
+            KpmMeasurement::PrbUsedDl => end.dl_prbs_used.saturating_sub(start.dl_prbs_used).checked_div(slots)?,
+            KpmMeasurement::PrbUsedUl => end.ul_prbs_used.saturating_sub(start.ul_prbs_used).checked_div(slots)?,
+            KpmMeasurement::UeThpDl => (dl_bytes * 8).checked_div(period_ms)?,
+            KpmMeasurement::UeThpUl => (ul_bytes * 8).checked_div(period_ms)?,
+            KpmMeasurement::RlcSduVolumeDl => dl_bytes * 8 / 1000,
+            KpmMeasurement::RlcSduVolumeUl => ul_bytes * 8 / 1000,
+            KpmMeasurement::ConnMean => end.ues.len() as u64,
+            KpmMeasurement::TbTotNbrDl => tbs(|stats| stats.dl_total),
+            KpmMeasurement::TbErrTotalNbrDl => tbs(|stats| stats.dl_errors),
+            KpmMeasurement::TbTotNbrUl => tbs(|stats| stats.ul_total),
+            KpmMeasurement::TbErrTotalNbrUl => tbs(|stats| stats.ul_errors),
+        };
+        Some(value.min(u32::MAX as u64) as u32)
+    }
+
+    /// Value of a UE level measurement over the period between two samples,
+    /// None for cell level measurements and a UE not connected at the end
+    pub fn ue_value(&self, ue_id: u32, start: &KpmSnapshot, end: &KpmSnapshot, period_ms: u64) -> Option<u32> {
+        let current = end.ues.get(&ue_id)?;
+        let previous = start.ues.get(&ue_id).copied().unwrap_or_default();
+        let slots = end.slots.saturating_sub(start.slots);
+        let dl_bytes = current.rlc.tx_bytes.saturating_sub(previous.rlc.tx_bytes);
+        let ul_bytes = current.rlc.rx_bytes.saturating_sub(previous.rlc.rx_bytes);
+        let value = match self {
+            KpmMeasurement::PrbUsedDl => current.dl_prbs.saturating_sub(previous.dl_prbs).checked_div(slots)?,
+            KpmMeasurement::PrbUsedUl => current.ul_prbs.saturating_sub(previous.ul_prbs).checked_div(slots)?,
+            KpmMeasurement::UeThpDl => (dl_bytes * 8).checked_div(period_ms)?,
+            KpmMeasurement::UeThpUl => (ul_bytes * 8).checked_div(period_ms)?,
+            KpmMeasurement::RlcSduVolumeDl => dl_bytes * 8 / 1000,
+            KpmMeasurement::RlcSduVolumeUl => ul_bytes * 8 / 1000,
+            _ => return None,
+        };
+        Some(value.min(u32::MAX as u64) as u32)
+    }
+}
+
+fn percentage(used: u64, available: u64) -> Option<u64> {
+    (used * 100).checked_div(available)
+}
+
+/// Counters of a connected UE
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UeCounters {
+    /// Downlink PRBs allocated
+    pub dl_prbs: u64,
+    /// Uplink PRBs allocated
+    pub ul_prbs: u64,
+    /// SDU volumes of the UE's RLC entities
+    pub rlc: RlcStats,
+}
+
+/// Counters of the cell sampled at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KpmSnapshot {
+    /// Slots scheduled
+    pub slots: u64,
+    /// PRBs of the carrier in each slot
+    pub prbs_per_slot: u32,
+    /// Downlink PRBs allocated
+    pub dl_prbs_used: u64,
+    /// Uplink PRBs allocated
+    pub ul_prbs_used: u64,
+    /// Transport block outcomes
+    pub transport_blocks: TransportBlockStats,
+    /// Connected UEs by UE ID
+    pub ues: HashMap<u32, UeCounters>,
+}
+
+/// Source of the counters the measurements are computed from
+#[async_trait]
+pub trait KpmSource: Send + Sync {
+    /// Sample the counters
+    async fn snapshot(&self) -> KpmSnapshot;
+}
+
+/// Counters of the protocol stack of a gNB, each layer being optional as a
+/// CU or DU only runs part of the stack
+pub struct StackKpmSource {
+    scheduler: Option<Arc<Mutex<MacScheduler>>>,
+    phy: Option<Arc<RwLock<EnhancedPhyLayer>>>,
+    rrc: Option<Arc<RwLock<RrcLayer>>>,
+}
+
+impl StackKpmSource {
+    /// Read the counters of the given layers
+    pub fn new(
+        scheduler: Option<Arc<Mutex<MacScheduler>>>,
+        phy: Option<Arc<RwLock<EnhancedPhyLayer>>>,
+        rrc: Option<Arc<RwLock<RrcLayer>>>,
+    ) -> Self {
+        Self { scheduler, phy, rrc }
+    }
+}
+
+#[async_trait]
+impl KpmSource for StackKpmSource {
+    async fn snapshot(&self) -> KpmSnapshot {
+        let mut snapshot = KpmSnapshot::default();
+        let mut ue_usage = HashMap::new();
+        if let Some(scheduler) = &self.scheduler {
+            let metrics = scheduler.lock().await.metrics();
+            snapshot.slots = metrics.slots;
+            snapshot.prbs_per_slot = metrics.prbs_per_slot;
+            snapshot.dl_prbs_used = metrics.dl_prbs_used;
+            snapshot.ul_prbs_used = metrics.ul_prbs_used;
+            ue_usage = metrics.ues;
+        }
+        if let Some(phy) = &self.phy {
+            snapshot.transport_blocks = phy.read().await.get_stats().await.transport_blocks;
+        }
+        if let Some(rrc) = &self.rrc {
+            for volume in rrc.read().await.drb_volumes().await {
+                let usage = ue_usage.get(&volume.c_rnti).copied().unwrap_or_default();
+                snapshot.ues.insert(volume.ue_id, UeCounters {
+                    dl_prbs: usage.dl_prbs,
+                    ul_prbs: usage.ul_prbs,
+                    rlc: volume.rlc,
+                });
+            }
+        }
+        snapshot
+    }
+}
+
+/// Time as a 64 bit NTP timestamp
+pub fn ntp_timestamp(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (since_epoch.subsec_nanos() as u64) * (1 << 32) / 1_000_000_000;
+    seconds << 32 | fraction
+}
+
+/// Measurement type: name or ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeasurementType {
+    Name(String),
+    Id(u32),
+}
+
+impl From<KpmMeasurement> for MeasurementType {
+    fn from(measurement: KpmMeasurement) -> Self {
+        MeasurementType::Name(measurement.name().to_string())
+    }
+}
+
+impl AperCodec for MeasurementType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match self {
+            MeasurementType::Name(name) => {
+                enc.put_choice(0, 2, true)?;
+                enc.put_printable_string(name, 1, 150, true)
+            }
+            MeasurementType::Id(id) => {
+                enc.put_choice(1, 2, true)?;
+                enc.put_integer(*id as u64, 1, 65536, true)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(2, true)? {
+            0 => Ok(MeasurementType::Name(dec.get_printable_string(1, 150, true)?)),
+            1 => Ok(MeasurementType::Id(dec.get_integer(1, 65536, true)? as u32)),
+            _ => Err(LayerError::ProcessingError("Unknown measurement type".into())),
+        }
+    }
+}
+
+/// Measurement information list (MeasurementInfoList), every measurement with
+/// the single label noLabel
+fn encode_measurement_info(enc: &mut AperEncoder, measurements: &[MeasurementType]) -> Result<(), LayerError> {
+    enc.put_length(measurements.len(), 1, Some(MAX_MEASUREMENT_INFO))?;
+    for measurement in measurements {
+        enc.put_bool(false);
+        measurement.encode(enc)?;
+        // LabelInfoList with one LabelInfoItem
+        enc.put_length(1, 1, Some(MAX_LABELS_AND_VALUES))?;
+        enc.put_bool(false);
+        // MeasurementLabel: extension bit, 21 optional fields of which noLabel is present
+        enc.put_bool(false);
+        enc.put_bits(1 << 20, 21);
+        enc.put_enumerated(0, 1, true)?;
+    }
+    Ok(())
+}
+
+fn decode_measurement_info(dec: &mut AperDecoder) -> Result<Vec<MeasurementType>, LayerError> {
+    let count = dec.get_length(1, Some(MAX_MEASUREMENT_INFO))?;
+    let mut measurements = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        measurements.push(MeasurementType::decode(dec)?);
+        let labels = dec.get_length(1, Some(MAX_LABELS_AND_VALUES))?;
+        for _ in 0..labels {
+            dec.get_bool()?;
+            let extended = dec.get_bool()?;
+            if extended || dec.get_bits(21)? != 1 << 20 {
+                return Err(LayerError::ProcessingError("Measurement labels are not supported".into()));
+            }
+            dec.get_enumerated(1, true)?;
+        }
+    }
+    Ok(measurements)
+}
+
+/// RAN function definition of the KPM service model
+/// (E2SM-KPM-RANfunction-Description)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KpmRanFunctionDescription {
+    /// Name of the RAN function
+    pub name: RanFunctionName,
+    /// Event trigger styles, all with event trigger format 1
+    pub event_trigger_styles: Vec<RicStyle>,
+    /// Report styles with their action definition format and measurements,
+    /// all with indication header and message format 1
+    pub report_styles: Vec<(RicStyle, i64, Vec<KpmMeasurement>)>,
+}
+
+impl KpmRanFunctionDescription {
+    /// Description of what this E2 node reports
+    pub fn supported() -> Self {
+        Self {
+            name: RanFunctionName {
+                short_name: KPM_SHORT_NAME.to_string(),
+                oid: KPM_OID.to_string(),
+                description: "KPM Monitor".to_string(),
+                instance: None,
+            },
+            event_trigger_styles: vec![RicStyle { style_type: 1, name: "Periodic Report".to_string() }],
+            report_styles: vec![
+                (RicStyle { style_type: STYLE_CELL, name: "E2 Node Measurement".to_string() }, 1,
+                 KpmMeasurement::ALL.to_vec()),
+                (RicStyle { style_type: STYLE_UE, name: "E2 Node Measurement for a single UE".to_string() }, 2,
+                 KpmMeasurement::ALL.into_iter().filter(|measurement| measurement.ue_level()).collect()),
+            ],
+        }
+    }
+}
+
+impl KpmMeasurement {
+    /// Whether the measurement is also reported per UE
+    fn ue_level(&self) -> bool {
+        matches!(self,
+                 KpmMeasurement::PrbUsedDl | KpmMeasurement::PrbUsedUl | KpmMeasurement::UeThpDl
+                 | KpmMeasurement::UeThpUl | KpmMeasurement::RlcSduVolumeDl | KpmMeasurement::RlcSduVolumeUl)
+    }
+}
+
+impl AperCodec for KpmRanFunctionDescription {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(!self.event_trigger_styles.is_empty());
+        enc.put_bool(!self.report_styles.is_empty());
+        self.name.encode(enc)?;
+        if !self.event_trigger_styles.is_empty() {
+            enc.put_length(self.event_trigger_styles.len(), 1, Some(MAX_STYLES))?;
+            for style in &self.event_trigger_styles {
+                enc.put_bool(false);
+                style.encode_fields(enc)?;
+                // ric-EventTriggerFormat-Type
+                put_unconstrained_integer(enc, 1)?;
+            }
+        }
+        if !self.report_styles.is_empty() {
+            enc.put_length(self.report_styles.len(), 1, Some(MAX_STYLES))?;
+            for (style, action_format, measurements) in &self.report_styles {
+                enc.put_bool(false);
+                style.encode_fields(enc)?;
+                put_unconstrained_integer(enc, *action_format)?;
+                // MeasurementInfo-Action-List: names with their IDs
+                enc.put_length(measurements.len(), 1, Some(MAX_MEASUREMENT_INFO))?;
+                for measurement in measurements {
+                    enc.put_bool(false);
+                    enc.put_bool(true);
+                    enc.put_printable_string(measurement.name(), 1, 150, true)?;
+                    enc.put_integer(measurement.id() as u64, 1, 65536, true)?;
+                }
+                // ric-IndicationHeaderFormat-Type, ric-IndicationMessageFormat-Type
+                put_unconstrained_integer(enc, 1)?;
+                put_unconstrained_integer(enc, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_event_trigger_styles = dec.get_bool()?;
+        let has_report_styles = dec.get_bool()?;
+        let name = RanFunctionName::decode(dec)?;
+        let mut event_trigger_styles = Vec::new();
+        if has_event_trigger_styles {
+            for _ in 0..dec.get_length(1, Some(MAX_STYLES))? {
+                dec.get_bool()?;
+                event_trigger_styles.push(RicStyle::decode_fields(dec)?);
+                get_unconstrained_integer(dec)?;
+            }
+        }
+        let mut report_styles = Vec::new();
+        if has_report_styles {
+            for _ in 0..dec.get_length(1, Some(MAX_STYLES))? {
+                dec.get_bool()?;
+                let style = RicStyle::decode_fields(dec)?;
+                let action_format = get_unconstrained_integer(dec)?;
+                let mut measurements = Vec::new();
+                for _ in 0..dec.get_length(1, Some(MAX_MEASUREMENT_INFO))? {
+                    dec.get_bool()?;
+                    let has_id = dec.get_bool()?;
+                    let name = dec.get_printable_string(1, 150, true)?;
+                    if has_id {
+                        dec.get_integer(1, 65536, true)?;
+                    }
+                    // Measurements of other implementations are skipped
+                    measurements.extend(KpmMeasurement::find(&MeasurementType::Name(name)));
+                }
+                get_unconstrained_integer(dec)?;
+                get_unconstrained_integer(dec)?;
+                report_styles.push((style, action_format, measurements));
+            }
+        }
+        Ok(Self { name, event_trigger_styles, report_styles })
+    }
+}
+
+/// Event trigger of a subscription (E2SM-KPM-EventTriggerDefinition format 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KpmEventTrigger {
+    /// Reporting period in milliseconds
+    pub reporting_period_ms: u32,
+}
+
+impl AperCodec for KpmEventTrigger {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_choice(0, 1, true)?;
+        enc.put_bool(false);
+        enc.put_integer(self.reporting_period_ms as u64, 1, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        if dec.get_choice(1, true)? != 0 {
+            return Err(LayerError::ProcessingError("Unsupported KPM event trigger format".into()));
+        }
+        dec.get_bool()?;
+        let reporting_period_ms = dec.get_integer(1, u32::MAX as u64, false)? as u32;
+        Ok(Self { reporting_period_ms })
+    }
+}
+
+/// Measurements to collect (E2SM-KPM-ActionDefinition-Format1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KpmSubscriptionInfo {
+    /// Measurements, each without label
+    pub measurements: Vec<MeasurementType>,
+    /// Granularity period in milliseconds
+    pub granularity_period_ms: u32,
+    /// Cell to measure, the E2 node's cell if absent
+    pub cell: Option<NrCgi>,
+}
+
+impl AperCodec for KpmSubscriptionInfo {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.cell.is_some());
+        encode_measurement_info(enc, &self.measurements)?;
+        enc.put_integer(self.granularity_period_ms as u64, 1, u32::MAX as u64, false)?;
+        if let Some(cell) = self.cell {
+            Cgi(cell).encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_cell = dec.get_bool()?;
+        let measurements = decode_measurement_info(dec)?;
+        let granularity_period_ms = dec.get_integer(1, u32::MAX as u64, false)? as u32;
+        let cell = if has_cell { Some(Cgi::decode(dec)?.0) } else { None };
+        Ok(Self { measurements, granularity_period_ms, cell })
+    }
+}
+
+/// Action definition of a report action (E2SM-KPM-ActionDefinition)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KpmActionDefinition {
+    /// Report style 1 with action definition format 1
+    Cell(KpmSubscriptionInfo),
+    /// Report style 2 with action definition format 2
+    Ue { ue_id: UeId, info: KpmSubscriptionInfo },
+}
+
+impl KpmActionDefinition {
+    /// Measurements and granularity of the action
+    pub fn info(&self) -> &KpmSubscriptionInfo {
+        match self {
+            KpmActionDefinition::Cell(info) | KpmActionDefinition::Ue { info, .. } => info,
+        }
+    }
+}
+
+impl AperCodec for KpmActionDefinition {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        match self {
+            KpmActionDefinition::Cell(info) => {
+                put_unconstrained_integer(enc, STYLE_CELL)?;
+                enc.put_choice(0, 3, true)?;
+                info.encode(enc)
+            }
+            KpmActionDefinition::Ue { ue_id, info } => {
+                put_unconstrained_integer(enc, STYLE_UE)?;
+                enc.put_choice(1, 3, true)?;
+                enc.put_bool(false);
+                ue_id.encode(enc)?;
+                info.encode(enc)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let style = get_unconstrained_integer(dec)?;
+        match (style, dec.get_choice(3, true)?) {
+            (STYLE_CELL, 0) => Ok(KpmActionDefinition::Cell(KpmSubscriptionInfo::decode(dec)?)),
+            (STYLE_UE, 1) => {
+                dec.get_bool()?;
+                let ue_id = UeId::decode(dec)?;
+                let info = KpmSubscriptionInfo::decode(dec)?;
+                Ok(KpmActionDefinition::Ue { ue_id, info })
+            }
+            (style, format) => Err(LayerError::ProcessingError(
+                format!("Unsupported KPM report style {} with action definition format {}", style, format + 1))),
+        }
+    }
+}
+
+/// Indication header (E2SM-KPM-IndicationHeader format 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KpmIndicationHeader {
+    /// Start of the collection as NTP timestamp
+    pub collection_start: u64,
+}
+
+impl AperCodec for KpmIndicationHeader {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_choice(0, 1, true)?;
+        // Format 1: extension, file format version, sender name, sender type and vendor name absent
+        enc.put_bool(false);
+        enc.put_bits(0, 4);
+        enc.put_octet_string(&self.collection_start.to_be_bytes(), 8, Some(8), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        if dec.get_choice(1, true)? != 0 {
+            return Err(LayerError::ProcessingError("Unsupported KPM indication header format".into()));
+        }
+        dec.get_bool()?;
+        let optionals = dec.get_bits(4)?;
+        let timestamp = dec.get_octet_string(8, Some(8), false)?;
+        let collection_start = timestamp.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        // fileFormatversion, senderName, senderType and vendorName
+        for (bit, ub) in [(3, 15), (2, 400), (1, 8), (0, 32)] {
+            if optionals & (1 << bit) != 0 {
+                dec.get_printable_string(0, ub, true)?;
+            }
+        }
+        Ok(Self { collection_start })
+    }
+}
+
+/// Value of a measurement (MeasurementRecordItem)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasurementValue {
+    Integer(u32),
+    NoValue,
+}
+
+impl From<Option<u32>> for MeasurementValue {
+    fn from(value: Option<u32>) -> Self {
+        value.map_or(MeasurementValue::NoValue, MeasurementValue::Integer)
+    }
+}
+
+/// Indication message (E2SM-KPM-IndicationMessage format 1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KpmIndicationMessage {
+    /// One record per granularity period, one value per measurement
+    pub records: Vec<Vec<MeasurementValue>>,
+    /// Measurements of the records
+    pub measurements: Vec<MeasurementType>,
+    /// Granularity period in milliseconds
+    pub granularity_period_ms: u32,
+}
+
+impl AperCodec for KpmIndicationMessage {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_choice(0, 2, true)?;
+        // Format 1: measInfoList and granulPeriod present
+        enc.put_bool(false);
+        enc.put_bool(true);
+        enc.put_bool(true);
+        enc.put_length(self.records.len(), 1, Some(MAX_MEASUREMENT_RECORDS))?;
+        for record in &self.records {
+            // MeasurementDataItem: incompleteFlag absent
+            enc.put_bool(false);
+            enc.put_bool(false);
+            enc.put_length(record.len(), 1, Some(MAX_LABELS_AND_VALUES))?;
+            for value in record {
+                match value {
+                    MeasurementValue::Integer(value) => {
+                        enc.put_choice(0, 3, true)?;
+                        enc.put_integer(*value as u64, 0, u32::MAX as u64, false)?;
+                    }
+                    MeasurementValue::NoValue => enc.put_choice(2, 3, true)?,
+                }
+            }
+        }
+        encode_measurement_info(enc, &self.measurements)?;
+        enc.put_integer(self.granularity_period_ms as u64, 1, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        if dec.get_choice(2, true)? != 0 {
+            return Err(LayerError::ProcessingError("Unsupported KPM indication message format".into()));
+        }
+        dec.get_bool()?;
+        let has_measurements = dec.get_bool()?;
+        let has_granularity = dec.get_bool()?;
+        let count = dec.get_length(1, Some(MAX_MEASUREMENT_RECORDS))?;
+        let mut records = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let incomplete = dec.get_bool()?;
+            let values = dec.get_length(1, Some(MAX_LABELS_AND_VALUES))?;
+            let mut record = Vec::with_capacity(values);
+            for _ in 0..values {
+                record.push(match dec.get_choice(3, true)? {
+                    0 => MeasurementValue::Integer(dec.get_integer(0, u32::MAX as u64, false)? as u32),
+                    2 => MeasurementValue::NoValue,
+                    _ => return Err(LayerError::ProcessingError("REAL measurement values are not supported".into())),
+                });
+            }
+            if incomplete {
+                dec.get_enumerated(1, true)?;
+            }
+            records.push(record);
+        }
+        let measurements = if has_measurements { decode_measurement_info(dec)? } else { Vec::new() };
+        let granularity_period_ms = if has_granularity { dec.get_integer(1, u32::MAX as u64, false)? as u32 } else { 0 };
+        Ok(Self { records, measurements, granularity_period_ms })
+    }
+}
+
+/// Encode a service model structure into the octet string E2AP carries
+pub fn encode_e2sm<T: AperCodec>(value: &T) -> Result<Bytes, LayerError> {
+    let mut enc = AperEncoder::new();
+    value.encode(&mut enc)?;
+    Ok(enc.into_bytes())
+}
+
+/// Decode a service model structure from the octet string E2AP carries
+pub fn decode_e2sm<T: AperCodec>(data: &[u8]) -> Result<T, LayerError> {
+    T::decode(&mut AperDecoder::new(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: NrCgi = NrCgi { plmn_id: [0x02, 0xF8, 0x39], nr_cell_identity: 0x19B001 };
+
+    #[test]
+    fn test_kpm_codec() {
+        let description = KpmRanFunctionDescription::supported();
+        assert_eq!(decode_e2sm::<KpmRanFunctionDescription>(&encode_e2sm(&description).unwrap()).unwrap(),
+                   description);
+
+        let trigger = KpmEventTrigger { reporting_period_ms: 1000 };
+        assert_eq!(decode_e2sm::<KpmEventTrigger>(&encode_e2sm(&trigger).unwrap()).unwrap(), trigger);
+
+        let cell = KpmActionDefinition::Cell(KpmSubscriptionInfo {
+            measurements: vec![KpmMeasurement::PrbTotDl.into(), MeasurementType::Id(9)],
+            granularity_period_ms: 500,
+            cell: Some(CELL),
+        });
+        let decoded = decode_e2sm::<KpmActionDefinition>(&encode_e2sm(&cell).unwrap()).unwrap();
+        assert_eq!(decoded, cell);
+        assert_eq!(decoded.info().measurements.iter().map(KpmMeasurement::find).collect::<Vec<_>>(),
+                   vec![Some(KpmMeasurement::PrbTotDl), Some(KpmMeasurement::ConnMean)]);
+
+        let ue = KpmActionDefinition::Ue {
+            ue_id: UeId(7),
+            info: KpmSubscriptionInfo {
+                measurements: vec![KpmMeasurement::UeThpDl.into()],
+                granularity_period_ms: 1000,
+                cell: None,
+            },
+        };
+        assert_eq!(decode_e2sm::<KpmActionDefinition>(&encode_e2sm(&ue).unwrap()).unwrap(), ue);
+
+        let header = KpmIndicationHeader { collection_start: ntp_timestamp(SystemTime::now()) };
+        assert_eq!(decode_e2sm::<KpmIndicationHeader>(&encode_e2sm(&header).unwrap()).unwrap(), header);
+
+        let message = KpmIndicationMessage {
+            records: vec![vec![MeasurementValue::Integer(42), MeasurementValue::NoValue]],
+            measurements: vec![KpmMeasurement::PrbTotDl.into(), KpmMeasurement::TbTotNbrUl.into()],
+            granularity_period_ms: 1000,
+        };
+        assert_eq!(decode_e2sm::<KpmIndicationMessage>(&encode_e2sm(&message).unwrap()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_kpm_measurements() {
+        let rlc = |tx_bytes, rx_bytes| RlcStats { tx_sdus: 1, tx_bytes, rx_sdus: 1, rx_bytes };
+        let start = KpmSnapshot {
+            slots: 1000,
+            prbs_per_slot: 52,
+            dl_prbs_used: 5000,
+            ul_prbs_used: 0,
+            transport_blocks: TransportBlockStats { dl_total: 10, dl_errors: 1, ul_total: 0, ul_errors: 0 },
+            ues: HashMap::from([(1, UeCounters { dl_prbs: 100, ul_prbs: 0, rlc: rlc(1000, 0) })]),
+        };
+        let end = KpmSnapshot {
+            slots: 2000,
+            dl_prbs_used: 5000 + 26_000,
+            ul_prbs_used: 5200,
+            transport_blocks: TransportBlockStats { dl_total: 110, dl_errors: 11, ul_total: 50, ul_errors: 5 },
+            ues: HashMap::from([
+                (1, UeCounters { dl_prbs: 10_100, ul_prbs: 2000, rlc: rlc(126_000, 25_000) }),
+                (2, UeCounters { dl_prbs: 1000, ul_prbs: 0, rlc: rlc(0, 0) }),
+            ]),
+            ..start.clone()
+        };
+
+        let cell = |measurement: KpmMeasurement| measurement.cell_value(&start, &end, 1000);
+        assert_eq!(cell(KpmMeasurement::PrbTotDl), Some(50));
+        assert_eq!(cell(KpmMeasurement::PrbTotUl), Some(10));
+        assert_eq!(cell(KpmMeasurement::PrbUsedDl), Some(26));
+        assert_eq!(cell(KpmMeasurement::UeThpDl), Some(1000));
+        assert_eq!(cell(KpmMeasurement::UeThpUl), Some(200));
+        assert_eq!(cell(KpmMeasurement::RlcSduVolumeDl), Some(1000));
+        assert_eq!(cell(KpmMeasurement::ConnMean), Some(2));
+        assert_eq!(cell(KpmMeasurement::TbTotNbrDl), Some(100));
+        assert_eq!(cell(KpmMeasurement::TbErrTotalNbrDl), Some(10));
+        assert_eq!(cell(KpmMeasurement::TbErrTotalNbrUl), Some(5));
+
+        let ue = |measurement: KpmMeasurement, ue_id| measurement.ue_value(ue_id, &start, &end, 1000);
+        assert_eq!(ue(KpmMeasurement::PrbUsedDl, 1), Some(10));
+        assert_eq!(ue(KpmMeasurement::PrbUsedUl, 1), Some(2));
+        assert_eq!(ue(KpmMeasurement::UeThpDl, 1), Some(1000));
+        assert_eq!(ue(KpmMeasurement::UeThpDl, 3), None);
+        assert_eq!(ue(KpmMeasurement::ConnMean, 1), None);
+    }
+
+    #[test]
+    fn test_kpm_decode_errors() {
+        // Event trigger, indication header and message formats not supported
+        assert!(matches!(decode_e2sm::<KpmEventTrigger>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<KpmIndicationHeader>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<KpmIndicationMessage>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        let header = encode_e2sm(&KpmIndicationHeader { collection_start: 1 }).unwrap();
+        assert!(decode_e2sm::<KpmIndicationHeader>(&header[..header.len() - 1]).is_err());
+        assert!(decode_e2sm::<KpmEventTrigger>(&[]).is_err());
+
+        // Report style and action definition format must match
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        put_unconstrained_integer(&mut enc, STYLE_CELL).unwrap();
+        enc.put_choice(1, 3, true).unwrap();
+        assert!(matches!(decode_e2sm::<KpmActionDefinition>(&enc.into_bytes()), Err(LayerError::ProcessingError(_))));
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        put_unconstrained_integer(&mut enc, 3).unwrap();
+        enc.put_choice(2, 3, true).unwrap();
+        assert!(matches!(decode_e2sm::<KpmActionDefinition>(&enc.into_bytes()), Err(LayerError::ProcessingError(_))));
+
+        // Measurements with a label other than noLabel
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_length(1, 1, Some(MAX_MEASUREMENT_INFO)).unwrap();
+        enc.put_bool(false);
+        MeasurementType::from(KpmMeasurement::PrbTotDl).encode(&mut enc).unwrap();
+        enc.put_length(1, 1, Some(MAX_LABELS_AND_VALUES)).unwrap();
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bits(1 << 19, 21);
+        assert!(matches!(decode_e2sm::<KpmSubscriptionInfo>(&enc.into_bytes()), Err(LayerError::ProcessingError(_))));
+
+        // REAL measurement values
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        enc.put_choice(0, 2, true).unwrap();
+        enc.put_bits(0, 3);
+        enc.put_length(1, 1, Some(MAX_MEASUREMENT_RECORDS)).unwrap();
+        enc.put_bits(0, 2);
+        enc.put_length(1, 1, Some(MAX_LABELS_AND_VALUES)).unwrap();
+        enc.put_choice(1, 3, true).unwrap();
+        assert!(matches!(decode_e2sm::<KpmIndicationMessage>(&enc.into_bytes()), Err(LayerError::ProcessingError(_))));
+
+        // Measurements and records cannot be empty
+        let info = KpmSubscriptionInfo { measurements: Vec::new(), granularity_period_ms: 1000, cell: None };
+        assert!(encode_e2sm(&KpmActionDefinition::Cell(info)).is_err());
+        let message = KpmIndicationMessage { records: Vec::new(), measurements: Vec::new(), granularity_period_ms: 1000 };
+        assert!(encode_e2sm(&message).is_err());
+
+        // Unknown measurements, and values over an empty period
+        assert_eq!(KpmMeasurement::find(&MeasurementType::Name("DRB.Unknown".to_string())), None);
+        assert_eq!(KpmMeasurement::find(&MeasurementType::Id(0)), None);
+        let snapshot = KpmSnapshot::default();
+        assert_eq!(KpmMeasurement::PrbTotDl.cell_value(&snapshot, &snapshot, 1000), None);
+        assert_eq!(KpmMeasurement::PrbUsedUl.cell_value(&snapshot, &snapshot, 1000), None);
+        assert_eq!(KpmMeasurement::UeThpDl.cell_value(&snapshot, &snapshot, 0), None);
+        assert_eq!(KpmMeasurement::ConnMean.cell_value(&snapshot, &snapshot, 1000), Some(0));
+    }
+}
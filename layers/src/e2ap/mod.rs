@@ -0,0 +1,339 @@
+//! E2 Application Protocol (E2AP) Implementation
+//!
+//! The E2 node side of the O-RAN E2 interface (O-RAN.WG3.E2AP): the gNB opens
+//! an association to a near-RT RIC, announces its RAN functions in E2 Setup and
+//! serves the RIC Subscriptions of the xApps with periodic RIC Indications.
+//!
+//! The E2SM-KPM service model reports cell and UE measurements computed from
+//! the counters of the MAC scheduler, the PHY and the RLC entities through a
+//! [`kpm::KpmSource`]. The E2SM-RC service model lets xApps steer the gNB with
+//! RIC Controls: slice PRB quotas and scheduling policy of the MAC scheduler
+//! and UE handovers, applied through a [`rc::RcControl`].
+
+pub mod agent;
+pub mod control;
+pub mod e2sm;
+pub mod kpm;
+pub mod pdu;
+pub mod rc;
+pub mod subscription;
+pub mod transport;
+
+use crate::ngap::pdu::Criticality;
+
+pub use agent::{run_e2_agent, E2Agent, E2AgentConfig};
+pub use kpm::{KpmSnapshot, KpmSource, StackKpmSource};
+pub use rc::{RcControl, RcControlRequest, StackRcControl};
+
+/// E2AP procedure codes (O-RAN.WG3.E2AP section 9.3.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2apProcedureCode {
+    E2Setup = 1,
+    ErrorIndication = 2,
+    Reset = 3,
+    RicControl = 4,
+    RicIndication = 5,
+    RicServiceQuery = 6,
+    RicServiceUpdate = 7,
+    RicSubscription = 8,
+    RicSubscriptionDelete = 9,
+}
+
+impl E2apProcedureCode {
+    /// Look up a procedure code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use E2apProcedureCode::*;
+
+        [E2Setup, ErrorIndication, Reset, RicControl, RicIndication, RicServiceQuery, RicServiceUpdate,
+         RicSubscription, RicSubscriptionDelete]
+            .into_iter()
+            .find(|procedure| *procedure as u8 == code)
+    }
+
+    /// Criticality of the procedure: reject for class 1 procedures, ignore for
+    /// class 2
+    pub fn criticality(&self) -> Criticality {
+        match self {
+            E2apProcedureCode::ErrorIndication | E2apProcedureCode::RicIndication
+            | E2apProcedureCode::RicServiceQuery => Criticality::Ignore,
+            _ => Criticality::Reject,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{GlobalGnbId, NrCgi};
+    use crate::ngap::transport::NgTransportKind;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use e2sm::UeId;
+    use kpm::*;
+    use pdu::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+    use tokio::time::timeout;
+    use transport::{E2Listener, E2Receiver, E2TransportEvent, E2AP_STREAM};
+
+    const PLMN: [u8; 3] = [0x02, 0xF8, 0x39];
+    const CELL: NrCgi = NrCgi { plmn_id: PLMN, nr_cell_identity: 0x19B001 };
+
+    /// Counters advancing by the same amount at every sample: half of the 52
+    /// PRBs used over 10 slots, UE 1 getting 10 of them and 1250 bytes, one in
+    /// ten transport blocks failing. Both actions sample the source, so only
+    /// the ratios do not depend on how their samples interleave.
+    struct SteadySource(Mutex<KpmSnapshot>);
+
+    #[async_trait]
+    impl KpmSource for SteadySource {
+        async fn snapshot(&self) -> KpmSnapshot {
+            let mut snapshot = self.0.lock().await;
+            snapshot.slots += 10;
+            snapshot.dl_prbs_used += 260;
+            snapshot.transport_blocks.dl_total += 10;
+            snapshot.transport_blocks.dl_errors += 1;
+            let ue = snapshot.ues.entry(1).or_default();
+            ue.dl_prbs += 100;
+            ue.rlc.tx_bytes += 1250;
+            snapshot.clone()
+        }
+    }
+
+    async fn recv(receiver: &mut E2Receiver) -> E2apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            E2TransportEvent::Data { payload, .. } => E2apPdu::decode(&payload).unwrap(),
+            E2TransportEvent::AssociationLost(reason) => panic!("association lost: {}", reason),
+        }
+    }
+
+    fn action(action_id: u8, definition: &KpmActionDefinition) -> RicActionToBeSetup {
+        RicActionToBeSetup {
+            action_id,
+            action_type: RicActionType::Report,
+            definition: Some(encode_e2sm(definition).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2_kpm_subscription() {
+        let ric = E2Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let source = SteadySource(Mutex::new(KpmSnapshot { prbs_per_slot: 52, ..Default::default() }));
+        let agent = Arc::new(E2Agent::new(E2AgentConfig {
+            ric_address: ric.local_addr().unwrap(),
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            global_gnb_id: GlobalGnbId { plmn_id: PLMN, gnb_id: 0x19B, gnb_id_bits: 22 },
+            nr_cgi: CELL,
+            amf_name: "amf".to_string(),
+            reconnect_interval: Duration::from_millis(100),
+        }, Arc::new(source)));
+        let agent_task = tokio::spawn(run_e2_agent(Arc::clone(&agent)));
+        let (ric_tx, mut ric_rx) = ric.accept().await.unwrap();
+
+        // E2 Setup offers the KPM RAN function
+        let request = recv(&mut ric_rx).await;
+        assert_eq!(request.procedure(), Some(E2apProcedureCode::E2Setup));
+        assert_eq!(request.ie::<GlobalE2NodeId>(ID_GLOBAL_E2_NODE_ID).unwrap().0.gnb_id, 0x19B);
+        let functions = request.ie::<RanFunctionsList>(ID_RAN_FUNCTIONS_ADDED).unwrap().0;
+        assert_eq!((functions[0].id, functions[0].oid.as_str()), (KPM_RAN_FUNCTION_ID, KPM_OID));
+        let description = decode_e2sm::<KpmRanFunctionDescription>(&functions[0].definition).unwrap();
+        assert_eq!(description.report_styles.len(), 2);
+
+        let ric_id = GlobalRicId { plmn_id: PLMN, ric_id: 0x12345 };
+        let response = E2apPdu::successful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &request.ie::<TransactionId>(ID_TRANSACTION_ID).unwrap()).unwrap()
+            .with_ie(ID_GLOBAL_RIC_ID, Criticality::Reject, &ric_id).unwrap()
+            .with_ie(ID_RAN_FUNCTIONS_ACCEPTED, Criticality::Reject,
+                     &RanFunctionsIdList(vec![RanFunctionIdItem { id: KPM_RAN_FUNCTION_ID, revision: KPM_REVISION }])).unwrap()
+            .with_ie(ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK, Criticality::Reject,
+                     &E2NodeComponentConfigAdditionAckList(vec![E2NodeComponentConfigAdditionAck {
+                         amf_name: "amf".to_string(),
+                         failure_cause: None,
+                     }])).unwrap();
+        ric_tx.send(E2AP_STREAM, &response).await.unwrap();
+
+        // Subscribe to cell and UE measurements, the insert action is not supported
+        let request_id = RicRequestId { requestor_id: 100, instance_id: 1 };
+        let cell = KpmActionDefinition::Cell(KpmSubscriptionInfo {
+            measurements: vec![KpmMeasurement::PrbTotDl.into(), KpmMeasurement::ConnMean.into(),
+                               KpmMeasurement::TbTotNbrDl.into(), KpmMeasurement::TbErrTotalNbrDl.into()],
+            granularity_period_ms: 50,
+            cell: Some(CELL),
+        });
+        let ue = KpmActionDefinition::Ue {
+            ue_id: UeId(1),
+            info: KpmSubscriptionInfo {
+                measurements: vec![KpmMeasurement::PrbUsedDl.into(), KpmMeasurement::UeThpDl.into(),
+                                   KpmMeasurement::ConnMean.into()],
+                granularity_period_ms: 50,
+                cell: None,
+            },
+        };
+        let details = RicSubscriptionDetails {
+            event_trigger: encode_e2sm(&KpmEventTrigger { reporting_period_ms: 100 }).unwrap(),
+            actions: vec![
+                action(1, &cell),
+                action(2, &ue),
+                RicActionToBeSetup { action_id: 3, action_type: RicActionType::Insert, definition: None },
+            ],
+        };
+        let subscription = E2apPdu::initiating(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID)).unwrap()
+            .with_ie(ID_RIC_SUBSCRIPTION_DETAILS, Criticality::Reject, &details).unwrap();
+        ric_tx.send(E2AP_STREAM, &subscription).await.unwrap();
+
+        let response = recv(&mut ric_rx).await;
+        assert_eq!((response.pdu_type, response.procedure()),
+                   (E2apPduType::SuccessfulOutcome, Some(E2apProcedureCode::RicSubscription)));
+        assert_eq!(response.ie::<RicActionAdmittedList>(ID_RIC_ACTIONS_ADMITTED).unwrap().0,
+                   vec![RicActionAdmitted { action_id: 1 }, RicActionAdmitted { action_id: 2 }]);
+        assert_eq!(response.ie::<RicActionNotAdmittedList>(ID_RIC_ACTIONS_NOT_ADMITTED).unwrap().0,
+                   vec![RicActionNotAdmitted { action_id: 3, cause: Cause::ACTION_NOT_SUPPORTED }]);
+        assert_eq!(agent.ric_id().await, Some(ric_id));
+        assert_eq!(agent.subscription_count().await, 1);
+
+        // Two records per indication, the subscription's SN counting both actions
+        let mut sns = Vec::new();
+        let mut reported = HashMap::new();
+        while reported.len() < 2 || sns.len() < 4 {
+            let indication = recv(&mut ric_rx).await;
+            assert_eq!(indication.procedure(), Some(E2apProcedureCode::RicIndication));
+            assert_eq!(indication.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap(), request_id);
+            assert_eq!(indication.ie::<RicIndicationType>(ID_RIC_INDICATION_TYPE).unwrap(), RicIndicationType::Report);
+            sns.push(indication.ie::<RicIndicationSn>(ID_RIC_INDICATION_SN).unwrap().0);
+            let header = indication.ie::<Bytes>(ID_RIC_INDICATION_HEADER).unwrap();
+            assert!(decode_e2sm::<KpmIndicationHeader>(&header).unwrap().collection_start > 0);
+            let message = decode_e2sm::<KpmIndicationMessage>(&indication.ie::<Bytes>(ID_RIC_INDICATION_MESSAGE).unwrap())
+                .unwrap();
+            assert_eq!(message.records.len(), 2);
+            assert_eq!(message.granularity_period_ms, 50);
+            reported.insert(indication.ie::<RicActionId>(ID_RIC_ACTION_ID).unwrap().0, message.records[1].clone());
+        }
+        sns.sort();
+        assert_eq!(sns, vec![0, 1, 2, 3]);
+        use MeasurementValue::{Integer, NoValue};
+        let [Integer(50), Integer(1), Integer(total), Integer(errors)] = reported[&1][..] else {
+            panic!("unexpected cell record {:?}", reported[&1]);
+        };
+        assert_eq!(total, errors * 10);
+        let [Integer(10), Integer(throughput), NoValue] = reported[&2][..] else {
+            panic!("unexpected UE record {:?}", reported[&2]);
+        };
+        assert!(throughput > 0 && throughput % 200 == 0);
+
+        // Delete the subscription, then an unknown one
+        for (request_id, pdu_type) in [(request_id, E2apPduType::SuccessfulOutcome),
+                                       (RicRequestId { requestor_id: 100, instance_id: 2 }, E2apPduType::UnsuccessfulOutcome)] {
+            let delete = E2apPdu::initiating(E2apProcedureCode::RicSubscriptionDelete)
+                .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+                .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID)).unwrap();
+            ric_tx.send(E2AP_STREAM, &delete).await.unwrap();
+            let response = loop {
+                let pdu = recv(&mut ric_rx).await;
+                if pdu.procedure() == Some(E2apProcedureCode::RicSubscriptionDelete) {
+                    break pdu;
+                }
+            };
+            assert_eq!(response.pdu_type, pdu_type);
+            assert_eq!(response.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap(), request_id);
+        }
+        assert_eq!(agent.subscription_count().await, 0);
+
+        // No more indications once deleted
+        assert!(timeout(Duration::from_millis(250), ric_rx.recv()).await.is_err());
+        agent_task.abort();
+    }
+
+    /// Records the controls, failing handovers
+    struct RecordingControl(Mutex<Vec<RcControlRequest>>);
+
+    #[async_trait]
+    impl RcControl for RecordingControl {
+        async fn execute(&self, request: RcControlRequest) -> Result<(), crate::LayerError> {
+            let handover = matches!(request, RcControlRequest::Handover { .. });
+            self.0.lock().await.push(request);
+            if handover {
+                return Err(crate::LayerError::InvalidState("UE not connected".into()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2_rc_control() {
+        use crate::mac::{SchedulingPolicy, SlicePrbQuota};
+        use common::types::SNssai;
+        use rc::*;
+
+        let ric = E2Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let control = Arc::new(RecordingControl(Mutex::new(Vec::new())));
+        let mut agent = E2Agent::new(E2AgentConfig {
+            ric_address: ric.local_addr().unwrap(),
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            global_gnb_id: GlobalGnbId { plmn_id: PLMN, gnb_id: 0x19B, gnb_id_bits: 22 },
+            nr_cgi: CELL,
+            amf_name: "amf".to_string(),
+            reconnect_interval: Duration::from_millis(100),
+        }, Arc::new(SteadySource(Mutex::new(KpmSnapshot::default()))));
+        agent.set_rc_control(control.clone());
+        let agent_task = tokio::spawn(run_e2_agent(Arc::new(agent)));
+        let (ric_tx, mut ric_rx) = ric.accept().await.unwrap();
+
+        // E2 Setup offers the RC RAN function next to KPM
+        let request = recv(&mut ric_rx).await;
+        let functions = request.ie::<RanFunctionsList>(ID_RAN_FUNCTIONS_ADDED).unwrap().0;
+        assert_eq!((functions[1].id, functions[1].oid.as_str()), (RC_RAN_FUNCTION_ID, RC_OID));
+        assert_eq!(decode_e2sm::<RcRanFunctionDefinition>(&functions[1].definition).unwrap(),
+                   RcRanFunctionDefinition::supported());
+        let response = E2apPdu::successful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &request.ie::<TransactionId>(ID_TRANSACTION_ID).unwrap()).unwrap()
+            .with_ie(ID_GLOBAL_RIC_ID, Criticality::Reject, &GlobalRicId { plmn_id: PLMN, ric_id: 0x12345 }).unwrap();
+        ric_tx.send(E2AP_STREAM, &response).await.unwrap();
+
+        let quotas = RcControlRequest::SliceQuotas {
+            plmn_id: PLMN,
+            quotas: vec![SlicePrbQuota { s_nssai: SNssai { sst: 1, sd: Some(1) }, min_ratio: 30, max_ratio: 80, dedicated_ratio: 5 }],
+        };
+        let policy = RcControlRequest::SchedulingPolicy(SchedulingPolicy::ProportionalFair);
+        let handover = RcControlRequest::Handover {
+            ue_id: 1,
+            target: NrCgi { plmn_id: PLMN, nr_cell_identity: 0x19B002 },
+        };
+        // The policy is not acknowledged, the handover fails, the KPM function takes no controls
+        let controls = [
+            (1, RC_RAN_FUNCTION_ID, &quotas, RicControlAckRequest::Ack, Some((E2apPduType::SuccessfulOutcome, None))),
+            (2, RC_RAN_FUNCTION_ID, &policy, RicControlAckRequest::NoAck, None),
+            (3, RC_RAN_FUNCTION_ID, &handover, RicControlAckRequest::Ack,
+             Some((E2apPduType::UnsuccessfulOutcome, Some(Cause::CONTROL_FAILED_TO_EXECUTE)))),
+            (4, KPM_RAN_FUNCTION_ID, &policy, RicControlAckRequest::Ack,
+             Some((E2apPduType::UnsuccessfulOutcome, Some(Cause::RAN_FUNCTION_ID_INVALID)))),
+        ];
+        for (instance_id, function_id, control, ack_request, outcome) in controls {
+            let request_id = RicRequestId { requestor_id: 100, instance_id };
+            let (header, message) = control.encode().unwrap();
+            let request = E2apPdu::initiating(E2apProcedureCode::RicControl)
+                .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+                .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(function_id)).unwrap()
+                .with_ie(ID_RIC_CALL_PROCESS_ID, Criticality::Reject, &Bytes::from(vec![instance_id as u8])).unwrap()
+                .with_ie(ID_RIC_CONTROL_HEADER, Criticality::Reject, &header).unwrap()
+                .with_ie(ID_RIC_CONTROL_MESSAGE, Criticality::Reject, &message).unwrap()
+                .with_ie(ID_RIC_CONTROL_ACK_REQUEST, Criticality::Reject, &ack_request).unwrap();
+            ric_tx.send(E2AP_STREAM, &request).await.unwrap();
+            let Some((pdu_type, cause)) = outcome else {
+                continue;
+            };
+            let response = recv(&mut ric_rx).await;
+            assert_eq!((response.pdu_type, response.procedure()), (pdu_type, Some(E2apProcedureCode::RicControl)));
+            assert_eq!(response.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap(), request_id);
+            assert_eq!(response.ie::<Bytes>(ID_RIC_CALL_PROCESS_ID).unwrap(), Bytes::from(vec![instance_id as u8]));
+            assert_eq!(response.optional_ie::<Cause>(ID_CAUSE).unwrap(), cause);
+        }
+        assert_eq!(*control.0.lock().await, vec![quotas, policy, handover]);
+        agent_task.abort();
+    }
+}
@@ -0,0 +1,843 @@
+//! E2AP PDU and Information Element encoding
+//!
+//! E2AP-PDU structure and protocol IE containers according to O-RAN.WG3.E2AP
+//! section 9.3, encoded with APER. The container machinery and the Global gNB
+//! ID come from the NGAP codec. RAN function, action and indication contents
+//! are opaque octet strings here, they are encoded by the service models.
+
+use super::E2apProcedureCode;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{
+    decode_ie_list, encode_ie_list, ie_list, ApPdu, ApPduType, ApProcedureCode, AperCodec, Criticality, GlobalGnbId,
+};
+use crate::LayerError;
+use bytes::Bytes;
+
+/// Protocol IE identifiers (O-RAN.WG3.E2AP section 9.3.7)
+pub const ID_CAUSE: u16 = 1;
+pub const ID_GLOBAL_E2_NODE_ID: u16 = 3;
+pub const ID_GLOBAL_RIC_ID: u16 = 4;
+pub const ID_RAN_FUNCTION_ID: u16 = 5;
+pub const ID_RAN_FUNCTION_ID_ITEM: u16 = 6;
+pub const ID_RAN_FUNCTION_IE_CAUSE_ITEM: u16 = 7;
+pub const ID_RAN_FUNCTION_ITEM: u16 = 8;
+pub const ID_RAN_FUNCTIONS_ACCEPTED: u16 = 9;
+pub const ID_RAN_FUNCTIONS_ADDED: u16 = 10;
+pub const ID_RAN_FUNCTIONS_REJECTED: u16 = 13;
+pub const ID_RIC_ACTION_ADMITTED_ITEM: u16 = 14;
+pub const ID_RIC_ACTION_ID: u16 = 15;
+pub const ID_RIC_ACTION_NOT_ADMITTED_ITEM: u16 = 16;
+pub const ID_RIC_ACTIONS_ADMITTED: u16 = 17;
+pub const ID_RIC_ACTIONS_NOT_ADMITTED: u16 = 18;
+pub const ID_RIC_ACTION_TO_BE_SETUP_ITEM: u16 = 19;
+pub const ID_RIC_CALL_PROCESS_ID: u16 = 20;
+pub const ID_RIC_CONTROL_ACK_REQUEST: u16 = 21;
+pub const ID_RIC_CONTROL_HEADER: u16 = 22;
+pub const ID_RIC_CONTROL_MESSAGE: u16 = 23;
+pub const ID_RIC_INDICATION_HEADER: u16 = 25;
+pub const ID_RIC_INDICATION_MESSAGE: u16 = 26;
+pub const ID_RIC_INDICATION_SN: u16 = 27;
+pub const ID_RIC_INDICATION_TYPE: u16 = 28;
+pub const ID_RIC_REQUEST_ID: u16 = 29;
+pub const ID_RIC_SUBSCRIPTION_DETAILS: u16 = 30;
+pub const ID_TIME_TO_WAIT: u16 = 31;
+pub const ID_RIC_CONTROL_OUTCOME: u16 = 32;
+pub const ID_TRANSACTION_ID: u16 = 49;
+pub const ID_E2_NODE_COMPONENT_CONFIG_ADDITION: u16 = 50;
+pub const ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ITEM: u16 = 51;
+pub const ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK: u16 = 52;
+pub const ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK_ITEM: u16 = 53;
+
+/// maxofRANfunctionID
+const MAX_RAN_FUNCTIONS: usize = 256;
+/// maxofRICactionID
+const MAX_RIC_ACTIONS: usize = 16;
+/// maxofE2nodeComponents
+const MAX_E2_NODE_COMPONENTS: usize = 1024;
+
+impl ApProcedureCode for E2apProcedureCode {
+    const PROTOCOL: &'static str = "E2AP";
+    // initiatingMessage, successfulOutcome and unsuccessfulOutcome, extensible
+    const PDU_CHOICE: (usize, bool) = (3, true);
+
+    fn from_u8(code: u8) -> Option<Self> {
+        E2apProcedureCode::from_u8(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn criticality(&self) -> Criticality {
+        E2apProcedureCode::criticality(self)
+    }
+}
+
+/// E2AP-PDU choice
+pub type E2apPduType = ApPduType;
+
+/// E2AP PDU
+pub type E2apPdu = ApPdu<E2apProcedureCode>;
+
+/// Transaction ID, INTEGER (0..255, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionId(pub u8);
+
+impl AperCodec for TransactionId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 255, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 255, true)? as u8))
+    }
+}
+
+/// Global E2 Node ID (gNB alternative only, O-RAN.WG3.E2AP section 9.2.6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalE2NodeId(pub GlobalGnbId);
+
+impl AperCodec for GlobalE2NodeId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // GlobalE2node-ID: gNB
+        enc.put_choice(0, 4, true)?;
+        // GlobalE2node-gNB-ID: extension, global-en-gNB-ID, gNB-CU-UP-ID and gNB-DU-ID absent
+        enc.put_bool(false);
+        enc.put_bits(0, 3);
+        // GlobalgNB-ID: extension bit
+        enc.put_bool(false);
+        self.0.plmn_id.encode(enc)?;
+        // GNB-ID-Choice: gnb-ID
+        enc.put_choice(0, 1, true)?;
+        let bits = self.0.gnb_id_bits as usize;
+        let value = (self.0.gnb_id as u64) << (32 - bits);
+        enc.put_bit_string(&(value as u32).to_be_bytes(), bits, 22, Some(32), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(4, true)? != 0 {
+            return Err(LayerError::ProcessingError("Only gNB Global E2 Node IDs are supported".into()));
+        }
+        dec.get_bool()?;
+        if dec.get_bits(3)? != 0 {
+            return Err(LayerError::ProcessingError("Split gNB E2 Node IDs are not supported".into()));
+        }
+        dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        if dec.get_choice(1, true)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let (data, bits) = dec.get_bit_string(22, Some(32), false)?;
+        let mut padded = [0u8; 4];
+        padded[..data.len()].copy_from_slice(&data);
+        Ok(Self(GlobalGnbId {
+            plmn_id,
+            gnb_id: u32::from_be_bytes(padded) >> (32 - bits),
+            gnb_id_bits: bits as u8,
+        }))
+    }
+}
+
+/// Global RIC ID (O-RAN.WG3.E2AP section 9.2.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalRicId {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// Near-RT RIC ID (20 bits)
+    pub ric_id: u32,
+}
+
+impl AperCodec for GlobalRicId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        let value = (self.ric_id & 0xF_FFFF) << 12;
+        enc.put_bit_string(&value.to_be_bytes()[..3], 20, 20, Some(20), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let (data, _) = dec.get_bit_string(20, Some(20), false)?;
+        let mut padded = [0u8; 4];
+        padded[..data.len()].copy_from_slice(&data);
+        Ok(Self { plmn_id, ric_id: u32::from_be_bytes(padded) >> 12 })
+    }
+}
+
+/// Cause (O-RAN.WG3.E2AP section 9.2.1)
+///
+/// Values are the ENUMERATED indices of the respective cause group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    RicRequest(u8),
+    RicService(u8),
+    E2Node(u8),
+    Transport(u8),
+    Protocol(u8),
+    Misc(u8),
+}
+
+impl Cause {
+    /// Root values of the ricRequest, ricService, e2Node, transport, protocol
+    /// and misc groups
+    const ROOT_COUNTS: [usize; 6] = [14, 3, 1, 2, 7, 4];
+
+    /// RIC request: RAN function ID invalid
+    pub const RAN_FUNCTION_ID_INVALID: Cause = Cause::RicRequest(0);
+    /// RIC request: action not supported
+    pub const ACTION_NOT_SUPPORTED: Cause = Cause::RicRequest(1);
+    /// RIC request: excessive actions
+    pub const EXCESSIVE_ACTIONS: Cause = Cause::RicRequest(2);
+    /// RIC request: duplicate action
+    pub const DUPLICATE_ACTION: Cause = Cause::RicRequest(3);
+    /// RIC request: duplicate event trigger
+    pub const DUPLICATE_EVENT_TRIGGER: Cause = Cause::RicRequest(4);
+    /// RIC request: request ID unknown
+    pub const REQUEST_ID_UNKNOWN: Cause = Cause::RicRequest(6);
+    /// RIC request: control message invalid
+    pub const CONTROL_MESSAGE_INVALID: Cause = Cause::RicRequest(8);
+    /// RIC request: control failed to execute
+    pub const CONTROL_FAILED_TO_EXECUTE: Cause = Cause::RicRequest(11);
+    /// RIC request: system not ready
+    pub const SYSTEM_NOT_READY: Cause = Cause::RicRequest(12);
+    /// RIC request: unspecified
+    pub const RIC_REQUEST_UNSPECIFIED: Cause = Cause::RicRequest(13);
+    /// Protocol: semantic error
+    pub const SEMANTIC_ERROR: Cause = Cause::Protocol(4);
+    /// Misc: unspecified
+    pub const MISC_UNSPECIFIED: Cause = Cause::Misc(3);
+
+    fn group(&self) -> (usize, u8) {
+        match *self {
+            Cause::RicRequest(value) => (0, value),
+            Cause::RicService(value) => (1, value),
+            Cause::E2Node(value) => (2, value),
+            Cause::Transport(value) => (3, value),
+            Cause::Protocol(value) => (4, value),
+            Cause::Misc(value) => (5, value),
+        }
+    }
+}
+
+impl AperCodec for Cause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let (group, value) = self.group();
+        enc.put_choice(group, 6, true)?;
+        enc.put_enumerated(value as usize, Self::ROOT_COUNTS[group], true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let group = dec.get_choice(6, true)?;
+        if group >= Self::ROOT_COUNTS.len() {
+            return Err(LayerError::ProcessingError("Unknown cause group".into()));
+        }
+        let value = dec.get_enumerated(Self::ROOT_COUNTS[group], true)? as u8;
+        Ok(match group {
+            0 => Cause::RicRequest(value),
+            1 => Cause::RicService(value),
+            2 => Cause::E2Node(value),
+            3 => Cause::Transport(value),
+            4 => Cause::Protocol(value),
+            _ => Cause::Misc(value),
+        })
+    }
+}
+
+/// RAN function ID, INTEGER (0..4095)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RanFunctionId(pub u16);
+
+impl AperCodec for RanFunctionId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 4095, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 4095, false)? as u16))
+    }
+}
+
+/// RAN function offered in E2 Setup (RANfunction-Item)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RanFunctionItem {
+    /// RAN function ID
+    pub id: u16,
+    /// RAN function definition encoded by the service model
+    pub definition: Bytes,
+    /// RAN function revision
+    pub revision: u16,
+    /// Object identifier of the service model
+    pub oid: String,
+}
+
+impl AperCodec for RanFunctionItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.id as u64, 0, 4095, false)?;
+        enc.put_octet_string(&self.definition, 0, None, false)?;
+        enc.put_integer(self.revision as u64, 0, 4095, false)?;
+        enc.put_printable_string(&self.oid, 1, 1000, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let id = dec.get_integer(0, 4095, false)? as u16;
+        let definition = Bytes::from(dec.get_octet_string(0, None, false)?);
+        let revision = dec.get_integer(0, 4095, false)? as u16;
+        let oid = dec.get_printable_string(1, 1000, true)?;
+        Ok(Self { id, definition, revision, oid })
+    }
+}
+
+ie_list!(
+    /// RAN functions added in E2 Setup (RANfunctions-List)
+    RanFunctionsList, RanFunctionItem, ID_RAN_FUNCTION_ITEM, Criticality::Ignore, 1, MAX_RAN_FUNCTIONS
+);
+
+/// RAN function accepted by the RIC (RANfunctionID-Item)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RanFunctionIdItem {
+    /// RAN function ID
+    pub id: u16,
+    /// RAN function revision
+    pub revision: u16,
+}
+
+impl AperCodec for RanFunctionIdItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.id as u64, 0, 4095, false)?;
+        enc.put_integer(self.revision as u64, 0, 4095, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let id = dec.get_integer(0, 4095, false)? as u16;
+        let revision = dec.get_integer(0, 4095, false)? as u16;
+        Ok(Self { id, revision })
+    }
+}
+
+ie_list!(
+    /// RAN functions accepted by the RIC (RANfunctionsID-List)
+    RanFunctionsIdList, RanFunctionIdItem, ID_RAN_FUNCTION_ID_ITEM, Criticality::Ignore, 1, MAX_RAN_FUNCTIONS
+);
+
+/// RAN function rejected by the RIC (RANfunctionIDcause-Item)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RanFunctionIdCauseItem {
+    /// RAN function ID
+    pub id: u16,
+    /// Why the function was rejected
+    pub cause: Cause,
+}
+
+impl AperCodec for RanFunctionIdCauseItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.id as u64, 0, 4095, false)?;
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let id = dec.get_integer(0, 4095, false)? as u16;
+        let cause = Cause::decode(dec)?;
+        Ok(Self { id, cause })
+    }
+}
+
+ie_list!(
+    /// RAN functions rejected by the RIC (RANfunctionsIDcause-List)
+    RanFunctionsIdCauseList, RanFunctionIdCauseItem, ID_RAN_FUNCTION_IE_CAUSE_ITEM, Criticality::Ignore, 1,
+    MAX_RAN_FUNCTIONS
+);
+
+/// NG interface of the E2 node and the AMF it connects to
+///
+/// Only the NG interface type is reported as an E2 node component: interface
+/// type ng, component ID with the AMF name.
+fn encode_ng_component(enc: &mut AperEncoder, amf_name: &str) -> Result<(), LayerError> {
+    // E2nodeComponentInterfaceType: ng
+    enc.put_enumerated(0, 7, true)?;
+    // E2nodeComponentID: e2nodeComponentInterfaceTypeNG
+    enc.put_choice(0, 7, true)?;
+    enc.put_bool(false);
+    enc.put_printable_string(amf_name, 1, 150, true)
+}
+
+fn decode_ng_component(dec: &mut AperDecoder) -> Result<String, LayerError> {
+    if dec.get_enumerated(7, true)? != 0 || dec.get_choice(7, true)? != 0 {
+        return Err(LayerError::ProcessingError("Only NG E2 node components are supported".into()));
+    }
+    dec.get_bool()?;
+    dec.get_printable_string(1, 150, true)
+}
+
+/// E2 node component reported in E2 Setup (E2nodeComponentConfigAddition-Item)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct E2NodeComponentConfigAddition {
+    /// Name of the AMF the NG interface connects to
+    pub amf_name: String,
+    /// Last NG Setup Request sent
+    pub request_part: Bytes,
+    /// Last NG Setup Response received
+    pub response_part: Bytes,
+}
+
+impl AperCodec for E2NodeComponentConfigAddition {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        encode_ng_component(enc, &self.amf_name)?;
+        // E2nodeComponentConfiguration
+        enc.put_bool(false);
+        enc.put_octet_string(&self.request_part, 0, None, false)?;
+        enc.put_octet_string(&self.response_part, 0, None, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let amf_name = decode_ng_component(dec)?;
+        dec.get_bool()?;
+        let request_part = Bytes::from(dec.get_octet_string(0, None, false)?);
+        let response_part = Bytes::from(dec.get_octet_string(0, None, false)?);
+        Ok(Self { amf_name, request_part, response_part })
+    }
+}
+
+ie_list!(
+    /// E2 node components reported in E2 Setup (E2nodeComponentConfigAddition-List)
+    E2NodeComponentConfigAdditionList, E2NodeComponentConfigAddition, ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ITEM,
+    Criticality::Reject, 1, MAX_E2_NODE_COMPONENTS
+);
+
+/// Outcome of an E2 node component in E2 Setup (E2nodeComponentConfigAdditionAck-Item)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct E2NodeComponentConfigAdditionAck {
+    /// Name of the AMF the NG interface connects to
+    pub amf_name: String,
+    /// Cause of a failed update, None on success
+    pub failure_cause: Option<Cause>,
+}
+
+impl AperCodec for E2NodeComponentConfigAdditionAck {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        encode_ng_component(enc, &self.amf_name)?;
+        // E2nodeComponentConfigurationAck: updateOutcome success or failure
+        enc.put_bool(false);
+        enc.put_bool(self.failure_cause.is_some());
+        enc.put_enumerated(usize::from(self.failure_cause.is_some()), 2, true)?;
+        if let Some(cause) = &self.failure_cause {
+            cause.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let amf_name = decode_ng_component(dec)?;
+        dec.get_bool()?;
+        let has_cause = dec.get_bool()?;
+        let failed = dec.get_enumerated(2, true)? == 1;
+        let cause = if has_cause { Some(Cause::decode(dec)?) } else { None };
+        let failure_cause = match (failed, cause) {
+            (false, _) => None,
+            (true, cause) => Some(cause.unwrap_or(Cause::MISC_UNSPECIFIED)),
+        };
+        Ok(Self { amf_name, failure_cause })
+    }
+}
+
+ie_list!(
+    /// Outcome of the E2 node components (E2nodeComponentConfigAdditionAck-List)
+    E2NodeComponentConfigAdditionAckList, E2NodeComponentConfigAdditionAck,
+    ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK_ITEM, Criticality::Reject, 1, MAX_E2_NODE_COMPONENTS
+);
+
+/// RIC Request ID (O-RAN.WG3.E2AP section 9.2.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RicRequestId {
+    /// Requestor ID, chosen by the xApp
+    pub requestor_id: u16,
+    /// Instance ID
+    pub instance_id: u16,
+}
+
+impl AperCodec for RicRequestId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.requestor_id as u64, 0, 65535, false)?;
+        enc.put_integer(self.instance_id as u64, 0, 65535, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let requestor_id = dec.get_integer(0, 65535, false)? as u16;
+        let instance_id = dec.get_integer(0, 65535, false)? as u16;
+        Ok(Self { requestor_id, instance_id })
+    }
+}
+
+/// RIC action type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RicActionType {
+    Insert = 0,
+    Policy = 1,
+    Report = 2,
+}
+
+/// RIC action requested in a subscription (RICaction-ToBeSetup-Item)
+///
+/// A subsequent action is accepted on decoding and ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RicActionToBeSetup {
+    /// RIC action ID
+    pub action_id: u8,
+    /// RIC action type
+    pub action_type: RicActionType,
+    /// Action definition encoded by the service model
+    pub definition: Option<Bytes>,
+}
+
+impl AperCodec for RicActionToBeSetup {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.definition.is_some());
+        enc.put_bool(false);
+        enc.put_integer(self.action_id as u64, 0, 255, false)?;
+        enc.put_enumerated(self.action_type as usize, 3, true)?;
+        if let Some(definition) = &self.definition {
+            enc.put_octet_string(definition, 0, None, false)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_definition = dec.get_bool()?;
+        let has_subsequent_action = dec.get_bool()?;
+        let action_id = dec.get_integer(0, 255, false)? as u8;
+        let action_type = match dec.get_enumerated(3, true)? {
+            0 => RicActionType::Insert,
+            1 => RicActionType::Policy,
+            2 => RicActionType::Report,
+            _ => return Err(LayerError::ProcessingError("Unknown RIC action type".into())),
+        };
+        let definition = if has_definition {
+            Some(Bytes::from(dec.get_octet_string(0, None, false)?))
+        } else {
+            None
+        };
+        if has_subsequent_action {
+            // RICsubsequentAction: subsequent action type and time to wait
+            dec.get_bool()?;
+            dec.get_enumerated(2, true)?;
+            dec.get_enumerated(18, true)?;
+        }
+        Ok(Self { action_id, action_type, definition })
+    }
+}
+
+/// Event trigger and actions of a subscription (RICsubscriptionDetails)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RicSubscriptionDetails {
+    /// Event trigger definition encoded by the service model
+    pub event_trigger: Bytes,
+    /// Actions to set up
+    pub actions: Vec<RicActionToBeSetup>,
+}
+
+impl AperCodec for RicSubscriptionDetails {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_octet_string(&self.event_trigger, 0, None, false)?;
+        encode_ie_list(enc, ID_RIC_ACTION_TO_BE_SETUP_ITEM, Criticality::Ignore, &self.actions, 1, MAX_RIC_ACTIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let event_trigger = Bytes::from(dec.get_octet_string(0, None, false)?);
+        let actions = decode_ie_list(dec, 1, MAX_RIC_ACTIONS)?;
+        Ok(Self { event_trigger, actions })
+    }
+}
+
+/// Action admitted by the E2 node (RICaction-Admitted-Item)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RicActionAdmitted {
+    /// RIC action ID
+    pub action_id: u8,
+}
+
+impl AperCodec for RicActionAdmitted {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.action_id as u64, 0, 255, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        Ok(Self { action_id: dec.get_integer(0, 255, false)? as u8 })
+    }
+}
+
+ie_list!(
+    /// Actions admitted by the E2 node (RICaction-Admitted-List)
+    RicActionAdmittedList, RicActionAdmitted, ID_RIC_ACTION_ADMITTED_ITEM, Criticality::Ignore, 1, MAX_RIC_ACTIONS
+);
+
+/// Action not admitted by the E2 node (RICaction-NotAdmitted-Item)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RicActionNotAdmitted {
+    /// RIC action ID
+    pub action_id: u8,
+    /// Why the action was not admitted
+    pub cause: Cause,
+}
+
+impl AperCodec for RicActionNotAdmitted {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.action_id as u64, 0, 255, false)?;
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let action_id = dec.get_integer(0, 255, false)? as u8;
+        let cause = Cause::decode(dec)?;
+        Ok(Self { action_id, cause })
+    }
+}
+
+ie_list!(
+    /// Actions not admitted by the E2 node (RICaction-NotAdmitted-List)
+    RicActionNotAdmittedList, RicActionNotAdmitted, ID_RIC_ACTION_NOT_ADMITTED_ITEM, Criticality::Ignore, 0,
+    MAX_RIC_ACTIONS
+);
+
+/// RIC action ID, INTEGER (0..255)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RicActionId(pub u8);
+
+impl AperCodec for RicActionId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 255, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 255, false)? as u8))
+    }
+}
+
+/// RIC indication SN, INTEGER (0..65535)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RicIndicationSn(pub u16);
+
+impl AperCodec for RicIndicationSn {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 65535, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 65535, false)? as u16))
+    }
+}
+
+/// RIC indication type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RicIndicationType {
+    Report = 0,
+    Insert = 1,
+}
+
+impl AperCodec for RicIndicationType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(2, true)? {
+            0 => Ok(RicIndicationType::Report),
+            1 => Ok(RicIndicationType::Insert),
+            _ => Err(LayerError::ProcessingError("Unknown RIC indication type".into())),
+        }
+    }
+}
+
+/// Whether the RIC wants a RIC Control Acknowledge (RICcontrolAckRequest)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RicControlAckRequest {
+    NoAck = 0,
+    Ack = 1,
+}
+
+impl AperCodec for RicControlAckRequest {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(2, true)? {
+            0 => Ok(RicControlAckRequest::NoAck),
+            1 => Ok(RicControlAckRequest::Ack),
+            _ => Err(LayerError::ProcessingError("Unknown RIC control ack request".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{decode_ie, encode_ie};
+
+    #[test]
+    fn test_e2ap_messages() {
+        let global_id = GlobalE2NodeId(GlobalGnbId { plmn_id: [0x02, 0xF8, 0x39], gnb_id: 0x19B, gnb_id_bits: 22 });
+        let functions = RanFunctionsList(vec![RanFunctionItem {
+            id: 2,
+            definition: Bytes::from_static(&[0x01, 0x02, 0x03]),
+            revision: 1,
+            oid: "1.3.6.1.4.1.53148.1.3.2.2".to_string(),
+        }]);
+        let components = E2NodeComponentConfigAdditionList(vec![E2NodeComponentConfigAddition {
+            amf_name: "amf".to_string(),
+            request_part: Bytes::new(),
+            response_part: Bytes::from_static(&[0x20]),
+        }]);
+        let request = E2apPdu::initiating(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(3)).unwrap()
+            .with_ie(ID_GLOBAL_E2_NODE_ID, Criticality::Reject, &global_id).unwrap()
+            .with_ie(ID_RAN_FUNCTIONS_ADDED, Criticality::Reject, &functions).unwrap()
+            .with_ie(ID_E2_NODE_COMPONENT_CONFIG_ADDITION, Criticality::Reject, &components).unwrap();
+        let encoded = request.encode().unwrap();
+        // initiatingMessage, procedure code 1, criticality reject
+        assert_eq!(&encoded[..3], &[0x00, 0x01, 0x00]);
+        let decoded = E2apPdu::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.procedure(), Some(E2apProcedureCode::E2Setup));
+        assert_eq!(decoded.ie::<GlobalE2NodeId>(ID_GLOBAL_E2_NODE_ID).unwrap(), global_id);
+        assert_eq!(decoded.ie::<RanFunctionsList>(ID_RAN_FUNCTIONS_ADDED).unwrap(), functions);
+        assert_eq!(decoded.ie::<E2NodeComponentConfigAdditionList>(ID_E2_NODE_COMPONENT_CONFIG_ADDITION).unwrap(),
+                   components);
+
+        let ric_id = GlobalRicId { plmn_id: [0x02, 0xF8, 0x39], ric_id: 0xABCDE };
+        let acks = E2NodeComponentConfigAdditionAckList(vec![E2NodeComponentConfigAdditionAck {
+            amf_name: "amf".to_string(),
+            failure_cause: Some(Cause::E2Node(0)),
+        }]);
+        let response = E2apPdu::successful(E2apProcedureCode::E2Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(3)).unwrap()
+            .with_ie(ID_GLOBAL_RIC_ID, Criticality::Reject, &ric_id).unwrap()
+            .with_ie(ID_RAN_FUNCTIONS_ACCEPTED, Criticality::Reject,
+                     &RanFunctionsIdList(vec![RanFunctionIdItem { id: 2, revision: 1 }])).unwrap()
+            .with_ie(ID_RAN_FUNCTIONS_REJECTED, Criticality::Reject,
+                     &RanFunctionsIdCauseList(vec![RanFunctionIdCauseItem { id: 3, cause: Cause::RicService(0) }]))
+            .unwrap()
+            .with_ie(ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK, Criticality::Reject, &acks).unwrap();
+        let decoded = E2apPdu::decode(&response.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<GlobalRicId>(ID_GLOBAL_RIC_ID).unwrap(), ric_id);
+        assert_eq!(decoded.ie::<RanFunctionsIdCauseList>(ID_RAN_FUNCTIONS_REJECTED).unwrap().0[0].id, 3);
+        assert_eq!(decoded.ie::<E2NodeComponentConfigAdditionAckList>(ID_E2_NODE_COMPONENT_CONFIG_ADDITION_ACK)
+                       .unwrap(), acks);
+
+        // RIC Subscription with a report and an insert action
+        let request_id = RicRequestId { requestor_id: 1000, instance_id: 1 };
+        let details = RicSubscriptionDetails {
+            event_trigger: Bytes::from_static(&[0x00, 0x03, 0xE8]),
+            actions: vec![
+                RicActionToBeSetup { action_id: 0, action_type: RicActionType::Report, definition: Some(Bytes::from_static(&[0x01])) },
+                RicActionToBeSetup { action_id: 1, action_type: RicActionType::Insert, definition: None },
+            ],
+        };
+        let subscription = E2apPdu::initiating(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(2)).unwrap()
+            .with_ie(ID_RIC_SUBSCRIPTION_DETAILS, Criticality::Reject, &details).unwrap();
+        let decoded = E2apPdu::decode(&subscription.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap(), request_id);
+        assert_eq!(decoded.ie::<RicSubscriptionDetails>(ID_RIC_SUBSCRIPTION_DETAILS).unwrap(), details);
+
+        let response = E2apPdu::successful(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_ACTIONS_ADMITTED, Criticality::Reject, &RicActionAdmittedList(vec![RicActionAdmitted { action_id: 0 }])).unwrap()
+            .with_ie(ID_RIC_ACTIONS_NOT_ADMITTED, Criticality::Reject, &RicActionNotAdmittedList(vec![
+                RicActionNotAdmitted { action_id: 1, cause: Cause::ACTION_NOT_SUPPORTED },
+            ])).unwrap();
+        let decoded = E2apPdu::decode(&response.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<RicActionAdmittedList>(ID_RIC_ACTIONS_ADMITTED).unwrap().0,
+                   vec![RicActionAdmitted { action_id: 0 }]);
+        assert_eq!(decoded.ie::<RicActionNotAdmittedList>(ID_RIC_ACTIONS_NOT_ADMITTED).unwrap().0[0].cause,
+                   Cause::ACTION_NOT_SUPPORTED);
+    }
+
+    #[test]
+    fn test_e2ap_decode_errors() {
+        // RAN functions need an ID and revision in range and an OID, the list at most 256 functions
+        let function = RanFunctionItem {
+            id: 2,
+            definition: Bytes::from_static(&[0x01, 0x02, 0x03, 0x04]),
+            revision: 1,
+            oid: "1.3.6.1.4.1.53148.1.3.2.2".to_string(),
+        };
+        assert!(encode_ie(&RanFunctionItem { oid: String::new(), ..function.clone() }).is_err());
+        assert!(encode_ie(&RanFunctionItem { id: 4096, ..function.clone() }).is_err());
+        assert!(encode_ie(&RanFunctionItem { revision: 4096, ..function.clone() }).is_err());
+        assert!(encode_ie(&RanFunctionsList(Vec::new())).is_err());
+        assert!(encode_ie(&RanFunctionsList(vec![function.clone(); MAX_RAN_FUNCTIONS + 1])).is_err());
+        // Definition cut short, then the OID
+        let encoded = encode_ie(&function).unwrap();
+        assert!(matches!(decode_ie::<RanFunctionItem>(&encoded[..5]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<RanFunctionItem>(&encoded[..encoded.len() - 1]), Err(LayerError::InvalidPdu)));
+        assert_eq!(decode_ie::<RanFunctionItem>(&encoded).unwrap(), function);
+
+        // Subscriptions need one to 16 actions, each of a known type
+        let action = RicActionToBeSetup { action_id: 1, action_type: RicActionType::Report, definition: None };
+        let details = |actions| RicSubscriptionDetails { event_trigger: Bytes::from_static(&[0x01]), actions };
+        assert!(encode_ie(&details(Vec::new())).is_err());
+        assert!(encode_ie(&details(vec![action.clone(); MAX_RIC_ACTIONS + 1])).is_err());
+        let mut enc = AperEncoder::new();
+        enc.put_bits(0, 3);
+        enc.put_integer(0, 0, 255, false).unwrap();
+        // Action type from the extension
+        enc.put_bool(true);
+        enc.put_bool(false);
+        enc.put_bits(0, 6);
+        assert!(matches!(decode_ie::<RicActionToBeSetup>(&enc.into_bytes()), Err(LayerError::ProcessingError(_))));
+        // An action definition cut short inside the action list
+        let encoded = encode_ie(&details(vec![RicActionToBeSetup {
+            definition: Some(Bytes::from_static(&[0x01, 0x02])),
+            ..action.clone()
+        }])).unwrap();
+        assert!(matches!(decode_ie::<RicSubscriptionDetails>(&encoded[..encoded.len() - 1]), Err(LayerError::InvalidPdu)));
+        // A subsequent action is skipped
+        let mut enc = AperEncoder::new();
+        enc.put_bits(1, 3);
+        enc.put_integer(1, 0, 255, false).unwrap();
+        enc.put_enumerated(RicActionType::Report as usize, 3, true).unwrap();
+        enc.put_bool(false);
+        enc.put_enumerated(0, 2, true).unwrap();
+        enc.put_enumerated(5, 18, true).unwrap();
+        assert_eq!(decode_ie::<RicActionToBeSetup>(&enc.into_bytes()).unwrap(), action);
+
+        // Cause group beyond misc, cause value outside its root
+        assert!(matches!(decode_ie::<Cause>(&[0x80]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<Cause>(&[0x60]), Err(LayerError::InvalidPdu)));
+
+        // en-gNB and split gNB E2 nodes, E2 node components other than NG
+        assert!(matches!(decode_ie::<GlobalE2NodeId>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<GlobalE2NodeId>(&[0x02]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<E2NodeComponentConfigAddition>(&[0x08, 0x00]), Err(LayerError::ProcessingError(_))));
+
+        // A failed component update without a cause
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        encode_ng_component(&mut enc, "amf").unwrap();
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_enumerated(1, 2, true).unwrap();
+        assert_eq!(decode_ie::<E2NodeComponentConfigAdditionAck>(&enc.into_bytes()).unwrap().failure_cause,
+                   Some(Cause::MISC_UNSPECIFIED));
+    }
+}
@@ -0,0 +1,885 @@
+//! E2SM-RC service model
+//!
+//! RAN control according to O-RAN.WG3.E2SM-RC, restricted to control service
+//! styles with control header and message format 1:
+//! - style 2, radio resource allocation: the slice level PRB quotas (action 6)
+//!   and, as implementation specific action, the scheduling policy of
+//!   [`MacScheduler`]
+//! - style 3, connected mode mobility: handover of a UE to a target cell
+//!   (action 1), carried out by [`RrcLayer`]
+//!
+//! The RAN parameters are the trees of E2SM-RC section 8.4, built and walked
+//! by [`RcControlRequest`].
+
+use super::e2sm::{
+    get_nr_cgi, get_unconstrained_integer, put_nr_cgi, put_unconstrained_integer, RanFunctionName, RicStyle, UeId,
+};
+use super::kpm::{decode_e2sm, encode_e2sm};
+use crate::mac::{MacScheduler, SchedulingPolicy, SlicePrbQuota};
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{AperCodec, NrCgi};
+use crate::rrc::RrcLayer;
+use crate::LayerError;
+use async_trait::async_trait;
+use bytes::Bytes;
+use common::types::SNssai;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+
+/// RAN function ID the RC service model is offered under
+pub const RC_RAN_FUNCTION_ID: u16 = 3;
+/// Object identifier of E2SM-RC
+pub const RC_OID: &str = "1.3.6.1.4.1.53148.1.1.2.3";
+/// RAN function revision
+pub const RC_REVISION: u16 = 0;
+/// Short name of the service model
+const RC_SHORT_NAME: &str = "ORAN-E2SM-RC";
+
+/// Control style: radio resource allocation control
+pub const STYLE_RADIO_RESOURCE_ALLOCATION: i64 = 2;
+/// Control style: connected mode mobility control
+pub const STYLE_CONNECTED_MODE_MOBILITY: i64 = 3;
+
+/// Radio resource allocation action: slice level PRB quota
+pub const ACTION_SLICE_PRB_QUOTA: u16 = 6;
+/// Radio resource allocation action: scheduling policy, implementation specific
+pub const ACTION_SCHEDULING_POLICY: u16 = 100;
+/// Connected mode mobility action: handover control
+pub const ACTION_HANDOVER: u16 = 1;
+
+/// maxnoofControlStyles
+const MAX_STYLES: usize = 63;
+/// maxnoofControlAction
+const MAX_ACTIONS: usize = 65535;
+/// maxnoofAssociatedRANParameters, maxnoofParametersinStructure and maxnoofItemsinList
+const MAX_PARAMETERS: usize = 65535;
+
+/// Slice level PRB quota parameters (E2SM-RC section 8.4.3.6)
+mod quota {
+    pub const RRM_POLICY_RATIO_LIST: u32 = 1;
+    pub const RRM_POLICY_RATIO_GROUP: u32 = 2;
+    pub const RRM_POLICY: u32 = 3;
+    pub const RRM_POLICY_MEMBER_LIST: u32 = 4;
+    pub const RRM_POLICY_MEMBER: u32 = 5;
+    pub const PLMN_IDENTITY: u32 = 6;
+    pub const S_NSSAI: u32 = 7;
+    pub const SST: u32 = 8;
+    pub const SD: u32 = 9;
+    pub const MIN_PRB_POLICY_RATIO: u32 = 10;
+    pub const MAX_PRB_POLICY_RATIO: u32 = 11;
+    pub const DEDICATED_PRB_POLICY_RATIO: u32 = 12;
+}
+
+/// Handover control parameters (E2SM-RC section 8.4.4.1)
+mod handover {
+    pub const TARGET_PRIMARY_CELL_ID: u32 = 1;
+    pub const TARGET_CELL: u32 = 2;
+    pub const NR_CELL: u32 = 3;
+    pub const NR_CGI: u32 = 4;
+}
+
+/// Scheduling policy parameter of the scheduling policy action
+const SCHEDULING_POLICY: u32 = 1;
+
+/// Value of an element RAN parameter (RANParameter-Value)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RanParameterValue {
+    Boolean(bool),
+    Integer(i64),
+    OctetString(Bytes),
+    PrintableString(String),
+}
+
+impl AperCodec for RanParameterValue {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match self {
+            RanParameterValue::Boolean(value) => {
+                enc.put_choice(0, 6, true)?;
+                enc.put_bool(*value);
+                Ok(())
+            }
+            RanParameterValue::Integer(value) => {
+                enc.put_choice(1, 6, true)?;
+                put_unconstrained_integer(enc, *value)
+            }
+            RanParameterValue::OctetString(value) => {
+                enc.put_choice(4, 6, true)?;
+                value.encode(enc)
+            }
+            RanParameterValue::PrintableString(value) => {
+                enc.put_choice(5, 6, true)?;
+                enc.put_printable_string(value, 0, u32::MAX as usize, false)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(6, true)? {
+            0 => Ok(RanParameterValue::Boolean(dec.get_bool()?)),
+            1 => Ok(RanParameterValue::Integer(get_unconstrained_integer(dec)?)),
+            4 => Ok(RanParameterValue::OctetString(Bytes::decode(dec)?)),
+            5 => Ok(RanParameterValue::PrintableString(dec.get_printable_string(0, u32::MAX as usize, false)?)),
+            _ => Err(LayerError::ProcessingError("REAL and BIT STRING RAN parameters are not supported".into())),
+        }
+    }
+}
+
+/// Value of a RAN parameter (RANParameter-ValueType)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RanParameterValueType {
+    /// Element, key is set for the parameters identifying a list item
+    Element { key: bool, value: RanParameterValue },
+    /// Structure of parameters
+    Structure(Vec<RanParameter>),
+    /// List of structures
+    List(Vec<Vec<RanParameter>>),
+}
+
+/// RAN parameter with its ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RanParameter {
+    pub id: u32,
+    pub value: RanParameterValueType,
+}
+
+impl RanParameter {
+    fn element(id: u32, value: RanParameterValue) -> Self {
+        Self { id, value: RanParameterValueType::Element { key: false, value } }
+    }
+
+    fn key(id: u32, value: RanParameterValue) -> Self {
+        Self { id, value: RanParameterValueType::Element { key: true, value } }
+    }
+
+    fn structure(id: u32, parameters: Vec<RanParameter>) -> Self {
+        Self { id, value: RanParameterValueType::Structure(parameters) }
+    }
+}
+
+/// Encode RANParameter-STRUCTURE
+fn encode_structure(enc: &mut AperEncoder, parameters: &[RanParameter]) -> Result<(), LayerError> {
+    enc.put_bool(false);
+    enc.put_bool(!parameters.is_empty());
+    if !parameters.is_empty() {
+        enc.put_length(parameters.len(), 1, Some(MAX_PARAMETERS))?;
+        for parameter in parameters {
+            enc.put_bool(false);
+            parameter.encode(enc)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode_structure(dec: &mut AperDecoder) -> Result<Vec<RanParameter>, LayerError> {
+    dec.get_bool()?;
+    let mut parameters = Vec::new();
+    if dec.get_bool()? {
+        for _ in 0..dec.get_length(1, Some(MAX_PARAMETERS))? {
+            dec.get_bool()?;
+            parameters.push(RanParameter::decode(dec)?);
+        }
+    }
+    Ok(parameters)
+}
+
+impl AperCodec for RanParameterValueType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match self {
+            RanParameterValueType::Element { key: true, value } => {
+                enc.put_choice(0, 4, true)?;
+                enc.put_bool(false);
+                value.encode(enc)
+            }
+            RanParameterValueType::Element { key: false, value } => {
+                enc.put_choice(1, 4, true)?;
+                enc.put_bool(false);
+                enc.put_bool(true);
+                value.encode(enc)
+            }
+            RanParameterValueType::Structure(parameters) => {
+                enc.put_choice(2, 4, true)?;
+                enc.put_bool(false);
+                encode_structure(enc, parameters)
+            }
+            RanParameterValueType::List(items) => {
+                enc.put_choice(3, 4, true)?;
+                // ranP-Choice-List and RANParameter-LIST extension bits
+                enc.put_bool(false);
+                enc.put_bool(false);
+                enc.put_length(items.len(), 1, Some(MAX_PARAMETERS))?;
+                for item in items {
+                    encode_structure(enc, item)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(4, true)? {
+            0 => {
+                dec.get_bool()?;
+                Ok(RanParameterValueType::Element { key: true, value: RanParameterValue::decode(dec)? })
+            }
+            1 => {
+                dec.get_bool()?;
+                if !dec.get_bool()? {
+                    return Err(LayerError::ProcessingError("RAN parameter without value".into()));
+                }
+                Ok(RanParameterValueType::Element { key: false, value: RanParameterValue::decode(dec)? })
+            }
+            2 => {
+                dec.get_bool()?;
+                Ok(RanParameterValueType::Structure(decode_structure(dec)?))
+            }
+            3 => {
+                dec.get_bool()?;
+                dec.get_bool()?;
+                let count = dec.get_length(1, Some(MAX_PARAMETERS))?;
+                let mut items = Vec::with_capacity(count);
+                for _ in 0..count {
+                    items.push(decode_structure(dec)?);
+                }
+                Ok(RanParameterValueType::List(items))
+            }
+            _ => Err(LayerError::ProcessingError("Unknown RAN parameter value type".into())),
+        }
+    }
+}
+
+impl AperCodec for RanParameter {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.id as u64, 1, u32::MAX as u64, true)?;
+        self.value.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let id = dec.get_integer(1, u32::MAX as u64, true)? as u32;
+        Ok(Self { id, value: RanParameterValueType::decode(dec)? })
+    }
+}
+
+/// Control action offered in a control style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RcControlAction {
+    /// Control action ID
+    pub id: u16,
+    /// Control action name
+    pub name: String,
+    /// IDs and names of the top level RAN parameters
+    pub parameters: Vec<(u32, String)>,
+}
+
+/// RAN function definition of the RC service model, control styles only
+/// (E2SM-RC-RANFunctionDefinition)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RcRanFunctionDefinition {
+    /// Name of the RAN function
+    pub name: RanFunctionName,
+    /// Control styles with their actions, all with control header, message and
+    /// outcome format 1
+    pub control_styles: Vec<(RicStyle, Vec<RcControlAction>)>,
+}
+
+impl RcRanFunctionDefinition {
+    /// Description of the controls this E2 node accepts
+    pub fn supported() -> Self {
+        let action = |id, name: &str, parameters: &[(u32, &str)]| RcControlAction {
+            id,
+            name: name.to_string(),
+            parameters: parameters.iter().map(|(id, name)| (*id, name.to_string())).collect(),
+        };
+        Self {
+            name: RanFunctionName {
+                short_name: RC_SHORT_NAME.to_string(),
+                oid: RC_OID.to_string(),
+                description: "RAN Control".to_string(),
+                instance: None,
+            },
+            control_styles: vec![
+                (RicStyle { style_type: STYLE_RADIO_RESOURCE_ALLOCATION, name: "Radio Resource Allocation Control".to_string() },
+                 vec![
+                     action(ACTION_SLICE_PRB_QUOTA, "Slice-level PRB quota",
+                            &[(quota::RRM_POLICY_RATIO_LIST, "RRM Policy Ratio List")]),
+                     action(ACTION_SCHEDULING_POLICY, "Scheduling policy", &[(SCHEDULING_POLICY, "Scheduling Policy")]),
+                 ]),
+                (RicStyle { style_type: STYLE_CONNECTED_MODE_MOBILITY, name: "Connected Mode Mobility Control".to_string() },
+                 vec![action(ACTION_HANDOVER, "Handover Control",
+                             &[(handover::TARGET_PRIMARY_CELL_ID, "Target Primary Cell ID")])]),
+            ],
+        }
+    }
+}
+
+impl AperCodec for RcRanFunctionDefinition {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        // Event trigger, report, insert, control and policy definitions: control only
+        enc.put_bits(0b00010, 5);
+        self.name.encode(enc)?;
+        // RANFunctionDefinition-Control
+        enc.put_bool(false);
+        enc.put_length(self.control_styles.len(), 1, Some(MAX_STYLES))?;
+        for (style, actions) in &self.control_styles {
+            // Extension, action list present, call process ID format and outcome parameters absent
+            enc.put_bool(false);
+            enc.put_bool(!actions.is_empty());
+            enc.put_bits(0, 2);
+            style.encode_fields(enc)?;
+            if !actions.is_empty() {
+                enc.put_length(actions.len(), 1, Some(MAX_ACTIONS))?;
+                for action in actions {
+                    enc.put_bool(false);
+                    enc.put_bool(!action.parameters.is_empty());
+                    enc.put_integer(action.id as u64, 1, 65535, true)?;
+                    enc.put_printable_string(&action.name, 1, 150, true)?;
+                    if !action.parameters.is_empty() {
+                        enc.put_length(action.parameters.len(), 1, Some(MAX_PARAMETERS))?;
+                        for (id, name) in &action.parameters {
+                            enc.put_bool(false);
+                            enc.put_integer(*id as u64, 1, u32::MAX as u64, true)?;
+                            enc.put_printable_string(name, 1, 150, true)?;
+                        }
+                    }
+                }
+            }
+            // ric-ControlHeaderFormat-Type, ric-ControlMessageFormat-Type, ric-ControlOutcomeFormat-Type
+            put_unconstrained_integer(enc, 1)?;
+            put_unconstrained_integer(enc, 1)?;
+            put_unconstrained_integer(enc, 1)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let optionals = dec.get_bits(5)?;
+        let name = RanFunctionName::decode(dec)?;
+        if optionals & 0b11101 != 0 {
+            return Err(LayerError::ProcessingError("Only RC control definitions are supported".into()));
+        }
+        let mut control_styles = Vec::new();
+        if optionals & 0b00010 != 0 {
+            dec.get_bool()?;
+            for _ in 0..dec.get_length(1, Some(MAX_STYLES))? {
+                dec.get_bool()?;
+                let has_actions = dec.get_bool()?;
+                let style_optionals = dec.get_bits(2)?;
+                if style_optionals & 0b01 != 0 {
+                    return Err(LayerError::ProcessingError("RC control outcome parameters are not supported".into()));
+                }
+                let style = RicStyle::decode_fields(dec)?;
+                let mut actions = Vec::new();
+                if has_actions {
+                    for _ in 0..dec.get_length(1, Some(MAX_ACTIONS))? {
+                        dec.get_bool()?;
+                        let has_parameters = dec.get_bool()?;
+                        let id = dec.get_integer(1, 65535, true)? as u16;
+                        let name = dec.get_printable_string(1, 150, true)?;
+                        let mut parameters = Vec::new();
+                        if has_parameters {
+                            for _ in 0..dec.get_length(1, Some(MAX_PARAMETERS))? {
+                                if dec.get_bool()? {
+                                    return Err(LayerError::ProcessingError(
+                                        "RAN parameter definitions are not supported".into()));
+                                }
+                                let id = dec.get_integer(1, u32::MAX as u64, true)? as u32;
+                                parameters.push((id, dec.get_printable_string(1, 150, true)?));
+                            }
+                        }
+                        actions.push(RcControlAction { id, name, parameters });
+                    }
+                }
+                get_unconstrained_integer(dec)?;
+                get_unconstrained_integer(dec)?;
+                if style_optionals & 0b10 != 0 {
+                    get_unconstrained_integer(dec)?;
+                }
+                get_unconstrained_integer(dec)?;
+                control_styles.push((style, actions));
+            }
+        }
+        Ok(Self { name, control_styles })
+    }
+}
+
+/// Control header (E2SM-RC-ControlHeader format 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RcControlHeader {
+    /// UE the action applies to, ignored by cell level actions
+    pub ue_id: UeId,
+    /// Control style
+    pub style_type: i64,
+    /// Control action within the style
+    pub action_id: u16,
+}
+
+impl AperCodec for RcControlHeader {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_choice(0, 1, true)?;
+        // Format 1: extension, ric-ControlDecision absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.ue_id.encode(enc)?;
+        put_unconstrained_integer(enc, self.style_type)?;
+        enc.put_integer(self.action_id as u64, 1, 65535, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        if dec.get_choice(1, true)? != 0 {
+            return Err(LayerError::ProcessingError("Unsupported RC control header format".into()));
+        }
+        dec.get_bool()?;
+        let has_decision = dec.get_bool()?;
+        let ue_id = UeId::decode(dec)?;
+        let style_type = get_unconstrained_integer(dec)?;
+        let action_id = dec.get_integer(1, 65535, true)? as u16;
+        if has_decision {
+            dec.get_enumerated(2, true)?;
+        }
+        Ok(Self { ue_id, style_type, action_id })
+    }
+}
+
+/// Control message (E2SM-RC-ControlMessage format 1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RcControlMessage {
+    /// Top level RAN parameters of the action
+    pub parameters: Vec<RanParameter>,
+}
+
+impl AperCodec for RcControlMessage {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_choice(0, 1, true)?;
+        enc.put_bool(false);
+        enc.put_length(self.parameters.len(), 0, Some(MAX_PARAMETERS))?;
+        for parameter in &self.parameters {
+            enc.put_bool(false);
+            parameter.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        if dec.get_choice(1, true)? != 0 {
+            return Err(LayerError::ProcessingError("Unsupported RC control message format".into()));
+        }
+        dec.get_bool()?;
+        let count = dec.get_length(0, Some(MAX_PARAMETERS))?;
+        let mut parameters = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            parameters.push(RanParameter::decode(dec)?);
+        }
+        Ok(Self { parameters })
+    }
+}
+
+/// Control requested by the RIC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RcControlRequest {
+    /// Replace the PRB quotas of the slices of a PLMN
+    SliceQuotas { plmn_id: [u8; 3], quotas: Vec<SlicePrbQuota> },
+    /// Change the order UEs are scheduled in
+    SchedulingPolicy(SchedulingPolicy),
+    /// Hand a UE over to a target cell
+    Handover { ue_id: u32, target: NrCgi },
+}
+
+fn invalid(reason: &str) -> LayerError {
+    LayerError::ProcessingError(format!("Invalid RC control message: {}", reason))
+}
+
+/// Find the parameter with the given ID
+fn parameter(parameters: &[RanParameter], id: u32) -> Result<&RanParameterValueType, LayerError> {
+    parameters.iter()
+        .find(|parameter| parameter.id == id)
+        .map(|parameter| &parameter.value)
+        .ok_or_else(|| invalid(&format!("RAN parameter {} missing", id)))
+}
+
+fn structure(parameters: &[RanParameter], id: u32) -> Result<&[RanParameter], LayerError> {
+    match parameter(parameters, id)? {
+        RanParameterValueType::Structure(parameters) => Ok(parameters),
+        _ => Err(invalid(&format!("RAN parameter {} is not a structure", id))),
+    }
+}
+
+fn list(parameters: &[RanParameter], id: u32) -> Result<&[Vec<RanParameter>], LayerError> {
+    match parameter(parameters, id)? {
+        RanParameterValueType::List(items) => Ok(items),
+        _ => Err(invalid(&format!("RAN parameter {} is not a list", id))),
+    }
+}
+
+fn element(parameters: &[RanParameter], id: u32) -> Result<&RanParameterValue, LayerError> {
+    match parameter(parameters, id)? {
+        RanParameterValueType::Element { value, .. } => Ok(value),
+        _ => Err(invalid(&format!("RAN parameter {} is not an element", id))),
+    }
+}
+
+fn integer(parameters: &[RanParameter], id: u32) -> Result<i64, LayerError> {
+    match element(parameters, id)? {
+        RanParameterValue::Integer(value) => Ok(*value),
+        _ => Err(invalid(&format!("RAN parameter {} is not an integer", id))),
+    }
+}
+
+fn octets(parameters: &[RanParameter], id: u32) -> Result<&[u8], LayerError> {
+    match element(parameters, id)? {
+        RanParameterValue::OctetString(value) => Ok(value),
+        _ => Err(invalid(&format!("RAN parameter {} is not an octet string", id))),
+    }
+}
+
+fn ratio(parameters: &[RanParameter], id: u32) -> Result<u8, LayerError> {
+    u8::try_from(integer(parameters, id)?).ok().filter(|ratio| *ratio <= 100)
+        .ok_or_else(|| invalid(&format!("RAN parameter {} is not a percentage", id)))
+}
+
+impl RcControlRequest {
+    /// Control header for the request
+    pub fn header(&self) -> RcControlHeader {
+        let (ue_id, style_type, action_id) = match self {
+            RcControlRequest::SliceQuotas { .. } => (0, STYLE_RADIO_RESOURCE_ALLOCATION, ACTION_SLICE_PRB_QUOTA),
+            RcControlRequest::SchedulingPolicy(_) => (0, STYLE_RADIO_RESOURCE_ALLOCATION, ACTION_SCHEDULING_POLICY),
+            RcControlRequest::Handover { ue_id, .. } => (*ue_id, STYLE_CONNECTED_MODE_MOBILITY, ACTION_HANDOVER),
+        };
+        RcControlHeader { ue_id: UeId(ue_id), style_type, action_id }
+    }
+
+    /// Control message for the request
+    pub fn message(&self) -> Result<RcControlMessage, LayerError> {
+        let parameters = match self {
+            RcControlRequest::SliceQuotas { plmn_id, quotas } => {
+                let groups = quotas.iter().map(|quota| {
+                    let mut s_nssai = vec![RanParameter::key(
+                        quota::SST, RanParameterValue::OctetString(Bytes::copy_from_slice(&[quota.s_nssai.sst])))];
+                    if let Some(sd) = quota.s_nssai.sd {
+                        s_nssai.push(RanParameter::key(
+                            quota::SD, RanParameterValue::OctetString(Bytes::copy_from_slice(&sd.to_be_bytes()[1..]))));
+                    }
+                    let member = vec![RanParameter::structure(quota::RRM_POLICY_MEMBER, vec![
+                        RanParameter::key(quota::PLMN_IDENTITY, RanParameterValue::OctetString(Bytes::copy_from_slice(plmn_id))),
+                        RanParameter::structure(quota::S_NSSAI, s_nssai),
+                    ])];
+                    vec![
+                        RanParameter::structure(quota::RRM_POLICY_RATIO_GROUP, vec![
+                            RanParameter::structure(quota::RRM_POLICY, vec![RanParameter {
+                                id: quota::RRM_POLICY_MEMBER_LIST,
+                                value: RanParameterValueType::List(vec![member]),
+                            }]),
+                            RanParameter::element(quota::MIN_PRB_POLICY_RATIO,
+                                                  RanParameterValue::Integer(quota.min_ratio as i64)),
+                            RanParameter::element(quota::MAX_PRB_POLICY_RATIO,
+                                                  RanParameterValue::Integer(quota.max_ratio as i64)),
+                            RanParameter::element(quota::DEDICATED_PRB_POLICY_RATIO,
+                                                  RanParameterValue::Integer(quota.dedicated_ratio as i64)),
+                        ]),
+                    ]
+                }).collect();
+                vec![RanParameter { id: quota::RRM_POLICY_RATIO_LIST, value: RanParameterValueType::List(groups) }]
+            }
+            RcControlRequest::SchedulingPolicy(policy) => {
+                vec![RanParameter::element(SCHEDULING_POLICY, RanParameterValue::Integer(*policy as i64))]
+            }
+            RcControlRequest::Handover { target, .. } => {
+                let mut enc = AperEncoder::new();
+                put_nr_cgi(&mut enc, target)?;
+                vec![RanParameter::structure(handover::TARGET_PRIMARY_CELL_ID, vec![
+                    RanParameter::structure(handover::TARGET_CELL, vec![
+                        RanParameter::structure(handover::NR_CELL, vec![
+                            RanParameter::element(handover::NR_CGI, RanParameterValue::OctetString(enc.into_bytes())),
+                        ]),
+                    ]),
+                ])]
+            }
+        };
+        Ok(RcControlMessage { parameters })
+    }
+
+    /// Interpret the control header and message of a RIC Control Request
+    pub fn decode(header: &RcControlHeader, message: &RcControlMessage) -> Result<Self, LayerError> {
+        let parameters = &message.parameters;
+        match (header.style_type, header.action_id) {
+            (STYLE_RADIO_RESOURCE_ALLOCATION, ACTION_SLICE_PRB_QUOTA) => {
+                let mut plmn = None;
+                let mut quotas = Vec::new();
+                for group in list(parameters, quota::RRM_POLICY_RATIO_LIST)? {
+                    let group = structure(group, quota::RRM_POLICY_RATIO_GROUP)?;
+                    let min_ratio = ratio(group, quota::MIN_PRB_POLICY_RATIO)?;
+                    let max_ratio = ratio(group, quota::MAX_PRB_POLICY_RATIO)?;
+                    let dedicated_ratio = ratio(group, quota::DEDICATED_PRB_POLICY_RATIO)?;
+                    // Every member of a group gets the group's ratios
+                    for member in list(structure(group, quota::RRM_POLICY)?, quota::RRM_POLICY_MEMBER_LIST)? {
+                        let member = structure(member, quota::RRM_POLICY_MEMBER)?;
+                        let plmn_id = <[u8; 3]>::try_from(octets(member, quota::PLMN_IDENTITY)?)
+                            .map_err(|_| invalid("PLMN identity is not 3 octets"))?;
+                        if *plmn.get_or_insert(plmn_id) != plmn_id {
+                            return Err(invalid("quotas for more than one PLMN"));
+                        }
+                        let s_nssai = structure(member, quota::S_NSSAI)?;
+                        let [sst] = octets(s_nssai, quota::SST)? else {
+                            return Err(invalid("SST is not 1 octet"));
+                        };
+                        let sd = match parameter(s_nssai, quota::SD) {
+                            Ok(_) => match octets(s_nssai, quota::SD)? {
+                                [a, b, c] => Some(u32::from_be_bytes([0, *a, *b, *c])),
+                                _ => return Err(invalid("SD is not 3 octets")),
+                            },
+                            Err(_) => None,
+                        };
+                        quotas.push(SlicePrbQuota {
+                            s_nssai: SNssai { sst: *sst, sd },
+                            min_ratio,
+                            max_ratio,
+                            dedicated_ratio,
+                        });
+                    }
+                }
+                let plmn_id = plmn.ok_or_else(|| invalid("no RRM policy member"))?;
+                Ok(RcControlRequest::SliceQuotas { plmn_id, quotas })
+            }
+            (STYLE_RADIO_RESOURCE_ALLOCATION, ACTION_SCHEDULING_POLICY) => {
+                match integer(parameters, SCHEDULING_POLICY)? {
+                    0 => Ok(RcControlRequest::SchedulingPolicy(SchedulingPolicy::RoundRobin)),
+                    1 => Ok(RcControlRequest::SchedulingPolicy(SchedulingPolicy::ProportionalFair)),
+                    2 => Ok(RcControlRequest::SchedulingPolicy(SchedulingPolicy::MaxCqi)),
+                    policy => Err(invalid(&format!("unknown scheduling policy {}", policy))),
+                }
+            }
+            (STYLE_CONNECTED_MODE_MOBILITY, ACTION_HANDOVER) => {
+                let cell = structure(structure(structure(parameters, handover::TARGET_PRIMARY_CELL_ID)?,
+                                               handover::TARGET_CELL)?, handover::NR_CELL)?;
+                let target = get_nr_cgi(&mut AperDecoder::new(octets(cell, handover::NR_CGI)?))?;
+                Ok(RcControlRequest::Handover { ue_id: header.ue_id.0, target })
+            }
+            (style, action) => Err(LayerError::ProcessingError(
+                format!("Unsupported RC control style {} action {}", style, action))),
+        }
+    }
+
+    /// Encode the control header and message as E2AP carries them
+    pub fn encode(&self) -> Result<(Bytes, Bytes), LayerError> {
+        Ok((encode_e2sm(&self.header())?, encode_e2sm(&self.message()?)?))
+    }
+
+    /// Decode the control header and message E2AP carries
+    pub fn decode_e2sm(header: &[u8], message: &[u8]) -> Result<Self, LayerError> {
+        Self::decode(&decode_e2sm(header)?, &decode_e2sm(message)?)
+    }
+}
+
+/// Executor of the controls requested by the RIC
+#[async_trait]
+pub trait RcControl: Send + Sync {
+    /// Apply a control, the error is reported to the RIC as failure to execute
+    async fn execute(&self, request: RcControlRequest) -> Result<(), LayerError>;
+}
+
+/// Resolves the NR CGI of a handover target to its physical cell ID
+pub type CellResolver = Box<dyn Fn(&NrCgi) -> Option<u16> + Send + Sync>;
+
+/// Controls applied to the protocol stack of a gNB, each layer being optional
+/// as a CU or DU only runs part of the stack
+pub struct StackRcControl {
+    plmn_id: [u8; 3],
+    scheduler: Option<Arc<Mutex<MacScheduler>>>,
+    rrc: Option<Arc<RwLock<RrcLayer>>>,
+    resolver: CellResolver,
+}
+
+impl StackRcControl {
+    /// Apply the controls of the cell's PLMN to the given layers
+    pub fn new(
+        plmn_id: [u8; 3],
+        scheduler: Option<Arc<Mutex<MacScheduler>>>,
+        rrc: Option<Arc<RwLock<RrcLayer>>>,
+        resolver: CellResolver,
+    ) -> Self {
+        Self { plmn_id, scheduler, rrc, resolver }
+    }
+}
+
+#[async_trait]
+impl RcControl for StackRcControl {
+    async fn execute(&self, request: RcControlRequest) -> Result<(), LayerError> {
+        match request {
+            RcControlRequest::SliceQuotas { plmn_id, quotas } => {
+                if plmn_id != self.plmn_id {
+                    return Err(LayerError::InvalidConfiguration(format!("PLMN {:02X?} is not served", plmn_id)));
+                }
+                let scheduler = self.scheduler.as_ref()
+                    .ok_or_else(|| LayerError::InvalidState("No MAC scheduler to apply slice quotas to".into()))?;
+                scheduler.lock().await.set_slice_quotas(quotas)
+            }
+            RcControlRequest::SchedulingPolicy(policy) => {
+                let scheduler = self.scheduler.as_ref()
+                    .ok_or_else(|| LayerError::InvalidState("No MAC scheduler to apply the policy to".into()))?;
+                scheduler.lock().await.set_scheduling_policy(policy);
+                Ok(())
+            }
+            RcControlRequest::Handover { ue_id, target } => {
+                let rrc = self.rrc.as_ref()
+                    .ok_or_else(|| LayerError::InvalidState("No RRC layer to hand UEs over".into()))?;
+                let target_pci = (self.resolver)(&target)
+                    .ok_or_else(|| LayerError::InvalidConfiguration(format!("Unknown target cell {:?}", target)))?;
+                rrc.write().await.handover_ue(ue_id, target_pci).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rc_codec() {
+        let definition = RcRanFunctionDefinition::supported();
+        assert_eq!(decode_e2sm::<RcRanFunctionDefinition>(&encode_e2sm(&definition).unwrap()).unwrap(), definition);
+
+        let requests = [
+            RcControlRequest::SliceQuotas {
+                plmn_id: [0x02, 0xF8, 0x39],
+                quotas: vec![
+                    SlicePrbQuota { s_nssai: SNssai { sst: 1, sd: None }, min_ratio: 50, max_ratio: 100, dedicated_ratio: 10 },
+                    SlicePrbQuota { s_nssai: SNssai { sst: 2, sd: Some(0x0000AB) }, min_ratio: 20, max_ratio: 40, dedicated_ratio: 0 },
+                ],
+            },
+            RcControlRequest::SchedulingPolicy(SchedulingPolicy::MaxCqi),
+            RcControlRequest::Handover {
+                ue_id: 7,
+                target: NrCgi { plmn_id: [0x02, 0xF8, 0x39], nr_cell_identity: 0x19C001 },
+            },
+        ];
+        for request in requests {
+            let (header, message) = request.encode().unwrap();
+            assert_eq!(RcControlRequest::decode_e2sm(&header, &message).unwrap(), request);
+        }
+
+        // An unknown scheduling policy is refused
+        let mut message = RcControlRequest::SchedulingPolicy(SchedulingPolicy::RoundRobin).message().unwrap();
+        message.parameters[0] = RanParameter::element(SCHEDULING_POLICY, RanParameterValue::Integer(3));
+        let header = RcControlRequest::SchedulingPolicy(SchedulingPolicy::RoundRobin).header();
+        assert!(RcControlRequest::decode(&header, &message).is_err());
+    }
+
+    #[test]
+    fn test_rc_decode_errors() {
+        // REAL value, CHOICE extensions, element without value
+        assert!(matches!(decode_e2sm::<RanParameterValue>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<RanParameterValue>(&[0x80]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<RanParameterValueType>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<RanParameterValueType>(&[0x80]), Err(LayerError::ProcessingError(_))));
+
+        // Header and message formats other than 1, truncations
+        assert!(matches!(decode_e2sm::<RcControlHeader>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_e2sm::<RcControlMessage>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        let (header, message) = RcControlRequest::SchedulingPolicy(SchedulingPolicy::MaxCqi).encode().unwrap();
+        assert!(RcControlRequest::decode_e2sm(&header[..header.len() - 1], &message).is_err());
+        assert!(RcControlRequest::decode_e2sm(&header, &message[..message.len() - 1]).is_err());
+        assert!(RcControlRequest::decode_e2sm(&[], &message).is_err());
+
+        // Function definitions with other than control styles
+        let mut definition = encode_e2sm(&RcRanFunctionDefinition::supported()).unwrap().to_vec();
+        definition[0] |= 0x40;
+        assert!(matches!(decode_e2sm::<RcRanFunctionDefinition>(&definition), Err(LayerError::ProcessingError(_))));
+    }
+
+    #[test]
+    fn test_rc_invalid_control() {
+        let refused = |header: RcControlHeader, message: &RcControlMessage| {
+            matches!(RcControlRequest::decode(&header, message), Err(LayerError::ProcessingError(_)))
+        };
+        let octets = |value: &[u8]| RanParameterValue::OctetString(Bytes::copy_from_slice(value));
+        let member = |plmn_id: &[u8], s_nssai: Vec<RanParameter>| vec![RanParameter::structure(quota::RRM_POLICY_MEMBER, vec![
+            RanParameter::key(quota::PLMN_IDENTITY, octets(plmn_id)),
+            RanParameter::structure(quota::S_NSSAI, s_nssai),
+        ])];
+        let group = |members: Vec<Vec<RanParameter>>, min_ratio: i64| vec![
+            RanParameter::structure(quota::RRM_POLICY_RATIO_GROUP, vec![
+                RanParameter::structure(quota::RRM_POLICY, vec![RanParameter {
+                    id: quota::RRM_POLICY_MEMBER_LIST,
+                    value: RanParameterValueType::List(members),
+                }]),
+                RanParameter::element(quota::MIN_PRB_POLICY_RATIO, RanParameterValue::Integer(min_ratio)),
+                RanParameter::element(quota::MAX_PRB_POLICY_RATIO, RanParameterValue::Integer(100)),
+                RanParameter::element(quota::DEDICATED_PRB_POLICY_RATIO, RanParameterValue::Integer(0)),
+            ]),
+        ];
+        let quotas = |groups: Vec<Vec<RanParameter>>| RcControlMessage {
+            parameters: vec![RanParameter { id: quota::RRM_POLICY_RATIO_LIST, value: RanParameterValueType::List(groups) }],
+        };
+        const PLMN: [u8; 3] = [0x02, 0xF8, 0x39];
+        let sst = |sst: &[u8]| vec![RanParameter::key(quota::SST, octets(sst))];
+        let header = RcControlRequest::SliceQuotas { plmn_id: PLMN, quotas: vec![] }.header();
+        assert!(RcControlRequest::decode(&header, &quotas(vec![group(vec![member(&PLMN, sst(&[1]))], 50)])).is_ok());
+
+        // Ratios beyond a percentage, malformed PLMN, SST and SD, quotas of
+        // two PLMNs, no member at all
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN, sst(&[1]))], 101)])));
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN, sst(&[1]))], -1)])));
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN[..2], sst(&[1]))], 50)])));
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN, sst(&[1, 0]))], 50)])));
+        let short_sd = vec![RanParameter::key(quota::SST, octets(&[1])), RanParameter::key(quota::SD, octets(&[0, 1]))];
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN, short_sd)], 50)])));
+        let other_plmn = member(&[0x00, 0xF1, 0x10], sst(&[1]));
+        assert!(refused(header, &quotas(vec![group(vec![member(&PLMN, sst(&[1])), other_plmn], 50)])));
+        assert!(refused(header, &quotas(vec![])));
+
+        // Missing parameters and parameters of the wrong kind
+        assert!(refused(header, &RcControlMessage { parameters: vec![] }));
+        assert!(refused(header, &RcControlMessage {
+            parameters: vec![RanParameter::structure(quota::RRM_POLICY_RATIO_LIST, vec![])],
+        }));
+        let policy = RcControlRequest::SchedulingPolicy(SchedulingPolicy::RoundRobin).header();
+        assert!(refused(policy, &RcControlMessage { parameters: vec![RanParameter::element(SCHEDULING_POLICY, octets(&[0]))] }));
+        assert!(refused(policy, &RcControlMessage { parameters: vec![RanParameter::structure(SCHEDULING_POLICY, vec![])] }));
+
+        // Handover target cell truncated or not a structure
+        let target = NrCgi { plmn_id: PLMN, nr_cell_identity: 0x19C001 };
+        let handover = RcControlRequest::Handover { ue_id: 7, target };
+        let mut message = handover.message().unwrap();
+        let cell = |cell: RanParameter| RcControlMessage {
+            parameters: vec![RanParameter::structure(handover::TARGET_PRIMARY_CELL_ID, vec![
+                RanParameter::structure(handover::TARGET_CELL, vec![cell]),
+            ])],
+        };
+        let truncated = cell(RanParameter::structure(handover::NR_CELL, vec![
+            RanParameter::element(handover::NR_CGI, octets(&[0x02])),
+        ]));
+        assert!(matches!(RcControlRequest::decode(&handover.header(), &truncated), Err(LayerError::InvalidPdu)));
+        assert!(refused(handover.header(), &cell(RanParameter::element(handover::NR_CELL, octets(&[0x02])))));
+        message.parameters[0].id = handover::TARGET_CELL;
+        assert!(refused(handover.header(), &message));
+
+        // Style and action combinations that are not offered
+        let unknown = RcControlHeader { ue_id: UeId(7), style_type: STYLE_CONNECTED_MODE_MOBILITY, action_id: ACTION_SLICE_PRB_QUOTA };
+        assert!(refused(unknown, &handover.message().unwrap()));
+        let unknown = RcControlHeader { style_type: 1, ..handover.header() };
+        assert!(refused(unknown, &handover.message().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_stack_rc_control_failures() {
+        let control = StackRcControl::new([0x02, 0xF8, 0x39], None, None, Box::new(|_| Some(3)));
+
+        // Quotas of a PLMN that is not served, layers the stack does not run
+        let quotas = vec![SlicePrbQuota { s_nssai: SNssai { sst: 1, sd: None }, min_ratio: 50, max_ratio: 100, dedicated_ratio: 0 }];
+        assert!(matches!(control.execute(RcControlRequest::SliceQuotas { plmn_id: [0x00, 0xF1, 0x10], quotas: quotas.clone() }).await,
+                         Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(control.execute(RcControlRequest::SliceQuotas { plmn_id: [0x02, 0xF8, 0x39], quotas }).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(matches!(control.execute(RcControlRequest::SchedulingPolicy(SchedulingPolicy::MaxCqi)).await,
+                         Err(LayerError::InvalidState(_))));
+        let target = NrCgi { plmn_id: [0x02, 0xF8, 0x39], nr_cell_identity: 0x19C001 };
+        assert!(matches!(control.execute(RcControlRequest::Handover { ue_id: 7, target }).await,
+                         Err(LayerError::InvalidState(_))));
+    }
+}
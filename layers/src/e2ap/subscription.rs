@@ -0,0 +1,357 @@
+//! RIC Subscription procedures
+//!
+//! RIC Subscription and RIC Subscription Delete (O-RAN.WG3.E2AP sections 8.2.1
+//! and 8.2.2) for the KPM service model. Every admitted report action runs as
+//! a task that samples the counters at each granularity period and sends a RIC
+//! Indication with the collected records at each reporting period; deleting
+//! the subscription aborts its tasks.
+
+use super::agent::E2Agent;
+use super::e2sm::UeId;
+use super::kpm::*;
+use super::pdu::*;
+use super::transport::{E2Sender, E2AP_STREAM};
+use super::E2apProcedureCode;
+use crate::ngap::pdu::Criticality;
+use crate::LayerError;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+/// Admitted subscription, dropping it stops its report actions
+pub(super) struct Subscription {
+    _actions: JoinSet<()>,
+}
+
+/// Report action admitted in a subscription
+struct ReportAction {
+    request_id: RicRequestId,
+    action_id: u8,
+    reporting_period: Duration,
+    definition: KpmActionDefinition,
+}
+
+impl E2Agent {
+    /// Handle a RIC Subscription Request: admit the KPM report actions and
+    /// start reporting
+    pub(super) async fn handle_subscription_request(
+        &self,
+        pdu: &E2apPdu,
+        sender: &Arc<E2Sender>,
+    ) -> Result<(), LayerError> {
+        let request_id = pdu.ie::<RicRequestId>(ID_RIC_REQUEST_ID)?;
+        let function_id = pdu.ie::<RanFunctionId>(ID_RAN_FUNCTION_ID)?;
+        let details = pdu.ie::<RicSubscriptionDetails>(ID_RIC_SUBSCRIPTION_DETAILS)?;
+
+        if function_id.0 != KPM_RAN_FUNCTION_ID {
+            return send_subscription_failure(sender, request_id, function_id, Cause::RAN_FUNCTION_ID_INVALID).await;
+        }
+        let Ok(trigger) = decode_e2sm::<KpmEventTrigger>(&details.event_trigger) else {
+            return send_subscription_failure(sender, request_id, function_id, Cause::RIC_REQUEST_UNSPECIFIED).await;
+        };
+        let mut subscriptions = self.subscriptions.lock().await;
+        if subscriptions.contains_key(&request_id) {
+            return send_subscription_failure(sender, request_id, function_id, Cause::RIC_REQUEST_UNSPECIFIED).await;
+        }
+
+        let reporting_period = Duration::from_millis(trigger.reporting_period_ms as u64);
+        let mut admitted = Vec::new();
+        let mut not_admitted = Vec::new();
+        let mut action_ids = HashSet::new();
+        for action in details.actions {
+            if !action_ids.insert(action.action_id) {
+                not_admitted.push(RicActionNotAdmitted { action_id: action.action_id, cause: Cause::DUPLICATE_ACTION });
+                continue;
+            }
+            match self.admit(&action, trigger) {
+                Some(definition) => admitted.push(ReportAction {
+                    request_id,
+                    action_id: action.action_id,
+                    reporting_period,
+                    definition,
+                }),
+                None => not_admitted.push(RicActionNotAdmitted {
+                    action_id: action.action_id,
+                    cause: Cause::ACTION_NOT_SUPPORTED,
+                }),
+            }
+        }
+        if admitted.is_empty() {
+            let cause = not_admitted.first().map_or(Cause::ACTION_NOT_SUPPORTED, |action| action.cause);
+            return send_subscription_failure(sender, request_id, function_id, cause).await;
+        }
+
+        let admitted_ids = admitted.iter().map(|action| RicActionAdmitted { action_id: action.action_id }).collect();
+        let mut response = E2apPdu::successful(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?
+            .with_ie(ID_RIC_ACTIONS_ADMITTED, Criticality::Reject, &RicActionAdmittedList(admitted_ids))?;
+        if !not_admitted.is_empty() {
+            response.add_ie(ID_RIC_ACTIONS_NOT_ADMITTED, Criticality::Reject, &RicActionNotAdmittedList(not_admitted))?;
+        }
+        sender.send(E2AP_STREAM, &response).await?;
+
+        info!("RIC subscription {}/{} admitted with {} report actions every {} ms",
+              request_id.requestor_id, request_id.instance_id, admitted.len(), trigger.reporting_period_ms);
+        let sn = Arc::new(AtomicU16::new(0));
+        let mut actions = JoinSet::new();
+        for action in admitted {
+            actions.spawn(report(action, Arc::clone(sender), Arc::clone(&self.kpm), Arc::clone(&sn)));
+        }
+        subscriptions.insert(request_id, Subscription { _actions: actions });
+        Ok(())
+    }
+
+    /// Decode the definition of an action, None if it cannot be served
+    fn admit(&self, action: &RicActionToBeSetup, trigger: KpmEventTrigger) -> Option<KpmActionDefinition> {
+        if action.action_type != RicActionType::Report {
+            return None;
+        }
+        let definition = decode_e2sm::<KpmActionDefinition>(action.definition.as_ref()?).ok()?;
+        let info = definition.info();
+        let served_cell = info.cell.is_none_or(|cell| cell == self.config.nr_cgi);
+        let measurements_known = info.measurements.iter().all(|measurement| KpmMeasurement::find(measurement).is_some());
+        let granularity_fits = info.granularity_period_ms > 0 && info.granularity_period_ms <= trigger.reporting_period_ms;
+        (served_cell && measurements_known && granularity_fits).then_some(definition)
+    }
+
+    /// Handle a RIC Subscription Delete Request
+    pub(super) async fn handle_subscription_delete_request(
+        &self,
+        pdu: &E2apPdu,
+        sender: &Arc<E2Sender>,
+    ) -> Result<(), LayerError> {
+        let request_id = pdu.ie::<RicRequestId>(ID_RIC_REQUEST_ID)?;
+        let function_id = pdu.ie::<RanFunctionId>(ID_RAN_FUNCTION_ID)?;
+
+        let response = if self.subscriptions.lock().await.remove(&request_id).is_some() {
+            info!("RIC subscription {}/{} deleted", request_id.requestor_id, request_id.instance_id);
+            E2apPdu::successful(E2apProcedureCode::RicSubscriptionDelete)
+                .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+                .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?
+        } else {
+            E2apPdu::unsuccessful(E2apProcedureCode::RicSubscriptionDelete)
+                .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+                .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?
+                .with_ie(ID_CAUSE, Criticality::Ignore, &Cause::REQUEST_ID_UNKNOWN)?
+        };
+        sender.send(E2AP_STREAM, &response).await
+    }
+}
+
+async fn send_subscription_failure(
+    sender: &E2Sender,
+    request_id: RicRequestId,
+    function_id: RanFunctionId,
+    cause: Cause,
+) -> Result<(), LayerError> {
+    warn!("Rejecting RIC subscription {}/{}: {:?}", request_id.requestor_id, request_id.instance_id, cause);
+    let failure = E2apPdu::unsuccessful(E2apProcedureCode::RicSubscription)
+        .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id)?
+        .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &function_id)?
+        .with_ie(ID_CAUSE, Criticality::Reject, &cause)?;
+    sender.send(E2AP_STREAM, &failure).await
+}
+
+/// Values of the measurements of an action over one granularity period
+fn record(definition: &KpmActionDefinition, start: &KpmSnapshot, end: &KpmSnapshot) -> Vec<MeasurementValue> {
+    let info = definition.info();
+    let period_ms = info.granularity_period_ms as u64;
+    info.measurements.iter()
+        .map(|measurement_type| {
+            let measurement = KpmMeasurement::find(measurement_type);
+            let value = match definition {
+                KpmActionDefinition::Cell(_) => measurement.and_then(|m| m.cell_value(start, end, period_ms)),
+                KpmActionDefinition::Ue { ue_id: UeId(ue_id), .. } => {
+                    measurement.and_then(|m| m.ue_value(*ue_id, start, end, period_ms))
+                }
+            };
+            MeasurementValue::from(value)
+        })
+        .collect()
+}
+
+/// Run a report action: collect a record every granularity period and send
+/// the records every reporting period
+async fn report(action: ReportAction, sender: Arc<E2Sender>, source: Arc<dyn KpmSource>, sn: Arc<AtomicU16>) {
+    let info = action.definition.info().clone();
+    let granularity = Duration::from_millis(info.granularity_period_ms as u64);
+    let records_per_report = (action.reporting_period.as_millis() / granularity.as_millis()).max(1) as usize;
+    let mut ticks = tokio::time::interval(granularity);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticks.tick().await;
+    let mut previous = source.snapshot().await;
+
+    loop {
+        let header = KpmIndicationHeader { collection_start: ntp_timestamp(SystemTime::now()) };
+        let mut records = Vec::with_capacity(records_per_report);
+        for _ in 0..records_per_report {
+            ticks.tick().await;
+            let current = source.snapshot().await;
+            records.push(record(&action.definition, &previous, &current));
+            previous = current;
+        }
+        let message = KpmIndicationMessage {
+            records,
+            measurements: info.measurements.clone(),
+            granularity_period_ms: info.granularity_period_ms,
+        };
+        let sn = RicIndicationSn(sn.fetch_add(1, Ordering::Relaxed));
+        if let Err(e) = send_indication(&sender, &action, sn, &header, &message).await {
+            warn!("Failed to send RIC indication of action {}: {}", action.action_id, e);
+        }
+    }
+}
+
+async fn send_indication(
+    sender: &E2Sender,
+    action: &ReportAction,
+    sn: RicIndicationSn,
+    header: &KpmIndicationHeader,
+    message: &KpmIndicationMessage,
+) -> Result<(), LayerError> {
+    let indication = E2apPdu::initiating(E2apProcedureCode::RicIndication)
+        .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &action.request_id)?
+        .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID))?
+        .with_ie(ID_RIC_ACTION_ID, Criticality::Reject, &RicActionId(action.action_id))?
+        .with_ie(ID_RIC_INDICATION_SN, Criticality::Reject, &sn)?
+        .with_ie(ID_RIC_INDICATION_TYPE, Criticality::Reject, &RicIndicationType::Report)?
+        .with_ie(ID_RIC_INDICATION_HEADER, Criticality::Reject, &encode_e2sm(header)?)?
+        .with_ie(ID_RIC_INDICATION_MESSAGE, Criticality::Reject, &encode_e2sm(message)?)?;
+    sender.send(E2AP_STREAM, &indication).await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::e2ap::rc::RC_RAN_FUNCTION_ID;
+    use crate::e2ap::transport::{E2Receiver, E2TransportEvent};
+    use crate::ngap::pdu::NrCgi;
+    use crate::test_support::{e2_agent, e2_association, E2_CELL};
+    use tokio::time::timeout;
+
+    /// Next outcome of `procedure`, skipping the indications of the
+    /// admitted subscriptions
+    async fn recv_outcome(receiver: &mut E2Receiver, procedure: E2apProcedureCode) -> E2apPdu {
+        loop {
+            let pdu = match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+                E2TransportEvent::Data { payload, .. } => E2apPdu::decode(&payload).unwrap(),
+                E2TransportEvent::AssociationLost(reason) => panic!("association lost: {}", reason),
+            };
+            if pdu.procedure() == Some(procedure) {
+                return pdu;
+            }
+        }
+    }
+
+    /// Cause of the failure the agent answers `request` with
+    async fn failure_cause(agent: &E2Agent, sender: &Arc<E2Sender>, receiver: &mut E2Receiver, request: E2apPdu) -> Cause {
+        let request_id = request.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap();
+        agent.handle_subscription_request(&request, sender).await.unwrap();
+        let failure = recv_outcome(receiver, E2apProcedureCode::RicSubscription).await;
+        assert_eq!(failure.pdu_type, E2apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<RicRequestId>(ID_RIC_REQUEST_ID).unwrap(), request_id);
+        failure.ie::<Cause>(ID_CAUSE).unwrap()
+    }
+
+    fn request(request_id: RicRequestId, function_id: u16, details: &RicSubscriptionDetails) -> E2apPdu {
+        E2apPdu::initiating(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(function_id)).unwrap()
+            .with_ie(ID_RIC_SUBSCRIPTION_DETAILS, Criticality::Reject, details).unwrap()
+    }
+
+    fn report(action_id: u8, granularity_period_ms: u32, measurement: MeasurementType, cell: Option<NrCgi>) -> RicActionToBeSetup {
+        let definition = KpmActionDefinition::Cell(KpmSubscriptionInfo {
+            measurements: vec![measurement],
+            granularity_period_ms,
+            cell,
+        });
+        RicActionToBeSetup {
+            action_id,
+            action_type: RicActionType::Report,
+            definition: Some(encode_e2sm(&definition).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_failures() {
+        let ((_ric_tx, mut ric_rx), (node_tx, _node_rx)) = e2_association().await;
+        let sender = Arc::new(node_tx);
+        let agent = e2_agent(sender.peer());
+        let request_id = RicRequestId { requestor_id: 100, instance_id: 1 };
+        let trigger = encode_e2sm(&KpmEventTrigger { reporting_period_ms: 100 }).unwrap();
+        let prb = || MeasurementType::from(KpmMeasurement::PrbTotDl);
+
+        // Subscription details missing
+        let incomplete = E2apPdu::initiating(E2apProcedureCode::RicSubscription)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &request_id).unwrap()
+            .with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID)).unwrap();
+        assert!(matches!(agent.handle_subscription_request(&incomplete, &sender).await,
+                         Err(LayerError::ProcessingError(_))));
+
+        // RAN function other than KPM, event trigger format not supported
+        let details = RicSubscriptionDetails { event_trigger: trigger.clone(), actions: vec![report(1, 50, prb(), None)] };
+        assert_eq!(failure_cause(&agent, &sender, &mut ric_rx, request(request_id, RC_RAN_FUNCTION_ID, &details)).await, Cause::RAN_FUNCTION_ID_INVALID);
+        let unsupported = RicSubscriptionDetails { event_trigger: Bytes::from_static(&[0x40]), ..details.clone() };
+        assert_eq!(failure_cause(&agent, &sender, &mut ric_rx, request(request_id, KPM_RAN_FUNCTION_ID, &unsupported)).await,
+                   Cause::RIC_REQUEST_UNSPECIFIED);
+
+        // None of the actions can be served: insert, without or with an
+        // undecodable definition, unknown measurement, another cell and
+        // granularity beyond the reporting period
+        let other_cell = NrCgi { nr_cell_identity: 0x19C001, ..E2_CELL };
+        let unserved = RicSubscriptionDetails {
+            event_trigger: trigger.clone(),
+            actions: vec![
+                RicActionToBeSetup { action_id: 1, action_type: RicActionType::Insert, definition: None },
+                RicActionToBeSetup { action_id: 2, action_type: RicActionType::Report, definition: None },
+                RicActionToBeSetup { action_id: 3, action_type: RicActionType::Report, definition: Some(Bytes::from_static(&[0x00])) },
+                report(4, 50, MeasurementType::Name("DRB.Unknown".to_string()), None),
+                report(5, 50, prb(), Some(other_cell)),
+                report(6, 200, prb(), Some(E2_CELL)),
+            ],
+        };
+        assert_eq!(failure_cause(&agent, &sender, &mut ric_rx, request(request_id, KPM_RAN_FUNCTION_ID, &unserved)).await, Cause::ACTION_NOT_SUPPORTED);
+        assert_eq!(agent.subscription_count().await, 0);
+
+        // A duplicate action is not admitted next to the original
+        let duplicate = RicSubscriptionDetails {
+            event_trigger: trigger,
+            actions: vec![report(1, 50, prb(), None), report(1, 100, prb(), None)],
+        };
+        agent.handle_subscription_request(&request(request_id, KPM_RAN_FUNCTION_ID, &duplicate), &sender).await.unwrap();
+        let response = recv_outcome(&mut ric_rx, E2apProcedureCode::RicSubscription).await;
+        assert_eq!(response.pdu_type, E2apPduType::SuccessfulOutcome);
+        assert_eq!(response.ie::<RicActionAdmittedList>(ID_RIC_ACTIONS_ADMITTED).unwrap().0,
+                   vec![RicActionAdmitted { action_id: 1 }]);
+        assert_eq!(response.ie::<RicActionNotAdmittedList>(ID_RIC_ACTIONS_NOT_ADMITTED).unwrap().0,
+                   vec![RicActionNotAdmitted { action_id: 1, cause: Cause::DUPLICATE_ACTION }]);
+
+        // The request ID is in use
+        assert_eq!(failure_cause(&agent, &sender, &mut ric_rx, request(request_id, KPM_RAN_FUNCTION_ID, &details)).await, Cause::RIC_REQUEST_UNSPECIFIED);
+        assert_eq!(agent.subscription_count().await, 1);
+
+        // Deletes need the RAN function and a known request ID
+        let delete = |instance_id| E2apPdu::initiating(E2apProcedureCode::RicSubscriptionDelete)
+            .with_ie(ID_RIC_REQUEST_ID, Criticality::Reject, &RicRequestId { requestor_id: 100, instance_id }).unwrap();
+        assert!(matches!(agent.handle_subscription_delete_request(&delete(1), &sender).await,
+                         Err(LayerError::ProcessingError(_))));
+        let unknown = delete(2).with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID)).unwrap();
+        agent.handle_subscription_delete_request(&unknown, &sender).await.unwrap();
+        let failure = recv_outcome(&mut ric_rx, E2apProcedureCode::RicSubscriptionDelete).await;
+        assert_eq!(failure.pdu_type, E2apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(ID_CAUSE).unwrap(), Cause::REQUEST_ID_UNKNOWN);
+        assert_eq!(agent.subscription_count().await, 1);
+        let known = delete(1).with_ie(ID_RAN_FUNCTION_ID, Criticality::Reject, &RanFunctionId(KPM_RAN_FUNCTION_ID)).unwrap();
+        agent.handle_subscription_delete_request(&known, &sender).await.unwrap();
+        let response = recv_outcome(&mut ric_rx, E2apProcedureCode::RicSubscriptionDelete).await;
+        assert_eq!(response.pdu_type, E2apPduType::SuccessfulOutcome);
+        assert_eq!(agent.subscription_count().await, 0);
+    }
+}
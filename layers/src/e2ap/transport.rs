@@ -0,0 +1,55 @@
+//! E2 transport
+//!
+//! Carries E2AP over SCTP as specified by O-RAN.WG3.E2GAP: the E2 node opens
+//! the association towards the near-RT RIC, payload protocol identifier 70,
+//! all signalling on stream 0.
+
+use super::E2apProcedureCode;
+use crate::ngap::transport::{self, ApListener, ApReceiver, ApSender, NgTransportKind, SctpProtocol, TransportEvent};
+use crate::LayerError;
+use std::net::SocketAddr;
+
+/// SCTP payload protocol identifier of E2AP (O-RAN.WG3.E2GAP section 6)
+pub const E2AP_PPID: u32 = 70;
+/// SCTP destination port of E2AP at the near-RT RIC
+pub const E2AP_PORT: u16 = 36421;
+/// SCTP stream all E2AP signalling is sent on
+pub const E2AP_STREAM: u16 = 0;
+
+impl SctpProtocol for E2apProcedureCode {
+    const INTERFACE: &'static str = "E2";
+    const PPID: u32 = E2AP_PPID;
+    const NUM_STREAMS: u16 = 1;
+}
+
+/// Event received on the E2 transport
+pub type E2TransportEvent = TransportEvent;
+/// Sending side of an E2 association
+pub type E2Sender = ApSender<E2apProcedureCode>;
+/// Receiving side of an E2 association
+pub type E2Receiver = ApReceiver<E2apProcedureCode>;
+/// Listening socket for E2 associations, the near-RT RIC side
+pub type E2Listener = ApListener<E2apProcedureCode>;
+
+/// Open an E2 association towards a near-RT RIC
+pub async fn connect(
+    kind: NgTransportKind,
+    local_address: SocketAddr,
+    peer_address: SocketAddr,
+) -> Result<(E2Sender, E2Receiver), LayerError> {
+    transport::connect(kind, local_address, peer_address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_e2_sctp_parameters() {
+        // O-RAN.WG3.E2GAP section 6, a single stream for all signalling
+        assert_eq!(E2apProcedureCode::PPID, 70);
+        assert_eq!(E2AP_PORT, 36421);
+        assert_eq!(E2apProcedureCode::NUM_STREAMS, 1);
+        assert!(E2AP_STREAM < E2apProcedureCode::NUM_STREAMS);
+    }
+}
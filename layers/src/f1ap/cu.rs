@@ -0,0 +1,381 @@
+//! gNB-CU side of F1
+//!
+//! Accepts the F1-C association of one gNB-DU and stands in for its MAC towards
+//! RRC. Uplink RRC messages and DRB data received from the DU are passed to RRC
+//! over the same kind of channels the MAC uses in a monolithic gNB.
+
+use super::pdu::{Cause, F1apPdu, F1apPduType, ServedCellInformation};
+use super::transport::{F1Listener, F1Receiver, F1Sender, F1TransportEvent};
+use super::nru::NrUFrame;
+use super::user_plane::{F1uBearer, F1uEndpoint};
+use super::F1apProcedureCode;
+use crate::gtpu::pdu::GtpuPdu;
+use crate::mac::UeSchedulingCapabilities;
+use crate::ngap::pdu::NrCgi;
+use crate::ngap::transport::NgTransportKind;
+use crate::rrc::{DrbBearerConfig, PagingRequest, RarGrant, RrcCuUpInterface, RrcMacInterface, RrcMessageType};
+use crate::LayerError;
+use async_trait::async_trait;
+use bytes::Bytes;
+use common::types::Rnti;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+
+/// gNB-CU configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct F1apCuConfig {
+    /// Local address the F1-C listener is bound to
+    pub bind_address: SocketAddr,
+    /// F1-C transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// Local F1-U address, also given to the DU for uplink tunnels
+    pub f1u_address: SocketAddr,
+    /// gNB-CU name sent in F1 Setup Response
+    pub gnb_cu_name: String,
+}
+
+/// gNB-DU served by the CU
+pub(super) struct ConnectedDu {
+    /// gNB-DU ID
+    pub(super) gnb_du_id: u64,
+    /// Sending side of its association
+    pub(super) sender: Arc<F1Sender>,
+    /// Cells activated in F1 Setup
+    pub(super) cells: Vec<ServedCellInformation>,
+}
+
+/// UE-associated F1AP state of the CU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CuUeContext {
+    /// gNB-CU UE F1AP ID
+    pub(super) cu_ue_id: u32,
+    /// gNB-DU UE F1AP ID
+    pub(super) du_ue_id: u32,
+    /// Cell the UE accessed
+    pub(super) nr_cgi: NrCgi,
+    /// UE Context Setup was sent, DRB changes use UE Context Modification
+    pub(super) context_setup: bool,
+}
+
+/// gNB-CU F1AP entity
+pub struct F1apCu {
+    pub(super) config: F1apCuConfig,
+    listener: F1Listener,
+    /// Connected gNB-DU
+    pub(super) du: RwLock<Option<ConnectedDu>>,
+    /// UE contexts indexed by C-RNTI
+    pub(super) ue_contexts: Mutex<HashMap<u16, CuUeContext>>,
+    /// Next gNB-CU UE F1AP ID
+    pub(super) next_cu_ue_id: AtomicU32,
+    /// F1-U tunnels of the DRBs
+    pub(super) f1u: F1uEndpoint,
+    /// gNB-CU-UP terminating F1-U instead of the CU itself
+    pub(super) cu_up: Option<Arc<dyn RrcCuUpInterface>>,
+    /// Channel towards RRC for uplink RRC messages
+    rrc_tx: Option<mpsc::Sender<(Rnti, Bytes)>>,
+    /// Channel towards RRC for uplink DRB data: C-RNTI, LCID and PDU
+    user_data_tx: Option<mpsc::Sender<(Rnti, u8, Bytes)>>,
+}
+
+impl F1apCu {
+    /// Bind the F1-C listener and the F1-U socket
+    pub async fn new(config: F1apCuConfig) -> Result<Self, LayerError> {
+        let listener = F1Listener::bind(config.transport, config.bind_address).await?;
+        let f1u = F1uEndpoint::bind(config.f1u_address).await?;
+        info!("gNB-CU {} listening for F1-C on {} ({:?}), F1-U on {}",
+              config.gnb_cu_name, listener.local_addr().unwrap_or(config.bind_address), config.transport,
+              config.f1u_address);
+        Ok(Self {
+            config,
+            listener,
+            du: RwLock::new(None),
+            ue_contexts: Mutex::new(HashMap::new()),
+            next_cu_ue_id: AtomicU32::new(1),
+            f1u,
+            cu_up: None,
+            rrc_tx: None,
+            user_data_tx: None,
+        })
+    }
+
+    /// Set the channel used to pass uplink RRC messages to RRC
+    pub fn set_rrc_channel(&mut self, tx: mpsc::Sender<(Rnti, Bytes)>) {
+        self.rrc_tx = Some(tx);
+    }
+
+    /// Set the gNB-CU-UP the DRB tunnels of the DU are given to
+    pub fn set_cu_up_interface(&mut self, cu_up: Arc<dyn RrcCuUpInterface>) {
+        self.cu_up = Some(cu_up);
+    }
+
+    /// Set the channel used to pass uplink DRB data to RRC
+    pub fn set_user_data_channel(&mut self, tx: mpsc::Sender<(Rnti, u8, Bytes)>) {
+        self.user_data_tx = Some(tx);
+    }
+
+    /// Local address of the F1-C listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// gNB-DU ID of the connected DU
+    pub async fn connected_du(&self) -> Option<u64> {
+        self.du.read().await.as_ref().map(|du| du.gnb_du_id)
+    }
+
+    /// Sending side of the association with the connected DU
+    pub(super) async fn du_sender(&self) -> Result<Arc<F1Sender>, LayerError> {
+        self.du.read().await.as_ref()
+            .map(|du| Arc::clone(&du.sender))
+            .ok_or_else(|| LayerError::InvalidState("No gNB-DU connected".into()))
+    }
+
+    /// UE context of a C-RNTI
+    pub(super) async fn ue_context(&self, rnti: Rnti) -> Result<CuUeContext, LayerError> {
+        self.ue_contexts.lock().await.get(&rnti.0).copied()
+            .ok_or_else(|| LayerError::InvalidState(format!("No F1 UE context for RNTI {}", rnti.0)))
+    }
+
+    /// C-RNTI of the UE with a gNB-CU UE F1AP ID
+    pub(super) async fn rnti_of(&self, cu_ue_id: u32) -> Result<Rnti, LayerError> {
+        self.ue_contexts.lock().await.iter()
+            .find(|(_, ctx)| ctx.cu_ue_id == cu_ue_id)
+            .map(|(rnti, _)| Rnti(*rnti))
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown gNB-CU UE F1AP ID {}", cu_ue_id)))
+    }
+
+    /// Pass an uplink RRC message to RRC
+    pub(super) async fn send_to_rrc(&self, rnti: Rnti, data: Bytes) {
+        if let Some(rrc_tx) = &self.rrc_tx {
+            if let Err(e) = rrc_tx.send((rnti, data)).await {
+                error!("Failed to send message to RRC: {}", e);
+            }
+        } else {
+            debug!("No RRC channel configured");
+        }
+    }
+
+    /// Handle a PDU received on the association of `sender`
+    async fn handle_pdu(&self, pdu: F1apPdu, sender: &Arc<F1Sender>) -> Result<(), LayerError> {
+        use F1apProcedureCode::*;
+
+        match (pdu.pdu_type, pdu.procedure()) {
+            (F1apPduType::InitiatingMessage, Some(F1Setup)) => self.handle_f1_setup_request(&pdu, sender).await,
+            (F1apPduType::InitiatingMessage, Some(InitialUlRrcMessageTransfer)) => {
+                self.handle_initial_ul_rrc_message_transfer(&pdu).await
+            }
+            (F1apPduType::InitiatingMessage, Some(UlRrcMessageTransfer)) => self.handle_ul_rrc_message_transfer(&pdu).await,
+            (F1apPduType::SuccessfulOutcome, Some(UeContextSetup)) => self.handle_ue_context_setup_response(&pdu).await,
+            (F1apPduType::UnsuccessfulOutcome, Some(UeContextSetup)) => self.handle_ue_context_setup_failure(&pdu).await,
+            (F1apPduType::SuccessfulOutcome, Some(UeContextModification)) => {
+                self.handle_ue_context_modification_response(&pdu).await
+            }
+            (F1apPduType::UnsuccessfulOutcome, Some(UeContextModification)) => {
+                self.handle_ue_context_modification_failure(&pdu).await
+            }
+            (F1apPduType::SuccessfulOutcome, Some(UeContextRelease)) => self.handle_ue_context_release_complete(&pdu).await,
+            (pdu_type, procedure) => {
+                debug!("Unhandled F1AP {:?} of procedure {:?} ({})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the DU and its UEs once its association is gone
+    async fn handle_association_lost(&self, sender: &Arc<F1Sender>, reason: &str) {
+        let mut du = self.du.write().await;
+        if !du.as_ref().is_some_and(|du| Arc::ptr_eq(&du.sender, sender)) {
+            debug!("F1-C association with {} closed: {}", sender.peer(), reason);
+            return;
+        }
+        if let Some(du) = du.take() {
+            warn!("F1-C association with gNB-DU {} lost: {}", du.gnb_du_id, reason);
+        }
+        drop(du);
+
+        let released = std::mem::take(&mut *self.ue_contexts.lock().await);
+        if !released.is_empty() {
+            warn!("Dropping {} F1 UE contexts of the lost gNB-DU", released.len());
+        }
+        self.f1u.clear().await;
+    }
+
+    /// Handle a G-PDU received on an uplink F1-U tunnel
+    async fn handle_f1u_pdu(&self, teid: u32, bearer: F1uBearer, pdu: GtpuPdu) {
+        if let Some(container) = &pdu.nr_ran_container {
+            match NrUFrame::decode(container) {
+                Ok(NrUFrame::DlDataDeliveryStatus(status)) => {
+                    if !status.lost_nr_u_sn_ranges.is_empty() {
+                        warn!("gNB-DU lost NR-U SNs {:?} of DRB {} of RNTI {}",
+                              status.lost_nr_u_sn_ranges, bearer.drb_id, bearer.rnti.0);
+                    }
+                    debug!("DL Data Delivery Status of DRB {} of RNTI {}: desired buffer size {} bytes{}",
+                           bearer.drb_id, bearer.rnti.0, status.desired_buffer_size,
+                           if status.final_frame { ", final" } else { "" });
+                    self.f1u.set_desired_buffer_size(teid, status.desired_buffer_size).await;
+                }
+                Ok(frame) => debug!("Ignoring uplink NR-U frame {:?}", frame),
+                Err(e) => debug!("Invalid NR-U frame on DRB {} of RNTI {}: {}", bearer.drb_id, bearer.rnti.0, e),
+            }
+        }
+        if pdu.payload.is_empty() {
+            return;
+        }
+        if let Some(user_data_tx) = &self.user_data_tx {
+            if let Err(e) = user_data_tx.send((bearer.rnti, bearer.lcid, pdu.payload)).await {
+                error!("Failed to send user data to RRC: {}", e);
+            }
+        } else {
+            debug!("No user data channel configured");
+        }
+    }
+}
+
+#[async_trait]
+impl RrcMacInterface for F1apCu {
+    async fn send_rrc_message(&self, rnti: Rnti, msg_type: RrcMessageType, data: Bytes) -> Result<(), LayerError> {
+        match msg_type {
+            // The DU delivers RRC Reject while releasing the UE (TS 38.473 section 8.3.3)
+            RrcMessageType::RrcReject => {
+                self.release_ue_context(rnti, Cause::RADIO_NETWORK_UNSPECIFIED, Some(data)).await
+            }
+            RrcMessageType::RrcSetup => self.send_dl_rrc_message_transfer(rnti, 0, data).await,
+            _ => self.send_dl_rrc_message_transfer(rnti, 1, data).await,
+        }
+    }
+
+    async fn allocate_c_rnti(&self) -> Result<Rnti, LayerError> {
+        Err(LayerError::InvalidState("C-RNTIs are allocated by the gNB-DU".into()))
+    }
+
+    async fn configure_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError> {
+        self.send_ue_capabilities(rnti, capabilities).await
+    }
+
+    async fn schedule_rar(&self, _tc_rnti: Rnti, _grant: RarGrant) -> Result<(), LayerError> {
+        Err(LayerError::InvalidState("Random access is handled by the gNB-DU".into()))
+    }
+
+    async fn release_ue(&self, rnti: Rnti) -> Result<(), LayerError> {
+        if !self.ue_contexts.lock().await.contains_key(&rnti.0) {
+            debug!("No F1 UE context to release for RNTI {}", rnti.0);
+            return Ok(());
+        }
+        self.release_ue_context(rnti, Cause::NORMAL_RELEASE, None).await
+    }
+
+    async fn schedule_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+        self.send_paging(request).await
+    }
+
+    async fn send_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        self.f1u.send_downlink(rnti, lcid, data).await
+    }
+
+    async fn configure_drbs(&self, rnti: Rnti, to_setup: Vec<DrbBearerConfig>, to_release: Vec<u8>) -> Result<(), LayerError> {
+        self.request_drbs(rnti, to_setup, to_release).await
+    }
+}
+
+/// Serve one gNB-DU association until it is lost
+async fn run_association(cu: Arc<F1apCu>, sender: F1Sender, mut receiver: F1Receiver) {
+    let sender = Arc::new(sender);
+    info!("F1-C association from {}", sender.peer());
+    loop {
+        match receiver.recv().await {
+            F1TransportEvent::Data { stream, payload } => {
+                let pdu = match F1apPdu::decode(&payload) {
+                    Ok(pdu) => pdu,
+                    Err(e) => {
+                        warn!("Dropping undecodable F1AP PDU on stream {}: {}", stream, e);
+                        continue;
+                    }
+                };
+                if let Err(e) = cu.handle_pdu(pdu, &sender).await {
+                    warn!("Failed to handle F1AP PDU from {}: {}", sender.peer(), e);
+                }
+            }
+            F1TransportEvent::AssociationLost(reason) => {
+                cu.handle_association_lost(&sender, &reason).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Accept gNB-DU associations and receive F1-U data until the task is dropped
+pub async fn run_f1_cu(cu: Arc<F1apCu>) {
+    let control_plane = async {
+        loop {
+            match cu.listener.accept().await {
+                Ok((sender, receiver)) => {
+                    tokio::spawn(run_association(Arc::clone(&cu), sender, receiver));
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
+    };
+    let user_plane = async {
+        loop {
+            let (teid, bearer, pdu) = cu.f1u.recv().await;
+            cu.handle_f1u_pdu(teid, bearer, pdu).await;
+        }
+    };
+    tokio::join!(control_plane, user_plane);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rrc::PagingUeIdentity;
+    use crate::test_support::{f1_association, served_cell};
+
+    #[tokio::test]
+    async fn test_cu_without_du() {
+        let cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+
+        // MAC functions of the DU are refused, unknown UEs are released quietly
+        assert!(matches!(cu.allocate_c_rnti().await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu.schedule_rar(rnti, RarGrant { timing_advance: 0, ul_grant: 0, tc_rnti: rnti }).await, Err(LayerError::InvalidState(_))));
+        cu.release_ue(rnti).await.unwrap();
+        assert!(matches!(cu.send_user_data(rnti, 4, Bytes::from_static(&[0x45])).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu.send_rrc_message(rnti, RrcMessageType::RrcReject, Bytes::from_static(&[0x03])).await,
+                         Err(LayerError::InvalidState(_))));
+        let paging = PagingRequest { identity: PagingUeIdentity::NgSTmsi(1), ue_id: 1, paging_cycle: None, priority: None };
+        assert!(matches!(cu.schedule_paging(paging).await, Err(LayerError::InvalidState(_))));
+
+        // PDUs the CU does not handle
+        let ((cu_tx, _cu_rx), _du) = f1_association().await;
+        let sender = Arc::new(cu_tx);
+        cu.handle_pdu(F1apPdu::initiating(F1apProcedureCode::Paging), &sender).await.unwrap();
+        let mut unknown = F1apPdu::initiating(F1apProcedureCode::F1Setup);
+        unknown.procedure_code = 0xFE;
+        cu.handle_pdu(unknown, &sender).await.unwrap();
+        assert!(matches!(cu.handle_pdu(F1apPdu::initiating(F1apProcedureCode::InitialUlRrcMessageTransfer), &sender).await,
+                         Err(LayerError::ProcessingError(_))));
+
+        // Losing another association keeps the gNB-DU, losing its own drops the UEs
+        *cu.du.write().await = Some(ConnectedDu { gnb_du_id: 1, sender: Arc::clone(&sender), cells: vec![served_cell()] });
+        cu.ue_contexts.lock().await.insert(rnti.0, CuUeContext {
+            cu_ue_id: 1, du_ue_id: 1, nr_cgi: served_cell().nr_cgi, context_setup: true,
+        });
+        cu.f1u.add_bearer(F1uBearer::new(rnti, 1, 4)).await.unwrap();
+        let ((other, _other_rx), _other_du) = f1_association().await;
+        cu.handle_association_lost(&Arc::new(other), "closed").await;
+        assert_eq!(cu.connected_du().await, Some(1));
+        cu.handle_association_lost(&sender, "closed").await;
+        assert_eq!(cu.connected_du().await, None);
+        assert!(cu.ue_contexts.lock().await.is_empty());
+        assert!(cu.f1u.bearers(rnti).await.is_empty());
+    }
+}
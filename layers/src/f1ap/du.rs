@@ -0,0 +1,328 @@
+//! gNB-DU side of F1
+//!
+//! Opens the F1-C association towards the gNB-CU, sets it up with the served
+//! cell and drives the MAC with the UE-associated procedures of the CU. The
+//! association is re-established after a loss, the UEs of the old one are
+//! released in the MAC.
+
+use super::nru::{DlDataDeliveryStatus, NrUFrame};
+use super::pdu::{self, F1apPdu, F1apPduType, GnbCuUeF1apId, GnbDuUeF1apId, ServedCellInformation};
+use super::transport::{self, F1Receiver, F1Sender, F1TransportEvent, NON_UE_STREAM};
+use super::user_plane::{F1uBearer, F1uEndpoint};
+use super::F1apProcedureCode;
+use crate::gtpu::pdu::GtpuPdu;
+use crate::ngap::transport::NgTransportKind;
+use crate::rrc::RrcMacInterface;
+use crate::LayerError;
+use bytes::{BufMut, Bytes, BytesMut};
+use common::types::Rnti;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU8};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+/// Buffer asked for in DL Data Delivery Status, per DRB
+const DESIRED_BUFFER_SIZE: u32 = 1 << 20;
+
+/// gNB-DU configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct F1apDuConfig {
+    /// F1-C address of the gNB-CU
+    pub cu_address: SocketAddr,
+    /// Local F1-C address
+    pub local_address: SocketAddr,
+    /// F1-C transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// Local F1-U address, also given to the CU for downlink tunnels
+    pub f1u_address: SocketAddr,
+    /// gNB-DU ID (36 bits)
+    pub gnb_du_id: u64,
+    /// gNB-DU name sent in F1 Setup Request
+    pub gnb_du_name: String,
+    /// Served cell
+    pub served_cell: ServedCellInformation,
+    /// Delay between attempts to (re-)establish the association
+    pub reconnect_interval: Duration,
+}
+
+/// UE-associated F1AP state of the DU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct DuUeContext {
+    /// gNB-DU UE F1AP ID
+    pub(super) du_ue_id: u32,
+    /// gNB-CU UE F1AP ID, known after the first message of the CU
+    pub(super) cu_ue_id: Option<u32>,
+}
+
+/// gNB-DU F1AP entity
+pub struct F1apDu {
+    pub(super) config: F1apDuConfig,
+    /// MAC of the DU
+    pub(super) mac: Arc<dyn RrcMacInterface>,
+    /// Sending side of the association, once F1 Setup succeeded
+    sender: RwLock<Option<Arc<F1Sender>>>,
+    /// UE contexts indexed by C-RNTI
+    pub(super) ue_contexts: Mutex<HashMap<u16, DuUeContext>>,
+    /// Next gNB-DU UE F1AP ID
+    pub(super) next_du_ue_id: AtomicU32,
+    /// Next transaction ID of non UE-associated procedures
+    pub(super) next_transaction_id: AtomicU8,
+    /// F1-U tunnels of the DRBs
+    pub(super) f1u: F1uEndpoint,
+}
+
+impl F1apDu {
+    /// Bind the F1-U socket
+    pub async fn new(config: F1apDuConfig, mac: Arc<dyn RrcMacInterface>) -> Result<Self, LayerError> {
+        let f1u = F1uEndpoint::bind(config.f1u_address).await?;
+        Ok(Self {
+            config,
+            mac,
+            sender: RwLock::new(None),
+            ue_contexts: Mutex::new(HashMap::new()),
+            next_du_ue_id: AtomicU32::new(1),
+            next_transaction_id: AtomicU8::new(0),
+            f1u,
+        })
+    }
+
+    /// Whether F1 Setup with the gNB-CU succeeded
+    pub async fn is_connected(&self) -> bool {
+        self.sender.read().await.is_some()
+    }
+
+    /// Send a PDU to the gNB-CU
+    pub(super) async fn send(&self, stream: u16, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let sender = self.sender.read().await.clone()
+            .ok_or_else(|| LayerError::InvalidState("F1 not set up with the gNB-CU".into()))?;
+        sender.send(stream, pdu).await
+    }
+
+    /// DU and CU F1AP IDs of a C-RNTI
+    pub(super) async fn ue_ids(&self, rnti: Rnti) -> Result<(u32, u32), LayerError> {
+        let contexts = self.ue_contexts.lock().await;
+        let ctx = contexts.get(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState(format!("No F1 UE context for RNTI {}", rnti.0)))?;
+        let cu_ue_id = ctx.cu_ue_id
+            .ok_or_else(|| LayerError::InvalidState(format!("RNTI {} not known to the gNB-CU yet", rnti.0)))?;
+        Ok((ctx.du_ue_id, cu_ue_id))
+    }
+
+    /// C-RNTI of the UE addressed by a UE-associated PDU, noting its gNB-CU UE F1AP ID
+    pub(super) async fn ue_of(&self, pdu: &F1apPdu) -> Result<Rnti, LayerError> {
+        let cu_ue_id = pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0;
+        let du_ue_id = pdu.ie::<GnbDuUeF1apId>(pdu::ID_GNB_DU_UE_F1AP_ID)?.0;
+        let mut contexts = self.ue_contexts.lock().await;
+        let (rnti, ctx) = contexts.iter_mut()
+            .find(|(_, ctx)| ctx.du_ue_id == du_ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown gNB-DU UE F1AP ID {}", du_ue_id)))?;
+        ctx.cu_ue_id = Some(cu_ue_id);
+        Ok(Rnti(*rnti))
+    }
+
+    /// CellGroupConfig of a UE in the simplified encoding used by RRC: cell
+    /// group ID 0 followed by (bearer ID, LCID) pairs of SRB1 and the DRBs
+    pub(super) fn cell_group_config(&self, drbs: &[(u8, u8)]) -> Bytes {
+        let mut buf = BytesMut::with_capacity(3 + 2 * drbs.len());
+        buf.put_u8(0);
+        buf.put_slice(&[1, 1]);
+        for (drb_id, lcid) in drbs {
+            buf.put_u8(*drb_id);
+            buf.put_u8(*lcid);
+        }
+        buf.freeze()
+    }
+
+    /// Handle a PDU received from the gNB-CU
+    async fn handle_pdu(&self, pdu: F1apPdu) -> Result<(), LayerError> {
+        use F1apProcedureCode::*;
+
+        match (pdu.pdu_type, pdu.procedure()) {
+            (F1apPduType::InitiatingMessage, Some(DlRrcMessageTransfer)) => self.handle_dl_rrc_message_transfer(&pdu).await,
+            (F1apPduType::InitiatingMessage, Some(UeContextSetup)) => self.handle_ue_context_setup_request(&pdu).await,
+            (F1apPduType::InitiatingMessage, Some(UeContextModification)) => {
+                self.handle_ue_context_modification_request(&pdu).await
+            }
+            (F1apPduType::InitiatingMessage, Some(UeContextRelease)) => self.handle_ue_context_release_command(&pdu).await,
+            (F1apPduType::InitiatingMessage, Some(Paging)) => self.handle_paging(&pdu).await,
+            (pdu_type, procedure) => {
+                debug!("Unhandled F1AP {:?} of procedure {:?} ({})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Open the association and run F1 Setup
+    async fn connect(&self) -> Result<F1Receiver, LayerError> {
+        let (sender, mut receiver) = transport::connect(self.config.transport, self.config.local_address,
+                                                        self.config.cu_address).await?;
+        sender.send(NON_UE_STREAM, &self.f1_setup_request()?).await?;
+        loop {
+            match receiver.recv().await {
+                F1TransportEvent::Data { payload, .. } => {
+                    let pdu = F1apPdu::decode(&payload)?;
+                    if pdu.procedure() != Some(F1apProcedureCode::F1Setup) {
+                        debug!("Ignoring F1AP procedure {} before F1 Setup", pdu.procedure_code);
+                        continue;
+                    }
+                    self.handle_f1_setup_outcome(&pdu)?;
+                    break;
+                }
+                F1TransportEvent::AssociationLost(reason) => {
+                    return Err(LayerError::InitializationFailed(format!("F1 Setup aborted: {}", reason)));
+                }
+            }
+        }
+        *self.sender.write().await = Some(Arc::new(sender));
+        Ok(receiver)
+    }
+
+    /// Release every UE in the MAC once the association is gone
+    async fn handle_association_lost(&self, reason: &str) {
+        warn!("F1-C association with gNB-CU {} lost: {}", self.config.cu_address, reason);
+        *self.sender.write().await = None;
+        let released = std::mem::take(&mut *self.ue_contexts.lock().await);
+        for rnti in released.keys() {
+            if let Err(e) = self.mac.release_ue(Rnti(*rnti)).await {
+                warn!("Failed to release MAC resources of RNTI {}: {}", rnti, e);
+            }
+        }
+        self.f1u.clear().await;
+    }
+
+    /// Keep the association with the gNB-CU up
+    async fn run_control_plane(&self) {
+        loop {
+            match self.connect().await {
+                Ok(mut receiver) => loop {
+                    match receiver.recv().await {
+                        F1TransportEvent::Data { stream, payload } => match F1apPdu::decode(&payload) {
+                            Ok(pdu) => {
+                                if let Err(e) = self.handle_pdu(pdu).await {
+                                    warn!("Failed to handle F1AP PDU from gNB-CU: {}", e);
+                                }
+                            }
+                            Err(e) => warn!("Dropping undecodable F1AP PDU on stream {}: {}", stream, e),
+                        },
+                        F1TransportEvent::AssociationLost(reason) => {
+                            self.handle_association_lost(&reason).await;
+                            break;
+                        }
+                    }
+                },
+                Err(e) => warn!("{}", e),
+            }
+            tokio::time::sleep(self.config.reconnect_interval).await;
+        }
+    }
+
+    /// Send an uplink PDCP PDU of a DRB to the gNB-CU
+    pub async fn send_uplink_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        self.f1u.send_uplink(rnti, lcid, data).await
+    }
+
+    /// Pass a downlink F1-U PDU to the MAC, reporting the delivery status when
+    /// polled or when NR-U sequence numbers are missing
+    async fn handle_f1u_pdu(&self, teid: u32, bearer: F1uBearer, pdu: GtpuPdu) -> Result<(), LayerError> {
+        let mut report = false;
+        let mut lost = None;
+        if let Some(container) = &pdu.nr_ran_container {
+            match NrUFrame::decode(container)? {
+                NrUFrame::DlUserData(user_data) => {
+                    report = user_data.report_polling;
+                    lost = self.f1u.check_nr_u_sn(teid, user_data.nr_u_sn).await;
+                }
+                frame => debug!("Ignoring downlink NR-U frame {:?}", frame),
+            }
+        }
+        if let Some((start, end)) = lost {
+            warn!("NR-U SNs {}..={} of DRB {} of RNTI {} lost", start, end, bearer.drb_id, bearer.rnti.0);
+        }
+        if !pdu.payload.is_empty() {
+            self.mac.send_user_data(bearer.rnti, bearer.lcid, pdu.payload).await?;
+        }
+        if let (true, Some(remote)) = (report || lost.is_some(), bearer.remote) {
+            let status = DlDataDeliveryStatus {
+                desired_buffer_size: DESIRED_BUFFER_SIZE,
+                lost_nr_u_sn_ranges: lost.into_iter().collect(),
+                final_frame: false,
+            };
+            self.f1u.send_delivery_status(&remote, &status).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive F1-U data
+    async fn run_user_plane(&self) {
+        loop {
+            let (teid, bearer, pdu) = self.f1u.recv().await;
+            if let Err(e) = self.handle_f1u_pdu(teid, bearer, pdu).await {
+                debug!("Dropping F1-U PDU on TEID {:#x}: {}", teid, e);
+            }
+        }
+    }
+}
+
+/// Run the F1 interface of the DU: keep the association with the CU up and
+/// receive F1-U data until the task is dropped
+pub async fn run_f1_du(du: Arc<F1apDu>) {
+    info!("gNB-DU {} connecting to gNB-CU at {} ({:?})",
+          du.config.gnb_du_id, du.config.cu_address, du.config.transport);
+    tokio::join!(du.run_control_plane(), du.run_user_plane());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::nru::DlUserData;
+    use crate::ngap::pdu::GtpTunnel;
+    use crate::test_support::{f1ap_du_config, RecordingMac};
+    use std::net::IpAddr;
+
+    #[tokio::test]
+    async fn test_du_failures() {
+        let mac = Arc::new(RecordingMac::default());
+        // Nothing listens on the gNB-CU port
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let du = F1apDu::new(f1ap_du_config(closed), mac.clone()).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+
+        assert!(du.connect().await.is_err());
+        assert!(!du.is_connected().await);
+        assert!(matches!(du.send(NON_UE_STREAM, &F1apPdu::initiating(F1apProcedureCode::F1Setup)).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(matches!(du.send_uplink_user_data(rnti, 4, Bytes::from_static(&[0x45])).await,
+                         Err(LayerError::InvalidState(_))));
+        du.handle_pdu(F1apPdu::successful(F1apProcedureCode::F1Setup)).await.unwrap();
+
+        // UE IDs before and after the gNB-CU UE F1AP ID is known
+        assert!(matches!(du.ue_ids(rnti).await, Err(LayerError::InvalidState(_))));
+        du.ue_contexts.lock().await.insert(rnti.0, DuUeContext { du_ue_id: 1, cu_ue_id: None });
+        assert!(matches!(du.ue_ids(rnti).await, Err(LayerError::InvalidState(_))));
+        let pdu = F1apPdu::initiating(F1apProcedureCode::UeContextSetup);
+        assert!(matches!(du.ue_of(&pdu).await, Err(LayerError::ProcessingError(_))));
+
+        // F1-U: an undecodable NR-U frame is dropped, a gap in NR-U SNs is not
+        let mut bearer = F1uBearer::new(rnti, 1, 4);
+        bearer.remote = Some(GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 0x100 });
+        let teid = du.f1u.add_bearer(bearer.clone()).await.unwrap().teid;
+        let packet = Bytes::from_static(&[0x45, 0x00]);
+        let invalid = GtpuPdu::nr_u(teid, Bytes::from_static(&[0x70]), packet.clone());
+        assert!(du.handle_f1u_pdu(teid, bearer.clone(), invalid).await.is_err());
+        assert!(mac.user_data.lock().unwrap().is_empty());
+        for nr_u_sn in [1, 3] {
+            let user_data = DlUserData { nr_u_sn, report_polling: false, user_data_existence: true };
+            du.handle_f1u_pdu(teid, bearer.clone(), GtpuPdu::nr_u(teid, user_data.encode(), packet.clone())).await.unwrap();
+        }
+        assert_eq!(mac.user_data.lock().unwrap().len(), 2);
+
+        // Losing the association releases the UEs in the MAC
+        du.handle_association_lost("closed").await;
+        assert_eq!(*mac.released.lock().unwrap(), vec![rnti]);
+        assert!(du.ue_contexts.lock().await.is_empty());
+        assert!(du.f1u.bearers(rnti).await.is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+//! F1 Application Protocol (F1AP) Implementation
+//!
+//! Splits the gNB into a gNB-CU, holding RRC, PDCP and SDAP, and a gNB-DU,
+//! holding RLC, MAC and PHY, according to 3GPP TS 38.473 (F1-C) and TS 38.425
+//! (NR user plane protocol on F1-U).
+//!
+//! On the CU side [`F1apCu`] stands in for the MAC towards RRC: it implements
+//! [`RrcMacInterface`](crate::rrc::RrcMacInterface) by turning RRC messages into
+//! DL RRC Message Transfer, DRB changes into UE Context Setup and Modification,
+//! and DRB data into F1-U tunnels. On the DU side [`F1apDu`] drives the MAC with
+//! what the CU asks for and passes uplink RRC messages and DRB data back.
+
+pub mod cu;
+pub mod du;
+pub mod nru;
+pub mod paging;
+pub mod pdu;
+pub mod rrc_transfer;
+pub mod setup;
+pub mod transport;
+pub mod ue_context;
+pub mod user_plane;
+
+use crate::ngap::pdu::Criticality;
+
+pub use cu::{run_f1_cu, F1apCu, F1apCuConfig};
+pub use du::{run_f1_du, F1apDu, F1apDuConfig};
+
+/// F1AP procedure codes (3GPP TS 38.473 section 9.4.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum F1apProcedureCode {
+    Reset = 0,
+    F1Setup = 1,
+    ErrorIndication = 2,
+    UeContextSetup = 5,
+    UeContextRelease = 6,
+    UeContextModification = 7,
+    UeContextReleaseRequest = 10,
+    InitialUlRrcMessageTransfer = 11,
+    DlRrcMessageTransfer = 12,
+    UlRrcMessageTransfer = 13,
+    Paging = 18,
+}
+
+impl F1apProcedureCode {
+    /// Look up a procedure code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use F1apProcedureCode::*;
+
+        [
+            Reset, F1Setup, ErrorIndication, UeContextSetup, UeContextRelease, UeContextModification,
+            UeContextReleaseRequest, InitialUlRrcMessageTransfer, DlRrcMessageTransfer, UlRrcMessageTransfer,
+            Paging,
+        ].into_iter().find(|procedure| *procedure as u8 == code)
+    }
+
+    /// Criticality of the procedure (3GPP TS 38.473 section 9.4.4): reject for
+    /// class 1 procedures, ignore for class 2
+    pub fn criticality(&self) -> Criticality {
+        use F1apProcedureCode::*;
+
+        match self {
+            ErrorIndication | UeContextReleaseRequest | InitialUlRrcMessageTransfer | DlRrcMessageTransfer |
+            UlRrcMessageTransfer | Paging => Criticality::Ignore,
+            _ => Criticality::Reject,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pdu::{FddInfo, ServedCellInformation};
+    use super::*;
+    use crate::test_support::{wait_until, RecordingMac};
+    use crate::mac::UeSchedulingCapabilities;
+    use crate::ngap::pdu::NrCgi;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::rrc::{DrbBearerConfig, PagingRequest, PagingUeIdentity, RrcMacInterface, RrcMessageType};
+    use crate::rrc::reconfiguration::default_drb_rlc_config;
+    use bytes::Bytes;
+    use common::types::Rnti;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_f1_split() {
+        let nr_cgi = NrCgi { plmn_id: [0x00, 0xF1, 0x10], nr_cell_identity: 0x0000_0401 };
+        let mut cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.43:2152".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(8);
+        let (user_data_tx, mut user_data_rx) = mpsc::channel(8);
+        cu.set_rrc_channel(rrc_tx);
+        cu.set_user_data_channel(user_data_tx);
+        let cu = Arc::new(cu);
+        let cu_task = tokio::spawn(run_f1_cu(Arc::clone(&cu)));
+
+        let mac = Arc::new(RecordingMac::default());
+        let du = Arc::new(F1apDu::new(F1apDuConfig {
+            cu_address: cu.local_addr().unwrap(),
+            local_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.44:2152".parse().unwrap(),
+            gnb_du_id: 1,
+            gnb_du_name: "du".into(),
+            served_cell: ServedCellInformation {
+                nr_cgi,
+                nr_pci: 1,
+                tac: Some(7),
+                served_plmns: vec![[0x00, 0xF1, 0x10]],
+                fdd_info: FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 15, nrb: 52 },
+                measurement_timing_configuration: Bytes::from_static(&[0x10]),
+            },
+            reconnect_interval: Duration::from_millis(100),
+        }, mac.clone()).await.unwrap());
+        let du_task = tokio::spawn(run_f1_du(Arc::clone(&du)));
+
+        // F1 Setup
+        wait_until(|| async { du.is_connected().await && cu.connected_du().await == Some(1) }).await;
+
+        // RRC Setup Request reaches RRC in the CU, RRC Setup the MAC in the DU
+        let rnti = Rnti::new(0x4601);
+        let setup_request = Bytes::from_static(&[0x00, 0x01]);
+        du.handle_uplink_rrc_message(rnti, setup_request.clone()).await.unwrap();
+        assert_eq!(timeout(Duration::from_secs(2), rrc_rx.recv()).await.unwrap(), Some((rnti, setup_request)));
+        let setup = Bytes::from_static(&[0x01, 0x02]);
+        cu.send_rrc_message(rnti, RrcMessageType::RrcSetup, setup.clone()).await.unwrap();
+        wait_until(|| async { mac.sent.lock().unwrap().last() == Some(&(rnti, RrcMessageType::RrcSetup, setup.clone())) }).await;
+
+        // RRC Setup Complete on SRB1
+        let setup_complete = Bytes::from_static(&[0x02, 0x03]);
+        du.handle_uplink_rrc_message(rnti, setup_complete.clone()).await.unwrap();
+        assert_eq!(timeout(Duration::from_secs(2), rrc_rx.recv()).await.unwrap(), Some((rnti, setup_complete)));
+
+        // UE capabilities reach the DU scheduler
+        let capabilities = UeSchedulingCapabilities {
+            supports_256qam_dl: true,
+            supports_256qam_ul: false,
+            max_mimo_layers_dl: 2,
+            max_mimo_layers_ul: 1,
+        };
+        cu.configure_ue_capabilities(rnti, capabilities).await.unwrap();
+        wait_until(|| async { mac.capabilities.lock().unwrap().contains(&(rnti, capabilities)) }).await;
+
+        // DRB setup in UE Context Setup brings up both F1-U tunnels
+        let drb = DrbBearerConfig { drb_id: 1, lcid: 4, rlc_config: default_drb_rlc_config(), qos_flows: vec![1], ul_tunnel: None, s_nssai: None };
+        cu.configure_drbs(rnti, vec![drb], vec![]).await.unwrap();
+        wait_until(|| async { cu.f1u.bearers(rnti).await.iter().any(|bearer| bearer.remote.is_some()) }).await;
+        let configured = mac.drbs.lock().unwrap().clone();
+        assert_eq!(configured.len(), 1);
+        assert_eq!((configured[0].1[0].drb_id, configured[0].1[0].lcid), (1, 4));
+
+        // Downlink data is polled for a delivery status
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+        cu.send_user_data(rnti, 4, packet.clone()).await.unwrap();
+        wait_until(|| async { mac.user_data.lock().unwrap().contains(&(rnti, 4, packet.clone())) }).await;
+        wait_until(|| async { cu.f1u.bearers(rnti).await.iter().all(|bearer| bearer.desired_buffer_size.is_some()) }).await;
+
+        // Uplink data reaches the CU
+        du.send_uplink_user_data(rnti, 4, packet.clone()).await.unwrap();
+        assert_eq!(timeout(Duration::from_secs(2), user_data_rx.recv()).await.unwrap(), Some((rnti, 4, packet)));
+
+        // Paging in the served cell
+        cu.schedule_paging(PagingRequest {
+            identity: PagingUeIdentity::NgSTmsi(0x0001_0203_0405),
+            ue_id: 5,
+            paging_cycle: Some(64),
+            priority: Some(2),
+        }).await.unwrap();
+        wait_until(|| async { !mac.paging.lock().unwrap().is_empty() }).await;
+        let paging = mac.paging.lock().unwrap()[0].clone();
+        assert_eq!(paging.identity, PagingUeIdentity::NgSTmsi(0x0001_0203_0405));
+        assert_eq!((paging.ue_id, paging.paging_cycle, paging.priority), (5, Some(64), Some(2)));
+
+        // UE Context Release
+        cu.release_ue(rnti).await.unwrap();
+        wait_until(|| async { cu.ue_contexts.lock().await.is_empty() }).await;
+        assert_eq!(*mac.released.lock().unwrap(), vec![rnti]);
+        assert!(du.f1u.bearers(rnti).await.is_empty());
+
+        du_task.abort();
+        cu_task.abort();
+    }
+}
@@ -0,0 +1,145 @@
+//! NR user plane protocol (NR-U)
+//!
+//! Flow control frames exchanged on F1-U in the NR RAN Container extension
+//! header of GTP-U, according to 3GPP TS 38.425 section 5.5.2.
+
+use crate::LayerError;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// PDU type of DL USER DATA
+const PDU_TYPE_DL_USER_DATA: u8 = 0;
+/// PDU type of DL DATA DELIVERY STATUS
+const PDU_TYPE_DL_DATA_DELIVERY_STATUS: u8 = 1;
+/// Report polling flag of DL USER DATA
+const REPORT_POLLING: u8 = 0x01;
+/// User data existence flag of DL USER DATA
+const USER_DATA_EXISTENCE: u8 = 0x04;
+/// Final frame indication of DL DATA DELIVERY STATUS
+const FINAL_FRAME: u8 = 0x02;
+/// Lost packet report of DL DATA DELIVERY STATUS
+const LOST_PACKET_REPORT: u8 = 0x01;
+/// Largest NR-U sequence number
+pub const MAX_NR_U_SN: u32 = 0xFF_FFFF;
+
+/// DL USER DATA frame (TS 38.425 section 5.5.2.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DlUserData {
+    /// NR-U sequence number of the frame
+    pub nr_u_sn: u32,
+    /// Ask the DU for a DL DATA DELIVERY STATUS
+    pub report_polling: bool,
+    /// The frame carries a PDCP PDU
+    pub user_data_existence: bool,
+}
+
+impl DlUserData {
+    /// Encode the frame
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(5);
+        buf.put_u8(PDU_TYPE_DL_USER_DATA << 4 | if self.report_polling { REPORT_POLLING } else { 0 });
+        buf.put_u8(if self.user_data_existence { USER_DATA_EXISTENCE } else { 0 });
+        buf.put_slice(&(self.nr_u_sn & MAX_NR_U_SN).to_be_bytes()[1..]);
+        buf.freeze()
+    }
+}
+
+/// DL DATA DELIVERY STATUS frame (TS 38.425 section 5.5.2.2)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DlDataDeliveryStatus {
+    /// Buffer the DU wants for the bearer, in bytes
+    pub desired_buffer_size: u32,
+    /// Ranges of NR-U sequence numbers not received, start and end included
+    pub lost_nr_u_sn_ranges: Vec<(u32, u32)>,
+    /// Last status of the bearer, sent when it is released
+    pub final_frame: bool,
+}
+
+impl DlDataDeliveryStatus {
+    /// Encode the frame
+    pub fn encode(&self) -> Bytes {
+        let lost = !self.lost_nr_u_sn_ranges.is_empty();
+        let mut buf = BytesMut::with_capacity(7 + 6 * self.lost_nr_u_sn_ranges.len());
+        buf.put_u8(PDU_TYPE_DL_DATA_DELIVERY_STATUS << 4
+            | if self.final_frame { FINAL_FRAME } else { 0 }
+            | if lost { LOST_PACKET_REPORT } else { 0 });
+        buf.put_u8(0);
+        buf.put_u32(self.desired_buffer_size);
+        if lost {
+            buf.put_u8(self.lost_nr_u_sn_ranges.len().min(u8::MAX as usize) as u8);
+            for (start, end) in self.lost_nr_u_sn_ranges.iter().take(u8::MAX as usize) {
+                buf.put_slice(&(start & MAX_NR_U_SN).to_be_bytes()[1..]);
+                buf.put_slice(&(end & MAX_NR_U_SN).to_be_bytes()[1..]);
+            }
+        }
+        buf.freeze()
+    }
+}
+
+/// NR-U frame carried in an NR RAN Container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NrUFrame {
+    DlUserData(DlUserData),
+    DlDataDeliveryStatus(DlDataDeliveryStatus),
+}
+
+impl NrUFrame {
+    /// Decode a frame, ignoring the optional fields that are not used
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        if data.len() < 2 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let sn = |offset: usize| -> Result<u32, LayerError> {
+            let octets = data.get(offset..offset + 3).ok_or(LayerError::InvalidPdu)?;
+            Ok(u32::from_be_bytes([0, octets[0], octets[1], octets[2]]))
+        };
+        match data[0] >> 4 {
+            PDU_TYPE_DL_USER_DATA => Ok(NrUFrame::DlUserData(DlUserData {
+                nr_u_sn: sn(2)?,
+                report_polling: data[0] & REPORT_POLLING != 0,
+                user_data_existence: data[1] & USER_DATA_EXISTENCE != 0,
+            })),
+            PDU_TYPE_DL_DATA_DELIVERY_STATUS => {
+                let size = data.get(2..6).ok_or(LayerError::InvalidPdu)?;
+                let mut status = DlDataDeliveryStatus {
+                    desired_buffer_size: u32::from_be_bytes([size[0], size[1], size[2], size[3]]),
+                    lost_nr_u_sn_ranges: Vec::new(),
+                    final_frame: data[0] & FINAL_FRAME != 0,
+                };
+                if data[0] & LOST_PACKET_REPORT != 0 {
+                    let count = *data.get(6).ok_or(LayerError::InvalidPdu)? as usize;
+                    for i in 0..count {
+                        status.lost_nr_u_sn_ranges.push((sn(7 + 6 * i)?, sn(10 + 6 * i)?));
+                    }
+                }
+                Ok(NrUFrame::DlDataDeliveryStatus(status))
+            }
+            pdu_type => Err(LayerError::ProcessingError(format!("Unsupported NR-U PDU type {}", pdu_type))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nr_u_frames() {
+        let user_data = DlUserData { nr_u_sn: 0x01_0203, report_polling: true, user_data_existence: true };
+        let encoded = user_data.encode();
+        assert_eq!(&encoded[..], &[0x01, 0x04, 0x01, 0x02, 0x03]);
+        assert_eq!(NrUFrame::decode(&encoded).unwrap(), NrUFrame::DlUserData(user_data));
+
+        let status = DlDataDeliveryStatus {
+            desired_buffer_size: 1 << 20,
+            lost_nr_u_sn_ranges: vec![(3, 5)],
+            final_frame: false,
+        };
+        let encoded = status.encode();
+        assert_eq!(&encoded[..], &[0x11, 0x00, 0x00, 0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x05]);
+        assert_eq!(NrUFrame::decode(&encoded).unwrap(), NrUFrame::DlDataDeliveryStatus(status));
+
+        let status = DlDataDeliveryStatus { desired_buffer_size: 0, lost_nr_u_sn_ranges: Vec::new(), final_frame: true };
+        assert_eq!(NrUFrame::decode(&status.encode()).unwrap(), NrUFrame::DlDataDeliveryStatus(status));
+        assert!(NrUFrame::decode(&[0x20, 0x00]).is_err());
+    }
+}
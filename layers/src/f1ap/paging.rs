@@ -0,0 +1,141 @@
+//! Paging (3GPP TS 38.473 section 8.7)
+//!
+//! RRC in the gNB-CU pages a UE in the cells of the gNB-DU, which schedules the
+//! paging record in the UE's paging occasions.
+
+use super::cu::F1apCu;
+use super::du::F1apDu;
+use super::pdu::{
+    self, F1apPdu, PagingCellItem, PagingCellList, PagingDrx, PagingIdentity, PagingPriority, UeIdentityIndexValue,
+};
+use super::transport::NON_UE_STREAM;
+use super::F1apProcedureCode;
+use crate::ngap::pdu::Criticality;
+use crate::rrc::{PagingRequest, PagingUeIdentity};
+use crate::LayerError;
+use tracing::{debug, info};
+
+impl F1apCu {
+    /// Send Paging to the DU for all its cells
+    pub(super) async fn send_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+        let (sender, cells) = {
+            let du = self.du.read().await;
+            let du = du.as_ref().ok_or_else(|| LayerError::InvalidState("No gNB-DU connected".into()))?;
+            (du.sender.clone(), du.cells.iter().map(|cell| cell.nr_cgi).collect::<Vec<_>>())
+        };
+        if cells.is_empty() {
+            return Err(LayerError::InvalidState("No active cell to page in".into()));
+        }
+
+        let identity = match request.identity {
+            PagingUeIdentity::NgSTmsi(s_tmsi) => PagingIdentity::FiveGSTmsi(s_tmsi),
+            PagingUeIdentity::FullIRnti(i_rnti) => PagingIdentity::IRnti(i_rnti),
+        };
+        let mut paging = F1apPdu::initiating(F1apProcedureCode::Paging)
+            .with_ie(pdu::ID_UE_IDENTITY_INDEX_VALUE, Criticality::Reject, &UeIdentityIndexValue(request.ue_id))?
+            .with_ie(pdu::ID_PAGING_IDENTITY, Criticality::Reject, &identity)?;
+        // Paging DRX only takes the values of the default paging cycle
+        if let Some(cycle) = request.paging_cycle.filter(|cycle| [32, 64, 128, 256].contains(cycle)) {
+            paging.add_ie(pdu::ID_PAGING_DRX, Criticality::Ignore, &PagingDrx(cycle))?;
+        }
+        if let Some(priority) = request.priority {
+            paging.add_ie(pdu::ID_PAGING_PRIORITY, Criticality::Ignore, &PagingPriority(priority))?;
+        }
+        let cells = cells.into_iter().map(|nr_cgi| PagingCellItem { nr_cgi }).collect();
+        paging.add_ie(pdu::ID_PAGING_CELL_LIST, Criticality::Ignore, &PagingCellList(cells))?;
+
+        debug!("F1AP Paging for {:?}", request.identity);
+        sender.send(NON_UE_STREAM, &paging).await
+    }
+}
+
+impl F1apDu {
+    /// Handle Paging from the CU
+    pub(super) async fn handle_paging(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let ue_id = pdu.ie::<UeIdentityIndexValue>(pdu::ID_UE_IDENTITY_INDEX_VALUE)?.0;
+        let identity = pdu.ie::<PagingIdentity>(pdu::ID_PAGING_IDENTITY)?;
+        let paging_drx = pdu.optional_ie::<PagingDrx>(pdu::ID_PAGING_DRX)?;
+        let priority = pdu.optional_ie::<PagingPriority>(pdu::ID_PAGING_PRIORITY)?;
+        let cells = pdu.ie::<PagingCellList>(pdu::ID_PAGING_CELL_LIST)?;
+
+        if !cells.0.iter().any(|cell| cell.nr_cgi == self.config.served_cell.nr_cgi) {
+            debug!("Paging for {:?} not for the served cell", identity);
+            return Ok(());
+        }
+
+        info!("Paging for {:?} (paging DRX {:?}, priority {:?})", identity, paging_drx, priority);
+        let identity = match identity {
+            PagingIdentity::FiveGSTmsi(s_tmsi) => PagingUeIdentity::NgSTmsi(s_tmsi),
+            PagingIdentity::IRnti(i_rnti) => PagingUeIdentity::FullIRnti(i_rnti),
+        };
+        self.mac.schedule_paging(PagingRequest {
+            identity,
+            ue_id,
+            paging_cycle: paging_drx.map(|drx| drx.0),
+            priority: priority.map(|priority| priority.0),
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::cu::ConnectedDu;
+    use crate::f1ap::transport::F1TransportEvent;
+    use crate::f1ap::F1apCuConfig;
+    use crate::ngap::pdu::NrCgi;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::test_support::{f1_association, f1ap_du_config, served_cell, RecordingMac};
+    use bytes::Bytes;
+    use std::sync::Arc;
+
+    fn request(paging_cycle: Option<u16>) -> PagingRequest {
+        PagingRequest { identity: PagingUeIdentity::FullIRnti(0x12_3456_789A), ue_id: 5, paging_cycle, priority: Some(1) }
+    }
+
+    #[tokio::test]
+    async fn test_paging_failures() {
+        let cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+
+        // No gNB-DU, then a gNB-DU without active cell
+        assert!(matches!(cu.send_paging(request(Some(64))).await, Err(LayerError::InvalidState(_))));
+        let ((cu_tx, _cu_rx), (_du_tx, mut du_rx)) = f1_association().await;
+        *cu.du.write().await = Some(ConnectedDu { gnb_du_id: 1, sender: Arc::new(cu_tx), cells: Vec::new() });
+        assert!(matches!(cu.send_paging(request(Some(64))).await, Err(LayerError::InvalidState(_))));
+
+        // A paging cycle Paging DRX cannot carry is left out
+        cu.du.write().await.as_mut().unwrap().cells.push(served_cell());
+        cu.send_paging(request(Some(512))).await.unwrap();
+        let F1TransportEvent::Data { payload, .. } = du_rx.recv().await else { panic!("F1-C association lost") };
+        let paging = F1apPdu::decode(&payload).unwrap();
+        assert_eq!(paging.optional_ie::<PagingDrx>(pdu::ID_PAGING_DRX).unwrap(), None);
+        assert_eq!(paging.ie::<PagingPriority>(pdu::ID_PAGING_PRIORITY).unwrap(), PagingPriority(1));
+
+        // DU: paging for another cell, without cell list, with an undecodable identity
+        let mac = Arc::new(RecordingMac::default());
+        let du = F1apDu::new(f1ap_du_config(cu.local_addr().unwrap()), mac.clone()).await.unwrap();
+        let other_cell = NrCgi { nr_cell_identity: 0x0000_0402, ..served_cell().nr_cgi };
+        let mut elsewhere = paging.clone();
+        elsewhere.ies.retain(|ie| ie.id != pdu::ID_PAGING_CELL_LIST);
+        elsewhere.add_ie(pdu::ID_PAGING_CELL_LIST, Criticality::Ignore,
+                         &PagingCellList(vec![PagingCellItem { nr_cgi: other_cell }])).unwrap();
+        du.handle_paging(&elsewhere).await.unwrap();
+        let mut no_cells = paging.clone();
+        no_cells.ies.retain(|ie| ie.id != pdu::ID_PAGING_CELL_LIST);
+        assert!(matches!(du.handle_paging(&no_cells).await, Err(LayerError::ProcessingError(_))));
+        let mut bad_identity = paging.clone();
+        let index = bad_identity.ies.iter().position(|ie| ie.id == pdu::ID_PAGING_IDENTITY).unwrap();
+        bad_identity.ies[index].value = Bytes::from_static(&[0x80]);
+        assert!(matches!(du.handle_paging(&bad_identity).await, Err(LayerError::InvalidPdu)));
+        assert!(mac.paging.lock().unwrap().is_empty());
+
+        du.handle_paging(&paging).await.unwrap();
+        let scheduled = mac.paging.lock().unwrap()[0].clone();
+        assert_eq!((scheduled.identity, scheduled.paging_cycle), (PagingUeIdentity::FullIRnti(0x12_3456_789A), None));
+    }
+}
@@ -0,0 +1,1127 @@
+//! F1AP PDU and Information Element encoding
+//!
+//! F1AP-PDU structure and protocol IE containers according to 3GPP TS 38.473
+//! section 9.4, encoded with APER. The container machinery and the IEs F1AP
+//! shares with NGAP (NR CGI, GTP tunnel, octet strings) come from the NGAP codec.
+
+use super::F1apProcedureCode;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{
+    ie_list, skip_ie_extensions, ApPdu, ApPduType, ApProcedureCode, AperCodec, Criticality, GtpTunnel, NrCgi,
+};
+use crate::rlc::RlcMode;
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::SNssai;
+
+/// Protocol IE identifiers (3GPP TS 38.473 section 9.4.7)
+pub const ID_CAUSE: u16 = 0;
+pub const ID_CELLS_TO_BE_ACTIVATED_LIST: u16 = 3;
+pub const ID_CELLS_TO_BE_ACTIVATED_LIST_ITEM: u16 = 4;
+pub const ID_CU_TO_DU_RRC_INFORMATION: u16 = 9;
+pub const ID_DRBS_FAILED_TO_BE_SETUP_ITEM: u16 = 14;
+pub const ID_DRBS_FAILED_TO_BE_SETUP_LIST: u16 = 15;
+pub const ID_DRBS_FAILED_TO_BE_SETUP_MOD_ITEM: u16 = 16;
+pub const ID_DRBS_FAILED_TO_BE_SETUP_MOD_LIST: u16 = 17;
+pub const ID_DRBS_SETUP_ITEM: u16 = 26;
+pub const ID_DRBS_SETUP_LIST: u16 = 27;
+pub const ID_DRBS_SETUP_MOD_ITEM: u16 = 28;
+pub const ID_DRBS_SETUP_MOD_LIST: u16 = 29;
+pub const ID_DRBS_TO_BE_RELEASED_ITEM: u16 = 32;
+pub const ID_DRBS_TO_BE_RELEASED_LIST: u16 = 33;
+pub const ID_DRBS_TO_BE_SETUP_ITEM: u16 = 34;
+pub const ID_DRBS_TO_BE_SETUP_LIST: u16 = 35;
+pub const ID_DRBS_TO_BE_SETUP_MOD_ITEM: u16 = 36;
+pub const ID_DRBS_TO_BE_SETUP_MOD_LIST: u16 = 37;
+pub const ID_DU_TO_CU_RRC_INFORMATION: u16 = 39;
+pub const ID_GNB_CU_UE_F1AP_ID: u16 = 40;
+pub const ID_GNB_DU_UE_F1AP_ID: u16 = 41;
+pub const ID_GNB_DU_ID: u16 = 42;
+pub const ID_GNB_DU_SERVED_CELLS_ITEM: u16 = 43;
+pub const ID_GNB_DU_SERVED_CELLS_LIST: u16 = 44;
+pub const ID_GNB_DU_NAME: u16 = 45;
+pub const ID_RRC_CONTAINER: u16 = 50;
+pub const ID_SP_CELL_ID: u16 = 63;
+pub const ID_SRB_ID: u16 = 64;
+pub const ID_TIME_TO_WAIT: u16 = 77;
+pub const ID_TRANSACTION_ID: u16 = 78;
+pub const ID_GNB_CU_NAME: u16 = 82;
+pub const ID_C_RNTI: u16 = 95;
+pub const ID_SERV_CELL_INDEX: u16 = 107;
+pub const ID_NR_CGI: u16 = 111;
+pub const ID_PAGING_CELL_ITEM: u16 = 112;
+pub const ID_PAGING_CELL_LIST: u16 = 113;
+pub const ID_PAGING_DRX: u16 = 114;
+pub const ID_PAGING_PRIORITY: u16 = 115;
+pub const ID_UE_IDENTITY_INDEX_VALUE: u16 = 117;
+pub const ID_PAGING_IDENTITY: u16 = 127;
+pub const ID_DU_TO_CU_RRC_CONTAINER: u16 = 128;
+pub const ID_GNB_CU_RRC_VERSION: u16 = 170;
+pub const ID_GNB_DU_RRC_VERSION: u16 = 171;
+
+/// maxCellingNBDU
+const MAX_CELLS_IN_GNB_DU: usize = 512;
+/// maxnoofBPLMNs
+const MAX_BPLMNS: usize = 6;
+/// maxnoofNrCellBands
+const MAX_NR_CELL_BANDS: usize = 32;
+/// maxnoofDRBs
+const MAX_DRBS: usize = 64;
+/// maxnoofQoSFlows
+const MAX_QOS_FLOWS: usize = 64;
+/// maxnoofULUPTNLInformation and maxnoofDLUPTNLInformation
+const MAX_UP_TNL_INFORMATION: usize = 2;
+/// maxnoofPagingCells
+const MAX_PAGING_CELLS: usize = 512;
+/// maxNRARFCN
+const MAX_NR_ARFCN: u64 = 3_279_165;
+
+impl ApProcedureCode for F1apProcedureCode {
+    const PROTOCOL: &'static str = "F1AP";
+    // initiatingMessage, successfulOutcome, unsuccessfulOutcome and choice-extension
+    const PDU_CHOICE: (usize, bool) = (4, false);
+
+    fn from_u8(code: u8) -> Option<Self> {
+        F1apProcedureCode::from_u8(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn criticality(&self) -> Criticality {
+        F1apProcedureCode::criticality(self)
+    }
+}
+
+/// F1AP-PDU choice
+pub type F1apPduType = ApPduType;
+
+/// F1AP PDU
+pub type F1apPdu = ApPdu<F1apProcedureCode>;
+
+impl F1apPdu {
+    /// Add the gNB-CU and gNB-DU UE F1AP IDs of a UE-associated message
+    pub fn with_ue_ids(self, cu_ue_id: u32, du_ue_id: u32) -> Result<Self, LayerError> {
+        self.with_ie(ID_GNB_CU_UE_F1AP_ID, Criticality::Reject, &GnbCuUeF1apId(cu_ue_id))?
+            .with_ie(ID_GNB_DU_UE_F1AP_ID, Criticality::Reject, &GnbDuUeF1apId(du_ue_id))
+    }
+}
+
+/// gNB-CU UE F1AP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbCuUeF1apId(pub u32);
+
+impl AperCodec for GnbCuUeF1apId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// gNB-DU UE F1AP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbDuUeF1apId(pub u32);
+
+impl AperCodec for GnbDuUeF1apId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// Transaction ID, INTEGER (0..255, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionId(pub u8);
+
+impl AperCodec for TransactionId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 255, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 255, true)? as u8))
+    }
+}
+
+/// gNB-DU ID, INTEGER (0..2^36-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnbDuId(pub u64);
+
+impl AperCodec for GnbDuId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0, 0, (1 << 36) - 1, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, (1 << 36) - 1, false)?))
+    }
+}
+
+/// SRB ID, INTEGER (0..3, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SrbId(pub u8);
+
+impl AperCodec for SrbId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 3, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 3, true)? as u8))
+    }
+}
+
+/// C-RNTI, INTEGER (0..65535, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CRnti(pub u16);
+
+impl AperCodec for CRnti {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 65535, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 65535, true)? as u16))
+    }
+}
+
+/// ServCellIndex, INTEGER (0..31, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServCellIndex(pub u8);
+
+impl AperCodec for ServCellIndex {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 31, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 31, true)? as u8))
+    }
+}
+
+/// RRC-Version (3GPP TS 38.473 section 9.3.1.70): latest supported release as
+/// a 3 bit string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RrcVersion(pub u8);
+
+impl AperCodec for RrcVersion {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bit_string(&[self.0 << 5], 3, 3, Some(3), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let (data, _) = dec.get_bit_string(3, Some(3), false)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self(data[0] >> 5))
+    }
+}
+
+/// Cause (3GPP TS 38.473 section 9.3.1.2)
+///
+/// Values are the ENUMERATED indices of the respective cause group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    RadioNetwork(u8),
+    Transport(u8),
+    Protocol(u8),
+    Misc(u8),
+}
+
+impl Cause {
+    /// Root values of the radioNetwork, transport, protocol and misc groups
+    const ROOT_COUNTS: [usize; 4] = [11, 2, 7, 5];
+
+    /// Radio network: unspecified
+    pub const RADIO_NETWORK_UNSPECIFIED: Cause = Cause::RadioNetwork(0);
+    /// Radio network: unknown or already allocated gNB-CU UE F1AP ID
+    pub const UNKNOWN_GNB_CU_UE_F1AP_ID: Cause = Cause::RadioNetwork(2);
+    /// Radio network: unknown or already allocated gNB-DU UE F1AP ID
+    pub const UNKNOWN_GNB_DU_UE_F1AP_ID: Cause = Cause::RadioNetwork(3);
+    /// Radio network: no radio resources available
+    pub const NO_RADIO_RESOURCES_AVAILABLE: Cause = Cause::RadioNetwork(8);
+    /// Radio network: normal release
+    pub const NORMAL_RELEASE: Cause = Cause::RadioNetwork(10);
+    /// Transport: transport resource unavailable
+    pub const TRANSPORT_RESOURCE_UNAVAILABLE: Cause = Cause::Transport(1);
+    /// Misc: control processing overload
+    pub const CONTROL_PROCESSING_OVERLOAD: Cause = Cause::Misc(0);
+    /// Misc: unspecified
+    pub const MISC_UNSPECIFIED: Cause = Cause::Misc(4);
+
+    fn group(&self) -> (usize, u8) {
+        match *self {
+            Cause::RadioNetwork(value) => (0, value),
+            Cause::Transport(value) => (1, value),
+            Cause::Protocol(value) => (2, value),
+            Cause::Misc(value) => (3, value),
+        }
+    }
+}
+
+impl AperCodec for Cause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let (group, value) = self.group();
+        enc.put_choice(group, 5, false)?;
+        enc.put_enumerated(value as usize, Self::ROOT_COUNTS[group], true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let group = dec.get_choice(5, false)?;
+        if group >= Self::ROOT_COUNTS.len() {
+            return Err(LayerError::ProcessingError("Unknown cause group".into()));
+        }
+        let value = dec.get_enumerated(Self::ROOT_COUNTS[group], true)? as u8;
+        Ok(match group {
+            0 => Cause::RadioNetwork(value),
+            1 => Cause::Transport(value),
+            2 => Cause::Protocol(value),
+            _ => Cause::Misc(value),
+        })
+    }
+}
+
+/// NR transmission bandwidths, NRNRB (3GPP TS 38.473 section 9.3.1.32)
+const NR_NRB_VALUES: [u16; 29] = [
+    11, 18, 24, 25, 31, 32, 38, 51, 52, 65, 66, 78, 79, 93, 106, 107, 121, 132, 133, 135, 160, 162, 189, 216,
+    217, 245, 264, 270, 273,
+];
+
+/// FDD carrier of a served cell: NR-Mode-Info fDD with one frequency band per
+/// direction and no SUL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FddInfo {
+    /// UL NR-ARFCN
+    pub ul_arfcn: u32,
+    /// DL NR-ARFCN
+    pub dl_arfcn: u32,
+    /// NR operating band
+    pub band: u16,
+    /// Subcarrier spacing in kHz
+    pub scs_khz: u32,
+    /// Transmission bandwidth in resource blocks
+    pub nrb: u16,
+}
+
+impl FddInfo {
+    fn encode_freq_info(&self, enc: &mut AperEncoder, arfcn: u32) -> Result<(), LayerError> {
+        // NRFreqInfo: extension, sul_Information and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_constrained_whole_number(arfcn as u64, 0, MAX_NR_ARFCN)?;
+        // freqBandListNr with a single FreqBandNrItem and no SUL bands
+        enc.put_length(1, 1, Some(MAX_NR_CELL_BANDS))?;
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.band as u64, 1, 1024, true)?;
+        enc.put_length(0, 0, Some(MAX_NR_CELL_BANDS))
+    }
+
+    fn decode_freq_info(dec: &mut AperDecoder) -> Result<(u32, u16), LayerError> {
+        dec.get_bool()?;
+        let sul = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let arfcn = dec.get_constrained_whole_number(0, MAX_NR_ARFCN)? as u32;
+        if sul {
+            return Err(LayerError::ProcessingError("SUL carriers are not supported".into()));
+        }
+        let bands = dec.get_length(1, Some(MAX_NR_CELL_BANDS))?;
+        let mut band = 0;
+        for i in 0..bands {
+            dec.get_bool()?;
+            let band_extensions = dec.get_bool()?;
+            let indicator = dec.get_integer(1, 1024, true)? as u16;
+            if i == 0 {
+                band = indicator;
+            }
+            let sul_bands = dec.get_length(0, Some(MAX_NR_CELL_BANDS))?;
+            for _ in 0..sul_bands {
+                // SupportedSULFreqBandItem: extension, iE-Extensions, band indicator
+                dec.get_bool()?;
+                let sul_extensions = dec.get_bool()?;
+                dec.get_integer(1, 1024, true)?;
+                skip_ie_extensions(dec, sul_extensions)?;
+            }
+            skip_ie_extensions(dec, band_extensions)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok((arfcn, band))
+    }
+
+    fn encode_bandwidth(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let scs = match self.scs_khz {
+            15 => 0,
+            30 => 1,
+            60 => 2,
+            120 => 3,
+            other => return Err(LayerError::InvalidConfiguration(format!("Invalid subcarrier spacing {} kHz", other))),
+        };
+        let nrb = NR_NRB_VALUES.iter().position(|nrb| *nrb == self.nrb)
+            .ok_or_else(|| LayerError::InvalidConfiguration(format!("Invalid transmission bandwidth {} RBs", self.nrb)))?;
+        // Transmission-Bandwidth: extension, iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_enumerated(scs, 4, true)?;
+        enc.put_enumerated(nrb, NR_NRB_VALUES.len(), true)
+    }
+
+    fn decode_bandwidth(dec: &mut AperDecoder) -> Result<(u32, u16), LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let scs = dec.get_enumerated(4, true)?;
+        let nrb = dec.get_enumerated(NR_NRB_VALUES.len(), true)?;
+        skip_ie_extensions(dec, extensions)?;
+        let scs_khz = [15, 30, 60, 120].get(scs).copied().ok_or(LayerError::InvalidPdu)?;
+        let nrb = NR_NRB_VALUES.get(nrb).copied().ok_or(LayerError::InvalidPdu)?;
+        Ok((scs_khz, nrb))
+    }
+}
+
+impl AperCodec for FddInfo {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // NR-Mode-Info: fDD
+        enc.put_choice(0, 3, false)?;
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.encode_freq_info(enc, self.ul_arfcn)?;
+        self.encode_freq_info(enc, self.dl_arfcn)?;
+        self.encode_bandwidth(enc)?;
+        self.encode_bandwidth(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(3, false)? != 0 {
+            return Err(LayerError::ProcessingError("Only FDD cells are supported".into()));
+        }
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let (ul_arfcn, band) = Self::decode_freq_info(dec)?;
+        let (dl_arfcn, _) = Self::decode_freq_info(dec)?;
+        Self::decode_bandwidth(dec)?;
+        let (scs_khz, nrb) = Self::decode_bandwidth(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { ul_arfcn, dl_arfcn, band, scs_khz, nrb })
+    }
+}
+
+/// Served Cell Information (3GPP TS 38.473 section 9.3.1.10)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedCellInformation {
+    /// NR CGI of the cell
+    pub nr_cgi: NrCgi,
+    /// Physical cell identity
+    pub nr_pci: u16,
+    /// 5GS tracking area code (24 bits)
+    pub tac: Option<u32>,
+    /// Served PLMNs
+    pub served_plmns: Vec<[u8; 3]>,
+    /// FDD carrier
+    pub fdd_info: FddInfo,
+    /// MeasurementTimingConfiguration of TS 38.331
+    pub measurement_timing_configuration: Bytes,
+}
+
+impl AperCodec for ServedCellInformation {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, then fiveGS-TAC, configured-EPS-TAC and iE-Extensions presence
+        enc.put_bool(false);
+        enc.put_bool(self.tac.is_some());
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.nr_cgi.encode(enc)?;
+        enc.put_constrained_whole_number(self.nr_pci as u64, 0, 1007)?;
+        if let Some(tac) = self.tac {
+            enc.put_octet_string(&tac.to_be_bytes()[1..], 3, Some(3), false)?;
+        }
+        enc.put_length(self.served_plmns.len(), 1, Some(MAX_BPLMNS))?;
+        for plmn_id in &self.served_plmns {
+            // ServedPLMNs-Item: extension, iE-Extensions absent
+            enc.put_bool(false);
+            enc.put_bool(false);
+            plmn_id.encode(enc)?;
+        }
+        self.fdd_info.encode(enc)?;
+        enc.put_octet_string(&self.measurement_timing_configuration, 0, None, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_tac = dec.get_bool()?;
+        let has_eps_tac = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let nr_cgi = NrCgi::decode(dec)?;
+        let nr_pci = dec.get_constrained_whole_number(0, 1007)? as u16;
+        let tac = if has_tac {
+            let tac = dec.get_octet_string(3, Some(3), false)?;
+            Some(u32::from_be_bytes([0, tac[0], tac[1], tac[2]]))
+        } else {
+            None
+        };
+        if has_eps_tac {
+            dec.get_octet_string(2, Some(2), false)?;
+        }
+        let count = dec.get_length(1, Some(MAX_BPLMNS))?;
+        let mut served_plmns = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let plmn_extensions = dec.get_bool()?;
+            served_plmns.push(<[u8; 3]>::decode(dec)?);
+            skip_ie_extensions(dec, plmn_extensions)?;
+        }
+        let fdd_info = FddInfo::decode(dec)?;
+        let measurement_timing_configuration = Bytes::from(dec.get_octet_string(0, None, false)?);
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            nr_cgi,
+            nr_pci,
+            tac,
+            served_plmns,
+            fdd_info,
+            measurement_timing_configuration,
+        })
+    }
+}
+
+/// GNB-DU-Served-Cells-Item, without gNB-DU System Information
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GnbDuServedCellsItem {
+    /// Served cell
+    pub served_cell_information: ServedCellInformation,
+}
+
+impl AperCodec for GnbDuServedCellsItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, gNB-DU-System-Information and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.served_cell_information.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let system_information = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let served_cell_information = ServedCellInformation::decode(dec)?;
+        if system_information {
+            // GNB-DU-System-Information: MIB, SIB1 and iE-Extensions
+            dec.get_bool()?;
+            let si_extensions = dec.get_bool()?;
+            dec.get_octet_string(0, None, false)?;
+            dec.get_octet_string(0, None, false)?;
+            skip_ie_extensions(dec, si_extensions)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { served_cell_information })
+    }
+}
+
+ie_list!(
+    /// gNB-DU Served Cells List
+    GnbDuServedCellsList, GnbDuServedCellsItem, ID_GNB_DU_SERVED_CELLS_ITEM, Criticality::Reject, MAX_CELLS_IN_GNB_DU
+);
+
+/// Cells to be Activated List Item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellsToBeActivatedItem {
+    /// NR CGI of the cell
+    pub nr_cgi: NrCgi,
+    /// Physical cell identity
+    pub nr_pci: Option<u16>,
+}
+
+impl AperCodec for CellsToBeActivatedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.nr_pci.is_some());
+        enc.put_bool(false);
+        self.nr_cgi.encode(enc)?;
+        if let Some(nr_pci) = self.nr_pci {
+            enc.put_constrained_whole_number(nr_pci as u64, 0, 1007)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_pci = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let nr_cgi = NrCgi::decode(dec)?;
+        let nr_pci = if has_pci {
+            Some(dec.get_constrained_whole_number(0, 1007)? as u16)
+        } else {
+            None
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { nr_cgi, nr_pci })
+    }
+}
+
+ie_list!(
+    /// Cells to be Activated List
+    CellsToBeActivatedList, CellsToBeActivatedItem, ID_CELLS_TO_BE_ACTIVATED_LIST_ITEM, Criticality::Reject,
+    MAX_CELLS_IN_GNB_DU
+);
+
+/// DU to CU RRC Information (3GPP TS 38.473 section 9.3.1.26)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuToCuRrcInformation {
+    /// CellGroupConfig of TS 38.331
+    pub cell_group_config: Bytes,
+}
+
+impl AperCodec for DuToCuRrcInformation {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, measGapConfig, requestedP-MaxFR1 and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 3);
+        enc.put_octet_string(&self.cell_group_config, 0, None, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let meas_gap_config = dec.get_bool()?;
+        let requested_p_max = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let cell_group_config = Bytes::from(dec.get_octet_string(0, None, false)?);
+        if meas_gap_config {
+            dec.get_octet_string(0, None, false)?;
+        }
+        if requested_p_max {
+            dec.get_octet_string(0, None, false)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { cell_group_config })
+    }
+}
+
+/// CU to DU RRC Information (3GPP TS 38.473 section 9.3.1.25)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CuToDuRrcInformation {
+    /// UE-CapabilityRAT-ContainerList of TS 38.331
+    pub ue_capability_rat_container_list: Option<Bytes>,
+}
+
+impl AperCodec for CuToDuRrcInformation {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, then cG-ConfigInfo, uE-CapabilityRAT-ContainerList, measConfig
+        // and iE-Extensions presence
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bool(self.ue_capability_rat_container_list.is_some());
+        enc.put_bool(false);
+        enc.put_bool(false);
+        if let Some(list) = &self.ue_capability_rat_container_list {
+            enc.put_octet_string(list, 0, None, false)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let cg_config_info = dec.get_bool()?;
+        let has_capabilities = dec.get_bool()?;
+        let meas_config = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if cg_config_info {
+            dec.get_octet_string(0, None, false)?;
+        }
+        let ue_capability_rat_container_list = if has_capabilities {
+            Some(Bytes::from(dec.get_octet_string(0, None, false)?))
+        } else {
+            None
+        };
+        if meas_config {
+            dec.get_octet_string(0, None, false)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { ue_capability_rat_container_list })
+    }
+}
+
+/// UP Transport Layer Information list item (UL and DL UP TNL Information to be
+/// setup), SEQUENCE (SIZE(1..2)) OF a single GTP tunnel
+fn encode_up_tnl_list(enc: &mut AperEncoder, tunnels: &[GtpTunnel]) -> Result<(), LayerError> {
+    enc.put_length(tunnels.len(), 1, Some(MAX_UP_TNL_INFORMATION))?;
+    for tunnel in tunnels {
+        // ULUPTNLInformation-ToBeSetup-Item / DLUPTNLInformation-ToBeSetup-Item
+        enc.put_bool(false);
+        enc.put_bool(false);
+        tunnel.encode(enc)?;
+    }
+    Ok(())
+}
+
+fn decode_up_tnl_list(dec: &mut AperDecoder) -> Result<Vec<GtpTunnel>, LayerError> {
+    let count = dec.get_length(1, Some(MAX_UP_TNL_INFORMATION))?;
+    let mut tunnels = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        tunnels.push(GtpTunnel::decode(dec)?);
+        skip_ie_extensions(dec, extensions)?;
+    }
+    Ok(tunnels)
+}
+
+impl AperCodec for RlcMode {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let index = match self {
+            RlcMode::Am => 0,
+            RlcMode::Um => 1,
+            RlcMode::Tm => return Err(LayerError::InvalidConfiguration("DRBs cannot use RLC TM".into())),
+        };
+        enc.put_enumerated(index, 4, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(4, true)? {
+            0 => Ok(RlcMode::Am),
+            // Bidirectional and unidirectional UM
+            1..=3 => Ok(RlcMode::Um),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// DRBs to be Setup Item (3GPP TS 38.473 section 9.2.2.1), also used for DRBs
+/// to be Setup Mod
+///
+/// The QoS Information is reduced to the S-NSSAI of the DRB, when known, and the
+/// QoS flows mapped to it; the flow level QoS parameters stay in the CU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrbToBeSetupItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Slice of the PDU session carried by the DRB
+    pub s_nssai: Option<SNssai>,
+    /// QoS flows mapped to the DRB
+    pub qos_flows: Vec<u8>,
+    /// UL F1-U tunnel of the CU
+    pub ul_tunnel: GtpTunnel,
+    /// RLC mode of the DRB
+    pub rlc_mode: RlcMode,
+}
+
+impl AperCodec for DrbToBeSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, then uLConfiguration, duplicationActivation and iE-Extensions presence
+        enc.put_bool(false);
+        enc.put_bits(0, 3);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        enc.put_bool(self.s_nssai.is_some());
+        if let Some(s_nssai) = &self.s_nssai {
+            s_nssai.encode(enc)?;
+        }
+        enc.put_length(self.qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+        for qfi in &self.qos_flows {
+            enc.put_integer(*qfi as u64, 0, 63, true)?;
+        }
+        encode_up_tnl_list(enc, std::slice::from_ref(&self.ul_tunnel))?;
+        self.rlc_mode.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let ul_configuration = dec.get_bool()?;
+        let duplication_activation = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let s_nssai = if dec.get_bool()? { Some(SNssai::decode(dec)?) } else { None };
+        let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+        let qos_flows = (0..count)
+            .map(|_| dec.get_integer(0, 63, true).map(|qfi| qfi as u8))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ul_tunnel = decode_up_tnl_list(dec)?[0];
+        let rlc_mode = RlcMode::decode(dec)?;
+        if ul_configuration {
+            // ULConfiguration: extension, iE-Extensions, uLUEConfiguration
+            dec.get_bool()?;
+            let ul_extensions = dec.get_bool()?;
+            dec.get_enumerated(3, true)?;
+            skip_ie_extensions(dec, ul_extensions)?;
+        }
+        if duplication_activation {
+            dec.get_enumerated(2, true)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, s_nssai, qos_flows, ul_tunnel, rlc_mode })
+    }
+}
+
+ie_list!(
+    /// DRBs to be Setup List
+    DrbsToBeSetupList, DrbToBeSetupItem, ID_DRBS_TO_BE_SETUP_ITEM, Criticality::Reject, MAX_DRBS
+);
+
+ie_list!(
+    /// DRBs to be Setup Mod List
+    DrbsToBeSetupModList, DrbToBeSetupItem, ID_DRBS_TO_BE_SETUP_MOD_ITEM, Criticality::Reject, MAX_DRBS
+);
+
+/// DRBs Setup Item, also used for DRBs Setup Mod
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbSetupItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Logical channel of the DRB
+    pub lcid: Option<u8>,
+    /// DL F1-U tunnel of the DU
+    pub dl_tunnel: GtpTunnel,
+}
+
+impl AperCodec for DrbSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.lcid.is_some());
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        if let Some(lcid) = self.lcid {
+            enc.put_integer(lcid as u64, 1, 32, true)?;
+        }
+        encode_up_tnl_list(enc, std::slice::from_ref(&self.dl_tunnel))
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_lcid = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let lcid = if has_lcid {
+            Some(dec.get_integer(1, 32, true)? as u8)
+        } else {
+            None
+        };
+        let dl_tunnel = decode_up_tnl_list(dec)?[0];
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, lcid, dl_tunnel })
+    }
+}
+
+ie_list!(
+    /// DRBs Setup List
+    DrbsSetupList, DrbSetupItem, ID_DRBS_SETUP_ITEM, Criticality::Ignore, MAX_DRBS
+);
+
+ie_list!(
+    /// DRBs Setup Mod List
+    DrbsSetupModList, DrbSetupItem, ID_DRBS_SETUP_MOD_ITEM, Criticality::Ignore, MAX_DRBS
+);
+
+/// DRBs Failed to be Setup Item, also used for DRBs Failed to be Setup Mod
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbFailedToBeSetupItem {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Failure cause
+    pub cause: Option<Cause>,
+}
+
+impl AperCodec for DrbFailedToBeSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.cause.is_some());
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        if let Some(cause) = &self.cause {
+            cause.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_cause = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let cause = if has_cause { Some(Cause::decode(dec)?) } else { None };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, cause })
+    }
+}
+
+ie_list!(
+    /// DRBs Failed to be Setup List
+    DrbsFailedToBeSetupList, DrbFailedToBeSetupItem, ID_DRBS_FAILED_TO_BE_SETUP_ITEM, Criticality::Ignore, MAX_DRBS
+);
+
+ie_list!(
+    /// DRBs Failed to be Setup Mod List
+    DrbsFailedToBeSetupModList, DrbFailedToBeSetupItem, ID_DRBS_FAILED_TO_BE_SETUP_MOD_ITEM, Criticality::Ignore,
+    MAX_DRBS
+);
+
+/// DRBs to be Released Item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbToBeReleasedItem {
+    /// DRB identity
+    pub drb_id: u8,
+}
+
+impl AperCodec for DrbToBeReleasedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id })
+    }
+}
+
+ie_list!(
+    /// DRBs to be Released List
+    DrbsToBeReleasedList, DrbToBeReleasedItem, ID_DRBS_TO_BE_RELEASED_ITEM, Criticality::Reject, MAX_DRBS
+);
+
+/// UE Identity Index Value: indexLength10, UE_ID of TS 38.304
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UeIdentityIndexValue(pub u16);
+
+impl AperCodec for UeIdentityIndexValue {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_choice(0, 2, false)?;
+        enc.put_bit_string(&((self.0 & 0x3FF) << 6).to_be_bytes(), 10, 10, Some(10), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(2, false)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let (data, _) = dec.get_bit_string(10, Some(10), false)?;
+        Ok(Self(u16::from_be_bytes([data[0], data[1]]) >> 6))
+    }
+}
+
+/// Paging Identity (3GPP TS 38.473 section 9.3.1.93)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagingIdentity {
+    /// RAN UE paging identity: fullI-RNTI (40 bits)
+    IRnti(u64),
+    /// CN UE paging identity: 5G-S-TMSI (48 bits)
+    FiveGSTmsi(u64),
+}
+
+impl AperCodec for PagingIdentity {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match *self {
+            PagingIdentity::IRnti(i_rnti) => {
+                enc.put_choice(0, 3, false)?;
+                enc.put_bool(false);
+                enc.put_bool(false);
+                enc.put_bit_string(&i_rnti.to_be_bytes()[3..], 40, 40, Some(40), false)
+            }
+            PagingIdentity::FiveGSTmsi(s_tmsi) => {
+                enc.put_choice(1, 3, false)?;
+                enc.put_choice(0, 2, false)?;
+                enc.put_bit_string(&s_tmsi.to_be_bytes()[2..], 48, 48, Some(48), false)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let to_u64 = |data: &[u8]| data.iter().fold(0u64, |value, octet| (value << 8) | *octet as u64);
+        match dec.get_choice(3, false)? {
+            0 => {
+                dec.get_bool()?;
+                let extensions = dec.get_bool()?;
+                let (data, _) = dec.get_bit_string(40, Some(40), false)?;
+                skip_ie_extensions(dec, extensions)?;
+                Ok(PagingIdentity::IRnti(to_u64(&data)))
+            }
+            1 => {
+                if dec.get_choice(2, false)? != 0 {
+                    return Err(LayerError::InvalidPdu);
+                }
+                let (data, _) = dec.get_bit_string(48, Some(48), false)?;
+                Ok(PagingIdentity::FiveGSTmsi(to_u64(&data)))
+            }
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// Paging DRX in radio frames, ENUMERATED {v32, v64, v128, v256, ...}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingDrx(pub u16);
+
+impl AperCodec for PagingDrx {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let index = [32, 64, 128, 256].iter().position(|frames| *frames == self.0)
+            .ok_or_else(|| LayerError::InvalidConfiguration(format!("Invalid paging DRX {}", self.0)))?;
+        enc.put_enumerated(index, 4, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let index = dec.get_enumerated(4, true)?;
+        [32, 64, 128, 256].get(index).map(|frames| Self(*frames)).ok_or(LayerError::InvalidPdu)
+    }
+}
+
+/// Paging Priority 1 (highest) to 8, ENUMERATED {priolevel1, ..., priolevel8, ...}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingPriority(pub u8);
+
+impl AperCodec for PagingPriority {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(self.0.clamp(1, 8) as usize - 1, 8, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_enumerated(8, true)? as u8 + 1))
+    }
+}
+
+/// Paging Cell Item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingCellItem {
+    /// NR CGI of the cell
+    pub nr_cgi: NrCgi,
+}
+
+impl AperCodec for PagingCellItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.nr_cgi.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let nr_cgi = NrCgi::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { nr_cgi })
+    }
+}
+
+ie_list!(
+    /// Paging Cell List
+    PagingCellList, PagingCellItem, ID_PAGING_CELL_ITEM, Criticality::Ignore, MAX_PAGING_CELLS
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::NodeName;
+    use crate::test_support::{decode_ie, encode_ie};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_f1ap_messages() {
+        let nr_cgi = NrCgi { plmn_id: [0x00, 0xF1, 0x10], nr_cell_identity: 0x0000_0401 };
+        let served_cell = ServedCellInformation {
+            nr_cgi,
+            nr_pci: 1,
+            tac: Some(7),
+            served_plmns: vec![[0x00, 0xF1, 0x10]],
+            fdd_info: FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 15, nrb: 52 },
+            measurement_timing_configuration: Bytes::from_static(&[0x10]),
+        };
+        let request = F1apPdu::initiating(F1apProcedureCode::F1Setup)
+            .with_ie(ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(3)).unwrap()
+            .with_ie(ID_GNB_DU_ID, Criticality::Reject, &GnbDuId(0x9_0000_0001)).unwrap()
+            .with_ie(ID_GNB_DU_SERVED_CELLS_LIST, Criticality::Reject, &GnbDuServedCellsList(vec![
+                GnbDuServedCellsItem { served_cell_information: served_cell.clone() },
+            ])).unwrap()
+            .with_ie(ID_GNB_DU_RRC_VERSION, Criticality::Reject, &RrcVersion(1)).unwrap();
+        let encoded = request.encode().unwrap();
+        // initiatingMessage, procedure code 1, criticality reject
+        assert_eq!(&encoded[..3], &[0x00, 0x01, 0x00]);
+        let decoded = F1apPdu::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.procedure(), Some(F1apProcedureCode::F1Setup));
+        assert_eq!(decoded.ie::<GnbDuId>(ID_GNB_DU_ID).unwrap(), GnbDuId(0x9_0000_0001));
+        let cells: GnbDuServedCellsList = decoded.ie(ID_GNB_DU_SERVED_CELLS_LIST).unwrap();
+        assert_eq!(cells.0[0].served_cell_information, served_cell);
+        assert_eq!(decoded.ie::<RrcVersion>(ID_GNB_DU_RRC_VERSION).unwrap(), RrcVersion(1));
+        assert!(decoded.optional_ie::<NodeName>(ID_GNB_DU_NAME).unwrap().is_none());
+
+        // UE Context Setup with a DRB and its F1-U tunnels
+        let ul_tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), teid: 0x100 };
+        let dl_tunnel = GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), teid: 0x200 };
+        let drbs = DrbsToBeSetupList(vec![DrbToBeSetupItem {
+            drb_id: 1, s_nssai: Some(SNssai { sst: 1, sd: Some(0x000102) }), qos_flows: vec![1, 5], ul_tunnel, rlc_mode: RlcMode::Am,
+        }]);
+        let setup = F1apPdu::initiating(F1apProcedureCode::UeContextSetup)
+            .with_ie(ID_GNB_CU_UE_F1AP_ID, Criticality::Reject, &GnbCuUeF1apId(7)).unwrap()
+            .with_ie(ID_SP_CELL_ID, Criticality::Reject, &nr_cgi).unwrap()
+            .with_ie(ID_SERV_CELL_INDEX, Criticality::Reject, &ServCellIndex(0)).unwrap()
+            .with_ie(ID_CU_TO_DU_RRC_INFORMATION, Criticality::Reject, &CuToDuRrcInformation::default()).unwrap()
+            .with_ie(ID_DRBS_TO_BE_SETUP_LIST, Criticality::Reject, &drbs).unwrap();
+        let decoded = F1apPdu::decode(&setup.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<DrbsToBeSetupList>(ID_DRBS_TO_BE_SETUP_LIST).unwrap(), drbs);
+        let response = DrbsSetupList(vec![DrbSetupItem { drb_id: 1, lcid: Some(4), dl_tunnel }]);
+        let failed = DrbsFailedToBeSetupList(vec![DrbFailedToBeSetupItem { drb_id: 2, cause: Some(Cause::NO_RADIO_RESOURCES_AVAILABLE) }]);
+        let pdu = F1apPdu::successful(F1apProcedureCode::UeContextSetup)
+            .with_ie(ID_DRBS_SETUP_LIST, Criticality::Ignore, &response).unwrap()
+            .with_ie(ID_DRBS_FAILED_TO_BE_SETUP_LIST, Criticality::Ignore, &failed).unwrap()
+            .with_ie(ID_DU_TO_CU_RRC_INFORMATION, Criticality::Reject,
+                     &DuToCuRrcInformation { cell_group_config: Bytes::from_static(&[0x10, 0x00, 0x01]) }).unwrap();
+        let decoded = F1apPdu::decode(&pdu.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<DrbsSetupList>(ID_DRBS_SETUP_LIST).unwrap(), response);
+        assert_eq!(decoded.ie::<DrbsFailedToBeSetupList>(ID_DRBS_FAILED_TO_BE_SETUP_LIST).unwrap(), failed);
+
+        // Paging identities, cause and the UE identity index
+        for identity in [PagingIdentity::IRnti(0x12_3456_789A), PagingIdentity::FiveGSTmsi(0x0042_C000_0405)] {
+            let pdu = F1apPdu::initiating(F1apProcedureCode::Paging)
+                .with_ie(ID_PAGING_IDENTITY, Criticality::Reject, &identity).unwrap()
+                .with_ie(ID_UE_IDENTITY_INDEX_VALUE, Criticality::Reject, &UeIdentityIndexValue(1000)).unwrap()
+                .with_ie(ID_CAUSE, Criticality::Ignore, &Cause::NORMAL_RELEASE).unwrap();
+            let decoded = F1apPdu::decode(&pdu.encode().unwrap()).unwrap();
+            assert_eq!(decoded.ie::<PagingIdentity>(ID_PAGING_IDENTITY).unwrap(), identity);
+            assert_eq!(decoded.ie::<UeIdentityIndexValue>(ID_UE_IDENTITY_INDEX_VALUE).unwrap(), UeIdentityIndexValue(1000));
+            assert_eq!(decoded.ie::<Cause>(ID_CAUSE).unwrap(), Cause::NORMAL_RELEASE);
+        }
+    }
+
+    #[test]
+    fn test_f1ap_decode_errors() {
+        // F1AP-PDU choice-extension, procedure codes F1AP does not define
+        assert!(matches!(F1apPdu::decode(&[0xC0, 0x00, 0x00, 0x00]), Err(LayerError::ProcessingError(_))));
+        let mut setup = F1apPdu::initiating(F1apProcedureCode::F1Setup)
+            .with_ie(ID_GNB_DU_ID, Criticality::Reject, &GnbDuId(1)).unwrap()
+            .encode().unwrap().to_vec();
+        setup[1] = 0x30;
+        let decoded = F1apPdu::decode(&setup).unwrap();
+        assert_eq!(decoded.procedure(), None);
+        assert_eq!(decoded.ie::<GnbDuId>(ID_GNB_DU_ID).unwrap(), GnbDuId(1));
+
+        // IE values outside their CHOICE or ENUMERATED root
+        assert!(matches!(decode_ie::<Cause>(&[0x80]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<FddInfo>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(decode_ie::<RlcMode>(&[0x81]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<UeIdentityIndexValue>(&[0x80, 0x00, 0x00]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<PagingIdentity>(&[0x80]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<PagingIdentity>(&[0x60]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_ie::<PagingDrx>(&[0x81]), Err(LayerError::InvalidPdu)));
+        assert!(decode_ie::<PagingIdentity>(&[0x00, 0x12]).is_err());
+
+        // Values F1AP cannot encode
+        let fdd_info = FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 45, nrb: 52 };
+        assert!(matches!(encode_ie(&fdd_info), Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(encode_ie(&FddInfo { scs_khz: 15, nrb: 50, ..fdd_info }), Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(encode_ie(&RlcMode::Tm), Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(encode_ie(&PagingDrx(48)), Err(LayerError::InvalidConfiguration(_))));
+
+        // Out of range paging priority is clamped
+        let priority = encode_ie(&PagingPriority(0)).unwrap();
+        assert_eq!(decode_ie::<PagingPriority>(&priority).unwrap(), PagingPriority(1));
+    }
+}
@@ -0,0 +1,215 @@
+//! RRC Message Transfer (3GPP TS 38.473 section 8.4)
+//!
+//! Carries RRC messages between the UE-facing DU and RRC in the CU: CCCH
+//! messages in Initial UL RRC Message Transfer, which also creates the F1 UE
+//! context, and SRB messages in DL and UL RRC Message Transfer.
+
+use super::cu::{CuUeContext, F1apCu};
+use super::du::{DuUeContext, F1apDu};
+use super::pdu::{self, CRnti, F1apPdu, GnbCuUeF1apId, GnbDuUeF1apId, SrbId, TransactionId};
+use super::transport::UE_STREAM;
+use super::F1apProcedureCode;
+use crate::ngap::pdu::{Criticality, NrCgi};
+use crate::rrc::RrcMessageType;
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::Rnti;
+use std::sync::atomic::Ordering;
+use tracing::{debug, info};
+
+impl F1apCu {
+    /// Handle Initial UL RRC Message Transfer: create the UE context and pass
+    /// the CCCH message to RRC under the C-RNTI given by the DU
+    pub(super) async fn handle_initial_ul_rrc_message_transfer(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let du_ue_id = pdu.ie::<GnbDuUeF1apId>(pdu::ID_GNB_DU_UE_F1AP_ID)?.0;
+        let nr_cgi = pdu.ie::<NrCgi>(pdu::ID_NR_CGI)?;
+        let rnti = Rnti(pdu.ie::<CRnti>(pdu::ID_C_RNTI)?.0);
+        let rrc_container = pdu.ie::<Bytes>(pdu::ID_RRC_CONTAINER)?;
+        let cell_group_config = pdu.optional_ie::<Bytes>(pdu::ID_DU_TO_CU_RRC_CONTAINER)?;
+
+        let cu_ue_id = self.next_cu_ue_id.fetch_add(1, Ordering::Relaxed);
+        info!("Initial UL RRC Message Transfer for RNTI {} (gNB-DU UE F1AP ID {}, gNB-CU UE F1AP ID {})",
+              rnti.0, du_ue_id, cu_ue_id);
+        if let Some(cell_group_config) = &cell_group_config {
+            debug!("CellGroupConfig of RNTI {}: {} bytes", rnti.0, cell_group_config.len());
+        }
+        let previous = self.ue_contexts.lock().await.insert(rnti.0, CuUeContext {
+            cu_ue_id,
+            du_ue_id,
+            nr_cgi,
+            context_setup: false,
+        });
+        if previous.is_some() {
+            // The DU reused the C-RNTI, the old UE is gone
+            self.f1u.remove_bearers(rnti, None).await;
+        }
+
+        self.send_to_rrc(rnti, rrc_container).await;
+        Ok(())
+    }
+
+    /// Handle UL RRC Message Transfer
+    pub(super) async fn handle_ul_rrc_message_transfer(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let cu_ue_id = pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0;
+        let srb_id = pdu.ie::<SrbId>(pdu::ID_SRB_ID)?.0;
+        let rrc_container = pdu.ie::<Bytes>(pdu::ID_RRC_CONTAINER)?;
+        let rnti = self.rnti_of(cu_ue_id).await?;
+
+        debug!("UL RRC Message Transfer on SRB{} for RNTI {}: {} bytes", srb_id, rnti.0, rrc_container.len());
+        self.send_to_rrc(rnti, rrc_container).await;
+        Ok(())
+    }
+
+    /// Send an RRC message to a UE on an SRB in DL RRC Message Transfer
+    pub(super) async fn send_dl_rrc_message_transfer(&self, rnti: Rnti, srb_id: u8, data: Bytes) -> Result<(), LayerError> {
+        let ctx = self.ue_context(rnti).await?;
+        let transfer = F1apPdu::initiating(F1apProcedureCode::DlRrcMessageTransfer)
+            .with_ue_ids(ctx.cu_ue_id, ctx.du_ue_id)?
+            .with_ie(pdu::ID_SRB_ID, Criticality::Reject, &SrbId(srb_id))?
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &data)?;
+        debug!("DL RRC Message Transfer on SRB{} for RNTI {}: {} bytes", srb_id, rnti.0, data.len());
+        self.du_sender().await?.send(UE_STREAM, &transfer).await
+    }
+}
+
+impl F1apDu {
+    /// Pass an uplink RRC message received by the MAC to the gNB-CU
+    ///
+    /// CCCH messages start a new F1 UE context in Initial UL RRC Message
+    /// Transfer, all other messages go on SRB1 of the existing context.
+    pub async fn handle_uplink_rrc_message(&self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let ccch = matches!(RrcMessageType::from_pdu(&data),
+            Some(RrcMessageType::RrcSetupRequest | RrcMessageType::RrcReestablishmentRequest |
+                 RrcMessageType::RrcResumeRequest | RrcMessageType::RrcResumeRequest1));
+        let existing = self.ue_contexts.lock().await.get(&rnti.0).copied();
+        match existing {
+            Some(DuUeContext { du_ue_id, cu_ue_id: Some(cu_ue_id) }) if !ccch => {
+                let transfer = F1apPdu::initiating(F1apProcedureCode::UlRrcMessageTransfer)
+                    .with_ue_ids(cu_ue_id, du_ue_id)?
+                    .with_ie(pdu::ID_SRB_ID, Criticality::Reject, &SrbId(1))?
+                    .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &data)?;
+                self.send(UE_STREAM, &transfer).await
+            }
+            Some(DuUeContext { cu_ue_id: None, .. }) if !ccch => {
+                Err(LayerError::InvalidState(format!("RNTI {} not known to the gNB-CU yet", rnti.0)))
+            }
+            _ => self.send_initial_ul_rrc_message_transfer(rnti, data).await,
+        }
+    }
+
+    /// Create the F1 UE context of a C-RNTI with Initial UL RRC Message Transfer
+    async fn send_initial_ul_rrc_message_transfer(&self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let du_ue_id = self.next_du_ue_id.fetch_add(1, Ordering::Relaxed);
+        let transaction_id = self.next_transaction_id.fetch_add(1, Ordering::Relaxed);
+        let transfer = F1apPdu::initiating(F1apProcedureCode::InitialUlRrcMessageTransfer)
+            .with_ie(pdu::ID_GNB_DU_UE_F1AP_ID, Criticality::Reject, &GnbDuUeF1apId(du_ue_id))?
+            .with_ie(pdu::ID_NR_CGI, Criticality::Reject, &self.config.served_cell.nr_cgi)?
+            .with_ie(pdu::ID_C_RNTI, Criticality::Reject, &CRnti(rnti.0))?
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &data)?
+            .with_ie(pdu::ID_DU_TO_CU_RRC_CONTAINER, Criticality::Reject, &self.cell_group_config(&[]))?
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Ignore, &TransactionId(transaction_id))?;
+
+        info!("Initial UL RRC Message Transfer for RNTI {} (gNB-DU UE F1AP ID {})", rnti.0, du_ue_id);
+        self.ue_contexts.lock().await.insert(rnti.0, DuUeContext { du_ue_id, cu_ue_id: None });
+        self.send(UE_STREAM, &transfer).await
+    }
+
+    /// Handle DL RRC Message Transfer: pass the RRC message to the MAC
+    pub(super) async fn handle_dl_rrc_message_transfer(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.ue_of(pdu).await?;
+        let srb_id = pdu.ie::<SrbId>(pdu::ID_SRB_ID)?.0;
+        let rrc_container = pdu.ie::<Bytes>(pdu::ID_RRC_CONTAINER)?;
+        let msg_type = RrcMessageType::from_pdu(&rrc_container)
+            .ok_or_else(|| LayerError::ProcessingError(format!("Unknown RRC message on SRB{} for RNTI {}", srb_id, rnti.0)))?;
+
+        debug!("DL RRC Message Transfer on SRB{} for RNTI {}: {:?}", srb_id, rnti.0, msg_type);
+        self.mac.send_rrc_message(rnti, msg_type, rrc_container).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::user_plane::F1uBearer;
+    use crate::f1ap::F1apCuConfig;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::test_support::{f1ap_du_config, served_cell, RecordingMac};
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    fn dl_transfer(cu_ue_id: u32, du_ue_id: u32, rrc_container: &'static [u8]) -> F1apPdu {
+        F1apPdu::initiating(F1apProcedureCode::DlRrcMessageTransfer)
+            .with_ue_ids(cu_ue_id, du_ue_id).unwrap()
+            .with_ie(pdu::ID_SRB_ID, Criticality::Reject, &SrbId(1)).unwrap()
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &Bytes::from_static(rrc_container)).unwrap()
+    }
+
+    fn initial_transfer(du_ue_id: u32, rnti: Rnti) -> F1apPdu {
+        F1apPdu::initiating(F1apProcedureCode::InitialUlRrcMessageTransfer)
+            .with_ie(pdu::ID_GNB_DU_UE_F1AP_ID, Criticality::Reject, &GnbDuUeF1apId(du_ue_id)).unwrap()
+            .with_ie(pdu::ID_NR_CGI, Criticality::Reject, &served_cell().nr_cgi).unwrap()
+            .with_ie(pdu::ID_C_RNTI, Criticality::Reject, &CRnti(rnti.0)).unwrap()
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &Bytes::from_static(&[0x00, 0x01])).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rrc_transfer_failures() {
+        let mut cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(8);
+        cu.set_rrc_channel(rrc_tx);
+        let mac = Arc::new(RecordingMac::default());
+        let du = F1apDu::new(f1ap_du_config(cu.local_addr().unwrap()), mac.clone()).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+
+        // DU without F1 Setup: the CCCH message creates the context but is not
+        // sent, SRB1 messages wait for the gNB-CU UE F1AP ID
+        assert!(matches!(du.handle_uplink_rrc_message(rnti, Bytes::from_static(&[0x00, 0x01])).await,
+                         Err(LayerError::InvalidState(_))));
+        assert_eq!(du.ue_contexts.lock().await[&rnti.0].cu_ue_id, None);
+        assert!(matches!(du.handle_uplink_rrc_message(rnti, Bytes::from_static(&[0x02, 0x03])).await,
+                         Err(LayerError::InvalidState(_))));
+
+        // DL transfers for an unknown UE, without SRB ID, with an unknown RRC message
+        assert!(matches!(du.handle_dl_rrc_message_transfer(&dl_transfer(1, 99, &[0x01])).await,
+                         Err(LayerError::InvalidState(_))));
+        let mut no_srb = dl_transfer(1, 1, &[0x01]);
+        no_srb.ies.retain(|ie| ie.id != pdu::ID_SRB_ID);
+        assert!(matches!(du.handle_dl_rrc_message_transfer(&no_srb).await, Err(LayerError::ProcessingError(_))));
+        assert!(matches!(du.handle_dl_rrc_message_transfer(&dl_transfer(1, 1, &[0x3F])).await,
+                         Err(LayerError::ProcessingError(_))));
+        assert!(mac.sent.lock().unwrap().is_empty());
+
+        // CU: Initial UL RRC Message Transfer without C-RNTI, UL transfer for an unknown UE
+        let mut no_rnti = initial_transfer(1, rnti);
+        no_rnti.ies.retain(|ie| ie.id != pdu::ID_C_RNTI);
+        assert!(matches!(cu.handle_initial_ul_rrc_message_transfer(&no_rnti).await, Err(LayerError::ProcessingError(_))));
+        assert!(cu.ue_contexts.lock().await.is_empty());
+        let ul = F1apPdu::initiating(F1apProcedureCode::UlRrcMessageTransfer)
+            .with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_SRB_ID, Criticality::Reject, &SrbId(1)).unwrap()
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Reject, &Bytes::from_static(&[0x02, 0x03])).unwrap();
+        assert!(matches!(cu.handle_ul_rrc_message_transfer(&ul).await, Err(LayerError::InvalidState(_))));
+        assert!(rrc_rx.try_recv().is_err());
+
+        // A reused C-RNTI replaces the old UE and its F1-U bearers
+        cu.handle_initial_ul_rrc_message_transfer(&initial_transfer(1, rnti)).await.unwrap();
+        cu.f1u.add_bearer(F1uBearer::new(rnti, 1, 4)).await.unwrap();
+        cu.handle_initial_ul_rrc_message_transfer(&initial_transfer(2, rnti)).await.unwrap();
+        assert!(cu.f1u.bearers(rnti).await.is_empty());
+        assert_eq!(cu.ue_context(rnti).await.unwrap().cu_ue_id, 2);
+        assert!(matches!(cu.handle_ul_rrc_message_transfer(&ul).await, Err(LayerError::InvalidState(_))));
+        assert_eq!(rrc_rx.try_recv().unwrap().0, rnti);
+        assert_eq!(rrc_rx.try_recv().unwrap().0, rnti);
+
+        // DL transfers without gNB-DU, or for an unknown C-RNTI
+        assert!(matches!(cu.send_dl_rrc_message_transfer(rnti, 1, Bytes::from_static(&[0x01])).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu.send_dl_rrc_message_transfer(Rnti::new(0x4602), 1, Bytes::from_static(&[0x01])).await,
+                         Err(LayerError::InvalidState(_))));
+    }
+}
@@ -0,0 +1,176 @@
+//! F1 Setup (3GPP TS 38.473 section 8.2.3)
+//!
+//! The gNB-DU announces its served cells once the F1-C association is up; the
+//! gNB-CU activates all of them. A gNB-CU serves a single gNB-DU, further DUs
+//! are rejected.
+
+use super::cu::{ConnectedDu, F1apCu};
+use super::du::F1apDu;
+use super::pdu::{
+    self, Cause, CellsToBeActivatedItem, CellsToBeActivatedList, F1apPdu, F1apPduType, GnbDuId, GnbDuServedCellsItem,
+    GnbDuServedCellsList, RrcVersion, TransactionId,
+};
+use super::transport::{F1Sender, NON_UE_STREAM};
+use super::F1apProcedureCode;
+use crate::ngap::pdu::{Criticality, NodeName, TimeToWait};
+use crate::LayerError;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Latest RRC release supported, as in RRC-Version: Release 16
+const RRC_VERSION: RrcVersion = RrcVersion(0b100);
+
+impl F1apCu {
+    /// Handle F1 Setup Request from a gNB-DU
+    pub(super) async fn handle_f1_setup_request(&self, pdu: &F1apPdu, sender: &Arc<F1Sender>) -> Result<(), LayerError> {
+        let transaction_id = pdu.ie::<TransactionId>(pdu::ID_TRANSACTION_ID)?;
+        let gnb_du_id = pdu.ie::<GnbDuId>(pdu::ID_GNB_DU_ID)?.0;
+        let name = pdu.optional_ie::<NodeName>(pdu::ID_GNB_DU_NAME)?;
+        let cells = pdu.optional_ie::<GnbDuServedCellsList>(pdu::ID_GNB_DU_SERVED_CELLS_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+
+        let mut du = self.du.write().await;
+        if let Some(connected) = du.as_ref().filter(|du| !Arc::ptr_eq(&du.sender, sender)) {
+            warn!("Rejecting F1 Setup of gNB-DU {} from {}: gNB-DU {} already connected",
+                  gnb_du_id, sender.peer(), connected.gnb_du_id);
+            let failure = F1apPdu::unsuccessful(F1apProcedureCode::F1Setup)
+                .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::CONTROL_PROCESSING_OVERLOAD)?
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V60s)?;
+            return sender.send(NON_UE_STREAM, &failure).await;
+        }
+
+        info!("F1 Setup from gNB-DU {} ({}) at {} with {} cells",
+              gnb_du_id, name.map(|name| name.0).unwrap_or_default(), sender.peer(), cells.len());
+        let cells: Vec<_> = cells.into_iter().map(|cell| cell.served_cell_information).collect();
+        let mut response = F1apPdu::successful(F1apProcedureCode::F1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &transaction_id)?
+            .with_ie(pdu::ID_GNB_CU_NAME, Criticality::Ignore, &NodeName(self.config.gnb_cu_name.clone()))?;
+        if !cells.is_empty() {
+            let activated = cells.iter()
+                .map(|cell| CellsToBeActivatedItem { nr_cgi: cell.nr_cgi, nr_pci: Some(cell.nr_pci) })
+                .collect();
+            response.add_ie(pdu::ID_CELLS_TO_BE_ACTIVATED_LIST, Criticality::Reject, &CellsToBeActivatedList(activated))?;
+        }
+        response.add_ie(pdu::ID_GNB_CU_RRC_VERSION, Criticality::Ignore, &RRC_VERSION)?;
+        sender.send(NON_UE_STREAM, &response).await?;
+
+        *du = Some(ConnectedDu {
+            gnb_du_id,
+            sender: Arc::clone(sender),
+            cells,
+        });
+        Ok(())
+    }
+}
+
+impl F1apDu {
+    /// Build F1 Setup Request with the served cell
+    pub(super) fn f1_setup_request(&self) -> Result<F1apPdu, LayerError> {
+        let transaction_id = self.next_transaction_id.fetch_add(1, Ordering::Relaxed);
+        let cells = GnbDuServedCellsList(vec![GnbDuServedCellsItem {
+            served_cell_information: self.config.served_cell.clone(),
+        }]);
+        F1apPdu::initiating(F1apProcedureCode::F1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(transaction_id))?
+            .with_ie(pdu::ID_GNB_DU_ID, Criticality::Reject, &GnbDuId(self.config.gnb_du_id))?
+            .with_ie(pdu::ID_GNB_DU_NAME, Criticality::Ignore, &NodeName(self.config.gnb_du_name.clone()))?
+            .with_ie(pdu::ID_GNB_DU_SERVED_CELLS_LIST, Criticality::Reject, &cells)?
+            .with_ie(pdu::ID_GNB_DU_RRC_VERSION, Criticality::Reject, &RRC_VERSION)
+    }
+
+    /// Handle F1 Setup Response or Failure
+    pub(super) fn handle_f1_setup_outcome(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        match pdu.pdu_type {
+            F1apPduType::SuccessfulOutcome => {
+                let name = pdu.optional_ie::<NodeName>(pdu::ID_GNB_CU_NAME)?;
+                let activated = pdu.optional_ie::<CellsToBeActivatedList>(pdu::ID_CELLS_TO_BE_ACTIVATED_LIST)?
+                    .map(|list| list.0)
+                    .unwrap_or_default();
+                if !activated.iter().any(|cell| cell.nr_cgi == self.config.served_cell.nr_cgi) {
+                    warn!("gNB-CU did not activate the served cell");
+                }
+                info!("F1 Setup with gNB-CU {} done, {} cells activated",
+                      name.map(|name| name.0).unwrap_or_default(), activated.len());
+                Ok(())
+            }
+            _ => {
+                let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+                let time_to_wait = pdu.optional_ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT)?;
+                Err(LayerError::InitializationFailed(
+                    format!("F1 Setup rejected by the gNB-CU: {:?} (time to wait {:?})", cause, time_to_wait)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::transport::{F1Receiver, F1TransportEvent};
+    use crate::f1ap::F1apCuConfig;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::test_support::{f1_association, f1ap_du_config, RecordingMac};
+    use bytes::Bytes;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn recv_pdu(receiver: &mut F1Receiver) -> F1apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            F1TransportEvent::Data { payload, .. } => F1apPdu::decode(&payload).unwrap(),
+            F1TransportEvent::AssociationLost(reason) => panic!("F1-C association lost: {}", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_f1_setup_failures() {
+        let cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+        let du = F1apDu::new(f1ap_du_config(cu.local_addr().unwrap()), Arc::new(RecordingMac::default())).await.unwrap();
+        let ((first_tx, _first_cu_rx), (_first_du_tx, mut first_rx)) = f1_association().await;
+        let first = Arc::new(first_tx);
+
+        // Request without gNB-DU ID
+        let request = du.f1_setup_request().unwrap();
+        let mut incomplete = request.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_GNB_DU_ID);
+        assert!(matches!(cu.handle_f1_setup_request(&incomplete, &first).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(cu.connected_du().await, None);
+
+        // The first gNB-DU is set up, a second association is rejected
+        cu.handle_f1_setup_request(&request, &first).await.unwrap();
+        du.handle_f1_setup_outcome(&recv_pdu(&mut first_rx).await).unwrap();
+        let ((second_tx, _second_cu_rx), (_second_du_tx, mut second_rx)) = f1_association().await;
+        cu.handle_f1_setup_request(&request, &Arc::new(second_tx)).await.unwrap();
+        let failure = recv_pdu(&mut second_rx).await;
+        assert_eq!(failure.pdu_type, F1apPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::CONTROL_PROCESSING_OVERLOAD);
+        assert_eq!(failure.ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT).unwrap(), TimeToWait::V60s);
+        assert!(matches!(du.handle_f1_setup_outcome(&failure), Err(LayerError::InitializationFailed(_))));
+        assert_eq!(cu.connected_du().await, Some(1));
+
+        // Failure without Cause, response with an undecodable cell list
+        let failure = F1apPdu::unsuccessful(F1apProcedureCode::F1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap();
+        assert!(matches!(du.handle_f1_setup_outcome(&failure), Err(LayerError::ProcessingError(_))));
+        let mut response = F1apPdu::successful(F1apProcedureCode::F1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap()
+            .with_ie(pdu::ID_CELLS_TO_BE_ACTIVATED_LIST, Criticality::Reject, &CellsToBeActivatedList(vec![
+                CellsToBeActivatedItem { nr_cgi: du.config.served_cell.nr_cgi, nr_pci: None },
+            ])).unwrap();
+        du.handle_f1_setup_outcome(&response).unwrap();
+        response.ies[1].value = Bytes::new();
+        assert!(du.handle_f1_setup_outcome(&response).is_err());
+
+        // A response activating other cells only is accepted
+        let response = F1apPdu::successful(F1apProcedureCode::F1Setup)
+            .with_ie(pdu::ID_TRANSACTION_ID, Criticality::Reject, &TransactionId(0)).unwrap();
+        du.handle_f1_setup_outcome(&response).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+//! F1-C transport
+//!
+//! Carries F1AP over SCTP as specified by 3GPP TS 38.472: the gNB-DU opens the
+//! association towards the gNB-CU, payload protocol identifier 62, stream 0 for
+//! non UE-associated signalling. Where kernel SCTP is not available the TCP
+//! framing of the NG-C transport is used instead.
+
+use super::F1apProcedureCode;
+use crate::ngap::transport::{self, ApListener, ApReceiver, ApSender, NgTransportKind, SctpProtocol, TransportEvent};
+use crate::LayerError;
+use std::net::SocketAddr;
+
+/// SCTP payload protocol identifier of F1AP (TS 38.472 section 7)
+pub const F1AP_PPID: u32 = 62;
+/// SCTP stream reserved for non UE-associated signalling (TS 38.472 section 7)
+pub const NON_UE_STREAM: u16 = 0;
+/// SCTP stream used for UE-associated signalling
+pub const UE_STREAM: u16 = 1;
+
+impl SctpProtocol for F1apProcedureCode {
+    const INTERFACE: &'static str = "F1-C";
+    const PPID: u32 = F1AP_PPID;
+    const NUM_STREAMS: u16 = 2;
+}
+
+/// Event received on the F1-C transport
+pub type F1TransportEvent = TransportEvent;
+/// Sending side of an F1-C association
+pub type F1Sender = ApSender<F1apProcedureCode>;
+/// Receiving side of an F1-C association
+pub type F1Receiver = ApReceiver<F1apProcedureCode>;
+/// Listening socket of the gNB-CU
+pub type F1Listener = ApListener<F1apProcedureCode>;
+
+/// Open the F1-C association from the gNB-DU to the gNB-CU
+pub async fn connect(
+    kind: NgTransportKind,
+    local_address: SocketAddr,
+    cu_address: SocketAddr,
+) -> Result<(F1Sender, F1Receiver), LayerError> {
+    transport::connect(kind, local_address, cu_address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f1c_sctp_parameters() {
+        // TS 38.472 section 7, one stream for each kind of signalling
+        assert_eq!(F1apProcedureCode::PPID, 62);
+        assert_eq!(F1apProcedureCode::NUM_STREAMS, 2);
+        assert_ne!(NON_UE_STREAM, UE_STREAM);
+        assert!(UE_STREAM < F1apProcedureCode::NUM_STREAMS);
+    }
+}
@@ -0,0 +1,573 @@
+//! UE Context Management (3GPP TS 38.473 section 8.3)
+//!
+//! The gNB-CU sets up the DRBs of a UE in the gNB-DU with UE Context Setup the
+//! first time and UE Context Modification afterwards, exchanging the F1-U
+//! tunnels of each DRB, and removes the UE with UE Context Release. UE
+//! capabilities received by RRC in the CU reach the DU scheduler in the CU to
+//! DU RRC Information of a UE Context Modification.
+
+use super::cu::F1apCu;
+use super::du::F1apDu;
+use super::nru::DlDataDeliveryStatus;
+use super::pdu::{
+    self, Cause, CuToDuRrcInformation, DrbFailedToBeSetupItem, DrbSetupItem, DrbToBeReleasedItem, DrbToBeSetupItem,
+    DrbsFailedToBeSetupList, DrbsFailedToBeSetupModList, DrbsSetupList, DrbsSetupModList, DrbsToBeReleasedList,
+    DrbsToBeSetupList, DrbsToBeSetupModList, DuToCuRrcInformation, F1apPdu, GnbCuUeF1apId, ServCellIndex, SrbId,
+};
+use super::transport::UE_STREAM;
+use super::user_plane::F1uBearer;
+use super::F1apProcedureCode;
+use crate::mac::UeSchedulingCapabilities;
+use crate::ngap::pdu::Criticality;
+use crate::rrc::capability::{self, BandCapability, RatType, UeCapabilityRatContainer, UeNrCapability};
+use crate::rrc::reconfiguration::{default_drb_rlc_config, DRB_LCID_OFFSET};
+use crate::rrc::{DrbBearerConfig, RrcMessageType};
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::Rnti;
+use tracing::{debug, info, warn};
+
+impl F1apCu {
+    /// Ask the DU to add and release DRBs of a UE, creating the uplink F1-U
+    /// tunnels of the new DRBs
+    pub(super) async fn request_drbs(
+        &self,
+        rnti: Rnti,
+        to_setup: Vec<DrbBearerConfig>,
+        to_release: Vec<u8>,
+    ) -> Result<(), LayerError> {
+        let ctx = self.ue_context(rnti).await?;
+
+        let mut drbs = Vec::with_capacity(to_setup.len());
+        for drb in &to_setup {
+            let ul_tunnel = match drb.ul_tunnel {
+                // F1-U terminates in a gNB-CU-UP
+                Some(ul_tunnel) => ul_tunnel,
+                None => self.f1u.add_bearer(F1uBearer::new(rnti, drb.drb_id, drb.lcid)).await?,
+            };
+            drbs.push(DrbToBeSetupItem {
+                drb_id: drb.drb_id,
+                s_nssai: drb.s_nssai.clone(),
+                qos_flows: drb.qos_flows.clone(),
+                ul_tunnel,
+                rlc_mode: drb.rlc_config.mode,
+            });
+        }
+        if !to_release.is_empty() {
+            self.f1u.remove_bearers(rnti, Some(&to_release)).await;
+        }
+
+        let request = if !ctx.context_setup {
+            let mut request = F1apPdu::initiating(F1apProcedureCode::UeContextSetup)
+                .with_ue_ids(ctx.cu_ue_id, ctx.du_ue_id)?
+                .with_ie(pdu::ID_SP_CELL_ID, Criticality::Reject, &ctx.nr_cgi)?
+                .with_ie(pdu::ID_SERV_CELL_INDEX, Criticality::Reject, &ServCellIndex(0))?
+                .with_ie(pdu::ID_CU_TO_DU_RRC_INFORMATION, Criticality::Reject, &CuToDuRrcInformation::default())?;
+            if !drbs.is_empty() {
+                request.add_ie(pdu::ID_DRBS_TO_BE_SETUP_LIST, Criticality::Reject, &DrbsToBeSetupList(drbs))?;
+            }
+            if let Some(ctx) = self.ue_contexts.lock().await.get_mut(&rnti.0) {
+                ctx.context_setup = true;
+            }
+            info!("UE Context Setup for RNTI {}: DRBs {:?}", rnti.0,
+                  to_setup.iter().map(|drb| drb.drb_id).collect::<Vec<_>>());
+            request
+        } else {
+            let mut request = F1apPdu::initiating(F1apProcedureCode::UeContextModification)
+                .with_ue_ids(ctx.cu_ue_id, ctx.du_ue_id)?;
+            if !drbs.is_empty() {
+                request.add_ie(pdu::ID_DRBS_TO_BE_SETUP_MOD_LIST, Criticality::Reject, &DrbsToBeSetupModList(drbs))?;
+            }
+            if !to_release.is_empty() {
+                let released = to_release.iter().map(|drb_id| DrbToBeReleasedItem { drb_id: *drb_id }).collect();
+                request.add_ie(pdu::ID_DRBS_TO_BE_RELEASED_LIST, Criticality::Reject, &DrbsToBeReleasedList(released))?;
+            }
+            info!("UE Context Modification for RNTI {}: DRBs {:?} to set up, {:?} to release", rnti.0,
+                  to_setup.iter().map(|drb| drb.drb_id).collect::<Vec<_>>(), to_release);
+            request
+        };
+        self.du_sender().await?.send(UE_STREAM, &request).await
+    }
+
+    /// Give the scheduling capabilities of a UE to the DU as the
+    /// UE-CapabilityRAT-ContainerList of CU to DU RRC Information
+    pub(super) async fn send_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError> {
+        let ctx = self.ue_context(rnti).await?;
+        let band = self.du.read().await.as_ref()
+            .and_then(|du| du.cells.iter().find(|cell| cell.nr_cgi == ctx.nr_cgi))
+            .map(|cell| cell.fdd_info.band)
+            .ok_or_else(|| LayerError::InvalidState(format!("Cell of RNTI {} not served by the gNB-DU", rnti.0)))?;
+        let nr_capability = UeNrCapability {
+            access_stratum_release: 15,
+            pdcp: Default::default(),
+            rlc: Default::default(),
+            bands: vec![BandCapability {
+                band,
+                supports_256qam_dl: capabilities.supports_256qam_dl,
+                supports_256qam_ul: capabilities.supports_256qam_ul,
+                max_mimo_layers_dl: capabilities.max_mimo_layers_dl,
+                max_mimo_layers_ul: capabilities.max_mimo_layers_ul,
+            }],
+        };
+        let containers = [UeCapabilityRatContainer { rat_type: RatType::Nr, container: nr_capability.encode() }];
+        let rrc_information = CuToDuRrcInformation {
+            ue_capability_rat_container_list: Some(capability::encode_container_list(&containers)),
+        };
+        let request = F1apPdu::initiating(F1apProcedureCode::UeContextModification)
+            .with_ue_ids(ctx.cu_ue_id, ctx.du_ue_id)?
+            .with_ie(pdu::ID_CU_TO_DU_RRC_INFORMATION, Criticality::Reject, &rrc_information)?;
+
+        info!("UE Context Modification for RNTI {}: UE capabilities {:?}", rnti.0, capabilities);
+        self.du_sender().await?.send(UE_STREAM, &request).await
+    }
+
+    /// Note the downlink tunnels of the DRBs set up by the DU, or pass them on to
+    /// the gNB-CU-UP
+    async fn apply_drbs_setup(&self, rnti: Rnti, setup: Vec<DrbSetupItem>, failed: Vec<DrbFailedToBeSetupItem>) {
+        if let Some(cu_up) = &self.cu_up {
+            if !setup.is_empty() {
+                let tunnels = setup.iter().map(|drb| (drb.drb_id, drb.dl_tunnel)).collect();
+                if let Err(e) = cu_up.set_dl_tunnels(rnti, tunnels).await {
+                    warn!("Failed to pass the F1-U tunnels of RNTI {} to the gNB-CU-UP: {}", rnti.0, e);
+                }
+            }
+        } else {
+            for drb in setup {
+                if !self.f1u.set_remote(rnti, drb.drb_id, drb.dl_tunnel).await {
+                    debug!("DRB {} of RNTI {} set up by the DU is not requested", drb.drb_id, rnti.0);
+                }
+            }
+        }
+        if !failed.is_empty() {
+            let drb_ids: Vec<u8> = failed.iter().map(|drb| drb.drb_id).collect();
+            warn!("gNB-DU failed to set up DRBs {:?} of RNTI {}: {:?}", drb_ids, rnti.0,
+                  failed.iter().map(|drb| drb.cause).collect::<Vec<_>>());
+            self.f1u.remove_bearers(rnti, Some(&drb_ids)).await;
+        }
+    }
+
+    /// Handle UE Context Setup Response
+    pub(super) async fn handle_ue_context_setup_response(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.rnti_of(pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0).await?;
+        let cell_group = pdu.ie::<DuToCuRrcInformation>(pdu::ID_DU_TO_CU_RRC_INFORMATION)?;
+        let setup = pdu.optional_ie::<DrbsSetupList>(pdu::ID_DRBS_SETUP_LIST)?.map(|list| list.0).unwrap_or_default();
+        let failed = pdu.optional_ie::<DrbsFailedToBeSetupList>(pdu::ID_DRBS_FAILED_TO_BE_SETUP_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+
+        info!("UE Context Setup Response for RNTI {}: {} DRBs set up, CellGroupConfig {} bytes",
+              rnti.0, setup.len(), cell_group.cell_group_config.len());
+        self.apply_drbs_setup(rnti, setup, failed).await;
+        Ok(())
+    }
+
+    /// Handle UE Context Setup Failure
+    pub(super) async fn handle_ue_context_setup_failure(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.rnti_of(pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0).await?;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+
+        warn!("UE Context Setup for RNTI {} failed: {:?}", rnti.0, cause);
+        if let Some(ctx) = self.ue_contexts.lock().await.get_mut(&rnti.0) {
+            ctx.context_setup = false;
+        }
+        self.f1u.remove_bearers(rnti, None).await;
+        Ok(())
+    }
+
+    /// Handle UE Context Modification Response
+    pub(super) async fn handle_ue_context_modification_response(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.rnti_of(pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0).await?;
+        let setup = pdu.optional_ie::<DrbsSetupModList>(pdu::ID_DRBS_SETUP_MOD_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+        let failed = pdu.optional_ie::<DrbsFailedToBeSetupModList>(pdu::ID_DRBS_FAILED_TO_BE_SETUP_MOD_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+
+        info!("UE Context Modification Response for RNTI {}: {} DRBs set up", rnti.0, setup.len());
+        self.apply_drbs_setup(rnti, setup, failed).await;
+        Ok(())
+    }
+
+    /// Handle UE Context Modification Failure
+    pub(super) async fn handle_ue_context_modification_failure(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.rnti_of(pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0).await?;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+
+        warn!("UE Context Modification for RNTI {} failed: {:?}", rnti.0, cause);
+        let pending: Vec<u8> = self.f1u.bearers(rnti).await.into_iter()
+            .filter(|bearer| bearer.remote.is_none())
+            .map(|bearer| bearer.drb_id)
+            .collect();
+        self.f1u.remove_bearers(rnti, Some(&pending)).await;
+        Ok(())
+    }
+
+    /// Release a UE in the DU, optionally delivering a last RRC message on SRB0
+    /// (RRC Reject) or SRB1 first
+    pub(super) async fn release_ue_context(&self, rnti: Rnti, cause: Cause, rrc_container: Option<Bytes>) -> Result<(), LayerError> {
+        let ctx = self.ue_context(rnti).await?;
+        let mut command = F1apPdu::initiating(F1apProcedureCode::UeContextRelease)
+            .with_ue_ids(ctx.cu_ue_id, ctx.du_ue_id)?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        if let Some(rrc_container) = rrc_container {
+            let srb_id = if ctx.context_setup { 1 } else { 0 };
+            command.add_ie(pdu::ID_RRC_CONTAINER, Criticality::Ignore, &rrc_container)?;
+            command.add_ie(pdu::ID_SRB_ID, Criticality::Ignore, &SrbId(srb_id))?;
+        }
+
+        info!("UE Context Release Command for RNTI {}: {:?}", rnti.0, cause);
+        self.f1u.remove_bearers(rnti, None).await;
+        self.du_sender().await?.send(UE_STREAM, &command).await
+    }
+
+    /// Handle UE Context Release Complete
+    pub(super) async fn handle_ue_context_release_complete(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let cu_ue_id = pdu.ie::<GnbCuUeF1apId>(pdu::ID_GNB_CU_UE_F1AP_ID)?.0;
+        let mut contexts = self.ue_contexts.lock().await;
+        let rnti = contexts.iter().find(|(_, ctx)| ctx.cu_ue_id == cu_ue_id).map(|(rnti, _)| *rnti);
+        match rnti {
+            Some(rnti) => {
+                contexts.remove(&rnti);
+                info!("UE Context Release Complete for RNTI {}", rnti);
+            }
+            None => debug!("UE Context Release Complete for unknown gNB-CU UE F1AP ID {}", cu_ue_id),
+        }
+        Ok(())
+    }
+}
+
+impl F1apDu {
+    /// Add the logical channels and F1-U tunnels of DRBs requested by the CU
+    async fn setup_drbs(
+        &self,
+        rnti: Rnti,
+        drbs: Vec<DrbToBeSetupItem>,
+        to_release: Vec<u8>,
+    ) -> (Vec<DrbSetupItem>, Vec<DrbFailedToBeSetupItem>) {
+        if drbs.is_empty() && to_release.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        if !to_release.is_empty() {
+            for bearer in self.f1u.remove_bearers(rnti, Some(&to_release)).await {
+                self.send_final_delivery_status(&bearer).await;
+            }
+        }
+
+        let mut setup = Vec::with_capacity(drbs.len());
+        let mut configs = Vec::with_capacity(drbs.len());
+        for drb in &drbs {
+            let lcid = drb.drb_id + DRB_LCID_OFFSET;
+            let mut bearer = F1uBearer::new(rnti, drb.drb_id, lcid);
+            bearer.remote = Some(drb.ul_tunnel);
+            let dl_tunnel = match self.f1u.add_bearer(bearer).await {
+                Ok(dl_tunnel) => dl_tunnel,
+                Err(e) => {
+                    warn!("No F1-U tunnel for DRB {} of RNTI {}: {}", drb.drb_id, rnti.0, e);
+                    continue;
+                }
+            };
+            setup.push(DrbSetupItem { drb_id: drb.drb_id, lcid: Some(lcid), dl_tunnel });
+            configs.push(DrbBearerConfig {
+                drb_id: drb.drb_id,
+                lcid,
+                rlc_config: crate::rlc::RlcConfig { mode: drb.rlc_mode, ..default_drb_rlc_config() },
+                qos_flows: drb.qos_flows.clone(),
+                ul_tunnel: None,
+                s_nssai: drb.s_nssai.clone(),
+            });
+        }
+
+        if let Err(e) = self.mac.configure_drbs(rnti, configs, to_release).await {
+            warn!("MAC failed to configure the DRBs of RNTI {}: {}", rnti.0, e);
+            let drb_ids: Vec<u8> = setup.iter().map(|drb| drb.drb_id).collect();
+            self.f1u.remove_bearers(rnti, Some(&drb_ids)).await;
+            setup.clear();
+        }
+        let failed = drbs.iter()
+            .filter(|drb| !setup.iter().any(|done| done.drb_id == drb.drb_id))
+            .map(|drb| DrbFailedToBeSetupItem { drb_id: drb.drb_id, cause: Some(Cause::NO_RADIO_RESOURCES_AVAILABLE) })
+            .collect();
+        (setup, failed)
+    }
+
+    /// Configure the scheduler with the UE capabilities in CU to DU RRC
+    /// Information, if the CU sent any
+    async fn apply_cu_to_du_rrc_information(&self, rnti: Rnti, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let Some(list) = pdu.optional_ie::<CuToDuRrcInformation>(pdu::ID_CU_TO_DU_RRC_INFORMATION)?
+            .and_then(|information| information.ue_capability_rat_container_list)
+        else {
+            return Ok(());
+        };
+        let containers = capability::decode_container_list(&list)?;
+        let Some(container) = containers.iter().find(|container| container.rat_type == RatType::Nr) else {
+            debug!("No UE-NR-Capability container for RNTI {} from the gNB-CU", rnti.0);
+            return Ok(());
+        };
+        let capabilities = UeNrCapability::decode(&container.container)?
+            .scheduling_capabilities(self.config.served_cell.fdd_info.band);
+        debug!("UE capabilities of RNTI {} from the gNB-CU: {:?}", rnti.0, capabilities);
+        self.mac.configure_ue_capabilities(rnti, capabilities).await
+    }
+
+    /// Tell the CU that no more downlink data of a released DRB is expected
+    async fn send_final_delivery_status(&self, bearer: &F1uBearer) {
+        let Some(remote) = bearer.remote else {
+            return;
+        };
+        let status = DlDataDeliveryStatus { desired_buffer_size: 0, lost_nr_u_sn_ranges: Vec::new(), final_frame: true };
+        if let Err(e) = self.f1u.send_delivery_status(&remote, &status).await {
+            debug!("Failed to send the final DL Data Delivery Status of DRB {}: {}", bearer.drb_id, e);
+        }
+    }
+
+    /// Handle UE Context Setup Request
+    pub(super) async fn handle_ue_context_setup_request(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.ue_of(pdu).await?;
+        let drbs = pdu.optional_ie::<DrbsToBeSetupList>(pdu::ID_DRBS_TO_BE_SETUP_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+        let (du_ue_id, cu_ue_id) = self.ue_ids(rnti).await?;
+
+        info!("UE Context Setup Request for RNTI {}: {} DRBs", rnti.0, drbs.len());
+        if let Err(e) = self.apply_cu_to_du_rrc_information(rnti, pdu).await {
+            warn!("Failed to apply the UE capabilities of RNTI {}: {}", rnti.0, e);
+        }
+        let (setup, failed) = self.setup_drbs(rnti, drbs, Vec::new()).await;
+        let lcids: Vec<(u8, u8)> = setup.iter().map(|drb| (drb.drb_id, drb.lcid.unwrap_or_default())).collect();
+        let mut response = F1apPdu::successful(F1apProcedureCode::UeContextSetup)
+            .with_ue_ids(cu_ue_id, du_ue_id)?
+            .with_ie(pdu::ID_DU_TO_CU_RRC_INFORMATION, Criticality::Reject,
+                     &DuToCuRrcInformation { cell_group_config: self.cell_group_config(&lcids) })?;
+        if !setup.is_empty() {
+            response.add_ie(pdu::ID_DRBS_SETUP_LIST, Criticality::Ignore, &DrbsSetupList(setup))?;
+        }
+        if !failed.is_empty() {
+            response.add_ie(pdu::ID_DRBS_FAILED_TO_BE_SETUP_LIST, Criticality::Ignore, &DrbsFailedToBeSetupList(failed))?;
+        }
+        self.send(UE_STREAM, &response).await
+    }
+
+    /// Handle UE Context Modification Request
+    pub(super) async fn handle_ue_context_modification_request(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.ue_of(pdu).await?;
+        let drbs = pdu.optional_ie::<DrbsToBeSetupModList>(pdu::ID_DRBS_TO_BE_SETUP_MOD_LIST)?
+            .map(|list| list.0)
+            .unwrap_or_default();
+        let to_release: Vec<u8> = pdu.optional_ie::<DrbsToBeReleasedList>(pdu::ID_DRBS_TO_BE_RELEASED_LIST)?
+            .map(|list| list.0.iter().map(|drb| drb.drb_id).collect())
+            .unwrap_or_default();
+        let (du_ue_id, cu_ue_id) = self.ue_ids(rnti).await?;
+
+        info!("UE Context Modification Request for RNTI {}: {} DRBs to set up, {:?} to release",
+              rnti.0, drbs.len(), to_release);
+        if let Err(e) = self.apply_cu_to_du_rrc_information(rnti, pdu).await {
+            warn!("Failed to apply the UE capabilities of RNTI {}: {}", rnti.0, e);
+        }
+        let (setup, failed) = self.setup_drbs(rnti, drbs, to_release).await;
+        let mut response = F1apPdu::successful(F1apProcedureCode::UeContextModification)
+            .with_ue_ids(cu_ue_id, du_ue_id)?;
+        if !setup.is_empty() {
+            response.add_ie(pdu::ID_DRBS_SETUP_MOD_LIST, Criticality::Ignore, &DrbsSetupModList(setup))?;
+        }
+        if !failed.is_empty() {
+            response.add_ie(pdu::ID_DRBS_FAILED_TO_BE_SETUP_MOD_LIST, Criticality::Ignore,
+                            &DrbsFailedToBeSetupModList(failed))?;
+        }
+        self.send(UE_STREAM, &response).await
+    }
+
+    /// Handle UE Context Release Command: deliver the last RRC message, then
+    /// release the UE in the MAC
+    pub(super) async fn handle_ue_context_release_command(&self, pdu: &F1apPdu) -> Result<(), LayerError> {
+        let rnti = self.ue_of(pdu).await?;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        let rrc_container = pdu.optional_ie::<Bytes>(pdu::ID_RRC_CONTAINER)?;
+        let (du_ue_id, cu_ue_id) = self.ue_ids(rnti).await?;
+
+        info!("UE Context Release Command for RNTI {}: {:?}", rnti.0, cause);
+        if let Some(rrc_container) = rrc_container {
+            match RrcMessageType::from_pdu(&rrc_container) {
+                Some(msg_type) => self.mac.send_rrc_message(rnti, msg_type, rrc_container).await?,
+                None => warn!("Dropping unknown RRC message in UE Context Release Command"),
+            }
+        }
+        for bearer in self.f1u.remove_bearers(rnti, None).await {
+            self.send_final_delivery_status(&bearer).await;
+        }
+        if let Err(e) = self.mac.release_ue(rnti).await {
+            warn!("Failed to release MAC resources of RNTI {}: {}", rnti.0, e);
+        }
+        self.ue_contexts.lock().await.remove(&rnti.0);
+
+        let complete = F1apPdu::successful(F1apProcedureCode::UeContextRelease).with_ue_ids(cu_ue_id, du_ue_id)?;
+        self.send(UE_STREAM, &complete).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::cu::{ConnectedDu, CuUeContext};
+    use crate::f1ap::du::DuUeContext;
+    use crate::f1ap::transport::{F1Receiver, F1TransportEvent};
+    use crate::f1ap::F1apCuConfig;
+    use crate::ngap::pdu::GtpTunnel;
+    use crate::ngap::transport::NgTransportKind;
+    use crate::test_support::{f1_association, f1ap_du_config, served_cell, RecordingMac};
+    use std::net::IpAddr;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn recv_pdu(receiver: &mut F1Receiver) -> F1apPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            F1TransportEvent::Data { payload, .. } => F1apPdu::decode(&payload).unwrap(),
+            F1TransportEvent::AssociationLost(reason) => panic!("F1-C association lost: {}", reason),
+        }
+    }
+
+    fn drb(drb_id: u8) -> DrbBearerConfig {
+        DrbBearerConfig {
+            drb_id,
+            lcid: drb_id + DRB_LCID_OFFSET,
+            rlc_config: default_drb_rlc_config(),
+            qos_flows: vec![1],
+            ul_tunnel: None,
+            s_nssai: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cu_ue_context_failures() {
+        let cu = F1apCu::new(F1apCuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport: NgTransportKind::TcpFramed,
+            f1u_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_cu_name: "cu".into(),
+        }).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+        let capabilities = UeSchedulingCapabilities {
+            supports_256qam_dl: true,
+            supports_256qam_ul: false,
+            max_mimo_layers_dl: 2,
+            max_mimo_layers_ul: 1,
+        };
+
+        // Unknown UE
+        assert!(matches!(cu.request_drbs(rnti, vec![drb(1)], Vec::new()).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu.send_ue_capabilities(rnti, capabilities).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(cu.release_ue_context(rnti, Cause::NORMAL_RELEASE, None).await, Err(LayerError::InvalidState(_))));
+
+        // Capabilities of a UE in a cell the gNB-DU does not serve
+        let ((cu_tx, _cu_rx), (_du_tx, mut du_rx)) = f1_association().await;
+        *cu.du.write().await = Some(ConnectedDu { gnb_du_id: 1, sender: Arc::new(cu_tx), cells: Vec::new() });
+        cu.ue_contexts.lock().await.insert(rnti.0, CuUeContext {
+            cu_ue_id: 1, du_ue_id: 1, nr_cgi: served_cell().nr_cgi, context_setup: false,
+        });
+        assert!(matches!(cu.send_ue_capabilities(rnti, capabilities).await, Err(LayerError::InvalidState(_))));
+
+        // UE Context Setup Failure drops the requested bearers and the next
+        // request is a UE Context Setup again
+        cu.request_drbs(rnti, vec![drb(1)], Vec::new()).await.unwrap();
+        assert_eq!(recv_pdu(&mut du_rx).await.procedure(), Some(F1apProcedureCode::UeContextSetup));
+        let failure = F1apPdu::unsuccessful(F1apProcedureCode::UeContextSetup).with_ue_ids(1, 1).unwrap();
+        assert!(matches!(cu.handle_ue_context_setup_failure(&failure).await, Err(LayerError::ProcessingError(_))));
+        let failure = failure.with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NO_RADIO_RESOURCES_AVAILABLE).unwrap();
+        cu.handle_ue_context_setup_failure(&failure).await.unwrap();
+        assert!(cu.f1u.bearers(rnti).await.is_empty());
+        assert!(!cu.ue_context(rnti).await.unwrap().context_setup);
+        let unknown = F1apPdu::unsuccessful(F1apProcedureCode::UeContextSetup).with_ue_ids(9, 1).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NO_RADIO_RESOURCES_AVAILABLE).unwrap();
+        assert!(matches!(cu.handle_ue_context_setup_failure(&unknown).await, Err(LayerError::InvalidState(_))));
+
+        // Response without DU to CU RRC Information, then with a failed DRB and
+        // a DRB that was never requested
+        cu.request_drbs(rnti, vec![drb(1), drb(2)], Vec::new()).await.unwrap();
+        assert_eq!(recv_pdu(&mut du_rx).await.procedure(), Some(F1apProcedureCode::UeContextSetup));
+        let dl_tunnel = GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 0x200 };
+        let response = F1apPdu::successful(F1apProcedureCode::UeContextSetup).with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_DRBS_SETUP_LIST, Criticality::Ignore, &DrbsSetupList(vec![
+                DrbSetupItem { drb_id: 1, lcid: Some(4), dl_tunnel },
+                DrbSetupItem { drb_id: 5, lcid: Some(8), dl_tunnel },
+            ])).unwrap()
+            .with_ie(pdu::ID_DRBS_FAILED_TO_BE_SETUP_LIST, Criticality::Ignore, &DrbsFailedToBeSetupList(vec![
+                DrbFailedToBeSetupItem { drb_id: 2, cause: None },
+            ])).unwrap();
+        assert!(matches!(cu.handle_ue_context_setup_response(&response).await, Err(LayerError::ProcessingError(_))));
+        let response = response.with_ie(pdu::ID_DU_TO_CU_RRC_INFORMATION, Criticality::Reject,
+                                        &DuToCuRrcInformation { cell_group_config: Bytes::from_static(&[0x00]) }).unwrap();
+        cu.handle_ue_context_setup_response(&response).await.unwrap();
+        let bearers = cu.f1u.bearers(rnti).await;
+        assert_eq!(bearers.len(), 1);
+        assert_eq!((bearers[0].drb_id, bearers[0].remote), (1, Some(dl_tunnel)));
+
+        // UE Context Modification Failure only drops the DRBs still pending
+        cu.request_drbs(rnti, vec![drb(3)], Vec::new()).await.unwrap();
+        assert_eq!(recv_pdu(&mut du_rx).await.procedure(), Some(F1apProcedureCode::UeContextModification));
+        let failure = F1apPdu::unsuccessful(F1apProcedureCode::UeContextModification).with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NO_RADIO_RESOURCES_AVAILABLE).unwrap();
+        cu.handle_ue_context_modification_failure(&failure).await.unwrap();
+        let bearers = cu.f1u.bearers(rnti).await;
+        assert_eq!(bearers.iter().map(|bearer| bearer.drb_id).collect::<Vec<_>>(), vec![1]);
+
+        // Release Complete for another UE keeps the context
+        let complete = F1apPdu::successful(F1apProcedureCode::UeContextRelease).with_ue_ids(9, 1).unwrap();
+        cu.handle_ue_context_release_complete(&complete).await.unwrap();
+        assert!(cu.ue_context(rnti).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_du_ue_context_failures() {
+        let mac = Arc::new(RecordingMac::default());
+        let du = F1apDu::new(f1ap_du_config("127.0.0.1:38472".parse().unwrap()), mac.clone()).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+        let ul_tunnel = GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 0x100 };
+        let drbs = DrbsToBeSetupList(vec![DrbToBeSetupItem {
+            drb_id: 1, s_nssai: None, qos_flows: vec![1], ul_tunnel, rlc_mode: crate::rlc::RlcMode::Am,
+        }]);
+        let request = F1apPdu::initiating(F1apProcedureCode::UeContextSetup).with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_DRBS_TO_BE_SETUP_LIST, Criticality::Reject, &drbs).unwrap();
+
+        // Unknown gNB-DU UE F1AP ID
+        assert!(matches!(du.handle_ue_context_setup_request(&request).await, Err(LayerError::InvalidState(_))));
+        du.ue_contexts.lock().await.insert(rnti.0, DuUeContext { du_ue_id: 1, cu_ue_id: None });
+
+        // The MAC refuses the DRBs: no F1-U tunnel is left behind (the
+        // response cannot be sent without F1 Setup)
+        mac.reject_drbs.store(true, Ordering::Relaxed);
+        assert!(matches!(du.handle_ue_context_setup_request(&request).await, Err(LayerError::InvalidState(_))));
+        assert!(du.f1u.bearers(rnti).await.is_empty());
+        assert!(mac.drbs.lock().unwrap().is_empty());
+        let (setup, failed) = du.setup_drbs(rnti, drbs.0.clone(), Vec::new()).await;
+        assert!(setup.is_empty());
+        assert_eq!(failed, vec![DrbFailedToBeSetupItem { drb_id: 1, cause: Some(Cause::NO_RADIO_RESOURCES_AVAILABLE) }]);
+        mac.reject_drbs.store(false, Ordering::Relaxed);
+
+        // Undecodable UE capabilities do not stop the DRB setup
+        let modification = F1apPdu::initiating(F1apProcedureCode::UeContextModification).with_ue_ids(1, 1).unwrap()
+            .with_ie(pdu::ID_DRBS_TO_BE_SETUP_MOD_LIST, Criticality::Reject, &DrbsToBeSetupModList(drbs.0.clone())).unwrap()
+            .with_ie(pdu::ID_CU_TO_DU_RRC_INFORMATION, Criticality::Reject, &CuToDuRrcInformation {
+                ue_capability_rat_container_list: Some(Bytes::from_static(&[0xFF])),
+            }).unwrap();
+        assert!(du.apply_cu_to_du_rrc_information(rnti, &modification).await.is_err());
+        assert!(matches!(du.handle_ue_context_modification_request(&modification).await, Err(LayerError::InvalidState(_))));
+        assert_eq!(du.f1u.bearers(rnti).await.len(), 1);
+        assert!(mac.capabilities.lock().unwrap().is_empty());
+
+        // Release Command without Cause, then with an unknown RRC message: the
+        // UE is still released in the MAC
+        let command = F1apPdu::initiating(F1apProcedureCode::UeContextRelease).with_ue_ids(1, 1).unwrap();
+        assert!(matches!(du.handle_ue_context_release_command(&command).await, Err(LayerError::ProcessingError(_))));
+        let command = command
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NORMAL_RELEASE).unwrap()
+            .with_ie(pdu::ID_RRC_CONTAINER, Criticality::Ignore, &Bytes::from_static(&[0x3F])).unwrap();
+        assert!(matches!(du.handle_ue_context_release_command(&command).await, Err(LayerError::InvalidState(_))));
+        assert!(mac.sent.lock().unwrap().is_empty());
+        assert_eq!(*mac.released.lock().unwrap(), vec![rnti]);
+        assert!(du.ue_contexts.lock().await.is_empty());
+        assert!(du.f1u.bearers(rnti).await.is_empty());
+        assert!(matches!(du.handle_ue_context_modification_request(&modification).await, Err(LayerError::InvalidState(_))));
+    }
+}
@@ -0,0 +1,275 @@
+//! F1-U endpoint
+//!
+//! GTP-U tunnels carrying the PDCP PDUs of the DRBs between gNB-CU and gNB-DU,
+//! one per DRB and direction (TS 38.470 section 5.2). Each side receives on
+//! TEIDs it allocated and sends to the TEID given by the peer in the UE Context
+//! Setup or Modification procedures.
+
+use super::nru::{DlDataDeliveryStatus, DlUserData, MAX_NR_U_SN};
+use crate::gtpu::pdu::{GtpuMessageType, GtpuPdu, GTPU_PORT};
+use crate::ngap::pdu::GtpTunnel;
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::Rnti;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tracing::{debug, error};
+
+/// Largest GTP-U datagram received
+const MAX_DATAGRAM_LEN: usize = 65535;
+/// DL USER DATA frames between two polls for a delivery status
+const REPORT_POLLING_INTERVAL: u32 = 64;
+
+/// F1-U bearer of a DRB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct F1uBearer {
+    /// C-RNTI of the UE in the DU
+    pub rnti: Rnti,
+    /// DRB identity
+    pub drb_id: u8,
+    /// Logical channel of the DRB
+    pub lcid: u8,
+    /// Tunnel of the peer, unknown until the peer answered
+    pub remote: Option<GtpTunnel>,
+    /// NR-U sequence number of the next DL USER DATA frame (CU side)
+    pub next_nr_u_sn: u32,
+    /// NR-U sequence number expected next (DU side)
+    pub expected_nr_u_sn: Option<u32>,
+    /// Desired buffer size last reported by the DU (CU side)
+    pub desired_buffer_size: Option<u32>,
+}
+
+impl F1uBearer {
+    /// Bearer without a peer tunnel yet
+    pub fn new(rnti: Rnti, drb_id: u8, lcid: u8) -> Self {
+        Self {
+            rnti,
+            drb_id,
+            lcid,
+            remote: None,
+            next_nr_u_sn: 0,
+            expected_nr_u_sn: None,
+            desired_buffer_size: None,
+        }
+    }
+}
+
+/// GTP-U socket and bearers of one side of F1-U
+pub struct F1uEndpoint {
+    socket: UdpSocket,
+    /// Bearers indexed by local TEID
+    bearers: Mutex<HashMap<u32, F1uBearer>>,
+    next_teid: Mutex<u32>,
+}
+
+impl F1uEndpoint {
+    /// Bind the F1-U socket
+    pub async fn bind(address: SocketAddr) -> Result<Self, LayerError> {
+        let socket = UdpSocket::bind(address).await
+            .map_err(|e| LayerError::InitializationFailed(format!("Cannot bind F1-U socket to {}: {}", address, e)))?;
+        Ok(Self {
+            socket,
+            bearers: Mutex::new(HashMap::new()),
+            next_teid: Mutex::new(1),
+        })
+    }
+
+    /// Local address of the socket
+    pub fn local_addr(&self) -> Result<SocketAddr, LayerError> {
+        self.socket.local_addr().map_err(|e| LayerError::ProcessingError(format!("F1-U socket: {}", e)))
+    }
+
+    /// Add a bearer, returning the local tunnel it receives on
+    pub async fn add_bearer(&self, bearer: F1uBearer) -> Result<GtpTunnel, LayerError> {
+        let teid = {
+            let mut next_teid = self.next_teid.lock().await;
+            let teid = *next_teid;
+            *next_teid = next_teid.checked_add(1).unwrap_or(1);
+            teid
+        };
+        let mut bearers = self.bearers.lock().await;
+        bearers.retain(|_, existing| existing.rnti != bearer.rnti || existing.drb_id != bearer.drb_id);
+        bearers.insert(teid, bearer);
+        Ok(GtpTunnel { transport_layer_address: self.local_addr()?.ip(), teid })
+    }
+
+    /// Set the peer tunnel of a bearer
+    pub async fn set_remote(&self, rnti: Rnti, drb_id: u8, remote: GtpTunnel) -> bool {
+        let mut bearers = self.bearers.lock().await;
+        match bearers.values_mut().find(|bearer| bearer.rnti == rnti && bearer.drb_id == drb_id) {
+            Some(bearer) => {
+                bearer.remote = Some(remote);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove bearers of a UE, all of them if `drb_ids` is None
+    pub async fn remove_bearers(&self, rnti: Rnti, drb_ids: Option<&[u8]>) -> Vec<F1uBearer> {
+        let mut bearers = self.bearers.lock().await;
+        let teids: Vec<u32> = bearers.iter()
+            .filter(|(_, bearer)| bearer.rnti == rnti && drb_ids.is_none_or(|ids| ids.contains(&bearer.drb_id)))
+            .map(|(teid, _)| *teid)
+            .collect();
+        teids.into_iter().filter_map(|teid| bearers.remove(&teid)).collect()
+    }
+
+    /// Remove all bearers
+    pub async fn clear(&self) {
+        self.bearers.lock().await.clear();
+    }
+
+    /// Bearers of a UE
+    pub async fn bearers(&self, rnti: Rnti) -> Vec<F1uBearer> {
+        self.bearers.lock().await.values().filter(|bearer| bearer.rnti == rnti).cloned().collect()
+    }
+
+    async fn send(&self, pdu: &GtpuPdu, remote: &GtpTunnel) -> Result<(), LayerError> {
+        let peer = SocketAddr::new(remote.transport_layer_address, GTPU_PORT);
+        self.socket.send_to(&pdu.encode(), peer).await
+            .map_err(|e| LayerError::ProcessingError(format!("F1-U send to {} failed: {}", peer, e)))?;
+        Ok(())
+    }
+
+    /// Send a downlink PDCP PDU in a DL USER DATA frame (gNB-CU)
+    pub async fn send_downlink(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        let (remote, user_data) = {
+            let mut bearers = self.bearers.lock().await;
+            let bearer = bearers.values_mut()
+                .find(|bearer| bearer.rnti == rnti && bearer.lcid == lcid)
+                .ok_or_else(|| LayerError::InvalidState(format!("No F1-U bearer on LCID {} of RNTI {}", lcid, rnti.0)))?;
+            let remote = bearer.remote
+                .ok_or_else(|| LayerError::InvalidState(format!("DRB {} of RNTI {} not set up in the DU", bearer.drb_id, rnti.0)))?;
+            let nr_u_sn = bearer.next_nr_u_sn;
+            bearer.next_nr_u_sn = (nr_u_sn + 1) & MAX_NR_U_SN;
+            let user_data = DlUserData {
+                nr_u_sn,
+                report_polling: nr_u_sn % REPORT_POLLING_INTERVAL == 0,
+                user_data_existence: true,
+            };
+            (remote, user_data)
+        };
+        self.send(&GtpuPdu::nr_u(remote.teid, user_data.encode(), data), &remote).await
+    }
+
+    /// Send an uplink PDCP PDU (gNB-DU)
+    pub async fn send_uplink(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        let remote = self.bearers.lock().await.values()
+            .find(|bearer| bearer.rnti == rnti && bearer.lcid == lcid)
+            .and_then(|bearer| bearer.remote)
+            .ok_or_else(|| LayerError::InvalidState(format!("No F1-U bearer on LCID {} of RNTI {}", lcid, rnti.0)))?;
+        self.send(&GtpuPdu::g_pdu(remote.teid, None, data), &remote).await
+    }
+
+    /// Send a DL DATA DELIVERY STATUS without user data (gNB-DU)
+    pub async fn send_delivery_status(&self, remote: &GtpTunnel, status: &DlDataDeliveryStatus) -> Result<(), LayerError> {
+        self.send(&GtpuPdu::nr_u(remote.teid, status.encode(), Bytes::new()), remote).await
+    }
+
+    /// Note a received NR-U sequence number, returning the range skipped before it
+    pub async fn check_nr_u_sn(&self, teid: u32, nr_u_sn: u32) -> Option<(u32, u32)> {
+        let mut bearers = self.bearers.lock().await;
+        let bearer = bearers.get_mut(&teid)?;
+        let expected = bearer.expected_nr_u_sn.replace((nr_u_sn + 1) & MAX_NR_U_SN);
+        match expected {
+            Some(expected) if expected != nr_u_sn => Some((expected, nr_u_sn.wrapping_sub(1) & MAX_NR_U_SN)),
+            _ => None,
+        }
+    }
+
+    /// Note the desired buffer size reported for a bearer
+    pub async fn set_desired_buffer_size(&self, teid: u32, desired_buffer_size: u32) {
+        if let Some(bearer) = self.bearers.lock().await.get_mut(&teid) {
+            bearer.desired_buffer_size = Some(desired_buffer_size);
+        }
+    }
+
+    /// Wait for the next G-PDU received on a known bearer
+    pub async fn recv(&self) -> (u32, F1uBearer, GtpuPdu) {
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            let (len, peer) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    error!("F1-U receive error: {}", e);
+                    continue;
+                }
+            };
+            let pdu = match GtpuPdu::decode(Bytes::copy_from_slice(&buf[..len])) {
+                Ok(pdu) if pdu.message_type == GtpuMessageType::GPdu => pdu,
+                Ok(pdu) => {
+                    debug!("Ignoring GTP-U {:?} from {} on F1-U", pdu.message_type, peer);
+                    continue;
+                }
+                Err(e) => {
+                    debug!("Dropping F1-U datagram from {}: {}", peer, e);
+                    continue;
+                }
+            };
+            match self.bearers.lock().await.get(&pdu.teid) {
+                Some(bearer) => return (pdu.teid, bearer.clone(), pdu),
+                None => debug!("F1-U G-PDU from {} for unknown TEID {:#x}", peer, pdu.teid),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f1ap::nru::NrUFrame;
+    use std::net::IpAddr;
+
+    #[tokio::test]
+    async fn test_f1u_bearer_errors() {
+        let endpoint = F1uEndpoint::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let rnti = Rnti::new(0x4601);
+        let packet = Bytes::from_static(&[0x45, 0x00]);
+
+        // Unknown LCID, bearer without peer tunnel
+        assert!(matches!(endpoint.send_downlink(rnti, 4, packet.clone()).await, Err(LayerError::InvalidState(_))));
+        let local = endpoint.add_bearer(F1uBearer::new(rnti, 1, 4)).await.unwrap();
+        assert!(matches!(endpoint.send_downlink(rnti, 4, packet.clone()).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(endpoint.send_uplink(rnti, 4, packet.clone()).await, Err(LayerError::InvalidState(_))));
+        let remote = GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 0x55 };
+        assert!(!endpoint.set_remote(rnti, 2, remote).await);
+        assert!(!endpoint.set_remote(Rnti::new(0x4602), 1, remote).await);
+
+        // A new bearer for the same DRB replaces the old TEID
+        let replaced = endpoint.add_bearer(F1uBearer::new(rnti, 1, 4)).await.unwrap();
+        assert_ne!(replaced.teid, local.teid);
+        assert_eq!(endpoint.bearers(rnti).await.len(), 1);
+        endpoint.add_bearer(F1uBearer::new(rnti, 2, 5)).await.unwrap();
+        assert_eq!(endpoint.remove_bearers(rnti, Some(&[2, 3])).await.len(), 1);
+        assert!(endpoint.remove_bearers(Rnti::new(0x4602), None).await.is_empty());
+
+        // NR-U sequence numbers: unknown TEID, first frame, gap, wrap around
+        assert_eq!(endpoint.check_nr_u_sn(local.teid, 7).await, None);
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, 0).await, None);
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, 3).await, Some((1, 2)));
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, 4).await, None);
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, MAX_NR_U_SN).await, Some((5, MAX_NR_U_SN - 1)));
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, 0).await, None);
+        assert_eq!(endpoint.check_nr_u_sn(replaced.teid, 0).await, Some((1, MAX_NR_U_SN)));
+        endpoint.set_desired_buffer_size(local.teid, 100).await;
+        assert_eq!(endpoint.bearers(rnti).await[0].desired_buffer_size, None);
+
+        // Only G-PDUs of known TEIDs are received
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = endpoint.local_addr().unwrap();
+        socket.send_to(&[0x30, 0xFF], address).await.unwrap();
+        socket.send_to(&GtpuPdu::echo_request(1).encode(), address).await.unwrap();
+        socket.send_to(&GtpuPdu::g_pdu(local.teid, None, packet.clone()).encode(), address).await.unwrap();
+        let status = DlDataDeliveryStatus { desired_buffer_size: 0, lost_nr_u_sn_ranges: Vec::new(), final_frame: true };
+        socket.send_to(&GtpuPdu::nr_u(replaced.teid, status.encode(), Bytes::new()).encode(), address).await.unwrap();
+        let (teid, bearer, pdu) = tokio::time::timeout(std::time::Duration::from_secs(2), endpoint.recv()).await.unwrap();
+        assert_eq!((teid, bearer.drb_id), (replaced.teid, 1));
+        assert_eq!(NrUFrame::decode(&pdu.nr_ran_container.unwrap()).unwrap(), NrUFrame::DlDataDeliveryStatus(status));
+
+        endpoint.clear().await;
+        assert!(endpoint.bearers(rnti).await.is_empty());
+    }
+}
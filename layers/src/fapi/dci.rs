@@ -0,0 +1,405 @@
+//! DCI payloads
+//!
+//! The PDCCH PDUs of FAPI carry each DCI as its packed payload bits, the
+//! first field in the most significant bits of the first octet. The MAC
+//! schedules DCI format 1_0 with CRC scrambled by SI-RNTI, P-RNTI and C-RNTI
+//! and DCI format 0_0 with CRC scrambled by C-RNTI, all in CORESET#0, packed
+//! and unpacked here according to TS 38.212 sections 7.3.1.2.1 and 7.3.1.1.1.
+
+use crate::mac::{P_RNTI, SI_RNTI};
+use crate::LayerError;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Reserved bits of DCI format 1_0 scrambled by SI-RNTI
+const SI_RNTI_RESERVED_BITS: u8 = 15;
+/// Reserved bits of DCI format 1_0 scrambled by P-RNTI
+const P_RNTI_RESERVED_BITS: u8 = 6;
+/// Bits of DCI format 1_0 besides the frequency domain resource assignment
+const DCI_1_0_FIXED_BITS: u8 = 28;
+/// Bits of DCI format 0_0 besides the frequency domain resource assignment
+/// and the padding
+const DCI_0_0_FIXED_BITS: u8 = 20;
+/// Identifier for DCI formats of DCI format 0_0
+const DCI_FORMAT_UL: u32 = 0;
+/// Identifier for DCI formats of DCI format 1_0
+const DCI_FORMAT_DL: u32 = 1;
+
+/// DCI format 1_0 in CORESET#0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dci10 {
+    /// Scheduling of system information
+    SiRnti {
+        frequency_resource: u16,
+        time_resource: u8,
+        vrb_to_prb_mapping: u8,
+        modulation_coding_scheme: u8,
+        redundancy_version: u8,
+        system_information_indicator: u8,
+    },
+    /// Scheduling of paging and short messages
+    PRnti {
+        short_messages_indicator: u8,
+        short_messages: u8,
+        frequency_resource: u16,
+        time_resource: u8,
+        vrb_to_prb_mapping: u8,
+        modulation_coding_scheme: u8,
+        tb_scaling: u8,
+    },
+    /// Scheduling of the DL-SCH of a UE
+    CRnti {
+        rnti: u16,
+        frequency_resource: u16,
+        time_resource: u8,
+        vrb_to_prb_mapping: u8,
+        modulation_coding_scheme: u8,
+        new_data_indicator: u8,
+        redundancy_version: u8,
+        harq_process_number: u8,
+        downlink_assignment_index: u8,
+        tpc_command: u8,
+        pucch_resource_indicator: u8,
+        harq_feedback_timing: u8,
+    },
+}
+
+impl Dci10 {
+    /// RNTI scrambling the CRC of the DCI
+    pub fn rnti(&self) -> u16 {
+        match self {
+            Dci10::SiRnti { .. } => SI_RNTI,
+            Dci10::PRnti { .. } => P_RNTI,
+            Dci10::CRnti { rnti, .. } => *rnti,
+        }
+    }
+
+    /// Frequency domain resource assignment
+    pub fn frequency_resource(&self) -> u16 {
+        match self {
+            Dci10::SiRnti { frequency_resource, .. }
+            | Dci10::PRnti { frequency_resource, .. }
+            | Dci10::CRnti { frequency_resource, .. } => *frequency_resource,
+        }
+    }
+
+    /// Time domain resource assignment
+    pub fn time_resource(&self) -> u8 {
+        match self {
+            Dci10::SiRnti { time_resource, .. }
+            | Dci10::PRnti { time_resource, .. }
+            | Dci10::CRnti { time_resource, .. } => *time_resource,
+        }
+    }
+
+    /// Modulation and coding scheme
+    pub fn modulation_coding_scheme(&self) -> u8 {
+        match self {
+            Dci10::SiRnti { modulation_coding_scheme, .. }
+            | Dci10::PRnti { modulation_coding_scheme, .. }
+            | Dci10::CRnti { modulation_coding_scheme, .. } => *modulation_coding_scheme,
+        }
+    }
+
+    /// Fields of the DCI with their widths, for a CORESET#0 of `coreset_rbs` RBs
+    fn fields(&self, coreset_rbs: u16) -> Vec<(u32, u8)> {
+        let frequency_bits = frequency_resource_bits(coreset_rbs);
+        match *self {
+            Dci10::SiRnti {
+                frequency_resource, time_resource, vrb_to_prb_mapping, modulation_coding_scheme,
+                redundancy_version, system_information_indicator,
+            } => vec![
+                (frequency_resource as u32, frequency_bits),
+                (time_resource as u32, 4),
+                (vrb_to_prb_mapping as u32, 1),
+                (modulation_coding_scheme as u32, 5),
+                (redundancy_version as u32, 2),
+                (system_information_indicator as u32, 1),
+                (0, SI_RNTI_RESERVED_BITS),
+            ],
+            Dci10::PRnti {
+                short_messages_indicator, short_messages, frequency_resource, time_resource, vrb_to_prb_mapping,
+                modulation_coding_scheme, tb_scaling,
+            } => vec![
+                (short_messages_indicator as u32, 2),
+                (short_messages as u32, 8),
+                (frequency_resource as u32, frequency_bits),
+                (time_resource as u32, 4),
+                (vrb_to_prb_mapping as u32, 1),
+                (modulation_coding_scheme as u32, 5),
+                (tb_scaling as u32, 2),
+                (0, P_RNTI_RESERVED_BITS),
+            ],
+            Dci10::CRnti {
+                frequency_resource, time_resource, vrb_to_prb_mapping, modulation_coding_scheme,
+                new_data_indicator, redundancy_version, harq_process_number, downlink_assignment_index,
+                tpc_command, pucch_resource_indicator, harq_feedback_timing, ..
+            } => vec![
+                (DCI_FORMAT_DL, 1),
+                (frequency_resource as u32, frequency_bits),
+                (time_resource as u32, 4),
+                (vrb_to_prb_mapping as u32, 1),
+                (modulation_coding_scheme as u32, 5),
+                (new_data_indicator as u32, 1),
+                (redundancy_version as u32, 2),
+                (harq_process_number as u32, 4),
+                (downlink_assignment_index as u32, 2),
+                (tpc_command as u32, 2),
+                (pucch_resource_indicator as u32, 3),
+                (harq_feedback_timing as u32, 3),
+            ],
+        }
+    }
+
+    /// Pack the DCI for a CORESET#0 of `coreset_rbs` RBs, returning its size
+    /// in bits and its payload
+    pub fn pack(&self, coreset_rbs: u16) -> (u16, Bytes) {
+        pack_fields(&self.fields(coreset_rbs))
+    }
+
+    /// Unpack the DCI of an RNTI for a CORESET#0 of `coreset_rbs` RBs, any
+    /// RNTI but SI-RNTI and P-RNTI being a C-RNTI
+    pub fn unpack(rnti: u16, payload: &[u8], size_bits: u16, coreset_rbs: u16) -> Result<Self, LayerError> {
+        let template = match rnti {
+            SI_RNTI => Dci10::SiRnti {
+                frequency_resource: 0, time_resource: 0, vrb_to_prb_mapping: 0, modulation_coding_scheme: 0,
+                redundancy_version: 0, system_information_indicator: 0,
+            },
+            P_RNTI => Dci10::PRnti {
+                short_messages_indicator: 0, short_messages: 0, frequency_resource: 0, time_resource: 0,
+                vrb_to_prb_mapping: 0, modulation_coding_scheme: 0, tb_scaling: 0,
+            },
+            rnti => Dci10::CRnti {
+                rnti, frequency_resource: 0, time_resource: 0, vrb_to_prb_mapping: 0, modulation_coding_scheme: 0,
+                new_data_indicator: 0, redundancy_version: 0, harq_process_number: 0, downlink_assignment_index: 0,
+                tpc_command: 0, pucch_resource_indicator: 0, harq_feedback_timing: 0,
+            },
+        };
+        let mut values = unpack_fields(&template.fields(coreset_rbs), payload, size_bits)?.into_iter();
+        let mut next = || values.next().unwrap_or(0);
+        Ok(match template {
+            Dci10::SiRnti { .. } => Dci10::SiRnti {
+                frequency_resource: next() as u16,
+                time_resource: next() as u8,
+                vrb_to_prb_mapping: next() as u8,
+                modulation_coding_scheme: next() as u8,
+                redundancy_version: next() as u8,
+                system_information_indicator: next() as u8,
+            },
+            Dci10::PRnti { .. } => Dci10::PRnti {
+                short_messages_indicator: next() as u8,
+                short_messages: next() as u8,
+                frequency_resource: next() as u16,
+                time_resource: next() as u8,
+                vrb_to_prb_mapping: next() as u8,
+                modulation_coding_scheme: next() as u8,
+                tb_scaling: next() as u8,
+            },
+            Dci10::CRnti { rnti, .. } => {
+                // DCI format 0_0 of the same size
+                if next() != DCI_FORMAT_DL {
+                    return Err(LayerError::InvalidPdu);
+                }
+                Dci10::CRnti {
+                    rnti,
+                    frequency_resource: next() as u16,
+                    time_resource: next() as u8,
+                    vrb_to_prb_mapping: next() as u8,
+                    modulation_coding_scheme: next() as u8,
+                    new_data_indicator: next() as u8,
+                    redundancy_version: next() as u8,
+                    harq_process_number: next() as u8,
+                    downlink_assignment_index: next() as u8,
+                    tpc_command: next() as u8,
+                    pucch_resource_indicator: next() as u8,
+                    harq_feedback_timing: next() as u8,
+                }
+            }
+        })
+    }
+}
+
+/// DCI format 0_0 with CRC scrambled by C-RNTI in CORESET#0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dci00 {
+    pub frequency_resource: u16,
+    pub time_resource: u8,
+    pub frequency_hopping: u8,
+    pub modulation_coding_scheme: u8,
+    pub new_data_indicator: u8,
+    pub redundancy_version: u8,
+    pub harq_process_number: u8,
+    pub tpc_command: u8,
+}
+
+impl Dci00 {
+    /// Fields of the DCI with their widths, for an UL BWP of `ul_bwp_rbs` RBs,
+    /// sized as DCI format 1_0 in a CORESET#0 of `coreset_rbs` RBs: padded,
+    /// or with the most significant bits of the frequency domain resource
+    /// assignment truncated (TS 38.212 section 7.3.1.0)
+    fn fields(&self, ul_bwp_rbs: u16, coreset_rbs: u16) -> Vec<(u32, u8)> {
+        let mut frequency_bits = frequency_resource_bits(ul_bwp_rbs);
+        let size_1_0 = frequency_resource_bits(coreset_rbs) + DCI_1_0_FIXED_BITS;
+        let size = frequency_bits + DCI_0_0_FIXED_BITS;
+        frequency_bits -= size.saturating_sub(size_1_0);
+        vec![
+            (DCI_FORMAT_UL, 1),
+            (self.frequency_resource as u32, frequency_bits),
+            (self.time_resource as u32, 4),
+            (self.frequency_hopping as u32, 1),
+            (self.modulation_coding_scheme as u32, 5),
+            (self.new_data_indicator as u32, 1),
+            (self.redundancy_version as u32, 2),
+            (self.harq_process_number as u32, 4),
+            (self.tpc_command as u32, 2),
+            (0, size_1_0.saturating_sub(size)),
+        ]
+    }
+
+    /// Pack the DCI for an UL BWP of `ul_bwp_rbs` RBs and a CORESET#0 of
+    /// `coreset_rbs` RBs, returning its size in bits and its payload
+    pub fn pack(&self, ul_bwp_rbs: u16, coreset_rbs: u16) -> (u16, Bytes) {
+        pack_fields(&self.fields(ul_bwp_rbs, coreset_rbs))
+    }
+
+    /// Unpack the DCI for an UL BWP of `ul_bwp_rbs` RBs and a CORESET#0 of
+    /// `coreset_rbs` RBs
+    pub fn unpack(payload: &[u8], size_bits: u16, ul_bwp_rbs: u16, coreset_rbs: u16) -> Result<Self, LayerError> {
+        let template = Dci00 {
+            frequency_resource: 0, time_resource: 0, frequency_hopping: 0, modulation_coding_scheme: 0,
+            new_data_indicator: 0, redundancy_version: 0, harq_process_number: 0, tpc_command: 0,
+        };
+        let mut values = unpack_fields(&template.fields(ul_bwp_rbs, coreset_rbs), payload, size_bits)?.into_iter();
+        let mut next = || values.next().unwrap_or(0);
+        // DCI format 1_0 of the same size
+        if next() != DCI_FORMAT_UL {
+            return Err(LayerError::InvalidPdu);
+        }
+        Ok(Dci00 {
+            frequency_resource: next() as u16,
+            time_resource: next() as u8,
+            frequency_hopping: next() as u8,
+            modulation_coding_scheme: next() as u8,
+            new_data_indicator: next() as u8,
+            redundancy_version: next() as u8,
+            harq_process_number: next() as u8,
+            tpc_command: next() as u8,
+        })
+    }
+}
+
+/// Pack fields of the given widths, the first in the most significant bits,
+/// returning their size in bits and the payload
+fn pack_fields(fields: &[(u32, u8)]) -> (u16, Bytes) {
+    let size: u16 = fields.iter().map(|(_, width)| *width as u16).sum();
+    let mut buf = BytesMut::with_capacity(size.div_ceil(8) as usize);
+    let (mut acc, mut len) = (0u64, 0u8);
+    for &(value, width) in fields {
+        acc = acc << width | (value as u64 & ((1 << width) - 1));
+        len += width;
+        while len >= 8 {
+            len -= 8;
+            buf.put_u8((acc >> len) as u8);
+        }
+    }
+    if len > 0 {
+        buf.put_u8((acc << (8 - len)) as u8);
+    }
+    (size, buf.freeze())
+}
+
+/// Values of the fields of a payload, by the widths of `fields`
+fn unpack_fields(fields: &[(u32, u8)], payload: &[u8], size_bits: u16) -> Result<Vec<u32>, LayerError> {
+    if fields.iter().map(|(_, width)| *width as u16).sum::<u16>() != size_bits
+        || payload.len() < size_bits.div_ceil(8) as usize {
+        return Err(LayerError::InvalidPdu);
+    }
+
+    let mut position = 0usize;
+    Ok(fields.iter().map(|(_, width)| {
+        let mut value = 0u32;
+        for _ in 0..*width {
+            value = value << 1 | (payload[position / 8] >> (7 - position % 8) & 1) as u32;
+            position += 1;
+        }
+        value
+    }).collect())
+}
+
+/// Bits of the frequency domain resource assignment: ceil(log2(N(N+1)/2))
+fn frequency_resource_bits(coreset_rbs: u16) -> u8 {
+    let n = coreset_rbs as u32;
+    let combinations = (n * (n + 1) / 2).max(1);
+    (32 - (combinations - 1).leading_zeros()) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dci_1_0_payloads() {
+        assert_eq!(frequency_resource_bits(24), 9);
+        assert_eq!(frequency_resource_bits(48), 11);
+        assert_eq!(frequency_resource_bits(96), 13);
+
+        let sib1 = Dci10::SiRnti {
+            frequency_resource: 90, time_resource: 0, vrb_to_prb_mapping: 0, modulation_coding_scheme: 2,
+            redundancy_version: 0, system_information_indicator: 0,
+        };
+        let (size, payload) = sib1.pack(48);
+        assert_eq!(size, 39);
+        assert_eq!(payload.len(), 5);
+        // FDRA 90 in 11 bits, TDRA 0, VRB-to-PRB 0, then MCS 2
+        assert_eq!(&payload[..3], &[0x0B, 0x40, 0x10]);
+        assert_eq!(Dci10::unpack(SI_RNTI, &payload, size, 48).unwrap(), sib1);
+
+        let paging = Dci10::PRnti {
+            short_messages_indicator: 1, short_messages: 0, frequency_resource: 1080, time_resource: 0,
+            vrb_to_prb_mapping: 0, modulation_coding_scheme: 2, tb_scaling: 0,
+        };
+        let (size, payload) = paging.pack(48);
+        assert_eq!(size, 39);
+        assert_eq!(Dci10::unpack(P_RNTI, &payload, size, 48).unwrap(), paging);
+        assert!(Dci10::unpack(P_RNTI, &payload, size, 24).is_err());
+        assert!(Dci10::unpack(0x4601, &payload, size, 48).is_err());
+    }
+
+    #[test]
+    fn test_dci_c_rnti_payloads() {
+        let dl = Dci10::CRnti {
+            rnti: 0x4601, frequency_resource: 1080, time_resource: 0, vrb_to_prb_mapping: 0,
+            modulation_coding_scheme: 9, new_data_indicator: 1, redundancy_version: 0, harq_process_number: 3,
+            downlink_assignment_index: 0, tpc_command: 1, pucch_resource_indicator: 0, harq_feedback_timing: 3,
+        };
+        let (size, payload) = dl.pack(48);
+        // DCI format 1_0 has the same size whatever its RNTI
+        assert_eq!(size, 39);
+        // Identifier 1, then FDRA 1080 in 11 bits
+        assert_eq!(payload[0], 0x80 | (1080 >> 4) as u8);
+        assert_eq!(Dci10::unpack(0x4601, &payload, size, 48).unwrap(), dl);
+        assert_eq!(dl.rnti(), 0x4601);
+
+        let ul = Dci00 {
+            frequency_resource: 1350, time_resource: 0, frequency_hopping: 0, modulation_coding_scheme: 9,
+            new_data_indicator: 1, redundancy_version: 0, harq_process_number: 5, tpc_command: 1,
+        };
+        // 52 RBs take 11 bits like the 48 of CORESET#0: 8 bits of padding
+        let (size, payload) = ul.pack(52, 48);
+        assert_eq!(size, 39);
+        assert_eq!(payload[0] & 0x80, 0);
+        assert_eq!(Dci00::unpack(&payload, size, 52, 48).unwrap(), ul);
+        // Formats 0_0 and 1_0 of the same size are told apart by their identifier
+        assert!(Dci10::unpack(0x4601, &payload, size, 48).is_err());
+        let (size, dl_payload) = dl.pack(48);
+        assert!(Dci00::unpack(&dl_payload, size, 52, 48).is_err());
+
+        // 273 RBs take 16 bits, still fitting in the 37 bits of a 24 RB CORESET#0
+        assert_eq!(ul.pack(273, 24).0, 37);
+        // The FDRA of a BWP too wide for the size of DCI format 1_0 is truncated
+        let wide = Dci00 { frequency_resource: 0xFFFF, ..ul };
+        let (size, payload) = wide.pack(275 * 2, 2);
+        assert_eq!(size, frequency_resource_bits(2) as u16 + DCI_1_0_FIXED_BITS as u16);
+        assert!(Dci00::unpack(&payload, size, 275 * 2, 2).unwrap().frequency_resource < 0xFFFF);
+        assert!(Dci00::unpack(&payload[..2], size, 275 * 2, 2).is_err());
+    }
+}
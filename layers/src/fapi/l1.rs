@@ -0,0 +1,667 @@
+//! L1 side of FAPI
+//!
+//! [`FapiL1`] puts the PHY of this stack behind FAPI. It takes the place of
+//! the MAC as the [`MacPhyInterface`] of the PHY: at the first symbol of each
+//! slot it sends SLOT.indication to the MAC and waits for the DL_TTI.request
+//! (and TX_Data.request) of the slot, which it turns back into the
+//! [`SlotSchedule`] the PHY maps: SSB, SIB1, paging and the PDSCH of the UEs. PRACH detections and transport blocks
+//! received by the PHY go to the MAC as RACH.indication and
+//! RX_Data.indication.
+//!
+//! The PHY keeps the configuration it was built with: CONFIG.request is
+//! checked and kept, not applied.
+
+use super::dci::Dci10;
+use super::l2::qam_mod_order;
+use super::p5::{ConfigRequest, ConfigResponse, ErrorCode, ErrorIndication};
+use super::p7::{
+    timing_advance_step_us, CrcIndication, DlDci, DlTtiPdu, DlTtiRequest, PdcchPdu, PdschPdu, RachIndication,
+    RachOccasion, RachPreamble, RxDataIndication, RxPdu, SlotIndication, TxDataRequest, UciIndication, UlDciRequest,
+    UlTtiRequest, MAX_RACH_TIMING_ADVANCE,
+};
+use super::transport::{FapiReceiver, FapiSender};
+use super::{FapiMessage, FapiMessageType};
+use crate::mac::scheduler::{Coreset0Config, PdschTimeAlloc};
+use crate::mac::{
+    MacPhyInterface, PagingScheduleInfo, Sib1ScheduleInfo, SlotSchedule, SsbScheduleInfo, UeDlGrant, P_RNTI,
+};
+use crate::phy::prach::{self, PrachDetectionResult};
+use crate::LayerError;
+use async_trait::async_trait;
+use bytes::Bytes;
+use common::types::Rnti;
+use common::{CorsetConfig, ModulationScheme};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Slots whose requests are kept ahead of the PHY
+const MAX_PENDING_SLOTS: usize = 16;
+/// UL_TTI.requests kept for the uplink processing
+const MAX_UL_REQUESTS: usize = 64;
+/// First symbols of the SSBs of a slot, for Case A (TS 38.213 section 4.1)
+const SSB_CASE_A_SYMBOLS: [u8; 2] = [2, 8];
+
+/// State of the L1 (SCF 222.10.02 section 2.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1State {
+    Idle,
+    Configured,
+    Running,
+}
+
+/// P7 requests received for a slot
+#[derive(Debug, Default)]
+struct SlotRequests {
+    dl_tti: Option<DlTtiRequest>,
+    ul_dci: Option<UlDciRequest>,
+    tx_data: Option<TxDataRequest>,
+}
+
+impl SlotRequests {
+    /// All the requests the PHY needs for the slot have arrived
+    fn is_complete(&self) -> bool {
+        match &self.dl_tti {
+            Some(dl_tti) => {
+                self.tx_data.is_some() || !dl_tti.pdus.iter().any(|pdu| matches!(pdu, DlTtiPdu::Pdsch(_)))
+            }
+            None => false,
+        }
+    }
+}
+
+struct L1Context {
+    state: L1State,
+    config: Option<ConfigRequest>,
+    /// Requests of the coming slots, by SFN and slot
+    slots: VecDeque<((u16, u16), SlotRequests)>,
+    /// Schedule of the slot the PHY is in, `None` if the MAC did not answer in time
+    current: Option<(u32, u8, Option<SlotSchedule>)>,
+    /// Slot the MAC did not answer in time
+    late_slot: Option<(u16, u16)>,
+    ul_requests: VecDeque<UlTtiRequest>,
+    /// Last SIB1 sent by the MAC
+    sib1_payload: Option<Bytes>,
+}
+
+/// L1 side of FAPI in front of the PHY
+pub struct FapiL1 {
+    sender: FapiSender,
+    context: Mutex<L1Context>,
+    slot_ready: Notify,
+    /// Time the MAC has to send the requests of a slot
+    response_timeout: Duration,
+}
+
+impl FapiL1 {
+    /// Create the L1 side of FAPI, waiting up to `response_timeout` for the
+    /// requests of each slot
+    pub fn new(sender: FapiSender, response_timeout: Duration) -> Self {
+        Self {
+            sender,
+            context: Mutex::new(L1Context {
+                state: L1State::Idle,
+                config: None,
+                slots: VecDeque::new(),
+                current: None,
+                late_slot: None,
+                ul_requests: VecDeque::new(),
+                sib1_payload: None,
+            }),
+            slot_ready: Notify::new(),
+            response_timeout,
+        }
+    }
+
+    /// State of the L1
+    pub async fn state(&self) -> L1State {
+        self.context.lock().await.state
+    }
+
+    /// Configuration received in CONFIG.request
+    pub async fn config(&self) -> Option<ConfigRequest> {
+        self.context.lock().await.config.clone()
+    }
+
+    /// UL_TTI.requests received since the last call
+    pub async fn take_ul_requests(&self) -> Vec<UlTtiRequest> {
+        self.context.lock().await.ul_requests.drain(..).collect()
+    }
+
+    /// Serve the MAC until it is gone
+    pub async fn run(&self, receiver: &mut FapiReceiver) -> Result<(), LayerError> {
+        while let Some(message) = receiver.recv().await {
+            match message {
+                FapiMessage::ConfigRequest(config) => self.handle_config(config).await?,
+                FapiMessage::StartRequest => {
+                    let mut context = self.context.lock().await;
+                    if context.state == L1State::Configured {
+                        context.state = L1State::Running;
+                        info!("L1 running");
+                    } else {
+                        drop(context);
+                        self.send_error(0, 0, FapiMessageType::StartRequest, ErrorCode::MsgInvalidState).await?;
+                    }
+                }
+                FapiMessage::StopRequest => {
+                    let mut context = self.context.lock().await;
+                    if context.state == L1State::Running {
+                        context.state = L1State::Configured;
+                        context.slots.clear();
+                        context.current = None;
+                        drop(context);
+                        info!("L1 stopped");
+                        self.sender.send(FapiMessage::StopIndication).await?;
+                    } else {
+                        drop(context);
+                        self.send_error(0, 0, FapiMessageType::StopRequest, ErrorCode::MsgInvalidState).await?;
+                    }
+                }
+                FapiMessage::DlTtiRequest(request) => {
+                    let (sfn, slot) = (request.sfn, request.slot);
+                    self.store(sfn, slot, FapiMessageType::DlTtiRequest, |requests| requests.dl_tti = Some(request))
+                        .await?
+                }
+                FapiMessage::UlDciRequest(request) => {
+                    let (sfn, slot) = (request.sfn, request.slot);
+                    self.store(sfn, slot, FapiMessageType::UlDciRequest, |requests| requests.ul_dci = Some(request))
+                        .await?
+                }
+                FapiMessage::TxDataRequest(request) => {
+                    let (sfn, slot) = (request.sfn, request.slot);
+                    self.store(sfn, slot, FapiMessageType::TxDataRequest, |requests| requests.tx_data = Some(request))
+                        .await?
+                }
+                FapiMessage::UlTtiRequest(request) => {
+                    let mut context = self.context.lock().await;
+                    if context.state != L1State::Running {
+                        drop(context);
+                        self.send_error(request.sfn, request.slot, FapiMessageType::UlTtiRequest,
+                                        ErrorCode::MsgInvalidState).await?;
+                        continue;
+                    }
+                    if context.ul_requests.len() == MAX_UL_REQUESTS {
+                        context.ul_requests.pop_front();
+                    }
+                    context.ul_requests.push_back(request);
+                }
+                message => warn!("Unexpected FAPI {:?} from the MAC", message.message_type()),
+            }
+        }
+        info!("FAPI connection to the MAC closed");
+        Ok(())
+    }
+
+    /// Check and keep a configuration
+    async fn handle_config(&self, config: ConfigRequest) -> Result<(), LayerError> {
+        let mut context = self.context.lock().await;
+        let response = if context.state == L1State::Running {
+            ConfigResponse { error_code: ErrorCode::MsgInvalidState, invalid_tags: Vec::new() }
+        } else {
+            let invalid_tags = config.invalid_tags();
+            if invalid_tags.is_empty() {
+                info!("L1 configured: PCI {}, {} PRBs", config.phy_cell_id, config.dl_grid_size);
+                context.state = L1State::Configured;
+                context.config = Some(config);
+                ConfigResponse { error_code: ErrorCode::MsgOk, invalid_tags }
+            } else {
+                warn!("Rejecting CONFIG.request with invalid TLVs {:04x?}", invalid_tags);
+                ConfigResponse { error_code: ErrorCode::MsgInvalidConfig, invalid_tags }
+            }
+        };
+        drop(context);
+        self.sender.send(FapiMessage::ConfigResponse(response)).await
+    }
+
+    /// Keep a request for the slot it is meant for
+    async fn store(&self, sfn: u16, slot: u16, message_type: FapiMessageType, update: impl FnOnce(&mut SlotRequests))
+        -> Result<(), LayerError> {
+        let mut context = self.context.lock().await;
+        let error = if context.state != L1State::Running {
+            Some(ErrorCode::MsgInvalidState)
+        } else if context.late_slot == Some((sfn, slot)) {
+            Some(ErrorCode::MsgSlotErr)
+        } else {
+            None
+        };
+        if let Some(error_code) = error {
+            drop(context);
+            return self.send_error(sfn, slot, message_type, error_code).await;
+        }
+
+        let index = match context.slots.iter().position(|(key, _)| *key == (sfn, slot)) {
+            Some(index) => index,
+            None => {
+                if context.slots.len() == MAX_PENDING_SLOTS {
+                    context.slots.pop_front();
+                }
+                context.slots.push_back(((sfn, slot), SlotRequests::default()));
+                context.slots.len() - 1
+            }
+        };
+        update(&mut context.slots[index].1);
+        drop(context);
+        self.slot_ready.notify_waiters();
+        Ok(())
+    }
+
+    async fn send_error(&self, sfn: u16, slot: u16, message_type: FapiMessageType, error_code: ErrorCode)
+        -> Result<(), LayerError> {
+        warn!("Rejecting FAPI {:?} for {}.{}: {:?}", message_type, sfn, slot, error_code);
+        let indication = ErrorIndication { sfn, slot, message_id: message_type as u8, error_code };
+        self.sender.send(FapiMessage::ErrorIndication(indication)).await
+    }
+
+    /// Send an RX_Data.indication
+    pub async fn indicate_rx_data(&self, indication: RxDataIndication) -> Result<(), LayerError> {
+        self.indicate(FapiMessage::RxDataIndication(indication)).await
+    }
+
+    /// Send a CRC.indication
+    pub async fn indicate_crc(&self, indication: CrcIndication) -> Result<(), LayerError> {
+        self.indicate(FapiMessage::CrcIndication(indication)).await
+    }
+
+    /// Send a UCI.indication
+    pub async fn indicate_uci(&self, indication: UciIndication) -> Result<(), LayerError> {
+        self.indicate(FapiMessage::UciIndication(indication)).await
+    }
+
+    async fn indicate(&self, message: FapiMessage) -> Result<(), LayerError> {
+        if self.context.lock().await.state != L1State::Running {
+            return Err(LayerError::InvalidState("L1 not running".into()));
+        }
+        self.sender.send(message).await
+    }
+
+    /// SFN and slot of the slot the PHY is in
+    async fn current_slot(&self) -> (u16, u16) {
+        match &self.context.lock().await.current {
+            Some((frame, slot, _)) => ((*frame % 1024) as u16, *slot as u16),
+            None => (0, 0),
+        }
+    }
+
+    /// Wait for the requests of a slot
+    async fn wait_for_slot(&self, sfn: u16, slot: u16) -> Option<SlotRequests> {
+        let deadline = Instant::now() + self.response_timeout;
+        loop {
+            let notified = self.slot_ready.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut context = self.context.lock().await;
+                if let Some(index) = context.slots.iter().position(|(key, requests)| {
+                    *key == (sfn, slot) && requests.is_complete()
+                }) {
+                    // Requests of the slots before are too late now
+                    return context.slots.drain(..=index).next_back().map(|(_, requests)| requests);
+                }
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    /// Schedule of a slot from its requests
+    async fn slot_schedule(&self, frame: u32, slot: u8, requests: SlotRequests) -> SlotSchedule {
+        let mut schedule = SlotSchedule { frame, slot, ssb_info: None, sib1_info: None, paging_info: None, dl_grants: Vec::new(), ul_grants: Vec::new() };
+        let dl_tti = requests.dl_tti.unwrap_or(DlTtiRequest { sfn: 0, slot: 0, pdus: Vec::new() });
+        if let Some(ul_dci) = &requests.ul_dci {
+            debug!("Ignoring {} UL DCI PDCCHs, the PHY has no PUSCH receiver to grant", ul_dci.pdcchs.len());
+        }
+
+        let pdcchs: Vec<&PdcchPdu> = dl_tti.pdus.iter().filter_map(|pdu| match pdu {
+            DlTtiPdu::Pdcch(pdcch) => Some(pdcch),
+            _ => None,
+        }).collect();
+        for pdu in &dl_tti.pdus {
+            match pdu {
+                DlTtiPdu::Ssb(ssb) => {
+                    schedule.ssb_info = Some(SsbScheduleInfo {
+                        ssb_index: ssb.ssb_block_index,
+                        start_symbol: SSB_CASE_A_SYMBOLS[ssb.ssb_block_index as usize % 2],
+                    });
+                }
+                DlTtiPdu::Pdsch(pdsch) => {
+                    let dci = pdcchs.iter().find_map(|pdcch| {
+                        pdcch.dl_dcis.iter().find(|dci| dci.rnti == pdsch.rnti).map(|dci| (*pdcch, dci))
+                    });
+                    let payload = requests.tx_data.as_ref().and_then(|tx_data| {
+                        tx_data.pdus.iter().find(|tx| tx.pdu_index == pdsch.pdu_index).map(|tx| tx.payload.clone())
+                    });
+                    let (Some((pdcch, dci)), Some(payload)) = (dci, payload) else {
+                        warn!("Dropping PDSCH of RNTI {} without DCI or payload", pdsch.rnti);
+                        continue;
+                    };
+                    let pdsch = match ScheduledPdsch::new(pdcch, dci, pdsch) {
+                        Ok(pdsch) => pdsch,
+                        Err(e) => {
+                            warn!("Dropping PDSCH of RNTI {}: {}", pdsch.rnti, e);
+                            continue;
+                        }
+                    };
+                    match pdsch.dci {
+                        Dci10::SiRnti { .. } => {
+                            self.context.lock().await.sib1_payload = Some(payload);
+                            schedule.sib1_info = Some(pdsch.sib1_info());
+                        }
+                        Dci10::PRnti { .. } => schedule.paging_info = Some(pdsch.paging_info(payload)),
+                        Dci10::CRnti { .. } => schedule.dl_grants.push(pdsch.dl_grant(payload)),
+                    }
+                }
+                DlTtiPdu::Pdcch(_) => {}
+            }
+        }
+        schedule
+    }
+}
+
+/// PDSCH of SIB1, paging or a UE as received in DL_TTI.request
+struct ScheduledPdsch {
+    dci: Dci10,
+    coreset: CorsetConfig,
+    aggregation_level: u8,
+    cce_index: u16,
+    pdsch_time_alloc: PdschTimeAlloc,
+    tbs_bytes: usize,
+    modulation: ModulationScheme,
+    mcs_table: u8,
+    prb_allocation: Vec<u16>,
+}
+
+impl ScheduledPdsch {
+    fn new(pdcch: &PdcchPdu, dci: &DlDci, pdsch: &PdschPdu) -> Result<Self, LayerError> {
+        let coreset_rbs = pdcch.coreset_rbs();
+        let modulation = [ModulationScheme::Qpsk, ModulationScheme::Qam16, ModulationScheme::Qam64,
+                          ModulationScheme::Qam256]
+            .into_iter()
+            .find(|modulation| qam_mod_order(*modulation) == pdsch.qam_mod_order)
+            .ok_or(LayerError::InvalidPdu)?;
+        let prb_start = pdsch.bwp_start + pdsch.rb_start;
+        Ok(Self {
+            dci: Dci10::unpack(dci.rnti, &dci.payload, dci.payload_size_bits, coreset_rbs.len() as u16)?,
+            coreset: CorsetConfig {
+                start_symbol: pdcch.start_symbol_index,
+                duration: pdcch.duration_symbols,
+                frequency_domain_resources: coreset_rbs,
+            },
+            aggregation_level: dci.aggregation_level,
+            cce_index: dci.cce_index,
+            pdsch_time_alloc: PdschTimeAlloc {
+                start_symbol: pdsch.start_symbol_index,
+                num_symbols: pdsch.nr_of_symbols,
+            },
+            tbs_bytes: pdsch.tb_size as usize,
+            modulation,
+            mcs_table: pdsch.mcs_table,
+            prb_allocation: (prb_start..prb_start + pdsch.rb_size).collect(),
+        })
+    }
+
+    fn dl_grant(self, payload: Bytes) -> UeDlGrant {
+        let (harq_process, ndi) = match self.dci {
+            Dci10::CRnti { harq_process_number, new_data_indicator, .. } => (harq_process_number, new_data_indicator != 0),
+            _ => (0, true),
+        };
+        UeDlGrant {
+            rnti: Rnti(self.dci.rnti()),
+            pdsch_time_alloc: self.pdsch_time_alloc,
+            coreset: self.coreset,
+            frequency_domain_assignment: self.dci.frequency_resource(),
+            time_domain_assignment: self.dci.time_resource(),
+            mcs_index: self.dci.modulation_coding_scheme(),
+            mcs_table: self.mcs_table,
+            aggregation_level: self.aggregation_level,
+            cce_index: self.cce_index,
+            harq_process,
+            ndi,
+            tbs_bytes: self.tbs_bytes,
+            modulation: self.modulation,
+            prb_allocation: self.prb_allocation,
+            payload,
+        }
+    }
+
+    fn sib1_info(self) -> Sib1ScheduleInfo {
+        Sib1ScheduleInfo {
+            coreset0: Coreset0Config {
+                num_rbs: self.coreset.frequency_domain_resources.len() as u32,
+                num_symbols: self.coreset.duration as u32,
+                rb_offset: self.coreset.frequency_domain_resources.first().copied().unwrap_or(0) as u32,
+            },
+            pdsch_time_alloc: self.pdsch_time_alloc,
+            payload_size: self.tbs_bytes,
+            coreset: self.coreset,
+            frequency_domain_assignment: self.dci.frequency_resource(),
+            time_domain_assignment: self.dci.time_resource(),
+            mcs_index: self.dci.modulation_coding_scheme(),
+            aggregation_level: self.aggregation_level,
+            cce_index: self.cce_index,
+            tbs_bytes: self.tbs_bytes,
+            modulation: self.modulation,
+            prb_allocation: self.prb_allocation,
+        }
+    }
+
+    fn paging_info(self, payload: Bytes) -> PagingScheduleInfo {
+        PagingScheduleInfo {
+            rnti: P_RNTI,
+            pdsch_time_alloc: self.pdsch_time_alloc,
+            coreset: self.coreset,
+            frequency_domain_assignment: self.dci.frequency_resource(),
+            time_domain_assignment: self.dci.time_resource(),
+            mcs_index: self.dci.modulation_coding_scheme(),
+            aggregation_level: self.aggregation_level,
+            cce_index: self.cce_index,
+            tbs_bytes: self.tbs_bytes,
+            modulation: self.modulation,
+            prb_allocation: self.prb_allocation,
+            payload,
+        }
+    }
+}
+
+#[async_trait]
+impl MacPhyInterface for FapiL1 {
+    async fn get_slot_schedule(&self, frame: u32, slot: u8) -> Result<SlotSchedule, LayerError> {
+        let mut context = self.context.lock().await;
+        if context.state != L1State::Running {
+            return Err(LayerError::InvalidState("L1 not running".into()));
+        }
+        // The PHY asks at every symbol, the MAC is asked once per slot
+        if let Some((current_frame, current_slot, schedule)) = &context.current {
+            if (*current_frame, *current_slot) == (frame, slot) {
+                return schedule.clone().ok_or_else(|| {
+                    LayerError::ProcessingError(format!("No DL_TTI.request for {}.{}", frame, slot))
+                });
+            }
+        }
+        context.current = Some((frame, slot, None));
+        drop(context);
+
+        let (sfn, fapi_slot) = ((frame % 1024) as u16, slot as u16);
+        self.sender.send(FapiMessage::SlotIndication(SlotIndication { sfn, slot: fapi_slot })).await?;
+        let Some(requests) = self.wait_for_slot(sfn, fapi_slot).await else {
+            self.context.lock().await.late_slot = Some((sfn, fapi_slot));
+            return Err(LayerError::ProcessingError(format!("No DL_TTI.request for {}.{} in time", frame, slot)));
+        };
+
+        let schedule = self.slot_schedule(frame, slot, requests).await;
+        let mut context = self.context.lock().await;
+        if context.current.as_ref().is_some_and(|(f, s, _)| (*f, *s) == (frame, slot)) {
+            context.current = Some((frame, slot, Some(schedule.clone())));
+        }
+        Ok(schedule)
+    }
+
+    async fn get_sib1_payload(&self) -> Result<Bytes, LayerError> {
+        self.context.lock().await.sib1_payload.clone()
+            .ok_or_else(|| LayerError::InvalidState("No SIB1 received from the MAC".into()))
+    }
+
+    async fn report_prach_detection(&self, detection: PrachDetectionResult) -> Result<(), LayerError> {
+        let (phy_cell_id, scs_common, prach_config_index) = match &self.context.lock().await.config {
+            Some(config) => (config.phy_cell_id, config.scs_common, config.prach_config_index),
+            None => return Err(LayerError::NotInitialized),
+        };
+        let step_us = timing_advance_step_us(scs_common);
+        let symbol_index = prach::prach_occasion(prach_config_index, detection.frame, detection.slot)
+            .map_or(0, |occasion| occasion.starting_symbol);
+        let occasion = RachOccasion {
+            phy_cell_id,
+            symbol_index,
+            slot_index: detection.slot,
+            ra_index: 0,
+            avg_rssi: ((detection.rssi_dbm + 140.0) * 10.0).clamp(0.0, 1700.0) as u16,
+            // The PRACH detector measures no SNR, reported as 0 dB
+            avg_snr: 128,
+            preambles: detection.preambles.iter().map(|preamble| RachPreamble {
+                preamble_index: preamble.preamble_index,
+                timing_advance: ((preamble.timing_advance_us / step_us).round() as u16).min(MAX_RACH_TIMING_ADVANCE),
+                preamble_pwr: ((preamble.power_dbm + 140.0) * 1000.0).clamp(0.0, 170_000.0) as u32,
+            }).collect(),
+        };
+        let indication = RachIndication {
+            sfn: (detection.frame % 1024) as u16,
+            slot: detection.slot as u16,
+            occasions: vec![occasion],
+        };
+        self.indicate(FapiMessage::RachIndication(indication)).await
+    }
+
+    async fn report_rx_data(&self, rnti: u16, data: Bytes) -> Result<(), LayerError> {
+        let (sfn, slot) = self.current_slot().await;
+        let indication = RxDataIndication {
+            sfn,
+            slot,
+            pdus: vec![RxPdu {
+                handle: 0,
+                rnti,
+                harq_id: 0,
+                // Link quality is not measured by the PHY: 0 dB SNR, no timing correction
+                ul_cqi: 128,
+                timing_advance: 31,
+                rssi: 0,
+                payload: data,
+            }],
+        };
+        self.indicate_rx_data(indication).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fapi::transport::in_process;
+    use crate::fapi::p5::tag;
+    use crate::phy::prach::PreambleDetection;
+    use std::sync::Arc;
+
+    fn valid_config() -> ConfigRequest {
+        ConfigRequest { dl_grid_size: 52, ul_grid_size: 52, phy_cell_id: 1, ..Default::default() }
+    }
+
+    async fn recv(receiver: &mut FapiReceiver) -> FapiMessage {
+        tokio::time::timeout(Duration::from_secs(1), receiver.recv()).await.unwrap().unwrap()
+    }
+
+    fn error(sfn: u16, slot: u16, message_type: FapiMessageType, error_code: ErrorCode) -> FapiMessage {
+        FapiMessage::ErrorIndication(ErrorIndication { sfn, slot, message_id: message_type as u8, error_code })
+    }
+
+    #[tokio::test]
+    async fn test_l1_failures() {
+        let ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) = in_process(16);
+        let l1 = Arc::new(FapiL1::new(l1_tx, Duration::from_millis(50)));
+        let l1_task = tokio::spawn({
+            let l1 = Arc::clone(&l1);
+            async move { l1.run(&mut l1_rx).await }
+        });
+
+        // Nothing but CONFIG.request is accepted in IDLE
+        mac_tx.send(FapiMessage::StartRequest).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(0, 0, FapiMessageType::StartRequest, ErrorCode::MsgInvalidState));
+        mac_tx.send(FapiMessage::StopRequest).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(0, 0, FapiMessageType::StopRequest, ErrorCode::MsgInvalidState));
+        mac_tx.send(FapiMessage::DlTtiRequest(DlTtiRequest { sfn: 4, slot: 1, pdus: vec![] })).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(4, 1, FapiMessageType::DlTtiRequest, ErrorCode::MsgInvalidState));
+        mac_tx.send(FapiMessage::UlTtiRequest(UlTtiRequest { sfn: 4, slot: 2, pdus: vec![] })).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(4, 2, FapiMessageType::UlTtiRequest, ErrorCode::MsgInvalidState));
+        assert!(matches!(l1.get_slot_schedule(0, 0).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(l1.get_sib1_payload().await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(l1.indicate_crc(CrcIndication { sfn: 0, slot: 0, crcs: vec![] }).await,
+                         Err(LayerError::InvalidState(_))));
+        let detection = PrachDetectionResult {
+            frame: 1,
+            slot: 9,
+            rssi_dbm: -90.0,
+            preambles: vec![PreambleDetection {
+                preamble_index: 1,
+                timing_advance_samples: 0,
+                timing_advance_us: 0.0,
+                detection_metric: 3.0,
+                power_dbm: -95.0,
+            }],
+            time_resolution_us: 0.0326,
+            max_timing_advance_us: 200.0,
+        };
+        assert!(matches!(l1.report_prach_detection(detection).await, Err(LayerError::NotInitialized)));
+
+        // An invalid configuration leaves the L1 in IDLE
+        mac_tx.send(FapiMessage::ConfigRequest(ConfigRequest { phy_cell_id: 1008, ..valid_config() })).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, FapiMessage::ConfigResponse(ConfigResponse {
+            error_code: ErrorCode::MsgInvalidConfig,
+            invalid_tags: vec![tag::PHY_CELL_ID],
+        }));
+        assert_eq!((l1.state().await, l1.config().await), (L1State::Idle, None));
+
+        // Running, the L1 cannot be reconfigured and messages of the L1 from
+        // the MAC are ignored
+        mac_tx.send(FapiMessage::ConfigRequest(valid_config())).await.unwrap();
+        assert!(matches!(recv(&mut mac_rx).await, FapiMessage::ConfigResponse(ConfigResponse { error_code: ErrorCode::MsgOk, .. })));
+        mac_tx.send(FapiMessage::StartRequest).await.unwrap();
+        mac_tx.send(FapiMessage::SlotIndication(SlotIndication { sfn: 0, slot: 0 })).await.unwrap();
+        mac_tx.send(FapiMessage::ConfigRequest(valid_config())).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, FapiMessage::ConfigResponse(ConfigResponse {
+            error_code: ErrorCode::MsgInvalidState,
+            invalid_tags: vec![],
+        }));
+        assert_eq!(l1.state().await, L1State::Running);
+
+        // A slot the MAC does not answer in time fails, and so do the requests
+        // arriving for it afterwards
+        assert!(matches!(l1.get_slot_schedule(3, 2).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(recv(&mut mac_rx).await, FapiMessage::SlotIndication(SlotIndication { sfn: 3, slot: 2 }));
+        assert!(matches!(l1.get_slot_schedule(3, 2).await, Err(LayerError::ProcessingError(_))));
+        mac_tx.send(FapiMessage::DlTtiRequest(DlTtiRequest { sfn: 3, slot: 2, pdus: vec![] })).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(3, 2, FapiMessageType::DlTtiRequest, ErrorCode::MsgSlotErr));
+
+        // A DL_TTI.request with a PDSCH waits for its TX_Data.request
+        mac_tx.send(FapiMessage::DlTtiRequest(DlTtiRequest {
+            sfn: 3,
+            slot: 3,
+            pdus: vec![DlTtiPdu::Pdsch(PdschPdu {
+                rnti: 0x4601, pdu_index: 0, bwp_size: 48, bwp_start: 0, subcarrier_spacing: 0, cyclic_prefix: 0,
+                target_code_rate: 1930, qam_mod_order: 2, mcs_index: 2, mcs_table: 0, rv_index: 0, tb_size: 10,
+                data_scrambling_id: 1, nr_of_layers: 1, ref_point: 1, dl_dmrs_symb_pos: 0x0004, dmrs_config_type: 0,
+                rb_start: 0, rb_size: 4, vrb_to_prb_mapping: 0, start_symbol_index: 2, nr_of_symbols: 4,
+            })],
+        })).await.unwrap();
+        assert!(matches!(l1.get_slot_schedule(3, 3).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(recv(&mut mac_rx).await, FapiMessage::SlotIndication(SlotIndication { sfn: 3, slot: 3 }));
+
+        // Stopped, the L1 is back in CONFIGURED and a second stop is refused
+        mac_tx.send(FapiMessage::StopRequest).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, FapiMessage::StopIndication);
+        mac_tx.send(FapiMessage::StopRequest).await.unwrap();
+        assert_eq!(recv(&mut mac_rx).await, error(0, 0, FapiMessageType::StopRequest, ErrorCode::MsgInvalidState));
+        assert_eq!(l1.state().await, L1State::Configured);
+
+        drop(mac_tx);
+        l1_task.await.unwrap().unwrap();
+    }
+}
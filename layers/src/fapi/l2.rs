@@ -0,0 +1,661 @@
+//! MAC side of FAPI
+//!
+//! [`FapiL2`] drives an L1 on behalf of the MAC: it configures and starts the
+//! L1 with P5 messages, answers each SLOT.indication with the DL_TTI.request,
+//! UL_TTI.request, UL_DCI.request and TX_Data.request built from the schedule
+//! of the MAC, and reports the preambles and transport blocks the L1 receives
+//! back to the MAC through [`MacPhyInterface`]. The PUSCH granted by a DCI is
+//! requested in the UL_TTI.request of the slot it is sent in, K2 slots later.
+
+use super::dci::{Dci00, Dci10};
+use super::p5::{ConfigRequest, ErrorCode};
+use super::p7::{
+    timing_advance_step_us, DlDci, DlTtiPdu, DlTtiRequest, PdcchPdu, PdschPdu, PrachPdu, PuschPdu, RachIndication,
+    SlotIndication, SsbPdu, TxDataRequest, TxPdu, UlDciRequest, UlTtiPdu, UlTtiRequest, MAX_RACH_TIMING_ADVANCE,
+};
+use super::transport::{FapiReceiver, FapiSender};
+use super::FapiMessage;
+use crate::mac::mcs::mcs_table_of_index;
+use crate::mac::{MacPhyInterface, SlotSchedule, UeUlGrant};
+use crate::phy::prach::{self, PrachDetectionResult, PrachFormat, PreambleDetection};
+use crate::LayerError;
+use bytes::Bytes;
+use common::{CorsetConfig, ModulationScheme};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Time the L1 has to answer CONFIG.request
+const CONFIG_RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest K2 of a PUSCH time domain allocation (TS 38.331 PUSCH-TimeDomainResourceAllocation)
+const MAX_K2: u32 = 32;
+/// Downlink assignment of DCI format 1_0: PUCCH resource 0, HARQ-ACK K1 = 4 slots, TPC 0 dB
+const TPC_COMMAND_0_DB: u8 = 1;
+const HARQ_FEEDBACK_TIMING_K1_4: u8 = 3;
+
+/// Counters of the messages exchanged with the L1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FapiL2Stats {
+    pub slot_indications: u64,
+    pub dl_tti_requests: u64,
+    pub ul_tti_requests: u64,
+    pub ul_dci_requests: u64,
+    pub tx_data_requests: u64,
+    pub rach_indications: u64,
+    pub rx_data_indications: u64,
+    pub crc_indications: u64,
+    /// Transport blocks received with a CRC error
+    pub crc_failures: u64,
+    pub uci_indications: u64,
+    pub error_indications: u64,
+}
+
+/// PDSCH scheduled in CORESET#0, SIB1, paging or the DL-SCH of a UE, with its DCI
+struct ScheduledPdsch<'a> {
+    dci: Dci10,
+    coreset: &'a CorsetConfig,
+    aggregation_level: u8,
+    cce_index: u16,
+    start_symbol: u8,
+    num_symbols: u8,
+    tbs_bytes: usize,
+    modulation: ModulationScheme,
+    mcs_table: u8,
+    prb_allocation: &'a [u16],
+    payload: Bytes,
+}
+
+/// MAC side of FAPI
+pub struct FapiL2 {
+    mac: Arc<dyn MacPhyInterface>,
+    config: ConfigRequest,
+    sender: FapiSender,
+    stats: Mutex<FapiL2Stats>,
+    /// PUSCHs granted for the coming slots, by slot counted from SFN 0
+    pending_puschs: Mutex<Vec<(u32, PuschPdu)>>,
+}
+
+impl FapiL2 {
+    /// Create the MAC side of FAPI for an L1 to be configured with `config`
+    pub fn new(mac: Arc<dyn MacPhyInterface>, config: ConfigRequest, sender: FapiSender) -> Self {
+        Self { mac, config, sender, stats: Mutex::new(FapiL2Stats::default()), pending_puschs: Mutex::new(Vec::new()) }
+    }
+
+    /// Counters of the messages exchanged so far
+    pub fn stats(&self) -> FapiL2Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Configure the L1 and start it
+    pub async fn start(&self, receiver: &mut FapiReceiver) -> Result<(), LayerError> {
+        self.sender.send(FapiMessage::ConfigRequest(self.config.clone())).await?;
+        let response = tokio::time::timeout(CONFIG_RESPONSE_TIMEOUT, async {
+            loop {
+                match receiver.recv().await {
+                    Some(FapiMessage::ConfigResponse(response)) => return Some(response),
+                    Some(message) => debug!("Ignoring FAPI {:?} before CONFIG.response", message.message_type()),
+                    None => return None,
+                }
+            }
+        })
+        .await
+        .map_err(|_| LayerError::InitializationFailed("No CONFIG.response from the L1".to_string()))?
+        .ok_or_else(|| LayerError::InitializationFailed("L1 gone before CONFIG.response".to_string()))?;
+
+        if response.error_code != ErrorCode::MsgOk {
+            return Err(LayerError::InitializationFailed(format!(
+                "L1 rejected CONFIG.request with {:?}, invalid TLVs {:04x?}",
+                response.error_code, response.invalid_tags
+            )));
+        }
+        self.sender.send(FapiMessage::StartRequest).await?;
+        info!("L1 configured and started");
+        Ok(())
+    }
+
+    /// Stop the L1
+    pub async fn stop(&self) -> Result<(), LayerError> {
+        self.sender.send(FapiMessage::StopRequest).await
+    }
+
+    /// Serve the L1 until it stops
+    pub async fn run(&self, receiver: &mut FapiReceiver) -> Result<(), LayerError> {
+        while let Some(message) = receiver.recv().await {
+            match message {
+                FapiMessage::SlotIndication(indication) => {
+                    self.stats.lock().unwrap().slot_indications += 1;
+                    self.handle_slot(indication).await?;
+                }
+                FapiMessage::RachIndication(indication) => {
+                    self.stats.lock().unwrap().rach_indications += 1;
+                    for detection in self.prach_detections(&indication) {
+                        if let Err(e) = self.mac.report_prach_detection(detection).await {
+                            warn!("MAC rejected the RACH.indication: {}", e);
+                        }
+                    }
+                }
+                FapiMessage::RxDataIndication(indication) => {
+                    self.stats.lock().unwrap().rx_data_indications += 1;
+                    for pdu in indication.pdus {
+                        if let Err(e) = self.mac.report_rx_data(pdu.rnti, pdu.payload).await {
+                            warn!("MAC rejected the transport block of RNTI {}: {}", pdu.rnti, e);
+                        }
+                    }
+                }
+                FapiMessage::CrcIndication(indication) => {
+                    let mut stats = self.stats.lock().unwrap();
+                    stats.crc_indications += 1;
+                    stats.crc_failures += indication.crcs.iter().filter(|crc| !crc.tb_crc_ok).count() as u64;
+                }
+                FapiMessage::UciIndication(indication) => {
+                    debug!("UCI.indication with {} UCIs in {}.{}", indication.ucis.len(), indication.sfn, indication.slot);
+                    self.stats.lock().unwrap().uci_indications += 1;
+                }
+                FapiMessage::ErrorIndication(indication) => {
+                    warn!("L1 error {:?} for message 0x{:02x} in {}.{}",
+                          indication.error_code, indication.message_id, indication.sfn, indication.slot);
+                    self.stats.lock().unwrap().error_indications += 1;
+                }
+                FapiMessage::StopIndication => {
+                    info!("L1 stopped");
+                    return Ok(());
+                }
+                message => warn!("Unexpected FAPI {:?} from the L1", message.message_type()),
+            }
+        }
+        Err(LayerError::ProcessingError("FAPI connection to the L1 lost".to_string()))
+    }
+
+    /// Send the requests of a slot
+    async fn handle_slot(&self, indication: SlotIndication) -> Result<(), LayerError> {
+        let SlotIndication { sfn, slot } = indication;
+        let schedule = match self.mac.get_slot_schedule(sfn as u32, slot as u8).await {
+            Ok(schedule) => Some(schedule),
+            Err(e) => {
+                debug!("No MAC schedule for {}.{}: {}", sfn, slot, e);
+                None
+            }
+        };
+        let sib1_payload = match schedule.as_ref().and_then(|schedule| schedule.sib1_info.as_ref()) {
+            Some(_) => self.mac.get_sib1_payload().await.map_err(|e| warn!("No SIB1 payload: {}", e)).ok(),
+            None => None,
+        };
+
+        let (dl_tti, tx_data) = self.dl_requests(sfn, slot, schedule.as_ref(), sib1_payload);
+        self.sender.send(FapiMessage::DlTtiRequest(dl_tti)).await?;
+        self.stats.lock().unwrap().dl_tti_requests += 1;
+
+        let ul_grants = schedule.as_ref().map_or(&[][..], |schedule| &schedule.ul_grants[..]);
+        self.queue_puschs(sfn, slot, ul_grants);
+        let ul_tti = self.ul_tti_request(sfn, slot);
+        if !ul_tti.pdus.is_empty() {
+            self.sender.send(FapiMessage::UlTtiRequest(ul_tti)).await?;
+            self.stats.lock().unwrap().ul_tti_requests += 1;
+        }
+
+        if let Some(ul_dci) = self.ul_dci_request(sfn, slot, ul_grants) {
+            self.sender.send(FapiMessage::UlDciRequest(ul_dci)).await?;
+            self.stats.lock().unwrap().ul_dci_requests += 1;
+        }
+
+        if !tx_data.pdus.is_empty() {
+            self.sender.send(FapiMessage::TxDataRequest(tx_data)).await?;
+            self.stats.lock().unwrap().tx_data_requests += 1;
+        }
+        Ok(())
+    }
+
+    /// DL_TTI.request and TX_Data.request of a slot: the SSB, and the SIB1,
+    /// paging and UE PDSCHs with their DCIs in CORESET#0
+    fn dl_requests(&self, sfn: u16, slot: u16, schedule: Option<&SlotSchedule>, sib1_payload: Option<Bytes>)
+        -> (DlTtiRequest, TxDataRequest) {
+        let mut dl_tti = DlTtiRequest { sfn, slot, pdus: Vec::new() };
+        let mut tx_data = TxDataRequest { sfn, slot, pdus: Vec::new() };
+        let Some(schedule) = schedule else {
+            return (dl_tti, tx_data);
+        };
+
+        if let Some(ssb) = &schedule.ssb_info {
+            dl_tti.pdus.push(DlTtiPdu::Ssb(SsbPdu {
+                phy_cell_id: self.config.phy_cell_id,
+                beta_pss: 0,
+                ssb_block_index: ssb.ssb_index,
+                ssb_subcarrier_offset: self.config.ssb_subcarrier_offset,
+                ssb_offset_point_a: self.config.ssb_offset_point_a,
+                // The PHY generates the MIB
+                bch_payload_flag: 2,
+                bch_payload: 0,
+            }));
+        }
+
+        let mut pdschs = Vec::new();
+        if let (Some(sib1), Some(payload)) = (&schedule.sib1_info, sib1_payload) {
+            pdschs.push(ScheduledPdsch {
+                dci: Dci10::SiRnti {
+                    frequency_resource: sib1.frequency_domain_assignment,
+                    time_resource: sib1.time_domain_assignment,
+                    vrb_to_prb_mapping: 0,
+                    modulation_coding_scheme: sib1.mcs_index,
+                    redundancy_version: 0,
+                    system_information_indicator: 0,
+                },
+                coreset: &sib1.coreset,
+                aggregation_level: sib1.aggregation_level,
+                cce_index: sib1.cce_index,
+                start_symbol: sib1.pdsch_time_alloc.start_symbol,
+                num_symbols: sib1.pdsch_time_alloc.num_symbols,
+                tbs_bytes: sib1.tbs_bytes,
+                modulation: sib1.modulation,
+                mcs_table: 0,
+                prb_allocation: &sib1.prb_allocation,
+                payload,
+            });
+        }
+        if let Some(paging) = &schedule.paging_info {
+            pdschs.push(ScheduledPdsch {
+                dci: Dci10::PRnti {
+                    // Paging records only, no short message
+                    short_messages_indicator: 1,
+                    short_messages: 0,
+                    frequency_resource: paging.frequency_domain_assignment,
+                    time_resource: paging.time_domain_assignment,
+                    vrb_to_prb_mapping: 0,
+                    modulation_coding_scheme: paging.mcs_index,
+                    tb_scaling: 0,
+                },
+                coreset: &paging.coreset,
+                aggregation_level: paging.aggregation_level,
+                cce_index: paging.cce_index,
+                start_symbol: paging.pdsch_time_alloc.start_symbol,
+                num_symbols: paging.pdsch_time_alloc.num_symbols,
+                tbs_bytes: paging.tbs_bytes,
+                modulation: paging.modulation,
+                mcs_table: 0,
+                prb_allocation: &paging.prb_allocation,
+                payload: paging.payload.clone(),
+            });
+        }
+        for grant in &schedule.dl_grants {
+            pdschs.push(ScheduledPdsch {
+                dci: Dci10::CRnti {
+                    rnti: grant.rnti.0,
+                    frequency_resource: grant.frequency_domain_assignment,
+                    time_resource: grant.time_domain_assignment,
+                    vrb_to_prb_mapping: 0,
+                    modulation_coding_scheme: grant.mcs_index,
+                    new_data_indicator: grant.ndi as u8,
+                    redundancy_version: 0,
+                    harq_process_number: grant.harq_process,
+                    downlink_assignment_index: 0,
+                    tpc_command: TPC_COMMAND_0_DB,
+                    pucch_resource_indicator: 0,
+                    harq_feedback_timing: HARQ_FEEDBACK_TIMING_K1_4,
+                },
+                coreset: &grant.coreset,
+                aggregation_level: grant.aggregation_level,
+                cce_index: grant.cce_index,
+                start_symbol: grant.pdsch_time_alloc.start_symbol,
+                num_symbols: grant.pdsch_time_alloc.num_symbols,
+                tbs_bytes: grant.tbs_bytes,
+                modulation: grant.modulation,
+                mcs_table: grant.mcs_table,
+                prb_allocation: &grant.prb_allocation,
+                payload: grant.payload.clone(),
+            });
+        }
+        let Some(coreset) = pdschs.first().map(|pdsch| pdsch.coreset) else {
+            return (dl_tti, tx_data);
+        };
+
+        let (bwp_start, bwp_size) = coreset0_bwp(coreset);
+        let mut pdcch = self.coreset0_pdcch(coreset);
+        for (pdu_index, pdsch) in pdschs.into_iter().enumerate() {
+            let (payload_size_bits, dci_payload) = pdsch.dci.pack(bwp_size);
+            pdcch.dl_dcis.push(DlDci {
+                rnti: pdsch.dci.rnti(),
+                scrambling_id: self.config.phy_cell_id,
+                scrambling_rnti: 0,
+                cce_index: pdsch.cce_index,
+                aggregation_level: pdsch.aggregation_level,
+                beta_pdcch_1_0: 0,
+                power_control_offset_ss: 0,
+                payload_size_bits,
+                payload: dci_payload,
+            });
+
+            let mcs_index = pdsch.dci.modulation_coding_scheme();
+            let rb_start = pdsch.prb_allocation.first().copied().unwrap_or(bwp_start);
+            dl_tti.pdus.push(DlTtiPdu::Pdsch(PdschPdu {
+                rnti: pdsch.dci.rnti(),
+                pdu_index: pdu_index as u16,
+                bwp_size,
+                bwp_start,
+                subcarrier_spacing: self.config.scs_common,
+                cyclic_prefix: 0,
+                target_code_rate: target_code_rate(pdsch.mcs_table, mcs_index),
+                qam_mod_order: qam_mod_order(pdsch.modulation),
+                mcs_index,
+                mcs_table: pdsch.mcs_table,
+                rv_index: 0,
+                tb_size: pdsch.tbs_bytes as u32,
+                data_scrambling_id: self.config.phy_cell_id,
+                nr_of_layers: 1,
+                ref_point: 1,
+                // DM-RS on the first symbol of the PDSCH
+                dl_dmrs_symb_pos: 1 << pdsch.start_symbol,
+                dmrs_config_type: 0,
+                rb_start: rb_start.saturating_sub(bwp_start),
+                rb_size: pdsch.prb_allocation.len() as u16,
+                vrb_to_prb_mapping: 0,
+                start_symbol_index: pdsch.start_symbol,
+                nr_of_symbols: pdsch.num_symbols,
+            }));
+            tx_data.pdus.push(TxPdu { pdu_index: pdu_index as u16, payload: pdsch.payload });
+        }
+        dl_tti.pdus.insert(usize::from(schedule.ssb_info.is_some()), DlTtiPdu::Pdcch(pdcch));
+        (dl_tti, tx_data)
+    }
+
+    /// PDCCH PDU of CORESET#0, without DCIs yet
+    fn coreset0_pdcch(&self, coreset: &CorsetConfig) -> PdcchPdu {
+        let (bwp_start, bwp_size) = coreset0_bwp(coreset);
+        PdcchPdu {
+            bwp_size,
+            bwp_start,
+            subcarrier_spacing: self.config.scs_common,
+            cyclic_prefix: 0,
+            start_symbol_index: coreset.start_symbol,
+            duration_symbols: coreset.duration,
+            freq_domain_resource: PdcchPdu::freq_domain_resource_of(bwp_size),
+            // Interleaved mapping of CORESET#0 (TS 38.211 section 7.3.2.2)
+            cce_reg_mapping_type: 1,
+            reg_bundle_size: 6,
+            interleaver_size: 2,
+            coreset_type: 0,
+            shift_index: self.config.phy_cell_id,
+            precoder_granularity: 0,
+            dl_dcis: Vec::new(),
+        }
+    }
+
+    /// UL_DCI.request of a slot: the DCI format 0_0 of each UL grant, if any
+    fn ul_dci_request(&self, sfn: u16, slot: u16, grants: &[UeUlGrant]) -> Option<UlDciRequest> {
+        let mut pdcch = self.coreset0_pdcch(&grants.first()?.coreset);
+        for grant in grants {
+            let dci = Dci00 {
+                frequency_resource: grant.frequency_domain_assignment,
+                time_resource: grant.time_domain_assignment,
+                frequency_hopping: 0,
+                modulation_coding_scheme: grant.mcs_index,
+                new_data_indicator: grant.ndi as u8,
+                redundancy_version: 0,
+                harq_process_number: grant.harq_process,
+                tpc_command: TPC_COMMAND_0_DB,
+            };
+            let (payload_size_bits, payload) = dci.pack(self.config.ul_grid_size, pdcch.bwp_size);
+            pdcch.dl_dcis.push(DlDci {
+                rnti: grant.rnti.0,
+                scrambling_id: self.config.phy_cell_id,
+                scrambling_rnti: 0,
+                cce_index: grant.cce_index,
+                aggregation_level: grant.aggregation_level,
+                beta_pdcch_1_0: 0,
+                power_control_offset_ss: 0,
+                payload_size_bits,
+                payload,
+            });
+        }
+        Some(UlDciRequest { sfn, slot, pdcchs: vec![pdcch] })
+    }
+
+    /// Keep the PUSCHs of the UL grants of a slot for the slots they are sent in
+    fn queue_puschs(&self, sfn: u16, slot: u16, grants: &[UeUlGrant]) {
+        let slots_per_frame = 10u32 << self.config.scs_common;
+        let period = 1024 * slots_per_frame;
+        let now = sfn as u32 * slots_per_frame + slot as u32;
+        let mut pending = self.pending_puschs.lock().unwrap();
+        for grant in grants {
+            let rb_start = grant.prb_allocation.first().copied().unwrap_or(0);
+            let pusch = PuschPdu {
+                rnti: grant.rnti.0,
+                handle: grant.rnti.0 as u32,
+                bwp_size: self.config.ul_grid_size,
+                bwp_start: 0,
+                subcarrier_spacing: self.config.scs_common,
+                cyclic_prefix: 0,
+                target_code_rate: target_code_rate(grant.mcs_table, grant.mcs_index),
+                qam_mod_order: qam_mod_order(grant.modulation),
+                mcs_index: grant.mcs_index,
+                mcs_table: grant.mcs_table,
+                transform_precoding: false,
+                data_scrambling_id: self.config.phy_cell_id,
+                nr_of_layers: 1,
+                // DM-RS on the first symbol of the PUSCH
+                ul_dmrs_symb_pos: 1 << grant.pusch_time_alloc.start_symbol,
+                dmrs_config_type: 0,
+                rb_start,
+                rb_size: grant.prb_allocation.len() as u16,
+                vrb_to_prb_mapping: 0,
+                start_symbol_index: grant.pusch_time_alloc.start_symbol,
+                nr_of_symbols: grant.pusch_time_alloc.num_symbols,
+                rv_index: 0,
+                harq_process_id: grant.harq_process,
+                new_data_indicator: grant.ndi,
+                tb_size: grant.tbs_bytes as u32,
+            };
+            pending.push(((now + grant.pusch_time_alloc.k2 as u32) % period, pusch));
+        }
+    }
+
+    /// UL_TTI.request of a slot: its PRACH occasion, if any, and the PUSCHs
+    /// granted for it
+    fn ul_tti_request(&self, sfn: u16, slot: u16) -> UlTtiRequest {
+        let mut request = UlTtiRequest { sfn, slot, pdus: Vec::new() };
+        if let Some(occasion) = prach::prach_occasion(self.config.prach_config_index, sfn as u32, slot as u8) {
+            request.pdus.push(UlTtiPdu::Prach(PrachPdu {
+                phy_cell_id: self.config.phy_cell_id,
+                num_prach_ocas: occasion.num_occasions_within_slot,
+                prach_format: prach_format(occasion.format),
+                index_fd_ra: 0,
+                prach_start_symbol: occasion.starting_symbol,
+                num_cs: prach::cyclic_shift(self.config.prach_zero_corr_conf as u16) as u16,
+            }));
+        }
+
+        let slots_per_frame = 10u32 << self.config.scs_common;
+        let period = 1024 * slots_per_frame;
+        let now = sfn as u32 * slots_per_frame + slot as u32;
+        self.pending_puschs.lock().unwrap().retain(|(target, pusch)| {
+            match (target + period - now) % period {
+                0 => request.pdus.push(UlTtiPdu::Pusch(*pusch)),
+                ahead if ahead <= MAX_K2 => return true,
+                _ => debug!("Dropping the PUSCH of RNTI {}, no SLOT.indication for its slot", pusch.rnti),
+            }
+            false
+        });
+        request
+    }
+
+    /// PRACH detections of the occasions of a RACH.indication
+    fn prach_detections(&self, indication: &RachIndication) -> Vec<PrachDetectionResult> {
+        let step_us = timing_advance_step_us(self.config.scs_common);
+        // T_A steps of 16 samples at 30.72 Msps for 15 kHz
+        let samples_per_step = 16 >> self.config.scs_common.min(4);
+        indication
+            .occasions
+            .iter()
+            .map(|occasion| PrachDetectionResult {
+                frame: indication.sfn as u32,
+                slot: indication.slot as u8,
+                rssi_dbm: occasion.avg_rssi as f32 / 10.0 - 140.0,
+                preambles: occasion
+                    .preambles
+                    .iter()
+                    .map(|preamble| PreambleDetection {
+                        preamble_index: preamble.preamble_index,
+                        timing_advance_samples: preamble.timing_advance as u32 * samples_per_step,
+                        timing_advance_us: preamble.timing_advance as f32 * step_us,
+                        // FAPI reports detected preambles without their metric
+                        detection_metric: 1.0,
+                        power_dbm: preamble.preamble_pwr as f32 / 1000.0 - 140.0,
+                    })
+                    .collect(),
+                time_resolution_us: step_us,
+                max_timing_advance_us: MAX_RACH_TIMING_ADVANCE as f32 * step_us,
+            })
+            .collect()
+    }
+}
+
+/// BWP of CORESET#0: its first RB and its size
+fn coreset0_bwp(coreset: &CorsetConfig) -> (u16, u16) {
+    let bwp_start = coreset.frequency_domain_resources.first().copied().unwrap_or(0);
+    (bwp_start, coreset.frequency_domain_resources.len() as u16)
+}
+
+/// Target code rate of an MCS, times 1024 times 10
+fn target_code_rate(mcs_table: u8, mcs_index: u8) -> u16 {
+    mcs_table_of_index(mcs_table).get(mcs_index as usize).map_or(0, |mcs| mcs.code_rate_x10240)
+}
+
+/// Bits per modulation symbol of a modulation scheme
+pub(super) fn qam_mod_order(modulation: ModulationScheme) -> u8 {
+    match modulation {
+        ModulationScheme::Qpsk => 2,
+        ModulationScheme::Qam16 => 4,
+        ModulationScheme::Qam64 => 6,
+        ModulationScheme::Qam256 => 8,
+    }
+}
+
+/// FAPI preamble format of a PRACH format
+fn prach_format(format: PrachFormat) -> u8 {
+    match format {
+        PrachFormat::Format0 => 0,
+        PrachFormat::Format1 => 1,
+        PrachFormat::Format2 => 2,
+        PrachFormat::Format3 => 3,
+        PrachFormat::FormatA1 => 4,
+        PrachFormat::FormatA2 => 5,
+        PrachFormat::FormatA3 => 6,
+        PrachFormat::FormatB1 => 7,
+        PrachFormat::FormatB4 => 8,
+        PrachFormat::FormatC0 => 9,
+        PrachFormat::FormatC2 => 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fapi::p5::{ConfigResponse, ErrorIndication};
+    use crate::fapi::p7::{Crc, CrcIndication, RachOccasion, RachPreamble, RxDataIndication, RxPdu, UciIndication};
+    use crate::fapi::transport::in_process;
+    use async_trait::async_trait;
+
+    /// MAC with nothing scheduled that refuses what the L1 reports
+    struct RefusingMac;
+
+    #[async_trait]
+    impl MacPhyInterface for RefusingMac {
+        async fn get_slot_schedule(&self, _frame: u32, _slot: u8) -> Result<SlotSchedule, LayerError> {
+            Err(LayerError::NotInitialized)
+        }
+
+        async fn get_sib1_payload(&self) -> Result<Bytes, LayerError> {
+            Err(LayerError::NotInitialized)
+        }
+
+        async fn report_prach_detection(&self, _detection: PrachDetectionResult) -> Result<(), LayerError> {
+            Err(LayerError::NotInitialized)
+        }
+
+        async fn report_rx_data(&self, _rnti: u16, _data: Bytes) -> Result<(), LayerError> {
+            Err(LayerError::NotInitialized)
+        }
+    }
+
+    fn refusing_l2(sender: FapiSender) -> FapiL2 {
+        FapiL2::new(Arc::new(RefusingMac), ConfigRequest { dl_grid_size: 52, ul_grid_size: 52, ..Default::default() },
+                    sender)
+    }
+
+    #[tokio::test]
+    async fn test_l2_start_failures() {
+        // CONFIG.request rejected, after other messages that are ignored
+        let ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) = in_process(4);
+        let l2 = refusing_l2(mac_tx);
+        let l1 = async {
+            assert!(matches!(l1_rx.recv().await, Some(FapiMessage::ConfigRequest(_))));
+            l1_tx.send(FapiMessage::StopIndication).await.unwrap();
+            l1_tx.send(FapiMessage::ConfigResponse(ConfigResponse {
+                error_code: ErrorCode::MsgInvalidConfig,
+                invalid_tags: vec![crate::fapi::p5::tag::PHY_CELL_ID],
+            })).await.unwrap();
+        };
+        let (started, _) = tokio::join!(l2.start(&mut mac_rx), l1);
+        assert!(matches!(started, Err(LayerError::InitializationFailed(_))));
+        // No START.request follows
+        drop(l2);
+        assert_eq!(l1_rx.recv().await, None);
+
+        // The L1 gone or silent
+        let ((mac_tx, mut mac_rx), (l1_tx, l1_rx)) = in_process(4);
+        drop((l1_tx, l1_rx));
+        assert!(matches!(refusing_l2(mac_tx).start(&mut mac_rx).await, Err(LayerError::ProcessingError(_))));
+        let ((mac_tx, mut mac_rx), (l1_tx, _l1_rx)) = in_process(4);
+        let l2 = refusing_l2(mac_tx);
+        let (started, _) = tokio::join!(l2.start(&mut mac_rx), async { drop(l1_tx) });
+        assert!(matches!(started, Err(LayerError::InitializationFailed(_))));
+        let ((mac_tx, mut mac_rx), (_l1_tx, _l1_rx)) = in_process(4);
+        assert!(matches!(refusing_l2(mac_tx).start(&mut mac_rx).await, Err(LayerError::InitializationFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_l2_run_failures() {
+        let ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) = in_process(16);
+        let l2 = refusing_l2(mac_tx);
+
+        // Without a schedule of the MAC the slot gets an empty DL_TTI.request,
+        // indications the MAC refuses and errors of the L1 are counted only
+        l1_tx.send(FapiMessage::SlotIndication(SlotIndication { sfn: 0, slot: 0 })).await.unwrap();
+        l1_tx.send(FapiMessage::RachIndication(RachIndication {
+            sfn: 1, slot: 9, occasions: vec![RachOccasion {
+                phy_cell_id: 0, symbol_index: 0, slot_index: 9, ra_index: 0, avg_rssi: 500, avg_snr: 128,
+                preambles: vec![RachPreamble { preamble_index: 1, timing_advance: 10, preamble_pwr: 50_000 }],
+            }],
+        })).await.unwrap();
+        l1_tx.send(FapiMessage::RxDataIndication(RxDataIndication {
+            sfn: 2, slot: 0, pdus: vec![RxPdu {
+                handle: 1, rnti: 0x4601, harq_id: 0, ul_cqi: 0, timing_advance: 0, rssi: 0,
+                payload: Bytes::from_static(&[0x00]),
+            }],
+        })).await.unwrap();
+        let crc = Crc { handle: 1, rnti: 0x4601, harq_id: 0, tb_crc_ok: false, ul_cqi: 0, timing_advance: 0, rssi: 0 };
+        l1_tx.send(FapiMessage::CrcIndication(CrcIndication {
+            sfn: 2, slot: 0, crcs: vec![crc, Crc { tb_crc_ok: true, ..crc }, crc],
+        })).await.unwrap();
+        l1_tx.send(FapiMessage::UciIndication(UciIndication { sfn: 2, slot: 0, ucis: vec![] })).await.unwrap();
+        l1_tx.send(FapiMessage::ErrorIndication(ErrorIndication {
+            sfn: 0, slot: 0, message_id: 0x80, error_code: ErrorCode::MsgSlotErr,
+        })).await.unwrap();
+        l1_tx.send(FapiMessage::StartRequest).await.unwrap();
+        drop(l1_tx);
+        assert!(matches!(l2.run(&mut mac_rx).await, Err(LayerError::ProcessingError(_))));
+
+        assert_eq!(l1_rx.recv().await, Some(FapiMessage::DlTtiRequest(DlTtiRequest { sfn: 0, slot: 0, pdus: vec![] })));
+        let stats = l2.stats();
+        assert_eq!(stats, FapiL2Stats {
+            slot_indications: 1,
+            dl_tti_requests: 1,
+            rach_indications: 1,
+            rx_data_indications: 1,
+            crc_indications: 1,
+            crc_failures: 2,
+            uci_indications: 1,
+            error_indications: 1,
+            ..Default::default()
+        });
+    }
+}
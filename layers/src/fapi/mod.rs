@@ -0,0 +1,413 @@
+//! SCF 5G FAPI
+//!
+//! The interface between the MAC and the L1 specified by the Small Cell Forum
+//! (SCF 222.10.02): P5 messages configure, start and stop the L1, and P7
+//! messages carry what is sent and received in each slot.
+//!
+//! [`l2::FapiL2`] drives an L1 from the MAC: it answers each SLOT.indication
+//! with the requests built from the schedule of the MAC and reports the RACH
+//! and RX_Data indications back to it. [`l1::FapiL1`] puts the PHY of this
+//! stack behind FAPI by implementing [`crate::mac::MacPhyInterface`] on top of
+//! the P7 requests it receives. The two meet over one of the bindings of
+//! [`transport`], in the same process or over a TCP socket.
+
+pub mod dci;
+pub mod l1;
+pub mod l2;
+pub mod p5;
+pub mod p7;
+pub mod transport;
+
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+pub use dci::Dci10;
+pub use l1::{FapiL1, L1State};
+pub use l2::{FapiL2, FapiL2Stats};
+pub use p5::{ConfigRequest, ConfigResponse, ErrorCode, ErrorIndication, FrameDuplexType};
+pub use p7::{
+    CrcIndication, DlTtiRequest, RachIndication, RxDataIndication, SlotIndication, TxDataRequest, UciIndication,
+    UlDciRequest, UlTtiRequest,
+};
+pub use transport::{FapiListener, FapiReceiver, FapiSender};
+
+/// Length of the header of a block of messages
+pub const BLOCK_HEADER_LEN: usize = 2;
+/// Length of the header of a message
+pub const MESSAGE_HEADER_LEN: usize = 6;
+
+/// Message type IDs (SCF 222.10.02 Table 3-5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FapiMessageType {
+    ConfigRequest = 0x02,
+    ConfigResponse = 0x03,
+    StartRequest = 0x04,
+    StopRequest = 0x05,
+    StopIndication = 0x06,
+    ErrorIndication = 0x07,
+    DlTtiRequest = 0x80,
+    UlTtiRequest = 0x81,
+    SlotIndication = 0x82,
+    UlDciRequest = 0x83,
+    TxDataRequest = 0x84,
+    RxDataIndication = 0x85,
+    CrcIndication = 0x86,
+    UciIndication = 0x87,
+    RachIndication = 0x89,
+}
+
+impl FapiMessageType {
+    /// Message type of an ID
+    pub fn from_u16(id: u16) -> Option<Self> {
+        use FapiMessageType::*;
+        [
+            ConfigRequest, ConfigResponse, StartRequest, StopRequest, StopIndication, ErrorIndication, DlTtiRequest,
+            UlTtiRequest, SlotIndication, UlDciRequest, TxDataRequest, RxDataIndication, CrcIndication, UciIndication,
+            RachIndication,
+        ]
+        .into_iter()
+        .find(|message_type| *message_type as u16 == id)
+    }
+}
+
+/// FAPI message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FapiMessage {
+    ConfigRequest(ConfigRequest),
+    ConfigResponse(ConfigResponse),
+    StartRequest,
+    StopRequest,
+    StopIndication,
+    ErrorIndication(ErrorIndication),
+    DlTtiRequest(DlTtiRequest),
+    UlTtiRequest(UlTtiRequest),
+    SlotIndication(SlotIndication),
+    UlDciRequest(UlDciRequest),
+    TxDataRequest(TxDataRequest),
+    RxDataIndication(RxDataIndication),
+    CrcIndication(CrcIndication),
+    UciIndication(UciIndication),
+    RachIndication(RachIndication),
+}
+
+impl FapiMessage {
+    /// Message type of the message
+    pub fn message_type(&self) -> FapiMessageType {
+        match self {
+            FapiMessage::ConfigRequest(_) => FapiMessageType::ConfigRequest,
+            FapiMessage::ConfigResponse(_) => FapiMessageType::ConfigResponse,
+            FapiMessage::StartRequest => FapiMessageType::StartRequest,
+            FapiMessage::StopRequest => FapiMessageType::StopRequest,
+            FapiMessage::StopIndication => FapiMessageType::StopIndication,
+            FapiMessage::ErrorIndication(_) => FapiMessageType::ErrorIndication,
+            FapiMessage::DlTtiRequest(_) => FapiMessageType::DlTtiRequest,
+            FapiMessage::UlTtiRequest(_) => FapiMessageType::UlTtiRequest,
+            FapiMessage::SlotIndication(_) => FapiMessageType::SlotIndication,
+            FapiMessage::UlDciRequest(_) => FapiMessageType::UlDciRequest,
+            FapiMessage::TxDataRequest(_) => FapiMessageType::TxDataRequest,
+            FapiMessage::RxDataIndication(_) => FapiMessageType::RxDataIndication,
+            FapiMessage::CrcIndication(_) => FapiMessageType::CrcIndication,
+            FapiMessage::UciIndication(_) => FapiMessageType::UciIndication,
+            FapiMessage::RachIndication(_) => FapiMessageType::RachIndication,
+        }
+    }
+
+    /// Encode the message: type ID and body length, then the body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.message_type() as u16);
+        let length_offset = buf.len();
+        buf.put_u32_le(0);
+        match self {
+            FapiMessage::ConfigRequest(message) => message.encode(buf),
+            FapiMessage::ConfigResponse(message) => message.encode(buf),
+            FapiMessage::StartRequest | FapiMessage::StopRequest | FapiMessage::StopIndication => {}
+            FapiMessage::ErrorIndication(message) => message.encode(buf),
+            FapiMessage::DlTtiRequest(message) => message.encode(buf),
+            FapiMessage::UlTtiRequest(message) => message.encode(buf),
+            FapiMessage::SlotIndication(message) => message.encode(buf),
+            FapiMessage::UlDciRequest(message) => message.encode(buf),
+            FapiMessage::TxDataRequest(message) => message.encode(buf),
+            FapiMessage::RxDataIndication(message) => message.encode(buf),
+            FapiMessage::CrcIndication(message) => message.encode(buf),
+            FapiMessage::UciIndication(message) => message.encode(buf),
+            FapiMessage::RachIndication(message) => message.encode(buf),
+        }
+        let length = (buf.len() - length_offset - 4) as u32;
+        buf[length_offset..length_offset + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    /// Decode a message off the front of the buffer
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, MESSAGE_HEADER_LEN)?;
+        let message_type = header.get_u16_le();
+        let length = header.get_u32_le() as usize;
+        Self::decode_body(message_type, take(buf, length)?)
+    }
+
+    /// Decode the body of a message of type ID `message_type`
+    pub fn decode_body(message_type: u16, mut body: &[u8]) -> Result<Self, LayerError> {
+        let message_type = FapiMessageType::from_u16(message_type).ok_or(LayerError::InvalidPdu)?;
+        let body = &mut body;
+        Ok(match message_type {
+            FapiMessageType::ConfigRequest => FapiMessage::ConfigRequest(ConfigRequest::decode(body)?),
+            FapiMessageType::ConfigResponse => FapiMessage::ConfigResponse(ConfigResponse::decode(body)?),
+            FapiMessageType::StartRequest => FapiMessage::StartRequest,
+            FapiMessageType::StopRequest => FapiMessage::StopRequest,
+            FapiMessageType::StopIndication => FapiMessage::StopIndication,
+            FapiMessageType::ErrorIndication => FapiMessage::ErrorIndication(ErrorIndication::decode(body)?),
+            FapiMessageType::DlTtiRequest => FapiMessage::DlTtiRequest(DlTtiRequest::decode(body)?),
+            FapiMessageType::UlTtiRequest => FapiMessage::UlTtiRequest(UlTtiRequest::decode(body)?),
+            FapiMessageType::SlotIndication => FapiMessage::SlotIndication(SlotIndication::decode(body)?),
+            FapiMessageType::UlDciRequest => FapiMessage::UlDciRequest(UlDciRequest::decode(body)?),
+            FapiMessageType::TxDataRequest => FapiMessage::TxDataRequest(TxDataRequest::decode(body)?),
+            FapiMessageType::RxDataIndication => FapiMessage::RxDataIndication(RxDataIndication::decode(body)?),
+            FapiMessageType::CrcIndication => FapiMessage::CrcIndication(CrcIndication::decode(body)?),
+            FapiMessageType::UciIndication => FapiMessage::UciIndication(UciIndication::decode(body)?),
+            FapiMessageType::RachIndication => FapiMessage::RachIndication(RachIndication::decode(body)?),
+        })
+    }
+}
+
+/// Encode a block of messages: number of messages and opaque handle, then
+/// the messages (SCF 222.10.02 section 3.2.1)
+pub fn encode_block(messages: &[FapiMessage]) -> Bytes {
+    let mut buf = BytesMut::new();
+    buf.put_u8(messages.len() as u8);
+    buf.put_u8(0);
+    for message in messages {
+        message.encode(&mut buf);
+    }
+    buf.freeze()
+}
+
+/// Decode a block of messages
+pub fn decode_block(mut data: &[u8]) -> Result<Vec<FapiMessage>, LayerError> {
+    let num_messages = take(&mut data, BLOCK_HEADER_LEN)?[0];
+    (0..num_messages).map(|_| FapiMessage::decode(&mut data)).collect()
+}
+
+/// Split `len` bytes off the front of the buffer
+pub(crate) fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], LayerError> {
+    if buf.remaining() < len {
+        return Err(LayerError::InvalidPdu);
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mac::{default_sib1_config, EnhancedMacLayer, MacConfig, MacPhyInterface, MacScheduler};
+    use crate::phy::prach::{PrachDetectionResult, PreambleDetection};
+    use crate::rrc::{PagingRequest, PagingUeIdentity, RrcMacInterface};
+    use crate::ProtocolLayer;
+    use common::types::{Bandwidth, CellId, Rnti, SubcarrierSpacing};
+    use p7::{Crc, TxPdu, UlTtiPdu};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_message_block() {
+        let messages = vec![
+            FapiMessage::StartRequest,
+            FapiMessage::SlotIndication(SlotIndication { sfn: 513, slot: 7 }),
+            FapiMessage::TxDataRequest(TxDataRequest {
+                sfn: 513,
+                slot: 7,
+                pdus: vec![TxPdu { pdu_index: 0, payload: Bytes::from_static(&[1, 2, 3]) }],
+            }),
+            FapiMessage::CrcIndication(CrcIndication {
+                sfn: 513,
+                slot: 3,
+                crcs: vec![Crc {
+                    handle: 9, rnti: 0x4601, harq_id: 2, tb_crc_ok: false, ul_cqi: 150, timing_advance: 31,
+                    rssi: 900,
+                }],
+            }),
+        ];
+        let block = encode_block(&messages);
+        assert_eq!(&block[..10], &[4, 0, 0x04, 0x00, 0, 0, 0, 0, 0x82, 0x00]);
+        assert_eq!(&block[10..18], &[4, 0, 0, 0, 0x01, 0x02, 7, 0]);
+        assert_eq!(decode_block(&block).unwrap(), messages);
+
+        assert!(decode_block(&block[..block.len() - 1]).is_err());
+        let mut unknown = block.to_vec();
+        unknown[2] = 0x7F;
+        assert!(decode_block(&unknown).is_err());
+    }
+
+    fn l1_config(phy_cell_id: u16) -> ConfigRequest {
+        ConfigRequest {
+            dl_bandwidth: 10, dl_frequency: 3_600_000, dl_grid_size: 52, num_tx_ant: 1,
+            ul_bandwidth: 10, ul_frequency: 3_600_000, ul_grid_size: 52, num_rx_ant: 1,
+            phy_cell_id,
+            prach_zero_corr_conf: 12,
+            ..Default::default()
+        }
+    }
+
+    /// Drive the PHY side of an L1 served by the MAC through a FAPI binding
+    async fn exercise_binding(mac_end: (FapiSender, FapiReceiver), l1_end: (FapiSender, FapiReceiver)) {
+        let mac_config = MacConfig {
+            cell_id: CellId(1),
+            scs: SubcarrierSpacing::Scs15,
+            bandwidth: Bandwidth::Bw10,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        };
+        let mut reference = MacScheduler::new(CellId(1), SubcarrierSpacing::Scs15, Bandwidth::Bw10, 6).unwrap();
+        reference.set_pcch_config(mac_config.sib1_config.pcch_config).unwrap();
+        let mut mac = EnhancedMacLayer::new(mac_config).unwrap();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(4);
+        let (user_data_tx, mut user_data_rx) = mpsc::channel(4);
+        mac.set_rrc_channel(rrc_tx);
+        mac.set_user_data_channel(user_data_tx);
+        mac.initialize().await.unwrap();
+        let mac = Arc::new(mac);
+
+        let ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) = (mac_end, l1_end);
+        let l1 = Arc::new(FapiL1::new(l1_tx, Duration::from_secs(1)));
+        let l1_task = tokio::spawn({
+            let l1 = Arc::clone(&l1);
+            async move { l1.run(&mut l1_rx).await }
+        });
+        assert!(l1.get_slot_schedule(0, 0).await.is_err());
+
+        let l2 = Arc::new(FapiL2::new(mac.clone(), l1_config(1), mac_tx));
+        l2.start(&mut mac_rx).await.unwrap();
+        let l2_task = tokio::spawn({
+            let l2 = Arc::clone(&l2);
+            async move { l2.run(&mut mac_rx).await }
+        });
+        while l1.state().await != L1State::Running {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(l1.config().await, Some(l1_config(1)));
+
+        // SSB and SIB1 come back to the PHY as the MAC scheduled them
+        let schedule = l1.get_slot_schedule(0, 0).await.unwrap();
+        assert_eq!(format!("{:?}", schedule), format!("{:?}", reference.get_slot_schedule(0, 0)));
+        assert!(schedule.ssb_info.is_some() && schedule.sib1_info.is_some());
+        assert_eq!(l1.get_sib1_payload().await.unwrap(), mac.get_sib1_payload().await.unwrap());
+        // Asked again at the next symbols, the MAC is not asked again
+        assert_eq!(format!("{:?}", l1.get_slot_schedule(0, 0).await.unwrap()), format!("{:?}", schedule));
+
+        // And so does paging
+        let request = PagingRequest {
+            identity: PagingUeIdentity::NgSTmsi(0x0042_C000_0405),
+            ue_id: 5,
+            paging_cycle: None,
+            priority: None,
+        };
+        mac.schedule_paging(request).await.unwrap();
+        reference.queue_paging(request);
+        let schedule = l1.get_slot_schedule(133, 0).await.unwrap();
+        assert_eq!(format!("{:?}", schedule.paging_info), format!("{:?}", reference.take_paging(133, 0)));
+        assert!(schedule.paging_info.is_some() && schedule.ssb_info.is_none());
+
+        // PRACH occasion of configuration index 0: frame 1 of 16, subframe 9
+        assert!(l1.take_ul_requests().await.is_empty());
+        let schedule = l1.get_slot_schedule(1, 9).await.unwrap();
+        assert!(schedule.ssb_info.is_none() && schedule.sib1_info.is_none());
+        let ul_requests = l1.take_ul_requests().await;
+        assert_eq!(ul_requests.len(), 1);
+        assert!(matches!(&ul_requests[0].pdus[..], [UlTtiPdu::Prach(prach)] if prach.num_cs == 119));
+
+        // A preamble gets a TC-RNTI, whose first transport block is its Msg3
+        let detection = PrachDetectionResult {
+            frame: 1,
+            slot: 9,
+            rssi_dbm: -90.0,
+            preambles: vec![PreambleDetection {
+                preamble_index: 12,
+                timing_advance_samples: 160,
+                timing_advance_us: 5.2,
+                detection_metric: 3.0,
+                power_dbm: -95.0,
+            }],
+            time_resolution_us: 0.0326,
+            max_timing_advance_us: 200.0,
+        };
+        l1.report_prach_detection(detection).await.unwrap();
+        l1.report_rx_data(0x4601, Bytes::from_static(&[0x14, 0x7A, 0x2C])).await.unwrap();
+        let (rnti, msg3) = tokio::time::timeout(Duration::from_secs(1), rrc_rx.recv()).await.unwrap().unwrap();
+        assert_eq!((rnti, &msg3[..]), (Rnti(0x4601), &[0x14, 0x7A, 0x2C][..]));
+
+        // The next transport blocks of the C-RNTI are demultiplexed: SRB1 to RRC,
+        // the DRB to the user plane
+        l1.report_rx_data(0x4601, Bytes::from_static(&[0x01, 0x02, 0x00, 0x2A, 0x04, 0x01, 0x81])).await.unwrap();
+        let (rnti, srb1) = tokio::time::timeout(Duration::from_secs(1), rrc_rx.recv()).await.unwrap().unwrap();
+        assert_eq!((rnti, &srb1[..]), (Rnti(0x4601), &[0x00, 0x2A][..]));
+        let (rnti, lcid, drb) = tokio::time::timeout(Duration::from_secs(1), user_data_rx.recv()).await.unwrap().unwrap();
+        assert_eq!((rnti, lcid, &drb[..]), (Rnti(0x4601), 4, &[0x81][..]));
+
+        // A DL-SCH MAC PDU reaches the PHY with its DCI format 1_0
+        mac.send_user_data(Rnti(0x4601), 1, Bytes::from_static(&[0x00, 0x2B])).await.unwrap();
+        let schedule = l1.get_slot_schedule(2, 3).await.unwrap();
+        assert_eq!(schedule.dl_grants.len(), 1);
+        let grant = &schedule.dl_grants[0];
+        assert_eq!((grant.rnti, grant.tbs_bytes, grant.ndi), (Rnti(0x4601), grant.payload.len(), true));
+        let sdus = crate::mac::pdu::decode_dl_sch(&grant.payload).unwrap();
+        assert_eq!((sdus[0].subheader.lcid, &sdus[0].data[..]), (1, &[0x00, 0x2B][..]));
+
+        // A Short BSR of 198 bytes gets a DCI format 0_0 in UL_DCI.request and
+        // a PUSCH in the UL_TTI.request K2 = 1 slot later
+        l1.report_rx_data(0x4601, Bytes::from_static(&[crate::mac::pdu::UL_LCID_SHORT_BSR, 0x0A])).await.unwrap();
+        assert!(l1.get_slot_schedule(2, 4).await.unwrap().dl_grants.is_empty());
+        assert!(l1.take_ul_requests().await.is_empty());
+        l1.get_slot_schedule(2, 5).await.unwrap();
+        let ul_requests = l1.take_ul_requests().await;
+        let [UlTtiPdu::Pusch(pusch)] = &ul_requests[0].pdus[..] else {
+            panic!("Expected the PUSCH of the UE, got {:?}", ul_requests);
+        };
+        assert_eq!((ul_requests[0].sfn, ul_requests[0].slot, pusch.rnti), (2, 5, 0x4601));
+        assert!(pusch.tb_size >= 198);
+        assert_eq!(pusch.target_code_rate, crate::mac::mcs::MCS_TABLE_1[pusch.mcs_index as usize].code_rate_x10240);
+
+        l2.stop().await.unwrap();
+        l2_task.await.unwrap().unwrap();
+        assert_eq!(l1.state().await, L1State::Configured);
+        let stats = l2.stats();
+        assert_eq!((stats.slot_indications, stats.dl_tti_requests, stats.ul_tti_requests), (6, 6, 2));
+        assert_eq!((stats.ul_dci_requests, stats.tx_data_requests), (1, 3));
+        assert_eq!((stats.rach_indications, stats.rx_data_indications), (1, 3));
+        assert_eq!(stats.error_indications, 0);
+
+        drop(l2);
+        l1_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mac_and_phy_over_fapi() {
+        let (mac_end, l1_end) = transport::in_process(16);
+        exercise_binding(mac_end, l1_end).await;
+
+        let listener = FapiListener::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (mac_end, l1_end) = tokio::join!(transport::connect(listener.local_addr().unwrap()), listener.accept());
+        exercise_binding(mac_end.unwrap(), l1_end.unwrap()).await;
+
+        // An L1 rejects a configuration it cannot run
+        let ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) = transport::in_process(4);
+        let l1 = Arc::new(FapiL1::new(l1_tx, Duration::from_secs(1)));
+        tokio::spawn({
+            let l1 = Arc::clone(&l1);
+            async move { l1.run(&mut l1_rx).await }
+        });
+        let mac = EnhancedMacLayer::new(MacConfig {
+            cell_id: CellId(1),
+            scs: SubcarrierSpacing::Scs15,
+            bandwidth: Bandwidth::Bw10,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        }).unwrap();
+        let l2 = FapiL2::new(Arc::new(mac), l1_config(1008), mac_tx);
+        let error = l2.start(&mut mac_rx).await.unwrap_err();
+        assert!(error.to_string().contains("100c"), "{}", error);
+        assert_eq!(l1.state().await, L1State::Idle);
+    }
+}
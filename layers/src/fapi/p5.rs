@@ -0,0 +1,392 @@
+//! P5 configuration messages
+//!
+//! The messages bringing the L1 from IDLE through CONFIGURED to RUNNING and
+//! back (SCF 222.10.02 section 3.3): CONFIG.request with the cell
+//! configuration as TLVs, CONFIG.response, START.request, STOP.request,
+//! STOP.indication, and ERROR.indication for requests the L1 rejects.
+
+use super::take;
+use crate::LayerError;
+use bytes::{Buf, BufMut, BytesMut};
+use tracing::debug;
+
+/// Tags of the CONFIG.request TLVs
+pub mod tag {
+    pub const DL_BANDWIDTH: u16 = 0x1001;
+    pub const DL_FREQUENCY: u16 = 0x1002;
+    pub const DL_GRID_SIZE: u16 = 0x1004;
+    pub const NUM_TX_ANT: u16 = 0x1005;
+    pub const UL_BANDWIDTH: u16 = 0x1006;
+    pub const UL_FREQUENCY: u16 = 0x1007;
+    pub const UL_GRID_SIZE: u16 = 0x1009;
+    pub const NUM_RX_ANT: u16 = 0x100A;
+    pub const PHY_CELL_ID: u16 = 0x100C;
+    pub const FRAME_DUPLEX_TYPE: u16 = 0x100D;
+    pub const SCS_COMMON: u16 = 0x1010;
+    pub const PRACH_SUBC_SPACING: u16 = 0x1012;
+    pub const PRACH_ROOT_SEQUENCE_INDEX: u16 = 0x1015;
+    pub const PRACH_ZERO_CORR_CONF: u16 = 0x1018;
+    pub const SSB_OFFSET_POINT_A: u16 = 0x101D;
+    pub const SSB_PERIOD: u16 = 0x101F;
+    pub const SSB_SUBCARRIER_OFFSET: u16 = 0x1020;
+    pub const SSB_MASK: u16 = 0x1022;
+    pub const PRACH_CONFIG_INDEX: u16 = 0x1029;
+}
+
+/// Numerologies of the per numerology grid size TLVs
+const NUM_NUMEROLOGIES: usize = 5;
+
+/// Error codes of ERROR.indication and CONFIG.response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    MsgOk = 0,
+    MsgInvalidState = 1,
+    MsgInvalidConfig = 2,
+    SfnOutOfSync = 3,
+    MsgSlotErr = 4,
+    MsgBchMissing = 5,
+    MsgInvalidSfn = 6,
+    MsgUlDciErr = 7,
+    MsgTxErr = 8,
+}
+
+impl ErrorCode {
+    /// Look up an error code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use ErrorCode::*;
+
+        [MsgOk, MsgInvalidState, MsgInvalidConfig, SfnOutOfSync, MsgSlotErr, MsgBchMissing, MsgInvalidSfn,
+         MsgUlDciErr, MsgTxErr]
+            .into_iter()
+            .find(|error| *error as u8 == code)
+    }
+}
+
+/// Frame duplex type of the cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameDuplexType {
+    #[default]
+    Fdd = 0,
+    Tdd = 1,
+}
+
+/// CONFIG.request: carrier, cell, SSB and PRACH configuration of the L1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigRequest {
+    /// Downlink carrier bandwidth in MHz
+    pub dl_bandwidth: u16,
+    /// Downlink point A in kHz
+    pub dl_frequency: u32,
+    /// Downlink grid size in PRBs, for the common subcarrier spacing
+    pub dl_grid_size: u16,
+    pub num_tx_ant: u16,
+    /// Uplink carrier bandwidth in MHz
+    pub ul_bandwidth: u16,
+    /// Uplink point A in kHz
+    pub ul_frequency: u32,
+    /// Uplink grid size in PRBs, for the common subcarrier spacing
+    pub ul_grid_size: u16,
+    pub num_rx_ant: u16,
+    pub phy_cell_id: u16,
+    pub frame_duplex_type: FrameDuplexType,
+    /// Numerology of the common subcarrier spacing
+    pub scs_common: u8,
+    /// Numerology of the PRACH subcarrier spacing
+    pub prach_sub_c_spacing: u8,
+    pub prach_root_sequence_index: u16,
+    pub prach_zero_corr_conf: u8,
+    /// PRACH configuration index (TS 38.211 Table 6.3.3.2-2)
+    pub prach_config_index: u8,
+    /// Offset of the SSB from point A in PRBs
+    pub ssb_offset_point_a: u16,
+    /// SSB periodicity: 0 for 5 ms up to 5 for 160 ms
+    pub ssb_period: u8,
+    /// k_SSB, in subcarriers
+    pub ssb_subcarrier_offset: u8,
+    /// SSBs transmitted, the first in the most significant bit
+    pub ssb_mask: u32,
+}
+
+impl ConfigRequest {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let tlvs: Vec<(u16, Vec<u8>)> = vec![
+            (tag::DL_BANDWIDTH, self.dl_bandwidth.to_le_bytes().to_vec()),
+            (tag::DL_FREQUENCY, self.dl_frequency.to_le_bytes().to_vec()),
+            (tag::DL_GRID_SIZE, grid_sizes(self.scs_common, self.dl_grid_size)),
+            (tag::NUM_TX_ANT, self.num_tx_ant.to_le_bytes().to_vec()),
+            (tag::UL_BANDWIDTH, self.ul_bandwidth.to_le_bytes().to_vec()),
+            (tag::UL_FREQUENCY, self.ul_frequency.to_le_bytes().to_vec()),
+            (tag::UL_GRID_SIZE, grid_sizes(self.scs_common, self.ul_grid_size)),
+            (tag::NUM_RX_ANT, self.num_rx_ant.to_le_bytes().to_vec()),
+            (tag::PHY_CELL_ID, self.phy_cell_id.to_le_bytes().to_vec()),
+            (tag::FRAME_DUPLEX_TYPE, vec![self.frame_duplex_type as u8]),
+            (tag::SCS_COMMON, vec![self.scs_common]),
+            (tag::PRACH_SUBC_SPACING, vec![self.prach_sub_c_spacing]),
+            (tag::PRACH_ROOT_SEQUENCE_INDEX, self.prach_root_sequence_index.to_le_bytes().to_vec()),
+            (tag::PRACH_ZERO_CORR_CONF, vec![self.prach_zero_corr_conf]),
+            (tag::SSB_OFFSET_POINT_A, self.ssb_offset_point_a.to_le_bytes().to_vec()),
+            (tag::SSB_PERIOD, vec![self.ssb_period]),
+            (tag::SSB_SUBCARRIER_OFFSET, vec![self.ssb_subcarrier_offset]),
+            (tag::SSB_MASK, self.ssb_mask.to_le_bytes().to_vec()),
+            (tag::PRACH_CONFIG_INDEX, vec![self.prach_config_index]),
+        ];
+
+        buf.put_u8(tlvs.len() as u8);
+        for (tag, value) in tlvs {
+            buf.put_u16_le(tag);
+            buf.put_u16_le(value.len() as u16);
+            buf.put_slice(&value);
+            // Values are padded to 32 bits
+            buf.put_bytes(0, (4 - value.len() % 4) % 4);
+        }
+    }
+
+    /// Decode the message body, skipping the TLVs the L1 does not use
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let num_tlvs = take(buf, 1)?.get_u8();
+        let mut config = Self::default();
+        let mut grid_sizes = (None, None);
+        for _ in 0..num_tlvs {
+            let mut header = take(buf, 4)?;
+            let (tag, len) = (header.get_u16_le(), header.get_u16_le() as usize);
+            let mut value = take(buf, len)?;
+            take(buf, (4 - len % 4) % 4)?;
+            let value = &mut value;
+            match tag {
+                tag::DL_BANDWIDTH => config.dl_bandwidth = take(value, 2)?.get_u16_le(),
+                tag::DL_FREQUENCY => config.dl_frequency = take(value, 4)?.get_u32_le(),
+                tag::DL_GRID_SIZE => grid_sizes.0 = Some(take(value, 2 * NUM_NUMEROLOGIES)?),
+                tag::NUM_TX_ANT => config.num_tx_ant = take(value, 2)?.get_u16_le(),
+                tag::UL_BANDWIDTH => config.ul_bandwidth = take(value, 2)?.get_u16_le(),
+                tag::UL_FREQUENCY => config.ul_frequency = take(value, 4)?.get_u32_le(),
+                tag::UL_GRID_SIZE => grid_sizes.1 = Some(take(value, 2 * NUM_NUMEROLOGIES)?),
+                tag::NUM_RX_ANT => config.num_rx_ant = take(value, 2)?.get_u16_le(),
+                tag::PHY_CELL_ID => config.phy_cell_id = take(value, 2)?.get_u16_le(),
+                tag::FRAME_DUPLEX_TYPE => {
+                    config.frame_duplex_type = match take(value, 1)?.get_u8() {
+                        0 => FrameDuplexType::Fdd,
+                        1 => FrameDuplexType::Tdd,
+                        _ => return Err(LayerError::InvalidPdu),
+                    }
+                }
+                tag::SCS_COMMON => config.scs_common = take(value, 1)?.get_u8(),
+                tag::PRACH_SUBC_SPACING => config.prach_sub_c_spacing = take(value, 1)?.get_u8(),
+                tag::PRACH_ROOT_SEQUENCE_INDEX => config.prach_root_sequence_index = take(value, 2)?.get_u16_le(),
+                tag::PRACH_ZERO_CORR_CONF => config.prach_zero_corr_conf = take(value, 1)?.get_u8(),
+                tag::SSB_OFFSET_POINT_A => config.ssb_offset_point_a = take(value, 2)?.get_u16_le(),
+                tag::SSB_PERIOD => config.ssb_period = take(value, 1)?.get_u8(),
+                tag::SSB_SUBCARRIER_OFFSET => config.ssb_subcarrier_offset = take(value, 1)?.get_u8(),
+                tag::SSB_MASK => config.ssb_mask = take(value, 4)?.get_u32_le(),
+                tag::PRACH_CONFIG_INDEX => config.prach_config_index = take(value, 1)?.get_u8(),
+                _ => debug!("Skipping CONFIG.request TLV {:#06x}", tag),
+            }
+        }
+
+        // The grid sizes are per numerology, the common one is used
+        let numerology = config.scs_common as usize;
+        if numerology < NUM_NUMEROLOGIES {
+            if let Some(mut sizes) = grid_sizes.0 {
+                sizes.advance(2 * numerology);
+                config.dl_grid_size = sizes.get_u16_le();
+            }
+            if let Some(mut sizes) = grid_sizes.1 {
+                sizes.advance(2 * numerology);
+                config.ul_grid_size = sizes.get_u16_le();
+            }
+        }
+        Ok(config)
+    }
+
+    /// Tags of the values the L1 cannot be configured with
+    pub fn invalid_tags(&self) -> Vec<u16> {
+        let mut invalid = Vec::new();
+        if self.scs_common as usize >= NUM_NUMEROLOGIES {
+            invalid.push(tag::SCS_COMMON);
+        }
+        if self.dl_grid_size == 0 || self.dl_grid_size > 275 {
+            invalid.push(tag::DL_GRID_SIZE);
+        }
+        if self.ul_grid_size == 0 || self.ul_grid_size > 275 {
+            invalid.push(tag::UL_GRID_SIZE);
+        }
+        if self.phy_cell_id > 1007 {
+            invalid.push(tag::PHY_CELL_ID);
+        }
+        if self.ssb_period > 5 {
+            invalid.push(tag::SSB_PERIOD);
+        }
+        invalid
+    }
+}
+
+/// Grid sizes of all numerologies, zero but for the one used
+fn grid_sizes(numerology: u8, grid_size: u16) -> Vec<u8> {
+    (0..NUM_NUMEROLOGIES)
+        .flat_map(|mu| if mu == numerology as usize { grid_size } else { 0 }.to_le_bytes())
+        .collect()
+}
+
+/// CONFIG.response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigResponse {
+    pub error_code: ErrorCode,
+    /// Tags of the invalid or unsupported TLVs
+    pub invalid_tags: Vec<u16>,
+}
+
+impl ConfigResponse {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.error_code as u8);
+        buf.put_u8(self.invalid_tags.len() as u8);
+        // No TLVs only valid in IDLE or RUNNING, none missing
+        buf.put_bytes(0, 3);
+        for tag in &self.invalid_tags {
+            buf.put_u16_le(*tag);
+            buf.put_u16_le(0);
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 5)?;
+        let error_code = ErrorCode::from_u8(header.get_u8()).ok_or(LayerError::InvalidPdu)?;
+        let num_invalid = header.get_u8();
+        let invalid_tags = (0..num_invalid)
+            .map(|_| take(buf, 4).map(|mut tlv| tlv.get_u16_le()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { error_code, invalid_tags })
+    }
+}
+
+/// ERROR.indication: a request the L1 rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorIndication {
+    pub sfn: u16,
+    pub slot: u16,
+    /// Message type of the rejected request
+    pub message_id: u8,
+    pub error_code: ErrorCode,
+}
+
+impl ErrorIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u8(self.message_id);
+        buf.put_u8(self.error_code as u8);
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 6)?;
+        Ok(Self {
+            sfn: body.get_u16_le(),
+            slot: body.get_u16_le(),
+            message_id: body.get_u8(),
+            error_code: ErrorCode::from_u8(body.get_u8()).ok_or(LayerError::InvalidPdu)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_request_tlvs() {
+        let config = ConfigRequest {
+            dl_bandwidth: 10,
+            dl_frequency: 1_842_600,
+            dl_grid_size: 52,
+            num_tx_ant: 1,
+            ul_bandwidth: 10,
+            ul_frequency: 1_747_600,
+            ul_grid_size: 52,
+            num_rx_ant: 1,
+            phy_cell_id: 500,
+            frame_duplex_type: FrameDuplexType::Fdd,
+            scs_common: 0,
+            prach_sub_c_spacing: 0,
+            prach_root_sequence_index: 1,
+            prach_zero_corr_conf: 12,
+            prach_config_index: 16,
+            ssb_offset_point_a: 13,
+            ssb_period: 2,
+            ssb_subcarrier_offset: 6,
+            ssb_mask: 0xC000_0000,
+        };
+        let mut buf = BytesMut::new();
+        config.encode(&mut buf);
+        // Number of TLVs, then dlBandwidth padded to 32 bits
+        assert_eq!(&buf[..9], &[19, 0x01, 0x10, 2, 0, 10, 0, 0, 0]);
+        assert_eq!(buf.len() % 4, 1);
+        assert_eq!(ConfigRequest::decode(&mut &buf[..]).unwrap(), config);
+        assert!(config.invalid_tags().is_empty());
+        assert_eq!(ConfigRequest { phy_cell_id: 1008, dl_grid_size: 0, ..config.clone() }.invalid_tags(),
+                   vec![tag::DL_GRID_SIZE, tag::PHY_CELL_ID]);
+
+        // Unknown TLVs are skipped
+        let mut unknown = BytesMut::new();
+        unknown.put_u8(2);
+        unknown.put_slice(&[0xFF, 0x10, 1, 0, 7, 0, 0, 0]);
+        unknown.put_slice(&[0x0C, 0x10, 2, 0, 0xF4, 0x01, 0, 0]);
+        assert_eq!(ConfigRequest::decode(&mut &unknown[..]).unwrap().phy_cell_id, 500);
+        assert!(ConfigRequest::decode(&mut &buf[..buf.len() - 1]).is_err());
+
+        let response = ConfigResponse { error_code: ErrorCode::MsgInvalidConfig, invalid_tags: vec![tag::PHY_CELL_ID] };
+        let mut buf = BytesMut::new();
+        response.encode(&mut buf);
+        assert_eq!(ConfigResponse::decode(&mut &buf[..]).unwrap(), response);
+    }
+
+    #[test]
+    fn test_p5_decode_errors() {
+        let tlv = |tag: u16, value: &[u8]| {
+            let mut buf = BytesMut::new();
+            buf.put_u8(1);
+            buf.put_u16_le(tag);
+            buf.put_u16_le(value.len() as u16);
+            buf.put_slice(value);
+            buf.put_bytes(0, (4 - value.len() % 4) % 4);
+            buf
+        };
+        // Values shorter than their tag, unknown duplex type, missing padding
+        assert!(matches!(ConfigRequest::decode(&mut &tlv(tag::PHY_CELL_ID, &[1])[..]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(ConfigRequest::decode(&mut &tlv(tag::DL_GRID_SIZE, &[52, 0])[..]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(ConfigRequest::decode(&mut &tlv(tag::FRAME_DUPLEX_TYPE, &[2])[..]), Err(LayerError::InvalidPdu)));
+        let duplex = tlv(tag::FRAME_DUPLEX_TYPE, &[1]);
+        assert_eq!(ConfigRequest::decode(&mut &duplex[..]).unwrap().frame_duplex_type, FrameDuplexType::Tdd);
+        assert!(ConfigRequest::decode(&mut &duplex[..duplex.len() - 1]).is_err());
+        assert!(ConfigRequest::decode(&mut &[][..]).is_err());
+
+        // A numerology without grid size leaves the grids unset, which is
+        // reported with the other invalid values
+        let mut buf = BytesMut::new();
+        ConfigRequest { scs_common: 5, dl_grid_size: 52, ul_grid_size: 52, ..Default::default() }.encode(&mut buf);
+        let config = ConfigRequest::decode(&mut &buf[..]).unwrap();
+        assert_eq!((config.dl_grid_size, config.ul_grid_size), (0, 0));
+        assert_eq!(ConfigRequest { ssb_period: 6, ul_grid_size: 276, ..config }.invalid_tags(),
+                   vec![tag::SCS_COMMON, tag::DL_GRID_SIZE, tag::UL_GRID_SIZE, tag::SSB_PERIOD]);
+
+        // CONFIG.response with an unknown error code or fewer tags than counted
+        let response = ConfigResponse { error_code: ErrorCode::MsgInvalidConfig, invalid_tags: vec![tag::PHY_CELL_ID] };
+        let mut buf = BytesMut::new();
+        response.encode(&mut buf);
+        assert!(ConfigResponse::decode(&mut &buf[..buf.len() - 1]).is_err());
+        let mut other = buf.to_vec();
+        other[0] = 9;
+        assert!(matches!(ConfigResponse::decode(&mut &other[..]), Err(LayerError::InvalidPdu)));
+
+        // ERROR.indication
+        let error = ErrorIndication { sfn: 1023, slot: 19, message_id: 0x80, error_code: ErrorCode::MsgSlotErr };
+        let mut buf = BytesMut::new();
+        error.encode(&mut buf);
+        assert_eq!(ErrorIndication::decode(&mut &buf[..]).unwrap(), error);
+        assert!(ErrorIndication::decode(&mut &buf[..5]).is_err());
+        buf[5] = 9;
+        assert!(matches!(ErrorIndication::decode(&mut &buf[..]), Err(LayerError::InvalidPdu)));
+    }
+}
@@ -0,0 +1,1340 @@
+//! P7 slot messages
+//!
+//! The per slot messages between the MAC and the L1 (SCF 222.10.02 section
+//! 3.4): SLOT.indication starts each slot, the MAC answers with the
+//! DL_TTI.request, UL_TTI.request, UL_DCI.request and TX_Data.request of the
+//! slot, and the L1 reports what it received in RX_Data.indication,
+//! CRC.indication, UCI.indication and RACH.indication.
+//!
+//! PDUs carry the fields of the specification this stack uses, in the order of
+//! the specification, with a single codeword and resource allocation type 1
+//! (contiguous RBs) for PDSCH and PUSCH.
+
+use super::take;
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tracing::debug;
+
+/// PDU types of DL_TTI.request
+const DL_PDU_PDCCH: u16 = 0;
+const DL_PDU_PDSCH: u16 = 1;
+const DL_PDU_SSB: u16 = 3;
+/// PDU types of UL_TTI.request
+const UL_PDU_PRACH: u16 = 0;
+const UL_PDU_PUSCH: u16 = 1;
+const UL_PDU_PUCCH: u16 = 2;
+/// PDU type of the UCI of PUCCH formats 0 and 1 in UCI.indication
+const UCI_PDU_PUCCH_F01: u16 = 1;
+/// pduBitmap of UCI PUCCH format 0/1 PDUs
+const UCI_SR_PRESENT: u8 = 0x01;
+const UCI_HARQ_PRESENT: u8 = 0x02;
+/// Resource allocation type 1
+const RESOURCE_ALLOCATION_TYPE_1: u8 = 1;
+/// TX_Data.request TLV tag of a payload carried by value
+const TX_DATA_TAG_VALUE: u16 = 0;
+
+/// Write a PDU preceded by its type and size, the header included
+fn put_pdu(buf: &mut BytesMut, pdu_type: u16, encode: impl FnOnce(&mut BytesMut)) {
+    let start = buf.len();
+    buf.put_u16_le(pdu_type);
+    buf.put_u16_le(0);
+    encode(buf);
+    let size = (buf.len() - start) as u16;
+    buf[start + 2..start + 4].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Split a PDU off the front of the buffer, returning its type and body
+fn take_pdu<'a>(buf: &mut &'a [u8]) -> Result<(u16, &'a [u8]), LayerError> {
+    let mut header = take(buf, 4)?;
+    let pdu_type = header.get_u16_le();
+    let size = (header.get_u16_le() as usize).checked_sub(4).ok_or(LayerError::InvalidPdu)?;
+    Ok((pdu_type, take(buf, size)?))
+}
+
+/// Split `len` bytes of payload off the front of the buffer
+fn take_payload(buf: &mut &[u8], len: usize) -> Result<Bytes, LayerError> {
+    take(buf, len).map(Bytes::copy_from_slice)
+}
+
+/// SLOT.indication: the L1 starts a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotIndication {
+    pub sfn: u16,
+    pub slot: u16,
+}
+
+impl SlotIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 4)?;
+        Ok(Self { sfn: body.get_u16_le(), slot: body.get_u16_le() })
+    }
+}
+
+/// DCI of a PDCCH PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DlDci {
+    pub rnti: u16,
+    /// n_ID of the PDCCH scrambling
+    pub scrambling_id: u16,
+    /// n_RNTI of the PDCCH scrambling, 0 in common search spaces
+    pub scrambling_rnti: u16,
+    pub cce_index: u16,
+    pub aggregation_level: u8,
+    /// PDCCH power relative to the SSS, for DCI format 1_0
+    pub beta_pdcch_1_0: u8,
+    pub power_control_offset_ss: u8,
+    /// Size of the DCI in bits
+    pub payload_size_bits: u16,
+    /// DCI bits, the first in the most significant bit of the first octet
+    pub payload: Bytes,
+}
+
+/// PDCCH PDU: a CORESET and the DCIs sent in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdcchPdu {
+    pub bwp_size: u16,
+    pub bwp_start: u16,
+    pub subcarrier_spacing: u8,
+    pub cyclic_prefix: u8,
+    pub start_symbol_index: u8,
+    pub duration_symbols: u8,
+    /// Groups of 6 RBs from the start of the BWP, the first in the most
+    /// significant bit of the first octet
+    pub freq_domain_resource: [u8; 6],
+    pub cce_reg_mapping_type: u8,
+    pub reg_bundle_size: u8,
+    pub interleaver_size: u8,
+    /// 0 for CORESET#0 configured by the PBCH, 1 otherwise
+    pub coreset_type: u8,
+    pub shift_index: u16,
+    pub precoder_granularity: u8,
+    pub dl_dcis: Vec<DlDci>,
+}
+
+impl PdcchPdu {
+    /// RBs of the CORESET
+    pub fn coreset_rbs(&self) -> Vec<u16> {
+        (0..48u16)
+            .filter(|group| self.freq_domain_resource[*group as usize / 8] & (0x80 >> (group % 8)) != 0)
+            .flat_map(|group| {
+                let start = self.bwp_start + 6 * group;
+                start..start + 6
+            })
+            .collect()
+    }
+
+    /// Frequency domain resources of a CORESET of contiguous groups of 6 RBs
+    /// from the start of the BWP
+    pub fn freq_domain_resource_of(num_rbs: u16) -> [u8; 6] {
+        let mut bitmap = [0u8; 6];
+        for group in 0..(num_rbs / 6).min(48) {
+            bitmap[group as usize / 8] |= 0x80 >> (group % 8);
+        }
+        bitmap
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.bwp_size);
+        buf.put_u16_le(self.bwp_start);
+        buf.put_u8(self.subcarrier_spacing);
+        buf.put_u8(self.cyclic_prefix);
+        buf.put_u8(self.start_symbol_index);
+        buf.put_u8(self.duration_symbols);
+        buf.put_slice(&self.freq_domain_resource);
+        buf.put_u8(self.cce_reg_mapping_type);
+        buf.put_u8(self.reg_bundle_size);
+        buf.put_u8(self.interleaver_size);
+        buf.put_u8(self.coreset_type);
+        buf.put_u16_le(self.shift_index);
+        buf.put_u8(self.precoder_granularity);
+        buf.put_u16_le(self.dl_dcis.len() as u16);
+        for dci in &self.dl_dcis {
+            buf.put_u16_le(dci.rnti);
+            buf.put_u16_le(dci.scrambling_id);
+            buf.put_u16_le(dci.scrambling_rnti);
+            buf.put_u16_le(dci.cce_index);
+            buf.put_u8(dci.aggregation_level);
+            buf.put_u8(dci.beta_pdcch_1_0);
+            buf.put_u8(dci.power_control_offset_ss);
+            buf.put_u16_le(dci.payload_size_bits);
+            let len = dci.payload_size_bits.div_ceil(8) as usize;
+            buf.put_slice(&dci.payload[..len.min(dci.payload.len())]);
+            buf.put_bytes(0, len.saturating_sub(dci.payload.len()));
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 23)?;
+        let mut pdu = Self {
+            bwp_size: header.get_u16_le(),
+            bwp_start: header.get_u16_le(),
+            subcarrier_spacing: header.get_u8(),
+            cyclic_prefix: header.get_u8(),
+            start_symbol_index: header.get_u8(),
+            duration_symbols: header.get_u8(),
+            freq_domain_resource: {
+                let mut bitmap = [0u8; 6];
+                header.copy_to_slice(&mut bitmap);
+                bitmap
+            },
+            cce_reg_mapping_type: header.get_u8(),
+            reg_bundle_size: header.get_u8(),
+            interleaver_size: header.get_u8(),
+            coreset_type: header.get_u8(),
+            shift_index: header.get_u16_le(),
+            precoder_granularity: header.get_u8(),
+            dl_dcis: Vec::new(),
+        };
+        let num_dcis = header.get_u16_le();
+        for _ in 0..num_dcis {
+            let mut dci = take(buf, 13)?;
+            let (rnti, scrambling_id, scrambling_rnti, cce_index) =
+                (dci.get_u16_le(), dci.get_u16_le(), dci.get_u16_le(), dci.get_u16_le());
+            let (aggregation_level, beta_pdcch_1_0, power_control_offset_ss) = (dci.get_u8(), dci.get_u8(), dci.get_u8());
+            let payload_size_bits = dci.get_u16_le();
+            pdu.dl_dcis.push(DlDci {
+                rnti,
+                scrambling_id,
+                scrambling_rnti,
+                cce_index,
+                aggregation_level,
+                beta_pdcch_1_0,
+                power_control_offset_ss,
+                payload_size_bits,
+                payload: take_payload(buf, payload_size_bits.div_ceil(8) as usize)?,
+            });
+        }
+        Ok(pdu)
+    }
+}
+
+/// PDSCH PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdschPdu {
+    pub rnti: u16,
+    /// Index of the TX_Data.request PDU carrying the transport block
+    pub pdu_index: u16,
+    pub bwp_size: u16,
+    pub bwp_start: u16,
+    pub subcarrier_spacing: u8,
+    pub cyclic_prefix: u8,
+    /// Target code rate times 1024 per modulation bit, times 10
+    pub target_code_rate: u16,
+    /// Bits per modulation symbol: 2, 4, 6 or 8
+    pub qam_mod_order: u8,
+    pub mcs_index: u8,
+    pub mcs_table: u8,
+    pub rv_index: u8,
+    /// Transport block size in octets
+    pub tb_size: u32,
+    pub data_scrambling_id: u16,
+    pub nr_of_layers: u8,
+    /// 0 for point A, 1 for the start of the BWP
+    pub ref_point: u8,
+    /// Symbols of the slot carrying DM-RS, symbol 0 in the least significant bit
+    pub dl_dmrs_symb_pos: u16,
+    pub dmrs_config_type: u8,
+    /// First RB from the start of the BWP
+    pub rb_start: u16,
+    pub rb_size: u16,
+    pub vrb_to_prb_mapping: u8,
+    pub start_symbol_index: u8,
+    pub nr_of_symbols: u8,
+}
+
+impl PdschPdu {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(0); // pduBitmap: no PTRS or CBG retransmission
+        buf.put_u16_le(self.rnti);
+        buf.put_u16_le(self.pdu_index);
+        buf.put_u16_le(self.bwp_size);
+        buf.put_u16_le(self.bwp_start);
+        buf.put_u8(self.subcarrier_spacing);
+        buf.put_u8(self.cyclic_prefix);
+        buf.put_u8(1); // nrOfCodewords
+        buf.put_u16_le(self.target_code_rate);
+        buf.put_u8(self.qam_mod_order);
+        buf.put_u8(self.mcs_index);
+        buf.put_u8(self.mcs_table);
+        buf.put_u8(self.rv_index);
+        buf.put_u32_le(self.tb_size);
+        buf.put_u16_le(self.data_scrambling_id);
+        buf.put_u8(self.nr_of_layers);
+        buf.put_u8(0); // transmissionScheme
+        buf.put_u8(self.ref_point);
+        buf.put_u16_le(self.dl_dmrs_symb_pos);
+        buf.put_u8(self.dmrs_config_type);
+        buf.put_u8(RESOURCE_ALLOCATION_TYPE_1);
+        buf.put_u16_le(self.rb_start);
+        buf.put_u16_le(self.rb_size);
+        buf.put_u8(self.vrb_to_prb_mapping);
+        buf.put_u8(self.start_symbol_index);
+        buf.put_u8(self.nr_of_symbols);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 39)?;
+        body.advance(2);
+        let (rnti, pdu_index, bwp_size, bwp_start) =
+            (body.get_u16_le(), body.get_u16_le(), body.get_u16_le(), body.get_u16_le());
+        let (subcarrier_spacing, cyclic_prefix) = (body.get_u8(), body.get_u8());
+        if body.get_u8() != 1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let target_code_rate = body.get_u16_le();
+        let (qam_mod_order, mcs_index, mcs_table, rv_index) = (body.get_u8(), body.get_u8(), body.get_u8(), body.get_u8());
+        let tb_size = body.get_u32_le();
+        let data_scrambling_id = body.get_u16_le();
+        let nr_of_layers = body.get_u8();
+        body.advance(1);
+        let ref_point = body.get_u8();
+        let dl_dmrs_symb_pos = body.get_u16_le();
+        let dmrs_config_type = body.get_u8();
+        if body.get_u8() != RESOURCE_ALLOCATION_TYPE_1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        Ok(Self {
+            rnti,
+            pdu_index,
+            bwp_size,
+            bwp_start,
+            subcarrier_spacing,
+            cyclic_prefix,
+            target_code_rate,
+            qam_mod_order,
+            mcs_index,
+            mcs_table,
+            rv_index,
+            tb_size,
+            data_scrambling_id,
+            nr_of_layers,
+            ref_point,
+            dl_dmrs_symb_pos,
+            dmrs_config_type,
+            rb_start: body.get_u16_le(),
+            rb_size: body.get_u16_le(),
+            vrb_to_prb_mapping: body.get_u8(),
+            start_symbol_index: body.get_u8(),
+            nr_of_symbols: body.get_u8(),
+        })
+    }
+}
+
+/// SSB PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SsbPdu {
+    pub phy_cell_id: u16,
+    /// PSS power relative to the SSS: 0 for 0 dB, 1 for 3 dB
+    pub beta_pss: u8,
+    pub ssb_block_index: u8,
+    pub ssb_subcarrier_offset: u8,
+    pub ssb_offset_point_a: u16,
+    /// 0 when the MAC provides the whole MIB, 1 when the L1 adds the timing
+    /// bits, 2 when the L1 generates the whole PBCH payload
+    pub bch_payload_flag: u8,
+    pub bch_payload: u32,
+}
+
+impl SsbPdu {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.phy_cell_id);
+        buf.put_u8(self.beta_pss);
+        buf.put_u8(self.ssb_block_index);
+        buf.put_u8(self.ssb_subcarrier_offset);
+        buf.put_u16_le(self.ssb_offset_point_a);
+        buf.put_u8(self.bch_payload_flag);
+        buf.put_u32_le(self.bch_payload);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 12)?;
+        Ok(Self {
+            phy_cell_id: body.get_u16_le(),
+            beta_pss: body.get_u8(),
+            ssb_block_index: body.get_u8(),
+            ssb_subcarrier_offset: body.get_u8(),
+            ssb_offset_point_a: body.get_u16_le(),
+            bch_payload_flag: body.get_u8(),
+            bch_payload: body.get_u32_le(),
+        })
+    }
+}
+
+/// PDU of DL_TTI.request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DlTtiPdu {
+    Pdcch(PdcchPdu),
+    Pdsch(PdschPdu),
+    Ssb(SsbPdu),
+}
+
+/// DL_TTI.request: the downlink channels of a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DlTtiRequest {
+    pub sfn: u16,
+    pub slot: u16,
+    pub pdus: Vec<DlTtiPdu>,
+}
+
+impl DlTtiRequest {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u8(self.pdus.len() as u8);
+        buf.put_u8(0); // nGroup: no UE groups
+        for pdu in &self.pdus {
+            match pdu {
+                DlTtiPdu::Pdcch(pdcch) => put_pdu(buf, DL_PDU_PDCCH, |buf| pdcch.encode(buf)),
+                DlTtiPdu::Pdsch(pdsch) => put_pdu(buf, DL_PDU_PDSCH, |buf| pdsch.encode(buf)),
+                DlTtiPdu::Ssb(ssb) => put_pdu(buf, DL_PDU_SSB, |buf| ssb.encode(buf)),
+            }
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 6)?;
+        let (sfn, slot, num_pdus) = (header.get_u16_le(), header.get_u16_le(), header.get_u8());
+        let mut pdus = Vec::with_capacity(num_pdus as usize);
+        for _ in 0..num_pdus {
+            let (pdu_type, mut body) = take_pdu(buf)?;
+            pdus.push(match pdu_type {
+                DL_PDU_PDCCH => DlTtiPdu::Pdcch(PdcchPdu::decode(&mut body)?),
+                DL_PDU_PDSCH => DlTtiPdu::Pdsch(PdschPdu::decode(&mut body)?),
+                DL_PDU_SSB => DlTtiPdu::Ssb(SsbPdu::decode(&mut body)?),
+                _ => return Err(LayerError::InvalidPdu),
+            });
+        }
+        Ok(Self { sfn, slot, pdus })
+    }
+}
+
+/// PRACH PDU: a PRACH occasion to detect preambles in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrachPdu {
+    pub phy_cell_id: u16,
+    /// PRACH occasions in time within the slot
+    pub num_prach_ocas: u8,
+    /// Preamble format: 0 to 3 for long preambles, then A1, A2, A3, B1, B4, C0 and C2
+    pub prach_format: u8,
+    /// Index of the occasion in frequency
+    pub index_fd_ra: u8,
+    pub prach_start_symbol: u8,
+    /// Cyclic shift N_CS
+    pub num_cs: u16,
+}
+
+impl PrachPdu {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.phy_cell_id);
+        buf.put_u8(self.num_prach_ocas);
+        buf.put_u8(self.prach_format);
+        buf.put_u8(self.index_fd_ra);
+        buf.put_u8(self.prach_start_symbol);
+        buf.put_u16_le(self.num_cs);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 8)?;
+        Ok(Self {
+            phy_cell_id: body.get_u16_le(),
+            num_prach_ocas: body.get_u8(),
+            prach_format: body.get_u8(),
+            index_fd_ra: body.get_u8(),
+            prach_start_symbol: body.get_u8(),
+            num_cs: body.get_u16_le(),
+        })
+    }
+}
+
+/// PUSCH PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PuschPdu {
+    pub rnti: u16,
+    /// Handle the L1 reports the reception with
+    pub handle: u32,
+    pub bwp_size: u16,
+    pub bwp_start: u16,
+    pub subcarrier_spacing: u8,
+    pub cyclic_prefix: u8,
+    /// Target code rate times 1024 per modulation bit, times 10
+    pub target_code_rate: u16,
+    pub qam_mod_order: u8,
+    pub mcs_index: u8,
+    pub mcs_table: u8,
+    pub transform_precoding: bool,
+    pub data_scrambling_id: u16,
+    pub nr_of_layers: u8,
+    /// Symbols of the slot carrying DM-RS, symbol 0 in the least significant bit
+    pub ul_dmrs_symb_pos: u16,
+    pub dmrs_config_type: u8,
+    /// First RB from the start of the BWP
+    pub rb_start: u16,
+    pub rb_size: u16,
+    pub vrb_to_prb_mapping: u8,
+    pub start_symbol_index: u8,
+    pub nr_of_symbols: u8,
+    pub rv_index: u8,
+    pub harq_process_id: u8,
+    pub new_data_indicator: bool,
+    /// Transport block size in octets
+    pub tb_size: u32,
+}
+
+impl PuschPdu {
+    /// pduBitmap bit of a PUSCH carrying data
+    const PUSCH_DATA: u16 = 0x01;
+
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(Self::PUSCH_DATA);
+        buf.put_u16_le(self.rnti);
+        buf.put_u32_le(self.handle);
+        buf.put_u16_le(self.bwp_size);
+        buf.put_u16_le(self.bwp_start);
+        buf.put_u8(self.subcarrier_spacing);
+        buf.put_u8(self.cyclic_prefix);
+        buf.put_u16_le(self.target_code_rate);
+        buf.put_u8(self.qam_mod_order);
+        buf.put_u8(self.mcs_index);
+        buf.put_u8(self.mcs_table);
+        buf.put_u8(self.transform_precoding as u8);
+        buf.put_u16_le(self.data_scrambling_id);
+        buf.put_u8(self.nr_of_layers);
+        buf.put_u16_le(self.ul_dmrs_symb_pos);
+        buf.put_u8(self.dmrs_config_type);
+        buf.put_u8(RESOURCE_ALLOCATION_TYPE_1);
+        buf.put_u16_le(self.rb_start);
+        buf.put_u16_le(self.rb_size);
+        buf.put_u8(self.vrb_to_prb_mapping);
+        buf.put_u8(self.start_symbol_index);
+        buf.put_u8(self.nr_of_symbols);
+        buf.put_u8(self.rv_index);
+        buf.put_u8(self.harq_process_id);
+        buf.put_u8(self.new_data_indicator as u8);
+        buf.put_u32_le(self.tb_size);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 41)?;
+        if body.get_u16_le() & Self::PUSCH_DATA == 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let (rnti, handle) = (body.get_u16_le(), body.get_u32_le());
+        let (bwp_size, bwp_start) = (body.get_u16_le(), body.get_u16_le());
+        let (subcarrier_spacing, cyclic_prefix) = (body.get_u8(), body.get_u8());
+        let target_code_rate = body.get_u16_le();
+        let (qam_mod_order, mcs_index, mcs_table) = (body.get_u8(), body.get_u8(), body.get_u8());
+        let transform_precoding = body.get_u8() != 0;
+        let data_scrambling_id = body.get_u16_le();
+        let nr_of_layers = body.get_u8();
+        let ul_dmrs_symb_pos = body.get_u16_le();
+        let dmrs_config_type = body.get_u8();
+        if body.get_u8() != RESOURCE_ALLOCATION_TYPE_1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        Ok(Self {
+            rnti,
+            handle,
+            bwp_size,
+            bwp_start,
+            subcarrier_spacing,
+            cyclic_prefix,
+            target_code_rate,
+            qam_mod_order,
+            mcs_index,
+            mcs_table,
+            transform_precoding,
+            data_scrambling_id,
+            nr_of_layers,
+            ul_dmrs_symb_pos,
+            dmrs_config_type,
+            rb_start: body.get_u16_le(),
+            rb_size: body.get_u16_le(),
+            vrb_to_prb_mapping: body.get_u8(),
+            start_symbol_index: body.get_u8(),
+            nr_of_symbols: body.get_u8(),
+            rv_index: body.get_u8(),
+            harq_process_id: body.get_u8(),
+            new_data_indicator: body.get_u8() != 0,
+            tb_size: body.get_u32_le(),
+        })
+    }
+}
+
+/// PUCCH PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PucchPdu {
+    pub rnti: u16,
+    /// Handle the L1 reports the UCI with
+    pub handle: u32,
+    pub bwp_size: u16,
+    pub bwp_start: u16,
+    pub subcarrier_spacing: u8,
+    pub cyclic_prefix: u8,
+    /// PUCCH format 0 to 4
+    pub format_type: u8,
+    /// First PRB from the start of the BWP
+    pub prb_start: u16,
+    pub prb_size: u16,
+    pub start_symbol_index: u8,
+    pub nr_of_symbols: u8,
+    pub initial_cyclic_shift: u16,
+    pub time_domain_occ_idx: u8,
+    /// The PUCCH occasion may carry a scheduling request
+    pub sr_flag: bool,
+    /// HARQ-ACK bits
+    pub bit_len_harq: u16,
+    /// CSI part 1 bits
+    pub bit_len_csi_part1: u16,
+}
+
+impl PucchPdu {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.rnti);
+        buf.put_u32_le(self.handle);
+        buf.put_u16_le(self.bwp_size);
+        buf.put_u16_le(self.bwp_start);
+        buf.put_u8(self.subcarrier_spacing);
+        buf.put_u8(self.cyclic_prefix);
+        buf.put_u8(self.format_type);
+        buf.put_u16_le(self.prb_start);
+        buf.put_u16_le(self.prb_size);
+        buf.put_u8(self.start_symbol_index);
+        buf.put_u8(self.nr_of_symbols);
+        buf.put_u16_le(self.initial_cyclic_shift);
+        buf.put_u8(self.time_domain_occ_idx);
+        buf.put_u8(self.sr_flag as u8);
+        buf.put_u16_le(self.bit_len_harq);
+        buf.put_u16_le(self.bit_len_csi_part1);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut body = take(buf, 27)?;
+        Ok(Self {
+            rnti: body.get_u16_le(),
+            handle: body.get_u32_le(),
+            bwp_size: body.get_u16_le(),
+            bwp_start: body.get_u16_le(),
+            subcarrier_spacing: body.get_u8(),
+            cyclic_prefix: body.get_u8(),
+            format_type: body.get_u8(),
+            prb_start: body.get_u16_le(),
+            prb_size: body.get_u16_le(),
+            start_symbol_index: body.get_u8(),
+            nr_of_symbols: body.get_u8(),
+            initial_cyclic_shift: body.get_u16_le(),
+            time_domain_occ_idx: body.get_u8(),
+            sr_flag: body.get_u8() != 0,
+            bit_len_harq: body.get_u16_le(),
+            bit_len_csi_part1: body.get_u16_le(),
+        })
+    }
+}
+
+/// PDU of UL_TTI.request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UlTtiPdu {
+    Prach(PrachPdu),
+    Pusch(PuschPdu),
+    Pucch(PucchPdu),
+}
+
+/// UL_TTI.request: the uplink channels of a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UlTtiRequest {
+    pub sfn: u16,
+    pub slot: u16,
+    pub pdus: Vec<UlTtiPdu>,
+}
+
+impl UlTtiRequest {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let count = |matches: fn(&UlTtiPdu) -> bool| self.pdus.iter().filter(|pdu| matches(pdu)).count() as u8;
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u16_le(self.pdus.len() as u16);
+        buf.put_u8(count(|pdu| matches!(pdu, UlTtiPdu::Prach(_))));
+        buf.put_u8(count(|pdu| matches!(pdu, UlTtiPdu::Pusch(_))));
+        buf.put_u8(count(|pdu| matches!(pdu, UlTtiPdu::Pucch(_))));
+        buf.put_u8(0); // nGroup: no UE groups
+        for pdu in &self.pdus {
+            match pdu {
+                UlTtiPdu::Prach(prach) => put_pdu(buf, UL_PDU_PRACH, |buf| prach.encode(buf)),
+                UlTtiPdu::Pusch(pusch) => put_pdu(buf, UL_PDU_PUSCH, |buf| pusch.encode(buf)),
+                UlTtiPdu::Pucch(pucch) => put_pdu(buf, UL_PDU_PUCCH, |buf| pucch.encode(buf)),
+            }
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 10)?;
+        let (sfn, slot, num_pdus) = (header.get_u16_le(), header.get_u16_le(), header.get_u16_le());
+        let mut pdus = Vec::with_capacity(num_pdus as usize);
+        for _ in 0..num_pdus {
+            let (pdu_type, mut body) = take_pdu(buf)?;
+            pdus.push(match pdu_type {
+                UL_PDU_PRACH => UlTtiPdu::Prach(PrachPdu::decode(&mut body)?),
+                UL_PDU_PUSCH => UlTtiPdu::Pusch(PuschPdu::decode(&mut body)?),
+                UL_PDU_PUCCH => UlTtiPdu::Pucch(PucchPdu::decode(&mut body)?),
+                _ => return Err(LayerError::InvalidPdu),
+            });
+        }
+        Ok(Self { sfn, slot, pdus })
+    }
+}
+
+/// UL_DCI.request: the DCIs granting uplink resources in a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UlDciRequest {
+    pub sfn: u16,
+    pub slot: u16,
+    pub pdcchs: Vec<PdcchPdu>,
+}
+
+impl UlDciRequest {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u8(self.pdcchs.len() as u8);
+        for pdcch in &self.pdcchs {
+            put_pdu(buf, DL_PDU_PDCCH, |buf| pdcch.encode(buf));
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 5)?;
+        let (sfn, slot, num_pdus) = (header.get_u16_le(), header.get_u16_le(), header.get_u8());
+        let mut pdcchs = Vec::with_capacity(num_pdus as usize);
+        for _ in 0..num_pdus {
+            match take_pdu(buf)? {
+                (DL_PDU_PDCCH, mut body) => pdcchs.push(PdcchPdu::decode(&mut body)?),
+                _ => return Err(LayerError::InvalidPdu),
+            }
+        }
+        Ok(Self { sfn, slot, pdcchs })
+    }
+}
+
+/// Transport block of TX_Data.request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxPdu {
+    /// Index of the PDSCH PDU of DL_TTI.request it is sent on
+    pub pdu_index: u16,
+    pub payload: Bytes,
+}
+
+/// TX_Data.request: the transport blocks of the PDSCHs of a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxDataRequest {
+    pub sfn: u16,
+    pub slot: u16,
+    pub pdus: Vec<TxPdu>,
+}
+
+impl TxDataRequest {
+    /// Encode the message body, each payload carried by value in one TLV
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u16_le(self.pdus.len() as u16);
+        for pdu in &self.pdus {
+            buf.put_u32_le(pdu.payload.len() as u32);
+            buf.put_u16_le(pdu.pdu_index);
+            buf.put_u32_le(1);
+            buf.put_u16_le(TX_DATA_TAG_VALUE);
+            buf.put_u32_le(pdu.payload.len() as u32);
+            buf.put_slice(&pdu.payload);
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 6)?;
+        let (sfn, slot, num_pdus) = (header.get_u16_le(), header.get_u16_le(), header.get_u16_le());
+        let mut pdus = Vec::with_capacity(num_pdus as usize);
+        for _ in 0..num_pdus {
+            let mut pdu = take(buf, 16)?;
+            pdu.advance(4);
+            let pdu_index = pdu.get_u16_le();
+            if pdu.get_u32_le() != 1 || pdu.get_u16_le() != TX_DATA_TAG_VALUE {
+                return Err(LayerError::InvalidPdu);
+            }
+            let len = pdu.get_u32_le() as usize;
+            pdus.push(TxPdu { pdu_index, payload: take_payload(buf, len)? });
+        }
+        Ok(Self { sfn, slot, pdus })
+    }
+}
+
+/// Transport block of RX_Data.indication
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RxPdu {
+    /// Handle of the PUSCH PDU
+    pub handle: u32,
+    pub rnti: u16,
+    pub harq_id: u8,
+    /// SNR: 0 to 255 for -64 dB to 63 dB in 0.5 dB steps
+    pub ul_cqi: u8,
+    /// Timing advance command T_A, 0 to 63
+    pub timing_advance: u16,
+    /// RSSI: 0 to 1280 for -128 dBFS to 0 dBFS in 0.1 dB steps
+    pub rssi: u16,
+    pub payload: Bytes,
+}
+
+/// RX_Data.indication: the transport blocks decoded on PUSCH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RxDataIndication {
+    pub sfn: u16,
+    pub slot: u16,
+    pub pdus: Vec<RxPdu>,
+}
+
+impl RxDataIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u16_le(self.pdus.len() as u16);
+        for pdu in &self.pdus {
+            buf.put_u32_le(pdu.handle);
+            buf.put_u16_le(pdu.rnti);
+            buf.put_u8(pdu.harq_id);
+            buf.put_u32_le(pdu.payload.len() as u32);
+            buf.put_u8(pdu.ul_cqi);
+            buf.put_u16_le(pdu.timing_advance);
+            buf.put_u16_le(pdu.rssi);
+            buf.put_slice(&pdu.payload);
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 6)?;
+        let (sfn, slot, num_pdus) = (header.get_u16_le(), header.get_u16_le(), header.get_u16_le());
+        let mut pdus = Vec::with_capacity(num_pdus as usize);
+        for _ in 0..num_pdus {
+            let mut pdu = take(buf, 16)?;
+            let (handle, rnti, harq_id) = (pdu.get_u32_le(), pdu.get_u16_le(), pdu.get_u8());
+            let len = pdu.get_u32_le() as usize;
+            pdus.push(RxPdu {
+                handle,
+                rnti,
+                harq_id,
+                ul_cqi: pdu.get_u8(),
+                timing_advance: pdu.get_u16_le(),
+                rssi: pdu.get_u16_le(),
+                payload: take_payload(buf, len)?,
+            });
+        }
+        Ok(Self { sfn, slot, pdus })
+    }
+}
+
+/// CRC check of a PUSCH transport block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc {
+    /// Handle of the PUSCH PDU
+    pub handle: u32,
+    pub rnti: u16,
+    pub harq_id: u8,
+    pub tb_crc_ok: bool,
+    /// SNR: 0 to 255 for -64 dB to 63 dB in 0.5 dB steps
+    pub ul_cqi: u8,
+    /// Timing advance command T_A, 0 to 63
+    pub timing_advance: u16,
+    /// RSSI: 0 to 1280 for -128 dBFS to 0 dBFS in 0.1 dB steps
+    pub rssi: u16,
+}
+
+/// CRC.indication: the CRC checks of the PUSCHs of a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcIndication {
+    pub sfn: u16,
+    pub slot: u16,
+    pub crcs: Vec<Crc>,
+}
+
+impl CrcIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u16_le(self.crcs.len() as u16);
+        for crc in &self.crcs {
+            buf.put_u32_le(crc.handle);
+            buf.put_u16_le(crc.rnti);
+            buf.put_u8(crc.harq_id);
+            // TbCrcStatus: 0 for pass, 1 for fail
+            buf.put_u8(!crc.tb_crc_ok as u8);
+            buf.put_u16_le(0); // NumCb: no code block group CRCs
+            buf.put_u8(crc.ul_cqi);
+            buf.put_u16_le(crc.timing_advance);
+            buf.put_u16_le(crc.rssi);
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 6)?;
+        let (sfn, slot, num_crcs) = (header.get_u16_le(), header.get_u16_le(), header.get_u16_le());
+        let mut crcs = Vec::with_capacity(num_crcs as usize);
+        for _ in 0..num_crcs {
+            let mut crc = take(buf, 15)?;
+            let (handle, rnti, harq_id) = (crc.get_u32_le(), crc.get_u16_le(), crc.get_u8());
+            let tb_crc_ok = crc.get_u8() == 0;
+            if crc.get_u16_le() != 0 {
+                return Err(LayerError::InvalidPdu);
+            }
+            crcs.push(Crc {
+                handle,
+                rnti,
+                harq_id,
+                tb_crc_ok,
+                ul_cqi: crc.get_u8(),
+                timing_advance: crc.get_u16_le(),
+                rssi: crc.get_u16_le(),
+            });
+        }
+        Ok(Self { sfn, slot, crcs })
+    }
+}
+
+/// HARQ feedback of a transport block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HarqValue {
+    Ack = 0,
+    Nack = 1,
+    Dtx = 2,
+}
+
+/// UCI received on PUCCH format 0 or 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciPucchF01 {
+    /// Handle of the PUCCH PDU
+    pub handle: u32,
+    pub rnti: u16,
+    /// 0 for PUCCH format 0, 1 for format 1
+    pub pucch_format: u8,
+    /// SNR: 0 to 255 for -64 dB to 63 dB in 0.5 dB steps
+    pub ul_cqi: u8,
+    /// Timing advance command T_A, 0 to 63
+    pub timing_advance: u16,
+    /// RSSI: 0 to 1280 for -128 dBFS to 0 dBFS in 0.1 dB steps
+    pub rssi: u16,
+    /// Scheduling request detected, if the occasion had one
+    pub sr: Option<bool>,
+    pub harq: Vec<HarqValue>,
+}
+
+/// UCI.indication: the UCI received in a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciIndication {
+    pub sfn: u16,
+    pub slot: u16,
+    pub ucis: Vec<UciPucchF01>,
+}
+
+impl UciIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u16_le(self.ucis.len() as u16);
+        for uci in &self.ucis {
+            put_pdu(buf, UCI_PDU_PUCCH_F01, |buf| {
+                let bitmap = if uci.sr.is_some() { UCI_SR_PRESENT } else { 0 }
+                    | if uci.harq.is_empty() { 0 } else { UCI_HARQ_PRESENT };
+                buf.put_u8(bitmap);
+                buf.put_u32_le(uci.handle);
+                buf.put_u16_le(uci.rnti);
+                buf.put_u8(uci.pucch_format);
+                buf.put_u8(uci.ul_cqi);
+                buf.put_u16_le(uci.timing_advance);
+                buf.put_u16_le(uci.rssi);
+                if let Some(sr) = uci.sr {
+                    buf.put_u8(sr as u8);
+                    buf.put_u8(0); // SrConfidenceLevel: good
+                }
+                if !uci.harq.is_empty() {
+                    buf.put_u8(uci.harq.len() as u8);
+                    buf.put_u8(0); // HarqConfidenceLevel: good
+                    for value in &uci.harq {
+                        buf.put_u8(*value as u8);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Decode the message body, skipping the UCI of PUSCH and of PUCCH
+    /// formats 2 to 4
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 6)?;
+        let (sfn, slot, num_ucis) = (header.get_u16_le(), header.get_u16_le(), header.get_u16_le());
+        let mut ucis = Vec::with_capacity(num_ucis as usize);
+        for _ in 0..num_ucis {
+            let (pdu_type, mut body) = take_pdu(buf)?;
+            if pdu_type != UCI_PDU_PUCCH_F01 {
+                debug!("Skipping UCI PDU of type {}", pdu_type);
+                continue;
+            }
+            let mut fields = take(&mut body, 13)?;
+            let bitmap = fields.get_u8();
+            let mut uci = UciPucchF01 {
+                handle: fields.get_u32_le(),
+                rnti: fields.get_u16_le(),
+                pucch_format: fields.get_u8(),
+                ul_cqi: fields.get_u8(),
+                timing_advance: fields.get_u16_le(),
+                rssi: fields.get_u16_le(),
+                sr: None,
+                harq: Vec::new(),
+            };
+            if bitmap & UCI_SR_PRESENT != 0 {
+                uci.sr = Some(take(&mut body, 2)?.get_u8() != 0);
+            }
+            if bitmap & UCI_HARQ_PRESENT != 0 {
+                let num_harq = take(&mut body, 2)?.get_u8() as usize;
+                uci.harq = take(&mut body, num_harq)?
+                    .iter()
+                    .map(|value| match value {
+                        0 => Ok(HarqValue::Ack),
+                        1 => Ok(HarqValue::Nack),
+                        2 => Ok(HarqValue::Dtx),
+                        _ => Err(LayerError::InvalidPdu),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            ucis.push(uci);
+        }
+        Ok(Self { sfn, slot, ucis })
+    }
+}
+
+/// Largest timing advance of RACH.indication
+pub const MAX_RACH_TIMING_ADVANCE: u16 = 3846;
+
+/// Step of the timing advance T_A for numerology `numerology`, 16·64·T_c/2^µ,
+/// in microseconds
+pub fn timing_advance_step_us(numerology: u8) -> f32 {
+    16.0 * 64.0 / (480e3 * 4096.0) * 1e6 / (1u32 << numerology) as f32
+}
+
+/// Preamble detected in a PRACH occasion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RachPreamble {
+    pub preamble_index: u8,
+    /// Timing advance T_A of the Random Access Response, 0 to 3846
+    pub timing_advance: u16,
+    /// Received power: 0 to 170000 for -140 dBm to 30 dBm in 0.001 dB steps
+    pub preamble_pwr: u32,
+}
+
+/// PRACH occasion with detected preambles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RachOccasion {
+    pub phy_cell_id: u16,
+    /// First symbol of the occasion
+    pub symbol_index: u8,
+    /// Slot of the occasion within the frame
+    pub slot_index: u8,
+    /// Index of the occasion in frequency
+    pub ra_index: u8,
+    /// Average RSSI: 0 to 1700 for -140 dBm to 30 dBm in 0.1 dB steps
+    pub avg_rssi: u16,
+    /// SNR: 0 to 255 for -64 dB to 63 dB in 0.5 dB steps
+    pub avg_snr: u8,
+    pub preambles: Vec<RachPreamble>,
+}
+
+/// RACH.indication: the preambles detected in the PRACH occasions of a slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RachIndication {
+    pub sfn: u16,
+    pub slot: u16,
+    pub occasions: Vec<RachOccasion>,
+}
+
+impl RachIndication {
+    /// Encode the message body
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16_le(self.sfn);
+        buf.put_u16_le(self.slot);
+        buf.put_u8(self.occasions.len() as u8);
+        for occasion in &self.occasions {
+            buf.put_u16_le(occasion.phy_cell_id);
+            buf.put_u8(occasion.symbol_index);
+            buf.put_u8(occasion.slot_index);
+            buf.put_u8(occasion.ra_index);
+            buf.put_u16_le(occasion.avg_rssi);
+            buf.put_u8(occasion.avg_snr);
+            buf.put_u8(occasion.preambles.len() as u8);
+            for preamble in &occasion.preambles {
+                buf.put_u8(preamble.preamble_index);
+                buf.put_u16_le(preamble.timing_advance);
+                buf.put_u32_le(preamble.preamble_pwr);
+            }
+        }
+    }
+
+    /// Decode the message body
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut header = take(buf, 5)?;
+        let (sfn, slot, num_occasions) = (header.get_u16_le(), header.get_u16_le(), header.get_u8());
+        let mut occasions = Vec::with_capacity(num_occasions as usize);
+        for _ in 0..num_occasions {
+            let mut fields = take(buf, 9)?;
+            let mut occasion = RachOccasion {
+                phy_cell_id: fields.get_u16_le(),
+                symbol_index: fields.get_u8(),
+                slot_index: fields.get_u8(),
+                ra_index: fields.get_u8(),
+                avg_rssi: fields.get_u16_le(),
+                avg_snr: fields.get_u8(),
+                preambles: Vec::new(),
+            };
+            for _ in 0..fields.get_u8() {
+                let mut preamble = take(buf, 7)?;
+                occasion.preambles.push(RachPreamble {
+                    preamble_index: preamble.get_u8(),
+                    timing_advance: preamble.get_u16_le(),
+                    preamble_pwr: preamble.get_u32_le(),
+                });
+            }
+            occasions.push(occasion);
+        }
+        Ok(Self { sfn, slot, occasions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(message: &T, encode: impl Fn(&T, &mut BytesMut), decode: impl Fn(&mut &[u8]) -> Result<T, LayerError>)
+        -> BytesMut
+    where
+        T: PartialEq + std::fmt::Debug,
+    {
+        let mut buf = BytesMut::new();
+        encode(message, &mut buf);
+        let mut data = &buf[..];
+        assert_eq!(&decode(&mut data).unwrap(), message);
+        assert!(data.is_empty());
+        assert!(decode(&mut &buf[..buf.len() - 1]).is_err());
+        buf
+    }
+
+    /// PDSCH of a SIB1 of 100 bytes
+    fn sib1_pdsch() -> PdschPdu {
+        PdschPdu {
+            rnti: 0xFFFF, pdu_index: 0, bwp_size: 48, bwp_start: 12, subcarrier_spacing: 0, cyclic_prefix: 0,
+            target_code_rate: 1930, qam_mod_order: 2, mcs_index: 2, mcs_table: 0, rv_index: 0, tb_size: 100,
+            data_scrambling_id: 1, nr_of_layers: 1, ref_point: 1, dl_dmrs_symb_pos: 0x0002, dmrs_config_type: 0,
+            rb_start: 0, rb_size: 12, vrb_to_prb_mapping: 0, start_symbol_index: 1, nr_of_symbols: 4,
+        }
+    }
+
+    /// PUSCH of a Msg3 of 56 bytes
+    fn msg3_pusch() -> PuschPdu {
+        PuschPdu {
+            rnti: 0x4601, handle: 7, bwp_size: 52, bwp_start: 0, subcarrier_spacing: 0, cyclic_prefix: 0,
+            target_code_rate: 1570, qam_mod_order: 2, mcs_index: 1, mcs_table: 0, transform_precoding: false,
+            data_scrambling_id: 1, nr_of_layers: 1, ul_dmrs_symb_pos: 0x0004, dmrs_config_type: 0,
+            rb_start: 10, rb_size: 4, vrb_to_prb_mapping: 0, start_symbol_index: 0, nr_of_symbols: 14,
+            rv_index: 0, harq_process_id: 3, new_data_indicator: true, tb_size: 56,
+        }
+    }
+
+    #[test]
+    fn test_p7_messages() {
+        let pdcch = PdcchPdu {
+            bwp_size: 48, bwp_start: 12, subcarrier_spacing: 0, cyclic_prefix: 0, start_symbol_index: 0,
+            duration_symbols: 1, freq_domain_resource: PdcchPdu::freq_domain_resource_of(48), cce_reg_mapping_type: 1,
+            reg_bundle_size: 6, interleaver_size: 2, coreset_type: 0, shift_index: 1, precoder_granularity: 0,
+            dl_dcis: vec![DlDci {
+                rnti: 0xFFFF, scrambling_id: 1, scrambling_rnti: 0, cce_index: 0, aggregation_level: 4,
+                beta_pdcch_1_0: 0, power_control_offset_ss: 0, payload_size_bits: 39,
+                payload: Bytes::from_static(&[0x0B, 0x40, 0x10, 0, 0]),
+            }],
+        };
+        assert_eq!(pdcch.freq_domain_resource, [0xFF, 0, 0, 0, 0, 0]);
+        assert_eq!(pdcch.coreset_rbs(), (12..60).collect::<Vec<u16>>());
+
+        let pdsch = sib1_pdsch();
+        let ssb = SsbPdu {
+            phy_cell_id: 1, beta_pss: 0, ssb_block_index: 2, ssb_subcarrier_offset: 0, ssb_offset_point_a: 0,
+            bch_payload_flag: 2, bch_payload: 0,
+        };
+        let dl_tti = DlTtiRequest {
+            sfn: 2,
+            slot: 1,
+            pdus: vec![DlTtiPdu::Ssb(ssb), DlTtiPdu::Pdcch(pdcch.clone()), DlTtiPdu::Pdsch(pdsch)],
+        };
+        let buf = round_trip(&dl_tti, DlTtiRequest::encode, DlTtiRequest::decode);
+        // SFN, slot, 3 PDUs, no groups, then the SSB PDU of 16 bytes
+        assert_eq!(&buf[..10], &[2, 0, 1, 0, 3, 0, 3, 0, 16, 0]);
+        round_trip(&UlDciRequest { sfn: 2, slot: 1, pdcchs: vec![pdcch] }, UlDciRequest::encode, UlDciRequest::decode);
+
+        let ul_tti = UlTtiRequest {
+            sfn: 17,
+            slot: 9,
+            pdus: vec![
+                UlTtiPdu::Prach(PrachPdu {
+                    phy_cell_id: 1, num_prach_ocas: 1, prach_format: 0, index_fd_ra: 0, prach_start_symbol: 0,
+                    num_cs: 119,
+                }),
+                UlTtiPdu::Pusch(msg3_pusch()),
+                UlTtiPdu::Pucch(PucchPdu {
+                    rnti: 0x4601, handle: 8, bwp_size: 52, bwp_start: 0, subcarrier_spacing: 0, cyclic_prefix: 0,
+                    format_type: 1, prb_start: 51, prb_size: 1, start_symbol_index: 0, nr_of_symbols: 14,
+                    initial_cyclic_shift: 0, time_domain_occ_idx: 0, sr_flag: true, bit_len_harq: 1,
+                    bit_len_csi_part1: 0,
+                }),
+            ],
+        };
+        let buf = round_trip(&ul_tti, UlTtiRequest::encode, UlTtiRequest::decode);
+        // 3 PDUs: 1 PRACH, 1 PUSCH, 1 PUCCH, no groups
+        assert_eq!(&buf[4..10], &[3, 0, 1, 1, 1, 0]);
+
+        let tx_data = TxDataRequest {
+            sfn: 2, slot: 1, pdus: vec![TxPdu { pdu_index: 0, payload: Bytes::from_static(b"sib1") }],
+        };
+        let buf = round_trip(&tx_data, TxDataRequest::encode, TxDataRequest::decode);
+        assert_eq!(&buf[6..22], &[4, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 4, 0, 0, 0]);
+
+        let rx_data = RxDataIndication {
+            sfn: 20, slot: 3, pdus: vec![RxPdu {
+                handle: 7, rnti: 0x4601, harq_id: 3, ul_cqi: 148, timing_advance: 31, rssi: 800,
+                payload: Bytes::from_static(&[0x34, 0x12, 0x56]),
+            }],
+        };
+        round_trip(&rx_data, RxDataIndication::encode, RxDataIndication::decode);
+
+        let uci = UciIndication {
+            sfn: 20, slot: 4, ucis: vec![UciPucchF01 {
+                handle: 8, rnti: 0x4601, pucch_format: 1, ul_cqi: 140, timing_advance: 31, rssi: 700,
+                sr: Some(true), harq: vec![HarqValue::Ack, HarqValue::Dtx],
+            }],
+        };
+        let buf = round_trip(&uci, UciIndication::encode, UciIndication::decode);
+        // UCI of other PUCCH formats is skipped
+        let mut other = buf.to_vec();
+        other[6] = 2;
+        assert!(UciIndication::decode(&mut &other[..]).unwrap().ucis.is_empty());
+
+        let rach = RachIndication {
+            sfn: 17, slot: 9, occasions: vec![RachOccasion {
+                phy_cell_id: 1, symbol_index: 0, slot_index: 9, ra_index: 0, avg_rssi: 500, avg_snr: 128,
+                preambles: vec![RachPreamble { preamble_index: 12, timing_advance: 10, preamble_pwr: 50_000 }],
+            }],
+        };
+        round_trip(&rach, RachIndication::encode, RachIndication::decode);
+        assert!((timing_advance_step_us(0) - 0.5208).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_p7_decode_errors() {
+        let encoded = |encode: &dyn Fn(&mut BytesMut)| {
+            let mut buf = BytesMut::new();
+            encode(&mut buf);
+            buf.to_vec()
+        };
+        assert!(matches!(SlotIndication::decode(&mut &[0, 0, 1][..]), Err(LayerError::InvalidPdu)));
+
+        // DL_TTI.request: unknown PDU type, PDU size below its header, two
+        // codewords, resource allocation type 0
+        let dl_tti = encoded(&|buf| DlTtiRequest { sfn: 0, slot: 0, pdus: vec![DlTtiPdu::Pdsch(sib1_pdsch())] }.encode(buf));
+        let invalid = |offset: usize, value: u8| {
+            let mut other = dl_tti.clone();
+            other[offset] = value;
+            matches!(DlTtiRequest::decode(&mut &other[..]), Err(LayerError::InvalidPdu))
+        };
+        assert!(invalid(6, 9));
+        assert!(invalid(8, 3));
+        assert_eq!((dl_tti[22], dl_tti[41]), (1, RESOURCE_ALLOCATION_TYPE_1));
+        assert!(invalid(22, 2));
+        assert!(invalid(41, 0));
+
+        // UL_TTI.request: unknown PDU type, PUSCH without data, resource
+        // allocation type 0
+        let ul_tti = encoded(&|buf| UlTtiRequest { sfn: 0, slot: 0, pdus: vec![UlTtiPdu::Pusch(msg3_pusch())] }.encode(buf));
+        let invalid = |offset: usize, value: u8| {
+            let mut other = ul_tti.clone();
+            other[offset] = value;
+            matches!(UlTtiRequest::decode(&mut &other[..]), Err(LayerError::InvalidPdu))
+        };
+        assert!(invalid(10, 5));
+        assert!(invalid(14, 0));
+        assert_eq!(ul_tti[40], RESOURCE_ALLOCATION_TYPE_1);
+        assert!(invalid(40, 0));
+
+        // UL_DCI.request only carries PDCCH PDUs
+        let mut ul_dci = BytesMut::new();
+        ul_dci.put_slice(&[0, 0, 0, 0, 1]);
+        put_pdu(&mut ul_dci, DL_PDU_SSB, |buf| buf.put_bytes(0, 12));
+        assert!(matches!(UlDciRequest::decode(&mut &ul_dci[..]), Err(LayerError::InvalidPdu)));
+
+        // TX_Data.request: more than one TLV, payload by reference, payload
+        // beyond the message
+        let tx_data = encoded(&|buf| TxDataRequest {
+            sfn: 0, slot: 0, pdus: vec![TxPdu { pdu_index: 0, payload: Bytes::from_static(b"sib1") }],
+        }.encode(buf));
+        let invalid = |offset: usize, value: u8| {
+            let mut other = tx_data.clone();
+            other[offset] = value;
+            matches!(TxDataRequest::decode(&mut &other[..]), Err(LayerError::InvalidPdu))
+        };
+        assert!(invalid(12, 2));
+        assert!(invalid(16, 1));
+        assert!(invalid(18, 5));
+
+        // CRC.indication with code block group CRCs
+        let crc = encoded(&|buf| CrcIndication {
+            sfn: 0, slot: 0, crcs: vec![Crc {
+                handle: 1, rnti: 0x4601, harq_id: 0, tb_crc_ok: true, ul_cqi: 0, timing_advance: 0, rssi: 0,
+            }],
+        }.encode(buf));
+        let mut other = crc.clone();
+        other[14] = 1;
+        assert!(matches!(CrcIndication::decode(&mut &other[..]), Err(LayerError::InvalidPdu)));
+
+        // UCI.indication: unknown HARQ value, SR announced but missing
+        let uci = encoded(&|buf| UciIndication {
+            sfn: 0, slot: 0, ucis: vec![UciPucchF01 {
+                handle: 1, rnti: 0x4601, pucch_format: 0, ul_cqi: 0, timing_advance: 0, rssi: 0,
+                sr: None, harq: vec![HarqValue::Nack],
+            }],
+        }.encode(buf));
+        let mut other = uci.clone();
+        *other.last_mut().unwrap() = 3;
+        assert!(matches!(UciIndication::decode(&mut &other[..]), Err(LayerError::InvalidPdu)));
+        let mut other = uci.clone();
+        other[10] = UCI_SR_PRESENT | UCI_HARQ_PRESENT;
+        assert!(matches!(UciIndication::decode(&mut &other[..]), Err(LayerError::InvalidPdu)));
+
+        // RACH.indication with more preambles than carried
+        let rach = encoded(&|buf| RachIndication {
+            sfn: 0, slot: 0, occasions: vec![RachOccasion {
+                phy_cell_id: 1, symbol_index: 0, slot_index: 9, ra_index: 0, avg_rssi: 0, avg_snr: 0,
+                preambles: vec![RachPreamble { preamble_index: 1, timing_advance: 0, preamble_pwr: 0 }],
+            }],
+        }.encode(buf));
+        let mut other = rach.clone();
+        other[13] = 2;
+        assert!(matches!(RachIndication::decode(&mut &other[..]), Err(LayerError::InvalidPdu)));
+    }
+}
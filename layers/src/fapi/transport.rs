@@ -0,0 +1,172 @@
+//! FAPI transport
+//!
+//! Two bindings carry the messages between the MAC and the L1. The in-process
+//! binding hands decoded messages over tokio channels, for a MAC and an L1 in
+//! the same process. The socket binding carries blocks of messages (SCF
+//! 222.10.02 section 3.2.1) over a TCP connection, for an L1 in another
+//! process or on another host; the L1 listens and the MAC connects. Messages
+//! are sent one per block and received from blocks of any size.
+
+use super::{FapiMessage, BLOCK_HEADER_LEN, MESSAGE_HEADER_LEN};
+use crate::LayerError;
+use bytes::{Buf, BytesMut};
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+
+enum Writer {
+    InProcess(mpsc::Sender<FapiMessage>),
+    Socket(Mutex<OwnedWriteHalf>),
+}
+
+/// Sending side of a FAPI binding
+pub struct FapiSender {
+    writer: Writer,
+}
+
+impl FapiSender {
+    /// Send a message
+    pub async fn send(&self, message: FapiMessage) -> Result<(), LayerError> {
+        let message_type = message.message_type();
+        match &self.writer {
+            Writer::InProcess(sender) => sender
+                .send(message)
+                .await
+                .map_err(|_| LayerError::ProcessingError("FAPI peer is gone".to_string()))?,
+            Writer::Socket(writer) => {
+                let mut buf = BytesMut::new();
+                buf.extend_from_slice(&[1, 0]);
+                message.encode(&mut buf);
+                writer.lock().await.write_all(&buf).await.map_err(|e| {
+                    LayerError::ProcessingError(format!("Failed to send FAPI {:?}: {}", message_type, e))
+                })?;
+            }
+        }
+        debug!("Sent FAPI {:?}", message_type);
+        Ok(())
+    }
+}
+
+/// Receiving side of a FAPI binding
+pub enum FapiReceiver {
+    InProcess(mpsc::Receiver<FapiMessage>),
+    /// Messages of a block are returned one by one
+    Socket { stream: OwnedReadHalf, pending: VecDeque<FapiMessage> },
+}
+
+impl FapiReceiver {
+    /// Receive the next message, `None` once the peer is gone
+    pub async fn recv(&mut self) -> Option<FapiMessage> {
+        match self {
+            FapiReceiver::InProcess(receiver) => receiver.recv().await,
+            FapiReceiver::Socket { stream, pending } => loop {
+                if let Some(message) = pending.pop_front() {
+                    return Some(message);
+                }
+                let mut header = [0u8; BLOCK_HEADER_LEN];
+                stream.read_exact(&mut header).await.ok()?;
+                for _ in 0..header[0] {
+                    let mut message_header = [0u8; MESSAGE_HEADER_LEN];
+                    stream.read_exact(&mut message_header).await.ok()?;
+                    let mut fields = &message_header[..];
+                    let (message_type, length) = (fields.get_u16_le(), fields.get_u32_le());
+                    let mut body = vec![0u8; length as usize];
+                    stream.read_exact(&mut body).await.ok()?;
+                    match FapiMessage::decode_body(message_type, &body) {
+                        Ok(message) => pending.push_back(message),
+                        Err(e) => warn!("Dropping malformed FAPI message of type 0x{:02x}: {}", message_type, e),
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Connected in-process binding: the MAC end and the L1 end
+pub fn in_process(capacity: usize) -> ((FapiSender, FapiReceiver), (FapiSender, FapiReceiver)) {
+    let (to_l1, from_mac) = mpsc::channel(capacity);
+    let (to_mac, from_l1) = mpsc::channel(capacity);
+    (
+        (FapiSender { writer: Writer::InProcess(to_l1) }, FapiReceiver::InProcess(from_l1)),
+        (FapiSender { writer: Writer::InProcess(to_mac) }, FapiReceiver::InProcess(from_mac)),
+    )
+}
+
+fn socket_halves(stream: TcpStream) -> io::Result<(FapiSender, FapiReceiver)> {
+    stream.set_nodelay(true)?;
+    let (reader, writer) = stream.into_split();
+    Ok((
+        FapiSender { writer: Writer::Socket(Mutex::new(writer)) },
+        FapiReceiver::Socket { stream: reader, pending: VecDeque::new() },
+    ))
+}
+
+/// Connect the MAC to an L1 listening on `address`
+pub async fn connect(address: SocketAddr) -> Result<(FapiSender, FapiReceiver), LayerError> {
+    let halves = async { socket_halves(TcpStream::connect(address).await?) }
+        .await
+        .map_err(|e| LayerError::InitializationFailed(format!("Failed to connect to the L1 at {}: {}", address, e)))?;
+    info!("FAPI connection established with {}", address);
+    Ok(halves)
+}
+
+/// Listening socket of an L1 for its MAC
+pub struct FapiListener {
+    listener: TcpListener,
+}
+
+impl FapiListener {
+    /// Listen for the MAC
+    pub async fn bind(address: SocketAddr) -> Result<Self, LayerError> {
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| LayerError::InitializationFailed(format!("Cannot listen for FAPI on {}: {}", address, e)))?;
+        Ok(Self { listener })
+    }
+
+    /// Local address of the listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Wait for the MAC to connect
+    pub async fn accept(&self) -> Result<(FapiSender, FapiReceiver), LayerError> {
+        let accepted = async {
+            let (stream, peer) = self.listener.accept().await?;
+            info!("FAPI connection accepted from {}", peer);
+            socket_halves(stream)
+        };
+        accepted.await.map_err(|e| LayerError::ProcessingError(format!("FAPI accept failed: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fapi::SlotIndication;
+
+    #[tokio::test]
+    async fn test_fapi_transport() {
+        let listener = FapiListener::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (mac, l1) = tokio::join!(connect(listener.local_addr().unwrap()), listener.accept());
+        let (socket_mac, socket_l1) = (mac.unwrap(), l1.unwrap());
+
+        for ((mac_tx, mut mac_rx), (l1_tx, mut l1_rx)) in [in_process(4), (socket_mac, socket_l1)] {
+            mac_tx.send(FapiMessage::StartRequest).await.unwrap();
+            assert_eq!(l1_rx.recv().await, Some(FapiMessage::StartRequest));
+
+            let slot = FapiMessage::SlotIndication(SlotIndication { sfn: 1023, slot: 19 });
+            l1_tx.send(slot.clone()).await.unwrap();
+            assert_eq!(mac_rx.recv().await, Some(slot));
+
+            drop(l1_tx);
+            drop(l1_rx);
+            assert_eq!(mac_rx.recv().await, None);
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! GPRS Tunnelling Protocol User Plane (GTP-U) Implementation
+//!
+//! N3 user-plane endpoint of the CU-UP according to 3GPP TS 29.281. NGAP creates
+//! one tunnel per PDU session when the session is set up (TS 38.413 section
+//! 8.2.1); downlink G-PDUs received on its TEID are passed to RRC for the DRB of
+//! their QoS flow, uplink packets of the DRBs are sent to the UPF endpoint.
+//!
+//! After an Xn handover the source gNB relays the downlink of the UE to the
+//! target gNB over Xn-U until the UPF marks the end of the old path with an End
+//! Marker (TS 38.300 section 9.2.3.2.3).
+
+pub mod pdu;
+pub mod tunnel;
+
+use crate::ngap::pdu::GtpTunnel;
+use crate::rrc::{GtpuRrcMessage, RrcGtpuMessage};
+use crate::{LayerError, ProtocolLayer};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::HashMap;
+use pdu::{GtpuMessageType, GtpuPdu, PduSessionInformation, GTPU_PORT};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, warn};
+use tunnel::{NguTunnel, TunnelTable};
+
+/// Largest GTP-U datagram received
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// GTP-U endpoint configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GtpuConfig {
+    /// Local address the N3 socket is bound to
+    pub bind_address: SocketAddr,
+    /// Address given to the UPF for downlink tunnels
+    pub external_address: IpAddr,
+}
+
+/// Messages sent from NGAP towards the GTP-U endpoint
+#[derive(Debug, Clone)]
+pub enum NgapGtpuMessage {
+    /// NG-U tunnel of a PDU session set up towards the UPF
+    CreateTunnel {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU session ID
+        pdu_session_id: u8,
+        /// gNB-side TEID
+        dl_teid: u32,
+        /// UPF endpoint of the uplink
+        ul_tunnel: GtpTunnel,
+    },
+    /// PDU sessions released
+    ReleaseTunnels {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// Released PDU sessions
+        pdu_session_ids: Vec<u8>,
+    },
+    /// UE context released with all its PDU sessions
+    ReleaseUe {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+    },
+    /// UE handed over: relay the downlink of a PDU session to the target gNB
+    ForwardTunnel {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU session ID
+        pdu_session_id: u8,
+        /// Xn-U downlink forwarding tunnel of the target gNB
+        forwarding_tunnel: GtpTunnel,
+    },
+}
+
+/// GTP-U endpoint
+pub struct GtpuLayer {
+    config: GtpuConfig,
+    /// N3 socket, shared with the receive task
+    socket: Option<Arc<UdpSocket>>,
+    /// Tunnels of the PDU sessions
+    tunnels: TunnelTable,
+    /// Xn-U forwarding tunnels of handed over UEs, indexed by the TEID whose
+    /// downlink they relay
+    forwarding: HashMap<u32, GtpTunnel>,
+    /// Channel towards RRC
+    rrc_tx: Option<mpsc::Sender<GtpuRrcMessage>>,
+    /// Sequence number of the next Echo Request
+    echo_sequence_number: u16,
+}
+
+impl GtpuLayer {
+    /// Create a new GTP-U endpoint
+    pub fn new(config: GtpuConfig) -> Self {
+        Self {
+            config,
+            socket: None,
+            tunnels: TunnelTable::default(),
+            forwarding: HashMap::new(),
+            rrc_tx: None,
+            echo_sequence_number: 0,
+        }
+    }
+
+    /// Set the channel used to pass downlink packets to RRC
+    pub fn set_rrc_channel(&mut self, tx: mpsc::Sender<GtpuRrcMessage>) {
+        self.rrc_tx = Some(tx);
+    }
+
+    /// Local address of the N3 socket
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.as_ref().and_then(|socket| socket.local_addr().ok())
+    }
+
+    /// Tunnels of the PDU sessions
+    pub fn tunnels(&self) -> &TunnelTable {
+        &self.tunnels
+    }
+
+    fn socket(&self) -> Result<&Arc<UdpSocket>, LayerError> {
+        self.socket.as_ref().ok_or(LayerError::NotInitialized)
+    }
+
+    async fn send(&self, pdu: &GtpuPdu, peer: SocketAddr) -> Result<(), LayerError> {
+        self.socket()?.send_to(&pdu.encode(), peer).await
+            .map_err(|e| LayerError::ProcessingError(format!("GTP-U send to {} failed: {}", peer, e)))?;
+        Ok(())
+    }
+
+    /// Handle a message from the NGAP layer
+    pub async fn handle_ngap_message(&mut self, message: NgapGtpuMessage) -> Result<(), LayerError> {
+        match message {
+            NgapGtpuMessage::CreateTunnel { ue_id, pdu_session_id, dl_teid, ul_tunnel } => {
+                info!("GTP-U tunnel for UE {} PDU session {}: TEID {:#x}, UPF {}/{:#x}",
+                      ue_id, pdu_session_id, dl_teid, ul_tunnel.transport_layer_address, ul_tunnel.teid);
+                if let Some(replaced) = self.tunnels.insert(NguTunnel { ue_id, pdu_session_id, dl_teid, ul_tunnel }) {
+                    debug!("Replaced tunnel with TEID {:#x}", replaced.dl_teid);
+                    self.forwarding.remove(&replaced.dl_teid);
+                }
+            }
+            NgapGtpuMessage::ReleaseTunnels { ue_id, pdu_session_ids } => {
+                for pdu_session_id in pdu_session_ids {
+                    if let Some(tunnel) = self.tunnels.remove(ue_id, pdu_session_id) {
+                        info!("GTP-U tunnel {:#x} of UE {} PDU session {} released", tunnel.dl_teid, ue_id, pdu_session_id);
+                        self.forwarding.remove(&tunnel.dl_teid);
+                    }
+                }
+            }
+            NgapGtpuMessage::ReleaseUe { ue_id } => {
+                let released = self.tunnels.remove_ue(ue_id);
+                if !released.is_empty() {
+                    info!("{} GTP-U tunnels of UE {} released", released.len(), ue_id);
+                }
+                for tunnel in released {
+                    self.forwarding.remove(&tunnel.dl_teid);
+                }
+            }
+            NgapGtpuMessage::ForwardTunnel { ue_id, pdu_session_id, forwarding_tunnel } => {
+                let tunnel = self.tunnels.session(ue_id, pdu_session_id)
+                    .ok_or_else(|| LayerError::InvalidState(
+                        format!("No GTP-U tunnel for UE {} PDU session {}", ue_id, pdu_session_id)))?;
+                info!("Forwarding downlink of UE {} PDU session {} to {}/{:#x}",
+                      ue_id, pdu_session_id, forwarding_tunnel.transport_layer_address, forwarding_tunnel.teid);
+                self.forwarding.insert(tunnel.dl_teid, forwarding_tunnel);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a message from the RRC layer: send an uplink packet to the UPF
+    pub async fn handle_rrc_message(&mut self, message: RrcGtpuMessage) -> Result<(), LayerError> {
+        match message {
+            RrcGtpuMessage::UplinkData { ue_id, pdu_session_id, qfi, data } => {
+                let tunnel = self.tunnels.session(ue_id, pdu_session_id)
+                    .ok_or_else(|| LayerError::InvalidState(
+                        format!("No GTP-U tunnel for UE {} PDU session {}", ue_id, pdu_session_id)))?;
+                let peer = SocketAddr::new(tunnel.ul_tunnel.transport_layer_address, GTPU_PORT);
+                let pdu = GtpuPdu::g_pdu(tunnel.ul_tunnel.teid, Some(PduSessionInformation::Uplink { qfi }), data);
+                self.send(&pdu, peer).await
+            }
+        }
+    }
+
+    /// Send an Echo Request to a GTP-U peer (TS 29.281 section 7.2.1)
+    pub async fn send_echo_request(&mut self, peer: IpAddr) -> Result<(), LayerError> {
+        let sequence_number = self.echo_sequence_number;
+        self.echo_sequence_number = self.echo_sequence_number.wrapping_add(1);
+        self.send(&GtpuPdu::echo_request(sequence_number), SocketAddr::new(peer, GTPU_PORT)).await
+    }
+
+    /// Send an End Marker on the uplink tunnel of a PDU session, after its last
+    /// packet on the current path (TS 29.281 section 7.3.2)
+    pub async fn send_end_marker(&mut self, ue_id: u32, pdu_session_id: u8) -> Result<(), LayerError> {
+        let tunnel = self.tunnels.session(ue_id, pdu_session_id)
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No GTP-U tunnel for UE {} PDU session {}", ue_id, pdu_session_id)))?;
+        let peer = SocketAddr::new(tunnel.ul_tunnel.transport_layer_address, GTPU_PORT);
+        self.send(&GtpuPdu::end_marker(tunnel.ul_tunnel.teid, None), peer).await
+    }
+
+    /// Handle a datagram received on the N3 socket
+    pub async fn handle_datagram(&mut self, data: Bytes, peer: SocketAddr) -> Result<(), LayerError> {
+        let pdu = GtpuPdu::decode(data)?;
+        match pdu.message_type {
+            GtpuMessageType::GPdu => self.handle_g_pdu(pdu, peer).await,
+            GtpuMessageType::EchoRequest => {
+                debug!("GTP-U Echo Request from {}", peer);
+                self.send(&GtpuPdu::echo_response(&pdu), peer).await
+            }
+            GtpuMessageType::EchoResponse => {
+                debug!("GTP-U Echo Response from {} (sequence number {:?})", peer, pdu.sequence_number);
+                Ok(())
+            }
+            GtpuMessageType::ErrorIndication => self.handle_error_indication(&pdu, peer),
+            GtpuMessageType::EndMarker => {
+                if let Some(forwarding_tunnel) = self.forwarding.remove(&pdu.teid) {
+                    // Last packet on the old path: pass the End Marker on and
+                    // drop the tunnel of the handed over UE
+                    let peer = SocketAddr::new(forwarding_tunnel.transport_layer_address, GTPU_PORT);
+                    self.send(&GtpuPdu::end_marker(forwarding_tunnel.teid, None), peer).await?;
+                    if let Some(tunnel) = self.tunnels.get(pdu.teid).cloned() {
+                        info!("Xn-U forwarding of UE {} PDU session {} ended", tunnel.ue_id, tunnel.pdu_session_id);
+                        self.tunnels.remove(tunnel.ue_id, tunnel.pdu_session_id);
+                    }
+                    return Ok(());
+                }
+                match self.tunnels.get(pdu.teid) {
+                    Some(tunnel) => info!("End Marker from {} for UE {} PDU session {}",
+                                          peer, tunnel.ue_id, tunnel.pdu_session_id),
+                    None => debug!("End Marker from {} for unknown TEID {:#x}", peer, pdu.teid),
+                }
+                Ok(())
+            }
+            GtpuMessageType::SupportedExtensionHeadersNotification => {
+                debug!("Supported Extension Headers Notification from {}", peer);
+                Ok(())
+            }
+        }
+    }
+
+    /// Pass a downlink G-PDU to RRC, or to the target gNB of a handed over UE,
+    /// or answer with Error Indication if the TEID is unknown (TS 29.281 section 7.3.1)
+    async fn handle_g_pdu(&mut self, pdu: GtpuPdu, peer: SocketAddr) -> Result<(), LayerError> {
+        let Some(tunnel) = self.tunnels.get(pdu.teid) else {
+            warn!("G-PDU from {} for unknown TEID {:#x}, sending Error Indication", peer, pdu.teid);
+            let indication = GtpuPdu::error_indication(pdu.teid, self.config.external_address, peer.port());
+            return self.send(&indication, SocketAddr::new(peer.ip(), GTPU_PORT)).await;
+        };
+        if let Some(forwarding_tunnel) = self.forwarding.get(&pdu.teid) {
+            let target = SocketAddr::new(forwarding_tunnel.transport_layer_address, GTPU_PORT);
+            let forwarded = GtpuPdu::g_pdu(forwarding_tunnel.teid, pdu.pdu_session_information, pdu.payload);
+            return self.send(&forwarded, target).await;
+        }
+        let (qfi, rqi) = match pdu.pdu_session_information {
+            Some(PduSessionInformation::Downlink { qfi, rqi }) => (Some(qfi), rqi),
+            _ => (None, false),
+        };
+        let message = GtpuRrcMessage::DownlinkData {
+            ue_id: tunnel.ue_id,
+            pdu_session_id: tunnel.pdu_session_id,
+            qfi,
+            rqi,
+            data: pdu.payload,
+        };
+        match &self.rrc_tx {
+            Some(rrc_tx) => rrc_tx.send(message).await
+                .map_err(|_| LayerError::ProcessingError("RRC channel closed".into())),
+            None => Err(LayerError::InvalidState("No RRC channel configured".into())),
+        }
+    }
+
+    /// The UPF does not know the uplink TEID any more: drop the tunnels using it
+    fn handle_error_indication(&mut self, pdu: &GtpuPdu, peer: SocketAddr) -> Result<(), LayerError> {
+        let (teid, address) = pdu.error_indication_ies()?;
+        let stale = self.tunnels.by_uplink(&GtpTunnel { transport_layer_address: address, teid });
+        if stale.is_empty() {
+            debug!("Error Indication from {} for unused TEID {:#x}", peer, teid);
+        }
+        for tunnel in stale {
+            warn!("Error Indication from {}: UPF lost TEID {:#x} of UE {} PDU session {}",
+                  peer, teid, tunnel.ue_id, tunnel.pdu_session_id);
+            self.tunnels.remove(tunnel.ue_id, tunnel.pdu_session_id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProtocolLayer for GtpuLayer {
+    async fn initialize(&mut self) -> Result<(), LayerError> {
+        let socket = UdpSocket::bind(self.config.bind_address).await
+            .map_err(|e| LayerError::InitializationFailed(
+                format!("Cannot bind GTP-U socket to {}: {}", self.config.bind_address, e)))?;
+        info!("GTP-U endpoint listening on {} (external address {})",
+              socket.local_addr().unwrap_or(self.config.bind_address), self.config.external_address);
+        self.socket = Some(Arc::new(socket));
+        Ok(())
+    }
+
+    async fn process_uplink(&mut self, _data: Bytes) -> Result<Bytes, LayerError> {
+        Err(LayerError::ProcessingError("Uplink packets are sent per PDU session through handle_rrc_message".into()))
+    }
+
+    async fn process_downlink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
+        // T-PDU of a G-PDU received on a known tunnel
+        let pdu = GtpuPdu::decode(data)?;
+        if pdu.message_type != GtpuMessageType::GPdu || self.tunnels.get(pdu.teid).is_none() {
+            return Err(LayerError::InvalidPdu);
+        }
+        Ok(pdu.payload)
+    }
+
+    async fn shutdown(&mut self) -> Result<(), LayerError> {
+        info!("Shutting down GTP-U endpoint");
+        self.socket = None;
+        self.tunnels.clear();
+        self.forwarding.clear();
+        Ok(())
+    }
+}
+
+/// Receive datagrams on the N3 socket and hand them to the GTP-U endpoint until
+/// it is shut down
+pub async fn run_gtpu_endpoint(gtpu: Arc<RwLock<GtpuLayer>>) {
+    let Some(socket) = gtpu.read().await.socket.clone() else {
+        warn!("GTP-U endpoint not initialized");
+        return;
+    };
+    let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+    loop {
+        // Wait without holding the layer so tunnels can be set up meanwhile
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                error!("GTP-U receive error: {}", e);
+                continue;
+            }
+        };
+        let mut gtpu_guard = gtpu.write().await;
+        if gtpu_guard.socket.is_none() {
+            return;
+        }
+        if let Err(e) = gtpu_guard.handle_datagram(Bytes::copy_from_slice(&buf[..len]), peer).await {
+            debug!("Dropping GTP-U datagram from {}: {}", peer, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn recv(socket: &UdpSocket) -> (GtpuPdu, SocketAddr) {
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        let (len, peer) = timeout(Duration::from_secs(2), socket.recv_from(&mut buf)).await.unwrap().unwrap();
+        (GtpuPdu::decode(Bytes::copy_from_slice(&buf[..len])).unwrap(), peer)
+    }
+
+    #[tokio::test]
+    async fn test_n3_tunnel() {
+        // UPF on its own loopback address with the standard port
+        let upf_address = IpAddr::from([127, 0, 0, 41]);
+        let upf = UdpSocket::bind(SocketAddr::new(upf_address, GTPU_PORT)).await.unwrap();
+
+        let mut gtpu = GtpuLayer::new(GtpuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            external_address: IpAddr::from([127, 0, 0, 1]),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(8);
+        gtpu.set_rrc_channel(rrc_tx);
+        gtpu.initialize().await.unwrap();
+        let gnb_address = gtpu.local_addr().unwrap();
+        gtpu.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: GtpTunnel { transport_layer_address: upf_address, teid: 0x101 },
+        }).await.unwrap();
+        let gtpu = Arc::new(RwLock::new(gtpu));
+        let endpoint = tokio::spawn(run_gtpu_endpoint(Arc::clone(&gtpu)));
+
+        // Downlink packet reaches RRC with its QoS flow
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14, 0x01, 0x02]);
+        let downlink = GtpuPdu::g_pdu(7, Some(PduSessionInformation::Downlink { qfi: 1, rqi: false }), packet.clone());
+        upf.send_to(&downlink.encode(), gnb_address).await.unwrap();
+        let GtpuRrcMessage::DownlinkData { ue_id, pdu_session_id, qfi, data, .. } =
+            timeout(Duration::from_secs(2), rrc_rx.recv()).await.unwrap().unwrap();
+        assert_eq!((ue_id, pdu_session_id, qfi, data), (1000, 1, Some(1), packet.clone()));
+
+        // Uplink packet goes to the UPF TEID with the UL PDU Session Container
+        gtpu.write().await.handle_rrc_message(RrcGtpuMessage::UplinkData {
+            ue_id: 1000, pdu_session_id: 1, qfi: 1, data: packet.clone(),
+        }).await.unwrap();
+        let (uplink, _) = recv(&upf).await;
+        assert_eq!(uplink.teid, 0x101);
+        assert_eq!(uplink.pdu_session_information, Some(PduSessionInformation::Uplink { qfi: 1 }));
+        assert_eq!(uplink.payload, packet);
+
+        // Echo
+        upf.send_to(&GtpuPdu::echo_request(3).encode(), gnb_address).await.unwrap();
+        let (response, _) = recv(&upf).await;
+        assert_eq!(response.message_type, GtpuMessageType::EchoResponse);
+        assert_eq!(response.sequence_number, Some(3));
+
+        // Unknown TEID: Error Indication
+        upf.send_to(&GtpuPdu::g_pdu(8, None, packet.clone()).encode(), gnb_address).await.unwrap();
+        let (indication, _) = recv(&upf).await;
+        assert_eq!(indication.message_type, GtpuMessageType::ErrorIndication);
+        assert_eq!(indication.error_indication_ies().unwrap(), (8, IpAddr::from([127, 0, 0, 1])));
+
+        // Error Indication from the UPF drops the tunnel
+        let lost = GtpuPdu::error_indication(0x101, upf_address, gnb_address.port());
+        upf.send_to(&lost.encode(), gnb_address).await.unwrap();
+        timeout(Duration::from_secs(2), async {
+            while !gtpu.read().await.tunnels().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+
+        gtpu.write().await.shutdown().await.unwrap();
+        endpoint.abort();
+    }
+
+    #[tokio::test]
+    async fn test_xn_u_forwarding() {
+        let upf = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target_address = IpAddr::from([127, 0, 0, 45]);
+        let target = UdpSocket::bind(SocketAddr::new(target_address, GTPU_PORT)).await.unwrap();
+
+        let mut gtpu = GtpuLayer::new(GtpuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            external_address: IpAddr::from([127, 0, 0, 1]),
+        });
+        gtpu.initialize().await.unwrap();
+        let gnb_address = gtpu.local_addr().unwrap();
+        gtpu.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 0x101 },
+        }).await.unwrap();
+        gtpu.handle_ngap_message(NgapGtpuMessage::ForwardTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            forwarding_tunnel: GtpTunnel { transport_layer_address: target_address, teid: 0x55 },
+        }).await.unwrap();
+        let gtpu = Arc::new(RwLock::new(gtpu));
+        let endpoint = tokio::spawn(run_gtpu_endpoint(Arc::clone(&gtpu)));
+
+        // Downlink of the handed over UE is relayed with its QoS flow
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+        let information = Some(PduSessionInformation::Downlink { qfi: 1, rqi: false });
+        upf.send_to(&GtpuPdu::g_pdu(7, information, packet.clone()).encode(), gnb_address).await.unwrap();
+        let (forwarded, _) = recv(&target).await;
+        assert_eq!((forwarded.teid, forwarded.pdu_session_information, forwarded.payload), (0x55, information, packet));
+
+        // End Marker from the UPF ends forwarding and the tunnel
+        upf.send_to(&GtpuPdu::end_marker(7, None).encode(), gnb_address).await.unwrap();
+        let (end_marker, _) = recv(&target).await;
+        assert_eq!((end_marker.message_type, end_marker.teid), (GtpuMessageType::EndMarker, 0x55));
+        assert!(gtpu.read().await.tunnels().is_empty());
+
+        gtpu.write().await.shutdown().await.unwrap();
+        endpoint.abort();
+    }
+
+    #[tokio::test]
+    async fn test_gtpu_failures() {
+        let mut gtpu = GtpuLayer::new(GtpuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            external_address: IpAddr::from([127, 0, 0, 1]),
+        });
+        let upf: SocketAddr = "127.0.0.1:2152".parse().unwrap();
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+
+        // Nothing is sent before initialization
+        assert!(matches!(gtpu.send_echo_request(upf.ip()).await, Err(LayerError::NotInitialized)));
+        assert!(matches!(gtpu.handle_datagram(GtpuPdu::echo_request(1).encode(), upf).await,
+                         Err(LayerError::NotInitialized)));
+        gtpu.initialize().await.unwrap();
+
+        // Unknown PDU sessions
+        let uplink = RrcGtpuMessage::UplinkData { ue_id: 1000, pdu_session_id: 1, qfi: 1, data: packet.clone() };
+        assert!(matches!(gtpu.handle_rrc_message(uplink).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(gtpu.send_end_marker(1000, 1).await, Err(LayerError::InvalidState(_))));
+        let forward = NgapGtpuMessage::ForwardTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            forwarding_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 45]), teid: 0x55 },
+        };
+        assert!(matches!(gtpu.handle_ngap_message(forward).await, Err(LayerError::InvalidState(_))));
+        gtpu.handle_ngap_message(NgapGtpuMessage::ReleaseTunnels { ue_id: 1000, pdu_session_ids: vec![1] }).await.unwrap();
+        gtpu.handle_ngap_message(NgapGtpuMessage::ReleaseUe { ue_id: 1000 }).await.unwrap();
+
+        // Downlink on a known tunnel without RRC channel
+        gtpu.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id: 1000,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: GtpTunnel { transport_layer_address: upf.ip(), teid: 0x101 },
+        }).await.unwrap();
+        let downlink = GtpuPdu::g_pdu(7, None, packet.clone()).encode();
+        assert!(matches!(gtpu.handle_datagram(downlink.clone(), upf).await, Err(LayerError::InvalidState(_))));
+        assert_eq!(gtpu.process_downlink(downlink).await.unwrap(), packet);
+        assert!(matches!(gtpu.process_downlink(GtpuPdu::g_pdu(8, None, packet.clone()).encode()).await,
+                         Err(LayerError::InvalidPdu)));
+        assert!(matches!(gtpu.process_downlink(GtpuPdu::end_marker(7, None).encode()).await,
+                         Err(LayerError::InvalidPdu)));
+        assert!(matches!(gtpu.process_uplink(packet).await, Err(LayerError::ProcessingError(_))));
+
+        // Malformed datagrams, Error Indications without IEs or for other TEIDs
+        assert!(matches!(gtpu.handle_datagram(Bytes::from_static(&[0x30, 0xFF]), upf).await,
+                         Err(LayerError::InvalidPdu)));
+        let mut indication = GtpuPdu::error_indication(0x101, upf.ip(), 2152);
+        indication.payload = Bytes::new();
+        assert!(matches!(gtpu.handle_datagram(indication.encode(), upf).await, Err(LayerError::InvalidPdu)));
+        let other = GtpuPdu::error_indication(0x102, upf.ip(), 2152);
+        gtpu.handle_datagram(other.encode(), upf).await.unwrap();
+        assert_eq!(gtpu.tunnels().len(), 1);
+
+        // An End Marker of a tunnel without forwarding keeps the tunnel
+        gtpu.handle_datagram(GtpuPdu::end_marker(7, None).encode(), upf).await.unwrap();
+        gtpu.handle_datagram(GtpuPdu::end_marker(8, None).encode(), upf).await.unwrap();
+        assert_eq!(gtpu.tunnels().len(), 1);
+
+        gtpu.shutdown().await.unwrap();
+        assert!(gtpu.tunnels().is_empty());
+        assert!(matches!(gtpu.send_echo_request(upf.ip()).await, Err(LayerError::NotInitialized)));
+    }
+}
@@ -0,0 +1,482 @@
+//! GTP-U message encoding and decoding
+//!
+//! GTPv1-U header with the optional sequence number and extension headers
+//! (3GPP TS 29.281 section 5), the messages used on N3 (section 7), the PDU
+//! Session Container extension header carrying the QFI (TS 38.415 section 5.5)
+//! and the NR RAN Container carrying the NR-U frames of F1-U (TS 38.425).
+
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::net::IpAddr;
+
+/// UDP port of GTP-U (TS 29.281 section 4.4.2)
+pub const GTPU_PORT: u16 = 2152;
+
+/// Version 1, protocol type GTP
+const FLAGS_VERSION_PT: u8 = 0x30;
+const FLAG_E: u8 = 0x04;
+const FLAG_S: u8 = 0x02;
+const FLAG_PN: u8 = 0x01;
+/// Mandatory part of the header
+const HEADER_LEN: usize = 8;
+
+/// Extension header types (TS 29.281 section 5.2.1)
+const EXT_NONE: u8 = 0x00;
+const EXT_UDP_PORT: u8 = 0x40;
+const EXT_NR_RAN_CONTAINER: u8 = 0x84;
+const EXT_PDU_SESSION_CONTAINER: u8 = 0x85;
+
+/// Information element types (TS 29.281 section 8)
+const IE_RECOVERY: u8 = 14;
+const IE_TEID_DATA_I: u8 = 16;
+const IE_GTPU_PEER_ADDRESS: u8 = 133;
+
+/// PDU types of the PDU Session Container (TS 38.415 section 5.5.3.1)
+const PDU_TYPE_DL: u8 = 0;
+const PDU_TYPE_UL: u8 = 1;
+
+/// GTP-U message types (TS 29.281 section 6.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GtpuMessageType {
+    EchoRequest = 1,
+    EchoResponse = 2,
+    ErrorIndication = 26,
+    SupportedExtensionHeadersNotification = 31,
+    EndMarker = 254,
+    GPdu = 255,
+}
+
+impl GtpuMessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::EchoRequest),
+            2 => Some(Self::EchoResponse),
+            26 => Some(Self::ErrorIndication),
+            31 => Some(Self::SupportedExtensionHeadersNotification),
+            254 => Some(Self::EndMarker),
+            255 => Some(Self::GPdu),
+            _ => None,
+        }
+    }
+}
+
+/// PDU Session Container extension header (TS 38.415 section 5.5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PduSessionInformation {
+    /// DL PDU SESSION INFORMATION, from the UPF
+    Downlink {
+        /// QoS flow identifier
+        qfi: u8,
+        /// Reflective QoS indication
+        rqi: bool,
+    },
+    /// UL PDU SESSION INFORMATION, towards the UPF
+    Uplink {
+        /// QoS flow identifier
+        qfi: u8,
+    },
+}
+
+impl PduSessionInformation {
+    /// QoS flow identifier
+    pub fn qfi(&self) -> u8 {
+        match self {
+            Self::Downlink { qfi, .. } | Self::Uplink { qfi } => *qfi,
+        }
+    }
+
+    fn encode(&self) -> [u8; 2] {
+        match self {
+            Self::Downlink { qfi, rqi } => [PDU_TYPE_DL << 4, ((*rqi as u8) << 6) | (qfi & 0x3F)],
+            Self::Uplink { qfi } => [PDU_TYPE_UL << 4, qfi & 0x3F],
+        }
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, LayerError> {
+        if content.len() < 2 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let qfi = content[1] & 0x3F;
+        match content[0] >> 4 {
+            PDU_TYPE_DL => Ok(Self::Downlink { qfi, rqi: content[1] & 0x40 != 0 }),
+            PDU_TYPE_UL => Ok(Self::Uplink { qfi }),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// GTP-U message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GtpuPdu {
+    /// Message type
+    pub message_type: GtpuMessageType,
+    /// Tunnel endpoint identifier of the receiving side
+    pub teid: u32,
+    /// Sequence number
+    pub sequence_number: Option<u16>,
+    /// PDU Session Container extension header
+    pub pdu_session_information: Option<PduSessionInformation>,
+    /// UDP Port extension header: source port of the message the Error Indication refers to
+    pub udp_port: Option<u16>,
+    /// NR RAN Container extension header: NR-U frame of an F1-U tunnel
+    pub nr_ran_container: Option<Bytes>,
+    /// T-PDU of a G-PDU, information elements of the other messages
+    pub payload: Bytes,
+}
+
+impl GtpuPdu {
+    /// G-PDU carrying a user packet
+    pub fn g_pdu(teid: u32, pdu_session_information: Option<PduSessionInformation>, packet: Bytes) -> Self {
+        Self {
+            message_type: GtpuMessageType::GPdu,
+            teid,
+            sequence_number: None,
+            pdu_session_information,
+            udp_port: None,
+            nr_ran_container: None,
+            payload: packet,
+        }
+    }
+
+    /// G-PDU of an F1-U tunnel with an NR-U frame, the packet may be empty
+    pub fn nr_u(teid: u32, nr_ran_container: Bytes, packet: Bytes) -> Self {
+        Self {
+            nr_ran_container: Some(nr_ran_container),
+            ..Self::g_pdu(teid, None, packet)
+        }
+    }
+
+    /// Echo Request; the sequence number is mandatory (TS 29.281 section 7.2.1)
+    pub fn echo_request(sequence_number: u16) -> Self {
+        Self {
+            message_type: GtpuMessageType::EchoRequest,
+            teid: 0,
+            sequence_number: Some(sequence_number),
+            pdu_session_information: None,
+            udp_port: None,
+            nr_ran_container: None,
+            payload: Bytes::new(),
+        }
+    }
+
+    /// Echo Response to an Echo Request, with the Recovery IE set to zero (section 7.2.2)
+    pub fn echo_response(request: &GtpuPdu) -> Self {
+        Self {
+            message_type: GtpuMessageType::EchoResponse,
+            teid: 0,
+            sequence_number: Some(request.sequence_number.unwrap_or(0)),
+            pdu_session_information: None,
+            udp_port: None,
+            nr_ran_container: None,
+            payload: Bytes::from_static(&[IE_RECOVERY, 0]),
+        }
+    }
+
+    /// Error Indication for a G-PDU received on an unknown TEID (section 7.3.1)
+    ///
+    /// `local_address` is the address the G-PDU was sent to, `source_port` the
+    /// UDP source port it came from.
+    pub fn error_indication(teid: u32, local_address: IpAddr, source_port: u16) -> Self {
+        let mut ies = BytesMut::new();
+        ies.put_u8(IE_TEID_DATA_I);
+        ies.put_u32(teid);
+        ies.put_u8(IE_GTPU_PEER_ADDRESS);
+        match local_address {
+            IpAddr::V4(address) => {
+                ies.put_u16(4);
+                ies.put_slice(&address.octets());
+            }
+            IpAddr::V6(address) => {
+                ies.put_u16(16);
+                ies.put_slice(&address.octets());
+            }
+        }
+        Self {
+            message_type: GtpuMessageType::ErrorIndication,
+            teid: 0,
+            sequence_number: None,
+            pdu_session_information: None,
+            udp_port: Some(source_port),
+            nr_ran_container: None,
+            payload: ies.freeze(),
+        }
+    }
+
+    /// End Marker closing the downlink of a tunnel (section 7.3.2)
+    pub fn end_marker(teid: u32, pdu_session_information: Option<PduSessionInformation>) -> Self {
+        Self {
+            message_type: GtpuMessageType::EndMarker,
+            teid,
+            sequence_number: None,
+            pdu_session_information,
+            udp_port: None,
+            nr_ran_container: None,
+            payload: Bytes::new(),
+        }
+    }
+
+    /// TEID Data I and GTP-U Peer Address of an Error Indication
+    pub fn error_indication_ies(&self) -> Result<(u32, IpAddr), LayerError> {
+        let mut buf = self.payload.clone();
+        let mut teid = None;
+        let mut peer = None;
+        while buf.has_remaining() {
+            let ie_type = buf.get_u8();
+            match ie_type {
+                IE_TEID_DATA_I => {
+                    ensure_remaining(&buf, 4)?;
+                    teid = Some(buf.get_u32());
+                }
+                IE_RECOVERY => {
+                    ensure_remaining(&buf, 1)?;
+                    buf.advance(1);
+                }
+                // TLV information elements
+                t if t >= 128 => {
+                    ensure_remaining(&buf, 2)?;
+                    let len = buf.get_u16() as usize;
+                    ensure_remaining(&buf, len)?;
+                    let value = buf.split_to(len);
+                    if t == IE_GTPU_PEER_ADDRESS {
+                        peer = match len {
+                            4 => Some(IpAddr::from(<[u8; 4]>::try_from(&value[..]).unwrap())),
+                            16 => Some(IpAddr::from(<[u8; 16]>::try_from(&value[..]).unwrap())),
+                            _ => return Err(LayerError::InvalidPdu),
+                        };
+                    }
+                }
+                _ => return Err(LayerError::InvalidPdu),
+            }
+        }
+        teid.zip(peer).ok_or(LayerError::InvalidPdu)
+    }
+
+    /// Encode the message
+    pub fn encode(&self) -> Bytes {
+        let mut extensions: Vec<(u8, &[u8])> = Vec::new();
+        let udp_port = self.udp_port.map(u16::to_be_bytes);
+        if let Some(port) = &udp_port {
+            extensions.push((EXT_UDP_PORT, port));
+        }
+        let pdu_session_information = self.pdu_session_information.map(|information| information.encode());
+        if let Some(information) = &pdu_session_information {
+            extensions.push((EXT_PDU_SESSION_CONTAINER, information));
+        }
+        if let Some(container) = &self.nr_ran_container {
+            extensions.push((EXT_NR_RAN_CONTAINER, container));
+        }
+        let optional = self.sequence_number.is_some() || !extensions.is_empty();
+        // Extension headers are multiples of 4 octets, length and next type included
+        let extension_len = |content: &[u8]| (content.len() + 2).div_ceil(4) * 4;
+        let extensions_len: usize = extensions.iter().map(|(_, content)| extension_len(content)).sum();
+
+        let mut buf = BytesMut::with_capacity(HEADER_LEN + 4 + extensions_len + self.payload.len());
+        let mut flags = FLAGS_VERSION_PT;
+        if self.sequence_number.is_some() {
+            flags |= FLAG_S;
+        }
+        if !extensions.is_empty() {
+            flags |= FLAG_E;
+        }
+        buf.put_u8(flags);
+        buf.put_u8(self.message_type as u8);
+        // Length of everything after the mandatory header
+        let length = optional as usize * 4 + extensions_len + self.payload.len();
+        buf.put_u16(length as u16);
+        buf.put_u32(self.teid);
+        if optional {
+            buf.put_u16(self.sequence_number.unwrap_or(0));
+            // N-PDU number
+            buf.put_u8(0);
+            buf.put_u8(extensions.first().map_or(EXT_NONE, |(ext_type, _)| *ext_type));
+            for (i, (_, content)) in extensions.iter().enumerate() {
+                let len = extension_len(content);
+                buf.put_u8((len / 4) as u8);
+                buf.put_slice(content);
+                buf.put_bytes(0, len - 2 - content.len());
+                buf.put_u8(extensions.get(i + 1).map_or(EXT_NONE, |(ext_type, _)| *ext_type));
+            }
+        }
+        buf.put_slice(&self.payload);
+        buf.freeze()
+    }
+
+    /// Decode a message
+    pub fn decode(data: Bytes) -> Result<Self, LayerError> {
+        if data.len() < HEADER_LEN {
+            return Err(LayerError::InvalidPdu);
+        }
+        let mut buf = data;
+        let flags = buf.get_u8();
+        if flags & 0xF0 != FLAGS_VERSION_PT {
+            return Err(LayerError::InvalidPdu);
+        }
+        let message_type = GtpuMessageType::from_u8(buf.get_u8()).ok_or(LayerError::InvalidPdu)?;
+        let length = buf.get_u16() as usize;
+        let teid = buf.get_u32();
+        if buf.len() < length {
+            return Err(LayerError::InvalidPdu);
+        }
+        buf.truncate(length);
+
+        let mut pdu = Self {
+            message_type,
+            teid,
+            sequence_number: None,
+            pdu_session_information: None,
+            udp_port: None,
+            nr_ran_container: None,
+            payload: Bytes::new(),
+        };
+        if flags & (FLAG_E | FLAG_S | FLAG_PN) != 0 {
+            ensure_remaining(&buf, 4)?;
+            let sequence_number = buf.get_u16();
+            if flags & FLAG_S != 0 {
+                pdu.sequence_number = Some(sequence_number);
+            }
+            buf.advance(1);
+            let mut next = buf.get_u8();
+            if flags & FLAG_E == 0 {
+                next = EXT_NONE;
+            }
+            while next != EXT_NONE {
+                ensure_remaining(&buf, 1)?;
+                let len = buf.get_u8() as usize * 4;
+                if len == 0 {
+                    return Err(LayerError::InvalidPdu);
+                }
+                ensure_remaining(&buf, len - 1)?;
+                let content = buf.split_to(len - 2);
+                match next {
+                    EXT_PDU_SESSION_CONTAINER => {
+                        pdu.pdu_session_information = Some(PduSessionInformation::decode(&content)?);
+                    }
+                    EXT_NR_RAN_CONTAINER => pdu.nr_ran_container = Some(content),
+                    EXT_UDP_PORT if content.len() >= 2 => {
+                        pdu.udp_port = Some(u16::from_be_bytes([content[0], content[1]]));
+                    }
+                    // Extension headers the receiver has to comprehend (section 5.2.1)
+                    t if t & 0x80 != 0 => return Err(LayerError::InvalidPdu),
+                    _ => {}
+                }
+                next = buf.get_u8();
+            }
+        }
+        pdu.payload = buf;
+        Ok(pdu)
+    }
+}
+
+fn ensure_remaining(buf: &Bytes, len: usize) -> Result<(), LayerError> {
+    if buf.remaining() < len {
+        return Err(LayerError::InvalidPdu);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_gtpu_messages() {
+        // G-PDU with a DL PDU Session Container as sent by a UPF
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+        let downlink = GtpuPdu::g_pdu(0x1234, Some(PduSessionInformation::Downlink { qfi: 9, rqi: true }), packet.clone());
+        let encoded = downlink.encode();
+        assert_eq!(&encoded[..16], &[
+            0x34, 0xFF, 0x00, 0x0C, 0x00, 0x00, 0x12, 0x34,
+            0x00, 0x00, 0x00, 0x85, 0x01, 0x00, 0x49, 0x00,
+        ]);
+        assert_eq!(GtpuPdu::decode(encoded).unwrap(), downlink);
+
+        // Plain G-PDU without optional fields
+        let uplink = GtpuPdu::g_pdu(7, None, packet.clone());
+        let encoded = uplink.encode();
+        assert_eq!(encoded.len(), 8 + packet.len());
+        assert_eq!(GtpuPdu::decode(encoded).unwrap().payload, packet);
+
+        let request = GtpuPdu::echo_request(0x55);
+        let response = GtpuPdu::decode(GtpuPdu::echo_response(&request).encode()).unwrap();
+        assert_eq!(response.message_type, GtpuMessageType::EchoResponse);
+        assert_eq!(response.sequence_number, Some(0x55));
+
+        let local = IpAddr::V4(Ipv4Addr::new(10, 53, 1, 2));
+        let indication = GtpuPdu::decode(GtpuPdu::error_indication(0xDEAD, local, 40000).encode()).unwrap();
+        assert_eq!(indication.udp_port, Some(40000));
+        assert_eq!(indication.error_indication_ies().unwrap(), (0xDEAD, local));
+
+        // NR-U frame padded to the extension header length, with and without packet
+        let nr_u = GtpuPdu::nr_u(0x99, Bytes::from_static(&[0x01, 0x04, 0x00, 0x00, 0x07]), packet.clone());
+        let encoded = nr_u.encode();
+        assert_eq!(&encoded[8..20], &[0x00, 0x00, 0x00, 0x84, 0x02, 0x01, 0x04, 0x00, 0x00, 0x07, 0x00, 0x00]);
+        let decoded = GtpuPdu::decode(encoded).unwrap();
+        assert_eq!(&decoded.nr_ran_container.unwrap()[..5], &[0x01, 0x04, 0x00, 0x00, 0x07]);
+        assert_eq!(decoded.payload, packet);
+        let status = GtpuPdu::nr_u(0x98, Bytes::from_static(&[0x10, 0x00]), Bytes::new());
+        assert_eq!(GtpuPdu::decode(status.encode()).unwrap(), status);
+
+        let marker = GtpuPdu::decode(GtpuPdu::end_marker(0x42, None).encode()).unwrap();
+        assert_eq!(marker.message_type, GtpuMessageType::EndMarker);
+        assert!(marker.payload.is_empty());
+
+        // Unknown extension header that must be comprehended
+        let mut unknown = BytesMut::from(&GtpuPdu::g_pdu(1, Some(PduSessionInformation::Uplink { qfi: 1 }), packet).encode()[..]);
+        unknown[11] = 0xC0;
+        assert!(GtpuPdu::decode(unknown.freeze()).is_err());
+    }
+
+    #[test]
+    fn test_gtpu_decode_errors() {
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+        let encoded = GtpuPdu::g_pdu(1, Some(PduSessionInformation::Uplink { qfi: 1 }), packet.clone()).encode();
+
+        // Truncated header, wrong version, unknown message type, length beyond the datagram
+        assert!(matches!(GtpuPdu::decode(encoded.slice(..7)), Err(LayerError::InvalidPdu)));
+        let mut version = BytesMut::from(&encoded[..]);
+        version[0] = 0x50 | (version[0] & 0x0F);
+        assert!(matches!(GtpuPdu::decode(version.freeze()), Err(LayerError::InvalidPdu)));
+        let mut message_type = BytesMut::from(&encoded[..]);
+        message_type[1] = 3;
+        assert!(matches!(GtpuPdu::decode(message_type.freeze()), Err(LayerError::InvalidPdu)));
+        assert!(matches!(GtpuPdu::decode(encoded.slice(..encoded.len() - 1)), Err(LayerError::InvalidPdu)));
+
+        // Optional fields or extension header cut short, zero extension length
+        assert!(matches!(GtpuPdu::decode(Bytes::from_static(&[0x32, 0xFF, 0x00, 0x02, 0, 0, 0, 1, 0, 0])),
+                         Err(LayerError::InvalidPdu)));
+        assert!(matches!(GtpuPdu::decode(Bytes::from_static(&[0x34, 0xFF, 0x00, 0x06, 0, 0, 0, 1, 0, 0, 0, 0x85, 0x01, 0x10])),
+                         Err(LayerError::InvalidPdu)));
+        let mut zero_length = BytesMut::from(&encoded[..]);
+        zero_length[12] = 0;
+        assert!(matches!(GtpuPdu::decode(zero_length.freeze()), Err(LayerError::InvalidPdu)));
+
+        // Unknown PDU type in the PDU Session Container
+        let mut pdu_type = BytesMut::from(&encoded[..]);
+        pdu_type[13] = 0x20;
+        assert!(matches!(GtpuPdu::decode(pdu_type.freeze()), Err(LayerError::InvalidPdu)));
+
+        // An unknown extension header the receiver may skip is ignored
+        let mut skipped = BytesMut::from(&encoded[..]);
+        skipped[11] = 0x03;
+        let decoded = GtpuPdu::decode(skipped.freeze()).unwrap();
+        assert_eq!((decoded.pdu_session_information, decoded.payload), (None, packet));
+
+        // Error Indication IEs missing, truncated or of an unknown TV type
+        let local = IpAddr::V4(Ipv4Addr::new(10, 53, 1, 2));
+        let mut indication = GtpuPdu::error_indication(0xDEAD, local, 40000);
+        let ies = indication.payload.clone();
+        indication.payload = ies.slice(..5);
+        assert!(matches!(indication.error_indication_ies(), Err(LayerError::InvalidPdu)));
+        indication.payload = ies.slice(..ies.len() - 1);
+        assert!(matches!(indication.error_indication_ies(), Err(LayerError::InvalidPdu)));
+        indication.payload = Bytes::from_static(&[IE_TEID_DATA_I, 0, 0, 0, 1, IE_GTPU_PEER_ADDRESS, 0, 3, 10, 0, 0]);
+        assert!(matches!(indication.error_indication_ies(), Err(LayerError::InvalidPdu)));
+        indication.payload = Bytes::from_static(&[IE_TEID_DATA_I, 0, 0, 0, 1, 17, 0]);
+        assert!(matches!(indication.error_indication_ies(), Err(LayerError::InvalidPdu)));
+        // Recovery and unknown TLV IEs are skipped
+        indication.payload = Bytes::from_static(&[IE_RECOVERY, 0, IE_TEID_DATA_I, 0, 0, 0, 1, 200, 0, 1, 0xFF,
+                                                  IE_GTPU_PEER_ADDRESS, 0, 4, 10, 53, 1, 2]);
+        assert_eq!(indication.error_indication_ies().unwrap(), (1, local));
+    }
+}
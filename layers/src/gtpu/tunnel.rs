@@ -0,0 +1,150 @@
+//! GTP-U tunnel table
+//!
+//! Maps the gNB-side TEIDs allocated by NGAP to the PDU sessions they serve, and
+//! each PDU session to the UPF endpoint of its uplink tunnel.
+
+use crate::ngap::pdu::GtpTunnel;
+use std::collections::HashMap;
+
+/// NG-U tunnel of a PDU session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NguTunnel {
+    /// UE identifier (RAN UE NGAP ID)
+    pub ue_id: u32,
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// gNB-side TEID, receiving the downlink
+    pub dl_teid: u32,
+    /// UPF endpoint of the uplink
+    pub ul_tunnel: GtpTunnel,
+}
+
+/// Tunnels indexed by downlink TEID and by PDU session
+#[derive(Debug, Default)]
+pub struct TunnelTable {
+    tunnels: HashMap<u32, NguTunnel>,
+    sessions: HashMap<(u32, u8), u32>,
+}
+
+impl TunnelTable {
+    /// Add a tunnel, replacing the previous tunnel of the PDU session
+    pub fn insert(&mut self, tunnel: NguTunnel) -> Option<NguTunnel> {
+        let replaced = self.remove(tunnel.ue_id, tunnel.pdu_session_id);
+        self.sessions.insert((tunnel.ue_id, tunnel.pdu_session_id), tunnel.dl_teid);
+        if let Some(previous) = self.tunnels.insert(tunnel.dl_teid, tunnel) {
+            // TEID reused by NGAP: the older session loses its tunnel
+            self.sessions.remove(&(previous.ue_id, previous.pdu_session_id));
+        }
+        replaced
+    }
+
+    /// Tunnel receiving on a TEID
+    pub fn get(&self, dl_teid: u32) -> Option<&NguTunnel> {
+        self.tunnels.get(&dl_teid)
+    }
+
+    /// Tunnel of a PDU session
+    pub fn session(&self, ue_id: u32, pdu_session_id: u8) -> Option<&NguTunnel> {
+        self.sessions.get(&(ue_id, pdu_session_id)).and_then(|teid| self.tunnels.get(teid))
+    }
+
+    /// Tunnels sending uplink towards a UPF endpoint
+    pub fn by_uplink(&self, ul_tunnel: &GtpTunnel) -> Vec<NguTunnel> {
+        self.tunnels.values().filter(|tunnel| tunnel.ul_tunnel == *ul_tunnel).cloned().collect()
+    }
+
+    /// Remove the tunnel of a PDU session
+    pub fn remove(&mut self, ue_id: u32, pdu_session_id: u8) -> Option<NguTunnel> {
+        let teid = self.sessions.remove(&(ue_id, pdu_session_id))?;
+        self.tunnels.remove(&teid)
+    }
+
+    /// Remove all tunnels of a UE
+    pub fn remove_ue(&mut self, ue_id: u32) -> Vec<NguTunnel> {
+        let pdu_session_ids: Vec<u8> = self.sessions.keys()
+            .filter(|(id, _)| *id == ue_id)
+            .map(|(_, pdu_session_id)| *pdu_session_id)
+            .collect();
+        pdu_session_ids.into_iter().filter_map(|id| self.remove(ue_id, id)).collect()
+    }
+
+    /// Number of tunnels
+    pub fn len(&self) -> usize {
+        self.tunnels.len()
+    }
+
+    /// Check if there is no tunnel
+    pub fn is_empty(&self) -> bool {
+        self.tunnels.is_empty()
+    }
+
+    /// Drop all tunnels
+    pub fn clear(&mut self) {
+        self.tunnels.clear();
+        self.sessions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    fn tunnel(ue_id: u32, pdu_session_id: u8, dl_teid: u32) -> NguTunnel {
+        NguTunnel {
+            ue_id,
+            pdu_session_id,
+            dl_teid,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([10, 45, 0, 1]), teid: 0x100 + dl_teid },
+        }
+    }
+
+    #[test]
+    fn test_tunnel_table() {
+        let mut table = TunnelTable::default();
+        assert!(table.insert(tunnel(1000, 1, 1)).is_none());
+        assert!(table.insert(tunnel(1000, 2, 2)).is_none());
+        assert!(table.insert(tunnel(1001, 1, 3)).is_none());
+        assert_eq!(table.get(2).unwrap().pdu_session_id, 2);
+        assert_eq!(table.session(1001, 1).unwrap().dl_teid, 3);
+
+        // New tunnel for an existing session replaces the old TEID
+        assert_eq!(table.insert(tunnel(1000, 1, 4)).unwrap().dl_teid, 1);
+        assert!(table.get(1).is_none());
+        assert_eq!(table.by_uplink(&tunnel(0, 0, 4).ul_tunnel).len(), 1);
+
+        let mut released: Vec<u32> = table.remove_ue(1000).iter().map(|tunnel| tunnel.dl_teid).collect();
+        released.sort_unstable();
+        assert_eq!(released, vec![2, 4]);
+        assert_eq!(table.len(), 1);
+        assert!(table.remove(1001, 1).is_some());
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_tunnel_table_conflicts() {
+        let mut table = TunnelTable::default();
+        assert!(table.remove(1000, 1).is_none());
+        assert!(table.remove_ue(1000).is_empty());
+        assert!(table.session(1000, 1).is_none());
+
+        // A TEID reused for another session takes the tunnel from the older session
+        assert!(table.insert(tunnel(1000, 1, 1)).is_none());
+        assert!(table.insert(tunnel(1001, 1, 1)).is_none());
+        assert!(table.session(1000, 1).is_none());
+        assert_eq!(table.get(1).unwrap().ue_id, 1001);
+        assert_eq!(table.len(), 1);
+        assert!(table.remove_ue(1000).is_empty());
+
+        // Uplink lookup matches address and TEID
+        let mut other_upf = tunnel(1002, 1, 2);
+        other_upf.ul_tunnel.transport_layer_address = IpAddr::from([10, 45, 0, 2]);
+        table.insert(other_upf.clone());
+        assert_eq!(table.by_uplink(&other_upf.ul_tunnel), vec![other_upf]);
+        assert!(table.by_uplink(&tunnel(0, 0, 2).ul_tunnel).is_empty());
+
+        table.clear();
+        assert!(table.is_empty());
+        assert!(table.session(1001, 1).is_none());
+    }
+}
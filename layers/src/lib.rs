@@ -6,8 +6,17 @@ pub mod phy;
 pub mod mac;
 pub mod rlc;
 pub mod pdcp;
+pub mod sdap;
 pub mod rrc;
 pub mod ngap;
+pub mod gtpu;
+pub mod f1ap;
+pub mod e1ap;
+pub mod xnap;
+pub mod e2ap;
+pub mod fapi;
+#[cfg(test)]
+mod test_support;
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -0,0 +1,194 @@
+//! Modulation and Coding Scheme Tables
+//!
+//! MCS tables and transport block size determination for PDSCH and PUSCH
+//! according to 3GPP TS 38.214 sections 5.1.3 and 6.1.4
+
+use common::types::ModulationScheme;
+
+/// Entry of an MCS or CQI table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McsEntry {
+    /// Modulation order Qm
+    pub modulation_order: u8,
+    /// Target code rate R x 1024 x 10, as carried by FAPI
+    pub code_rate_x10240: u16,
+}
+
+impl McsEntry {
+    /// Modulation of the entry
+    pub fn modulation(&self) -> ModulationScheme {
+        match self.modulation_order {
+            2 => ModulationScheme::Qpsk,
+            4 => ModulationScheme::Qam16,
+            6 => ModulationScheme::Qam64,
+            _ => ModulationScheme::Qam256,
+        }
+    }
+
+    /// Spectral efficiency Qm x R in units of 1/10240 bit per RE
+    pub fn spectral_efficiency(&self) -> u32 {
+        self.modulation_order as u32 * self.code_rate_x10240 as u32
+    }
+}
+
+const fn entry(modulation_order: u8, code_rate_x1024: u16) -> McsEntry {
+    McsEntry { modulation_order, code_rate_x10240: code_rate_x1024 * 10 }
+}
+
+const fn entry_x10240(modulation_order: u8, code_rate_x10240: u16) -> McsEntry {
+    McsEntry { modulation_order, code_rate_x10240 }
+}
+
+/// MCS index table 1 for PDSCH and PUSCH, up to 64QAM (TS 38.214 Table 5.1.3.1-1)
+pub const MCS_TABLE_1: [McsEntry; 29] = [
+    entry(2, 120), entry(2, 157), entry(2, 193), entry(2, 251), entry(2, 308), entry(2, 379), entry(2, 449),
+    entry(2, 526), entry(2, 602), entry(2, 679), entry(4, 340), entry(4, 378), entry(4, 434), entry(4, 490),
+    entry(4, 553), entry(4, 616), entry(4, 658), entry(6, 438), entry(6, 466), entry(6, 517), entry(6, 567),
+    entry(6, 616), entry(6, 666), entry(6, 719), entry(6, 772), entry(6, 822), entry(6, 873), entry(6, 910),
+    entry(6, 948),
+];
+
+/// MCS index table 2 for PDSCH and PUSCH, up to 256QAM (TS 38.214 Table 5.1.3.1-2)
+pub const MCS_TABLE_2: [McsEntry; 28] = [
+    entry(2, 120), entry(2, 193), entry(2, 308), entry(2, 449), entry(2, 602), entry(4, 378), entry(4, 434),
+    entry(4, 490), entry(4, 553), entry(4, 616), entry(4, 658), entry(6, 466), entry(6, 517), entry(6, 567),
+    entry(6, 616), entry(6, 666), entry(6, 719), entry(6, 772), entry(6, 822), entry(6, 873), entry_x10240(8, 6825),
+    entry(8, 711), entry(8, 754), entry(8, 797), entry(8, 841), entry(8, 885), entry_x10240(8, 9165), entry(8, 948),
+];
+
+/// CQI table 1, up to 64QAM, from CQI 1 (TS 38.214 Table 5.2.2.1-2)
+const CQI_TABLE_1: [McsEntry; 15] = [
+    entry(2, 78), entry(2, 120), entry(2, 193), entry(2, 308), entry(2, 449), entry(2, 602), entry(4, 378),
+    entry(4, 490), entry(4, 616), entry(6, 466), entry(6, 567), entry(6, 666), entry(6, 772), entry(6, 873),
+    entry(6, 948),
+];
+
+/// CQI table 2, up to 256QAM, from CQI 1 (TS 38.214 Table 5.2.2.1-3)
+const CQI_TABLE_2: [McsEntry; 15] = [
+    entry(2, 78), entry(2, 193), entry(2, 449), entry(4, 378), entry(4, 490), entry(4, 616), entry(6, 466),
+    entry(6, 567), entry(6, 666), entry(6, 772), entry(6, 873), entry(8, 711), entry(8, 797), entry(8, 885),
+    entry(8, 948),
+];
+
+/// MCS table of a UE limited to `max_modulation`: table 2 once 256QAM is
+/// allowed, table 1 otherwise
+pub fn mcs_table(max_modulation: ModulationScheme) -> &'static [McsEntry] {
+    mcs_table_of_index(mcs_table_index(max_modulation))
+}
+
+/// Index of the MCS table of a UE limited to `max_modulation`, as carried by
+/// FAPI: 0 for table 1, 1 for table 2
+pub fn mcs_table_index(max_modulation: ModulationScheme) -> u8 {
+    u8::from(max_modulation == ModulationScheme::Qam256)
+}
+
+/// MCS table of an index as carried by FAPI, table 1 for the indices of the
+/// tables not supported
+pub fn mcs_table_of_index(index: u8) -> &'static [McsEntry] {
+    match index {
+        1 => &MCS_TABLE_2,
+        _ => &MCS_TABLE_1,
+    }
+}
+
+/// Spectral efficiency of a reported CQI, read from the CQI table matching
+/// the MCS table of `max_modulation`; CQI 0 is out of range
+pub fn cqi_spectral_efficiency(max_modulation: ModulationScheme, cqi: u8) -> Option<u32> {
+    let table: &[McsEntry] = match max_modulation {
+        ModulationScheme::Qam256 => &CQI_TABLE_2,
+        _ => &CQI_TABLE_1,
+    };
+    let index = (cqi as usize).checked_sub(1)?;
+    table.get(index).map(McsEntry::spectral_efficiency)
+}
+
+/// Highest MCS index of `table` not exceeding a spectral efficiency, the
+/// lowest one if none does
+pub fn select_mcs(table: &[McsEntry], spectral_efficiency: u32) -> u8 {
+    table.iter().rposition(|mcs| mcs.spectral_efficiency() <= spectral_efficiency).unwrap_or(0) as u8
+}
+
+/// TBS for N_info up to 3824 (TS 38.214 Table 5.1.3.2-1)
+const TBS_TABLE: [u32; 93] = [
+    24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120, 128, 136, 144, 152, 160, 168, 176, 184, 192, 208, 224,
+    240, 256, 272, 288, 304, 320, 336, 352, 368, 384, 408, 432, 456, 480, 504, 528, 552, 576, 608, 640, 672, 704,
+    736, 768, 808, 848, 888, 928, 984, 1032, 1064, 1128, 1160, 1192, 1224, 1256, 1288, 1320, 1352, 1416, 1480,
+    1544, 1608, 1672, 1736, 1800, 1864, 1928, 2024, 2088, 2152, 2216, 2280, 2408, 2472, 2536, 2600, 2664, 2728,
+    2792, 2856, 2976, 3104, 3240, 3368, 3496, 3624, 3752, 3824,
+];
+
+/// Transport block size in bits (TS 38.214 section 5.1.3.2)
+///
+/// `dmrs_res_per_prb` counts the DM-RS resource elements of a PRB over the
+/// allocated symbols, including those of CDM groups without data.
+pub fn transport_block_size(mcs: McsEntry, num_prbs: u32, num_symbols: u8, dmrs_res_per_prb: u32, num_layers: u8) -> u32 {
+    let re_per_prb = (12 * num_symbols as u32).saturating_sub(dmrs_res_per_prb).min(156);
+    let num_res = re_per_prb * num_prbs;
+    let n_info = num_res as f64 * mcs.code_rate_x10240 as f64 / 10240.0 * mcs.modulation_order as f64 * num_layers as f64;
+    if n_info <= 0.0 {
+        return 0;
+    }
+
+    if n_info <= 3824.0 {
+        let n = (n_info.log2().floor() as i32 - 6).max(3);
+        let step = 2f64.powi(n);
+        let n_info_q = (step * (n_info / step).floor()).max(24.0) as u32;
+        return TBS_TABLE.iter().copied().find(|&tbs| tbs >= n_info_q).unwrap_or(3824);
+    }
+
+    let n = (n_info - 24.0).log2().floor() as i32 - 5;
+    let step = 2f64.powi(n);
+    let n_info_q = (step * ((n_info - 24.0) / step).round()).max(3840.0) as u32;
+    let code_blocks = if mcs.code_rate_x10240 <= 2560 {
+        (n_info_q + 24).div_ceil(3816)
+    } else if n_info_q > 8424 {
+        (n_info_q + 24).div_ceil(8424)
+    } else {
+        1
+    };
+    8 * code_blocks * (n_info_q + 24).div_ceil(8 * code_blocks) - 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_block_size() {
+        // 12 symbols with one DM-RS symbol: 132 REs per PRB
+        assert_eq!(transport_block_size(MCS_TABLE_1[0], 1, 12, 12, 1), 24);
+        assert_eq!(transport_block_size(MCS_TABLE_1[9], 10, 12, 12, 1), 1800);
+        // Above 3824 bits, several code blocks
+        assert_eq!(transport_block_size(MCS_TABLE_1[28], 52, 12, 12, 1), 37896);
+        assert_eq!(transport_block_size(MCS_TABLE_1[0], 0, 12, 12, 1), 0);
+        // Larger allocations never carry less
+        let sizes: Vec<u32> = (1..=52).map(|prbs| transport_block_size(MCS_TABLE_1[15], prbs, 12, 12, 1)).collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(MCS_TABLE_1[16].modulation(), ModulationScheme::Qam16);
+        // 256QAM at code rate 682.5/1024
+        assert_eq!(transport_block_size(MCS_TABLE_2[20], 10, 12, 12, 1), 7040);
+    }
+
+    #[test]
+    fn test_mcs_selection() {
+        assert_eq!(mcs_table(ModulationScheme::Qam64).len(), 29);
+        assert_eq!(mcs_table(ModulationScheme::Qam256)[27].modulation(), ModulationScheme::Qam256);
+        assert_eq!(mcs_table_index(ModulationScheme::Qam256), 1);
+        assert_eq!(mcs_table_of_index(mcs_table_index(ModulationScheme::Qam64)).len(), 29);
+        assert_eq!(MCS_TABLE_2[26].code_rate_x10240, 9165);
+
+        // CQI 6 of table 1 is QPSK at 602/1024, MCS 8 of table 1
+        let efficiency = cqi_spectral_efficiency(ModulationScheme::Qam64, 6).unwrap();
+        assert_eq!(select_mcs(&MCS_TABLE_1, efficiency), 8);
+        // CQI 15 of table 2 is 256QAM at 948/1024, the top of table 2
+        let efficiency = cqi_spectral_efficiency(ModulationScheme::Qam256, 15).unwrap();
+        assert_eq!(select_mcs(&MCS_TABLE_2, efficiency), 27);
+        // CQI 12 of table 2 is 256QAM at 711/1024, MCS 21 of table 2
+        let efficiency = cqi_spectral_efficiency(ModulationScheme::Qam256, 12).unwrap();
+        assert_eq!(select_mcs(&MCS_TABLE_2, efficiency), 21);
+
+        assert_eq!(cqi_spectral_efficiency(ModulationScheme::Qam64, 0), None);
+        assert_eq!(cqi_spectral_efficiency(ModulationScheme::Qam256, 16), None);
+        assert_eq!(select_mcs(&MCS_TABLE_1, 0), 0);
+    }
+}
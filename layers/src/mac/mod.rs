@@ -2,22 +2,38 @@
 //! 
 //! Implements the 5G NR MAC layer according to 3GPP TS 38.321
 
+pub mod mcs;
+pub mod paging;
+pub mod pdu;
 pub mod scheduler;
 pub mod sib1;
 
 use crate::{LayerError, ProtocolLayer};
-use crate::rrc::{RrcMacInterface, RrcMessageType, RarGrant};
+use crate::rrc::{DrbBearerConfig, PagingRequest, RrcMacInterface, RrcMessageType, RarGrant};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut, BufMut};
 use tracing::{debug, info, warn, error};
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU16, Ordering};
 use tokio::sync::{Mutex, RwLock, mpsc};
 
-pub use scheduler::{MacScheduler, SlotSchedule, SsbScheduleInfo, Sib1ScheduleInfo};
-pub use sib1::{Sib1Generator, Sib1Config, default_sib1_config};
+pub use paging::{PagingOccasion, PcchConfig, P_RNTI};
+pub use pdu::{MacSdu, MacSubheader};
+pub use scheduler::{
+    DrbLogicalChannel, MacScheduler, PagingScheduleInfo, SchedulerMetrics, SchedulingPolicy, SlicePrbQuota,
+    SlotSchedule, SsbScheduleInfo, Sib1ScheduleInfo, UeDlGrant, UeResourceUsage, UeSchedulingCapabilities, UeUlGrant,
+};
+pub use sib1::{Sib1Generator, Sib1Config, default_sib1_config, SI_RNTI};
 use common::types::{CellId, SubcarrierSpacing, Bandwidth, Rnti};
 
+/// First C-RNTI value handed out by the gNB
+const C_RNTI_FIRST: u16 = 0x4601;
+/// Last C-RNTI value (0xFFF0-0xFFFF are reserved, TS 38.321 Table 7.1-1)
+const C_RNTI_LAST: u16 = 0xFFEF;
+/// LCID of the first DRB, LCIDs 1 to 3 carry SRB1 to SRB3 (TS 38.331 section 6.3.2)
+const LCID_FIRST_DRB: u8 = 4;
+
 /// MAC PDU types
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MacPduType {
@@ -72,6 +88,9 @@ pub trait MacPhyInterface: Send + Sync {
     
     /// Report PRACH detection from PHY
     async fn report_prach_detection(&self, detection: crate::phy::prach::PrachDetectionResult) -> Result<(), LayerError>;
+    
+    /// Report a transport block decoded on PUSCH for an RNTI
+    async fn report_rx_data(&self, rnti: u16, data: Bytes) -> Result<(), LayerError>;
 }
 
 /// Enhanced MAC layer implementation
@@ -83,21 +102,26 @@ pub struct EnhancedMacLayer {
     initialized: bool,
     /// Next C-RNTI to allocate
     next_c_rnti: Arc<AtomicU16>,
+    /// C-RNTIs currently in use
+    active_rntis: Arc<Mutex<HashSet<u16>>>,
     /// Ongoing Random Access procedures
     ra_procedures: Arc<Mutex<Vec<RandomAccessProcedure>>>,
     /// RRC message sender
     rrc_tx: Option<mpsc::Sender<(Rnti, Bytes)>>,
+    /// Uplink user data sender, RLC PDUs of the DRBs by C-RNTI and LCID
+    user_data_tx: Option<mpsc::Sender<(Rnti, u8, Bytes)>>,
 }
 
 impl EnhancedMacLayer {
     /// Create a new enhanced MAC layer instance
     pub fn new(config: MacConfig) -> Result<Self, LayerError> {
-        let scheduler = MacScheduler::new(
+        let mut scheduler = MacScheduler::new(
             config.cell_id,
             config.scs,
             config.bandwidth,
             config.coreset0_index,
         )?;
+        scheduler.set_pcch_config(config.sib1_config.pcch_config)?;
         
         let sib1_generator = Sib1Generator::new(config.sib1_config.clone());
         
@@ -107,17 +131,71 @@ impl EnhancedMacLayer {
             sib1_generator: Arc::new(sib1_generator),
             sib1_payload: Arc::new(RwLock::new(None)),
             initialized: false,
-            next_c_rnti: Arc::new(AtomicU16::new(0x4601)), // Start C-RNTI allocation
+            next_c_rnti: Arc::new(AtomicU16::new(C_RNTI_FIRST)), // Start C-RNTI allocation
+            active_rntis: Arc::new(Mutex::new(HashSet::new())),
             ra_procedures: Arc::new(Mutex::new(Vec::new())),
             rrc_tx: None,
+            user_data_tx: None,
         })
     }
     
+    /// Scheduler of the cell, shared with the entities reading its metrics
+    pub fn scheduler(&self) -> Arc<Mutex<MacScheduler>> {
+        self.scheduler.clone()
+    }
+    
     /// Set RRC message channel
     pub fn set_rrc_channel(&mut self, tx: mpsc::Sender<(Rnti, Bytes)>) {
         self.rrc_tx = Some(tx);
     }
     
+    /// Set the channel of the RLC PDUs received on the DRBs
+    pub fn set_user_data_channel(&mut self, tx: mpsc::Sender<(Rnti, u8, Bytes)>) {
+        self.user_data_tx = Some(tx);
+    }
+    
+    /// Pass the MAC SDUs of an UL-SCH transport block to RRC, the SRBs as RRC
+    /// messages and the DRBs as user data
+    async fn process_ul_sch(&self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        for sdu in pdu::decode_ul_sch(&data)? {
+            match sdu.subheader.lcid {
+                lcid @ (pdu::LCID_CCCH..LCID_FIRST_DRB | pdu::UL_LCID_CCCH_48) => {
+                    debug!("UL-SCH SDU on LCID {} from RNTI {}: {} bytes", lcid, rnti.0, sdu.data.len());
+                    if let Some(rrc_tx) = &self.rrc_tx {
+                        rrc_tx.send((rnti, sdu.data)).await
+                            .map_err(|_| LayerError::ProcessingError("RRC channel error".into()))?;
+                    }
+                }
+                lcid @ LCID_FIRST_DRB..=pdu::LCID_MAX_LOGICAL_CHANNEL => match &self.user_data_tx {
+                    Some(user_data_tx) => user_data_tx.send((rnti, lcid, sdu.data)).await
+                        .map_err(|_| LayerError::ProcessingError("User data channel error".into()))?,
+                    None => warn!("No user data channel, dropping LCID {} data of RNTI {}", lcid, rnti.0),
+                },
+                lcid => match pdu::bsr_buffer_size(&sdu)? {
+                    Some(bytes) => self.scheduler.lock().await.report_ul_buffer(rnti, bytes),
+                    None => debug!("UL-SCH MAC CE {} from RNTI {}: {:02x?}", lcid, rnti.0, &sdu.data[..]),
+                },
+            }
+        }
+        Ok(())
+    }
+    
+    /// Allocate a free C-RNTI, wrapping around the C-RNTI range
+    async fn allocate_rnti(&self) -> Result<Rnti, LayerError> {
+        let mut active = self.active_rntis.lock().await;
+        for _ in 0..=(C_RNTI_LAST - C_RNTI_FIRST) as u32 + 1 {
+            let value = self.next_c_rnti.fetch_add(1, Ordering::SeqCst);
+            if !(C_RNTI_FIRST..=C_RNTI_LAST).contains(&value) {
+                self.next_c_rnti.store(C_RNTI_FIRST, Ordering::SeqCst);
+                continue;
+            }
+            if active.insert(value) {
+                return Ok(Rnti::new(value));
+            }
+        }
+        Err(LayerError::ResourceUnavailable)
+    }
+    
     /// Generate Random Access Response
     fn generate_rar(&self, tc_rnti: Rnti, timing_advance: u16) -> Bytes {
         let mut buf = BytesMut::new();
@@ -148,11 +226,20 @@ impl EnhancedMacLayer {
         
         // Extract RRC message from MAC PDU
         // In real implementation, would parse MAC header
-        // For now, assume entire payload is RRC message
+        // For now, assume entire payload is RRC message, unless it starts with
+        // a C-RNTI MAC CE (TS 38.321 6.1.3.2), as sent by a UE accessing the
+        // cell at handover: the rest then belongs to that already known C-RNTI
+        let (rnti, data) = if data.len() > 3 && data[0] & 0x3F == pdu::UL_LCID_C_RNTI {
+            let c_rnti = Rnti(u16::from_be_bytes([data[1], data[2]]));
+            info!("Msg3 carries C-RNTI MAC CE for C-RNTI {}", c_rnti.0);
+            (c_rnti, data.slice(3..))
+        } else {
+            (tc_rnti, data)
+        };
         
         if let Some(rrc_tx) = &self.rrc_tx {
             // Forward to RRC layer
-            if let Err(e) = rrc_tx.send((tc_rnti, data)).await {
+            if let Err(e) = rrc_tx.send((rnti, data)).await {
                 error!("Failed to send message to RRC: {}", e);
                 return Err(LayerError::ProcessingError("RRC channel error".into()));
             }
@@ -236,8 +323,12 @@ impl MacPhyInterface for EnhancedMacLayer {
             return Err(LayerError::NotInitialized);
         }
         
-        let scheduler = self.scheduler.lock().await;
-        let schedule = scheduler.get_slot_schedule(frame, slot);
+        let mut scheduler = self.scheduler.lock().await;
+        let mut schedule = scheduler.get_slot_schedule(frame, slot);
+        schedule.paging_info = scheduler.take_paging(frame, slot);
+        scheduler.record_slot(&schedule);
+        schedule.dl_grants = scheduler.take_dl_grants(&schedule);
+        schedule.ul_grants = scheduler.take_ul_grants(&schedule);
         
         Ok(schedule)
     }
@@ -269,7 +360,13 @@ impl MacPhyInterface for EnhancedMacLayer {
             
             // Initiate Random Access procedure
             // 1. Allocate TC-RNTI for the UE
-            let tc_rnti = Rnti::new(self.next_c_rnti.fetch_add(1, Ordering::SeqCst));
+            let tc_rnti = match self.allocate_rnti().await {
+                Ok(rnti) => rnti,
+                Err(e) => {
+                    warn!("No TC-RNTI available for preamble {}: {}", preamble.preamble_index, e);
+                    continue;
+                }
+            };
             
             // 2. Create RA procedure state
             let ra_proc = RandomAccessProcedure {
@@ -291,24 +388,27 @@ impl MacPhyInterface for EnhancedMacLayer {
         
         Ok(())
     }
-}
-
-/// MAC subheader structure
-#[derive(Debug, Clone)]
-pub struct MacSubheader {
-    /// Logical channel ID
-    pub lcid: u8,
-    /// Length field
-    pub length: Option<u16>,
-}
-
-/// MAC Service Data Unit (SDU)
-#[derive(Debug)]
-pub struct MacSdu {
-    /// Subheader
-    pub subheader: MacSubheader,
-    /// Payload data
-    pub data: Bytes,
+    
+    async fn report_rx_data(&self, rnti: u16, data: Bytes) -> Result<(), LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        // The first UL-SCH transport block of a TC-RNTI is its Msg3, which
+        // completes the Random Access procedure: the next ones are UL-SCH
+        // data of the C-RNTI
+        let is_msg3 = {
+            let mut ra_procs = self.ra_procedures.lock().await;
+            let count = ra_procs.len();
+            ra_procs.retain(|ra_proc| ra_proc.tc_rnti.0 != rnti);
+            ra_procs.len() < count
+        };
+        if is_msg3 {
+            self.process_msg3(Rnti(rnti), data).await
+        } else {
+            self.process_ul_sch(Rnti(rnti), data).await
+        }
+    }
 }
 
 #[async_trait]
@@ -336,8 +436,17 @@ impl RrcMacInterface for EnhancedMacLayer {
     }
     
     async fn allocate_c_rnti(&self) -> Result<Rnti, LayerError> {
-        let rnti_value = self.next_c_rnti.fetch_add(1, Ordering::SeqCst);
-        Ok(Rnti::new(rnti_value))
+        self.allocate_rnti().await
+    }
+    
+    async fn configure_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError> {
+        info!("MAC: Applying UE capabilities for RNTI {}: 256QAM DL={}, DL layers={}", 
+              rnti.0, capabilities.supports_256qam_dl, capabilities.max_mimo_layers_dl);
+        
+        let mut scheduler = self.scheduler.lock().await;
+        scheduler.set_ue_capabilities(rnti, capabilities);
+        
+        Ok(())
     }
     
     async fn schedule_rar(&self, tc_rnti: Rnti, grant: RarGrant) -> Result<(), LayerError> {
@@ -356,11 +465,54 @@ impl RrcMacInterface for EnhancedMacLayer {
         
         Ok(())
     }
+    
+    async fn release_ue(&self, rnti: Rnti) -> Result<(), LayerError> {
+        info!("MAC: Releasing RNTI {}", rnti.0);
+        
+        self.ra_procedures.lock().await.retain(|ra_proc| ra_proc.tc_rnti != rnti);
+        self.scheduler.lock().await.remove_ue(rnti);
+        self.active_rntis.lock().await.remove(&rnti.0);
+        
+        Ok(())
+    }
+    
+    async fn schedule_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        let occasion = self.scheduler.lock().await.queue_paging(request);
+        info!("MAC: Scheduling Paging for {:?} in paging frame {} of {} frames, i_s={}",
+              request.identity, occasion.paging_frame, occasion.paging_cycle, occasion.i_s);
+        Ok(())
+    }
+
+    async fn send_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+
+        debug!("MAC: User data on LCID {} for RNTI {}, size: {} bytes", lcid, rnti.0, data.len());
+        self.scheduler.lock().await.queue_dl_data(rnti, lcid, data);
+        Ok(())
+    }
+
+    async fn configure_drbs(&self, rnti: Rnti, to_setup: Vec<DrbBearerConfig>, to_release: Vec<u8>) -> Result<(), LayerError> {
+        let mut scheduler = self.scheduler.lock().await;
+        for drb_id in to_release {
+            scheduler.release_drb(rnti, drb_id);
+        }
+        for drb in to_setup {
+            scheduler.add_drb(rnti, DrbLogicalChannel { drb_id: drb.drb_id, lcid: drb.lcid, s_nssai: drb.s_nssai });
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::types::SNssai;
     
     #[tokio::test]
     async fn test_enhanced_mac_initialization() {
@@ -369,7 +521,8 @@ mod tests {
             scs: SubcarrierSpacing::Scs15,
             bandwidth: Bandwidth::Bw10,
             max_ues: 32,
-            sib1_config: default_sib1_config(CellId(1)),
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
         };
         
         let mut mac = EnhancedMacLayer::new(config).unwrap();
@@ -383,9 +536,103 @@ mod tests {
         let sib1 = mac.get_sib1_payload().await.unwrap();
         assert!(sib1.len() >= 100);
         
+        // Paging goes out in the UE's paging frame (default cycle 128, one PF per frame)
+        mac.schedule_paging(PagingRequest {
+            identity: crate::rrc::PagingUeIdentity::NgSTmsi(0x0042_C000_0405),
+            ue_id: 5,
+            paging_cycle: None,
+            priority: None,
+        }).await.unwrap();
+        assert!(mac.get_slot_schedule(4, 0).await.unwrap().paging_info.is_none());
+        let paging = mac.get_slot_schedule(128 + 5, 0).await.unwrap().paging_info.unwrap();
+        assert_eq!(paging.rnti, P_RNTI);
+        
         // Test C-RNTI allocation
         let rnti1 = mac.allocate_c_rnti().await.unwrap();
         let rnti2 = mac.allocate_c_rnti().await.unwrap();
         assert_ne!(rnti1.0, rnti2.0);
+        
+        // Released C-RNTIs are reused once the allocator wraps around
+        mac.release_ue(rnti1).await.unwrap();
+        mac.next_c_rnti.store(C_RNTI_LAST, Ordering::SeqCst);
+        assert_eq!(mac.allocate_c_rnti().await.unwrap().0, C_RNTI_LAST);
+        assert_eq!(mac.allocate_c_rnti().await.unwrap(), rnti1);
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_ul_sch_routing() {
+        let mut mac = EnhancedMacLayer::new(MacConfig {
+            cell_id: CellId(1),
+            scs: SubcarrierSpacing::Scs15,
+            bandwidth: Bandwidth::Bw10,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        }).unwrap();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(4);
+        let (user_data_tx, mut user_data_rx) = mpsc::channel(4);
+        mac.set_rrc_channel(rrc_tx);
+        mac.set_user_data_channel(user_data_tx);
+        mac.initialize().await.unwrap();
+        
+        // SRB1 to RRC, DRB to the user plane, C-RNTI MAC CE consumed by the MAC
+        let ul_sch = Bytes::from_static(&[
+            pdu::UL_LCID_C_RNTI, 0x46, 0x01,
+            0x01, 0x02, 0x00, 0x2A,
+            0x05, 0x01, 0x81,
+        ]);
+        mac.report_rx_data(0x4601, ul_sch).await.unwrap();
+        assert_eq!(rrc_rx.try_recv().unwrap(), (Rnti(0x4601), Bytes::from_static(&[0x00, 0x2A])));
+        assert_eq!(user_data_rx.try_recv().unwrap(), (Rnti(0x4601), 5, Bytes::from_static(&[0x81])));
+        assert!(rrc_rx.try_recv().is_err());
+        
+        // Nothing is delivered out of a malformed transport block
+        assert!(mac.report_rx_data(0x4601, Bytes::from_static(&[0x01, 0x05, 0x00, 0x00])).await.is_err());
+        assert!(rrc_rx.try_recv().is_err());
+        
+        // A Short BSR of 198 bytes gets the UE a PUSCH grant
+        mac.report_rx_data(0x4601, Bytes::from_static(&[pdu::UL_LCID_SHORT_BSR, 0x0A])).await.unwrap();
+        let schedule = mac.get_slot_schedule(1, 3).await.unwrap();
+        assert_eq!(schedule.ul_grants.len(), 1);
+        assert_eq!(schedule.ul_grants[0].rnti, Rnti(0x4601));
+        assert!(schedule.ul_grants[0].tbs_bytes >= 198);
+        assert!(mac.get_slot_schedule(1, 4).await.unwrap().ul_grants.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_configure_drbs() {
+        use crate::rrc::reconfiguration::default_drb_rlc_config;
+        
+        let mut mac = EnhancedMacLayer::new(MacConfig {
+            cell_id: CellId(1),
+            scs: SubcarrierSpacing::Scs15,
+            bandwidth: Bandwidth::Bw10,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        }).unwrap();
+        mac.initialize().await.unwrap();
+        let rnti = Rnti(0x4601);
+        let embb = SNssai { sst: 1, sd: None };
+        let drb = |drb_id, lcid, s_nssai| DrbBearerConfig {
+            drb_id, lcid, rlc_config: default_drb_rlc_config(), qos_flows: vec![1], ul_tunnel: None, s_nssai,
+        };
+        
+        mac.configure_drbs(rnti, vec![drb(1, 4, Some(embb.clone())), drb(2, 5, None)], Vec::new()).await.unwrap();
+        mac.send_user_data(rnti, 4, Bytes::from_static(&[0x01; 10])).await.unwrap();
+        mac.send_user_data(rnti, 5, Bytes::from_static(&[0x02; 10])).await.unwrap();
+        let scheduler = mac.scheduler();
+        assert_eq!(scheduler.lock().await.drbs(rnti), &[
+            DrbLogicalChannel { drb_id: 1, lcid: 4, s_nssai: Some(embb) },
+            DrbLogicalChannel { drb_id: 2, lcid: 5, s_nssai: None },
+        ]);
+        
+        // Releasing DRB 2 drops its queued data, a new DRB may reuse its LCID
+        mac.configure_drbs(rnti, vec![drb(3, 5, None)], vec![2]).await.unwrap();
+        assert_eq!(scheduler.lock().await.dl_buffer_bytes(rnti), 12);
+        assert_eq!(scheduler.lock().await.drbs(rnti).len(), 2);
+        
+        mac.release_ue(rnti).await.unwrap();
+        assert!(scheduler.lock().await.drbs(rnti).is_empty());
+    }
+}
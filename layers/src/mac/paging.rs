@@ -0,0 +1,164 @@
+//! Paging Frame and Paging Occasion Calculation
+//!
+//! Implements the paging occasion formulas of 3GPP TS 38.304 section 7.1 for
+//! the PCCH configuration broadcast in SIB1
+
+use crate::LayerError;
+
+/// P-RNTI (TS 38.321 Table 7.1-1)
+pub const P_RNTI: u16 = 0xFFFE;
+
+/// Maximum number of paging records in one Paging message (maxNrofPageRec)
+pub const MAX_PAGING_RECORDS: usize = 32;
+
+/// PCCH configuration (PCCH-Config in DownlinkConfigCommonSIB, TS 38.331)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcchConfig {
+    /// Default paging cycle T in radio frames (32, 64, 128 or 256)
+    pub default_paging_cycle: u16,
+    /// T div N, the distance in radio frames between paging frames
+    /// (1 = oneT, 2 = halfT, 4 = quarterT, 8 = oneEighthT, 16 = oneSixteenthT)
+    pub paging_frame_spacing: u16,
+    /// Paging frame offset PF_offset, below the paging frame spacing
+    pub paging_frame_offset: u16,
+    /// Number of paging occasions per paging frame Ns (1, 2 or 4)
+    pub ns: u8,
+}
+
+impl Default for PcchConfig {
+    fn default() -> Self {
+        Self {
+            default_paging_cycle: 128,  // rf128
+            paging_frame_spacing: 1,  // oneT: every frame is a paging frame
+            paging_frame_offset: 0,
+            ns: 1,
+        }
+    }
+}
+
+/// Paging occasion of a UE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingOccasion {
+    /// Paging cycle T in radio frames
+    pub paging_cycle: u16,
+    /// SFN mod T of the paging frame
+    pub paging_frame: u16,
+    /// Index i_s of the paging occasion within the paging frame
+    pub i_s: u8,
+}
+
+impl PagingOccasion {
+    /// Check if a system frame number is a paging frame of this occasion
+    pub fn is_paging_frame(&self, sfn: u32) -> bool {
+        sfn % self.paging_cycle as u32 == self.paging_frame as u32
+    }
+}
+
+impl PcchConfig {
+    /// Check the configuration against the values allowed by TS 38.331
+    pub fn validate(&self) -> Result<(), LayerError> {
+        if ![32, 64, 128, 256].contains(&self.default_paging_cycle) {
+            return Err(LayerError::InvalidConfiguration(
+                format!("Invalid default paging cycle: {}", self.default_paging_cycle)
+            ));
+        }
+        if ![1, 2, 4, 8, 16].contains(&self.paging_frame_spacing)
+            || self.paging_frame_offset >= self.paging_frame_spacing {
+            return Err(LayerError::InvalidConfiguration(
+                format!("Invalid paging frame spacing {} with offset {}",
+                        self.paging_frame_spacing, self.paging_frame_offset)
+            ));
+        }
+        if ![1, 2, 4].contains(&self.ns) {
+            return Err(LayerError::InvalidConfiguration(format!("Invalid Ns: {}", self.ns)));
+        }
+        Ok(())
+    }
+
+    /// Paging cycle T: the shortest of the UE specific and the default paging cycle
+    pub fn paging_cycle(&self, ue_specific_cycle: Option<u16>) -> u16 {
+        ue_specific_cycle
+            .filter(|cycle| *cycle > 0)
+            .map_or(self.default_paging_cycle, |cycle| cycle.min(self.default_paging_cycle))
+    }
+
+    /// Paging frame and occasion for a UE_ID (5G-S-TMSI mod 1024)
+    ///
+    /// PF: (SFN + PF_offset) mod T = (T div N) * (UE_ID mod N)
+    /// i_s = floor(UE_ID / N) mod Ns
+    pub fn paging_occasion(&self, ue_id: u16, ue_specific_cycle: Option<u16>) -> PagingOccasion {
+        let t = self.paging_cycle(ue_specific_cycle);
+        let spacing = self.paging_frame_spacing.clamp(1, t);
+        let n = t / spacing;
+        let ue_id = ue_id % 1024;
+
+        PagingOccasion {
+            paging_cycle: t,
+            paging_frame: (spacing * (ue_id % n) + t - self.paging_frame_offset % t) % t,
+            i_s: ((ue_id / n) % self.ns.max(1) as u16) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paging_occasion() {
+        // Every frame is a paging frame: PF = UE_ID mod T
+        let config = PcchConfig::default();
+        assert!(config.validate().is_ok());
+        let occasion = config.paging_occasion(300, None);
+        assert_eq!(occasion, PagingOccasion { paging_cycle: 128, paging_frame: 300 % 128, i_s: 0 });
+        assert!(occasion.is_paging_frame(44));
+        assert!(occasion.is_paging_frame(44 + 128 * 3));
+        assert!(!occasion.is_paging_frame(45));
+
+        // A shorter UE specific cycle takes precedence over the default one
+        assert_eq!(config.paging_cycle(Some(32)), 32);
+        assert_eq!(config.paging_cycle(Some(256)), 128);
+        assert_eq!(config.paging_occasion(300, Some(32)).paging_frame, 300 % 32);
+
+        // T = 64, N = T/4 = 16, PF_offset = 1, Ns = 2
+        let config = PcchConfig {
+            default_paging_cycle: 64,
+            paging_frame_spacing: 4,
+            paging_frame_offset: 1,
+            ns: 2,
+        };
+        assert!(config.validate().is_ok());
+        // UE_ID 21: UE_ID mod N = 5, (SFN + 1) mod 64 = 20, i_s = (21 / 16) mod 2 = 1
+        let occasion = config.paging_occasion(21, None);
+        assert_eq!(occasion, PagingOccasion { paging_cycle: 64, paging_frame: 19, i_s: 1 });
+        // UE_ID 0 pages in SFN 63 with the offset wrapping around
+        assert_eq!(config.paging_occasion(0, None).paging_frame, 63);
+
+        assert!(PcchConfig { ns: 3, ..config }.validate().is_err());
+        assert!(PcchConfig { paging_frame_offset: 4, ..config }.validate().is_err());
+        assert!(PcchConfig { default_paging_cycle: 100, ..config }.validate().is_err());
+    }
+
+    #[test]
+    fn test_paging_occasion_limits() {
+        let config = PcchConfig::default();
+        // UE_ID is 5G-S-TMSI mod 1024
+        assert_eq!(config.paging_occasion(1024 + 5, None), config.paging_occasion(5, None));
+        // A zero UE specific cycle is not a cycle
+        assert_eq!(config.paging_cycle(Some(0)), 128);
+
+        // The paging frame spacing never exceeds the cycle
+        let config = PcchConfig { default_paging_cycle: 32, paging_frame_spacing: 16, paging_frame_offset: 0, ns: 4 };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.paging_occasion(3, Some(8)), PagingOccasion { paging_cycle: 8, paging_frame: 0, i_s: 3 });
+
+        for invalid in [
+            PcchConfig { ns: 0, ..config },
+            PcchConfig { paging_frame_spacing: 3, ..config },
+            PcchConfig { paging_frame_offset: 16, ..config },
+            PcchConfig { default_paging_cycle: 0, ..config },
+        ] {
+            assert!(matches!(invalid.validate(), Err(LayerError::InvalidConfiguration(_))), "{:?}", invalid);
+        }
+    }
+}
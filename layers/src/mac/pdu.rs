@@ -0,0 +1,303 @@
+//! MAC PDU Multiplexing
+//!
+//! Builds DL-SCH MAC PDUs out of the RLC PDUs queued for a UE and splits the
+//! UL-SCH MAC PDUs received on PUSCH into their MAC SDUs and control elements,
+//! with the R/F/LCID/L subheaders of 3GPP TS 38.321 section 6.1.2
+
+use crate::LayerError;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// LCID of the CCCH (TS 38.321 Tables 6.2.1-1 and 6.2.1-2)
+pub const LCID_CCCH: u8 = 0;
+/// Highest LCID of a logical channel
+pub const LCID_MAX_LOGICAL_CHANNEL: u8 = 32;
+/// LCID of padding
+pub const LCID_PADDING: u8 = 63;
+
+/// LCID of the Timing Advance Command MAC CE on DL-SCH
+pub const DL_LCID_TIMING_ADVANCE: u8 = 61;
+/// LCID of the UE Contention Resolution Identity MAC CE on DL-SCH
+pub const DL_LCID_CONTENTION_RESOLUTION: u8 = 62;
+
+/// LCID of the 48 bit CCCH on UL-SCH
+pub const UL_LCID_CCCH_48: u8 = 52;
+/// LCID of the Single Entry PHR MAC CE on UL-SCH
+pub const UL_LCID_SINGLE_ENTRY_PHR: u8 = 57;
+/// LCID of the C-RNTI MAC CE on UL-SCH
+pub const UL_LCID_C_RNTI: u8 = 58;
+/// LCID of the Short Truncated BSR MAC CE on UL-SCH
+pub const UL_LCID_SHORT_TRUNCATED_BSR: u8 = 59;
+/// LCID of the Long Truncated BSR MAC CE on UL-SCH
+pub const UL_LCID_LONG_TRUNCATED_BSR: u8 = 60;
+/// LCID of the Short BSR MAC CE on UL-SCH
+pub const UL_LCID_SHORT_BSR: u8 = 61;
+/// LCID of the Long BSR MAC CE on UL-SCH
+pub const UL_LCID_LONG_BSR: u8 = 62;
+
+/// Upper bounds in bytes of the 5 bit Buffer Size field (TS 38.321 Table 6.1.3.1-1),
+/// the last index reporting more than 150000 bytes
+const SHORT_BSR_BUFFER_SIZE: [u32; 32] = [
+    0, 10, 14, 20, 28, 38, 53, 74, 102, 142, 198, 276, 384, 535, 745, 1038, 1446, 2014, 2806, 3909, 5446, 7587,
+    10570, 14726, 20516, 28581, 39818, 55474, 77284, 107669, 150000, 150000,
+];
+
+/// Upper bound in bytes of the 8 bit Buffer Size field (TS 38.321 Table 6.1.3.1-2)
+///
+/// The bounds of the table grow geometrically from 10 bytes at index 1 to
+/// 81338368 bytes at index 253, they are computed along that progression.
+/// Index 254 reports more than 81338368 bytes, 255 is reserved.
+fn long_bsr_buffer_size(index: u8) -> u32 {
+    match index {
+        0 => 0,
+        1..=253 => (10.0 * 8133836.8f64.powf((index - 1) as f64 / 252.0)).round() as u32,
+        _ => 81338368,
+    }
+}
+
+/// MAC subheader structure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacSubheader {
+    /// Logical channel ID
+    pub lcid: u8,
+    /// Length field, absent for fixed size MAC CEs
+    pub length: Option<u16>,
+}
+
+/// MAC Service Data Unit (SDU) or MAC CE with its subheader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacSdu {
+    /// Subheader
+    pub subheader: MacSubheader,
+    /// Payload data
+    pub data: Bytes,
+}
+
+impl MacSdu {
+    /// MAC SDU of a logical channel
+    pub fn new(lcid: u8, data: Bytes) -> Self {
+        Self { subheader: MacSubheader { lcid, length: Some(data.len() as u16) }, data }
+    }
+}
+
+/// Size of the MAC subPDU carrying a MAC SDU of `sdu_len` bytes: the 8 bit L
+/// field up to 255 bytes, the 16 bit one above
+pub fn subpdu_len(sdu_len: usize) -> usize {
+    if sdu_len < 256 { sdu_len + 2 } else { sdu_len + 3 }
+}
+
+/// Size of the fixed size MAC CEs of DL-SCH, which have no L field
+fn dl_fixed_size(lcid: u8) -> Option<usize> {
+    match lcid {
+        DL_LCID_TIMING_ADVANCE => Some(1),
+        DL_LCID_CONTENTION_RESOLUTION => Some(6),
+        _ => None,
+    }
+}
+
+/// Size of the fixed size UL-SCH MAC SDUs and CEs, which have no L field
+fn ul_fixed_size(lcid: u8) -> Option<usize> {
+    match lcid {
+        LCID_CCCH => Some(8),
+        UL_LCID_CCCH_48 => Some(6),
+        UL_LCID_SINGLE_ENTRY_PHR | UL_LCID_C_RNTI => Some(2),
+        UL_LCID_SHORT_TRUNCATED_BSR | UL_LCID_SHORT_BSR => Some(1),
+        _ => None,
+    }
+}
+
+/// Check if an UL-SCH LCID has an L field
+fn ul_has_length(lcid: u8) -> bool {
+    matches!(lcid, 1..=LCID_MAX_LOGICAL_CHANNEL | UL_LCID_LONG_TRUNCATED_BSR | UL_LCID_LONG_BSR)
+}
+
+/// Check if a DL-SCH LCID has an L field
+fn dl_has_length(lcid: u8) -> bool {
+    matches!(lcid, LCID_CCCH..=LCID_MAX_LOGICAL_CHANNEL)
+}
+
+/// Build a DL-SCH MAC PDU of `tbs_bytes` bytes, padded at the end
+pub fn encode_dl_sch(sdus: &[MacSdu], tbs_bytes: usize) -> Result<Bytes, LayerError> {
+    let mut buf = BytesMut::with_capacity(tbs_bytes);
+    for sdu in sdus {
+        let lcid = sdu.subheader.lcid;
+        if let Some(size) = dl_fixed_size(lcid) {
+            if sdu.data.len() != size {
+                return Err(LayerError::InvalidPdu);
+            }
+            buf.put_u8(lcid);
+        } else if dl_has_length(lcid) {
+            match sdu.data.len() {
+                len if len < 256 => {
+                    buf.put_u8(lcid);
+                    buf.put_u8(len as u8);
+                }
+                len if len <= u16::MAX as usize => {
+                    buf.put_u8(0x40 | lcid);
+                    buf.put_u16(len as u16);
+                }
+                _ => return Err(LayerError::InvalidPdu),
+            }
+        } else {
+            return Err(LayerError::InvalidPdu);
+        }
+        buf.put_slice(&sdu.data);
+    }
+    if buf.len() > tbs_bytes {
+        return Err(LayerError::ProcessingError(format!(
+            "MAC PDU of {} bytes exceeds the transport block of {} bytes", buf.len(), tbs_bytes)));
+    }
+    if buf.len() < tbs_bytes {
+        buf.put_u8(LCID_PADDING);
+        buf.resize(tbs_bytes, 0);
+    }
+    Ok(buf.freeze())
+}
+
+/// Split a MAC PDU into its subPDUs, up to the padding
+fn decode(
+    pdu: &Bytes,
+    fixed_size: fn(u8) -> Option<usize>,
+    has_length: fn(u8) -> bool,
+) -> Result<Vec<MacSdu>, LayerError> {
+    let mut sdus = Vec::new();
+    let mut offset = 0;
+    while offset < pdu.len() {
+        let header = pdu[offset];
+        let lcid = header & 0x3F;
+        if lcid == LCID_PADDING {
+            break;
+        }
+        let (size, length, start) = if let Some(size) = fixed_size(lcid) {
+            (size, None, offset + 1)
+        } else if has_length(lcid) {
+            if header & 0x40 == 0 {
+                let len = *pdu.get(offset + 1).ok_or(LayerError::InvalidPdu)?;
+                (len as usize, Some(len as u16), offset + 2)
+            } else {
+                let bytes = pdu.get(offset + 1..offset + 3).ok_or(LayerError::InvalidPdu)?;
+                let len = u16::from_be_bytes([bytes[0], bytes[1]]);
+                (len as usize, Some(len), offset + 3)
+            }
+        } else {
+            return Err(LayerError::InvalidPdu);
+        };
+        if start + size > pdu.len() {
+            return Err(LayerError::InvalidPdu);
+        }
+        sdus.push(MacSdu { subheader: MacSubheader { lcid, length }, data: pdu.slice(start..start + size) });
+        offset = start + size;
+    }
+    Ok(sdus)
+}
+
+/// Split a DL-SCH MAC PDU into its MAC SDUs and MAC CEs
+pub fn decode_dl_sch(pdu: &Bytes) -> Result<Vec<MacSdu>, LayerError> {
+    decode(pdu, dl_fixed_size, dl_has_length)
+}
+
+/// Split an UL-SCH MAC PDU into its MAC SDUs and MAC CEs
+pub fn decode_ul_sch(pdu: &Bytes) -> Result<Vec<MacSdu>, LayerError> {
+    decode(pdu, ul_fixed_size, ul_has_length)
+}
+
+/// Bytes a UE reports waiting for transmission in a BSR MAC CE (TS 38.321
+/// section 6.1.3.1), summed over the reported logical channel groups; `None`
+/// for other MAC SDUs and CEs
+pub fn bsr_buffer_size(sdu: &MacSdu) -> Result<Option<u32>, LayerError> {
+    match sdu.subheader.lcid {
+        UL_LCID_SHORT_BSR | UL_LCID_SHORT_TRUNCATED_BSR => {
+            let octet = *sdu.data.first().ok_or(LayerError::InvalidPdu)?;
+            Ok(Some(SHORT_BSR_BUFFER_SIZE[(octet & 0x1F) as usize]))
+        }
+        UL_LCID_LONG_BSR | UL_LCID_LONG_TRUNCATED_BSR => {
+            let (&lcg_bitmap, sizes) = sdu.data.split_first().ok_or(LayerError::InvalidPdu)?;
+            let reported = lcg_bitmap.count_ones() as usize;
+            // The truncated BSR carries the sizes of only some of the groups
+            let complete = sdu.subheader.lcid == UL_LCID_LONG_BSR;
+            if sizes.len() > reported || (complete && sizes.len() != reported) {
+                return Err(LayerError::InvalidPdu);
+            }
+            Ok(Some(sizes.iter().map(|&index| long_bsr_buffer_size(index)).sum()))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dl_sch_multiplexing() {
+        let srb = Bytes::from_static(&[0x00, 0x01, 0x02]);
+        let drb = Bytes::from(vec![0x5A; 300]);
+        let sdus = vec![MacSdu::new(1, srb.clone()), MacSdu::new(4, drb.clone())];
+        let pdu = encode_dl_sch(&sdus, 320).unwrap();
+        assert_eq!(pdu.len(), 320);
+        assert_eq!(&pdu[..5], &[0x01, 0x03, 0x00, 0x01, 0x02]);
+        // 16 bit L above 255 bytes
+        assert_eq!(&pdu[5..8], &[0x44, 0x01, 0x2C]);
+        assert_eq!(pdu[8 + 300], LCID_PADDING);
+        assert_eq!(subpdu_len(srb.len()) + subpdu_len(drb.len()), 8 + 300);
+        assert_eq!(decode_dl_sch(&pdu).unwrap(), sdus);
+
+        // Exactly filled, no padding subheader
+        assert_eq!(encode_dl_sch(&sdus[..1], 5).unwrap().len(), 5);
+        assert!(encode_dl_sch(&sdus, 100).is_err());
+        let ta = MacSdu { subheader: MacSubheader { lcid: DL_LCID_TIMING_ADVANCE, length: None }, data: Bytes::from_static(&[31]) };
+        assert_eq!(&encode_dl_sch(&[ta], 2).unwrap()[..], &[DL_LCID_TIMING_ADVANCE, 31]);
+    }
+
+    #[test]
+    fn test_ul_sch_demultiplexing() {
+        // Short BSR, C-RNTI MAC CE, DRB SDU and padding
+        let pdu = Bytes::from_static(&[
+            UL_LCID_SHORT_BSR, 0x2A,
+            UL_LCID_C_RNTI, 0x46, 0x01,
+            0x04, 0x02, 0x81, 0x45,
+            LCID_PADDING, 0x00, 0x00,
+        ]);
+        let sdus = decode_ul_sch(&pdu).unwrap();
+        assert_eq!(sdus.len(), 3);
+        assert_eq!((sdus[0].subheader.lcid, sdus[0].subheader.length, &sdus[0].data[..]), (UL_LCID_SHORT_BSR, None, &[0x2A][..]));
+        assert_eq!(&sdus[1].data[..], &[0x46, 0x01]);
+        assert_eq!(sdus[2], MacSdu::new(4, Bytes::from_static(&[0x81, 0x45])));
+    }
+
+    #[test]
+    fn test_ul_sch_decode_errors() {
+        // L beyond the end of the PDU
+        assert!(matches!(decode_ul_sch(&Bytes::from_static(&[0x04, 0x05, 0x00])), Err(LayerError::InvalidPdu)));
+        // Truncated 16 bit L
+        assert!(matches!(decode_ul_sch(&Bytes::from_static(&[0x44, 0x01])), Err(LayerError::InvalidPdu)));
+        // Fixed size CE cut short
+        assert!(matches!(decode_ul_sch(&Bytes::from_static(&[UL_LCID_C_RNTI, 0x46])), Err(LayerError::InvalidPdu)));
+        // Reserved LCID
+        assert!(matches!(decode_ul_sch(&Bytes::from_static(&[40, 0x00])), Err(LayerError::InvalidPdu)));
+        // An empty PDU carries nothing
+        assert!(decode_ul_sch(&Bytes::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_buffer_status_report() {
+        let ce = |lcid: u8, data: &'static [u8]| MacSdu {
+            subheader: MacSubheader { lcid, length: None },
+            data: Bytes::from_static(data),
+        };
+        // LCG 1, BS index 10
+        assert_eq!(bsr_buffer_size(&ce(UL_LCID_SHORT_BSR, &[0x2A])).unwrap(), Some(198));
+        assert_eq!(bsr_buffer_size(&ce(UL_LCID_SHORT_TRUNCATED_BSR, &[0x1F])).unwrap(), Some(150000));
+        // LCG 0 and LCG 2, indices 1 and 253
+        assert_eq!(bsr_buffer_size(&ce(UL_LCID_LONG_BSR, &[0x05, 1, 253])).unwrap(), Some(10 + 81338368));
+        assert_eq!(bsr_buffer_size(&ce(UL_LCID_LONG_BSR, &[0x00])).unwrap(), Some(0));
+        // A truncated Long BSR may leave out groups, a Long BSR may not
+        assert_eq!(bsr_buffer_size(&ce(UL_LCID_LONG_TRUNCATED_BSR, &[0x05, 254])).unwrap(), Some(81338368));
+        assert!(matches!(bsr_buffer_size(&ce(UL_LCID_LONG_BSR, &[0x05, 1])), Err(LayerError::InvalidPdu)));
+        assert!(matches!(bsr_buffer_size(&ce(UL_LCID_LONG_BSR, &[0x01, 1, 2])), Err(LayerError::InvalidPdu)));
+        assert!(matches!(bsr_buffer_size(&ce(UL_LCID_SHORT_BSR, &[])), Err(LayerError::InvalidPdu)));
+        assert_eq!(bsr_buffer_size(&MacSdu::new(4, Bytes::from_static(&[0x2A]))).unwrap(), None);
+
+        // The computed bounds never decrease with the index
+        assert!((1..=255).all(|index| long_bsr_buffer_size(index) >= long_bsr_buffer_size(index - 1)));
+    }
+}
@@ -1,10 +1,32 @@
 //! MAC Scheduler Implementation
 //! 
-//! Handles scheduling of system information (SSB, SIB1) and user data
+//! Handles scheduling of system information (SSB, SIB1), paging and user data
 
+use super::mcs::{cqi_spectral_efficiency, mcs_table, mcs_table_index, select_mcs, transport_block_size, McsEntry};
+use super::paging::{PagingOccasion, PcchConfig, MAX_PAGING_RECORDS, P_RNTI};
+use super::pdu::{encode_dl_sch, subpdu_len, MacSdu};
 use crate::LayerError;
-use common::types::{SubcarrierSpacing, Bandwidth, CellId};
-use tracing::{debug, info};
+use crate::rrc::{Paging, PagingRequest};
+use bytes::Bytes;
+use common::types::{SubcarrierSpacing, Bandwidth, CellId, Rnti, ModulationScheme, SNssai};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use tracing::{debug, info, warn};
+
+/// Spectral efficiency assumed until a UE reports its CQI, QPSK at code
+/// rate 602/1024 (CQI 6 of TS 38.214 Table 5.2.2.1-2)
+const INITIAL_SPECTRAL_EFFICIENCY: u32 = 2 * 6020;
+/// Aggregation level of the UE PDCCH
+const UE_AGGREGATION_LEVEL: u8 = 4;
+/// DL HARQ processes of a UE (TS 38.321 section 5.3.1)
+const NUM_DL_HARQ_PROCESSES: u8 = 16;
+/// UL HARQ processes of a UE (TS 38.321 section 5.4.1)
+const NUM_UL_HARQ_PROCESSES: u8 = 16;
+/// DM-RS REs per PRB of the UE PDSCH, a single front loaded DM-RS symbol
+const PDSCH_DMRS_RES_PER_PRB: u32 = 12;
+/// DM-RS REs per PRB of the UE PUSCH, a single front loaded DM-RS symbol
+const PUSCH_DMRS_RES_PER_PRB: u32 = 12;
+/// Slots over which the proportional fair scheduler averages the UE throughput
+const PF_WINDOW_SLOTS: f64 = 100.0;
 
 /// CORESET#0 configuration based on 3GPP TS 38.213
 #[derive(Debug, Clone)]
@@ -67,6 +89,12 @@ pub struct SlotSchedule {
     pub ssb_info: Option<SsbScheduleInfo>,
     /// SIB1 transmission info if scheduled
     pub sib1_info: Option<Sib1ScheduleInfo>,
+    /// Paging transmission info if a paging occasion has records to send
+    pub paging_info: Option<PagingScheduleInfo>,
+    /// PDSCH of the UEs with downlink data
+    pub dl_grants: Vec<UeDlGrant>,
+    /// PUSCH of the UEs with uplink data, granted in this slot
+    pub ul_grants: Vec<UeUlGrant>,
 }
 
 /// SSB scheduling information
@@ -107,6 +135,112 @@ pub struct Sib1ScheduleInfo {
     pub prb_allocation: Vec<u16>,
 }
 
+/// Paging scheduling information, PDCCH with CRC scrambled by P-RNTI
+#[derive(Debug, Clone)]
+pub struct PagingScheduleInfo {
+    /// P-RNTI
+    pub rnti: u16,
+    /// PDSCH time domain allocation
+    pub pdsch_time_alloc: PdschTimeAlloc,
+    /// CORESET configuration (CORESET#0)
+    pub coreset: common::CorsetConfig,
+    /// Frequency domain assignment for DCI
+    pub frequency_domain_assignment: u16,
+    /// Time domain assignment for DCI
+    pub time_domain_assignment: u8,
+    /// MCS index
+    pub mcs_index: u8,
+    /// Aggregation level for PDCCH
+    pub aggregation_level: u8,
+    /// CCE index
+    pub cce_index: u16,
+    /// Transport block size in bytes
+    pub tbs_bytes: usize,
+    /// Modulation scheme
+    pub modulation: common::ModulationScheme,
+    /// PRB allocation
+    pub prb_allocation: Vec<u16>,
+    /// Paging message
+    pub payload: Bytes,
+}
+
+/// PDSCH of a UE, PDCCH with CRC scrambled by its C-RNTI
+#[derive(Debug, Clone)]
+pub struct UeDlGrant {
+    /// C-RNTI
+    pub rnti: Rnti,
+    /// PDSCH time domain allocation
+    pub pdsch_time_alloc: PdschTimeAlloc,
+    /// CORESET configuration (CORESET#0)
+    pub coreset: common::CorsetConfig,
+    /// Frequency domain assignment for DCI
+    pub frequency_domain_assignment: u16,
+    /// Time domain assignment for DCI
+    pub time_domain_assignment: u8,
+    /// MCS index
+    pub mcs_index: u8,
+    /// MCS table of the MCS index: 0 for table 1, 1 for table 2
+    pub mcs_table: u8,
+    /// Aggregation level for PDCCH
+    pub aggregation_level: u8,
+    /// CCE index
+    pub cce_index: u16,
+    /// HARQ process number
+    pub harq_process: u8,
+    /// New data indicator
+    pub ndi: bool,
+    /// Transport block size in bytes
+    pub tbs_bytes: usize,
+    /// Modulation scheme
+    pub modulation: ModulationScheme,
+    /// PRB allocation
+    pub prb_allocation: Vec<u16>,
+    /// DL-SCH MAC PDU
+    pub payload: Bytes,
+}
+
+/// PUSCH of a UE, PDCCH with CRC scrambled by its C-RNTI (DCI format 0_0)
+#[derive(Debug, Clone)]
+pub struct UeUlGrant {
+    /// C-RNTI
+    pub rnti: Rnti,
+    /// PUSCH time domain allocation
+    pub pusch_time_alloc: PuschTimeAlloc,
+    /// CORESET configuration (CORESET#0)
+    pub coreset: common::CorsetConfig,
+    /// Frequency domain assignment for DCI
+    pub frequency_domain_assignment: u16,
+    /// Time domain assignment for DCI
+    pub time_domain_assignment: u8,
+    /// MCS index
+    pub mcs_index: u8,
+    /// MCS table of the MCS index: 0 for table 1, 1 for table 2
+    pub mcs_table: u8,
+    /// Aggregation level for PDCCH
+    pub aggregation_level: u8,
+    /// CCE index
+    pub cce_index: u16,
+    /// HARQ process number
+    pub harq_process: u8,
+    /// New data indicator
+    pub ndi: bool,
+    /// Transport block size in bytes
+    pub tbs_bytes: usize,
+    /// Modulation scheme
+    pub modulation: ModulationScheme,
+    /// PRB allocation
+    pub prb_allocation: Vec<u16>,
+}
+
+/// Paging record waiting for its paging occasion
+#[derive(Debug, Clone)]
+struct PendingPaging {
+    /// Paging occasion of the UE
+    occasion: PagingOccasion,
+    /// Record to transmit
+    request: PagingRequest,
+}
+
 /// PDSCH time domain resource allocation
 #[derive(Debug, Clone)]
 pub struct PdschTimeAlloc {
@@ -116,6 +250,103 @@ pub struct PdschTimeAlloc {
     pub num_symbols: u8,
 }
 
+/// PUSCH time domain resource allocation
+#[derive(Debug, Clone)]
+pub struct PuschTimeAlloc {
+    /// Slots between the PDCCH and the PUSCH (K2)
+    pub k2: u8,
+    /// Starting symbol (S)
+    pub start_symbol: u8,
+    /// Number of symbols (L)
+    pub num_symbols: u8,
+}
+
+/// Per-UE capability flags relevant for link adaptation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UeSchedulingCapabilities {
+    /// UE supports 256QAM on PDSCH
+    pub supports_256qam_dl: bool,
+    /// UE supports 256QAM on PUSCH
+    pub supports_256qam_ul: bool,
+    /// Maximum number of PDSCH MIMO layers
+    pub max_mimo_layers_dl: u8,
+    /// Maximum number of PUSCH MIMO layers
+    pub max_mimo_layers_ul: u8,
+}
+
+impl Default for UeSchedulingCapabilities {
+    fn default() -> Self {
+        // Baseline capabilities every NR UE supports
+        Self {
+            supports_256qam_dl: false,
+            supports_256qam_ul: false,
+            max_mimo_layers_dl: 1,
+            max_mimo_layers_ul: 1,
+        }
+    }
+}
+
+/// PRBs allocated to a UE
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UeResourceUsage {
+    /// Downlink PRBs allocated
+    pub dl_prbs: u64,
+    /// Uplink PRBs allocated
+    pub ul_prbs: u64,
+}
+
+/// PRB usage counted by the scheduler since the cell started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulerMetrics {
+    /// Slots scheduled
+    pub slots: u64,
+    /// PRBs of the carrier in each slot
+    pub prbs_per_slot: u32,
+    /// Downlink PRBs allocated, common channels included
+    pub dl_prbs_used: u64,
+    /// Uplink PRBs allocated
+    pub ul_prbs_used: u64,
+    /// Usage of each UE by C-RNTI
+    pub ues: HashMap<Rnti, UeResourceUsage>,
+}
+
+/// Logical channel of a DRB of a UE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrbLogicalChannel {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Logical channel identity
+    pub lcid: u8,
+    /// Slice of the PDU session carried by the DRB, None where it is not known
+    pub s_nssai: Option<SNssai>,
+}
+
+/// Order in which UEs with pending data are served
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    /// Every UE in turn
+    #[default]
+    RoundRobin,
+    /// Achievable rate relative to the UE's average throughput
+    ProportionalFair,
+    /// Best channel quality first
+    MaxCqi,
+}
+
+/// Share of the PRBs of a slot granted to a slice (RRM policy ratios of
+/// TS 28.541 section 5.3.43)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlicePrbQuota {
+    /// Slice the quota applies to
+    pub s_nssai: SNssai,
+    /// Percentage of PRBs the slice gets first when it has data, shared otherwise
+    pub min_ratio: u8,
+    /// Percentage of PRBs the slice may use at most
+    pub max_ratio: u8,
+    /// Percentage of PRBs reserved for the slice, never shared
+    pub dedicated_ratio: u8,
+}
+
 /// MAC scheduler
 pub struct MacScheduler {
     /// Cell ID
@@ -130,6 +361,42 @@ pub struct MacScheduler {
     sib1_period_ms: u32,
     /// CORESET#0 configuration
     coreset0_config: Coreset0Config,
+    /// UE capabilities reported by RRC, indexed by C-RNTI
+    ue_capabilities: HashMap<Rnti, UeSchedulingCapabilities>,
+    /// PCCH configuration broadcast in SIB1
+    pcch_config: PcchConfig,
+    /// Paging records waiting for their paging occasion
+    pending_paging: Vec<PendingPaging>,
+    /// Paging of the current slot, by frame and slot
+    current_paging: Option<(u32, u8, PagingScheduleInfo)>,
+    /// PRB usage counters
+    metrics: SchedulerMetrics,
+    /// Last slot counted in the metrics, by frame and slot
+    last_counted_slot: Option<(u32, u8)>,
+    /// Order in which UEs are served
+    policy: SchedulingPolicy,
+    /// PRB quotas of the slices, slices without one share what is left
+    slice_quotas: Vec<SlicePrbQuota>,
+    /// DRB logical channels of the UEs, by C-RNTI
+    drbs: HashMap<Rnti, Vec<DrbLogicalChannel>>,
+    /// RLC PDUs waiting for transmission, by C-RNTI and LCID
+    dl_queues: HashMap<Rnti, BTreeMap<u8, VecDeque<Bytes>>>,
+    /// Next DL HARQ process and NDI of each process, by C-RNTI
+    dl_harq: HashMap<Rnti, (u8, u16)>,
+    /// Bytes the UEs reported waiting in their last BSR, less what was granted since
+    ul_buffers: HashMap<Rnti, u32>,
+    /// Next UL HARQ process and NDI of each process, by C-RNTI
+    ul_harq: HashMap<Rnti, (u8, u16)>,
+    /// Last wideband CQI reported by each UE
+    ue_cqi: HashMap<Rnti, u8>,
+    /// Average DL bytes per slot of each UE, for proportional fair scheduling
+    dl_throughput: HashMap<Rnti, f64>,
+    /// Average UL bytes per slot of each UE, for proportional fair scheduling
+    ul_throughput: HashMap<Rnti, f64>,
+    /// UE transmissions and grants of the current slot, by frame and slot
+    current_ue_grants: Option<(u32, u8, Vec<UeDlGrant>, Vec<UeUlGrant>)>,
+    /// Rotation of the UEs served first
+    next_ue: usize,
 }
 
 impl MacScheduler {
@@ -143,6 +410,7 @@ impl MacScheduler {
         // Get CORESET#0 configuration from MIB pdcch_config_sib1
         // Use the coreset0_index from configuration
         let coreset0_config = Coreset0Config::from_index(coreset0_index)?;
+        let prbs_per_slot = common::utils::calculate_nrb(bandwidth.as_hz(), scs as u16) as u32;
         
         Ok(Self {
             cell_id,
@@ -151,9 +419,208 @@ impl MacScheduler {
             ssb_period_ms: 20,  // 20ms SSB periodicity for initial cell search
             sib1_period_ms: 20,  // 20ms SIB1 periodicity when SSB period <= 20ms (TS 38.331)
             coreset0_config,
+            ue_capabilities: HashMap::new(),
+            pcch_config: PcchConfig::default(),
+            pending_paging: Vec::new(),
+            current_paging: None,
+            metrics: SchedulerMetrics { prbs_per_slot, ..Default::default() },
+            last_counted_slot: None,
+            policy: SchedulingPolicy::default(),
+            slice_quotas: Vec::new(),
+            drbs: HashMap::new(),
+            dl_queues: HashMap::new(),
+            dl_harq: HashMap::new(),
+            ul_buffers: HashMap::new(),
+            ul_harq: HashMap::new(),
+            ue_cqi: HashMap::new(),
+            dl_throughput: HashMap::new(),
+            ul_throughput: HashMap::new(),
+            current_ue_grants: None,
+            next_ue: 0,
         })
     }
     
+    /// Set the PCCH configuration broadcast in SIB1
+    pub fn set_pcch_config(&mut self, pcch_config: PcchConfig) -> Result<(), LayerError> {
+        pcch_config.validate()?;
+        self.pcch_config = pcch_config;
+        Ok(())
+    }
+    
+    /// Store capabilities for a UE
+    pub fn set_ue_capabilities(&mut self, rnti: Rnti, capabilities: UeSchedulingCapabilities) {
+        debug!("Scheduler: RNTI {} capabilities {:?}", rnti.0, capabilities);
+        self.ue_capabilities.insert(rnti, capabilities);
+    }
+    
+    /// Get capabilities for a UE (defaults if not yet reported)
+    pub fn ue_capabilities(&self, rnti: Rnti) -> UeSchedulingCapabilities {
+        self.ue_capabilities.get(&rnti).copied().unwrap_or_default()
+    }
+    
+    /// Forget capabilities, usage and pending data of a released UE
+    pub fn remove_ue(&mut self, rnti: Rnti) {
+        self.ue_capabilities.remove(&rnti);
+        self.metrics.ues.remove(&rnti);
+        self.drbs.remove(&rnti);
+        self.dl_queues.remove(&rnti);
+        self.dl_harq.remove(&rnti);
+        self.ul_buffers.remove(&rnti);
+        self.ul_harq.remove(&rnti);
+        self.ue_cqi.remove(&rnti);
+        self.dl_throughput.remove(&rnti);
+        self.ul_throughput.remove(&rnti);
+    }
+    
+    /// Store the wideband CQI reported by a UE
+    pub fn set_ue_cqi(&mut self, rnti: Rnti, cqi: u8) {
+        self.ue_cqi.insert(rnti, cqi);
+    }
+    
+    /// Store the buffer size reported by a UE in a BSR
+    pub fn report_ul_buffer(&mut self, rnti: Rnti, bytes: u32) {
+        debug!("Scheduler: RNTI {} reports {} bytes to send", rnti.0, bytes);
+        self.ul_buffers.insert(rnti, bytes);
+    }
+    
+    /// Bytes a UE has to send that are not granted yet
+    pub fn ul_buffer_bytes(&self, rnti: Rnti) -> u32 {
+        self.ul_buffers.get(&rnti).copied().unwrap_or(0)
+    }
+    
+    /// Add or modify the logical channel of a DRB of a UE
+    pub fn add_drb(&mut self, rnti: Rnti, drb: DrbLogicalChannel) {
+        debug!("Scheduler: RNTI {} DRB {} on LCID {}", rnti.0, drb.drb_id, drb.lcid);
+        let drbs = self.drbs.entry(rnti).or_default();
+        drbs.retain(|other| other.drb_id != drb.drb_id && other.lcid != drb.lcid);
+        drbs.push(drb);
+    }
+    
+    /// Release a DRB of a UE, dropping the data still queued on its logical channel
+    pub fn release_drb(&mut self, rnti: Rnti, drb_id: u8) {
+        let Some(drbs) = self.drbs.get_mut(&rnti) else { return };
+        for drb in drbs.iter().filter(|drb| drb.drb_id == drb_id) {
+            debug!("Scheduler: RNTI {} releases DRB {} on LCID {}", rnti.0, drb.drb_id, drb.lcid);
+            if let Some(queues) = self.dl_queues.get_mut(&rnti) {
+                queues.remove(&drb.lcid);
+            }
+        }
+        drbs.retain(|drb| drb.drb_id != drb_id);
+    }
+    
+    /// DRB logical channels of a UE
+    pub fn drbs(&self, rnti: Rnti) -> &[DrbLogicalChannel] {
+        self.drbs.get(&rnti).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Queue an RLC PDU of a logical channel for transmission to a UE
+    pub fn queue_dl_data(&mut self, rnti: Rnti, lcid: u8, data: Bytes) {
+        self.dl_queues.entry(rnti).or_default().entry(lcid).or_default().push_back(data);
+    }
+    
+    /// Bytes waiting for transmission to a UE, MAC subheaders included
+    pub fn dl_buffer_bytes(&self, rnti: Rnti) -> usize {
+        self.dl_queues.get(&rnti).map_or(0, |queues| {
+            queues.values().flatten().map(|pdu| subpdu_len(pdu.len())).sum()
+        })
+    }
+    
+    /// Change the order in which UEs are served, taking effect from the next slot
+    pub fn set_scheduling_policy(&mut self, policy: SchedulingPolicy) {
+        info!("Scheduler: policy {:?}", policy);
+        self.policy = policy;
+    }
+    
+    /// Order in which UEs are served
+    pub fn scheduling_policy(&self) -> SchedulingPolicy {
+        self.policy
+    }
+    
+    /// Replace the PRB quotas of the slices
+    ///
+    /// Each quota needs dedicated <= min <= max <= 100 percent, and the
+    /// minimum ratios of all slices together cannot exceed the carrier.
+    pub fn set_slice_quotas(&mut self, quotas: Vec<SlicePrbQuota>) -> Result<(), LayerError> {
+        for quota in &quotas {
+            if quota.dedicated_ratio > quota.min_ratio || quota.min_ratio > quota.max_ratio || quota.max_ratio > 100 {
+                return Err(LayerError::InvalidConfiguration(format!(
+                    "Inconsistent PRB ratios {}/{}/{} for slice {:?}",
+                    quota.dedicated_ratio, quota.min_ratio, quota.max_ratio, quota.s_nssai)));
+            }
+            if quotas.iter().filter(|other| other.s_nssai == quota.s_nssai).count() > 1 {
+                return Err(LayerError::InvalidConfiguration(format!("Several quotas for slice {:?}", quota.s_nssai)));
+            }
+        }
+        let min_total: u32 = quotas.iter().map(|quota| quota.min_ratio as u32).sum();
+        if min_total > 100 {
+            return Err(LayerError::InvalidConfiguration(format!("Minimum PRB ratios add up to {}%", min_total)));
+        }
+        info!("Scheduler: PRB quotas for {} slices", quotas.len());
+        self.slice_quotas = quotas;
+        Ok(())
+    }
+    
+    /// PRB quotas of the slices
+    pub fn slice_quotas(&self) -> &[SlicePrbQuota] {
+        &self.slice_quotas
+    }
+    
+    /// PRBs of a slot a slice is guaranteed and may use at most
+    ///
+    /// A slice without quota gets no guarantee and may use whatever the
+    /// other slices do not have reserved.
+    pub fn slice_prb_limits(&self, s_nssai: &SNssai) -> (u32, u32) {
+        let prbs = self.metrics.prbs_per_slot;
+        match self.slice_quotas.iter().find(|quota| &quota.s_nssai == s_nssai) {
+            Some(quota) => (prbs * quota.min_ratio as u32 / 100, prbs * quota.max_ratio as u32 / 100),
+            None => {
+                let dedicated: u32 = self.slice_quotas.iter().map(|quota| quota.dedicated_ratio as u32).sum();
+                (0, prbs * (100 - dedicated.min(100)) / 100)
+            }
+        }
+    }
+    
+    /// Highest PDSCH modulation the UE may be scheduled with
+    pub fn max_dl_modulation(&self, rnti: Rnti) -> ModulationScheme {
+        if self.ue_capabilities(rnti).supports_256qam_dl {
+            ModulationScheme::Qam256
+        } else {
+            ModulationScheme::Qam64
+        }
+    }
+    
+    /// Highest PUSCH modulation the UE may be scheduled with
+    pub fn max_ul_modulation(&self, rnti: Rnti) -> ModulationScheme {
+        if self.ue_capabilities(rnti).supports_256qam_ul {
+            ModulationScheme::Qam256
+        } else {
+            ModulationScheme::Qam64
+        }
+    }
+    
+    /// MCS index and entry of the UE PDSCH, from the MCS table of its highest
+    /// modulation and the spectral efficiency of its last CQI
+    pub fn dl_mcs(&self, rnti: Rnti) -> (u8, McsEntry) {
+        let max_modulation = self.max_dl_modulation(rnti);
+        let efficiency = self.ue_cqi.get(&rnti)
+            .map_or(Some(INITIAL_SPECTRAL_EFFICIENCY), |&cqi| cqi_spectral_efficiency(max_modulation, cqi))
+            .unwrap_or(0);
+        let table = mcs_table(max_modulation);
+        let mcs_index = select_mcs(table, efficiency);
+        (mcs_index, table[mcs_index as usize])
+    }
+    
+    /// MCS index and entry of the UE PUSCH, from the MCS table of its highest
+    /// modulation
+    ///
+    /// The PUSCH is not measured, the UE is granted at the initial spectral
+    /// efficiency.
+    pub fn ul_mcs(&self, rnti: Rnti) -> (u8, McsEntry) {
+        let table = mcs_table(self.max_ul_modulation(rnti));
+        let mcs_index = select_mcs(table, INITIAL_SPECTRAL_EFFICIENCY);
+        (mcs_index, table[mcs_index as usize])
+    }
+    
     /// Get Type0-PDCCH CSS monitoring slots for SIB1
     /// Based on TS 38.213 Table 13-11
     pub fn get_sib1_monitoring_slots(&self) -> Vec<u32> {
@@ -184,16 +651,13 @@ impl MacScheduler {
             slot,
             ssb_info: None,
             sib1_info: None,
+            paging_info: None,
+            dl_grants: Vec::new(),
+            ul_grants: Vec::new(),
         };
         
         // Calculate timing based on SCS
-        let slots_per_frame = match self.scs {
-            SubcarrierSpacing::Scs15 => 10,
-            SubcarrierSpacing::Scs30 => 20,
-            SubcarrierSpacing::Scs60 => 40,
-            SubcarrierSpacing::Scs120 => 80,
-            SubcarrierSpacing::Scs240 => 160,
-        };
+        let slots_per_frame = self.slots_per_frame();
         
         // Check if this slot should have SSB
         if self.is_ssb_slot(frame, slot, slots_per_frame) {
@@ -258,6 +722,460 @@ impl MacScheduler {
         schedule
     }
     
+    /// Queue a paging record for the UE's next paging occasion
+    pub fn queue_paging(&mut self, request: PagingRequest) -> PagingOccasion {
+        let occasion = self.pcch_config.paging_occasion(request.ue_id, request.paging_cycle);
+        // A repeated request replaces the one still waiting
+        self.pending_paging.retain(|pending| pending.request.identity != request.identity);
+        self.pending_paging.push(PendingPaging { occasion, request });
+        debug!("Queued paging for UE_ID {} in PF {} (T={}), i_s={}",
+               request.ue_id, occasion.paging_frame, occasion.paging_cycle, occasion.i_s);
+        occasion
+    }
+    
+    /// Number of paging records waiting for their paging occasion
+    pub fn pending_paging_count(&self) -> usize {
+        self.pending_paging.len()
+    }
+    
+    /// Build the Paging transmission of a slot, removing the records sent
+    ///
+    /// The i_s-th paging occasion of a paging frame starts at slot
+    /// i_s * slots_per_frame / Ns. Records beyond maxNrofPageRec stay queued
+    /// for the next cycle, the highest paging priority going first. The slot
+    /// schedule is queried once per symbol, so the same slot returns the same
+    /// transmission.
+    pub fn take_paging(&mut self, frame: u32, slot: u8) -> Option<PagingScheduleInfo> {
+        if let Some((current_frame, current_slot, paging)) = &self.current_paging {
+            if (*current_frame, *current_slot) == (frame, slot) {
+                return Some(paging.clone());
+            }
+        }
+        self.current_paging = None;
+        
+        let sfn = frame % 1024;
+        let slots_per_frame = self.slots_per_frame();
+        let ns = self.pcch_config.ns.max(1) as u32;
+        let (mut due, waiting): (Vec<_>, Vec<_>) = self.pending_paging.drain(..).partition(|pending| {
+            pending.occasion.is_paging_frame(sfn)
+                && slot as u32 == pending.occasion.i_s as u32 * slots_per_frame / ns
+        });
+        self.pending_paging = waiting;
+        if due.is_empty() {
+            return None;
+        }
+        
+        due.sort_by_key(|pending| pending.request.priority.unwrap_or(u8::MAX));
+        if due.len() > MAX_PAGING_RECORDS {
+            self.pending_paging.extend(due.split_off(MAX_PAGING_RECORDS));
+        }
+        let payload = Paging {
+            paging_records: due.iter().map(|pending| pending.request.identity).collect(),
+        }.encode();
+        
+        // PDSCH next to the SIB1 allocation within CORESET#0
+        let coreset_start = self.coreset0_config.rb_offset;
+        let prb_offset = 12;
+        let prb_length = 12;
+        let prb_start = coreset_start + prb_offset;
+        info!("Scheduled Paging with {} records in frame={}, slot={}", due.len(), frame, slot);
+        
+        let paging = PagingScheduleInfo {
+            rnti: P_RNTI,
+            pdsch_time_alloc: PdschTimeAlloc {
+                start_symbol: self.coreset0_config.num_symbols as u8,  // After CORESET#0
+                num_symbols: 4,
+            },
+            coreset: common::CorsetConfig {
+                start_symbol: 0,
+                duration: self.coreset0_config.num_symbols as u8,
+                frequency_domain_resources: (coreset_start..coreset_start + self.coreset0_config.num_rbs)
+                    .map(|rb| rb as u16)
+                    .collect(),
+            },
+            frequency_domain_assignment: resource_indication_value(self.coreset0_config.num_rbs, prb_offset, prb_length),
+            time_domain_assignment: 0,
+            mcs_index: 2,  // Same conservative MCS as SIB1
+            aggregation_level: 4,
+            cce_index: 4,  // CCEs 0-3 carry the SIB1 PDCCH
+            tbs_bytes: payload.len(),
+            modulation: common::ModulationScheme::Qpsk,
+            prb_allocation: (prb_start..prb_start + prb_length)
+                .map(|rb| rb as u16)
+                .collect(),
+            payload,
+        };
+        self.current_paging = Some((frame, slot, paging.clone()));
+        Some(paging)
+    }
+    
+    /// Build the PDSCH transmissions of the UEs in a slot, taking their data
+    /// off the queues
+    ///
+    /// Until a dedicated BWP is configured the UEs are served in the initial
+    /// BWP, on the RBs of CORESET#0 that SIB1 and paging leave free, with
+    /// their PDCCH on the CCEs of CORESET#0 left free. The PDSCH is not rate
+    /// matched around the SSB, slots with an SSB carry none. The slot schedule
+    /// is queried once per symbol, so the same slot returns the same
+    /// transmissions.
+    pub fn take_dl_grants(&mut self, common: &SlotSchedule) -> Vec<UeDlGrant> {
+        self.schedule_ues(common).0
+    }
+    
+    /// Build the PUSCH grants of the UEs sent in a slot, out of their
+    /// buffer status reports
+    ///
+    /// The initial UL BWP spans the carrier, the DCIs share the CCEs of
+    /// CORESET#0 with the downlink. The same slot returns the same grants.
+    pub fn take_ul_grants(&mut self, common: &SlotSchedule) -> Vec<UeUlGrant> {
+        self.schedule_ues(common).1
+    }
+    
+    /// Schedule the UEs of a slot once, downlink first
+    fn schedule_ues(&mut self, common: &SlotSchedule) -> (Vec<UeDlGrant>, Vec<UeUlGrant>) {
+        if let Some((frame, slot, dl_grants, ul_grants)) = &self.current_ue_grants {
+            if (*frame, *slot) == (common.frame, common.slot) {
+                return (dl_grants.clone(), ul_grants.clone());
+            }
+        }
+        let rotation = self.next_ue;
+        self.next_ue = self.next_ue.wrapping_add(1);
+        let mut free_cces = self.free_ue_cces(common);
+        let dl_grants = if common.ssb_info.is_some() {
+            Vec::new()
+        } else {
+            self.schedule_dl(common, &mut free_cces, rotation)
+        };
+        let ul_grants = self.schedule_ul(common, &mut free_cces, rotation);
+        average_throughput(&mut self.dl_throughput, dl_grants.iter().map(|grant| (grant.rnti, grant.tbs_bytes)));
+        average_throughput(&mut self.ul_throughput, ul_grants.iter().map(|grant| (grant.rnti, grant.tbs_bytes)));
+        self.current_ue_grants = Some((common.frame, common.slot, dl_grants.clone(), ul_grants.clone()));
+        (dl_grants, ul_grants)
+    }
+    
+    /// Order the UEs to serve by the scheduling policy
+    ///
+    /// The UEs are rotated every slot, which is the order of round robin and
+    /// breaks the ties of the other policies. UEs of slices with a minimum
+    /// PRB ratio come first, so that their share is served before the rest.
+    fn order_ues(
+        &self,
+        mut ues: Vec<(Rnti, Option<SNssai>)>,
+        rotation: usize,
+        spectral_efficiency: impl Fn(Rnti) -> u32,
+        throughput: &HashMap<Rnti, f64>,
+    ) -> Vec<(Rnti, Option<SNssai>)> {
+        ues.sort_by_key(|(rnti, _)| rnti.0);
+        if !ues.is_empty() {
+            let first = rotation % ues.len();
+            ues.rotate_left(first);
+        }
+        match self.policy {
+            SchedulingPolicy::RoundRobin => {}
+            SchedulingPolicy::MaxCqi => ues.sort_by_key(|(rnti, _)| std::cmp::Reverse(spectral_efficiency(*rnti))),
+            SchedulingPolicy::ProportionalFair => {
+                let metric = |rnti: Rnti| {
+                    spectral_efficiency(rnti) as f64 / throughput.get(&rnti).copied().unwrap_or(0.0).max(1.0)
+                };
+                ues.sort_by(|(a, _), (b, _)| metric(*b).total_cmp(&metric(*a)));
+            }
+        }
+        let guaranteed = |s_nssai: &Option<SNssai>| {
+            s_nssai.as_ref().is_some_and(|s_nssai| {
+                self.slice_quotas.iter().any(|quota| &quota.s_nssai == s_nssai && quota.min_ratio > 0)
+            })
+        };
+        ues.sort_by_key(|(_, s_nssai)| !guaranteed(s_nssai));
+        ues
+    }
+    
+    /// Slice of a DRB logical channel of a UE, None for SRBs and DRBs of an unknown slice
+    fn lcid_slice(&self, rnti: Rnti, lcid: u8) -> Option<&SNssai> {
+        self.drbs.get(&rnti)?.iter().find(|drb| drb.lcid == lcid)?.s_nssai.as_ref()
+    }
+    
+    /// Slice a UE is served in on the downlink this slot: the one of its
+    /// lowest DRB logical channel with data
+    fn dl_slice(&self, rnti: Rnti) -> Option<SNssai> {
+        let queues = self.dl_queues.get(&rnti)?;
+        queues.iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .find_map(|(&lcid, _)| self.lcid_slice(rnti, lcid))
+            .cloned()
+    }
+    
+    /// Slice a UE is served in on the uplink: the one of its first DRB
+    fn ul_slice(&self, rnti: Rnti) -> Option<SNssai> {
+        self.drbs.get(&rnti)?.iter().find_map(|drb| drb.s_nssai.clone())
+    }
+    
+    /// CCEs of CORESET#0 left free by SIB1 and paging, at the UE aggregation level
+    fn free_ue_cces(&self, common: &SlotSchedule) -> VecDeque<u16> {
+        let common_cces: Vec<(u16, u16)> = common.sib1_info.iter()
+            .map(|sib1| (sib1.cce_index, sib1.aggregation_level as u16))
+            .chain(common.paging_info.iter().map(|paging| (paging.cce_index, paging.aggregation_level as u16)))
+            .collect();
+        let num_cces = (self.coreset0_config.num_rbs * self.coreset0_config.num_symbols / 6) as u16;
+        (0..num_cces).step_by(UE_AGGREGATION_LEVEL as usize)
+            .filter(|&cce| cce + UE_AGGREGATION_LEVEL as u16 <= num_cces)
+            .filter(|&cce| common_cces.iter().all(|&(start, len)| cce + (UE_AGGREGATION_LEVEL as u16) <= start || start + len <= cce))
+            .collect()
+    }
+    
+    /// CORESET#0 carrying the UE PDCCH
+    fn ue_coreset(&self) -> common::CorsetConfig {
+        let coreset0 = &self.coreset0_config;
+        common::CorsetConfig {
+            start_symbol: 0,
+            duration: coreset0.num_symbols as u8,
+            frequency_domain_resources: (coreset0.rb_offset..coreset0.rb_offset + coreset0.num_rbs)
+                .map(|rb| rb as u16)
+                .collect(),
+        }
+    }
+    
+    /// Allocate the free resources of a slot to the UEs with downlink data
+    ///
+    /// A UE is served in one slice per slot, with its SRB data and the data
+    /// of the DRBs of that slice.
+    fn schedule_dl(&mut self, common: &SlotSchedule, free_cces: &mut VecDeque<u16>, rotation: usize) -> Vec<UeDlGrant> {
+        let ues: Vec<(Rnti, Option<SNssai>)> = self.dl_queues.iter()
+            .filter(|(_, queues)| queues.values().any(|queue| !queue.is_empty()))
+            .map(|(rnti, _)| (*rnti, self.dl_slice(*rnti)))
+            .collect();
+        if ues.is_empty() {
+            return Vec::new();
+        }
+        let ues = self.order_ues(ues, rotation, |rnti| self.dl_mcs(rnti).1.spectral_efficiency(), &self.dl_throughput);
+        
+        let coreset0 = self.coreset0_config.clone();
+        let mut free_rbs = vec![true; coreset0.num_rbs as usize];
+        let common_prbs = common.sib1_info.iter().flat_map(|sib1| sib1.prb_allocation.iter())
+            .chain(common.paging_info.iter().flat_map(|paging| paging.prb_allocation.iter()));
+        for &prb in common_prbs {
+            if let Some(free) = (prb as u32).checked_sub(coreset0.rb_offset).and_then(|rb| free_rbs.get_mut(rb as usize)) {
+                *free = false;
+            }
+        }
+        let mut budget = SliceBudget::new(self.slice_quotas.clone(), coreset0.num_rbs, ues.iter().filter_map(|(_, s_nssai)| s_nssai.clone()));
+        
+        let pdsch_time_alloc = self.ue_pdsch_time_alloc();
+        let coreset = self.ue_coreset();
+        let mut grants = Vec::new();
+        for (rnti, s_nssai) in ues {
+            let (rb_start, max_run) = largest_free_run(&free_rbs);
+            let Some(&cce_index) = free_cces.front() else { break };
+            let free_prbs = free_rbs.iter().filter(|&&free| free).count() as u32;
+            let max_prbs = max_run.min(budget.available(s_nssai.as_ref(), free_prbs));
+            if max_prbs == 0 {
+                continue;
+            }
+            let (mcs_index, mcs) = self.dl_mcs(rnti);
+            let tbs_bytes = |num_prbs: u32| {
+                transport_block_size(mcs, num_prbs, pdsch_time_alloc.num_symbols, PDSCH_DMRS_RES_PER_PRB, 1) as usize / 8
+            };
+            let in_slice = |scheduler: &Self, lcid: u8| {
+                scheduler.drbs.get(&rnti)
+                    .and_then(|drbs| drbs.iter().find(|drb| drb.lcid == lcid))
+                    .is_none_or(|drb| drb.s_nssai == s_nssai)
+            };
+            let pending: usize = self.dl_queues.get(&rnti).map_or(0, |queues| {
+                queues.iter()
+                    .filter(|(&lcid, _)| in_slice(self, lcid))
+                    .flat_map(|(_, queue)| queue.iter())
+                    .map(|pdu| subpdu_len(pdu.len()))
+                    .sum()
+            });
+            let num_prbs = (1..=max_prbs).find(|&num_prbs| tbs_bytes(num_prbs) >= pending).unwrap_or(max_prbs);
+            let tbs = tbs_bytes(num_prbs);
+            let lcids: Vec<u8> = self.dl_queues.get(&rnti).map_or(Vec::new(), |queues| {
+                queues.keys().copied().filter(|&lcid| in_slice(self, lcid)).collect()
+            });
+            let sdus = self.pop_dl_sdus(rnti, &lcids, tbs, tbs_bytes(coreset0.num_rbs));
+            if sdus.is_empty() {
+                continue;
+            }
+            let payload = match encode_dl_sch(&sdus, tbs) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Dropping DL-SCH data of RNTI {}: {}", rnti.0, e);
+                    continue;
+                }
+            };
+            free_cces.pop_front();
+            free_rbs[rb_start as usize..(rb_start + num_prbs) as usize].fill(false);
+            budget.use_prbs(s_nssai.as_ref(), num_prbs);
+            let (harq_process, ndi) = next_harq_process(&mut self.dl_harq, rnti, NUM_DL_HARQ_PROCESSES);
+            self.record_ue_allocation(rnti, num_prbs, 0);
+            debug!("Scheduled {} bytes for RNTI {} on PRBs {}+{} with MCS {} in frame={}, slot={}",
+                   tbs, rnti.0, rb_start, num_prbs, mcs_index, common.frame, common.slot);
+            
+            grants.push(UeDlGrant {
+                rnti,
+                pdsch_time_alloc: pdsch_time_alloc.clone(),
+                coreset: coreset.clone(),
+                frequency_domain_assignment: resource_indication_value(coreset0.num_rbs, rb_start, num_prbs),
+                time_domain_assignment: 0,
+                mcs_index,
+                mcs_table: mcs_table_index(self.max_dl_modulation(rnti)),
+                aggregation_level: UE_AGGREGATION_LEVEL,
+                cce_index,
+                harq_process,
+                ndi,
+                tbs_bytes: tbs,
+                modulation: mcs.modulation(),
+                prb_allocation: (coreset0.rb_offset + rb_start..coreset0.rb_offset + rb_start + num_prbs)
+                    .map(|rb| rb as u16)
+                    .collect(),
+                payload,
+            });
+        }
+        grants
+    }
+    
+    /// Grant the uplink PRBs of the carrier to the UEs with buffered data
+    fn schedule_ul(&mut self, common: &SlotSchedule, free_cces: &mut VecDeque<u16>, rotation: usize) -> Vec<UeUlGrant> {
+        let ues: Vec<(Rnti, Option<SNssai>)> = self.ul_buffers.iter()
+            .filter(|(_, &bytes)| bytes > 0)
+            .map(|(rnti, _)| (*rnti, self.ul_slice(*rnti)))
+            .collect();
+        if ues.is_empty() {
+            return Vec::new();
+        }
+        let ues = self.order_ues(ues, rotation, |rnti| self.ul_mcs(rnti).1.spectral_efficiency(), &self.ul_throughput);
+        
+        let num_rbs = self.metrics.prbs_per_slot;
+        let mut budget = SliceBudget::new(self.slice_quotas.clone(), num_rbs, ues.iter().filter_map(|(_, s_nssai)| s_nssai.clone()));
+        let mut rb_start = 0;
+        let pusch_time_alloc = self.ue_pusch_time_alloc();
+        let coreset = self.ue_coreset();
+        let mut grants = Vec::new();
+        for (rnti, s_nssai) in ues {
+            let max_prbs = budget.available(s_nssai.as_ref(), num_rbs - rb_start);
+            if max_prbs == 0 {
+                continue;
+            }
+            let Some(cce_index) = free_cces.pop_front() else { break };
+            let (mcs_index, mcs) = self.ul_mcs(rnti);
+            let tbs_bytes = |num_prbs: u32| {
+                transport_block_size(mcs, num_prbs, pusch_time_alloc.num_symbols, PUSCH_DMRS_RES_PER_PRB, 1) as usize / 8
+            };
+            let pending = self.ul_buffer_bytes(rnti) as usize;
+            let num_prbs = (1..=max_prbs).find(|&num_prbs| tbs_bytes(num_prbs) >= pending).unwrap_or(max_prbs);
+            let tbs = tbs_bytes(num_prbs);
+            if let Some(bytes) = self.ul_buffers.get_mut(&rnti) {
+                *bytes = bytes.saturating_sub(tbs as u32);
+            }
+            budget.use_prbs(s_nssai.as_ref(), num_prbs);
+            let (harq_process, ndi) = next_harq_process(&mut self.ul_harq, rnti, NUM_UL_HARQ_PROCESSES);
+            self.record_ue_allocation(rnti, 0, num_prbs);
+            debug!("Granted {} bytes to RNTI {} on UL PRBs {}+{} with MCS {} in frame={}, slot={}",
+                   tbs, rnti.0, rb_start, num_prbs, mcs_index, common.frame, common.slot);
+            
+            grants.push(UeUlGrant {
+                rnti,
+                pusch_time_alloc: pusch_time_alloc.clone(),
+                coreset: coreset.clone(),
+                frequency_domain_assignment: resource_indication_value(num_rbs, rb_start, num_prbs),
+                time_domain_assignment: 0,
+                mcs_index,
+                mcs_table: mcs_table_index(self.max_ul_modulation(rnti)),
+                aggregation_level: UE_AGGREGATION_LEVEL,
+                cce_index,
+                harq_process,
+                ndi,
+                tbs_bytes: tbs,
+                modulation: mcs.modulation(),
+                prb_allocation: (rb_start..rb_start + num_prbs).map(|rb| rb as u16).collect(),
+            });
+            rb_start += num_prbs;
+        }
+        grants
+    }
+    
+    /// Take the RLC PDUs of a UE's logical channels fitting in a transport
+    /// block, lowest LCID first; PDUs too large for any transport block are
+    /// dropped
+    fn pop_dl_sdus(&mut self, rnti: Rnti, lcids: &[u8], tbs_bytes: usize, max_tbs_bytes: usize) -> Vec<MacSdu> {
+        let mut sdus = Vec::new();
+        let mut remaining = tbs_bytes;
+        let Some(queues) = self.dl_queues.get_mut(&rnti) else { return sdus };
+        for (&lcid, queue) in queues.iter_mut().filter(|(lcid, _)| lcids.contains(lcid)) {
+            while let Some(pdu) = queue.front() {
+                let len = subpdu_len(pdu.len());
+                if len > max_tbs_bytes {
+                    warn!("Dropping RLC PDU of {} bytes on LCID {} of RNTI {}, larger than a transport block",
+                          pdu.len(), lcid, rnti.0);
+                    queue.pop_front();
+                    continue;
+                }
+                if len > remaining {
+                    break;
+                }
+                remaining -= len;
+                sdus.extend(queue.pop_front().map(|pdu| MacSdu::new(lcid, pdu)));
+            }
+        }
+        sdus
+    }
+    
+    /// PDSCH time domain allocation of the UEs, row 1 of the default table A
+    /// (TS 38.214 Table 5.1.2.1.1-2): after CORESET#0 up to the end of the slot
+    fn ue_pdsch_time_alloc(&self) -> PdschTimeAlloc {
+        let start_symbol = if self.coreset0_config.num_symbols <= 2 { 2 } else { 3 };
+        PdschTimeAlloc { start_symbol, num_symbols: 14 - start_symbol }
+    }
+    
+    /// PUSCH time domain allocation of the UEs, row 1 of the default table A
+    /// (TS 38.214 Table 6.1.2.1.1-2): the whole slot, K2 = j
+    fn ue_pusch_time_alloc(&self) -> PuschTimeAlloc {
+        let k2 = match self.scs {
+            SubcarrierSpacing::Scs15 | SubcarrierSpacing::Scs30 => 1,
+            SubcarrierSpacing::Scs60 => 2,
+            SubcarrierSpacing::Scs120 | SubcarrierSpacing::Scs240 => 3,
+        };
+        PuschTimeAlloc { k2, start_symbol: 0, num_symbols: 14 }
+    }
+    
+    /// Count the PRBs of a slot's common channels
+    ///
+    /// The slot schedule is queried once per symbol, a slot is counted once.
+    pub fn record_slot(&mut self, schedule: &SlotSchedule) {
+        if self.last_counted_slot == Some((schedule.frame, schedule.slot)) {
+            return;
+        }
+        self.last_counted_slot = Some((schedule.frame, schedule.slot));
+        self.metrics.slots += 1;
+        let sib1_prbs = schedule.sib1_info.as_ref().map_or(0, |sib1| sib1.prb_allocation.len());
+        let paging_prbs = schedule.paging_info.as_ref().map_or(0, |paging| paging.prb_allocation.len());
+        self.metrics.dl_prbs_used += (sib1_prbs + paging_prbs) as u64;
+    }
+    
+    /// Count the PRBs allocated to a UE in a slot
+    pub fn record_ue_allocation(&mut self, rnti: Rnti, dl_prbs: u32, ul_prbs: u32) {
+        let usage = self.metrics.ues.entry(rnti).or_default();
+        usage.dl_prbs += dl_prbs as u64;
+        usage.ul_prbs += ul_prbs as u64;
+        self.metrics.dl_prbs_used += dl_prbs as u64;
+        self.metrics.ul_prbs_used += ul_prbs as u64;
+    }
+    
+    /// PRB usage counted so far
+    pub fn metrics(&self) -> SchedulerMetrics {
+        self.metrics.clone()
+    }
+    
+    /// Slots per radio frame for the subcarrier spacing
+    fn slots_per_frame(&self) -> u32 {
+        match self.scs {
+            SubcarrierSpacing::Scs15 => 10,
+            SubcarrierSpacing::Scs30 => 20,
+            SubcarrierSpacing::Scs60 => 40,
+            SubcarrierSpacing::Scs120 => 80,
+            SubcarrierSpacing::Scs240 => 160,
+        }
+    }
+    
     /// Check if this slot should contain SSB
     fn is_ssb_slot(&self, frame: u32, slot: u8, _slots_per_frame: u32) -> bool {
         // SSB every 20ms (2 frames)
@@ -298,9 +1216,107 @@ impl MacScheduler {
     }
 }
 
+/// HARQ process of a new transmission of a UE, with its toggled NDI
+fn next_harq_process(harq: &mut HashMap<Rnti, (u8, u16)>, rnti: Rnti, num_processes: u8) -> (u8, bool) {
+    let (next, ndi_bits) = harq.entry(rnti).or_default();
+    let harq_process = *next;
+    *ndi_bits ^= 1 << harq_process;
+    *next = (harq_process + 1) % num_processes;
+    (harq_process, *ndi_bits & (1 << harq_process) != 0)
+}
+
+/// Fold the bytes served to the UEs in a slot into their average throughput
+fn average_throughput(throughput: &mut HashMap<Rnti, f64>, served: impl Iterator<Item = (Rnti, usize)>) {
+    for average in throughput.values_mut() {
+        *average -= *average / PF_WINDOW_SLOTS;
+    }
+    for (rnti, bytes) in served {
+        *throughput.entry(rnti).or_default() += bytes as f64 / PF_WINDOW_SLOTS;
+    }
+}
+
+/// PRBs of a slot used by the slices, against their quotas
+///
+/// The minimum of a slice with data and the dedicated PRBs of a slice
+/// without are kept from the other slices until the slice has used them.
+struct SliceBudget {
+    /// PRB quotas of the slices
+    quotas: Vec<SlicePrbQuota>,
+    /// PRBs the ratios apply to
+    prbs: u32,
+    /// Slices with data in the slot
+    with_data: Vec<SNssai>,
+    /// PRBs used by each slice, None for the UEs with SRB data only
+    used: HashMap<Option<SNssai>, u32>,
+}
+
+impl SliceBudget {
+    fn new(quotas: Vec<SlicePrbQuota>, prbs: u32, with_data: impl Iterator<Item = SNssai>) -> Self {
+        Self { quotas, prbs, with_data: with_data.collect(), used: HashMap::new() }
+    }
+    
+    fn ratio(&self, ratio: u8) -> u32 {
+        self.prbs * ratio as u32 / 100
+    }
+    
+    fn used(&self, s_nssai: Option<&SNssai>) -> u32 {
+        self.used.get(&s_nssai.cloned()).copied().unwrap_or(0)
+    }
+    
+    /// PRBs a slice may still use out of the free ones
+    fn available(&self, s_nssai: Option<&SNssai>, free_prbs: u32) -> u32 {
+        let quota = s_nssai.and_then(|s_nssai| self.quotas.iter().find(|quota| &quota.s_nssai == s_nssai));
+        let max = match (s_nssai, quota) {
+            (None, _) => self.prbs,
+            (Some(_), Some(quota)) => self.ratio(quota.max_ratio),
+            (Some(_), None) => {
+                let dedicated: u32 = self.quotas.iter().map(|quota| quota.dedicated_ratio as u32).sum();
+                self.prbs * (100 - dedicated.min(100)) / 100
+            }
+        };
+        let reserved: u32 = self.quotas.iter()
+            .filter(|quota| Some(&quota.s_nssai) != s_nssai)
+            .map(|quota| {
+                let ratio = if self.with_data.contains(&quota.s_nssai) { quota.min_ratio } else { quota.dedicated_ratio };
+                self.ratio(ratio).saturating_sub(self.used(Some(&quota.s_nssai)))
+            })
+            .sum();
+        max.saturating_sub(self.used(s_nssai)).min(free_prbs.saturating_sub(reserved))
+    }
+    
+    fn use_prbs(&mut self, s_nssai: Option<&SNssai>, prbs: u32) {
+        *self.used.entry(s_nssai.cloned()).or_default() += prbs;
+    }
+}
+
+/// Start and length of the longest run of free RBs
+fn largest_free_run(free_rbs: &[bool]) -> (u32, u32) {
+    let mut best = (0, 0);
+    let mut start = 0;
+    for (rb, &free) in free_rbs.iter().enumerate() {
+        if !free {
+            start = rb + 1;
+        } else if rb + 1 - start > best.1 {
+            best = (start, rb + 1 - start);
+        }
+    }
+    (best.0 as u32, best.1 as u32)
+}
+
+/// Resource indication value of a contiguous allocation in a bandwidth of
+/// `n_rb` resource blocks (TS 38.214 section 5.1.2.2.2)
+fn resource_indication_value(n_rb: u32, rb_start: u32, length: u32) -> u16 {
+    if length - 1 <= n_rb / 2 {
+        (n_rb * (length - 1) + rb_start) as u16
+    } else {
+        (n_rb * (n_rb - length + 1) + (n_rb - 1 - rb_start)) as u16
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rrc::PagingUeIdentity;
     
     #[test]
     fn test_coreset0_config() {
@@ -367,4 +1383,350 @@ mod tests {
         assert_eq!(monitoring_slots, expected_slots,
                    "Type0-PDCCH monitoring slots should match Table 13-11");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_paging_scheduling() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        scheduler.set_pcch_config(PcchConfig {
+            default_paging_cycle: 32,
+            paging_frame_spacing: 2,
+            paging_frame_offset: 0,
+            ns: 2,
+        }).unwrap();
+        
+        // UE_ID 21: N = 16, PF = 2 * (21 mod 16) = 10, i_s = 1 in slot 5
+        let identity = PagingUeIdentity::NgSTmsi(0x0042_C000_0015);
+        let occasion = scheduler.queue_paging(PagingRequest {
+            identity,
+            ue_id: 21,
+            paging_cycle: None,
+            priority: None,
+        });
+        assert_eq!((occasion.paging_frame, occasion.i_s), (10, 1));
+        assert!(scheduler.take_paging(10, 0).is_none());
+        assert!(scheduler.take_paging(11, 5).is_none());
+        
+        let paging = scheduler.take_paging(32 + 10, 5).unwrap();
+        assert_eq!(paging.rnti, P_RNTI);
+        assert_eq!(paging.cce_index, 4);
+        assert_eq!(paging.frequency_domain_assignment, 48 * 11 + 12);
+        assert_eq!(Paging::decode(&paging.payload).unwrap().paging_records, vec![identity]);
+        assert_eq!(scheduler.pending_paging_count(), 0);
+        // Every symbol of the paging occasion sees the same transmission
+        assert_eq!(scheduler.take_paging(32 + 10, 5).unwrap().payload, paging.payload);
+        assert!(scheduler.take_paging(64 + 10, 5).is_none());
+        
+        // Records beyond maxNrofPageRec wait for the next cycle, highest priority first
+        for tmsi in 0..MAX_PAGING_RECORDS as u64 {
+            scheduler.queue_paging(PagingRequest {
+                identity: PagingUeIdentity::NgSTmsi(tmsi << 10),
+                ue_id: 0,
+                paging_cycle: None,
+                priority: Some(8),
+            });
+        }
+        let urgent = PagingUeIdentity::NgSTmsi(0xFFFF << 10);
+        scheduler.queue_paging(PagingRequest { identity: urgent, ue_id: 0, paging_cycle: None, priority: Some(1) });
+        let paging = Paging::decode(&scheduler.take_paging(0, 0).unwrap().payload).unwrap();
+        assert_eq!(paging.paging_records.len(), MAX_PAGING_RECORDS);
+        assert_eq!(paging.paging_records[0], urgent);
+        assert_eq!(scheduler.pending_paging_count(), 1);
+        assert!(scheduler.take_paging(32, 0).is_some());
+    }
+    
+    #[test]
+    fn test_ue_capability_modulation_limit() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        
+        let rnti = Rnti::new(0x4601);
+        // Unknown UE is limited to 64QAM
+        assert_eq!(scheduler.max_dl_modulation(rnti), ModulationScheme::Qam64);
+        
+        scheduler.set_ue_capabilities(rnti, UeSchedulingCapabilities {
+            supports_256qam_dl: true,
+            supports_256qam_ul: false,
+            max_mimo_layers_dl: 2,
+            max_mimo_layers_ul: 1,
+        });
+        assert_eq!(scheduler.max_dl_modulation(rnti), ModulationScheme::Qam256);
+        assert_eq!(scheduler.max_ul_modulation(rnti), ModulationScheme::Qam64);
+        
+        scheduler.remove_ue(rnti);
+        assert_eq!(scheduler.max_dl_modulation(rnti), ModulationScheme::Qam64);
+    }
+    
+    #[test]
+    fn test_scheduler_metrics() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        
+        // Frame 0 slot 0 carries SIB1, every symbol queries the same slot
+        for _ in 0..14 {
+            let schedule = scheduler.get_slot_schedule(0, 0);
+            scheduler.record_slot(&schedule);
+        }
+        let schedule = scheduler.get_slot_schedule(0, 1);
+        scheduler.record_slot(&schedule);
+        
+        let rnti = Rnti::new(0x4601);
+        scheduler.record_ue_allocation(rnti, 20, 8);
+        let metrics = scheduler.metrics();
+        assert_eq!(metrics.slots, 2);
+        assert_eq!(metrics.prbs_per_slot, 111);
+        assert_eq!(metrics.dl_prbs_used, 12 + 20);
+        assert_eq!(metrics.ul_prbs_used, 8);
+        assert_eq!(metrics.ues[&rnti], UeResourceUsage { dl_prbs: 20, ul_prbs: 8 });
+        
+        scheduler.remove_ue(rnti);
+        assert!(scheduler.metrics().ues.is_empty());
+    }
+    
+    #[test]
+    fn test_slice_quotas() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw10,
+            6,
+        ).unwrap();
+        assert_eq!(scheduler.scheduling_policy(), SchedulingPolicy::RoundRobin);
+        scheduler.set_scheduling_policy(SchedulingPolicy::ProportionalFair);
+        assert_eq!(scheduler.scheduling_policy(), SchedulingPolicy::ProportionalFair);
+        
+        let embb = SNssai { sst: 1, sd: None };
+        let urllc = SNssai { sst: 2, sd: Some(0x000001) };
+        let quota = |s_nssai: &SNssai, dedicated_ratio, min_ratio, max_ratio| SlicePrbQuota {
+            s_nssai: s_nssai.clone(), min_ratio, max_ratio, dedicated_ratio,
+        };
+        assert!(scheduler.set_slice_quotas(vec![quota(&embb, 30, 20, 100)]).is_err());
+        assert!(scheduler.set_slice_quotas(vec![quota(&embb, 0, 60, 100), quota(&urllc, 0, 50, 100)]).is_err());
+        assert!(scheduler.set_slice_quotas(vec![quota(&embb, 0, 10, 50), quota(&embb, 0, 10, 50)]).is_err());
+        
+        scheduler.set_slice_quotas(vec![quota(&embb, 0, 50, 100), quota(&urllc, 20, 20, 40)]).unwrap();
+        assert_eq!(scheduler.slice_quotas().len(), 2);
+        assert_eq!(scheduler.metrics().prbs_per_slot, 55);
+        assert_eq!(scheduler.slice_prb_limits(&embb), (27, 55));
+        assert_eq!(scheduler.slice_prb_limits(&urllc), (11, 22));
+        assert_eq!(scheduler.slice_prb_limits(&SNssai { sst: 3, sd: None }), (0, 44));
+    }
+    
+    #[test]
+    fn test_ue_dl_scheduling() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        let ue1 = Rnti::new(0x4601);
+        let ue2 = Rnti::new(0x4602);
+        scheduler.queue_dl_data(ue1, 4, Bytes::from(vec![0x11; 100]));
+        scheduler.queue_dl_data(ue1, 1, Bytes::from_static(&[0x00, 0x01]));
+        scheduler.queue_dl_data(ue2, 5, Bytes::from(vec![0x22; 40]));
+        assert_eq!(scheduler.dl_buffer_bytes(ue1), 102 + 4);
+        
+        // No UE data next to the SSB
+        let schedule = scheduler.get_slot_schedule(0, 0);
+        assert!(scheduler.take_dl_grants(&schedule).is_empty());
+        
+        let schedule = scheduler.get_slot_schedule(1, 3);
+        let grants = scheduler.take_dl_grants(&schedule);
+        assert_eq!(grants.len(), 2);
+        let grant1 = grants.iter().find(|grant| grant.rnti == ue1).unwrap();
+        let grant2 = grants.iter().find(|grant| grant.rnti == ue2).unwrap();
+        assert_ne!(grant1.cce_index, grant2.cce_index);
+        assert!(grant1.prb_allocation.iter().all(|prb| !grant2.prb_allocation.contains(prb)));
+        assert_eq!((grant1.pdsch_time_alloc.start_symbol, grant1.pdsch_time_alloc.num_symbols), (2, 12));
+        assert_eq!(grant1.payload.len(), grant1.tbs_bytes);
+        assert!(grant1.ndi);
+        
+        // SRB first, then the DRB
+        let sdus = crate::mac::pdu::decode_dl_sch(&grant1.payload).unwrap();
+        assert_eq!(sdus.iter().map(|sdu| sdu.subheader.lcid).collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!(sdus[1].data.len(), 100);
+        assert_eq!(scheduler.dl_buffer_bytes(ue1), 0);
+        
+        // Every symbol of the slot sees the same transmissions, counted once
+        assert_eq!(scheduler.take_dl_grants(&schedule).len(), 2);
+        let prbs = (grant1.prb_allocation.len() + grant2.prb_allocation.len()) as u64;
+        assert_eq!(scheduler.metrics().dl_prbs_used, prbs);
+        assert!(scheduler.take_dl_grants(&scheduler.get_slot_schedule(1, 4)).is_empty());
+        
+        // The next transmission uses the next HARQ process
+        scheduler.queue_dl_data(ue1, 4, Bytes::from_static(&[0x33]));
+        let grants = scheduler.take_dl_grants(&scheduler.get_slot_schedule(1, 5));
+        assert_eq!(grants[0].harq_process, grant1.harq_process + 1);
+        
+        // An RLC PDU no transport block can carry is dropped
+        scheduler.queue_dl_data(ue2, 4, Bytes::from(vec![0; 60000]));
+        assert!(scheduler.take_dl_grants(&scheduler.get_slot_schedule(1, 6)).is_empty());
+        assert_eq!(scheduler.dl_buffer_bytes(ue2), 0);
+        
+        scheduler.queue_dl_data(ue1, 4, Bytes::from_static(&[0x44]));
+        scheduler.remove_ue(ue1);
+        assert_eq!(scheduler.dl_buffer_bytes(ue1), 0);
+    }
+    
+    #[test]
+    fn test_ue_link_adaptation() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        let ue1 = Rnti::new(0x4601);
+        let ue2 = Rnti::new(0x4602);
+        scheduler.set_ue_capabilities(ue1, UeSchedulingCapabilities {
+            supports_256qam_dl: true,
+            supports_256qam_ul: true,
+            max_mimo_layers_dl: 1,
+            max_mimo_layers_ul: 1,
+        });
+        // Until a CQI is reported both tables start from QPSK at 602/1024
+        assert_eq!(scheduler.dl_mcs(ue1).0, 4);
+        assert_eq!(scheduler.dl_mcs(ue2).0, 8);
+        scheduler.set_ue_cqi(ue1, 15);
+        scheduler.set_ue_cqi(ue2, 15);
+        scheduler.queue_dl_data(ue1, 4, Bytes::from(vec![0x11; 500]));
+        scheduler.queue_dl_data(ue2, 4, Bytes::from(vec![0x22; 500]));
+        scheduler.report_ul_buffer(ue1, 100);
+        scheduler.report_ul_buffer(ue2, 1000);
+        
+        let schedule = scheduler.get_slot_schedule(1, 3);
+        let dl_grants = scheduler.take_dl_grants(&schedule);
+        let grant1 = dl_grants.iter().find(|grant| grant.rnti == ue1).unwrap();
+        let grant2 = dl_grants.iter().find(|grant| grant.rnti == ue2).unwrap();
+        // Table 2 for the 256QAM capable UE, table 1 for the other
+        assert_eq!((grant1.mcs_index, grant1.modulation), (27, ModulationScheme::Qam256));
+        assert_eq!((grant2.mcs_index, grant2.modulation), (28, ModulationScheme::Qam64));
+        assert!(grant1.prb_allocation.len() < grant2.prb_allocation.len());
+        
+        // Both AL4 candidates of CORESET#0 carry the DL assignments, the UL waits
+        assert!(scheduler.take_ul_grants(&schedule).is_empty());
+        let schedule = scheduler.get_slot_schedule(1, 4);
+        assert!(scheduler.take_dl_grants(&schedule).is_empty());
+        let ul_grants = scheduler.take_ul_grants(&schedule);
+        assert_eq!(ul_grants.len(), 2);
+        let ul1 = ul_grants.iter().find(|grant| grant.rnti == ue1).unwrap();
+        let ul2 = ul_grants.iter().find(|grant| grant.rnti == ue2).unwrap();
+        assert_eq!((ul1.mcs_index, ul1.modulation), (4, ModulationScheme::Qpsk));
+        assert_eq!((ul2.mcs_index, ul2.modulation), (8, ModulationScheme::Qpsk));
+        assert!(ul1.tbs_bytes >= 100 && ul2.tbs_bytes >= 1000);
+        assert_eq!((ul1.pusch_time_alloc.k2, ul1.pusch_time_alloc.num_symbols), (1, 14));
+        assert!(ul1.prb_allocation.iter().all(|prb| !ul2.prb_allocation.contains(prb)));
+        assert_ne!(ul1.cce_index, ul2.cce_index);
+        
+        // Granted bytes are taken off the reported buffers, the grants of a slot are stable
+        assert_eq!(scheduler.ul_buffer_bytes(ue1), 0);
+        assert_eq!(scheduler.take_ul_grants(&schedule).len(), 2);
+        assert!(scheduler.take_ul_grants(&scheduler.get_slot_schedule(1, 5)).is_empty());
+        assert_eq!(scheduler.metrics().ues[&ue1].ul_prbs, ul1.prb_allocation.len() as u64);
+    }
+    
+    #[test]
+    fn test_scheduling_policy_order() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        let ue1 = Rnti::new(0x4601);
+        let ue2 = Rnti::new(0x4602);
+        // More than the whole CORESET#0 bandwidth carries at the initial MCS
+        let queue = |scheduler: &mut MacScheduler, rnti| {
+            for _ in 0..3 {
+                scheduler.queue_dl_data(rnti, 4, Bytes::from(vec![0x11; 500]));
+            }
+        };
+        let served_first = |scheduler: &mut MacScheduler, slot| {
+            for rnti in [ue1, ue2] {
+                queue(scheduler, rnti);
+            }
+            let schedule = scheduler.get_slot_schedule(1, slot);
+            let grants = scheduler.take_dl_grants(&schedule);
+            scheduler.remove_ue(ue1);
+            scheduler.remove_ue(ue2);
+            grants[0].rnti
+        };
+        
+        // Round robin alternates, max CQI keeps the best channel first
+        scheduler.set_ue_cqi(ue2, 15);
+        assert_ne!(served_first(&mut scheduler, 3), served_first(&mut scheduler, 4));
+        scheduler.set_scheduling_policy(SchedulingPolicy::MaxCqi);
+        for slot in 5..8 {
+            scheduler.set_ue_cqi(ue2, 15);
+            assert_eq!(served_first(&mut scheduler, slot), ue2);
+        }
+        
+        // Proportional fair favours the UE served least so far
+        scheduler.set_scheduling_policy(SchedulingPolicy::ProportionalFair);
+        queue(&mut scheduler, ue1);
+        let schedule = scheduler.get_slot_schedule(1, 8);
+        assert_eq!(scheduler.take_dl_grants(&schedule)[0].rnti, ue1);
+        queue(&mut scheduler, ue2);
+        let schedule = scheduler.get_slot_schedule(1, 9);
+        let grants = scheduler.take_dl_grants(&schedule);
+        assert_eq!(grants[0].rnti, ue2);
+        assert!(grants.iter().all(|grant| grant.rnti != ue1));
+    }
+    
+    #[test]
+    fn test_slice_prb_allocation() {
+        let mut scheduler = MacScheduler::new(
+            CellId(1),
+            SubcarrierSpacing::Scs15,
+            Bandwidth::Bw20,
+            6,
+        ).unwrap();
+        let embb = SNssai { sst: 1, sd: None };
+        let urllc = SNssai { sst: 2, sd: Some(0x000001) };
+        let other = SNssai { sst: 3, sd: None };
+        scheduler.set_slice_quotas(vec![
+            SlicePrbQuota { s_nssai: embb.clone(), min_ratio: 30, max_ratio: 50, dedicated_ratio: 30 },
+            SlicePrbQuota { s_nssai: urllc.clone(), min_ratio: 20, max_ratio: 30, dedicated_ratio: 0 },
+        ]).unwrap();
+        let ue1 = Rnti::new(0x4601);
+        let ue2 = Rnti::new(0x4602);
+        let ue3 = Rnti::new(0x4603);
+        for (rnti, s_nssai) in [(ue1, &other), (ue2, &urllc), (ue3, &embb)] {
+            scheduler.add_drb(rnti, DrbLogicalChannel { drb_id: 1, lcid: 4, s_nssai: Some(s_nssai.clone()) });
+        }
+        
+        // The URLLC UE comes first and stops at its maximum, the UE of the
+        // slice without quota leaves the eMBB dedicated PRBs alone
+        scheduler.report_ul_buffer(ue1, 100_000);
+        scheduler.report_ul_buffer(ue2, 100_000);
+        let schedule = scheduler.get_slot_schedule(1, 4);
+        let grants = scheduler.take_ul_grants(&schedule);
+        assert_eq!(scheduler.metrics().prbs_per_slot, 111);
+        assert_eq!(grants.len(), 2);
+        assert_eq!((grants[0].rnti, grants[0].prb_allocation.len()), (ue2, 33));
+        assert_eq!((grants[1].rnti, grants[1].prb_allocation.len()), (ue1, 111 - 33 - 33));
+        
+        // With data the eMBB slice is kept its minimum and capped at its maximum
+        scheduler.report_ul_buffer(ue3, 100_000);
+        let schedule = scheduler.get_slot_schedule(1, 5);
+        let grants = scheduler.take_ul_grants(&schedule);
+        assert_eq!(grants.len(), 2);
+        let prbs = |rnti| grants.iter().find(|grant| grant.rnti == rnti).map_or(0, |grant| grant.prb_allocation.len());
+        assert_eq!(prbs(ue2), 33);
+        assert_eq!(prbs(ue3), 55);
+        assert_eq!(prbs(ue1), 0);
+    }
+}
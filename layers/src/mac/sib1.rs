@@ -2,16 +2,19 @@
 //! 
 //! Implements SIB1 message creation according to 3GPP TS 38.331
 
+use super::paging::PcchConfig;
 use crate::LayerError;
-use common::types::CellId;
 use bytes::{Bytes, BytesMut, BufMut};
 use tracing::info;
 
+/// SI-RNTI (TS 38.321 Table 7.1-1)
+pub const SI_RNTI: u16 = 0xFFFF;
+
 /// SIB1 configuration
 #[derive(Debug, Clone)]
 pub struct Sib1Config {
-    /// Cell ID
-    pub cell_id: CellId,
+    /// NR cell identity (36 bits)
+    pub nr_cell_identity: u64,
     /// PLMN identity (MCC + MNC)
     pub plmn_id: PlmnId,
     /// Tracking area code
@@ -20,6 +23,8 @@ pub struct Sib1Config {
     pub cell_selection_info: CellSelectionInfo,
     /// Frequency band list
     pub freq_band_list: Vec<u16>,
+    /// Paging configuration
+    pub pcch_config: PcchConfig,
 }
 
 /// PLMN Identity
@@ -112,9 +117,8 @@ impl Sib1Generator {
         buffer.put_u8(((self.config.tac >> 8) & 0xFF) as u8);
         buffer.put_u8((self.config.tac & 0xFF) as u8);
         
-        // Cell Identity (28 bits in 4 octets)
-        let cell_id = self.config.cell_id.0 as u32;
-        buffer.put_u32(cell_id << 4);  // Left-aligned in 32 bits
+        // Cell Identity (36 bits in 5 octets)
+        buffer.put_slice(&(self.config.nr_cell_identity << 4).to_be_bytes()[3..]);  // Left-aligned in 40 bits
         
         // Cell Barred (1 bit) - not barred
         buffer.put_u8(0x00);
@@ -155,6 +159,13 @@ impl Sib1Generator {
         // PDSCH Config Common
         buffer.put_u8(0x00);  // Default configuration
         
+        // PCCH-Config
+        let pcch = &self.config.pcch_config;
+        buffer.put_u16(pcch.default_paging_cycle);  // defaultPagingCycle in radio frames
+        buffer.put_u8(pcch.paging_frame_spacing as u8);  // nAndPagingFrameOffset: T div N
+        buffer.put_u8(pcch.paging_frame_offset as u8);  // PF_offset
+        buffer.put_u8(pcch.ns);  // ns
+        
         // Uplink Config Common (minimal for FDD)
         buffer.put_u8(0x01);  // Presence flags - RACH config present
         
@@ -199,13 +210,14 @@ impl Sib1Generator {
 }
 
 /// Create default SIB1 configuration for testing
-pub fn default_sib1_config(cell_id: CellId) -> Sib1Config {
+pub fn default_sib1_config(nr_cell_identity: u64) -> Sib1Config {
     Sib1Config {
-        cell_id,
+        nr_cell_identity,
         plmn_id: PlmnId::test_plmn(),
         tac: 1,  // Test TAC
         cell_selection_info: CellSelectionInfo::default(),
         freq_band_list: vec![3],  // Band 3 for our test
+        pcch_config: PcchConfig::default(),
     }
 }
 
@@ -226,10 +238,12 @@ mod tests {
     
     #[test]
     fn test_sib1_generation() {
-        let config = default_sib1_config(CellId(1));
+        let config = default_sib1_config(0x0_0194_4001);
         let generator = Sib1Generator::new(config);
         
         let sib1 = generator.generate_sib1().unwrap();
         assert!(sib1.len() >= 100);  // Minimum SIB1 size
+        // The 36-bit cell identity follows the PLMN and TAC
+        assert_eq!(&sib1[8..13], &[0x00, 0x19, 0x44, 0x00, 0x10]);
     }
 }
\ No newline at end of file
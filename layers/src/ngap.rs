@@ -1,404 +0,0 @@
-//! NG Application Protocol (NGAP) Layer Implementation
-//! 
-//! Implements the 5G NGAP protocol according to 3GPP TS 38.413
-
-use crate::{LayerError, ProtocolLayer};
-use async_trait::async_trait;
-use bytes::{Bytes, BytesMut, BufMut};
-use tracing::{debug, info, error, warn};
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use std::time::Duration;
-use sctp_rs::ConnectedSocket;
-use sctp_rs::SendData;
-
-/// NGAP layer configuration
-pub struct NgapConfig {
-    /// AMF address
-    pub amf_address: SocketAddr,
-    /// Local address for SCTP binding
-    pub local_address: SocketAddr,
-    /// gNB ID
-    pub gnb_id: u32,
-    /// PLMN ID (MCC + MNC)
-    pub plmn_id: [u8; 3],
-}
-
-/// NGAP layer implementation
-pub struct NgapLayer {
-    config: NgapConfig,
-    initialized: bool,
-    /// NG connection state
-    ng_connected: bool,
-    /// SCTP socket for AMF connection
-    sctp_socket: Option<Arc<Mutex<ConnectedSocket>>>,
-}
-
-#[allow(clippy::new_without_default)]
-impl NgapLayer {
-    /// Create a new NGAP layer instance
-    pub fn new(config: NgapConfig) -> Self {
-        Self {
-            config,
-            initialized: false,
-            ng_connected: false,
-            sctp_socket: None,
-        }
-    }
-    
-    /// Establish NG connection with AMF
-    async fn setup_ng_connection(&mut self) -> Result<(), LayerError> {
-        info!("Setting up NG connection to AMF at {}", self.config.amf_address);
-        info!("Using SCTP connection for NGAP");
-        
-        // Connect to AMF using SCTP
-        let amf_addr = self.config.amf_address;
-        let local_addr = self.config.local_address;
-        
-        // Try TCP connection as fallback since SCTP requires privileged mode
-        warn!("SCTP connection requires privileged mode in Docker. Attempting TCP connection instead.");
-        info!("Connecting to AMF at {} using TCP transport", amf_addr);
-        
-        // For now, we'll use a TCP connection that speaks SCTP protocol
-        // This is a workaround for Docker limitations
-        use tokio::net::TcpStream;
-        
-        let tcp_stream = TcpStream::connect(amf_addr).await
-            .map_err(|e| {
-                error!("Failed to connect to AMF at {}: {}", amf_addr, e);
-                LayerError::InitializationFailed(format!("Failed to connect to AMF: {}", e))
-            })?;
-        
-        info!("TCP connection established with AMF at {}", amf_addr);
-        
-        // For now, store None since we're using TCP
-        // TODO: Implement proper SCTP-over-TCP wrapper
-        self.sctp_socket = None;
-        
-        // Skip NG Setup for now - we need proper SCTP
-        warn!("Skipping NG Setup Request - proper SCTP implementation needed");
-        
-        // Mark as connected for testing purposes
-        self.ng_connected = true;
-        warn!("NGAP layer initialized in test mode (no actual AMF connection)");
-        return Ok(());
-        
-        info!("SCTP connection established with AMF at {}", self.config.amf_address);
-        
-        // Store the connected socket (unreachable due to early return above)
-        // self.sctp_socket = Some(Arc::new(Mutex::new(connected_socket)));
-        
-        // Send NG Setup Request
-        self.send_ng_setup_request().await?;
-        
-        // Wait for NG Setup Response
-        self.wait_for_ng_setup_response().await?;
-        
-        self.ng_connected = true;
-        info!("NG Setup procedure completed successfully");
-        Ok(())
-    }
-    
-    /// Send NG Setup Request message
-    async fn send_ng_setup_request(&mut self) -> Result<(), LayerError> {
-        info!("Sending NG Setup Request");
-        
-        // Build NG Setup Request message
-        let ng_setup_request = self.build_ng_setup_request()?;
-        
-        // Send over SCTP
-        if let Some(socket) = &self.sctp_socket {
-            let socket_clone = Arc::clone(socket);
-            let request = ng_setup_request.clone();
-            let len = request.len();
-            
-            // Send over SCTP
-            let mut socket_guard = socket_clone.lock().await;
-            let send_data = SendData {
-                payload: request,
-                snd_info: None,
-            };
-            socket_guard.sctp_send(send_data).await
-                .map_err(|e| LayerError::ProcessingError(format!("Failed to send NG Setup Request: {}", e)))?;
-            
-            info!("NG Setup Request sent successfully ({} bytes) on SCTP", len);
-        } else {
-            return Err(LayerError::InvalidState("SCTP socket not established".to_string()));
-        }
-        
-        Ok(())
-    }
-    
-    /// Build NG Setup Request message
-    fn build_ng_setup_request(&self) -> Result<Vec<u8>, LayerError> {
-        let mut buffer = BytesMut::new();
-        
-        // NGAP PDU header
-        // Procedure Code: 21 (NG Setup)
-        // Criticality: reject (0)
-        // PDU Type: Initiating Message (0)
-        buffer.put_u8(0x00); // PDU type: Initiating Message
-        buffer.put_u8(21);   // Procedure code: NG Setup
-        buffer.put_u8(0x00); // Criticality: reject
-        
-        // Length placeholder (will update later)
-        let length_pos = buffer.len();
-        buffer.put_u8(0x00);
-        
-        // NG Setup Request IEs
-        let mut ie_buffer = BytesMut::new();
-        
-        // Global RAN Node ID IE
-        ie_buffer.put_u8(0x00); // IE ID: GlobalRANNodeID
-        ie_buffer.put_u8(0x1B); // IE ID continued
-        ie_buffer.put_u8(0x00); // Criticality: reject
-        
-        // gNB ID
-        ie_buffer.put_u8(0x00); // Choice: gNB
-        ie_buffer.put_u8(0x00); // PLMN ID length
-        ie_buffer.put_u8(0x03); // PLMN ID length (3 bytes)
-        ie_buffer.put_slice(&self.config.plmn_id); // PLMN ID
-        
-        // gNB ID (24 bits)
-        ie_buffer.put_u8(0x00); // gNB ID choice: gNB ID
-        ie_buffer.put_u8(0x18); // Bit string length: 24 bits
-        ie_buffer.put_u8((self.config.gnb_id >> 16) as u8);
-        ie_buffer.put_u8((self.config.gnb_id >> 8) as u8);
-        ie_buffer.put_u8(self.config.gnb_id as u8);
-        
-        // RAN Node Name IE (optional but helpful)
-        ie_buffer.put_u8(0x00); // IE ID: RANNodeName
-        ie_buffer.put_u8(0x52); // IE ID continued
-        ie_buffer.put_u8(0x40); // Criticality: ignore
-        let node_name = b"Albor-gNodeB";
-        ie_buffer.put_u8(node_name.len() as u8);
-        ie_buffer.put_slice(node_name);
-        
-        // Supported TA List IE
-        ie_buffer.put_u8(0x00); // IE ID: SupportedTAList
-        ie_buffer.put_u8(0x66); // IE ID continued
-        ie_buffer.put_u8(0x00); // Criticality: reject
-        ie_buffer.put_u8(0x01); // Number of TA items: 1
-        
-        // TAC (3 bytes)
-        ie_buffer.put_u8(0x00);
-        ie_buffer.put_u8(0x00);
-        ie_buffer.put_u8(0x07); // TAC: 7
-        
-        // Broadcast PLMNs
-        ie_buffer.put_u8(0x01); // Number of broadcast PLMNs: 1
-        ie_buffer.put_slice(&self.config.plmn_id); // PLMN ID
-        
-        // S-NSSAI list (empty for now)
-        ie_buffer.put_u8(0x00); // Number of S-NSSAIs: 0
-        
-        // Paging DRX IE
-        ie_buffer.put_u8(0x00); // IE ID: DefaultPagingDRX
-        ie_buffer.put_u8(0x15); // IE ID continued
-        ie_buffer.put_u8(0x40); // Criticality: ignore
-        ie_buffer.put_u8(0x01); // Length: 1 byte
-        ie_buffer.put_u8(0x01); // DRX value: rf64 (default)
-        
-        // Update length field
-        let ie_len = ie_buffer.len();
-        buffer[length_pos] = ie_len as u8;
-        
-        // Append IEs to main buffer
-        buffer.extend_from_slice(&ie_buffer);
-        
-        Ok(buffer.to_vec())
-    }
-    
-    /// Wait for NG Setup Response
-    async fn wait_for_ng_setup_response(&mut self) -> Result<(), LayerError> {
-        info!("Waiting for NG Setup Response");
-        
-        if let Some(socket) = &self.sctp_socket {
-            let socket_clone = Arc::clone(socket);
-            
-            // Set timeout for response
-            let timeout = Duration::from_secs(10);
-            let start_time = tokio::time::Instant::now();
-            
-            loop {
-                if start_time.elapsed() > timeout {
-                    error!("NG Setup Response timeout after {} seconds", timeout.as_secs());
-                    return Err(LayerError::ProcessingError("NG Setup Response timeout".to_string()));
-                }
-                
-                // Clone socket reference for the blocking task
-                let socket_for_task = Arc::clone(&socket_clone);
-                
-                // Read with timeout
-                let read_result = tokio::time::timeout(
-                    Duration::from_millis(100),
-                    async {
-                        let mut socket_guard = socket_for_task.lock().await;
-                        socket_guard.sctp_recv().await
-                    }
-                ).await;
-                
-                match read_result {
-                    Ok(Ok(notification_or_data)) => {
-                        match notification_or_data {
-                            sctp_rs::NotificationOrData::Notification(notification) => {
-                                warn!("Received SCTP notification: {:?}", notification);
-                                // Handle notifications if needed
-                                continue;
-                            }
-                            sctp_rs::NotificationOrData::Data(data) => {
-                                let len = data.payload.len();
-                                info!("Received NGAP message: {} bytes on SCTP", len);
-                                debug!("Message bytes: {:02X?}", &data.payload[..len.min(32)]);
-                                
-                                // Simple check for NG Setup Response
-                                // PDU type: Successful Outcome (0x20) or Unsuccessful Outcome (0x40)
-                                if len > 3 && data.payload[1] == 21 { // Procedure code: NG Setup
-                                    if data.payload[0] == 0x20 {
-                                        info!("Received NG Setup Response (Success)");
-                                        return Ok(());
-                                    } else if data.payload[0] == 0x40 {
-                                        error!("Received NG Setup Failure");
-                                        return Err(LayerError::ProcessingError("NG Setup rejected by AMF".to_string()));
-                                    }
-                                }
-                                
-                                // Continue reading if this wasn't our expected message
-                                warn!("Received unexpected NGAP message, continuing to wait");
-                            }
-                        }
-                    }
-                    Ok(Err(e)) => {
-                        error!("Failed to read from SCTP socket: {}", e);
-                        return Err(LayerError::ProcessingError(format!("SCTP receive error: {}", e)));
-                    }
-                    Err(_) => {
-                        // Timeout on read, continue loop
-                        continue;
-                    }
-                }
-            }
-        } else {
-            return Err(LayerError::InvalidState("SCTP socket not established".to_string()));
-        }
-    }
-}
-
-#[async_trait]
-impl ProtocolLayer for NgapLayer {
-    async fn initialize(&mut self) -> Result<(), LayerError> {
-        info!("Initializing NGAP layer");
-        debug!("NGAP config: gnb_id={:#x}, amf_address={}", 
-               self.config.gnb_id,
-               self.config.amf_address);
-        
-        // TODO: Initialize NGAP resources
-        // - Setup SCTP endpoint
-        // - Prepare NG Setup Request
-        
-        self.initialized = true;
-        
-        // Attempt to connect to AMF
-        if let Err(e) = self.setup_ng_connection().await {
-            self.initialized = false;
-            return Err(e);
-        }
-        
-        info!("NGAP layer initialized successfully");
-        Ok(())
-    }
-    
-    async fn process_uplink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
-        if !self.initialized {
-            return Err(LayerError::NotInitialized);
-        }
-        
-        if !self.ng_connected {
-            return Err(LayerError::ProcessingError("NG connection not established".to_string()));
-        }
-        
-        debug!("NGAP processing uplink data: {} bytes", data.len());
-        
-        // TODO: Process NGAP messages from RRC
-        // - Encode NGAP PDUs
-        // - Send over SCTP to AMF
-        
-        Ok(data)
-    }
-    
-    async fn process_downlink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
-        if !self.initialized {
-            return Err(LayerError::NotInitialized);
-        }
-        
-        debug!("NGAP processing downlink data: {} bytes", data.len());
-        
-        // TODO: Process NGAP messages from AMF
-        // - Decode NGAP PDUs
-        // - Forward to RRC layer
-        
-        Ok(data)
-    }
-    
-    async fn shutdown(&mut self) -> Result<(), LayerError> {
-        info!("Shutting down NGAP layer");
-        
-        if self.ng_connected {
-            // Close SCTP socket
-            if let Some(socket) = self.sctp_socket.take() {
-                let socket_guard = socket.lock().await;
-                // The socket will be closed when dropped
-                drop(socket_guard);
-                info!("SCTP socket closed");
-            }
-            
-            self.ng_connected = false;
-        }
-        
-        self.initialized = false;
-        Ok(())
-    }
-}
-
-/// NGAP procedure codes
-#[derive(Debug, Clone, Copy)]
-pub enum NgapProcedureCode {
-    NgSetup = 21,
-    InitialUeMessage = 15,
-    DownlinkNasTransport = 4,
-    UplinkNasTransport = 46,
-    UeContextReleaseRequest = 41,
-    UeContextReleaseCommand = 42,
-}
-
-/// NGAP message types
-#[derive(Debug, Clone)]
-pub struct NgapMessage {
-    /// Procedure code
-    pub procedure_code: NgapProcedureCode,
-    /// Criticality
-    pub criticality: u8,
-    /// Message payload
-    pub payload: Bytes,
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::str::FromStr;
-    
-    #[tokio::test]
-    async fn test_ngap_initialization() {
-        let config = NgapConfig {
-            amf_address: SocketAddr::from_str("127.0.0.1:38412").unwrap(),
-            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
-            gnb_id: 0x19B,
-            plmn_id: [0x02, 0xF8, 0x39], // 208/93
-        };
-        
-        let mut ngap = NgapLayer::new(config);
-        // Note: This will fail in test as we can't actually connect to AMF
-        assert!(ngap.initialize().await.is_err());
-    }
-}
\ No newline at end of file
@@ -0,0 +1,361 @@
+//! AMF pool and AMF selection
+//!
+//! The gNB keeps one NG association per configured AMF. A new UE is given to
+//! the AMF it identifies through its 5G-S-TMSI or registered AMF when that AMF
+//! is connected and still serves the GUAMI; otherwise to an AMF supporting the
+//! requested slices, balanced by relative AMF capacity (3GPP TS 23.501 section
+//! 6.3.5, TS 38.300 section 10.6). GUAMIs reported unavailable in AMF Status
+//! Indication (TS 38.413 section 8.7.6) are not selected, their backup AMF is.
+
+use super::pdu::{self, FiveGSTmsi, Guami, NgapPdu, UnavailableGuamiItem};
+use super::setup::AmfInfo;
+use super::transport::NgTransport;
+use super::NgapLayer;
+use crate::rrc::AmfSelectionInfo;
+use crate::LayerError;
+use common::types::SNssai;
+use std::cmp::Ordering;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// AMF to connect to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmfEndpoint {
+    /// AMF address
+    pub address: SocketAddr,
+    /// Additional AMF addresses for SCTP multi-homing, on the same port
+    pub extra_addresses: Vec<IpAddr>,
+}
+
+impl From<SocketAddr> for AmfEndpoint {
+    fn from(address: SocketAddr) -> Self {
+        Self { address, extra_addresses: Vec::new() }
+    }
+}
+
+/// State of the NG association with one AMF
+pub(super) struct AmfConnection {
+    pub(super) endpoint: AmfEndpoint,
+    /// Transport towards the AMF
+    pub(super) transport: Option<NgTransport>,
+    /// NG Setup completed on the current association
+    pub(super) ng_connected: bool,
+    /// AMF information from NG Setup Response
+    pub(super) info: Option<AmfInfo>,
+    /// GUAMIs reported unavailable in AMF Status Indication
+    pub(super) unavailable_guamis: Vec<UnavailableGuamiItem>,
+    /// Time to Wait of the last NG Setup Failure
+    pub(super) ng_setup_wait: Option<Duration>,
+    /// Time of the next attempt to re-establish the association
+    pub(super) next_attempt: Option<Instant>,
+}
+
+impl AmfConnection {
+    pub(super) fn new(endpoint: AmfEndpoint) -> Self {
+        Self {
+            endpoint,
+            transport: None,
+            ng_connected: false,
+            info: None,
+            unavailable_guamis: Vec::new(),
+            ng_setup_wait: None,
+            next_attempt: None,
+        }
+    }
+
+    /// AMF information when NG Setup is done on the current association
+    fn connected_info(&self) -> Option<&AmfInfo> {
+        self.info.as_ref().filter(|_| self.ng_connected)
+    }
+
+    /// Check if the AMF supports a slice in a PLMN
+    fn supports_slice(&self, plmn_id: &[u8; 3], slice: &SNssai) -> bool {
+        self.connected_info().is_some_and(|info| {
+            info.plmn_support.iter().any(|item| item.plmn_id == *plmn_id && item.slices.contains(slice))
+        })
+    }
+}
+
+impl NgapLayer {
+    /// Number of configured AMFs
+    pub fn num_amfs(&self) -> usize {
+        self.amfs.len()
+    }
+
+    /// Check if NG Setup is done with an AMF
+    pub fn is_amf_connected(&self, amf: usize) -> bool {
+        self.amfs.get(amf).is_some_and(|connection| connection.ng_connected)
+    }
+
+    /// Number of UEs served by an AMF
+    fn amf_ue_count(&self, amf: usize) -> usize {
+        self.ue_contexts.values().filter(|ctx| ctx.amf == Some(amf)).count()
+    }
+
+    /// Select the AMF for a new UE
+    pub fn select_amf(&self, selection: &AmfSelectionInfo) -> Option<usize> {
+        let serving_plmn = self.config.plmn_id;
+
+        // The 5G-S-TMSI identifies the AMF Set and AMF Pointer in the serving PLMN
+        let identified = selection.s_tmsi
+            .map(|s_tmsi| {
+                let s_tmsi = FiveGSTmsi::from_value(s_tmsi);
+                (serving_plmn, None, s_tmsi.amf_set_id, s_tmsi.amf_pointer)
+            })
+            .or_else(|| selection.registered_amf.map(|amf| {
+                (amf.plmn_id.unwrap_or(serving_plmn), Some(amf.amf_region_id()), amf.amf_set_id(), amf.amf_pointer())
+            }));
+        if let Some((plmn_id, amf_region_id, amf_set_id, amf_pointer)) = identified {
+            let matches = |guami: &Guami| guami.plmn_id == plmn_id && guami.amf_set_id == amf_set_id
+                && guami.amf_pointer == amf_pointer && amf_region_id.is_none_or(|region| guami.amf_region_id == region);
+            if let Some(amf) = self.amf_serving_guami(matches) {
+                debug!("AMF {} selected by UE provided GUAMI", amf);
+                return Some(amf);
+            }
+        }
+
+        self.select_amf_by_load(&selection.requested_nssai)
+    }
+
+    /// Connected AMF serving a GUAMI, or the backup AMF of an unavailable GUAMI
+    pub(super) fn amf_serving_guami(&self, matches: impl Fn(&Guami) -> bool) -> Option<usize> {
+        for (amf, connection) in self.amfs.iter().enumerate() {
+            let Some(info) = connection.connected_info() else {
+                continue;
+            };
+            for served in info.served_guamis.iter().filter(|served| matches(&served.guami)) {
+                let Some(unavailable) = self.unavailable_guami(&served.guami) else {
+                    return Some(amf);
+                };
+                let backup = unavailable.backup_amf_name.as_ref().or(served.backup_amf_name.as_ref());
+                if let Some(backup) = backup.and_then(|name| self.connected_amf_by_name(name)) {
+                    debug!("GUAMI {:?} unavailable, using backup AMF {}", served.guami, backup);
+                    return Some(backup);
+                }
+            }
+        }
+        None
+    }
+
+    /// Unavailable GUAMI reported by any of the AMFs
+    fn unavailable_guami(&self, guami: &Guami) -> Option<&UnavailableGuamiItem> {
+        self.amfs.iter()
+            .flat_map(|connection| &connection.unavailable_guamis)
+            .find(|item| item.guami == *guami)
+    }
+
+    /// Connected AMF with a given name
+    fn connected_amf_by_name(&self, name: &str) -> Option<usize> {
+        self.amfs.iter().position(|connection| connection.connected_info().is_some_and(|info| info.amf_name == name))
+    }
+
+    /// Select among the connected AMFs supporting the requested slices the one
+    /// with the fewest UEs relative to its capacity
+    ///
+    /// AMFs supporting all requested slices come first, then those supporting
+    /// some of them. An AMF with relative capacity 0 only gets UEs when no other
+    /// AMF can take them.
+    fn select_amf_by_load(&self, requested_nssai: &[SNssai]) -> Option<usize> {
+        let serving_plmn = self.config.plmn_id;
+        let connected: Vec<usize> = (0..self.amfs.len()).filter(|amf| self.is_amf_connected(*amf)).collect();
+
+        let mut candidates: Vec<usize> = connected.iter().copied()
+            .filter(|amf| requested_nssai.iter().all(|slice| self.amfs[*amf].supports_slice(&serving_plmn, slice)))
+            .collect();
+        if candidates.is_empty() {
+            candidates = connected.iter().copied()
+                .filter(|amf| requested_nssai.iter().any(|slice| self.amfs[*amf].supports_slice(&serving_plmn, slice)))
+                .collect();
+        }
+        if candidates.is_empty() {
+            candidates = connected;
+        }
+
+        let load = |amf: usize| {
+            let capacity = self.amfs[amf].info.as_ref().map_or(0, |info| info.relative_capacity) as u64;
+            (capacity, self.amf_ue_count(amf) as u64 + 1)
+        };
+        candidates.into_iter().min_by(|a, b| {
+            let (capacity_a, ues_a) = load(*a);
+            let (capacity_b, ues_b) = load(*b);
+            (capacity_a == 0).cmp(&(capacity_b == 0))
+                .then_with(|| if capacity_a == 0 { Ordering::Equal } else { (ues_a * capacity_b).cmp(&(ues_b * capacity_a)) })
+        })
+    }
+
+    /// Handle AMF Status Indication: the listed GUAMIs are no longer selected
+    pub(super) fn handle_amf_status_indication(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let items = pdu.ie::<Vec<UnavailableGuamiItem>>(pdu::ID_UNAVAILABLE_GUAMI_LIST)?;
+        let Some(connection) = self.amfs.get_mut(self.active_amf) else {
+            return Ok(());
+        };
+
+        for item in items {
+            warn!("AMF at {} reports GUAMI {:?} unavailable (backup AMF {:?})",
+                  connection.endpoint.address, item.guami, item.backup_amf_name);
+            connection.unavailable_guamis.retain(|unavailable| unavailable.guami != item.guami);
+            connection.unavailable_guamis.push(item);
+        }
+        info!("{} unavailable GUAMIs at AMF {}", connection.unavailable_guamis.len(), connection.endpoint.address);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{Criticality, PagingDrx, PlmnSupportItem, ServedGuamiItem};
+    use crate::ngap::{NgapConfig, NgapProcedureCode, NgapUeContext};
+    use crate::rrc::RegisteredAmf;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn guami(amf_pointer: u8) -> Guami {
+        Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 1, amf_pointer }
+    }
+
+    fn amf_info(name: &str, amf_pointer: u8, relative_capacity: u8, slices: Vec<SNssai>) -> AmfInfo {
+        AmfInfo {
+            amf_name: name.to_string(),
+            served_guamis: vec![ServedGuamiItem { guami: guami(amf_pointer), backup_amf_name: None }],
+            relative_capacity,
+            plmn_support: vec![PlmnSupportItem { plmn_id: PLMN, slices }],
+        }
+    }
+
+    #[test]
+    fn test_amf_selection() {
+        let embb = SNssai { sst: 1, sd: None };
+        let urllc = SNssai { sst: 2, sd: Some(0x000001) };
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec!["127.0.0.5:38412".parse::<SocketAddr>().unwrap().into(),
+                       "127.0.0.6:38412".parse::<SocketAddr>().unwrap().into()],
+            local_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        assert_eq!(ngap.num_amfs(), 2);
+        assert_eq!(ngap.select_amf(&AmfSelectionInfo::default()), None);
+
+        ngap.amfs[0].info = Some(amf_info("amf0", 1, 100, vec![embb.clone()]));
+        ngap.amfs[0].ng_connected = true;
+        ngap.amfs[1].info = Some(amf_info("amf1", 2, 200, vec![embb.clone(), urllc.clone()]));
+        ngap.amfs[1].ng_connected = true;
+
+        // Only the second AMF serves URLLC
+        let urllc_ue = AmfSelectionInfo { requested_nssai: vec![urllc], ..Default::default() };
+        assert_eq!(ngap.select_amf(&urllc_ue), Some(1));
+
+        // eMBB UEs are spread according to relative capacity: amf1 takes two for one
+        let mut selected = Vec::new();
+        for ue_id in 1..=6 {
+            let amf = ngap.select_amf(&AmfSelectionInfo { requested_nssai: vec![embb.clone()], ..Default::default() }).unwrap();
+            ngap.ue_contexts.insert(ue_id, NgapUeContext { ran_ue_ngap_id: ue_id, amf: Some(amf), ..Default::default() });
+            selected.push(amf);
+        }
+        assert_eq!(selected.iter().filter(|amf| **amf == 1).count(), 4);
+
+        // A registered UE goes back to its AMF, whatever the load
+        let s_tmsi = FiveGSTmsi { amf_set_id: 1, amf_pointer: 1, five_g_tmsi: 0xC000_0001 }.value();
+        let registered = AmfSelectionInfo { s_tmsi: Some(s_tmsi), ..Default::default() };
+        assert_eq!(ngap.select_amf(&registered), Some(0));
+        let registered_amf = RegisteredAmf { plmn_id: None, amf_identifier: 2 << 16 | 1 << 6 | 2 };
+        assert_eq!(ngap.select_amf(&AmfSelectionInfo { registered_amf: Some(registered_amf), ..Default::default() }),
+                   Some(1));
+
+        // amf0 reports its GUAMI unavailable with amf1 as backup
+        let status = NgapPdu::initiating(NgapProcedureCode::AmfStatusIndication)
+            .with_ie(pdu::ID_UNAVAILABLE_GUAMI_LIST, Criticality::Reject, &vec![UnavailableGuamiItem {
+                guami: guami(1),
+                timer_approach: true,
+                backup_amf_name: Some("amf1".to_string()),
+            }]).unwrap();
+        let status = NgapPdu::decode(&status.encode().unwrap()).unwrap();
+        ngap.active_amf = 0;
+        ngap.handle_amf_status_indication(&status).unwrap();
+        assert_eq!(ngap.amfs[0].unavailable_guamis.len(), 1);
+        assert_eq!(ngap.select_amf(&registered), Some(1));
+
+        // Disconnected AMFs are not selected
+        ngap.amfs[1].ng_connected = false;
+        assert_eq!(ngap.select_amf(&urllc_ue), Some(0));
+        assert_eq!(ngap.select_amf(&registered), Some(0));
+    }
+
+    #[test]
+    fn test_amf_selection_fallbacks() {
+        let embb = SNssai { sst: 1, sd: None };
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec!["127.0.0.5:38412".parse::<SocketAddr>().unwrap().into(),
+                       "127.0.0.6:38412".parse::<SocketAddr>().unwrap().into()],
+            local_address: "127.0.0.1:0".parse().unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        ngap.amfs[0].info = Some(amf_info("amf0", 1, 0, vec![embb.clone()]));
+        ngap.amfs[0].ng_connected = true;
+        ngap.amfs[1].info = Some(amf_info("amf1", 2, 10, vec![embb.clone()]));
+        ngap.amfs[1].ng_connected = true;
+        for ue_id in 1..=20 {
+            ngap.ue_contexts.insert(ue_id, NgapUeContext { ran_ue_ngap_id: ue_id, amf: Some(1), ..Default::default() });
+        }
+
+        // An AMF with relative capacity 0 only takes UEs no other AMF can take,
+        // and a slice nobody supports does not prevent the selection
+        let unknown_slice = AmfSelectionInfo { requested_nssai: vec![SNssai { sst: 9, sd: None }], ..Default::default() };
+        assert_eq!(ngap.select_amf(&unknown_slice), Some(1));
+        ngap.amfs[1].ng_connected = false;
+        assert_eq!(ngap.select_amf(&unknown_slice), Some(0));
+        ngap.amfs[1].ng_connected = true;
+
+        // GUAMIs of another PLMN or AMF Region fall back to the load
+        let registered = |plmn_id, amf_region_id: u32| AmfSelectionInfo {
+            registered_amf: Some(RegisteredAmf { plmn_id, amf_identifier: amf_region_id << 16 | 1 << 6 | 1 }),
+            ..Default::default()
+        };
+        assert_eq!(ngap.select_amf(&registered(None, 2)), Some(0));
+        assert_eq!(ngap.select_amf(&registered(Some([0x00, 0xF1, 0x10]), 2)), Some(1));
+        assert_eq!(ngap.select_amf(&registered(None, 3)), Some(1));
+
+        // Unavailable GUAMI without a connected backup AMF
+        let status = |backup_amf_name: Option<&str>| NgapPdu::initiating(NgapProcedureCode::AmfStatusIndication)
+            .with_ie(pdu::ID_UNAVAILABLE_GUAMI_LIST, Criticality::Reject, &vec![UnavailableGuamiItem {
+                guami: guami(1),
+                timer_approach: false,
+                backup_amf_name: backup_amf_name.map(str::to_string),
+            }]).unwrap();
+        ngap.active_amf = 0;
+        ngap.handle_amf_status_indication(&status(Some("amf7"))).unwrap();
+        assert_eq!(ngap.select_amf(&registered(None, 2)), Some(1));
+        // A repeated report replaces the earlier one
+        ngap.handle_amf_status_indication(&status(None)).unwrap();
+        assert_eq!(ngap.amfs[0].unavailable_guamis.len(), 1);
+        assert_eq!(ngap.amfs[0].unavailable_guamis[0].backup_amf_name, None);
+
+        // Missing Unavailable GUAMI List, and a report of an AMF that is gone
+        let empty = NgapPdu::initiating(NgapProcedureCode::AmfStatusIndication);
+        assert!(matches!(ngap.handle_amf_status_indication(&empty), Err(LayerError::ProcessingError(_))));
+        ngap.active_amf = 5;
+        ngap.handle_amf_status_indication(&status(None)).unwrap();
+    }
+}
@@ -0,0 +1,547 @@
+//! Aligned Packed Encoding Rules (APER)
+//!
+//! Bit-level encoder and decoder for the subset of ITU-T X.691 (ALIGNED variant)
+//! used by NGAP, F1AP, E1AP and XnAP
+
+use crate::LayerError;
+use bytes::Bytes;
+
+/// Threshold above which lengths and whole numbers use the unconstrained forms
+const LIMIT_64K: u64 = 65536;
+
+/// Number of bits needed to encode values in a range of `range` values
+fn range_bits(range: u64) -> usize {
+    if range <= 1 {
+        0
+    } else {
+        64 - (range - 1).leading_zeros() as usize
+    }
+}
+
+/// Number of octets needed to hold `value` (at least one)
+fn octets_needed(value: u64) -> usize {
+    (range_bits(value.saturating_add(1)).max(1)).div_ceil(8)
+}
+
+/// APER encoder
+#[derive(Debug, Default)]
+pub struct AperEncoder {
+    buf: Vec<u8>,
+    /// Number of bits written
+    bits: usize,
+}
+
+impl AperEncoder {
+    /// Create an empty encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the `n` least significant bits of `value`, MSB first
+    pub fn put_bits(&mut self, value: u64, n: usize) {
+        for i in (0..n).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.buf.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                let last = self.buf.len() - 1;
+                self.buf[last] |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    /// Write a single bit
+    pub fn put_bool(&mut self, value: bool) {
+        self.put_bits(value as u64, 1);
+    }
+
+    /// Pad with zero bits up to the next octet boundary
+    pub fn align(&mut self) {
+        self.bits = self.buf.len() * 8;
+    }
+
+    /// Write octets at the current bit position
+    pub fn put_octets(&mut self, data: &[u8]) {
+        if self.bits.is_multiple_of(8) {
+            self.buf.extend_from_slice(data);
+            self.bits += data.len() * 8;
+        } else {
+            for byte in data {
+                self.put_bits(*byte as u64, 8);
+            }
+        }
+    }
+
+    /// Encode a constrained whole number in `lb..=ub`
+    pub fn put_constrained_whole_number(&mut self, value: u64, lb: u64, ub: u64) -> Result<(), LayerError> {
+        if value < lb || value > ub {
+            return Err(LayerError::ProcessingError(format!("Value {} outside {}..{}", value, lb, ub)));
+        }
+        let range = ub - lb + 1;
+        let offset = value - lb;
+        match range {
+            1 => {}
+            2..=255 => self.put_bits(offset, range_bits(range)),
+            256 => {
+                self.align();
+                self.put_bits(offset, 8);
+            }
+            257..=LIMIT_64K => {
+                self.align();
+                self.put_bits(offset, 16);
+            }
+            _ => {
+                // Indefinite length case: octet count then aligned octets
+                let max_octets = octets_needed(ub - lb);
+                let octets = octets_needed(offset);
+                self.put_constrained_whole_number(octets as u64, 1, max_octets as u64)?;
+                self.align();
+                self.put_bits(offset, octets * 8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode an INTEGER (lb..ub), optionally with an extension marker
+    pub fn put_integer(&mut self, value: u64, lb: u64, ub: u64, extensible: bool) -> Result<(), LayerError> {
+        if extensible {
+            self.put_bool(false);
+        }
+        self.put_constrained_whole_number(value, lb, ub)
+    }
+
+    /// Encode an unconstrained length determinant
+    pub fn put_unconstrained_length(&mut self, len: usize) -> Result<(), LayerError> {
+        self.align();
+        match len {
+            0..=127 => self.put_bits(len as u64, 8),
+            128..=16383 => self.put_bits(0x8000 | len as u64, 16),
+            _ => return Err(LayerError::ProcessingError(format!("Length {} requires fragmentation", len))),
+        }
+        Ok(())
+    }
+
+    /// Encode a length determinant constrained to `lb..=ub` (ub of None is unbounded)
+    pub fn put_length(&mut self, len: usize, lb: usize, ub: Option<usize>) -> Result<(), LayerError> {
+        match ub {
+            Some(ub) if (ub as u64) < LIMIT_64K => {
+                self.put_constrained_whole_number(len as u64, lb as u64, ub as u64)
+            }
+            _ => self.put_unconstrained_length(len),
+        }
+    }
+
+    /// Encode the index of an ENUMERATED value
+    pub fn put_enumerated(&mut self, index: usize, root_count: usize, extensible: bool) -> Result<(), LayerError> {
+        if extensible {
+            self.put_bool(false);
+        }
+        self.put_constrained_whole_number(index as u64, 0, root_count as u64 - 1)
+    }
+
+    /// Encode the index of a CHOICE alternative
+    pub fn put_choice(&mut self, index: usize, root_count: usize, extensible: bool) -> Result<(), LayerError> {
+        self.put_enumerated(index, root_count, extensible)
+    }
+
+    /// Encode an OCTET STRING with size constraint `lb..=ub`
+    pub fn put_octet_string(&mut self, data: &[u8], lb: usize, ub: Option<usize>, extensible: bool) -> Result<(), LayerError> {
+        if extensible {
+            self.put_bool(false);
+        }
+        if data.len() < lb || ub.is_some_and(|ub| data.len() > ub) {
+            return Err(LayerError::ProcessingError(format!("OCTET STRING size {} out of range", data.len())));
+        }
+        match ub {
+            Some(ub) if lb == ub && (ub as u64) < LIMIT_64K => {
+                if ub > 2 {
+                    self.align();
+                }
+            }
+            _ => {
+                self.put_length(data.len(), lb, ub)?;
+                if !data.is_empty() {
+                    self.align();
+                }
+            }
+        }
+        self.put_octets(data);
+        Ok(())
+    }
+
+    /// Encode a BIT STRING of `bits` bits (left aligned in `data`) with size constraint `lb..=ub`
+    pub fn put_bit_string(&mut self, data: &[u8], bits: usize, lb: usize, ub: Option<usize>, extensible: bool) -> Result<(), LayerError> {
+        if extensible {
+            self.put_bool(false);
+        }
+        if bits < lb || ub.is_some_and(|ub| bits > ub) || data.len() * 8 < bits {
+            return Err(LayerError::ProcessingError(format!("BIT STRING size {} out of range", bits)));
+        }
+        match ub {
+            Some(ub) if lb == ub && (ub as u64) < LIMIT_64K => {
+                if ub > 16 {
+                    self.align();
+                }
+            }
+            _ => {
+                // Only fixed-size strings of up to 16 bits are left unaligned (X.691 16.9-16.11)
+                self.put_length(bits, lb, ub)?;
+                if bits > 0 {
+                    self.align();
+                }
+            }
+        }
+        for i in 0..bits {
+            self.put_bool(data[i / 8] & (0x80 >> (i % 8)) != 0);
+        }
+        Ok(())
+    }
+
+    /// Encode a PrintableString / VisibleString with size constraint `lb..=ub`
+    ///
+    /// Characters use 8 bits each in the ALIGNED variant.
+    pub fn put_printable_string(&mut self, value: &str, lb: usize, ub: usize, extensible: bool) -> Result<(), LayerError> {
+        if extensible {
+            self.put_bool(false);
+        }
+        let chars = value.as_bytes();
+        if chars.len() < lb || chars.len() > ub {
+            return Err(LayerError::ProcessingError(format!("String size {} out of range", chars.len())));
+        }
+        if lb != ub {
+            self.put_length(chars.len(), lb, Some(ub))?;
+        }
+        if ub * 8 > 16 {
+            self.align();
+        }
+        self.put_octets(chars);
+        Ok(())
+    }
+
+    /// Encode an open type (complete encoding wrapped in an octet string)
+    pub fn put_open_type(&mut self, encoding: &[u8]) -> Result<(), LayerError> {
+        self.put_unconstrained_length(encoding.len())?;
+        self.put_octets(encoding);
+        Ok(())
+    }
+
+    /// Finish the encoding; an empty encoding is one zero octet
+    pub fn into_bytes(mut self) -> Bytes {
+        if self.buf.is_empty() {
+            self.buf.push(0);
+        }
+        Bytes::from(self.buf)
+    }
+}
+
+/// APER decoder
+#[derive(Debug)]
+pub struct AperDecoder<'a> {
+    data: &'a [u8],
+    /// Number of bits consumed
+    bits: usize,
+}
+
+impl<'a> AperDecoder<'a> {
+    /// Create a decoder over a complete encoding
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, bits: 0 }
+    }
+
+    /// Number of unread bits
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.bits
+    }
+
+    /// Read `n` bits, MSB first
+    pub fn get_bits(&mut self, n: usize) -> Result<u64, LayerError> {
+        if n > 64 || self.remaining_bits() < n {
+            return Err(LayerError::InvalidPdu);
+        }
+        let mut value = 0u64;
+        for _ in 0..n {
+            let bit = (self.data[self.bits / 8] >> (7 - self.bits % 8)) & 1;
+            value = (value << 1) | bit as u64;
+            self.bits += 1;
+        }
+        Ok(value)
+    }
+
+    /// Read a single bit
+    pub fn get_bool(&mut self) -> Result<bool, LayerError> {
+        Ok(self.get_bits(1)? != 0)
+    }
+
+    /// Skip to the next octet boundary
+    pub fn align(&mut self) {
+        self.bits = self.bits.div_ceil(8) * 8;
+    }
+
+    /// Read `len` octets at the current bit position
+    pub fn get_octets(&mut self, len: usize) -> Result<Vec<u8>, LayerError> {
+        if self.remaining_bits() < len * 8 {
+            return Err(LayerError::InvalidPdu);
+        }
+        if self.bits.is_multiple_of(8) {
+            let start = self.bits / 8;
+            self.bits += len * 8;
+            Ok(self.data[start..start + len].to_vec())
+        } else {
+            (0..len).map(|_| self.get_bits(8).map(|b| b as u8)).collect()
+        }
+    }
+
+    /// Decode a constrained whole number in `lb..=ub`
+    pub fn get_constrained_whole_number(&mut self, lb: u64, ub: u64) -> Result<u64, LayerError> {
+        let range = ub - lb + 1;
+        let offset = match range {
+            1 => 0,
+            2..=255 => self.get_bits(range_bits(range))?,
+            256 => {
+                self.align();
+                self.get_bits(8)?
+            }
+            257..=LIMIT_64K => {
+                self.align();
+                self.get_bits(16)?
+            }
+            _ => {
+                let max_octets = octets_needed(ub - lb);
+                let octets = self.get_constrained_whole_number(1, max_octets as u64)? as usize;
+                self.align();
+                self.get_bits(octets * 8)?
+            }
+        };
+        let value = lb.checked_add(offset).ok_or(LayerError::InvalidPdu)?;
+        if value > ub {
+            return Err(LayerError::InvalidPdu);
+        }
+        Ok(value)
+    }
+
+    /// Decode an INTEGER (lb..ub), optionally with an extension marker
+    pub fn get_integer(&mut self, lb: u64, ub: u64, extensible: bool) -> Result<u64, LayerError> {
+        if extensible && self.get_bool()? {
+            // Value outside the root: semi-constrained encoding
+            let len = self.get_unconstrained_length()?;
+            let octets = self.get_octets(len)?;
+            if len > 8 {
+                return Err(LayerError::InvalidPdu);
+            }
+            return Ok(octets.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64));
+        }
+        self.get_constrained_whole_number(lb, ub)
+    }
+
+    /// Decode an unconstrained length determinant
+    pub fn get_unconstrained_length(&mut self) -> Result<usize, LayerError> {
+        self.align();
+        let first = self.get_bits(8)?;
+        if first & 0x80 == 0 {
+            Ok(first as usize)
+        } else if first & 0xC0 == 0x80 {
+            Ok((((first & 0x3F) << 8) | self.get_bits(8)?) as usize)
+        } else {
+            Err(LayerError::ProcessingError("Fragmented APER length not supported".into()))
+        }
+    }
+
+    /// Decode a length determinant constrained to `lb..=ub` (ub of None is unbounded)
+    pub fn get_length(&mut self, lb: usize, ub: Option<usize>) -> Result<usize, LayerError> {
+        match ub {
+            Some(ub) if (ub as u64) < LIMIT_64K => {
+                Ok(self.get_constrained_whole_number(lb as u64, ub as u64)? as usize)
+            }
+            _ => self.get_unconstrained_length(),
+        }
+    }
+
+    /// Decode the index of an ENUMERATED value
+    ///
+    /// Values from the extension are returned as `root_count + index`.
+    pub fn get_enumerated(&mut self, root_count: usize, extensible: bool) -> Result<usize, LayerError> {
+        if extensible && self.get_bool()? {
+            // Normally small non-negative whole number
+            let index = if self.get_bool()? {
+                let len = self.get_unconstrained_length()?;
+                self.get_octets(len)?.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+            } else {
+                self.get_bits(6)? as usize
+            };
+            return Ok(root_count + index);
+        }
+        Ok(self.get_constrained_whole_number(0, root_count as u64 - 1)? as usize)
+    }
+
+    /// Decode the index of a CHOICE alternative
+    ///
+    /// Alternatives from the extension are skipped and reported as an error.
+    pub fn get_choice(&mut self, root_count: usize, extensible: bool) -> Result<usize, LayerError> {
+        if extensible && self.get_bool()? {
+            return Err(LayerError::ProcessingError("Unknown CHOICE extension".into()));
+        }
+        Ok(self.get_constrained_whole_number(0, root_count as u64 - 1)? as usize)
+    }
+
+    /// Decode an OCTET STRING with size constraint `lb..=ub`
+    pub fn get_octet_string(&mut self, lb: usize, ub: Option<usize>, extensible: bool) -> Result<Vec<u8>, LayerError> {
+        if extensible && self.get_bool()? {
+            let len = self.get_unconstrained_length()?;
+            return self.get_octets(len);
+        }
+        let len = match ub {
+            Some(ub) if lb == ub && (ub as u64) < LIMIT_64K => {
+                if ub > 2 {
+                    self.align();
+                }
+                ub
+            }
+            _ => {
+                let len = self.get_length(lb, ub)?;
+                if len > 0 {
+                    self.align();
+                }
+                len
+            }
+        };
+        self.get_octets(len)
+    }
+
+    /// Decode a BIT STRING with size constraint `lb..=ub`, returning (data, bits)
+    pub fn get_bit_string(&mut self, lb: usize, ub: Option<usize>, extensible: bool) -> Result<(Vec<u8>, usize), LayerError> {
+        let bits = if extensible && self.get_bool()? {
+            let bits = self.get_unconstrained_length()?;
+            self.align();
+            bits
+        } else {
+            match ub {
+                Some(ub) if lb == ub && (ub as u64) < LIMIT_64K => {
+                    if ub > 16 {
+                        self.align();
+                    }
+                    ub
+                }
+                _ => {
+                    let bits = self.get_length(lb, ub)?;
+                    if bits > 0 {
+                        self.align();
+                    }
+                    bits
+                }
+            }
+        };
+        let mut data = vec![0u8; bits.div_ceil(8)];
+        for i in 0..bits {
+            if self.get_bool()? {
+                data[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        Ok((data, bits))
+    }
+
+    /// Decode a PrintableString / VisibleString with size constraint `lb..=ub`
+    pub fn get_printable_string(&mut self, lb: usize, ub: usize, extensible: bool) -> Result<String, LayerError> {
+        let len = if extensible && self.get_bool()? {
+            self.get_unconstrained_length()?
+        } else if lb != ub {
+            self.get_length(lb, Some(ub))?
+        } else {
+            ub
+        };
+        if ub * 8 > 16 {
+            self.align();
+        }
+        String::from_utf8(self.get_octets(len)?).map_err(|_| LayerError::InvalidPdu)
+    }
+
+    /// Decode an open type, returning its complete encoding
+    pub fn get_open_type(&mut self) -> Result<Vec<u8>, LayerError> {
+        let len = self.get_unconstrained_length()?;
+        self.get_octets(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constrained_whole_numbers() {
+        let mut enc = AperEncoder::new();
+        enc.put_bool(false);
+        enc.put_constrained_whole_number(2, 0, 2).unwrap(); // 2 bits
+        enc.put_constrained_whole_number(0x15, 0, 255).unwrap(); // aligned octet
+        enc.put_constrained_whole_number(3, 0, 65535).unwrap(); // aligned two octets
+        enc.put_constrained_whole_number(0x1234, 0, 0xFFFF_FFFF).unwrap(); // length + octets
+        let bytes = enc.into_bytes();
+        assert_eq!(&bytes[..], &[0x40, 0x15, 0x00, 0x03, 0x40, 0x12, 0x34]);
+
+        let mut dec = AperDecoder::new(&bytes);
+        assert!(!dec.get_bool().unwrap());
+        assert_eq!(dec.get_constrained_whole_number(0, 2).unwrap(), 2);
+        assert_eq!(dec.get_constrained_whole_number(0, 255).unwrap(), 0x15);
+        assert_eq!(dec.get_constrained_whole_number(0, 65535).unwrap(), 3);
+        assert_eq!(dec.get_constrained_whole_number(0, 0xFFFF_FFFF).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut enc = AperEncoder::new();
+        enc.put_bit_string(&[0x00, 0x00, 0x01, 0x9B], 32, 22, Some(32), false).unwrap();
+        enc.put_printable_string("gNB", 1, 150, true).unwrap();
+        enc.put_octet_string(&[0x7E; 200], 0, None, false).unwrap();
+        let bytes = enc.into_bytes();
+        assert_eq!(&bytes[..5], &[0xA0, 0x00, 0x00, 0x01, 0x9B]);
+        assert_eq!(&bytes[5..12], &[0x01, 0x00, b'g', b'N', b'B', 0x80, 0xC8]);
+
+        let mut dec = AperDecoder::new(&bytes);
+        assert_eq!(dec.get_bit_string(22, Some(32), false).unwrap(), (vec![0x00, 0x00, 0x01, 0x9B], 32));
+        assert_eq!(dec.get_printable_string(1, 150, true).unwrap(), "gNB");
+        assert_eq!(dec.get_octet_string(0, None, false).unwrap(), vec![0x7E; 200]);
+    }
+
+    #[test]
+    fn test_short_bit_strings() {
+        // A variable-size string of up to 16 bits is aligned after its length,
+        // a fixed-size one is not: 1 | 0011 000 | 1010 | 0110011001
+        let mut enc = AperEncoder::new();
+        enc.put_bool(true);
+        enc.put_bit_string(&[0xA0], 4, 1, Some(16), false).unwrap();
+        enc.put_bit_string(&[0x66, 0x40], 10, 10, Some(10), false).unwrap();
+        let bytes = enc.into_bytes();
+        assert_eq!(&bytes[..], &[0x98, 0xA6, 0x64]);
+
+        let mut dec = AperDecoder::new(&bytes);
+        assert!(dec.get_bool().unwrap());
+        assert_eq!(dec.get_bit_string(1, Some(16), false).unwrap(), (vec![0xA0], 4));
+        assert_eq!(dec.get_bit_string(10, Some(10), false).unwrap(), (vec![0x66, 0x40], 10));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // Values outside the constraint are not encoded
+        let mut enc = AperEncoder::new();
+        assert!(matches!(enc.put_constrained_whole_number(3, 0, 2), Err(LayerError::ProcessingError(_))));
+
+        // Reading past the end of the encoding
+        let mut dec = AperDecoder::new(&[0x12]);
+        assert!(matches!(dec.get_bits(9), Err(LayerError::InvalidPdu)));
+        assert!(matches!(dec.get_octets(2), Err(LayerError::InvalidPdu)));
+        assert!(matches!(AperDecoder::new(&[]).get_bool(), Err(LayerError::InvalidPdu)));
+
+        // Offset above the upper bound of a range that is not a power of two
+        assert!(matches!(AperDecoder::new(&[0xC0]).get_constrained_whole_number(0, 2), Err(LayerError::InvalidPdu)));
+
+        // Fragmented lengths
+        assert!(matches!(AperDecoder::new(&[0xC1, 0x00]).get_unconstrained_length(),
+                         Err(LayerError::ProcessingError(_))));
+
+        // CHOICE extensions are reported, ENUMERATED extensions follow the root
+        assert!(matches!(AperDecoder::new(&[0x80]).get_choice(3, true), Err(LayerError::ProcessingError(_))));
+        assert_eq!(AperDecoder::new(&[0x81]).get_enumerated(4, true).unwrap(), 5);
+
+        // Truncated open type
+        assert!(matches!(AperDecoder::new(&[0x03, 0x00]).get_open_type(), Err(LayerError::InvalidPdu)));
+    }
+}
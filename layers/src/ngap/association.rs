@@ -0,0 +1,363 @@
+//! NG-C association management
+//!
+//! Brings up the transport towards each AMF and runs NG Setup on it, hands the
+//! PDUs received afterwards to the NGAP procedures, and re-establishes an
+//! association when it is lost. NG Setup is redone on every new association: it
+//! re-initialises the NGAP UE-related contexts (3GPP TS 38.413 section 8.7.1),
+//! so the UEs served by an AMF are released when its association goes down.
+
+use super::setup::NgSetupOutcome;
+use super::transport::{NgTransport, NgTransportEvent};
+use super::NgapLayer;
+use crate::{LayerError, ProtocolLayer};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+impl NgapLayer {
+    /// Open the transport towards an AMF, replacing any previous one
+    pub(super) async fn connect_transport(&mut self, amf: usize) -> Result<(), LayerError> {
+        let connection = &mut self.amfs[amf];
+        connection.transport = None;
+        let transport = NgTransport::connect(
+            &self.config.transport,
+            self.config.local_address,
+            connection.endpoint.address,
+            &connection.endpoint.extra_addresses,
+        ).await?;
+        connection.transport = Some(transport);
+        Ok(())
+    }
+
+    /// Pass the events of an AMF transport to the NG association task once NG Setup is done
+    pub(super) fn start_transport_reader(&mut self, amf: usize) {
+        if let Some(transport) = &mut self.amfs[amf].transport {
+            transport.start_reader(amf, self.transport_tx.clone());
+        }
+    }
+
+    /// Take the receiver of the transport events, to be driven by `run_ng_association`
+    pub fn take_transport_events(&mut self) -> Option<mpsc::Receiver<(usize, NgTransportEvent)>> {
+        self.transport_rx.take()
+    }
+
+    /// Check if NG Setup has completed with at least one AMF
+    pub fn is_ng_connected(&self) -> bool {
+        self.amfs.iter().any(|connection| connection.ng_connected)
+    }
+
+    /// Delay before the next reconnection attempt to an AMF: the configured
+    /// interval, or the Time to Wait of its last NG Setup Failure when longer
+    pub fn reconnect_delay(&self, amf: usize) -> Duration {
+        let ng_setup_wait = self.amfs.get(amf).and_then(|connection| connection.ng_setup_wait);
+        self.config.transport.reconnect_interval.max(ng_setup_wait.unwrap_or_default())
+    }
+
+    /// Schedule the next attempt to re-establish the association with an AMF
+    pub(super) fn schedule_reconnect(&mut self, amf: usize) {
+        let delay = self.reconnect_delay(amf);
+        self.amfs[amf].next_attempt = Some(Instant::now() + delay);
+    }
+
+    /// AMF with the earliest pending reconnection and its time
+    fn next_reconnect(&self) -> Option<(usize, Instant)> {
+        self.amfs.iter().enumerate()
+            .filter(|(_, connection)| !connection.ng_connected)
+            .filter_map(|(amf, connection)| connection.next_attempt.map(|at| (amf, at)))
+            .min_by_key(|(_, at)| *at)
+    }
+
+    /// Make one attempt to re-establish the association with an AMF and redo NG
+    /// Setup, scheduling the next attempt on failure
+    pub async fn reconnect(&mut self, amf: usize) -> Result<(), LayerError> {
+        if self.stopped {
+            return Err(LayerError::InvalidState("NGAP layer is shut down".to_string()));
+        }
+        info!("Re-establishing NG connection to AMF at {}", self.amfs[amf].endpoint.address);
+        self.amfs[amf].ng_setup_wait = None;
+
+        let result = match self.connect_transport(amf).await {
+            Ok(()) => self.ng_setup_attempt(amf).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(NgSetupOutcome::Accepted(amf_info)) => {
+                self.store_amf_info(amf, amf_info);
+                self.amfs[amf].ng_connected = true;
+                self.amfs[amf].next_attempt = None;
+                self.initialized = true;
+                self.start_transport_reader(amf);
+                info!("NG connection to AMF at {} re-established", self.amfs[amf].endpoint.address);
+                Ok(())
+            }
+            Ok(NgSetupOutcome::Rejected { cause, time_to_wait }) => {
+                self.amfs[amf].transport = None;
+                self.amfs[amf].ng_setup_wait = time_to_wait.map(|time_to_wait| Duration::from_secs(time_to_wait.seconds()));
+                self.schedule_reconnect(amf);
+                Err(LayerError::ProcessingError(format!("NG Setup rejected by AMF: {:?}", cause)))
+            }
+            Err(e) => {
+                self.amfs[amf].transport = None;
+                self.schedule_reconnect(amf);
+                Err(e)
+            }
+        }
+    }
+
+    /// Handle an event of the transport towards an AMF
+    pub async fn handle_transport_event(&mut self, amf: usize, event: NgTransportEvent) -> Result<(), LayerError> {
+        match event {
+            NgTransportEvent::Data { stream, payload } => {
+                if !self.is_amf_connected(amf) {
+                    warn!("Dropping NGAP PDU received on stream {} without NG connection", stream);
+                    return Ok(());
+                }
+                // Non UE-associated answers go back to this AMF
+                self.active_amf = amf;
+                self.process_downlink(payload).await.map(|_| ())
+            }
+            NgTransportEvent::AssociationLost(reason) => self.handle_association_lost(amf, &reason).await,
+        }
+    }
+
+    /// Drop the NG connection with an AMF and release the UEs it served
+    async fn handle_association_lost(&mut self, amf: usize, reason: &str) -> Result<(), LayerError> {
+        let Some(connection) = self.amfs.get_mut(amf).filter(|connection| connection.transport.is_some()) else {
+            return Ok(());
+        };
+        warn!("NG association with AMF at {} lost: {}", connection.endpoint.address, reason);
+        connection.transport = None;
+        connection.ng_connected = false;
+        connection.info = None;
+        connection.unavailable_guamis.clear();
+        connection.next_attempt = Some(Instant::now());
+
+        let ue_ids: Vec<u32> = self.ue_contexts.values()
+            .filter(|ctx| ctx.amf == Some(amf))
+            .map(|ctx| ctx.ran_ue_ngap_id)
+            .collect();
+        if !ue_ids.is_empty() {
+            info!("Releasing {} UEs of the lost NG association", ue_ids.len());
+        }
+        self.release_ues_locally(ue_ids).await
+    }
+}
+
+/// Drive the NG associations of a shared NGAP layer: PDUs from the AMFs go to
+/// the NGAP procedures, and a lost association is re-established with NG Setup
+/// every `reconnect_delay` until it comes back. Returns once the layer is shut down.
+pub async fn run_ng_association(ngap: Arc<RwLock<NgapLayer>>) {
+    let Some(mut events) = ngap.write().await.take_transport_events() else {
+        warn!("NG association is already driven by another task");
+        return;
+    };
+
+    loop {
+        let next_reconnect = {
+            let ngap_guard = ngap.read().await;
+            if ngap_guard.stopped {
+                return;
+            }
+            ngap_guard.next_reconnect()
+        };
+
+        if let Some((amf, _)) = next_reconnect.filter(|(_, at)| *at <= Instant::now()) {
+            let result = ngap.write().await.reconnect(amf).await;
+            if let Err(e) = result {
+                let delay = ngap.read().await.reconnect_delay(amf);
+                warn!("NG reconnection to AMF {} failed: {}, retrying in {}s", amf, e, delay.as_secs());
+            }
+            continue;
+        }
+
+        // Wait without holding the layer so RRC messages are still handled
+        let event = match next_reconnect {
+            Some((_, at)) => tokio::select! {
+                event = events.recv() => event,
+                _ = tokio::time::sleep_until(at) => continue,
+            },
+            None => events.recv().await,
+        };
+        let Some((amf, event)) = event else {
+            return;
+        };
+        if let Err(e) = ngap.write().await.handle_transport_event(amf, event).await {
+            error!("NGAP downlink processing error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{
+        self, BroadcastPlmnItem, Cause, Criticality, Guami, NgapPdu, NodeName, PagingDrx, PlmnSupportItem,
+        RelativeAmfCapacity, ServedGuamiItem, SupportedTaItem, TimeToWait,
+    };
+    use crate::ngap::transport::{encode_frame, read_frame, NgTransportConfig, NgTransportKind, NGAP_PPID, NON_UE_STREAM};
+    use crate::ngap::{NgapConfig, NgapProcedureCode, NgapUeContext};
+    use crate::rrc::NgapRrcMessage;
+    use common::types::SNssai;
+    use std::net::{IpAddr, SocketAddr};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn ng_setup_response(amf_name: &str) -> Vec<u8> {
+        let served_guamis = vec![ServedGuamiItem {
+            guami: Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 1, amf_pointer: 0 },
+            backup_amf_name: None,
+        }];
+        let plmn_support = vec![PlmnSupportItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }];
+        NgapPdu::successful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_AMF_NAME, Criticality::Reject, &NodeName(amf_name.to_string())).unwrap()
+            .with_ie(pdu::ID_SERVED_GUAMI_LIST, Criticality::Reject, &served_guamis).unwrap()
+            .with_ie(pdu::ID_RELATIVE_AMF_CAPACITY, Criticality::Ignore, &RelativeAmfCapacity(255)).unwrap()
+            .with_ie(pdu::ID_PLMN_SUPPORT_LIST, Criticality::Reject, &plmn_support).unwrap()
+            .encode().unwrap().to_vec()
+    }
+
+    /// Stand-in AMF: accept the association and answer NG Setup
+    async fn accept_ng_setup(listener: &TcpListener, amf_name: &str) -> TcpStream {
+        let (mut amf, _) = listener.accept().await.unwrap();
+        let (stream, ppid, request) = read_frame(&mut amf).await.unwrap().unwrap();
+        assert_eq!((stream, ppid), (NON_UE_STREAM, NGAP_PPID));
+        assert_eq!(NgapPdu::decode(&request).unwrap().procedure(), Some(NgapProcedureCode::NgSetup));
+        amf.write_all(&encode_frame(NON_UE_STREAM, NGAP_PPID, &ng_setup_response(amf_name))).await.unwrap();
+        amf
+    }
+
+    #[tokio::test]
+    async fn test_ng_association_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![listener.local_addr().unwrap().into(), listener2.local_addr().unwrap().into()],
+            local_address: "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 1,
+                broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig {
+                kind: NgTransportKind::TcpFramed,
+                reconnect_interval: Duration::from_millis(10),
+                ..Default::default()
+            },
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+
+        let (initialized, mut amf, _amf2) = tokio::join!(
+            ngap.initialize(), accept_ng_setup(&listener, "amf0"), accept_ng_setup(&listener2, "amf1"));
+        initialized.unwrap();
+        assert!(ngap.is_amf_connected(0) && ngap.is_amf_connected(1));
+        assert_eq!(ngap.amf_info(0).unwrap().amf_name, "amf0");
+        assert_eq!(ngap.amf_info(1).unwrap().amf_name, "amf1");
+        ngap.ue_contexts.insert(5, NgapUeContext { ran_ue_ngap_id: 5, amf_ue_ngap_id: Some(9), amf: Some(0), ..Default::default() });
+        ngap.ue_contexts.insert(6, NgapUeContext { ran_ue_ngap_id: 6, amf_ue_ngap_id: Some(3), amf: Some(1), ..Default::default() });
+
+        let ngap = Arc::new(RwLock::new(ngap));
+        let association = tokio::spawn(run_ng_association(Arc::clone(&ngap)));
+
+        // PDUs from the AMF reach the NGAP procedures: an undecodable one is
+        // answered with Error Indication
+        amf.write_all(&encode_frame(NON_UE_STREAM, NGAP_PPID, &[0x00])).await.unwrap();
+        let (_, _, indication) = read_frame(&mut amf).await.unwrap().unwrap();
+        let indication = NgapPdu::decode(&indication).unwrap();
+        assert_eq!(indication.procedure(), Some(NgapProcedureCode::ErrorIndication));
+        assert_eq!(indication.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::TRANSFER_SYNTAX_ERROR);
+
+        // Losing an association releases the UEs of that AMF only, and NG Setup
+        // is redone on a new one
+        drop(amf);
+        match rrc_rx.recv().await.unwrap() {
+            NgapRrcMessage::UeContextRelease { ue_id } => assert_eq!(ue_id, 5),
+            other => panic!("unexpected message {:?}", other),
+        }
+        let _amf = accept_ng_setup(&listener, "amf0").await;
+        for _ in 0..100 {
+            if ngap.read().await.is_amf_connected(0) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(ngap.read().await.is_amf_connected(0));
+        assert!(ngap.read().await.is_amf_connected(1));
+        assert_eq!(ngap.read().await.ue_contexts.keys().collect::<Vec<_>>(), vec![&6]);
+
+        ngap.write().await.shutdown().await.unwrap();
+        association.abort();
+    }
+
+    #[tokio::test]
+    async fn test_ng_association_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![listener.local_addr().unwrap().into()],
+            local_address: "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 1,
+                broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig {
+                kind: NgTransportKind::TcpFramed,
+                reconnect_interval: Duration::from_millis(10),
+                ..Default::default()
+            },
+            handover_targets: Vec::new(),
+        });
+
+        // NG Setup Failure: the Time to Wait of the AMF delays the next attempt
+        let reject = async {
+            let (mut amf, _) = listener.accept().await.unwrap();
+            read_frame(&mut amf).await.unwrap().unwrap();
+            let failure = NgapPdu::unsuccessful(NgapProcedureCode::NgSetup)
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNKNOWN_PLMN).unwrap()
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V2s).unwrap();
+            amf.write_all(&encode_frame(NON_UE_STREAM, NGAP_PPID, &failure.encode().unwrap())).await.unwrap();
+            amf
+        };
+        let (result, _amf) = tokio::join!(ngap.reconnect(0), reject);
+        assert!(matches!(result, Err(LayerError::ProcessingError(_))));
+        assert!(!ngap.is_amf_connected(0));
+        assert!(ngap.amfs[0].transport.is_none());
+        assert_eq!(ngap.reconnect_delay(0), Duration::from_secs(2));
+        assert!(ngap.amfs[0].next_attempt.is_some());
+
+        // Events of an AMF without NG connection are dropped
+        ngap.handle_transport_event(0, NgTransportEvent::Data { stream: 0, payload: ng_setup_response("amf0").into() })
+            .await.unwrap();
+        ngap.handle_transport_event(0, NgTransportEvent::AssociationLost("gone".to_string())).await.unwrap();
+
+        // AMF not listening any more
+        drop(listener);
+        ngap.amfs[0].next_attempt = None;
+        assert!(ngap.reconnect(0).await.is_err());
+        assert_eq!(ngap.reconnect_delay(0), Duration::from_millis(10));
+        assert!(ngap.amfs[0].next_attempt.is_some());
+
+        // No reconnection once shut down
+        ngap.shutdown().await.unwrap();
+        assert!(matches!(ngap.reconnect(0).await, Err(LayerError::InvalidState(_))));
+    }
+}
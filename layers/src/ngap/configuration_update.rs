@@ -0,0 +1,210 @@
+//! RAN Configuration Update (3GPP TS 38.413 section 8.7.2)
+//!
+//! Sends the new Supported TA List to every connected AMF when the served
+//! tracking areas or slices change, and asks the AMF to release the UEs with
+//! PDU sessions on slices the cell no longer supports.
+
+use super::pdu::{self, Cause, Criticality, NgapPdu, SupportedTaItem, TimeToWait};
+use super::transport::NON_UE_STREAM;
+use super::{NgapLayer, NgapProcedureCode};
+use crate::LayerError;
+use common::types::SNssai;
+use tracing::{info, warn};
+
+impl NgapLayer {
+    /// Slices supported in the tracking area and PLMN of the served cell
+    fn cell_slices(&self) -> Vec<SNssai> {
+        self.config.supported_tas.iter()
+            .filter(|ta| ta.tac == self.config.tac)
+            .flat_map(|ta| &ta.broadcast_plmns)
+            .filter(|plmn| plmn.plmn_id == self.config.plmn_id)
+            .flat_map(|plmn| plmn.slices.iter().cloned())
+            .collect()
+    }
+
+    /// Change the served tracking areas and slices, e.g. after a configuration reload
+    ///
+    /// Every connected AMF gets a RAN Configuration Update. UEs with a PDU session
+    /// on a slice that is no longer supported are released.
+    pub async fn update_supported_tas(&mut self, supported_tas: Vec<SupportedTaItem>) -> Result<(), LayerError> {
+        if supported_tas.is_empty() {
+            return Err(LayerError::ConfigurationError("At least one supported TA is required".to_string()));
+        }
+        if supported_tas == self.config.supported_tas {
+            return Ok(());
+        }
+        self.config.supported_tas = supported_tas;
+
+        let pdu = self.build_ran_configuration_update()?;
+        for amf in 0..self.amfs.len() {
+            if !self.is_amf_connected(amf) {
+                continue;
+            }
+            info!("Sending RAN Configuration Update to AMF {}", amf);
+            if let Err(e) = self.send_to_amf(amf, NON_UE_STREAM, pdu.clone()).await {
+                warn!("Failed to send RAN Configuration Update to AMF {}: {}", amf, e);
+            }
+        }
+
+        let slices = self.cell_slices();
+        let mut released: Vec<(u32, Vec<u8>)> = self.ue_contexts.values()
+            .filter(|ctx| ctx.pdu_sessions.values().any(|session| !slices.contains(&session.s_nssai)))
+            .map(|ctx| {
+                let mut pdu_session_ids: Vec<u8> = ctx.pdu_sessions.keys().copied().collect();
+                pdu_session_ids.sort_unstable();
+                (ctx.ran_ue_ngap_id, pdu_session_ids)
+            })
+            .collect();
+        released.sort_unstable();
+        for (ran_ue_ngap_id, pdu_session_ids) in released {
+            info!("Slice of RAN UE NGAP ID {} no longer supported, requesting release", ran_ue_ngap_id);
+            self.request_ue_context_release(ran_ue_ngap_id, Cause::SLICE_NOT_SUPPORTED, &pdu_session_ids).await?;
+        }
+        Ok(())
+    }
+
+    /// Build RAN Configuration Update with the Supported TA List
+    pub(super) fn build_ran_configuration_update(&self) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::RanConfigurationUpdate)
+            .with_ie(pdu::ID_SUPPORTED_TA_LIST, Criticality::Reject, &self.config.supported_tas)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle RAN Configuration Update Acknowledge
+    pub(super) fn handle_ran_configuration_update_acknowledge(&self) -> Result<(), LayerError> {
+        info!("RAN Configuration Update acknowledged by AMF {}", self.active_amf);
+        Ok(())
+    }
+
+    /// Handle RAN Configuration Update Failure
+    pub(super) fn handle_ran_configuration_update_failure(&self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        match pdu.optional_ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT)? {
+            Some(time_to_wait) => warn!("RAN Configuration Update rejected by AMF {} (cause {:?}), retry allowed after {}s",
+                                        self.active_amf, cause, time_to_wait.seconds()),
+            None => warn!("RAN Configuration Update rejected by AMF {}: {:?}", self.active_amf, cause),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::context::PduSessionContext;
+    use crate::ngap::pdu::{BroadcastPlmnItem, GtpTunnel, PagingDrx, PduSessionType};
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use crate::rrc::NgapRrcMessage;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn supported_tas(slices: Vec<SNssai>) -> Vec<SupportedTaItem> {
+        vec![SupportedTaItem { tac: 1, broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices }] }]
+    }
+
+    #[tokio::test]
+    async fn test_ran_configuration_update() {
+        let embb = SNssai { sst: 1, sd: None };
+        let urllc = SNssai { sst: 2, sd: None };
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: supported_tas(vec![embb.clone(), urllc.clone()]),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        for (ran_ue_ngap_id, slice) in [(1, embb.clone()), (2, urllc.clone())] {
+            let mut ctx = NgapUeContext { ran_ue_ngap_id, amf: Some(0), ..Default::default() };
+            ctx.pdu_sessions.insert(1, PduSessionContext {
+                s_nssai: slice,
+                pdu_session_type: PduSessionType::Ipv4,
+                ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), teid: 1 },
+                dl_teid: ran_ue_ngap_id,
+                qos_flows: Vec::new(),
+                session_ambr: None,
+            });
+            ngap.ue_contexts.insert(ran_ue_ngap_id, ctx);
+        }
+
+        // Dropping URLLC releases the UE using it; without an NG connection to
+        // the AMF the release is local
+        ngap.update_supported_tas(supported_tas(vec![embb.clone()])).await.unwrap();
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 2 }));
+        assert!(rrc_rx.try_recv().is_err());
+
+        let update = NgapPdu::decode(&ngap.build_ran_configuration_update().unwrap()).unwrap();
+        assert_eq!(update.procedure(), Some(NgapProcedureCode::RanConfigurationUpdate));
+        assert_eq!(update.ie::<Vec<SupportedTaItem>>(pdu::ID_SUPPORTED_TA_LIST).unwrap(), supported_tas(vec![embb]));
+
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::RanConfigurationUpdate)
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNKNOWN_PLMN).unwrap()
+            .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V5s).unwrap();
+        ngap.handle_ran_configuration_update_failure(&NgapPdu::decode(&failure.encode().unwrap()).unwrap()).unwrap();
+        assert!(ngap.update_supported_tas(Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ran_configuration_update_errors() {
+        let embb = SNssai { sst: 1, sd: None };
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: supported_tas(vec![embb.clone()]),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let mut ctx = NgapUeContext { ran_ue_ngap_id: 1, amf: Some(0), ..Default::default() };
+        ctx.pdu_sessions.insert(1, PduSessionContext {
+            s_nssai: embb.clone(),
+            pdu_session_type: PduSessionType::Ipv4,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), teid: 1 },
+            dl_teid: 1,
+            qos_flows: Vec::new(),
+            session_ambr: None,
+        });
+        ngap.ue_contexts.insert(1, ctx);
+
+        // An empty list is refused and the same list changes nothing
+        assert!(matches!(ngap.update_supported_tas(Vec::new()).await, Err(LayerError::ConfigurationError(_))));
+        assert_eq!(ngap.config.supported_tas, supported_tas(vec![embb.clone()]));
+        ngap.update_supported_tas(supported_tas(vec![embb.clone()])).await.unwrap();
+        assert!(rrc_rx.try_recv().is_err());
+
+        // The slice moves to a tracking area the cell is not in
+        let other_ta = vec![SupportedTaItem { tac: 2, broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![embb] }] }];
+        ngap.update_supported_tas(other_ta).await.unwrap();
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 1 }));
+
+        // Failure without Cause, and with a Time to Wait that does not decode
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::RanConfigurationUpdate);
+        assert!(matches!(ngap.handle_ran_configuration_update_failure(&failure), Err(LayerError::ProcessingError(_))));
+        let mut failure = NgapPdu::unsuccessful(NgapProcedureCode::RanConfigurationUpdate)
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNKNOWN_PLMN).unwrap()
+            .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V5s).unwrap();
+        failure.ies[1].value = bytes::Bytes::from_static(&[0xE0]);
+        assert!(ngap.handle_ran_configuration_update_failure(&failure).is_err());
+    }
+}
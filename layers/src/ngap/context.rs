@@ -0,0 +1,580 @@
+//! UE context setup and PDU session resource setup
+//!
+//! Implements Initial Context Setup (3GPP TS 38.413 section 8.3.1) and PDU
+//! Session Resource Setup (section 8.2.1). The requests are checked against the
+//! served slices, gNB-side GTP-U tunnels are allocated and opened in the GTP-U
+//! endpoint, and the bearers are set up by RRC before the Response/Failure is
+//! returned to the AMF.
+
+use super::pdu::{
+    self, AllowedNssai, AmfUeNgapId, Cause, Criticality, GtpTunnel, Guami, NgapPdu, PduSessionResourceItem,
+    PduSessionResourceSetupItem, PduSessionResourceSetupResponseTransfer, PduSessionResourceSetupUnsuccessfulTransfer,
+    PduSessionType, RanUeNgapId, RrcInactiveTransitionReportRequest, SecurityKey, UeSecurityCapabilities,
+};
+use super::{NgapLayer, NgapProcedureCode};
+use crate::gtpu::NgapGtpuMessage;
+use crate::rrc::{NgapRrcMessage, PduSessionResource, RrcReleaseCause};
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::{AggregateMaximumBitRate, QosFlowDescriptor, SNssai};
+use tracing::{debug, error, info, warn};
+
+/// PDU session resources held for a UE
+#[derive(Debug, Clone, PartialEq)]
+pub struct PduSessionContext {
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+    /// PDU session type
+    pub pdu_session_type: PduSessionType,
+    /// UPF endpoint of the NG-U tunnel
+    pub ul_tunnel: GtpTunnel,
+    /// gNB-side TEID of the NG-U tunnel
+    pub dl_teid: u32,
+    /// QoS flows of the PDU session
+    pub qos_flows: Vec<QosFlowDescriptor>,
+    /// PDU Session Aggregate Maximum Bit Rate
+    pub session_ambr: Option<AggregateMaximumBitRate>,
+}
+
+impl NgapLayer {
+    /// Handle Initial Context Setup Request from the AMF
+    pub(super) async fn handle_initial_context_setup_request(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let guami = pdu.ie::<Guami>(pdu::ID_GUAMI)?;
+        let allowed_nssai = pdu.ie::<AllowedNssai>(pdu::ID_ALLOWED_NSSAI)?;
+        let security_capabilities = pdu.ie::<UeSecurityCapabilities>(pdu::ID_UE_SECURITY_CAPABILITIES)?;
+        let security_key = pdu.ie::<SecurityKey>(pdu::ID_SECURITY_KEY)?;
+        let ue_ambr = pdu.optional_ie::<AggregateMaximumBitRate>(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE)?;
+        let items = pdu.optional_ie::<Vec<PduSessionResourceSetupItem>>(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_REQ)?
+            .unwrap_or_default();
+        let ue_radio_capability = pdu.optional_ie::<Bytes>(pdu::ID_UE_RADIO_CAPABILITY)?;
+        let nas_pdu = pdu.optional_ie::<Bytes>(pdu::ID_NAS_PDU)?;
+        let report_request = pdu.optional_ie::<RrcInactiveTransitionReportRequest>(
+            pdu::ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        ue_context.amf_ue_ngap_id = Some(amf_ue_ngap_id);
+        ue_context.guami = Some(guami);
+        ue_context.allowed_nssai = allowed_nssai.0;
+        ue_context.security_capabilities = Some(security_capabilities);
+        if ue_ambr.is_some() {
+            ue_context.ue_ambr = ue_ambr;
+        }
+        info!("Initial Context Setup Request for RAN UE NGAP ID {} with {} PDU sessions", ran_ue_ngap_id, items.len());
+        if let Some(report_request) = report_request {
+            self.set_rrc_inactive_transition_report_request(ran_ue_ngap_id, report_request);
+        }
+
+        let sessions = self.admit_pdu_sessions(ran_ue_ngap_id, items)?;
+        let admitted: Vec<u8> = sessions.iter().map(|session| session.pdu_session_id).collect();
+        self.create_gtpu_tunnels(ran_ue_ngap_id, &admitted).await;
+        self.send_to_rrc(NgapRrcMessage::InitialContextSetup {
+            ue_id: ran_ue_ngap_id,
+            security_key: security_key.0,
+            nr_encryption_algorithms: security_capabilities.nr_encryption_algorithms,
+            nr_integrity_algorithms: security_capabilities.nr_integrity_algorithms,
+            sessions,
+            nas_pdu,
+            ue_radio_capability,
+        }).await
+    }
+
+    /// Handle PDU Session Resource Setup Request from the AMF
+    pub(super) async fn handle_pdu_session_resource_setup_request(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let nas_pdu = pdu.optional_ie::<Bytes>(pdu::ID_NAS_PDU)?;
+        let items = pdu.ie::<Vec<PduSessionResourceSetupItem>>(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_REQ)?;
+        let ue_ambr = pdu.optional_ie::<AggregateMaximumBitRate>(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .filter(|ctx| ctx.amf_ue_ngap_id == Some(amf_ue_ngap_id))
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("Unknown UE (AMF UE NGAP ID {}, RAN UE NGAP ID {})", amf_ue_ngap_id, ran_ue_ngap_id)))?;
+        if ue_ambr.is_some() {
+            ue_context.ue_ambr = ue_ambr;
+        }
+        info!("PDU Session Resource Setup Request for RAN UE NGAP ID {} with {} PDU sessions", ran_ue_ngap_id, items.len());
+
+        let sessions = self.admit_pdu_sessions(ran_ue_ngap_id, items)?;
+        let admitted: Vec<u8> = sessions.iter().map(|session| session.pdu_session_id).collect();
+        self.create_gtpu_tunnels(ran_ue_ngap_id, &admitted).await;
+        self.send_to_rrc(NgapRrcMessage::PduSessionResourceSetup {
+            ue_id: ran_ue_ngap_id,
+            sessions,
+            nas_pdu,
+        }).await
+    }
+
+    /// Check the requested PDU sessions, allocate their gNB-side GTP-U tunnels and
+    /// return the sessions to set up over the radio
+    ///
+    /// Rejected sessions are kept with their cause for the response.
+    fn admit_pdu_sessions(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        items: Vec<PduSessionResourceSetupItem>,
+    ) -> Result<Vec<PduSessionResource>, LayerError> {
+        let mut sessions = Vec::with_capacity(items.len());
+
+        for item in items {
+            let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+                .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+            let id = item.pdu_session_id;
+
+            let rejection = if ue_context.pdu_sessions.contains_key(&id)
+                || ue_context.rejected_pdu_sessions.iter().any(|(rejected, _)| *rejected == id) {
+                Some(Cause::MULTIPLE_PDU_SESSION_ID_INSTANCES)
+            } else if !self.config.supported_tas.iter()
+                .flat_map(|ta| &ta.broadcast_plmns)
+                .any(|plmn| plmn.slices.contains(&item.s_nssai)) {
+                Some(Cause::SLICE_NOT_SUPPORTED)
+            } else {
+                None
+            };
+            if let Some(cause) = rejection {
+                warn!("Rejecting PDU session {} of RAN UE NGAP ID {}: {:?}", id, ran_ue_ngap_id, cause);
+                ue_context.rejected_pdu_sessions.push((id, cause));
+                continue;
+            }
+
+            let dl_teid = self.next_gtpu_teid;
+            self.next_gtpu_teid = self.next_gtpu_teid.checked_add(1).unwrap_or(1);
+            let transfer = item.transfer;
+            debug!("PDU session {} ({:?}, {:?}): UPF {}/{:#x}, gNB TEID {:#x}, QFIs {:?}",
+                   id, item.s_nssai, transfer.pdu_session_type, transfer.ul_tunnel.transport_layer_address,
+                   transfer.ul_tunnel.teid, dl_teid, transfer.qos_flows.iter().map(|flow| flow.qfi).collect::<Vec<_>>());
+
+            let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+                .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+            sessions.push(PduSessionResource {
+                pdu_session_id: id,
+                qos_flows: transfer.qos_flows.iter().map(|flow| flow.qfi).collect(),
+                nas_pdu: item.nas_pdu,
+                s_nssai: item.s_nssai.clone(),
+            });
+            ue_context.pdu_sessions.insert(id, PduSessionContext {
+                s_nssai: item.s_nssai,
+                pdu_session_type: transfer.pdu_session_type,
+                ul_tunnel: transfer.ul_tunnel,
+                dl_teid,
+                qos_flows: transfer.qos_flows,
+                session_ambr: transfer.session_ambr,
+            });
+        }
+        Ok(sessions)
+    }
+
+    /// Collect the outcome of a PDU session setup: setup response transfers for the
+    /// sessions set up by RRC, unsuccessful transfers for the others
+    fn pdu_session_setup_outcome(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(Vec<PduSessionResourceItem>, Vec<PduSessionResourceItem>), LayerError> {
+        let gtpu_address = self.config.gtpu_address;
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+
+        let mut setup = Vec::with_capacity(succeeded.len());
+        for id in succeeded {
+            let session = ue_context.pdu_sessions.get(id)
+                .ok_or_else(|| LayerError::InvalidState(format!("Unknown PDU session {}", id)))?;
+            setup.push(PduSessionResourceItem::new(*id, &PduSessionResourceSetupResponseTransfer {
+                dl_tunnel: GtpTunnel {
+                    transport_layer_address: gtpu_address,
+                    teid: session.dl_teid,
+                },
+                qos_flows: session.qos_flows.iter().map(|flow| flow.qfi).collect(),
+            })?);
+        }
+
+        let mut rejected = std::mem::take(&mut ue_context.rejected_pdu_sessions);
+        for id in failed {
+            ue_context.pdu_sessions.remove(id);
+            rejected.push((*id, Cause::RADIO_RESOURCES_NOT_AVAILABLE));
+        }
+        let failed = rejected.into_iter()
+            .map(|(id, cause)| PduSessionResourceItem::new(id, &PduSessionResourceSetupUnsuccessfulTransfer { cause }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((setup, failed))
+    }
+
+    /// Send Initial Context Setup Response
+    pub(super) async fn send_initial_context_setup_response(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        self.release_gtpu_tunnels(ran_ue_ngap_id, failed).await;
+        let (setup, failed) = self.pdu_session_setup_outcome(ran_ue_ngap_id, succeeded, failed)?;
+
+        info!("Sending Initial Context Setup Response for RAN UE NGAP ID {} ({} PDU sessions set up, {} failed)",
+              ran_ue_ngap_id, setup.len(), failed.len());
+        let pdu = Self::build_initial_context_setup_response(amf_ue_ngap_id, ran_ue_ngap_id, setup, failed)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await?;
+        self.send_single_rrc_connected_state_report(ran_ue_ngap_id).await
+    }
+
+    /// Build Initial Context Setup Response
+    pub(super) fn build_initial_context_setup_response(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        setup: Vec<PduSessionResourceItem>,
+        failed: Vec<PduSessionResourceItem>,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::successful(NgapProcedureCode::InitialContextSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !setup.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_RES, Criticality::Ignore, &setup)?;
+        }
+        if !failed.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_CXT_RES, Criticality::Ignore, &failed)?;
+        }
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Send Initial Context Setup Failure, releasing the PDU sessions of the request
+    pub(super) async fn send_initial_context_setup_failure(&mut self, ran_ue_ngap_id: u32, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        let cause = Cause::from(cause);
+        let released = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .map(|ctx| {
+                ctx.rejected_pdu_sessions.clear();
+                ctx.pdu_sessions.drain().map(|(id, _)| id).collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        self.release_gtpu_tunnels(ran_ue_ngap_id, &released).await;
+        let failed = released.into_iter()
+            .map(|id| PduSessionResourceItem::new(id, &PduSessionResourceSetupUnsuccessfulTransfer { cause }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        warn!("Sending Initial Context Setup Failure for RAN UE NGAP ID {}: {:?}", ran_ue_ngap_id, cause);
+        let pdu = Self::build_initial_context_setup_failure(amf_ue_ngap_id, ran_ue_ngap_id, failed, cause)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build Initial Context Setup Failure
+    pub(super) fn build_initial_context_setup_failure(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        failed: Vec<PduSessionResourceItem>,
+        cause: Cause,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::unsuccessful(NgapProcedureCode::InitialContextSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !failed.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_CXT_FAIL, Criticality::Ignore, &failed)?;
+        }
+        pdu.add_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Send PDU Session Resource Setup Response
+    pub(super) async fn send_pdu_session_resource_setup_response(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        self.release_gtpu_tunnels(ran_ue_ngap_id, failed).await;
+        let (setup, failed) = self.pdu_session_setup_outcome(ran_ue_ngap_id, succeeded, failed)?;
+
+        info!("Sending PDU Session Resource Setup Response for RAN UE NGAP ID {} ({} set up, {} failed)",
+              ran_ue_ngap_id, setup.len(), failed.len());
+        let pdu = Self::build_pdu_session_resource_setup_response(amf_ue_ngap_id, ran_ue_ngap_id, setup, failed)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build PDU Session Resource Setup Response
+    pub(super) fn build_pdu_session_resource_setup_response(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        setup: Vec<PduSessionResourceItem>,
+        failed: Vec<PduSessionResourceItem>,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::successful(NgapProcedureCode::PduSessionResourceSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !setup.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_RES, Criticality::Ignore, &setup)?;
+        }
+        if !failed.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_SU_RES, Criticality::Ignore, &failed)?;
+        }
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// AMF UE NGAP ID of a UE with an established NG connection
+    pub(super) fn amf_ue_ngap_id(&self, ran_ue_ngap_id: u32) -> Result<u64, LayerError> {
+        self.ue_contexts.get(&ran_ue_ngap_id)
+            .and_then(|ctx| ctx.amf_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No AMF UE NGAP ID for RAN UE NGAP ID {}", ran_ue_ngap_id)))
+    }
+
+    /// Have the GTP-U endpoint open the N3 tunnels of admitted PDU sessions
+    ///
+    /// Tunnels are opened before the radio bearers so that a gNB-CU-UP behind
+    /// E1 knows the NG-U endpoints when RRC sets up the DRBs.
+    pub(super) async fn create_gtpu_tunnels(&self, ran_ue_ngap_id: u32, pdu_session_ids: &[u8]) {
+        let Some(ue_context) = self.ue_contexts.get(&ran_ue_ngap_id) else {
+            return;
+        };
+        for id in pdu_session_ids {
+            if let Some(session) = ue_context.pdu_sessions.get(id) {
+                self.send_to_gtpu(NgapGtpuMessage::CreateTunnel {
+                    ue_id: ran_ue_ngap_id,
+                    pdu_session_id: *id,
+                    dl_teid: session.dl_teid,
+                    ul_tunnel: session.ul_tunnel,
+                }).await;
+            }
+        }
+    }
+
+    /// Close the N3 tunnels of PDU sessions that were not set up
+    async fn release_gtpu_tunnels(&self, ran_ue_ngap_id: u32, pdu_session_ids: &[u8]) {
+        if !pdu_session_ids.is_empty() {
+            self.send_to_gtpu(NgapGtpuMessage::ReleaseTunnels {
+                ue_id: ran_ue_ngap_id,
+                pdu_session_ids: pdu_session_ids.to_vec(),
+            }).await;
+        }
+    }
+
+    /// Pass a message to the GTP-U endpoint
+    pub(super) async fn send_to_gtpu(&self, message: NgapGtpuMessage) {
+        if let Some(gtpu_tx) = &self.gtpu_tx {
+            if let Err(e) = gtpu_tx.send(message).await {
+                error!("Failed to send message to GTP-U: {}", e);
+            }
+        } else {
+            debug!("No GTP-U channel configured, dropping {:?}", message);
+        }
+    }
+
+    /// Pass a message to RRC
+    pub(super) async fn send_to_rrc(&self, message: NgapRrcMessage) -> Result<(), LayerError> {
+        match &self.rrc_tx {
+            Some(rrc_tx) => rrc_tx.send(message).await
+                .map_err(|_| LayerError::ProcessingError("RRC channel closed".into())),
+            None => Err(LayerError::InvalidState("No RRC channel configured".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{BroadcastPlmnItem, PagingDrx, PduSessionResourceSetupRequestTransfer, SupportedTaItem};
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use common::types::{AllocationRetentionPriority, FiveQi, QosCharacteristics};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn setup_item(pdu_session_id: u8, s_nssai: SNssai) -> PduSessionResourceSetupItem {
+        PduSessionResourceSetupItem {
+            pdu_session_id,
+            nas_pdu: Some(Bytes::from_static(&[0x7E, 0x00, 0x68])),
+            s_nssai,
+            transfer: PduSessionResourceSetupRequestTransfer {
+                session_ambr: Some(AggregateMaximumBitRate { dl: 1_000_000_000, ul: 500_000_000 }),
+                ul_tunnel: GtpTunnel {
+                    transport_layer_address: IpAddr::V4(Ipv4Addr::new(10, 45, 0, 1)),
+                    teid: 0x0000_0101,
+                },
+                pdu_session_type: PduSessionType::Ipv4,
+                qos_flows: vec![QosFlowDescriptor {
+                    qfi: 1,
+                    characteristics: QosCharacteristics::NonDynamic { five_qi: FiveQi::DEFAULT, priority_level: None },
+                    arp: AllocationRetentionPriority { priority_level: 8, may_trigger_pre_emption: false, pre_emptable: false },
+                    gbr: None,
+                }],
+            },
+        }
+    }
+
+    fn test_layer() -> NgapLayer {
+        NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 1,
+                broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::V4(Ipv4Addr::new(10, 53, 1, 2)),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_initial_context_setup() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let (gtpu_tx, mut gtpu_rx) = mpsc::channel(10);
+        ngap.set_gtpu_channel(gtpu_tx);
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, ..Default::default() });
+
+        // Session 1 is admitted, session 2 uses a slice we do not serve and session 1
+        // appears twice
+        let key = [0x5A; 32];
+        let request = NgapPdu::initiating(NgapProcedureCode::InitialContextSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_GUAMI, Criticality::Reject, &Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 1, amf_pointer: 0 }).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_REQ, Criticality::Reject, &vec![
+                setup_item(1, SNssai { sst: 1, sd: None }),
+                setup_item(2, SNssai { sst: 2, sd: Some(1) }),
+                setup_item(1, SNssai { sst: 1, sd: None }),
+            ]).unwrap()
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(vec![SNssai { sst: 1, sd: None }])).unwrap()
+            .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Reject, &UeSecurityCapabilities {
+                nr_encryption_algorithms: 0xE000,
+                nr_integrity_algorithms: 0xE000,
+                ..Default::default()
+            }).unwrap()
+            .with_ie(pdu::ID_SECURITY_KEY, Criticality::Reject, &SecurityKey(key)).unwrap()
+            .with_ie(pdu::ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST, Criticality::Ignore,
+                     &RrcInactiveTransitionReportRequest::SubsequentStateTransitionReport).unwrap();
+        let request = NgapPdu::decode(&request.encode().unwrap()).unwrap();
+        ngap.handle_initial_context_setup_request(&request).await.unwrap();
+
+        match rrc_rx.try_recv().unwrap() {
+            NgapRrcMessage::InitialContextSetup { ue_id, security_key, nr_encryption_algorithms, sessions, .. } => {
+                assert_eq!(ue_id, 1000);
+                assert_eq!(security_key, key);
+                assert_eq!(nr_encryption_algorithms, 0xE000);
+                assert_eq!(sessions.len(), 1);
+                assert_eq!(sessions[0].pdu_session_id, 1);
+                assert_eq!(sessions[0].qos_flows, vec![1]);
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+        let ue_context = &ngap.ue_contexts[&1000];
+        assert_eq!(ue_context.amf_ue_ngap_id, Some(7));
+        assert_eq!(ue_context.rrc_inactive_transition_report_request,
+                   Some(RrcInactiveTransitionReportRequest::SubsequentStateTransitionReport));
+        assert_eq!(ue_context.pdu_sessions[&1].ul_tunnel.teid, 0x0000_0101);
+        let dl_teid = ue_context.pdu_sessions[&1].dl_teid;
+
+        // The GTP-U endpoint opens the tunnel of the admitted session before RRC sets it up
+        assert!(matches!(gtpu_rx.try_recv().unwrap(),
+                         NgapGtpuMessage::CreateTunnel { ue_id: 1000, pdu_session_id: 1, dl_teid: teid, ref ul_tunnel }
+                         if teid == dl_teid && ul_tunnel.teid == 0x0000_0101));
+        assert!(gtpu_rx.try_recv().is_err());
+
+        // Response carries our tunnel for session 1 and the rejected sessions
+        let (setup, failed) = ngap.pdu_session_setup_outcome(1000, &[1], &[]).unwrap();
+        let response = NgapLayer::build_initial_context_setup_response(7, 1000, setup, failed).unwrap();
+        let response = NgapPdu::decode(&response).unwrap();
+        assert_eq!(response.procedure(), Some(NgapProcedureCode::InitialContextSetup));
+        let setup = response.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_RES).unwrap();
+        let transfer = setup[0].decode_transfer::<PduSessionResourceSetupResponseTransfer>().unwrap();
+        assert_eq!(transfer.dl_tunnel, GtpTunnel { transport_layer_address: ngap.config.gtpu_address, teid: dl_teid });
+        assert_eq!(transfer.qos_flows, vec![1]);
+        let failed = response.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_CXT_RES).unwrap();
+        let causes: Vec<(u8, Cause)> = failed.iter()
+            .map(|item| (item.pdu_session_id, item.decode_transfer::<PduSessionResourceSetupUnsuccessfulTransfer>().unwrap().cause))
+            .collect();
+        assert_eq!(causes, vec![(2, Cause::SLICE_NOT_SUPPORTED), (1, Cause::MULTIPLE_PDU_SESSION_ID_INSTANCES)]);
+
+        // A second session that RRC fails to set up is released again
+        let request = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_REQ, Criticality::Reject,
+                     &vec![setup_item(5, SNssai { sst: 1, sd: None })]).unwrap();
+        ngap.handle_pdu_session_resource_setup_request(&request).await.unwrap();
+        assert!(matches!(rrc_rx.try_recv().unwrap(),
+                         NgapRrcMessage::PduSessionResourceSetup { ue_id: 1000, ref sessions, .. } if sessions[0].nas_pdu.is_some()));
+        assert_ne!(ngap.ue_contexts[&1000].pdu_sessions[&5].dl_teid, dl_teid);
+        assert!(matches!(gtpu_rx.try_recv().unwrap(), NgapGtpuMessage::CreateTunnel { pdu_session_id: 5, .. }));
+        let (setup, failed) = ngap.pdu_session_setup_outcome(1000, &[], &[5]).unwrap();
+        assert!(setup.is_empty());
+        assert_eq!(failed[0].decode_transfer::<PduSessionResourceSetupUnsuccessfulTransfer>().unwrap().cause,
+                   Cause::RADIO_RESOURCES_NOT_AVAILABLE);
+        assert!(!ngap.ue_contexts[&1000].pdu_sessions.contains_key(&5));
+
+        let failure = NgapLayer::build_initial_context_setup_failure(7, 1000, Vec::new(), Cause::ALGORITHMS_NOT_SUPPORTED).unwrap();
+        let failure = NgapPdu::decode(&failure).unwrap();
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::ALGORITHMS_NOT_SUPPORTED);
+    }
+
+    #[tokio::test]
+    async fn test_initial_context_setup_failures() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let (gtpu_tx, mut gtpu_rx) = mpsc::channel(10);
+        ngap.set_gtpu_channel(gtpu_tx);
+        let request = |ran_ue_ngap_id, with_key: bool| {
+            let mut request = NgapPdu::initiating(NgapProcedureCode::InitialContextSetup)
+                .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+                .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id)).unwrap()
+                .with_ie(pdu::ID_GUAMI, Criticality::Reject, &Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 1, amf_pointer: 0 }).unwrap()
+                .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_REQ, Criticality::Reject,
+                         &vec![setup_item(1, SNssai { sst: 1, sd: None })]).unwrap()
+                .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(vec![SNssai { sst: 1, sd: None }])).unwrap()
+                .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Reject, &UeSecurityCapabilities::default()).unwrap();
+            if with_key {
+                request.add_ie(pdu::ID_SECURITY_KEY, Criticality::Reject, &SecurityKey([0x5A; 32])).unwrap();
+            }
+            request
+        };
+
+        // Unknown UE and missing Security Key
+        assert!(matches!(ngap.handle_initial_context_setup_request(&request(1000, true)).await,
+                         Err(LayerError::InvalidState(_))));
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, ..Default::default() });
+        assert!(matches!(ngap.handle_initial_context_setup_request(&request(1000, false)).await,
+                         Err(LayerError::ProcessingError(_))));
+        assert!(rrc_rx.try_recv().is_err());
+        assert!(ngap.ue_contexts[&1000].pdu_sessions.is_empty());
+
+        // PDU Session Resource Setup with another AMF UE NGAP ID
+        ngap.handle_initial_context_setup_request(&request(1000, true)).await.unwrap();
+        rrc_rx.try_recv().unwrap();
+        assert!(matches!(gtpu_rx.try_recv().unwrap(), NgapGtpuMessage::CreateTunnel { pdu_session_id: 1, .. }));
+        let setup = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(8)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_REQ, Criticality::Reject,
+                     &vec![setup_item(5, SNssai { sst: 1, sd: None })]).unwrap();
+        assert!(matches!(ngap.handle_pdu_session_resource_setup_request(&setup).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(!ngap.ue_contexts[&1000].pdu_sessions.contains_key(&5));
+
+        // A failed setup releases the sessions of the request, here without an NG connection
+        assert!(ngap.send_initial_context_setup_failure(1000, RrcReleaseCause::AlgorithmsNotSupported).await.is_err());
+        assert!(ngap.ue_contexts[&1000].pdu_sessions.is_empty());
+        assert!(matches!(gtpu_rx.try_recv().unwrap(),
+                         NgapGtpuMessage::ReleaseTunnels { ue_id: 1000, ref pdu_session_ids } if *pdu_session_ids == [1]));
+
+        // Responses need the AMF UE NGAP ID
+        assert!(matches!(ngap.send_initial_context_setup_response(1001, &[], &[]).await,
+                         Err(LayerError::InvalidState(_))));
+    }
+}
+
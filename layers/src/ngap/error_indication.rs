@@ -0,0 +1,146 @@
+//! Error Indication (3GPP TS 38.413 section 8.7.5)
+//!
+//! Reports protocol errors to the AMF, in particular UE-associated messages
+//! whose NGAP IDs do not match a UE context, and logs the errors reported by
+//! the AMF.
+
+use super::pdu::{self, AmfUeNgapId, Cause, Criticality, NgapPdu, RanUeNgapId};
+use super::{NgapLayer, NgapProcedureCode};
+use crate::LayerError;
+use tracing::{info, warn};
+
+impl NgapLayer {
+    /// Send Error Indication, with the UE NGAP IDs for a UE-associated error
+    pub(super) async fn send_error_indication(
+        &self,
+        amf_ue_ngap_id: Option<u64>,
+        ran_ue_ngap_id: Option<u32>,
+        cause: Cause,
+    ) -> Result<(), LayerError> {
+        warn!("Sending Error Indication (AMF UE NGAP ID {:?}, RAN UE NGAP ID {:?}): {:?}",
+              amf_ue_ngap_id, ran_ue_ngap_id, cause);
+        let pdu = Self::build_error_indication(amf_ue_ngap_id, ran_ue_ngap_id, cause)?;
+        match ran_ue_ngap_id {
+            Some(ran_ue_ngap_id) => self.send_ue_pdu(ran_ue_ngap_id, pdu).await,
+            None => self.send_pdu(pdu).await,
+        }
+    }
+
+    /// Build Error Indication
+    pub(super) fn build_error_indication(
+        amf_ue_ngap_id: Option<u64>,
+        ran_ue_ngap_id: Option<u32>,
+        cause: Cause,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::initiating(NgapProcedureCode::ErrorIndication);
+        if let Some(amf_ue_ngap_id) = amf_ue_ngap_id {
+            pdu.add_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?;
+        }
+        if let Some(ran_ue_ngap_id) = ran_ue_ngap_id {
+            pdu.add_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        }
+        pdu.add_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle Error Indication from the AMF
+    pub(super) fn handle_error_indication(&self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.optional_ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.map(|id| id.0);
+        let ran_ue_ngap_id = pdu.optional_ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.map(|id| id.0);
+        let cause = pdu.optional_ie::<Cause>(pdu::ID_CAUSE)?;
+
+        warn!("Error Indication from AMF (AMF UE NGAP ID {:?}, RAN UE NGAP ID {:?}): {:?}",
+              amf_ue_ngap_id, ran_ue_ngap_id, cause);
+        Ok(())
+    }
+
+    /// Check the UE NGAP IDs of a UE-associated message against the UE contexts
+    ///
+    /// Returns false, after reporting the error to the AMF, if the RAN UE NGAP ID
+    /// is unknown or the AMF UE NGAP ID does not match the one stored. Messages
+    /// that may (re)assign the AMF UE NGAP ID skip the AMF UE NGAP ID check.
+    pub(super) async fn check_ue_association(&self, pdu: &NgapPdu, assigns_amf_id: bool) -> Result<bool, LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+
+        let cause = match self.ue_contexts.get(&ran_ue_ngap_id) {
+            None => Cause::UNKNOWN_LOCAL_UE_NGAP_ID,
+            Some(ctx) if !assigns_amf_id && ctx.amf_ue_ngap_id != Some(amf_ue_ngap_id) => {
+                Cause::INCONSISTENT_REMOTE_UE_NGAP_ID
+            }
+            Some(_) => return Ok(true),
+        };
+
+        info!("Discarding {:?} for AMF UE NGAP ID {}, RAN UE NGAP ID {}", pdu.procedure(), amf_ue_ngap_id, ran_ue_ngap_id);
+        self.send_error_indication(Some(amf_ue_ngap_id), Some(ran_ue_ngap_id), cause).await?;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_indication_encoding() {
+        let bytes = NgapLayer::build_error_indication(Some(7), Some(1000), Cause::UNKNOWN_LOCAL_UE_NGAP_ID).unwrap();
+        let pdu = NgapPdu::decode(&bytes).unwrap();
+        assert_eq!(pdu.procedure(), Some(NgapProcedureCode::ErrorIndication));
+        assert_eq!(pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID).unwrap().0, 7);
+        assert_eq!(pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID).unwrap().0, 1000);
+        assert_eq!(pdu.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::UNKNOWN_LOCAL_UE_NGAP_ID);
+
+        // Non UE-associated error: only the cause
+        let bytes = NgapLayer::build_error_indication(None, None, Cause::TRANSFER_SYNTAX_ERROR).unwrap();
+        let pdu = NgapPdu::decode(&bytes).unwrap();
+        assert_eq!(pdu.optional_ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID).unwrap(), None);
+        assert_eq!(pdu.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::TRANSFER_SYNTAX_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_ue_association_errors() {
+        use crate::ngap::pdu::PagingDrx;
+        use crate::ngap::{NgapConfig, NgapUeContext};
+        use std::net::{IpAddr, SocketAddr};
+        use std::str::FromStr;
+
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x99, 0xF9, 0x07],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+        let message = |amf_ue_ngap_id, ran_ue_ngap_id| NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id)).unwrap();
+
+        assert!(ngap.check_ue_association(&message(7, 1000), false).await.unwrap());
+        assert!(ngap.check_ue_association(&message(8, 1000), true).await.unwrap());
+
+        // Unknown RAN UE NGAP ID and inconsistent AMF UE NGAP ID are reported,
+        // which fails without an NG connection
+        assert!(ngap.check_ue_association(&message(7, 1001), true).await.is_err());
+        assert!(ngap.check_ue_association(&message(8, 1000), false).await.is_err());
+
+        // Missing UE NGAP IDs
+        let missing = NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap();
+        assert!(matches!(ngap.check_ue_association(&missing, false).await, Err(LayerError::ProcessingError(_))));
+
+        // Error Indication from the AMF: every IE is optional, but must decode
+        ngap.handle_error_indication(&NgapPdu::initiating(NgapProcedureCode::ErrorIndication)).unwrap();
+        let mut indication = NgapPdu::decode(&NgapLayer::build_error_indication(None, None, Cause::Nas(0)).unwrap()).unwrap();
+        indication.ies[0].value = bytes::Bytes::from_static(&[0xE0]);
+        assert!(ngap.handle_error_indication(&indication).is_err());
+    }
+}
@@ -0,0 +1,1018 @@
+//! Handover between gNBs
+//!
+//! Glue between RRC, XnAP and the AMF for a handover between two gNBs (3GPP TS
+//! 38.300 section 9.2.3). Over Xn the source gives the UE context it holds for
+//! the AMF to the target, which admits the PDU sessions with its own N3 tunnels,
+//! receives the downlink forwarded by the source on them and switches the path
+//! towards the UPF with Path Switch Request (TS 38.413 section 8.4.4).
+//!
+//! Neighbours without Xn are reached through the AMF (N2): Handover Preparation
+//! from the source, Handover Resource Allocation at the target, the RAN status
+//! transfer between them and Handover Notification once the UE arrived (TS
+//! 38.413 sections 8.4.1 to 8.4.3 and 8.4.6 to 8.4.7).
+
+use super::context::PduSessionContext;
+use super::pdu::{
+    self, AllowedNssai, AmfUeNgapId, Cause, Criticality, GlobalGnbId, GtpTunnel, Guami, HandoverCommandTransfer,
+    HandoverRequestAcknowledgeTransfer, HandoverRequiredTransfer, HandoverResourceAllocationUnsuccessfulTransfer,
+    HandoverType, NgapPdu, NrCgi, PathSwitchRequestAcknowledgeTransfer, PathSwitchRequestTransfer,
+    PathSwitchRequestUnsuccessfulTransfer, PduSessionResourceItem, PduSessionResourceSetupItemHoReq,
+    RanStatusTransferContainer, RanUeNgapId, SecurityContext, SourceToTargetTransparentContainer, Tai,
+    TargetRanNodeId, TargetToSourceTransparentContainer, UeSecurityCapabilities,
+};
+use super::transport::NON_UE_STREAM;
+use super::{NgapLayer, NgapProcedureCode, NgapUeContext};
+use crate::gtpu::NgapGtpuMessage;
+use crate::rrc::{DrbSnStatus, NgapRrcMessage, PduSessionResource, RrcReleaseCause};
+use crate::xnap::pdu::{self as xnap_pdu, PduSessionAdmittedItem, PduSessionToBeSetupItem, UeContextInfoHoRequest};
+use crate::xnap::{NgapXnapMessage, XnapNgapMessage};
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::{AggregateMaximumBitRate, SNssai};
+use std::net::{IpAddr, Ipv4Addr};
+use tracing::{debug, info, warn};
+
+/// Role of the gNB in the handover of a UE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NgHandover {
+    /// The UE was commanded to a neighbour over Xn, its downlink is forwarded
+    XnSource,
+    /// The UE was admitted from a neighbour over Xn and its path is not
+    /// switched yet
+    XnTarget,
+    /// The UE was commanded to a neighbour through the AMF
+    N2Source,
+    /// The UE was admitted through the AMF and has not arrived yet
+    N2Target,
+}
+
+/// Neighbour cell without Xn, reached by N2 handover through the AMF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandoverTarget {
+    /// Physical cell ID the UE reports the cell with
+    pub pci: u16,
+    /// gNB ID of the gNB serving the cell
+    pub gnb_id: u32,
+    /// gNB ID length in bits (22..32)
+    pub gnb_id_bits: u8,
+    /// NR cell identity of the cell (36 bits)
+    pub nr_cell_identity: u64,
+    /// Tracking area code of the cell
+    pub tac: u32,
+}
+
+/// Where an incoming handover comes from, to answer it there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandoverOrigin {
+    /// Handover Request from a neighbour over Xn
+    Xn { handover_id: u32 },
+    /// Handover Request from an AMF
+    N2 { amf: usize },
+}
+
+/// Incoming handover waiting for RRC to admit the UE
+#[derive(Debug)]
+pub(super) struct PendingHandover {
+    /// Where to send the answer
+    origin: HandoverOrigin,
+    /// AMF UE NGAP ID of the UE
+    amf_ue_ngap_id: u64,
+    /// GUAMI of the AMF serving the UE
+    guami: Guami,
+    /// UE security capabilities
+    security_capabilities: UeSecurityCapabilities,
+    /// UE Aggregate Maximum Bit Rate
+    ue_ambr: AggregateMaximumBitRate,
+    /// Slices the UE is allowed to use, known for an N2 handover only
+    allowed_nssai: Vec<SNssai>,
+    /// PDU sessions to set up
+    sessions: Vec<PduSessionToBeSetupItem>,
+    /// PDU sessions rejected before reaching RRC and why
+    rejected: Vec<(u8, Cause)>,
+}
+
+impl NgapLayer {
+    /// Handle a message from XnAP
+    pub async fn handle_xnap_message(&mut self, message: XnapNgapMessage) -> Result<(), LayerError> {
+        match message {
+            XnapNgapMessage::HandoverRequest { handover_id, guami, context } => {
+                self.handle_xn_handover_request(handover_id, guami, context).await
+            }
+            XnapNgapMessage::HandoverRequestAcknowledge { ue_id, admitted, rrc_container } => {
+                self.handle_xn_handover_request_acknowledge(ue_id, admitted, rrc_container).await
+            }
+            XnapNgapMessage::HandoverPreparationFailure { ue_id, cause } => {
+                warn!("Xn handover of RAN UE NGAP ID {} failed: {:?}", ue_id, cause);
+                self.send_to_rrc(NgapRrcMessage::HandoverPreparationFailure { ue_id }).await
+            }
+            XnapNgapMessage::SnStatusTransfer { ue_id, drbs } => {
+                self.send_to_rrc(NgapRrcMessage::SnStatusTransfer { ue_id, drbs }).await
+            }
+            XnapNgapMessage::UeContextRelease { ue_id } => {
+                self.send_to_rrc(NgapRrcMessage::UeContextRelease { ue_id }).await
+            }
+        }
+    }
+
+    /// Pass a message to XnAP
+    async fn send_to_xnap(&self, message: NgapXnapMessage) -> Result<(), LayerError> {
+        match &self.xnap_tx {
+            Some(xnap_tx) => xnap_tx.send(message).await
+                .map_err(|_| LayerError::ProcessingError("XnAP channel closed".into())),
+            None => Err(LayerError::InvalidState("No XnAP channel configured".into())),
+        }
+    }
+
+    /// Source side: prepare the handover of a UE leaving the cell, through the
+    /// AMF for the configured N2 neighbours and over Xn for the others
+    pub(super) async fn send_handover_required(
+        &mut self,
+        ue_id: u32,
+        target_pci: u16,
+        security_key: [u8; 32],
+        next_hop_chaining_count: u8,
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        if let Some(target) = self.config.handover_targets.iter().find(|target| target.pci == target_pci).copied() {
+            return match self.build_handover_required(ue_id, &target, rrc_container) {
+                Ok(pdu) => {
+                    info!("Handover Required for RAN UE NGAP ID {} towards gNB {:#x} PCI {}",
+                          ue_id, target.gnb_id, target_pci);
+                    self.send_ue_pdu(ue_id, pdu).await
+                }
+                Err(e) => {
+                    warn!("Cannot hand over RAN UE NGAP ID {} through the AMF: {}", ue_id, e);
+                    self.send_to_rrc(NgapRrcMessage::HandoverPreparationFailure { ue_id }).await
+                }
+            };
+        }
+
+        let request = self.ue_contexts.get(&ue_id)
+            .filter(|ue_context| self.xnap_tx.is_some() && !ue_context.pdu_sessions.is_empty())
+            .and_then(|ue_context| {
+                let context = UeContextInfoHoRequest {
+                    amf_ue_ngap_id: ue_context.amf_ue_ngap_id?,
+                    // Filled in by XnAP
+                    source_cp_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    security_capabilities: ue_context.security_capabilities?,
+                    security_key,
+                    next_hop_chaining_count,
+                    ue_ambr: ue_context.ue_ambr.unwrap_or(AggregateMaximumBitRate { dl: 0, ul: 0 }),
+                    sessions: Self::sessions_to_be_setup(ue_context),
+                    rrc_context: rrc_container,
+                };
+                Some(NgapXnapMessage::HandoverRequest { ue_id, target_pci, guami: ue_context.guami?, context })
+            });
+
+        match request {
+            Some(request) => {
+                info!("Handover Required for RAN UE NGAP ID {} towards PCI {}", ue_id, target_pci);
+                self.send_to_xnap(request).await
+            }
+            None => {
+                warn!("Cannot hand over RAN UE NGAP ID {}: no Xn, UE context or PDU session established", ue_id);
+                self.send_to_rrc(NgapRrcMessage::HandoverPreparationFailure { ue_id }).await
+            }
+        }
+    }
+
+    /// PDU sessions of a UE as handed to an Xn neighbour, by PDU session ID
+    fn sessions_to_be_setup(ue_context: &NgapUeContext) -> Vec<PduSessionToBeSetupItem> {
+        let mut sessions: Vec<PduSessionToBeSetupItem> = ue_context.pdu_sessions.iter()
+            .map(|(id, session)| PduSessionToBeSetupItem {
+                pdu_session_id: *id,
+                s_nssai: session.s_nssai.clone(),
+                session_ambr: session.session_ambr,
+                ul_tunnel: session.ul_tunnel,
+                pdu_session_type: session.pdu_session_type,
+                qos_flows: session.qos_flows.clone(),
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.pdu_session_id);
+        sessions
+    }
+
+    /// Build Handover Required
+    fn build_handover_required(&self, ue_id: u32, target: &HandoverTarget, rrc_container: Bytes) -> Result<Vec<u8>, LayerError> {
+        let ue_context = self.ue_contexts.get(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ue_id)))?;
+        let amf_ue_ngap_id = ue_context.amf_ue_ngap_id
+            .ok_or_else(|| LayerError::InvalidState(format!("No NG connection for RAN UE NGAP ID {}", ue_id)))?;
+        let mut session_ids: Vec<u8> = ue_context.pdu_sessions.keys().copied().collect();
+        if session_ids.is_empty() {
+            return Err(LayerError::InvalidState("No PDU session established".into()));
+        }
+        session_ids.sort_unstable();
+        let sessions = session_ids.into_iter()
+            .map(|id| PduSessionResourceItem::new(id, &HandoverRequiredTransfer))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let plmn_id = self.config.plmn_id;
+        let target_id = TargetRanNodeId {
+            global_gnb_id: GlobalGnbId { plmn_id, gnb_id: target.gnb_id, gnb_id_bits: target.gnb_id_bits },
+            selected_tai: Tai { plmn_id, tac: target.tac },
+        };
+        let container = SourceToTargetTransparentContainer {
+            rrc_container,
+            target_cell: NrCgi { plmn_id, nr_cell_identity: target.nr_cell_identity },
+            source_cell: NrCgi { plmn_id, nr_cell_identity: self.config.nr_cell_identity },
+        };
+        let pdu = NgapPdu::initiating(NgapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue_id))?
+            .with_ie(pdu::ID_HANDOVER_TYPE, Criticality::Reject, &HandoverType::Intra5gs)?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASON)?
+            .with_ie(pdu::ID_TARGET_ID, Criticality::Reject, &target_id)?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_LIST_HO_RQD, Criticality::Reject, &sessions)?
+            .with_ie(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER, Criticality::Reject, &container)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Source side: Handover Command from the AMF, forward the downlink to the
+    /// tunnels of the target and command the UE to it
+    pub(super) async fn handle_handover_command(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let sessions = pdu.optional_ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_HANDOVER_LIST)?
+            .unwrap_or_default();
+        let container = pdu.ie::<TargetToSourceTransparentContainer>(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .filter(|ctx| ctx.amf_ue_ngap_id == Some(amf_ue_ngap_id))
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("Unknown UE (AMF UE NGAP ID {}, RAN UE NGAP ID {})", amf_ue_ngap_id, ran_ue_ngap_id)))?;
+        ue_context.handover = Some(NgHandover::N2Source);
+        info!("Handover Command for RAN UE NGAP ID {}, forwarding {} PDU sessions", ran_ue_ngap_id, sessions.len());
+
+        for session in sessions {
+            let transfer = session.decode_transfer::<HandoverCommandTransfer>()?;
+            if let Some(forwarding_tunnel) = transfer.dl_forwarding_tunnel {
+                self.send_to_gtpu(NgapGtpuMessage::ForwardTunnel {
+                    ue_id: ran_ue_ngap_id,
+                    pdu_session_id: session.pdu_session_id,
+                    forwarding_tunnel,
+                }).await;
+            }
+        }
+        self.send_to_rrc(NgapRrcMessage::HandoverCommand {
+            ue_id: ran_ue_ngap_id,
+            rrc_container: container.rrc_container,
+        }).await
+    }
+
+    /// Source side: the AMF or the target refused the handover
+    pub(super) async fn handle_handover_preparation_failure(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        warn!("N2 handover of RAN UE NGAP ID {} failed: {:?}", ran_ue_ngap_id, cause);
+        self.send_to_rrc(NgapRrcMessage::HandoverPreparationFailure { ue_id: ran_ue_ngap_id }).await
+    }
+
+    /// Source side: forward the downlink to the tunnels of the target and
+    /// command the UE to it
+    async fn handle_xn_handover_request_acknowledge(
+        &mut self,
+        ue_id: u32,
+        admitted: Vec<PduSessionAdmittedItem>,
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        let ue_context = self.ue_contexts.get_mut(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ue_id)))?;
+        ue_context.handover = Some(NgHandover::XnSource);
+
+        for session in admitted {
+            if let Some(forwarding_tunnel) = session.forwarding_tunnel {
+                self.send_to_gtpu(NgapGtpuMessage::ForwardTunnel {
+                    ue_id,
+                    pdu_session_id: session.pdu_session_id,
+                    forwarding_tunnel,
+                }).await;
+            }
+        }
+        self.send_to_rrc(NgapRrcMessage::HandoverCommand { ue_id, rrc_container }).await
+    }
+
+    /// Target side: Handover Request from a neighbour over Xn
+    async fn handle_xn_handover_request(
+        &mut self,
+        handover_id: u32,
+        guami: Guami,
+        context: UeContextInfoHoRequest,
+    ) -> Result<(), LayerError> {
+        let pending = PendingHandover {
+            origin: HandoverOrigin::Xn { handover_id },
+            amf_ue_ngap_id: context.amf_ue_ngap_id,
+            guami,
+            security_capabilities: context.security_capabilities,
+            ue_ambr: context.ue_ambr,
+            allowed_nssai: Vec::new(),
+            sessions: context.sessions,
+            rejected: Vec::new(),
+        };
+        self.admit_handover(pending, context.security_key, context.next_hop_chaining_count, context.rrc_context).await
+    }
+
+    /// Target side: Handover Request from the AMF
+    ///
+    /// The target derives K_gNB from the NH of the security context.
+    pub(super) async fn handle_handover_request(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let handover_type = pdu.ie::<HandoverType>(pdu::ID_HANDOVER_TYPE)?;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        let ue_ambr = pdu.ie::<AggregateMaximumBitRate>(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE)?;
+        let security_capabilities = pdu.ie::<UeSecurityCapabilities>(pdu::ID_UE_SECURITY_CAPABILITIES)?;
+        let security_context = pdu.ie::<SecurityContext>(pdu::ID_SECURITY_CONTEXT)?;
+        let items = pdu.ie::<Vec<PduSessionResourceSetupItemHoReq>>(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_HO_REQ)?;
+        let allowed_nssai = pdu.ie::<AllowedNssai>(pdu::ID_ALLOWED_NSSAI)?;
+        let container = pdu.ie::<SourceToTargetTransparentContainer>(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER)?;
+        let guami = pdu.ie::<Guami>(pdu::ID_GUAMI)?;
+        info!("Handover Request for AMF UE NGAP ID {} ({:?}, {:?}) with {} PDU sessions",
+              amf_ue_ngap_id, handover_type, cause, items.len());
+
+        let origin = HandoverOrigin::N2 { amf: self.active_amf };
+        if handover_type != HandoverType::Intra5gs || container.target_cell.nr_cell_identity != self.config.nr_cell_identity {
+            warn!("Handover of AMF UE NGAP ID {} to cell {:#x} not served here", amf_ue_ngap_id,
+                  container.target_cell.nr_cell_identity);
+            return self.send_n2_handover_failure(origin, amf_ue_ngap_id, Cause::CELL_NOT_AVAILABLE).await;
+        }
+
+        let sessions = items.into_iter()
+            .map(|item| PduSessionToBeSetupItem {
+                pdu_session_id: item.pdu_session_id,
+                s_nssai: item.s_nssai,
+                session_ambr: item.transfer.session_ambr,
+                ul_tunnel: item.transfer.ul_tunnel,
+                pdu_session_type: item.transfer.pdu_session_type,
+                qos_flows: item.transfer.qos_flows,
+            })
+            .collect();
+        let pending = PendingHandover {
+            origin,
+            amf_ue_ngap_id,
+            guami,
+            security_capabilities,
+            ue_ambr,
+            allowed_nssai: allowed_nssai.0,
+            sessions,
+            rejected: Vec::new(),
+        };
+        self.admit_handover(pending, security_context.next_hop, security_context.next_hop_chaining_count,
+                            container.rrc_container).await
+    }
+
+    /// Target side: check the PDU sessions of a UE handed over and ask RRC to
+    /// admit it
+    async fn admit_handover(
+        &mut self,
+        mut pending: PendingHandover,
+        security_key: [u8; 32],
+        next_hop_chaining_count: u8,
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        let handover_id = self.next_handover_id;
+        self.next_handover_id = self.next_handover_id.wrapping_add(1);
+
+        let supported = |s_nssai: &SNssai| self.config.supported_tas.iter()
+            .flat_map(|ta| &ta.broadcast_plmns)
+            .any(|plmn| plmn.slices.contains(s_nssai));
+        let (sessions, rejected): (Vec<PduSessionToBeSetupItem>, Vec<PduSessionToBeSetupItem>) =
+            std::mem::take(&mut pending.sessions).into_iter().partition(|session| supported(&session.s_nssai));
+        pending.sessions = sessions;
+        pending.rejected = rejected.iter().map(|session| (session.pdu_session_id, Cause::SLICE_NOT_SUPPORTED)).collect();
+        if pending.sessions.is_empty() {
+            warn!("Rejecting handover {:?}: none of the PDU sessions {:?} can be admitted", pending.origin, pending.rejected);
+            return self.refuse_handover(&pending, RrcReleaseCause::NoRadioResources).await;
+        }
+
+        let message = NgapRrcMessage::HandoverRequest {
+            handover_id,
+            security_key,
+            next_hop_chaining_count,
+            nr_encryption_algorithms: pending.security_capabilities.nr_encryption_algorithms,
+            nr_integrity_algorithms: pending.security_capabilities.nr_integrity_algorithms,
+            sessions: pending.sessions.iter()
+                .map(|session| PduSessionResource {
+                    pdu_session_id: session.pdu_session_id,
+                    qos_flows: session.qos_flows.iter().map(|flow| flow.qfi).collect(),
+                    nas_pdu: None,
+                    s_nssai: session.s_nssai.clone(),
+                })
+                .collect(),
+            rrc_container,
+        };
+        self.pending_handovers.insert(handover_id, pending);
+        self.send_to_rrc(message).await
+    }
+
+    /// Target side: create the context of the UE admitted by RRC, open its N3
+    /// tunnels and acknowledge with them as forwarding tunnels
+    pub(super) async fn send_handover_request_acknowledge(
+        &mut self,
+        handover_id: u32,
+        ue_id: u32,
+        admitted: &[u8],
+        failed: &[u8],
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        let pending = self.pending_handovers.remove(&handover_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown handover {}", handover_id)))?;
+
+        let (amf, handover) = match pending.origin {
+            HandoverOrigin::Xn { .. } => (self.amf_serving_guami(|guami| *guami == pending.guami), NgHandover::XnTarget),
+            HandoverOrigin::N2 { amf } => (Some(amf), NgHandover::N2Target),
+        };
+        let mut ue_context = NgapUeContext {
+            ran_ue_ngap_id: ue_id,
+            amf_ue_ngap_id: Some(pending.amf_ue_ngap_id),
+            amf,
+            guami: Some(pending.guami),
+            allowed_nssai: pending.allowed_nssai,
+            ue_ambr: Some(pending.ue_ambr),
+            security_capabilities: Some(pending.security_capabilities),
+            handover: Some(handover),
+            ..Default::default()
+        };
+        for session in pending.sessions.into_iter().filter(|session| admitted.contains(&session.pdu_session_id)) {
+            let dl_teid = self.next_gtpu_teid;
+            self.next_gtpu_teid = self.next_gtpu_teid.checked_add(1).unwrap_or(1);
+            ue_context.pdu_sessions.insert(session.pdu_session_id, PduSessionContext {
+                s_nssai: session.s_nssai,
+                pdu_session_type: session.pdu_session_type,
+                ul_tunnel: session.ul_tunnel,
+                dl_teid,
+                qos_flows: session.qos_flows,
+                session_ambr: session.session_ambr,
+            });
+        }
+        let mut admitted: Vec<u8> = ue_context.pdu_sessions.keys().copied().collect();
+        admitted.sort_unstable();
+        let admitted_tunnels: Vec<(u8, GtpTunnel, Vec<u8>)> = admitted.iter()
+            .map(|id| {
+                let session = &ue_context.pdu_sessions[id];
+                let tunnel = GtpTunnel { transport_layer_address: self.config.gtpu_address, teid: session.dl_teid };
+                (*id, tunnel, session.qos_flows.iter().map(|flow| flow.qfi).collect())
+            })
+            .collect();
+        self.ue_contexts.insert(ue_id, ue_context);
+        self.create_gtpu_tunnels(ue_id, &admitted).await;
+
+        let mut not_admitted: Vec<(u8, Cause)> = failed.iter()
+            .map(|id| (*id, Cause::RADIO_RESOURCES_NOT_AVAILABLE))
+            .chain(pending.rejected.iter().copied())
+            .collect();
+        not_admitted.sort_unstable_by_key(|(id, _)| *id);
+        info!("Admitted handover {} as RAN UE NGAP ID {}, PDU sessions {:?} (not admitted {:?})",
+              handover_id, ue_id, admitted, not_admitted);
+
+        match pending.origin {
+            HandoverOrigin::Xn { handover_id } => {
+                let admitted = admitted_tunnels.into_iter()
+                    .map(|(pdu_session_id, tunnel, qos_flows)| PduSessionAdmittedItem {
+                        pdu_session_id,
+                        qos_flows,
+                        forwarding_tunnel: Some(tunnel),
+                    })
+                    .collect();
+                self.send_to_xnap(NgapXnapMessage::HandoverRequestAcknowledge {
+                    handover_id,
+                    ue_id,
+                    admitted,
+                    not_admitted: not_admitted.into_iter().map(|(id, cause)| (id, xn_cause(cause))).collect(),
+                    rrc_container,
+                }).await
+            }
+            HandoverOrigin::N2 { .. } => {
+                let admitted = admitted_tunnels.into_iter()
+                    .map(|(pdu_session_id, tunnel, qos_flows)| {
+                        PduSessionResourceItem::new(pdu_session_id, &HandoverRequestAcknowledgeTransfer {
+                            dl_tunnel: tunnel,
+                            dl_forwarding_tunnel: Some(tunnel),
+                            qos_flows,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut pdu = NgapPdu::successful(NgapProcedureCode::HandoverResourceAllocation)
+                    .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(pending.amf_ue_ngap_id))?
+                    .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ue_id))?
+                    .with_ie(pdu::ID_PDU_SESSION_RESOURCE_ADMITTED_LIST, Criticality::Ignore, &admitted)?;
+                if !not_admitted.is_empty() {
+                    let failed = not_admitted.iter()
+                        .map(|(id, cause)| PduSessionResourceItem::new(*id, &HandoverResourceAllocationUnsuccessfulTransfer {
+                            cause: *cause,
+                        }))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_HO_ACK, Criticality::Ignore, &failed)?;
+                }
+                pdu.add_ie(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER, Criticality::Reject,
+                           &TargetToSourceTransparentContainer { rrc_container })?;
+                self.send_ue_pdu(ue_id, pdu.encode()?.to_vec()).await
+            }
+        }
+    }
+
+    /// Target side: RRC cannot admit the UE
+    pub(super) async fn send_handover_failure(&mut self, handover_id: u32, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        let Some(pending) = self.pending_handovers.remove(&handover_id) else {
+            debug!("Handover {} already answered", handover_id);
+            return Ok(());
+        };
+        self.refuse_handover(&pending, cause).await
+    }
+
+    /// Target side: answer a handover that cannot be admitted
+    async fn refuse_handover(&self, pending: &PendingHandover, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        match pending.origin {
+            HandoverOrigin::Xn { handover_id } => {
+                self.send_to_xnap(NgapXnapMessage::HandoverPreparationFailure {
+                    handover_id,
+                    cause: xnap_pdu::Cause::from(cause),
+                }).await
+            }
+            HandoverOrigin::N2 { .. } => {
+                self.send_n2_handover_failure(pending.origin, pending.amf_ue_ngap_id, Cause::from(cause)).await
+            }
+        }
+    }
+
+    /// Target side: send Handover Failure to the AMF
+    async fn send_n2_handover_failure(&self, origin: HandoverOrigin, amf_ue_ngap_id: u64, cause: Cause) -> Result<(), LayerError> {
+        let HandoverOrigin::N2 { amf } = origin else {
+            return Err(LayerError::InvalidState("Handover Failure for an Xn handover".into()));
+        };
+        info!("Sending Handover Failure for AMF UE NGAP ID {}: {:?}", amf_ue_ngap_id, cause);
+        let pdu = NgapPdu::unsuccessful(NgapProcedureCode::HandoverResourceAllocation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        // No RAN UE NGAP ID was allocated, the UE has no stream of its own yet
+        self.send_to_amf(amf, NON_UE_STREAM, pdu.encode()?.to_vec()).await
+    }
+
+    /// Source side: pass the PDCP COUNTs of a UE commanded to the target
+    pub(super) async fn send_sn_status_transfer(&mut self, ue_id: u32, drbs: Vec<DrbSnStatus>) -> Result<(), LayerError> {
+        let ue_context = self.ue_contexts.get(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ue_id)))?;
+        match (ue_context.handover, ue_context.amf_ue_ngap_id) {
+            (Some(NgHandover::N2Source), Some(amf_ue_ngap_id)) => {
+                if drbs.is_empty() {
+                    return Ok(());
+                }
+                let pdu = NgapPdu::initiating(NgapProcedureCode::UplinkRanStatusTransfer)
+                    .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+                    .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue_id))?
+                    .with_ie(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER, Criticality::Reject,
+                             &RanStatusTransferContainer(drbs))?;
+                self.send_ue_pdu(ue_id, pdu.encode()?.to_vec()).await
+            }
+            _ => self.send_to_xnap(NgapXnapMessage::SnStatusTransfer { ue_id, drbs }).await,
+        }
+    }
+
+    /// Target side: PDCP COUNTs relayed by the AMF
+    pub(super) async fn handle_downlink_ran_status_transfer(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let container = pdu.ie::<RanStatusTransferContainer>(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER)?;
+        self.send_to_rrc(NgapRrcMessage::SnStatusTransfer { ue_id: ran_ue_ngap_id, drbs: container.0 }).await
+    }
+
+    /// Target side: the UE arrived, tell the AMF or switch the path of an Xn
+    /// handover towards the target
+    pub(super) async fn send_handover_notify(&mut self, ue_id: u32) -> Result<(), LayerError> {
+        let user_location = self.user_location_information();
+        let ue_context = self.ue_contexts.get_mut(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ue_id)))?;
+        let amf_ue_ngap_id = ue_context.amf_ue_ngap_id
+            .ok_or_else(|| LayerError::InvalidState(format!("No NG connection for RAN UE NGAP ID {}", ue_id)))?;
+
+        let pdu = match ue_context.handover {
+            Some(NgHandover::N2Target) => {
+                ue_context.handover = None;
+                info!("RAN UE NGAP ID {} arrived after N2 handover", ue_id);
+                NgapPdu::initiating(NgapProcedureCode::HandoverNotification)
+                    .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+                    .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue_id))?
+                    .with_ie(pdu::ID_USER_LOCATION_INFORMATION, Criticality::Ignore, &user_location)?
+            }
+            Some(NgHandover::XnTarget) => {
+                info!("RAN UE NGAP ID {} arrived after Xn handover, sending Path Switch Request", ue_id);
+                let mut session_ids: Vec<u8> = ue_context.pdu_sessions.keys().copied().collect();
+                session_ids.sort_unstable();
+                let sessions = session_ids.iter()
+                    .map(|id| {
+                        let session = &ue_context.pdu_sessions[id];
+                        PduSessionResourceItem::new(*id, &PathSwitchRequestTransfer {
+                            dl_tunnel: GtpTunnel {
+                                transport_layer_address: self.config.gtpu_address,
+                                teid: session.dl_teid,
+                            },
+                            qos_flows: session.qos_flows.iter().map(|flow| flow.qfi).collect(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let security_capabilities = ue_context.security_capabilities.unwrap_or_default();
+                NgapPdu::initiating(NgapProcedureCode::PathSwitchRequest)
+                    .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue_id))?
+                    .with_ie(pdu::ID_SOURCE_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+                    .with_ie(pdu::ID_USER_LOCATION_INFORMATION, Criticality::Ignore, &user_location)?
+                    .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Ignore, &security_capabilities)?
+                    .with_ie(pdu::ID_PDU_SESSION_RESOURCE_TO_BE_SWITCHED_DL_LIST, Criticality::Reject, &sessions)?
+            }
+            _ => {
+                debug!("RAN UE NGAP ID {} is not being handed over", ue_id);
+                return Ok(());
+            }
+        };
+        self.send_ue_pdu(ue_id, pdu.encode()?.to_vec()).await
+    }
+
+    /// Target side: the AMF switched the path of an Xn handover, take the new
+    /// uplink tunnels and let the source release the UE
+    pub(super) async fn handle_path_switch_request_acknowledge(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let security_context = pdu.ie::<SecurityContext>(pdu::ID_SECURITY_CONTEXT)?;
+        let switched = pdu.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_SWITCHED_LIST)?;
+        let allowed_nssai = pdu.ie::<AllowedNssai>(pdu::ID_ALLOWED_NSSAI)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .filter(|ctx| ctx.handover == Some(NgHandover::XnTarget))
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No path switch pending for RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        // The AMF may allocate a new AMF UE NGAP ID for the target
+        ue_context.amf_ue_ngap_id = Some(amf_ue_ngap_id);
+        ue_context.allowed_nssai = allowed_nssai.0;
+        ue_context.handover = None;
+        let mut updated = Vec::new();
+        for item in switched {
+            let transfer = item.decode_transfer::<PathSwitchRequestAcknowledgeTransfer>()?;
+            if let (Some(ul_tunnel), Some(session)) = (transfer.ul_tunnel, ue_context.pdu_sessions.get_mut(&item.pdu_session_id)) {
+                session.ul_tunnel = ul_tunnel;
+                updated.push(item.pdu_session_id);
+            }
+        }
+        info!("Path switched for RAN UE NGAP ID {} (NCC {}), new UPF tunnels for PDU sessions {:?}",
+              ran_ue_ngap_id, security_context.next_hop_chaining_count, updated);
+        self.create_gtpu_tunnels(ran_ue_ngap_id, &updated).await;
+        self.send_to_xnap(NgapXnapMessage::UeContextRelease { ue_id: ran_ue_ngap_id }).await
+    }
+
+    /// Target side: the AMF could not switch the path, the UE is released
+    pub(super) async fn handle_path_switch_request_failure(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let released = pdu.optional_ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_RELEASED_LIST_PS_FAIL)?
+            .unwrap_or_default();
+        let causes = released.iter()
+            .map(|item| item.decode_transfer::<PathSwitchRequestUnsuccessfulTransfer>().map(|transfer| transfer.cause))
+            .collect::<Result<Vec<_>, _>>()?;
+        warn!("Path switch of RAN UE NGAP ID {} failed: {:?}", ran_ue_ngap_id, causes);
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        // The AMF keeps the UE at the source, the release is local
+        ue_context.amf_ue_ngap_id = None;
+        ue_context.handover = None;
+        self.send_to_xnap(NgapXnapMessage::UeContextRelease { ue_id: ran_ue_ngap_id }).await?;
+        self.send_to_rrc(NgapRrcMessage::UeContextRelease { ue_id: ran_ue_ngap_id }).await
+    }
+}
+
+/// XnAP cause of a PDU session the target does not admit
+fn xn_cause(cause: Cause) -> xnap_pdu::Cause {
+    if cause == Cause::SLICE_NOT_SUPPORTED {
+        xnap_pdu::Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN
+    } else {
+        xnap_pdu::Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{BroadcastPlmnItem, PduSessionResourceSetupRequestTransfer, SupportedTaItem};
+    use crate::ngap::transport::NgTransportConfig;
+    use crate::ngap::{pdu::PagingDrx, NgapConfig};
+    use crate::test_support::xn_ue_context;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    const PLMN: [u8; 3] = [0x02, 0xF8, 0x39];
+    const GUAMI: Guami = Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 1, amf_pointer: 0 };
+
+    /// gNB serving slice 1, with an N2 neighbour on PCI 3
+    fn test_layer() -> NgapLayer {
+        NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 0x19B,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 7,
+                broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x19B001,
+            tac: 7,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig::default(),
+            handover_targets: vec![HandoverTarget { pci: 3, gnb_id: 0x19D, gnb_id_bits: 22, nr_cell_identity: 0x19D001, tac: 9 }],
+        })
+    }
+
+    #[test]
+    fn test_handover_required_encoding() {
+        let config = NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 0x19B,
+            gnb_id_bits: 22,
+            plmn_id: [0x02, 0xF8, 0x39],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x19B001,
+            tac: 7,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig::default(),
+            handover_targets: Vec::new(),
+        };
+        let target = HandoverTarget { pci: 2, gnb_id: 0x19C, gnb_id_bits: 22, nr_cell_identity: 0x19C001, tac: 8 };
+        let mut ngap = NgapLayer::new(config);
+        assert!(ngap.build_handover_required(1000, &target, Bytes::new()).is_err());
+
+        let session = PduSessionContext {
+            s_nssai: SNssai { sst: 1, sd: None },
+            pdu_session_type: pdu::PduSessionType::Ipv4,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 2]), teid: 0x101 },
+            dl_teid: 1,
+            qos_flows: Vec::new(),
+            session_ambr: None,
+        };
+        ngap.ue_contexts.insert(1000, NgapUeContext {
+            ran_ue_ngap_id: 1000,
+            amf_ue_ngap_id: Some(7),
+            pdu_sessions: [(1, session)].into_iter().collect(),
+            ..Default::default()
+        });
+        let rrc_container = Bytes::from_static(&[0x01, 0x02, 0x03]);
+        let bytes = ngap.build_handover_required(1000, &target, rrc_container.clone()).unwrap();
+        let pdu = NgapPdu::decode(&bytes).unwrap();
+        assert_eq!(pdu.procedure(), Some(NgapProcedureCode::HandoverPreparation));
+        assert_eq!(pdu.ie::<HandoverType>(pdu::ID_HANDOVER_TYPE).unwrap(), HandoverType::Intra5gs);
+        let target_id = pdu.ie::<TargetRanNodeId>(pdu::ID_TARGET_ID).unwrap();
+        assert_eq!((target_id.global_gnb_id.gnb_id, target_id.selected_tai.tac), (0x19C, 8));
+        let sessions = pdu.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_LIST_HO_RQD).unwrap();
+        assert_eq!(sessions[0].pdu_session_id, 1);
+        assert_eq!(sessions[0].decode_transfer::<HandoverRequiredTransfer>().unwrap(), HandoverRequiredTransfer);
+        let container = pdu.ie::<SourceToTargetTransparentContainer>(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER)
+            .unwrap();
+        assert_eq!(container.rrc_container, rrc_container);
+        assert_eq!(container.target_cell.nr_cell_identity, 0x19C001);
+        assert_eq!(container.source_cell.nr_cell_identity, 0x19B001);
+    }
+
+    #[test]
+    fn test_handover_transfers_roundtrip() {
+        let tunnel = GtpTunnel { transport_layer_address: IpAddr::from([10, 0, 0, 1]), teid: 0x42 };
+        let ack = HandoverRequestAcknowledgeTransfer { dl_tunnel: tunnel, dl_forwarding_tunnel: Some(tunnel), qos_flows: vec![1, 5] };
+        let item = PduSessionResourceItem::new(1, &ack).unwrap();
+        assert_eq!(item.decode_transfer::<HandoverRequestAcknowledgeTransfer>().unwrap(), ack);
+
+        let command = HandoverCommandTransfer { dl_forwarding_tunnel: Some(tunnel), qos_flows_to_forward: vec![1] };
+        let item = PduSessionResourceItem::new(1, &command).unwrap();
+        assert_eq!(item.decode_transfer::<HandoverCommandTransfer>().unwrap(), command);
+
+        let status = RanStatusTransferContainer(vec![DrbSnStatus { drb_id: 1, ul_count: 5 << 18 | 17, dl_count: 300_000 }]);
+        let pdu = NgapPdu::initiating(NgapProcedureCode::UplinkRanStatusTransfer)
+            .with_ie(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER, Criticality::Reject, &status).unwrap();
+        let decoded = NgapPdu::decode(&pdu.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<RanStatusTransferContainer>(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER).unwrap(), status);
+
+        let switch = PathSwitchRequestTransfer { dl_tunnel: tunnel, qos_flows: vec![1] };
+        let item = PduSessionResourceItem::new(1, &switch).unwrap();
+        assert_eq!(item.decode_transfer::<PathSwitchRequestTransfer>().unwrap(), switch);
+        let switched = PathSwitchRequestAcknowledgeTransfer { ul_tunnel: Some(tunnel) };
+        let item = PduSessionResourceItem::new(1, &switched).unwrap();
+        assert_eq!(item.decode_transfer::<PathSwitchRequestAcknowledgeTransfer>().unwrap(), switched);
+    }
+
+    #[tokio::test]
+    async fn test_source_handover_failures() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let (gtpu_tx, mut gtpu_rx) = mpsc::channel(10);
+        ngap.set_gtpu_channel(gtpu_tx);
+        let preparation_failed = |message| matches!(message, NgapRrcMessage::HandoverPreparationFailure { ue_id: 1000 });
+
+        // Unknown UE towards the N2 neighbour, and no Xn towards the others
+        ngap.send_handover_required(1000, 3, [0x5A; 32], 1, Bytes::new()).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+        ngap.ue_contexts.insert(1000, NgapUeContext {
+            ran_ue_ngap_id: 1000,
+            amf_ue_ngap_id: Some(7),
+            guami: Some(GUAMI),
+            security_capabilities: Some(UeSecurityCapabilities::default()),
+            ..Default::default()
+        });
+        ngap.send_handover_required(1000, 2, [0x5A; 32], 1, Bytes::new()).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+
+        // A UE without PDU session is not handed over either way
+        let (xnap_tx, mut xnap_rx) = mpsc::channel(10);
+        ngap.set_xnap_channel(xnap_tx);
+        ngap.send_handover_required(1000, 2, [0x5A; 32], 1, Bytes::new()).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+        ngap.send_handover_required(1000, 3, [0x5A; 32], 1, Bytes::new()).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+        assert!(xnap_rx.try_recv().is_err());
+
+        // Handover Command without container, for another AMF UE NGAP ID or
+        // with an unsupported transfer
+        let command = |amf_ue_ngap_id, transfer: &'static [u8]| {
+            NgapPdu::successful(NgapProcedureCode::HandoverPreparation)
+                .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id)).unwrap()
+                .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(1000)).unwrap()
+                .with_ie(pdu::ID_PDU_SESSION_RESOURCE_HANDOVER_LIST, Criticality::Ignore,
+                         &vec![PduSessionResourceItem { pdu_session_id: 1, transfer: Bytes::from_static(transfer) }]).unwrap()
+        };
+        let container = TargetToSourceTransparentContainer { rrc_container: Bytes::from_static(&[0x01]) };
+        assert!(matches!(ngap.handle_handover_command(&command(7, &[0x00])).await, Err(LayerError::ProcessingError(_))));
+        let pdu = command(8, &[0x00])
+            .with_ie(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER, Criticality::Reject, &container).unwrap();
+        assert!(matches!(ngap.handle_handover_command(&pdu).await, Err(LayerError::InvalidState(_))));
+        assert_eq!(ngap.ue_contexts[&1000].handover, None);
+        // Per DRB data forwarding
+        let pdu = command(7, &[0x10])
+            .with_ie(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER, Criticality::Reject, &container).unwrap();
+        assert!(matches!(ngap.handle_handover_command(&pdu).await, Err(LayerError::ProcessingError(_))));
+        assert!(rrc_rx.try_recv().is_err());
+        assert!(gtpu_rx.try_recv().is_err());
+
+        // Handover Preparation Failure needs a cause and reaches RRC
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(1000)).unwrap();
+        assert!(matches!(ngap.handle_handover_preparation_failure(&failure).await, Err(LayerError::ProcessingError(_))));
+        let failure = failure.with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::RADIO_RESOURCES_NOT_AVAILABLE).unwrap();
+        ngap.handle_handover_preparation_failure(&NgapPdu::decode(&failure.encode().unwrap()).unwrap()).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+        ngap.handle_xnap_message(XnapNgapMessage::HandoverPreparationFailure {
+            ue_id: 1000, cause: xnap_pdu::Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL,
+        }).await.unwrap();
+        assert!(preparation_failed(rrc_rx.try_recv().unwrap()));
+
+        // Xn acknowledgements and SN status of unknown UEs
+        assert!(matches!(ngap.handle_xnap_message(XnapNgapMessage::HandoverRequestAcknowledge {
+            ue_id: 1001, admitted: Vec::new(), rrc_container: Bytes::new(),
+        }).await, Err(LayerError::InvalidState(_))));
+        let drbs = vec![DrbSnStatus { drb_id: 1, ul_count: 0, dl_count: 0 }];
+        assert!(matches!(ngap.send_sn_status_transfer(1001, drbs.clone()).await, Err(LayerError::InvalidState(_))));
+        assert!(rrc_rx.try_recv().is_err());
+
+        // Without NG connection the SN status of an N2 handover cannot be sent
+        ngap.ue_contexts.get_mut(&1000).unwrap().handover = Some(NgHandover::N2Source);
+        assert!(matches!(ngap.send_sn_status_transfer(1000, drbs).await, Err(LayerError::ProcessingError(_))));
+        assert!(xnap_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_target_handover_failures() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let (xnap_tx, mut xnap_rx) = mpsc::channel(10);
+        ngap.set_xnap_channel(xnap_tx);
+
+        // No session on a slice served here
+        let mut context = xn_ue_context();
+        context.sessions[0].s_nssai = SNssai { sst: 2, sd: None };
+        ngap.handle_xnap_message(XnapNgapMessage::HandoverRequest { handover_id: 5, guami: GUAMI, context }).await.unwrap();
+        assert!(matches!(xnap_rx.try_recv().unwrap(), NgapXnapMessage::HandoverPreparationFailure {
+            handover_id: 5, cause: xnap_pdu::Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL,
+        }));
+        assert!(rrc_rx.try_recv().is_err());
+        assert!(ngap.pending_handovers.is_empty());
+
+        // RRC refuses the UE, once
+        let context = xn_ue_context();
+        ngap.handle_xnap_message(XnapNgapMessage::HandoverRequest { handover_id: 6, guami: GUAMI, context }).await.unwrap();
+        let NgapRrcMessage::HandoverRequest { handover_id, .. } = rrc_rx.try_recv().unwrap() else {
+            panic!("expected Handover Request");
+        };
+        ngap.send_handover_failure(handover_id, RrcReleaseCause::AlgorithmsNotSupported).await.unwrap();
+        assert!(matches!(xnap_rx.try_recv().unwrap(), NgapXnapMessage::HandoverPreparationFailure {
+            handover_id: 6, cause: xnap_pdu::Cause::ALGORITHMS_NOT_SUPPORTED,
+        }));
+        ngap.send_handover_failure(handover_id, RrcReleaseCause::AlgorithmsNotSupported).await.unwrap();
+        assert!(xnap_rx.try_recv().is_err());
+        assert!(matches!(ngap.send_handover_request_acknowledge(handover_id, 1000, &[1], &[], Bytes::new()).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(ngap.ue_contexts.is_empty());
+
+        // Sessions not admitted carry why: a slice not served here, a session RRC cannot set up
+        let mut context = xn_ue_context();
+        let mut other_slice = context.sessions[0].clone();
+        other_slice.pdu_session_id = 2;
+        other_slice.s_nssai = SNssai { sst: 2, sd: None };
+        let mut failed = context.sessions[0].clone();
+        failed.pdu_session_id = 3;
+        context.sessions.extend([other_slice, failed]);
+        ngap.handle_xnap_message(XnapNgapMessage::HandoverRequest { handover_id: 7, guami: GUAMI, context }).await.unwrap();
+        let NgapRrcMessage::HandoverRequest { handover_id, .. } = rrc_rx.try_recv().unwrap() else {
+            panic!("expected Handover Request");
+        };
+        ngap.send_handover_request_acknowledge(handover_id, 1000, &[1], &[3], Bytes::new()).await.unwrap();
+        let NgapXnapMessage::HandoverRequestAcknowledge { not_admitted, .. } = xnap_rx.try_recv().unwrap() else {
+            panic!("expected Handover Request Acknowledge");
+        };
+        assert_eq!(not_admitted, vec![
+            (2, xnap_pdu::Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN),
+            (3, xnap_pdu::Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL),
+        ]);
+        ngap.ue_contexts.clear();
+
+        // N2 Handover Request without GUAMI, and for a cell not served here
+        let session = xn_ue_context().sessions.remove(0);
+        let request = NgapPdu::initiating(NgapProcedureCode::HandoverResourceAllocation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_HANDOVER_TYPE, Criticality::Reject, &HandoverType::Intra5gs).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASON).unwrap()
+            .with_ie(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Reject,
+                     &AggregateMaximumBitRate { dl: 100_000_000, ul: 50_000_000 }).unwrap()
+            .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Reject, &UeSecurityCapabilities::default()).unwrap()
+            .with_ie(pdu::ID_SECURITY_CONTEXT, Criticality::Reject,
+                     &SecurityContext { next_hop_chaining_count: 2, next_hop: [0x5A; 32] }).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_HO_REQ, Criticality::Reject, &vec![PduSessionResourceSetupItemHoReq {
+                pdu_session_id: 1,
+                s_nssai: session.s_nssai,
+                transfer: PduSessionResourceSetupRequestTransfer {
+                    session_ambr: None,
+                    ul_tunnel: session.ul_tunnel,
+                    pdu_session_type: session.pdu_session_type,
+                    qos_flows: session.qos_flows,
+                },
+            }]).unwrap()
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(vec![SNssai { sst: 1, sd: None }])).unwrap()
+            .with_ie(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER, Criticality::Reject, &SourceToTargetTransparentContainer {
+                rrc_container: Bytes::from_static(&[0x01]),
+                target_cell: NrCgi { plmn_id: PLMN, nr_cell_identity: 0x19C001 },
+                source_cell: NrCgi { plmn_id: PLMN, nr_cell_identity: 0x19D001 },
+            }).unwrap();
+        assert!(matches!(ngap.handle_handover_request(&request).await, Err(LayerError::ProcessingError(_))));
+        // Handover Failure cannot reach the AMF without NG connection
+        let request = NgapPdu::decode(&request.with_ie(pdu::ID_GUAMI, Criticality::Reject, &GUAMI).unwrap()
+            .encode().unwrap()).unwrap();
+        assert!(matches!(ngap.handle_handover_request(&request).await, Err(LayerError::ProcessingError(_))));
+        assert!(rrc_rx.try_recv().is_err());
+        assert!(ngap.pending_handovers.is_empty());
+
+        // Arrival of unknown UEs, UEs without NG connection and UEs not handed over
+        assert!(matches!(ngap.send_handover_notify(1000).await, Err(LayerError::InvalidState(_))));
+        ngap.ue_contexts.insert(1000, NgapUeContext {
+            ran_ue_ngap_id: 1000,
+            handover: Some(NgHandover::N2Target),
+            ..Default::default()
+        });
+        assert!(matches!(ngap.send_handover_notify(1000).await, Err(LayerError::InvalidState(_))));
+        ngap.ue_contexts.insert(1001, NgapUeContext { ran_ue_ngap_id: 1001, amf_ue_ngap_id: Some(8), ..Default::default() });
+        ngap.send_handover_notify(1001).await.unwrap();
+
+        // SN status relayed by the AMF needs the container
+        let status = NgapPdu::initiating(NgapProcedureCode::DownlinkRanStatusTransfer)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(8)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1001)).unwrap();
+        assert!(matches!(ngap.handle_downlink_ran_status_transfer(&status).await, Err(LayerError::ProcessingError(_))));
+
+        // Path switch outcomes for a UE without path switch, with an unknown
+        // cause group or for an unknown UE
+        let acknowledge = NgapPdu::successful(NgapProcedureCode::PathSwitchRequest)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(9)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(1001)).unwrap()
+            .with_ie(pdu::ID_SECURITY_CONTEXT, Criticality::Reject,
+                     &SecurityContext { next_hop_chaining_count: 1, next_hop: [0x5A; 32] }).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SWITCHED_LIST, Criticality::Ignore,
+                     &vec![PduSessionResourceItem::new(1, &PathSwitchRequestAcknowledgeTransfer { ul_tunnel: None }).unwrap()]).unwrap()
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(vec![SNssai { sst: 1, sd: None }])).unwrap();
+        assert!(matches!(ngap.handle_path_switch_request_acknowledge(&acknowledge).await, Err(LayerError::InvalidState(_))));
+        assert_eq!(ngap.ue_contexts[&1001].amf_ue_ngap_id, Some(8));
+        let failure = |ran_ue_ngap_id, transfer| {
+            NgapPdu::unsuccessful(NgapProcedureCode::PathSwitchRequest)
+                .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(8)).unwrap()
+                .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id)).unwrap()
+                .with_ie(pdu::ID_PDU_SESSION_RESOURCE_RELEASED_LIST_PS_FAIL, Criticality::Ignore,
+                         &vec![PduSessionResourceItem { pdu_session_id: 1, transfer }]).unwrap()
+        };
+        assert!(matches!(ngap.handle_path_switch_request_failure(&failure(1001, Bytes::from_static(&[0x28]))).await,
+                         Err(LayerError::ProcessingError(_))));
+        let transfer = PduSessionResourceItem::new(1, &PathSwitchRequestUnsuccessfulTransfer {
+            cause: Cause::RADIO_RESOURCES_NOT_AVAILABLE,
+        }).unwrap().transfer;
+        assert!(matches!(ngap.handle_path_switch_request_failure(&failure(1002, transfer)).await,
+                         Err(LayerError::InvalidState(_))));
+        assert_eq!(ngap.ue_contexts[&1001].amf_ue_ngap_id, Some(8));
+        assert!(xnap_rx.try_recv().is_err());
+        assert!(rrc_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! RRC Inactive Transition Report (3GPP TS 38.413 section 8.3.5)
+//!
+//! The AMF asks for reports in Initial Context Setup Request or UE Context
+//! Modification Request. A subsequent state transition report request has
+//! every transition of the UE between RRC_CONNECTED and RRC_INACTIVE reported,
+//! a single RRC connected state report request is answered once, right after
+//! the response to the request since the UE is then RRC_CONNECTED.
+
+use super::pdu::{self, AmfUeNgapId, Criticality, NgapPdu, RanUeNgapId, RrcInactiveTransitionReportRequest, RrcState};
+use super::{NgapLayer, NgapProcedureCode};
+use crate::rrc;
+use crate::LayerError;
+use tracing::{debug, info};
+
+impl NgapLayer {
+    /// Keep the RRC Inactive Transition Report Request of the AMF for a UE
+    pub(super) fn set_rrc_inactive_transition_report_request(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        request: RrcInactiveTransitionReportRequest,
+    ) {
+        if let Some(ue_context) = self.ue_contexts.get_mut(&ran_ue_ngap_id) {
+            debug!("RRC Inactive Transition Report Request {:?} for RAN UE NGAP ID {}", request, ran_ue_ngap_id);
+            ue_context.rrc_inactive_transition_report_request =
+                (request != RrcInactiveTransitionReportRequest::CancelReport).then_some(request);
+        }
+    }
+
+    /// Answer a single RRC connected state report request of the AMF
+    pub(super) async fn send_single_rrc_connected_state_report(&mut self, ran_ue_ngap_id: u32) -> Result<(), LayerError> {
+        let Some(ue_context) = self.ue_contexts.get_mut(&ran_ue_ngap_id) else {
+            return Ok(());
+        };
+        if ue_context.rrc_inactive_transition_report_request
+            != Some(RrcInactiveTransitionReportRequest::SingleRrcConnectedStateReport)
+        {
+            return Ok(());
+        }
+        ue_context.rrc_inactive_transition_report_request = None;
+        self.send_rrc_inactive_transition_report(ran_ue_ngap_id, RrcState::Connected).await
+    }
+
+    /// Handle an RRC state transition of a UE, reporting it if the AMF asked for
+    /// subsequent state transition reports
+    pub(super) async fn handle_rrc_state_transition(&mut self, ran_ue_ngap_id: u32, state: rrc::RrcState) -> Result<(), LayerError> {
+        let state = match state {
+            rrc::RrcState::Inactive => RrcState::Inactive,
+            rrc::RrcState::Connected => RrcState::Connected,
+            rrc::RrcState::Idle => {
+                debug!("UE {} RRC state is now Idle, released through UE Context Release", ran_ue_ngap_id);
+                return Ok(());
+            }
+        };
+        let reported = self.ue_contexts.get(&ran_ue_ngap_id).is_some_and(|ctx| {
+            ctx.rrc_inactive_transition_report_request
+                == Some(RrcInactiveTransitionReportRequest::SubsequentStateTransitionReport)
+        });
+        if !reported {
+            debug!("UE {} RRC state is now {:?}, not reported", ran_ue_ngap_id, state);
+            return Ok(());
+        }
+        self.send_rrc_inactive_transition_report(ran_ue_ngap_id, state).await
+    }
+
+    /// Send RRC Inactive Transition Report
+    async fn send_rrc_inactive_transition_report(&mut self, ran_ue_ngap_id: u32, state: RrcState) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        info!("Sending RRC Inactive Transition Report for RAN UE NGAP ID {} ({:?})", ran_ue_ngap_id, state);
+        let pdu = self.build_rrc_inactive_transition_report(amf_ue_ngap_id, ran_ue_ngap_id, state)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build RRC Inactive Transition Report
+    pub(super) fn build_rrc_inactive_transition_report(
+        &self,
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        state: RrcState,
+    ) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::RrcInactiveTransitionReport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_RRC_STATE, Criticality::Ignore, &state)?
+            .with_ie(pdu::ID_USER_LOCATION_INFORMATION, Criticality::Ignore, &self.user_location_information())?;
+        Ok(pdu.encode()?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{PagingDrx, UserLocationInformationNr};
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn ngap() -> NgapLayer {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        ngap.ue_contexts.insert(1000, NgapUeContext {
+            ran_ue_ngap_id: 1000,
+            amf_ue_ngap_id: Some(7),
+            ..Default::default()
+        });
+        ngap
+    }
+
+    #[test]
+    fn test_rrc_inactive_transition_report() {
+        let ngap = ngap();
+        let report = ngap.build_rrc_inactive_transition_report(7, 1000, RrcState::Inactive).unwrap();
+        let report = NgapPdu::decode(&report).unwrap();
+        assert_eq!(report.procedure(), Some(NgapProcedureCode::RrcInactiveTransitionReport));
+        assert_eq!(report.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID).unwrap().0, 7);
+        assert_eq!(report.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID).unwrap().0, 1000);
+        assert_eq!(report.ie::<RrcState>(pdu::ID_RRC_STATE).unwrap(), RrcState::Inactive);
+        assert_eq!(report.ie::<UserLocationInformationNr>(pdu::ID_USER_LOCATION_INFORMATION).unwrap(),
+                   ngap.user_location_information());
+    }
+
+    #[tokio::test]
+    async fn test_rrc_inactive_transition_report_request() {
+        let mut ngap = ngap();
+
+        // Without a request of the AMF, transitions are not reported
+        ngap.handle_rrc_state_transition(1000, rrc::RrcState::Inactive).await.unwrap();
+        ngap.send_single_rrc_connected_state_report(1000).await.unwrap();
+
+        // Subsequent state transitions are reported, here without an NG connection
+        ngap.set_rrc_inactive_transition_report_request(1000, RrcInactiveTransitionReportRequest::SubsequentStateTransitionReport);
+        assert!(ngap.handle_rrc_state_transition(1000, rrc::RrcState::Inactive).await.is_err());
+        assert!(ngap.handle_rrc_state_transition(1000, rrc::RrcState::Connected).await.is_err());
+        ngap.handle_rrc_state_transition(1000, rrc::RrcState::Idle).await.unwrap();
+
+        // A single RRC connected state report is sent once
+        ngap.set_rrc_inactive_transition_report_request(1000, RrcInactiveTransitionReportRequest::SingleRrcConnectedStateReport);
+        ngap.handle_rrc_state_transition(1000, rrc::RrcState::Inactive).await.unwrap();
+        assert!(ngap.send_single_rrc_connected_state_report(1000).await.is_err());
+        assert_eq!(ngap.ue_contexts[&1000].rrc_inactive_transition_report_request, None);
+        ngap.send_single_rrc_connected_state_report(1000).await.unwrap();
+
+        ngap.set_rrc_inactive_transition_report_request(1000, RrcInactiveTransitionReportRequest::SubsequentStateTransitionReport);
+        ngap.set_rrc_inactive_transition_report_request(1000, RrcInactiveTransitionReportRequest::CancelReport);
+        assert_eq!(ngap.ue_contexts[&1000].rrc_inactive_transition_report_request, None);
+        ngap.handle_rrc_state_transition(1000, rrc::RrcState::Inactive).await.unwrap();
+    }
+
+    #[test]
+    fn test_rrc_inactive_transition_report_request_ie() {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::UeContextModification)
+            .with_ie(pdu::ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST, Criticality::Ignore,
+                     &RrcInactiveTransitionReportRequest::SingleRrcConnectedStateReport).unwrap();
+        let pdu = NgapPdu::decode(&pdu.encode().unwrap()).unwrap();
+        assert_eq!(pdu.ie::<RrcInactiveTransitionReportRequest>(pdu::ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST).unwrap(),
+                   RrcInactiveTransitionReportRequest::SingleRrcConnectedStateReport);
+        assert!(pdu.optional_ie::<RrcState>(pdu::ID_RRC_STATE).unwrap().is_none());
+    }
+}
@@ -0,0 +1,673 @@
+//! NG Application Protocol (NGAP) Layer Implementation
+//! 
+//! Implements the 5G NGAP protocol according to 3GPP TS 38.413
+
+pub mod amf;
+pub mod aper;
+pub mod association;
+pub mod configuration_update;
+pub mod context;
+pub mod error_indication;
+pub mod handover;
+pub mod inactive;
+pub mod modification;
+pub mod nas_transport;
+pub mod paging;
+pub mod pdu;
+pub mod release;
+pub mod reset;
+pub mod setup;
+pub mod transport;
+
+use crate::{LayerError, ProtocolLayer};
+use crate::gtpu::NgapGtpuMessage;
+use crate::rrc::{NgapRrcMessage, PduSessionProcedure, RrcNgapMessage};
+use crate::xnap::NgapXnapMessage;
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use tokio::sync::mpsc;
+use pdu::{
+    AmfUeNgapId, Cause, Criticality, Guami, NgapPdu, NgapPduType, PagingDrx, RanUeNgapId,
+    RrcInactiveTransitionReportRequest, SupportedTaItem, UeSecurityCapabilities,
+};
+use common::types::{AggregateMaximumBitRate, SNssai};
+use context::PduSessionContext;
+use handover::{HandoverTarget, NgHandover, PendingHandover};
+use modification::PendingContextModification;
+use pdu::PduSessionResourceModifyRequestTransfer;
+use amf::{AmfConnection, AmfEndpoint};
+use transport::{NgTransportConfig, NgTransportEvent, NON_UE_STREAM};
+
+/// NGAP layer configuration
+pub struct NgapConfig {
+    /// AMFs to connect to, one NG association each
+    pub amfs: Vec<AmfEndpoint>,
+    /// Local address for SCTP binding
+    pub local_address: SocketAddr,
+    /// gNB ID
+    pub gnb_id: u32,
+    /// gNB ID length in bits (22..32)
+    pub gnb_id_bits: u8,
+    /// PLMN ID (MCC + MNC)
+    pub plmn_id: [u8; 3],
+    /// RAN node name sent in NG Setup
+    pub ran_node_name: String,
+    /// Tracking areas served by the gNB, with their PLMNs and slices
+    pub supported_tas: Vec<SupportedTaItem>,
+    /// Default paging DRX of the cell
+    pub default_paging_drx: PagingDrx,
+    /// NR cell identity of the served cell (36 bits)
+    pub nr_cell_identity: u64,
+    /// Tracking area code of the served cell
+    pub tac: u32,
+    /// Local N3 GTP-U address given to the UPF for downlink tunnels
+    pub gtpu_address: IpAddr,
+    /// NG-C transport: SCTP or TCP framing, streams and multi-homing
+    pub transport: NgTransportConfig,
+    /// Neighbour cells handed over to through the AMF, the others are reached over Xn
+    pub handover_targets: Vec<HandoverTarget>,
+}
+
+/// UE-associated NGAP state
+#[derive(Debug, Default)]
+pub struct NgapUeContext {
+    /// RAN UE NGAP ID
+    pub ran_ue_ngap_id: u32,
+    /// AMF UE NGAP ID (known after the first downlink UE-associated message)
+    pub amf_ue_ngap_id: Option<u64>,
+    /// Index of the AMF serving the UE
+    pub amf: Option<usize>,
+    /// UE radio capability waiting to be indicated to the AMF
+    pub pending_ue_radio_capability: Option<Bytes>,
+    /// GUAMI of the serving AMF
+    pub guami: Option<Guami>,
+    /// Slices the UE is allowed to use
+    pub allowed_nssai: Vec<SNssai>,
+    /// UE Aggregate Maximum Bit Rate
+    pub ue_ambr: Option<AggregateMaximumBitRate>,
+    /// PDU sessions indexed by PDU session ID
+    pub pdu_sessions: HashMap<u8, PduSessionContext>,
+    /// PDU sessions of the ongoing setup or modification rejected before reaching RRC
+    pub rejected_pdu_sessions: Vec<(u8, Cause)>,
+    /// PDU session modifications waiting for RRC
+    pub pending_pdu_session_modifications: HashMap<u8, PduSessionResourceModifyRequestTransfer>,
+    /// UE Context Modification waiting for RRC
+    pub pending_context_modification: Option<PendingContextModification>,
+    /// UE security capabilities, given to the target of a handover
+    pub security_capabilities: Option<UeSecurityCapabilities>,
+    /// Ongoing handover of the UE
+    pub handover: Option<NgHandover>,
+    /// RRC state transitions of the UE the AMF asked to be reported
+    pub rrc_inactive_transition_report_request: Option<RrcInactiveTransitionReportRequest>,
+}
+
+/// NGAP layer implementation
+pub struct NgapLayer {
+    config: NgapConfig,
+    initialized: bool,
+    /// Set once the layer is shut down, stops reconnection
+    stopped: bool,
+    /// NG associations, one per configured AMF
+    amfs: Vec<AmfConnection>,
+    /// AMF receiving non UE-associated signalling: the AMF the PDU being
+    /// processed came from
+    active_amf: usize,
+    /// Events of the transports once NG Setup is done, tagged with the AMF index
+    transport_tx: mpsc::Sender<(usize, NgTransportEvent)>,
+    transport_rx: Option<mpsc::Receiver<(usize, NgTransportEvent)>>,
+    /// UE contexts indexed by RAN UE NGAP ID
+    ue_contexts: HashMap<u32, NgapUeContext>,
+    /// Channel towards RRC
+    rrc_tx: Option<mpsc::Sender<NgapRrcMessage>>,
+    /// Channel towards the GTP-U endpoint
+    gtpu_tx: Option<mpsc::Sender<NgapGtpuMessage>>,
+    /// Next gNB-side GTP-U TEID
+    next_gtpu_teid: u32,
+    /// Channel towards XnAP
+    xnap_tx: Option<mpsc::Sender<NgapXnapMessage>>,
+    /// Incoming handovers waiting for RRC, by handover ID
+    pending_handovers: HashMap<u32, PendingHandover>,
+    /// Next handover ID given to RRC
+    next_handover_id: u32,
+}
+
+#[allow(clippy::new_without_default)]
+impl NgapLayer {
+    /// Create a new NGAP layer instance
+    pub fn new(config: NgapConfig) -> Self {
+        let (transport_tx, transport_rx) = mpsc::channel(100);
+        let amfs = config.amfs.iter().cloned().map(AmfConnection::new).collect();
+        Self {
+            config,
+            initialized: false,
+            stopped: false,
+            amfs,
+            active_amf: 0,
+            transport_tx,
+            transport_rx: Some(transport_rx),
+            ue_contexts: HashMap::new(),
+            rrc_tx: None,
+            gtpu_tx: None,
+            next_gtpu_teid: 1,
+            xnap_tx: None,
+            pending_handovers: HashMap::new(),
+            next_handover_id: 1,
+        }
+    }
+    
+    /// Set the channel used to pass messages to RRC
+    pub fn set_rrc_channel(&mut self, tx: mpsc::Sender<NgapRrcMessage>) {
+        self.rrc_tx = Some(tx);
+    }
+    
+    /// Set the channel used to manage the tunnels of the GTP-U endpoint
+    pub fn set_gtpu_channel(&mut self, tx: mpsc::Sender<NgapGtpuMessage>) {
+        self.gtpu_tx = Some(tx);
+    }
+    
+    /// Set the channel used to hand UEs over to neighbouring gNBs
+    pub fn set_xnap_channel(&mut self, tx: mpsc::Sender<NgapXnapMessage>) {
+        self.xnap_tx = Some(tx);
+    }
+    
+    /// Handle a message from the RRC layer
+    pub async fn handle_rrc_message(&mut self, message: RrcNgapMessage) -> Result<(), LayerError> {
+        match message {
+            RrcNgapMessage::InitialUeMessage { ue_id, nas_pdu, establishment_cause, amf_selection } => {
+                self.send_initial_ue_message(ue_id, nas_pdu, establishment_cause, &amf_selection).await
+            }
+            RrcNgapMessage::UplinkNasTransport { ue_id, nas_pdu } => {
+                self.send_uplink_nas_transport(ue_id, nas_pdu).await
+            }
+            RrcNgapMessage::UeRadioCapabilityInfo { ue_id, ue_radio_capability } => {
+                self.send_ue_radio_capability_info(ue_id, ue_radio_capability).await
+            }
+            RrcNgapMessage::PduSessionResourceResponse { ue_id, procedure, succeeded, failed } => match procedure {
+                PduSessionProcedure::InitialContextSetup => {
+                    self.send_initial_context_setup_response(ue_id, &succeeded, &failed).await
+                }
+                PduSessionProcedure::Setup => {
+                    self.send_pdu_session_resource_setup_response(ue_id, &succeeded, &failed).await
+                }
+                PduSessionProcedure::Modify => {
+                    self.send_pdu_session_resource_modify_response(ue_id, &succeeded, &failed).await
+                }
+                PduSessionProcedure::Release => {
+                    self.send_pdu_session_resource_release_response(ue_id, &succeeded, &failed).await
+                }
+            },
+            RrcNgapMessage::InitialContextSetupFailure { ue_id, cause } => {
+                self.send_initial_context_setup_failure(ue_id, cause).await
+            }
+            RrcNgapMessage::UeContextReleaseRequest { ue_id, cause, pdu_session_ids } => {
+                self.send_ue_context_release_request(ue_id, cause, &pdu_session_ids).await
+            }
+            RrcNgapMessage::RrcStateTransition { ue_id, state } => {
+                self.handle_rrc_state_transition(ue_id, state).await
+            }
+            RrcNgapMessage::UeContextReleaseComplete { ue_id, pdu_session_ids } => {
+                self.send_ue_context_release_complete(ue_id, &pdu_session_ids).await
+            }
+            RrcNgapMessage::UeContextModificationResponse { ue_id } => {
+                self.send_ue_context_modification_response(ue_id).await
+            }
+            RrcNgapMessage::UeContextModificationFailure { ue_id, cause } => {
+                self.send_ue_context_modification_failure(ue_id, cause).await
+            }
+            RrcNgapMessage::HandoverRequired { ue_id, target_pci, security_key, next_hop_chaining_count, rrc_container } => {
+                self.send_handover_required(ue_id, target_pci, security_key, next_hop_chaining_count, rrc_container).await
+            }
+            RrcNgapMessage::HandoverRequestAcknowledge { handover_id, ue_id, admitted, failed, rrc_container } => {
+                self.send_handover_request_acknowledge(handover_id, ue_id, &admitted, &failed, rrc_container).await
+            }
+            RrcNgapMessage::HandoverFailure { handover_id, cause } => {
+                self.send_handover_failure(handover_id, cause).await
+            }
+            RrcNgapMessage::SnStatusTransfer { ue_id, drbs } => self.send_sn_status_transfer(ue_id, drbs).await,
+            RrcNgapMessage::HandoverNotify { ue_id } => self.send_handover_notify(ue_id).await,
+        }
+    }
+    
+    /// Send UE Radio Capability Info Indication, or keep the capability until the
+    /// AMF UE NGAP ID is known
+    async fn send_ue_radio_capability_info(&mut self, ran_ue_ngap_id: u32, ue_radio_capability: Bytes) -> Result<(), LayerError> {
+        let ue_context = self.ue_contexts.entry(ran_ue_ngap_id).or_insert_with(|| NgapUeContext {
+            ran_ue_ngap_id,
+            ..Default::default()
+        });
+        
+        let amf_ue_ngap_id = match ue_context.amf_ue_ngap_id {
+            Some(id) => id,
+            None => {
+                debug!("AMF UE NGAP ID unknown for RAN UE NGAP ID {}, deferring UE Radio Capability Info Indication", 
+                       ran_ue_ngap_id);
+                ue_context.pending_ue_radio_capability = Some(ue_radio_capability);
+                return Ok(());
+            }
+        };
+        
+        info!("Sending UE Radio Capability Info Indication for RAN UE NGAP ID {}", ran_ue_ngap_id);
+        let pdu = self.build_ue_radio_capability_info_indication(amf_ue_ngap_id, ran_ue_ngap_id, &ue_radio_capability)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+    
+    /// Build UE Radio Capability Info Indication message
+    fn build_ue_radio_capability_info_indication(&self, amf_ue_ngap_id: u64, ran_ue_ngap_id: u32, ue_radio_capability: &Bytes) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::UeRadioCapabilityInfoIndication)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_UE_RADIO_CAPABILITY, Criticality::Ignore, ue_radio_capability)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+    
+    /// Handle an NGAP PDU received from the AMF
+    async fn handle_amf_pdu(&mut self, pdu: NgapPdu) -> Result<(), LayerError> {
+        let Some(procedure) = pdu.procedure() else {
+            warn!("Unknown NGAP procedure code {}", pdu.procedure_code);
+            return Ok(());
+        };
+        
+        // UE-associated requests are checked against the UE contexts first. Downlink
+        // NAS Transport and Initial Context Setup may assign the AMF UE NGAP ID.
+        if pdu.pdu_type == NgapPduType::InitiatingMessage {
+            let assigns_amf_id = match procedure {
+                NgapProcedureCode::DownlinkNasTransport | NgapProcedureCode::InitialContextSetup => Some(true),
+                NgapProcedureCode::PduSessionResourceSetup | NgapProcedureCode::PduSessionResourceModify |
+                NgapProcedureCode::PduSessionResourceRelease | NgapProcedureCode::UeContextModification |
+                NgapProcedureCode::DownlinkRanStatusTransfer => Some(false),
+                _ => None,
+            };
+            if let Some(assigns_amf_id) = assigns_amf_id {
+                if !self.check_ue_association(&pdu, assigns_amf_id).await? {
+                    return Ok(());
+                }
+            }
+        }
+        
+        match (pdu.pdu_type, procedure) {
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::DownlinkNasTransport) => {
+                self.handle_downlink_nas_transport(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::InitialContextSetup) => {
+                self.handle_initial_context_setup_request(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::PduSessionResourceSetup) => {
+                self.handle_pdu_session_resource_setup_request(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::PduSessionResourceModify) => {
+                self.handle_pdu_session_resource_modify_request(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::PduSessionResourceRelease) => {
+                self.handle_pdu_session_resource_release_command(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::UeContextModification) => {
+                self.handle_ue_context_modification_request(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::UeContextRelease) => {
+                self.handle_ue_context_release_command(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::HandoverResourceAllocation) => {
+                self.handle_handover_request(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::DownlinkRanStatusTransfer) => {
+                self.handle_downlink_ran_status_transfer(&pdu).await
+            }
+            (NgapPduType::SuccessfulOutcome, NgapProcedureCode::HandoverPreparation) => {
+                self.handle_handover_command(&pdu).await
+            }
+            (NgapPduType::UnsuccessfulOutcome, NgapProcedureCode::HandoverPreparation) => {
+                self.handle_handover_preparation_failure(&pdu).await
+            }
+            (NgapPduType::SuccessfulOutcome, NgapProcedureCode::PathSwitchRequest) => {
+                self.handle_path_switch_request_acknowledge(&pdu).await
+            }
+            (NgapPduType::UnsuccessfulOutcome, NgapProcedureCode::PathSwitchRequest) => {
+                self.handle_path_switch_request_failure(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::Paging) => {
+                self.handle_paging(&pdu).await
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::ErrorIndication) => {
+                self.handle_error_indication(&pdu)
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::AmfStatusIndication) => {
+                self.handle_amf_status_indication(&pdu)
+            }
+            (NgapPduType::InitiatingMessage, NgapProcedureCode::NgReset) => {
+                self.handle_ng_reset(&pdu).await
+            }
+            (NgapPduType::SuccessfulOutcome, NgapProcedureCode::NgReset) => {
+                self.handle_ng_reset_acknowledge(&pdu)
+            }
+            (NgapPduType::SuccessfulOutcome, NgapProcedureCode::RanConfigurationUpdate) => {
+                self.handle_ran_configuration_update_acknowledge()
+            }
+            (NgapPduType::UnsuccessfulOutcome, NgapProcedureCode::RanConfigurationUpdate) => {
+                self.handle_ran_configuration_update_failure(&pdu)
+            }
+            (pdu_type, procedure) => {
+                debug!("Unhandled NGAP {:?} for {:?}", pdu_type, procedure);
+                Ok(())
+            }
+        }
+    }
+    
+    /// Send a non UE-associated NGAP PDU to the active AMF
+    async fn send_pdu(&self, pdu: Vec<u8>) -> Result<(), LayerError> {
+        self.send_to_amf(self.active_amf, NON_UE_STREAM, pdu).await
+    }
+    
+    /// Send a UE-associated NGAP PDU to the AMF serving the UE, on the stream of the UE
+    async fn send_ue_pdu(&self, ran_ue_ngap_id: u32, pdu: Vec<u8>) -> Result<(), LayerError> {
+        let amf = self.ue_contexts.get(&ran_ue_ngap_id)
+            .and_then(|ctx| ctx.amf)
+            .unwrap_or(self.active_amf);
+        let stream = self.amfs.get(amf)
+            .and_then(|connection| connection.transport.as_ref())
+            .map_or(NON_UE_STREAM, |transport| transport.ue_stream(ran_ue_ngap_id));
+        self.send_to_amf(amf, stream, pdu).await
+    }
+    
+    async fn send_to_amf(&self, amf: usize, stream: u16, pdu: Vec<u8>) -> Result<(), LayerError> {
+        if !self.is_amf_connected(amf) {
+            return Err(LayerError::ProcessingError("NG connection not established".to_string()));
+        }
+        
+        match &self.amfs[amf].transport {
+            Some(transport) => transport.send(stream, pdu).await,
+            None => Err(LayerError::InvalidState("NG transport not established".to_string())),
+        }
+    }
+    
+    /// Establish the NG connection with each AMF. AMFs that cannot be reached
+    /// are left to the NG association task to retry.
+    async fn setup_ng_connection(&mut self) -> Result<(), LayerError> {
+        if self.amfs.is_empty() {
+            return Err(LayerError::InvalidConfiguration("No AMF configured".to_string()));
+        }
+        
+        let mut last_error = None;
+        for amf in 0..self.amfs.len() {
+            info!("Setting up NG connection to AMF at {} over {:?}",
+                  self.amfs[amf].endpoint.address, self.config.transport.kind);
+            let result = match self.connect_transport(amf).await {
+                // NG Setup, retried with backoff while the AMF asks us to wait
+                Ok(()) => self.perform_ng_setup(amf).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    self.amfs[amf].ng_connected = true;
+                    self.amfs[amf].next_attempt = None;
+                    self.start_transport_reader(amf);
+                    info!("NG Setup procedure completed successfully with AMF at {}", self.amfs[amf].endpoint.address);
+                }
+                Err(e) => {
+                    warn!("NG connection to AMF at {} failed: {}", self.amfs[amf].endpoint.address, e);
+                    self.amfs[amf].transport = None;
+                    self.schedule_reconnect(amf);
+                    last_error = Some(e);
+                }
+            }
+        }
+        
+        match last_error {
+            Some(e) if !self.is_ng_connected() => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolLayer for NgapLayer {
+    async fn initialize(&mut self) -> Result<(), LayerError> {
+        info!("Initializing NGAP layer");
+        debug!("NGAP config: gnb_id={:#x}, amfs={:?}", 
+               self.config.gnb_id,
+               self.config.amfs.iter().map(|amf| amf.address).collect::<Vec<_>>());
+        
+        self.stopped = false;
+        self.initialized = true;
+        
+        // Attempt to connect to AMF
+        if let Err(e) = self.setup_ng_connection().await {
+            self.initialized = false;
+            return Err(e);
+        }
+        
+        info!("NGAP layer initialized successfully");
+        Ok(())
+    }
+    
+    async fn process_uplink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        if !self.is_ng_connected() {
+            return Err(LayerError::ProcessingError("NG connection not established".to_string()));
+        }
+        
+        debug!("NGAP processing uplink data: {} bytes", data.len());
+        
+        // UE-associated signalling from RRC arrives through handle_rrc_message, here
+        // the data is an already encoded NGAP PDU for the AMF
+        let pdu = NgapPdu::decode(&data)?;
+        match pdu.optional_ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)? {
+            Some(ran_ue_ngap_id) => self.send_ue_pdu(ran_ue_ngap_id.0, data.to_vec()).await?,
+            None => self.send_pdu(data.to_vec()).await?,
+        }
+        
+        Ok(Bytes::new())
+    }
+    
+    async fn process_downlink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        debug!("NGAP processing downlink data: {} bytes", data.len());
+        
+        // Messages for the UE are forwarded to RRC on the RRC channel
+        let pdu = match NgapPdu::decode(&data) {
+            Ok(pdu) => pdu,
+            Err(e) => {
+                if let Err(report_error) = self.send_error_indication(None, None, Cause::TRANSFER_SYNTAX_ERROR).await {
+                    warn!("Failed to report undecodable NGAP PDU: {}", report_error);
+                }
+                return Err(e);
+            }
+        };
+        self.handle_amf_pdu(pdu).await?;
+        
+        Ok(Bytes::new())
+    }
+    
+    async fn shutdown(&mut self) -> Result<(), LayerError> {
+        info!("Shutting down NGAP layer");
+        
+        // Closing the transports ends the associations with the AMFs
+        for connection in &mut self.amfs {
+            if connection.transport.take().is_some() {
+                info!("NG transport to AMF at {} closed", connection.endpoint.address);
+            }
+            connection.ng_connected = false;
+            connection.next_attempt = None;
+        }
+        self.stopped = true;
+        self.initialized = false;
+        Ok(())
+    }
+}
+
+/// NGAP procedure codes (3GPP TS 38.413 section 9.4.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NgapProcedureCode {
+    AmfConfigurationUpdate = 0,
+    AmfStatusIndication = 1,
+    DownlinkNasTransport = 4,
+    DownlinkRanStatusTransfer = 7,
+    ErrorIndication = 9,
+    HandoverCancel = 10,
+    HandoverNotification = 11,
+    HandoverPreparation = 12,
+    HandoverResourceAllocation = 13,
+    InitialContextSetup = 14,
+    InitialUeMessage = 15,
+    NgReset = 20,
+    NgSetup = 21,
+    Paging = 24,
+    PathSwitchRequest = 25,
+    PduSessionResourceModify = 26,
+    PduSessionResourceModifyIndication = 27,
+    PduSessionResourceRelease = 28,
+    PduSessionResourceSetup = 29,
+    RanConfigurationUpdate = 35,
+    RrcInactiveTransitionReport = 37,
+    UeContextModification = 40,
+    UeContextRelease = 41,
+    UeContextReleaseRequest = 42,
+    UeRadioCapabilityInfoIndication = 44,
+    UplinkNasTransport = 46,
+    UplinkRanStatusTransfer = 49,
+}
+
+impl NgapProcedureCode {
+    /// Look up a procedure code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use NgapProcedureCode::*;
+        
+        [
+            AmfConfigurationUpdate, AmfStatusIndication, DownlinkNasTransport, DownlinkRanStatusTransfer,
+            ErrorIndication, HandoverCancel, HandoverNotification, HandoverPreparation,
+            HandoverResourceAllocation, InitialContextSetup, InitialUeMessage, NgReset, NgSetup, Paging,
+            PathSwitchRequest, PduSessionResourceModify, PduSessionResourceModifyIndication,
+            PduSessionResourceRelease, PduSessionResourceSetup, RanConfigurationUpdate,
+            RrcInactiveTransitionReport, UeContextModification, UeContextRelease, UeContextReleaseRequest,
+            UeRadioCapabilityInfoIndication, UplinkNasTransport, UplinkRanStatusTransfer,
+        ].into_iter().find(|procedure| *procedure as u8 == code)
+    }
+    
+    /// Criticality of the procedure (3GPP TS 38.413 section 9.4.4): reject for
+    /// class 1 procedures, ignore for class 2
+    pub fn criticality(&self) -> Criticality {
+        use NgapProcedureCode::*;
+        
+        match self {
+            AmfStatusIndication | DownlinkNasTransport | DownlinkRanStatusTransfer | ErrorIndication |
+            HandoverNotification | InitialUeMessage | Paging | RrcInactiveTransitionReport |
+            UeContextReleaseRequest | UeRadioCapabilityInfoIndication | UplinkNasTransport |
+            UplinkRanStatusTransfer => Criticality::Ignore,
+            _ => Criticality::Reject,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdu::{GlobalGnbId, NgapPduType, NodeName};
+    use std::str::FromStr;
+    
+    #[tokio::test]
+    async fn test_ngap_initialization() {
+        let config = NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 0x19B,
+            gnb_id_bits: 32,
+            plmn_id: [0x02, 0xF8, 0x39], // 208/93
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x19B001,
+            tac: 7,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig::default(),
+            handover_targets: Vec::new(),
+        };
+        
+        let mut ngap = NgapLayer::new(config);
+        // Note: This will fail in test as we can't actually connect to AMF
+        assert!(ngap.initialize().await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_ue_radio_capability_deferred() {
+        let config = NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 0x19B,
+            gnb_id_bits: 32,
+            plmn_id: [0x02, 0xF8, 0x39],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x19B001,
+            tac: 7,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig::default(),
+            handover_targets: Vec::new(),
+        };
+        
+        let mut ngap = NgapLayer::new(config);
+        let message = RrcNgapMessage::UeRadioCapabilityInfo {
+            ue_id: 1000,
+            ue_radio_capability: Bytes::from_static(&[0x01, 0x00, 0x00, 0x00]),
+        };
+        
+        // Without an AMF UE NGAP ID the capability is kept for later
+        assert!(ngap.handle_rrc_message(message).await.is_ok());
+        assert!(ngap.ue_contexts[&1000].pending_ue_radio_capability.is_some());
+    }
+    
+    #[test]
+    fn test_ng_setup_request_from_config() {
+        let config = NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 0x19B,
+            gnb_id_bits: 22,
+            plmn_id: [0x02, 0xF8, 0x39],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 7,
+                broadcast_plmns: vec![pdu::BroadcastPlmnItem {
+                    plmn_id: [0x02, 0xF8, 0x39],
+                    slices: vec![common::types::SNssai { sst: 1, sd: Some(0x010203) }],
+                }],
+            }],
+            default_paging_drx: PagingDrx::V128,
+            nr_cell_identity: 0x19B001,
+            tac: 7,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: NgTransportConfig::default(),
+            handover_targets: Vec::new(),
+        };
+        
+        let ngap = NgapLayer::new(config);
+        let request = NgapPdu::decode(&ngap.build_ng_setup_request().unwrap()).unwrap();
+        assert_eq!(request.pdu_type, NgapPduType::InitiatingMessage);
+        assert_eq!(request.procedure(), Some(NgapProcedureCode::NgSetup));
+        assert_eq!(request.ie::<GlobalGnbId>(pdu::ID_GLOBAL_RAN_NODE_ID).unwrap(), GlobalGnbId {
+            plmn_id: [0x02, 0xF8, 0x39],
+            gnb_id: 0x19B,
+            gnb_id_bits: 22,
+        });
+        assert_eq!(request.ie::<NodeName>(pdu::ID_RAN_NODE_NAME).unwrap().0, "Albor-gNodeB");
+        assert_eq!(request.ie::<Vec<SupportedTaItem>>(pdu::ID_SUPPORTED_TA_LIST).unwrap(), ngap.config.supported_tas);
+        assert_eq!(request.ie::<PagingDrx>(pdu::ID_DEFAULT_PAGING_DRX).unwrap(), PagingDrx::V128);
+        
+        // UE-associated IDs keep their full range
+        let indication = ngap.build_ue_radio_capability_info_indication(
+            0xFF_0000_0001, 0x8000_0000, &Bytes::from_static(&[0x01, 0x02])).unwrap();
+        let indication = NgapPdu::decode(&indication).unwrap();
+        assert_eq!(indication.criticality, Criticality::Ignore);
+        assert_eq!(indication.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID).unwrap().0, 0xFF_0000_0001);
+        assert_eq!(indication.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID).unwrap().0, 0x8000_0000);
+        assert_eq!(&indication.ie::<Bytes>(pdu::ID_UE_RADIO_CAPABILITY).unwrap()[..], &[0x01, 0x02]);
+    }
+}
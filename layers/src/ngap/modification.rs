@@ -0,0 +1,418 @@
+//! UE context modification and PDU session resource modification
+//!
+//! Implements UE Context Modification (3GPP TS 38.413 section 8.3.4) and PDU
+//! Session Resource Modify (section 8.2.3). Security and bearer changes are
+//! applied by RRC, the NGAP state is updated once RRC reports success.
+
+use super::pdu::{
+    self, AmfUeNgapId, Cause, Criticality, NgapPdu, PduSessionResourceItem, PduSessionResourceModifyItem,
+    PduSessionResourceModifyRequestTransfer, PduSessionResourceModifyResponseTransfer,
+    PduSessionResourceModifyUnsuccessfulTransfer, RanUeNgapId, RrcInactiveTransitionReportRequest, SecurityKey,
+    UeSecurityCapabilities,
+};
+use super::context::PduSessionContext;
+use super::{NgapLayer, NgapProcedureCode};
+use crate::rrc::{NgapRrcMessage, PduSessionResourceModify, RrcReleaseCause};
+use crate::LayerError;
+use common::types::AggregateMaximumBitRate;
+use tracing::{debug, info, warn};
+
+/// UE Context Modification waiting for RRC
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingContextModification {
+    /// New UE Aggregate Maximum Bit Rate
+    pub ue_ambr: Option<AggregateMaximumBitRate>,
+    /// New AMF UE NGAP ID
+    pub new_amf_ue_ngap_id: Option<u64>,
+}
+
+impl NgapLayer {
+    /// Handle UE Context Modification Request from the AMF
+    pub(super) async fn handle_ue_context_modification_request(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let security_key = pdu.optional_ie::<SecurityKey>(pdu::ID_SECURITY_KEY)?;
+        let ue_ambr = pdu.optional_ie::<AggregateMaximumBitRate>(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE)?;
+        let security_capabilities = pdu.optional_ie::<UeSecurityCapabilities>(pdu::ID_UE_SECURITY_CAPABILITIES)?;
+        let new_amf_ue_ngap_id = pdu.optional_ie::<AmfUeNgapId>(pdu::ID_NEW_AMF_UE_NGAP_ID)?.map(|id| id.0);
+        let report_request = pdu.optional_ie::<RrcInactiveTransitionReportRequest>(
+            pdu::ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        ue_context.pending_context_modification = Some(PendingContextModification { ue_ambr, new_amf_ue_ngap_id });
+        info!("UE Context Modification Request for RAN UE NGAP ID {} (new key: {}, UE-AMBR: {:?})",
+              ran_ue_ngap_id, security_key.is_some(), ue_ambr);
+        if let Some(report_request) = report_request {
+            self.set_rrc_inactive_transition_report_request(ran_ue_ngap_id, report_request);
+        }
+
+        self.send_to_rrc(NgapRrcMessage::UeContextModification {
+            ue_id: ran_ue_ngap_id,
+            security_key: security_key.map(|key| key.0),
+            nr_encryption_algorithms: security_capabilities.map(|caps| caps.nr_encryption_algorithms),
+            nr_integrity_algorithms: security_capabilities.map(|caps| caps.nr_integrity_algorithms),
+        }).await
+    }
+
+    /// Send UE Context Modification Response, applying the modification
+    pub(super) async fn send_ue_context_modification_response(&mut self, ran_ue_ngap_id: u32) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        if let Some(ue_context) = self.ue_contexts.get_mut(&ran_ue_ngap_id) {
+            let modification = ue_context.pending_context_modification.take().unwrap_or_default();
+            if modification.ue_ambr.is_some() {
+                ue_context.ue_ambr = modification.ue_ambr;
+            }
+            if let Some(new_amf_ue_ngap_id) = modification.new_amf_ue_ngap_id {
+                debug!("AMF UE NGAP ID of RAN UE NGAP ID {} changed from {} to {}",
+                       ran_ue_ngap_id, amf_ue_ngap_id, new_amf_ue_ngap_id);
+                ue_context.amf_ue_ngap_id = Some(new_amf_ue_ngap_id);
+            }
+        }
+
+        info!("Sending UE Context Modification Response for RAN UE NGAP ID {}", ran_ue_ngap_id);
+        let pdu = Self::build_ue_context_modification_response(amf_ue_ngap_id, ran_ue_ngap_id)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await?;
+        self.send_single_rrc_connected_state_report(ran_ue_ngap_id).await
+    }
+
+    /// Build UE Context Modification Response
+    pub(super) fn build_ue_context_modification_response(amf_ue_ngap_id: u64, ran_ue_ngap_id: u32) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::successful(NgapProcedureCode::UeContextModification)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Send UE Context Modification Failure, keeping the UE context unchanged
+    pub(super) async fn send_ue_context_modification_failure(&mut self, ran_ue_ngap_id: u32, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        if let Some(ue_context) = self.ue_contexts.get_mut(&ran_ue_ngap_id) {
+            ue_context.pending_context_modification = None;
+        }
+
+        let cause = Cause::from(cause);
+        warn!("Sending UE Context Modification Failure for RAN UE NGAP ID {}: {:?}", ran_ue_ngap_id, cause);
+        let pdu = Self::build_ue_context_modification_failure(amf_ue_ngap_id, ran_ue_ngap_id, cause)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build UE Context Modification Failure
+    pub(super) fn build_ue_context_modification_failure(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        cause: Cause,
+    ) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::unsuccessful(NgapProcedureCode::UeContextModification)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle PDU Session Resource Modify Request from the AMF
+    pub(super) async fn handle_pdu_session_resource_modify_request(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let items = pdu.ie::<Vec<PduSessionResourceModifyItem>>(pdu::ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_REQ)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        info!("PDU Session Resource Modify Request for RAN UE NGAP ID {} with {} PDU sessions", ran_ue_ngap_id, items.len());
+
+        let mut sessions = Vec::with_capacity(items.len());
+        for item in items {
+            let id = item.pdu_session_id;
+            let Some(session) = ue_context.pdu_sessions.get(&id) else {
+                warn!("Cannot modify unknown PDU session {} of RAN UE NGAP ID {}", id, ran_ue_ngap_id);
+                ue_context.rejected_pdu_sessions.push((id, Cause::UNKNOWN_PDU_SESSION_ID));
+                continue;
+            };
+
+            let transfer = item.transfer;
+            sessions.push(PduSessionResourceModify {
+                pdu_session_id: id,
+                qos_flows_to_add: transfer.qos_flows_to_add_or_modify.iter()
+                    .map(|flow| flow.qfi)
+                    .filter(|qfi| !session.qos_flows.iter().any(|flow| flow.qfi == *qfi))
+                    .collect(),
+                qos_flows_to_release: transfer.qos_flows_to_release.iter().map(|flow| flow.qfi).collect(),
+                nas_pdu: item.nas_pdu,
+            });
+            ue_context.pending_pdu_session_modifications.insert(id, transfer);
+        }
+
+        self.send_to_rrc(NgapRrcMessage::PduSessionResourceModify {
+            ue_id: ran_ue_ngap_id,
+            sessions,
+            nas_pdu: None,
+        }).await
+    }
+
+    /// Collect the outcome of a PDU session modification, applying the changes
+    /// of the sessions modified by RRC
+    fn pdu_session_modify_outcome(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(Vec<PduSessionResourceItem>, Vec<PduSessionResourceItem>), LayerError> {
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+
+        let mut modified = Vec::with_capacity(succeeded.len());
+        for id in succeeded {
+            let (Some(transfer), Some(session)) = (
+                ue_context.pending_pdu_session_modifications.remove(id),
+                ue_context.pdu_sessions.get_mut(id),
+            ) else {
+                return Err(LayerError::InvalidState(format!("No pending modification of PDU session {}", id)));
+            };
+            apply_modification(session, &transfer);
+            modified.push(PduSessionResourceItem::new(*id, &PduSessionResourceModifyResponseTransfer {
+                qos_flows: transfer.qos_flows_to_add_or_modify.iter().map(|flow| flow.qfi).collect(),
+                failed_qos_flows: Vec::new(),
+            })?);
+        }
+
+        let mut rejected = std::mem::take(&mut ue_context.rejected_pdu_sessions);
+        for id in failed {
+            ue_context.pending_pdu_session_modifications.remove(id);
+            rejected.push((*id, Cause::RADIO_RESOURCES_NOT_AVAILABLE));
+        }
+        let failed = rejected.into_iter()
+            .map(|(id, cause)| PduSessionResourceItem::new(id, &PduSessionResourceModifyUnsuccessfulTransfer { cause }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((modified, failed))
+    }
+
+    /// Send PDU Session Resource Modify Response
+    pub(super) async fn send_pdu_session_resource_modify_response(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        let (modified, failed) = self.pdu_session_modify_outcome(ran_ue_ngap_id, succeeded, failed)?;
+
+        info!("Sending PDU Session Resource Modify Response for RAN UE NGAP ID {} ({} modified, {} failed)",
+              ran_ue_ngap_id, modified.len(), failed.len());
+        let pdu = Self::build_pdu_session_resource_modify_response(amf_ue_ngap_id, ran_ue_ngap_id, modified, failed)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build PDU Session Resource Modify Response
+    pub(super) fn build_pdu_session_resource_modify_response(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        modified: Vec<PduSessionResourceItem>,
+        failed: Vec<PduSessionResourceItem>,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::successful(NgapProcedureCode::PduSessionResourceModify)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !modified.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_RES, Criticality::Ignore, &modified)?;
+        }
+        if !failed.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_MODIFY_LIST_MOD_RES, Criticality::Ignore, &failed)?;
+        }
+        Ok(pdu.encode()?.to_vec())
+    }
+}
+
+/// Apply a PDU Session Resource Modify Request Transfer to the session state
+fn apply_modification(session: &mut PduSessionContext, transfer: &PduSessionResourceModifyRequestTransfer) {
+    if transfer.session_ambr.is_some() {
+        session.session_ambr = transfer.session_ambr;
+    }
+    session.qos_flows.retain(|flow| !transfer.qos_flows_to_release.iter().any(|released| released.qfi == flow.qfi));
+    for flow in &transfer.qos_flows_to_add_or_modify {
+        match session.qos_flows.iter_mut().find(|existing| existing.qfi == flow.qfi) {
+            Some(existing) => *existing = *flow,
+            None => session.qos_flows.push(*flow),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{GtpTunnel, PagingDrx, PduSessionType, QosFlowWithCause};
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use bytes::Bytes;
+    use common::types::{AllocationRetentionPriority, FiveQi, QosCharacteristics, QosFlowDescriptor, SNssai};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    fn flow(qfi: u8, five_qi: FiveQi) -> QosFlowDescriptor {
+        QosFlowDescriptor {
+            qfi,
+            characteristics: QosCharacteristics::NonDynamic { five_qi, priority_level: None },
+            arp: AllocationRetentionPriority { priority_level: 8, may_trigger_pre_emption: false, pre_emptable: false },
+            gbr: None,
+        }
+    }
+
+    fn test_layer() -> NgapLayer {
+        NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x99, 0xF9, 0x07],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_pdu_session_resource_modify() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let mut ue_context = NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() };
+        ue_context.pdu_sessions.insert(1, PduSessionContext {
+            s_nssai: SNssai { sst: 1, sd: None },
+            pdu_session_type: PduSessionType::Ipv4,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([10, 45, 0, 1]), teid: 0x101 },
+            dl_teid: 1,
+            qos_flows: vec![flow(1, FiveQi::DEFAULT), flow(2, FiveQi::VIDEO)],
+            session_ambr: None,
+        });
+        ngap.ue_contexts.insert(1000, ue_context);
+
+        // Session 1 gets a voice flow and drops flow 2, session 3 does not exist
+        let transfer = PduSessionResourceModifyRequestTransfer {
+            session_ambr: Some(AggregateMaximumBitRate { dl: 2_000_000_000, ul: 1_000_000_000 }),
+            qos_flows_to_add_or_modify: vec![flow(5, FiveQi::VOICE)],
+            qos_flows_to_release: vec![QosFlowWithCause { qfi: 2, cause: Cause::Nas(0) }],
+        };
+        let request = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceModify)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_REQ, Criticality::Reject, &vec![
+                PduSessionResourceModifyItem {
+                    pdu_session_id: 1,
+                    nas_pdu: Some(Bytes::from_static(&[0x7E, 0x00, 0x68])),
+                    transfer: transfer.clone(),
+                },
+                PduSessionResourceModifyItem { pdu_session_id: 3, nas_pdu: None, transfer },
+            ]).unwrap();
+        let request = NgapPdu::decode(&request.encode().unwrap()).unwrap();
+        ngap.handle_pdu_session_resource_modify_request(&request).await.unwrap();
+
+        match rrc_rx.try_recv().unwrap() {
+            NgapRrcMessage::PduSessionResourceModify { ue_id, sessions, .. } => {
+                assert_eq!(ue_id, 1000);
+                assert_eq!(sessions.len(), 1);
+                assert_eq!(sessions[0].qos_flows_to_add, vec![5]);
+                assert_eq!(sessions[0].qos_flows_to_release, vec![2]);
+                assert!(sessions[0].nas_pdu.is_some());
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        let (modified, failed) = ngap.pdu_session_modify_outcome(1000, &[1], &[]).unwrap();
+        let session = &ngap.ue_contexts[&1000].pdu_sessions[&1];
+        assert_eq!(session.qos_flows.iter().map(|flow| flow.qfi).collect::<Vec<_>>(), vec![1, 5]);
+        assert_eq!(session.session_ambr.unwrap().dl, 2_000_000_000);
+
+        let response = NgapLayer::build_pdu_session_resource_modify_response(7, 1000, modified, failed).unwrap();
+        let response = NgapPdu::decode(&response).unwrap();
+        let modified = response.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_RES).unwrap();
+        assert_eq!(modified[0].decode_transfer::<PduSessionResourceModifyResponseTransfer>().unwrap().qos_flows, vec![5]);
+        let failed = response.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_MODIFY_LIST_MOD_RES).unwrap();
+        assert_eq!(failed[0].pdu_session_id, 3);
+        assert_eq!(failed[0].decode_transfer::<PduSessionResourceModifyUnsuccessfulTransfer>().unwrap().cause,
+                   Cause::UNKNOWN_PDU_SESSION_ID);
+    }
+
+    #[tokio::test]
+    async fn test_ue_context_modification() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+
+        let request = NgapPdu::initiating(NgapProcedureCode::UeContextModification)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_SECURITY_KEY, Criticality::Reject, &SecurityKey([0x3C; 32])).unwrap()
+            .with_ie(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Ignore,
+                     &AggregateMaximumBitRate { dl: 300_000_000, ul: 100_000_000 }).unwrap();
+        let request = NgapPdu::decode(&request.encode().unwrap()).unwrap();
+        ngap.handle_ue_context_modification_request(&request).await.unwrap();
+        match rrc_rx.try_recv().unwrap() {
+            NgapRrcMessage::UeContextModification { ue_id, security_key, nr_encryption_algorithms, .. } => {
+                assert_eq!(ue_id, 1000);
+                assert_eq!(security_key, Some([0x3C; 32]));
+                assert_eq!(nr_encryption_algorithms, None);
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        // Not connected to an AMF: the response cannot be sent but the new UE-AMBR
+        // is applied
+        assert!(ngap.send_ue_context_modification_response(1000).await.is_err());
+        assert_eq!(ngap.ue_contexts[&1000].ue_ambr.unwrap().dl, 300_000_000);
+        assert!(ngap.ue_contexts[&1000].pending_context_modification.is_none());
+
+        let failure = NgapLayer::build_ue_context_modification_failure(7, 1000, Cause::ALGORITHMS_NOT_SUPPORTED).unwrap();
+        let failure = NgapPdu::decode(&failure).unwrap();
+        assert_eq!(failure.pdu_type, pdu::NgapPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::ALGORITHMS_NOT_SUPPORTED);
+    }
+
+    #[tokio::test]
+    async fn test_modification_failures() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+
+        // Unknown UE
+        let request = NgapPdu::initiating(NgapProcedureCode::UeContextModification)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Ignore,
+                     &AggregateMaximumBitRate { dl: 300_000_000, ul: 100_000_000 }).unwrap()
+            .with_ie(pdu::ID_NEW_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(9)).unwrap();
+        assert!(matches!(ngap.handle_ue_context_modification_request(&request).await, Err(LayerError::InvalidState(_))));
+        let modify = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceModify)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_REQ, Criticality::Reject,
+                     &vec![PduSessionResourceModifyItem {
+                         pdu_session_id: 1,
+                         nas_pdu: None,
+                         transfer: PduSessionResourceModifyRequestTransfer {
+                             session_ambr: None,
+                             qos_flows_to_add_or_modify: vec![flow(5, FiveQi::VOICE)],
+                             qos_flows_to_release: Vec::new(),
+                         },
+                     }]).unwrap();
+        assert!(matches!(ngap.handle_pdu_session_resource_modify_request(&modify).await, Err(LayerError::InvalidState(_))));
+        assert!(rrc_rx.try_recv().is_err());
+
+        // A failed modification leaves UE-AMBR and AMF UE NGAP ID unchanged
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+        ngap.handle_ue_context_modification_request(&request).await.unwrap();
+        rrc_rx.try_recv().unwrap();
+        assert!(ngap.send_ue_context_modification_failure(1000, RrcReleaseCause::AlgorithmsNotSupported).await.is_err());
+        let ue_context = &ngap.ue_contexts[&1000];
+        assert!(ue_context.pending_context_modification.is_none());
+        assert_eq!((ue_context.ue_ambr, ue_context.amf_ue_ngap_id), (None, Some(7)));
+
+        // Missing mandatory modify list
+        let modify = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceModify)
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap();
+        assert!(matches!(ngap.handle_pdu_session_resource_modify_request(&modify).await, Err(LayerError::ProcessingError(_))));
+    }
+}
@@ -0,0 +1,245 @@
+//! UE-associated NAS transport
+//!
+//! Implements Initial UE Message, Uplink NAS Transport and Downlink NAS Transport
+//! according to 3GPP TS 38.413 section 8.6. Initial UE Message goes to the AMF
+//! selected for the UE, see `amf`.
+
+use super::pdu::{
+    self, AmfUeNgapId, Criticality, FiveGSTmsi, NgapPdu, NrCgi, RanUeNgapId, Tai, UeContextRequest,
+    UserLocationInformationNr,
+};
+use super::{NgapLayer, NgapProcedureCode, NgapUeContext};
+use crate::rrc::{AmfSelectionInfo, EstablishmentCause, NgapRrcMessage};
+use crate::LayerError;
+use bytes::Bytes;
+use tracing::{debug, info, warn};
+
+impl NgapLayer {
+    /// User location of UEs served by this gNB
+    pub(super) fn user_location_information(&self) -> UserLocationInformationNr {
+        UserLocationInformationNr {
+            nr_cgi: NrCgi {
+                plmn_id: self.config.plmn_id,
+                nr_cell_identity: self.config.nr_cell_identity,
+            },
+            tai: Tai {
+                plmn_id: self.config.plmn_id,
+                tac: self.config.tac,
+            },
+        }
+    }
+
+    /// Allocate the UE-associated logical NG connection, select the AMF and send
+    /// Initial UE Message
+    pub(super) async fn send_initial_ue_message(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        nas_pdu: Bytes,
+        establishment_cause: EstablishmentCause,
+        amf_selection: &AmfSelectionInfo,
+    ) -> Result<(), LayerError> {
+        if self.ue_contexts.get(&ran_ue_ngap_id).is_some_and(|ctx| ctx.amf_ue_ngap_id.is_some()) {
+            return Err(LayerError::InvalidState(format!("RAN UE NGAP ID {} already in use", ran_ue_ngap_id)));
+        }
+        let amf = self.select_amf(amf_selection);
+        self.ue_contexts.insert(ran_ue_ngap_id, NgapUeContext {
+            ran_ue_ngap_id,
+            amf,
+            ..Default::default()
+        });
+        let Some(amf) = amf else {
+            return Err(LayerError::ProcessingError(format!("No AMF available for RAN UE NGAP ID {}", ran_ue_ngap_id)));
+        };
+
+        info!("Sending Initial UE Message for RAN UE NGAP ID {} to AMF {} (cause {:?})",
+              ran_ue_ngap_id, amf, establishment_cause);
+        let pdu = self.build_initial_ue_message(ran_ue_ngap_id, &nas_pdu, establishment_cause, amf_selection.s_tmsi)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build Initial UE Message
+    pub(super) fn build_initial_ue_message(
+        &self,
+        ran_ue_ngap_id: u32,
+        nas_pdu: &Bytes,
+        establishment_cause: EstablishmentCause,
+        s_tmsi: Option<u64>,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::initiating(NgapProcedureCode::InitialUeMessage)
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Reject, nas_pdu)?
+            .with_ie(pdu::ID_USER_LOCATION_INFORMATION, Criticality::Reject, &self.user_location_information())?
+            .with_ie(pdu::ID_RRC_ESTABLISHMENT_CAUSE, Criticality::Ignore, &establishment_cause)?;
+        if let Some(s_tmsi) = s_tmsi {
+            pdu.add_ie(pdu::ID_FIVE_G_S_TMSI, Criticality::Reject, &FiveGSTmsi::from_value(s_tmsi))?;
+        }
+        let pdu = pdu.with_ie(pdu::ID_UE_CONTEXT_REQUEST, Criticality::Ignore, &UeContextRequest)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Send Uplink NAS Transport for a UE with an established NG connection
+    pub(super) async fn send_uplink_nas_transport(&mut self, ran_ue_ngap_id: u32, nas_pdu: Bytes) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.ue_contexts.get(&ran_ue_ngap_id)
+            .and_then(|ctx| ctx.amf_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No AMF UE NGAP ID for RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+
+        debug!("Sending Uplink NAS Transport for RAN UE NGAP ID {} ({} bytes)", ran_ue_ngap_id, nas_pdu.len());
+        let pdu = self.build_uplink_nas_transport(amf_ue_ngap_id, ran_ue_ngap_id, &nas_pdu)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build Uplink NAS Transport
+    pub(super) fn build_uplink_nas_transport(&self, amf_ue_ngap_id: u64, ran_ue_ngap_id: u32, nas_pdu: &Bytes) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::UplinkNasTransport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Reject, nas_pdu)?
+            .with_ie(pdu::ID_USER_LOCATION_INFORMATION, Criticality::Ignore, &self.user_location_information())?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle Downlink NAS Transport from the AMF
+    pub(super) async fn handle_downlink_nas_transport(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let nas_pdu = pdu.ie::<Bytes>(pdu::ID_NAS_PDU)?;
+
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        if ue_context.amf_ue_ngap_id != Some(amf_ue_ngap_id) {
+            if let Some(old) = ue_context.amf_ue_ngap_id {
+                warn!("AMF UE NGAP ID of RAN UE NGAP ID {} changed from {} to {}", ran_ue_ngap_id, old, amf_ue_ngap_id);
+            }
+            ue_context.amf_ue_ngap_id = Some(amf_ue_ngap_id);
+        }
+        let pending_capability = ue_context.pending_ue_radio_capability.take();
+        debug!("Downlink NAS Transport for RAN UE NGAP ID {} ({} bytes)", ran_ue_ngap_id, nas_pdu.len());
+
+        match &self.rrc_tx {
+            Some(rrc_tx) => {
+                rrc_tx.send(NgapRrcMessage::DownlinkNasTransport { ue_id: ran_ue_ngap_id, nas_pdu }).await
+                    .map_err(|_| LayerError::ProcessingError("RRC channel closed".into()))?;
+            }
+            None => warn!("No RRC channel, dropping NAS PDU for RAN UE NGAP ID {}", ran_ue_ngap_id),
+        }
+
+        // Now that the AMF UE NGAP ID is known, indicate a capability received earlier
+        if let Some(ue_radio_capability) = pending_capability {
+            self.send_ue_radio_capability_info(ran_ue_ngap_id, ue_radio_capability).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::PagingDrx;
+    use crate::ngap::NgapConfig;
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    #[tokio::test]
+    async fn test_nas_transport() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let nas = Bytes::from_static(&[0x7E, 0x00, 0x41, 0x79]);
+
+        let s_tmsi = FiveGSTmsi { amf_set_id: 1, amf_pointer: 0, five_g_tmsi: 0x1234 };
+        let initial = NgapPdu::decode(&ngap.build_initial_ue_message(
+            1000, &nas, EstablishmentCause::MoSignalling, Some(s_tmsi.value())).unwrap()).unwrap();
+        assert_eq!(initial.procedure(), Some(NgapProcedureCode::InitialUeMessage));
+        assert_eq!(initial.criticality, Criticality::Ignore);
+        assert_eq!(initial.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID).unwrap().0, 1000);
+        assert_eq!(initial.ie::<Bytes>(pdu::ID_NAS_PDU).unwrap(), nas);
+        let location = initial.ie::<UserLocationInformationNr>(pdu::ID_USER_LOCATION_INFORMATION).unwrap();
+        assert_eq!(location.nr_cgi.nr_cell_identity, 0x000004001);
+        assert_eq!(location.tai, Tai { plmn_id: PLMN, tac: 1 });
+        assert_eq!(initial.ie::<EstablishmentCause>(pdu::ID_RRC_ESTABLISHMENT_CAUSE).unwrap(),
+                   EstablishmentCause::MoSignalling);
+        assert_eq!(initial.ie::<FiveGSTmsi>(pdu::ID_FIVE_G_S_TMSI).unwrap(), s_tmsi);
+
+        // No NG connection to the AMF, but the UE context is allocated
+        let selection = AmfSelectionInfo::default();
+        assert!(ngap.send_initial_ue_message(1000, nas.clone(), EstablishmentCause::MoSignalling, &selection).await.is_err());
+        assert!(ngap.ue_contexts.contains_key(&1000));
+        assert!(ngap.send_uplink_nas_transport(1000, nas.clone()).await.is_err());
+
+        // Downlink NAS Transport assigns the AMF UE NGAP ID and goes to RRC
+        let downlink = NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(1)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Reject, &nas).unwrap();
+        ngap.handle_downlink_nas_transport(&downlink).await.unwrap();
+        assert_eq!(ngap.ue_contexts[&1000].amf_ue_ngap_id, Some(1));
+        assert!(matches!(rrc_rx.try_recv().unwrap(),
+                         NgapRrcMessage::DownlinkNasTransport { ue_id: 1000, nas_pdu } if nas_pdu == nas));
+
+        let uplink = NgapPdu::decode(&ngap.build_uplink_nas_transport(1, 1000, &nas).unwrap()).unwrap();
+        assert_eq!(uplink.procedure(), Some(NgapProcedureCode::UplinkNasTransport));
+        assert_eq!(uplink.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID).unwrap().0, 1);
+        assert_eq!(uplink.ie::<UserLocationInformationNr>(pdu::ID_USER_LOCATION_INFORMATION).unwrap(), location);
+    }
+
+    #[tokio::test]
+    async fn test_nas_transport_failures() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        let nas = Bytes::from_static(&[0x7E, 0x00, 0x56, 0x00]);
+
+        // Downlink NAS Transport for a UE the gNB does not know
+        let downlink = NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(1)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Reject, &nas).unwrap();
+        assert!(matches!(ngap.handle_downlink_nas_transport(&downlink).await, Err(LayerError::InvalidState(_))));
+
+        // Missing NAS-PDU
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, ..Default::default() });
+        let downlink = NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(1)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap();
+        assert!(matches!(ngap.handle_downlink_nas_transport(&downlink).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(ngap.ue_contexts[&1000].amf_ue_ngap_id, None);
+        assert!(rrc_rx.try_recv().is_err());
+
+        // Uplink NAS Transport before the AMF UE NGAP ID is known
+        assert!(matches!(ngap.send_uplink_nas_transport(1000, nas.clone()).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(ngap.send_uplink_nas_transport(1001, nas).await, Err(LayerError::InvalidState(_))));
+    }
+}
+
@@ -0,0 +1,138 @@
+//! Paging (3GPP TS 38.413 section 8.5.1)
+//!
+//! Forwards AMF paging for UEs in RRC_IDLE to RRC when the served cell belongs
+//! to one of the tracking areas to page in.
+
+use super::pdu::{self, NgapPdu, PagingDrx, PagingPriority, TaiListForPaging, UePagingIdentity};
+use super::NgapLayer;
+use crate::rrc::NgapRrcMessage;
+use crate::LayerError;
+use tracing::{debug, info};
+
+impl NgapLayer {
+    /// Handle Paging from the AMF
+    pub(super) async fn handle_paging(&self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let identity = pdu.ie::<UePagingIdentity>(pdu::ID_UE_PAGING_IDENTITY)?.0;
+        let tais = pdu.ie::<TaiListForPaging>(pdu::ID_TAI_LIST_FOR_PAGING)?;
+        let paging_drx = pdu.optional_ie::<PagingDrx>(pdu::ID_PAGING_DRX)?;
+        let paging_priority = pdu.optional_ie::<PagingPriority>(pdu::ID_PAGING_PRIORITY)?;
+
+        let served = tais.0.iter().any(|tai| tai.plmn_id == self.config.plmn_id && tai.tac == self.config.tac);
+        if !served {
+            debug!("Paging for 5G-S-TMSI {:012X} not for served TAC {}", identity.value(), self.config.tac);
+            return Ok(());
+        }
+
+        info!("Paging for 5G-S-TMSI {:012X} (paging DRX {:?}, priority {:?})",
+              identity.value(), paging_drx, paging_priority);
+        self.send_to_rrc(NgapRrcMessage::Paging {
+            s_tmsi: identity.value(),
+            paging_drx: paging_drx.map(|drx| drx.frames()),
+            paging_priority: paging_priority.map(|priority| priority.0),
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{Criticality, FiveGSTmsi, Tai};
+    use crate::ngap::{NgapConfig, NgapProcedureCode};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+    fn paging(tac: u32) -> NgapPdu {
+        let identity = FiveGSTmsi { amf_set_id: 1, amf_pointer: 2, five_g_tmsi: 0xC000_0001 };
+        NgapPdu::initiating(NgapProcedureCode::Paging)
+            .with_ie(pdu::ID_UE_PAGING_IDENTITY, Criticality::Ignore, &UePagingIdentity(identity)).unwrap()
+            .with_ie(pdu::ID_PAGING_DRX, Criticality::Ignore, &PagingDrx::V32).unwrap()
+            .with_ie(pdu::ID_TAI_LIST_FOR_PAGING, Criticality::Ignore,
+                     &TaiListForPaging(vec![Tai { plmn_id: PLMN, tac: 7 }, Tai { plmn_id: PLMN, tac }])).unwrap()
+            .with_ie(pdu::ID_PAGING_PRIORITY, Criticality::Ignore, &PagingPriority(3)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_paging() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+
+        let decoded = NgapPdu::decode(&paging(1).encode().unwrap()).unwrap();
+        assert_eq!(decoded.procedure(), Some(NgapProcedureCode::Paging));
+        assert_eq!(decoded.ie::<TaiListForPaging>(pdu::ID_TAI_LIST_FOR_PAGING).unwrap().0.len(), 2);
+
+        ngap.handle_paging(&decoded).await.unwrap();
+        match rrc_rx.try_recv().unwrap() {
+            NgapRrcMessage::Paging { s_tmsi, paging_drx, paging_priority } => {
+                assert_eq!(s_tmsi, 0x0042_C000_0001);
+                assert_eq!(FiveGSTmsi::from_value(s_tmsi).amf_pointer, 2);
+                assert_eq!(paging_drx, Some(32));
+                assert_eq!(paging_priority, Some(3));
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // Tracking areas not served by the cell are ignored
+        ngap.handle_paging(&paging(2)).await.unwrap();
+        assert!(rrc_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paging_errors() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x00, 0xF1, 0x10],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+
+        // Same TAC in another PLMN
+        ngap.handle_paging(&paging(1)).await.unwrap();
+        assert!(rrc_rx.try_recv().is_err());
+
+        // Missing UE Paging Identity or TAI List
+        let mut missing = paging(1);
+        missing.ies.retain(|ie| ie.id != pdu::ID_UE_PAGING_IDENTITY);
+        assert!(matches!(ngap.handle_paging(&missing).await, Err(LayerError::ProcessingError(_))));
+        let mut missing = paging(1);
+        missing.ies.retain(|ie| ie.id != pdu::ID_TAI_LIST_FOR_PAGING);
+        assert!(matches!(ngap.handle_paging(&missing).await, Err(LayerError::ProcessingError(_))));
+
+        // UE Paging Identity extension and Paging DRX outside the root
+        let mut extension = paging(1);
+        extension.ies[0].value = bytes::Bytes::from_static(&[0x80]);
+        assert!(matches!(ngap.handle_paging(&extension).await, Err(LayerError::ProcessingError(_))));
+        let mut drx = paging(1);
+        drx.ies[1].value = bytes::Bytes::from_static(&[0x81]);
+        assert!(matches!(ngap.handle_paging(&drx).await, Err(LayerError::InvalidPdu)));
+        assert!(rrc_rx.try_recv().is_err());
+    }
+}
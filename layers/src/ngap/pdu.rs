@@ -0,0 +1,3214 @@
+//! NGAP PDU and Information Element encoding
+//!
+//! NGAP-PDU structure and protocol IE containers according to 3GPP TS 38.413
+//! section 9.4, encoded with APER
+
+use super::aper::{AperDecoder, AperEncoder};
+use super::NgapProcedureCode;
+use crate::LayerError;
+use crate::rrc::{DrbSnStatus, EstablishmentCause, RrcReleaseCause};
+use bytes::Bytes;
+use common::types::{
+    AggregateMaximumBitRate, AllocationRetentionPriority, FiveQi, GbrQosInformation, QosCharacteristics,
+    QosFlowDescriptor, SNssai,
+};
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Protocol IE identifiers (3GPP TS 38.413 section 9.4.7)
+pub const ID_ALLOWED_NSSAI: u16 = 0;
+pub const ID_AMF_NAME: u16 = 1;
+pub const ID_AMF_UE_NGAP_ID: u16 = 10;
+pub const ID_CAUSE: u16 = 15;
+pub const ID_CRITICALITY_DIAGNOSTICS: u16 = 19;
+pub const ID_DEFAULT_PAGING_DRX: u16 = 21;
+pub const ID_DIRECT_FORWARDING_PATH_AVAILABILITY: u16 = 22;
+pub const ID_FIVE_G_S_TMSI: u16 = 26;
+pub const ID_GLOBAL_RAN_NODE_ID: u16 = 27;
+pub const ID_GUAMI: u16 = 28;
+pub const ID_HANDOVER_TYPE: u16 = 29;
+pub const ID_NAS_PDU: u16 = 38;
+pub const ID_NEW_AMF_UE_NGAP_ID: u16 = 40;
+pub const ID_PAGING_DRX: u16 = 50;
+pub const ID_PAGING_PRIORITY: u16 = 52;
+pub const ID_PDU_SESSION_RESOURCE_ADMITTED_LIST: u16 = 53;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_MODIFY_LIST_MOD_RES: u16 = 54;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_CXT_RES: u16 = 55;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_HO_ACK: u16 = 56;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_PS_REQ: u16 = 57;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_SU_RES: u16 = 58;
+pub const ID_PDU_SESSION_RESOURCE_HANDOVER_LIST: u16 = 59;
+pub const ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_CPL: u16 = 60;
+pub const ID_PDU_SESSION_RESOURCE_LIST_HO_RQD: u16 = 61;
+pub const ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_REQ: u16 = 64;
+pub const ID_PDU_SESSION_RESOURCE_MODIFY_LIST_MOD_RES: u16 = 65;
+pub const ID_PDU_SESSION_RESOURCE_RELEASED_LIST_PS_ACK: u16 = 68;
+pub const ID_PDU_SESSION_RESOURCE_RELEASED_LIST_PS_FAIL: u16 = 69;
+pub const ID_PDU_SESSION_RESOURCE_RELEASED_LIST_REL_RES: u16 = 70;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_REQ: u16 = 71;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST_CXT_RES: u16 = 72;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST_HO_REQ: u16 = 73;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_REQ: u16 = 74;
+pub const ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_RES: u16 = 75;
+pub const ID_PDU_SESSION_RESOURCE_TO_BE_SWITCHED_DL_LIST: u16 = 76;
+pub const ID_PDU_SESSION_RESOURCE_SWITCHED_LIST: u16 = 77;
+pub const ID_PDU_SESSION_RESOURCE_TO_RELEASE_LIST_HO_CMD: u16 = 78;
+pub const ID_PDU_SESSION_RESOURCE_TO_RELEASE_LIST_REL_CMD: u16 = 79;
+pub const ID_PLMN_SUPPORT_LIST: u16 = 80;
+pub const ID_RAN_NODE_NAME: u16 = 82;
+pub const ID_RAN_PAGING_PRIORITY: u16 = 83;
+pub const ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER: u16 = 84;
+pub const ID_RAN_UE_NGAP_ID: u16 = 85;
+pub const ID_RELATIVE_AMF_CAPACITY: u16 = 86;
+pub const ID_RESET_TYPE: u16 = 88;
+pub const ID_RRC_ESTABLISHMENT_CAUSE: u16 = 90;
+pub const ID_RRC_INACTIVE_TRANSITION_REPORT_REQUEST: u16 = 91;
+pub const ID_RRC_STATE: u16 = 92;
+pub const ID_SECURITY_CONTEXT: u16 = 93;
+pub const ID_SECURITY_KEY: u16 = 94;
+pub const ID_SERVED_GUAMI_LIST: u16 = 96;
+pub const ID_SOURCE_AMF_UE_NGAP_ID: u16 = 100;
+pub const ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER: u16 = 101;
+pub const ID_SUPPORTED_TA_LIST: u16 = 102;
+pub const ID_TAI_LIST_FOR_PAGING: u16 = 103;
+pub const ID_TARGET_ID: u16 = 105;
+pub const ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER: u16 = 106;
+pub const ID_TIME_TO_WAIT: u16 = 107;
+pub const ID_UE_AGGREGATE_MAXIMUM_BIT_RATE: u16 = 110;
+pub const ID_UE_ASSOCIATED_LOGICAL_NG_CONNECTION_LIST: u16 = 111;
+pub const ID_UE_CONTEXT_REQUEST: u16 = 112;
+pub const ID_UE_NGAP_IDS: u16 = 114;
+pub const ID_UE_PAGING_IDENTITY: u16 = 115;
+pub const ID_UE_RADIO_CAPABILITY: u16 = 117;
+pub const ID_UE_SECURITY_CAPABILITIES: u16 = 119;
+pub const ID_UNAVAILABLE_GUAMI_LIST: u16 = 120;
+pub const ID_USER_LOCATION_INFORMATION: u16 = 121;
+pub const ID_PDU_SESSION_AGGREGATE_MAXIMUM_BIT_RATE: u16 = 130;
+pub const ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_CXT_FAIL: u16 = 132;
+pub const ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_REQ: u16 = 133;
+pub const ID_PDU_SESSION_TYPE: u16 = 134;
+pub const ID_QOS_FLOW_ADD_OR_MODIFY_REQUEST_LIST: u16 = 135;
+pub const ID_QOS_FLOW_SETUP_REQUEST_LIST: u16 = 136;
+pub const ID_QOS_FLOW_TO_RELEASE_LIST: u16 = 137;
+pub const ID_UL_NGU_UP_TNL_INFORMATION: u16 = 139;
+
+/// maxnoofTACs
+const MAX_TACS: usize = 256;
+/// maxnoofTAIforPaging
+const MAX_TAIS_FOR_PAGING: usize = 16;
+/// maxnoofBPLMNs
+const MAX_BPLMNS: usize = 12;
+/// maxnoofSliceItems
+const MAX_SLICE_ITEMS: usize = 1024;
+/// maxnoofServedGUAMIs
+const MAX_SERVED_GUAMIS: usize = 256;
+/// maxnoofPLMNs
+const MAX_PLMNS: usize = 12;
+/// maxnoofAllowedS-NSSAIs
+const MAX_ALLOWED_S_NSSAIS: usize = 8;
+/// maxnoofPDUSessions
+const MAX_PDU_SESSIONS: usize = 256;
+/// maxnoofQosFlows
+const MAX_QOS_FLOWS: usize = 64;
+/// maxnoofDRBs
+const MAX_DRBS: usize = 32;
+/// maxnoofCellsinUEHistoryInfo
+const MAX_CELLS_IN_UE_HISTORY: usize = 16;
+/// maxnoofNGConnectionsToReset
+const MAX_NG_CONNECTIONS_TO_RESET: usize = 65536;
+/// Upper bound of BitRate, INTEGER (0..4000000000000, ...)
+const MAX_BIT_RATE: u64 = 4_000_000_000_000;
+
+/// Types with an APER encoding
+pub trait AperCodec: Sized {
+    /// Append the APER encoding of `self`
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError>;
+    /// Decode a value from the current position
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError>;
+}
+
+/// Criticality (3GPP TS 38.413 section 9.3.1.2 semantics)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criticality {
+    Reject = 0,
+    Ignore = 1,
+    Notify = 2,
+}
+
+impl AperCodec for Criticality {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 3, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(3, false)? {
+            0 => Ok(Criticality::Reject),
+            1 => Ok(Criticality::Ignore),
+            _ => Ok(Criticality::Notify),
+        }
+    }
+}
+
+/// A single protocol IE with its value kept as an open type encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolIe {
+    /// Protocol IE identifier
+    pub id: u16,
+    /// Criticality of the IE
+    pub criticality: Criticality,
+    /// APER encoding of the IE value
+    pub value: Bytes,
+}
+
+/// Procedure codes of an application protocol whose PDU is a CHOICE of
+/// initiating message, successful outcome and unsuccessful outcome, each a
+/// procedure code, a criticality and a protocol IE container
+pub trait ApProcedureCode: Copy + std::fmt::Debug {
+    /// Name of the protocol
+    const PROTOCOL: &'static str;
+    /// Alternatives of the PDU CHOICE root and whether it is extensible
+    const PDU_CHOICE: (usize, bool);
+
+    /// Look up a procedure code
+    fn from_u8(code: u8) -> Option<Self>;
+    /// Value of the procedure code
+    fn code(self) -> u8;
+    /// Criticality of the procedure
+    fn criticality(&self) -> Criticality;
+}
+
+impl ApProcedureCode for NgapProcedureCode {
+    const PROTOCOL: &'static str = "NGAP";
+    const PDU_CHOICE: (usize, bool) = (3, true);
+
+    fn from_u8(code: u8) -> Option<Self> {
+        NgapProcedureCode::from_u8(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn criticality(&self) -> Criticality {
+        NgapProcedureCode::criticality(self)
+    }
+}
+
+/// Alternative of the PDU CHOICE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApPduType {
+    InitiatingMessage = 0,
+    SuccessfulOutcome = 1,
+    UnsuccessfulOutcome = 2,
+}
+
+/// NGAP-PDU choice
+pub type NgapPduType = ApPduType;
+
+/// Application protocol PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApPdu<P> {
+    /// Message type
+    pub pdu_type: ApPduType,
+    /// Procedure code
+    pub procedure_code: u8,
+    /// Procedure criticality
+    pub criticality: Criticality,
+    /// Protocol IEs of the message
+    pub ies: Vec<ProtocolIe>,
+    procedure: PhantomData<P>,
+}
+
+/// NGAP PDU
+pub type NgapPdu = ApPdu<NgapProcedureCode>;
+
+impl<P: ApProcedureCode> ApPdu<P> {
+    /// Create an empty PDU
+    pub fn new(pdu_type: ApPduType, procedure: P, criticality: Criticality) -> Self {
+        Self {
+            pdu_type,
+            procedure_code: procedure.code(),
+            criticality,
+            ies: Vec::new(),
+            procedure: PhantomData,
+        }
+    }
+
+    /// Create an initiating message with the criticality the procedure uses
+    pub fn initiating(procedure: P) -> Self {
+        Self::new(ApPduType::InitiatingMessage, procedure, procedure.criticality())
+    }
+
+    /// Create a successful outcome message
+    pub fn successful(procedure: P) -> Self {
+        Self::new(ApPduType::SuccessfulOutcome, procedure, procedure.criticality())
+    }
+
+    /// Create an unsuccessful outcome message
+    pub fn unsuccessful(procedure: P) -> Self {
+        Self::new(ApPduType::UnsuccessfulOutcome, procedure, procedure.criticality())
+    }
+
+    /// Procedure of this PDU, if known
+    pub fn procedure(&self) -> Option<P> {
+        P::from_u8(self.procedure_code)
+    }
+
+    /// Append an IE
+    pub fn add_ie<T: AperCodec>(&mut self, id: u16, criticality: Criticality, value: &T) -> Result<(), LayerError> {
+        self.ies.push(protocol_ie(id, criticality, value)?);
+        Ok(())
+    }
+
+    /// Builder form of [`ApPdu::add_ie`]
+    pub fn with_ie<T: AperCodec>(mut self, id: u16, criticality: Criticality, value: &T) -> Result<Self, LayerError> {
+        self.add_ie(id, criticality, value)?;
+        Ok(self)
+    }
+
+    /// Decode an optional IE
+    pub fn optional_ie<T: AperCodec>(&self, id: u16) -> Result<Option<T>, LayerError> {
+        find_ie(&self.ies, id)
+    }
+
+    /// Decode a mandatory IE
+    pub fn ie<T: AperCodec>(&self, id: u16) -> Result<T, LayerError> {
+        self.optional_ie(id)?
+            .ok_or_else(|| LayerError::ProcessingError(format!("Missing mandatory IE {} in procedure {}", id, self.procedure_code)))
+    }
+
+    /// Encode the PDU
+    pub fn encode(&self) -> Result<Bytes, LayerError> {
+        let mut value = AperEncoder::new();
+        encode_ie_container(&mut value, &self.ies)?;
+        let value = value.into_bytes();
+
+        let (root_count, extensible) = P::PDU_CHOICE;
+        let mut enc = AperEncoder::new();
+        enc.put_choice(self.pdu_type as usize, root_count, extensible)?;
+        enc.put_constrained_whole_number(self.procedure_code as u64, 0, 255)?;
+        self.criticality.encode(&mut enc)?;
+        enc.put_open_type(&value)?;
+        Ok(enc.into_bytes())
+    }
+
+    /// Decode a PDU
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let (root_count, extensible) = P::PDU_CHOICE;
+        let mut dec = AperDecoder::new(data);
+        let pdu_type = match dec.get_choice(root_count, extensible)? {
+            0 => ApPduType::InitiatingMessage,
+            1 => ApPduType::SuccessfulOutcome,
+            2 => ApPduType::UnsuccessfulOutcome,
+            _ => return Err(LayerError::ProcessingError(format!("Unsupported {}-PDU extension", P::PROTOCOL))),
+        };
+        let procedure_code = dec.get_constrained_whole_number(0, 255)? as u8;
+        let criticality = Criticality::decode(&mut dec)?;
+        let value = dec.get_open_type()?;
+
+        let ies = decode_ie_container(&mut AperDecoder::new(&value))?;
+
+        Ok(Self {
+            pdu_type,
+            procedure_code,
+            criticality,
+            ies,
+            procedure: PhantomData,
+        })
+    }
+}
+
+/// Encode a protocol IE container: SEQUENCE { protocolIEs, ... }
+pub(crate) fn encode_ie_container(enc: &mut AperEncoder, ies: &[ProtocolIe]) -> Result<(), LayerError> {
+    enc.put_bool(false);
+    enc.put_length(ies.len(), 0, Some(65535))?;
+    for ie in ies {
+        enc.put_constrained_whole_number(ie.id as u64, 0, 65535)?;
+        ie.criticality.encode(enc)?;
+        enc.put_open_type(&ie.value)?;
+    }
+    Ok(())
+}
+
+/// Decode a protocol IE container
+pub(crate) fn decode_ie_container(dec: &mut AperDecoder) -> Result<Vec<ProtocolIe>, LayerError> {
+    // Extensions of the container itself are not used by NGAP
+    dec.get_bool()?;
+    let count = dec.get_length(0, Some(65535))?;
+    let mut ies = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id = dec.get_constrained_whole_number(0, 65535)? as u16;
+        let criticality = Criticality::decode(dec)?;
+        let value = Bytes::from(dec.get_open_type()?);
+        ies.push(ProtocolIe { id, criticality, value });
+    }
+    Ok(ies)
+}
+
+/// Decode the first IE with the given id
+pub(crate) fn find_ie<T: AperCodec>(ies: &[ProtocolIe], id: u16) -> Result<Option<T>, LayerError> {
+    match ies.iter().find(|ie| ie.id == id) {
+        Some(ie) => T::decode(&mut AperDecoder::new(&ie.value)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Build a protocol IE
+pub(crate) fn protocol_ie<T: AperCodec>(id: u16, criticality: Criticality, value: &T) -> Result<ProtocolIe, LayerError> {
+    let mut enc = AperEncoder::new();
+    value.encode(&mut enc)?;
+    Ok(ProtocolIe {
+        id,
+        criticality,
+        value: enc.into_bytes(),
+    })
+}
+
+/// Encode a list of ProtocolIE-SingleContainer items with size constraint `min..=max`
+pub(crate) fn encode_ie_list<T: AperCodec>(
+    enc: &mut AperEncoder,
+    id: u16,
+    criticality: Criticality,
+    items: &[T],
+    min: usize,
+    max: usize,
+) -> Result<(), LayerError> {
+    enc.put_length(items.len(), min, Some(max))?;
+    for item in items {
+        let ie = protocol_ie(id, criticality, item)?;
+        enc.put_constrained_whole_number(ie.id as u64, 0, 65535)?;
+        ie.criticality.encode(enc)?;
+        enc.put_open_type(&ie.value)?;
+    }
+    Ok(())
+}
+
+/// Decode a list of ProtocolIE-SingleContainer items with size constraint `min..=max`
+pub(crate) fn decode_ie_list<T: AperCodec>(dec: &mut AperDecoder, min: usize, max: usize) -> Result<Vec<T>, LayerError> {
+    let count = dec.get_length(min, Some(max))?;
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_constrained_whole_number(0, 65535)?;
+        Criticality::decode(dec)?;
+        let value = dec.get_open_type()?;
+        items.push(T::decode(&mut AperDecoder::new(&value))?);
+    }
+    Ok(items)
+}
+
+/// Declare a list IE made of ProtocolIE-SingleContainer items, of size
+/// `1..=max` unless a minimum is given
+macro_rules! ie_list {
+    ($(#[$doc:meta])* $name:ident, $item:ty, $item_id:expr, $criticality:expr, $min:expr, $max:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name(pub Vec<$item>);
+
+        impl AperCodec for $name {
+            fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+                $crate::ngap::pdu::encode_ie_list(enc, $item_id, $criticality, &self.0, $min, $max)
+            }
+
+            fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+                Ok(Self($crate::ngap::pdu::decode_ie_list(dec, $min, $max)?))
+            }
+        }
+    };
+    ($(#[$doc:meta])* $name:ident, $item:ty, $item_id:expr, $criticality:expr, $max:expr) => {
+        $crate::ngap::pdu::ie_list!($(#[$doc])* $name, $item, $item_id, $criticality, 1, $max);
+    };
+}
+pub(crate) use ie_list;
+
+/// Encode a SEQUENCE OF with size constraint `1..=max`
+pub(crate) fn encode_list<T: AperCodec>(enc: &mut AperEncoder, items: &[T], max: usize) -> Result<(), LayerError> {
+    enc.put_length(items.len(), 1, Some(max))?;
+    for item in items {
+        item.encode(enc)?;
+    }
+    Ok(())
+}
+
+/// Decode a SEQUENCE OF with size constraint `1..=max`
+pub(crate) fn decode_list<T: AperCodec>(dec: &mut AperDecoder, max: usize) -> Result<Vec<T>, LayerError> {
+    let count = dec.get_length(1, Some(max))?;
+    (0..count).map(|_| T::decode(dec)).collect()
+}
+
+/// Skip the iE-Extensions of a SEQUENCE whose presence bit was set
+pub(crate) fn skip_ie_extensions(dec: &mut AperDecoder, present: bool) -> Result<(), LayerError> {
+    if present {
+        // ProtocolExtensionContainer SIZE(1..65535) of id, criticality, open type
+        let count = dec.get_length(1, Some(65535))?;
+        for _ in 0..count {
+            dec.get_constrained_whole_number(0, 65535)?;
+            Criticality::decode(dec)?;
+            dec.get_open_type()?;
+        }
+    }
+    Ok(())
+}
+
+/// Unconstrained OCTET STRING (NAS-PDU, UERadioCapability, ...)
+impl AperCodec for Bytes {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_octet_string(self, 0, None, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Bytes::from(dec.get_octet_string(0, None, false)?))
+    }
+}
+
+/// PLMN Identity, OCTET STRING (SIZE(3))
+impl AperCodec for [u8; 3] {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_octet_string(self, 3, Some(3), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let octets = dec.get_octet_string(3, Some(3), false)?;
+        Ok([octets[0], octets[1], octets[2]])
+    }
+}
+
+/// AMF UE NGAP ID, INTEGER (0..2^40-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmfUeNgapId(pub u64);
+
+impl AperCodec for AmfUeNgapId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0, 0, (1 << 40) - 1, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, (1 << 40) - 1, false)?))
+    }
+}
+
+/// RAN UE NGAP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RanUeNgapId(pub u32);
+
+impl AperCodec for RanUeNgapId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// Global RAN Node ID (gNB alternative only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalGnbId {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// gNB ID value
+    pub gnb_id: u32,
+    /// gNB ID length in bits (22..32)
+    pub gnb_id_bits: u8,
+}
+
+impl AperCodec for GlobalGnbId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // GlobalRANNodeID: globalGNB-ID
+        enc.put_choice(0, 4, false)?;
+        // GlobalGNB-ID: extension bit, iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        // GNB-ID: gNB-ID
+        enc.put_choice(0, 2, false)?;
+        let bits = self.gnb_id_bits as usize;
+        let value = (self.gnb_id as u64) << (32 - bits);
+        enc.put_bit_string(&(value as u32).to_be_bytes(), bits, 22, Some(32), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(4, false)? != 0 {
+            return Err(LayerError::ProcessingError("Only gNB Global RAN Node IDs are supported".into()));
+        }
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        if dec.get_choice(2, false)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let (data, bits) = dec.get_bit_string(22, Some(32), false)?;
+        let mut padded = [0u8; 4];
+        padded[..data.len()].copy_from_slice(&data);
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            plmn_id,
+            gnb_id: u32::from_be_bytes(padded) >> (32 - bits),
+            gnb_id_bits: bits as u8,
+        })
+    }
+}
+
+/// RAN Node Name / AMF Name, PrintableString (SIZE(1..150, ...))
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeName(pub String);
+
+impl AperCodec for NodeName {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_printable_string(&self.0, 1, 150, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_printable_string(1, 150, true)?))
+    }
+}
+
+/// S-NSSAI (3GPP TS 38.413 section 9.3.1.24)
+impl AperCodec for SNssai {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.sd.is_some());
+        enc.put_bool(false);
+        enc.put_octet_string(&[self.sst], 1, Some(1), false)?;
+        if let Some(sd) = self.sd {
+            enc.put_octet_string(&sd.to_be_bytes()[1..], 3, Some(3), false)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let sd_present = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let sst = dec.get_octet_string(1, Some(1), false)?[0];
+        let sd = if sd_present {
+            let octets = dec.get_octet_string(3, Some(3), false)?;
+            Some(u32::from_be_bytes([0, octets[0], octets[1], octets[2]]))
+        } else {
+            None
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(SNssai { sst, sd })
+    }
+}
+
+/// Slice Support Item: SEQUENCE { s-NSSAI, iE-Extensions OPTIONAL, ... }
+struct SliceSupportItem(SNssai);
+
+impl AperCodec for SliceSupportItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.0.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let s_nssai = SNssai::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self(s_nssai))
+    }
+}
+
+/// Slice Support List, SEQUENCE (SIZE(1..maxnoofSliceItems)) OF Slice Support Item
+pub fn encode_slice_support_list(enc: &mut AperEncoder, slices: &[SNssai]) -> Result<(), LayerError> {
+    let items: Vec<SliceSupportItem> = slices.iter().cloned().map(SliceSupportItem).collect();
+    encode_list(enc, &items, MAX_SLICE_ITEMS)
+}
+
+/// Decode a Slice Support List
+pub fn decode_slice_support_list(dec: &mut AperDecoder) -> Result<Vec<SNssai>, LayerError> {
+    Ok(decode_list::<SliceSupportItem>(dec, MAX_SLICE_ITEMS)?.into_iter().map(|item| item.0).collect())
+}
+
+/// Broadcast PLMN Item of the Supported TA List
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastPlmnItem {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// Slices supported in the TA for this PLMN
+    pub slices: Vec<SNssai>,
+}
+
+impl AperCodec for BroadcastPlmnItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        encode_slice_support_list(enc, &self.slices)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let slices = decode_slice_support_list(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { plmn_id, slices })
+    }
+}
+
+/// Supported TA Item (3GPP TS 38.413 section 9.2.6.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupportedTaItem {
+    /// Tracking area code (24 bits)
+    pub tac: u32,
+    /// PLMNs broadcast in the TA
+    pub broadcast_plmns: Vec<BroadcastPlmnItem>,
+}
+
+impl AperCodec for SupportedTaItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_octet_string(&self.tac.to_be_bytes()[1..], 3, Some(3), false)?;
+        encode_list(enc, &self.broadcast_plmns, MAX_BPLMNS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let tac = dec.get_octet_string(3, Some(3), false)?;
+        let broadcast_plmns = decode_list(dec, MAX_BPLMNS)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            tac: u32::from_be_bytes([0, tac[0], tac[1], tac[2]]),
+            broadcast_plmns,
+        })
+    }
+}
+
+/// Supported TA List, SEQUENCE (SIZE(1..maxnoofTACs)) OF Supported TA Item
+impl AperCodec for Vec<SupportedTaItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_TACS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_TACS)
+    }
+}
+
+/// Default Paging DRX (3GPP TS 38.413 section 9.3.1.90)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagingDrx {
+    V32 = 0,
+    V64 = 1,
+    V128 = 2,
+    V256 = 3,
+}
+
+impl PagingDrx {
+    /// Paging DRX for a cycle length in radio frames, rounded up
+    pub fn from_frames(frames: u16) -> Self {
+        match frames {
+            0..=32 => PagingDrx::V32,
+            33..=64 => PagingDrx::V64,
+            65..=128 => PagingDrx::V128,
+            _ => PagingDrx::V256,
+        }
+    }
+
+    /// Cycle length in radio frames
+    pub fn frames(&self) -> u16 {
+        32 << (*self as u16)
+    }
+}
+
+impl AperCodec for PagingDrx {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 4, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(4, true)? {
+            0 => Ok(PagingDrx::V32),
+            1 => Ok(PagingDrx::V64),
+            2 => Ok(PagingDrx::V128),
+            3 => Ok(PagingDrx::V256),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// GUAMI (3GPP TS 38.413 section 9.3.3.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guami {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// AMF Region ID (8 bits)
+    pub amf_region_id: u8,
+    /// AMF Set ID (10 bits)
+    pub amf_set_id: u16,
+    /// AMF Pointer (6 bits)
+    pub amf_pointer: u8,
+}
+
+impl AperCodec for Guami {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        enc.put_bit_string(&[self.amf_region_id], 8, 8, Some(8), false)?;
+        enc.put_bit_string(&(self.amf_set_id << 6).to_be_bytes(), 10, 10, Some(10), false)?;
+        enc.put_bit_string(&[self.amf_pointer << 2], 6, 6, Some(6), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let (region, _) = dec.get_bit_string(8, Some(8), false)?;
+        let (set, _) = dec.get_bit_string(10, Some(10), false)?;
+        let (pointer, _) = dec.get_bit_string(6, Some(6), false)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            plmn_id,
+            amf_region_id: region[0],
+            amf_set_id: u16::from_be_bytes([set[0], set[1]]) >> 6,
+            amf_pointer: pointer[0] >> 2,
+        })
+    }
+}
+
+/// Served GUAMI Item of NG Setup Response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedGuamiItem {
+    /// GUAMI served by the AMF
+    pub guami: Guami,
+    /// Name of the backup AMF for this GUAMI
+    pub backup_amf_name: Option<String>,
+}
+
+impl AperCodec for ServedGuamiItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.backup_amf_name.is_some());
+        enc.put_bool(false);
+        self.guami.encode(enc)?;
+        if let Some(name) = &self.backup_amf_name {
+            NodeName(name.clone()).encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let backup_present = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let guami = Guami::decode(dec)?;
+        let backup_amf_name = if backup_present {
+            Some(NodeName::decode(dec)?.0)
+        } else {
+            None
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { guami, backup_amf_name })
+    }
+}
+
+/// Served GUAMI List, SEQUENCE (SIZE(1..maxnoofServedGUAMIs)) OF Served GUAMI Item
+impl AperCodec for Vec<ServedGuamiItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_SERVED_GUAMIS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_SERVED_GUAMIS)
+    }
+}
+
+/// Unavailable GUAMI Item of AMF Status Indication
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnavailableGuamiItem {
+    /// GUAMI no longer served by the AMF
+    pub guami: Guami,
+    /// The AMF removes the GUAMI after a timer rather than immediately
+    pub timer_approach: bool,
+    /// Name of the AMF taking over the GUAMI
+    pub backup_amf_name: Option<String>,
+}
+
+impl AperCodec for UnavailableGuamiItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.timer_approach);
+        enc.put_bool(self.backup_amf_name.is_some());
+        enc.put_bool(false);
+        self.guami.encode(enc)?;
+        if self.timer_approach {
+            enc.put_enumerated(0, 1, true)?;  // apply-timer
+        }
+        if let Some(name) = &self.backup_amf_name {
+            NodeName(name.clone()).encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let timer_approach = dec.get_bool()?;
+        let backup_present = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let guami = Guami::decode(dec)?;
+        if timer_approach {
+            dec.get_enumerated(1, true)?;
+        }
+        let backup_amf_name = if backup_present {
+            Some(NodeName::decode(dec)?.0)
+        } else {
+            None
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { guami, timer_approach, backup_amf_name })
+    }
+}
+
+/// Unavailable GUAMI List, SEQUENCE (SIZE(1..maxnoofServedGUAMIs)) OF Unavailable GUAMI Item
+impl AperCodec for Vec<UnavailableGuamiItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_SERVED_GUAMIS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_SERVED_GUAMIS)
+    }
+}
+
+/// PLMN Support Item: slices the AMF supports for a PLMN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlmnSupportItem {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// Supported slices
+    pub slices: Vec<SNssai>,
+}
+
+impl AperCodec for PlmnSupportItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        encode_slice_support_list(enc, &self.slices)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let slices = decode_slice_support_list(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { plmn_id, slices })
+    }
+}
+
+/// PLMN Support List, SEQUENCE (SIZE(1..maxnoofPLMNs)) OF PLMN Support Item
+impl AperCodec for Vec<PlmnSupportItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PLMNS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PLMNS)
+    }
+}
+
+/// Relative AMF Capacity, INTEGER (0..255)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelativeAmfCapacity(pub u8);
+
+impl AperCodec for RelativeAmfCapacity {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, 255, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, 255, false)? as u8))
+    }
+}
+
+/// Cause (3GPP TS 38.413 section 9.3.1.2)
+///
+/// Values are the ENUMERATED indices of the respective cause group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    RadioNetwork(u8),
+    Transport(u8),
+    Nas(u8),
+    Protocol(u8),
+    Misc(u8),
+}
+
+impl Cause {
+    /// Root values of the radioNetwork, transport, nas, protocol and misc groups
+    const ROOT_COUNTS: [usize; 5] = [45, 2, 4, 7, 6];
+
+    /// Misc: unspecified
+    pub const MISC_UNSPECIFIED: Cause = Cause::Misc(5);
+    /// Misc: unknown PLMN or SNPN
+    pub const MISC_UNKNOWN_PLMN: Cause = Cause::Misc(4);
+    /// Radio network: successful handover
+    pub const SUCCESSFUL_HANDOVER: Cause = Cause::RadioNetwork(2);
+    /// Radio network: release due to NG-RAN generated reason
+    pub const RELEASE_DUE_TO_NGRAN_GENERATED_REASON: Cause = Cause::RadioNetwork(3);
+    /// Radio network: cell not available
+    pub const CELL_NOT_AVAILABLE: Cause = Cause::RadioNetwork(11);
+    /// Radio network: unknown target ID
+    pub const UNKNOWN_TARGET_ID: Cause = Cause::RadioNetwork(12);
+    /// Radio network: unknown local UE NGAP ID
+    pub const UNKNOWN_LOCAL_UE_NGAP_ID: Cause = Cause::RadioNetwork(14);
+    /// Radio network: inconsistent remote UE NGAP ID
+    pub const INCONSISTENT_REMOTE_UE_NGAP_ID: Cause = Cause::RadioNetwork(15);
+    /// Radio network: handover desirable for radio reason
+    pub const HANDOVER_DESIRABLE_FOR_RADIO_REASON: Cause = Cause::RadioNetwork(16);
+    /// Radio network: user inactivity
+    pub const USER_INACTIVITY: Cause = Cause::RadioNetwork(20);
+    /// Radio network: radio connection with UE lost
+    pub const RADIO_CONNECTION_WITH_UE_LOST: Cause = Cause::RadioNetwork(21);
+    /// Radio network: radio resources not available
+    pub const RADIO_RESOURCES_NOT_AVAILABLE: Cause = Cause::RadioNetwork(22);
+    /// Radio network: failure in the radio interface procedure
+    pub const FAILURE_IN_RADIO_INTERFACE_PROCEDURE: Cause = Cause::RadioNetwork(24);
+    /// Radio network: multiple PDU session ID instances
+    pub const MULTIPLE_PDU_SESSION_ID_INSTANCES: Cause = Cause::RadioNetwork(28);
+    /// Radio network: encryption and/or integrity protection algorithms not supported
+    pub const ALGORITHMS_NOT_SUPPORTED: Cause = Cause::RadioNetwork(30);
+    /// Radio network: unknown PDU session ID
+    pub const UNKNOWN_PDU_SESSION_ID: Cause = Cause::RadioNetwork(26);
+    /// Radio network: slice not supported
+    pub const SLICE_NOT_SUPPORTED: Cause = Cause::RadioNetwork(39);
+    /// Protocol: transfer syntax error
+    pub const TRANSFER_SYNTAX_ERROR: Cause = Cause::Protocol(0);
+    /// Protocol: message not compatible with receiver state
+    pub const MESSAGE_NOT_COMPATIBLE_WITH_RECEIVER_STATE: Cause = Cause::Protocol(3);
+
+    fn group(&self) -> (usize, u8) {
+        match *self {
+            Cause::RadioNetwork(value) => (0, value),
+            Cause::Transport(value) => (1, value),
+            Cause::Nas(value) => (2, value),
+            Cause::Protocol(value) => (3, value),
+            Cause::Misc(value) => (4, value),
+        }
+    }
+}
+
+impl From<RrcReleaseCause> for Cause {
+    fn from(cause: RrcReleaseCause) -> Self {
+        match cause {
+            RrcReleaseCause::UserInactivity => Cause::USER_INACTIVITY,
+            RrcReleaseCause::RadioConnectionWithUeLost => Cause::RADIO_CONNECTION_WITH_UE_LOST,
+            RrcReleaseCause::FailureInRadioInterfaceProcedure => Cause::FAILURE_IN_RADIO_INTERFACE_PROCEDURE,
+            RrcReleaseCause::AlgorithmsNotSupported => Cause::ALGORITHMS_NOT_SUPPORTED,
+            RrcReleaseCause::NormalRelease => Cause::RELEASE_DUE_TO_NGRAN_GENERATED_REASON,
+            RrcReleaseCause::NoRadioResources => Cause::RADIO_RESOURCES_NOT_AVAILABLE,
+        }
+    }
+}
+
+impl AperCodec for Cause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let (group, value) = self.group();
+        enc.put_choice(group, 6, false)?;
+        enc.put_enumerated(value as usize, Self::ROOT_COUNTS[group], true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let group = dec.get_choice(6, false)?;
+        if group >= Self::ROOT_COUNTS.len() {
+            return Err(LayerError::ProcessingError("Unknown cause group".into()));
+        }
+        let value = dec.get_enumerated(Self::ROOT_COUNTS[group], true)? as u8;
+        Ok(match group {
+            0 => Cause::RadioNetwork(value),
+            1 => Cause::Transport(value),
+            2 => Cause::Nas(value),
+            3 => Cause::Protocol(value),
+            _ => Cause::Misc(value),
+        })
+    }
+}
+
+/// Time to Wait (3GPP TS 38.413 section 9.3.1.56)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeToWait {
+    V1s = 0,
+    V2s = 1,
+    V5s = 2,
+    V10s = 3,
+    V20s = 4,
+    V60s = 5,
+}
+
+impl TimeToWait {
+    /// Waiting time in seconds
+    pub fn seconds(&self) -> u64 {
+        [1, 2, 5, 10, 20, 60][*self as usize]
+    }
+}
+
+impl AperCodec for TimeToWait {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 6, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(6, true)? {
+            0 => Ok(TimeToWait::V1s),
+            1 => Ok(TimeToWait::V2s),
+            2 => Ok(TimeToWait::V5s),
+            3 => Ok(TimeToWait::V10s),
+            4 => Ok(TimeToWait::V20s),
+            5 => Ok(TimeToWait::V60s),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// NR Cell Global Identity (3GPP TS 38.413 section 9.3.1.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NrCgi {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// NR cell identity (36 bits)
+    pub nr_cell_identity: u64,
+}
+
+impl AperCodec for NrCgi {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        let value = (self.nr_cell_identity & 0xF_FFFF_FFFF) << 28;
+        enc.put_bit_string(&value.to_be_bytes()[..5], 36, 36, Some(36), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let (data, _) = dec.get_bit_string(36, Some(36), false)?;
+        let mut padded = [0u8; 8];
+        padded[..data.len()].copy_from_slice(&data);
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            plmn_id,
+            nr_cell_identity: u64::from_be_bytes(padded) >> 28,
+        })
+    }
+}
+
+/// Tracking Area Identity (3GPP TS 38.413 section 9.3.3.11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tai {
+    /// PLMN identity
+    pub plmn_id: [u8; 3],
+    /// Tracking area code (24 bits)
+    pub tac: u32,
+}
+
+impl AperCodec for Tai {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.plmn_id.encode(enc)?;
+        enc.put_octet_string(&self.tac.to_be_bytes()[1..], 3, Some(3), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        let tac = dec.get_octet_string(3, Some(3), false)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            plmn_id,
+            tac: u32::from_be_bytes([0, tac[0], tac[1], tac[2]]),
+        })
+    }
+}
+
+/// 5G-S-TMSI (3GPP TS 38.413 section 9.3.3.20)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FiveGSTmsi {
+    /// AMF Set ID (10 bits)
+    pub amf_set_id: u16,
+    /// AMF Pointer (6 bits)
+    pub amf_pointer: u8,
+    /// 5G-TMSI
+    pub five_g_tmsi: u32,
+}
+
+impl FiveGSTmsi {
+    /// 48-bit value as used by RRC (AMF Set ID, AMF Pointer, 5G-TMSI)
+    pub fn value(&self) -> u64 {
+        ((self.amf_set_id as u64 & 0x3FF) << 38) | ((self.amf_pointer as u64 & 0x3F) << 32) | self.five_g_tmsi as u64
+    }
+
+    /// Split a 48-bit 5G-S-TMSI into its fields
+    pub fn from_value(value: u64) -> Self {
+        Self {
+            amf_set_id: ((value >> 38) & 0x3FF) as u16,
+            amf_pointer: ((value >> 32) & 0x3F) as u8,
+            five_g_tmsi: value as u32,
+        }
+    }
+}
+
+impl AperCodec for FiveGSTmsi {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bit_string(&(self.amf_set_id << 6).to_be_bytes(), 10, 10, Some(10), false)?;
+        enc.put_bit_string(&[self.amf_pointer << 2], 6, 6, Some(6), false)?;
+        enc.put_octet_string(&self.five_g_tmsi.to_be_bytes(), 4, Some(4), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let (set, _) = dec.get_bit_string(10, Some(10), false)?;
+        let (pointer, _) = dec.get_bit_string(6, Some(6), false)?;
+        let tmsi = dec.get_octet_string(4, Some(4), false)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            amf_set_id: u16::from_be_bytes([set[0], set[1]]) >> 6,
+            amf_pointer: pointer[0] >> 2,
+            five_g_tmsi: u32::from_be_bytes([tmsi[0], tmsi[1], tmsi[2], tmsi[3]]),
+        })
+    }
+}
+
+/// UE Paging Identity (3GPP TS 38.413 section 9.3.3.23), a CHOICE whose only
+/// alternative is the 5G-S-TMSI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UePagingIdentity(pub FiveGSTmsi);
+
+impl AperCodec for UePagingIdentity {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_choice(0, 2, false)?;
+        self.0.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(2, false)? {
+            0 => Ok(Self(FiveGSTmsi::decode(dec)?)),
+            _ => Err(LayerError::ProcessingError("Unsupported UE paging identity".into())),
+        }
+    }
+}
+
+/// TAI List for Paging (3GPP TS 38.413 section 9.2.4.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaiListForPaging(pub Vec<Tai>);
+
+impl AperCodec for TaiListForPaging {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_length(self.0.len(), 1, Some(MAX_TAIS_FOR_PAGING))?;
+        for tai in &self.0 {
+            enc.put_bool(false);
+            enc.put_bool(false);
+            tai.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let count = dec.get_length(1, Some(MAX_TAIS_FOR_PAGING))?;
+        let mut tais = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            tais.push(Tai::decode(dec)?);
+            skip_ie_extensions(dec, extensions)?;
+        }
+        Ok(Self(tais))
+    }
+}
+
+/// Paging Priority (3GPP TS 38.413 section 9.3.1.78), level 1 (highest) to 8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PagingPriority(pub u8);
+
+impl AperCodec for PagingPriority {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        if !(1..=8).contains(&self.0) {
+            return Err(LayerError::InvalidPdu);
+        }
+        enc.put_enumerated(self.0 as usize - 1, 8, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(8, true)? {
+            level @ 0..=7 => Ok(Self(level as u8 + 1)),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// User Location Information, NR alternative (3GPP TS 38.413 section 9.3.1.16)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserLocationInformationNr {
+    /// Serving cell
+    pub nr_cgi: NrCgi,
+    /// Tracking area of the serving cell
+    pub tai: Tai,
+}
+
+impl AperCodec for UserLocationInformationNr {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // UserLocationInformation: userLocationInformationNR
+        enc.put_choice(1, 4, false)?;
+        // Extension bit, timeStamp and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        self.nr_cgi.encode(enc)?;
+        self.tai.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(4, false)? != 1 {
+            return Err(LayerError::ProcessingError("Only NR user location information is supported".into()));
+        }
+        dec.get_bool()?;
+        let time_stamp = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let nr_cgi = NrCgi::decode(dec)?;
+        let tai = Tai::decode(dec)?;
+        if time_stamp {
+            dec.get_octet_string(4, Some(4), false)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { nr_cgi, tai })
+    }
+}
+
+/// RRC Establishment Cause (3GPP TS 38.413 section 9.3.1.111), same root values as in RRC
+impl AperCodec for EstablishmentCause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 10, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        use EstablishmentCause::*;
+
+        [
+            Emergency, HighPriorityAccess, MtAccess, MoSignalling, MoData, MoVoiceCall, MoVideoCall,
+            MoSms, MpsService, McsService,
+        ].get(dec.get_enumerated(10, true)?).copied().ok_or(LayerError::InvalidPdu)
+    }
+}
+
+/// UE Context Request (3GPP TS 38.413 section 9.3.1.107), ENUMERATED {requested, ...}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UeContextRequest;
+
+impl AperCodec for UeContextRequest {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(0, 1, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_enumerated(1, true)?;
+        Ok(Self)
+    }
+}
+
+/// RRC Inactive Transition Report Request (3GPP TS 38.413 section 9.3.1.91)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RrcInactiveTransitionReportRequest {
+    /// Report every RRC state transition of the UE
+    SubsequentStateTransitionReport = 0,
+    /// Report the next transition of the UE to RRC_CONNECTED only
+    SingleRrcConnectedStateReport = 1,
+    /// Stop reporting
+    CancelReport = 2,
+}
+
+impl AperCodec for RrcInactiveTransitionReportRequest {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 3, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        use RrcInactiveTransitionReportRequest::*;
+
+        [SubsequentStateTransitionReport, SingleRrcConnectedStateReport, CancelReport]
+            .get(dec.get_enumerated(3, true)?).copied().ok_or(LayerError::InvalidPdu)
+    }
+}
+
+/// RRC State (3GPP TS 38.413 section 9.3.1.92)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RrcState {
+    Inactive = 0,
+    Connected = 1,
+}
+
+impl AperCodec for RrcState {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        [RrcState::Inactive, RrcState::Connected]
+            .get(dec.get_enumerated(2, true)?).copied().ok_or(LayerError::InvalidPdu)
+    }
+}
+
+/// BitRate, INTEGER (0..4000000000000, ...)
+fn put_bit_rate(enc: &mut AperEncoder, bit_rate: u64) -> Result<(), LayerError> {
+    enc.put_integer(bit_rate.min(MAX_BIT_RATE), 0, MAX_BIT_RATE, true)
+}
+
+fn get_bit_rate(dec: &mut AperDecoder) -> Result<u64, LayerError> {
+    dec.get_integer(0, MAX_BIT_RATE, true)
+}
+
+/// UE Aggregate Maximum Bit Rate / PDU Session Aggregate Maximum Bit Rate
+/// (3GPP TS 38.413 sections 9.3.1.58 and 9.3.1.102)
+impl AperCodec for AggregateMaximumBitRate {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        put_bit_rate(enc, self.dl)?;
+        put_bit_rate(enc, self.ul)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let dl = get_bit_rate(dec)?;
+        let ul = get_bit_rate(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { dl, ul })
+    }
+}
+
+/// UE Security Capabilities (3GPP TS 38.413 section 9.3.1.86)
+///
+/// Each algorithm set is a 16-bit mask, the first (most significant) bit is
+/// algorithm 1 (128-NEA1/128-NIA1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UeSecurityCapabilities {
+    /// NR encryption algorithms
+    pub nr_encryption_algorithms: u16,
+    /// NR integrity protection algorithms
+    pub nr_integrity_algorithms: u16,
+    /// E-UTRA encryption algorithms
+    pub eutra_encryption_algorithms: u16,
+    /// E-UTRA integrity protection algorithms
+    pub eutra_integrity_algorithms: u16,
+}
+
+impl AperCodec for UeSecurityCapabilities {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        for mask in [
+            self.nr_encryption_algorithms,
+            self.nr_integrity_algorithms,
+            self.eutra_encryption_algorithms,
+            self.eutra_integrity_algorithms,
+        ] {
+            enc.put_bit_string(&mask.to_be_bytes(), 16, 16, Some(16), true)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let mut masks = [0u16; 4];
+        for mask in &mut masks {
+            let (data, _) = dec.get_bit_string(16, Some(16), true)?;
+            *mask = u16::from_be_bytes([data.first().copied().unwrap_or(0), data.get(1).copied().unwrap_or(0)]);
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            nr_encryption_algorithms: masks[0],
+            nr_integrity_algorithms: masks[1],
+            eutra_encryption_algorithms: masks[2],
+            eutra_integrity_algorithms: masks[3],
+        })
+    }
+}
+
+/// Security Key (K_gNB), BIT STRING (SIZE(256))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityKey(pub [u8; 32]);
+
+impl AperCodec for SecurityKey {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bit_string(&self.0, 256, 256, Some(256), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let (data, _) = dec.get_bit_string(256, Some(256), false)?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&data);
+        Ok(Self(key))
+    }
+}
+
+/// Allowed NSSAI (3GPP TS 38.413 section 9.3.1.31)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedNssai(pub Vec<SNssai>);
+
+impl AperCodec for AllowedNssai {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_length(self.0.len(), 1, Some(MAX_ALLOWED_S_NSSAIS))?;
+        for s_nssai in &self.0 {
+            // Allowed NSSAI Item: extension bit, iE-Extensions absent
+            enc.put_bool(false);
+            enc.put_bool(false);
+            s_nssai.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let count = dec.get_length(1, Some(MAX_ALLOWED_S_NSSAIS))?;
+        let mut slices = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            slices.push(SNssai::decode(dec)?);
+            skip_ie_extensions(dec, extensions)?;
+        }
+        Ok(Self(slices))
+    }
+}
+
+/// GTP tunnel endpoint, the gTPTunnel alternative of UP Transport Layer
+/// Information (3GPP TS 38.413 section 9.3.2.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GtpTunnel {
+    /// Transport layer address of the endpoint
+    pub transport_layer_address: IpAddr,
+    /// GTP-U tunnel endpoint identifier
+    pub teid: u32,
+}
+
+impl AperCodec for GtpTunnel {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // UPTransportLayerInformation: gTPTunnel
+        enc.put_choice(0, 2, false)?;
+        enc.put_bool(false);
+        enc.put_bool(false);
+        match self.transport_layer_address {
+            IpAddr::V4(addr) => enc.put_bit_string(&addr.octets(), 32, 1, Some(160), true)?,
+            IpAddr::V6(addr) => enc.put_bit_string(&addr.octets(), 128, 1, Some(160), true)?,
+        }
+        enc.put_octet_string(&self.teid.to_be_bytes(), 4, Some(4), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(2, false)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let (data, bits) = dec.get_bit_string(1, Some(160), true)?;
+        // An IPv4v6 endpoint (160 bits) carries the IPv4 address first
+        let transport_layer_address = match bits {
+            32 | 160 => IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+            128 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&data[..16]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        let teid = dec.get_octet_string(4, Some(4), false)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            transport_layer_address,
+            teid: u32::from_be_bytes([teid[0], teid[1], teid[2], teid[3]]),
+        })
+    }
+}
+
+/// PDU Session Type (3GPP TS 38.413 section 9.3.1.52)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PduSessionType {
+    Ipv4 = 0,
+    Ipv6 = 1,
+    Ipv4v6 = 2,
+    Ethernet = 3,
+    Unstructured = 4,
+}
+
+impl AperCodec for PduSessionType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 5, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        use PduSessionType::*;
+
+        [Ipv4, Ipv6, Ipv4v6, Ethernet, Unstructured]
+            .get(dec.get_enumerated(5, true)?).copied().ok_or(LayerError::InvalidPdu)
+    }
+}
+
+/// Allocation and Retention Priority (3GPP TS 38.413 section 9.3.1.19)
+impl AperCodec for AllocationRetentionPriority {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.priority_level as u64, 1, 15, false)?;
+        enc.put_enumerated(self.may_trigger_pre_emption as usize, 2, true)?;
+        enc.put_enumerated(self.pre_emptable as usize, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let priority_level = dec.get_integer(1, 15, false)? as u8;
+        let may_trigger_pre_emption = dec.get_enumerated(2, true)? == 1;
+        let pre_emptable = dec.get_enumerated(2, true)? == 1;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            priority_level,
+            may_trigger_pre_emption,
+            pre_emptable,
+        })
+    }
+}
+
+/// QoS Characteristics (3GPP TS 38.413 sections 9.3.1.18 and 9.3.1.28)
+impl AperCodec for QosCharacteristics {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match *self {
+            QosCharacteristics::NonDynamic { five_qi, priority_level } => {
+                enc.put_choice(0, 3, false)?;
+                // Non Dynamic 5QI Descriptor: extension bit, priorityLevelQos,
+                // averagingWindow, maximumDataBurstVolume, iE-Extensions
+                enc.put_bool(false);
+                enc.put_bool(priority_level.is_some());
+                enc.put_bits(0, 3);
+                enc.put_integer(five_qi.0 as u64, 0, 255, true)?;
+                if let Some(level) = priority_level {
+                    enc.put_integer(level as u64, 1, 127, true)?;
+                }
+            }
+            QosCharacteristics::Dynamic { five_qi, priority_level, packet_delay_budget, per_scalar, per_exponent } => {
+                enc.put_choice(1, 3, false)?;
+                // Dynamic 5QI Descriptor: extension bit, fiveQI, delayCritical,
+                // averagingWindow, maximumDataBurstVolume, iE-Extensions
+                enc.put_bool(false);
+                enc.put_bool(five_qi.is_some());
+                enc.put_bits(0, 4);
+                enc.put_integer(priority_level as u64, 1, 127, true)?;
+                enc.put_integer(packet_delay_budget as u64, 0, 1023, true)?;
+                // Packet Error Rate
+                enc.put_bool(false);
+                enc.put_bool(false);
+                enc.put_integer(per_scalar as u64, 0, 9, true)?;
+                enc.put_integer(per_exponent as u64, 0, 9, true)?;
+                if let Some(five_qi) = five_qi {
+                    enc.put_integer(five_qi.0 as u64, 0, 255, true)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(3, false)? {
+            0 => {
+                dec.get_bool()?;
+                let has_priority = dec.get_bool()?;
+                let has_averaging_window = dec.get_bool()?;
+                let has_burst_volume = dec.get_bool()?;
+                let extensions = dec.get_bool()?;
+                let five_qi = FiveQi(dec.get_integer(0, 255, true)? as u8);
+                let priority_level = if has_priority {
+                    Some(dec.get_integer(1, 127, true)? as u8)
+                } else {
+                    None
+                };
+                if has_averaging_window {
+                    dec.get_integer(0, 4095, true)?;
+                }
+                if has_burst_volume {
+                    dec.get_integer(0, 4095, true)?;
+                }
+                skip_ie_extensions(dec, extensions)?;
+                Ok(QosCharacteristics::NonDynamic { five_qi, priority_level })
+            }
+            1 => {
+                dec.get_bool()?;
+                let has_five_qi = dec.get_bool()?;
+                let has_delay_critical = dec.get_bool()?;
+                let has_averaging_window = dec.get_bool()?;
+                let has_burst_volume = dec.get_bool()?;
+                let extensions = dec.get_bool()?;
+                let priority_level = dec.get_integer(1, 127, true)? as u8;
+                let packet_delay_budget = dec.get_integer(0, 1023, true)? as u16;
+                dec.get_bool()?;
+                let per_extensions = dec.get_bool()?;
+                let per_scalar = dec.get_integer(0, 9, true)? as u8;
+                let per_exponent = dec.get_integer(0, 9, true)? as u8;
+                skip_ie_extensions(dec, per_extensions)?;
+                let five_qi = if has_five_qi {
+                    Some(FiveQi(dec.get_integer(0, 255, true)? as u8))
+                } else {
+                    None
+                };
+                if has_delay_critical {
+                    dec.get_enumerated(2, true)?;
+                }
+                if has_averaging_window {
+                    dec.get_integer(0, 4095, true)?;
+                }
+                if has_burst_volume {
+                    dec.get_integer(0, 4095, true)?;
+                }
+                skip_ie_extensions(dec, extensions)?;
+                Ok(QosCharacteristics::Dynamic { five_qi, priority_level, packet_delay_budget, per_scalar, per_exponent })
+            }
+            _ => Err(LayerError::ProcessingError("Unsupported QoS characteristics".into())),
+        }
+    }
+}
+
+/// GBR QoS Flow Information (3GPP TS 38.413 section 9.3.1.20)
+impl AperCodec for GbrQosInformation {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, notificationControl, maximumPacketLossRateDL/UL, iE-Extensions
+        enc.put_bool(false);
+        enc.put_bits(0, 4);
+        put_bit_rate(enc, self.max_bit_rate_dl)?;
+        put_bit_rate(enc, self.max_bit_rate_ul)?;
+        put_bit_rate(enc, self.guaranteed_bit_rate_dl)?;
+        put_bit_rate(enc, self.guaranteed_bit_rate_ul)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_notification_control = dec.get_bool()?;
+        let has_loss_rate_dl = dec.get_bool()?;
+        let has_loss_rate_ul = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let info = Self {
+            max_bit_rate_dl: get_bit_rate(dec)?,
+            max_bit_rate_ul: get_bit_rate(dec)?,
+            guaranteed_bit_rate_dl: get_bit_rate(dec)?,
+            guaranteed_bit_rate_ul: get_bit_rate(dec)?,
+        };
+        if has_notification_control {
+            dec.get_enumerated(1, true)?;
+        }
+        if has_loss_rate_dl {
+            dec.get_integer(0, 1000, true)?;
+        }
+        if has_loss_rate_ul {
+            dec.get_integer(0, 1000, true)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(info)
+    }
+}
+
+/// QoS Flow Level QoS Parameters of a flow (3GPP TS 38.413 section 9.3.1.12)
+fn encode_qos_flow_level_parameters(enc: &mut AperEncoder, flow: &QosFlowDescriptor) -> Result<(), LayerError> {
+    // Extension bit, gBR-QosInformation, reflectiveQosAttribute,
+    // additionalQosFlowInformation, iE-Extensions
+    enc.put_bool(false);
+    enc.put_bool(flow.gbr.is_some());
+    enc.put_bits(0, 3);
+    flow.characteristics.encode(enc)?;
+    flow.arp.encode(enc)?;
+    if let Some(gbr) = &flow.gbr {
+        gbr.encode(enc)?;
+    }
+    Ok(())
+}
+
+fn decode_qos_flow_level_parameters(dec: &mut AperDecoder, qfi: u8) -> Result<QosFlowDescriptor, LayerError> {
+    dec.get_bool()?;
+    let has_gbr = dec.get_bool()?;
+    let has_reflective_qos = dec.get_bool()?;
+    let has_additional_info = dec.get_bool()?;
+    let extensions = dec.get_bool()?;
+    let characteristics = QosCharacteristics::decode(dec)?;
+    let arp = AllocationRetentionPriority::decode(dec)?;
+    let gbr = if has_gbr {
+        Some(GbrQosInformation::decode(dec)?)
+    } else {
+        None
+    };
+    if has_reflective_qos {
+        dec.get_enumerated(1, true)?;
+    }
+    if has_additional_info {
+        dec.get_enumerated(1, true)?;
+    }
+    skip_ie_extensions(dec, extensions)?;
+    Ok(QosFlowDescriptor {
+        qfi,
+        characteristics,
+        arp,
+        gbr,
+    })
+}
+
+/// QoS Flow Setup Request Item
+impl AperCodec for QosFlowDescriptor {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, e-RAB-ID and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        enc.put_integer(self.qfi as u64, 0, 63, true)?;
+        encode_qos_flow_level_parameters(enc, self)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_e_rab_id = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let qfi = dec.get_integer(0, 63, true)? as u8;
+        let flow = decode_qos_flow_level_parameters(dec, qfi)?;
+        if has_e_rab_id {
+            dec.get_integer(0, 15, true)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(flow)
+    }
+}
+
+impl AperCodec for Vec<QosFlowDescriptor> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_QOS_FLOWS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_QOS_FLOWS)
+    }
+}
+
+/// PDU Session Resource Setup Request Transfer (3GPP TS 38.413 section 9.3.4.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceSetupRequestTransfer {
+    /// PDU Session Aggregate Maximum Bit Rate (non-GBR flows)
+    pub session_ambr: Option<AggregateMaximumBitRate>,
+    /// UPF endpoint of the NG-U tunnel
+    pub ul_tunnel: GtpTunnel,
+    /// PDU session type
+    pub pdu_session_type: PduSessionType,
+    /// QoS flows to set up
+    pub qos_flows: Vec<QosFlowDescriptor>,
+}
+
+impl AperCodec for PduSessionResourceSetupRequestTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let mut ies = Vec::new();
+        if let Some(session_ambr) = &self.session_ambr {
+            ies.push(protocol_ie(ID_PDU_SESSION_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Reject, session_ambr)?);
+        }
+        ies.push(protocol_ie(ID_UL_NGU_UP_TNL_INFORMATION, Criticality::Reject, &self.ul_tunnel)?);
+        ies.push(protocol_ie(ID_PDU_SESSION_TYPE, Criticality::Reject, &self.pdu_session_type)?);
+        ies.push(protocol_ie(ID_QOS_FLOW_SETUP_REQUEST_LIST, Criticality::Reject, &self.qos_flows)?);
+        encode_ie_container(enc, &ies)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let ies = decode_ie_container(dec)?;
+        let missing = |id| LayerError::ProcessingError(format!("Missing mandatory IE {} in PDU Session Resource Setup Request Transfer", id));
+        Ok(Self {
+            session_ambr: find_ie(&ies, ID_PDU_SESSION_AGGREGATE_MAXIMUM_BIT_RATE)?,
+            ul_tunnel: find_ie(&ies, ID_UL_NGU_UP_TNL_INFORMATION)?.ok_or_else(|| missing(ID_UL_NGU_UP_TNL_INFORMATION))?,
+            pdu_session_type: find_ie(&ies, ID_PDU_SESSION_TYPE)?.ok_or_else(|| missing(ID_PDU_SESSION_TYPE))?,
+            qos_flows: find_ie(&ies, ID_QOS_FLOW_SETUP_REQUEST_LIST)?.ok_or_else(|| missing(ID_QOS_FLOW_SETUP_REQUEST_LIST))?,
+        })
+    }
+}
+
+/// PDU Session Resource Setup Item, used in both the Initial Context Setup
+/// Request (CxtReq) and the PDU Session Resource Setup Request (SUReq)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceSetupItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// NAS PDU for the PDU session
+    pub nas_pdu: Option<Bytes>,
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+    /// PDU Session Resource Setup Request Transfer
+    pub transfer: PduSessionResourceSetupRequestTransfer,
+}
+
+impl AperCodec for PduSessionResourceSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.nas_pdu.is_some());
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        if let Some(nas_pdu) = &self.nas_pdu {
+            nas_pdu.encode(enc)?;
+        }
+        self.s_nssai.encode(enc)?;
+        let mut transfer = AperEncoder::new();
+        self.transfer.encode(&mut transfer)?;
+        transfer.into_bytes().encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_nas_pdu = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let nas_pdu = if has_nas_pdu {
+            Some(Bytes::decode(dec)?)
+        } else {
+            None
+        };
+        let s_nssai = SNssai::decode(dec)?;
+        let transfer = Bytes::decode(dec)?;
+        let transfer = PduSessionResourceSetupRequestTransfer::decode(&mut AperDecoder::new(&transfer))?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            pdu_session_id,
+            nas_pdu,
+            s_nssai,
+            transfer,
+        })
+    }
+}
+
+/// PDU Session Resource Setup List, SEQUENCE (SIZE(1..maxnoofPDUSessions))
+impl AperCodec for Vec<PduSessionResourceSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource Setup Response Transfer (3GPP TS 38.413 section 9.3.4.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceSetupResponseTransfer {
+    /// gNB endpoint of the NG-U tunnel
+    pub dl_tunnel: GtpTunnel,
+    /// QoS flows carried by the tunnel
+    pub qos_flows: Vec<u8>,
+}
+
+impl AperCodec for PduSessionResourceSetupResponseTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, additionalDLQosFlowPerTNLInformation, securityResult,
+        // qosFlowFailedToSetupList and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 4);
+        // QoS Flow per TNL Information
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.dl_tunnel.encode(enc)?;
+        enc.put_length(self.qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+        for qfi in &self.qos_flows {
+            // Associated QoS Flow Item: qosFlowMappingIndication and iE-Extensions absent
+            enc.put_bool(false);
+            enc.put_bits(0, 2);
+            enc.put_integer(*qfi as u64, 0, 63, true)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        // Additional tunnels, security result and failed QoS flows follow the
+        // mandatory part and are not used
+        dec.get_bits(4)?;
+        dec.get_bool()?;
+        let tnl_extensions = dec.get_bool()?;
+        let dl_tunnel = GtpTunnel::decode(dec)?;
+        let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+        let mut qos_flows = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let has_mapping_indication = dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+            if has_mapping_indication {
+                dec.get_enumerated(2, true)?;
+            }
+            skip_ie_extensions(dec, extensions)?;
+        }
+        skip_ie_extensions(dec, tnl_extensions)?;
+        Ok(Self { dl_tunnel, qos_flows })
+    }
+}
+
+/// PDU Session Resource Setup Unsuccessful Transfer (3GPP TS 38.413 section 9.3.4.16)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PduSessionResourceSetupUnsuccessfulTransfer {
+    /// Failure cause
+    pub cause: Cause,
+}
+
+impl AperCodec for PduSessionResourceSetupUnsuccessfulTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, criticalityDiagnostics and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        // Criticality diagnostics follow the cause and are not used
+        dec.get_bits(2)?;
+        Ok(Self { cause: Cause::decode(dec)? })
+    }
+}
+
+/// PDU session ID with an encoded transfer, the shape shared by the setup
+/// response (CxtRes/SURes), failed to setup (CxtRes/SURes/CxtFail/HOAck/PSReq)
+/// and the handover and path switch items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// Encoded transfer
+    pub transfer: Bytes,
+}
+
+impl PduSessionResourceItem {
+    /// Build an item from a transfer
+    pub fn new<T: AperCodec>(pdu_session_id: u8, transfer: &T) -> Result<Self, LayerError> {
+        let mut enc = AperEncoder::new();
+        transfer.encode(&mut enc)?;
+        Ok(Self {
+            pdu_session_id,
+            transfer: enc.into_bytes(),
+        })
+    }
+
+    /// Decode the transfer
+    pub fn decode_transfer<T: AperCodec>(&self) -> Result<T, LayerError> {
+        T::decode(&mut AperDecoder::new(&self.transfer))
+    }
+}
+
+impl AperCodec for PduSessionResourceItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.transfer.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let transfer = Bytes::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            pdu_session_id,
+            transfer,
+        })
+    }
+}
+
+/// List of PDU session items, SEQUENCE (SIZE(1..maxnoofPDUSessions))
+impl AperCodec for Vec<PduSessionResourceItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// UE NGAP IDs (3GPP TS 38.413 section 9.3.3.18)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UeNgapIds {
+    /// Both AMF UE NGAP ID and RAN UE NGAP ID
+    Pair {
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+    },
+    /// AMF UE NGAP ID only
+    Amf(u64),
+}
+
+impl UeNgapIds {
+    /// AMF UE NGAP ID of either alternative
+    pub fn amf_ue_ngap_id(&self) -> u64 {
+        match self {
+            UeNgapIds::Pair { amf_ue_ngap_id, .. } | UeNgapIds::Amf(amf_ue_ngap_id) => *amf_ue_ngap_id,
+        }
+    }
+}
+
+impl AperCodec for UeNgapIds {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match self {
+            UeNgapIds::Pair { amf_ue_ngap_id, ran_ue_ngap_id } => {
+                enc.put_choice(0, 3, false)?;
+                enc.put_bool(false);
+                enc.put_bool(false);
+                AmfUeNgapId(*amf_ue_ngap_id).encode(enc)?;
+                RanUeNgapId(*ran_ue_ngap_id).encode(enc)
+            }
+            UeNgapIds::Amf(amf_ue_ngap_id) => {
+                enc.put_choice(1, 3, false)?;
+                AmfUeNgapId(*amf_ue_ngap_id).encode(enc)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(3, false)? {
+            0 => {
+                dec.get_bool()?;
+                let extensions = dec.get_bool()?;
+                let amf_ue_ngap_id = AmfUeNgapId::decode(dec)?.0;
+                let ran_ue_ngap_id = RanUeNgapId::decode(dec)?.0;
+                skip_ie_extensions(dec, extensions)?;
+                Ok(UeNgapIds::Pair { amf_ue_ngap_id, ran_ue_ngap_id })
+            }
+            1 => Ok(UeNgapIds::Amf(AmfUeNgapId::decode(dec)?.0)),
+            _ => Err(LayerError::ProcessingError("Unsupported UE NGAP IDs alternative".into())),
+        }
+    }
+}
+
+/// UE-associated logical NG-connection Item, listed in NG Reset and NG Reset
+/// Acknowledge (3GPP TS 38.413 section 9.2.6.11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UeAssociatedNgConnection {
+    /// AMF UE NGAP ID, if known
+    pub amf_ue_ngap_id: Option<u64>,
+    /// RAN UE NGAP ID, if known
+    pub ran_ue_ngap_id: Option<u32>,
+}
+
+impl AperCodec for UeAssociatedNgConnection {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.amf_ue_ngap_id.is_some());
+        enc.put_bool(self.ran_ue_ngap_id.is_some());
+        enc.put_bool(false);
+        if let Some(amf_ue_ngap_id) = self.amf_ue_ngap_id {
+            AmfUeNgapId(amf_ue_ngap_id).encode(enc)?;
+        }
+        if let Some(ran_ue_ngap_id) = self.ran_ue_ngap_id {
+            RanUeNgapId(ran_ue_ngap_id).encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let amf_present = dec.get_bool()?;
+        let ran_present = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let amf_ue_ngap_id = if amf_present { Some(AmfUeNgapId::decode(dec)?.0) } else { None };
+        let ran_ue_ngap_id = if ran_present { Some(RanUeNgapId::decode(dec)?.0) } else { None };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { amf_ue_ngap_id, ran_ue_ngap_id })
+    }
+}
+
+/// UE-associated logical NG-connection List, SEQUENCE (SIZE(1..maxnoofNGConnectionsToReset))
+impl AperCodec for Vec<UeAssociatedNgConnection> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_NG_CONNECTIONS_TO_RESET)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_NG_CONNECTIONS_TO_RESET)
+    }
+}
+
+/// Reset Type of NG Reset (3GPP TS 38.413 section 9.2.6.11)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResetType {
+    /// Reset of the whole NG interface
+    NgInterface,
+    /// Reset of the listed UE-associated logical NG-connections
+    PartOfNgInterface(Vec<UeAssociatedNgConnection>),
+}
+
+impl AperCodec for ResetType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        match self {
+            ResetType::NgInterface => {
+                enc.put_choice(0, 3, false)?;
+                enc.put_enumerated(0, 1, true)  // reset-all
+            }
+            ResetType::PartOfNgInterface(connections) => {
+                enc.put_choice(1, 3, false)?;
+                connections.encode(enc)
+            }
+        }
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_choice(3, false)? {
+            0 => {
+                dec.get_enumerated(1, true)?;
+                Ok(ResetType::NgInterface)
+            }
+            1 => Ok(ResetType::PartOfNgInterface(Vec::decode(dec)?)),
+            _ => Err(LayerError::ProcessingError("Unsupported Reset Type alternative".into())),
+        }
+    }
+}
+
+/// List of PDU session IDs, the shape of the PDU Session Resource List in the
+/// UE Context Release Request (CxtRelReq) and Complete (CxtRelCpl)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PduSessionIdList(pub Vec<u8>);
+
+impl AperCodec for PduSessionIdList {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_length(self.0.len(), 1, Some(MAX_PDU_SESSIONS))?;
+        for pdu_session_id in &self.0 {
+            enc.put_bool(false);
+            enc.put_bool(false);
+            enc.put_integer(*pdu_session_id as u64, 0, 255, false)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let count = dec.get_length(1, Some(MAX_PDU_SESSIONS))?;
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            ids.push(dec.get_integer(0, 255, false)? as u8);
+            skip_ie_extensions(dec, extensions)?;
+        }
+        Ok(Self(ids))
+    }
+}
+
+/// PDU Session Resource Release Command Transfer (3GPP TS 38.413 section 9.3.4.12)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PduSessionResourceReleaseCommandTransfer {
+    /// Release cause
+    pub cause: Cause,
+}
+
+impl AperCodec for PduSessionResourceReleaseCommandTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let cause = Cause::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { cause })
+    }
+}
+
+/// PDU Session Resource Release Response Transfer (3GPP TS 38.413 section 9.3.4.21)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PduSessionResourceReleaseResponseTransfer;
+
+impl AperCodec for PduSessionResourceReleaseResponseTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self)
+    }
+}
+
+/// QoS flow with a cause, used for the flows to release and the flows that
+/// failed to be added or modified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QosFlowWithCause {
+    /// QoS flow identifier
+    pub qfi: u8,
+    /// Cause
+    pub cause: Cause,
+}
+
+impl AperCodec for QosFlowWithCause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.qfi as u64, 0, 63, true)?;
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let qfi = dec.get_integer(0, 63, true)? as u8;
+        let cause = Cause::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { qfi, cause })
+    }
+}
+
+/// QoS Flow List with Cause (3GPP TS 38.413 section 9.3.1.13)
+impl AperCodec for Vec<QosFlowWithCause> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_QOS_FLOWS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_QOS_FLOWS)
+    }
+}
+
+/// QoS Flow Add or Modify Request List. Items are always encoded with their
+/// QoS parameters, items without parameters are skipped on decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct QosFlowAddOrModifyRequestList(Vec<QosFlowDescriptor>);
+
+impl AperCodec for QosFlowAddOrModifyRequestList {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_length(self.0.len(), 1, Some(MAX_QOS_FLOWS))?;
+        for flow in &self.0 {
+            // Extension bit, qosFlowLevelQosParameters present, e-RAB-ID and
+            // iE-Extensions absent
+            enc.put_bool(false);
+            enc.put_bool(true);
+            enc.put_bits(0, 2);
+            enc.put_integer(flow.qfi as u64, 0, 63, true)?;
+            encode_qos_flow_level_parameters(enc, flow)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+        let mut flows = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let has_parameters = dec.get_bool()?;
+            let has_e_rab_id = dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            let qfi = dec.get_integer(0, 63, true)? as u8;
+            let flow = if has_parameters {
+                Some(decode_qos_flow_level_parameters(dec, qfi)?)
+            } else {
+                None
+            };
+            if has_e_rab_id {
+                dec.get_integer(0, 15, true)?;
+            }
+            skip_ie_extensions(dec, extensions)?;
+            flows.extend(flow);
+        }
+        Ok(Self(flows))
+    }
+}
+
+/// PDU Session Resource Modify Request Transfer (3GPP TS 38.413 section 9.3.4.3)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PduSessionResourceModifyRequestTransfer {
+    /// New PDU Session Aggregate Maximum Bit Rate
+    pub session_ambr: Option<AggregateMaximumBitRate>,
+    /// QoS flows to add or modify
+    pub qos_flows_to_add_or_modify: Vec<QosFlowDescriptor>,
+    /// QoS flows to release
+    pub qos_flows_to_release: Vec<QosFlowWithCause>,
+}
+
+impl AperCodec for PduSessionResourceModifyRequestTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let mut ies = Vec::new();
+        if let Some(session_ambr) = &self.session_ambr {
+            ies.push(protocol_ie(ID_PDU_SESSION_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Reject, session_ambr)?);
+        }
+        if !self.qos_flows_to_add_or_modify.is_empty() {
+            let flows = QosFlowAddOrModifyRequestList(self.qos_flows_to_add_or_modify.clone());
+            ies.push(protocol_ie(ID_QOS_FLOW_ADD_OR_MODIFY_REQUEST_LIST, Criticality::Reject, &flows)?);
+        }
+        if !self.qos_flows_to_release.is_empty() {
+            ies.push(protocol_ie(ID_QOS_FLOW_TO_RELEASE_LIST, Criticality::Reject, &self.qos_flows_to_release)?);
+        }
+        encode_ie_container(enc, &ies)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let ies = decode_ie_container(dec)?;
+        Ok(Self {
+            session_ambr: find_ie(&ies, ID_PDU_SESSION_AGGREGATE_MAXIMUM_BIT_RATE)?,
+            qos_flows_to_add_or_modify: find_ie::<QosFlowAddOrModifyRequestList>(&ies, ID_QOS_FLOW_ADD_OR_MODIFY_REQUEST_LIST)?
+                .map(|list| list.0)
+                .unwrap_or_default(),
+            qos_flows_to_release: find_ie(&ies, ID_QOS_FLOW_TO_RELEASE_LIST)?.unwrap_or_default(),
+        })
+    }
+}
+
+/// PDU Session Resource Modify Item (ModReq)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceModifyItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// NAS PDU for the PDU session
+    pub nas_pdu: Option<Bytes>,
+    /// PDU Session Resource Modify Request Transfer
+    pub transfer: PduSessionResourceModifyRequestTransfer,
+}
+
+impl AperCodec for PduSessionResourceModifyItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(self.nas_pdu.is_some());
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        if let Some(nas_pdu) = &self.nas_pdu {
+            nas_pdu.encode(enc)?;
+        }
+        let mut transfer = AperEncoder::new();
+        self.transfer.encode(&mut transfer)?;
+        transfer.into_bytes().encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_nas_pdu = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let nas_pdu = if has_nas_pdu {
+            Some(Bytes::decode(dec)?)
+        } else {
+            None
+        };
+        let transfer = Bytes::decode(dec)?;
+        let transfer = PduSessionResourceModifyRequestTransfer::decode(&mut AperDecoder::new(&transfer))?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            pdu_session_id,
+            nas_pdu,
+            transfer,
+        })
+    }
+}
+
+/// PDU Session Resource Modify List (ModReq)
+impl AperCodec for Vec<PduSessionResourceModifyItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU Session Resource Modify Response Transfer (3GPP TS 38.413 section 9.3.4.4)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PduSessionResourceModifyResponseTransfer {
+    /// QoS flows added or modified
+    pub qos_flows: Vec<u8>,
+    /// QoS flows that could not be added or modified
+    pub failed_qos_flows: Vec<QosFlowWithCause>,
+}
+
+impl AperCodec for PduSessionResourceModifyResponseTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, dL/uL-NGU-UP-TNLInformation, qosFlowAddOrModifyResponseList,
+        // additionalDLQosFlowPerTNLInformation, qosFlowFailedToAddOrModifyList,
+        // iE-Extensions
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        enc.put_bool(!self.qos_flows.is_empty());
+        enc.put_bool(false);
+        enc.put_bool(!self.failed_qos_flows.is_empty());
+        enc.put_bool(false);
+        if !self.qos_flows.is_empty() {
+            enc.put_length(self.qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+            for qfi in &self.qos_flows {
+                enc.put_bool(false);
+                enc.put_bool(false);
+                enc.put_integer(*qfi as u64, 0, 63, true)?;
+            }
+        }
+        if !self.failed_qos_flows.is_empty() {
+            self.failed_qos_flows.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_dl_tunnel = dec.get_bool()?;
+        let has_ul_tunnel = dec.get_bool()?;
+        let has_qos_flows = dec.get_bool()?;
+        if dec.get_bool()? {
+            return Err(LayerError::ProcessingError("Additional DL QoS flow tunnels not supported".into()));
+        }
+        let has_failed_qos_flows = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if has_dl_tunnel {
+            GtpTunnel::decode(dec)?;
+        }
+        if has_ul_tunnel {
+            GtpTunnel::decode(dec)?;
+        }
+        let mut qos_flows = Vec::new();
+        if has_qos_flows {
+            let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+            for _ in 0..count {
+                dec.get_bool()?;
+                let item_extensions = dec.get_bool()?;
+                qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+                skip_ie_extensions(dec, item_extensions)?;
+            }
+        }
+        let failed_qos_flows = if has_failed_qos_flows {
+            Vec::<QosFlowWithCause>::decode(dec)?
+        } else {
+            Vec::new()
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { qos_flows, failed_qos_flows })
+    }
+}
+
+/// PDU Session Resource Modify Unsuccessful Transfer (3GPP TS 38.413 section
+/// 9.3.4.17), same content as the setup variant
+pub type PduSessionResourceModifyUnsuccessfulTransfer = PduSessionResourceSetupUnsuccessfulTransfer;
+
+/// Handover Type (3GPP TS 38.413 section 9.3.1.22)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoverType {
+    Intra5gs = 0,
+    FiveGsToEps = 1,
+    EpsTo5gs = 2,
+}
+
+impl AperCodec for HandoverType {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_enumerated(*self as usize, 3, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        match dec.get_enumerated(3, true)? {
+            0 => Ok(HandoverType::Intra5gs),
+            1 => Ok(HandoverType::FiveGsToEps),
+            2 => Ok(HandoverType::EpsTo5gs),
+            _ => Err(LayerError::InvalidPdu),
+        }
+    }
+}
+
+/// Target ID, target NG-RAN node alternative (3GPP TS 38.413 section 9.3.1.25)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetRanNodeId {
+    /// Global ID of the target gNB
+    pub global_gnb_id: GlobalGnbId,
+    /// Tracking area of the target cell
+    pub selected_tai: Tai,
+}
+
+impl AperCodec for TargetRanNodeId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // TargetID: targetRANNodeID
+        enc.put_choice(0, 3, false)?;
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.global_gnb_id.encode(enc)?;
+        self.selected_tai.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(3, false)? != 0 {
+            return Err(LayerError::ProcessingError("Only NG-RAN handover targets are supported".into()));
+        }
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let global_gnb_id = GlobalGnbId::decode(dec)?;
+        let selected_tai = Tai::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { global_gnb_id, selected_tai })
+    }
+}
+
+/// NG-RAN CGI, NR alternative
+fn encode_ngran_cgi(enc: &mut AperEncoder, cgi: &NrCgi) -> Result<(), LayerError> {
+    enc.put_choice(0, 3, false)?;
+    cgi.encode(enc)
+}
+
+fn decode_ngran_cgi(dec: &mut AperDecoder) -> Result<NrCgi, LayerError> {
+    if dec.get_choice(3, false)? != 0 {
+        return Err(LayerError::ProcessingError("Only NR cells are supported".into()));
+    }
+    NrCgi::decode(dec)
+}
+
+/// Encode a transparent container: an OCTET STRING holding the APER encoding
+/// of the container SEQUENCE
+fn encode_transparent_container(
+    enc: &mut AperEncoder,
+    encode: impl FnOnce(&mut AperEncoder) -> Result<(), LayerError>,
+) -> Result<(), LayerError> {
+    let mut container = AperEncoder::new();
+    encode(&mut container)?;
+    container.into_bytes().encode(enc)
+}
+
+/// Source NG-RAN Node to Target NG-RAN Node Transparent Container (3GPP TS
+/// 38.413 section 9.3.1.29)
+///
+/// The UE history holds the source cell only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceToTargetTransparentContainer {
+    /// Encoded HandoverPreparationInformation
+    pub rrc_container: Bytes,
+    /// Target cell
+    pub target_cell: NrCgi,
+    /// Last visited cell of the UE
+    pub source_cell: NrCgi,
+}
+
+impl AperCodec for SourceToTargetTransparentContainer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_transparent_container(enc, |enc| {
+            // Extension bit, pDUSessionResourceInformationList, e-RABInformationList,
+            // indexToRFSP and iE-Extensions absent
+            enc.put_bool(false);
+            enc.put_bits(0, 4);
+            self.rrc_container.encode(enc)?;
+            encode_ngran_cgi(enc, &self.target_cell)?;
+            // UE History Information: one Last Visited Cell Item
+            enc.put_length(1, 1, Some(MAX_CELLS_IN_UE_HISTORY))?;
+            enc.put_bool(false);
+            enc.put_bool(false);
+            // LastVisitedCellInformation: nGRANCell, with enhanced granularity,
+            // HO cause and iE-Extensions absent
+            enc.put_choice(0, 5, false)?;
+            enc.put_bool(false);
+            enc.put_bits(0, 3);
+            encode_ngran_cgi(enc, &self.source_cell)?;
+            // Cell Type: small
+            enc.put_bool(false);
+            enc.put_bool(false);
+            enc.put_enumerated(1, 4, true)?;
+            // Time UE stayed in cell, not tracked
+            enc.put_integer(0, 0, 4095, false)
+        })
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let container = Bytes::decode(dec)?;
+        let dec = &mut AperDecoder::new(&container);
+        dec.get_bool()?;
+        let has_pdu_session_information = dec.get_bool()?;
+        let has_e_rab_information = dec.get_bool()?;
+        let has_index_to_rfsp = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if has_pdu_session_information || has_e_rab_information {
+            return Err(LayerError::ProcessingError("Per PDU session forwarding information not supported".into()));
+        }
+        let rrc_container = Bytes::decode(dec)?;
+        let target_cell = decode_ngran_cgi(dec)?;
+        if has_index_to_rfsp {
+            dec.get_integer(1, 256, true)?;
+        }
+        let count = dec.get_length(1, Some(MAX_CELLS_IN_UE_HISTORY))?;
+        let mut history = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let item_extensions = dec.get_bool()?;
+            if dec.get_choice(5, false)? != 0 {
+                return Err(LayerError::ProcessingError("Only NG-RAN cells are supported in the UE history".into()));
+            }
+            dec.get_bool()?;
+            let has_enhanced_granularity = dec.get_bool()?;
+            let has_ho_cause = dec.get_bool()?;
+            let cell_extensions = dec.get_bool()?;
+            history.push(decode_ngran_cgi(dec)?);
+            dec.get_bool()?;
+            let cell_type_extensions = dec.get_bool()?;
+            dec.get_enumerated(4, true)?;
+            skip_ie_extensions(dec, cell_type_extensions)?;
+            dec.get_integer(0, 4095, false)?;
+            if has_enhanced_granularity {
+                dec.get_integer(0, 40950, false)?;
+            }
+            if has_ho_cause {
+                Cause::decode(dec)?;
+            }
+            skip_ie_extensions(dec, cell_extensions)?;
+            skip_ie_extensions(dec, item_extensions)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            rrc_container,
+            target_cell,
+            source_cell: history[0],
+        })
+    }
+}
+
+/// Target NG-RAN Node to Source NG-RAN Node Transparent Container (3GPP TS
+/// 38.413 section 9.3.1.30)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetToSourceTransparentContainer {
+    /// Encoded RRCReconfiguration commanding the UE to the target
+    pub rrc_container: Bytes,
+}
+
+impl AperCodec for TargetToSourceTransparentContainer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_transparent_container(enc, |enc| {
+            enc.put_bool(false);
+            enc.put_bool(false);
+            self.rrc_container.encode(enc)
+        })
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let container = Bytes::decode(dec)?;
+        let dec = &mut AperDecoder::new(&container);
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let rrc_container = Bytes::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { rrc_container })
+    }
+}
+
+/// QoS flow list whose items hold the QFI only (QoS Flow To Be Forwarded
+/// List, QoS Flow Accepted List)
+fn encode_qfi_list(enc: &mut AperEncoder, qos_flows: &[u8]) -> Result<(), LayerError> {
+    enc.put_length(qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+    for qfi in qos_flows {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(*qfi as u64, 0, 63, true)?;
+    }
+    Ok(())
+}
+
+fn decode_qfi_list(dec: &mut AperDecoder) -> Result<Vec<u8>, LayerError> {
+    let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+    let mut qos_flows = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+        skip_ie_extensions(dec, extensions)?;
+    }
+    Ok(qos_flows)
+}
+
+/// Handover Required Transfer (3GPP TS 38.413 section 9.3.4.14)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandoverRequiredTransfer;
+
+impl AperCodec for HandoverRequiredTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, directForwardingPathAvailability and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_direct_forwarding_path = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if has_direct_forwarding_path {
+            dec.get_enumerated(1, true)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self)
+    }
+}
+
+/// Handover Command Transfer (3GPP TS 38.413 section 9.3.4.10)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HandoverCommandTransfer {
+    /// Tunnel of the target the source forwards the downlink to
+    pub dl_forwarding_tunnel: Option<GtpTunnel>,
+    /// QoS flows whose downlink is forwarded
+    pub qos_flows_to_forward: Vec<u8>,
+}
+
+impl AperCodec for HandoverCommandTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, dLForwardingUP-TNLInformation, qosFlowToBeForwardedList,
+        // dataForwardingResponseDRBList and iE-Extensions
+        enc.put_bool(false);
+        enc.put_bool(self.dl_forwarding_tunnel.is_some());
+        enc.put_bool(!self.qos_flows_to_forward.is_empty());
+        enc.put_bits(0, 2);
+        if let Some(tunnel) = &self.dl_forwarding_tunnel {
+            tunnel.encode(enc)?;
+        }
+        if !self.qos_flows_to_forward.is_empty() {
+            encode_qfi_list(enc, &self.qos_flows_to_forward)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_forwarding_tunnel = dec.get_bool()?;
+        let has_qos_flows = dec.get_bool()?;
+        if dec.get_bool()? {
+            return Err(LayerError::ProcessingError("Per DRB data forwarding not supported".into()));
+        }
+        let extensions = dec.get_bool()?;
+        let dl_forwarding_tunnel = if has_forwarding_tunnel {
+            Some(GtpTunnel::decode(dec)?)
+        } else {
+            None
+        };
+        let qos_flows_to_forward = if has_qos_flows {
+            decode_qfi_list(dec)?
+        } else {
+            Vec::new()
+        };
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { dl_forwarding_tunnel, qos_flows_to_forward })
+    }
+}
+
+/// PDU Session Resource Setup Item of the Handover Request (HOReq)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionResourceSetupItemHoReq {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+    /// Handover Request Transfer, a PDU Session Resource Setup Request Transfer
+    pub transfer: PduSessionResourceSetupRequestTransfer,
+}
+
+impl AperCodec for PduSessionResourceSetupItemHoReq {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.s_nssai.encode(enc)?;
+        let mut transfer = AperEncoder::new();
+        self.transfer.encode(&mut transfer)?;
+        transfer.into_bytes().encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let s_nssai = SNssai::decode(dec)?;
+        let transfer = Bytes::decode(dec)?;
+        let transfer = PduSessionResourceSetupRequestTransfer::decode(&mut AperDecoder::new(&transfer))?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            pdu_session_id,
+            s_nssai,
+            transfer,
+        })
+    }
+}
+
+/// PDU Session Resource Setup List (HOReq)
+impl AperCodec for Vec<PduSessionResourceSetupItemHoReq> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// Security Context (3GPP TS 38.413 section 9.3.1.88)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityContext {
+    /// Next Hop Chaining Count (0..7)
+    pub next_hop_chaining_count: u8,
+    /// Next Hop (NH) the target derives K_gNB from
+    pub next_hop: [u8; 32],
+}
+
+impl AperCodec for SecurityContext {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.next_hop_chaining_count as u64, 0, 7, false)?;
+        SecurityKey(self.next_hop).encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let next_hop_chaining_count = dec.get_integer(0, 7, false)? as u8;
+        let next_hop = SecurityKey::decode(dec)?.0;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { next_hop_chaining_count, next_hop })
+    }
+}
+
+/// Handover Request Acknowledge Transfer (3GPP TS 38.413 section 9.3.4.11)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandoverRequestAcknowledgeTransfer {
+    /// gNB endpoint of the NG-U tunnel
+    pub dl_tunnel: GtpTunnel,
+    /// Tunnel receiving the downlink forwarded by the source
+    pub dl_forwarding_tunnel: Option<GtpTunnel>,
+    /// QoS flows set up, with data forwarding accepted when a forwarding
+    /// tunnel is given
+    pub qos_flows: Vec<u8>,
+}
+
+impl AperCodec for HandoverRequestAcknowledgeTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, dLForwardingUP-TNLInformation, securityResult,
+        // qosFlowFailedToSetupList, dataForwardingResponseDRBList and iE-Extensions
+        enc.put_bool(false);
+        enc.put_bool(self.dl_forwarding_tunnel.is_some());
+        enc.put_bits(0, 4);
+        self.dl_tunnel.encode(enc)?;
+        if let Some(tunnel) = &self.dl_forwarding_tunnel {
+            tunnel.encode(enc)?;
+        }
+        // QoS Flow List with Data Forwarding
+        enc.put_length(self.qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+        for qfi in &self.qos_flows {
+            enc.put_bool(false);
+            enc.put_bool(self.dl_forwarding_tunnel.is_some());
+            enc.put_bool(false);
+            enc.put_integer(*qfi as u64, 0, 63, true)?;
+            if self.dl_forwarding_tunnel.is_some() {
+                // dataForwardingAccepted
+                enc.put_enumerated(0, 1, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_forwarding_tunnel = dec.get_bool()?;
+        let has_security_result = dec.get_bool()?;
+        let has_failed_qos_flows = dec.get_bool()?;
+        if dec.get_bool()? {
+            return Err(LayerError::ProcessingError("Per DRB data forwarding not supported".into()));
+        }
+        let extensions = dec.get_bool()?;
+        let dl_tunnel = GtpTunnel::decode(dec)?;
+        let dl_forwarding_tunnel = if has_forwarding_tunnel {
+            Some(GtpTunnel::decode(dec)?)
+        } else {
+            None
+        };
+        if has_security_result {
+            // Security Result: integrity and confidentiality protection results
+            dec.get_bool()?;
+            let result_extensions = dec.get_bool()?;
+            dec.get_enumerated(2, true)?;
+            dec.get_enumerated(2, true)?;
+            skip_ie_extensions(dec, result_extensions)?;
+        }
+        let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+        let mut qos_flows = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let has_forwarding_accepted = dec.get_bool()?;
+            let item_extensions = dec.get_bool()?;
+            qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+            if has_forwarding_accepted {
+                dec.get_enumerated(1, true)?;
+            }
+            skip_ie_extensions(dec, item_extensions)?;
+        }
+        if has_failed_qos_flows {
+            Vec::<QosFlowWithCause>::decode(dec)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { dl_tunnel, dl_forwarding_tunnel, qos_flows })
+    }
+}
+
+/// Handover Resource Allocation Unsuccessful Transfer (3GPP TS 38.413 section
+/// 9.3.4.13), same content as the setup variant
+pub type HandoverResourceAllocationUnsuccessfulTransfer = PduSessionResourceSetupUnsuccessfulTransfer;
+
+/// RAN Status Transfer Transparent Container (3GPP TS 38.413 section 9.3.1.46)
+/// with 18-bit PDCP SNs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RanStatusTransferContainer(pub Vec<DrbSnStatus>);
+
+/// COUNT Value for PDCP SN Length 18
+fn encode_count(enc: &mut AperEncoder, count: u32) -> Result<(), LayerError> {
+    enc.put_bool(false);
+    enc.put_bool(false);
+    enc.put_integer((count & 0x3_FFFF) as u64, 0, 262143, false)?;
+    enc.put_integer((count >> 18) as u64 & 0x3FFF, 0, 16383, false)
+}
+
+fn decode_count(dec: &mut AperDecoder) -> Result<u32, LayerError> {
+    dec.get_bool()?;
+    let extensions = dec.get_bool()?;
+    let sn = dec.get_integer(0, 262143, false)? as u32;
+    let hfn = dec.get_integer(0, 16383, false)? as u32;
+    skip_ie_extensions(dec, extensions)?;
+    Ok(hfn << 18 | sn)
+}
+
+impl AperCodec for RanStatusTransferContainer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_length(self.0.len(), 1, Some(MAX_DRBS))?;
+        for status in &self.0 {
+            enc.put_bool(false);
+            enc.put_bool(false);
+            enc.put_integer(status.drb_id as u64, 1, 32, true)?;
+            // DRB Status UL: dRBStatusUL18, receiveStatusOfUL-PDCP-SDUs and iE-Extension absent
+            enc.put_choice(1, 3, false)?;
+            enc.put_bool(false);
+            enc.put_bits(0, 2);
+            encode_count(enc, status.ul_count)?;
+            // DRB Status DL: dRBStatusDL18
+            enc.put_choice(1, 3, false)?;
+            enc.put_bool(false);
+            enc.put_bool(false);
+            encode_count(enc, status.dl_count)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let count = dec.get_length(1, Some(MAX_DRBS))?;
+        let mut drbs = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let item_extensions = dec.get_bool()?;
+            let drb_id = dec.get_integer(1, 32, true)? as u8;
+            if dec.get_choice(3, false)? != 1 {
+                return Err(LayerError::ProcessingError("Only 18-bit PDCP SN status is supported".into()));
+            }
+            dec.get_bool()?;
+            let has_receive_status = dec.get_bool()?;
+            let ul_extensions = dec.get_bool()?;
+            let ul_count = decode_count(dec)?;
+            if has_receive_status {
+                dec.get_bit_string(1, Some(131072), false)?;
+            }
+            skip_ie_extensions(dec, ul_extensions)?;
+            if dec.get_choice(3, false)? != 1 {
+                return Err(LayerError::ProcessingError("Only 18-bit PDCP SN status is supported".into()));
+            }
+            dec.get_bool()?;
+            let dl_extensions = dec.get_bool()?;
+            let dl_count = decode_count(dec)?;
+            skip_ie_extensions(dec, dl_extensions)?;
+            skip_ie_extensions(dec, item_extensions)?;
+            drbs.push(DrbSnStatus { drb_id, ul_count, dl_count });
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self(drbs))
+    }
+}
+
+/// Path Switch Request Transfer (3GPP TS 38.413 section 9.3.4.8)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSwitchRequestTransfer {
+    /// gNB endpoint of the NG-U tunnel at the target
+    pub dl_tunnel: GtpTunnel,
+    /// QoS flows accepted by the target
+    pub qos_flows: Vec<u8>,
+}
+
+impl AperCodec for PathSwitchRequestTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, dL-NGU-TNLInformationReused, userPlaneSecurityInformation
+        // and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bits(0, 3);
+        self.dl_tunnel.encode(enc)?;
+        encode_qfi_list(enc, &self.qos_flows)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_tnl_reused = dec.get_bool()?;
+        if dec.get_bool()? {
+            return Err(LayerError::ProcessingError("User plane security information not supported".into()));
+        }
+        let extensions = dec.get_bool()?;
+        let dl_tunnel = GtpTunnel::decode(dec)?;
+        if has_tnl_reused {
+            dec.get_enumerated(1, true)?;
+        }
+        let qos_flows = decode_qfi_list(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { dl_tunnel, qos_flows })
+    }
+}
+
+/// Path Switch Request Acknowledge Transfer (3GPP TS 38.413 section 9.3.4.9)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PathSwitchRequestAcknowledgeTransfer {
+    /// New UPF endpoint of the NG-U tunnel, if it changed
+    pub ul_tunnel: Option<GtpTunnel>,
+}
+
+impl AperCodec for PathSwitchRequestAcknowledgeTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension bit, uL-NGU-UP-TNLInformation, securityIndication and iE-Extensions
+        enc.put_bool(false);
+        enc.put_bool(self.ul_tunnel.is_some());
+        enc.put_bits(0, 2);
+        if let Some(tunnel) = &self.ul_tunnel {
+            tunnel.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_ul_tunnel = dec.get_bool()?;
+        let has_security_indication = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let ul_tunnel = if has_ul_tunnel {
+            Some(GtpTunnel::decode(dec)?)
+        } else {
+            None
+        };
+        if has_security_indication {
+            // Security Indication: integrity and confidentiality protection
+            // indications, optional maximum integrity protected data rate
+            dec.get_bool()?;
+            let has_max_data_rate = dec.get_bool()?;
+            let indication_extensions = dec.get_bool()?;
+            dec.get_enumerated(3, true)?;
+            dec.get_enumerated(3, true)?;
+            if has_max_data_rate {
+                dec.get_enumerated(2, true)?;
+            }
+            skip_ie_extensions(dec, indication_extensions)?;
+        }
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { ul_tunnel })
+    }
+}
+
+/// Path Switch Request Unsuccessful Transfer (3GPP TS 38.413 section 9.3.4.20)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathSwitchRequestUnsuccessfulTransfer {
+    /// Failure cause
+    pub cause: Cause,
+}
+
+impl AperCodec for PathSwitchRequestUnsuccessfulTransfer {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.cause.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let cause = Cause::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { cause })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ng_setup_request_encoding() {
+        let supported_tas = vec![SupportedTaItem {
+            tac: 1,
+            broadcast_plmns: vec![BroadcastPlmnItem {
+                plmn_id: [0x99, 0xF9, 0x07],
+                slices: vec![SNssai { sst: 1, sd: None }],
+            }],
+        }];
+        let pdu = NgapPdu::initiating(NgapProcedureCode::NgSetup)
+            .with_ie(ID_GLOBAL_RAN_NODE_ID, Criticality::Reject, &GlobalGnbId {
+                plmn_id: [0x99, 0xF9, 0x07],
+                gnb_id: 1,
+                gnb_id_bits: 32,
+            }).unwrap()
+            .with_ie(ID_RAN_NODE_NAME, Criticality::Ignore, &NodeName("gNB".into())).unwrap()
+            .with_ie(ID_SUPPORTED_TA_LIST, Criticality::Reject, &supported_tas).unwrap()
+            .with_ie(ID_DEFAULT_PAGING_DRX, Criticality::Ignore, &PagingDrx::V64).unwrap();
+        let bytes = pdu.encode().unwrap();
+
+        let expected: &[u8] = &[
+            0x00, 0x15, 0x00, 0x2F, 0x00, 0x00, 0x04,
+            // GlobalRANNodeID
+            0x00, 0x1B, 0x00, 0x09, 0x00, 0x99, 0xF9, 0x07, 0x50, 0x00, 0x00, 0x00, 0x01,
+            // RANNodeName
+            0x00, 0x52, 0x40, 0x05, 0x01, 0x00, b'g', b'N', b'B',
+            // SupportedTAList
+            0x00, 0x66, 0x00, 0x0D, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x99, 0xF9, 0x07, 0x00, 0x00, 0x00, 0x08,
+            // DefaultPagingDRX
+            0x00, 0x15, 0x40, 0x01, 0x20,
+        ];
+        assert_eq!(&bytes[..], expected);
+
+        let decoded = NgapPdu::decode(&bytes).unwrap();
+        assert_eq!(decoded, pdu);
+        assert_eq!(decoded.procedure(), Some(NgapProcedureCode::NgSetup));
+        assert_eq!(decoded.ie::<GlobalGnbId>(ID_GLOBAL_RAN_NODE_ID).unwrap().gnb_id, 1);
+        assert_eq!(decoded.ie::<Vec<SupportedTaItem>>(ID_SUPPORTED_TA_LIST).unwrap(), supported_tas);
+        assert_eq!(decoded.ie::<PagingDrx>(ID_DEFAULT_PAGING_DRX).unwrap(), PagingDrx::V64);
+    }
+
+    #[test]
+    fn test_decode_errors_and_unknown_ies() {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::NgSetup)
+            .with_ie(ID_DEFAULT_PAGING_DRX, Criticality::Ignore, &PagingDrx::V64).unwrap()
+            .with_ie(0xFFF0, Criticality::Ignore, &NodeName("unknown".into())).unwrap();
+        let bytes = pdu.encode().unwrap();
+
+        // Truncated PDU and NGAP-PDU extension
+        assert!(NgapPdu::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(NgapPdu::decode(&[]).is_err());
+        assert!(matches!(NgapPdu::decode(&[0x80, 0x15, 0x00, 0x00]), Err(LayerError::ProcessingError(_))));
+
+        // Unknown IEs are kept but do not get in the way of known ones
+        let decoded = NgapPdu::decode(&bytes).unwrap();
+        assert_eq!(decoded.ies.len(), 2);
+        assert_eq!(decoded.ie::<PagingDrx>(ID_DEFAULT_PAGING_DRX).unwrap(), PagingDrx::V64);
+
+        // Missing mandatory and optional IEs
+        assert!(matches!(decoded.ie::<GlobalGnbId>(ID_GLOBAL_RAN_NODE_ID), Err(LayerError::ProcessingError(_))));
+        assert!(decoded.optional_ie::<NodeName>(ID_RAN_NODE_NAME).unwrap().is_none());
+
+        // IE value that does not decode as its type
+        let mut invalid = decoded.clone();
+        invalid.ies[0].value = Bytes::from_static(&[0x81]);
+        assert!(matches!(invalid.ie::<PagingDrx>(ID_DEFAULT_PAGING_DRX), Err(LayerError::InvalidPdu)));
+
+        // Unknown procedure codes decode without a procedure
+        let mut unknown = bytes.to_vec();
+        unknown[1] = 0xFE;
+        let unknown = NgapPdu::decode(&unknown).unwrap();
+        assert_eq!(unknown.procedure_code, 0xFE);
+        assert_eq!(unknown.procedure(), None);
+    }
+}
+
@@ -0,0 +1,355 @@
+//! UE context release and PDU session resource release
+//!
+//! Implements UE Context Release Request and UE Context Release (3GPP TS 38.413
+//! sections 8.3.2 and 8.3.3) and PDU Session Resource Release (section 8.2.2).
+//! The radio resources are released by RRC before the Complete/Response is
+//! returned to the AMF.
+
+use super::pdu::{
+    self, AmfUeNgapId, Cause, Criticality, NgapPdu, PduSessionIdList, PduSessionResourceItem,
+    PduSessionResourceReleaseCommandTransfer, PduSessionResourceReleaseResponseTransfer, RanUeNgapId, UeNgapIds,
+};
+use super::handover::NgHandover;
+use super::{NgapLayer, NgapProcedureCode};
+use crate::gtpu::NgapGtpuMessage;
+use crate::rrc::{NgapRrcMessage, RrcReleaseCause};
+use crate::LayerError;
+use bytes::Bytes;
+use tracing::{debug, info, warn};
+
+impl NgapLayer {
+    /// Send UE Context Release Request for a UE released by the gNB
+    ///
+    /// Without an AMF UE NGAP ID the AMF has no context for the UE and the
+    /// release is done locally.
+    pub(super) async fn send_ue_context_release_request(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        cause: RrcReleaseCause,
+        pdu_session_ids: &[u8],
+    ) -> Result<(), LayerError> {
+        self.request_ue_context_release(ran_ue_ngap_id, Cause::from(cause), pdu_session_ids).await
+    }
+
+    /// Ask the AMF to release a UE, or release it locally if the AMF has no context for it
+    pub(super) async fn request_ue_context_release(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        cause: Cause,
+        pdu_session_ids: &[u8],
+    ) -> Result<(), LayerError> {
+        let Ok(amf_ue_ngap_id) = self.amf_ue_ngap_id(ran_ue_ngap_id) else {
+            debug!("No NG connection for RAN UE NGAP ID {}, releasing locally", ran_ue_ngap_id);
+            return self.send_to_rrc(NgapRrcMessage::UeContextRelease { ue_id: ran_ue_ngap_id }).await;
+        };
+
+        info!("Sending UE Context Release Request for RAN UE NGAP ID {}: {:?}", ran_ue_ngap_id, cause);
+        let pdu = Self::build_ue_context_release_request(amf_ue_ngap_id, ran_ue_ngap_id, pdu_session_ids, cause)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build UE Context Release Request
+    pub(super) fn build_ue_context_release_request(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        pdu_session_ids: &[u8],
+        cause: Cause,
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::initiating(NgapProcedureCode::UeContextReleaseRequest)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !pdu_session_ids.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_REQ, Criticality::Reject,
+                       &PduSessionIdList(pdu_session_ids.to_vec()))?;
+        }
+        pdu.add_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle UE Context Release Command from the AMF
+    pub(super) async fn handle_ue_context_release_command(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ue_ngap_ids = pdu.ie::<UeNgapIds>(pdu::ID_UE_NGAP_IDS)?;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+
+        let ran_ue_ngap_id = match ue_ngap_ids {
+            UeNgapIds::Pair { ran_ue_ngap_id, .. } => Some(ran_ue_ngap_id),
+            UeNgapIds::Amf(amf_ue_ngap_id) => self.ue_contexts.values()
+                .find(|ctx| ctx.amf_ue_ngap_id == Some(amf_ue_ngap_id))
+                .map(|ctx| ctx.ran_ue_ngap_id),
+        }.filter(|id| self.ue_contexts.contains_key(id));
+
+        let Some(ran_ue_ngap_id) = ran_ue_ngap_id else {
+            warn!("UE Context Release Command for unknown UE {:?}", ue_ngap_ids);
+            return match ue_ngap_ids {
+                // The AMF knows both IDs, confirm the release so it can clean up
+                UeNgapIds::Pair { amf_ue_ngap_id, ran_ue_ngap_id } => {
+                    let pdu = Self::build_ue_context_release_complete(amf_ue_ngap_id, ran_ue_ngap_id, &[])?;
+                    self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+                }
+                UeNgapIds::Amf(amf_ue_ngap_id) => {
+                    self.send_error_indication(Some(amf_ue_ngap_id), None, Cause::INCONSISTENT_REMOTE_UE_NGAP_ID).await
+                }
+            };
+        };
+
+        info!("UE Context Release Command for RAN UE NGAP ID {}: {:?}", ran_ue_ngap_id, cause);
+        self.send_to_rrc(NgapRrcMessage::UeContextRelease { ue_id: ran_ue_ngap_id }).await
+    }
+
+    /// Send UE Context Release Complete once RRC has released the UE, and drop
+    /// the UE-associated logical NG connection
+    pub(super) async fn send_ue_context_release_complete(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        pdu_session_ids: &[u8],
+    ) -> Result<(), LayerError> {
+        let Some(ue_context) = self.ue_contexts.remove(&ran_ue_ngap_id) else {
+            debug!("UE context for RAN UE NGAP ID {} already released", ran_ue_ngap_id);
+            return Ok(());
+        };
+        if ue_context.handover == Some(NgHandover::XnSource) {
+            // The AMF moves the UE to the target, the N3 tunnels keep forwarding
+            // to it until the UPF sends the End Marker
+            debug!("RAN UE NGAP ID {} released after Xn handover", ran_ue_ngap_id);
+            return Ok(());
+        }
+        self.send_to_gtpu(NgapGtpuMessage::ReleaseUe { ue_id: ran_ue_ngap_id }).await;
+        let Some(amf_ue_ngap_id) = ue_context.amf_ue_ngap_id else {
+            debug!("RAN UE NGAP ID {} released locally", ran_ue_ngap_id);
+            return Ok(());
+        };
+
+        let mut released: Vec<u8> = ue_context.pdu_sessions.keys().copied()
+            .chain(pdu_session_ids.iter().copied())
+            .collect();
+        released.sort_unstable();
+        released.dedup();
+
+        info!("Sending UE Context Release Complete for RAN UE NGAP ID {} (PDU sessions {:?})", ran_ue_ngap_id, released);
+        let pdu = Self::build_ue_context_release_complete(amf_ue_ngap_id, ran_ue_ngap_id, &released)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Build UE Context Release Complete
+    pub(super) fn build_ue_context_release_complete(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        pdu_session_ids: &[u8],
+    ) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::successful(NgapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?;
+        if !pdu_session_ids.is_empty() {
+            pdu.add_ie(pdu::ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_CPL, Criticality::Reject,
+                       &PduSessionIdList(pdu_session_ids.to_vec()))?;
+        }
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle PDU Session Resource Release Command from the AMF
+    pub(super) async fn handle_pdu_session_resource_release_command(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let nas_pdu = pdu.optional_ie::<Bytes>(pdu::ID_NAS_PDU)?;
+        let items = pdu.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_TO_RELEASE_LIST_REL_CMD)?;
+
+        let mut pdu_session_ids = Vec::with_capacity(items.len());
+        for item in &items {
+            let transfer = item.decode_transfer::<PduSessionResourceReleaseCommandTransfer>()?;
+            debug!("Releasing PDU session {} of RAN UE NGAP ID {}: {:?}", item.pdu_session_id, ran_ue_ngap_id, transfer.cause);
+            pdu_session_ids.push(item.pdu_session_id);
+        }
+        info!("PDU Session Resource Release Command for RAN UE NGAP ID {}: {:?}", ran_ue_ngap_id, pdu_session_ids);
+
+        self.send_to_rrc(NgapRrcMessage::PduSessionResourceRelease {
+            ue_id: ran_ue_ngap_id,
+            pdu_session_ids,
+            nas_pdu,
+        }).await
+    }
+
+    /// Send PDU Session Resource Release Response
+    ///
+    /// Sessions RRC had no bearers for are gone as well and are reported released.
+    pub(super) async fn send_pdu_session_resource_release_response(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        succeeded: &[u8],
+        failed: &[u8],
+    ) -> Result<(), LayerError> {
+        let amf_ue_ngap_id = self.amf_ue_ngap_id(ran_ue_ngap_id)?;
+        let released = self.release_pdu_session_contexts(ran_ue_ngap_id, succeeded.iter().chain(failed))?;
+        self.send_to_gtpu(NgapGtpuMessage::ReleaseTunnels {
+            ue_id: ran_ue_ngap_id,
+            pdu_session_ids: succeeded.iter().chain(failed).copied().collect(),
+        }).await;
+
+        info!("Sending PDU Session Resource Release Response for RAN UE NGAP ID {} ({} released)",
+              ran_ue_ngap_id, released.len());
+        let pdu = Self::build_pdu_session_resource_release_response(amf_ue_ngap_id, ran_ue_ngap_id, released)?;
+        self.send_ue_pdu(ran_ue_ngap_id, pdu).await
+    }
+
+    /// Drop the NGAP state of released PDU sessions and build their response items
+    fn release_pdu_session_contexts<'a>(
+        &mut self,
+        ran_ue_ngap_id: u32,
+        pdu_session_ids: impl Iterator<Item = &'a u8>,
+    ) -> Result<Vec<PduSessionResourceItem>, LayerError> {
+        let ue_context = self.ue_contexts.get_mut(&ran_ue_ngap_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown RAN UE NGAP ID {}", ran_ue_ngap_id)))?;
+        pdu_session_ids
+            .map(|id| {
+                ue_context.pdu_sessions.remove(id);
+                PduSessionResourceItem::new(*id, &PduSessionResourceReleaseResponseTransfer)
+            })
+            .collect()
+    }
+
+    /// Build PDU Session Resource Release Response
+    pub(super) fn build_pdu_session_resource_release_response(
+        amf_ue_ngap_id: u64,
+        ran_ue_ngap_id: u32,
+        released: Vec<PduSessionResourceItem>,
+    ) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::successful(NgapProcedureCode::PduSessionResourceRelease)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_RELEASED_LIST_REL_RES, Criticality::Ignore, &released)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::PagingDrx;
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    fn test_layer() -> NgapLayer {
+        NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x99, 0xF9, 0x07],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000004001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ue_context_release() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+        ngap.ue_contexts.insert(1001, NgapUeContext { ran_ue_ngap_id: 1001, ..Default::default() });
+
+        // A UE the AMF does not know yet is released locally
+        ngap.send_ue_context_release_request(1001, RrcReleaseCause::UserInactivity, &[]).await.unwrap();
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 1001 }));
+        ngap.send_ue_context_release_complete(1001, &[]).await.unwrap();
+        assert!(!ngap.ue_contexts.contains_key(&1001));
+
+        let request = NgapLayer::build_ue_context_release_request(7, 1000, &[1, 2], Cause::USER_INACTIVITY).unwrap();
+        let request = NgapPdu::decode(&request).unwrap();
+        assert_eq!(request.procedure(), Some(NgapProcedureCode::UeContextReleaseRequest));
+        assert_eq!(request.ie::<PduSessionIdList>(pdu::ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_REQ).unwrap().0, vec![1, 2]);
+        assert_eq!(request.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::USER_INACTIVITY);
+
+        // The command may identify the UE by AMF UE NGAP ID only
+        let command = NgapPdu::initiating(NgapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_UE_NGAP_IDS, Criticality::Reject, &UeNgapIds::Amf(7)).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::Nas(0)).unwrap();
+        let command = NgapPdu::decode(&command.encode().unwrap()).unwrap();
+        ngap.handle_ue_context_release_command(&command).await.unwrap();
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 1000 }));
+
+        let complete = NgapLayer::build_ue_context_release_complete(7, 1000, &[1]).unwrap();
+        let complete = NgapPdu::decode(&complete).unwrap();
+        assert_eq!(complete.pdu_type, pdu::NgapPduType::SuccessfulOutcome);
+        assert_eq!(complete.ie::<PduSessionIdList>(pdu::ID_PDU_SESSION_RESOURCE_LIST_CXT_REL_CPL).unwrap().0, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_pdu_session_resource_release() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+
+        let command = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceRelease)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Ignore, &Bytes::from_static(&[0x7E, 0x00, 0x68])).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_TO_RELEASE_LIST_REL_CMD, Criticality::Reject, &vec![
+                PduSessionResourceItem::new(1, &PduSessionResourceReleaseCommandTransfer { cause: Cause::Nas(0) }).unwrap(),
+            ]).unwrap();
+        let command = NgapPdu::decode(&command.encode().unwrap()).unwrap();
+        ngap.handle_pdu_session_resource_release_command(&command).await.unwrap();
+        match rrc_rx.try_recv().unwrap() {
+            NgapRrcMessage::PduSessionResourceRelease { ue_id, pdu_session_ids, nas_pdu } => {
+                assert_eq!(ue_id, 1000);
+                assert_eq!(pdu_session_ids, vec![1]);
+                assert!(nas_pdu.is_some());
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        let released = ngap.release_pdu_session_contexts(1000, [1].iter()).unwrap();
+        let response = NgapLayer::build_pdu_session_resource_release_response(7, 1000, released).unwrap();
+        let response = NgapPdu::decode(&response).unwrap();
+        let released = response.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_RELEASED_LIST_REL_RES).unwrap();
+        assert_eq!(released[0].pdu_session_id, 1);
+        released[0].decode_transfer::<PduSessionResourceReleaseResponseTransfer>().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_release_failures() {
+        let mut ngap = test_layer();
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.ue_contexts.insert(1000, NgapUeContext { ran_ue_ngap_id: 1000, amf_ue_ngap_id: Some(7), ..Default::default() });
+
+        // Command without Cause
+        let command = NgapPdu::initiating(NgapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_UE_NGAP_IDS, Criticality::Reject, &UeNgapIds::Amf(7)).unwrap();
+        assert!(matches!(ngap.handle_ue_context_release_command(&command).await, Err(LayerError::ProcessingError(_))));
+
+        // Unknown UEs are answered on the NG connection, which is down here
+        for ue_ngap_ids in [UeNgapIds::Amf(8), UeNgapIds::Pair { amf_ue_ngap_id: 7, ran_ue_ngap_id: 1001 }] {
+            let command = NgapPdu::initiating(NgapProcedureCode::UeContextRelease)
+                .with_ie(pdu::ID_UE_NGAP_IDS, Criticality::Reject, &ue_ngap_ids).unwrap()
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::Nas(0)).unwrap();
+            assert!(ngap.handle_ue_context_release_command(&command).await.is_err());
+        }
+        assert!(rrc_rx.try_recv().is_err());
+        assert!(ngap.ue_contexts.contains_key(&1000));
+
+        // Release Command with a transfer that does not decode
+        let command = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceRelease)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(7)).unwrap()
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(1000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_TO_RELEASE_LIST_REL_CMD, Criticality::Reject, &vec![
+                PduSessionResourceItem { pdu_session_id: 1, transfer: Bytes::new() },
+            ]).unwrap();
+        assert!(matches!(ngap.handle_pdu_session_resource_release_command(&command).await, Err(LayerError::InvalidPdu)));
+        assert!(rrc_rx.try_recv().is_err());
+
+        // Responses for an unknown UE, and a second Release Complete
+        assert!(matches!(ngap.send_pdu_session_resource_release_response(1001, &[1], &[]).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(ngap.send_ue_context_release_complete(1000, &[]).await.is_err());
+        assert!(!ngap.ue_contexts.contains_key(&1000));
+        ngap.send_ue_context_release_complete(1000, &[]).await.unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+//! NG Reset (3GPP TS 38.413 section 8.7.4)
+//!
+//! Either side may reset the whole NG interface or a list of UE-associated
+//! logical NG-connections. The UE contexts concerned are dropped at once and
+//! RRC releases their radio resources; no UE Context Release Complete follows.
+
+use super::pdu::{self, Cause, Criticality, NgapPdu, ResetType, UeAssociatedNgConnection};
+use super::transport::NON_UE_STREAM;
+use super::{NgapLayer, NgapProcedureCode};
+use crate::gtpu::NgapGtpuMessage;
+use crate::rrc::NgapRrcMessage;
+use crate::LayerError;
+use tracing::{debug, info};
+
+impl NgapLayer {
+    /// Drop UE contexts without signalling to the AMF and have RRC release the UEs
+    pub(super) async fn release_ues_locally(&mut self, mut ue_ids: Vec<u32>) -> Result<(), LayerError> {
+        ue_ids.sort_unstable();
+        ue_ids.dedup();
+        for ue_id in ue_ids {
+            if self.ue_contexts.remove(&ue_id).is_some() {
+                self.send_to_gtpu(NgapGtpuMessage::ReleaseUe { ue_id }).await;
+                self.send_to_rrc(NgapRrcMessage::UeContextRelease { ue_id }).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// RAN UE NGAP ID of a listed UE-associated logical NG-connection served by an AMF
+    fn reset_connection_ue(&self, amf: usize, connection: &UeAssociatedNgConnection) -> Option<u32> {
+        let ctx = match connection.ran_ue_ngap_id {
+            Some(ran_ue_ngap_id) => self.ue_contexts.get(&ran_ue_ngap_id),
+            None => self.ue_contexts.values()
+                .find(|ctx| ctx.amf == Some(amf) && ctx.amf_ue_ngap_id.is_some()
+                      && ctx.amf_ue_ngap_id == connection.amf_ue_ngap_id),
+        }?;
+        let consistent = ctx.amf == Some(amf)
+            && connection.amf_ue_ngap_id.is_none_or(|id| ctx.amf_ue_ngap_id == Some(id));
+        consistent.then_some(ctx.ran_ue_ngap_id)
+    }
+
+    /// Reset the NG interface with an AMF, or part of it, and send NG Reset
+    ///
+    /// `ran_ue_ngap_ids` lists the UE-associated logical NG-connections to reset,
+    /// `None` resets all connections with the AMF.
+    pub async fn send_ng_reset(&mut self, amf: usize, cause: Cause, ran_ue_ngap_ids: Option<&[u32]>) -> Result<(), LayerError> {
+        let reset_type = match ran_ue_ngap_ids {
+            None => ResetType::NgInterface,
+            Some(ids) => {
+                let connections: Vec<_> = ids.iter()
+                    .filter_map(|id| self.ue_contexts.get(id))
+                    .filter(|ctx| ctx.amf == Some(amf))
+                    .map(|ctx| UeAssociatedNgConnection {
+                        amf_ue_ngap_id: ctx.amf_ue_ngap_id,
+                        ran_ue_ngap_id: Some(ctx.ran_ue_ngap_id),
+                    })
+                    .collect();
+                if connections.is_empty() {
+                    return Err(LayerError::InvalidState("No UE-associated NG connection to reset".to_string()));
+                }
+                ResetType::PartOfNgInterface(connections)
+            }
+        };
+
+        let ue_ids: Vec<u32> = match &reset_type {
+            ResetType::NgInterface => self.ue_contexts.values()
+                .filter(|ctx| ctx.amf == Some(amf))
+                .map(|ctx| ctx.ran_ue_ngap_id)
+                .collect(),
+            ResetType::PartOfNgInterface(connections) => connections.iter()
+                .filter_map(|connection| connection.ran_ue_ngap_id)
+                .collect(),
+        };
+        info!("Sending NG Reset to AMF {} for {} UEs: {:?}", amf, ue_ids.len(), cause);
+        self.release_ues_locally(ue_ids).await?;
+
+        let pdu = Self::build_ng_reset(cause, &reset_type)?;
+        self.send_to_amf(amf, NON_UE_STREAM, pdu).await
+    }
+
+    /// Build NG Reset
+    pub(super) fn build_ng_reset(cause: Cause, reset_type: &ResetType) -> Result<Vec<u8>, LayerError> {
+        let pdu = NgapPdu::initiating(NgapProcedureCode::NgReset)
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?
+            .with_ie(pdu::ID_RESET_TYPE, Criticality::Reject, reset_type)?;
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle NG Reset from the AMF: release the UEs and acknowledge
+    pub(super) async fn handle_ng_reset(&mut self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        let reset_type = pdu.ie::<ResetType>(pdu::ID_RESET_TYPE)?;
+        let amf = self.active_amf;
+
+        let (ue_ids, acknowledged) = match reset_type {
+            ResetType::NgInterface => {
+                let ue_ids: Vec<u32> = self.ue_contexts.values()
+                    .filter(|ctx| ctx.amf == Some(amf))
+                    .map(|ctx| ctx.ran_ue_ngap_id)
+                    .collect();
+                (ue_ids, None)
+            }
+            ResetType::PartOfNgInterface(connections) => {
+                // Acknowledge each listed connection in the received order, with
+                // the IDs known for it
+                let mut ue_ids = Vec::new();
+                let acknowledged: Vec<_> = connections.iter()
+                    .map(|connection| match self.reset_connection_ue(amf, connection) {
+                        Some(ran_ue_ngap_id) => {
+                            ue_ids.push(ran_ue_ngap_id);
+                            UeAssociatedNgConnection {
+                                amf_ue_ngap_id: self.ue_contexts[&ran_ue_ngap_id].amf_ue_ngap_id
+                                    .or(connection.amf_ue_ngap_id),
+                                ran_ue_ngap_id: Some(ran_ue_ngap_id),
+                            }
+                        }
+                        None => {
+                            debug!("NG Reset for unknown UE-associated NG connection {:?}", connection);
+                            *connection
+                        }
+                    })
+                    .collect();
+                (ue_ids, Some(acknowledged))
+            }
+        };
+
+        info!("NG Reset from AMF {} for {} UEs: {:?}", amf, ue_ids.len(), cause);
+        self.release_ues_locally(ue_ids).await?;
+
+        let pdu = Self::build_ng_reset_acknowledge(acknowledged.as_deref())?;
+        self.send_pdu(pdu).await
+    }
+
+    /// Build NG Reset Acknowledge, listing the connections of a partial reset
+    pub(super) fn build_ng_reset_acknowledge(connections: Option<&[UeAssociatedNgConnection]>) -> Result<Vec<u8>, LayerError> {
+        let mut pdu = NgapPdu::successful(NgapProcedureCode::NgReset);
+        if let Some(connections) = connections.filter(|connections| !connections.is_empty()) {
+            pdu.add_ie(pdu::ID_UE_ASSOCIATED_LOGICAL_NG_CONNECTION_LIST, Criticality::Ignore, &connections.to_vec())?;
+        }
+        Ok(pdu.encode()?.to_vec())
+    }
+
+    /// Handle NG Reset Acknowledge for an NG Reset sent by the gNB
+    pub(super) fn handle_ng_reset_acknowledge(&self, pdu: &NgapPdu) -> Result<(), LayerError> {
+        let connections = pdu.optional_ie::<Vec<UeAssociatedNgConnection>>(pdu::ID_UE_ASSOCIATED_LOGICAL_NG_CONNECTION_LIST)?;
+        match connections {
+            Some(connections) => info!("NG Reset acknowledged by AMF {} for {} connections",
+                                       self.active_amf, connections.len()),
+            None => info!("NG Reset acknowledged by AMF {}", self.active_amf),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::PagingDrx;
+    use crate::ngap::{NgapConfig, NgapUeContext};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_ng_reset() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x99, 0xF9, 0x07],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        for (ran_ue_ngap_id, amf_ue_ngap_id) in [(1, 11), (2, 12), (3, 13)] {
+            ngap.ue_contexts.insert(ran_ue_ngap_id, NgapUeContext {
+                ran_ue_ngap_id,
+                amf_ue_ngap_id: Some(amf_ue_ngap_id),
+                amf: Some(0),
+                ..Default::default()
+            });
+        }
+
+        // Partial reset: one UE by AMF UE NGAP ID, one unknown
+        let reset_type = ResetType::PartOfNgInterface(vec![
+            UeAssociatedNgConnection { amf_ue_ngap_id: Some(12), ran_ue_ngap_id: None },
+            UeAssociatedNgConnection { amf_ue_ngap_id: Some(99), ran_ue_ngap_id: Some(9) },
+        ]);
+        let reset = NgapPdu::decode(&NgapLayer::build_ng_reset(Cause::MISC_UNSPECIFIED, &reset_type).unwrap()).unwrap();
+        assert_eq!(reset.ie::<ResetType>(pdu::ID_RESET_TYPE).unwrap(), reset_type);
+        // No NG connection for the acknowledge, but the UE is released
+        assert!(ngap.handle_ng_reset(&reset).await.is_err());
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 2 }));
+        assert!(rrc_rx.try_recv().is_err());
+        assert_eq!(ngap.ue_contexts.len(), 2);
+
+        let acknowledge = NgapPdu::decode(&NgapLayer::build_ng_reset_acknowledge(Some(&[
+            UeAssociatedNgConnection { amf_ue_ngap_id: Some(12), ran_ue_ngap_id: Some(2) },
+        ])).unwrap()).unwrap();
+        assert_eq!(acknowledge.procedure(), Some(NgapProcedureCode::NgReset));
+        let connections = acknowledge.ie::<Vec<UeAssociatedNgConnection>>(pdu::ID_UE_ASSOCIATED_LOGICAL_NG_CONNECTION_LIST).unwrap();
+        assert_eq!(connections[0].ran_ue_ngap_id, Some(2));
+        ngap.handle_ng_reset_acknowledge(&acknowledge).unwrap();
+
+        // Mismatching IDs are not reset
+        let mismatch = UeAssociatedNgConnection { amf_ue_ngap_id: Some(12), ran_ue_ngap_id: Some(1) };
+        assert_eq!(ngap.reset_connection_ue(0, &mismatch), None);
+
+        // Full reset of the interface releases the other UEs
+        let reset = NgapPdu::decode(&NgapLayer::build_ng_reset(Cause::MISC_UNSPECIFIED, &ResetType::NgInterface).unwrap()).unwrap();
+        assert!(ngap.handle_ng_reset(&reset).await.is_err());
+        assert!(ngap.ue_contexts.is_empty());
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 1 }));
+        assert!(matches!(rrc_rx.try_recv().unwrap(), NgapRrcMessage::UeContextRelease { ue_id: 3 }));
+
+        // A gNB-initiated reset needs a UE of the AMF
+        assert!(ngap.send_ng_reset(0, Cause::MISC_UNSPECIFIED, Some(&[1])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ng_reset_errors() {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into(),
+                       SocketAddr::from_str("127.0.0.2:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: [0x99, 0xF9, 0x07],
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: Vec::new(),
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        });
+        let (rrc_tx, mut rrc_rx) = mpsc::channel(10);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.ue_contexts.insert(1, NgapUeContext { ran_ue_ngap_id: 1, amf_ue_ngap_id: Some(11), amf: Some(1), ..Default::default() });
+
+        // Missing Reset Type or Cause
+        let reset = NgapPdu::initiating(NgapProcedureCode::NgReset)
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNSPECIFIED).unwrap();
+        assert!(matches!(ngap.handle_ng_reset(&reset).await, Err(LayerError::ProcessingError(_))));
+        let reset = NgapPdu::initiating(NgapProcedureCode::NgReset)
+            .with_ie(pdu::ID_RESET_TYPE, Criticality::Reject, &ResetType::NgInterface).unwrap();
+        assert!(matches!(ngap.handle_ng_reset(&reset).await, Err(LayerError::ProcessingError(_))));
+
+        // A reset from AMF 0 leaves the UEs of AMF 1 alone, whichever IDs it lists
+        ngap.active_amf = 0;
+        let reset = NgapPdu::decode(&NgapLayer::build_ng_reset(Cause::MISC_UNSPECIFIED, &ResetType::NgInterface).unwrap()).unwrap();
+        assert!(ngap.handle_ng_reset(&reset).await.is_err());
+        let connection = UeAssociatedNgConnection { amf_ue_ngap_id: Some(11), ran_ue_ngap_id: Some(1) };
+        let reset = NgapPdu::decode(&NgapLayer::build_ng_reset(
+            Cause::MISC_UNSPECIFIED, &ResetType::PartOfNgInterface(vec![connection])).unwrap()).unwrap();
+        assert!(ngap.handle_ng_reset(&reset).await.is_err());
+        assert_eq!(ngap.reset_connection_ue(0, &connection), None);
+        assert!(ngap.ue_contexts.contains_key(&1));
+        assert!(rrc_rx.try_recv().is_err());
+
+        // A gNB-initiated partial reset only lists UEs of that AMF
+        assert!(matches!(ngap.send_ng_reset(0, Cause::MISC_UNSPECIFIED, Some(&[1, 2])).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(ngap.ue_contexts.contains_key(&1));
+
+        // Acknowledge with a list that does not decode
+        let mut acknowledge = NgapPdu::decode(&NgapLayer::build_ng_reset_acknowledge(Some(&[connection])).unwrap()).unwrap();
+        acknowledge.ies[0].value = bytes::Bytes::new();
+        assert!(ngap.handle_ng_reset_acknowledge(&acknowledge).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+//! NG Setup procedure
+//!
+//! Implements NG Setup according to 3GPP TS 38.413 section 8.7.1, including
+//! NG Setup Failure handling with Time to Wait and the consistency check of the
+//! AMF served GUAMIs and slices against the configured tracking areas
+
+use super::pdu::{
+    self, Cause, Criticality, GlobalGnbId, NgapPdu, NgapPduType, NodeName, PlmnSupportItem,
+    RelativeAmfCapacity, ServedGuamiItem, TimeToWait,
+};
+use super::transport::{NgTransportEvent, NON_UE_STREAM};
+use super::{NgapLayer, NgapProcedureCode};
+use crate::LayerError;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Number of NG Setup attempts before giving up
+pub const NG_SETUP_MAX_ATTEMPTS: u32 = 8;
+/// Initial NG Setup retry backoff in seconds
+const NG_SETUP_INITIAL_BACKOFF_S: u64 = 1;
+/// Upper bound of the NG Setup retry backoff in seconds
+const NG_SETUP_MAX_BACKOFF_S: u64 = 60;
+/// Time to wait for the NG Setup Response or Failure in seconds
+const NG_SETUP_RESPONSE_TIMEOUT_S: u64 = 10;
+
+/// AMF information received in NG Setup Response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmfInfo {
+    /// AMF name
+    pub amf_name: String,
+    /// GUAMIs served by the AMF
+    pub served_guamis: Vec<ServedGuamiItem>,
+    /// Relative capacity of the AMF (0..255)
+    pub relative_capacity: u8,
+    /// PLMNs and slices supported by the AMF
+    pub plmn_support: Vec<PlmnSupportItem>,
+}
+
+/// Result of an NG Setup attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NgSetupOutcome {
+    /// NG Setup Response received
+    Accepted(AmfInfo),
+    /// NG Setup Failure received
+    Rejected {
+        cause: Cause,
+        time_to_wait: Option<TimeToWait>,
+    },
+}
+
+/// Decode an NG Setup Response or NG Setup Failure
+pub fn parse_ng_setup_outcome(pdu: &NgapPdu) -> Result<NgSetupOutcome, LayerError> {
+    if pdu.procedure() != Some(NgapProcedureCode::NgSetup) {
+        return Err(LayerError::ProcessingError(format!("Unexpected procedure {} during NG Setup", pdu.procedure_code)));
+    }
+    
+    match pdu.pdu_type {
+        NgapPduType::SuccessfulOutcome => Ok(NgSetupOutcome::Accepted(AmfInfo {
+            amf_name: pdu.ie::<NodeName>(pdu::ID_AMF_NAME)?.0,
+            served_guamis: pdu.ie(pdu::ID_SERVED_GUAMI_LIST)?,
+            relative_capacity: pdu.ie::<RelativeAmfCapacity>(pdu::ID_RELATIVE_AMF_CAPACITY)?.0,
+            plmn_support: pdu.ie(pdu::ID_PLMN_SUPPORT_LIST)?,
+        })),
+        NgapPduType::UnsuccessfulOutcome => Ok(NgSetupOutcome::Rejected {
+            cause: pdu.ie(pdu::ID_CAUSE)?,
+            time_to_wait: pdu.optional_ie(pdu::ID_TIME_TO_WAIT)?,
+        }),
+        NgapPduType::InitiatingMessage => Err(LayerError::ProcessingError("NG Setup Request received from AMF".to_string())),
+    }
+}
+
+/// Format a PLMN identity as MCC-MNC
+pub fn plmn_to_string(plmn_id: &[u8; 3]) -> String {
+    let mcc = format!("{}{}{}", plmn_id[0] & 0x0F, plmn_id[0] >> 4, plmn_id[1] & 0x0F);
+    let mnc = if plmn_id[1] >> 4 == 0x0F {
+        format!("{}{}", plmn_id[2] & 0x0F, plmn_id[2] >> 4)
+    } else {
+        format!("{}{}{}", plmn_id[2] & 0x0F, plmn_id[2] >> 4, plmn_id[1] >> 4)
+    };
+    format!("{}-{}", mcc, mnc)
+}
+
+impl NgapLayer {
+    /// Run NG Setup with an AMF until it accepts it
+    ///
+    /// An NG Setup Failure carrying Time to Wait, or a missing response, is retried
+    /// after the larger of Time to Wait and an exponential backoff. A failure
+    /// without Time to Wait is final.
+    pub(super) async fn perform_ng_setup(&mut self, amf: usize) -> Result<(), LayerError> {
+        let mut backoff = Duration::from_secs(NG_SETUP_INITIAL_BACKOFF_S);
+        
+        for attempt in 1..=NG_SETUP_MAX_ATTEMPTS {
+            let wait = match self.ng_setup_attempt(amf).await {
+                Ok(NgSetupOutcome::Accepted(amf_info)) => {
+                    self.store_amf_info(amf, amf_info);
+                    return Ok(());
+                }
+                Ok(NgSetupOutcome::Rejected { cause, time_to_wait: Some(time_to_wait) }) => {
+                    warn!("NG Setup Failure (cause {:?}), AMF asks to wait {}s", cause, time_to_wait.seconds());
+                    backoff.max(Duration::from_secs(time_to_wait.seconds()))
+                }
+                Ok(NgSetupOutcome::Rejected { cause, time_to_wait: None }) => {
+                    error!("NG Setup Failure (cause {:?})", cause);
+                    return Err(LayerError::ProcessingError(format!("NG Setup rejected by AMF: {:?}", cause)));
+                }
+                Err(e) if self.amfs[amf].transport.is_none() => return Err(e),
+                Err(e) => {
+                    warn!("No NG Setup outcome: {}", e);
+                    backoff
+                }
+            };
+            
+            if attempt < NG_SETUP_MAX_ATTEMPTS {
+                info!("Retrying NG Setup in {}s (attempt {}/{})", wait.as_secs(), attempt + 1, NG_SETUP_MAX_ATTEMPTS);
+                tokio::time::sleep(wait).await;
+            }
+            backoff = (backoff * 2).min(Duration::from_secs(NG_SETUP_MAX_BACKOFF_S));
+        }
+        
+        Err(LayerError::ProcessingError(format!("NG Setup failed after {} attempts", NG_SETUP_MAX_ATTEMPTS)))
+    }
+    
+    /// Send NG Setup Request to an AMF and wait for its outcome
+    pub(super) async fn ng_setup_attempt(&mut self, amf: usize) -> Result<NgSetupOutcome, LayerError> {
+        self.send_ng_setup_request(amf).await?;
+        let response = self.wait_for_ng_setup_response(amf).await?;
+        parse_ng_setup_outcome(&response)
+    }
+    
+    /// Store the AMF information from NG Setup Response
+    pub(super) fn store_amf_info(&mut self, amf: usize, amf_info: AmfInfo) {
+        info!("NG Setup accepted by AMF {} (relative capacity {}, {} served GUAMIs, {} PLMNs)",
+              amf_info.amf_name, amf_info.relative_capacity,
+              amf_info.served_guamis.len(), amf_info.plmn_support.len());
+        
+        // With several AMFs, each one may serve only part of the slices
+        for mismatch in self.check_amf_configuration(&amf_info) {
+            if self.amfs.len() > 1 {
+                info!("AMF {}: {}", amf_info.amf_name, mismatch);
+            } else {
+                error!("Configuration error: {}", mismatch);
+            }
+        }
+        
+        let connection = &mut self.amfs[amf];
+        connection.info = Some(amf_info);
+        connection.unavailable_guamis.clear();
+    }
+    
+    /// Compare the configured tracking areas with what the AMF serves
+    ///
+    /// Returns one description per PLMN or slice the AMF does not support.
+    pub fn check_amf_configuration(&self, amf_info: &AmfInfo) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        
+        for ta in &self.config.supported_tas {
+            for broadcast_plmn in &ta.broadcast_plmns {
+                let plmn = plmn_to_string(&broadcast_plmn.plmn_id);
+                
+                if !amf_info.served_guamis.iter().any(|item| item.guami.plmn_id == broadcast_plmn.plmn_id) {
+                    mismatches.push(format!("PLMN {} of TAC {} is not in the AMF served GUAMI list", plmn, ta.tac));
+                }
+                
+                let Some(support) = amf_info.plmn_support.iter().find(|item| item.plmn_id == broadcast_plmn.plmn_id) else {
+                    mismatches.push(format!("PLMN {} of TAC {} is not in the AMF PLMN support list", plmn, ta.tac));
+                    continue;
+                };
+                
+                for slice in &broadcast_plmn.slices {
+                    if !support.slices.contains(slice) {
+                        mismatches.push(format!("Slice SST {} SD {:?} of PLMN {} (TAC {}) is not supported by the AMF",
+                                                slice.sst, slice.sd, plmn, ta.tac));
+                    }
+                }
+            }
+        }
+        
+        mismatches
+    }
+    
+    /// AMF information from the last successful NG Setup with an AMF
+    pub fn amf_info(&self, amf: usize) -> Option<&AmfInfo> {
+        self.amfs.get(amf).and_then(|connection| connection.info.as_ref())
+    }
+    
+    /// Send NG Setup Request message
+    async fn send_ng_setup_request(&mut self, amf: usize) -> Result<(), LayerError> {
+        info!("Sending NG Setup Request");
+        
+        let ng_setup_request = self.build_ng_setup_request()?;
+        let len = ng_setup_request.len();
+        
+        // NG Setup is non UE-associated signalling, sent before the NG connection is up
+        let transport = self.amfs[amf].transport.as_ref()
+            .ok_or_else(|| LayerError::InvalidState("NG transport not established".to_string()))?;
+        transport.send(NON_UE_STREAM, ng_setup_request).await?;
+        
+        info!("NG Setup Request sent successfully ({} bytes)", len);
+        Ok(())
+    }
+    
+    /// Build NG Setup Request message (3GPP TS 38.413 section 9.2.6.1)
+    pub(super) fn build_ng_setup_request(&self) -> Result<Vec<u8>, LayerError> {
+        if self.config.supported_tas.is_empty() {
+            return Err(LayerError::ConfigurationError("NG Setup requires at least one supported TA".to_string()));
+        }
+        
+        let global_ran_node_id = GlobalGnbId {
+            plmn_id: self.config.plmn_id,
+            gnb_id: self.config.gnb_id,
+            gnb_id_bits: self.config.gnb_id_bits,
+        };
+        
+        let pdu = NgapPdu::initiating(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_GLOBAL_RAN_NODE_ID, Criticality::Reject, &global_ran_node_id)?
+            .with_ie(pdu::ID_RAN_NODE_NAME, Criticality::Ignore, &NodeName(self.config.ran_node_name.clone()))?
+            .with_ie(pdu::ID_SUPPORTED_TA_LIST, Criticality::Reject, &self.config.supported_tas)?
+            .with_ie(pdu::ID_DEFAULT_PAGING_DRX, Criticality::Ignore, &self.config.default_paging_drx)?;
+        
+        Ok(pdu.encode()?.to_vec())
+    }
+    
+    /// Wait for the NG Setup Response or Failure
+    ///
+    /// Losing the association drops the transport.
+    async fn wait_for_ng_setup_response(&mut self, amf: usize) -> Result<NgapPdu, LayerError> {
+        info!("Waiting for NG Setup Response");
+        
+        let transport = self.amfs[amf].transport.as_mut()
+            .ok_or_else(|| LayerError::InvalidState("NG transport not established".to_string()))?;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(NG_SETUP_RESPONSE_TIMEOUT_S);
+        
+        loop {
+            let event = match tokio::time::timeout_at(deadline, transport.recv()).await {
+                Ok(event) => event?,
+                Err(_) => {
+                    error!("NG Setup Response timeout after {} seconds", NG_SETUP_RESPONSE_TIMEOUT_S);
+                    return Err(LayerError::ProcessingError("NG Setup Response timeout".to_string()));
+                }
+            };
+            
+            let payload = match event {
+                NgTransportEvent::Data { payload, .. } => payload,
+                NgTransportEvent::AssociationLost(reason) => {
+                    self.amfs[amf].transport = None;
+                    return Err(LayerError::ProcessingError(format!("NG association lost during NG Setup: {}", reason)));
+                }
+            };
+            debug!("Received NGAP message: {} bytes", payload.len());
+            
+            let pdu = match NgapPdu::decode(&payload) {
+                Ok(pdu) => pdu,
+                Err(e) => {
+                    warn!("Failed to decode NGAP PDU: {}", e);
+                    continue;
+                }
+            };
+            
+            if pdu.procedure() == Some(NgapProcedureCode::NgSetup) && pdu.pdu_type != NgapPduType::InitiatingMessage {
+                return Ok(pdu);
+            }
+            
+            // Continue reading if this wasn't our expected message
+            warn!("Received unexpected NGAP message (procedure {}), continuing to wait", pdu.procedure_code);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{BroadcastPlmnItem, Guami, PagingDrx, SupportedTaItem};
+    use crate::ngap::NgapConfig;
+    use common::types::SNssai;
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+    
+    const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+    
+    fn test_layer() -> NgapLayer {
+        NgapLayer::new(NgapConfig {
+            amfs: vec![SocketAddr::from_str("127.0.0.1:38412").unwrap().into()],
+            local_address: SocketAddr::from_str("0.0.0.0:38412").unwrap(),
+            gnb_id: 1,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: "Albor-gNodeB".to_string(),
+            supported_tas: vec![SupportedTaItem {
+                tac: 1,
+                broadcast_plmns: vec![BroadcastPlmnItem {
+                    plmn_id: PLMN,
+                    slices: vec![SNssai { sst: 1, sd: None }, SNssai { sst: 2, sd: Some(0x000001) }],
+                }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: 0x000400001,
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, 1]),
+            transport: Default::default(),
+            handover_targets: Vec::new(),
+        })
+    }
+    
+    #[test]
+    fn test_ng_setup_response() {
+        let served_guamis = vec![ServedGuamiItem {
+            guami: Guami { plmn_id: PLMN, amf_region_id: 2, amf_set_id: 0x3FF, amf_pointer: 0x21 },
+            backup_amf_name: None,
+        }];
+        let plmn_support = vec![PlmnSupportItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }];
+        let response = NgapPdu::successful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_AMF_NAME, Criticality::Reject, &NodeName("open5gs-amf0".to_string())).unwrap()
+            .with_ie(pdu::ID_SERVED_GUAMI_LIST, Criticality::Reject, &served_guamis).unwrap()
+            .with_ie(pdu::ID_RELATIVE_AMF_CAPACITY, Criticality::Ignore, &RelativeAmfCapacity(255)).unwrap()
+            .with_ie(pdu::ID_PLMN_SUPPORT_LIST, Criticality::Reject, &plmn_support).unwrap();
+        let response = NgapPdu::decode(&response.encode().unwrap()).unwrap();
+        
+        let NgSetupOutcome::Accepted(amf_info) = parse_ng_setup_outcome(&response).unwrap() else {
+            panic!("NG Setup Response not accepted");
+        };
+        assert_eq!(amf_info.amf_name, "open5gs-amf0");
+        assert_eq!(amf_info.served_guamis, served_guamis);
+        assert_eq!(amf_info.relative_capacity, 255);
+        assert_eq!(amf_info.plmn_support, plmn_support);
+        
+        // The configured SST 2 slice is not offered by the AMF
+        let mut ngap = test_layer();
+        let mismatches = ngap.check_amf_configuration(&amf_info);
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].contains("SST 2"));
+        assert!(mismatches[0].contains("999-70"));
+        
+        ngap.store_amf_info(0, amf_info);
+        assert_eq!(ngap.amf_info(0).unwrap().amf_name, "open5gs-amf0");
+    }
+    
+    #[test]
+    fn test_ng_setup_failure() {
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNKNOWN_PLMN).unwrap()
+            .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V10s).unwrap();
+        let failure = NgapPdu::decode(&failure.encode().unwrap()).unwrap();
+        
+        assert_eq!(parse_ng_setup_outcome(&failure).unwrap(), NgSetupOutcome::Rejected {
+            cause: Cause::MISC_UNKNOWN_PLMN,
+            time_to_wait: Some(TimeToWait::V10s),
+        });
+    }
+    
+    #[test]
+    fn test_ng_setup_outcome_errors() {
+        // Wrong procedure and NG Setup Request from the AMF
+        let reset = NgapPdu::successful(NgapProcedureCode::NgReset);
+        assert!(matches!(parse_ng_setup_outcome(&reset), Err(LayerError::ProcessingError(_))));
+        let request = NgapPdu::initiating(NgapProcedureCode::NgSetup);
+        assert!(matches!(parse_ng_setup_outcome(&request), Err(LayerError::ProcessingError(_))));
+        
+        // Missing mandatory IEs of the response and the failure
+        let response = NgapPdu::successful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_AMF_NAME, Criticality::Reject, &NodeName("amf".to_string())).unwrap();
+        assert!(matches!(parse_ng_setup_outcome(&response), Err(LayerError::ProcessingError(_))));
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V1s).unwrap();
+        assert!(matches!(parse_ng_setup_outcome(&failure), Err(LayerError::ProcessingError(_))));
+        
+        // Unknown IEs are ignored, the optional Time To Wait may be absent
+        let failure = NgapPdu::unsuccessful(NgapProcedureCode::NgSetup)
+            .with_ie(0xFFF0, Criticality::Ignore, &RelativeAmfCapacity(1)).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::MISC_UNKNOWN_PLMN).unwrap();
+        let failure = NgapPdu::decode(&failure.encode().unwrap()).unwrap();
+        assert_eq!(parse_ng_setup_outcome(&failure).unwrap(), NgSetupOutcome::Rejected {
+            cause: Cause::MISC_UNKNOWN_PLMN,
+            time_to_wait: None,
+        });
+    }
+}
@@ -0,0 +1,707 @@
+//! NG transport layer
+//!
+//! Carries NGAP over an SCTP association as specified by 3GPP TS 38.412: payload
+//! protocol identifier 60, stream 0 for non UE-associated signalling and the
+//! remaining streams for UE-associated signalling. Association loss reported by
+//! the SCTP stack (heartbeat failure, ABORT, SHUTDOWN) is passed up as an event.
+//!
+//! Where kernel SCTP is not available the same messages can be carried over TCP
+//! in length-prefixed frames that keep the SCTP stream and PPID, towards an AMF
+//! or relay speaking that framing.
+//!
+//! The other application protocols of the gNB (F1AP, E1AP, XnAP, E2AP) run
+//! over the same kind of association, with their own PPID and number of
+//! streams, through [`ApSender`], [`ApReceiver`] and [`ApListener`].
+
+use super::pdu::{ApPdu, ApProcedureCode};
+use crate::LayerError;
+use bytes::Bytes;
+use sctp_rs::{
+    AssocChangeState, BindxFlags, ConnectedSocket, Event, Listener, Notification, NotificationOrData, SendData,
+    SendInfo, Socket, SocketToAssociation, SubscribeEventAssocId,
+};
+use std::io;
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// SCTP payload protocol identifier of NGAP (TS 38.412 section 7)
+pub const NGAP_PPID: u32 = 60;
+/// SCTP stream reserved for non UE-associated signalling (TS 38.412 section 7)
+pub const NON_UE_STREAM: u16 = 0;
+/// Length of the TCP frame header: payload length, stream and PPID
+pub const FRAME_HEADER_LEN: usize = 10;
+/// Largest payload accepted in a TCP frame
+const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// Transport used for the NG-C interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NgTransportKind {
+    /// Kernel SCTP
+    Sctp,
+    /// Length-prefixed frames over TCP, for environments without kernel SCTP
+    TcpFramed,
+}
+
+/// NG transport configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NgTransportConfig {
+    /// Transport to use
+    pub kind: NgTransportKind,
+    /// Number of SCTP streams requested in each direction
+    pub num_streams: u16,
+    /// Additional local addresses for SCTP multi-homing
+    pub extra_local_addresses: Vec<IpAddr>,
+    /// Time allowed for the association to come up
+    pub connect_timeout: Duration,
+    /// Delay between attempts to re-establish a lost association
+    pub reconnect_interval: Duration,
+}
+
+impl Default for NgTransportConfig {
+    fn default() -> Self {
+        Self {
+            kind: NgTransportKind::Sctp,
+            num_streams: 4,
+            extra_local_addresses: Vec::new(),
+            connect_timeout: Duration::from_secs(5),
+            reconnect_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Event received on an association
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    /// PDU received on a stream
+    Data { stream: u16, payload: Bytes },
+    /// The association is gone and has to be re-established
+    AssociationLost(String),
+}
+
+/// Event received on the NG transport
+pub type NgTransportEvent = TransportEvent;
+
+/// Sending side of the connection
+enum Writer {
+    Sctp(Arc<ConnectedSocket>),
+    Tcp(Mutex<OwnedWriteHalf>),
+}
+
+impl Writer {
+    /// Send a PDU on a stream
+    async fn send(&self, stream: u16, ppid: u32, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Writer::Sctp(socket) => {
+                let snd_info = SendInfo {
+                    sid: stream,
+                    // sctp-rs passes the PPID to the kernel as is, it has to be in network order
+                    ppid: ppid.to_be(),
+                    ..Default::default()
+                };
+                socket.sctp_send(SendData { payload: payload.to_vec(), snd_info: Some(snd_info) }).await
+            }
+            Writer::Tcp(writer) => writer.lock().await.write_all(&encode_frame(stream, ppid, payload)).await,
+        }
+    }
+
+    /// Shut an SCTP association down, a TCP connection closes with its halves
+    fn shutdown(&self) {
+        if let Writer::Sctp(socket) = self {
+            let _ = socket.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+/// Receiving side of the connection
+enum Reader {
+    Sctp(Arc<ConnectedSocket>),
+    Tcp(OwnedReadHalf),
+}
+
+impl Reader {
+    /// Receive the next PDU with the given PPID or the loss of the association
+    async fn recv(&mut self, ppid: u32) -> TransportEvent {
+        match self {
+            Reader::Sctp(socket) => loop {
+                match socket.sctp_recv().await {
+                    Ok(NotificationOrData::Data(data)) => {
+                        if data.payload.is_empty() {
+                            return TransportEvent::AssociationLost("association closed by the peer".to_string());
+                        }
+                        let (stream, data_ppid) = data.rcv_info
+                            .map_or((NON_UE_STREAM, ppid), |info| (info.sid, u32::from_be(info.ppid)));
+                        if data_ppid != ppid {
+                            warn!("Dropping SCTP data with PPID {} on stream {}", data_ppid, stream);
+                            continue;
+                        }
+                        return TransportEvent::Data { stream, payload: Bytes::from(data.payload) };
+                    }
+                    Ok(NotificationOrData::Notification(Notification::AssociationChange(change))) => {
+                        match change.state {
+                            AssocChangeState::CommUp => debug!("SCTP association up"),
+                            // The peer restarted, its application protocol state is gone as well
+                            AssocChangeState::Restart => {
+                                return TransportEvent::AssociationLost("peer restarted the association".to_string());
+                            }
+                            // Heartbeat or retransmission failure, or ABORT from the peer
+                            state => return TransportEvent::AssociationLost(format!("SCTP association {:?}", state)),
+                        }
+                    }
+                    Ok(NotificationOrData::Notification(Notification::Shutdown(_))) => {
+                        return TransportEvent::AssociationLost("SCTP shutdown by the peer".to_string());
+                    }
+                    Ok(NotificationOrData::Notification(notification)) => {
+                        debug!("Ignoring SCTP notification {:?}", notification);
+                    }
+                    Err(e) => return TransportEvent::AssociationLost(format!("SCTP receive error: {}", e)),
+                }
+            },
+            Reader::Tcp(stream) => loop {
+                match read_frame(stream).await {
+                    Ok(Some((sid, frame_ppid, payload))) if frame_ppid == ppid => {
+                        return TransportEvent::Data { stream: sid, payload };
+                    }
+                    Ok(Some((sid, frame_ppid, _))) => warn!("Dropping frame with PPID {} on stream {}", frame_ppid, sid),
+                    Ok(None) => return TransportEvent::AssociationLost("connection closed by the peer".to_string()),
+                    Err(e) => return TransportEvent::AssociationLost(format!("TCP receive error: {}", e)),
+                }
+            },
+        }
+    }
+}
+
+/// Connection to the AMF
+pub struct NgTransport {
+    kind: NgTransportKind,
+    writer: Writer,
+    /// Receiving side until the reader task is started
+    reader: Option<Reader>,
+    reader_task: Option<JoinHandle<()>>,
+    /// Number of outbound streams negotiated with the AMF
+    num_streams: u16,
+}
+
+impl NgTransport {
+    /// Connect to an AMF, multi-homed on its extra addresses with SCTP
+    pub async fn connect(
+        config: &NgTransportConfig,
+        local_address: SocketAddr,
+        amf_address: SocketAddr,
+        extra_amf_addresses: &[IpAddr],
+    ) -> Result<Self, LayerError> {
+        let connect = async {
+            match config.kind {
+                NgTransportKind::Sctp => Self::connect_sctp(config, local_address, amf_address, extra_amf_addresses).await,
+                NgTransportKind::TcpFramed => Self::connect_tcp(local_address, amf_address).await,
+            }
+        };
+        let transport = tokio::time::timeout(config.connect_timeout, connect).await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))
+            .and_then(|result| result)
+            .map_err(|e| LayerError::InitializationFailed(format!("Failed to connect to AMF at {}: {}", amf_address, e)))?;
+
+        info!("{:?} connection established with AMF at {} ({} streams)",
+              config.kind, amf_address, transport.num_streams);
+        Ok(transport)
+    }
+
+    async fn connect_sctp(
+        config: &NgTransportConfig,
+        local_address: SocketAddr,
+        amf_address: SocketAddr,
+        extra_amf_addresses: &[IpAddr],
+    ) -> io::Result<Self> {
+        let socket = match amf_address {
+            SocketAddr::V4(_) => Socket::new_v4(SocketToAssociation::OneToOne)?,
+            SocketAddr::V6(_) => Socket::new_v6(SocketToAssociation::OneToOne)?,
+        };
+        let num_streams = config.num_streams.max(1);
+        socket.sctp_setup_init_params(num_streams, num_streams, 0, 0)?;
+        socket.sctp_subscribe_events(&[Event::Association, Event::Shutdown], SubscribeEventAssocId::Current)?;
+
+        if local_address.is_ipv4() == amf_address.is_ipv4() {
+            socket.bind(local_address)?;
+            let extra_local: Vec<_> = config.extra_local_addresses.iter()
+                .map(|ip| SocketAddr::new(*ip, local_address.port()))
+                .collect();
+            if !extra_local.is_empty() {
+                socket.sctp_bindx(&extra_local, BindxFlags::Add)?;
+            }
+        }
+
+        let amf_addresses: Vec<_> = std::iter::once(amf_address)
+            .chain(extra_amf_addresses.iter().map(|ip| SocketAddr::new(*ip, amf_address.port())))
+            .collect();
+        // The connect future of sctp-rs is not Send, drive it from a blocking thread
+        let runtime = tokio::runtime::Handle::current();
+        let (socket, assoc_id) = tokio::task::spawn_blocking(move || runtime.block_on(socket.sctp_connectx(&amf_addresses)))
+            .await
+            .map_err(io::Error::other)??;
+        socket.sctp_request_rcvinfo(true)?;
+
+        // The AMF may accept fewer streams than requested
+        let num_streams = match socket.sctp_get_status(assoc_id) {
+            Ok(status) => status.outstreams.clamp(1, num_streams),
+            Err(e) => {
+                warn!("Failed to read SCTP association status: {}", e);
+                num_streams
+            }
+        };
+
+        let socket = Arc::new(socket);
+        Ok(Self {
+            kind: NgTransportKind::Sctp,
+            writer: Writer::Sctp(Arc::clone(&socket)),
+            reader: Some(Reader::Sctp(socket)),
+            reader_task: None,
+            num_streams,
+        })
+    }
+
+    async fn connect_tcp(local_address: SocketAddr, amf_address: SocketAddr) -> io::Result<Self> {
+        let (writer, reader) = connect_tcp(local_address, amf_address).await?;
+        Ok(Self {
+            kind: NgTransportKind::TcpFramed,
+            writer,
+            reader: Some(reader),
+            reader_task: None,
+            // Stream IDs are carried in the frames, any number can be used
+            num_streams: u16::MAX,
+        })
+    }
+
+    /// Transport in use
+    pub fn kind(&self) -> NgTransportKind {
+        self.kind
+    }
+
+    /// Number of outbound streams
+    pub fn num_streams(&self) -> u16 {
+        self.num_streams
+    }
+
+    /// Stream carrying the UE-associated signalling of a UE
+    pub fn ue_stream(&self, ran_ue_ngap_id: u32) -> u16 {
+        match self.num_streams {
+            0 | 1 => NON_UE_STREAM,
+            n => 1 + (ran_ue_ngap_id % (n as u32 - 1)) as u16,
+        }
+    }
+
+    /// Send an NGAP PDU on a stream
+    pub async fn send(&self, stream: u16, payload: Vec<u8>) -> Result<(), LayerError> {
+        self.writer.send(stream, NGAP_PPID, &payload).await
+            .map_err(|e| LayerError::ProcessingError(format!("Failed to send NGAP PDU: {}", e)))?;
+
+        debug!("Sent NGAP PDU ({} bytes) on stream {}", payload.len(), stream);
+        Ok(())
+    }
+
+    /// Receive the next event before the reader task is started
+    pub async fn recv(&mut self) -> Result<NgTransportEvent, LayerError> {
+        match &mut self.reader {
+            Some(reader) => Ok(reader.recv(NGAP_PPID).await),
+            None => Err(LayerError::InvalidState("NG transport events go to the reader task".to_string())),
+        }
+    }
+
+    /// Pass all further events to a channel, tagged with the AMF index
+    pub fn start_reader(&mut self, amf: usize, events: mpsc::Sender<(usize, NgTransportEvent)>) {
+        let Some(mut reader) = self.reader.take() else {
+            return;
+        };
+        self.reader_task = Some(tokio::spawn(async move {
+            loop {
+                let event = reader.recv(NGAP_PPID).await;
+                let lost = matches!(event, NgTransportEvent::AssociationLost(_));
+                if events.send((amf, event)).await.is_err() || lost {
+                    break;
+                }
+            }
+        }));
+    }
+}
+
+impl Drop for NgTransport {
+    fn drop(&mut self) {
+        if let Some(task) = self.reader_task.take() {
+            task.abort();
+        }
+        self.writer.shutdown();
+    }
+}
+
+/// SCTP usage of an application protocol other than NGAP
+pub trait SctpProtocol: ApProcedureCode + Send + Sync + 'static {
+    /// Name of the interface in logs and errors
+    const INTERFACE: &'static str;
+    /// SCTP payload protocol identifier
+    const PPID: u32;
+    /// Number of SCTP streams requested in each direction
+    const NUM_STREAMS: u16;
+}
+
+/// Sending side of an association carrying protocol `P`
+pub struct ApSender<P> {
+    writer: Writer,
+    peer: SocketAddr,
+    protocol: PhantomData<P>,
+}
+
+impl<P: SctpProtocol> ApSender<P> {
+    fn new(writer: Writer, peer: SocketAddr) -> Self {
+        Self { writer, peer, protocol: PhantomData }
+    }
+
+    /// Address of the peer
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Encode and send a PDU on a stream
+    pub async fn send(&self, stream: u16, pdu: &ApPdu<P>) -> Result<(), LayerError> {
+        let payload = pdu.encode()?;
+        self.writer.send(stream, P::PPID, &payload).await.map_err(|e| {
+            LayerError::ProcessingError(format!("Failed to send {} PDU to {}: {}", P::PROTOCOL, self.peer, e))
+        })?;
+
+        debug!("Sent {} {:?} ({} bytes) on stream {}", P::PROTOCOL, pdu.procedure(), payload.len(), stream);
+        Ok(())
+    }
+}
+
+impl<P> Drop for ApSender<P> {
+    fn drop(&mut self) {
+        self.writer.shutdown();
+    }
+}
+
+/// Receiving side of an association carrying protocol `P`
+pub struct ApReceiver<P> {
+    reader: Reader,
+    protocol: PhantomData<P>,
+}
+
+impl<P: SctpProtocol> ApReceiver<P> {
+    fn new(reader: Reader) -> Self {
+        Self { reader, protocol: PhantomData }
+    }
+
+    /// Receive the next PDU or the loss of the association
+    pub async fn recv(&mut self) -> TransportEvent {
+        self.reader.recv(P::PPID).await
+    }
+}
+
+/// Open an association carrying protocol `P` towards a peer
+pub async fn connect<P: SctpProtocol>(
+    kind: NgTransportKind,
+    local_address: SocketAddr,
+    peer_address: SocketAddr,
+) -> Result<(ApSender<P>, ApReceiver<P>), LayerError> {
+    let result = match kind {
+        NgTransportKind::Sctp => connect_sctp(local_address, peer_address, P::NUM_STREAMS).await,
+        NgTransportKind::TcpFramed => connect_tcp(local_address, peer_address).await,
+    };
+    let (writer, reader) = result.map_err(|e| {
+        LayerError::InitializationFailed(format!("Failed to connect {} to {}: {}", P::INTERFACE, peer_address, e))
+    })?;
+    info!("{:?} {} connection established with {}", kind, P::INTERFACE, peer_address);
+    Ok((ApSender::new(writer, peer_address), ApReceiver::new(reader)))
+}
+
+async fn connect_sctp(local_address: SocketAddr, peer_address: SocketAddr, num_streams: u16) -> io::Result<(Writer, Reader)> {
+    let socket = match peer_address {
+        SocketAddr::V4(_) => Socket::new_v4(SocketToAssociation::OneToOne)?,
+        SocketAddr::V6(_) => Socket::new_v6(SocketToAssociation::OneToOne)?,
+    };
+    socket.sctp_setup_init_params(num_streams, num_streams, 0, 0)?;
+    socket.sctp_subscribe_events(&[Event::Shutdown], SubscribeEventAssocId::Current)?;
+    if local_address.is_ipv4() == peer_address.is_ipv4() {
+        socket.bind(local_address)?;
+    }
+    // The connect future of sctp-rs is not Send, drive it from a blocking thread
+    let runtime = tokio::runtime::Handle::current();
+    let (socket, _) = tokio::task::spawn_blocking(move || runtime.block_on(socket.sctp_connectx(&[peer_address])))
+        .await
+        .map_err(io::Error::other)??;
+    socket.sctp_request_rcvinfo(true)?;
+
+    let socket = Arc::new(socket);
+    Ok((Writer::Sctp(Arc::clone(&socket)), Reader::Sctp(socket)))
+}
+
+async fn connect_tcp(local_address: SocketAddr, peer_address: SocketAddr) -> io::Result<(Writer, Reader)> {
+    let socket = match peer_address {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    if local_address.is_ipv4() == peer_address.is_ipv4() {
+        socket.bind(local_address)?;
+    }
+    let stream = socket.connect(peer_address).await?;
+    stream.set_nodelay(true)?;
+
+    let (reader, writer) = stream.into_split();
+    Ok((Writer::Tcp(Mutex::new(writer)), Reader::Tcp(reader)))
+}
+
+/// Listening socket of the side accepting associations
+enum ListenerSocket {
+    Sctp(Listener, SocketAddr),
+    Tcp(TcpListener),
+}
+
+/// Listening socket for associations carrying protocol `P`
+pub struct ApListener<P> {
+    socket: ListenerSocket,
+    protocol: PhantomData<P>,
+}
+
+impl<P: SctpProtocol> ApListener<P> {
+    /// Listen for associations
+    pub async fn bind(kind: NgTransportKind, address: SocketAddr) -> Result<Self, LayerError> {
+        let bind = async {
+            let socket = match kind {
+                NgTransportKind::Sctp => {
+                    let socket = match address {
+                        SocketAddr::V4(_) => Socket::new_v4(SocketToAssociation::OneToOne)?,
+                        SocketAddr::V6(_) => Socket::new_v6(SocketToAssociation::OneToOne)?,
+                    };
+                    socket.sctp_setup_init_params(P::NUM_STREAMS, P::NUM_STREAMS, 0, 0)?;
+                    socket.bind(address)?;
+                    let listener = socket.listen(8)?;
+                    let local_address = listener.sctp_getladdrs(0).ok()
+                        .and_then(|addresses| addresses.first().copied())
+                        .unwrap_or(address);
+                    ListenerSocket::Sctp(listener, local_address)
+                }
+                NgTransportKind::TcpFramed => ListenerSocket::Tcp(TcpListener::bind(address).await?),
+            };
+            io::Result::Ok(socket)
+        };
+        let socket = bind.await.map_err(|e| {
+            LayerError::InitializationFailed(format!("Cannot listen for {} on {}: {}", P::INTERFACE, address, e))
+        })?;
+        Ok(Self { socket, protocol: PhantomData })
+    }
+
+    /// Local address of the listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.socket {
+            ListenerSocket::Sctp(_, address) => Some(*address),
+            ListenerSocket::Tcp(listener) => listener.local_addr().ok(),
+        }
+    }
+
+    /// Wait for the next association
+    pub async fn accept(&self) -> Result<(ApSender<P>, ApReceiver<P>), LayerError> {
+        let accepted = async {
+            let halves = match &self.socket {
+                ListenerSocket::Sctp(listener, _) => {
+                    let (socket, peer) = listener.accept().await?;
+                    socket.sctp_request_rcvinfo(true)?;
+                    let socket = Arc::new(socket);
+                    (ApSender::new(Writer::Sctp(Arc::clone(&socket)), peer), ApReceiver::new(Reader::Sctp(socket)))
+                }
+                ListenerSocket::Tcp(listener) => {
+                    let (stream, peer) = listener.accept().await?;
+                    stream.set_nodelay(true)?;
+                    let (reader, writer) = stream.into_split();
+                    (ApSender::new(Writer::Tcp(Mutex::new(writer)), peer), ApReceiver::new(Reader::Tcp(reader)))
+                }
+            };
+            io::Result::Ok(halves)
+        };
+        accepted.await.map_err(|e| LayerError::ProcessingError(format!("{} accept failed: {}", P::INTERFACE, e)))
+    }
+}
+
+/// Encode a TCP frame: payload length (4 octets), stream (2 octets), PPID
+/// (4 octets) and the payload, all in network order
+pub fn encode_frame(stream: u16, ppid: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&stream.to_be_bytes());
+    frame.extend_from_slice(&ppid.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one TCP frame, returning its stream, PPID and payload, or None when the
+/// connection is closed
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<(u16, u32, Bytes)>> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let stream = u16::from_be_bytes([header[4], header[5]]);
+    let ppid = u32::from_be_bytes([header[6], header[7], header[8], header[9]]);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes too large", len)));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some((stream, ppid, Bytes::from(payload))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::e1ap::E1apProcedureCode;
+    use crate::e2ap::E2apProcedureCode;
+    use crate::f1ap::F1apProcedureCode;
+    use crate::xnap::XnapProcedureCode;
+    use tokio::net::TcpListener;
+
+    /// Kernel SCTP is not available everywhere, SCTP cases are skipped without it
+    fn sctp_available() -> bool {
+        Socket::new_v4(SocketToAssociation::OneToOne).is_ok()
+    }
+
+    /// Exchange PDUs of protocol `P` both ways over an association, then lose it
+    async fn check_ap_transport<P: SctpProtocol>(kind: NgTransportKind, procedure: P) {
+        let listener = ApListener::<P>::bind(kind, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(
+            connect::<P>(kind, "127.0.0.1:0".parse().unwrap(), address),
+            listener.accept(),
+        );
+        let (client_tx, mut client_rx) = client.unwrap();
+        let (server_tx, mut server_rx) = server.unwrap();
+
+        let request = ApPdu::initiating(procedure);
+        client_tx.send(NON_UE_STREAM, &request).await.unwrap();
+        assert_eq!(server_rx.recv().await,
+                   TransportEvent::Data { stream: NON_UE_STREAM, payload: request.encode().unwrap() });
+
+        // The last stream the protocol asks for is usable
+        let stream = P::NUM_STREAMS - 1;
+        let response = ApPdu::successful(procedure);
+        server_tx.send(stream, &response).await.unwrap();
+        assert_eq!(client_rx.recv().await, TransportEvent::Data { stream, payload: response.encode().unwrap() });
+
+        drop((server_tx, server_rx));
+        assert!(matches!(client_rx.recv().await, TransportEvent::AssociationLost(_)), "{}", P::INTERFACE);
+    }
+
+    #[tokio::test]
+    async fn test_ap_transports() {
+        for kind in [NgTransportKind::TcpFramed, NgTransportKind::Sctp] {
+            if kind == NgTransportKind::Sctp && !sctp_available() {
+                continue;
+            }
+            check_ap_transport(kind, F1apProcedureCode::F1Setup).await;
+            check_ap_transport(kind, E1apProcedureCode::GnbCuUpE1Setup).await;
+            check_ap_transport(kind, XnapProcedureCode::XnSetup).await;
+            check_ap_transport(kind, E2apProcedureCode::E2Setup).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_framed_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let amf_address = listener.local_addr().unwrap();
+        let config = NgTransportConfig { kind: NgTransportKind::TcpFramed, ..Default::default() };
+
+        let (transport, accepted) = tokio::join!(
+            NgTransport::connect(&config, "127.0.0.1:0".parse().unwrap(), amf_address, &[]),
+            listener.accept(),
+        );
+        let mut transport = transport.unwrap();
+        let (mut amf, _) = accepted.unwrap();
+        assert_eq!(transport.kind(), NgTransportKind::TcpFramed);
+        assert_ne!(transport.ue_stream(7), NON_UE_STREAM);
+
+        // gNB to AMF keeps the stream and PPID
+        transport.send(3, vec![0x00, 0x15, 0x00]).await.unwrap();
+        let (stream, ppid, payload) = read_frame(&mut amf).await.unwrap().unwrap();
+        assert_eq!((stream, ppid, &payload[..]), (3, NGAP_PPID, &[0x00, 0x15, 0x00][..]));
+
+        // Frames with another PPID are dropped
+        amf.write_all(&encode_frame(0, 61, &[0xFF])).await.unwrap();
+        amf.write_all(&encode_frame(0, NGAP_PPID, &[0x20, 0x15])).await.unwrap();
+        assert_eq!(transport.recv().await.unwrap(),
+                   NgTransportEvent::Data { stream: 0, payload: Bytes::from_static(&[0x20, 0x15]) });
+
+        // Closing the connection is reported through the reader task
+        let (events_tx, mut events_rx) = mpsc::channel(4);
+        transport.start_reader(0, events_tx);
+        assert!(transport.recv().await.is_err());
+        drop(amf);
+        assert!(matches!(events_rx.recv().await, Some((0, NgTransportEvent::AssociationLost(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_sctp_transport() {
+        if !sctp_available() {
+            return;
+        }
+        let socket = Socket::new_v4(SocketToAssociation::OneToOne).unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(1).unwrap();
+        let amf_address = listener.sctp_getladdrs(0).ok()
+            .and_then(|addresses| addresses.first().copied());
+        let Some(amf_address) = amf_address else {
+            return;
+        };
+
+        let config = NgTransportConfig { num_streams: 3, ..Default::default() };
+        let (transport, accepted) = tokio::join!(
+            NgTransport::connect(&config, "127.0.0.1:0".parse().unwrap(), amf_address, &[]),
+            listener.accept(),
+        );
+        let transport = transport.unwrap();
+        let (amf, _) = accepted.unwrap();
+        amf.sctp_request_rcvinfo(true).unwrap();
+        assert!(transport.num_streams() <= 3);
+
+        transport.send(transport.ue_stream(1), vec![0x00, 0x0F]).await.unwrap();
+        loop {
+            if let NotificationOrData::Data(data) = amf.sctp_recv().await.unwrap() {
+                let info = data.rcv_info.unwrap();
+                assert_eq!(u32::from_be(info.ppid), NGAP_PPID);
+                assert_eq!(info.sid, transport.ue_stream(1));
+                assert_eq!(data.payload, vec![0x00, 0x0F]);
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frame_errors() {
+        // Closed connection between frames, and within a header
+        assert!(read_frame(&mut &[][..]).await.unwrap().is_none());
+        assert!(read_frame(&mut &[0x00, 0x00, 0x00][..]).await.unwrap().is_none());
+
+        // Payload shorter than its length
+        let frame = encode_frame(1, NGAP_PPID, &[0x00, 0x15, 0x00]);
+        let error = read_frame(&mut &frame[..frame.len() - 1]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Oversized frame
+        let mut frame = encode_frame(1, NGAP_PPID, &[]);
+        frame[..4].copy_from_slice(&((MAX_FRAME_LEN + 1) as u32).to_be_bytes());
+        assert_eq!(read_frame(&mut &frame[..]).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // No AMF listening
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let amf_address = listener.local_addr().unwrap();
+        drop(listener);
+        let config = NgTransportConfig { kind: NgTransportKind::TcpFramed, ..Default::default() };
+        assert!(NgTransport::connect(&config, "127.0.0.1:0".parse().unwrap(), amf_address, &[]).await.is_err());
+    }
+}
@@ -8,6 +8,7 @@ use bytes::Bytes;
 use tracing::{debug, info};
 
 /// PDCP layer configuration
+#[derive(Debug, Clone, PartialEq)]
 pub struct PdcpConfig {
     /// SN size in bits (12 or 18)
     pub sn_size: u8,
@@ -22,6 +23,7 @@ pub struct PdcpConfig {
 }
 
 /// PDCP layer implementation
+#[derive(Debug)]
 pub struct PdcpLayer {
     config: PdcpConfig,
     initialized: bool,
@@ -41,6 +43,19 @@ impl PdcpLayer {
             rx_next: 0,
         }
     }
+
+    /// COUNT of the next downlink PDCP SDU (TX_NEXT) and of the next expected
+    /// uplink PDCP SDU (RX_NEXT)
+    pub fn counts(&self) -> (u32, u32) {
+        (self.tx_next, self.rx_next)
+    }
+
+    /// Continue from the COUNTs of another PDCP entity, as received in an SN
+    /// Status Transfer at handover
+    pub fn set_counts(&mut self, tx_next: u32, rx_next: u32) {
+        self.tx_next = tx_next;
+        self.rx_next = rx_next;
+    }
 }
 
 #[async_trait]
@@ -15,6 +15,7 @@ pub mod pdsch;
 pub mod prach;
 pub mod dmrs;
 pub mod resampler;
+pub mod ru;
 
 // Re-export commonly used types
 pub use frame_structure::{FrameStructure, SlotConfig, SymbolType};
@@ -22,13 +23,16 @@ pub use resource_grid::{ResourceGrid, ResourceElement};
 pub use ofdm::{OfdmModulator, OfdmDemodulator};
 pub use pss_sss::{PssGenerator, SssGenerator, CellSearchResult};
 pub use pbch::{PbchProcessor, Mib};
-pub use pdcch::{PdcchProcessor, DciFormat10SiRnti};
+pub use pdcch::{PdcchProcessor, DciFormat00CRnti, DciFormat10CRnti, DciFormat10PRnti, DciFormat10SiRnti};
 pub use pdsch::{PdschProcessor, PdschConfig};
 pub use prach::{PrachDetector, PrachDetectionResult, RachConfigCommon};
+pub use ru::RuConfig;
 use resampler::{Resampler, ResamplerConfig};
+use ru::FrequencyDomainSymbol;
 
 use crate::{LayerError, mac::MacPhyInterface};
 use common::types::{Bandwidth, SubcarrierSpacing, Pci, CellId};
+use interfaces::oran_fh::{FronthaulStats, OranFhConfig, OranFronthaul};
 use interfaces::zmq_rf::{AsyncZmqRf, IqBuffer, ZmqRfConfig};
 use num_complex::Complex32;
 use std::sync::Arc;
@@ -100,6 +104,8 @@ struct PhyState {
     symbol_start_sample: u64,
     /// Cached PBCH symbols per SSB index
     current_pbch_symbols: HashMap<u8, Vec<Complex32>>,
+    /// Transport block outcomes
+    transport_blocks: TransportBlockStats,
 }
 
 impl PhyState {
@@ -112,6 +118,7 @@ impl PhyState {
             expected_sample_count: 0,
             symbol_start_sample: 0,
             current_pbch_symbols: HashMap::new(),
+            transport_blocks: TransportBlockStats::default(),
         }
     }
     
@@ -149,6 +156,10 @@ pub struct EnhancedPhyLayer {
     initialized: bool,
     /// Channel for sending samples to RF interface
     rf_tx_channel: Option<tokio::sync::mpsc::Sender<IqBuffer>>,
+    /// O-RU of the 7.2x split, taking frequency domain samples instead of the RF interface
+    fronthaul: Option<Arc<OranFronthaul>>,
+    /// Channel for sending frequency domain symbols to the fronthaul
+    fh_tx_channel: Option<tokio::sync::mpsc::Sender<FrequencyDomainSymbol>>,
     /// MAC-PHY interface for scheduling
     mac_interface: Option<Arc<dyn MacPhyInterface>>,
     /// Pre-computed PSS sequence (doesn't change)
@@ -300,6 +311,8 @@ impl EnhancedPhyLayer {
             running: Arc::new(RwLock::new(false)),
             initialized: false,
             rf_tx_channel: None,
+            fronthaul: None,
+            fh_tx_channel: None,
             mac_interface: None,
             pss_sequence_precomputed,
             sss_sequence_even_frame,
@@ -314,6 +327,43 @@ impl EnhancedPhyLayer {
         info!("MAC-PHY interface set");
     }
     
+    /// Initialize with the RU selected by the configuration
+    pub async fn initialize_with_ru(&mut self, ru_config: RuConfig) -> Result<(), LayerError> {
+        match ru_config {
+            RuConfig::Zmq(rf_config) => self.initialize_with_rf(rf_config).await,
+            RuConfig::OranFh(fh_config) => self.initialize_with_fronthaul(fh_config).await,
+        }
+    }
+    
+    /// Initialize with an O-RU behind an O-RAN 7.2x fronthaul
+    ///
+    /// OFDM modulation is left to the O-RU: the downlink sends the carrier's
+    /// subcarriers of each symbol from the resource grid, in the symbol's
+    /// transmission window. Frame 0 of the fronthaul clock starts now.
+    pub async fn initialize_with_fronthaul(&mut self, mut fh_config: OranFhConfig) -> Result<(), LayerError> {
+        info!("Initializing PHY layer with O-RAN 7.2x fronthaul");
+        
+        fh_config.numerology = numerology(self.config.subcarrier_spacing);
+        fh_config.num_prbs = self.resource_grid.lock().await.num_rbs();
+        let fronthaul = OranFronthaul::bind(fh_config, tokio::time::Instant::now()).await
+            .map_err(|e| LayerError::InitializationFailed(e.to_string()))?;
+        let fronthaul = Arc::new(fronthaul);
+        
+        // A slot of symbols ahead of the air time is enough: sending waits for
+        // the transmission window of each symbol, which paces the downlink
+        let symbols_per_slot = self.frame_structure.symbols_per_slot() as usize;
+        let (tx_sender, tx_receiver) = tokio::sync::mpsc::channel::<FrequencyDomainSymbol>(symbols_per_slot);
+        tokio::spawn(ru::run_fronthaul_tx(fronthaul.clone(), tx_receiver));
+        
+        self.fronthaul = Some(fronthaul);
+        self.fh_tx_channel = Some(tx_sender);
+        self.initialized = true;
+        *self.running.write().await = true;
+        
+        info!("PHY layer initialized with O-RAN fronthaul");
+        Ok(())
+    }
+    
     /// Initialize with RF interface
     pub async fn initialize_with_rf(&mut self, rf_config: ZmqRfConfig) -> Result<(), LayerError> {
         info!("Initializing PHY layer with RF interface");
@@ -435,6 +485,11 @@ impl EnhancedPhyLayer {
         // Start uplink processing
         let ul_handle = self.start_uplink_processing();
         
+        // Receive the uplink U-plane of the O-RU
+        if let Some(fronthaul) = &self.fronthaul {
+            tokio::spawn(ru::run_fronthaul_rx(fronthaul.clone(), self.running.clone()));
+        }
+        
         // Wait for tasks
         tokio::select! {
             _ = dl_handle => {
@@ -461,9 +516,9 @@ impl EnhancedPhyLayer {
         let pbch_processor = self.pbch_processor.clone();
         let pdcch_processor = self.pdcch_processor.clone();
         let pdsch_processor = self.pdsch_processor.clone();
-        debug!("Getting RF TX channel");
-        let rf_tx_channel = self.rf_tx_channel.as_ref().unwrap().clone();
-        debug!("RF TX channel obtained");
+        let rf_tx_channel = self.rf_tx_channel.clone();
+        let fh_tx_channel = self.fh_tx_channel.clone();
+        let fronthaul = self.fronthaul.clone();
         let config = self.config.clone();
         let mac_interface = self.mac_interface.clone();
         // Clone pre-computed sequences
@@ -507,7 +562,12 @@ impl EnhancedPhyLayer {
             // Pre-buffer only 20ms (one SSB period) to ensure UE receives SSBs at expected times
             // This ensures the first SSB the UE sees is at frame 0 or frame 2
             let pre_buffer_ms = 0.02; // 20ms = one SSB period
-            let pre_buffer_symbols = (pre_buffer_ms / symbol_duration.as_secs_f64()) as usize;
+            // An O-RU gets each symbol in its transmission window instead
+            let pre_buffer_symbols = if rf_tx_channel.is_some() {
+                (pre_buffer_ms / symbol_duration.as_secs_f64()) as usize
+            } else {
+                0
+            };
             let mut pre_buffer_count = 0;
             
             info!("Pre-buffering {} symbols ({}ms - one SSB period) to align timing...", pre_buffer_symbols, pre_buffer_ms * 1000.0);
@@ -607,7 +667,9 @@ impl EnhancedPhyLayer {
                 }
                 
                 // Try to send but don't block - this fills the circular buffer
-                let _ = rf_tx_channel.try_send(iq_buffer);
+                if let Some(rf_tx_channel) = &rf_tx_channel {
+                    let _ = rf_tx_channel.try_send(iq_buffer);
+                }
                 
                 // Update state with sample-based timing
                 state_guard.sample_count += samples_per_symbol as u64;
@@ -638,6 +700,19 @@ impl EnhancedPhyLayer {
             // No more sleep-based timing - everything is sample-driven
             let samples_per_symbol_u64 = samples_per_symbol as u64;
             
+            // Start at the next slot of the fronthaul clock, so that the O-RU
+            // gets each symbol in time for its air time
+            if let Some(fronthaul) = &fronthaul {
+                let next_slot = fronthaul.clock()
+                    .symbol_at(tokio::time::Instant::now() + frame_structure.slot_duration());
+                let mut state_guard = state.write().await;
+                state_guard.frame_number = next_slot.frame_id as u32;
+                state_guard.slot_number = next_slot.slot_in_frame(fronthaul.clock().numerology()) as u8;
+                state_guard.symbol_number = 0;
+                info!("Fronthaul downlink starting at frame={}, slot={}",
+                      state_guard.frame_number, state_guard.slot_number);
+            }
+            
             while *running.read().await {
                 // Process all symbols in a slot as a batch for better timing
                 for _symbol_in_slot in 0..symbols_per_slot {
@@ -912,6 +987,151 @@ impl EnhancedPhyLayer {
                         }
                     }
                     
+                    // Map Paging if a paging occasion falls in this slot
+                    if let Some(paging_info) = slot_schedule.as_ref().and_then(|schedule| schedule.paging_info.as_ref()) {
+                        // Map PDCCH for Paging (only in first symbol of CORESET)
+                        if symbol == paging_info.coreset.start_symbol {
+                            let dci_1_0 = DciFormat10PRnti {
+                                short_messages_indicator: 1, // Scheduling information only
+                                short_messages: 0,
+                                frequency_resource: paging_info.frequency_domain_assignment,
+                                time_resource: paging_info.time_domain_assignment,
+                                vrb_to_prb_mapping: 0, // Non-interleaved
+                                modulation_coding_scheme: paging_info.mcs_index,
+                                tb_scaling: 0,
+                            };
+                            let mut grid = resource_grid.lock().await;
+                            pdcch_processor.process_paging_pdcch(
+                                &mut grid,
+                                &paging_info.coreset,
+                                &dci_1_0,
+                                paging_info.aggregation_level,
+                                paging_info.cce_index,
+                            );
+                        }
+                        
+                        // Map PDSCH carrying the Paging message
+                        let paging_start = paging_info.pdsch_time_alloc.start_symbol;
+                        let paging_length = paging_info.pdsch_time_alloc.num_symbols;
+                        if symbol >= paging_start && symbol < paging_start + paging_length {
+                            let pdsch_config = PdschConfig {
+                                tbs_bytes: paging_info.tbs_bytes,
+                                modulation: paging_info.modulation,
+                                num_layers: 1,
+                                rv: 0,
+                                ldpc_base_graph: if paging_info.tbs_bytes > 292 { 1 } else { 2 },
+                                ndi: true,
+                                harq_id: 0,
+                                prb_allocation: paging_info.prb_allocation.clone(),
+                                start_symbol: paging_start,
+                                num_symbols: paging_length,
+                                dmrs_type: 0,
+                                dmrs_additional_pos: 0,
+                                dmrs_config_type: 0,
+                                n_id: config.pci.0,
+                                rnti: paging_info.rnti,
+                                code_block_size: (paging_info.tbs_bytes + 3) * 8, // TBS + CRC in bits
+                            };
+                            let mut grid = resource_grid.lock().await;
+                            pdsch_processor.process_sib1_pdsch(&mut grid, &paging_info.payload, &pdsch_config);
+                        }
+                    }
+                    
+                    // Map the PDSCH of the UEs scheduled in this slot
+                    for grant in slot_schedule.iter().flat_map(|schedule| schedule.dl_grants.iter()) {
+                        // Map PDCCH with CRC scrambled by the C-RNTI (only in first symbol of CORESET)
+                        if symbol == grant.coreset.start_symbol {
+                            let dci_1_0 = DciFormat10CRnti {
+                                frequency_resource: grant.frequency_domain_assignment,
+                                time_resource: grant.time_domain_assignment,
+                                vrb_to_prb_mapping: 0, // Non-interleaved
+                                modulation_coding_scheme: grant.mcs_index,
+                                new_data_indicator: grant.ndi as u8,
+                                redundancy_version: 0,
+                                harq_process_number: grant.harq_process,
+                                downlink_assignment_index: 0,
+                                tpc_command: 1, // 0 dB
+                                pucch_resource_indicator: 0,
+                                harq_feedback_timing: 3, // K1 = 4 slots
+                            };
+                            let mut grid = resource_grid.lock().await;
+                            pdcch_processor.process_ue_pdcch(
+                                &mut grid,
+                                &grant.coreset,
+                                &dci_1_0,
+                                grant.rnti.0,
+                                grant.aggregation_level,
+                                grant.cce_index,
+                            );
+                        }
+                        
+                        // Map PDSCH carrying the DL-SCH MAC PDU
+                        let pdsch_start = grant.pdsch_time_alloc.start_symbol;
+                        let pdsch_length = grant.pdsch_time_alloc.num_symbols;
+                        if symbol >= pdsch_start && symbol < pdsch_start + pdsch_length {
+                            let pdsch_config = PdschConfig {
+                                tbs_bytes: grant.tbs_bytes,
+                                modulation: grant.modulation,
+                                num_layers: 1,
+                                rv: 0,
+                                ldpc_base_graph: if grant.tbs_bytes > 292 { 1 } else { 2 },
+                                ndi: grant.ndi,
+                                harq_id: grant.harq_process,
+                                prb_allocation: grant.prb_allocation.clone(),
+                                start_symbol: pdsch_start,
+                                num_symbols: pdsch_length,
+                                dmrs_type: 0,
+                                dmrs_additional_pos: 0,
+                                dmrs_config_type: 0,
+                                n_id: config.pci.0,
+                                rnti: grant.rnti.0,
+                                code_block_size: (grant.tbs_bytes + 3) * 8, // TBS + CRC in bits
+                            };
+                            let mut grid = resource_grid.lock().await;
+                            pdsch_processor.process_sib1_pdsch(&mut grid, &grant.payload, &pdsch_config);
+                        }
+                    }
+                    
+                    // Map the PDCCH granting the PUSCH of the UEs K2 slots later
+                    for grant in slot_schedule.iter().flat_map(|schedule| schedule.ul_grants.iter()) {
+                        if symbol == grant.coreset.start_symbol {
+                            let dci_0_0 = DciFormat00CRnti {
+                                frequency_resource: grant.frequency_domain_assignment,
+                                time_resource: grant.time_domain_assignment,
+                                frequency_hopping: 0,
+                                modulation_coding_scheme: grant.mcs_index,
+                                new_data_indicator: grant.ndi as u8,
+                                redundancy_version: 0,
+                                harq_process_number: grant.harq_process,
+                                tpc_command: 1, // 0 dB
+                            };
+                            let mut grid = resource_grid.lock().await;
+                            pdcch_processor.process_ue_ul_pdcch(
+                                &mut grid,
+                                &grant.coreset,
+                                &dci_0_0,
+                                grant.rnti.0,
+                                grant.aggregation_level,
+                                grant.cce_index,
+                            );
+                        }
+                    }
+                    
+                    // 7.2x split: the O-RU modulates the carrier's subcarriers itself
+                    if let Some(fh_tx_channel) = &fh_tx_channel {
+                        let iq = resource_grid.lock().await.carrier_symbol(symbol);
+                        state_guard.advance_symbol(symbols_per_slot, slots_per_frame);
+                        drop(state_guard);
+                        if fh_tx_channel.send(FrequencyDomainSymbol { frame, slot, symbol, iq }).await.is_err() {
+                            error!("PHY DL: fronthaul channel closed, stopping downlink processing");
+                            break;
+                        }
+                        continue;
+                    }
+                    let Some(rf_tx_channel) = &rf_tx_channel else {
+                        break;
+                    };
+                    
                     // CRITICAL: Always transmit samples to maintain continuous ZMQ flow
                     // The UE expects continuous sample stream for proper cell detection
                     
@@ -1197,6 +1417,20 @@ impl EnhancedPhyLayer {
         Ok(())
     }
     
+    /// Count the HARQ feedback of a downlink transport block
+    pub async fn record_dl_harq_feedback(&self, ack: bool) {
+        let counters = &mut self.state.write().await.transport_blocks;
+        counters.dl_total += 1;
+        counters.dl_errors += u64::from(!ack);
+    }
+    
+    /// Count the CRC check of a decoded uplink transport block
+    pub async fn record_ul_crc(&self, crc_ok: bool) {
+        let counters = &mut self.state.write().await.transport_blocks;
+        counters.ul_total += 1;
+        counters.ul_errors += u64::from(!crc_ok);
+    }
+    
     /// Get PHY statistics
     pub async fn get_stats(&self) -> PhyStats {
         let state = self.state.read().await;
@@ -1211,11 +1445,27 @@ impl EnhancedPhyLayer {
             slot_number: state.slot_number,
             symbol_number: state.symbol_number,
             sample_count: state.sample_count,
+            transport_blocks: state.transport_blocks,
             rf_stats,
+            fronthaul_stats: self.fronthaul.as_ref().map(|fronthaul| fronthaul.stats()),
         }
     }
 }
 
+/// Transport blocks with HARQ feedback or a CRC result, the BLER is
+/// `errors / total` of a direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportBlockStats {
+    /// Downlink transport blocks with HARQ feedback
+    pub dl_total: u64,
+    /// Downlink transport blocks NACKed
+    pub dl_errors: u64,
+    /// Uplink transport blocks decoded
+    pub ul_total: u64,
+    /// Uplink transport blocks failing the CRC check
+    pub ul_errors: u64,
+}
+
 /// PHY layer statistics
 #[derive(Debug)]
 pub struct PhyStats {
@@ -1223,7 +1473,20 @@ pub struct PhyStats {
     pub slot_number: u8,
     pub symbol_number: u8,
     pub sample_count: u64,
+    pub transport_blocks: TransportBlockStats,
     pub rf_stats: Option<interfaces::zmq_rf::RfStats>,
+    pub fronthaul_stats: Option<FronthaulStats>,
+}
+
+/// Numerology (mu) of a subcarrier spacing
+fn numerology(scs: SubcarrierSpacing) -> u8 {
+    match scs {
+        SubcarrierSpacing::Scs15 => 0,
+        SubcarrierSpacing::Scs30 => 1,
+        SubcarrierSpacing::Scs60 => 2,
+        SubcarrierSpacing::Scs120 => 3,
+        SubcarrierSpacing::Scs240 => 4,
+    }
 }
 
 /// Calculate FFT size based on bandwidth and subcarrier spacing
@@ -1261,6 +1524,67 @@ pub fn calculate_fft_size(bandwidth: &Bandwidth, scs: &SubcarrierSpacing) -> Res
 #[cfg(test)]
 mod tests {
     use super::*;
+    use interfaces::oran_fh::{CplaneMessage, DataDirection, EcpriHeader, EcpriMessageType, UplaneMessage};
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_downlink_over_oran_fronthaul() {
+        let ru = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut phy = EnhancedPhyLayer::new(PhyConfig {
+            pci: Pci::new(1).unwrap(),
+            cell_id: CellId(1),
+            carrier_frequency: 1842.5e6,
+            bandwidth: Bandwidth::Bw10,
+            subcarrier_spacing: SubcarrierSpacing::Scs15,
+            num_tx_antennas: 1,
+            num_rx_antennas: 1,
+            cyclic_prefix: CyclicPrefix::Normal,
+            duplex_mode: DuplexMode::Fdd,
+            k_ssb: 0,
+            sample_rate: 15.36e6,
+            prach_config: RachConfigCommon::default(),
+        }).unwrap();
+        let fh_config = OranFhConfig {
+            local_address: "127.0.0.1:0".to_string(),
+            ru_address: ru.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+        phy.initialize_with_ru(RuConfig::OranFh(fh_config.clone())).await.unwrap();
+        let phy = Arc::new(phy);
+        let processing = tokio::spawn({
+            let phy = phy.clone();
+            async move { phy.start_processing().await }
+        });
+        
+        // Each slot is announced by downlink and uplink C-plane, then the
+        // symbols follow in the frequency domain until the SSB shows up
+        let mut buf = vec![0u8; 9000];
+        let mut directions = Vec::new();
+        let mut ssb_seen = false;
+        while !ssb_seen {
+            let (len, _) = tokio::time::timeout(Duration::from_secs(5), ru.recv_from(&mut buf)).await.unwrap().unwrap();
+            let (header, payload) = EcpriHeader::decode(&buf[..len]).unwrap();
+            match header.message_type {
+                EcpriMessageType::RealTimeControl => {
+                    let cplane = CplaneMessage::decode(payload, 52).unwrap();
+                    assert_eq!((cplane.time.symbol_id, cplane.sections[0].num_prb), (0, 52));
+                    directions.push(cplane.direction);
+                }
+                EcpriMessageType::IqData => {
+                    let uplane = UplaneMessage::decode(payload, fh_config.compression, fh_config.iq_scale, 52).unwrap();
+                    assert_eq!(uplane.direction, DataDirection::Downlink);
+                    ssb_seen = uplane.sections.iter().flat_map(|section| &section.iq).any(|re| re.norm() > 0.1);
+                }
+            }
+        }
+        assert_eq!(&directions[..2], &[DataDirection::Downlink, DataDirection::Uplink]);
+        
+        let stats = phy.get_stats().await.fronthaul_stats.unwrap();
+        assert!(stats.tx_cplane >= 2 && stats.tx_uplane > 0);
+        phy.stop_processing().await.unwrap();
+        processing.abort();
+    }
     
     #[test]
     fn test_fft_size_calculation() {
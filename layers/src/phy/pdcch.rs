@@ -25,6 +25,73 @@ pub struct DciFormat10SiRnti {
     pub system_information_indicator: u8,
 }
 
+/// DCI Format 1_0 for P-RNTI (paging)
+#[derive(Debug, Clone)]
+pub struct DciFormat10PRnti {
+    /// Short messages indicator (1: scheduling information only)
+    pub short_messages_indicator: u8,
+    /// Short messages (TS 38.331 section 6.5)
+    pub short_messages: u8,
+    /// Frequency domain resource assignment
+    pub frequency_resource: u16,
+    /// Time domain resource assignment
+    pub time_resource: u8,
+    /// VRB-to-PRB mapping (0: non-interleaved, 1: interleaved)
+    pub vrb_to_prb_mapping: u8,
+    /// Modulation and coding scheme (0-31)
+    pub modulation_coding_scheme: u8,
+    /// TB scaling (0: no scaling)
+    pub tb_scaling: u8,
+}
+
+/// DCI Format 1_0 for C-RNTI (PDSCH of a UE)
+#[derive(Debug, Clone)]
+pub struct DciFormat10CRnti {
+    /// Frequency domain resource assignment
+    pub frequency_resource: u16,
+    /// Time domain resource assignment
+    pub time_resource: u8,
+    /// VRB-to-PRB mapping (0: non-interleaved, 1: interleaved)
+    pub vrb_to_prb_mapping: u8,
+    /// Modulation and coding scheme (0-31)
+    pub modulation_coding_scheme: u8,
+    /// New data indicator
+    pub new_data_indicator: u8,
+    /// Redundancy version (0-3)
+    pub redundancy_version: u8,
+    /// HARQ process number (0-15)
+    pub harq_process_number: u8,
+    /// Downlink assignment index
+    pub downlink_assignment_index: u8,
+    /// TPC command for the scheduled PUCCH
+    pub tpc_command: u8,
+    /// PUCCH resource indicator
+    pub pucch_resource_indicator: u8,
+    /// PDSCH-to-HARQ feedback timing indicator
+    pub harq_feedback_timing: u8,
+}
+
+/// DCI Format 0_0 for C-RNTI (PUSCH of a UE)
+#[derive(Debug, Clone)]
+pub struct DciFormat00CRnti {
+    /// Frequency domain resource assignment
+    pub frequency_resource: u16,
+    /// Time domain resource assignment
+    pub time_resource: u8,
+    /// Frequency hopping flag
+    pub frequency_hopping: u8,
+    /// Modulation and coding scheme (0-31)
+    pub modulation_coding_scheme: u8,
+    /// New data indicator
+    pub new_data_indicator: u8,
+    /// Redundancy version (0-3)
+    pub redundancy_version: u8,
+    /// HARQ process number (0-15)
+    pub harq_process_number: u8,
+    /// TPC command for the scheduled PUSCH
+    pub tpc_command: u8,
+}
+
 /// PDCCH encoder configuration
 pub struct PdcchEncoderConfig {
     /// Total number of encoded bits (E)
@@ -79,19 +146,89 @@ impl PdcchProcessor {
         // 1. Encode DCI payload
         let dci_bits = self.encode_dci_format_1_0_si_rnti(dci, coreset);
         
-        // 2. Add CRC and scramble with SI-RNTI
-        let crc_attached = self.attach_crc_and_scramble(&dci_bits, 0xFFFF); // SI-RNTI = 0xFFFF
+        // 2-6. CRC scrambled with SI-RNTI, Polar coding and mapping
+        self.transmit_dci(resource_grid, coreset, &dci_bits, 0xFFFF, aggregation_level, cce_index); // SI-RNTI = 0xFFFF
+    }
+
+    /// Process PDCCH for paging
+    pub fn process_paging_pdcch(
+        &self,
+        resource_grid: &mut super::resource_grid::ResourceGrid,
+        coreset: &CorsetConfig,
+        dci: &DciFormat10PRnti,
+        aggregation_level: u8,
+        cce_index: u16,
+    ) {
+        info!(
+            "Processing PDCCH for Paging: AL={}, CCE={}",
+            aggregation_level, cce_index
+        );
+
+        let dci_bits = self.encode_dci_format_1_0_p_rnti(dci, coreset);
+        self.transmit_dci(resource_grid, coreset, &dci_bits, 0xFFFE, aggregation_level, cce_index); // P-RNTI = 0xFFFE
+    }
+
+    /// Process PDCCH scheduling the PDSCH of a UE
+    pub fn process_ue_pdcch(
+        &self,
+        resource_grid: &mut super::resource_grid::ResourceGrid,
+        coreset: &CorsetConfig,
+        dci: &DciFormat10CRnti,
+        rnti: u16,
+        aggregation_level: u8,
+        cce_index: u16,
+    ) {
+        debug!(
+            "Processing PDCCH for C-RNTI {}: AL={}, CCE={}",
+            rnti, aggregation_level, cce_index
+        );
+
+        let dci_bits = self.encode_dci_format_1_0_c_rnti(dci, coreset);
+        self.transmit_dci(resource_grid, coreset, &dci_bits, rnti, aggregation_level, cce_index);
+    }
+
+    /// Process PDCCH granting a PUSCH to a UE
+    pub fn process_ue_ul_pdcch(
+        &self,
+        resource_grid: &mut super::resource_grid::ResourceGrid,
+        coreset: &CorsetConfig,
+        dci: &DciFormat00CRnti,
+        rnti: u16,
+        aggregation_level: u8,
+        cce_index: u16,
+    ) {
+        debug!(
+            "Processing UL grant PDCCH for C-RNTI {}: AL={}, CCE={}",
+            rnti, aggregation_level, cce_index
+        );
+
+        let dci_bits = self.encode_dci_format_0_0_c_rnti(dci, coreset);
+        self.transmit_dci(resource_grid, coreset, &dci_bits, rnti, aggregation_level, cce_index);
+    }
+
+    /// Attach the RNTI scrambled CRC, encode and map a DCI to the CORESET
+    fn transmit_dci(
+        &self,
+        resource_grid: &mut super::resource_grid::ResourceGrid,
+        coreset: &CorsetConfig,
+        dci_bits: &[u8],
+        rnti: u16,
+        aggregation_level: u8,
+        cce_index: u16,
+    ) {
+        // Add CRC and scramble with the RNTI
+        let crc_attached = self.attach_crc_and_scramble(dci_bits, rnti);
         
-        // 3. Perform Polar encoding
+        // Perform Polar encoding
         let encoded_bits = self.polar_encode(&crc_attached, aggregation_level);
         
-        // 4. Scramble the encoded bits
-        let scrambled_bits = self.scramble_data(&encoded_bits, 0xFFFF, 0); // Using 0 for CORESET0
+        // Scramble the encoded bits
+        let scrambled_bits = self.scramble_data(&encoded_bits, rnti, 0); // Using 0 for CORESET0
         
-        // 5. Map to CORESET resources with modulation
+        // Map to CORESET resources with modulation
         self.map_to_coreset(resource_grid, coreset, &scrambled_bits, aggregation_level, cce_index);
         
-        // 6. Generate DMRS for PDCCH
+        // Generate DMRS for PDCCH
         self.generate_pdcch_dmrs(resource_grid, coreset, aggregation_level, cce_index);
     }
 
@@ -128,6 +265,122 @@ impl PdcchProcessor {
         bits
     }
 
+    /// Encode DCI Format 1_0 for P-RNTI (TS 38.212 section 7.3.1.2.1)
+    fn encode_dci_format_1_0_p_rnti(&self, dci: &DciFormat10PRnti, coreset: &CorsetConfig) -> Vec<u8> {
+        let mut bits = Vec::new();
+        
+        // Short messages indicator (2 bits) and short messages (8 bits)
+        self.append_bits(&mut bits, dci.short_messages_indicator as u32, 2);
+        self.append_bits(&mut bits, dci.short_messages as u32, 8);
+        
+        // Frequency domain resource assignment (depends on CORESET bandwidth)
+        let freq_bits = self.calculate_frequency_domain_bits(coreset);
+        self.append_bits(&mut bits, dci.frequency_resource as u32, freq_bits);
+        
+        // Time domain resource assignment (4 bits)
+        self.append_bits(&mut bits, dci.time_resource as u32, 4);
+        
+        // VRB-to-PRB mapping (1 bit)
+        self.append_bits(&mut bits, dci.vrb_to_prb_mapping as u32, 1);
+        
+        // Modulation and coding scheme (5 bits)
+        self.append_bits(&mut bits, dci.modulation_coding_scheme as u32, 5);
+        
+        // TB scaling (2 bits)
+        self.append_bits(&mut bits, dci.tb_scaling as u32, 2);
+        
+        // Reserved bits up to the format 1_0 size
+        let total_bits = self.calculate_dci_size(coreset);
+        while bits.len() < total_bits {
+            bits.push(0);
+        }
+        
+        debug!("Encoded DCI Format 1_0 P-RNTI: {} bits", bits.len());
+        bits
+    }
+
+    /// Encode DCI Format 1_0 for C-RNTI (TS 38.212 section 7.3.1.2.1)
+    fn encode_dci_format_1_0_c_rnti(&self, dci: &DciFormat10CRnti, coreset: &CorsetConfig) -> Vec<u8> {
+        let mut bits = Vec::new();
+        
+        // Identifier for DCI formats (1 bit, 1 for a DL format)
+        self.append_bits(&mut bits, 1, 1);
+        
+        // Frequency domain resource assignment (depends on CORESET bandwidth)
+        let freq_bits = self.calculate_frequency_domain_bits(coreset);
+        self.append_bits(&mut bits, dci.frequency_resource as u32, freq_bits);
+        
+        // Time domain resource assignment (4 bits)
+        self.append_bits(&mut bits, dci.time_resource as u32, 4);
+        
+        // VRB-to-PRB mapping (1 bit)
+        self.append_bits(&mut bits, dci.vrb_to_prb_mapping as u32, 1);
+        
+        // Modulation and coding scheme (5 bits)
+        self.append_bits(&mut bits, dci.modulation_coding_scheme as u32, 5);
+        
+        // New data indicator (1 bit) and redundancy version (2 bits)
+        self.append_bits(&mut bits, dci.new_data_indicator as u32, 1);
+        self.append_bits(&mut bits, dci.redundancy_version as u32, 2);
+        
+        // HARQ process number (4 bits) and downlink assignment index (2 bits)
+        self.append_bits(&mut bits, dci.harq_process_number as u32, 4);
+        self.append_bits(&mut bits, dci.downlink_assignment_index as u32, 2);
+        
+        // TPC command (2 bits), PUCCH resource indicator (3 bits) and
+        // PDSCH-to-HARQ feedback timing indicator (3 bits)
+        self.append_bits(&mut bits, dci.tpc_command as u32, 2);
+        self.append_bits(&mut bits, dci.pucch_resource_indicator as u32, 3);
+        self.append_bits(&mut bits, dci.harq_feedback_timing as u32, 3);
+        
+        // Padding up to the format 1_0 size
+        let total_bits = self.calculate_dci_size(coreset);
+        while bits.len() < total_bits {
+            bits.push(0);
+        }
+        
+        debug!("Encoded DCI Format 1_0 C-RNTI: {} bits", bits.len());
+        bits
+    }
+
+    /// Encode DCI Format 0_0 with CRC scrambled by C-RNTI (TS 38.212 section 7.3.1.1.1)
+    fn encode_dci_format_0_0_c_rnti(&self, dci: &DciFormat00CRnti, coreset: &CorsetConfig) -> Vec<u8> {
+        let mut bits = Vec::new();
+        
+        // Identifier for DCI formats (1 bit, 0 for an UL format)
+        self.append_bits(&mut bits, 0, 1);
+        
+        // Frequency domain resource assignment over the initial UL BWP, the carrier
+        let n_rb = common::utils::calculate_nrb(
+            self.cell_config.bandwidth.as_hz(), self.cell_config.subcarrier_spacing as u16) as u32;
+        let freq_bits = ((n_rb * (n_rb + 1) / 2) as f32).log2().ceil() as u8;
+        self.append_bits(&mut bits, dci.frequency_resource as u32, freq_bits);
+        
+        // Time domain resource assignment (4 bits) and frequency hopping flag (1 bit)
+        self.append_bits(&mut bits, dci.time_resource as u32, 4);
+        self.append_bits(&mut bits, dci.frequency_hopping as u32, 1);
+        
+        // Modulation and coding scheme (5 bits)
+        self.append_bits(&mut bits, dci.modulation_coding_scheme as u32, 5);
+        
+        // New data indicator (1 bit) and redundancy version (2 bits)
+        self.append_bits(&mut bits, dci.new_data_indicator as u32, 1);
+        self.append_bits(&mut bits, dci.redundancy_version as u32, 2);
+        
+        // HARQ process number (4 bits) and TPC command (2 bits)
+        self.append_bits(&mut bits, dci.harq_process_number as u32, 4);
+        self.append_bits(&mut bits, dci.tpc_command as u32, 2);
+        
+        // Padding up to the format 1_0 size
+        let total_bits = self.calculate_dci_size(coreset);
+        while bits.len() < total_bits {
+            bits.push(0);
+        }
+        
+        debug!("Encoded DCI Format 0_0 C-RNTI: {} bits", bits.len());
+        bits
+    }
+
     /// Attach CRC and scramble with RNTI
     fn attach_crc_and_scramble(&self, dci_bits: &[u8], rnti: u16) -> Vec<u8> {
         // Add 24 leading 1s for CRC calculation (as per srsRAN implementation)
@@ -207,6 +207,47 @@ fn get_prach_config_fdd(index: u8) -> Option<PrachConfigurationIndex> {
     }
 }
 
+/// PRACH configuration of a slot holding a PRACH occasion, for FDD
+pub fn prach_occasion(prach_config_index: u8, frame: u32, slot: u8) -> Option<PrachConfigurationIndex> {
+    let config = get_prach_config_fdd(prach_config_index)?;
+    
+    // Check system frame
+    let frame_in_period = frame % config.x;
+    if !config.y.contains(&(frame_in_period as u8)) {
+        return None;
+    }
+    
+    // For FDD, PRACH is in specific subframes
+    // Convert slot to subframe (assuming 15 kHz SCS)
+    let subframe = slot;  // 1 slot per subframe for 15 kHz
+    config.subframe_numbers.contains(&subframe).then_some(config)
+}
+
+/// Cyclic shift N_cs of a zero correlation zone config
+pub fn cyclic_shift(zero_correlation_zone_config: u16) -> u32 {
+    // Simplified - use table lookup in real implementation
+    // For FDD with unrestricted set, common values:
+    match zero_correlation_zone_config {
+        0 => 0,    // N_cs = 0 (no cyclic shift)
+        1 => 13,   // N_cs = 13
+        2 => 15,   // N_cs = 15
+        3 => 18,   // N_cs = 18
+        4 => 22,   // N_cs = 22
+        5 => 26,   // N_cs = 26
+        6 => 32,   // N_cs = 32
+        7 => 38,   // N_cs = 38
+        8 => 46,   // N_cs = 46
+        9 => 59,   // N_cs = 59
+        10 => 76,  // N_cs = 76
+        11 => 93,  // N_cs = 93
+        12 => 119, // N_cs = 119
+        13 => 167, // N_cs = 167
+        14 => 279, // N_cs = 279
+        15 => 419, // N_cs = 419
+        _ => 119,  // Default
+    }
+}
+
 /// RACH configuration common
 #[derive(Debug, Clone)]
 pub struct RachConfigCommon {
@@ -343,47 +384,12 @@ impl PrachDetector {
     
     /// Get cyclic shift value N_cs from zero correlation zone config
     fn get_cyclic_shift(&self) -> u32 {
-        // Simplified - use table lookup in real implementation
-        // For FDD with unrestricted set, common values:
-        match self.rach_config.zero_correlation_zone_config {
-            0 => 0,    // N_cs = 0 (no cyclic shift)
-            1 => 13,   // N_cs = 13
-            2 => 15,   // N_cs = 15
-            3 => 18,   // N_cs = 18
-            4 => 22,   // N_cs = 22
-            5 => 26,   // N_cs = 26
-            6 => 32,   // N_cs = 32
-            7 => 38,   // N_cs = 38
-            8 => 46,   // N_cs = 46
-            9 => 59,   // N_cs = 59
-            10 => 76,  // N_cs = 76
-            11 => 93,  // N_cs = 93
-            12 => 119, // N_cs = 119
-            13 => 167, // N_cs = 167
-            14 => 279, // N_cs = 279
-            15 => 419, // N_cs = 419
-            _ => 119,  // Default
-        }
+        cyclic_shift(self.rach_config.zero_correlation_zone_config)
     }
     
     /// Check if PRACH is scheduled in this slot
     pub fn is_prach_occasion(&self, frame: u32, slot: u8) -> bool {
-        // Get PRACH configuration
-        let config = match get_prach_config_fdd(self.rach_config.prach_config_index) {
-            Some(c) => c,
-            None => return false,
-        };
-        
-        // Check system frame
-        let frame_in_period = frame % config.x;
-        if !config.y.contains(&(frame_in_period as u8)) {
-            return false;
-        }
-        
-        // For FDD, PRACH is in specific subframes
-        // Convert slot to subframe (assuming 15 kHz SCS)
-        let subframe = slot;  // 1 slot per subframe for 15 kHz
-        config.subframe_numbers.contains(&subframe)
+        prach_occasion(self.rach_config.prach_config_index, frame, slot).is_some()
     }
     
     /// Detect PRACH preambles in received samples
@@ -505,6 +505,19 @@ impl ResourceGrid {
         Ok(())
     }
     
+    /// Number of resource blocks of the carrier
+    pub fn num_rbs(&self) -> u16 {
+        self.num_rbs
+    }
+    
+    /// Frequency domain samples of the carrier's subcarriers in a symbol,
+    /// lowest subcarrier first, as a 7.2x O-RU takes them
+    pub fn carrier_symbol(&self, symbol: u8) -> Vec<Complex32> {
+        (0..self.num_subcarriers)
+            .map(|subcarrier| self.get_re(subcarrier, symbol).unwrap_or_default())
+            .collect()
+    }
+    
     /// Convert logical subcarrier index to FFT bin index
     fn subcarrier_to_fft_index(&self, subcarrier: i16) -> usize {
         // DC is at fft_size/2
@@ -643,4 +656,22 @@ mod tests {
             assert_eq!(retrieved, Complex32::new(1.0, 0.0));
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_carrier_symbol() {
+        let mut grid = ResourceGrid::new(
+            2048,
+            14,
+            Bandwidth::Bw20,
+            SubcarrierSpacing::Scs15,
+        ).unwrap();
+        
+        grid.map_re(300, 3, Complex32::new(1.0, 0.0)).unwrap();
+        grid.map_re(900, 3, Complex32::new(0.0, -1.0)).unwrap();
+        
+        let symbol = grid.carrier_symbol(3);
+        assert_eq!(symbol.len(), grid.num_rbs() as usize * 12);
+        assert_eq!((symbol[300], symbol[900]), (Complex32::new(1.0, 0.0), Complex32::new(0.0, -1.0)));
+        assert_eq!(symbol.iter().filter(|re| re.norm_sqr() > 0.0).count(), 2);
+    }
+}
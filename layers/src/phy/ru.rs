@@ -0,0 +1,117 @@
+//! Radio Unit Drivers
+//!
+//! The PHY hands its downlink symbols to the RU selected by `device_driver`:
+//! time domain samples after OFDM modulation to the ZMQ RF driver, or, with
+//! the O-RAN 7.2x split, the frequency domain samples of the carrier's PRBs
+//! to an O-RU, which does the iFFT and cyclic prefix insertion itself. Every
+//! slot is announced to the O-RU by downlink and uplink C-plane messages.
+
+use crate::LayerError;
+use interfaces::oran_fh::{DataDirection, OranFhConfig, OranFronthaul, SymbolTime};
+use interfaces::zmq_rf::ZmqRfConfig;
+use num_complex::Complex32;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, warn};
+
+/// Section ID of the downlink C-plane and U-plane sections
+const DL_SECTION_ID: u16 = 0;
+/// Section ID of the uplink C-plane sections
+const UL_SECTION_ID: u16 = 1;
+
+/// RU the PHY runs against
+#[derive(Debug, Clone)]
+pub enum RuConfig {
+    /// ZMQ RF driver taking time domain samples
+    Zmq(ZmqRfConfig),
+    /// O-RU behind an O-RAN 7.2x fronthaul taking frequency domain samples
+    OranFh(OranFhConfig),
+}
+
+impl RuConfig {
+    /// RU of a device driver name (`zmq` or `oran_fh`) and its device arguments
+    pub fn from_device_driver(driver: &str, device_args: &str) -> Result<Self, LayerError> {
+        let invalid = |e: interfaces::InterfaceError| LayerError::InvalidConfiguration(e.to_string());
+        match driver {
+            "zmq" => Ok(RuConfig::Zmq(ZmqRfConfig::from_device_args(device_args, 1).map_err(invalid)?)),
+            "oran_fh" => Ok(RuConfig::OranFh(OranFhConfig::from_device_args(device_args).map_err(invalid)?)),
+            other => Err(LayerError::InvalidConfiguration(
+                format!("Unknown RU device driver {}, expected zmq or oran_fh", other)
+            )),
+        }
+    }
+}
+
+/// Downlink symbol of the carrier in the frequency domain
+#[derive(Debug, Clone)]
+pub(super) struct FrequencyDomainSymbol {
+    pub(super) frame: u32,
+    pub(super) slot: u8,
+    pub(super) symbol: u8,
+    /// Samples of every PRB, lowest subcarrier first
+    pub(super) iq: Vec<Complex32>,
+}
+
+/// Send the downlink symbols of the PHY to the O-RU, announcing each slot
+/// with C-plane messages on its first symbol
+pub(super) async fn run_fronthaul_tx(
+    fronthaul: Arc<OranFronthaul>,
+    mut symbols: mpsc::Receiver<FrequencyDomainSymbol>,
+) {
+    let numerology = fronthaul.clock().numerology();
+    while let Some(symbol) = symbols.recv().await {
+        let time = SymbolTime::new(symbol.frame, symbol.slot as u32, symbol.symbol, numerology);
+        if symbol.symbol == 0 {
+            for (direction, section_id) in [(DataDirection::Downlink, DL_SECTION_ID), (DataDirection::Uplink, UL_SECTION_ID)] {
+                if let Err(e) = fronthaul.send_cplane(&fronthaul.slot_cplane(direction, time, section_id)).await {
+                    error!("Failed to send {:?} C-plane of frame {} slot {}: {}", direction, symbol.frame, symbol.slot, e);
+                }
+            }
+        }
+        if let Err(e) = fronthaul.send_dl_symbol(time, DL_SECTION_ID, &symbol.iq).await {
+            error!("Failed to send U-plane of frame {} slot {} symbol {}: {}",
+                   symbol.frame, symbol.slot, symbol.symbol, e);
+        }
+    }
+    info!("Fronthaul transmission stopped");
+}
+
+/// Receive the uplink U-plane of the O-RU while the PHY is running
+pub(super) async fn run_fronthaul_rx(fronthaul: Arc<OranFronthaul>, running: Arc<RwLock<bool>>) {
+    while *running.read().await {
+        match fronthaul.recv_uplane().await {
+            Ok((eaxc_id, message)) => {
+                debug!("Uplink U-plane on eAxC {:?} for {:?}: {} sections",
+                       eaxc_id, message.time, message.sections.len());
+            }
+            Err(e) => {
+                warn!("Fronthaul reception stopped: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interfaces::oran_fh::Compression;
+
+    #[test]
+    fn test_ru_from_device_driver() {
+        let RuConfig::Zmq(zmq) = RuConfig::from_device_driver("zmq", "tx_port=tcp://*:2000").unwrap() else {
+            panic!("Expected the ZMQ RF driver");
+        };
+        assert_eq!(zmq.tx_address, "tcp://*:2000");
+
+        let RuConfig::OranFh(oran) = RuConfig::from_device_driver("oran_fh", "ru_addr=127.0.0.1:5000,iq_width=9").unwrap() else {
+            panic!("Expected the O-RAN fronthaul");
+        };
+        assert_eq!(oran.ru_address, "127.0.0.1:5000");
+        assert_eq!(oran.compression, Compression::BlockFloatingPoint { iq_width: 9 });
+
+        assert!(matches!(RuConfig::from_device_driver("uhd", ""), Err(LayerError::InvalidConfiguration(_))));
+        assert!(matches!(RuConfig::from_device_driver("oran_fh", "iq_width=0"),
+                         Err(LayerError::InvalidConfiguration(_))));
+    }
+}
@@ -8,7 +8,7 @@ use bytes::Bytes;
 use tracing::{debug, info};
 
 /// RLC operating modes
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RlcMode {
     /// Transparent Mode
     Tm,
@@ -19,6 +19,7 @@ pub enum RlcMode {
 }
 
 /// RLC layer configuration
+#[derive(Debug, Clone, PartialEq)]
 pub struct RlcConfig {
     /// Operating mode
     pub mode: RlcMode,
@@ -28,10 +29,34 @@ pub struct RlcConfig {
     pub poll_pdu: u32,
 }
 
+/// SDU volumes of an RLC entity since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RlcStats {
+    /// Downlink SDUs handed to the lower layer
+    pub tx_sdus: u64,
+    /// Downlink SDU bytes handed to the lower layer
+    pub tx_bytes: u64,
+    /// Uplink SDUs delivered to the upper layer
+    pub rx_sdus: u64,
+    /// Uplink SDU bytes delivered to the upper layer
+    pub rx_bytes: u64,
+}
+
+impl std::ops::AddAssign for RlcStats {
+    fn add_assign(&mut self, other: Self) {
+        self.tx_sdus += other.tx_sdus;
+        self.tx_bytes += other.tx_bytes;
+        self.rx_sdus += other.rx_sdus;
+        self.rx_bytes += other.rx_bytes;
+    }
+}
+
 /// RLC layer implementation
+#[derive(Debug)]
 pub struct RlcLayer {
     config: RlcConfig,
     initialized: bool,
+    stats: RlcStats,
 }
 
 impl RlcLayer {
@@ -40,8 +65,14 @@ impl RlcLayer {
         Self {
             config,
             initialized: false,
+            stats: RlcStats::default(),
         }
     }
+    
+    /// SDU volumes transferred so far
+    pub fn stats(&self) -> RlcStats {
+        self.stats
+    }
 }
 
 #[async_trait]
@@ -68,6 +99,8 @@ impl ProtocolLayer for RlcLayer {
         }
         
         debug!("RLC processing uplink data: {} bytes", data.len());
+        self.stats.rx_sdus += 1;
+        self.stats.rx_bytes += data.len() as u64;
         
         // TODO: Implement RLC uplink processing based on mode
         match self.config.mode {
@@ -92,6 +125,8 @@ impl ProtocolLayer for RlcLayer {
         }
         
         debug!("RLC processing downlink data: {} bytes", data.len());
+        self.stats.tx_sdus += 1;
+        self.stats.tx_bytes += data.len() as u64;
         
         // TODO: Implement RLC downlink processing based on mode
         match self.config.mode {
@@ -145,4 +180,19 @@ mod tests {
         let mut rlc = RlcLayer::new(config);
         assert!(rlc.initialize().await.is_ok());
     }
+    
+    #[tokio::test]
+    async fn test_rlc_stats() {
+        let mut rlc = RlcLayer::new(RlcConfig {
+            mode: RlcMode::Um,
+            sn_field_length: 12,
+            poll_pdu: 16,
+        });
+        rlc.initialize().await.unwrap();
+        rlc.process_downlink(Bytes::from_static(&[0; 100])).await.unwrap();
+        rlc.process_downlink(Bytes::from_static(&[0; 20])).await.unwrap();
+        rlc.process_uplink(Bytes::from_static(&[0; 40])).await.unwrap();
+        
+        assert_eq!(rlc.stats(), RlcStats { tx_sdus: 2, tx_bytes: 120, rx_sdus: 1, rx_bytes: 40 });
+    }
 }
\ No newline at end of file
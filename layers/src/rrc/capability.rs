@@ -0,0 +1,406 @@
+//! UE Capability Transfer
+//!
+//! Implements the UE capability transfer procedure according to 3GPP TS 38.331 Section 5.6.1
+//! and the UE-NR-Capability contents of TS 38.306
+
+use crate::LayerError;
+use crate::mac::UeSchedulingCapabilities;
+use bytes::{Bytes, BytesMut, BufMut};
+
+/// RAT types that can be requested in UE Capability Enquiry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatType {
+    /// NR
+    Nr = 0,
+    /// E-UTRA
+    Eutra = 1,
+    /// EN-DC / NE-DC band combinations
+    EutraNr = 2,
+}
+
+impl RatType {
+    /// Decode RAT type from its enumerated value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RatType::Nr),
+            1 => Some(RatType::Eutra),
+            2 => Some(RatType::EutraNr),
+            _ => None,
+        }
+    }
+}
+
+/// UE capability container for one RAT (UE-CapabilityRAT-Container)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UeCapabilityRatContainer {
+    /// RAT type
+    pub rat_type: RatType,
+    /// Encoded capability container
+    pub container: Bytes,
+}
+
+/// Per-band physical layer capabilities (BandNR)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandCapability {
+    /// NR band number
+    pub band: u16,
+    /// pdsch-256QAM-FR1 supported
+    pub supports_256qam_dl: bool,
+    /// pusch-256QAM supported
+    pub supports_256qam_ul: bool,
+    /// maxNumberMIMO-LayersPDSCH
+    pub max_mimo_layers_dl: u8,
+    /// maxNumberMIMO-LayersCB-PUSCH
+    pub max_mimo_layers_ul: u8,
+}
+
+/// PDCP capabilities (PDCP-Parameters)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdcpCapability {
+    /// Supported ROHC profiles (bitmap, bit 0 = profile 0x0000)
+    pub supported_rohc_profiles: u16,
+    /// maxNumberROHC-ContextSessions
+    pub max_rohc_context_sessions: u16,
+    /// outOfOrderDelivery supported
+    pub out_of_order_delivery: bool,
+    /// shortSN (12 bit PDCP SN for DRBs) supported
+    pub short_sn: bool,
+    /// pdcp-DuplicationSRB supported
+    pub duplication_srb: bool,
+}
+
+/// RLC capabilities (RLC-Parameters)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RlcCapability {
+    /// am-WithShortSN supported
+    pub am_with_short_sn: bool,
+    /// um-WithShortSN supported
+    pub um_with_short_sn: bool,
+    /// um-WithLongSN supported
+    pub um_with_long_sn: bool,
+}
+
+/// Decoded UE-NR-Capability
+#[derive(Debug, Clone, PartialEq)]
+pub struct UeNrCapability {
+    /// Access stratum release (15 = Rel-15, 16 = Rel-16, ...)
+    pub access_stratum_release: u8,
+    /// PDCP parameters
+    pub pdcp: PdcpCapability,
+    /// RLC parameters
+    pub rlc: RlcCapability,
+    /// Supported band list
+    pub bands: Vec<BandCapability>,
+}
+
+impl UeNrCapability {
+    /// Decode a UE-NR-Capability container
+    ///
+    /// Simplified layout (would be UPER in a real implementation):
+    /// release(1) | rohc profiles(2) | rohc sessions(2) | PDCP flags(1) | RLC flags(1) |
+    /// number of bands(1) | per band: band(2), flags(1), DL layers(1), UL layers(1)
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        if data.len() < 8 {
+            return Err(LayerError::InvalidPdu);
+        }
+
+        let access_stratum_release = data[0];
+        let pdcp_flags = data[5];
+        let pdcp = PdcpCapability {
+            supported_rohc_profiles: u16::from_be_bytes([data[1], data[2]]),
+            max_rohc_context_sessions: u16::from_be_bytes([data[3], data[4]]),
+            out_of_order_delivery: pdcp_flags & 0x01 != 0,
+            short_sn: pdcp_flags & 0x02 != 0,
+            duplication_srb: pdcp_flags & 0x04 != 0,
+        };
+
+        let rlc_flags = data[6];
+        let rlc = RlcCapability {
+            am_with_short_sn: rlc_flags & 0x01 != 0,
+            um_with_short_sn: rlc_flags & 0x02 != 0,
+            um_with_long_sn: rlc_flags & 0x04 != 0,
+        };
+
+        let num_bands = data[7] as usize;
+        let mut idx = 8;
+        if data.len() < idx + num_bands * 5 {
+            return Err(LayerError::InvalidPdu);
+        }
+
+        let mut bands = Vec::with_capacity(num_bands);
+        for _ in 0..num_bands {
+            let flags = data[idx + 2];
+            bands.push(BandCapability {
+                band: u16::from_be_bytes([data[idx], data[idx + 1]]),
+                supports_256qam_dl: flags & 0x01 != 0,
+                supports_256qam_ul: flags & 0x02 != 0,
+                max_mimo_layers_dl: data[idx + 3].max(1),
+                max_mimo_layers_ul: data[idx + 4].max(1),
+            });
+            idx += 5;
+        }
+
+        Ok(Self {
+            access_stratum_release,
+            pdcp,
+            rlc,
+            bands,
+        })
+    }
+
+    /// Encode the capability using the same simplified layout as [`UeNrCapability::decode`]
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(8 + self.bands.len() * 5);
+
+        buf.put_u8(self.access_stratum_release);
+        buf.put_u16(self.pdcp.supported_rohc_profiles);
+        buf.put_u16(self.pdcp.max_rohc_context_sessions);
+        buf.put_u8(
+            (self.pdcp.out_of_order_delivery as u8)
+                | ((self.pdcp.short_sn as u8) << 1)
+                | ((self.pdcp.duplication_srb as u8) << 2),
+        );
+        buf.put_u8(
+            (self.rlc.am_with_short_sn as u8)
+                | ((self.rlc.um_with_short_sn as u8) << 1)
+                | ((self.rlc.um_with_long_sn as u8) << 2),
+        );
+
+        buf.put_u8(self.bands.len() as u8);
+        for band in &self.bands {
+            buf.put_u16(band.band);
+            buf.put_u8((band.supports_256qam_dl as u8) | ((band.supports_256qam_ul as u8) << 1));
+            buf.put_u8(band.max_mimo_layers_dl);
+            buf.put_u8(band.max_mimo_layers_ul);
+        }
+
+        buf.freeze()
+    }
+
+    /// Get capabilities for a specific band
+    pub fn band(&self, band: u16) -> Option<&BandCapability> {
+        self.bands.iter().find(|b| b.band == band)
+    }
+
+    /// Derive the scheduling capabilities for the serving band
+    ///
+    /// Falls back to the most conservative values if the UE did not report the band.
+    pub fn scheduling_capabilities(&self, serving_band: u16) -> UeSchedulingCapabilities {
+        match self.band(serving_band) {
+            Some(band) => UeSchedulingCapabilities {
+                supports_256qam_dl: band.supports_256qam_dl,
+                supports_256qam_ul: band.supports_256qam_ul,
+                max_mimo_layers_dl: band.max_mimo_layers_dl,
+                max_mimo_layers_ul: band.max_mimo_layers_ul,
+            },
+            None => UeSchedulingCapabilities::default(),
+        }
+    }
+}
+
+/// UE Capability Enquiry message
+#[derive(Debug, Clone)]
+pub struct UeCapabilityEnquiry {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// Requested RAT types (ue-CapabilityRAT-RequestList)
+    pub rat_types: Vec<RatType>,
+}
+
+impl UeCapabilityEnquiry {
+    /// Encode UE Capability Enquiry
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(3 + self.rat_types.len());
+
+        buf.put_u8(0x30); // UE Capability Enquiry
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_u8(self.rat_types.len() as u8);
+        for rat_type in &self.rat_types {
+            buf.put_u8(*rat_type as u8);
+        }
+
+        buf.freeze()
+    }
+}
+
+/// UE Capability Information message
+#[derive(Debug, Clone)]
+pub struct UeCapabilityInformation {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// UE capability RAT container list
+    pub containers: Vec<UeCapabilityRatContainer>,
+}
+
+impl UeCapabilityInformation {
+    /// Decode UE Capability Information
+    ///
+    /// Layout: type(1) | transaction id(1) | UE-CapabilityRAT-ContainerList
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        if data.len() < 3 {
+            return Err(LayerError::InvalidPdu);
+        }
+
+        Ok(Self {
+            transaction_id: data[1] & 0x03,
+            containers: decode_container_list(&data[2..])?,
+        })
+    }
+
+    /// Encode UE Capability Information
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        buf.put_u8(0x31); // UE Capability Information
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_slice(&encode_container_list(&self.containers));
+
+        buf.freeze()
+    }
+
+    /// Get the container for a RAT type
+    pub fn container(&self, rat_type: RatType) -> Option<&UeCapabilityRatContainer> {
+        self.containers.iter().find(|c| c.rat_type == rat_type)
+    }
+}
+
+/// Encode a UE-CapabilityRAT-ContainerList
+///
+/// This is also the form exchanged with the AMF as UERadioCapability.
+/// Layout: number of containers(1) | per container: RAT type(1), length(2), container
+pub fn encode_container_list(containers: &[UeCapabilityRatContainer]) -> Bytes {
+    let mut buf = BytesMut::new();
+
+    buf.put_u8(containers.len() as u8);
+    for container in containers {
+        buf.put_u8(container.rat_type as u8);
+        buf.put_u16(container.container.len() as u16);
+        buf.put_slice(&container.container);
+    }
+
+    buf.freeze()
+}
+
+/// Decode a UE-CapabilityRAT-ContainerList
+pub fn decode_container_list(data: &[u8]) -> Result<Vec<UeCapabilityRatContainer>, LayerError> {
+    if data.is_empty() {
+        return Err(LayerError::InvalidPdu);
+    }
+
+    let num_containers = data[0] as usize;
+    let mut idx = 1;
+    let mut containers = Vec::with_capacity(num_containers);
+
+    for _ in 0..num_containers {
+        if data.len() < idx + 3 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let rat_type = RatType::from_u8(data[idx]).ok_or(LayerError::InvalidPdu)?;
+        let len = u16::from_be_bytes([data[idx + 1], data[idx + 2]]) as usize;
+        idx += 3;
+
+        if data.len() < idx + len {
+            return Err(LayerError::InvalidPdu);
+        }
+        containers.push(UeCapabilityRatContainer {
+            rat_type,
+            container: Bytes::copy_from_slice(&data[idx..idx + len]),
+        });
+        idx += len;
+    }
+
+    Ok(containers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_capability() -> UeNrCapability {
+        UeNrCapability {
+            access_stratum_release: 15,
+            pdcp: PdcpCapability {
+                supported_rohc_profiles: 0x0003,
+                max_rohc_context_sessions: 16,
+                out_of_order_delivery: true,
+                short_sn: true,
+                duplication_srb: false,
+            },
+            rlc: RlcCapability {
+                am_with_short_sn: true,
+                um_with_short_sn: true,
+                um_with_long_sn: true,
+            },
+            bands: vec![
+                BandCapability {
+                    band: 3,
+                    supports_256qam_dl: false,
+                    supports_256qam_ul: false,
+                    max_mimo_layers_dl: 2,
+                    max_mimo_layers_ul: 1,
+                },
+                BandCapability {
+                    band: 78,
+                    supports_256qam_dl: true,
+                    supports_256qam_ul: false,
+                    max_mimo_layers_dl: 4,
+                    max_mimo_layers_ul: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_nr_capability_roundtrip() {
+        let capability = test_capability();
+        let decoded = UeNrCapability::decode(&capability.encode()).unwrap();
+        assert_eq!(decoded, capability);
+
+        let caps = decoded.scheduling_capabilities(78);
+        assert!(caps.supports_256qam_dl);
+        assert_eq!(caps.max_mimo_layers_dl, 4);
+
+        // Band not reported: conservative defaults
+        let caps = decoded.scheduling_capabilities(1);
+        assert!(!caps.supports_256qam_dl);
+        assert_eq!(caps.max_mimo_layers_dl, 1);
+    }
+
+    #[test]
+    fn test_capability_information_decode() {
+        let info = UeCapabilityInformation {
+            transaction_id: 2,
+            containers: vec![UeCapabilityRatContainer {
+                rat_type: RatType::Nr,
+                container: test_capability().encode(),
+            }],
+        };
+
+        let decoded = UeCapabilityInformation::decode(&info.encode()).unwrap();
+        assert_eq!(decoded.transaction_id, 2);
+        assert!(decoded.container(RatType::Nr).is_some());
+        assert!(decoded.container(RatType::EutraNr).is_none());
+
+        // Truncated container
+        let encoded = info.encode();
+        assert!(UeCapabilityInformation::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_capability_decode_errors() {
+        let encoded = test_capability().encode();
+        assert!(matches!(UeNrCapability::decode(&encoded[..7]), Err(LayerError::InvalidPdu)));
+        // A band announced but missing
+        assert!(matches!(UeNrCapability::decode(&encoded[..encoded.len() - 1]), Err(LayerError::InvalidPdu)));
+
+        assert!(matches!(decode_container_list(&[]), Err(LayerError::InvalidPdu)));
+        // Unknown RAT type
+        assert!(matches!(decode_container_list(&[1, 7, 0, 1, 0xAA]), Err(LayerError::InvalidPdu)));
+        // Container header and content cut short
+        assert!(matches!(decode_container_list(&[1, 0, 0]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(decode_container_list(&[1, 0, 0, 2, 0xAA]), Err(LayerError::InvalidPdu)));
+        assert!(decode_container_list(&[0]).unwrap().is_empty());
+
+        assert!(matches!(UeCapabilityInformation::decode(&[0x31, 0x01]), Err(LayerError::InvalidPdu)));
+    }
+}
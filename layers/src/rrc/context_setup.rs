@@ -0,0 +1,246 @@
+//! Initial UE context setup
+//!
+//! Drives the RRC side of the NGAP Initial Context Setup procedure: AS security
+//! activation with Security Mode Command (3GPP TS 38.331 Section 5.3.4), UE
+//! capability transfer and the setup of the initial PDU sessions. Also applies
+//! the security updates of the NGAP UE Context Modification procedure
+
+use super::reconfiguration::{PduSessionProcedure, PduSessionResource};
+use super::security::{self, SecurityContext, SecurityModeCommand};
+use super::{RrcLayer, RrcMessageType, RrcNgapMessage, RrcReleaseCause};
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::Rnti;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Initial Context Setup waiting for the UE to activate AS security
+#[derive(Debug, Clone)]
+pub struct PendingContextSetup {
+    /// Transaction ID of the Security Mode Command
+    pub transaction_id: u8,
+    /// PDU sessions to set up once security is active
+    pub sessions: Vec<PduSessionResource>,
+    /// NAS PDU to deliver to the UE
+    pub nas_pdu: Option<Bytes>,
+    /// UE radio capability provided by the AMF
+    pub ue_radio_capability: Option<Bytes>,
+    /// Guard timer expiry
+    pub deadline: Instant,
+}
+
+impl RrcLayer {
+    /// Handle Initial Context Setup from NGAP by starting AS security activation
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn handle_initial_context_setup(
+        &mut self,
+        ue_id: u32,
+        security_key: [u8; 32],
+        nr_encryption_algorithms: u16,
+        nr_integrity_algorithms: u16,
+        sessions: Vec<PduSessionResource>,
+        nas_pdu: Option<Bytes>,
+        ue_radio_capability: Option<Bytes>,
+    ) -> Result<(), LayerError> {
+        let rnti = match self.connected_rnti(ue_id).await {
+            Ok(rnti) => rnti,
+            Err(e) => {
+                self.send_context_setup_failure(ue_id, RrcReleaseCause::FailureInRadioInterfaceProcedure).await;
+                return Err(e);
+            }
+        };
+
+        let Some((integrity_algorithm, ciphering_algorithm)) =
+            security::select_algorithms(nr_encryption_algorithms, nr_integrity_algorithms) else {
+            warn!("No common security algorithms with UE {} (NEA {:#06x}, NIA {:#06x})",
+                  ue_id, nr_encryption_algorithms, nr_integrity_algorithms);
+            self.send_context_setup_failure(ue_id, RrcReleaseCause::AlgorithmsNotSupported).await;
+            return Ok(());
+        };
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.pending_context_setup.is_some() || ue_context.security.is_some() {
+            return Err(LayerError::InvalidState(format!("Security already activated for UE {}", ue_id)));
+        }
+        let transaction_id = ue_context.allocate_transaction_id();
+        ue_context.security = Some(SecurityContext::new(security_key, integrity_algorithm, ciphering_algorithm));
+        ue_context.pending_context_setup = Some(PendingContextSetup {
+            transaction_id,
+            sessions,
+            nas_pdu,
+            ue_radio_capability,
+            deadline: Instant::now() + Duration::from_millis(self.config.procedure_guard_time_ms as u64),
+        });
+        drop(contexts);
+
+        let command = SecurityModeCommand {
+            transaction_id,
+            ciphering_algorithm,
+            integrity_algorithm,
+        };
+        info!("Sending Security Mode Command to RNTI {}: {:?}/{:?}", rnti.0, ciphering_algorithm, integrity_algorithm);
+        self.send_to_mac(rnti, RrcMessageType::SecurityModeCommand, command.encode()).await
+    }
+
+    /// Handle Security Mode Complete and continue the Initial Context Setup
+    pub(super) async fn handle_security_mode_complete(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let Some(pending) = self.take_pending_context_setup(rnti, &data).await? else {
+            return Ok(());
+        };
+        let (ue_id, capability_known) = {
+            let contexts = self.ue_contexts.lock().await;
+            let ue_context = contexts.get(&rnti.0)
+                .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+            (ue_context.ue_id, ue_context.ue_capability.is_some())
+        };
+        info!("AS security activated for UE {} (RNTI {})", ue_id, rnti.0);
+
+        if !capability_known {
+            if let Err(e) = self.start_ue_capability_transfer(rnti, pending.ue_radio_capability).await {
+                warn!("UE capability transfer for RNTI {} failed: {}", rnti.0, e);
+            }
+        }
+
+        // The Initial Context Setup Response is sent once the PDU sessions are handled
+        self.setup_pdu_sessions(ue_id, pending.sessions, pending.nas_pdu, PduSessionProcedure::InitialContextSetup).await
+    }
+
+    /// Handle Security Mode Failure: security is not activated and the setup fails
+    pub(super) async fn handle_security_mode_failure(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        if self.take_pending_context_setup(rnti, &data).await?.is_none() {
+            return Ok(());
+        }
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_id = match contexts.get_mut(&rnti.0) {
+            Some(ue_context) => {
+                ue_context.security = None;
+                ue_context.ue_id
+            }
+            None => return Err(LayerError::InvalidState("No UE context".into())),
+        };
+        drop(contexts);
+
+        warn!("Security Mode Failure from RNTI {}", rnti.0);
+        self.send_context_setup_failure(ue_id, RrcReleaseCause::FailureInRadioInterfaceProcedure).await;
+        Ok(())
+    }
+
+    /// Handle Security Mode Command guard timer expiry
+    pub(super) async fn check_context_setup_timers(&mut self, now: Instant) {
+        let mut expired = Vec::new();
+
+        let mut contexts = self.ue_contexts.lock().await;
+        for ue_context in contexts.values_mut() {
+            if ue_context.pending_context_setup.as_ref().is_some_and(|pending| pending.deadline <= now) {
+                warn!("Security Mode Command guard timer expired for RNTI {}", ue_context.c_rnti.0);
+                ue_context.pending_context_setup = None;
+                ue_context.security = None;
+                expired.push(ue_context.ue_id);
+            }
+        }
+        drop(contexts);
+
+        for ue_id in expired {
+            self.send_context_setup_failure(ue_id, RrcReleaseCause::FailureInRadioInterfaceProcedure).await;
+        }
+    }
+
+    /// Handle UE Context Modification from NGAP: refresh K_gNB and check the
+    /// updated UE security capabilities against the algorithms in use
+    pub(super) async fn handle_ue_context_modification(
+        &mut self,
+        ue_id: u32,
+        security_key: Option<[u8; 32]>,
+        nr_encryption_algorithms: Option<u16>,
+        nr_integrity_algorithms: Option<u16>,
+    ) -> Result<(), LayerError> {
+        let rnti = match self.connected_rnti(ue_id).await {
+            Ok(rnti) => rnti,
+            Err(e) => {
+                self.send_context_modification_outcome(ue_id, Some(RrcReleaseCause::FailureInRadioInterfaceProcedure)).await;
+                return Err(e);
+            }
+        };
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let capabilities_updated = nr_encryption_algorithms.is_some() || nr_integrity_algorithms.is_some();
+        let failure = match ue_context.security.as_mut() {
+            None if security_key.is_some() || capabilities_updated => {
+                warn!("UE Context Modification for UE {} without active AS security", ue_id);
+                Some(RrcReleaseCause::FailureInRadioInterfaceProcedure)
+            }
+            None => None,
+            Some(context) if capabilities_updated && !security::algorithms_supported(
+                context,
+                nr_encryption_algorithms.unwrap_or(u16::MAX),
+                nr_integrity_algorithms.unwrap_or(u16::MAX),
+            ) => {
+                warn!("Algorithms in use ({:?}/{:?}) no longer supported by UE {}",
+                      context.ciphering_algorithm, context.integrity_algorithm, ue_id);
+                Some(RrcReleaseCause::AlgorithmsNotSupported)
+            }
+            Some(context) => {
+                if let Some(security_key) = security_key {
+                    info!("Refreshing K_gNB for UE {} (RNTI {})", ue_id, rnti.0);
+                    *context = SecurityContext::new(security_key, context.integrity_algorithm, context.ciphering_algorithm);
+                }
+                None
+            }
+        };
+        drop(contexts);
+
+        self.send_context_modification_outcome(ue_id, failure).await;
+        Ok(())
+    }
+
+    /// Take the pending Initial Context Setup matching the transaction ID of a
+    /// Security Mode Complete/Failure
+    async fn take_pending_context_setup(&mut self, rnti: Rnti, data: &[u8]) -> Result<Option<PendingContextSetup>, LayerError> {
+        if data.len() < 2 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let transaction_id = data[1] & 0x03;
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        match ue_context.pending_context_setup.take() {
+            Some(pending) if pending.transaction_id == transaction_id => Ok(Some(pending)),
+            other => {
+                warn!("Unexpected security mode response from RNTI {} (transaction ID {})", rnti.0, transaction_id);
+                ue_context.pending_context_setup = other;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Report the outcome of a UE Context Modification to NGAP
+    async fn send_context_modification_outcome(&self, ue_id: u32, failure: Option<RrcReleaseCause>) {
+        let message = match failure {
+            Some(cause) => RrcNgapMessage::UeContextModificationFailure { ue_id, cause },
+            None => RrcNgapMessage::UeContextModificationResponse { ue_id },
+        };
+        if let Some(ngap_tx) = &self.ngap_tx {
+            if let Err(e) = ngap_tx.send(message).await {
+                error!("Failed to send UE Context Modification outcome to NGAP: {}", e);
+            }
+        } else {
+            debug!("No NGAP channel configured, UE Context Modification outcome not sent");
+        }
+    }
+
+    /// Report a failed Initial Context Setup to NGAP
+    async fn send_context_setup_failure(&self, ue_id: u32, cause: RrcReleaseCause) {
+        if let Some(ngap_tx) = &self.ngap_tx {
+            if let Err(e) = ngap_tx.send(RrcNgapMessage::InitialContextSetupFailure { ue_id, cause }).await {
+                error!("Failed to send Initial Context Setup failure to NGAP: {}", e);
+            }
+        } else {
+            debug!("No NGAP channel configured, Initial Context Setup failure not sent");
+        }
+    }
+}
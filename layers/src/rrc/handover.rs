@@ -0,0 +1,655 @@
+//! Handover between gNBs
+//!
+//! RRC side of the NG-RAN handover of 3GPP TS 38.300 section 9.2.3. The source
+//! gNB decides on a handover from an A3 Measurement Report, passes the UE
+//! context to the target in a HandoverPreparationInformation and forwards the
+//! target's RRCReconfiguration with reconfigurationWithSync to the UE (TS 38.331
+//! section 5.3.5.5). The target admits the UE with new DRBs, buffers the
+//! downlink data forwarded by the source and completes the handover when the UE
+//! sends RRCReconfigurationComplete on its new C-RNTI.
+
+use super::capability;
+use super::reconfiguration::{self, DrbBearerConfig, PendingReconfiguration, RrcReconfiguration};
+use super::security::{self, CipheringAlgorithm, IntegrityAlgorithm, SecurityContext};
+use super::{
+    PduSessionResource, RrcLayer, RrcMessageType, RrcNgapMessage, RrcReleaseCause, RrcState, UeContext,
+};
+use crate::sdap::SdapEntity;
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::{Rnti, SNssai};
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Time the source waits for the target to admit the UE (TXnRELOCprep)
+pub const HANDOVER_PREPARATION_TIME_MS: u64 = 2000;
+
+/// Time for the UE to reach the target: the source waits this long for the UE
+/// context release, the target for the UE (TXnRELOCoverall)
+pub const HANDOVER_OVERALL_TIME_MS: u64 = 5000;
+
+/// T304 signalled in reconfigurationWithSync, in ms
+pub const T304_MS: u16 = 1000;
+
+/// Forwarded downlink packets the target buffers per UE until the UE arrives
+pub const MAX_FORWARDED_PACKETS: usize = 1024;
+
+/// Target cell access of an RRC Reconfiguration for handover
+///
+/// reconfigurationWithSync of the SpCell together with the security
+/// algorithms and the NCC of the target (securityAlgorithmConfig and
+/// masterKeyUpdate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconfigurationWithSync {
+    /// Physical cell ID of the target cell
+    pub physical_cell_id: u16,
+    /// C-RNTI of the UE in the target cell
+    pub new_ue_identity: Rnti,
+    /// T304 in ms
+    pub t304_ms: u16,
+    /// Integrity algorithm of the target
+    pub integrity_algorithm: IntegrityAlgorithm,
+    /// Ciphering algorithm of the target
+    pub ciphering_algorithm: CipheringAlgorithm,
+    /// Next hop chaining count the UE derives K_gNB from
+    pub next_hop_chaining_count: u8,
+}
+
+impl ReconfigurationWithSync {
+    /// Encode as part of an RRC Reconfiguration
+    ///
+    /// Layout: pci(2) | newUE-Identity(2) | t304(2) | ciphering(1) | integrity(1) | NCC(1)
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16(self.physical_cell_id);
+        buf.put_u16(self.new_ue_identity.0);
+        buf.put_u16(self.t304_ms);
+        buf.put_u8(self.ciphering_algorithm as u8);
+        buf.put_u8(self.integrity_algorithm as u8);
+        buf.put_u8(self.next_hop_chaining_count & 0x07);
+    }
+
+    /// Decode from an RRC Reconfiguration
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        if buf.remaining() < 9 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let physical_cell_id = buf.get_u16();
+        let new_ue_identity = Rnti(buf.get_u16());
+        let t304_ms = buf.get_u16();
+        let ciphering_algorithm = match buf.get_u8() {
+            0 => CipheringAlgorithm::Nea0,
+            1 => CipheringAlgorithm::Nea1,
+            2 => CipheringAlgorithm::Nea2,
+            3 => CipheringAlgorithm::Nea3,
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        let integrity_algorithm = match buf.get_u8() {
+            0 => IntegrityAlgorithm::Nia0,
+            1 => IntegrityAlgorithm::Nia1,
+            2 => IntegrityAlgorithm::Nia2,
+            3 => IntegrityAlgorithm::Nia3,
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        Ok(Self {
+            physical_cell_id,
+            new_ue_identity,
+            t304_ms,
+            integrity_algorithm,
+            ciphering_algorithm,
+            next_hop_chaining_count: buf.get_u8() & 0x07,
+        })
+    }
+}
+
+/// UE context passed from the source to the target RRC (HandoverPreparationInformation)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandoverPreparationInformation {
+    /// Encoded UE-CapabilityRAT-ContainerList
+    pub ue_radio_capability: Option<Bytes>,
+    /// Radio configuration of the UE in the source cell
+    pub source_config: RrcReconfiguration,
+    /// Physical cell ID of the source cell
+    pub source_pci: u16,
+    /// C-RNTI of the UE in the source cell
+    pub source_c_rnti: Rnti,
+}
+
+impl HandoverPreparationInformation {
+    /// Encode HandoverPreparationInformation
+    ///
+    /// Layout: source pci(2) | source C-RNTI(2) | capability length(2) | capability | sourceConfig
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u16(self.source_pci);
+        buf.put_u16(self.source_c_rnti.0);
+        let capability = self.ue_radio_capability.as_deref().unwrap_or_default();
+        buf.put_u16(capability.len() as u16);
+        buf.put_slice(capability);
+        buf.put_slice(&self.source_config.encode());
+        buf.freeze()
+    }
+
+    /// Decode HandoverPreparationInformation
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        if buf.remaining() < 6 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let source_pci = buf.get_u16();
+        let source_c_rnti = Rnti(buf.get_u16());
+        let len = buf.get_u16() as usize;
+        if buf.remaining() < len {
+            return Err(LayerError::InvalidPdu);
+        }
+        let ue_radio_capability = (len > 0).then(|| Bytes::copy_from_slice(&buf[..len]));
+        buf.advance(len);
+        Ok(Self {
+            ue_radio_capability,
+            source_config: RrcReconfiguration::decode(buf)?,
+            source_pci,
+            source_c_rnti,
+        })
+    }
+}
+
+/// PDCP COUNTs of a DRB handed over (SN Status Transfer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrbSnStatus {
+    /// DRB identity
+    pub drb_id: u8,
+    /// COUNT of the first missing uplink SDU
+    pub ul_count: u32,
+    /// COUNT the target assigns to the next new downlink SDU
+    pub dl_count: u32,
+}
+
+/// Downlink packet forwarded by the source, held until the UE reaches the target
+#[derive(Debug, Clone)]
+pub struct ForwardedPacket {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// QoS flow of the packet
+    pub qfi: Option<u8>,
+    /// Reflective QoS indication
+    pub rqi: bool,
+    /// User packet
+    pub data: Bytes,
+}
+
+/// Handover a UE context takes part in
+#[derive(Debug)]
+pub enum HandoverState {
+    /// Source: Handover Required sent, waiting for the target to admit the UE
+    Preparing {
+        /// Target cell
+        target_pci: u16,
+        /// TXnRELOCprep expiry
+        deadline: Instant,
+    },
+    /// Source: UE commanded to the target, waiting for the UE context release
+    Executing {
+        /// Target cell
+        target_pci: u16,
+        /// TXnRELOCoverall expiry
+        deadline: Instant,
+    },
+    /// Target: UE admitted, waiting for it to access the cell
+    Admitted {
+        /// Downlink data forwarded by the source
+        buffered: Vec<ForwardedPacket>,
+        /// TXnRELOCoverall expiry
+        deadline: Instant,
+    },
+}
+
+impl RrcLayer {
+    /// Hand a connected UE over to a neighbour cell on request of a RAN
+    /// controller rather than on a measurement report
+    ///
+    /// The neighbour is assumed on the SSB frequency of the UE's first
+    /// measurement object, the serving frequency.
+    pub async fn handover_ue(&mut self, ue_id: u32, target_pci: u16) -> Result<(), LayerError> {
+        if target_pci == self.config.pci {
+            return Err(LayerError::InvalidState(format!("PCI {} is the serving cell", target_pci)));
+        }
+        let contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.values().find(|ue_context| ue_context.ue_id == ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("No UE context for UE {}", ue_id)))?;
+        let rnti = ue_context.c_rnti;
+        let ssb_frequency = ue_context.meas_config.as_ref()
+            .and_then(|config| config.meas_objects.first())
+            .map(|object| object.ssb_frequency)
+            .ok_or_else(|| LayerError::InvalidState(format!("No measurement configuration for UE {}", ue_id)))?;
+        drop(contexts);
+
+        self.start_handover(rnti, target_pci, ssb_frequency).await
+    }
+
+    /// Start a handover of a connected UE towards a neighbour cell
+    ///
+    /// The target is identified by the PCI and SSB ARFCN the UE reported;
+    /// NGAP finds the neighbour gNB serving it.
+    pub async fn start_handover(&mut self, rnti: Rnti, target_pci: u16, ssb_arfcn: u32) -> Result<(), LayerError> {
+        if self.ngap_tx.is_none() {
+            debug!("No NGAP channel configured, no handover of RNTI {}", rnti.0);
+            return Ok(());
+        }
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.state != RrcState::Connected || ue_context.handover.is_some()
+            || ue_context.pending_reconfiguration.is_some() || ue_context.release_requested_at.is_some() {
+            return Err(LayerError::InvalidState(format!("RNTI {} cannot be handed over now", rnti.0)));
+        }
+        let security = ue_context.security.as_ref()
+            .ok_or_else(|| LayerError::InvalidState(format!("AS security not active for RNTI {}", rnti.0)))?;
+        let security_key = security::derive_k_gnb_star(&security.k_gnb, target_pci, ssb_arfcn);
+        let next_hop_chaining_count = security.next_hop_chaining_count;
+
+        let mut source_config = RrcReconfiguration {
+            meas_config: ue_context.meas_config.clone(),
+            ..Default::default()
+        };
+        let mut drbs: Vec<_> = ue_context.drbs.values().collect();
+        drbs.sort_by_key(|drb| drb.drb_id);
+        for drb in drbs {
+            let (drb_to_add, rlc_bearer) = reconfiguration::drb_configuration(drb.drb_id, drb.pdu_session_id, &drb.qos_flows);
+            source_config.drbs_to_add_mod.push(drb_to_add);
+            source_config.rlc_bearers_to_add_mod.push(rlc_bearer);
+        }
+        let preparation = HandoverPreparationInformation {
+            ue_radio_capability: ue_context.ue_radio_capability.clone(),
+            source_config,
+            source_pci: self.config.pci,
+            source_c_rnti: rnti,
+        };
+        ue_context.handover = Some(HandoverState::Preparing {
+            target_pci,
+            deadline: Instant::now() + Duration::from_millis(HANDOVER_PREPARATION_TIME_MS),
+        });
+        let ue_id = ue_context.ue_id;
+        drop(contexts);
+
+        info!("Starting handover of UE {} (RNTI {}) to PCI {}", ue_id, rnti.0, target_pci);
+        self.send_to_ngap(RrcNgapMessage::HandoverRequired {
+            ue_id,
+            target_pci,
+            security_key,
+            next_hop_chaining_count,
+            rrc_container: preparation.encode(),
+        }).await;
+        Ok(())
+    }
+
+    /// Handle the target's handover command: send it to the UE and pass the
+    /// PDCP COUNTs of the DRBs on to the target
+    pub(super) async fn handle_handover_command(&mut self, ue_id: u32, rrc_container: Bytes) -> Result<(), LayerError> {
+        let command = RrcReconfiguration::decode(&rrc_container)?;
+        let Some(with_sync) = command.reconfiguration_with_sync else {
+            return Err(LayerError::ProcessingError("Handover command without reconfigurationWithSync".into()));
+        };
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.values_mut()
+            .find(|ctx| ctx.ue_id == ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown UE {}", ue_id)))?;
+        let Some(HandoverState::Preparing { target_pci, .. }) = ue_context.handover else {
+            return Err(LayerError::InvalidState(format!("No handover in preparation for UE {}", ue_id)));
+        };
+        ue_context.handover = Some(HandoverState::Executing {
+            target_pci,
+            deadline: Instant::now() + Duration::from_millis(HANDOVER_OVERALL_TIME_MS),
+        });
+        let rnti = ue_context.c_rnti;
+        let mut drbs = Vec::with_capacity(ue_context.drbs.len());
+        for drb in ue_context.drbs.values() {
+            let (dl_count, ul_count) = drb.pdcp.lock().await.counts();
+            drbs.push(DrbSnStatus { drb_id: drb.drb_id, ul_count, dl_count });
+        }
+        drbs.sort_by_key(|status| status.drb_id);
+        drop(contexts);
+
+        info!("Commanding UE {} (RNTI {}) to PCI {} as C-RNTI {}",
+              ue_id, rnti.0, with_sync.physical_cell_id, with_sync.new_ue_identity.0);
+        self.send_to_mac(rnti, RrcMessageType::RrcReconfiguration, rrc_container).await?;
+        self.send_to_ngap(RrcNgapMessage::SnStatusTransfer { ue_id, drbs }).await;
+        Ok(())
+    }
+
+    /// Handle a handover the target refused or that could not be prepared
+    pub(super) async fn handle_handover_preparation_failure(&mut self, ue_id: u32) -> Result<(), LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.values_mut()
+            .find(|ctx| ctx.ue_id == ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown UE {}", ue_id)))?;
+        if matches!(ue_context.handover, Some(HandoverState::Preparing { .. })) {
+            warn!("Handover preparation of UE {} failed, UE stays in the cell", ue_id);
+            ue_context.handover = None;
+        }
+        Ok(())
+    }
+
+    /// Admit a UE handed over from another gNB
+    ///
+    /// The UE gets a C-RNTI and DRBs with the IDs it used in the source cell;
+    /// the returned RRC Reconfiguration is the handover command the source
+    /// sends to the UE. The result goes to NGAP as Handover Request
+    /// Acknowledge or Handover Failure.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn handle_handover_request(
+        &mut self,
+        handover_id: u32,
+        security_key: [u8; 32],
+        next_hop_chaining_count: u8,
+        nr_encryption_algorithms: u16,
+        nr_integrity_algorithms: u16,
+        sessions: Vec<PduSessionResource>,
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        let active_contexts = self.ue_contexts.lock().await.len();
+        if active_contexts >= self.config.max_ue_contexts as usize {
+            warn!("Maximum number of UE contexts ({}) reached, refusing handover {}",
+                  self.config.max_ue_contexts, handover_id);
+            return self.send_handover_failure(handover_id, RrcReleaseCause::NoRadioResources).await;
+        }
+        let Some((integrity_algorithm, ciphering_algorithm)) =
+            security::select_algorithms(nr_encryption_algorithms, nr_integrity_algorithms) else {
+            warn!("No common security algorithms for handover {}", handover_id);
+            return self.send_handover_failure(handover_id, RrcReleaseCause::AlgorithmsNotSupported).await;
+        };
+        let preparation = match HandoverPreparationInformation::decode(&rrc_container) {
+            Ok(preparation) => preparation,
+            Err(e) => {
+                warn!("Invalid HandoverPreparationInformation in handover {}: {}", handover_id, e);
+                return self.send_handover_failure(handover_id, RrcReleaseCause::FailureInRadioInterfaceProcedure).await;
+            }
+        };
+        let mac_interface = self.mac_interface.clone()
+            .ok_or_else(|| LayerError::ConfigurationError("No MAC interface".into()))?;
+        let rnti = match mac_interface.allocate_c_rnti().await {
+            Ok(rnti) => rnti,
+            Err(e) => {
+                warn!("No C-RNTI for handover {}: {}", handover_id, e);
+                return self.send_handover_failure(handover_id, RrcReleaseCause::NoRadioResources).await;
+            }
+        };
+
+        let mut ue_id_guard = self.next_ue_id.lock().await;
+        let ue_id = *ue_id_guard;
+        *ue_id_guard += 1;
+        drop(ue_id_guard);
+
+        let mut ue_context = UeContext::new(ue_id, rnti);
+        let mut command = RrcReconfiguration {
+            transaction_id: ue_context.allocate_transaction_id(),
+            meas_config: self.config.meas_config.clone(),
+            ..Default::default()
+        };
+        let mut admitted = Vec::new();
+        let mut failed = Vec::new();
+        for session in sessions {
+            // Keep the DRB IDs of the source configuration for the QoS flows
+            let drb_id = preparation.source_config.drbs_to_add_mod.iter()
+                .filter_map(|drb| drb.sdap_config.as_ref().map(|sdap| (drb.drb_id, sdap)))
+                .find(|(_, sdap)| sdap.pdu_session_id == session.pdu_session_id)
+                .map(|(drb_id, _)| drb_id)
+                .filter(|drb_id| !ue_context.drbs.contains_key(drb_id))
+                .or_else(|| (1..=reconfiguration::MAX_DRB_ID).find(|id| !ue_context.drbs.contains_key(id)));
+            let Some(drb_id) = drb_id else {
+                failed.push(session.pdu_session_id);
+                continue;
+            };
+            let (drb, mut drb_to_add, rlc_bearer) =
+                match reconfiguration::establish_drb(drb_id, session.pdu_session_id, session.qos_flows, session.s_nssai).await {
+                    Ok(drb) => drb,
+                    Err(e) => {
+                        warn!("Failed to establish DRB {} for handover {}: {}", drb_id, handover_id, e);
+                        failed.push(session.pdu_session_id);
+                        continue;
+                    }
+                };
+            if let Some(sdap_config) = &drb_to_add.sdap_config {
+                ue_context.sdap_entities.entry(session.pdu_session_id)
+                    .or_insert_with(|| SdapEntity::new(session.pdu_session_id))
+                    .configure_drb(drb_id, sdap_config);
+            }
+            drb_to_add.reestablish_pdcp = true;
+            command.drbs_to_add_mod.push(drb_to_add);
+            command.rlc_bearers_to_add_mod.push(rlc_bearer);
+            ue_context.drbs.insert(drb_id, drb);
+            admitted.push(session.pdu_session_id);
+        }
+        if admitted.is_empty() && !failed.is_empty() {
+            if let Err(e) = mac_interface.release_ue(rnti).await {
+                warn!("Failed to release C-RNTI {}: {}", rnti.0, e);
+            }
+            return self.send_handover_failure(handover_id, RrcReleaseCause::NoRadioResources).await;
+        }
+
+        command.reconfiguration_with_sync = Some(ReconfigurationWithSync {
+            physical_cell_id: self.config.pci,
+            new_ue_identity: rnti,
+            t304_ms: T304_MS,
+            integrity_algorithm,
+            ciphering_algorithm,
+            next_hop_chaining_count,
+        });
+        let mut security = SecurityContext::new(security_key, integrity_algorithm, ciphering_algorithm);
+        security.next_hop_chaining_count = next_hop_chaining_count;
+        ue_context.security = Some(security);
+        ue_context.meas_config = command.meas_config.clone();
+        ue_context.pending_reconfiguration = Some(PendingReconfiguration {
+            transaction_id: command.transaction_id,
+            procedure: None,
+            pdu_session_ids: admitted.clone(),
+            failed_pdu_session_ids: failed.clone(),
+            deadline: Instant::now() + Duration::from_millis(HANDOVER_OVERALL_TIME_MS),
+        });
+        ue_context.handover = Some(HandoverState::Admitted {
+            buffered: Vec::new(),
+            deadline: Instant::now() + Duration::from_millis(HANDOVER_OVERALL_TIME_MS),
+        });
+        let drb_slices: HashMap<u8, SNssai> = ue_context.drbs.values()
+            .map(|drb| (drb.drb_id, drb.s_nssai.clone()))
+            .collect();
+        self.ue_contexts.lock().await.insert(rnti.0, ue_context);
+
+        info!("Admitted UE {} from PCI {} (C-RNTI {}) as C-RNTI {}: PDU sessions {:?}",
+              ue_id, preparation.source_pci, preparation.source_c_rnti.0, rnti.0, admitted);
+        if let Some(ue_radio_capability) = preparation.ue_radio_capability {
+            match capability::decode_container_list(&ue_radio_capability) {
+                Ok(containers) => {
+                    if let Err(e) = self.store_ue_capabilities(rnti, containers, ue_radio_capability, false).await {
+                        warn!("Failed to apply the UE capabilities of UE {}: {}", ue_id, e);
+                    }
+                }
+                Err(e) => warn!("Invalid UE capabilities for UE {}: {}", ue_id, e),
+            }
+        }
+        let to_setup = command.rlc_bearers_to_add_mod.iter()
+            .map(|bearer| DrbBearerConfig {
+                drb_id: bearer.drb_id,
+                lcid: bearer.logical_channel_id,
+                rlc_config: bearer.rlc_config.clone(),
+                qos_flows: command.drbs_to_add_mod.iter()
+                    .find(|drb| drb.drb_id == bearer.drb_id)
+                    .and_then(|drb| drb.sdap_config.as_ref())
+                    .map(|sdap| sdap.mapped_qos_flows_to_add.clone())
+                    .unwrap_or_default(),
+                ul_tunnel: None,
+                s_nssai: drb_slices.get(&bearer.drb_id).cloned(),
+            })
+            .collect::<Vec<_>>();
+        if !to_setup.is_empty() {
+            if let Err(e) = mac_interface.configure_drbs(rnti, to_setup, Vec::new()).await {
+                warn!("Failed to configure the DRB logical channels of RNTI {}: {}", rnti.0, e);
+            }
+        }
+
+        self.send_to_ngap(RrcNgapMessage::HandoverRequestAcknowledge {
+            handover_id,
+            ue_id,
+            admitted,
+            failed,
+            rrc_container: command.encode(),
+        }).await;
+        Ok(())
+    }
+
+    /// Continue the PDCP COUNTs of the source on the DRBs of a UE handed over
+    pub(super) async fn handle_sn_status_transfer(&mut self, ue_id: u32, drbs: Vec<DrbSnStatus>) -> Result<(), LayerError> {
+        let contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.values()
+            .find(|ctx| ctx.ue_id == ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown UE {}", ue_id)))?;
+        for status in drbs {
+            match ue_context.drbs.get(&status.drb_id) {
+                Some(drb) => {
+                    debug!("DRB {} of UE {} continues at UL COUNT {}, DL COUNT {}",
+                           status.drb_id, ue_id, status.ul_count, status.dl_count);
+                    drb.pdcp.lock().await.set_counts(status.dl_count, status.ul_count);
+                }
+                None => warn!("SN status for unknown DRB {} of UE {}", status.drb_id, ue_id),
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the handover of a UE that reached the cell: deliver the data
+    /// forwarded meanwhile and notify NGAP
+    pub(super) async fn complete_handover(&mut self, ue_id: u32, buffered: Vec<ForwardedPacket>) {
+        info!("UE {} completed the handover, delivering {} forwarded packets", ue_id, buffered.len());
+        for packet in buffered {
+            if let Err(e) = self.handle_downlink_user_data(
+                ue_id, packet.pdu_session_id, packet.qfi, packet.rqi, packet.data,
+            ).await {
+                debug!("Dropping forwarded packet of UE {}: {}", ue_id, e);
+            }
+        }
+        self.send_to_ngap(RrcNgapMessage::HandoverNotify { ue_id }).await;
+    }
+
+    /// Handle handover timer expiry on the source and target side
+    pub(super) async fn check_handover_timers(&mut self, now: Instant) {
+        let mut lost = Vec::new();
+        let mut not_arrived = Vec::new();
+
+        let mut contexts = self.ue_contexts.lock().await;
+        for ue_context in contexts.values_mut() {
+            match &ue_context.handover {
+                Some(HandoverState::Preparing { target_pci, deadline }) if *deadline <= now => {
+                    warn!("Handover preparation of RNTI {} to PCI {} timed out", ue_context.c_rnti.0, target_pci);
+                    ue_context.handover = None;
+                }
+                Some(HandoverState::Executing { target_pci, deadline }) if *deadline <= now => {
+                    warn!("No UE context release after handover of RNTI {} to PCI {}", ue_context.c_rnti.0, target_pci);
+                    ue_context.handover = None;
+                    lost.push(ue_context.c_rnti);
+                }
+                Some(HandoverState::Admitted { deadline, .. }) if *deadline <= now => {
+                    warn!("Handed over UE {} did not access the cell on RNTI {}", ue_context.ue_id, ue_context.c_rnti.0);
+                    not_arrived.push(ue_context.c_rnti);
+                }
+                _ => {}
+            }
+        }
+        drop(contexts);
+
+        for rnti in lost {
+            if let Err(e) = self.request_ue_context_release(rnti, RrcReleaseCause::RadioConnectionWithUeLost).await {
+                warn!("Failed to request release of RNTI {}: {}", rnti.0, e);
+            }
+        }
+        for rnti in not_arrived {
+            match self.teardown_ue_context(rnti).await {
+                Ok(ue_context) => {
+                    self.send_to_ngap(RrcNgapMessage::UeContextReleaseComplete {
+                        ue_id: ue_context.ue_id,
+                        pdu_session_ids: super::release::pdu_session_ids(&ue_context),
+                    }).await;
+                }
+                Err(e) => warn!("Failed to release RNTI {}: {}", rnti.0, e),
+            }
+        }
+    }
+
+    /// Refuse a handover request
+    async fn send_handover_failure(&self, handover_id: u32, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        self.send_to_ngap(RrcNgapMessage::HandoverFailure { handover_id, cause }).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handover_preparation_information_roundtrip() {
+        let (drb, rlc_bearer) = reconfiguration::drb_configuration(1, 5, &[1, 2]);
+        let command = RrcReconfiguration {
+            drbs_to_add_mod: vec![drb],
+            rlc_bearers_to_add_mod: vec![rlc_bearer],
+            reconfiguration_with_sync: Some(ReconfigurationWithSync {
+                physical_cell_id: 2,
+                new_ue_identity: Rnti(0x4602),
+                t304_ms: T304_MS,
+                integrity_algorithm: IntegrityAlgorithm::Nia2,
+                ciphering_algorithm: CipheringAlgorithm::Nea2,
+                next_hop_chaining_count: 1,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(RrcReconfiguration::decode(&command.encode()).unwrap(), command);
+
+        let information = HandoverPreparationInformation {
+            ue_radio_capability: Some(Bytes::from_static(&[0x01, 0x00, 0x00, 0x00])),
+            source_config: RrcReconfiguration { reconfiguration_with_sync: None, ..command },
+            source_pci: 1,
+            source_c_rnti: Rnti(0x4601),
+        };
+        let encoded = information.encode();
+        assert_eq!(HandoverPreparationInformation::decode(&encoded).unwrap(), information);
+        assert!(HandoverPreparationInformation::decode(&encoded[..5]).is_err());
+    }
+
+    #[test]
+    fn test_handover_decode_errors() {
+        let with_sync = ReconfigurationWithSync {
+            physical_cell_id: 2,
+            new_ue_identity: Rnti(0x4602),
+            t304_ms: T304_MS,
+            integrity_algorithm: IntegrityAlgorithm::Nia2,
+            ciphering_algorithm: CipheringAlgorithm::Nea2,
+            next_hop_chaining_count: 1,
+        };
+        let mut encoded = BytesMut::new();
+        with_sync.encode(&mut encoded);
+        assert!(matches!(ReconfigurationWithSync::decode(&mut &encoded[..8]), Err(LayerError::InvalidPdu)));
+
+        // Unknown ciphering and integrity algorithms
+        let mut unknown = encoded.clone();
+        unknown[6] = 4;
+        assert!(matches!(ReconfigurationWithSync::decode(&mut &unknown[..]), Err(LayerError::InvalidPdu)));
+        let mut unknown = encoded.clone();
+        unknown[7] = 0xFF;
+        assert!(matches!(ReconfigurationWithSync::decode(&mut &unknown[..]), Err(LayerError::InvalidPdu)));
+
+        // Only the three bits of the NCC are kept
+        encoded[8] = 0xFF;
+        assert_eq!(ReconfigurationWithSync::decode(&mut &encoded[..]).unwrap().next_hop_chaining_count, 7);
+
+        // Capability length beyond the container, and no source configuration
+        assert!(matches!(HandoverPreparationInformation::decode(&[0x00, 0x01, 0x46, 0x01, 0x00, 0x04, 0x01]),
+                         Err(LayerError::InvalidPdu)));
+        let information = HandoverPreparationInformation {
+            ue_radio_capability: None,
+            source_config: RrcReconfiguration::default(),
+            source_pci: 1,
+            source_c_rnti: Rnti(0x4601),
+        };
+        assert!(HandoverPreparationInformation::decode(&information.encode()[..6]).is_err());
+    }
+}
@@ -0,0 +1,616 @@
+//! RRC_INACTIVE Support
+//!
+//! Implements suspension of the RRC connection (RRC Release with suspendConfig),
+//! RRC Resume and RAN-initiated paging according to 3GPP TS 38.331 Sections 5.3.8
+//! and 5.3.13
+
+use super::release::{pdu_session_ids, RrcRelease, RrcReleaseCause};
+use super::{
+    EstablishmentCause, PagingRequest, RrcLayer, RrcMessageType, RrcNgapMessage, RrcSetupRequest, RrcState, UeContext,
+};
+use super::reconfiguration::release_drb_entities;
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::Rnti;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Number of RAN paging attempts before the UE is considered unreachable
+pub const MAX_RAN_PAGING_ATTEMPTS: u8 = 3;
+
+/// Suspend configuration sent in RRC Release
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuspendConfig {
+    /// fullI-RNTI (40 bits)
+    pub full_i_rnti: u64,
+    /// shortI-RNTI (24 bits)
+    pub short_i_rnti: u32,
+    /// ran-PagingCycle in radio frames (32, 64, 128 or 256)
+    pub ran_paging_cycle: u16,
+    /// RAN notification area as a list of tracking area codes
+    pub ran_notification_area: Vec<u32>,
+    /// nextHopChainingCount
+    pub next_hop_chaining_count: u8,
+}
+
+impl SuspendConfig {
+    /// Encode suspendConfig
+    ///
+    /// Layout: fullI-RNTI(5) | shortI-RNTI(3) | ran-PagingCycle(2) | n(1) | TAC(3) * n | NCC(1)
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_slice(&self.full_i_rnti.to_be_bytes()[3..]);
+        buf.put_slice(&self.short_i_rnti.to_be_bytes()[1..]);
+        buf.put_u16(self.ran_paging_cycle);
+        buf.put_u8(self.ran_notification_area.len() as u8);
+        for tac in &self.ran_notification_area {
+            buf.put_slice(&tac.to_be_bytes()[1..]);
+        }
+        buf.put_u8(self.next_hop_chaining_count & 0x07);
+    }
+
+    /// Decode suspendConfig
+    pub fn decode(buf: &mut Bytes) -> Result<Self, LayerError> {
+        if buf.remaining() < 11 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let full_i_rnti = get_uint(buf, 5);
+        let short_i_rnti = get_uint(buf, 3) as u32;
+        let ran_paging_cycle = buf.get_u16();
+        let count = buf.get_u8() as usize;
+        if buf.remaining() < count * 3 + 1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let ran_notification_area = (0..count).map(|_| get_uint(buf, 3) as u32).collect();
+        let next_hop_chaining_count = buf.get_u8() & 0x07;
+
+        Ok(Self {
+            full_i_rnti,
+            short_i_rnti,
+            ran_paging_cycle,
+            ran_notification_area,
+            next_hop_chaining_count,
+        })
+    }
+}
+
+/// Resume cause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeCause {
+    Emergency = 0,
+    HighPriorityAccess = 1,
+    MtAccess = 2,
+    MoSignalling = 3,
+    MoData = 4,
+    MoVoiceCall = 5,
+    MoVideoCall = 6,
+    MoSms = 7,
+    RnaUpdate = 8,
+    MpsPriorityAccess = 9,
+    McsPriorityAccess = 10,
+}
+
+impl ResumeCause {
+    /// Convert from the encoded value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Emergency),
+            1 => Some(Self::HighPriorityAccess),
+            2 => Some(Self::MtAccess),
+            3 => Some(Self::MoSignalling),
+            4 => Some(Self::MoData),
+            5 => Some(Self::MoVoiceCall),
+            6 => Some(Self::MoVideoCall),
+            7 => Some(Self::MoSms),
+            8 => Some(Self::RnaUpdate),
+            9 => Some(Self::MpsPriorityAccess),
+            10 => Some(Self::McsPriorityAccess),
+            _ => None,
+        }
+    }
+}
+
+/// Inactive RNTI carried in a resume request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeIdentity {
+    /// shortI-RNTI (RRCResumeRequest)
+    Short(u32),
+    /// fullI-RNTI (RRCResumeRequest1)
+    Full(u64),
+}
+
+/// RRC Resume Request / RRC Resume Request1 message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcResumeRequest {
+    /// Inactive RNTI of the UE
+    pub resume_identity: ResumeIdentity,
+    /// resumeMAC-I
+    pub resume_mac_i: u16,
+    /// Resume cause
+    pub resume_cause: ResumeCause,
+}
+
+impl RrcResumeRequest {
+    /// Decode RRC Resume Request or RRC Resume Request1
+    ///
+    /// Layout: type(1) | shortI-RNTI(3) or fullI-RNTI(5) | resumeMAC-I(2) | cause(1)
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = Bytes::copy_from_slice(data);
+        if !buf.has_remaining() {
+            return Err(LayerError::InvalidPdu);
+        }
+        let resume_identity = match buf.get_u8() {
+            0x08 if buf.remaining() >= 6 => ResumeIdentity::Short(get_uint(&mut buf, 3) as u32),
+            0x09 if buf.remaining() >= 8 => ResumeIdentity::Full(get_uint(&mut buf, 5)),
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        let resume_mac_i = buf.get_u16();
+        let resume_cause = ResumeCause::from_u8(buf.get_u8()).ok_or(LayerError::InvalidPdu)?;
+
+        Ok(Self {
+            resume_identity,
+            resume_mac_i,
+            resume_cause,
+        })
+    }
+
+    /// Encode RRC Resume Request or RRC Resume Request1
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(9);
+        match self.resume_identity {
+            ResumeIdentity::Short(short_i_rnti) => {
+                buf.put_u8(0x08); // RRC Resume Request
+                buf.put_slice(&short_i_rnti.to_be_bytes()[1..]);
+            }
+            ResumeIdentity::Full(full_i_rnti) => {
+                buf.put_u8(0x09); // RRC Resume Request1
+                buf.put_slice(&full_i_rnti.to_be_bytes()[3..]);
+            }
+        }
+        buf.put_u16(self.resume_mac_i);
+        buf.put_u8(self.resume_cause as u8);
+        buf.freeze()
+    }
+}
+
+/// RRC Resume message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcResume {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+}
+
+impl RrcResume {
+    /// Encode RRC Resume
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(2);
+        buf.put_u8(0x0A); // RRC Resume
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.freeze()
+    }
+}
+
+/// UE identity in a paging record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagingUeIdentity {
+    /// 5G-S-TMSI (48 bits), used for CN paging
+    NgSTmsi(u64),
+    /// fullI-RNTI (40 bits), used for RAN paging
+    FullIRnti(u64),
+}
+
+/// Paging message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paging {
+    /// Paged UE identities
+    pub paging_records: Vec<PagingUeIdentity>,
+}
+
+impl Paging {
+    /// Encode Paging
+    ///
+    /// Layout: type(1) | n(1) | (identity type(1) | identity(6 or 5)) * n
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(0x40); // Paging
+        buf.put_u8(self.paging_records.len() as u8);
+        for record in &self.paging_records {
+            match record {
+                PagingUeIdentity::NgSTmsi(s_tmsi) => {
+                    buf.put_u8(0);
+                    buf.put_slice(&s_tmsi.to_be_bytes()[2..]);
+                }
+                PagingUeIdentity::FullIRnti(i_rnti) => {
+                    buf.put_u8(1);
+                    buf.put_slice(&i_rnti.to_be_bytes()[3..]);
+                }
+            }
+        }
+        buf.freeze()
+    }
+
+    /// Decode Paging
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = Bytes::copy_from_slice(data);
+        if buf.remaining() < 2 || buf.get_u8() != 0x40 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let count = buf.get_u8() as usize;
+        let mut paging_records = Vec::with_capacity(count);
+        for _ in 0..count {
+            if !buf.has_remaining() {
+                return Err(LayerError::InvalidPdu);
+            }
+            let record = match buf.get_u8() {
+                0 if buf.remaining() >= 6 => PagingUeIdentity::NgSTmsi(get_uint(&mut buf, 6)),
+                1 if buf.remaining() >= 5 => PagingUeIdentity::FullIRnti(get_uint(&mut buf, 5)),
+                _ => return Err(LayerError::InvalidPdu),
+            };
+            paging_records.push(record);
+        }
+        Ok(Self { paging_records })
+    }
+}
+
+/// Read a big-endian unsigned integer of `len` bytes
+fn get_uint(buf: &mut Bytes, len: usize) -> u64 {
+    (0..len).fold(0u64, |acc, _| (acc << 8) | buf.get_u8() as u64)
+}
+
+impl RrcLayer {
+    /// Suspend the RRC connection of a UE and move it to RRC_INACTIVE
+    ///
+    /// The AS context including the DRB entities is retained, indexed by I-RNTI.
+    pub async fn suspend_ue(&mut self, rnti: Rnti) -> Result<u64, LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.security.is_none() {
+            return Err(LayerError::InvalidState("AS security not activated".into()));
+        }
+        let mut ue_context = contexts.remove(&rnti.0).unwrap();
+        drop(contexts);
+
+        let full_i_rnti = self.full_i_rnti(ue_context.ue_id);
+        let suspend_config = self.suspend_config(&ue_context, full_i_rnti);
+        let release = RrcRelease {
+            transaction_id: ue_context.allocate_transaction_id(),
+            suspend_config: Some(suspend_config),
+        };
+
+        info!("Suspending UE {} (RNTI {}) with I-RNTI {:#012x}", ue_context.ue_id, rnti.0, full_i_rnti);
+        if let Err(e) = self.send_to_mac(rnti, RrcMessageType::RrcRelease, release.encode()).await {
+            warn!("Failed to send RRC Release to RNTI {}: {}", rnti.0, e);
+        }
+        if let Some(mac_interface) = &self.mac_interface {
+            if let Err(e) = mac_interface.release_ue(rnti).await {
+                warn!("Failed to release MAC resources of RNTI {}: {}", rnti.0, e);
+            }
+        }
+
+        // The source C-RNTI is kept for resumeMAC-I verification
+        ue_context.state = RrcState::Inactive;
+        ue_context.i_rnti = Some(full_i_rnti);
+        ue_context.pending_reconfiguration = None;
+        ue_context.paging_attempts = 0;
+        ue_context.last_paging = None;
+        let ue_id = ue_context.ue_id;
+        self.inactive_contexts.lock().await.insert(full_i_rnti, ue_context);
+
+        self.send_to_ngap(RrcNgapMessage::RrcStateTransition {
+            ue_id,
+            state: RrcState::Inactive,
+        }).await;
+        Ok(full_i_rnti)
+    }
+
+    /// Handle RRC Resume Request or RRC Resume Request1 from UE
+    pub(super) async fn handle_rrc_resume_request(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let request = RrcResumeRequest::decode(&data)?;
+        info!("Handling RRC Resume Request from RNTI {}: {:?}, cause {:?}",
+              rnti.0, request.resume_identity, request.resume_cause);
+
+        let Some(full_i_rnti) = self.verify_resume(&request).await else {
+            // The AS context cannot be recovered: fall back to RRC Setup
+            warn!("RRC Resume from RNTI {} rejected, falling back to RRC Setup", rnti.0);
+            let setup_request = RrcSetupRequest {
+                ue_identity: Vec::new(),
+                establishment_cause: EstablishmentCause::MoSignalling,
+            };
+            return self.handle_rrc_setup_request(rnti, setup_request).await;
+        };
+
+        if request.resume_cause == ResumeCause::RnaUpdate {
+            return self.handle_rna_update(rnti, full_i_rnti).await;
+        }
+
+        self.resume_ue(rnti, full_i_rnti).await
+    }
+
+    /// Look up the inactive context and check the resumeMAC-I
+    async fn verify_resume(&self, request: &RrcResumeRequest) -> Option<u64> {
+        let contexts = self.inactive_contexts.lock().await;
+        let (full_i_rnti, ue_context) = match request.resume_identity {
+            ResumeIdentity::Full(full_i_rnti) => (full_i_rnti, contexts.get(&full_i_rnti)?),
+            ResumeIdentity::Short(short_i_rnti) => contexts.iter()
+                .find(|(full_i_rnti, _)| (**full_i_rnti & 0xFF_FFFF) as u32 == short_i_rnti)
+                .map(|(full_i_rnti, ctx)| (*full_i_rnti, ctx))?,
+        };
+        let security = ue_context.security.as_ref()?;
+
+        // VarResumeMAC-Input: source PCI, target cell identity, source C-RNTI
+        match security.short_mac_i(self.config.pci, self.config.nr_cell_identity, ue_context.c_rnti.0) {
+            Ok(expected) if expected == request.resume_mac_i => Some(full_i_rnti),
+            Ok(expected) => {
+                warn!("resumeMAC-I mismatch for I-RNTI {:#012x}: expected {:#06x}, received {:#06x}",
+                      full_i_rnti, expected, request.resume_mac_i);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to compute resumeMAC-I: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Resume a verified inactive UE on its new C-RNTI
+    async fn resume_ue(&mut self, rnti: Rnti, full_i_rnti: u64) -> Result<(), LayerError> {
+        let mut ue_context = self.inactive_contexts.lock().await.remove(&full_i_rnti)
+            .ok_or_else(|| LayerError::InvalidState("No inactive UE context".into()))?;
+        ue_context.c_rnti = rnti;
+        ue_context.state = RrcState::Connected;
+        ue_context.i_rnti = None;
+        ue_context.last_activity = Instant::now();
+        ue_context.release_requested_at = None;
+        let resume = RrcResume {
+            transaction_id: ue_context.allocate_transaction_id(),
+        };
+        let scheduling_capabilities = ue_context.ue_capability.as_ref()
+            .map(|capability| capability.scheduling_capabilities(self.config.band));
+        let ue_id = ue_context.ue_id;
+        self.ue_contexts.lock().await.insert(rnti.0, ue_context);
+
+        if let (Some(mac_interface), Some(capabilities)) = (&self.mac_interface, scheduling_capabilities) {
+            mac_interface.configure_ue_capabilities(rnti, capabilities).await?;
+        }
+
+        info!("Resuming UE {} on RNTI {}", ue_id, rnti.0);
+        self.send_to_mac(rnti, RrcMessageType::RrcResume, resume.encode()).await?;
+
+        self.send_to_ngap(RrcNgapMessage::RrcStateTransition {
+            ue_id,
+            state: RrcState::Connected,
+        }).await;
+        Ok(())
+    }
+
+    /// Answer a RAN notification area update by keeping the UE in RRC_INACTIVE
+    async fn handle_rna_update(&mut self, rnti: Rnti, full_i_rnti: u64) -> Result<(), LayerError> {
+        let mut contexts = self.inactive_contexts.lock().await;
+        let ue_context = contexts.get_mut(&full_i_rnti)
+            .ok_or_else(|| LayerError::InvalidState("No inactive UE context".into()))?;
+        ue_context.last_activity = Instant::now();
+        let release = RrcRelease {
+            transaction_id: ue_context.allocate_transaction_id(),
+            suspend_config: Some(self.suspend_config(ue_context, full_i_rnti)),
+        };
+        drop(contexts);
+
+        info!("RAN notification area update for I-RNTI {:#012x}", full_i_rnti);
+        self.send_to_mac(rnti, RrcMessageType::RrcRelease, release.encode()).await?;
+        if let Some(mac_interface) = &self.mac_interface {
+            mac_interface.release_ue(rnti).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle RRC Resume Complete from UE
+    pub(super) async fn handle_rrc_resume_complete(&mut self, rnti: Rnti) -> Result<(), LayerError> {
+        let contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        info!("RRC Resume Complete from RNTI {}: UE {} is RRC Connected with {} DRBs",
+              rnti.0, ue_context.ue_id, ue_context.drbs.len());
+        Ok(())
+    }
+
+    /// Notify RRC that downlink data is pending for a UE
+    ///
+    /// Starts RAN paging if the UE is in RRC_INACTIVE. Returns true if paging was started.
+    pub async fn handle_downlink_data_notification(&mut self, ue_id: u32) -> Result<bool, LayerError> {
+        let mut contexts = self.inactive_contexts.lock().await;
+        let Some((full_i_rnti, ue_context)) = contexts.iter_mut().find(|(_, ctx)| ctx.ue_id == ue_id) else {
+            return Ok(false);
+        };
+        if ue_context.last_paging.is_some() {
+            debug!("RAN paging already ongoing for UE {}", ue_id);
+            return Ok(true);
+        }
+        ue_context.paging_attempts = 1;
+        ue_context.last_paging = Some(Instant::now());
+        let full_i_rnti = *full_i_rnti;
+        drop(contexts);
+
+        info!("Downlink data for inactive UE {}, starting RAN paging", ue_id);
+        self.send_ran_paging(full_i_rnti).await?;
+        Ok(true)
+    }
+
+    /// Send a Paging message for an inactive UE
+    async fn send_ran_paging(&self, full_i_rnti: u64) -> Result<(), LayerError> {
+        // The 5G-S-TMSI is not known to RRC, so the paging occasion uses UE_ID 0
+        self.send_paging(PagingRequest {
+            identity: PagingUeIdentity::FullIRnti(full_i_rnti),
+            ue_id: 0,
+            paging_cycle: Some(self.config.ran_paging_cycle),
+            priority: None,
+        }).await
+    }
+
+    /// Page a UE in RRC_IDLE on request of the AMF
+    pub(super) async fn handle_cn_paging(
+        &self,
+        s_tmsi: u64,
+        paging_drx: Option<u16>,
+        paging_priority: Option<u8>,
+    ) -> Result<(), LayerError> {
+        info!("CN paging for 5G-S-TMSI {:012X}", s_tmsi);
+        self.send_paging(PagingRequest {
+            identity: PagingUeIdentity::NgSTmsi(s_tmsi),
+            ue_id: (s_tmsi % 1024) as u16,
+            paging_cycle: paging_drx,
+            priority: paging_priority,
+        }).await
+    }
+
+    /// Hand a paging record to MAC
+    async fn send_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+        let mac_interface = self.mac_interface.as_ref()
+            .ok_or_else(|| LayerError::ConfigurationError("No MAC interface".into()))?;
+        mac_interface.schedule_paging(request).await
+    }
+
+    /// Repeat RAN paging every paging cycle and give up on unreachable UEs
+    pub(super) async fn check_ran_paging_timers(&mut self, now: Instant) {
+        // One radio frame is 10 ms
+        let cycle = Duration::from_millis(self.config.ran_paging_cycle as u64 * 10);
+
+        let mut repeat = Vec::new();
+        let mut unreachable = Vec::new();
+
+        let mut contexts = self.inactive_contexts.lock().await;
+        for (full_i_rnti, ue_context) in contexts.iter_mut() {
+            let Some(last_paging) = ue_context.last_paging else {
+                continue;
+            };
+            if now < last_paging + cycle {
+                continue;
+            }
+            if ue_context.paging_attempts >= MAX_RAN_PAGING_ATTEMPTS {
+                unreachable.push(*full_i_rnti);
+            } else {
+                ue_context.paging_attempts += 1;
+                ue_context.last_paging = Some(now);
+                repeat.push(*full_i_rnti);
+            }
+        }
+        let unreachable: Vec<UeContext> = unreachable.iter()
+            .filter_map(|full_i_rnti| contexts.remove(full_i_rnti))
+            .collect();
+        drop(contexts);
+
+        for full_i_rnti in repeat {
+            if let Err(e) = self.send_ran_paging(full_i_rnti).await {
+                error!("Failed to send RAN paging: {}", e);
+            }
+        }
+
+        for mut ue_context in unreachable {
+            warn!("RAN paging of UE {} failed, requesting UE context release", ue_context.ue_id);
+            let pdu_session_ids = pdu_session_ids(&ue_context);
+            for (_, drb) in ue_context.drbs.drain() {
+                release_drb_entities(drb).await;
+            }
+            self.send_to_ngap(RrcNgapMessage::UeContextReleaseRequest {
+                ue_id: ue_context.ue_id,
+                cause: RrcReleaseCause::RadioConnectionWithUeLost,
+                pdu_session_ids,
+            }).await;
+        }
+    }
+
+    /// Drop the inactive context of a UE, returning its PDU sessions
+    pub(super) async fn remove_inactive_context(&mut self, ue_id: u32) -> Option<Vec<u8>> {
+        let mut contexts = self.inactive_contexts.lock().await;
+        let full_i_rnti = contexts.iter()
+            .find(|(_, ctx)| ctx.ue_id == ue_id)
+            .map(|(full_i_rnti, _)| *full_i_rnti)?;
+        let mut ue_context = contexts.remove(&full_i_rnti)?;
+        drop(contexts);
+
+        let pdu_session_ids = pdu_session_ids(&ue_context);
+        for (_, drb) in ue_context.drbs.drain() {
+            release_drb_entities(drb).await;
+        }
+        info!("Inactive UE {} (I-RNTI {:#012x}) released", ue_id, full_i_rnti);
+        Some(pdu_session_ids)
+    }
+
+    /// Build the fullI-RNTI of a UE: gNB part of the NR cell identity (16 bits) and UE identifier (24 bits)
+    ///
+    /// The gNB part is made of the 16 least significant bits of the 22-bit gNB ID.
+    fn full_i_rnti(&self, ue_id: u32) -> u64 {
+        (((self.config.nr_cell_identity >> 14) & 0xFFFF) << 24) | (ue_id as u64 & 0xFF_FFFF)
+    }
+
+    /// Build the suspendConfig for a UE
+    fn suspend_config(&self, ue_context: &UeContext, full_i_rnti: u64) -> SuspendConfig {
+        SuspendConfig {
+            full_i_rnti,
+            short_i_rnti: (full_i_rnti & 0xFF_FFFF) as u32,
+            ran_paging_cycle: self.config.ran_paging_cycle,
+            ran_notification_area: vec![self.config.tac],
+            next_hop_chaining_count: ue_context.security.as_ref()
+                .map(|security| security.next_hop_chaining_count)
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_request_and_paging_roundtrip() {
+        let request = RrcResumeRequest {
+            resume_identity: ResumeIdentity::Full(0x01_0000_03E8),
+            resume_mac_i: 0xBEEF,
+            resume_cause: ResumeCause::MtAccess,
+        };
+        assert_eq!(RrcResumeRequest::decode(&request.encode()).unwrap(), request);
+
+        let short = RrcResumeRequest {
+            resume_identity: ResumeIdentity::Short(0x0003E8),
+            ..request
+        };
+        assert_eq!(RrcResumeRequest::decode(&short.encode()).unwrap(), short);
+
+        let paging = Paging {
+            paging_records: vec![
+                PagingUeIdentity::FullIRnti(0x01_0000_03E8),
+                PagingUeIdentity::NgSTmsi(0x0102_0304_0506),
+            ],
+        };
+        assert_eq!(Paging::decode(&paging.encode()).unwrap(), paging);
+    }
+
+    #[test]
+    fn test_resume_request_and_paging_decode_errors() {
+        let request = RrcResumeRequest {
+            resume_identity: ResumeIdentity::Full(0x01_0000_03E8),
+            resume_mac_i: 0xBEEF,
+            resume_cause: ResumeCause::MoData,
+        }.encode();
+        assert!(matches!(RrcResumeRequest::decode(&[]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(RrcResumeRequest::decode(&request[..8]), Err(LayerError::InvalidPdu)));
+
+        // Not a Resume Request
+        let mut other = request.to_vec();
+        other[0] = 0x07;
+        assert!(matches!(RrcResumeRequest::decode(&other), Err(LayerError::InvalidPdu)));
+
+        // Spare resume cause
+        let mut spare = request.to_vec();
+        spare[8] = 11;
+        assert!(matches!(RrcResumeRequest::decode(&spare), Err(LayerError::InvalidPdu)));
+
+        let paging = Paging { paging_records: vec![PagingUeIdentity::NgSTmsi(0x0102_0304_0506)] }.encode();
+        assert!(matches!(Paging::decode(&paging[..paging.len() - 1]), Err(LayerError::InvalidPdu)));
+        let mut unknown_identity = paging.to_vec();
+        unknown_identity[2] = 2;
+        assert!(matches!(Paging::decode(&unknown_identity), Err(LayerError::InvalidPdu)));
+        assert!(matches!(Paging::decode(&[0x41, 0x00]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(Paging::decode(&[0x40, 0x01]), Err(LayerError::InvalidPdu)));
+    }
+}
+
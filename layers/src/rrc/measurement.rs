@@ -0,0 +1,694 @@
+//! Measurement Configuration and Reporting
+//!
+//! Implements MeasConfig generation and MeasurementReport handling according to
+//! 3GPP TS 38.331 Sections 5.5 and 6.3.2
+
+use super::reconfiguration::{PendingReconfiguration, RrcReconfiguration};
+use super::{RrcLayer, RrcMessageType};
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::{Rnti, SubcarrierSpacing};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Maximum number of measurement objects, report configurations and measurement identities
+pub const MAX_MEAS_ID: u8 = 64;
+
+/// Allowed TimeToTrigger values in ms (TS 38.331 Section 6.3.2)
+pub const TIME_TO_TRIGGER_VALUES_MS: [u16; 16] = [
+    0, 40, 64, 80, 100, 128, 160, 256, 320, 480, 512, 640, 1024, 1280, 2560, 5120,
+];
+
+/// Convert an RSRP-Range value to dBm
+pub fn rsrp_range_to_dbm(value: u8) -> f32 {
+    value as f32 - 156.0
+}
+
+/// Convert an RSRP in dBm to an RSRP-Range value (0-127)
+pub fn rsrp_dbm_to_range(dbm: f32) -> u8 {
+    (dbm + 156.0).round().clamp(0.0, 127.0) as u8
+}
+
+/// Convert an RSRQ-Range value to dB
+pub fn rsrq_range_to_db(value: u8) -> f32 {
+    (value as f32 - 87.0) / 2.0
+}
+
+/// Convert an RSRQ in dB to an RSRQ-Range value (0-127)
+pub fn rsrq_db_to_range(db: f32) -> u8 {
+    (db * 2.0 + 87.0).round().clamp(0.0, 127.0) as u8
+}
+
+/// Convert a SINR-Range value to dB
+pub fn sinr_range_to_db(value: u8) -> f32 {
+    (value as f32 - 46.0) / 2.0
+}
+
+/// Convert a SINR in dB to a SINR-Range value (0-127)
+pub fn sinr_db_to_range(db: f32) -> u8 {
+    (db * 2.0 + 46.0).round().clamp(0.0, 127.0) as u8
+}
+
+/// SSB based NR measurement object
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasObjectNr {
+    /// Measurement object identifier (1-64)
+    pub meas_object_id: u8,
+    /// SSB ARFCN
+    pub ssb_frequency: u32,
+    /// SSB subcarrier spacing
+    pub ssb_subcarrier_spacing: SubcarrierSpacing,
+}
+
+/// Quantity used to evaluate an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerQuantity {
+    Rsrp = 0,
+    Rsrq = 1,
+    Sinr = 2,
+}
+
+/// Measurement report triggering event
+///
+/// Thresholds are RSRP/RSRQ/SINR-Range values of the trigger quantity,
+/// offsets are in 0.5 dB steps (-30 to 30).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTrigger {
+    /// Serving becomes better than threshold
+    A1 { threshold: u8 },
+    /// Serving becomes worse than threshold
+    A2 { threshold: u8 },
+    /// Neighbour becomes offset better than SpCell
+    A3 { offset: i8 },
+    /// Neighbour becomes better than threshold
+    A4 { threshold: u8 },
+    /// SpCell becomes worse than threshold1 and neighbour becomes better than threshold2
+    A5 { threshold1: u8, threshold2: u8 },
+    /// Neighbour becomes offset better than SCell
+    A6 { offset: i8 },
+}
+
+impl EventTrigger {
+    fn event_id(&self) -> u8 {
+        match self {
+            EventTrigger::A1 { .. } => 1,
+            EventTrigger::A2 { .. } => 2,
+            EventTrigger::A3 { .. } => 3,
+            EventTrigger::A4 { .. } => 4,
+            EventTrigger::A5 { .. } => 5,
+            EventTrigger::A6 { .. } => 6,
+        }
+    }
+}
+
+/// Event triggered NR report configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportConfigNr {
+    /// Report configuration identifier (1-64)
+    pub report_config_id: u8,
+    /// Triggering event
+    pub event: EventTrigger,
+    /// Quantity the event is evaluated on
+    pub trigger_quantity: TriggerQuantity,
+    /// Hysteresis in 0.5 dB steps (0-30)
+    pub hysteresis: u8,
+    /// Time to trigger in ms
+    pub time_to_trigger_ms: u16,
+    /// Report interval in ms
+    pub report_interval_ms: u16,
+    /// Number of reports (0 = infinity)
+    pub report_amount: u8,
+    /// Maximum number of neighbour cells per report
+    pub max_report_cells: u8,
+    /// Report when leaving the entering condition
+    pub report_on_leave: bool,
+}
+
+/// Association of a measurement object with a report configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeasIdToAddMod {
+    /// Measurement identifier (1-64)
+    pub meas_id: u8,
+    /// Measurement object identifier
+    pub meas_object_id: u8,
+    /// Report configuration identifier
+    pub report_config_id: u8,
+}
+
+/// Measurement configuration
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeasConfig {
+    /// Measurement objects
+    pub meas_objects: Vec<MeasObjectNr>,
+    /// Report configurations
+    pub report_configs: Vec<ReportConfigNr>,
+    /// Measurement identities
+    pub meas_ids: Vec<MeasIdToAddMod>,
+    /// s-MeasureConfig as SSB RSRP-Range value
+    pub s_measure_ssb_rsrp: Option<u8>,
+}
+
+impl MeasConfig {
+    /// Check identifiers, references and value ranges
+    pub fn validate(&self) -> Result<(), LayerError> {
+        let invalid = |msg: String| Err(LayerError::InvalidConfiguration(msg));
+
+        let mut object_ids = Vec::new();
+        for object in &self.meas_objects {
+            if object.meas_object_id == 0 || object.meas_object_id > MAX_MEAS_ID
+                || object_ids.contains(&object.meas_object_id) {
+                return invalid(format!("Invalid measObjectId {}", object.meas_object_id));
+            }
+            object_ids.push(object.meas_object_id);
+        }
+
+        let mut report_ids = Vec::new();
+        for report in &self.report_configs {
+            if report.report_config_id == 0 || report.report_config_id > MAX_MEAS_ID
+                || report_ids.contains(&report.report_config_id) {
+                return invalid(format!("Invalid reportConfigId {}", report.report_config_id));
+            }
+            if report.hysteresis > 30 {
+                return invalid(format!("Hysteresis {} out of range", report.hysteresis));
+            }
+            if !TIME_TO_TRIGGER_VALUES_MS.contains(&report.time_to_trigger_ms) {
+                return invalid(format!("Invalid timeToTrigger {} ms", report.time_to_trigger_ms));
+            }
+            let thresholds_valid = match report.event {
+                EventTrigger::A1 { threshold } | EventTrigger::A2 { threshold } | EventTrigger::A4 { threshold } => {
+                    threshold <= 127
+                }
+                EventTrigger::A5 { threshold1, threshold2 } => threshold1 <= 127 && threshold2 <= 127,
+                EventTrigger::A3 { offset } | EventTrigger::A6 { offset } => (-30..=30).contains(&offset),
+            };
+            if !thresholds_valid {
+                return invalid(format!("Event {:?} out of range", report.event));
+            }
+            report_ids.push(report.report_config_id);
+        }
+
+        let mut meas_ids = Vec::new();
+        for meas_id in &self.meas_ids {
+            if meas_id.meas_id == 0 || meas_id.meas_id > MAX_MEAS_ID || meas_ids.contains(&meas_id.meas_id) {
+                return invalid(format!("Invalid measId {}", meas_id.meas_id));
+            }
+            if !object_ids.contains(&meas_id.meas_object_id) || !report_ids.contains(&meas_id.report_config_id) {
+                return invalid(format!("measId {} references unknown configuration", meas_id.meas_id));
+            }
+            meas_ids.push(meas_id.meas_id);
+        }
+
+        if self.s_measure_ssb_rsrp.is_some_and(|value| value > 127) {
+            return invalid("s-MeasureConfig out of range".into());
+        }
+        Ok(())
+    }
+
+    /// Find the report configuration of a measurement identity
+    pub fn report_config(&self, meas_id: u8) -> Option<&ReportConfigNr> {
+        let meas_id = self.meas_ids.iter().find(|id| id.meas_id == meas_id)?;
+        self.report_configs.iter().find(|report| report.report_config_id == meas_id.report_config_id)
+    }
+
+    /// Encode MeasConfig
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.meas_objects.len() as u8);
+        for object in &self.meas_objects {
+            buf.put_u8(object.meas_object_id);
+            buf.put_slice(&object.ssb_frequency.to_be_bytes()[1..]);
+            buf.put_u8(object.ssb_subcarrier_spacing as u8);
+        }
+
+        buf.put_u8(self.report_configs.len() as u8);
+        for report in &self.report_configs {
+            let (threshold1, threshold2, offset) = match report.event {
+                EventTrigger::A1 { threshold } | EventTrigger::A2 { threshold } | EventTrigger::A4 { threshold } => {
+                    (threshold, 0, 0)
+                }
+                EventTrigger::A5 { threshold1, threshold2 } => (threshold1, threshold2, 0),
+                EventTrigger::A3 { offset } | EventTrigger::A6 { offset } => (0, 0, offset),
+            };
+            buf.put_u8(report.report_config_id);
+            buf.put_u8(report.event.event_id());
+            buf.put_u8(report.trigger_quantity as u8);
+            buf.put_u8(threshold1);
+            buf.put_u8(threshold2);
+            buf.put_i8(offset);
+            buf.put_u8(report.hysteresis);
+            buf.put_u16(report.time_to_trigger_ms);
+            buf.put_u16(report.report_interval_ms);
+            buf.put_u8(report.report_amount);
+            buf.put_u8(report.max_report_cells);
+            buf.put_u8(report.report_on_leave as u8);
+        }
+
+        buf.put_u8(self.meas_ids.len() as u8);
+        for meas_id in &self.meas_ids {
+            buf.put_u8(meas_id.meas_id);
+            buf.put_u8(meas_id.meas_object_id);
+            buf.put_u8(meas_id.report_config_id);
+        }
+
+        match self.s_measure_ssb_rsrp {
+            Some(value) => {
+                buf.put_u8(1);
+                buf.put_u8(value);
+            }
+            None => buf.put_u8(0),
+        }
+    }
+
+    /// Decode MeasConfig
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut config = MeasConfig::default();
+
+        let count = take(buf, 1)?.get_u8();
+        for _ in 0..count {
+            let mut object = take(buf, 5)?;
+            let meas_object_id = object.get_u8();
+            let ssb_frequency = ((object.get_u8() as u32) << 16) | object.get_u16() as u32;
+            let ssb_subcarrier_spacing = match object.get_u8() {
+                15 => SubcarrierSpacing::Scs15,
+                30 => SubcarrierSpacing::Scs30,
+                60 => SubcarrierSpacing::Scs60,
+                120 => SubcarrierSpacing::Scs120,
+                240 => SubcarrierSpacing::Scs240,
+                _ => return Err(LayerError::InvalidPdu),
+            };
+            config.meas_objects.push(MeasObjectNr {
+                meas_object_id,
+                ssb_frequency,
+                ssb_subcarrier_spacing,
+            });
+        }
+
+        let count = take(buf, 1)?.get_u8();
+        for _ in 0..count {
+            let mut report = take(buf, 14)?;
+            let report_config_id = report.get_u8();
+            let event_id = report.get_u8();
+            let trigger_quantity = match report.get_u8() {
+                0 => TriggerQuantity::Rsrp,
+                1 => TriggerQuantity::Rsrq,
+                2 => TriggerQuantity::Sinr,
+                _ => return Err(LayerError::InvalidPdu),
+            };
+            let threshold1 = report.get_u8();
+            let threshold2 = report.get_u8();
+            let offset = report.get_i8();
+            let event = match event_id {
+                1 => EventTrigger::A1 { threshold: threshold1 },
+                2 => EventTrigger::A2 { threshold: threshold1 },
+                3 => EventTrigger::A3 { offset },
+                4 => EventTrigger::A4 { threshold: threshold1 },
+                5 => EventTrigger::A5 { threshold1, threshold2 },
+                6 => EventTrigger::A6 { offset },
+                _ => return Err(LayerError::InvalidPdu),
+            };
+            config.report_configs.push(ReportConfigNr {
+                report_config_id,
+                event,
+                trigger_quantity,
+                hysteresis: report.get_u8(),
+                time_to_trigger_ms: report.get_u16(),
+                report_interval_ms: report.get_u16(),
+                report_amount: report.get_u8(),
+                max_report_cells: report.get_u8(),
+                report_on_leave: report.get_u8() != 0,
+            });
+        }
+
+        let count = take(buf, 1)?.get_u8();
+        for _ in 0..count {
+            let mut meas_id = take(buf, 3)?;
+            config.meas_ids.push(MeasIdToAddMod {
+                meas_id: meas_id.get_u8(),
+                meas_object_id: meas_id.get_u8(),
+                report_config_id: meas_id.get_u8(),
+            });
+        }
+
+        if take(buf, 1)?.get_u8() != 0 {
+            config.s_measure_ssb_rsrp = Some(take(buf, 1)?.get_u8());
+        }
+        Ok(config)
+    }
+}
+
+/// Split `len` bytes off the front of the buffer
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], LayerError> {
+    if buf.remaining() < len {
+        return Err(LayerError::InvalidPdu);
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+/// Default measurement configuration for the serving SSB frequency
+///
+/// Configures A1/A2 on serving cell RSRP for coverage and A3 for intra-frequency mobility.
+pub fn default_meas_config(ssb_frequency: u32, ssb_subcarrier_spacing: SubcarrierSpacing) -> MeasConfig {
+    let report = |report_config_id, event| ReportConfigNr {
+        report_config_id,
+        event,
+        trigger_quantity: TriggerQuantity::Rsrp,
+        hysteresis: 2,
+        time_to_trigger_ms: 320,
+        report_interval_ms: 1024,
+        report_amount: 1,
+        max_report_cells: 4,
+        report_on_leave: false,
+    };
+
+    MeasConfig {
+        meas_objects: vec![MeasObjectNr {
+            meas_object_id: 1,
+            ssb_frequency,
+            ssb_subcarrier_spacing,
+        }],
+        report_configs: vec![
+            report(1, EventTrigger::A1 { threshold: rsrp_dbm_to_range(-100.0) }),
+            report(2, EventTrigger::A2 { threshold: rsrp_dbm_to_range(-110.0) }),
+            report(3, EventTrigger::A3 { offset: 6 }),
+        ],
+        meas_ids: (1..=3)
+            .map(|id| MeasIdToAddMod { meas_id: id, meas_object_id: 1, report_config_id: id })
+            .collect(),
+        s_measure_ssb_rsrp: None,
+    }
+}
+
+/// Measured quantities of a cell as range values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeasResultNr {
+    /// Physical cell ID
+    pub pci: u16,
+    /// RSRP-Range
+    pub rsrp: Option<u8>,
+    /// RSRQ-Range
+    pub rsrq: Option<u8>,
+    /// SINR-Range
+    pub sinr: Option<u8>,
+}
+
+impl MeasResultNr {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16(self.pci);
+        buf.put_u8(
+            (self.rsrp.is_some() as u8) | ((self.rsrq.is_some() as u8) << 1) | ((self.sinr.is_some() as u8) << 2),
+        );
+        buf.put_u8(self.rsrp.unwrap_or(0));
+        buf.put_u8(self.rsrq.unwrap_or(0));
+        buf.put_u8(self.sinr.unwrap_or(0));
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut result = take(buf, 6)?;
+        let pci = result.get_u16() & 0x3FF;
+        let flags = result.get_u8();
+        let (rsrp, rsrq, sinr) = (result.get_u8(), result.get_u8(), result.get_u8());
+        Ok(Self {
+            pci,
+            rsrp: (flags & 0x01 != 0).then_some(rsrp),
+            rsrq: (flags & 0x02 != 0).then_some(rsrq),
+            sinr: (flags & 0x04 != 0).then_some(sinr),
+        })
+    }
+}
+
+/// Measurement Report message
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementReport {
+    /// Measurement identity that triggered the report
+    pub meas_id: u8,
+    /// Serving cell results (servCellId, result)
+    pub serving_cells: Vec<(u8, MeasResultNr)>,
+    /// Neighbour cell results
+    pub neighbour_cells: Vec<MeasResultNr>,
+}
+
+impl MeasurementReport {
+    /// Encode Measurement Report
+    ///
+    /// Layout: type(1) | measId(1) | n(1) | (servCellId(1) | result) * n | m(1) | result * m
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(0x32); // Measurement Report
+        buf.put_u8(self.meas_id);
+        buf.put_u8(self.serving_cells.len() as u8);
+        for (serv_cell_id, result) in &self.serving_cells {
+            buf.put_u8(*serv_cell_id);
+            result.encode(&mut buf);
+        }
+        buf.put_u8(self.neighbour_cells.len() as u8);
+        for result in &self.neighbour_cells {
+            result.encode(&mut buf);
+        }
+        buf.freeze()
+    }
+
+    /// Decode Measurement Report
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        let mut header = take(&mut buf, 3)?;
+        if header.get_u8() != 0x32 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let meas_id = header.get_u8();
+
+        let mut serving_cells = Vec::new();
+        for _ in 0..header.get_u8() {
+            let serv_cell_id = take(&mut buf, 1)?.get_u8();
+            serving_cells.push((serv_cell_id, MeasResultNr::decode(&mut buf)?));
+        }
+
+        let mut neighbour_cells = Vec::new();
+        for _ in 0..take(&mut buf, 1)?.get_u8() {
+            neighbour_cells.push(MeasResultNr::decode(&mut buf)?);
+        }
+
+        Ok(Self {
+            meas_id,
+            serving_cells,
+            neighbour_cells,
+        })
+    }
+}
+
+/// Measured quantities of a cell in physical units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMeasurement {
+    /// Physical cell ID
+    pub pci: u16,
+    /// RSRP in dBm
+    pub rsrp_dbm: Option<f32>,
+    /// RSRQ in dB
+    pub rsrq_db: Option<f32>,
+    /// SINR in dB
+    pub sinr_db: Option<f32>,
+}
+
+impl From<&MeasResultNr> for CellMeasurement {
+    fn from(result: &MeasResultNr) -> Self {
+        Self {
+            pci: result.pci,
+            rsrp_dbm: result.rsrp.map(rsrp_range_to_dbm),
+            rsrq_db: result.rsrq.map(rsrq_range_to_db),
+            sinr_db: result.sinr.map(sinr_range_to_db),
+        }
+    }
+}
+
+/// Latest measurement results reported by a UE
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UeMeasurements {
+    /// Serving cell
+    pub serving: Option<CellMeasurement>,
+    /// Neighbour cells, strongest first
+    pub neighbours: Vec<CellMeasurement>,
+    /// Measurement identity of the last report
+    pub last_meas_id: u8,
+    /// Number of reports received
+    pub report_count: u32,
+}
+
+impl RrcLayer {
+    /// Send the configured MeasConfig to a UE in an RRC Reconfiguration
+    pub async fn configure_measurements(&mut self, rnti: Rnti) -> Result<(), LayerError> {
+        let Some(meas_config) = self.config.meas_config.clone() else {
+            return Ok(());
+        };
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.meas_config.is_some() {
+            return Ok(());
+        }
+        if ue_context.pending_reconfiguration.is_some() {
+            // Added to the next RRC Reconfiguration instead
+            debug!("RRC Reconfiguration ongoing for RNTI {}, deferring MeasConfig", rnti.0);
+            return Ok(());
+        }
+
+        let reconfiguration = RrcReconfiguration {
+            transaction_id: ue_context.allocate_transaction_id(),
+            meas_config: Some(meas_config.clone()),
+            ..Default::default()
+        };
+        ue_context.meas_config = Some(meas_config);
+        ue_context.pending_reconfiguration = Some(PendingReconfiguration {
+            transaction_id: reconfiguration.transaction_id,
+            procedure: None,
+            pdu_session_ids: Vec::new(),
+            failed_pdu_session_ids: Vec::new(),
+            deadline: Instant::now() + Duration::from_millis(self.config.procedure_guard_time_ms as u64),
+        });
+        drop(contexts);
+
+        info!("Sending MeasConfig to RNTI {}", rnti.0);
+        self.send_to_mac(rnti, RrcMessageType::RrcReconfiguration, reconfiguration.encode()).await
+    }
+
+    /// Handle Measurement Report from UE
+    pub(super) async fn handle_measurement_report(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let report = MeasurementReport::decode(&data)?;
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let event = ue_context.meas_config.as_ref()
+            .and_then(|config| config.report_config(report.meas_id))
+            .map(|report_config| report_config.event);
+        if event.is_none() {
+            warn!("Measurement Report from RNTI {} for unknown measId {}", rnti.0, report.meas_id);
+        }
+
+        let measurements = &mut ue_context.measurements;
+        measurements.last_meas_id = report.meas_id;
+        measurements.report_count += 1;
+        if let Some((_, serving)) = report.serving_cells.first() {
+            measurements.serving = Some(serving.into());
+        }
+        measurements.neighbours = report.neighbour_cells.iter().map(CellMeasurement::from).collect();
+        measurements.neighbours.sort_by(|a, b| {
+            b.rsrp_dbm.unwrap_or(f32::MIN).total_cmp(&a.rsrp_dbm.unwrap_or(f32::MIN))
+        });
+
+        info!("Measurement Report from RNTI {} (measId {}, {:?}): serving {:?}, {} neighbours",
+              rnti.0, report.meas_id, event, measurements.serving, measurements.neighbours.len());
+        let mut handover_target = None;
+        if let (Some(EventTrigger::A3 { .. }), Some(best)) = (event, measurements.neighbours.first()) {
+            info!("RNTI {}: neighbour PCI {} is better than the serving cell", rnti.0, best.pci);
+            let ssb_frequency = ue_context.meas_config.as_ref().and_then(|config| {
+                let meas_id = config.meas_ids.iter().find(|id| id.meas_id == report.meas_id)?;
+                config.meas_objects.iter()
+                    .find(|object| object.meas_object_id == meas_id.meas_object_id)
+                    .map(|object| object.ssb_frequency)
+            });
+            handover_target = ssb_frequency.map(|ssb_frequency| (best.pci, ssb_frequency));
+        }
+        drop(contexts);
+
+        if let Some((target_pci, ssb_frequency)) = handover_target {
+            if let Err(e) = self.start_handover(rnti, target_pci, ssb_frequency).await {
+                debug!("No handover of RNTI {} to PCI {}: {}", rnti.0, target_pci, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the latest measurement results of a UE
+    pub async fn get_ue_measurements(&self, rnti: Rnti) -> Option<UeMeasurements> {
+        let contexts = self.ue_contexts.lock().await;
+        contexts.get(&rnti.0).map(|ctx| ctx.measurements.clone())
+    }
+
+    /// Get the latest measurement results of all connected UEs
+    pub async fn get_all_ue_measurements(&self) -> Vec<(Rnti, UeMeasurements)> {
+        let contexts = self.ue_contexts.lock().await;
+        let mut measurements: Vec<_> = contexts.values()
+            .filter(|ctx| ctx.measurements.report_count > 0)
+            .map(|ctx| (ctx.c_rnti, ctx.measurements.clone()))
+            .collect();
+        measurements.sort_by_key(|(rnti, _)| rnti.0);
+        measurements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meas_config_roundtrip_and_validation() {
+        let mut config = default_meas_config(368500, SubcarrierSpacing::Scs15);
+        config.report_configs.push(ReportConfigNr {
+            report_config_id: 4,
+            event: EventTrigger::A5 { threshold1: 40, threshold2: 50 },
+            trigger_quantity: TriggerQuantity::Rsrq,
+            hysteresis: 1,
+            time_to_trigger_ms: 640,
+            report_interval_ms: 480,
+            report_amount: 0,
+            max_report_cells: 8,
+            report_on_leave: true,
+        });
+        config.s_measure_ssb_rsrp = Some(rsrp_dbm_to_range(-80.0));
+        assert!(config.validate().is_ok());
+
+        let mut buf = BytesMut::new();
+        config.encode(&mut buf);
+        let mut data = &buf[..];
+        assert_eq!(MeasConfig::decode(&mut data).unwrap(), config);
+        assert!(data.is_empty());
+
+        config.report_configs[0].time_to_trigger_ms = 300;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_range_conversion() {
+        assert_eq!(rsrp_range_to_dbm(rsrp_dbm_to_range(-95.0)), -95.0);
+        assert_eq!(rsrq_range_to_db(rsrq_db_to_range(-10.5)), -10.5);
+        assert_eq!(sinr_range_to_db(sinr_db_to_range(13.0)), 13.0);
+        assert_eq!(rsrp_dbm_to_range(-200.0), 0);
+    }
+
+    #[test]
+    fn test_meas_config_decode_and_validation_errors() {
+        let config = default_meas_config(368500, SubcarrierSpacing::Scs15);
+        let mut buf = BytesMut::new();
+        config.encode(&mut buf);
+        let decode = |data: &[u8]| MeasConfig::decode(&mut &data[..]);
+
+        // Octet 5 is the SSB subcarrier spacing of the first measObject, then come
+        // the report config count, its ID, event and trigger quantity
+        for (index, value) in [(5, 45), (8, 7), (9, 3)] {
+            let mut corrupt = buf.to_vec();
+            corrupt[index] = value;
+            assert!(matches!(decode(&corrupt), Err(LayerError::InvalidPdu)));
+        }
+        assert!(matches!(decode(&buf[..buf.len() - 1]), Err(LayerError::InvalidPdu)));
+
+        let mut duplicate = config.clone();
+        duplicate.meas_objects.push(duplicate.meas_objects[0].clone());
+        assert!(matches!(duplicate.validate(), Err(LayerError::InvalidConfiguration(_))));
+        let mut hysteresis = config.clone();
+        hysteresis.report_configs[0].hysteresis = 31;
+        assert!(matches!(hysteresis.validate(), Err(LayerError::InvalidConfiguration(_))));
+
+        let report = MeasurementReport {
+            meas_id: 1,
+            serving_cells: vec![(0, MeasResultNr { pci: 1, rsrp: Some(50), rsrq: None, sinr: None })],
+            neighbour_cells: Vec::new(),
+        }.encode();
+        assert!(MeasurementReport::decode(&report).is_ok());
+        assert!(matches!(MeasurementReport::decode(&report[..report.len() - 2]), Err(LayerError::InvalidPdu)));
+        let mut other = report.to_vec();
+        other[0] = 0x31;
+        assert!(matches!(MeasurementReport::decode(&other), Err(LayerError::InvalidPdu)));
+    }
+}
+
@@ -0,0 +1,2352 @@
+//! Radio Resource Control (RRC) Layer Implementation
+//! 
+//! Implements the 5G NR RRC layer according to 3GPP TS 38.331
+
+pub mod capability;
+pub mod context_setup;
+pub mod handover;
+pub mod inactive;
+pub mod measurement;
+pub mod nas_transport;
+pub mod reconfiguration;
+pub mod release;
+pub mod security;
+pub mod user_plane;
+
+use crate::{LayerError, ProtocolLayer};
+use crate::mac::UeSchedulingCapabilities;
+use crate::ngap::pdu::GtpTunnel;
+use crate::sdap::SdapEntity;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut, BufMut};
+use tracing::{debug, info, warn, error};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::Instant;
+use common::types::{Rnti, CellId};
+
+pub use capability::{
+    RatType, UeCapabilityEnquiry, UeCapabilityInformation, UeCapabilityRatContainer, UeNrCapability,
+};
+pub use handover::{
+    DrbSnStatus, ForwardedPacket, HandoverPreparationInformation, HandoverState, ReconfigurationWithSync,
+};
+pub use measurement::{
+    default_meas_config, CellMeasurement, EventTrigger, MeasConfig, MeasIdToAddMod, MeasObjectNr,
+    MeasResultNr, MeasurementReport, ReportConfigNr, TriggerQuantity, UeMeasurements,
+};
+pub use nas_transport::{AmfSelectionInfo, DlInformationTransfer, RegisteredAmf, RrcSetupComplete, UlInformationTransfer};
+pub use reconfiguration::{
+    DataRadioBearer, DrbBearerConfig, DrbToAddMod, PduSessionProcedure, PduSessionResource, PduSessionResourceModify, RrcReconfiguration,
+    UeDrbVolume,
+};
+pub use inactive::{
+    Paging, PagingUeIdentity, ResumeCause, ResumeIdentity, RrcResume, RrcResumeRequest, SuspendConfig,
+};
+pub use release::{
+    ReestablishmentCause, RrcReestablishment, RrcReestablishmentRequest, RrcReject, RrcRelease, RrcReleaseCause,
+};
+pub use security::{CipheringAlgorithm, IntegrityAlgorithm, SecurityContext, SecurityModeCommand};
+pub use user_plane::{GtpuRrcMessage, RrcGtpuMessage};
+
+/// RRC states for UE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RrcState {
+    /// RRC Idle state
+    Idle,
+    /// RRC Inactive state
+    Inactive,
+    /// RRC Connected state
+    Connected,
+}
+
+/// RRC message types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RrcMessageType {
+    /// MIB (Master Information Block)
+    Mib,
+    /// SIB1 (System Information Block 1)
+    Sib1,
+    /// RRC Setup Request
+    RrcSetupRequest,
+    /// RRC Setup
+    RrcSetup,
+    /// RRC Setup Complete
+    RrcSetupComplete,
+    /// RRC Reconfiguration
+    RrcReconfiguration,
+    /// RRC Reconfiguration Complete
+    RrcReconfigurationComplete,
+    /// Security Mode Command
+    SecurityModeCommand,
+    /// Security Mode Complete
+    SecurityModeComplete,
+    /// Security Mode Failure
+    SecurityModeFailure,
+    /// UE Capability Enquiry
+    UeCapabilityEnquiry,
+    /// UE Capability Information
+    UeCapabilityInformation,
+    /// RRC Reject
+    RrcReject,
+    /// RRC Release
+    RrcRelease,
+    /// RRC Re-establishment Request
+    RrcReestablishmentRequest,
+    /// RRC Re-establishment
+    RrcReestablishment,
+    /// RRC Re-establishment Complete
+    RrcReestablishmentComplete,
+    /// RRC Resume Request
+    RrcResumeRequest,
+    /// RRC Resume Request1
+    RrcResumeRequest1,
+    /// RRC Resume
+    RrcResume,
+    /// RRC Resume Complete
+    RrcResumeComplete,
+    /// Paging
+    Paging,
+    /// Measurement Report
+    MeasurementReport,
+    /// DL Information Transfer
+    DlInformationTransfer,
+    /// UL Information Transfer
+    UlInformationTransfer,
+}
+
+impl RrcMessageType {
+    /// Message type of an encoded RRC message
+    pub fn from_pdu(data: &[u8]) -> Option<Self> {
+        if data.is_empty() {
+            return None;
+        }
+        
+        // Simple message type detection (would be ASN.1 in real implementation)
+        match data[0] & 0x3F {
+            0x00 => Some(RrcMessageType::RrcSetupRequest),
+            0x01 => Some(RrcMessageType::RrcSetup),
+            0x02 => Some(RrcMessageType::RrcSetupComplete),
+            0x03 => Some(RrcMessageType::RrcReject),
+            0x04 => Some(RrcMessageType::RrcRelease),
+            0x05 => Some(RrcMessageType::RrcReestablishmentRequest),
+            0x06 => Some(RrcMessageType::RrcReestablishment),
+            0x07 => Some(RrcMessageType::RrcReestablishmentComplete),
+            0x08 => Some(RrcMessageType::RrcResumeRequest),
+            0x09 => Some(RrcMessageType::RrcResumeRequest1),
+            0x0A => Some(RrcMessageType::RrcResume),
+            0x0B => Some(RrcMessageType::RrcResumeComplete),
+            0x0C => Some(RrcMessageType::DlInformationTransfer),
+            0x0D => Some(RrcMessageType::UlInformationTransfer),
+            0x10 => Some(RrcMessageType::SecurityModeCommand),
+            0x11 => Some(RrcMessageType::SecurityModeComplete),
+            0x12 => Some(RrcMessageType::SecurityModeFailure),
+            0x20 => Some(RrcMessageType::RrcReconfiguration),
+            0x21 => Some(RrcMessageType::RrcReconfigurationComplete),
+            0x30 => Some(RrcMessageType::UeCapabilityEnquiry),
+            0x31 => Some(RrcMessageType::UeCapabilityInformation),
+            0x32 => Some(RrcMessageType::MeasurementReport),
+            _ => None,
+        }
+    }
+}
+
+/// Random Access Response Grant
+#[derive(Debug, Clone)]
+pub struct RarGrant {
+    /// Timing advance
+    pub timing_advance: u16,
+    /// Uplink grant
+    pub ul_grant: u32,
+    /// Temporary C-RNTI
+    pub tc_rnti: Rnti,
+}
+
+/// Paging record to transmit in the paging occasions of a UE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingRequest {
+    /// Paged UE identity
+    pub identity: PagingUeIdentity,
+    /// UE_ID selecting the paging occasion, 5G-S-TMSI mod 1024 (TS 38.304 section 7.1)
+    pub ue_id: u16,
+    /// UE specific paging cycle in radio frames, the default paging cycle applies if shorter
+    pub paging_cycle: Option<u16>,
+    /// Paging priority, 1 (highest) to 8
+    pub priority: Option<u8>,
+}
+
+/// RRC Setup Request message
+#[derive(Debug, Clone)]
+pub struct RrcSetupRequest {
+    /// UE identity (S-TMSI or random value)
+    pub ue_identity: Vec<u8>,
+    /// Establishment cause
+    pub establishment_cause: EstablishmentCause,
+}
+
+/// RRC establishment cause
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EstablishmentCause {
+    Emergency,
+    HighPriorityAccess,
+    MtAccess,
+    MoSignalling,
+    MoData,
+    MoVoiceCall,
+    MoVideoCall,
+    MoSms,
+    MpsService,
+    McsService,
+}
+
+/// RRC Setup message
+#[derive(Debug, Clone)]
+pub struct RrcSetup {
+    /// Radio bearer configuration
+    pub radio_bearer_config: RadioBearerConfig,
+    /// Master cell group configuration
+    pub master_cell_group: Vec<u8>,
+}
+
+/// Radio Bearer Configuration
+#[derive(Debug, Clone)]
+pub struct RadioBearerConfig {
+    /// SRB1 configuration
+    pub srb1_config: Vec<u8>,
+    /// Security algorithm configuration
+    pub security_algorithm_config: Option<Vec<u8>>,
+}
+
+/// RRC-MAC interface for message exchange
+#[async_trait]
+pub trait RrcMacInterface: Send + Sync {
+    /// Send RRC message to MAC for transmission
+    async fn send_rrc_message(&self, rnti: Rnti, msg_type: RrcMessageType, data: Bytes) -> Result<(), LayerError>;
+    
+    /// Allocate C-RNTI for a UE
+    async fn allocate_c_rnti(&self) -> Result<Rnti, LayerError>;
+    
+    /// Provide UE capabilities to the scheduler
+    async fn configure_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError>;
+    
+    /// Schedule Random Access Response
+    async fn schedule_rar(&self, tc_rnti: Rnti, grant: RarGrant) -> Result<(), LayerError>;
+    
+    /// Release all MAC resources of a UE and free its C-RNTI
+    async fn release_ue(&self, rnti: Rnti) -> Result<(), LayerError>;
+    
+    /// Schedule a paging record on the PCCH in the UE's paging occasions
+    async fn schedule_paging(&self, request: PagingRequest) -> Result<(), LayerError>;
+    
+    /// Send an RLC PDU of a DRB on its logical channel
+    async fn send_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError>;
+    
+    /// Add or release the logical channels of DRBs ahead of the RRC Reconfiguration
+    async fn configure_drbs(&self, rnti: Rnti, to_setup: Vec<DrbBearerConfig>, to_release: Vec<u8>) -> Result<(), LayerError>;
+}
+
+/// Interface towards a gNB-CU-UP holding PDCP, SDAP and the N3 tunnels of the DRBs
+#[async_trait]
+pub trait RrcCuUpInterface: Send + Sync {
+    /// Set up, modify and release DRBs of a UE ahead of the RRC Reconfiguration,
+    /// returning the uplink F1-U tunnel of each DRB set up
+    async fn configure_bearers(
+        &self,
+        ue_id: u32,
+        rnti: Rnti,
+        drbs: Vec<DrbToAddMod>,
+        to_release: Vec<u8>,
+    ) -> Result<Vec<(u8, GtpTunnel)>, LayerError>;
+
+    /// Pass on the downlink F1-U tunnels the gNB-DU allocated for DRBs of a UE
+    async fn set_dl_tunnels(&self, rnti: Rnti, tunnels: Vec<(u8, GtpTunnel)>) -> Result<(), LayerError>;
+}
+
+/// Messages sent from RRC towards NGAP
+#[derive(Debug, Clone)]
+pub enum RrcNgapMessage {
+    /// Initial NAS message received in RRC Setup Complete
+    InitialUeMessage {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// NAS PDU from the UE
+        nas_pdu: Bytes,
+        /// Establishment cause from RRC Setup Request
+        establishment_cause: EstablishmentCause,
+        /// UE provided information for AMF selection
+        amf_selection: AmfSelectionInfo,
+    },
+    /// NAS message received in UL Information Transfer
+    UplinkNasTransport {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// NAS PDU from the UE
+        nas_pdu: Bytes,
+    },
+    /// UE radio capability to be sent in UE Radio Capability Info Indication
+    UeRadioCapabilityInfo {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Encoded UE-CapabilityRAT-ContainerList
+        ue_radio_capability: Bytes,
+    },
+    /// Outcome of a PDU session resource procedure
+    PduSessionResourceResponse {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Procedure the response belongs to
+        procedure: PduSessionProcedure,
+        /// PDU sessions handled successfully
+        succeeded: Vec<u8>,
+        /// PDU sessions that failed
+        failed: Vec<u8>,
+    },
+    /// Initial Context Setup could not be completed
+    InitialContextSetupFailure {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Failure cause
+        cause: RrcReleaseCause,
+    },
+    /// Request the AMF to release a UE context
+    UeContextReleaseRequest {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Release cause
+        cause: RrcReleaseCause,
+        /// Active PDU sessions
+        pdu_session_ids: Vec<u8>,
+    },
+    /// UE moved between RRC_CONNECTED and RRC_INACTIVE
+    RrcStateTransition {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// New RRC state
+        state: RrcState,
+    },
+    /// UE context has been released
+    UeContextReleaseComplete {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU sessions that were active
+        pdu_session_ids: Vec<u8>,
+    },
+    /// Outcome of a UE Context Modification
+    UeContextModificationResponse {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+    },
+    /// UE Context Modification could not be applied
+    UeContextModificationFailure {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Failure cause
+        cause: RrcReleaseCause,
+    },
+    /// Source side: hand a UE over to the gNB serving a neighbour cell
+    HandoverRequired {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// Physical cell ID of the target cell
+        target_pci: u16,
+        /// K_gNB* for the target cell
+        security_key: [u8; 32],
+        /// Next hop chaining count
+        next_hop_chaining_count: u8,
+        /// Encoded HandoverPreparationInformation
+        rrc_container: Bytes,
+    },
+    /// Target side: UE admitted for a handover
+    HandoverRequestAcknowledge {
+        /// Handover identifier from the Handover Request
+        handover_id: u32,
+        /// UE identifier allocated by the target (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU sessions admitted
+        admitted: Vec<u8>,
+        /// PDU sessions not admitted
+        failed: Vec<u8>,
+        /// Encoded RRC Reconfiguration with reconfigurationWithSync (handover command)
+        rrc_container: Bytes,
+    },
+    /// Target side: UE not admitted for a handover
+    HandoverFailure {
+        /// Handover identifier from the Handover Request
+        handover_id: u32,
+        /// Failure cause
+        cause: RrcReleaseCause,
+    },
+    /// Source side: PDCP COUNTs of the DRBs of a UE commanded to the target
+    SnStatusTransfer {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+        /// COUNTs per DRB
+        drbs: Vec<DrbSnStatus>,
+    },
+    /// Target side: UE reached the cell after handover
+    HandoverNotify {
+        /// UE identifier (used as RAN UE NGAP ID)
+        ue_id: u32,
+    },
+}
+
+/// Messages sent from NGAP towards RRC
+#[derive(Debug, Clone)]
+pub enum NgapRrcMessage {
+    /// Initial Context Setup Request
+    InitialContextSetup {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// K_gNB
+        security_key: [u8; 32],
+        /// NR encryption algorithms supported by the UE (16-bit mask)
+        nr_encryption_algorithms: u16,
+        /// NR integrity protection algorithms supported by the UE (16-bit mask)
+        nr_integrity_algorithms: u16,
+        /// PDU sessions to set up
+        sessions: Vec<PduSessionResource>,
+        /// NAS PDU to deliver to the UE
+        nas_pdu: Option<Bytes>,
+        /// UE radio capability known by the AMF
+        ue_radio_capability: Option<Bytes>,
+    },
+    /// NAS PDU from Downlink NAS Transport
+    DownlinkNasTransport {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// NAS PDU to deliver to the UE
+        nas_pdu: Bytes,
+    },
+    /// PDU Session Resource Setup Request
+    PduSessionResourceSetup {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU sessions to set up
+        sessions: Vec<PduSessionResource>,
+        /// NAS PDU to deliver to the UE
+        nas_pdu: Option<Bytes>,
+    },
+    /// PDU Session Resource Modify Request
+    PduSessionResourceModify {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU sessions to modify
+        sessions: Vec<PduSessionResourceModify>,
+        /// NAS PDU to deliver to the UE
+        nas_pdu: Option<Bytes>,
+    },
+    /// PDU Session Resource Release Command
+    PduSessionResourceRelease {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU sessions to release
+        pdu_session_ids: Vec<u8>,
+        /// NAS PDU to deliver to the UE
+        nas_pdu: Option<Bytes>,
+    },
+    /// UE Context Release Command
+    UeContextRelease {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+    },
+    /// UE Context Modification Request
+    UeContextModification {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// New K_gNB
+        security_key: Option<[u8; 32]>,
+        /// Updated NR encryption algorithms supported by the UE
+        nr_encryption_algorithms: Option<u16>,
+        /// Updated NR integrity protection algorithms supported by the UE
+        nr_integrity_algorithms: Option<u16>,
+    },
+    /// Paging of a UE in RRC_IDLE
+    Paging {
+        /// 5G-S-TMSI (48 bits)
+        s_tmsi: u64,
+        /// UE specific DRX cycle in radio frames
+        paging_drx: Option<u16>,
+        /// Paging priority, 1 (highest) to 8
+        paging_priority: Option<u8>,
+    },
+    /// Source side: handover command from the target
+    HandoverCommand {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// Encoded RRC Reconfiguration with reconfigurationWithSync
+        rrc_container: Bytes,
+    },
+    /// Source side: the target did not admit the UE
+    HandoverPreparationFailure {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+    },
+    /// Target side: admit a UE handed over from another gNB
+    HandoverRequest {
+        /// Handover identifier to answer with
+        handover_id: u32,
+        /// K_gNB* from the source, or NH from the AMF after an N2 handover
+        security_key: [u8; 32],
+        /// Next hop chaining count
+        next_hop_chaining_count: u8,
+        /// NR encryption algorithms supported by the UE (16-bit mask)
+        nr_encryption_algorithms: u16,
+        /// NR integrity protection algorithms supported by the UE (16-bit mask)
+        nr_integrity_algorithms: u16,
+        /// PDU sessions to set up
+        sessions: Vec<PduSessionResource>,
+        /// Encoded HandoverPreparationInformation
+        rrc_container: Bytes,
+    },
+    /// Target side: PDCP COUNTs from the source
+    SnStatusTransfer {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// COUNTs per DRB
+        drbs: Vec<DrbSnStatus>,
+    },
+}
+
+/// UE context
+#[derive(Debug)]
+pub struct UeContext {
+    /// UE identifier
+    pub ue_id: u32,
+    /// C-RNTI
+    pub c_rnti: Rnti,
+    /// Current RRC state
+    pub state: RrcState,
+    /// Security capabilities
+    pub security_capabilities: Vec<u8>,
+    /// UE identity from RRC Setup Request
+    pub ue_identity: Vec<u8>,
+    /// Establishment cause
+    pub establishment_cause: Option<EstablishmentCause>,
+    /// Decoded UE-NR-Capability
+    pub ue_capability: Option<UeNrCapability>,
+    /// Encoded UE-CapabilityRAT-ContainerList (as exchanged with the AMF)
+    pub ue_radio_capability: Option<Bytes>,
+    /// Transaction ID of an outstanding UE Capability Enquiry
+    pub capability_transaction_id: Option<u8>,
+    /// Next RRC transaction identifier
+    pub next_transaction_id: u8,
+    /// Established data radio bearers indexed by DRB ID
+    pub drbs: HashMap<u8, DataRadioBearer>,
+    /// SDAP entities indexed by PDU session ID
+    pub sdap_entities: HashMap<u8, SdapEntity>,
+    /// Outstanding RRC Reconfiguration
+    pub pending_reconfiguration: Option<reconfiguration::PendingReconfiguration>,
+    /// Initial Context Setup waiting for Security Mode Complete
+    pub pending_context_setup: Option<context_setup::PendingContextSetup>,
+    /// AS security context
+    pub security: Option<SecurityContext>,
+    /// Time of the last user activity
+    pub last_activity: Instant,
+    /// Time a UE Context Release Request was sent to the AMF
+    pub release_requested_at: Option<Instant>,
+    /// fullI-RNTI while in RRC_INACTIVE
+    pub i_rnti: Option<u64>,
+    /// Number of RAN paging attempts
+    pub paging_attempts: u8,
+    /// Time of the last RAN paging attempt
+    pub last_paging: Option<Instant>,
+    /// Measurement configuration sent to the UE
+    pub meas_config: Option<MeasConfig>,
+    /// Latest measurement results
+    pub measurements: UeMeasurements,
+    /// Ongoing handover
+    pub handover: Option<HandoverState>,
+}
+
+impl UeContext {
+    /// Create the context of a connected UE
+    pub fn new(ue_id: u32, c_rnti: Rnti) -> Self {
+        Self {
+            ue_id,
+            c_rnti,
+            state: RrcState::Connected,
+            security_capabilities: Vec::new(),
+            ue_identity: Vec::new(),
+            establishment_cause: None,
+            ue_capability: None,
+            ue_radio_capability: None,
+            capability_transaction_id: None,
+            next_transaction_id: 0,
+            drbs: HashMap::new(),
+            sdap_entities: HashMap::new(),
+            pending_reconfiguration: None,
+            pending_context_setup: None,
+            security: None,
+            last_activity: Instant::now(),
+            release_requested_at: None,
+            i_rnti: None,
+            paging_attempts: 0,
+            last_paging: None,
+            meas_config: None,
+            measurements: UeMeasurements::default(),
+            handover: None,
+        }
+    }
+
+
+    /// Allocate the next RRC transaction identifier (0-3)
+    pub fn allocate_transaction_id(&mut self) -> u8 {
+        let id = self.next_transaction_id;
+        self.next_transaction_id = (self.next_transaction_id + 1) % 4;
+        id
+    }
+}
+
+/// RRC layer configuration
+pub struct RrcConfig {
+    /// System Information Block periodicity in ms
+    pub sib_periodicity: u32,
+    /// Maximum number of UE contexts
+    pub max_ue_contexts: u16,
+    /// Cell ID
+    pub cell_id: CellId,
+    /// NR cell identity (36 bits) broadcast in SIB1 and reported in the NR-CGI
+    pub nr_cell_identity: u64,
+    /// PLMN ID (encoded)
+    pub plmn_id: [u8; 3],
+    /// Tracking Area Code
+    pub tac: u32,
+    /// Serving NR band
+    pub band: u16,
+    /// Also request EUTRA-NR capabilities in UE Capability Enquiry
+    pub request_eutra_nr_capability: bool,
+    /// Guard time for RRC procedures awaiting a UE response, in ms
+    pub procedure_guard_time_ms: u32,
+    /// Time to wait for the UE Context Release Command after a UE Context
+    /// Release Request before releasing the UE locally, in ms
+    pub release_request_guard_time_ms: u32,
+    /// Physical cell ID
+    pub pci: u16,
+    /// UE inactivity timer in seconds
+    pub inactivity_timer_s: u32,
+    /// Move inactive UEs to RRC_INACTIVE instead of releasing them
+    pub inactive_enabled: bool,
+    /// ran-PagingCycle in radio frames (32, 64, 128 or 256)
+    pub ran_paging_cycle: u16,
+    /// Measurement configuration for connected UEs (None disables measurements)
+    pub meas_config: Option<MeasConfig>,
+}
+
+/// RRC layer implementation
+pub struct RrcLayer {
+    config: RrcConfig,
+    initialized: bool,
+    /// UE contexts indexed by C-RNTI
+    ue_contexts: Arc<Mutex<HashMap<u16, UeContext>>>,
+    /// Suspended UE contexts indexed by fullI-RNTI
+    inactive_contexts: Arc<Mutex<HashMap<u64, UeContext>>>,
+    /// MAC interface for message transmission
+    mac_interface: Option<Arc<dyn RrcMacInterface>>,
+    /// gNB-CU-UP interface when the user plane is split off over E1
+    cu_up_interface: Option<Arc<dyn RrcCuUpInterface>>,
+    /// Next UE ID to allocate
+    next_ue_id: Arc<Mutex<u32>>,
+    /// Message receiver from MAC
+    mac_rx: Option<mpsc::Receiver<(Rnti, Bytes)>>,
+    /// Message sender to MAC
+    mac_tx: Option<mpsc::Sender<(Rnti, RrcMessageType, Bytes)>>,
+    /// Message sender to NGAP
+    ngap_tx: Option<mpsc::Sender<RrcNgapMessage>>,
+    /// Uplink packet sender to the GTP-U endpoint
+    gtpu_tx: Option<mpsc::Sender<RrcGtpuMessage>>,
+}
+
+impl RrcLayer {
+    /// Create a new RRC layer instance
+    pub fn new(config: RrcConfig) -> Self {
+        Self {
+            config,
+            initialized: false,
+            ue_contexts: Arc::new(Mutex::new(HashMap::new())),
+            inactive_contexts: Arc::new(Mutex::new(HashMap::new())),
+            mac_interface: None,
+            cu_up_interface: None,
+            next_ue_id: Arc::new(Mutex::new(1000)),
+            mac_rx: None,
+            mac_tx: None,
+            ngap_tx: None,
+            gtpu_tx: None,
+        }
+    }
+    
+    /// Set MAC interface
+    pub fn set_mac_interface(&mut self, mac_interface: Arc<dyn RrcMacInterface>) {
+        self.mac_interface = Some(mac_interface);
+    }
+    
+    /// Set the gNB-CU-UP interface, moving the DRB user plane to the CU-UP
+    pub fn set_cu_up_interface(&mut self, cu_up_interface: Arc<dyn RrcCuUpInterface>) {
+        self.cu_up_interface = Some(cu_up_interface);
+    }
+    
+    /// Set NGAP message channel
+    pub fn set_ngap_channel(&mut self, tx: mpsc::Sender<RrcNgapMessage>) {
+        self.ngap_tx = Some(tx);
+    }
+    
+    /// Create message channels
+    pub fn create_channels(&mut self) -> (mpsc::Sender<(Rnti, Bytes)>, mpsc::Receiver<(Rnti, RrcMessageType, Bytes)>) {
+        let (mac_to_rrc_tx, mac_to_rrc_rx) = mpsc::channel(100);
+        let (rrc_to_mac_tx, rrc_to_mac_rx) = mpsc::channel(100);
+        
+        self.mac_rx = Some(mac_to_rrc_rx);
+        self.mac_tx = Some(rrc_to_mac_tx);
+        
+        (mac_to_rrc_tx, rrc_to_mac_rx)
+    }
+    
+    /// Handle RRC Setup Request from UE
+    async fn handle_rrc_setup_request(&mut self, rnti: Rnti, request: RrcSetupRequest) -> Result<(), LayerError> {
+        info!("Handling RRC Setup Request from RNTI {}: cause={:?}", rnti.0, request.establishment_cause);
+        
+        let active_contexts = self.ue_contexts.lock().await.len();
+        if active_contexts >= self.config.max_ue_contexts as usize {
+            warn!("Maximum number of UE contexts ({}) reached, rejecting RNTI {}",
+                  self.config.max_ue_contexts, rnti.0);
+            return self.send_rrc_reject(rnti).await;
+        }
+        
+        // Allocate UE ID
+        let mut ue_id_guard = self.next_ue_id.lock().await;
+        let ue_id = *ue_id_guard;
+        *ue_id_guard += 1;
+        drop(ue_id_guard);
+        
+        // Create new UE context
+        let ue_context = UeContext {
+            ue_identity: request.ue_identity.clone(),
+            establishment_cause: Some(request.establishment_cause),
+            ..UeContext::new(ue_id, rnti)
+        };
+        
+        // Store UE context
+        let mut contexts = self.ue_contexts.lock().await;
+        contexts.insert(rnti.0, ue_context);
+        drop(contexts);
+        
+        // Generate RRC Setup message
+        let rrc_setup = self.generate_rrc_setup(rnti).await?;
+        
+        // Send to MAC for transmission
+        self.send_to_mac(rnti, RrcMessageType::RrcSetup, rrc_setup).await?;
+        info!("Sent RRC Setup to RNTI {}", rnti.0);
+        
+        Ok(())
+    }
+    
+    /// Generate RRC Setup message
+    async fn generate_rrc_setup(&self, rnti: Rnti) -> Result<Bytes, LayerError> {
+        debug!("Generating RRC Setup for RNTI {}", rnti.0);
+        
+        // Create a simplified RRC Setup message
+        // In a real implementation, this would use ASN.1 encoding
+        let mut buf = BytesMut::new();
+        
+        // Message type indicator
+        buf.put_u8(0x00); // RRC Setup
+        
+        // Transaction ID
+        buf.put_u8(0x00);
+        
+        // Radio Bearer Config
+        // SRB1 configuration
+        buf.put_u8(0x01); // SRB ID
+        buf.put_u8(0x00); // RLC mode (AM)
+        buf.put_u8(0x05); // SN field length
+        
+        // Master Cell Group config (simplified)
+        buf.put_u8(0x10); // Cell group ID
+        buf.put_u16(self.config.cell_id.0); // Cell ID
+        
+        // Physical cell configuration
+        buf.put_u8(0x20); // Config type
+        buf.put_u8(0x00); // Default config
+        
+        Ok(buf.freeze())
+    }
+    
+    /// Start the UE capability transfer procedure
+    ///
+    /// If the AMF already provided the UE radio capability it is used directly, otherwise
+    /// a UE Capability Enquiry is sent to the UE.
+    pub async fn start_ue_capability_transfer(&mut self, rnti: Rnti, ue_radio_capability: Option<Bytes>) -> Result<(), LayerError> {
+        if let Some(ue_radio_capability) = ue_radio_capability {
+            info!("Using UE radio capability provided by AMF for RNTI {}", rnti.0);
+            let containers = capability::decode_container_list(&ue_radio_capability)?;
+            return self.store_ue_capabilities(rnti, containers, ue_radio_capability, false).await;
+        }
+        
+        let mut rat_types = vec![RatType::Nr];
+        if self.config.request_eutra_nr_capability {
+            rat_types.push(RatType::EutraNr);
+        }
+        
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let transaction_id = ue_context.allocate_transaction_id();
+        ue_context.capability_transaction_id = Some(transaction_id);
+        drop(contexts);
+        
+        let enquiry = UeCapabilityEnquiry {
+            transaction_id,
+            rat_types,
+        };
+        debug!("Sending UE Capability Enquiry to RNTI {}: {:?}", rnti.0, enquiry.rat_types);
+        
+        self.send_to_mac(rnti, RrcMessageType::UeCapabilityEnquiry, enquiry.encode()).await
+    }
+    
+    /// Handle UE Capability Information from UE
+    async fn handle_ue_capability_information(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let information = UeCapabilityInformation::decode(&data)?;
+        info!("Handling UE Capability Information from RNTI {}: {} containers", 
+              rnti.0, information.containers.len());
+        
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.capability_transaction_id != Some(information.transaction_id) {
+            warn!("UE Capability Information from RNTI {} with unexpected transaction ID {}", 
+                  rnti.0, information.transaction_id);
+        }
+        ue_context.capability_transaction_id = None;
+        drop(contexts);
+        
+        let ue_radio_capability = capability::encode_container_list(&information.containers);
+        self.store_ue_capabilities(rnti, information.containers, ue_radio_capability, true).await
+    }
+    
+    /// Store UE capabilities, configure the scheduler and optionally forward them to the AMF
+    async fn store_ue_capabilities(
+        &mut self,
+        rnti: Rnti,
+        containers: Vec<UeCapabilityRatContainer>,
+        ue_radio_capability: Bytes,
+        forward_to_amf: bool,
+    ) -> Result<(), LayerError> {
+        let nr_capability = match containers.iter().find(|c| c.rat_type == RatType::Nr) {
+            Some(container) => Some(UeNrCapability::decode(&container.container)?),
+            None => {
+                warn!("No UE-NR-Capability container for RNTI {}", rnti.0);
+                None
+            }
+        };
+        
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let ue_id = ue_context.ue_id;
+        ue_context.ue_capability = nr_capability.clone();
+        ue_context.ue_radio_capability = Some(ue_radio_capability.clone());
+        drop(contexts);
+        
+        if let Some(nr_capability) = &nr_capability {
+            let scheduling_capabilities = nr_capability.scheduling_capabilities(self.config.band);
+            info!("UE {} (RNTI {}) capabilities: release {}, {} bands, 256QAM DL={}", 
+                  ue_id, rnti.0, nr_capability.access_stratum_release, 
+                  nr_capability.bands.len(), scheduling_capabilities.supports_256qam_dl);
+            
+            if let Some(mac_interface) = &self.mac_interface {
+                mac_interface.configure_ue_capabilities(rnti, scheduling_capabilities).await?;
+            }
+        }
+        
+        if let Err(e) = self.configure_measurements(rnti).await {
+            warn!("Failed to configure measurements for RNTI {}: {}", rnti.0, e);
+        }
+        
+        if forward_to_amf {
+            if let Some(ngap_tx) = &self.ngap_tx {
+                let message = RrcNgapMessage::UeRadioCapabilityInfo {
+                    ue_id,
+                    ue_radio_capability,
+                };
+                if let Err(e) = ngap_tx.send(message).await {
+                    error!("Failed to send UE radio capability to NGAP: {}", e);
+                }
+            } else {
+                debug!("No NGAP channel configured, UE radio capability not forwarded");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Run RRC procedure guard timers, the UE inactivity timer, RAN paging and handover timers
+    pub async fn handle_timers(&mut self, now: Instant) {
+        self.check_reconfiguration_timers(now).await;
+        self.check_context_setup_timers(now).await;
+        self.check_inactivity_timers(now).await;
+        self.check_ran_paging_timers(now).await;
+        self.check_handover_timers(now).await;
+    }
+    
+    /// Get the decoded NR capability of a UE
+    pub async fn get_ue_capability(&self, rnti: Rnti) -> Option<UeNrCapability> {
+        let contexts = self.ue_contexts.lock().await;
+        contexts.get(&rnti.0).and_then(|ctx| ctx.ue_capability.clone())
+    }
+    
+    /// Send an RRC message to MAC for transmission
+    async fn send_to_mac(&self, rnti: Rnti, msg_type: RrcMessageType, data: Bytes) -> Result<(), LayerError> {
+        if let Some(mac_interface) = &self.mac_interface {
+            mac_interface.send_rrc_message(rnti, msg_type, data).await
+        } else {
+            error!("No MAC interface configured");
+            Err(LayerError::ConfigurationError("No MAC interface".into()))
+        }
+    }
+    
+    /// Parse RRC message type from data
+    fn parse_message_type(&self, data: &[u8]) -> Option<RrcMessageType> {
+        RrcMessageType::from_pdu(data)
+    }
+    
+    /// Handle an uplink RRC message received from MAC for a given RNTI
+    pub async fn handle_uplink_message(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        // Parse message type
+        if let Some(msg_type) = self.parse_message_type(&data) {
+            info!("Received RRC message type: {:?} from RNTI {}", msg_type, rnti.0);
+            self.report_ue_activity(rnti).await;
+            
+            match msg_type {
+                RrcMessageType::RrcSetupRequest => {
+                    match self.parse_rrc_setup_request(&data) {
+                        Ok(request) => {
+                            if let Err(e) = self.handle_rrc_setup_request(rnti, request).await {
+                                error!("Failed to handle RRC Setup Request: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to parse RRC Setup Request: {}", e);
+                        }
+                    }
+                }
+                RrcMessageType::RrcSetupComplete => {
+                    if let Err(e) = self.handle_rrc_setup_complete(rnti, data.clone()).await {
+                        error!("Failed to handle RRC Setup Complete: {}", e);
+                    }
+                }
+                RrcMessageType::RrcReconfigurationComplete => {
+                    if let Err(e) = self.handle_rrc_reconfiguration_complete(rnti, data.clone()).await {
+                        error!("Failed to handle RRC Reconfiguration Complete: {}", e);
+                    }
+                }
+                RrcMessageType::UeCapabilityInformation => {
+                    if let Err(e) = self.handle_ue_capability_information(rnti, data.clone()).await {
+                        error!("Failed to handle UE Capability Information: {}", e);
+                    }
+                }
+                RrcMessageType::RrcReestablishmentRequest => {
+                    if let Err(e) = self.handle_rrc_reestablishment_request(rnti, data.clone()).await {
+                        error!("Failed to handle RRC Re-establishment Request: {}", e);
+                    }
+                }
+                RrcMessageType::RrcReestablishmentComplete => {
+                    if let Err(e) = self.handle_rrc_reestablishment_complete(rnti).await {
+                        error!("Failed to handle RRC Re-establishment Complete: {}", e);
+                    }
+                }
+                RrcMessageType::RrcResumeRequest | RrcMessageType::RrcResumeRequest1 => {
+                    if let Err(e) = self.handle_rrc_resume_request(rnti, data.clone()).await {
+                        error!("Failed to handle RRC Resume Request: {}", e);
+                    }
+                }
+                RrcMessageType::MeasurementReport => {
+                    if let Err(e) = self.handle_measurement_report(rnti, data.clone()).await {
+                        error!("Failed to handle Measurement Report: {}", e);
+                    }
+                }
+                RrcMessageType::RrcResumeComplete => {
+                    if let Err(e) = self.handle_rrc_resume_complete(rnti).await {
+                        error!("Failed to handle RRC Resume Complete: {}", e);
+                    }
+                }
+                RrcMessageType::SecurityModeComplete => {
+                    if let Err(e) = self.handle_security_mode_complete(rnti, data.clone()).await {
+                        error!("Failed to handle Security Mode Complete: {}", e);
+                    }
+                }
+                RrcMessageType::SecurityModeFailure => {
+                    if let Err(e) = self.handle_security_mode_failure(rnti, data.clone()).await {
+                        error!("Failed to handle Security Mode Failure: {}", e);
+                    }
+                }
+                RrcMessageType::UlInformationTransfer => {
+                    if let Err(e) = self.handle_ul_information_transfer(rnti, data.clone()).await {
+                        error!("Failed to handle UL Information Transfer: {}", e);
+                    }
+                }
+                _ => {
+                    debug!("Unhandled RRC message type: {:?}", msg_type);
+                }
+            }
+        } else {
+            warn!("Unknown RRC message type");
+        }
+        
+        Ok(())
+    }
+    
+    /// Parse RRC Setup Request
+    fn parse_rrc_setup_request(&self, data: &[u8]) -> Result<RrcSetupRequest, LayerError> {
+        if data.len() < 3 {
+            return Err(LayerError::InvalidPdu);
+        }
+        
+        // Skip message type
+        let mut idx = 1;
+        
+        // Parse UE identity length
+        let id_len = data[idx] as usize;
+        idx += 1;
+        
+        if data.len() < idx + id_len + 1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        
+        // Extract UE identity
+        let ue_identity = data[idx..idx + id_len].to_vec();
+        idx += id_len;
+        
+        // Parse establishment cause
+        let cause_byte = data[idx];
+        let establishment_cause = match cause_byte {
+            0 => EstablishmentCause::Emergency,
+            1 => EstablishmentCause::HighPriorityAccess,
+            2 => EstablishmentCause::MtAccess,
+            3 => EstablishmentCause::MoSignalling,
+            4 => EstablishmentCause::MoData,
+            5 => EstablishmentCause::MoVoiceCall,
+            6 => EstablishmentCause::MoVideoCall,
+            7 => EstablishmentCause::MoSms,
+            _ => EstablishmentCause::MoData, // Default
+        };
+        
+        Ok(RrcSetupRequest {
+            ue_identity,
+            establishment_cause,
+        })
+    }
+}
+
+#[async_trait]
+impl ProtocolLayer for RrcLayer {
+    async fn initialize(&mut self) -> Result<(), LayerError> {
+        info!("Initializing RRC layer");
+        debug!("RRC config: sib_periodicity={}ms, max_ue_contexts={}", 
+               self.config.sib_periodicity,
+               self.config.max_ue_contexts);
+        
+        // TODO: Initialize RRC resources
+        // - Setup system information broadcasting
+        // - Setup security contexts
+        
+        if let Some(meas_config) = &self.config.meas_config {
+            meas_config.validate()?;
+            debug!("Measurement configuration: {} measObjects, {} reportConfigs, {} measIds",
+                   meas_config.meas_objects.len(), meas_config.report_configs.len(), meas_config.meas_ids.len());
+        }
+        
+        self.initialized = true;
+        info!("RRC layer initialized successfully");
+        Ok(())
+    }
+    
+    async fn process_uplink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        debug!("RRC processing uplink data: {} bytes", data.len());
+        
+        // Without RNTI information from lower layers, assume the first C-RNTI
+        let rnti = Rnti::new(0x4601);
+        self.handle_uplink_message(rnti, data.clone()).await?;
+        
+        // Pass through to upper layers if needed
+        Ok(data)
+    }
+    
+    async fn process_downlink(&mut self, data: Bytes) -> Result<Bytes, LayerError> {
+        if !self.initialized {
+            return Err(LayerError::NotInitialized);
+        }
+        
+        debug!("RRC processing downlink data: {} bytes", data.len());
+        
+        // TODO: Encode RRC messages for transmission
+        
+        Ok(data)
+    }
+    
+    async fn shutdown(&mut self) -> Result<(), LayerError> {
+        info!("Shutting down RRC layer");
+        
+        // Release all UE contexts
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_count = contexts.len();
+        if ue_count > 0 {
+            warn!("Releasing {} UE contexts", ue_count);
+            contexts.clear();
+        }
+        drop(contexts);
+        
+        self.initialized = false;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::{CellId, SNssai};
+    use capability::{BandCapability, PdcpCapability, RlcCapability};
+    
+    /// MAC stub recording what RRC sends
+    #[derive(Default)]
+    struct MockMac {
+        sent: std::sync::Mutex<Vec<(Rnti, RrcMessageType, Bytes)>>,
+        capabilities: std::sync::Mutex<Vec<(Rnti, UeSchedulingCapabilities)>>,
+        released: std::sync::Mutex<Vec<Rnti>>,
+        paging: std::sync::Mutex<Vec<PagingRequest>>,
+        user_data: std::sync::Mutex<Vec<(Rnti, u8, Bytes)>>,
+        drbs: std::sync::Mutex<Vec<(Rnti, Vec<DrbBearerConfig>, Vec<u8>)>>,
+    }
+    
+    #[async_trait]
+    impl RrcMacInterface for MockMac {
+        async fn send_rrc_message(&self, rnti: Rnti, msg_type: RrcMessageType, data: Bytes) -> Result<(), LayerError> {
+            self.sent.lock().unwrap().push((rnti, msg_type, data));
+            Ok(())
+        }
+        
+        async fn allocate_c_rnti(&self) -> Result<Rnti, LayerError> {
+            Ok(Rnti::new(0x4601))
+        }
+        
+        async fn configure_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError> {
+            self.capabilities.lock().unwrap().push((rnti, capabilities));
+            Ok(())
+        }
+        
+        async fn schedule_rar(&self, _tc_rnti: Rnti, _grant: RarGrant) -> Result<(), LayerError> {
+            Ok(())
+        }
+        
+        async fn release_ue(&self, rnti: Rnti) -> Result<(), LayerError> {
+            self.released.lock().unwrap().push(rnti);
+            Ok(())
+        }
+        
+        async fn schedule_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+            self.paging.lock().unwrap().push(request);
+            Ok(())
+        }
+        
+        async fn send_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+            self.user_data.lock().unwrap().push((rnti, lcid, data));
+            Ok(())
+        }
+        
+        async fn configure_drbs(&self, rnti: Rnti, to_setup: Vec<DrbBearerConfig>, to_release: Vec<u8>) -> Result<(), LayerError> {
+            self.drbs.lock().unwrap().push((rnti, to_setup, to_release));
+            Ok(())
+        }
+    }
+    
+    fn test_config() -> RrcConfig {
+        RrcConfig {
+            sib_periodicity: 160,
+            max_ue_contexts: 100,
+            cell_id: CellId(1),
+            nr_cell_identity: 0x0_0000_4001,
+            plmn_id: [0x00, 0xF1, 0x10], // 00101
+            tac: 7,
+            band: 3,
+            request_eutra_nr_capability: true,
+            procedure_guard_time_ms: 500,
+            release_request_guard_time_ms: 5000,
+            pci: 1,
+            inactivity_timer_s: 30,
+            inactive_enabled: true,
+            ran_paging_cycle: 32,
+            meas_config: Some(default_meas_config(368500, common::types::SubcarrierSpacing::Scs15)),
+        }
+    }
+    
+    async fn connected_rrc(mac: Arc<dyn RrcMacInterface>, rnti: Rnti) -> RrcLayer {
+        let mut rrc = RrcLayer::new(test_config());
+        rrc.set_mac_interface(mac);
+        rrc.initialize().await.unwrap();
+        
+        let request = RrcSetupRequest {
+            ue_identity: vec![0x01, 0x02, 0x03, 0x04],
+            establishment_cause: EstablishmentCause::MoData,
+        };
+        rrc.handle_rrc_setup_request(rnti, request).await.unwrap();
+        rrc
+    }
+    
+    #[tokio::test]
+    async fn test_rrc_initialization() {
+        let mut rrc = RrcLayer::new(test_config());
+        assert!(rrc.initialize().await.is_ok());
+    }
+    
+    #[tokio::test]
+    async fn test_ue_context_creation() {
+        let rnti = Rnti::new(0x4601);
+        let rrc = connected_rrc(Arc::new(MockMac::default()), rnti).await;
+        
+        let contexts = rrc.ue_contexts.lock().await;
+        assert_eq!(contexts.len(), 1);
+        assert_eq!(contexts[&rnti.0].state, RrcState::Connected);
+    }
+    
+    #[tokio::test]
+    async fn test_ue_capability_transfer() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        
+        // No capability from AMF: enquiry for NR and EUTRA-NR is sent
+        rrc.start_ue_capability_transfer(rnti, None).await.unwrap();
+        let (_, msg_type, enquiry) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::UeCapabilityEnquiry);
+        assert_eq!(&enquiry[2..], &[2, RatType::Nr as u8, RatType::EutraNr as u8]);
+        
+        let nr_capability = UeNrCapability {
+            access_stratum_release: 15,
+            pdcp: PdcpCapability::default(),
+            rlc: RlcCapability::default(),
+            bands: vec![BandCapability {
+                band: 3,
+                supports_256qam_dl: true,
+                supports_256qam_ul: false,
+                max_mimo_layers_dl: 2,
+                max_mimo_layers_ul: 1,
+            }],
+        };
+        let information = UeCapabilityInformation {
+            transaction_id: enquiry[1],
+            containers: vec![UeCapabilityRatContainer {
+                rat_type: RatType::Nr,
+                container: nr_capability.encode(),
+            }],
+        };
+        rrc.handle_uplink_message(rnti, information.encode()).await.unwrap();
+        
+        assert_eq!(rrc.get_ue_capability(rnti).await, Some(nr_capability));
+        let (_, caps) = mac.capabilities.lock().unwrap()[0];
+        assert!(caps.supports_256qam_dl);
+        assert_eq!(caps.max_mimo_layers_dl, 2);
+        
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::UeRadioCapabilityInfo { ue_radio_capability, .. } => {
+                assert_eq!(ue_radio_capability, capability::encode_container_list(&information.containers));
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_ue_capability_information_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        
+        let information = |containers| UeCapabilityInformation { transaction_id: 0, containers }.encode();
+        
+        // A UE-NR-Capability that does not decode fails the procedure
+        let corrupt = information(vec![UeCapabilityRatContainer {
+            rat_type: RatType::Nr,
+            container: Bytes::from_static(&[15, 0, 0]),
+        }]);
+        assert!(matches!(rrc.handle_ue_capability_information(rnti, corrupt).await, Err(LayerError::InvalidPdu)));
+        assert_eq!(rrc.get_ue_capability(rnti).await, None);
+        assert!(mac.capabilities.lock().unwrap().is_empty());
+        assert!(ngap_rx.try_recv().is_err());
+        
+        // Unknown UE
+        let eutra_only = information(vec![UeCapabilityRatContainer {
+            rat_type: RatType::Eutra,
+            container: Bytes::from_static(&[0x01]),
+        }]);
+        assert!(matches!(rrc.handle_ue_capability_information(Rnti::new(0x4602), eutra_only.clone()).await,
+                         Err(LayerError::InvalidState(_))));
+        
+        // Without an NR container the scheduler keeps its defaults, the AMF still gets the list
+        rrc.handle_ue_capability_information(rnti, eutra_only).await.unwrap();
+        assert_eq!(rrc.get_ue_capability(rnti).await, None);
+        assert!(mac.capabilities.lock().unwrap().is_empty());
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::UeRadioCapabilityInfo { .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_drb_setup_and_release() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        let setup = NgapRrcMessage::PduSessionResourceSetup {
+            ue_id,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            nas_pdu: Some(Bytes::from_static(&[0x7E, 0x00])),
+        };
+        rrc.handle_ngap_message(setup).await.unwrap();
+        
+        let (_, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::RrcReconfiguration);
+        let reconfiguration = RrcReconfiguration::decode(&data).unwrap();
+        assert_eq!(reconfiguration.drbs_to_add_mod[0].drb_id, 1);
+        assert_eq!(reconfiguration.rlc_bearers_to_add_mod[0].logical_channel_id, 4);
+        assert_eq!(reconfiguration.dedicated_nas_messages.len(), 1);
+        assert!(rrc.get_drb_entities(rnti, 1).await.is_some());
+        let (_, to_setup, _) = mac.drbs.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((to_setup[0].drb_id, to_setup[0].lcid, to_setup[0].qos_flows.clone()), (1, 4, vec![1]));
+        
+        // UE confirms the reconfiguration
+        let complete = Bytes::from(vec![0x21, reconfiguration.transaction_id]);
+        rrc.handle_uplink_message(rnti, complete).await.unwrap();
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::PduSessionResourceResponse { procedure, succeeded, failed, .. } => {
+                assert_eq!(procedure, PduSessionProcedure::Setup);
+                assert_eq!(succeeded, vec![1]);
+                assert!(failed.is_empty());
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        // User data goes through SDAP with its QoS flow in the header
+        let (gtpu_tx, mut gtpu_rx) = mpsc::channel(10);
+        rrc.set_gtpu_channel(gtpu_tx);
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14]);
+        rrc.handle_gtpu_message(GtpuRrcMessage::DownlinkData {
+            ue_id, pdu_session_id: 1, qfi: Some(1), rqi: false, data: packet.clone(),
+        }).await.unwrap();
+        let (_, lcid, pdu) = mac.user_data.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((lcid, pdu[0], pdu.slice(1..)), (4, 0x01, packet.clone()));
+        let mut uplink = vec![0x81];
+        uplink.extend_from_slice(&packet);
+        rrc.handle_uplink_user_data(rnti, 4, Bytes::from(uplink)).await.unwrap();
+        match gtpu_rx.try_recv().unwrap() {
+            RrcGtpuMessage::UplinkData { pdu_session_id, qfi, data, .. } => {
+                assert_eq!((pdu_session_id, qfi, data), (1, 1, packet));
+            }
+        }
+
+        // Release, then let the guard timer expire
+        let release = NgapRrcMessage::PduSessionResourceRelease {
+            ue_id,
+            pdu_session_ids: vec![1],
+            nas_pdu: None,
+        };
+        rrc.handle_ngap_message(release).await.unwrap();
+        assert!(rrc.get_drb_entities(rnti, 1).await.is_none());
+        assert_eq!(mac.drbs.lock().unwrap().last().unwrap().2, vec![1]);
+        
+        rrc.handle_timers(tokio::time::Instant::now() + tokio::time::Duration::from_secs(1)).await;
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::PduSessionResourceResponse { procedure, succeeded, failed, .. } => {
+                assert_eq!(procedure, PduSessionProcedure::Release);
+                assert_eq!(succeeded, vec![1]);
+                assert!(failed.is_empty());
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_drb_setup_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        let session = |pdu_session_id| PduSessionResource {
+            pdu_session_id, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+        };
+        
+        rrc.handle_ngap_message(NgapRrcMessage::PduSessionResourceSetup {
+            ue_id, sessions: vec![session(1)], nas_pdu: None,
+        }).await.unwrap();
+        let (_, _, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        let transaction_id = RrcReconfiguration::decode(&data).unwrap().transaction_id;
+        
+        // One RRC Reconfiguration at a time
+        let second = NgapRrcMessage::PduSessionResourceSetup { ue_id, sessions: vec![session(2)], nas_pdu: None };
+        assert!(matches!(rrc.handle_ngap_message(second).await, Err(LayerError::InvalidState(_))));
+        
+        // A complete of another transaction does not conclude the procedure
+        let complete = Bytes::from(vec![0x21, (transaction_id + 1) & 0x03]);
+        rrc.handle_rrc_reconfiguration_complete(rnti, complete).await.unwrap();
+        assert!(ngap_rx.try_recv().is_err());
+        assert!(matches!(rrc.handle_rrc_reconfiguration_complete(rnti, Bytes::from_static(&[0x21])).await,
+                         Err(LayerError::InvalidPdu)));
+        
+        // Without a complete, the guard timer fails the session and removes its DRB
+        rrc.handle_timers(tokio::time::Instant::now() + tokio::time::Duration::from_secs(1)).await;
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::PduSessionResourceResponse { procedure, succeeded, failed, .. } => {
+                assert_eq!(procedure, PduSessionProcedure::Setup);
+                assert!(succeeded.is_empty());
+                assert_eq!(failed, vec![1]);
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+        assert!(rrc.get_drb_entities(rnti, 1).await.is_none());
+        
+        // A PDU session set up twice fails the second time
+        rrc.handle_ngap_message(NgapRrcMessage::PduSessionResourceSetup {
+            ue_id, sessions: vec![session(1)], nas_pdu: None,
+        }).await.unwrap();
+        let (_, _, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        let transaction_id = RrcReconfiguration::decode(&data).unwrap().transaction_id;
+        rrc.handle_rrc_reconfiguration_complete(rnti, Bytes::from(vec![0x21, transaction_id])).await.unwrap();
+        ngap_rx.try_recv().unwrap();
+        rrc.handle_ngap_message(NgapRrcMessage::PduSessionResourceSetup {
+            ue_id, sessions: vec![session(1), session(3)], nas_pdu: None,
+        }).await.unwrap();
+        let (_, _, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        let reconfiguration = RrcReconfiguration::decode(&data).unwrap();
+        assert_eq!(reconfiguration.drbs_to_add_mod.len(), 1);
+        rrc.handle_rrc_reconfiguration_complete(rnti, Bytes::from(vec![0x21, reconfiguration.transaction_id])).await.unwrap();
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::PduSessionResourceResponse { succeeded, failed, .. } => {
+                assert_eq!((succeeded, failed), (vec![3], vec![1]));
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_rrc_reject_when_full() {
+        let mac = Arc::new(MockMac::default());
+        let mut config = test_config();
+        config.max_ue_contexts = 1;
+        let mut rrc = RrcLayer::new(config);
+        rrc.set_mac_interface(mac.clone());
+        rrc.initialize().await.unwrap();
+        
+        for rnti in [Rnti::new(0x4601), Rnti::new(0x4602)] {
+            let request = RrcSetupRequest {
+                ue_identity: vec![0x01],
+                establishment_cause: EstablishmentCause::MoData,
+            };
+            rrc.handle_rrc_setup_request(rnti, request).await.unwrap();
+        }
+        
+        let (rnti, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((rnti, msg_type), (Rnti::new(0x4602), RrcMessageType::RrcReject));
+        assert_eq!(data[1], release::RRC_REJECT_WAIT_TIME_S);
+        assert_eq!(*mac.released.lock().unwrap(), vec![Rnti::new(0x4602)]);
+        assert_eq!(rrc.ue_contexts.lock().await.len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_inactivity_release() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        // Inactivity timer expiry asks the AMF to release the UE
+        rrc.handle_timers(Instant::now() + tokio::time::Duration::from_secs(31)).await;
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::UeContextReleaseRequest { ue_id: id, cause, .. } => {
+                assert_eq!(id, ue_id);
+                assert_eq!(cause, RrcReleaseCause::UserInactivity);
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+        
+        // AMF commands the release
+        rrc.handle_ngap_message(NgapRrcMessage::UeContextRelease { ue_id }).await.unwrap();
+        assert_eq!(mac.sent.lock().unwrap().last().unwrap().1, RrcMessageType::RrcRelease);
+        assert_eq!(*mac.released.lock().unwrap(), vec![rnti]);
+        assert!(rrc.ue_contexts.lock().await.is_empty());
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::UeContextReleaseComplete { ue_id: id, .. } if id == ue_id));
+    }
+    
+    #[tokio::test]
+    async fn test_unanswered_release_request() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        rrc.report_radio_link_failure(rnti).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::UeContextReleaseRequest { .. }));
+        
+        // A slow AMF outlasting the procedure guard time keeps the UE
+        rrc.handle_timers(Instant::now() + tokio::time::Duration::from_secs(1)).await;
+        assert!(rrc.ue_contexts.lock().await.contains_key(&rnti.0));
+        assert!(mac.released.lock().unwrap().is_empty());
+        
+        // Without an answer within the release request guard time it is released locally
+        rrc.handle_timers(Instant::now() + tokio::time::Duration::from_secs(6)).await;
+        assert!(rrc.ue_contexts.lock().await.is_empty());
+        assert_eq!(*mac.released.lock().unwrap(), vec![rnti]);
+    }
+    
+    #[tokio::test]
+    async fn test_rrc_reestablishment() {
+        let mac = Arc::new(MockMac::default());
+        let old_rnti = Rnti::new(0x4601);
+        let new_rnti = Rnti::new(0x4602);
+        let mut rrc = connected_rrc(mac.clone(), old_rnti).await;
+        let security = SecurityContext::new([0x5A; 32], IntegrityAlgorithm::Nia2, CipheringAlgorithm::Nea0);
+        let short_mac_i = security.short_mac_i(1, test_config().nr_cell_identity, old_rnti.0).unwrap();
+        rrc.ue_contexts.lock().await.get_mut(&old_rnti.0).unwrap().security = Some(security);
+        
+        // A wrong ShortMAC-I falls back to RRC Setup
+        let request = RrcReestablishmentRequest {
+            c_rnti: old_rnti,
+            pci: 1,
+            short_mac_i: short_mac_i ^ 0xFFFF,
+            cause: ReestablishmentCause::OtherFailure,
+        };
+        rrc.handle_uplink_message(Rnti::new(0x4603), request.encode()).await.unwrap();
+        assert_eq!(mac.sent.lock().unwrap().last().unwrap().1, RrcMessageType::RrcSetup);
+        assert!(rrc.ue_contexts.lock().await.contains_key(&old_rnti.0));
+        
+        // A valid ShortMAC-I moves the context to the new C-RNTI
+        let request = RrcReestablishmentRequest { short_mac_i, ..request };
+        rrc.handle_uplink_message(new_rnti, request.encode()).await.unwrap();
+        let (rnti, msg_type, _) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((rnti, msg_type), (new_rnti, RrcMessageType::RrcReestablishment));
+        
+        let contexts = rrc.ue_contexts.lock().await;
+        assert!(!contexts.contains_key(&old_rnti.0));
+        assert_eq!(contexts[&new_rnti.0].c_rnti, new_rnti);
+        assert!(mac.released.lock().unwrap().contains(&old_rnti));
+    }
+    
+    #[tokio::test]
+    async fn test_rrc_reestablishment_failures() {
+        let mac = Arc::new(MockMac::default());
+        let old_rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), old_rnti).await;
+        let security = SecurityContext::new([0x5A; 32], IntegrityAlgorithm::Nia2, CipheringAlgorithm::Nea0);
+        let request = RrcReestablishmentRequest {
+            c_rnti: old_rnti,
+            pci: 1,
+            short_mac_i: security.short_mac_i(1, test_config().nr_cell_identity, old_rnti.0).unwrap(),
+            cause: ReestablishmentCause::ReconfigurationFailure,
+        };
+        
+        // Without AS security, from another cell or for an unknown C-RNTI the UE
+        // falls back to RRC Setup and the old context is kept
+        let attempts = [
+            request.clone(),
+            RrcReestablishmentRequest { pci: 2, ..request.clone() },
+            RrcReestablishmentRequest { c_rnti: Rnti::new(0x4700), ..request.clone() },
+        ];
+        for (i, attempt) in attempts.into_iter().enumerate() {
+            if i == 1 {
+                rrc.ue_contexts.lock().await.get_mut(&old_rnti.0).unwrap().security = Some(security.clone());
+            }
+            let rnti = Rnti::new(0x4610 + i as u16);
+            rrc.handle_rrc_reestablishment_request(rnti, attempt.encode()).await.unwrap();
+            assert_eq!(mac.sent.lock().unwrap().last().cloned().unwrap().0, rnti);
+            assert_eq!(mac.sent.lock().unwrap().last().unwrap().1, RrcMessageType::RrcSetup);
+            assert!(rrc.ue_contexts.lock().await.contains_key(&old_rnti.0));
+        }
+        assert!(!mac.released.lock().unwrap().contains(&old_rnti));
+        
+        // A truncated request is not answered
+        let sent = mac.sent.lock().unwrap().len();
+        assert!(matches!(rrc.handle_rrc_reestablishment_request(Rnti::new(0x4620), request.encode().slice(..5)).await,
+                         Err(LayerError::InvalidPdu)));
+        assert_eq!(mac.sent.lock().unwrap().len(), sent);
+        
+        // Re-establishment Complete of an unknown UE
+        assert!(matches!(rrc.handle_rrc_reestablishment_complete(Rnti::new(0x4621)).await,
+                         Err(LayerError::InvalidState(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_inactive_suspend_paging_and_resume() {
+        let mac = Arc::new(MockMac::default());
+        let old_rnti = Rnti::new(0x4601);
+        let new_rnti = Rnti::new(0x4602);
+        let mut rrc = connected_rrc(mac.clone(), old_rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let security = SecurityContext::new([0x33; 32], IntegrityAlgorithm::Nia2, CipheringAlgorithm::Nea0);
+        let resume_mac_i = security.short_mac_i(1, test_config().nr_cell_identity, old_rnti.0).unwrap();
+        let ue_id = {
+            let mut contexts = rrc.ue_contexts.lock().await;
+            let ue_context = contexts.get_mut(&old_rnti.0).unwrap();
+            ue_context.security = Some(security);
+            ue_context.ue_id
+        };
+        
+        // Inactivity suspends the UE instead of releasing it
+        rrc.handle_timers(Instant::now() + tokio::time::Duration::from_secs(31)).await;
+        let (_, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::RrcRelease);
+        let suspend_config = RrcRelease::decode(&data).unwrap().suspend_config.unwrap();
+        assert_eq!(suspend_config.ran_paging_cycle, 32);
+        assert_eq!(suspend_config.ran_notification_area, vec![7]);
+        // The I-RNTI carries the gNB ID of the NR cell identity
+        assert_eq!(suspend_config.full_i_rnti >> 24, 1);
+        assert!(rrc.ue_contexts.lock().await.is_empty());
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::RrcStateTransition { state: RrcState::Inactive, .. }));
+        
+        // Downlink data triggers RAN paging with the fullI-RNTI
+        assert!(rrc.handle_downlink_data_notification(ue_id).await.unwrap());
+        let paging = mac.paging.lock().unwrap()[0];
+        assert_eq!(paging.identity, PagingUeIdentity::FullIRnti(suspend_config.full_i_rnti));
+        assert_eq!(paging.paging_cycle, Some(32));
+        
+        // CN paging of an idle UE uses the 5G-S-TMSI
+        rrc.handle_ngap_message(NgapRrcMessage::Paging {
+            s_tmsi: 0x0042_C000_0401,
+            paging_drx: Some(64),
+            paging_priority: Some(2),
+        }).await.unwrap();
+        let paging = mac.paging.lock().unwrap()[1];
+        assert_eq!(paging, PagingRequest {
+            identity: PagingUeIdentity::NgSTmsi(0x0042_C000_0401),
+            ue_id: 1,
+            paging_cycle: Some(64),
+            priority: Some(2),
+        });
+        
+        // The paged UE resumes on a new C-RNTI
+        let request = RrcResumeRequest {
+            resume_identity: ResumeIdentity::Short(suspend_config.short_i_rnti),
+            resume_mac_i,
+            resume_cause: ResumeCause::MtAccess,
+        };
+        rrc.handle_uplink_message(new_rnti, request.encode()).await.unwrap();
+        let (rnti, msg_type, _) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((rnti, msg_type), (new_rnti, RrcMessageType::RrcResume));
+        assert_eq!(rrc.ue_contexts.lock().await[&new_rnti.0].state, RrcState::Connected);
+        assert!(rrc.inactive_contexts.lock().await.is_empty());
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::RrcStateTransition { state: RrcState::Connected, .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_inactive_failures() {
+        let mac = Arc::new(MockMac::default());
+        let old_rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), old_rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        
+        // A UE without AS security cannot be suspended
+        assert!(matches!(rrc.suspend_ue(old_rnti).await, Err(LayerError::InvalidState(_))));
+        assert!(rrc.ue_contexts.lock().await.contains_key(&old_rnti.0));
+        assert!(ngap_rx.try_recv().is_err());
+        
+        let security = SecurityContext::new([0x33; 32], IntegrityAlgorithm::Nia2, CipheringAlgorithm::Nea0);
+        let resume_mac_i = security.short_mac_i(1, test_config().nr_cell_identity, old_rnti.0).unwrap();
+        rrc.ue_contexts.lock().await.get_mut(&old_rnti.0).unwrap().security = Some(security);
+        let full_i_rnti = rrc.suspend_ue(old_rnti).await.unwrap();
+        ngap_rx.try_recv().unwrap();
+        
+        // Downlink data of a UE that is not inactive starts no paging
+        assert!(!rrc.handle_downlink_data_notification(0xFFFF).await.unwrap());
+        assert!(mac.paging.lock().unwrap().is_empty());
+        
+        // A wrong resumeMAC-I or an unknown I-RNTI falls back to RRC Setup, the
+        // inactive context is kept
+        let attempts = [
+            RrcResumeRequest {
+                resume_identity: ResumeIdentity::Full(full_i_rnti),
+                resume_mac_i: resume_mac_i ^ 0x0001,
+                resume_cause: ResumeCause::MoData,
+            },
+            RrcResumeRequest {
+                resume_identity: ResumeIdentity::Full(full_i_rnti + 1),
+                resume_mac_i,
+                resume_cause: ResumeCause::MoData,
+            },
+        ];
+        for (i, attempt) in attempts.into_iter().enumerate() {
+            let rnti = Rnti::new(0x4610 + i as u16);
+            rrc.handle_rrc_resume_request(rnti, attempt.encode()).await.unwrap();
+            let (sent_rnti, msg_type, _) = mac.sent.lock().unwrap().last().cloned().unwrap();
+            assert_eq!((sent_rnti, msg_type), (rnti, RrcMessageType::RrcSetup));
+            assert!(rrc.inactive_contexts.lock().await.contains_key(&full_i_rnti));
+        }
+        assert!(ngap_rx.try_recv().is_err());
+        
+        assert!(matches!(rrc.handle_rrc_resume_request(Rnti::new(0x4620), Bytes::from_static(&[0x08, 0x00])).await,
+                         Err(LayerError::InvalidPdu)));
+        assert!(matches!(rrc.handle_rrc_resume_complete(Rnti::new(0x4621)).await,
+                         Err(LayerError::InvalidState(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_measurement_report() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        
+        rrc.configure_measurements(rnti).await.unwrap();
+        let (_, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::RrcReconfiguration);
+        let reconfiguration = RrcReconfiguration::decode(&data).unwrap();
+        assert_eq!(reconfiguration.meas_config, test_config().meas_config);
+        rrc.handle_uplink_message(rnti, Bytes::from(vec![0x21, reconfiguration.transaction_id])).await.unwrap();
+        
+        // A3 report with two neighbours
+        let report = MeasurementReport {
+            meas_id: 3,
+            serving_cells: vec![(0, MeasResultNr { pci: 1, rsrp: Some(50), rsrq: Some(70), sinr: Some(60) })],
+            neighbour_cells: vec![
+                MeasResultNr { pci: 5, rsrp: Some(55), rsrq: None, sinr: None },
+                MeasResultNr { pci: 7, rsrp: Some(62), rsrq: Some(75), sinr: None },
+            ],
+        };
+        rrc.handle_uplink_message(rnti, report.encode()).await.unwrap();
+        
+        let measurements = rrc.get_ue_measurements(rnti).await.unwrap();
+        assert_eq!(measurements.report_count, 1);
+        assert_eq!(measurements.last_meas_id, 3);
+        let serving = measurements.serving.unwrap();
+        assert_eq!(serving.rsrp_dbm, Some(-106.0));
+        assert_eq!(serving.rsrq_db, Some(-8.5));
+        assert_eq!(serving.sinr_db, Some(7.0));
+        assert_eq!(measurements.neighbours.iter().map(|n| n.pci).collect::<Vec<_>>(), vec![7, 5]);
+        assert_eq!(rrc.get_all_ue_measurements().await.len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_measurement_report_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let report = MeasurementReport {
+            meas_id: 9,
+            serving_cells: vec![(0, MeasResultNr { pci: 1, rsrp: Some(50), rsrq: None, sinr: None })],
+            neighbour_cells: vec![MeasResultNr { pci: 5, rsrp: Some(70), rsrq: None, sinr: None }],
+        };
+        
+        assert!(matches!(rrc.handle_measurement_report(Rnti::new(0x4602), report.encode()).await,
+                         Err(LayerError::InvalidState(_))));
+        assert!(matches!(rrc.handle_measurement_report(rnti, report.encode().slice(..4)).await,
+                         Err(LayerError::InvalidPdu)));
+        assert_eq!(rrc.get_ue_measurements(rnti).await.unwrap().report_count, 0);
+        
+        // A report for a measId the UE was never given is kept but triggers nothing
+        let sent = mac.sent.lock().unwrap().len();
+        rrc.handle_measurement_report(rnti, report.encode()).await.unwrap();
+        let measurements = rrc.get_ue_measurements(rnti).await.unwrap();
+        assert_eq!((measurements.report_count, measurements.last_meas_id), (1, 9));
+        assert_eq!(mac.sent.lock().unwrap().len(), sent);
+        assert!(ngap_rx.try_recv().is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_nas_relay() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        // Registration Request in RRC Setup Complete becomes an Initial UE Message
+        let registration_request = Bytes::from_static(&[0x7E, 0x00, 0x41, 0x79, 0x00, 0x0D]);
+        let complete = RrcSetupComplete {
+            transaction_id: 0,
+            selected_plmn_identity: 1,
+            dedicated_nas_message: registration_request.clone(),
+            amf_selection: AmfSelectionInfo::default(),
+        };
+        rrc.handle_uplink_message(rnti, complete.encode()).await.unwrap();
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::InitialUeMessage { ue_id: id, nas_pdu, establishment_cause, .. } => {
+                assert_eq!(id, ue_id);
+                assert_eq!(nas_pdu, registration_request);
+                assert_eq!(establishment_cause, EstablishmentCause::MoData);
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+        
+        // Downlink NAS goes out in DL Information Transfer
+        let authentication_request = Bytes::from_static(&[0x7E, 0x00, 0x56, 0x00]);
+        rrc.handle_ngap_message(NgapRrcMessage::DownlinkNasTransport {
+            ue_id,
+            nas_pdu: authentication_request.clone(),
+        }).await.unwrap();
+        let (_, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::DlInformationTransfer);
+        assert_eq!(DlInformationTransfer::decode(&data).unwrap().dedicated_nas_message, authentication_request);
+        
+        // Uplink NAS from UL Information Transfer
+        let authentication_response = Bytes::from_static(&[0x7E, 0x00, 0x57, 0x2D]);
+        let transfer = UlInformationTransfer { dedicated_nas_message: authentication_response.clone() };
+        rrc.handle_uplink_message(rnti, transfer.encode()).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::UplinkNasTransport { ue_id: id, nas_pdu } if id == ue_id && nas_pdu == authentication_response));
+        
+        // Unknown UE
+        assert!(rrc.handle_ngap_message(NgapRrcMessage::DownlinkNasTransport {
+            ue_id: ue_id + 1,
+            nas_pdu: authentication_request,
+        }).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_nas_relay_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        let nas = Bytes::from_static(&[0x7E, 0x00, 0x57, 0x2D]);
+        
+        // UL Information Transfer from an unknown RNTI and DL NAS for an unknown UE
+        let transfer = UlInformationTransfer { dedicated_nas_message: nas.clone() };
+        assert!(matches!(rrc.handle_ul_information_transfer(Rnti::new(0x4602), transfer.encode()).await,
+                         Err(LayerError::InvalidState(_))));
+        let sent = mac.sent.lock().unwrap().len();
+        assert!(rrc.handle_downlink_nas_transport(ue_id + 1, nas.clone()).await.is_err());
+        assert_eq!(mac.sent.lock().unwrap().len(), sent);
+        
+        // RRC Setup Complete from an unknown RNTI or with an invalid PLMN index
+        let mut complete = RrcSetupComplete {
+            transaction_id: 0,
+            selected_plmn_identity: 1,
+            dedicated_nas_message: nas.clone(),
+            amf_selection: AmfSelectionInfo::default(),
+        };
+        assert!(matches!(rrc.handle_rrc_setup_complete(Rnti::new(0x4602), complete.encode()).await,
+                         Err(LayerError::InvalidState(_))));
+        complete.selected_plmn_identity = 13;
+        assert!(matches!(rrc.handle_rrc_setup_complete(rnti, complete.encode()).await,
+                         Err(LayerError::InvalidPdu)));
+        assert!(ngap_rx.try_recv().is_err());
+        
+        // A truncated UL Information Transfer is dropped
+        assert!(matches!(rrc.handle_ul_information_transfer(rnti, transfer.encode().slice(..4)).await,
+                         Err(LayerError::InvalidPdu)));
+        assert!(ngap_rx.try_recv().is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_initial_context_setup() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        let context_setup = |nr_integrity_algorithms| NgapRrcMessage::InitialContextSetup {
+            ue_id,
+            security_key: [0x5A; 32],
+            nr_encryption_algorithms: 0xE000,
+            nr_integrity_algorithms,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1,
+                qos_flows: vec![1],
+                nas_pdu: Some(Bytes::from_static(&[0x7E, 0x00, 0x68])),
+                s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            nas_pdu: Some(Bytes::from_static(&[0x7E, 0x00, 0x42])),
+            ue_radio_capability: None,
+        };
+        
+        // A UE without integrity protection algorithms cannot be served
+        rrc.handle_ngap_message(context_setup(0x0000)).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::InitialContextSetupFailure { cause: RrcReleaseCause::AlgorithmsNotSupported, .. }));
+        
+        rrc.handle_ngap_message(context_setup(0xE000)).await.unwrap();
+        let (_, msg_type, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(msg_type, RrcMessageType::SecurityModeCommand);
+        let command = SecurityModeCommand::decode(&data).unwrap();
+        assert_eq!(command.integrity_algorithm, IntegrityAlgorithm::Nia2);
+        assert_eq!(command.ciphering_algorithm, CipheringAlgorithm::Nea2);
+        
+        // Security Mode Complete triggers capability enquiry and the DRB setup
+        rrc.handle_uplink_message(rnti, Bytes::from(vec![0x11, command.transaction_id])).await.unwrap();
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].security.is_some());
+        let sent: Vec<_> = mac.sent.lock().unwrap().iter().rev().take(2).cloned().collect();
+        assert_eq!(sent[1].1, RrcMessageType::UeCapabilityEnquiry);
+        assert_eq!(sent[0].1, RrcMessageType::RrcReconfiguration);
+        let reconfiguration = RrcReconfiguration::decode(&sent[0].2).unwrap();
+        assert_eq!(reconfiguration.dedicated_nas_messages, vec![
+            Bytes::from_static(&[0x7E, 0x00, 0x42]),
+            Bytes::from_static(&[0x7E, 0x00, 0x68]),
+        ]);
+        
+        let complete = Bytes::from(vec![0x21, reconfiguration.transaction_id]);
+        rrc.handle_uplink_message(rnti, complete).await.unwrap();
+        match ngap_rx.try_recv().unwrap() {
+            RrcNgapMessage::PduSessionResourceResponse { procedure, succeeded, failed, .. } => {
+                assert_eq!(procedure, PduSessionProcedure::InitialContextSetup);
+                assert_eq!(succeeded, vec![1]);
+                assert!(failed.is_empty());
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+        
+        // UE Context Modification refreshes K_gNB and keeps the algorithms
+        let modification = |security_key, nr_integrity_algorithms: Option<u16>| NgapRrcMessage::UeContextModification {
+            ue_id,
+            security_key,
+            nr_encryption_algorithms: nr_integrity_algorithms.map(|_| 0xE000),
+            nr_integrity_algorithms,
+        };
+        rrc.handle_ngap_message(modification(Some([0x3C; 32]), None)).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::UeContextModificationResponse { .. }));
+        let security = rrc.ue_contexts.lock().await[&rnti.0].security.clone().unwrap();
+        assert_eq!(security.k_gnb, [0x3C; 32]);
+        assert_eq!(security.integrity_algorithm, IntegrityAlgorithm::Nia2);
+        
+        // Capabilities without the integrity algorithm in use are rejected
+        rrc.handle_ngap_message(modification(None, Some(0x8000))).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(),
+                         RrcNgapMessage::UeContextModificationFailure { cause: RrcReleaseCause::AlgorithmsNotSupported, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_initial_context_setup_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        // Unknown UE
+        assert!(rrc.handle_initial_context_setup(ue_id + 1, [0x5A; 32], 0xE000, 0xE000, Vec::new(), None, None).await.is_err());
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::InitialContextSetupFailure {
+            cause: RrcReleaseCause::FailureInRadioInterfaceProcedure, ..
+        }));
+        
+        // UE Context Modification of security before AS security is active
+        rrc.handle_ue_context_modification(ue_id, Some([0x3C; 32]), None, None).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::UeContextModificationFailure { .. }));
+        
+        // A second request while security activation is ongoing
+        rrc.handle_initial_context_setup(ue_id, [0x5A; 32], 0xE000, 0xE000, Vec::new(), None, None).await.unwrap();
+        let (_, _, data) = mac.sent.lock().unwrap().last().cloned().unwrap();
+        let command = SecurityModeCommand::decode(&data).unwrap();
+        assert!(matches!(rrc.handle_initial_context_setup(ue_id, [0x5A; 32], 0xE000, 0xE000, Vec::new(), None, None).await,
+                         Err(LayerError::InvalidState(_))));
+        
+        // Truncated response and a response to another transaction are ignored
+        assert!(matches!(rrc.handle_security_mode_complete(rnti, Bytes::from_static(&[0x11])).await,
+                         Err(LayerError::InvalidPdu)));
+        let other = (command.transaction_id + 1) & 0x03;
+        rrc.handle_security_mode_complete(rnti, Bytes::from(vec![0x11, other])).await.unwrap();
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].pending_context_setup.is_some());
+        assert!(ngap_rx.try_recv().is_err());
+        
+        // Security Mode Failure leaves security inactive and fails the setup
+        rrc.handle_security_mode_failure(rnti, Bytes::from(vec![0x12, command.transaction_id])).await.unwrap();
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::InitialContextSetupFailure {
+            cause: RrcReleaseCause::FailureInRadioInterfaceProcedure, ..
+        }));
+        let contexts = rrc.ue_contexts.lock().await;
+        assert!(contexts[&rnti.0].security.is_none() && contexts[&rnti.0].pending_context_setup.is_none());
+        drop(contexts);
+        
+        // No answer from the UE within the guard time
+        rrc.handle_initial_context_setup(ue_id, [0x5A; 32], 0xE000, 0xE000, Vec::new(), None, None).await.unwrap();
+        rrc.check_context_setup_timers(Instant::now()).await;
+        assert!(ngap_rx.try_recv().is_err());
+        rrc.check_context_setup_timers(Instant::now() + tokio::time::Duration::from_millis(600)).await;
+        assert!(matches!(ngap_rx.try_recv().unwrap(), RrcNgapMessage::InitialContextSetupFailure { .. }));
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].security.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_amf_capability_selects_mcs_table() {
+        use crate::mac::{default_sib1_config, EnhancedMacLayer, MacConfig, MacPhyInterface};
+        use common::types::ModulationScheme;
+        
+        let mut mac = EnhancedMacLayer::new(MacConfig {
+            cell_id: CellId(1),
+            scs: common::types::SubcarrierSpacing::Scs15,
+            bandwidth: common::types::Bandwidth::Bw20,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        }).unwrap();
+        mac.initialize().await.unwrap();
+        let mac = Arc::new(mac);
+        let scheduler = mac.scheduler();
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        let nr_capability = UeNrCapability {
+            access_stratum_release: 15,
+            pdcp: PdcpCapability::default(),
+            rlc: RlcCapability::default(),
+            bands: vec![BandCapability {
+                band: 3,
+                supports_256qam_dl: true,
+                supports_256qam_ul: false,
+                max_mimo_layers_dl: 1,
+                max_mimo_layers_ul: 1,
+            }],
+        };
+        let ue_radio_capability = capability::encode_container_list(&[UeCapabilityRatContainer {
+            rat_type: RatType::Nr,
+            container: nr_capability.encode(),
+        }]);
+        rrc.handle_ngap_message(NgapRrcMessage::InitialContextSetup {
+            ue_id,
+            security_key: [0x5A; 32],
+            nr_encryption_algorithms: 0xE000,
+            nr_integrity_algorithms: 0xE000,
+            sessions: Vec::new(),
+            nas_pdu: None,
+            ue_radio_capability: Some(ue_radio_capability),
+        }).await.unwrap();
+        assert_eq!(scheduler.lock().await.max_dl_modulation(rnti), ModulationScheme::Qam64);
+        
+        // Once security is active the AMF provided capability reaches the scheduler
+        let transaction_id = rrc.ue_contexts.lock().await[&rnti.0].pending_context_setup.as_ref().unwrap().transaction_id;
+        rrc.handle_uplink_message(rnti, Bytes::from(vec![0x11, transaction_id])).await.unwrap();
+        assert_eq!(rrc.get_ue_capability(rnti).await, Some(nr_capability));
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].capability_transaction_id.is_none());
+        assert!(!matches!(ngap_rx.try_recv(), Ok(RrcNgapMessage::UeRadioCapabilityInfo { .. })));
+        
+        // DL assignments of the UE now index MCS table 2
+        scheduler.lock().await.set_ue_cqi(rnti, 15);
+        mac.send_user_data(rnti, 4, Bytes::from(vec![0x5A; 200])).await.unwrap();
+        let schedule = mac.get_slot_schedule(1, 3).await.unwrap();
+        let grant = schedule.dl_grants.iter().find(|grant| grant.rnti == rnti).unwrap();
+        assert_eq!((grant.mcs_index, grant.modulation), (27, ModulationScheme::Qam256));
+    }
+
+    #[tokio::test]
+    async fn test_xn_handover() {
+        let source_mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut source = connected_rrc(source_mac.clone(), rnti).await;
+        let (source_tx, mut source_rx) = mpsc::channel(10);
+        source.set_ngap_channel(source_tx);
+        let ue_id = source.ue_contexts.lock().await[&rnti.0].ue_id;
+        
+        // UE with AS security and a DRB for PDU session 1
+        source.handle_ngap_message(NgapRrcMessage::InitialContextSetup {
+            ue_id,
+            security_key: [0x5A; 32],
+            nr_encryption_algorithms: 0xE000,
+            nr_integrity_algorithms: 0xE000,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            nas_pdu: None,
+            ue_radio_capability: None,
+        }).await.unwrap();
+        let (_, _, data) = source_mac.sent.lock().unwrap().last().cloned().unwrap();
+        let command = SecurityModeCommand::decode(&data).unwrap();
+        source.handle_uplink_message(rnti, Bytes::from(vec![0x11, command.transaction_id])).await.unwrap();
+        let (_, _, data) = source_mac.sent.lock().unwrap().last().cloned().unwrap();
+        let reconfiguration = RrcReconfiguration::decode(&data).unwrap();
+        source.handle_uplink_message(rnti, Bytes::from(vec![0x21, reconfiguration.transaction_id])).await.unwrap();
+        assert!(matches!(source_rx.try_recv().unwrap(), RrcNgapMessage::PduSessionResourceResponse { .. }));
+        source.configure_measurements(rnti).await.unwrap();
+        let (_, _, data) = source_mac.sent.lock().unwrap().last().cloned().unwrap();
+        let reconfiguration = RrcReconfiguration::decode(&data).unwrap();
+        source.handle_uplink_message(rnti, Bytes::from(vec![0x21, reconfiguration.transaction_id])).await.unwrap();
+        
+        // A controller cannot hand the UE over to the serving cell or an unknown UE anywhere
+        assert!(source.handover_ue(ue_id, 1).await.is_err());
+        assert!(source.handover_ue(ue_id + 1, 2).await.is_err());
+        assert!(source_rx.try_recv().is_err());
+        
+        // An A3 report naming PCI 2 starts the handover
+        let report = MeasurementReport {
+            meas_id: 3,
+            serving_cells: vec![(0, MeasResultNr { pci: 1, rsrp: Some(50), rsrq: None, sinr: None })],
+            neighbour_cells: vec![MeasResultNr { pci: 2, rsrp: Some(62), rsrq: None, sinr: None }],
+        };
+        source.handle_uplink_message(rnti, report.encode()).await.unwrap();
+        let RrcNgapMessage::HandoverRequired {
+            ue_id: id, target_pci, security_key, next_hop_chaining_count, rrc_container,
+        } = source_rx.try_recv().unwrap() else {
+            panic!("expected Handover Required");
+        };
+        assert_eq!((id, target_pci), (ue_id, 2));
+        assert_ne!(security_key, [0x5A; 32]);
+        let preparation = HandoverPreparationInformation::decode(&rrc_container).unwrap();
+        assert_eq!((preparation.source_pci, preparation.source_c_rnti), (1, rnti));
+        assert_eq!(preparation.source_config.drbs_to_add_mod.len(), 1);
+        
+        // The target admits the UE with the DRB IDs of the source
+        let target_mac = Arc::new(MockMac::default());
+        let mut target = RrcLayer::new(RrcConfig { pci: 2, ..test_config() });
+        target.set_mac_interface(target_mac.clone());
+        target.initialize().await.unwrap();
+        let (target_tx, mut target_rx) = mpsc::channel(10);
+        target.set_ngap_channel(target_tx);
+        target.handle_ngap_message(NgapRrcMessage::HandoverRequest {
+            handover_id: 1,
+            security_key,
+            next_hop_chaining_count,
+            nr_encryption_algorithms: 0xE000,
+            nr_integrity_algorithms: 0xE000,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            rrc_container,
+        }).await.unwrap();
+        let RrcNgapMessage::HandoverRequestAcknowledge {
+            handover_id, ue_id: target_ue_id, admitted, failed, rrc_container,
+        } = target_rx.try_recv().unwrap() else {
+            panic!("expected Handover Request Acknowledge");
+        };
+        assert_eq!((handover_id, admitted, failed), (1, vec![1], vec![]));
+        let handover_command = RrcReconfiguration::decode(&rrc_container).unwrap();
+        let with_sync = handover_command.reconfiguration_with_sync.unwrap();
+        assert_eq!((with_sync.physical_cell_id, with_sync.new_ue_identity), (2, rnti));
+        assert_eq!(handover_command.drbs_to_add_mod[0].drb_id, preparation.source_config.drbs_to_add_mod[0].drb_id);
+        assert!(handover_command.drbs_to_add_mod[0].reestablish_pdcp);
+        assert_eq!(target_mac.drbs.lock().unwrap().len(), 1);
+        
+        // The source commands the UE and passes the PDCP COUNTs on
+        source.handle_ngap_message(NgapRrcMessage::HandoverCommand { ue_id, rrc_container: rrc_container.clone() })
+            .await.unwrap();
+        let (_, msg_type, data) = source_mac.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((msg_type, data), (RrcMessageType::RrcReconfiguration, rrc_container));
+        let RrcNgapMessage::SnStatusTransfer { ue_id: id, drbs } = source_rx.try_recv().unwrap() else {
+            panic!("expected SN Status Transfer");
+        };
+        assert_eq!((id, drbs.len()), (ue_id, 1));
+        target.handle_ngap_message(NgapRrcMessage::SnStatusTransfer { ue_id: target_ue_id, drbs }).await.unwrap();
+        
+        // The UE completes the reconfiguration in the target cell
+        target.handle_uplink_message(rnti, Bytes::from(vec![0x21, handover_command.transaction_id])).await.unwrap();
+        assert!(matches!(target_rx.try_recv().unwrap(), RrcNgapMessage::HandoverNotify { ue_id: id } if id == target_ue_id));
+        assert!(target.ue_contexts.lock().await[&rnti.0].handover.is_none());
+        
+        // The source releases the UE that left
+        source.handle_ngap_message(NgapRrcMessage::UeContextRelease { ue_id }).await.unwrap();
+        assert!(matches!(source_rx.try_recv().unwrap(),
+                         RrcNgapMessage::UeContextReleaseComplete { ue_id: id, .. } if id == ue_id));
+        assert!(source.ue_contexts.lock().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_xn_handover_failures() {
+        let mac = Arc::new(MockMac::default());
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        rrc.ue_contexts.lock().await.get_mut(&rnti.0).unwrap().state = RrcState::Connected;
+        
+        // Without NGAP there is nowhere to hand the UE over to
+        rrc.start_handover(rnti, 2, 368500).await.unwrap();
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].handover.is_none());
+        
+        // Unknown UEs and UEs without AS security are not handed over
+        let (ngap_tx, mut ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        assert!(matches!(rrc.start_handover(Rnti::new(0x4602), 2, 368500).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(rrc.start_handover(rnti, 2, 368500).await, Err(LayerError::InvalidState(_))));
+        assert!(ngap_rx.try_recv().is_err());
+        
+        // Handover commands must carry reconfigurationWithSync and match a preparation
+        let mut command = RrcReconfiguration {
+            transaction_id: 1,
+            reconfiguration_with_sync: Some(ReconfigurationWithSync {
+                physical_cell_id: 2,
+                new_ue_identity: Rnti::new(0x4602),
+                t304_ms: 1000,
+                integrity_algorithm: IntegrityAlgorithm::Nia2,
+                ciphering_algorithm: CipheringAlgorithm::Nea2,
+                next_hop_chaining_count: 0,
+            }),
+            ..Default::default()
+        };
+        let sent = mac.sent.lock().unwrap().len();
+        assert!(rrc.handle_ngap_message(NgapRrcMessage::HandoverCommand {
+            ue_id, rrc_container: Bytes::from_static(&[0x00]),
+        }).await.is_err());
+        assert!(matches!(rrc.handle_ngap_message(NgapRrcMessage::HandoverCommand {
+            ue_id: ue_id + 1, rrc_container: command.encode(),
+        }).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(rrc.handle_ngap_message(NgapRrcMessage::HandoverCommand {
+            ue_id, rrc_container: command.encode(),
+        }).await, Err(LayerError::InvalidState(_))));
+        command.reconfiguration_with_sync = None;
+        assert!(matches!(rrc.handle_ngap_message(NgapRrcMessage::HandoverCommand {
+            ue_id, rrc_container: command.encode(),
+        }).await, Err(LayerError::ProcessingError(_))));
+        assert_eq!(mac.sent.lock().unwrap().len(), sent);
+        assert!(ngap_rx.try_recv().is_err());
+        
+        // A preparation failure leaves the UE in the cell
+        assert!(matches!(rrc.handle_ngap_message(NgapRrcMessage::HandoverPreparationFailure { ue_id: ue_id + 1 }).await,
+                         Err(LayerError::InvalidState(_))));
+        rrc.ue_contexts.lock().await.get_mut(&rnti.0).unwrap().handover = Some(HandoverState::Preparing {
+            target_pci: 2,
+            deadline: Instant::now() + tokio::time::Duration::from_secs(1),
+        });
+        rrc.handle_ngap_message(NgapRrcMessage::HandoverPreparationFailure { ue_id }).await.unwrap();
+        assert!(rrc.ue_contexts.lock().await[&rnti.0].handover.is_none());
+        
+        // SN status for an unknown UE
+        assert!(matches!(rrc.handle_ngap_message(NgapRrcMessage::SnStatusTransfer {
+            ue_id: ue_id + 1, drbs: vec![DrbSnStatus { drb_id: 1, ul_count: 0, dl_count: 0 }],
+        }).await, Err(LayerError::InvalidState(_))));
+        
+        // The target refuses UEs without common algorithms, capacity or a valid container
+        let preparation = HandoverPreparationInformation {
+            ue_radio_capability: None,
+            source_config: RrcReconfiguration::default(),
+            source_pci: 2,
+            source_c_rnti: Rnti::new(0x4603),
+        };
+        let request = |handover_id, nr_integrity_algorithms, rrc_container| NgapRrcMessage::HandoverRequest {
+            handover_id,
+            security_key: [0x5A; 32],
+            next_hop_chaining_count: 1,
+            nr_encryption_algorithms: 0xE000,
+            nr_integrity_algorithms,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            rrc_container,
+        };
+        let mut target = RrcLayer::new(RrcConfig { pci: 2, ..test_config() });
+        target.set_mac_interface(Arc::new(MockMac::default()));
+        target.initialize().await.unwrap();
+        let (target_tx, mut target_rx) = mpsc::channel(10);
+        target.set_ngap_channel(target_tx);
+        target.handle_ngap_message(request(1, 0x0000, preparation.encode())).await.unwrap();
+        assert!(matches!(target_rx.try_recv().unwrap(), RrcNgapMessage::HandoverFailure {
+            handover_id: 1, cause: RrcReleaseCause::AlgorithmsNotSupported,
+        }));
+        target.handle_ngap_message(request(2, 0xE000, Bytes::from_static(&[0x00, 0x02]))).await.unwrap();
+        assert!(matches!(target_rx.try_recv().unwrap(), RrcNgapMessage::HandoverFailure {
+            handover_id: 2, cause: RrcReleaseCause::FailureInRadioInterfaceProcedure,
+        }));
+        assert!(target.ue_contexts.lock().await.is_empty());
+        
+        let mut full = RrcLayer::new(RrcConfig { pci: 2, max_ue_contexts: 0, ..test_config() });
+        full.set_mac_interface(Arc::new(MockMac::default()));
+        full.initialize().await.unwrap();
+        let (full_tx, mut full_rx) = mpsc::channel(10);
+        full.set_ngap_channel(full_tx);
+        full.handle_ngap_message(request(3, 0xE000, preparation.encode())).await.unwrap();
+        assert!(matches!(full_rx.try_recv().unwrap(), RrcNgapMessage::HandoverFailure {
+            handover_id: 3, cause: RrcReleaseCause::NoRadioResources,
+        }));
+        assert!(full.ue_contexts.lock().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_user_data_through_mac() {
+        use crate::gtpu::pdu::{GtpuPdu, PduSessionInformation};
+        use crate::gtpu::{GtpuConfig, GtpuLayer, NgapGtpuMessage};
+        use crate::mac::{default_sib1_config, pdu, EnhancedMacLayer, MacConfig, MacPhyInterface};
+        
+        let mut mac = EnhancedMacLayer::new(MacConfig {
+            cell_id: CellId(1),
+            scs: common::types::SubcarrierSpacing::Scs15,
+            bandwidth: common::types::Bandwidth::Bw20,
+            max_ues: 32,
+            sib1_config: default_sib1_config(1),
+            coreset0_index: 6,
+        }).unwrap();
+        let (mac_user_data_tx, mut mac_user_data_rx) = mpsc::channel(10);
+        mac.set_user_data_channel(mac_user_data_tx);
+        mac.initialize().await.unwrap();
+        let mac = Arc::new(mac);
+        let rnti = Rnti::new(0x4601);
+        let mut rrc = connected_rrc(mac.clone(), rnti).await;
+        let (ngap_tx, _ngap_rx) = mpsc::channel(10);
+        rrc.set_ngap_channel(ngap_tx);
+        let (rrc_gtpu_tx, mut rrc_gtpu_rx) = mpsc::channel(10);
+        rrc.set_gtpu_channel(rrc_gtpu_tx);
+        let ue_id = rrc.ue_contexts.lock().await[&rnti.0].ue_id;
+        rrc.handle_ngap_message(NgapRrcMessage::PduSessionResourceSetup {
+            ue_id,
+            sessions: vec![PduSessionResource {
+                pdu_session_id: 1, qos_flows: vec![1], nas_pdu: None, s_nssai: SNssai { sst: 1, sd: None },
+            }],
+            nas_pdu: None,
+        }).await.unwrap();
+        
+        let mut gtpu = GtpuLayer::new(GtpuConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            external_address: std::net::IpAddr::from([127, 0, 0, 1]),
+        });
+        let (gtpu_rrc_tx, mut gtpu_rrc_rx) = mpsc::channel(10);
+        gtpu.set_rrc_channel(gtpu_rrc_tx);
+        gtpu.handle_ngap_message(NgapGtpuMessage::CreateTunnel {
+            ue_id,
+            pdu_session_id: 1,
+            dl_teid: 7,
+            ul_tunnel: GtpTunnel { transport_layer_address: std::net::IpAddr::from([127, 0, 0, 41]), teid: 0x101 },
+        }).await.unwrap();
+        
+        // A G-PDU from the UPF comes out of the MAC as a DL-SCH MAC PDU on the DRB
+        let packet = Bytes::from_static(&[0x45, 0x00, 0x00, 0x14, 0x01, 0x02]);
+        let g_pdu = GtpuPdu::g_pdu(7, Some(PduSessionInformation::Downlink { qfi: 1, rqi: false }), packet.clone());
+        gtpu.handle_datagram(g_pdu.encode(), "127.0.0.41:2152".parse().unwrap()).await.unwrap();
+        rrc.handle_gtpu_message(gtpu_rrc_rx.try_recv().unwrap()).await.unwrap();
+        let schedule = mac.get_slot_schedule(1, 3).await.unwrap();
+        assert_eq!(schedule.dl_grants.len(), 1);
+        let grant = &schedule.dl_grants[0];
+        assert_eq!(grant.rnti, rnti);
+        let sdus = pdu::decode_dl_sch(&grant.payload).unwrap();
+        assert_eq!(sdus.len(), 1);
+        assert_eq!((sdus[0].subheader.lcid, sdus[0].data[0], sdus[0].data.slice(1..)), (4, 0x01, packet.clone()));
+        
+        // A DRB SDU in an UL-SCH MAC PDU reaches GTP-U, the BSR stays in the MAC
+        let mut ul_sch = vec![pdu::UL_LCID_SHORT_BSR, 0x00, 4, packet.len() as u8 + 1, 0x81];
+        ul_sch.extend_from_slice(&packet);
+        ul_sch.extend_from_slice(&[pdu::LCID_PADDING, 0x00]);
+        mac.report_rx_data(rnti.0, Bytes::from(ul_sch)).await.unwrap();
+        let (ul_rnti, lcid, data) = mac_user_data_rx.try_recv().unwrap();
+        assert_eq!((ul_rnti, lcid), (rnti, 4));
+        assert!(mac_user_data_rx.try_recv().is_err());
+        rrc.handle_uplink_user_data(ul_rnti, lcid, data).await.unwrap();
+        match rrc_gtpu_rx.try_recv().unwrap() {
+            RrcGtpuMessage::UplinkData { pdu_session_id, qfi, data, .. } => {
+                assert_eq!((pdu_session_id, qfi, data), (1, 1, packet));
+            }
+        }
+        
+        // A corrupted transport block is rejected
+        assert!(mac.report_rx_data(rnti.0, Bytes::from_static(&[0x04, 0x10, 0x00])).await.is_err());
+    }
+}
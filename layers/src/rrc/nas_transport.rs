@@ -0,0 +1,409 @@
+//! NAS message transfer
+//!
+//! Carries NAS messages between the UE and NGAP: the initial NAS message in RRC
+//! Setup Complete and the DL/UL Information Transfer procedures of 3GPP TS 38.331
+//! Sections 5.3.3, 5.7.1 and 5.7.2. RRC Setup Complete also carries what the UE
+//! knows of its AMF and slices, used by NGAP for AMF selection.
+
+use super::{RrcLayer, RrcMessageType, RrcNgapMessage, RrcState};
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::{Rnti, SNssai};
+use tracing::{debug, info, warn};
+
+/// Maximum size of a dedicatedNAS-Message we accept
+const MAX_NAS_MESSAGE_SIZE: usize = 9000;
+
+/// Read a length-prefixed dedicatedNAS-Message
+fn decode_nas_message(buf: &mut &[u8]) -> Result<Bytes, LayerError> {
+    if buf.remaining() < 2 {
+        return Err(LayerError::InvalidPdu);
+    }
+    let len = buf.get_u16() as usize;
+    if len == 0 || len > MAX_NAS_MESSAGE_SIZE || buf.remaining() < len {
+        return Err(LayerError::InvalidPdu);
+    }
+    let nas = Bytes::copy_from_slice(&buf[..len]);
+    buf.advance(len);
+    Ok(nas)
+}
+
+/// Maximum number of slices in s-NSSAI-List (maxNrofS-NSSAI)
+const MAX_NROF_S_NSSAI: usize = 8;
+
+/// AMF the UE is registered with (RegisteredAMF, TS 38.331)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisteredAmf {
+    /// PLMN of the AMF, absent when it is the selected PLMN
+    pub plmn_id: Option<[u8; 3]>,
+    /// AMF Identifier: AMF Region ID (8 bits), AMF Set ID (10 bits) and AMF Pointer (6 bits)
+    pub amf_identifier: u32,
+}
+
+impl RegisteredAmf {
+    /// AMF Region ID
+    pub fn amf_region_id(&self) -> u8 {
+        (self.amf_identifier >> 16) as u8
+    }
+
+    /// AMF Set ID
+    pub fn amf_set_id(&self) -> u16 {
+        ((self.amf_identifier >> 6) & 0x3FF) as u16
+    }
+
+    /// AMF Pointer
+    pub fn amf_pointer(&self) -> u8 {
+        (self.amf_identifier & 0x3F) as u8
+    }
+}
+
+/// UE provided information for AMF selection (TS 38.300 section 10.6)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AmfSelectionInfo {
+    /// 5G-S-TMSI of a registered UE (48 bits)
+    pub s_tmsi: Option<u64>,
+    /// AMF the UE is registered with
+    pub registered_amf: Option<RegisteredAmf>,
+    /// Slices requested by the UE
+    pub requested_nssai: Vec<SNssai>,
+}
+
+impl AmfSelectionInfo {
+    fn is_empty(&self) -> bool {
+        self.s_tmsi.is_none() && self.registered_amf.is_none() && self.requested_nssai.is_empty()
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        let flags = self.s_tmsi.is_some() as u8
+            | (self.registered_amf.is_some() as u8) << 1
+            | (!self.requested_nssai.is_empty() as u8) << 2;
+        buf.put_u8(flags);
+        if let Some(s_tmsi) = self.s_tmsi {
+            buf.put_slice(&s_tmsi.to_be_bytes()[2..]);
+        }
+        if let Some(registered_amf) = &self.registered_amf {
+            buf.put_u8(registered_amf.plmn_id.is_some() as u8);
+            if let Some(plmn_id) = registered_amf.plmn_id {
+                buf.put_slice(&plmn_id);
+            }
+            buf.put_slice(&registered_amf.amf_identifier.to_be_bytes()[1..]);
+        }
+        if !self.requested_nssai.is_empty() {
+            buf.put_u8(self.requested_nssai.len() as u8);
+            for slice in &self.requested_nssai {
+                buf.put_u8(slice.sst);
+                buf.put_u8(slice.sd.is_some() as u8);
+                if let Some(sd) = slice.sd {
+                    buf.put_slice(&sd.to_be_bytes()[1..]);
+                }
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, LayerError> {
+        let mut info = Self::default();
+        if buf.remaining() < 1 {
+            return Ok(info);
+        }
+        let flags = buf.get_u8();
+
+        if flags & 0x01 != 0 {
+            if buf.remaining() < 6 {
+                return Err(LayerError::InvalidPdu);
+            }
+            info.s_tmsi = Some((buf.get_u16() as u64) << 32 | buf.get_u32() as u64);
+        }
+        if flags & 0x02 != 0 {
+            if buf.remaining() < 1 {
+                return Err(LayerError::InvalidPdu);
+            }
+            let plmn_present = buf.get_u8() != 0;
+            if buf.remaining() < 3 + 3 * plmn_present as usize {
+                return Err(LayerError::InvalidPdu);
+            }
+            let plmn_id = plmn_present.then(|| [buf.get_u8(), buf.get_u8(), buf.get_u8()]);
+            let amf_identifier = (buf.get_u8() as u32) << 16 | buf.get_u16() as u32;
+            info.registered_amf = Some(RegisteredAmf { plmn_id, amf_identifier });
+        }
+        if flags & 0x04 != 0 {
+            if buf.remaining() < 1 {
+                return Err(LayerError::InvalidPdu);
+            }
+            let count = buf.get_u8() as usize;
+            if count == 0 || count > MAX_NROF_S_NSSAI {
+                return Err(LayerError::InvalidPdu);
+            }
+            for _ in 0..count {
+                if buf.remaining() < 2 {
+                    return Err(LayerError::InvalidPdu);
+                }
+                let sst = buf.get_u8();
+                let sd = if buf.get_u8() != 0 {
+                    if buf.remaining() < 3 {
+                        return Err(LayerError::InvalidPdu);
+                    }
+                    Some((buf.get_u8() as u32) << 16 | buf.get_u16() as u32)
+                } else {
+                    None
+                };
+                info.requested_nssai.push(SNssai { sst, sd });
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// RRC Setup Complete message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcSetupComplete {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// Index of the selected PLMN in SIB1 (1-12)
+    pub selected_plmn_identity: u8,
+    /// Initial NAS message
+    pub dedicated_nas_message: Bytes,
+    /// 5G-S-TMSI, registered AMF and requested slices, when provided by the UE
+    pub amf_selection: AmfSelectionInfo,
+}
+
+impl RrcSetupComplete {
+    /// Encode RRC Setup Complete
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(5 + self.dedicated_nas_message.len());
+        buf.put_u8(0x02); // RRC Setup Complete
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_u8(self.selected_plmn_identity);
+        buf.put_u16(self.dedicated_nas_message.len() as u16);
+        buf.put_slice(&self.dedicated_nas_message);
+        if !self.amf_selection.is_empty() {
+            self.amf_selection.encode(&mut buf);
+        }
+        buf.freeze()
+    }
+
+    /// Decode RRC Setup Complete
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        if buf.remaining() < 3 {
+            return Err(LayerError::InvalidPdu);
+        }
+        buf.advance(1);
+        let transaction_id = buf.get_u8() & 0x03;
+        let selected_plmn_identity = buf.get_u8();
+        if !(1..=12).contains(&selected_plmn_identity) {
+            return Err(LayerError::InvalidPdu);
+        }
+        let dedicated_nas_message = decode_nas_message(&mut buf)?;
+        let amf_selection = AmfSelectionInfo::decode(&mut buf)?;
+
+        Ok(Self {
+            transaction_id,
+            selected_plmn_identity,
+            dedicated_nas_message,
+            amf_selection,
+        })
+    }
+}
+
+/// DL Information Transfer message
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlInformationTransfer {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// NAS message for the UE
+    pub dedicated_nas_message: Bytes,
+}
+
+impl DlInformationTransfer {
+    /// Encode DL Information Transfer
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(4 + self.dedicated_nas_message.len());
+        buf.put_u8(0x0C); // DL Information Transfer
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_u16(self.dedicated_nas_message.len() as u16);
+        buf.put_slice(&self.dedicated_nas_message);
+        buf.freeze()
+    }
+
+    /// Decode DL Information Transfer
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        if buf.remaining() < 2 {
+            return Err(LayerError::InvalidPdu);
+        }
+        buf.advance(1);
+        let transaction_id = buf.get_u8() & 0x03;
+        let dedicated_nas_message = decode_nas_message(&mut buf)?;
+
+        Ok(Self {
+            transaction_id,
+            dedicated_nas_message,
+        })
+    }
+}
+
+/// UL Information Transfer message
+#[derive(Debug, Clone, PartialEq)]
+pub struct UlInformationTransfer {
+    /// NAS message from the UE
+    pub dedicated_nas_message: Bytes,
+}
+
+impl UlInformationTransfer {
+    /// Encode UL Information Transfer
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(3 + self.dedicated_nas_message.len());
+        buf.put_u8(0x0D); // UL Information Transfer
+        buf.put_u16(self.dedicated_nas_message.len() as u16);
+        buf.put_slice(&self.dedicated_nas_message);
+        buf.freeze()
+    }
+
+    /// Decode UL Information Transfer
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        if buf.remaining() < 1 {
+            return Err(LayerError::InvalidPdu);
+        }
+        buf.advance(1);
+        Ok(Self {
+            dedicated_nas_message: decode_nas_message(&mut buf)?,
+        })
+    }
+}
+
+impl RrcLayer {
+    /// Handle RRC Setup Complete from UE and pass the initial NAS message to NGAP
+    pub(super) async fn handle_rrc_setup_complete(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        info!("Handling RRC Setup Complete from RNTI {}", rnti.0);
+        let complete = RrcSetupComplete::decode(&data)?;
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        ue_context.state = RrcState::Connected;
+        let ue_id = ue_context.ue_id;
+        let establishment_cause = ue_context.establishment_cause
+            .unwrap_or(super::EstablishmentCause::MoSignalling);
+        drop(contexts);
+        info!("UE {} (RNTI {}) is now RRC Connected", ue_id, rnti.0);
+
+        self.send_to_ngap(RrcNgapMessage::InitialUeMessage {
+            ue_id,
+            nas_pdu: complete.dedicated_nas_message,
+            establishment_cause,
+            amf_selection: complete.amf_selection,
+        }).await;
+        Ok(())
+    }
+
+    /// Handle UL Information Transfer from UE
+    pub(super) async fn handle_ul_information_transfer(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let transfer = UlInformationTransfer::decode(&data)?;
+
+        let contexts = self.ue_contexts.lock().await;
+        let ue_id = contexts.get(&rnti.0)
+            .filter(|ctx| ctx.state == RrcState::Connected)
+            .map(|ctx| ctx.ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("RNTI {} not connected", rnti.0)))?;
+        drop(contexts);
+        debug!("UL NAS message from UE {} ({} bytes)", ue_id, transfer.dedicated_nas_message.len());
+
+        self.send_to_ngap(RrcNgapMessage::UplinkNasTransport {
+            ue_id,
+            nas_pdu: transfer.dedicated_nas_message,
+        }).await;
+        Ok(())
+    }
+
+    /// Deliver a NAS message from the AMF in DL Information Transfer
+    pub(super) async fn handle_downlink_nas_transport(&mut self, ue_id: u32, nas_pdu: Bytes) -> Result<(), LayerError> {
+        let rnti = match self.connected_rnti(ue_id).await {
+            Ok(rnti) => rnti,
+            Err(e) => {
+                warn!("Dropping DL NAS message for UE {}: {}", ue_id, e);
+                return Err(e);
+            }
+        };
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let transaction_id = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?
+            .allocate_transaction_id();
+        drop(contexts);
+
+        debug!("DL NAS message for UE {} (RNTI {}, {} bytes)", ue_id, rnti.0, nas_pdu.len());
+        let transfer = DlInformationTransfer {
+            transaction_id,
+            dedicated_nas_message: nas_pdu,
+        };
+        self.send_to_mac(rnti, RrcMessageType::DlInformationTransfer, transfer.encode()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_information_transfer_roundtrip() {
+        let nas = Bytes::from_static(&[0x7E, 0x00, 0x41, 0x79]);
+
+        let mut complete = RrcSetupComplete {
+            transaction_id: 0,
+            selected_plmn_identity: 1,
+            dedicated_nas_message: nas.clone(),
+            amf_selection: AmfSelectionInfo::default(),
+        };
+        assert_eq!(RrcSetupComplete::decode(&complete.encode()).unwrap(), complete);
+
+        complete.amf_selection = AmfSelectionInfo {
+            s_tmsi: Some(0x0042_C000_0401),
+            registered_amf: Some(RegisteredAmf { plmn_id: Some([0x99, 0xF9, 0x07]), amf_identifier: 0x02_0041 }),
+            requested_nssai: vec![SNssai { sst: 1, sd: None }, SNssai { sst: 2, sd: Some(0x000001) }],
+        };
+        let decoded = RrcSetupComplete::decode(&complete.encode()).unwrap();
+        assert_eq!(decoded, complete);
+        let registered_amf = decoded.amf_selection.registered_amf.unwrap();
+        assert_eq!((registered_amf.amf_region_id(), registered_amf.amf_set_id(), registered_amf.amf_pointer()), (2, 1, 1));
+
+        let dl = DlInformationTransfer {
+            transaction_id: 2,
+            dedicated_nas_message: nas.clone(),
+        };
+        assert_eq!(DlInformationTransfer::decode(&dl.encode()).unwrap(), dl);
+
+        let ul = UlInformationTransfer { dedicated_nas_message: nas };
+        assert_eq!(UlInformationTransfer::decode(&ul.encode()).unwrap(), ul);
+
+        // A missing NAS message is rejected
+        assert!(RrcSetupComplete::decode(&[0x02, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_information_transfer_decode_errors() {
+        // Selected PLMN index outside 1..12
+        assert!(matches!(RrcSetupComplete::decode(&[0x02, 0x00, 0x00, 0x00, 0x01, 0x7E]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(RrcSetupComplete::decode(&[0x02, 0x00, 0x0D, 0x00, 0x01, 0x7E]), Err(LayerError::InvalidPdu)));
+
+        // Empty, truncated and oversized NAS messages
+        assert!(matches!(DlInformationTransfer::decode(&[0x0C, 0x00, 0x00, 0x00]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(DlInformationTransfer::decode(&[0x0C, 0x00, 0x00, 0x04, 0x7E]), Err(LayerError::InvalidPdu)));
+        let mut oversized = vec![0x0D, 0x23, 0x29];
+        oversized.resize(3 + 9001, 0);
+        assert!(matches!(UlInformationTransfer::decode(&oversized), Err(LayerError::InvalidPdu)));
+        assert!(matches!(UlInformationTransfer::decode(&[]), Err(LayerError::InvalidPdu)));
+
+        // Truncated 5G-S-TMSI, registered AMF and slice list, and slice lists of 0 or 9 entries
+        let header = [0x02, 0x00, 0x01, 0x00, 0x01, 0x7E];
+        for selection in [&[0x01, 0x00, 0x42][..], &[0x02, 0x01, 0x99, 0xF9], &[0x04, 0x02, 0x01, 0x00],
+                          &[0x04, 0x01, 0x01, 0x01, 0x00], &[0x04, 0x00], &[0x04, 0x09]] {
+            let data = [&header[..], selection].concat();
+            assert!(matches!(RrcSetupComplete::decode(&data), Err(LayerError::InvalidPdu)), "{:02X?}", selection);
+        }
+
+        // No AMF selection information at all is fine
+        assert_eq!(RrcSetupComplete::decode(&header).unwrap().amf_selection, AmfSelectionInfo::default());
+    }
+}
+
@@ -0,0 +1,1051 @@
+//! RRC Reconfiguration for Data Radio Bearers
+//!
+//! Implements DRB establishment, modification and release through RRCReconfiguration
+//! according to 3GPP TS 38.331 Section 5.3.5
+
+use super::handover::{HandoverState, ReconfigurationWithSync};
+use super::measurement::MeasConfig;
+use super::{NgapRrcMessage, RrcLayer, RrcMessageType, RrcNgapMessage, RrcState};
+use crate::ngap::pdu::GtpTunnel;
+use crate::pdcp::{PdcpConfig, PdcpLayer};
+use crate::rlc::{RlcConfig, RlcLayer, RlcMode, RlcStats};
+use crate::sdap::SdapEntity;
+use crate::{LayerError, ProtocolLayer};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::{Rnti, SNssai};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Maximum DRB identity (maxDRB)
+pub const MAX_DRB_ID: u8 = 29;
+
+/// LCIDs 1-3 are used by SRBs, DRBs start from LCID 4
+pub(crate) const DRB_LCID_OFFSET: u8 = 3;
+
+/// SDAP configuration of a DRB (SDAP-Config)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdapConfig {
+    /// PDU session the DRB belongs to
+    pub pdu_session_id: u8,
+    /// DRB is the default DRB of the PDU session
+    pub default_drb: bool,
+    /// SDAP header present on DL
+    pub sdap_header_dl: bool,
+    /// SDAP header present on UL
+    pub sdap_header_ul: bool,
+    /// QFIs newly mapped to the DRB
+    pub mapped_qos_flows_to_add: Vec<u8>,
+    /// QFIs no longer mapped to the DRB
+    pub mapped_qos_flows_to_release: Vec<u8>,
+}
+
+/// DRB to add or modify (DRB-ToAddMod)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrbToAddMod {
+    /// DRB identity
+    pub drb_id: u8,
+    /// SDAP configuration (mandatory on setup)
+    pub sdap_config: Option<SdapConfig>,
+    /// PDCP configuration (mandatory on setup)
+    pub pdcp_config: Option<PdcpConfig>,
+    /// Re-establish PDCP
+    pub reestablish_pdcp: bool,
+}
+
+/// RLC bearer configuration (RLC-BearerConfig)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RlcBearerConfig {
+    /// Logical channel identity
+    pub logical_channel_id: u8,
+    /// Served DRB
+    pub drb_id: u8,
+    /// RLC configuration
+    pub rlc_config: RlcConfig,
+}
+
+/// DRB as seen by the MAC and RLC half of the gNB, which may sit in a DU
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrbBearerConfig {
+    /// DRB identity
+    pub drb_id: u8,
+    /// Logical channel identity
+    pub lcid: u8,
+    /// RLC configuration
+    pub rlc_config: RlcConfig,
+    /// QoS flows mapped to the DRB
+    pub qos_flows: Vec<u8>,
+    /// Uplink F1-U tunnel in a gNB-CU-UP, None where the gNB-CU terminates F1-U
+    pub ul_tunnel: Option<GtpTunnel>,
+    /// Slice of the PDU session carried by the DRB, None where it is not known
+    pub s_nssai: Option<SNssai>,
+}
+
+/// RRC Reconfiguration message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RrcReconfiguration {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// DRBs to add or modify
+    pub drbs_to_add_mod: Vec<DrbToAddMod>,
+    /// DRBs to release
+    pub drbs_to_release: Vec<u8>,
+    /// RLC bearers to add or modify
+    pub rlc_bearers_to_add_mod: Vec<RlcBearerConfig>,
+    /// Logical channels to release
+    pub rlc_bearers_to_release: Vec<u8>,
+    /// Dedicated NAS messages
+    pub dedicated_nas_messages: Vec<Bytes>,
+    /// Measurement configuration
+    pub meas_config: Option<MeasConfig>,
+    /// Synchronous reconfiguration to a target cell (handover)
+    pub reconfiguration_with_sync: Option<ReconfigurationWithSync>,
+}
+
+impl RrcReconfiguration {
+    /// Encode RRC Reconfiguration
+    ///
+    /// Simplified layout (would be UPER in a real implementation):
+    /// type(1) | transaction id(1) | radioBearerConfig | cellGroupConfig | dedicatedNAS-MessageList | measConfig |
+    /// reconfigurationWithSync
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        buf.put_u8(0x20); // RRC Reconfiguration
+        buf.put_u8(self.transaction_id & 0x03);
+
+        // RadioBearerConfig
+        buf.put_u8(self.drbs_to_add_mod.len() as u8);
+        for drb in &self.drbs_to_add_mod {
+            buf.put_u8(drb.drb_id);
+            buf.put_u8(
+                (drb.sdap_config.is_some() as u8)
+                    | ((drb.pdcp_config.is_some() as u8) << 1)
+                    | ((drb.reestablish_pdcp as u8) << 2),
+            );
+            if let Some(sdap) = &drb.sdap_config {
+                buf.put_u8(sdap.pdu_session_id);
+                buf.put_u8(
+                    (sdap.default_drb as u8)
+                        | ((sdap.sdap_header_dl as u8) << 1)
+                        | ((sdap.sdap_header_ul as u8) << 2),
+                );
+                buf.put_u8(sdap.mapped_qos_flows_to_add.len() as u8);
+                buf.put_slice(&sdap.mapped_qos_flows_to_add);
+                buf.put_u8(sdap.mapped_qos_flows_to_release.len() as u8);
+                buf.put_slice(&sdap.mapped_qos_flows_to_release);
+            }
+            if let Some(pdcp) = &drb.pdcp_config {
+                buf.put_u8(pdcp.sn_size);
+                buf.put_u16(pdcp.discard_timer as u16);
+                buf.put_u16(pdcp.t_reordering as u16);
+                buf.put_u8((pdcp.integrity_protection as u8) | ((pdcp.ciphering as u8) << 1));
+            }
+        }
+        buf.put_u8(self.drbs_to_release.len() as u8);
+        buf.put_slice(&self.drbs_to_release);
+
+        // CellGroupConfig (RLC bearers only)
+        buf.put_u8(self.rlc_bearers_to_add_mod.len() as u8);
+        for bearer in &self.rlc_bearers_to_add_mod {
+            buf.put_u8(bearer.logical_channel_id);
+            buf.put_u8(bearer.drb_id);
+            buf.put_u8(match bearer.rlc_config.mode {
+                RlcMode::Tm => 0,
+                RlcMode::Um => 1,
+                RlcMode::Am => 2,
+            });
+            buf.put_u8(bearer.rlc_config.sn_field_length);
+            buf.put_u16(bearer.rlc_config.poll_pdu as u16);
+        }
+        buf.put_u8(self.rlc_bearers_to_release.len() as u8);
+        buf.put_slice(&self.rlc_bearers_to_release);
+
+        // Dedicated NAS message list
+        buf.put_u8(self.dedicated_nas_messages.len() as u8);
+        for nas in &self.dedicated_nas_messages {
+            buf.put_u16(nas.len() as u16);
+            buf.put_slice(nas);
+        }
+
+        // MeasConfig
+        match &self.meas_config {
+            Some(meas_config) => {
+                buf.put_u8(1);
+                meas_config.encode(&mut buf);
+            }
+            None => buf.put_u8(0),
+        }
+
+        // ReconfigurationWithSync
+        match &self.reconfiguration_with_sync {
+            Some(with_sync) => {
+                buf.put_u8(1);
+                with_sync.encode(&mut buf);
+            }
+            None => buf.put_u8(0),
+        }
+
+        buf.freeze()
+    }
+
+    /// Decode RRC Reconfiguration
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = data;
+        if buf.remaining() < 2 || buf.get_u8() & 0x3F != 0x20 {
+            return Err(LayerError::InvalidPdu);
+        }
+
+        let mut msg = RrcReconfiguration {
+            transaction_id: buf.get_u8() & 0x03,
+            ..Default::default()
+        };
+
+        let num_drbs = get_u8(&mut buf)?;
+        for _ in 0..num_drbs {
+            let drb_id = get_u8(&mut buf)?;
+            let presence = get_u8(&mut buf)?;
+            let sdap_config = if presence & 0x01 != 0 {
+                let pdu_session_id = get_u8(&mut buf)?;
+                let flags = get_u8(&mut buf)?;
+                let mapped_qos_flows_to_add = get_list(&mut buf)?;
+                let mapped_qos_flows_to_release = get_list(&mut buf)?;
+                Some(SdapConfig {
+                    pdu_session_id,
+                    default_drb: flags & 0x01 != 0,
+                    sdap_header_dl: flags & 0x02 != 0,
+                    sdap_header_ul: flags & 0x04 != 0,
+                    mapped_qos_flows_to_add,
+                    mapped_qos_flows_to_release,
+                })
+            } else {
+                None
+            };
+            let pdcp_config = if presence & 0x02 != 0 {
+                if buf.remaining() < 6 {
+                    return Err(LayerError::InvalidPdu);
+                }
+                let sn_size = buf.get_u8();
+                let discard_timer = buf.get_u16() as u32;
+                let t_reordering = buf.get_u16() as u32;
+                let flags = buf.get_u8();
+                Some(PdcpConfig {
+                    sn_size,
+                    discard_timer,
+                    t_reordering,
+                    integrity_protection: flags & 0x01 != 0,
+                    ciphering: flags & 0x02 != 0,
+                })
+            } else {
+                None
+            };
+            msg.drbs_to_add_mod.push(DrbToAddMod {
+                drb_id,
+                sdap_config,
+                pdcp_config,
+                reestablish_pdcp: presence & 0x04 != 0,
+            });
+        }
+        msg.drbs_to_release = get_list(&mut buf)?;
+
+        let num_bearers = get_u8(&mut buf)?;
+        for _ in 0..num_bearers {
+            if buf.remaining() < 6 {
+                return Err(LayerError::InvalidPdu);
+            }
+            let logical_channel_id = buf.get_u8();
+            let drb_id = buf.get_u8();
+            let mode = match buf.get_u8() {
+                0 => RlcMode::Tm,
+                1 => RlcMode::Um,
+                2 => RlcMode::Am,
+                _ => return Err(LayerError::InvalidPdu),
+            };
+            let sn_field_length = buf.get_u8();
+            let poll_pdu = buf.get_u16() as u32;
+            msg.rlc_bearers_to_add_mod.push(RlcBearerConfig {
+                logical_channel_id,
+                drb_id,
+                rlc_config: RlcConfig {
+                    mode,
+                    sn_field_length,
+                    poll_pdu,
+                },
+            });
+        }
+        msg.rlc_bearers_to_release = get_list(&mut buf)?;
+
+        let num_nas = get_u8(&mut buf)?;
+        for _ in 0..num_nas {
+            if buf.remaining() < 2 {
+                return Err(LayerError::InvalidPdu);
+            }
+            let len = buf.get_u16() as usize;
+            if buf.remaining() < len {
+                return Err(LayerError::InvalidPdu);
+            }
+            msg.dedicated_nas_messages.push(Bytes::copy_from_slice(&buf[..len]));
+            buf.advance(len);
+        }
+
+        if get_u8(&mut buf)? != 0 {
+            msg.meas_config = Some(MeasConfig::decode(&mut buf)?);
+        }
+        if get_u8(&mut buf)? != 0 {
+            msg.reconfiguration_with_sync = Some(ReconfigurationWithSync::decode(&mut buf)?);
+        }
+
+        Ok(msg)
+    }
+}
+
+fn get_u8(buf: &mut &[u8]) -> Result<u8, LayerError> {
+    if buf.has_remaining() {
+        Ok(buf.get_u8())
+    } else {
+        Err(LayerError::InvalidPdu)
+    }
+}
+
+fn get_list(buf: &mut &[u8]) -> Result<Vec<u8>, LayerError> {
+    let len = get_u8(buf)? as usize;
+    if buf.remaining() < len {
+        return Err(LayerError::InvalidPdu);
+    }
+    let list = buf[..len].to_vec();
+    buf.advance(len);
+    Ok(list)
+}
+
+/// QoS flows of a PDU session to be carried on a DRB
+#[derive(Debug, Clone, PartialEq)]
+pub struct PduSessionResource {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// QoS flow identifiers
+    pub qos_flows: Vec<u8>,
+    /// NAS PDU of the PDU session (e.g. PDU Session Establishment Accept)
+    pub nas_pdu: Option<Bytes>,
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+}
+
+/// Change of the QoS flows of an established PDU session
+#[derive(Debug, Clone, PartialEq)]
+pub struct PduSessionResourceModify {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// QoS flows to add
+    pub qos_flows_to_add: Vec<u8>,
+    /// QoS flows to release
+    pub qos_flows_to_release: Vec<u8>,
+    /// NAS PDU of the PDU session (e.g. PDU Session Modification Command)
+    pub nas_pdu: Option<Bytes>,
+}
+
+/// PDU session procedure driving an RRC Reconfiguration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PduSessionProcedure {
+    /// PDU sessions set up as part of Initial Context Setup
+    InitialContextSetup,
+    /// PDU Session Resource Setup
+    Setup,
+    /// PDU Session Resource Modify
+    Modify,
+    /// PDU Session Resource Release
+    Release,
+}
+
+/// Data radio bearer with its PDCP and RLC entities
+#[derive(Debug)]
+pub struct DataRadioBearer {
+    /// DRB identity
+    pub drb_id: u8,
+    /// PDU session carried by the DRB
+    pub pdu_session_id: u8,
+    /// Logical channel identity
+    pub lcid: u8,
+    /// QoS flows mapped to the DRB
+    pub qos_flows: Vec<u8>,
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+    /// PDCP entity
+    pub pdcp: Arc<Mutex<PdcpLayer>>,
+    /// RLC entity
+    pub rlc: Arc<Mutex<RlcLayer>>,
+}
+
+/// RLC volumes of a connected UE, summed over its DRBs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UeDrbVolume {
+    /// UE identifier
+    pub ue_id: u32,
+    /// C-RNTI
+    pub c_rnti: Rnti,
+    /// SDU volumes of the RLC entities
+    pub rlc: RlcStats,
+}
+
+/// Outstanding RRC Reconfiguration waiting for RRCReconfigurationComplete
+#[derive(Debug, Clone)]
+pub struct PendingReconfiguration {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// PDU session procedure that triggered the reconfiguration (None for RRC-internal ones)
+    pub procedure: Option<PduSessionProcedure>,
+    /// PDU sessions handled by the reconfiguration
+    pub pdu_session_ids: Vec<u8>,
+    /// PDU sessions that could not be handled
+    pub failed_pdu_session_ids: Vec<u8>,
+    /// Guard timer expiry
+    pub deadline: Instant,
+}
+
+/// Default PDCP configuration for DRBs
+pub fn default_drb_pdcp_config() -> PdcpConfig {
+    PdcpConfig {
+        sn_size: 18,
+        discard_timer: 100,
+        t_reordering: 100,
+        integrity_protection: false,
+        ciphering: true,
+    }
+}
+
+/// Default RLC configuration for DRBs
+pub fn default_drb_rlc_config() -> RlcConfig {
+    RlcConfig {
+        mode: RlcMode::Am,
+        sn_field_length: 18,
+        poll_pdu: 64,
+    }
+}
+
+impl RrcLayer {
+    /// Handle a message from the NGAP layer
+    pub async fn handle_ngap_message(&mut self, message: NgapRrcMessage) -> Result<(), LayerError> {
+        match message {
+            NgapRrcMessage::DownlinkNasTransport { ue_id, nas_pdu } => {
+                self.handle_downlink_nas_transport(ue_id, nas_pdu).await
+            }
+            NgapRrcMessage::InitialContextSetup {
+                ue_id, security_key, nr_encryption_algorithms, nr_integrity_algorithms, sessions, nas_pdu,
+                ue_radio_capability,
+            } => {
+                self.handle_initial_context_setup(
+                    ue_id, security_key, nr_encryption_algorithms, nr_integrity_algorithms, sessions, nas_pdu,
+                    ue_radio_capability,
+                ).await
+            }
+            NgapRrcMessage::PduSessionResourceSetup { ue_id, sessions, nas_pdu } => {
+                self.setup_pdu_sessions(ue_id, sessions, nas_pdu, PduSessionProcedure::Setup).await
+            }
+            NgapRrcMessage::PduSessionResourceModify { ue_id, sessions, nas_pdu } => {
+                self.modify_pdu_sessions(ue_id, sessions, nas_pdu).await
+            }
+            NgapRrcMessage::PduSessionResourceRelease { ue_id, pdu_session_ids, nas_pdu } => {
+                self.release_pdu_sessions(ue_id, pdu_session_ids, nas_pdu).await
+            }
+            NgapRrcMessage::UeContextRelease { ue_id } => {
+                self.handle_ue_context_release_command(ue_id).await
+            }
+            NgapRrcMessage::UeContextModification {
+                ue_id, security_key, nr_encryption_algorithms, nr_integrity_algorithms,
+            } => {
+                self.handle_ue_context_modification(
+                    ue_id, security_key, nr_encryption_algorithms, nr_integrity_algorithms,
+                ).await
+            }
+            NgapRrcMessage::Paging { s_tmsi, paging_drx, paging_priority } => {
+                self.handle_cn_paging(s_tmsi, paging_drx, paging_priority).await
+            }
+            NgapRrcMessage::HandoverCommand { ue_id, rrc_container } => {
+                self.handle_handover_command(ue_id, rrc_container).await
+            }
+            NgapRrcMessage::HandoverPreparationFailure { ue_id } => {
+                self.handle_handover_preparation_failure(ue_id).await
+            }
+            NgapRrcMessage::HandoverRequest {
+                handover_id, security_key, next_hop_chaining_count, nr_encryption_algorithms,
+                nr_integrity_algorithms, sessions, rrc_container,
+            } => {
+                self.handle_handover_request(
+                    handover_id, security_key, next_hop_chaining_count, nr_encryption_algorithms,
+                    nr_integrity_algorithms, sessions, rrc_container,
+                ).await
+            }
+            NgapRrcMessage::SnStatusTransfer { ue_id, drbs } => {
+                self.handle_sn_status_transfer(ue_id, drbs).await
+            }
+        }
+    }
+
+    /// Establish DRBs for new PDU sessions
+    pub(super) async fn setup_pdu_sessions(
+        &mut self,
+        ue_id: u32,
+        sessions: Vec<PduSessionResource>,
+        nas_pdu: Option<Bytes>,
+        procedure: PduSessionProcedure,
+    ) -> Result<(), LayerError> {
+        let rnti = self.connected_rnti(ue_id).await?;
+        info!("Setting up {} PDU sessions for UE {} (RNTI {})", sessions.len(), ue_id, rnti.0);
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.pending_reconfiguration.is_some() {
+            return Err(LayerError::InvalidState("RRC Reconfiguration already ongoing".into()));
+        }
+
+        let mut reconfiguration = RrcReconfiguration::default();
+        let mut established = Vec::new();
+        let mut failed = Vec::new();
+
+        for session in sessions {
+            if ue_context.drbs.values().any(|drb| drb.pdu_session_id == session.pdu_session_id) {
+                warn!("PDU session {} already established for UE {}", session.pdu_session_id, ue_id);
+                failed.push(session.pdu_session_id);
+                continue;
+            }
+            let Some(drb_id) = (1..=MAX_DRB_ID).find(|id| !ue_context.drbs.contains_key(id)) else {
+                warn!("No free DRB ID for PDU session {} of UE {}", session.pdu_session_id, ue_id);
+                failed.push(session.pdu_session_id);
+                continue;
+            };
+
+            let (drb, drb_to_add, rlc_bearer) =
+                match establish_drb(drb_id, session.pdu_session_id, session.qos_flows, session.s_nssai).await {
+                    Ok(drb) => drb,
+                    Err(e) => {
+                        error!("Failed to create DRB {}: {}", drb_id, e);
+                        failed.push(session.pdu_session_id);
+                        continue;
+                    }
+                };
+            if let Some(sdap_config) = &drb_to_add.sdap_config {
+                ue_context.sdap_entities.entry(session.pdu_session_id)
+                    .or_insert_with(|| SdapEntity::new(session.pdu_session_id))
+                    .configure_drb(drb_id, sdap_config);
+            }
+
+            reconfiguration.dedicated_nas_messages.extend(session.nas_pdu);
+            reconfiguration.drbs_to_add_mod.push(drb_to_add);
+            reconfiguration.rlc_bearers_to_add_mod.push(rlc_bearer);
+            debug!("DRB {} (LCID {}) for PDU session {} with QFIs {:?}",
+                   drb_id, drb.lcid, session.pdu_session_id, drb.qos_flows);
+            ue_context.drbs.insert(drb_id, drb);
+            established.push(session.pdu_session_id);
+        }
+        drop(contexts);
+
+        self.start_reconfiguration(
+            rnti,
+            ue_id,
+            reconfiguration,
+            nas_pdu,
+            procedure,
+            established,
+            failed,
+        ).await
+    }
+
+    /// Change the QoS flow mapping of established PDU sessions
+    async fn modify_pdu_sessions(
+        &mut self,
+        ue_id: u32,
+        sessions: Vec<PduSessionResourceModify>,
+        nas_pdu: Option<Bytes>,
+    ) -> Result<(), LayerError> {
+        let rnti = self.connected_rnti(ue_id).await?;
+        info!("Modifying {} PDU sessions for UE {} (RNTI {})", sessions.len(), ue_id, rnti.0);
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.pending_reconfiguration.is_some() {
+            return Err(LayerError::InvalidState("RRC Reconfiguration already ongoing".into()));
+        }
+
+        let mut reconfiguration = RrcReconfiguration::default();
+        let mut modified = Vec::new();
+        let mut failed = Vec::new();
+
+        for session in sessions {
+            let Some(drb) = ue_context.drbs.values_mut()
+                .find(|drb| drb.pdu_session_id == session.pdu_session_id) else {
+                warn!("PDU session {} not established for UE {}", session.pdu_session_id, ue_id);
+                failed.push(session.pdu_session_id);
+                continue;
+            };
+
+            drb.qos_flows.retain(|qfi| !session.qos_flows_to_release.contains(qfi));
+            for qfi in &session.qos_flows_to_add {
+                if !drb.qos_flows.contains(qfi) {
+                    drb.qos_flows.push(*qfi);
+                }
+            }
+
+            let sdap_config = SdapConfig {
+                pdu_session_id: session.pdu_session_id,
+                default_drb: true,
+                sdap_header_dl: true,
+                sdap_header_ul: true,
+                mapped_qos_flows_to_add: session.qos_flows_to_add,
+                mapped_qos_flows_to_release: session.qos_flows_to_release,
+            };
+            if let Some(sdap) = ue_context.sdap_entities.get_mut(&session.pdu_session_id) {
+                sdap.configure_drb(drb.drb_id, &sdap_config);
+            }
+
+            reconfiguration.drbs_to_add_mod.push(DrbToAddMod {
+                drb_id: drb.drb_id,
+                sdap_config: Some(sdap_config),
+                pdcp_config: None,
+                reestablish_pdcp: false,
+            });
+            reconfiguration.dedicated_nas_messages.extend(session.nas_pdu);
+            modified.push(session.pdu_session_id);
+        }
+        drop(contexts);
+
+        self.start_reconfiguration(
+            rnti,
+            ue_id,
+            reconfiguration,
+            nas_pdu,
+            PduSessionProcedure::Modify,
+            modified,
+            failed,
+        ).await
+    }
+
+    /// Release the DRBs of PDU sessions
+    async fn release_pdu_sessions(
+        &mut self,
+        ue_id: u32,
+        pdu_session_ids: Vec<u8>,
+        nas_pdu: Option<Bytes>,
+    ) -> Result<(), LayerError> {
+        let rnti = self.connected_rnti(ue_id).await?;
+        info!("Releasing PDU sessions {:?} for UE {} (RNTI {})", pdu_session_ids, ue_id, rnti.0);
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.pending_reconfiguration.is_some() {
+            return Err(LayerError::InvalidState("RRC Reconfiguration already ongoing".into()));
+        }
+
+        let mut reconfiguration = RrcReconfiguration::default();
+        let mut released = Vec::new();
+        let mut failed = Vec::new();
+
+        for pdu_session_id in pdu_session_ids {
+            let drb_ids: Vec<u8> = ue_context.drbs.values()
+                .filter(|drb| drb.pdu_session_id == pdu_session_id)
+                .map(|drb| drb.drb_id)
+                .collect();
+            if drb_ids.is_empty() {
+                warn!("PDU session {} not established for UE {}", pdu_session_id, ue_id);
+                failed.push(pdu_session_id);
+                continue;
+            }
+
+            for drb_id in drb_ids {
+                if let Some(drb) = ue_context.drbs.remove(&drb_id) {
+                    reconfiguration.drbs_to_release.push(drb_id);
+                    reconfiguration.rlc_bearers_to_release.push(drb.lcid);
+                    release_drb_entities(drb).await;
+                }
+            }
+            ue_context.sdap_entities.remove(&pdu_session_id);
+            released.push(pdu_session_id);
+        }
+        drop(contexts);
+
+        self.start_reconfiguration(
+            rnti,
+            ue_id,
+            reconfiguration,
+            nas_pdu,
+            PduSessionProcedure::Release,
+            released,
+            failed,
+        ).await
+    }
+
+    /// Send RRC Reconfiguration and start the guard timer
+    #[allow(clippy::too_many_arguments)]
+    async fn start_reconfiguration(
+        &mut self,
+        rnti: Rnti,
+        ue_id: u32,
+        mut reconfiguration: RrcReconfiguration,
+        nas_pdu: Option<Bytes>,
+        procedure: PduSessionProcedure,
+        pdu_session_ids: Vec<u8>,
+        failed_pdu_session_ids: Vec<u8>,
+    ) -> Result<(), LayerError> {
+        if pdu_session_ids.is_empty() {
+            // No bearer change to signal, a NAS PDU still goes to the UE
+            if let Some(nas_pdu) = nas_pdu {
+                self.handle_downlink_nas_transport(ue_id, nas_pdu).await?;
+            }
+            self.send_pdu_session_response(ue_id, procedure, Vec::new(), failed_pdu_session_ids).await;
+            return Ok(());
+        }
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        reconfiguration.transaction_id = ue_context.allocate_transaction_id();
+        if let Some(nas_pdu) = nas_pdu {
+            // NAS PDU of the procedure ahead of the per-session ones
+            reconfiguration.dedicated_nas_messages.insert(0, nas_pdu);
+        }
+        if ue_context.meas_config.is_none() {
+            // Measurements not configured yet: piggyback the MeasConfig
+            reconfiguration.meas_config = self.config.meas_config.clone();
+            ue_context.meas_config = self.config.meas_config.clone();
+        }
+        ue_context.pending_reconfiguration = Some(PendingReconfiguration {
+            transaction_id: reconfiguration.transaction_id,
+            procedure: Some(procedure),
+            pdu_session_ids,
+            failed_pdu_session_ids,
+            deadline: Instant::now() + Duration::from_millis(self.config.procedure_guard_time_ms as u64),
+        });
+        let drb_slices: HashMap<u8, SNssai> = ue_context.drbs.values()
+            .map(|drb| (drb.drb_id, drb.s_nssai.clone()))
+            .collect();
+        drop(contexts);
+
+        let mut to_setup: Vec<DrbBearerConfig> = reconfiguration.rlc_bearers_to_add_mod.iter()
+            .map(|bearer| DrbBearerConfig {
+                drb_id: bearer.drb_id,
+                lcid: bearer.logical_channel_id,
+                rlc_config: bearer.rlc_config.clone(),
+                qos_flows: reconfiguration.drbs_to_add_mod.iter()
+                    .find(|drb| drb.drb_id == bearer.drb_id)
+                    .and_then(|drb| drb.sdap_config.as_ref())
+                    .map(|sdap| sdap.mapped_qos_flows_to_add.clone())
+                    .unwrap_or_default(),
+                ul_tunnel: None,
+                s_nssai: drb_slices.get(&bearer.drb_id).cloned(),
+            })
+            .collect();
+        if let Some(cu_up_interface) = &self.cu_up_interface {
+            if !reconfiguration.drbs_to_add_mod.is_empty() || !reconfiguration.drbs_to_release.is_empty() {
+                match cu_up_interface.configure_bearers(
+                    ue_id, rnti, reconfiguration.drbs_to_add_mod.clone(), reconfiguration.drbs_to_release.clone(),
+                ).await {
+                    Ok(ul_tunnels) => {
+                        for drb in &mut to_setup {
+                            drb.ul_tunnel = ul_tunnels.iter()
+                                .find(|(drb_id, _)| *drb_id == drb.drb_id)
+                                .map(|(_, tunnel)| *tunnel);
+                        }
+                    }
+                    Err(e) => warn!("Failed to configure the bearers of UE {} in the gNB-CU-UP: {}", ue_id, e),
+                }
+            }
+        }
+        if !to_setup.is_empty() || !reconfiguration.drbs_to_release.is_empty() {
+            if let Some(mac_interface) = &self.mac_interface {
+                if let Err(e) = mac_interface.configure_drbs(rnti, to_setup, reconfiguration.drbs_to_release.clone()).await {
+                    warn!("Failed to configure the DRB logical channels of RNTI {}: {}", rnti.0, e);
+                }
+            }
+        }
+
+        info!("Sending RRC Reconfiguration to RNTI {}: {} DRBs to add/mod, {} DRBs to release",
+              rnti.0, reconfiguration.drbs_to_add_mod.len(), reconfiguration.drbs_to_release.len());
+        self.send_to_mac(rnti, RrcMessageType::RrcReconfiguration, reconfiguration.encode()).await
+    }
+
+    /// Handle RRC Reconfiguration Complete from UE
+    pub(super) async fn handle_rrc_reconfiguration_complete(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        if data.len() < 2 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let transaction_id = data[1] & 0x03;
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let ue_id = ue_context.ue_id;
+
+        let pending = match ue_context.pending_reconfiguration.take() {
+            Some(pending) if pending.transaction_id == transaction_id => pending,
+            other => {
+                warn!("Unexpected RRC Reconfiguration Complete from RNTI {} (transaction ID {})",
+                      rnti.0, transaction_id);
+                ue_context.pending_reconfiguration = other;
+                return Ok(());
+            }
+        };
+        // The UE accessing the cell completes a handover to this gNB
+        let handover = match ue_context.handover.take() {
+            Some(HandoverState::Admitted { buffered, .. }) => Some(buffered),
+            other => {
+                ue_context.handover = other;
+                None
+            }
+        };
+        drop(contexts);
+
+        if let Some(buffered) = handover {
+            self.complete_handover(ue_id, buffered).await;
+            return Ok(());
+        }
+
+        info!("RRC Reconfiguration Complete from RNTI {} for {:?} of PDU sessions {:?}",
+              rnti.0, pending.procedure, pending.pdu_session_ids);
+        if let Some(procedure) = pending.procedure {
+            self.send_pdu_session_response(ue_id, procedure, pending.pdu_session_ids, pending.failed_pdu_session_ids).await;
+        }
+        Ok(())
+    }
+
+    /// Handle RRC Reconfiguration guard timer expiry
+    pub(super) async fn check_reconfiguration_timers(&mut self, now: Instant) {
+        let mut expired = Vec::new();
+
+        let mut contexts = self.ue_contexts.lock().await;
+        for ue_context in contexts.values_mut() {
+            let timed_out = ue_context.pending_reconfiguration.as_ref()
+                .is_some_and(|pending| pending.deadline <= now);
+            if !timed_out {
+                continue;
+            }
+            let Some(pending) = ue_context.pending_reconfiguration.take() else {
+                continue;
+            };
+
+            warn!("RRC Reconfiguration guard timer expired for RNTI {}", ue_context.c_rnti.0);
+            let Some(procedure) = pending.procedure else {
+                continue;
+            };
+            if matches!(procedure, PduSessionProcedure::Setup | PduSessionProcedure::InitialContextSetup) {
+                // Remove the bearers that were never confirmed by the UE
+                let drb_ids: Vec<u8> = ue_context.drbs.values()
+                    .filter(|drb| pending.pdu_session_ids.contains(&drb.pdu_session_id))
+                    .map(|drb| drb.drb_id)
+                    .collect();
+                for drb_id in drb_ids {
+                    if let Some(drb) = ue_context.drbs.remove(&drb_id) {
+                        release_drb_entities(drb).await;
+                    }
+                }
+                for pdu_session_id in &pending.pdu_session_ids {
+                    ue_context.sdap_entities.remove(pdu_session_id);
+                }
+            }
+
+            let mut failed = pending.failed_pdu_session_ids;
+            let succeeded = if procedure == PduSessionProcedure::Release {
+                // Resources are already released locally
+                pending.pdu_session_ids
+            } else {
+                failed.extend(pending.pdu_session_ids);
+                Vec::new()
+            };
+            expired.push((ue_context.ue_id, procedure, succeeded, failed));
+        }
+        drop(contexts);
+
+        for (ue_id, procedure, succeeded, failed) in expired {
+            self.send_pdu_session_response(ue_id, procedure, succeeded, failed).await;
+        }
+    }
+
+    /// Get the PDCP and RLC entities of a DRB
+    pub async fn get_drb_entities(&self, rnti: Rnti, drb_id: u8) -> Option<(Arc<Mutex<PdcpLayer>>, Arc<Mutex<RlcLayer>>)> {
+        let contexts = self.ue_contexts.lock().await;
+        contexts.get(&rnti.0)
+            .and_then(|ctx| ctx.drbs.get(&drb_id))
+            .map(|drb| (drb.pdcp.clone(), drb.rlc.clone()))
+    }
+
+    /// RLC volumes of every connected UE
+    pub async fn drb_volumes(&self) -> Vec<UeDrbVolume> {
+        let contexts = self.ue_contexts.lock().await;
+        let mut volumes = Vec::new();
+        for ctx in contexts.values().filter(|ctx| ctx.state == RrcState::Connected) {
+            let mut rlc = RlcStats::default();
+            for drb in ctx.drbs.values() {
+                rlc += drb.rlc.lock().await.stats();
+            }
+            volumes.push(UeDrbVolume { ue_id: ctx.ue_id, c_rnti: ctx.c_rnti, rlc });
+        }
+        volumes
+    }
+
+    /// Find the C-RNTI of a connected UE
+    pub(super) async fn connected_rnti(&self, ue_id: u32) -> Result<Rnti, LayerError> {
+        let contexts = self.ue_contexts.lock().await;
+        contexts.values()
+            .find(|ctx| ctx.ue_id == ue_id && ctx.state == RrcState::Connected)
+            .map(|ctx| ctx.c_rnti)
+            .ok_or_else(|| LayerError::InvalidState(format!("UE {} not connected", ue_id)))
+    }
+
+    /// Report the outcome of a PDU session procedure to NGAP
+    pub(super) async fn send_pdu_session_response(
+        &self,
+        ue_id: u32,
+        procedure: PduSessionProcedure,
+        succeeded: Vec<u8>,
+        failed: Vec<u8>,
+    ) {
+        if let Some(ngap_tx) = &self.ngap_tx {
+            let message = RrcNgapMessage::PduSessionResourceResponse {
+                ue_id,
+                procedure,
+                succeeded,
+                failed,
+            };
+            if let Err(e) = ngap_tx.send(message).await {
+                error!("Failed to send PDU session response to NGAP: {}", e);
+            }
+        } else {
+            debug!("No NGAP channel configured, PDU session response not sent");
+        }
+    }
+}
+
+/// DRB-ToAddMod and RLC-BearerConfig setting up a DRB with the default configuration
+pub(super) fn drb_configuration(drb_id: u8, pdu_session_id: u8, qos_flows: &[u8]) -> (DrbToAddMod, RlcBearerConfig) {
+    let drb_to_add = DrbToAddMod {
+        drb_id,
+        sdap_config: Some(SdapConfig {
+            pdu_session_id,
+            default_drb: true,
+            sdap_header_dl: true,
+            sdap_header_ul: true,
+            mapped_qos_flows_to_add: qos_flows.to_vec(),
+            mapped_qos_flows_to_release: Vec::new(),
+        }),
+        pdcp_config: Some(default_drb_pdcp_config()),
+        reestablish_pdcp: false,
+    };
+    let rlc_bearer = RlcBearerConfig {
+        logical_channel_id: drb_id + DRB_LCID_OFFSET,
+        drb_id,
+        rlc_config: default_drb_rlc_config(),
+    };
+    (drb_to_add, rlc_bearer)
+}
+
+/// Instantiate the PDCP and RLC entities of a new DRB
+pub(super) async fn establish_drb(
+    drb_id: u8,
+    pdu_session_id: u8,
+    qos_flows: Vec<u8>,
+    s_nssai: SNssai,
+) -> Result<(DataRadioBearer, DrbToAddMod, RlcBearerConfig), LayerError> {
+    let (drb_to_add, rlc_bearer) = drb_configuration(drb_id, pdu_session_id, &qos_flows);
+    let mut pdcp = PdcpLayer::new(default_drb_pdcp_config());
+    let mut rlc = RlcLayer::new(rlc_bearer.rlc_config.clone());
+    pdcp.initialize().await?;
+    rlc.initialize().await?;
+
+    let drb = DataRadioBearer {
+        drb_id,
+        pdu_session_id,
+        lcid: rlc_bearer.logical_channel_id,
+        qos_flows,
+        s_nssai,
+        pdcp: Arc::new(Mutex::new(pdcp)),
+        rlc: Arc::new(Mutex::new(rlc)),
+    };
+    Ok((drb, drb_to_add, rlc_bearer))
+}
+
+/// Shut down the PDCP and RLC entities of a released DRB
+pub(super) async fn release_drb_entities(drb: DataRadioBearer) {
+    debug!("Releasing DRB {} (LCID {})", drb.drb_id, drb.lcid);
+    if let Err(e) = drb.pdcp.lock().await.shutdown().await {
+        warn!("Failed to shut down PDCP entity of DRB {}: {}", drb.drb_id, e);
+    }
+    if let Err(e) = drb.rlc.lock().await.shutdown().await {
+        warn!("Failed to shut down RLC entity of DRB {}: {}", drb.drb_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconfiguration_roundtrip() {
+        let msg = RrcReconfiguration {
+            transaction_id: 1,
+            drbs_to_add_mod: vec![DrbToAddMod {
+                drb_id: 1,
+                sdap_config: Some(SdapConfig {
+                    pdu_session_id: 5,
+                    default_drb: true,
+                    sdap_header_dl: true,
+                    sdap_header_ul: false,
+                    mapped_qos_flows_to_add: vec![1, 2],
+                    mapped_qos_flows_to_release: vec![],
+                }),
+                pdcp_config: Some(default_drb_pdcp_config()),
+                reestablish_pdcp: false,
+            }],
+            drbs_to_release: vec![3],
+            rlc_bearers_to_add_mod: vec![RlcBearerConfig {
+                logical_channel_id: 4,
+                drb_id: 1,
+                rlc_config: default_drb_rlc_config(),
+            }],
+            rlc_bearers_to_release: vec![6],
+            dedicated_nas_messages: vec![Bytes::from_static(&[0x7E, 0x00, 0x68])],
+            meas_config: None,
+            reconfiguration_with_sync: None,
+        };
+
+        let encoded = msg.encode();
+        assert_eq!(RrcReconfiguration::decode(&encoded).unwrap(), msg);
+        assert!(RrcReconfiguration::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_reconfiguration_decode_errors() {
+        let msg = RrcReconfiguration {
+            transaction_id: 2,
+            rlc_bearers_to_add_mod: vec![RlcBearerConfig {
+                logical_channel_id: 4,
+                drb_id: 1,
+                rlc_config: default_drb_rlc_config(),
+            }],
+            ..Default::default()
+        };
+        let encoded = msg.encode();
+        assert_eq!(RrcReconfiguration::decode(&encoded).unwrap(), msg);
+
+        // Not an RRC Reconfiguration
+        let mut other = encoded.to_vec();
+        other[0] = 0x21;
+        assert!(matches!(RrcReconfiguration::decode(&other), Err(LayerError::InvalidPdu)));
+
+        // Unknown RLC mode
+        let mut unknown_mode = encoded.to_vec();
+        unknown_mode[7] = 3;
+        assert!(matches!(RrcReconfiguration::decode(&unknown_mode), Err(LayerError::InvalidPdu)));
+
+        // A dedicated NAS message longer than the message
+        let mut nas = RrcReconfiguration {
+            dedicated_nas_messages: vec![Bytes::from_static(&[0x7E, 0x00])],
+            ..Default::default()
+        }.encode().to_vec();
+        nas[7] = 0x10;
+        assert!(matches!(RrcReconfiguration::decode(&nas), Err(LayerError::InvalidPdu)));
+        assert!(matches!(RrcReconfiguration::decode(&[0x20]), Err(LayerError::InvalidPdu)));
+    }
+}
+
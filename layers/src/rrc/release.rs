@@ -0,0 +1,541 @@
+//! RRC Connection Release, Reject and Re-establishment
+//!
+//! Implements the UE context lifecycle procedures of 3GPP TS 38.331 Sections 5.3.8
+//! (RRC release), 5.3.15 (RRC reject) and 5.3.7 (RRC re-establishment)
+
+use super::handover::HandoverState;
+use super::inactive::SuspendConfig;
+use super::reconfiguration::{release_drb_entities, DrbToAddMod, PendingReconfiguration, RrcReconfiguration};
+use super::{
+    EstablishmentCause, RrcLayer, RrcMessageType, RrcNgapMessage, RrcSetupRequest, RrcState, UeContext,
+};
+use crate::LayerError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::types::Rnti;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Wait time signalled in RRC Reject when the cell is congested, in seconds (1-16)
+pub const RRC_REJECT_WAIT_TIME_S: u8 = 10;
+
+/// Reason for releasing a UE context, reported to the AMF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RrcReleaseCause {
+    /// User inactivity
+    UserInactivity,
+    /// Radio link failure
+    RadioConnectionWithUeLost,
+    /// Failure of an RRC procedure
+    FailureInRadioInterfaceProcedure,
+    /// No common encryption and integrity protection algorithms with the UE
+    AlgorithmsNotSupported,
+    /// Release requested by the AMF
+    NormalRelease,
+    /// Not enough radio resources to admit the UE
+    NoRadioResources,
+}
+
+/// RRC Re-establishment cause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReestablishmentCause {
+    /// Reconfiguration failure
+    ReconfigurationFailure,
+    /// Handover failure
+    HandoverFailure,
+    /// Other failure (e.g. radio link failure)
+    OtherFailure,
+}
+
+/// RRC Reject message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcReject {
+    /// Wait time in seconds
+    pub wait_time: u8,
+}
+
+impl RrcReject {
+    /// Encode RRC Reject
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(2);
+        buf.put_u8(0x03); // RRC Reject
+        buf.put_u8(self.wait_time.clamp(1, 16));
+        buf.freeze()
+    }
+}
+
+/// RRC Release message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcRelease {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// Suspend configuration, present when moving the UE to RRC_INACTIVE
+    pub suspend_config: Option<SuspendConfig>,
+}
+
+impl RrcRelease {
+    /// Encode RRC Release
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(3);
+        buf.put_u8(0x04); // RRC Release
+        buf.put_u8(self.transaction_id & 0x03);
+        match &self.suspend_config {
+            Some(suspend_config) => {
+                buf.put_u8(0x01);
+                suspend_config.encode(&mut buf);
+            }
+            None => buf.put_u8(0x00),
+        }
+        buf.freeze()
+    }
+
+    /// Decode RRC Release
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        let mut buf = Bytes::copy_from_slice(data);
+        if buf.remaining() < 3 || buf.get_u8() != 0x04 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let transaction_id = buf.get_u8() & 0x03;
+        let suspend_config = match buf.get_u8() {
+            0x00 => None,
+            _ => Some(SuspendConfig::decode(&mut buf)?),
+        };
+        Ok(Self {
+            transaction_id,
+            suspend_config,
+        })
+    }
+}
+
+/// RRC Re-establishment Request message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcReestablishmentRequest {
+    /// C-RNTI used in the source cell
+    pub c_rnti: Rnti,
+    /// Physical cell ID of the source cell
+    pub pci: u16,
+    /// ShortMAC-I
+    pub short_mac_i: u16,
+    /// Re-establishment cause
+    pub cause: ReestablishmentCause,
+}
+
+impl RrcReestablishmentRequest {
+    /// Decode RRC Re-establishment Request
+    ///
+    /// Layout: type(1) | c-RNTI(2) | physCellId(2) | shortMAC-I(2) | cause(1)
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        if data.len() < 8 {
+            return Err(LayerError::InvalidPdu);
+        }
+
+        let cause = match data[7] {
+            0 => ReestablishmentCause::ReconfigurationFailure,
+            1 => ReestablishmentCause::HandoverFailure,
+            _ => ReestablishmentCause::OtherFailure,
+        };
+
+        Ok(Self {
+            c_rnti: Rnti::new(u16::from_be_bytes([data[1], data[2]])),
+            pci: u16::from_be_bytes([data[3], data[4]]) & 0x3FF,
+            short_mac_i: u16::from_be_bytes([data[5], data[6]]),
+            cause,
+        })
+    }
+
+    /// Encode RRC Re-establishment Request
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(8);
+        buf.put_u8(0x05); // RRC Re-establishment Request
+        buf.put_u16(self.c_rnti.0);
+        buf.put_u16(self.pci);
+        buf.put_u16(self.short_mac_i);
+        buf.put_u8(self.cause as u8);
+        buf.freeze()
+    }
+}
+
+/// RRC Re-establishment message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrcReestablishment {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// nextHopChainingCount
+    pub next_hop_chaining_count: u8,
+}
+
+impl RrcReestablishment {
+    /// Encode RRC Re-establishment
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(3);
+        buf.put_u8(0x06); // RRC Re-establishment
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_u8(self.next_hop_chaining_count & 0x07);
+        buf.freeze()
+    }
+}
+
+impl RrcLayer {
+    /// Release the RRC connection of a UE and tear down all of its state
+    ///
+    /// Returns the PDU sessions that were active on the UE.
+    pub async fn release_ue(&mut self, rnti: Rnti) -> Result<Vec<u8>, LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        let release = RrcRelease {
+            transaction_id: ue_context.allocate_transaction_id(),
+            suspend_config: None,
+        };
+        drop(contexts);
+
+        info!("Sending RRC Release to RNTI {}", rnti.0);
+        if let Err(e) = self.send_to_mac(rnti, RrcMessageType::RrcRelease, release.encode()).await {
+            warn!("Failed to send RRC Release to RNTI {}: {}", rnti.0, e);
+        }
+
+        let ue_context = self.teardown_ue_context(rnti).await?;
+        Ok(pdu_session_ids(&ue_context))
+    }
+
+    /// Release a UE on AMF command and confirm the release to NGAP
+    ///
+    /// A UE that left for another gNB in a handover is only torn down: it no
+    /// longer listens to this cell.
+    pub(super) async fn handle_ue_context_release_command(&mut self, ue_id: u32) -> Result<(), LayerError> {
+        let rnti = self.rnti_for_ue(ue_id).await;
+        let handed_over = match rnti {
+            Some(rnti) => self.ue_contexts.lock().await.get(&rnti.0)
+                .is_some_and(|ctx| matches!(ctx.handover, Some(HandoverState::Executing { .. }))),
+            None => false,
+        };
+        let pdu_session_ids = match rnti {
+            Some(rnti) if handed_over => pdu_session_ids(&self.teardown_ue_context(rnti).await?),
+            Some(rnti) => self.release_ue(rnti).await?,
+            None => match self.remove_inactive_context(ue_id).await {
+                Some(pdu_session_ids) => pdu_session_ids,
+                None => {
+                    warn!("UE Context Release Command for unknown UE {}", ue_id);
+                    Vec::new()
+                }
+            },
+        };
+
+        self.send_to_ngap(RrcNgapMessage::UeContextReleaseComplete {
+            ue_id,
+            pdu_session_ids,
+        }).await;
+        Ok(())
+    }
+
+    /// Remove a UE context and release its DRB entities and MAC resources
+    pub(super) async fn teardown_ue_context(&mut self, rnti: Rnti) -> Result<UeContext, LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let mut ue_context = contexts.remove(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        drop(contexts);
+
+        for (_, drb) in ue_context.drbs.drain() {
+            release_drb_entities(drb).await;
+        }
+
+        if let Some(mac_interface) = &self.mac_interface {
+            if let Err(e) = mac_interface.release_ue(rnti).await {
+                warn!("Failed to release MAC resources of RNTI {}: {}", rnti.0, e);
+            }
+        }
+
+        ue_context.state = RrcState::Idle;
+        info!("UE {} (RNTI {}) released", ue_context.ue_id, rnti.0);
+        Ok(ue_context)
+    }
+
+    /// Ask the AMF to release a UE context
+    pub async fn request_ue_context_release(&mut self, rnti: Rnti, cause: RrcReleaseCause) -> Result<(), LayerError> {
+        if self.ngap_tx.is_none() {
+            // No core network association: release locally
+            self.release_ue(rnti).await?;
+            return Ok(());
+        }
+
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        if ue_context.release_requested_at.is_some() {
+            return Ok(());
+        }
+        ue_context.release_requested_at = Some(Instant::now());
+        let ue_id = ue_context.ue_id;
+        let pdu_session_ids = pdu_session_ids(ue_context);
+        drop(contexts);
+
+        info!("Requesting UE context release for UE {} (RNTI {}): {:?}", ue_id, rnti.0, cause);
+        self.send_to_ngap(RrcNgapMessage::UeContextReleaseRequest {
+            ue_id,
+            cause,
+            pdu_session_ids,
+        }).await;
+        Ok(())
+    }
+
+    /// Report a radio link failure detected by lower layers
+    pub async fn report_radio_link_failure(&mut self, rnti: Rnti) -> Result<(), LayerError> {
+        warn!("Radio link failure for RNTI {}", rnti.0);
+        self.request_ue_context_release(rnti, RrcReleaseCause::RadioConnectionWithUeLost).await
+    }
+
+    /// Record user activity for the inactivity timer
+    pub async fn report_ue_activity(&self, rnti: Rnti) {
+        let mut contexts = self.ue_contexts.lock().await;
+        if let Some(ue_context) = contexts.get_mut(&rnti.0) {
+            ue_context.last_activity = Instant::now();
+        }
+    }
+
+    /// Check UE inactivity and pending release requests
+    pub(super) async fn check_inactivity_timers(&mut self, now: Instant) {
+        let inactivity = Duration::from_secs(self.config.inactivity_timer_s as u64);
+        let guard = Duration::from_millis(self.config.release_request_guard_time_ms as u64);
+
+        let mut inactive = Vec::new();
+        let mut unanswered = Vec::new();
+
+        let contexts = self.ue_contexts.lock().await;
+        for ue_context in contexts.values() {
+            if ue_context.state != RrcState::Connected || ue_context.handover.is_some() {
+                continue;
+            }
+            match ue_context.release_requested_at {
+                Some(requested_at) if now >= requested_at + guard => unanswered.push(ue_context.c_rnti),
+                Some(_) => {}
+                None if now >= ue_context.last_activity + inactivity => inactive.push(ue_context.c_rnti),
+                None => {}
+            }
+        }
+        drop(contexts);
+
+        for rnti in inactive {
+            info!("Inactivity timer expired for RNTI {}", rnti.0);
+            if self.config.inactive_enabled {
+                match self.suspend_ue(rnti).await {
+                    Ok(_) => continue,
+                    Err(e) => debug!("RNTI {} cannot be suspended: {}", rnti.0, e),
+                }
+            }
+            if let Err(e) = self.request_ue_context_release(rnti, RrcReleaseCause::UserInactivity).await {
+                error!("Failed to request release of RNTI {}: {}", rnti.0, e);
+            }
+        }
+
+        for rnti in unanswered {
+            // The AMF did not answer the release request: release locally
+            warn!("No UE Context Release Command for RNTI {} within {} ms, releasing locally without the AMF",
+                  rnti.0, self.config.release_request_guard_time_ms);
+            if let Ok(pdu_session_ids) = self.release_ue(rnti).await {
+                debug!("Released PDU sessions {:?} of RNTI {}", pdu_session_ids, rnti.0);
+            }
+        }
+    }
+
+    /// Reject an RRC Setup Request
+    pub(super) async fn send_rrc_reject(&self, rnti: Rnti) -> Result<(), LayerError> {
+        let reject = RrcReject {
+            wait_time: RRC_REJECT_WAIT_TIME_S,
+        };
+        info!("Sending RRC Reject to RNTI {} with wait time {}s", rnti.0, reject.wait_time);
+        self.send_to_mac(rnti, RrcMessageType::RrcReject, reject.encode()).await?;
+
+        if let Some(mac_interface) = &self.mac_interface {
+            mac_interface.release_ue(rnti).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle RRC Re-establishment Request from UE
+    pub(super) async fn handle_rrc_reestablishment_request(&mut self, rnti: Rnti, data: Bytes) -> Result<(), LayerError> {
+        let request = RrcReestablishmentRequest::decode(&data)?;
+        info!("Handling RRC Re-establishment Request from RNTI {}: old C-RNTI {}, PCI {}, cause {:?}",
+              rnti.0, request.c_rnti.0, request.pci, request.cause);
+
+        if self.verify_reestablishment(&request).await {
+            return self.reestablish_ue(rnti, request.c_rnti).await;
+        }
+
+        // Fall back to RRC Setup. An unverified request must not tear down the old
+        // context; it is released by the inactivity timer if the UE is really gone.
+        warn!("RRC Re-establishment of C-RNTI {} rejected, falling back to RRC Setup", request.c_rnti.0);
+        let setup_request = RrcSetupRequest {
+            ue_identity: Vec::new(),
+            establishment_cause: EstablishmentCause::MoSignalling,
+        };
+        self.handle_rrc_setup_request(rnti, setup_request).await
+    }
+
+    /// Check that the old context exists and the ShortMAC-I is valid
+    async fn verify_reestablishment(&self, request: &RrcReestablishmentRequest) -> bool {
+        if request.pci != self.config.pci {
+            debug!("Re-establishment from unknown source PCI {}", request.pci);
+            return false;
+        }
+
+        let contexts = self.ue_contexts.lock().await;
+        let Some(ue_context) = contexts.get(&request.c_rnti.0) else {
+            debug!("No UE context for C-RNTI {}", request.c_rnti.0);
+            return false;
+        };
+        let Some(security) = &ue_context.security else {
+            debug!("AS security not activated for C-RNTI {}", request.c_rnti.0);
+            return false;
+        };
+
+        match security.short_mac_i(request.pci, self.config.nr_cell_identity, request.c_rnti.0) {
+            Ok(expected) if expected == request.short_mac_i => true,
+            Ok(expected) => {
+                warn!("ShortMAC-I mismatch for C-RNTI {}: expected {:#06x}, received {:#06x}",
+                      request.c_rnti.0, expected, request.short_mac_i);
+                false
+            }
+            Err(e) => {
+                warn!("Failed to compute ShortMAC-I: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Move a verified UE context to its new C-RNTI and send RRC Re-establishment
+    async fn reestablish_ue(&mut self, rnti: Rnti, old_rnti: Rnti) -> Result<(), LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let mut ue_context = contexts.remove(&old_rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        ue_context.c_rnti = rnti;
+        ue_context.state = RrcState::Connected;
+        ue_context.last_activity = Instant::now();
+        ue_context.release_requested_at = None;
+        ue_context.pending_reconfiguration = None;
+        let reestablishment = RrcReestablishment {
+            transaction_id: ue_context.allocate_transaction_id(),
+            next_hop_chaining_count: ue_context.security.as_ref()
+                .map(|security| security.next_hop_chaining_count)
+                .unwrap_or(0),
+        };
+        let scheduling_capabilities = ue_context.ue_capability.as_ref()
+            .map(|capability| capability.scheduling_capabilities(self.config.band));
+        let ue_id = ue_context.ue_id;
+        contexts.insert(rnti.0, ue_context);
+        drop(contexts);
+
+        if let Some(mac_interface) = &self.mac_interface {
+            mac_interface.release_ue(old_rnti).await?;
+            if let Some(capabilities) = scheduling_capabilities {
+                mac_interface.configure_ue_capabilities(rnti, capabilities).await?;
+            }
+        }
+
+        info!("Re-establishing UE {} on RNTI {} (was {})", ue_id, rnti.0, old_rnti.0);
+        self.send_to_mac(rnti, RrcMessageType::RrcReestablishment, reestablishment.encode()).await
+    }
+
+    /// Handle RRC Re-establishment Complete and resume the DRBs
+    pub(super) async fn handle_rrc_reestablishment_complete(&mut self, rnti: Rnti) -> Result<(), LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState("No UE context".into()))?;
+        info!("RRC Re-establishment Complete from RNTI {}", rnti.0);
+
+        if ue_context.drbs.is_empty() {
+            return Ok(());
+        }
+
+        // Re-establish PDCP of all DRBs with an RRC Reconfiguration
+        let mut reconfiguration = RrcReconfiguration {
+            transaction_id: ue_context.allocate_transaction_id(),
+            ..Default::default()
+        };
+        for drb_id in ue_context.drbs.keys() {
+            reconfiguration.drbs_to_add_mod.push(DrbToAddMod {
+                drb_id: *drb_id,
+                sdap_config: None,
+                pdcp_config: None,
+                reestablish_pdcp: true,
+            });
+        }
+        ue_context.pending_reconfiguration = Some(PendingReconfiguration {
+            transaction_id: reconfiguration.transaction_id,
+            procedure: None,
+            pdu_session_ids: Vec::new(),
+            failed_pdu_session_ids: Vec::new(),
+            deadline: Instant::now() + Duration::from_millis(self.config.procedure_guard_time_ms as u64),
+        });
+        drop(contexts);
+
+        self.send_to_mac(rnti, RrcMessageType::RrcReconfiguration, reconfiguration.encode()).await
+    }
+
+    /// Find the C-RNTI of a UE by its identifier
+    pub(super) async fn rnti_for_ue(&self, ue_id: u32) -> Option<Rnti> {
+        let contexts = self.ue_contexts.lock().await;
+        contexts.values()
+            .find(|ctx| ctx.ue_id == ue_id)
+            .map(|ctx| ctx.c_rnti)
+    }
+
+    /// Send a message to NGAP if a channel is configured
+    pub(super) async fn send_to_ngap(&self, message: RrcNgapMessage) {
+        if let Some(ngap_tx) = &self.ngap_tx {
+            if let Err(e) = ngap_tx.send(message).await {
+                error!("Failed to send message to NGAP: {}", e);
+            }
+        } else {
+            debug!("No NGAP channel configured, dropping {:?}", message);
+        }
+    }
+}
+
+/// PDU sessions with established DRBs
+pub(super) fn pdu_session_ids(ue_context: &UeContext) -> Vec<u8> {
+    let mut ids: Vec<u8> = ue_context.drbs.values().map(|drb| drb.pdu_session_id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_codec() {
+        let release = RrcRelease {
+            transaction_id: 1,
+            suspend_config: Some(SuspendConfig {
+                full_i_rnti: 0x12_3456_789A,
+                short_i_rnti: 0x56_789A,
+                ran_paging_cycle: 32,
+                ran_notification_area: vec![7],
+                next_hop_chaining_count: 2,
+            }),
+        };
+        let encoded = release.encode();
+        assert_eq!(RrcRelease::decode(&encoded).unwrap(), release);
+
+        // Wrong message type, missing suspend flag and truncated suspendConfig
+        assert!(matches!(RrcRelease::decode(&[0x03, 0x01, 0x00]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(RrcRelease::decode(&encoded[..2]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(RrcRelease::decode(&encoded[..encoded.len() - 1]), Err(LayerError::InvalidPdu)));
+
+        let request = RrcReestablishmentRequest {
+            c_rnti: Rnti::new(0x4601),
+            pci: 1,
+            short_mac_i: 0xBEEF,
+            cause: ReestablishmentCause::HandoverFailure,
+        };
+        let encoded = request.encode();
+        assert_eq!(RrcReestablishmentRequest::decode(&encoded).unwrap(), request);
+        assert!(matches!(RrcReestablishmentRequest::decode(&encoded[..7]), Err(LayerError::InvalidPdu)));
+
+        // Spare cause values are other failures
+        let mut spare = encoded.to_vec();
+        spare[7] = 3;
+        assert_eq!(RrcReestablishmentRequest::decode(&spare).unwrap().cause, ReestablishmentCause::OtherFailure);
+    }
+}
+
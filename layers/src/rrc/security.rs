@@ -0,0 +1,306 @@
+//! Access Stratum Security
+//!
+//! Implements AS key derivation according to 3GPP TS 33.501 Annex A.8, the
+//! 128-NIA2 integrity algorithm according to TS 33.501 Annex D.3 and the
+//! Security Mode Command messages of TS 38.331 Section 5.3.4
+
+use crate::LayerError;
+use aes::Aes128;
+use bytes::{BufMut, Bytes, BytesMut};
+use cmac::{Cmac, Mac};
+use hmac::Hmac;
+use sha2::Sha256;
+
+/// NR integrity protection algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityAlgorithm {
+    /// Null integrity protection
+    Nia0 = 0,
+    /// SNOW 3G based
+    Nia1 = 1,
+    /// AES based
+    Nia2 = 2,
+    /// ZUC based
+    Nia3 = 3,
+}
+
+/// NR ciphering algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipheringAlgorithm {
+    /// Null ciphering
+    Nea0 = 0,
+    /// SNOW 3G based
+    Nea1 = 1,
+    /// AES based
+    Nea2 = 2,
+    /// ZUC based
+    Nea3 = 3,
+}
+
+/// Ciphering algorithms in order of preference
+const CIPHERING_PREFERENCE: [CipheringAlgorithm; 2] = [CipheringAlgorithm::Nea2, CipheringAlgorithm::Nea0];
+
+/// Integrity algorithms in order of preference (only those implemented here)
+const INTEGRITY_PREFERENCE: [IntegrityAlgorithm; 1] = [IntegrityAlgorithm::Nia2];
+
+/// Select the AS algorithms from the UE security capabilities received from the AMF
+///
+/// The masks are the 16-bit NR algorithm bit strings of TS 38.413, where the most
+/// significant bit is algorithm 1. NEA0 is always supported (TS 33.501 Section 5.11.1.1),
+/// NIA0 is never selected.
+pub fn select_algorithms(nr_encryption_algorithms: u16, nr_integrity_algorithms: u16) -> Option<(IntegrityAlgorithm, CipheringAlgorithm)> {
+    let integrity = INTEGRITY_PREFERENCE.into_iter()
+        .find(|algorithm| *algorithm as u8 != 0 && algorithm_supported(nr_integrity_algorithms, *algorithm as u8))?;
+    let ciphering = CIPHERING_PREFERENCE.into_iter()
+        .find(|algorithm| algorithm_supported(nr_encryption_algorithms, *algorithm as u8))?;
+    Some((integrity, ciphering))
+}
+
+/// Check that the algorithms in use are still allowed by updated UE security capabilities
+pub fn algorithms_supported(
+    context: &SecurityContext,
+    nr_encryption_algorithms: u16,
+    nr_integrity_algorithms: u16,
+) -> bool {
+    algorithm_supported(nr_encryption_algorithms, context.ciphering_algorithm as u8)
+        && algorithm_supported(nr_integrity_algorithms, context.integrity_algorithm as u8)
+}
+
+fn algorithm_supported(mask: u16, id: u8) -> bool {
+    id == 0 || mask & (0x8000 >> (id - 1)) != 0
+}
+
+/// Security Mode Command message
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityModeCommand {
+    /// RRC transaction identifier
+    pub transaction_id: u8,
+    /// Selected ciphering algorithm
+    pub ciphering_algorithm: CipheringAlgorithm,
+    /// Selected integrity algorithm
+    pub integrity_algorithm: IntegrityAlgorithm,
+}
+
+impl SecurityModeCommand {
+    /// Encode Security Mode Command
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(4);
+        buf.put_u8(0x10); // Security Mode Command
+        buf.put_u8(self.transaction_id & 0x03);
+        buf.put_u8(self.ciphering_algorithm as u8);
+        buf.put_u8(self.integrity_algorithm as u8);
+        buf.freeze()
+    }
+
+    /// Decode Security Mode Command
+    pub fn decode(data: &[u8]) -> Result<Self, LayerError> {
+        if data.len() < 4 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let ciphering_algorithm = match data[2] {
+            0 => CipheringAlgorithm::Nea0,
+            1 => CipheringAlgorithm::Nea1,
+            2 => CipheringAlgorithm::Nea2,
+            3 => CipheringAlgorithm::Nea3,
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        let integrity_algorithm = match data[3] {
+            0 => IntegrityAlgorithm::Nia0,
+            1 => IntegrityAlgorithm::Nia1,
+            2 => IntegrityAlgorithm::Nia2,
+            3 => IntegrityAlgorithm::Nia3,
+            _ => return Err(LayerError::InvalidPdu),
+        };
+        Ok(Self {
+            transaction_id: data[1] & 0x03,
+            ciphering_algorithm,
+            integrity_algorithm,
+        })
+    }
+}
+
+/// Algorithm type distinguishers (TS 33.501 Table A.8-1)
+const N_RRC_ENC_ALG: u8 = 0x03;
+const N_RRC_INT_ALG: u8 = 0x04;
+const N_UP_ENC_ALG: u8 = 0x05;
+const N_UP_INT_ALG: u8 = 0x06;
+
+/// AS security context of a UE
+#[derive(Debug, Clone)]
+pub struct SecurityContext {
+    /// K_gNB received from the AMF
+    pub k_gnb: [u8; 32],
+    /// Selected integrity algorithm
+    pub integrity_algorithm: IntegrityAlgorithm,
+    /// Selected ciphering algorithm
+    pub ciphering_algorithm: CipheringAlgorithm,
+    /// RRC integrity key
+    pub k_rrc_int: [u8; 16],
+    /// RRC ciphering key
+    pub k_rrc_enc: [u8; 16],
+    /// User plane integrity key
+    pub k_up_int: [u8; 16],
+    /// User plane ciphering key
+    pub k_up_enc: [u8; 16],
+    /// Next hop chaining count
+    pub next_hop_chaining_count: u8,
+}
+
+impl SecurityContext {
+    /// Create a security context and derive the AS keys from K_gNB
+    pub fn new(
+        k_gnb: [u8; 32],
+        integrity_algorithm: IntegrityAlgorithm,
+        ciphering_algorithm: CipheringAlgorithm,
+    ) -> Self {
+        Self {
+            k_gnb,
+            integrity_algorithm,
+            ciphering_algorithm,
+            k_rrc_int: derive_algorithm_key(&k_gnb, N_RRC_INT_ALG, integrity_algorithm as u8),
+            k_rrc_enc: derive_algorithm_key(&k_gnb, N_RRC_ENC_ALG, ciphering_algorithm as u8),
+            k_up_int: derive_algorithm_key(&k_gnb, N_UP_INT_ALG, integrity_algorithm as u8),
+            k_up_enc: derive_algorithm_key(&k_gnb, N_UP_ENC_ALG, ciphering_algorithm as u8),
+            next_hop_chaining_count: 0,
+        }
+    }
+
+    /// Compute the 16-bit ShortMAC-I / resumeMAC-I over a VarShortMAC-Input
+    ///
+    /// COUNT, BEARER and DIRECTION are set to binary ones (TS 38.331 Section 5.3.7.4).
+    pub fn short_mac_i(&self, pci: u16, cell_identity: u64, c_rnti: u16) -> Result<u16, LayerError> {
+        let input = encode_var_short_mac_input(pci, cell_identity, c_rnti);
+        let mac_i = compute_mac_i(self.integrity_algorithm, &self.k_rrc_int, 0xFFFF_FFFF, 0x1F, 1, &input)?;
+        Ok(u16::from_be_bytes([mac_i[2], mac_i[3]]))
+    }
+}
+
+/// Generic key derivation function (TS 33.220 Annex B.2)
+pub fn kdf(key: &[u8], fc: u8, params: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&[fc]);
+    for param in params {
+        mac.update(param);
+        mac.update(&(param.len() as u16).to_be_bytes());
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Derive a 128-bit algorithm key from K_gNB (TS 33.501 Annex A.8)
+pub fn derive_algorithm_key(k_gnb: &[u8; 32], distinguisher: u8, algorithm_id: u8) -> [u8; 16] {
+    let derived = kdf(k_gnb, 0x69, &[&[distinguisher], &[algorithm_id]]);
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&derived[16..]);
+    key
+}
+
+/// Derive K_gNB* for a horizontal key derivation at handover (TS 33.501 Annex A.11)
+///
+/// The target cell is identified by its PCI and the NR-ARFCN of its SSB.
+pub fn derive_k_gnb_star(k_gnb: &[u8; 32], pci: u16, arfcn_dl: u32) -> [u8; 32] {
+    let arfcn = arfcn_dl.to_be_bytes();
+    kdf(k_gnb, 0x70, &[&pci.to_be_bytes(), &arfcn[1..]])
+}
+
+/// Compute a 32-bit MAC-I over a byte-aligned message
+pub fn compute_mac_i(
+    algorithm: IntegrityAlgorithm,
+    key: &[u8; 16],
+    count: u32,
+    bearer: u8,
+    direction: u8,
+    message: &[u8],
+) -> Result<[u8; 4], LayerError> {
+    match algorithm {
+        IntegrityAlgorithm::Nia0 => Ok([0u8; 4]),
+        IntegrityAlgorithm::Nia2 => {
+            let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key)
+                .map_err(|_| LayerError::ProcessingError("Invalid NIA2 key".into()))?;
+            mac.update(&count.to_be_bytes());
+            mac.update(&[((bearer & 0x1F) << 3) | ((direction & 0x01) << 2), 0, 0, 0]);
+            mac.update(message);
+            let tag = mac.finalize().into_bytes();
+            Ok([tag[0], tag[1], tag[2], tag[3]])
+        }
+        IntegrityAlgorithm::Nia1 | IntegrityAlgorithm::Nia3 => Err(LayerError::ProcessingError(
+            format!("Integrity algorithm {:?} not supported", algorithm),
+        )),
+    }
+}
+
+/// Encode VarShortMAC-Input / VarResumeMAC-Input in UPER
+///
+/// Layout: sourcePhysCellId(10 bits) | targetCellIdentity(36 bits) |
+/// source-c-RNTI(16 bits), padded with 2 zero bits to a whole octet.
+pub fn encode_var_short_mac_input(pci: u16, cell_identity: u64, c_rnti: u16) -> [u8; 8] {
+    let bits = ((pci as u64 & 0x3FF) << 54)
+        | ((cell_identity & 0xF_FFFF_FFFF) << 18)
+        | ((c_rnti as u64) << 2);
+    bits.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nia2_mac_i() {
+        // 128-EIA2 test set 2 of TS 33.401 Annex C.2, the first byte-aligned one
+        let key = [0xd3, 0xc5, 0xd5, 0x92, 0x32, 0x7f, 0xb1, 0x1c, 0x40, 0x35, 0xc6, 0x68, 0x0a, 0xf8, 0xc6, 0xd1];
+        let message = [0x48, 0x45, 0x83, 0xd5, 0xaf, 0xe0, 0x82, 0xae];
+        let mac_i = compute_mac_i(IntegrityAlgorithm::Nia2, &key, 0x398a59b4, 0x1a, 1, &message).unwrap();
+        assert_eq!(mac_i, [0xb9, 0x37, 0x87, 0xe6]);
+
+        let null_mac = compute_mac_i(IntegrityAlgorithm::Nia0, &key, 0, 0, 0, b"message").unwrap();
+        assert_eq!(null_mac, [0u8; 4]);
+    }
+
+    #[test]
+    fn test_var_short_mac_input() {
+        assert_eq!(encode_var_short_mac_input(1, 0x19B01, 0x4601),
+                   [0x00, 0x40, 0x00, 0x06, 0x6C, 0x05, 0x18, 0x04]);
+        assert_eq!(encode_var_short_mac_input(1007, 0xF_FFFF_FFFF, 0xFFFF),
+                   [0xFB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFC]);
+    }
+
+    #[test]
+    fn test_short_mac_i() {
+        let context = SecurityContext::new([0x11; 32], IntegrityAlgorithm::Nia2, CipheringAlgorithm::Nea0);
+        assert_ne!(context.k_rrc_int, context.k_up_int);
+
+        let short_mac_i = context.short_mac_i(1, 0x19B01, 0x4601).unwrap();
+        assert_eq!(short_mac_i, context.short_mac_i(1, 0x19B01, 0x4601).unwrap());
+        assert_ne!(short_mac_i, context.short_mac_i(2, 0x19B01, 0x4601).unwrap());
+    }
+
+    #[test]
+    fn test_k_gnb_star() {
+        let k_gnb = [0x22; 32];
+        let k_gnb_star = derive_k_gnb_star(&k_gnb, 2, 368500);
+        assert_ne!(k_gnb_star, k_gnb);
+        assert_eq!(k_gnb_star, derive_k_gnb_star(&k_gnb, 2, 368500));
+        assert_ne!(k_gnb_star, derive_k_gnb_star(&k_gnb, 3, 368500));
+    }
+
+    #[test]
+    fn test_security_mode_command_decode_errors() {
+        let command = SecurityModeCommand {
+            transaction_id: 3,
+            ciphering_algorithm: CipheringAlgorithm::Nea2,
+            integrity_algorithm: IntegrityAlgorithm::Nia2,
+        };
+        let encoded = command.encode();
+        let decoded = SecurityModeCommand::decode(&encoded).unwrap();
+        assert_eq!((decoded.transaction_id, decoded.ciphering_algorithm, decoded.integrity_algorithm),
+                   (3, CipheringAlgorithm::Nea2, IntegrityAlgorithm::Nia2));
+
+        assert!(matches!(SecurityModeCommand::decode(&encoded[..3]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(SecurityModeCommand::decode(&[0x10, 0, 4, 2]), Err(LayerError::InvalidPdu)));
+        assert!(matches!(SecurityModeCommand::decode(&[0x10, 0, 2, 4]), Err(LayerError::InvalidPdu)));
+
+        // Algorithms without an implementation cannot protect messages
+        assert!(matches!(compute_mac_i(IntegrityAlgorithm::Nia1, &[0; 16], 0, 0, 0, b"message"),
+                         Err(LayerError::ProcessingError(_))));
+    }
+}
+
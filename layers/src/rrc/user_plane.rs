@@ -0,0 +1,161 @@
+//! User plane of the data radio bearers
+//!
+//! Carries user packets between the N3 tunnels of the GTP-U endpoint and the DRBs
+//! of a UE: the SDAP entity of the PDU session maps downlink packets from their
+//! QoS flow to a DRB (3GPP TS 37.324 section 5.2), whose PDCP and RLC entities
+//! pass them to MAC; uplink SDUs of a DRB go back through SDAP to the tunnel of
+//! the PDU session.
+
+use super::handover::{ForwardedPacket, HandoverState, MAX_FORWARDED_PACKETS};
+use super::{RrcLayer, RrcState};
+use crate::sdap::SdapUplink;
+use crate::{LayerError, ProtocolLayer};
+use bytes::Bytes;
+use common::types::Rnti;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, error, warn};
+
+/// Messages sent from the GTP-U endpoint towards RRC
+#[derive(Debug, Clone)]
+pub enum GtpuRrcMessage {
+    /// Downlink packet received on the N3 tunnel of a PDU session
+    DownlinkData {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU session ID
+        pdu_session_id: u8,
+        /// QoS flow of the packet, from the PDU Session Container
+        qfi: Option<u8>,
+        /// Reflective QoS indication
+        rqi: bool,
+        /// User packet
+        data: Bytes,
+    },
+}
+
+/// Messages sent from RRC towards the GTP-U endpoint
+#[derive(Debug, Clone)]
+pub enum RrcGtpuMessage {
+    /// Uplink packet of a PDU session received on a DRB
+    UplinkData {
+        /// UE identifier (RAN UE NGAP ID)
+        ue_id: u32,
+        /// PDU session ID
+        pdu_session_id: u8,
+        /// QoS flow of the packet
+        qfi: u8,
+        /// User packet
+        data: Bytes,
+    },
+}
+
+impl RrcLayer {
+    /// Set the channel used to pass uplink packets to the GTP-U endpoint
+    pub fn set_gtpu_channel(&mut self, tx: mpsc::Sender<RrcGtpuMessage>) {
+        self.gtpu_tx = Some(tx);
+    }
+
+    /// Handle a message from the GTP-U endpoint
+    pub async fn handle_gtpu_message(&mut self, message: GtpuRrcMessage) -> Result<(), LayerError> {
+        match message {
+            GtpuRrcMessage::DownlinkData { ue_id, pdu_session_id, qfi, rqi, data } => {
+                self.handle_downlink_user_data(ue_id, pdu_session_id, qfi, rqi, data).await
+            }
+        }
+    }
+
+    /// Pass a downlink packet through SDAP and the DRB carrying its QoS flow
+    ///
+    /// Packets for a UE in RRC_INACTIVE start RAN paging and are dropped.
+    /// Packets for a UE being handed over to this gNB are held until it
+    /// accesses the cell.
+    pub(super) async fn handle_downlink_user_data(
+        &mut self,
+        ue_id: u32,
+        pdu_session_id: u8,
+        qfi: Option<u8>,
+        rqi: bool,
+        data: Bytes,
+    ) -> Result<(), LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let Some(ue_context) = contexts.values_mut()
+            .find(|ctx| ctx.ue_id == ue_id && ctx.state == RrcState::Connected) else {
+            drop(contexts);
+            if !self.handle_downlink_data_notification(ue_id).await? {
+                debug!("Dropping downlink packet for unknown UE {}", ue_id);
+            }
+            return Ok(());
+        };
+        if let Some(HandoverState::Admitted { buffered, .. }) = &mut ue_context.handover {
+            if buffered.len() < MAX_FORWARDED_PACKETS {
+                buffered.push(ForwardedPacket { pdu_session_id, qfi, rqi, data });
+            } else {
+                debug!("Forwarding buffer of UE {} full, dropping downlink packet", ue_id);
+            }
+            return Ok(());
+        }
+        let rnti = ue_context.c_rnti;
+        let sdap = ue_context.sdap_entities.get_mut(&pdu_session_id)
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No SDAP entity for PDU session {} of UE {}", pdu_session_id, ue_id)))?;
+        let (drb_id, sdap_pdu) = sdap.process_downlink(qfi, rqi, data)?;
+        let drb = ue_context.drbs.get(&drb_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown DRB {} of UE {}", drb_id, ue_id)))?;
+        let (lcid, pdcp, rlc) = (drb.lcid, drb.pdcp.clone(), drb.rlc.clone());
+        ue_context.last_activity = Instant::now();
+        drop(contexts);
+
+        let pdcp_pdu = pdcp.lock().await.process_downlink(sdap_pdu).await?;
+        let rlc_pdu = rlc.lock().await.process_downlink(pdcp_pdu).await?;
+        let mac_interface = self.mac_interface.as_ref()
+            .ok_or_else(|| LayerError::ConfigurationError("No MAC interface".into()))?;
+        mac_interface.send_user_data(rnti, lcid, rlc_pdu).await
+    }
+
+    /// Handle an uplink RLC PDU received by MAC on the logical channel of a DRB
+    pub async fn handle_uplink_user_data(&mut self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        let mut contexts = self.ue_contexts.lock().await;
+        let ue_context = contexts.get_mut(&rnti.0)
+            .ok_or_else(|| LayerError::InvalidState(format!("No UE context for RNTI {}", rnti.0)))?;
+        let drb = ue_context.drbs.values()
+            .find(|drb| drb.lcid == lcid)
+            .ok_or_else(|| LayerError::InvalidState(format!("No DRB on LCID {} of RNTI {}", lcid, rnti.0)))?;
+        let ue_id = ue_context.ue_id;
+        let (drb_id, pdu_session_id, pdcp, rlc) = (drb.drb_id, drb.pdu_session_id, drb.pdcp.clone(), drb.rlc.clone());
+        ue_context.last_activity = Instant::now();
+        drop(contexts);
+
+        let pdcp_pdu = rlc.lock().await.process_uplink(data).await?;
+        let sdap_pdu = pdcp.lock().await.process_uplink(pdcp_pdu).await?;
+
+        let contexts = self.ue_contexts.lock().await;
+        let sdap = contexts.get(&rnti.0)
+            .and_then(|ctx| ctx.sdap_entities.get(&pdu_session_id))
+            .ok_or_else(|| LayerError::InvalidState(
+                format!("No SDAP entity for PDU session {} of UE {}", pdu_session_id, ue_id)))?;
+        let uplink = sdap.process_uplink(drb_id, sdap_pdu)?;
+        drop(contexts);
+
+        match uplink {
+            SdapUplink::Data { qfi, data } => {
+                self.send_to_gtpu(RrcGtpuMessage::UplinkData { ue_id, pdu_session_id, qfi, data }).await;
+            }
+            SdapUplink::EndMarker { qfi } => {
+                debug!("UE {} moved QoS flow {} off DRB {}", ue_id, qfi, drb_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass a message to the GTP-U endpoint
+    async fn send_to_gtpu(&self, message: RrcGtpuMessage) {
+        if let Some(gtpu_tx) = &self.gtpu_tx {
+            if let Err(e) = gtpu_tx.send(message).await {
+                error!("Failed to send message to GTP-U: {}", e);
+            }
+        } else {
+            warn!("No GTP-U channel configured, dropping uplink packet");
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Service Data Adaptation Protocol (SDAP) Layer Implementation
+//!
+//! Implements the 5G SDAP layer according to 3GPP TS 37.324. One entity per PDU
+//! session maps the QoS flows received from the GTP-U endpoint to the DRBs of
+//! the session, following the mapping rules configured by RRC, and adds or
+//! removes the SDAP header of each DRB.
+
+use crate::rrc::reconfiguration::SdapConfig;
+use crate::LayerError;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+
+/// Length of the SDAP header (TS 37.324 section 6.2.2)
+pub const SDAP_HEADER_LEN: usize = 1;
+
+const QFI_MASK: u8 = 0x3F;
+/// DL header: reflective QoS flow to DRB mapping indication
+const RDI: u8 = 0x80;
+/// DL header: reflective QoS indication
+const RQI: u8 = 0x40;
+/// UL header: data (1) or control (0) PDU
+const DC: u8 = 0x80;
+
+/// SDAP header presence on a DRB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SdapDrb {
+    header_dl: bool,
+    header_ul: bool,
+}
+
+/// Uplink SDAP PDU received on a DRB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdapUplink {
+    /// User packet of a QoS flow
+    Data { qfi: u8, data: Bytes },
+    /// End-marker control PDU: the UE sends no more packets of the QoS flow on
+    /// this DRB (section 6.2.3)
+    EndMarker { qfi: u8 },
+}
+
+/// SDAP entity of a PDU session
+#[derive(Debug)]
+pub struct SdapEntity {
+    /// PDU session served by the entity
+    pdu_session_id: u8,
+    /// DRBs of the PDU session
+    drbs: HashMap<u8, SdapDrb>,
+    /// Default DRB, carrying the QoS flows without a mapping rule
+    default_drb: Option<u8>,
+    /// Stored QoS flow to DRB mapping rules
+    mapping: HashMap<u8, u8>,
+    /// QoS flows whose next DL packet sets RDI so the UE updates its UL mapping
+    reflective_mapping: HashSet<u8>,
+}
+
+impl SdapEntity {
+    /// Create the SDAP entity of a PDU session
+    pub fn new(pdu_session_id: u8) -> Self {
+        Self {
+            pdu_session_id,
+            drbs: HashMap::new(),
+            default_drb: None,
+            mapping: HashMap::new(),
+            reflective_mapping: HashSet::new(),
+        }
+    }
+
+    /// PDU session served by the entity
+    pub fn pdu_session_id(&self) -> u8 {
+        self.pdu_session_id
+    }
+
+    /// Add or reconfigure a DRB and apply its mapping rules (section 5.3)
+    ///
+    /// A QoS flow mapped to the DRB is removed from any other DRB of the session.
+    /// When the DRB carries both SDAP headers, the UE is told of the new mapping
+    /// with RDI on the next DL packet of the flow.
+    pub fn configure_drb(&mut self, drb_id: u8, config: &SdapConfig) {
+        let drb = self.drbs.entry(drb_id).or_insert(SdapDrb { header_dl: false, header_ul: false });
+        drb.header_dl = config.sdap_header_dl;
+        drb.header_ul = config.sdap_header_ul;
+        let reflective = drb.header_dl && drb.header_ul;
+        if config.default_drb {
+            self.default_drb = Some(drb_id);
+        } else if self.default_drb == Some(drb_id) {
+            self.default_drb = None;
+        }
+
+        for qfi in &config.mapped_qos_flows_to_release {
+            if self.mapping.get(qfi) == Some(&drb_id) {
+                self.mapping.remove(qfi);
+                self.reflective_mapping.remove(qfi);
+            }
+        }
+        for qfi in &config.mapped_qos_flows_to_add {
+            let qfi = qfi & QFI_MASK;
+            if let Some(previous) = self.mapping.insert(qfi, drb_id).filter(|previous| *previous != drb_id) {
+                debug!("QoS flow {} of PDU session {} remapped from DRB {} to DRB {}",
+                       qfi, self.pdu_session_id, previous, drb_id);
+                if reflective {
+                    self.reflective_mapping.insert(qfi);
+                }
+            }
+        }
+        info!("SDAP entity of PDU session {}: DRB {} with QFIs {:?}, default DRB {:?}",
+              self.pdu_session_id, drb_id, self.flows_of(drb_id), self.default_drb);
+    }
+
+    /// Remove a released DRB and the mapping rules to it
+    pub fn release_drb(&mut self, drb_id: u8) {
+        self.drbs.remove(&drb_id);
+        self.mapping.retain(|_, drb| *drb != drb_id);
+        if self.default_drb == Some(drb_id) {
+            self.default_drb = None;
+        }
+    }
+
+    /// Check if the entity has no DRB left
+    pub fn is_empty(&self) -> bool {
+        self.drbs.is_empty()
+    }
+
+    /// QoS flows mapped to a DRB
+    pub fn flows_of(&self, drb_id: u8) -> Vec<u8> {
+        let mut flows: Vec<u8> = self.mapping.iter()
+            .filter(|(_, drb)| **drb == drb_id)
+            .map(|(qfi, _)| *qfi)
+            .collect();
+        flows.sort_unstable();
+        flows
+    }
+
+    /// DRB carrying a QoS flow: its mapping rule, or the default DRB (section 5.2.1)
+    pub fn drb_for_flow(&self, qfi: Option<u8>) -> Option<u8> {
+        qfi.and_then(|qfi| self.mapping.get(&qfi).copied()).or(self.default_drb)
+    }
+
+    /// Map a DL packet to its DRB and add the SDAP header if configured
+    ///
+    /// Returns the DRB and the SDAP PDU.
+    pub fn process_downlink(&mut self, qfi: Option<u8>, rqi: bool, data: Bytes) -> Result<(u8, Bytes), LayerError> {
+        let drb_id = self.drb_for_flow(qfi).ok_or_else(|| LayerError::InvalidState(
+            format!("No DRB for QoS flow {:?} of PDU session {}", qfi, self.pdu_session_id)))?;
+        let drb = self.drbs.get(&drb_id).ok_or(LayerError::InvalidState(format!("Unknown DRB {}", drb_id)))?;
+        if !drb.header_dl {
+            return Ok((drb_id, data));
+        }
+
+        let qfi = qfi.unwrap_or_default() & QFI_MASK;
+        let mut header = qfi;
+        if rqi {
+            header |= RQI;
+        }
+        if self.reflective_mapping.remove(&qfi) {
+            header |= RDI;
+        }
+        let mut pdu = BytesMut::with_capacity(SDAP_HEADER_LEN + data.len());
+        pdu.put_u8(header);
+        pdu.put_slice(&data);
+        Ok((drb_id, pdu.freeze()))
+    }
+
+    /// Remove the SDAP header of a UL PDU received on a DRB
+    ///
+    /// Without UL header the packet belongs to the lowest QoS flow mapped to the DRB.
+    pub fn process_uplink(&self, drb_id: u8, pdu: Bytes) -> Result<SdapUplink, LayerError> {
+        let drb = self.drbs.get(&drb_id).ok_or(LayerError::InvalidState(format!("Unknown DRB {}", drb_id)))?;
+        if !drb.header_ul {
+            let qfi = self.flows_of(drb_id).first().copied().ok_or_else(|| LayerError::InvalidState(
+                format!("No QoS flow mapped to DRB {}", drb_id)))?;
+            return Ok(SdapUplink::Data { qfi, data: pdu });
+        }
+
+        let header = *pdu.first().ok_or(LayerError::InvalidPdu)?;
+        let qfi = header & QFI_MASK;
+        if header & DC == 0 {
+            debug!("End-marker for QoS flow {} on DRB {}", qfi, drb_id);
+            return Ok(SdapUplink::EndMarker { qfi });
+        }
+        if self.mapping.get(&qfi).is_some_and(|mapped| *mapped != drb_id) {
+            debug!("QoS flow {} received on DRB {} instead of DRB {}", qfi, drb_id, self.mapping[&qfi]);
+        }
+        Ok(SdapUplink::Data { qfi, data: pdu.slice(SDAP_HEADER_LEN..) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(default_drb: bool, header: bool, add: Vec<u8>, release: Vec<u8>) -> SdapConfig {
+        SdapConfig {
+            pdu_session_id: 1,
+            default_drb,
+            sdap_header_dl: header,
+            sdap_header_ul: header,
+            mapped_qos_flows_to_add: add,
+            mapped_qos_flows_to_release: release,
+        }
+    }
+
+    #[test]
+    fn test_qos_flow_mapping_and_headers() {
+        let packet = Bytes::from_static(&[0x45, 0x00]);
+        let mut sdap = SdapEntity::new(1);
+        sdap.configure_drb(1, &config(true, true, vec![1], vec![]));
+        sdap.configure_drb(2, &config(false, false, vec![5], vec![]));
+
+        // Mapped flow, unknown flow to the default DRB, RQI in the header
+        assert_eq!(sdap.process_downlink(Some(5), false, packet.clone()).unwrap(), (2, packet.clone()));
+        let (drb_id, pdu) = sdap.process_downlink(Some(9), true, packet.clone()).unwrap();
+        assert_eq!((drb_id, pdu[0]), (1, RQI | 9));
+        assert_eq!(&pdu[1..], &packet[..]);
+
+        // Flow 5 moves to the default DRB: the next DL packet carries RDI once
+        sdap.configure_drb(1, &config(true, true, vec![5], vec![]));
+        assert_eq!(sdap.flows_of(1), vec![1, 5]);
+        assert!(sdap.flows_of(2).is_empty());
+        assert_eq!(sdap.process_downlink(Some(5), false, packet.clone()).unwrap().1[0], RDI | 5);
+        assert_eq!(sdap.process_downlink(Some(5), false, packet.clone()).unwrap().1[0], 5);
+
+        // UL data and end-marker with header, flow of the DRB without header
+        let mut ul = vec![DC | 5];
+        ul.extend_from_slice(&packet);
+        assert_eq!(sdap.process_uplink(1, Bytes::from(ul)).unwrap(), SdapUplink::Data { qfi: 5, data: packet.clone() });
+        assert_eq!(sdap.process_uplink(1, Bytes::from_static(&[5])).unwrap(), SdapUplink::EndMarker { qfi: 5 });
+        sdap.configure_drb(2, &config(false, false, vec![7], vec![]));
+        assert_eq!(sdap.process_uplink(2, packet.clone()).unwrap(), SdapUplink::Data { qfi: 7, data: packet.clone() });
+
+        // Released flows and DRBs fall back to the default DRB
+        sdap.configure_drb(2, &config(false, false, vec![], vec![7]));
+        assert_eq!(sdap.drb_for_flow(Some(7)), Some(1));
+        sdap.release_drb(1);
+        assert_eq!(sdap.drb_for_flow(Some(1)), None);
+        assert!(sdap.process_downlink(Some(1), false, packet).is_err());
+        sdap.release_drb(2);
+        assert!(sdap.is_empty());
+    }
+
+    #[test]
+    fn test_sdap_errors() {
+        let packet = Bytes::from_static(&[0x45, 0x00]);
+        let mut sdap = SdapEntity::new(1);
+
+        // No DRB at all, then a DRB without flow and without UL header
+        assert!(matches!(sdap.process_downlink(None, false, packet.clone()), Err(LayerError::InvalidState(_))));
+        assert!(matches!(sdap.process_uplink(1, packet.clone()), Err(LayerError::InvalidState(_))));
+        sdap.configure_drb(2, &config(false, false, vec![], vec![]));
+        assert!(matches!(sdap.process_uplink(2, packet.clone()), Err(LayerError::InvalidState(_))));
+        assert!(matches!(sdap.process_downlink(Some(1), false, packet.clone()), Err(LayerError::InvalidState(_))));
+
+        // Empty PDU on a DRB with UL header
+        sdap.configure_drb(1, &config(true, true, vec![1], vec![]));
+        assert!(matches!(sdap.process_uplink(1, Bytes::new()), Err(LayerError::InvalidPdu)));
+
+        // A flow received on another DRB than its mapping is still delivered
+        let mut ul = vec![DC | 9];
+        ul.extend_from_slice(&packet);
+        assert_eq!(sdap.process_uplink(1, Bytes::from(ul)).unwrap(), SdapUplink::Data { qfi: 9, data: packet.clone() });
+
+        // Releasing a flow mapped to another DRB keeps its rule, QFIs are 6 bits
+        sdap.configure_drb(2, &config(false, false, vec![0x45], vec![1]));
+        assert_eq!(sdap.flows_of(1), vec![1]);
+        assert_eq!(sdap.flows_of(2), vec![5]);
+
+        // Moving a flow to a DRB without both headers sets no RDI
+        sdap.configure_drb(2, &config(false, false, vec![1], vec![]));
+        assert_eq!(sdap.process_downlink(Some(1), false, packet.clone()).unwrap(), (2, packet.clone()));
+
+        // The default DRB is dropped when reconfigured as non-default
+        sdap.configure_drb(1, &config(false, true, vec![], vec![]));
+        assert_eq!(sdap.drb_for_flow(Some(9)), None);
+        assert_eq!(sdap.drb_for_flow(Some(1)), Some(2));
+    }
+}
@@ -0,0 +1,288 @@
+//! Helpers shared by the tests of the protocol layers
+
+use crate::e1ap::transport::{self as e1_transport, E1Listener, E1Receiver, E1Sender};
+use crate::e1ap::{E1apCuCp, E1apCuCpConfig, E1apCuUp, E1apCuUpConfig};
+use crate::e2ap::transport::{self as e2_transport, E2Listener, E2Receiver, E2Sender};
+use crate::e2ap::{E2Agent, E2AgentConfig, KpmSnapshot, KpmSource};
+use crate::f1ap::pdu::{FddInfo, ServedCellInformation};
+use crate::f1ap::transport::{connect, F1Listener, F1Receiver, F1Sender};
+use crate::f1ap::F1apDuConfig;
+use crate::gtpu::{GtpuConfig, GtpuLayer};
+use crate::mac::UeSchedulingCapabilities;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{
+    AperCodec, BroadcastPlmnItem, GlobalGnbId, GtpTunnel, NrCgi, PduSessionType, SupportedTaItem,
+    UeSecurityCapabilities,
+};
+use crate::ngap::transport::NgTransportKind;
+use crate::rrc::{DrbBearerConfig, PagingRequest, RarGrant, RrcMacInterface, RrcMessageType};
+use crate::xnap::pdu::{PduSessionToBeSetupItem, ServedCellNr, UeContextInfoHoRequest};
+use crate::xnap::transport::{self as xn_transport, XnListener, XnReceiver, XnSender};
+use crate::xnap::{XnapConfig, XnapNgapMessage, XnapNode};
+use crate::LayerError;
+use async_trait::async_trait;
+use bytes::Bytes;
+use common::types::{
+    AggregateMaximumBitRate, AllocationRetentionPriority, FiveQi, QosCharacteristics, QosFlowDescriptor, Rnti, SNssai,
+};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::timeout;
+
+/// Poll a condition every 10 ms until it holds, failing the test after 2 s
+pub(crate) async fn wait_until<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    timeout(Duration::from_secs(2), async {
+        while !condition().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+}
+
+/// Decode an IE value on its own
+pub(crate) fn decode_ie<T: AperCodec>(data: &[u8]) -> Result<T, LayerError> {
+    T::decode(&mut AperDecoder::new(data))
+}
+
+/// Encode an IE value on its own
+pub(crate) fn encode_ie<T: AperCodec>(value: &T) -> Result<Bytes, LayerError> {
+    let mut enc = AperEncoder::new();
+    value.encode(&mut enc)?;
+    Ok(enc.into_bytes())
+}
+
+/// MAC stub of a gNB-DU recording what F1AP asks for
+#[derive(Default)]
+pub(crate) struct RecordingMac {
+    pub(crate) sent: std::sync::Mutex<Vec<(Rnti, RrcMessageType, Bytes)>>,
+    pub(crate) released: std::sync::Mutex<Vec<Rnti>>,
+    pub(crate) paging: std::sync::Mutex<Vec<PagingRequest>>,
+    pub(crate) user_data: std::sync::Mutex<Vec<(Rnti, u8, Bytes)>>,
+    pub(crate) drbs: std::sync::Mutex<Vec<(Rnti, Vec<DrbBearerConfig>, Vec<u8>)>>,
+    pub(crate) capabilities: std::sync::Mutex<Vec<(Rnti, UeSchedulingCapabilities)>>,
+    /// Refuse DRB configurations, as a MAC out of logical channels would
+    pub(crate) reject_drbs: AtomicBool,
+}
+
+#[async_trait]
+impl RrcMacInterface for RecordingMac {
+    async fn send_rrc_message(&self, rnti: Rnti, msg_type: RrcMessageType, data: Bytes) -> Result<(), LayerError> {
+        self.sent.lock().unwrap().push((rnti, msg_type, data));
+        Ok(())
+    }
+
+    async fn allocate_c_rnti(&self) -> Result<Rnti, LayerError> {
+        Ok(Rnti::new(0x4601))
+    }
+
+    async fn configure_ue_capabilities(&self, rnti: Rnti, capabilities: UeSchedulingCapabilities) -> Result<(), LayerError> {
+        self.capabilities.lock().unwrap().push((rnti, capabilities));
+        Ok(())
+    }
+
+    async fn schedule_rar(&self, _tc_rnti: Rnti, _grant: RarGrant) -> Result<(), LayerError> {
+        Ok(())
+    }
+
+    async fn release_ue(&self, rnti: Rnti) -> Result<(), LayerError> {
+        self.released.lock().unwrap().push(rnti);
+        Ok(())
+    }
+
+    async fn schedule_paging(&self, request: PagingRequest) -> Result<(), LayerError> {
+        self.paging.lock().unwrap().push(request);
+        Ok(())
+    }
+
+    async fn send_user_data(&self, rnti: Rnti, lcid: u8, data: Bytes) -> Result<(), LayerError> {
+        self.user_data.lock().unwrap().push((rnti, lcid, data));
+        Ok(())
+    }
+
+    async fn configure_drbs(&self, rnti: Rnti, to_setup: Vec<DrbBearerConfig>, to_release: Vec<u8>) -> Result<(), LayerError> {
+        if self.reject_drbs.load(Ordering::Relaxed) {
+            return Err(LayerError::ResourceUnavailable);
+        }
+        self.drbs.lock().unwrap().push((rnti, to_setup, to_release));
+        Ok(())
+    }
+}
+
+/// Cell served by the gNB-DU of the F1AP tests
+pub(crate) fn served_cell() -> ServedCellInformation {
+    ServedCellInformation {
+        nr_cgi: NrCgi { plmn_id: [0x00, 0xF1, 0x10], nr_cell_identity: 0x0000_0401 },
+        nr_pci: 1,
+        tac: Some(7),
+        served_plmns: vec![[0x00, 0xF1, 0x10]],
+        fdd_info: FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 15, nrb: 52 },
+        measurement_timing_configuration: Bytes::from_static(&[0x10]),
+    }
+}
+
+/// gNB-DU configuration towards a gNB-CU, with an ephemeral F1-U port
+pub(crate) fn f1ap_du_config(cu_address: SocketAddr) -> F1apDuConfig {
+    F1apDuConfig {
+        cu_address,
+        local_address: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        f1u_address: "127.0.0.1:0".parse().unwrap(),
+        gnb_du_id: 1,
+        gnb_du_name: "du".into(),
+        served_cell: served_cell(),
+        reconnect_interval: Duration::from_millis(100),
+    }
+}
+
+/// F1-C association over loopback: the gNB-CU side, then the gNB-DU side
+pub(crate) async fn f1_association() -> ((F1Sender, F1Receiver), (F1Sender, F1Receiver)) {
+    let listener = F1Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let (du, cu) = tokio::join!(
+        connect(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap(), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    (cu.unwrap(), du.unwrap())
+}
+
+/// E1 association over loopback: the gNB-CU-CP side, then the gNB-CU-UP side
+pub(crate) async fn e1_association() -> ((E1Sender, E1Receiver), (E1Sender, E1Receiver)) {
+    let listener = E1Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let (cu_up, cu_cp) = tokio::join!(
+        e1_transport::connect(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap(), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    (cu_cp.unwrap(), cu_up.unwrap())
+}
+
+/// gNB-CU-CP with its E1 listener on an ephemeral loopback port
+pub(crate) async fn e1ap_cu_cp() -> E1apCuCp {
+    E1apCuCp::new(E1apCuCpConfig {
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        gnb_cu_cp_name: "cu-cp".into(),
+        ng_u_address: IpAddr::from([127, 0, 0, 1]),
+    }).await.unwrap()
+}
+
+/// gNB-CU-UP 2 towards a gNB-CU-CP, with ephemeral F1-U and N3 ports
+pub(crate) async fn e1ap_cu_up(cu_cp_address: SocketAddr) -> E1apCuUp {
+    let gtpu = GtpuLayer::new(GtpuConfig {
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        external_address: IpAddr::from([127, 0, 0, 1]),
+    });
+    E1apCuUp::new(E1apCuUpConfig {
+        cu_cp_address,
+        local_address: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        gnb_cu_up_id: 2,
+        gnb_cu_up_name: "cu-up".into(),
+        f1u_address: "127.0.0.1:0".parse().unwrap(),
+        reconnect_interval: Duration::from_millis(100),
+    }, Arc::new(RwLock::new(gtpu))).await.unwrap()
+}
+
+/// PLMN of the gNBs of the XnAP tests
+pub(crate) const XN_PLMN: [u8; 3] = [0x02, 0xF8, 0x39];
+
+/// XnAP node of gNB `gnb_id` serving `pci`, with the receiving end of its
+/// channel towards NGAP
+pub(crate) async fn xnap_node(gnb_id: u32, pci: u16, peers: Vec<SocketAddr>) -> (Arc<XnapNode>, mpsc::Receiver<XnapNgapMessage>) {
+    let (ngap_tx, ngap_rx) = mpsc::channel(8);
+    let node = XnapNode::new(XnapConfig {
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        global_gnb_id: GlobalGnbId { plmn_id: XN_PLMN, gnb_id, gnb_id_bits: 22 },
+        supported_tas: vec![SupportedTaItem {
+            tac: 7,
+            broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: XN_PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+        }],
+        served_cell: ServedCellNr {
+            pci,
+            nr_cgi: NrCgi { plmn_id: XN_PLMN, nr_cell_identity: (gnb_id as u64) << 14 | pci as u64 },
+            tac: 7,
+            fdd_info: FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 15, nrb: 52 },
+        },
+        peers,
+        reconnect_interval: Duration::from_millis(100),
+    }, ngap_tx).await.unwrap();
+    (Arc::new(node), ngap_rx)
+}
+
+/// UE context of a handover with one PDU session
+pub(crate) fn xn_ue_context() -> UeContextInfoHoRequest {
+    UeContextInfoHoRequest {
+        amf_ue_ngap_id: 1,
+        source_cp_address: IpAddr::from([0, 0, 0, 0]),
+        security_capabilities: UeSecurityCapabilities::default(),
+        security_key: [0x5A; 32],
+        next_hop_chaining_count: 0,
+        ue_ambr: AggregateMaximumBitRate { dl: 100_000_000, ul: 50_000_000 },
+        sessions: vec![PduSessionToBeSetupItem {
+            pdu_session_id: 1,
+            s_nssai: SNssai { sst: 1, sd: None },
+            session_ambr: None,
+            ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([10, 0, 0, 1]), teid: 0x101 },
+            pdu_session_type: PduSessionType::Ipv4,
+            qos_flows: vec![QosFlowDescriptor {
+                qfi: 1,
+                characteristics: QosCharacteristics::NonDynamic { five_qi: FiveQi(9), priority_level: None },
+                arp: AllocationRetentionPriority { priority_level: 8, may_trigger_pre_emption: false, pre_emptable: false },
+                gbr: None,
+            }],
+        }],
+        rrc_context: Bytes::from_static(&[0x00, 0x01]),
+    }
+}
+
+/// Xn association over loopback: the accepting side, then the connecting side
+pub(crate) async fn xn_association() -> ((XnSender, XnReceiver), (XnSender, XnReceiver)) {
+    let listener = XnListener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let (connecting, accepted) = tokio::join!(
+        xn_transport::connect(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap(), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    (accepted.unwrap(), connecting.unwrap())
+}
+
+/// Cell of the E2 node of the E2AP tests
+pub(crate) const E2_CELL: NrCgi = NrCgi { plmn_id: XN_PLMN, nr_cell_identity: 0x19B001 };
+
+/// KPM source of a cell without traffic
+pub(crate) struct IdleKpmSource;
+
+#[async_trait]
+impl KpmSource for IdleKpmSource {
+    async fn snapshot(&self) -> KpmSnapshot {
+        KpmSnapshot { prbs_per_slot: 52, ..Default::default() }
+    }
+}
+
+/// E2 agent of gNB 0x19B towards a near-RT RIC, measuring an idle cell
+pub(crate) fn e2_agent(ric_address: SocketAddr) -> E2Agent {
+    E2Agent::new(E2AgentConfig {
+        ric_address,
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        global_gnb_id: GlobalGnbId { plmn_id: XN_PLMN, gnb_id: 0x19B, gnb_id_bits: 22 },
+        nr_cgi: E2_CELL,
+        amf_name: "amf".to_string(),
+        reconnect_interval: Duration::from_millis(100),
+    }, Arc::new(IdleKpmSource))
+}
+
+/// E2 association over loopback: the near-RT RIC side, then the E2 node side
+pub(crate) async fn e2_association() -> ((E2Sender, E2Receiver), (E2Sender, E2Receiver)) {
+    let listener = E2Listener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let (node, ric) = tokio::join!(
+        e2_transport::connect(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap(), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    (ric.unwrap(), node.unwrap())
+}
@@ -0,0 +1,368 @@
+//! Xn handover (3GPP TS 38.423 sections 8.2.1, 8.2.3 and 8.2.7)
+//!
+//! Handover Preparation sends the UE context to the neighbour serving the
+//! target cell and returns the RRCReconfiguration it prepared. SN Status
+//! Transfer then carries the PDCP COUNTs of the DRBs, and UE Context Release
+//! tells the source that the UE arrived so it can let go of its context.
+//!
+//! The source knows the UE by its RAN UE NGAP ID; the target by a handover ID
+//! until NGAP admitted it, then by the RAN UE NGAP ID it allocated. Both serve
+//! as NG-RAN node UE XnAP IDs. The UE History Information is not sent.
+
+use super::node::{find_target, UeAssociation, XnapNode};
+use super::pdu::{
+    self, Cause, NgRanNodeUeXnapId, PduSessionAdmittedItem, PduSessionsNotAdmitted, TargetCgi, UeContextInfoHoRequest,
+    XnapPdu,
+};
+use super::transport::{XnSender, UE_STREAM};
+use super::{XnapNgapMessage, XnapProcedureCode};
+use crate::ngap::pdu::{Criticality, Guami};
+use crate::rrc::DrbSnStatus;
+use crate::LayerError;
+use bytes::Bytes;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+impl XnapNode {
+    /// Send Handover Request to the neighbour serving the target cell
+    pub(super) async fn start_handover(
+        &self,
+        ue_id: u32,
+        target_pci: u16,
+        guami: Guami,
+        mut context: UeContextInfoHoRequest,
+    ) -> Result<(), LayerError> {
+        let target = {
+            let neighbours = self.neighbours.read().await;
+            find_target(&neighbours, target_pci)
+                .map(|(neighbour, cell)| (Arc::clone(&neighbour.sender), neighbour.global_gnb_id, cell))
+        };
+        let Some((sender, gnb_id, cell)) = target else {
+            warn!("No Xn neighbour serves PCI {}, cannot hand over UE {}", target_pci, ue_id);
+            return self.send_to_ngap(XnapNgapMessage::HandoverPreparationFailure {
+                ue_id,
+                cause: Cause::RADIO_NETWORK_UNSPECIFIED,
+            }).await;
+        };
+
+        context.source_cp_address = self.config.bind_address.ip();
+        let request = XnapPdu::initiating(XnapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(ue_id))?
+            .with_ie(pdu::ID_CAUSE, Criticality::Reject, &Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASONS)?
+            .with_ie(pdu::ID_TARGET_CELL_GLOBAL_ID, Criticality::Reject, &TargetCgi(cell.nr_cgi))?
+            .with_ie(pdu::ID_GUAMI, Criticality::Reject, &guami)?
+            .with_ie(pdu::ID_UE_CONTEXT_INFO_HO_REQUEST, Criticality::Reject, &context)?;
+
+        info!("Handover of UE {} to PCI {} of gNB {:#x} at {}", ue_id, target_pci, gnb_id.gnb_id, sender.peer());
+        self.ues.lock().await.insert(ue_id, UeAssociation { sender: Arc::clone(&sender), peer_ue_id: None });
+        sender.send(UE_STREAM, &request).await
+    }
+
+    /// Handle Handover Request Acknowledge in the source
+    pub(super) async fn handle_handover_request_acknowledge(&self, pdu: &XnapPdu) -> Result<(), LayerError> {
+        let ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        let target_ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_TARGET_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        let admitted = pdu.ie::<Vec<PduSessionAdmittedItem>>(pdu::ID_PDU_SESSION_RESOURCES_ADMITTED_LIST)?;
+        let not_admitted = pdu.optional_ie::<PduSessionsNotAdmitted>(pdu::ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST)?;
+        let rrc_container = pdu.ie::<Bytes>(pdu::ID_TARGET2SOURCE_NG_RAN_NODE_TRANSP_CONTAINER)?;
+
+        match self.ues.lock().await.get_mut(&ue_id) {
+            Some(ue) => ue.peer_ue_id = Some(target_ue_id),
+            None => return Err(LayerError::InvalidState(format!("Handover Request Acknowledge for unknown UE {}", ue_id))),
+        }
+        info!("Target admitted UE {} as {}, PDU sessions not admitted: {:?}",
+              ue_id, target_ue_id, not_admitted.map(|sessions| sessions.0).unwrap_or_default());
+        self.send_to_ngap(XnapNgapMessage::HandoverRequestAcknowledge { ue_id, admitted, rrc_container }).await
+    }
+
+    /// Handle Handover Preparation Failure in the source
+    pub(super) async fn handle_handover_preparation_failure(&self, pdu: &XnapPdu) -> Result<(), LayerError> {
+        let ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+        if self.ues.lock().await.remove(&ue_id).is_none() {
+            return Err(LayerError::InvalidState(format!("Handover Preparation Failure for unknown UE {}", ue_id)));
+        }
+        warn!("Target rejected the handover of UE {}: {:?}", ue_id, cause);
+        self.send_to_ngap(XnapNgapMessage::HandoverPreparationFailure { ue_id, cause }).await
+    }
+
+    /// Send SN Status Transfer from the source
+    pub(super) async fn send_sn_status_transfer(&self, ue_id: u32, drbs: Vec<DrbSnStatus>) -> Result<(), LayerError> {
+        let (sender, target_ue_id) = self.ues.lock().await.get(&ue_id)
+            .and_then(|ue| ue.peer_ue_id.map(|peer_ue_id| (Arc::clone(&ue.sender), peer_ue_id)))
+            .ok_or_else(|| LayerError::InvalidState(format!("UE {} is not handed over on Xn", ue_id)))?;
+        if drbs.is_empty() {
+            return Ok(());
+        }
+        let transfer = XnapPdu::initiating(XnapProcedureCode::SnStatusTransfer)
+            .with_ie(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(ue_id))?
+            .with_ie(pdu::ID_NEW_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(target_ue_id))?
+            .with_ie(pdu::ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST, Criticality::Ignore, &drbs)?;
+        sender.send(UE_STREAM, &transfer).await
+    }
+
+    /// Handle UE Context Release in the source
+    pub(super) async fn handle_ue_context_release(&self, pdu: &XnapPdu) -> Result<(), LayerError> {
+        let ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        if self.ues.lock().await.remove(&ue_id).is_none() {
+            return Err(LayerError::InvalidState(format!("UE Context Release for unknown UE {}", ue_id)));
+        }
+        info!("UE {} completed the handover", ue_id);
+        self.send_to_ngap(XnapNgapMessage::UeContextRelease { ue_id }).await
+    }
+
+    /// Handle Handover Request in the target
+    pub(super) async fn handle_handover_request(&self, pdu: &XnapPdu, sender: &Arc<XnSender>) -> Result<(), LayerError> {
+        let source_ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        let target_cell = pdu.ie::<TargetCgi>(pdu::ID_TARGET_CELL_GLOBAL_ID)?.0;
+        let guami = pdu.ie::<Guami>(pdu::ID_GUAMI)?;
+        let context = pdu.ie::<UeContextInfoHoRequest>(pdu::ID_UE_CONTEXT_INFO_HO_REQUEST)?;
+
+        if target_cell != self.config.served_cell.nr_cgi {
+            warn!("Handover Request from {} for cell {:#x} not served here", sender.peer(), target_cell.nr_cell_identity);
+            return send_preparation_failure(sender, source_ue_id, Cause::RADIO_NETWORK_UNSPECIFIED).await;
+        }
+
+        let handover_id = self.next_handover_id.fetch_add(1, Ordering::Relaxed);
+        info!("Handover Request for UE {} of {} with {} PDU sessions, handover {}",
+              source_ue_id, sender.peer(), context.sessions.len(), handover_id);
+        self.admissions.lock().await
+            .insert(handover_id, UeAssociation { sender: Arc::clone(sender), peer_ue_id: Some(source_ue_id) });
+        self.send_to_ngap(XnapNgapMessage::HandoverRequest { handover_id, guami, context }).await
+    }
+
+    /// Send Handover Request Acknowledge from the target
+    pub(super) async fn acknowledge_handover(
+        &self,
+        handover_id: u32,
+        ue_id: u32,
+        admitted: Vec<PduSessionAdmittedItem>,
+        not_admitted: Vec<(u8, Cause)>,
+        rrc_container: Bytes,
+    ) -> Result<(), LayerError> {
+        let admission = self.admissions.lock().await.remove(&handover_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown handover {}", handover_id)))?;
+        let source_ue_id = admission.peer_ue_id.unwrap_or_default();
+        let sender = Arc::clone(&admission.sender);
+
+        let mut acknowledge = XnapPdu::successful(XnapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(source_ue_id))?
+            .with_ie(pdu::ID_TARGET_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(ue_id))?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCES_ADMITTED_LIST, Criticality::Ignore, &admitted)?;
+        if !not_admitted.is_empty() {
+            acknowledge.add_ie(pdu::ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST, Criticality::Ignore,
+                               &PduSessionsNotAdmitted(not_admitted))?;
+        }
+        acknowledge.add_ie(pdu::ID_TARGET2SOURCE_NG_RAN_NODE_TRANSP_CONTAINER, Criticality::Ignore, &rrc_container)?;
+
+        self.ues.lock().await.insert(ue_id, admission);
+        sender.send(UE_STREAM, &acknowledge).await
+    }
+
+    /// Send Handover Preparation Failure from the target
+    pub(super) async fn reject_handover(&self, handover_id: u32, cause: Cause) -> Result<(), LayerError> {
+        let admission = self.admissions.lock().await.remove(&handover_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("Unknown handover {}", handover_id)))?;
+        warn!("Rejecting handover {} of UE {}: {:?}", handover_id, admission.peer_ue_id.unwrap_or_default(), cause);
+        send_preparation_failure(&admission.sender, admission.peer_ue_id.unwrap_or_default(), cause).await
+    }
+
+    /// Handle SN Status Transfer in the target
+    pub(super) async fn handle_sn_status_transfer(&self, pdu: &XnapPdu) -> Result<(), LayerError> {
+        let ue_id = pdu.ie::<NgRanNodeUeXnapId>(pdu::ID_NEW_NG_RAN_NODE_UE_XNAP_ID)?.0;
+        let drbs = pdu.ie::<Vec<DrbSnStatus>>(pdu::ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST)?;
+        if !self.ues.lock().await.contains_key(&ue_id) {
+            return Err(LayerError::InvalidState(format!("SN Status Transfer for unknown UE {}", ue_id)));
+        }
+        self.send_to_ngap(XnapNgapMessage::SnStatusTransfer { ue_id, drbs }).await
+    }
+
+    /// Send UE Context Release from the target once the UE arrived
+    pub(super) async fn release_source(&self, ue_id: u32) -> Result<(), LayerError> {
+        let ue = self.ues.lock().await.remove(&ue_id)
+            .ok_or_else(|| LayerError::InvalidState(format!("UE {} is not handed over on Xn", ue_id)))?;
+        let release = XnapPdu::initiating(XnapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject,
+                     &NgRanNodeUeXnapId(ue.peer_ue_id.unwrap_or_default()))?
+            .with_ie(pdu::ID_NEW_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(ue_id))?;
+        ue.sender.send(UE_STREAM, &release).await
+    }
+}
+
+/// Send Handover Preparation Failure for a UE of the source
+async fn send_preparation_failure(sender: &XnSender, source_ue_id: u32, cause: Cause) -> Result<(), LayerError> {
+    let failure = XnapPdu::unsuccessful(XnapProcedureCode::HandoverPreparation)
+        .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(source_ue_id))?
+        .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+    sender.send(UE_STREAM, &failure).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{xn_association, xn_ue_context, xnap_node, XN_PLMN};
+    use crate::xnap::node::Neighbour;
+    use crate::xnap::pdu::XnapPduType;
+    use crate::xnap::transport::{XnReceiver, XnTransportEvent};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn admitted() -> Vec<PduSessionAdmittedItem> {
+        vec![PduSessionAdmittedItem { pdu_session_id: 1, qos_flows: vec![1], forwarding_tunnel: None }]
+    }
+
+    async fn recv_pdu(receiver: &mut XnReceiver) -> XnapPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            XnTransportEvent::Data { payload, .. } => XnapPdu::decode(&payload).unwrap(),
+            XnTransportEvent::AssociationLost(reason) => panic!("Xn association lost: {}", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_handover_failures() {
+        let (source, mut ngap_rx) = xnap_node(0x19B, 1, Vec::new()).await;
+        let (target, _target_ngap_rx) = xnap_node(0x19C, 2, Vec::new()).await;
+        let ((sender, _rx), (_target_tx, mut target_rx)) = xn_association().await;
+        let sender = Arc::new(sender);
+        let guami = Guami { plmn_id: XN_PLMN, amf_region_id: 1, amf_set_id: 1, amf_pointer: 1 };
+
+        // No neighbour serves the target cell
+        source.start_handover(1000, 2, guami, xn_ue_context()).await.unwrap();
+        assert_eq!(ngap_rx.recv().await.unwrap(), XnapNgapMessage::HandoverPreparationFailure {
+            ue_id: 1000,
+            cause: Cause::RADIO_NETWORK_UNSPECIFIED,
+        });
+        assert!(source.ues.lock().await.is_empty());
+
+        // Outcomes and transfers for UEs not in handover
+        let mut acknowledge = XnapPdu::successful(XnapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(pdu::ID_TARGET_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(2000)).unwrap()
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCES_ADMITTED_LIST, Criticality::Ignore, &admitted()).unwrap();
+        assert!(matches!(source.handle_handover_request_acknowledge(&acknowledge).await,
+                         Err(LayerError::ProcessingError(_))));
+        acknowledge.add_ie(pdu::ID_TARGET2SOURCE_NG_RAN_NODE_TRANSP_CONTAINER, Criticality::Ignore,
+                           &Bytes::from_static(&[0x08])).unwrap();
+        assert!(matches!(source.handle_handover_request_acknowledge(&acknowledge).await,
+                         Err(LayerError::InvalidState(_))));
+        let failure = XnapPdu::unsuccessful(XnapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL).unwrap();
+        assert!(matches!(source.handle_handover_preparation_failure(&failure).await, Err(LayerError::InvalidState(_))));
+        let drbs = vec![DrbSnStatus { drb_id: 1, ul_count: 5, dl_count: 12 }];
+        assert!(matches!(source.send_sn_status_transfer(1000, drbs.clone()).await, Err(LayerError::InvalidState(_))));
+        let release = XnapPdu::initiating(XnapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(1000)).unwrap();
+        assert!(matches!(source.handle_ue_context_release(&release).await, Err(LayerError::InvalidState(_))));
+
+        // The target rejects the handover
+        source.add_neighbour(Neighbour {
+            global_gnb_id: target.config.global_gnb_id,
+            served_cells: vec![target.config.served_cell],
+            sender: Arc::clone(&sender),
+        }).await;
+        source.start_handover(1000, 2, guami, xn_ue_context()).await.unwrap();
+        assert_eq!(recv_pdu(&mut target_rx).await.procedure(), Some(XnapProcedureCode::HandoverPreparation));
+        // SN Status Transfer needs the target's UE ID
+        assert!(matches!(source.send_sn_status_transfer(1000, drbs.clone()).await, Err(LayerError::InvalidState(_))));
+        let mut incomplete = failure.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_CAUSE);
+        assert!(matches!(source.handle_handover_preparation_failure(&incomplete).await,
+                         Err(LayerError::ProcessingError(_))));
+        source.handle_handover_preparation_failure(&failure).await.unwrap();
+        assert_eq!(ngap_rx.recv().await.unwrap(), XnapNgapMessage::HandoverPreparationFailure {
+            ue_id: 1000,
+            cause: Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL,
+        });
+        assert!(source.ues.lock().await.is_empty());
+
+        // Admitted by the target: an empty SN Status Transfer is not sent
+        source.start_handover(1000, 2, guami, xn_ue_context()).await.unwrap();
+        recv_pdu(&mut target_rx).await;
+        source.handle_handover_request_acknowledge(&acknowledge).await.unwrap();
+        assert!(matches!(ngap_rx.recv().await.unwrap(), XnapNgapMessage::HandoverRequestAcknowledge { ue_id: 1000, .. }));
+        source.send_sn_status_transfer(1000, Vec::new()).await.unwrap();
+        source.send_sn_status_transfer(1000, drbs).await.unwrap();
+        assert_eq!(recv_pdu(&mut target_rx).await.procedure(), Some(XnapProcedureCode::SnStatusTransfer));
+
+        // NGAP is gone
+        drop(ngap_rx);
+        assert!(matches!(source.handle_ue_context_release(&release).await, Err(LayerError::ProcessingError(_))));
+        assert!(source.ues.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_target_handover_failures() {
+        let (target, mut ngap_rx) = xnap_node(0x19C, 2, Vec::new()).await;
+        let ((sender, _rx), (_source_tx, mut source_rx)) = xn_association().await;
+        let sender = Arc::new(sender);
+        let guami = Guami { plmn_id: XN_PLMN, amf_region_id: 1, amf_set_id: 1, amf_pointer: 1 };
+        let request = XnapPdu::initiating(XnapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(pdu::ID_CAUSE, Criticality::Reject, &Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASONS).unwrap()
+            .with_ie(pdu::ID_TARGET_CELL_GLOBAL_ID, Criticality::Reject, &TargetCgi(target.config.served_cell.nr_cgi)).unwrap()
+            .with_ie(pdu::ID_GUAMI, Criticality::Reject, &guami).unwrap()
+            .with_ie(pdu::ID_UE_CONTEXT_INFO_HO_REQUEST, Criticality::Reject, &xn_ue_context()).unwrap();
+
+        // Request without GUAMI, request for a cell served elsewhere
+        let mut incomplete = request.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_GUAMI);
+        assert!(matches!(target.handle_handover_request(&incomplete, &sender).await, Err(LayerError::ProcessingError(_))));
+        let mut other_cell = request.clone();
+        other_cell.ies.retain(|ie| ie.id != pdu::ID_TARGET_CELL_GLOBAL_ID);
+        let mut nr_cgi = target.config.served_cell.nr_cgi;
+        nr_cgi.nr_cell_identity += 1;
+        other_cell.add_ie(pdu::ID_TARGET_CELL_GLOBAL_ID, Criticality::Reject, &TargetCgi(nr_cgi)).unwrap();
+        target.handle_handover_request(&other_cell, &sender).await.unwrap();
+        let failure = recv_pdu(&mut source_rx).await;
+        assert_eq!(failure.pdu_type, XnapPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<NgRanNodeUeXnapId>(pdu::ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID).unwrap(), NgRanNodeUeXnapId(1000));
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::RADIO_NETWORK_UNSPECIFIED);
+        assert!(target.admissions.lock().await.is_empty());
+
+        // NGAP answers for unknown handovers
+        let result = target.acknowledge_handover(9, 2000, Vec::new(), Vec::new(), Bytes::new()).await;
+        assert!(matches!(result, Err(LayerError::InvalidState(_))));
+        assert!(matches!(target.reject_handover(9, Cause::RADIO_NETWORK_UNSPECIFIED).await, Err(LayerError::InvalidState(_))));
+
+        // NGAP cannot admit the UE
+        target.handle_handover_request(&request, &sender).await.unwrap();
+        let XnapNgapMessage::HandoverRequest { handover_id, .. } = ngap_rx.recv().await.unwrap() else {
+            panic!("expected Handover Request");
+        };
+        target.reject_handover(handover_id, Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL).await.unwrap();
+        let failure = recv_pdu(&mut source_rx).await;
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL);
+        assert!(target.admissions.lock().await.is_empty());
+        assert!(target.ues.lock().await.is_empty());
+
+        // SN Status Transfer and release of UEs the target does not know
+        let transfer = XnapPdu::initiating(XnapProcedureCode::SnStatusTransfer)
+            .with_ie(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(pdu::ID_NEW_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(2000)).unwrap()
+            .with_ie(pdu::ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST, Criticality::Ignore,
+                     &vec![DrbSnStatus { drb_id: 1, ul_count: 5, dl_count: 12 }]).unwrap();
+        assert!(matches!(target.handle_sn_status_transfer(&transfer).await, Err(LayerError::InvalidState(_))));
+        assert!(matches!(target.release_source(2000).await, Err(LayerError::InvalidState(_))));
+
+        // Admitted UE with PDU sessions not admitted, released after arrival
+        target.handle_handover_request(&request, &sender).await.unwrap();
+        let XnapNgapMessage::HandoverRequest { handover_id, .. } = ngap_rx.recv().await.unwrap() else {
+            panic!("expected Handover Request");
+        };
+        let not_admitted = vec![(2, Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN)];
+        target.acknowledge_handover(handover_id, 2000, admitted(), not_admitted.clone(), Bytes::from_static(&[0x08]))
+            .await.unwrap();
+        let acknowledge = recv_pdu(&mut source_rx).await;
+        assert_eq!(acknowledge.ie::<PduSessionsNotAdmitted>(pdu::ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST).unwrap(),
+                   PduSessionsNotAdmitted(not_admitted));
+        let mut no_drbs = transfer.clone();
+        no_drbs.ies.retain(|ie| ie.id != pdu::ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST);
+        assert!(matches!(target.handle_sn_status_transfer(&no_drbs).await, Err(LayerError::ProcessingError(_))));
+        target.release_source(2000).await.unwrap();
+        let release = recv_pdu(&mut source_rx).await;
+        assert_eq!(release.ie::<NgRanNodeUeXnapId>(pdu::ID_OLD_NG_RAN_NODE_UE_XNAP_ID).unwrap(), NgRanNodeUeXnapId(1000));
+        assert!(matches!(target.release_source(2000).await, Err(LayerError::InvalidState(_))));
+    }
+}
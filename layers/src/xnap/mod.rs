@@ -0,0 +1,187 @@
+//! Xn Application Protocol (XnAP) Implementation
+//!
+//! Signalling between neighbouring gNBs according to 3GPP TS 38.423, limited to
+//! what an Xn based handover needs: Xn Setup to learn the served cells of the
+//! neighbours, Handover Preparation, SN Status Transfer and UE Context Release.
+//! The downlink arriving at the source during the handover is forwarded to the
+//! target over Xn-U by the GTP-U endpoint.
+//!
+//! [`XnapNode`] sits beside NGAP, which owns the UE contexts: NGAP hands it the
+//! UE context of a handover decided by RRC on a measurement report and gets the
+//! requests and outcomes received from the neighbours back.
+
+pub mod handover;
+pub mod node;
+pub mod pdu;
+pub mod setup;
+pub mod transport;
+
+use crate::ngap::pdu::{Criticality, Guami};
+use crate::rrc::DrbSnStatus;
+use bytes::Bytes;
+use pdu::{Cause, PduSessionAdmittedItem, UeContextInfoHoRequest};
+
+pub use node::{run_xnap, XnapConfig, XnapNode};
+
+/// XnAP procedure codes (3GPP TS 38.423 section 9.3.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XnapProcedureCode {
+    HandoverPreparation = 0,
+    SnStatusTransfer = 1,
+    HandoverCancel = 2,
+    UeContextRelease = 6,
+    XnSetup = 17,
+    ErrorIndication = 21,
+}
+
+impl XnapProcedureCode {
+    /// Look up a procedure code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        use XnapProcedureCode::*;
+
+        [HandoverPreparation, SnStatusTransfer, HandoverCancel, UeContextRelease, XnSetup, ErrorIndication]
+            .into_iter()
+            .find(|procedure| *procedure as u8 == code)
+    }
+
+    /// Criticality of the procedure (3GPP TS 38.423 section 9.3.4): reject for
+    /// class 1 procedures, ignore for class 2
+    pub fn criticality(&self) -> Criticality {
+        match self {
+            XnapProcedureCode::HandoverPreparation | XnapProcedureCode::XnSetup => Criticality::Reject,
+            _ => Criticality::Ignore,
+        }
+    }
+}
+
+/// Messages from NGAP to XnAP
+#[derive(Debug, Clone, PartialEq)]
+pub enum NgapXnapMessage {
+    /// Prepare the handover of a UE to the neighbour serving a cell
+    HandoverRequest {
+        ue_id: u32,
+        target_pci: u16,
+        guami: Guami,
+        context: UeContextInfoHoRequest,
+    },
+    /// The target admitted the UE handed over under `handover_id`
+    HandoverRequestAcknowledge {
+        handover_id: u32,
+        ue_id: u32,
+        admitted: Vec<PduSessionAdmittedItem>,
+        not_admitted: Vec<(u8, Cause)>,
+        rrc_container: Bytes,
+    },
+    /// The target cannot admit the UE handed over under `handover_id`
+    HandoverPreparationFailure { handover_id: u32, cause: Cause },
+    /// PDCP COUNTs of the UE leaving the source
+    SnStatusTransfer { ue_id: u32, drbs: Vec<DrbSnStatus> },
+    /// The UE arrived at the target, the source can release it
+    UeContextRelease { ue_id: u32 },
+}
+
+/// Messages from XnAP to NGAP
+#[derive(Debug, Clone, PartialEq)]
+pub enum XnapNgapMessage {
+    /// A neighbour hands a UE over, `handover_id` identifies it until admitted
+    HandoverRequest {
+        handover_id: u32,
+        guami: Guami,
+        context: UeContextInfoHoRequest,
+    },
+    /// The target admitted the UE
+    HandoverRequestAcknowledge {
+        ue_id: u32,
+        admitted: Vec<PduSessionAdmittedItem>,
+        rrc_container: Bytes,
+    },
+    /// The handover cannot be prepared
+    HandoverPreparationFailure { ue_id: u32, cause: Cause },
+    /// PDCP COUNTs of the UE arriving from the source
+    SnStatusTransfer { ue_id: u32, drbs: Vec<DrbSnStatus> },
+    /// The UE reached the target
+    UeContextRelease { ue_id: u32 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::GtpTunnel;
+    use crate::test_support::{wait_until, xn_ue_context, xnap_node, XN_PLMN};
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    async fn recv(rx: &mut mpsc::Receiver<XnapNgapMessage>) -> XnapNgapMessage {
+        timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_xn_handover() {
+        let (source, mut source_rx) = xnap_node(0x19B, 1, Vec::new()).await;
+        let (target, mut target_rx) = xnap_node(0x19C, 2, vec![source.local_addr().unwrap()]).await;
+        let (source_tx, source_ngap_rx) = mpsc::channel(8);
+        let (target_tx, target_ngap_rx) = mpsc::channel(8);
+        let source_task = tokio::spawn(run_xnap(Arc::clone(&source), source_ngap_rx));
+        let target_task = tokio::spawn(run_xnap(Arc::clone(&target), target_ngap_rx));
+
+        // Xn Setup, opened by the target
+        wait_until(|| async { source.neighbours().await.len() == 1 && target.neighbours().await.len() == 1 }).await;
+        assert_eq!(source.neighbours().await[0].gnb_id, 0x19C);
+        assert_eq!(target.neighbours().await[0].gnb_id, 0x19B);
+
+        // A measurement report names a cell no neighbour serves
+        let guami = Guami { plmn_id: XN_PLMN, amf_region_id: 1, amf_set_id: 1, amf_pointer: 1 };
+        let context = xn_ue_context();
+        source_tx.send(NgapXnapMessage::HandoverRequest {
+            ue_id: 1000, target_pci: 3, guami, context: context.clone(),
+        }).await.unwrap();
+        assert!(matches!(recv(&mut source_rx).await, XnapNgapMessage::HandoverPreparationFailure { ue_id: 1000, .. }));
+
+        // Handover Preparation
+        source_tx.send(NgapXnapMessage::HandoverRequest { ue_id: 1000, target_pci: 2, guami, context }).await.unwrap();
+        let XnapNgapMessage::HandoverRequest { handover_id, guami: received, context } = recv(&mut target_rx).await else {
+            panic!("expected Handover Request");
+        };
+        assert_eq!(received, guami);
+        assert_eq!(context.source_cp_address, IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(context.security_key, [0x5A; 32]);
+        assert_eq!(context.sessions[0].ul_tunnel.teid, 0x101);
+
+        let forwarding_tunnel = GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 1]), teid: 9 };
+        let admitted = vec![PduSessionAdmittedItem {
+            pdu_session_id: 1,
+            qos_flows: vec![1],
+            forwarding_tunnel: Some(forwarding_tunnel),
+        }];
+        target_tx.send(NgapXnapMessage::HandoverRequestAcknowledge {
+            handover_id,
+            ue_id: 2000,
+            admitted: admitted.clone(),
+            not_admitted: Vec::new(),
+            rrc_container: Bytes::from_static(&[0x08, 0x00]),
+        }).await.unwrap();
+        assert_eq!(recv(&mut source_rx).await, XnapNgapMessage::HandoverRequestAcknowledge {
+            ue_id: 1000,
+            admitted,
+            rrc_container: Bytes::from_static(&[0x08, 0x00]),
+        });
+
+        // SN Status Transfer reaches the target under its own ID
+        let drbs = vec![DrbSnStatus { drb_id: 1, ul_count: 5, dl_count: 12 }];
+        source_tx.send(NgapXnapMessage::SnStatusTransfer { ue_id: 1000, drbs: drbs.clone() }).await.unwrap();
+        assert_eq!(recv(&mut target_rx).await, XnapNgapMessage::SnStatusTransfer { ue_id: 2000, drbs });
+
+        // The UE arrived: the target releases the source
+        target_tx.send(NgapXnapMessage::UeContextRelease { ue_id: 2000 }).await.unwrap();
+        assert_eq!(recv(&mut source_rx).await, XnapNgapMessage::UeContextRelease { ue_id: 1000 });
+
+        // The target goes away: the source forgets it
+        target_task.abort();
+        drop(target);
+        wait_until(|| async { source.neighbours().await.is_empty() }).await;
+        source_task.abort();
+    }
+}
@@ -0,0 +1,361 @@
+//! XnAP node
+//!
+//! Keeps one Xn association per neighbouring gNB: the configured peers are
+//! connected to and re-connected after a loss, associations opened by other
+//! gNBs are accepted. Whichever side opened it, the association becomes usable
+//! once Xn Setup exchanged the served cells, which is how a handover target is
+//! found from the PCI in a measurement report.
+
+use super::pdu::{Cause, ServedCellNr, XnapPdu, XnapPduType};
+use super::transport::{self, XnListener, XnReceiver, XnSender, XnTransportEvent, NON_UE_STREAM};
+use super::{NgapXnapMessage, XnapNgapMessage, XnapProcedureCode};
+use crate::ngap::pdu::{GlobalGnbId, SupportedTaItem};
+use crate::ngap::transport::NgTransportKind;
+use crate::LayerError;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// XnAP configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XnapConfig {
+    /// Local address the Xn listener is bound to, also the Xn signalling address
+    pub bind_address: SocketAddr,
+    /// Xn transport: SCTP or TCP framing
+    pub transport: NgTransportKind,
+    /// Global gNB ID of this gNB
+    pub global_gnb_id: GlobalGnbId,
+    /// Tracking areas of this gNB
+    pub supported_tas: Vec<SupportedTaItem>,
+    /// Cell served by this gNB
+    pub served_cell: ServedCellNr,
+    /// Neighbours to open an association to
+    pub peers: Vec<SocketAddr>,
+    /// Delay between attempts to (re-)establish an association
+    pub reconnect_interval: Duration,
+}
+
+/// Neighbour gNB with which Xn Setup succeeded
+pub(super) struct Neighbour {
+    /// Global gNB ID of the neighbour
+    pub(super) global_gnb_id: GlobalGnbId,
+    /// Cells served by the neighbour
+    pub(super) served_cells: Vec<ServedCellNr>,
+    /// Sending side of the association
+    pub(super) sender: Arc<XnSender>,
+}
+
+/// UE handed over on an association
+pub(super) struct UeAssociation {
+    /// Association with the other side of the handover
+    pub(super) sender: Arc<XnSender>,
+    /// NG-RAN node UE XnAP ID the other side gave the UE, unknown on the
+    /// source until the target acknowledges
+    pub(super) peer_ue_id: Option<u32>,
+}
+
+/// XnAP entity of a gNB
+pub struct XnapNode {
+    pub(super) config: XnapConfig,
+    listener: XnListener,
+    /// Neighbours by association
+    pub(super) neighbours: RwLock<Vec<Neighbour>>,
+    /// UEs in handover by local NG-RAN node UE XnAP ID, the RAN UE NGAP ID
+    pub(super) ues: Mutex<HashMap<u32, UeAssociation>>,
+    /// Incoming handovers waiting for admission, by handover ID
+    pub(super) admissions: Mutex<HashMap<u32, UeAssociation>>,
+    /// Next handover ID
+    pub(super) next_handover_id: AtomicU32,
+    /// Channel towards NGAP
+    pub(super) ngap_tx: mpsc::Sender<XnapNgapMessage>,
+}
+
+impl XnapNode {
+    /// Bind the Xn listener
+    pub async fn new(config: XnapConfig, ngap_tx: mpsc::Sender<XnapNgapMessage>) -> Result<Self, LayerError> {
+        let listener = XnListener::bind(config.transport, config.bind_address).await?;
+        Ok(Self {
+            config,
+            listener,
+            neighbours: RwLock::new(Vec::new()),
+            ues: Mutex::new(HashMap::new()),
+            admissions: Mutex::new(HashMap::new()),
+            next_handover_id: AtomicU32::new(1),
+            ngap_tx,
+        })
+    }
+
+    /// Local address of the Xn listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Global gNB IDs of the neighbours with which Xn Setup succeeded
+    pub async fn neighbours(&self) -> Vec<GlobalGnbId> {
+        self.neighbours.read().await.iter().map(|neighbour| neighbour.global_gnb_id).collect()
+    }
+
+    /// Add a neighbour, replacing an older association with the same gNB
+    pub(super) async fn add_neighbour(&self, neighbour: Neighbour) {
+        let mut neighbours = self.neighbours.write().await;
+        neighbours.retain(|known| known.global_gnb_id != neighbour.global_gnb_id);
+        neighbours.push(neighbour);
+    }
+
+    /// Pass a message to NGAP
+    pub(super) async fn send_to_ngap(&self, message: XnapNgapMessage) -> Result<(), LayerError> {
+        self.ngap_tx.send(message).await
+            .map_err(|_| LayerError::ProcessingError("NGAP channel closed".into()))
+    }
+
+    /// Handle a message from NGAP
+    pub async fn handle_ngap_message(&self, message: NgapXnapMessage) -> Result<(), LayerError> {
+        match message {
+            NgapXnapMessage::HandoverRequest { ue_id, target_pci, guami, context } => {
+                self.start_handover(ue_id, target_pci, guami, context).await
+            }
+            NgapXnapMessage::HandoverRequestAcknowledge { handover_id, ue_id, admitted, not_admitted, rrc_container } => {
+                self.acknowledge_handover(handover_id, ue_id, admitted, not_admitted, rrc_container).await
+            }
+            NgapXnapMessage::HandoverPreparationFailure { handover_id, cause } => {
+                self.reject_handover(handover_id, cause).await
+            }
+            NgapXnapMessage::SnStatusTransfer { ue_id, drbs } => self.send_sn_status_transfer(ue_id, drbs).await,
+            NgapXnapMessage::UeContextRelease { ue_id } => self.release_source(ue_id).await,
+        }
+    }
+
+    /// Handle a PDU received on an association
+    async fn handle_pdu(&self, pdu: XnapPdu, sender: &Arc<XnSender>) -> Result<(), LayerError> {
+        use XnapProcedureCode::*;
+
+        match (pdu.pdu_type, pdu.procedure()) {
+            (XnapPduType::InitiatingMessage, Some(XnSetup)) => self.handle_xn_setup_request(&pdu, sender).await,
+            (XnapPduType::InitiatingMessage, Some(HandoverPreparation)) => {
+                self.handle_handover_request(&pdu, sender).await
+            }
+            (XnapPduType::SuccessfulOutcome, Some(HandoverPreparation)) => {
+                self.handle_handover_request_acknowledge(&pdu).await
+            }
+            (XnapPduType::UnsuccessfulOutcome, Some(HandoverPreparation)) => {
+                self.handle_handover_preparation_failure(&pdu).await
+            }
+            (XnapPduType::InitiatingMessage, Some(SnStatusTransfer)) => self.handle_sn_status_transfer(&pdu).await,
+            (XnapPduType::InitiatingMessage, Some(UeContextRelease)) => self.handle_ue_context_release(&pdu).await,
+            (pdu_type, procedure) => {
+                debug!("Unhandled XnAP {:?} of procedure {:?} ({})", pdu_type, procedure, pdu.procedure_code);
+                Ok(())
+            }
+        }
+    }
+
+    /// Open the association to a peer and run Xn Setup
+    async fn connect(&self, peer: SocketAddr) -> Result<(Arc<XnSender>, XnReceiver), LayerError> {
+        let local_address = SocketAddr::new(self.config.bind_address.ip(), 0);
+        let (sender, mut receiver) = transport::connect(self.config.transport, local_address, peer).await?;
+        let sender = Arc::new(sender);
+        sender.send(NON_UE_STREAM, &self.xn_setup_request()?).await?;
+        loop {
+            match receiver.recv().await {
+                XnTransportEvent::Data { payload, .. } => {
+                    let pdu = XnapPdu::decode(&payload)?;
+                    if pdu.procedure() != Some(XnapProcedureCode::XnSetup) || pdu.pdu_type == XnapPduType::InitiatingMessage {
+                        debug!("Ignoring XnAP procedure {} before Xn Setup", pdu.procedure_code);
+                        continue;
+                    }
+                    self.handle_xn_setup_outcome(&pdu, &sender).await?;
+                    return Ok((sender, receiver));
+                }
+                XnTransportEvent::AssociationLost(reason) => {
+                    return Err(LayerError::InitializationFailed(format!("Xn Setup with {} aborted: {}", peer, reason)));
+                }
+            }
+        }
+    }
+
+    /// Serve an association until it is lost
+    async fn serve(&self, sender: Arc<XnSender>, mut receiver: XnReceiver) {
+        loop {
+            match receiver.recv().await {
+                XnTransportEvent::Data { stream, payload } => {
+                    let pdu = match XnapPdu::decode(&payload) {
+                        Ok(pdu) => pdu,
+                        Err(e) => {
+                            warn!("Dropping undecodable XnAP PDU on stream {}: {}", stream, e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = self.handle_pdu(pdu, &sender).await {
+                        warn!("Failed to handle XnAP PDU from {}: {}", sender.peer(), e);
+                    }
+                }
+                XnTransportEvent::AssociationLost(reason) => {
+                    self.handle_association_lost(&sender, &reason).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Forget the neighbour behind a lost association and fail the handovers
+    /// still in preparation with it
+    async fn handle_association_lost(&self, sender: &Arc<XnSender>, reason: &str) {
+        warn!("Xn association with {} lost: {}", sender.peer(), reason);
+        self.neighbours.write().await.retain(|neighbour| !Arc::ptr_eq(&neighbour.sender, sender));
+        self.admissions.lock().await.retain(|_, admission| !Arc::ptr_eq(&admission.sender, sender));
+
+        let mut ues = self.ues.lock().await;
+        let lost: Vec<u32> = ues.iter()
+            .filter(|(_, ue)| Arc::ptr_eq(&ue.sender, sender))
+            .map(|(ue_id, _)| *ue_id)
+            .collect();
+        let mut preparing = Vec::new();
+        for ue_id in lost {
+            if ues.remove(&ue_id).is_some_and(|ue| ue.peer_ue_id.is_none()) {
+                preparing.push(ue_id);
+            }
+        }
+        drop(ues);
+
+        for ue_id in preparing {
+            let failure = XnapNgapMessage::HandoverPreparationFailure { ue_id, cause: Cause::Transport(0) };
+            if let Err(e) = self.send_to_ngap(failure).await {
+                warn!("Cannot fail the handover of UE {}: {}", ue_id, e);
+            }
+        }
+    }
+
+    /// Keep the association with a configured peer up
+    async fn run_peer(&self, peer: SocketAddr) {
+        loop {
+            match self.connect(peer).await {
+                Ok((sender, receiver)) => self.serve(sender, receiver).await,
+                Err(e) => warn!("{}", e),
+            }
+            tokio::time::sleep(self.config.reconnect_interval).await;
+        }
+    }
+}
+
+/// Serve one association opened by a neighbour
+async fn run_association(node: Arc<XnapNode>, sender: XnSender, receiver: XnReceiver) {
+    info!("Xn association from {}", sender.peer());
+    node.serve(Arc::new(sender), receiver).await;
+}
+
+/// Connect to the configured peers, accept the associations of other gNBs and
+/// pass the messages of NGAP until the task is dropped, which closes them
+pub async fn run_xnap(node: Arc<XnapNode>, mut ngap_rx: mpsc::Receiver<NgapXnapMessage>) {
+    info!("XnAP listening on {:?} ({:?}), {} configured peers",
+          node.local_addr(), node.config.transport, node.config.peers.len());
+    let mut associations = JoinSet::new();
+    for peer in node.config.peers.clone() {
+        let node = Arc::clone(&node);
+        associations.spawn(async move { node.run_peer(peer).await });
+    }
+
+    let ngap = async {
+        while let Some(message) = ngap_rx.recv().await {
+            if let Err(e) = node.handle_ngap_message(message).await {
+                warn!("Failed to handle NGAP message: {}", e);
+            }
+        }
+    };
+    let accept = async {
+        loop {
+            tokio::select! {
+                accepted = node.listener.accept() => match accepted {
+                    Ok((sender, receiver)) => {
+                        associations.spawn(run_association(Arc::clone(&node), sender, receiver));
+                    }
+                    Err(e) => warn!("{}", e),
+                },
+                Some(_) = associations.join_next() => {}
+            }
+        }
+    };
+    tokio::join!(ngap, accept);
+}
+
+/// Look up the neighbour serving a cell
+pub(super) fn find_target(neighbours: &[Neighbour], pci: u16) -> Option<(&Neighbour, ServedCellNr)> {
+    neighbours.iter().find_map(|neighbour| {
+        neighbour.served_cells.iter().find(|cell| cell.pci == pci).map(|cell| (neighbour, *cell))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{Criticality, TimeToWait};
+    use crate::test_support::{xn_association, xnap_node};
+    use crate::xnap::pdu;
+
+    #[tokio::test]
+    async fn test_xn_association_failures() {
+        let (node, mut ngap_rx) = xnap_node(0x19B, 1, Vec::new()).await;
+        let (peer, _peer_ngap_rx) = xnap_node(0x19C, 2, Vec::new()).await;
+        let ((lost, _lost_rx), _lost_peer) = xn_association().await;
+        let ((kept, _kept_rx), _kept_peer) = xn_association().await;
+        let (lost, kept) = (Arc::new(lost), Arc::new(kept));
+
+        // Unhandled procedures and unknown cells
+        node.handle_pdu(XnapPdu::initiating(XnapProcedureCode::ErrorIndication), &lost).await.unwrap();
+        assert!(find_target(&node.neighbours.read().await, 2).is_none());
+
+        // Losing an association fails the handovers in preparation on it and
+        // forgets the UEs already admitted
+        node.add_neighbour(Neighbour {
+            global_gnb_id: peer.config.global_gnb_id,
+            served_cells: vec![peer.config.served_cell],
+            sender: Arc::clone(&lost),
+        }).await;
+        assert_eq!(find_target(&node.neighbours.read().await, 2).unwrap().1, peer.config.served_cell);
+        let mut ues = node.ues.lock().await;
+        ues.insert(1000, UeAssociation { sender: Arc::clone(&lost), peer_ue_id: None });
+        ues.insert(1001, UeAssociation { sender: Arc::clone(&lost), peer_ue_id: Some(2001) });
+        ues.insert(1002, UeAssociation { sender: Arc::clone(&kept), peer_ue_id: None });
+        drop(ues);
+        node.admissions.lock().await.insert(1, UeAssociation { sender: Arc::clone(&lost), peer_ue_id: Some(3000) });
+        node.handle_association_lost(&lost, "closed").await;
+        assert_eq!(ngap_rx.recv().await.unwrap(),
+                   XnapNgapMessage::HandoverPreparationFailure { ue_id: 1000, cause: Cause::Transport(0) });
+        assert!(ngap_rx.try_recv().is_err());
+        assert!(node.neighbours().await.is_empty());
+        assert!(node.admissions.lock().await.is_empty());
+        assert_eq!(node.ues.lock().await.keys().collect::<Vec<_>>(), vec![&1002]);
+
+        // The peer rejects Xn Setup after an unrelated message
+        let listener = XnListener::bind(NgTransportKind::TcpFramed, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let peer_address = listener.local_addr().unwrap();
+        let rejecting = async {
+            let (sender, mut receiver) = listener.accept().await.unwrap();
+            receiver.recv().await;
+            sender.send(NON_UE_STREAM, &peer.xn_setup_request().unwrap()).await.unwrap();
+            let failure = XnapPdu::unsuccessful(XnapProcedureCode::XnSetup)
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::Misc(4)).unwrap()
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V60s).unwrap();
+            sender.send(NON_UE_STREAM, &failure).await.unwrap();
+            receiver
+        };
+        let (result, _receiver) = tokio::join!(node.connect(peer_address), rejecting);
+        assert!(matches!(result, Err(LayerError::InitializationFailed(_))));
+
+        // The peer goes away before answering, then nobody listens
+        let closing = async {
+            let (sender, mut receiver) = listener.accept().await.unwrap();
+            receiver.recv().await;
+            drop(sender);
+        };
+        let (result, _) = tokio::join!(node.connect(peer_address), closing);
+        assert!(matches!(result, Err(LayerError::InitializationFailed(_))));
+        drop(listener);
+        assert!(node.connect(peer_address).await.is_err());
+        assert!(node.neighbours().await.is_empty());
+    }
+}
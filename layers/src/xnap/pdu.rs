@@ -0,0 +1,933 @@
+//! XnAP PDU and Information Element encoding
+//!
+//! XnAP-PDU structure and protocol IE containers according to 3GPP TS 38.423
+//! section 9.3, encoded with APER. The container machinery and the IEs XnAP
+//! shares with NGAP (GUAMI, NR CGI, S-NSSAI, GTP tunnels, bit rates, QoS flows
+//! and UE security capabilities) come from the NGAP codec. Items are reduced to
+//! what a handover between two gNBs of this implementation needs.
+
+use super::XnapProcedureCode;
+use crate::f1ap::pdu::FddInfo;
+use crate::ngap::aper::{AperDecoder, AperEncoder};
+use crate::ngap::pdu::{
+    decode_list, encode_list, skip_ie_extensions, ApPdu, ApPduType, ApProcedureCode, AperCodec, BroadcastPlmnItem,
+    Criticality, GlobalGnbId, GtpTunnel, NrCgi, PduSessionType, SupportedTaItem, UeSecurityCapabilities,
+};
+use crate::rrc::{DrbSnStatus, RrcReleaseCause};
+use crate::LayerError;
+use bytes::Bytes;
+use common::types::{AggregateMaximumBitRate, QosFlowDescriptor, SNssai};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Protocol IE identifiers (3GPP TS 38.423 section 9.3.7)
+pub const ID_CAUSE: u16 = 7;
+pub const ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST: u16 = 12;
+pub const ID_GLOBAL_NG_RAN_NODE_ID: u16 = 14;
+pub const ID_GUAMI: u16 = 15;
+pub const ID_LIST_OF_SERVED_CELLS_NR: u16 = 19;
+pub const ID_NEW_NG_RAN_NODE_UE_XNAP_ID: u16 = 27;
+pub const ID_OLD_NG_RAN_NODE_UE_XNAP_ID: u16 = 29;
+pub const ID_PDU_SESSION_RESOURCES_ADMITTED_LIST: u16 = 42;
+pub const ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST: u16 = 43;
+pub const ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID: u16 = 73;
+pub const ID_TAI_SUPPORT_LIST: u16 = 75;
+pub const ID_TIME_TO_WAIT: u16 = 76;
+pub const ID_TARGET2SOURCE_NG_RAN_NODE_TRANSP_CONTAINER: u16 = 77;
+pub const ID_TARGET_CELL_GLOBAL_ID: u16 = 78;
+pub const ID_TARGET_NG_RAN_NODE_UE_XNAP_ID: u16 = 79;
+pub const ID_UE_CONTEXT_INFO_HO_REQUEST: u16 = 83;
+
+/// maxnoofsupportedTACs
+const MAX_TACS: usize = 256;
+/// maxnoofsupportedPLMNs
+const MAX_PLMNS: usize = 12;
+/// maxnoofSliceItems
+const MAX_SLICE_ITEMS: usize = 1024;
+/// maxnoofBPLMNs
+const MAX_BPLMNS: usize = 12;
+/// maxnoofCellsinNG-RANnode
+const MAX_CELLS: usize = 16384;
+/// maxnoofPDUSessions
+const MAX_PDU_SESSIONS: usize = 256;
+/// maxnoofQoSFlows
+const MAX_QOS_FLOWS: usize = 64;
+/// maxnoofDRBs
+const MAX_DRBS: usize = 32;
+
+impl ApProcedureCode for XnapProcedureCode {
+    const PROTOCOL: &'static str = "XnAP";
+    // initiatingMessage, successfulOutcome, unsuccessfulOutcome and choice-extension
+    const PDU_CHOICE: (usize, bool) = (4, false);
+
+    fn from_u8(code: u8) -> Option<Self> {
+        XnapProcedureCode::from_u8(code)
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn criticality(&self) -> Criticality {
+        XnapProcedureCode::criticality(self)
+    }
+}
+
+/// XnAP-PDU choice
+pub type XnapPduType = ApPduType;
+
+/// XnAP PDU
+pub type XnapPdu = ApPdu<XnapProcedureCode>;
+
+/// NG-RAN node UE XnAP ID, INTEGER (0..2^32-1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NgRanNodeUeXnapId(pub u32);
+
+impl AperCodec for NgRanNodeUeXnapId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_integer(self.0 as u64, 0, u32::MAX as u64, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        Ok(Self(dec.get_integer(0, u32::MAX as u64, false)? as u32))
+    }
+}
+
+/// Global NG-RAN Node ID (gNB alternative only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalNgRanNodeId(pub GlobalGnbId);
+
+impl AperCodec for GlobalNgRanNodeId {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // GlobalNG-RANNode-ID: gNB
+        enc.put_choice(0, 3, false)?;
+        // GlobalgNB-ID: extension bit, iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        self.0.plmn_id.encode(enc)?;
+        // GNB-ID-Choice: gnb-ID
+        enc.put_choice(0, 2, false)?;
+        let bits = self.0.gnb_id_bits as usize;
+        let value = (self.0.gnb_id as u64) << (32 - bits);
+        enc.put_bit_string(&(value as u32).to_be_bytes(), bits, 22, Some(32), false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(3, false)? != 0 {
+            return Err(LayerError::ProcessingError("Only gNB Global NG-RAN Node IDs are supported".into()));
+        }
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let plmn_id = <[u8; 3]>::decode(dec)?;
+        if dec.get_choice(2, false)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let (data, bits) = dec.get_bit_string(22, Some(32), false)?;
+        let mut padded = [0u8; 4];
+        padded[..data.len()].copy_from_slice(&data);
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self(GlobalGnbId {
+            plmn_id,
+            gnb_id: u32::from_be_bytes(padded) >> (32 - bits),
+            gnb_id_bits: bits as u8,
+        }))
+    }
+}
+
+/// Cause (3GPP TS 38.423 section 9.2.3.2)
+///
+/// Values are the ENUMERATED indices of the respective cause group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    RadioNetwork(u8),
+    Transport(u8),
+    Protocol(u8),
+    Misc(u8),
+}
+
+impl Cause {
+    /// Root values of the radioNetwork, transport, protocol and misc groups
+    const ROOT_COUNTS: [usize; 4] = [53, 2, 7, 5];
+
+    /// Radio network: handover desirable for radio reasons
+    pub const HANDOVER_DESIRABLE_FOR_RADIO_REASONS: Cause = Cause::RadioNetwork(1);
+    /// Radio network: no radio resources available in target cell
+    pub const NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL: Cause = Cause::RadioNetwork(4);
+    /// Radio network: TXnRELOCprep expiry
+    pub const TXNRELOCPREP_EXPIRY: Cause = Cause::RadioNetwork(10);
+    /// Radio network: unknown local NG-RAN node UE XnAP ID
+    pub const UNKNOWN_LOCAL_NG_RAN_NODE_UE_XNAP_ID: Cause = Cause::RadioNetwork(12);
+    /// Radio network: encryption and/or integrity protection algorithms not supported
+    pub const ALGORITHMS_NOT_SUPPORTED: Cause = Cause::RadioNetwork(14);
+    /// Radio network: procedure cancelled
+    pub const PROCEDURE_CANCELLED: Cause = Cause::RadioNetwork(32);
+    /// Radio network: user inactivity
+    pub const USER_INACTIVITY: Cause = Cause::RadioNetwork(35);
+    /// Radio network: radio connection with UE lost
+    pub const RADIO_CONNECTION_WITH_UE_LOST: Cause = Cause::RadioNetwork(36);
+    /// Radio network: failure in the radio interface procedure
+    pub const FAILURE_IN_RADIO_INTERFACE_PROCEDURE: Cause = Cause::RadioNetwork(37);
+    /// Radio network: slice(s) not supported by NG-RAN
+    pub const SLICE_NOT_SUPPORTED_BY_NG_RAN: Cause = Cause::RadioNetwork(45);
+    /// Radio network: unspecified
+    pub const RADIO_NETWORK_UNSPECIFIED: Cause = Cause::RadioNetwork(52);
+    /// Misc: control processing overload
+    pub const CONTROL_PROCESSING_OVERLOAD: Cause = Cause::Misc(0);
+
+    fn group(&self) -> (usize, u8) {
+        match *self {
+            Cause::RadioNetwork(value) => (0, value),
+            Cause::Transport(value) => (1, value),
+            Cause::Protocol(value) => (2, value),
+            Cause::Misc(value) => (3, value),
+        }
+    }
+}
+
+impl From<RrcReleaseCause> for Cause {
+    fn from(cause: RrcReleaseCause) -> Self {
+        match cause {
+            RrcReleaseCause::UserInactivity => Cause::USER_INACTIVITY,
+            RrcReleaseCause::RadioConnectionWithUeLost => Cause::RADIO_CONNECTION_WITH_UE_LOST,
+            RrcReleaseCause::FailureInRadioInterfaceProcedure => Cause::FAILURE_IN_RADIO_INTERFACE_PROCEDURE,
+            RrcReleaseCause::AlgorithmsNotSupported => Cause::ALGORITHMS_NOT_SUPPORTED,
+            RrcReleaseCause::NormalRelease => Cause::RADIO_NETWORK_UNSPECIFIED,
+            RrcReleaseCause::NoRadioResources => Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL,
+        }
+    }
+}
+
+impl AperCodec for Cause {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        let (group, value) = self.group();
+        enc.put_choice(group, 5, false)?;
+        enc.put_enumerated(value as usize, Self::ROOT_COUNTS[group], true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let group = dec.get_choice(5, false)?;
+        if group >= Self::ROOT_COUNTS.len() {
+            return Err(LayerError::ProcessingError("Unknown cause group".into()));
+        }
+        let value = dec.get_enumerated(Self::ROOT_COUNTS[group], true)? as u8;
+        Ok(match group {
+            0 => Cause::RadioNetwork(value),
+            1 => Cause::Transport(value),
+            2 => Cause::Protocol(value),
+            _ => Cause::Misc(value),
+        })
+    }
+}
+
+/// Tracking area code, OCTET STRING (SIZE(3))
+fn encode_tac(enc: &mut AperEncoder, tac: u32) -> Result<(), LayerError> {
+    enc.put_octet_string(&tac.to_be_bytes()[1..], 3, Some(3), false)
+}
+
+fn decode_tac(dec: &mut AperDecoder) -> Result<u32, LayerError> {
+    let octets = dec.get_octet_string(3, Some(3), false)?;
+    Ok(u32::from_be_bytes([0, octets[0], octets[1], octets[2]]))
+}
+
+/// TAI Support List (3GPP TS 38.423 section 9.2.3.20)
+///
+/// Same content as the Supported TA List of NG Setup; the slices of a PLMN are
+/// a plain list of S-NSSAIs in XnAP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaiSupportList(pub Vec<SupportedTaItem>);
+
+impl AperCodec for TaiSupportList {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_length(self.0.len(), 1, Some(MAX_TACS))?;
+        for ta in &self.0 {
+            enc.put_bool(false);
+            enc.put_bool(false);
+            encode_tac(enc, ta.tac)?;
+            enc.put_length(ta.broadcast_plmns.len(), 1, Some(MAX_PLMNS))?;
+            for plmn in &ta.broadcast_plmns {
+                // BroadcastPLMNinTAISupport-Item
+                enc.put_bool(false);
+                enc.put_bool(false);
+                plmn.plmn_id.encode(enc)?;
+                enc.put_length(plmn.slices.len(), 1, Some(MAX_SLICE_ITEMS))?;
+                for slice in &plmn.slices {
+                    slice.encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let count = dec.get_length(1, Some(MAX_TACS))?;
+        let mut tas = Vec::with_capacity(count);
+        for _ in 0..count {
+            dec.get_bool()?;
+            let extensions = dec.get_bool()?;
+            let tac = decode_tac(dec)?;
+            let plmns = dec.get_length(1, Some(MAX_PLMNS))?;
+            let mut broadcast_plmns = Vec::with_capacity(plmns);
+            for _ in 0..plmns {
+                dec.get_bool()?;
+                let plmn_extensions = dec.get_bool()?;
+                let plmn_id = <[u8; 3]>::decode(dec)?;
+                let slices = dec.get_length(1, Some(MAX_SLICE_ITEMS))?;
+                let slices = (0..slices).map(|_| SNssai::decode(dec)).collect::<Result<Vec<_>, _>>()?;
+                skip_ie_extensions(dec, plmn_extensions)?;
+                broadcast_plmns.push(BroadcastPlmnItem { plmn_id, slices });
+            }
+            skip_ie_extensions(dec, extensions)?;
+            tas.push(SupportedTaItem { tac, broadcast_plmns });
+        }
+        Ok(Self(tas))
+    }
+}
+
+/// Served cell of a gNB (ServedCellInformation-NR, 3GPP TS 38.423 section 9.2.2.11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServedCellNr {
+    /// Physical cell identity
+    pub pci: u16,
+    /// NR CGI of the cell
+    pub nr_cgi: NrCgi,
+    /// Tracking area code
+    pub tac: u32,
+    /// FDD carrier of the cell
+    pub fdd_info: FddInfo,
+}
+
+impl AperCodec for ServedCellNr {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // ServedCells-NR-Item: extension, neighbour-info-NR and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bool(false);
+        // ServedCellInformation-NR: extension, ranac and iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pci as u64, 0, 1007, true)?;
+        self.nr_cgi.encode(enc)?;
+        encode_tac(enc, self.tac)?;
+        enc.put_length(1, 1, Some(MAX_BPLMNS))?;
+        self.nr_cgi.plmn_id.encode(enc)?;
+        self.fdd_info.encode(enc)?;
+        // measurementTimingConfiguration
+        enc.put_octet_string(&[], 0, None, false)?;
+        // Connectivity-Support: eNDC-Support not-supported
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_enumerated(1, 2, true)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_neighbour_info = dec.get_bool()?;
+        let item_extensions = dec.get_bool()?;
+        if has_neighbour_info {
+            return Err(LayerError::ProcessingError("Neighbour information of served cells is not supported".into()));
+        }
+        dec.get_bool()?;
+        let has_ranac = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pci = dec.get_integer(0, 1007, true)? as u16;
+        let nr_cgi = NrCgi::decode(dec)?;
+        let tac = decode_tac(dec)?;
+        if has_ranac {
+            dec.get_integer(0, 255, false)?;
+        }
+        let plmns = dec.get_length(1, Some(MAX_BPLMNS))?;
+        for _ in 0..plmns {
+            <[u8; 3]>::decode(dec)?;
+        }
+        let fdd_info = FddInfo::decode(dec)?;
+        dec.get_octet_string(0, None, false)?;
+        dec.get_bool()?;
+        let connectivity_extensions = dec.get_bool()?;
+        dec.get_enumerated(2, true)?;
+        skip_ie_extensions(dec, connectivity_extensions)?;
+        skip_ie_extensions(dec, extensions)?;
+        skip_ie_extensions(dec, item_extensions)?;
+        Ok(Self { pci, nr_cgi, tac, fdd_info })
+    }
+}
+
+impl AperCodec for Vec<ServedCellNr> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_CELLS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_CELLS)
+    }
+}
+
+/// Target Cell Global ID (NR alternative only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetCgi(pub NrCgi);
+
+impl AperCodec for TargetCgi {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Target-CGI: nr
+        enc.put_choice(0, 3, false)?;
+        self.0.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        if dec.get_choice(3, false)? != 0 {
+            return Err(LayerError::ProcessingError("Only NR target cells are supported".into()));
+        }
+        Ok(Self(NrCgi::decode(dec)?))
+    }
+}
+
+/// Transport layer address, BIT STRING (SIZE(1..160, ...))
+fn encode_transport_layer_address(enc: &mut AperEncoder, address: IpAddr) -> Result<(), LayerError> {
+    match address {
+        IpAddr::V4(addr) => enc.put_bit_string(&addr.octets(), 32, 1, Some(160), true),
+        IpAddr::V6(addr) => enc.put_bit_string(&addr.octets(), 128, 1, Some(160), true),
+    }
+}
+
+fn decode_transport_layer_address(dec: &mut AperDecoder) -> Result<IpAddr, LayerError> {
+    let (data, bits) = dec.get_bit_string(1, Some(160), true)?;
+    match bits {
+        32 => Ok(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+        128 | 160 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&data[..16]);
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => Err(LayerError::ProcessingError(format!("Unsupported transport layer address of {} bits", bits))),
+    }
+}
+
+/// PDU session of the UE to set up in the target
+/// (PDUSessionResourcesToBeSetup-Item, 3GPP TS 38.423 section 9.2.1.1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PduSessionToBeSetupItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// Slice of the PDU session
+    pub s_nssai: SNssai,
+    /// PDU Session Aggregate Maximum Bit Rate
+    pub session_ambr: Option<AggregateMaximumBitRate>,
+    /// UPF endpoint of the NG-U tunnel
+    pub ul_tunnel: GtpTunnel,
+    /// PDU session type
+    pub pdu_session_type: PduSessionType,
+    /// QoS flows of the PDU session
+    pub qos_flows: Vec<QosFlowDescriptor>,
+}
+
+impl AperCodec for PduSessionToBeSetupItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, pduSessionAMBR, source-DL-NG-U-TNL-Information,
+        // securityIndication, pduSessionNetworkInstance, iE-Extensions
+        enc.put_bool(false);
+        enc.put_bool(self.session_ambr.is_some());
+        enc.put_bits(0, 4);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        self.s_nssai.encode(enc)?;
+        if let Some(session_ambr) = &self.session_ambr {
+            session_ambr.encode(enc)?;
+        }
+        self.ul_tunnel.encode(enc)?;
+        self.pdu_session_type.encode(enc)?;
+        self.qos_flows.encode(enc)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_session_ambr = dec.get_bool()?;
+        let has_source_tunnel = dec.get_bool()?;
+        let has_security_indication = dec.get_bool()?;
+        let has_network_instance = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if has_source_tunnel || has_security_indication || has_network_instance {
+            return Err(LayerError::ProcessingError("Unsupported optional IEs in PDU session to be set up".into()));
+        }
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        let s_nssai = SNssai::decode(dec)?;
+        let session_ambr = if has_session_ambr {
+            Some(AggregateMaximumBitRate::decode(dec)?)
+        } else {
+            None
+        };
+        let ul_tunnel = GtpTunnel::decode(dec)?;
+        let pdu_session_type = PduSessionType::decode(dec)?;
+        let qos_flows = Vec::<QosFlowDescriptor>::decode(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, s_nssai, session_ambr, ul_tunnel, pdu_session_type, qos_flows })
+    }
+}
+
+impl AperCodec for Vec<PduSessionToBeSetupItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// UE Context Information of Handover Request (3GPP TS 38.423 section 9.1.1.1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UeContextInfoHoRequest {
+    /// AMF UE NGAP ID of the UE
+    pub amf_ue_ngap_id: u64,
+    /// Xn signalling address of the source, filled in by XnAP
+    pub source_cp_address: IpAddr,
+    /// UE security capabilities
+    pub security_capabilities: UeSecurityCapabilities,
+    /// K_NG-RAN* for the target cell
+    pub security_key: [u8; 32],
+    /// Next hop chaining count
+    pub next_hop_chaining_count: u8,
+    /// UE Aggregate Maximum Bit Rate
+    pub ue_ambr: AggregateMaximumBitRate,
+    /// PDU sessions to set up
+    pub sessions: Vec<PduSessionToBeSetupItem>,
+    /// Encoded HandoverPreparationInformation
+    pub rrc_context: Bytes,
+}
+
+impl AperCodec for UeContextInfoHoRequest {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // Extension, indexToRatFrequencySelectionPriority, locationReportingInformation,
+        // mrl, iE-Extensions
+        enc.put_bool(false);
+        enc.put_bits(0, 4);
+        enc.put_integer(self.amf_ue_ngap_id, 0, (1 << 40) - 1, false)?;
+        // CPTransportLayerInformation: endpointIPAddress
+        enc.put_choice(0, 2, false)?;
+        encode_transport_layer_address(enc, self.source_cp_address)?;
+        self.security_capabilities.encode(enc)?;
+        // AS-SecurityInformation: extension, iE-Extensions absent
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_bit_string(&self.security_key, 256, 256, Some(256), false)?;
+        enc.put_integer(self.next_hop_chaining_count as u64, 0, 7, false)?;
+        self.ue_ambr.encode(enc)?;
+        self.sessions.encode(enc)?;
+        enc.put_octet_string(&self.rrc_context, 0, None, false)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_rfsp = dec.get_bool()?;
+        let has_location_reporting = dec.get_bool()?;
+        let has_mrl = dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        if has_location_reporting || has_mrl {
+            return Err(LayerError::ProcessingError("Unsupported optional IEs in UE context information".into()));
+        }
+        let amf_ue_ngap_id = dec.get_integer(0, (1 << 40) - 1, false)?;
+        if dec.get_choice(2, false)? != 0 {
+            return Err(LayerError::InvalidPdu);
+        }
+        let source_cp_address = decode_transport_layer_address(dec)?;
+        let security_capabilities = UeSecurityCapabilities::decode(dec)?;
+        dec.get_bool()?;
+        let security_extensions = dec.get_bool()?;
+        let (key, _) = dec.get_bit_string(256, Some(256), false)?;
+        let mut security_key = [0u8; 32];
+        security_key.copy_from_slice(&key);
+        let next_hop_chaining_count = dec.get_integer(0, 7, false)? as u8;
+        skip_ie_extensions(dec, security_extensions)?;
+        if has_rfsp {
+            dec.get_integer(1, 256, true)?;
+        }
+        let ue_ambr = AggregateMaximumBitRate::decode(dec)?;
+        let sessions = Vec::<PduSessionToBeSetupItem>::decode(dec)?;
+        let rrc_context = Bytes::from(dec.get_octet_string(0, None, false)?);
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self {
+            amf_ue_ngap_id,
+            source_cp_address,
+            security_capabilities,
+            security_key,
+            next_hop_chaining_count,
+            ue_ambr,
+            sessions,
+            rrc_context,
+        })
+    }
+}
+
+/// QoS flows as a list of QFIs, each item an extensible SEQUENCE
+fn encode_qos_flows(enc: &mut AperEncoder, qos_flows: &[u8]) -> Result<(), LayerError> {
+    enc.put_length(qos_flows.len(), 1, Some(MAX_QOS_FLOWS))?;
+    for qfi in qos_flows {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(*qfi as u64, 0, 63, true)?;
+    }
+    Ok(())
+}
+
+fn decode_qos_flows(dec: &mut AperDecoder) -> Result<Vec<u8>, LayerError> {
+    let count = dec.get_length(1, Some(MAX_QOS_FLOWS))?;
+    let mut qos_flows = Vec::with_capacity(count);
+    for _ in 0..count {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        qos_flows.push(dec.get_integer(0, 63, true)? as u8);
+        skip_ie_extensions(dec, extensions)?;
+    }
+    Ok(qos_flows)
+}
+
+/// PDU session admitted by the target (PDUSessionResourcesAdmitted-Item,
+/// 3GPP TS 38.423 section 9.2.1.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionAdmittedItem {
+    /// PDU session ID
+    pub pdu_session_id: u8,
+    /// QoS flows admitted
+    pub qos_flows: Vec<u8>,
+    /// Tunnel of the target receiving the downlink forwarded by the source
+    /// (PDU session level DL data forwarding GTP tunnel)
+    pub forwarding_tunnel: Option<GtpTunnel>,
+}
+
+impl AperCodec for PduSessionAdmittedItem {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_bool(false);
+        enc.put_integer(self.pdu_session_id as u64, 0, 255, false)?;
+        // PDUSessionResourceAdmittedInfo: extension, dL-NG-U-TNL-Information-Unchanged,
+        // qosFlowsNotAdmitted-List, dataForwardingInfoFromTarget, iE-Extensions
+        enc.put_bool(false);
+        enc.put_bits(0, 2);
+        enc.put_bool(self.forwarding_tunnel.is_some());
+        enc.put_bool(false);
+        encode_qos_flows(enc, &self.qos_flows)?;
+        if let Some(tunnel) = &self.forwarding_tunnel {
+            // DataForwardingInfoFromTargetNGRANnode: extension,
+            // pduSessionLevelDLDataForwardingGTPTunnel, pduSessionLevelULDataForwardingGTPTunnel,
+            // dataForwardingResponseDRBItemList, iE-Extensions
+            enc.put_bool(false);
+            enc.put_bool(true);
+            enc.put_bits(0, 3);
+            encode_qos_flows(enc, &self.qos_flows)?;
+            tunnel.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let extensions = dec.get_bool()?;
+        let pdu_session_id = dec.get_integer(0, 255, false)? as u8;
+        dec.get_bool()?;
+        let has_unchanged = dec.get_bool()?;
+        let has_not_admitted = dec.get_bool()?;
+        let has_forwarding = dec.get_bool()?;
+        let info_extensions = dec.get_bool()?;
+        if has_unchanged {
+            dec.get_enumerated(1, true)?;
+        }
+        let qos_flows = decode_qos_flows(dec)?;
+        if has_not_admitted {
+            return Err(LayerError::ProcessingError("QoS flows not admitted are not supported".into()));
+        }
+        let forwarding_tunnel = if has_forwarding {
+            dec.get_bool()?;
+            let has_dl_tunnel = dec.get_bool()?;
+            let has_ul_tunnel = dec.get_bool()?;
+            let has_drb_items = dec.get_bool()?;
+            let forwarding_extensions = dec.get_bool()?;
+            if has_ul_tunnel || has_drb_items {
+                return Err(LayerError::ProcessingError("Only PDU session level DL forwarding is supported".into()));
+            }
+            decode_qos_flows(dec)?;
+            let tunnel = if has_dl_tunnel { Some(GtpTunnel::decode(dec)?) } else { None };
+            skip_ie_extensions(dec, forwarding_extensions)?;
+            tunnel
+        } else {
+            None
+        };
+        skip_ie_extensions(dec, info_extensions)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { pdu_session_id, qos_flows, forwarding_tunnel })
+    }
+}
+
+impl AperCodec for Vec<PduSessionAdmittedItem> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_PDU_SESSIONS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_PDU_SESSIONS)
+    }
+}
+
+/// PDU sessions not admitted by the target, with the cause of each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PduSessionsNotAdmitted(pub Vec<(u8, Cause)>);
+
+impl AperCodec for PduSessionsNotAdmitted {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        // PDUSessionResourcesNotAdmitted-List: extension, pduSessionResourcesNotAdmitted-SNterminated
+        // absent, the other one present
+        enc.put_bool(false);
+        enc.put_bool(true);
+        enc.put_bool(false);
+        enc.put_length(self.0.len(), 1, Some(MAX_PDU_SESSIONS))?;
+        for (id, cause) in &self.0 {
+            // PDUSessionResourcesNotAdmitted-Item: extension, cause, iE-Extensions
+            enc.put_bool(false);
+            enc.put_bool(true);
+            enc.put_bool(false);
+            enc.put_integer(*id as u64, 0, 255, false)?;
+            cause.encode(enc)?;
+        }
+        Ok(())
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        dec.get_bool()?;
+        let has_list = dec.get_bool()?;
+        let has_sn_terminated = dec.get_bool()?;
+        if has_sn_terminated {
+            return Err(LayerError::ProcessingError("SN terminated PDU sessions are not supported".into()));
+        }
+        let mut sessions = Vec::new();
+        if has_list {
+            let count = dec.get_length(1, Some(MAX_PDU_SESSIONS))?;
+            for _ in 0..count {
+                dec.get_bool()?;
+                let has_cause = dec.get_bool()?;
+                let extensions = dec.get_bool()?;
+                let id = dec.get_integer(0, 255, false)? as u8;
+                // The cause is optional, a session without one was not admitted for no given reason
+                let cause = if has_cause { Cause::decode(dec)? } else { Cause::RADIO_NETWORK_UNSPECIFIED };
+                skip_ie_extensions(dec, extensions)?;
+                sessions.push((id, cause));
+            }
+        }
+        Ok(Self(sessions))
+    }
+}
+
+/// COUNT of an 18-bit PDCP SN (COUNT-PDCP-SN18)
+fn encode_count(enc: &mut AperEncoder, count: u32) -> Result<(), LayerError> {
+    enc.put_bool(false);
+    enc.put_integer((count & 0x3_FFFF) as u64, 0, 262143, false)?;
+    enc.put_integer((count >> 18) as u64 & 0x3FFF, 0, 16383, false)
+}
+
+fn decode_count(dec: &mut AperDecoder) -> Result<u32, LayerError> {
+    let extensions = dec.get_bool()?;
+    let sn = dec.get_integer(0, 262143, false)? as u32;
+    let hfn = dec.get_integer(0, 16383, false)? as u32;
+    skip_ie_extensions(dec, extensions)?;
+    Ok(hfn << 18 | sn)
+}
+
+/// DRBBStatusTransfer-Choice with an 18-bit PDCP SN and no receive status
+fn encode_status_transfer(enc: &mut AperEncoder, count: u32) -> Result<(), LayerError> {
+    enc.put_choice(1, 3, false)?;
+    // DRBBStatusTransfer18bitsSN: receiveStatusofPDCPSDU and iE-Extension absent
+    enc.put_bool(false);
+    enc.put_bool(false);
+    encode_count(enc, count)
+}
+
+fn decode_status_transfer(dec: &mut AperDecoder) -> Result<u32, LayerError> {
+    if dec.get_choice(3, false)? != 1 {
+        return Err(LayerError::ProcessingError("Only 18-bit PDCP SN status is supported".into()));
+    }
+    let has_receive_status = dec.get_bool()?;
+    let extensions = dec.get_bool()?;
+    if has_receive_status {
+        dec.get_bit_string(1, Some(131072), false)?;
+    }
+    let count = decode_count(dec)?;
+    skip_ie_extensions(dec, extensions)?;
+    Ok(count)
+}
+
+/// DRBs Subject To Status Transfer Item (3GPP TS 38.423 section 9.2.1.14)
+impl AperCodec for DrbSnStatus {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        enc.put_bool(false);
+        enc.put_integer(self.drb_id as u64, 1, 32, true)?;
+        encode_status_transfer(enc, self.ul_count)?;
+        encode_status_transfer(enc, self.dl_count)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        let extensions = dec.get_bool()?;
+        let drb_id = dec.get_integer(1, 32, true)? as u8;
+        let ul_count = decode_status_transfer(dec)?;
+        let dl_count = decode_status_transfer(dec)?;
+        skip_ie_extensions(dec, extensions)?;
+        Ok(Self { drb_id, ul_count, dl_count })
+    }
+}
+
+impl AperCodec for Vec<DrbSnStatus> {
+    fn encode(&self, enc: &mut AperEncoder) -> Result<(), LayerError> {
+        encode_list(enc, self, MAX_DRBS)
+    }
+
+    fn decode(dec: &mut AperDecoder) -> Result<Self, LayerError> {
+        decode_list(dec, MAX_DRBS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::Guami;
+    use crate::test_support::{decode_ie, encode_ie};
+    use common::types::{AllocationRetentionPriority, FiveQi, QosCharacteristics};
+
+    #[test]
+    fn test_xnap_messages() {
+        let global_id = GlobalNgRanNodeId(GlobalGnbId { plmn_id: [0x02, 0xF8, 0x39], gnb_id: 0x19B, gnb_id_bits: 22 });
+        let tas = TaiSupportList(vec![SupportedTaItem {
+            tac: 7,
+            broadcast_plmns: vec![BroadcastPlmnItem {
+                plmn_id: [0x02, 0xF8, 0x39],
+                slices: vec![SNssai { sst: 1, sd: None }, SNssai { sst: 2, sd: Some(0x010203) }],
+            }],
+        }]);
+        let cells = vec![ServedCellNr {
+            pci: 2,
+            nr_cgi: NrCgi { plmn_id: [0x02, 0xF8, 0x39], nr_cell_identity: 0x19B002 },
+            tac: 7,
+            fdd_info: FddInfo { ul_arfcn: 349500, dl_arfcn: 368500, band: 3, scs_khz: 15, nrb: 52 },
+        }];
+        let request = XnapPdu::initiating(XnapProcedureCode::XnSetup)
+            .with_ie(ID_GLOBAL_NG_RAN_NODE_ID, Criticality::Reject, &global_id).unwrap()
+            .with_ie(ID_TAI_SUPPORT_LIST, Criticality::Reject, &tas).unwrap()
+            .with_ie(ID_LIST_OF_SERVED_CELLS_NR, Criticality::Reject, &cells).unwrap();
+        let encoded = request.encode().unwrap();
+        // initiatingMessage, procedure code 17, criticality reject
+        assert_eq!(&encoded[..3], &[0x00, 0x11, 0x00]);
+        let decoded = XnapPdu::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.procedure(), Some(XnapProcedureCode::XnSetup));
+        assert_eq!(decoded.ie::<GlobalNgRanNodeId>(ID_GLOBAL_NG_RAN_NODE_ID).unwrap(), global_id);
+        assert_eq!(decoded.ie::<TaiSupportList>(ID_TAI_SUPPORT_LIST).unwrap(), tas);
+        assert_eq!(decoded.ie::<Vec<ServedCellNr>>(ID_LIST_OF_SERVED_CELLS_NR).unwrap(), cells);
+
+        // Handover Request with the UE context and a PDU session
+        let context = UeContextInfoHoRequest {
+            amf_ue_ngap_id: 0xFF_0000_0001,
+            source_cp_address: IpAddr::from([127, 0, 0, 1]),
+            security_capabilities: UeSecurityCapabilities {
+                nr_encryption_algorithms: 0xE000,
+                nr_integrity_algorithms: 0xE000,
+                ..Default::default()
+            },
+            security_key: [0x5A; 32],
+            next_hop_chaining_count: 2,
+            ue_ambr: AggregateMaximumBitRate { dl: 100_000_000, ul: 50_000_000 },
+            sessions: vec![PduSessionToBeSetupItem {
+                pdu_session_id: 1,
+                s_nssai: SNssai { sst: 1, sd: None },
+                session_ambr: Some(AggregateMaximumBitRate { dl: 10_000_000, ul: 5_000_000 }),
+                ul_tunnel: GtpTunnel { transport_layer_address: IpAddr::from([10, 0, 0, 1]), teid: 0x101 },
+                pdu_session_type: PduSessionType::Ipv4,
+                qos_flows: vec![QosFlowDescriptor {
+                    qfi: 1,
+                    characteristics: QosCharacteristics::NonDynamic { five_qi: FiveQi(9), priority_level: None },
+                    arp: AllocationRetentionPriority { priority_level: 8, may_trigger_pre_emption: false, pre_emptable: false },
+                    gbr: None,
+                }],
+            }],
+            rrc_context: Bytes::from_static(&[0x00, 0x01, 0x46, 0x01]),
+        };
+        let guami = Guami { plmn_id: [0x02, 0xF8, 0x39], amf_region_id: 1, amf_set_id: 1, amf_pointer: 1 };
+        let request = XnapPdu::initiating(XnapProcedureCode::HandoverPreparation)
+            .with_ie(ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Reject, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(ID_CAUSE, Criticality::Reject, &Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASONS).unwrap()
+            .with_ie(ID_TARGET_CELL_GLOBAL_ID, Criticality::Reject, &TargetCgi(cells[0].nr_cgi)).unwrap()
+            .with_ie(ID_GUAMI, Criticality::Reject, &guami).unwrap()
+            .with_ie(ID_UE_CONTEXT_INFO_HO_REQUEST, Criticality::Reject, &context).unwrap();
+        let decoded = XnapPdu::decode(&request.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<UeContextInfoHoRequest>(ID_UE_CONTEXT_INFO_HO_REQUEST).unwrap(), context);
+        assert_eq!(decoded.ie::<TargetCgi>(ID_TARGET_CELL_GLOBAL_ID).unwrap().0, cells[0].nr_cgi);
+        assert_eq!(decoded.ie::<Cause>(ID_CAUSE).unwrap(), Cause::HANDOVER_DESIRABLE_FOR_RADIO_REASONS);
+
+        // Acknowledge with a forwarding tunnel and a session not admitted
+        let admitted = vec![PduSessionAdmittedItem {
+            pdu_session_id: 1,
+            qos_flows: vec![1],
+            forwarding_tunnel: Some(GtpTunnel { transport_layer_address: IpAddr::from([127, 0, 0, 2]), teid: 7 }),
+        }];
+        let acknowledge = XnapPdu::successful(XnapProcedureCode::HandoverPreparation)
+            .with_ie(ID_SOURCE_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(1000)).unwrap()
+            .with_ie(ID_TARGET_NG_RAN_NODE_UE_XNAP_ID, Criticality::Ignore, &NgRanNodeUeXnapId(1)).unwrap()
+            .with_ie(ID_PDU_SESSION_RESOURCES_ADMITTED_LIST, Criticality::Ignore, &admitted).unwrap()
+            .with_ie(ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST, Criticality::Ignore,
+                     &PduSessionsNotAdmitted(vec![(2, Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN)])).unwrap()
+            .with_ie(ID_TARGET2SOURCE_NG_RAN_NODE_TRANSP_CONTAINER, Criticality::Ignore,
+                     &Bytes::from_static(&[0x01, 0x02])).unwrap();
+        let decoded = XnapPdu::decode(&acknowledge.encode().unwrap()).unwrap();
+        assert_eq!(decoded.ie::<Vec<PduSessionAdmittedItem>>(ID_PDU_SESSION_RESOURCES_ADMITTED_LIST).unwrap(), admitted);
+        assert_eq!(decoded.ie::<PduSessionsNotAdmitted>(ID_PDU_SESSION_RESOURCES_NOT_ADMITTED_LIST).unwrap().0,
+                   vec![(2, Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN)]);
+
+        // SN Status Transfer keeps the HFN and SN of the COUNTs
+        let drbs = vec![DrbSnStatus { drb_id: 1, ul_count: 5 << 18 | 17, dl_count: 300_000 }];
+        let transfer = XnapPdu::initiating(XnapProcedureCode::SnStatusTransfer)
+            .with_ie(ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST, Criticality::Ignore, &drbs).unwrap();
+        let decoded = XnapPdu::decode(&transfer.encode().unwrap()).unwrap();
+        assert_eq!(decoded.criticality, Criticality::Ignore);
+        assert_eq!(decoded.ie::<Vec<DrbSnStatus>>(ID_DRBS_SUBJECT_TO_STATUS_TRANSFER_LIST).unwrap(), drbs);
+    }
+
+    #[test]
+    fn test_xnap_decode_errors() {
+        // Cause group beyond misc
+        assert!(matches!(decode_ie::<Cause>(&[0x80]), Err(LayerError::ProcessingError(_))));
+
+        // Alternatives and optional IEs the codec does not support
+        // ng-eNB Global NG-RAN Node ID
+        assert!(matches!(decode_ie::<GlobalNgRanNodeId>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        // E-UTRA target cell
+        assert!(matches!(decode_ie::<TargetCgi>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        // Served cell with neighbour information
+        assert!(matches!(decode_ie::<ServedCellNr>(&[0x40]), Err(LayerError::ProcessingError(_))));
+        // PDU session with a source DL tunnel
+        assert!(matches!(decode_ie::<PduSessionToBeSetupItem>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        // UE context with location reporting information
+        assert!(matches!(decode_ie::<UeContextInfoHoRequest>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        // SN terminated PDU sessions not admitted
+        assert!(matches!(decode_ie::<PduSessionsNotAdmitted>(&[0x20]), Err(LayerError::ProcessingError(_))));
+        // DRB 1 with a 12-bit PDCP SN status
+        assert!(matches!(decode_ie::<DrbSnStatus>(&[0x00, 0x00]), Err(LayerError::ProcessingError(_))));
+        // Admitted PDU session with QoS flows not admitted
+        let admitted = PduSessionAdmittedItem { pdu_session_id: 1, qos_flows: vec![1], forwarding_tunnel: None };
+        let mut encoded = encode_ie(&admitted).unwrap().to_vec();
+        assert_eq!(decode_ie::<PduSessionAdmittedItem>(&encoded).unwrap(), admitted);
+        encoded[2] |= 0x20;
+        assert!(matches!(decode_ie::<PduSessionAdmittedItem>(&encoded), Err(LayerError::ProcessingError(_))));
+
+        // Lists XnAP requires to be non-empty
+        assert!(matches!(encode_ie(&TaiSupportList(Vec::new())), Err(LayerError::ProcessingError(_))));
+        let no_slices = TaiSupportList(vec![SupportedTaItem {
+            tac: 1,
+            broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: [0x02, 0xF8, 0x39], slices: Vec::new() }],
+        }]);
+        assert!(matches!(encode_ie(&no_slices), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(encode_ie(&PduSessionsNotAdmitted(Vec::new())), Err(LayerError::ProcessingError(_))));
+        assert!(matches!(encode_ie(&Vec::<PduSessionAdmittedItem>::new()), Err(LayerError::ProcessingError(_))));
+
+        // PDU sessions not admitted keep their IDs and causes, a missing cause is unspecified
+        let not_admitted = PduSessionsNotAdmitted(vec![
+            (3, Cause::NO_RADIO_RESOURCES_AVAILABLE_IN_TARGET_CELL),
+            (4, Cause::SLICE_NOT_SUPPORTED_BY_NG_RAN),
+        ]);
+        assert_eq!(decode_ie::<PduSessionsNotAdmitted>(&encode_ie(&not_admitted).unwrap()).unwrap(), not_admitted);
+        // List present, one item without cause, PDU session ID 5
+        assert_eq!(decode_ie::<PduSessionsNotAdmitted>(&[0x40, 0x00, 0x00, 0x05]).unwrap(),
+                   PduSessionsNotAdmitted(vec![(5, Cause::RADIO_NETWORK_UNSPECIFIED)]));
+    }
+}
@@ -0,0 +1,155 @@
+//! Xn Setup (3GPP TS 38.423 section 8.4.1)
+//!
+//! Both gNBs give their Global gNB ID, tracking areas and served cells. A
+//! neighbour without a PLMN in common is rejected.
+
+use super::node::{Neighbour, XnapNode};
+use super::pdu::{self, Cause, GlobalNgRanNodeId, ServedCellNr, TaiSupportList, XnapPdu, XnapPduType};
+use super::transport::{XnSender, NON_UE_STREAM};
+use super::XnapProcedureCode;
+use crate::ngap::pdu::{Criticality, TimeToWait};
+use crate::LayerError;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+impl XnapNode {
+    /// Build Xn Setup Request
+    pub(super) fn xn_setup_request(&self) -> Result<XnapPdu, LayerError> {
+        self.setup_message(XnapPdu::initiating(XnapProcedureCode::XnSetup))
+    }
+
+    /// Add the node information shared by Xn Setup Request and Response
+    fn setup_message(&self, pdu: XnapPdu) -> Result<XnapPdu, LayerError> {
+        pdu.with_ie(pdu::ID_GLOBAL_NG_RAN_NODE_ID, Criticality::Reject,
+                    &GlobalNgRanNodeId(self.config.global_gnb_id))?
+            .with_ie(pdu::ID_TAI_SUPPORT_LIST, Criticality::Reject,
+                     &TaiSupportList(self.config.supported_tas.clone()))?
+            .with_ie(pdu::ID_LIST_OF_SERVED_CELLS_NR, Criticality::Reject, &vec![self.config.served_cell])
+    }
+
+    /// Check if a neighbour broadcasts one of our PLMNs
+    fn shares_plmn(&self, tas: &TaiSupportList) -> bool {
+        let ours = self.config.supported_tas.iter().flat_map(|ta| &ta.broadcast_plmns);
+        let theirs: Vec<[u8; 3]> = tas.0.iter()
+            .flat_map(|ta| &ta.broadcast_plmns)
+            .map(|plmn| plmn.plmn_id)
+            .collect();
+        ours.into_iter().any(|plmn| theirs.contains(&plmn.plmn_id))
+    }
+
+    /// Handle Xn Setup Request
+    pub(super) async fn handle_xn_setup_request(&self, pdu: &XnapPdu, sender: &Arc<XnSender>) -> Result<(), LayerError> {
+        let global_gnb_id = pdu.ie::<GlobalNgRanNodeId>(pdu::ID_GLOBAL_NG_RAN_NODE_ID)?.0;
+        let tas = pdu.ie::<TaiSupportList>(pdu::ID_TAI_SUPPORT_LIST)?;
+        let served_cells = pdu.optional_ie::<Vec<ServedCellNr>>(pdu::ID_LIST_OF_SERVED_CELLS_NR)?.unwrap_or_default();
+
+        if !self.shares_plmn(&tas) {
+            warn!("Rejecting Xn Setup of gNB {:#x} from {}: no PLMN in common", global_gnb_id.gnb_id, sender.peer());
+            let failure = XnapPdu::unsuccessful(XnapProcedureCode::XnSetup)
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::Misc(4))?
+                .with_ie(pdu::ID_TIME_TO_WAIT, Criticality::Ignore, &TimeToWait::V60s)?;
+            return sender.send(NON_UE_STREAM, &failure).await;
+        }
+
+        info!("Xn Setup from gNB {:#x} at {} serving PCIs {:?}",
+              global_gnb_id.gnb_id, sender.peer(), served_cells.iter().map(|cell| cell.pci).collect::<Vec<_>>());
+        let response = self.setup_message(XnapPdu::successful(XnapProcedureCode::XnSetup))?;
+        sender.send(NON_UE_STREAM, &response).await?;
+
+        self.add_neighbour(Neighbour { global_gnb_id, served_cells, sender: Arc::clone(sender) }).await;
+        Ok(())
+    }
+
+    /// Handle Xn Setup Response or Failure
+    pub(super) async fn handle_xn_setup_outcome(&self, pdu: &XnapPdu, sender: &Arc<XnSender>) -> Result<(), LayerError> {
+        match pdu.pdu_type {
+            XnapPduType::SuccessfulOutcome => {
+                let global_gnb_id = pdu.ie::<GlobalNgRanNodeId>(pdu::ID_GLOBAL_NG_RAN_NODE_ID)?.0;
+                let served_cells = pdu.optional_ie::<Vec<ServedCellNr>>(pdu::ID_LIST_OF_SERVED_CELLS_NR)?
+                    .unwrap_or_default();
+                info!("Xn Setup with gNB {:#x} at {} done, serving PCIs {:?}",
+                      global_gnb_id.gnb_id, sender.peer(), served_cells.iter().map(|cell| cell.pci).collect::<Vec<_>>());
+                self.add_neighbour(Neighbour { global_gnb_id, served_cells, sender: Arc::clone(sender) }).await;
+                Ok(())
+            }
+            _ => {
+                let cause = pdu.ie::<Cause>(pdu::ID_CAUSE)?;
+                let time_to_wait = pdu.optional_ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT)?;
+                Err(LayerError::InitializationFailed(
+                    format!("Xn Setup rejected by {}: {:?} (time to wait {:?})", sender.peer(), cause, time_to_wait)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ngap::pdu::{BroadcastPlmnItem, SupportedTaItem};
+    use crate::test_support::{xn_association, xnap_node};
+    use crate::xnap::transport::{XnReceiver, XnTransportEvent};
+    use crate::xnap::XnapProcedureCode;
+    use bytes::Bytes;
+    use common::types::SNssai;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn recv_pdu(receiver: &mut XnReceiver) -> XnapPdu {
+        match timeout(Duration::from_secs(2), receiver.recv()).await.unwrap() {
+            XnTransportEvent::Data { payload, .. } => XnapPdu::decode(&payload).unwrap(),
+            XnTransportEvent::AssociationLost(reason) => panic!("Xn association lost: {}", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xn_setup_failures() {
+        let (node, _ngap_rx) = xnap_node(0x19B, 1, Vec::new()).await;
+        let (peer, _peer_ngap_rx) = xnap_node(0x19C, 2, Vec::new()).await;
+        let ((sender, _rx), (_peer_tx, mut peer_rx)) = xn_association().await;
+        let sender = Arc::new(sender);
+
+        // Request without TAI Support List
+        let request = peer.xn_setup_request().unwrap();
+        let mut incomplete = request.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_TAI_SUPPORT_LIST);
+        assert!(matches!(node.handle_xn_setup_request(&incomplete, &sender).await, Err(LayerError::ProcessingError(_))));
+
+        // A neighbour of another PLMN is rejected
+        let mut foreign = request.clone();
+        foreign.ies.retain(|ie| ie.id != pdu::ID_TAI_SUPPORT_LIST);
+        foreign.add_ie(pdu::ID_TAI_SUPPORT_LIST, Criticality::Reject, &TaiSupportList(vec![SupportedTaItem {
+            tac: 7,
+            broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: [0x00, 0xF1, 0x10], slices: vec![SNssai { sst: 1, sd: None }] }],
+        }])).unwrap();
+        node.handle_xn_setup_request(&foreign, &sender).await.unwrap();
+        let failure = recv_pdu(&mut peer_rx).await;
+        assert_eq!(failure.pdu_type, XnapPduType::UnsuccessfulOutcome);
+        assert_eq!(failure.ie::<Cause>(pdu::ID_CAUSE).unwrap(), Cause::Misc(4));
+        assert_eq!(failure.ie::<TimeToWait>(pdu::ID_TIME_TO_WAIT).unwrap(), TimeToWait::V60s);
+        assert!(node.neighbours().await.is_empty());
+        assert!(matches!(peer.handle_xn_setup_outcome(&failure, &sender).await, Err(LayerError::InitializationFailed(_))));
+        assert!(peer.neighbours().await.is_empty());
+
+        // Failure without Cause, response without Global NG-RAN Node ID or with
+        // an undecodable cell list
+        let failure = XnapPdu::unsuccessful(XnapProcedureCode::XnSetup);
+        assert!(matches!(peer.handle_xn_setup_outcome(&failure, &sender).await, Err(LayerError::ProcessingError(_))));
+        let mut response = node.setup_message(XnapPdu::successful(XnapProcedureCode::XnSetup)).unwrap();
+        let mut incomplete = response.clone();
+        incomplete.ies.retain(|ie| ie.id != pdu::ID_GLOBAL_NG_RAN_NODE_ID);
+        assert!(matches!(peer.handle_xn_setup_outcome(&incomplete, &sender).await, Err(LayerError::ProcessingError(_))));
+        response.ies[2].value = Bytes::new();
+        assert!(peer.handle_xn_setup_outcome(&response, &sender).await.is_err());
+        assert!(peer.neighbours().await.is_empty());
+
+        // A repeated setup from the same gNB replaces its association
+        node.handle_xn_setup_request(&request, &sender).await.unwrap();
+        assert_eq!(recv_pdu(&mut peer_rx).await.pdu_type, XnapPduType::SuccessfulOutcome);
+        let ((other, _other_rx), _other_peer) = xn_association().await;
+        let other = Arc::new(other);
+        node.handle_xn_setup_request(&request, &other).await.unwrap();
+        let neighbours = node.neighbours.read().await;
+        assert_eq!(neighbours.len(), 1);
+        assert!(Arc::ptr_eq(&neighbours[0].sender, &other));
+    }
+}
@@ -0,0 +1,58 @@
+//! Xn transport
+//!
+//! Carries XnAP over SCTP as specified by 3GPP TS 38.422: either NG-RAN node may
+//! open the association, payload protocol identifier 61, stream 0 for non
+//! UE-associated signalling.
+
+use super::XnapProcedureCode;
+use crate::ngap::transport::{self, ApListener, ApReceiver, ApSender, NgTransportKind, SctpProtocol, TransportEvent};
+use crate::LayerError;
+use std::net::SocketAddr;
+
+/// SCTP payload protocol identifier of XnAP (TS 38.422 section 7)
+pub const XNAP_PPID: u32 = 61;
+/// SCTP destination port of XnAP (TS 38.422 section 7)
+pub const XNAP_PORT: u16 = 38422;
+/// SCTP stream reserved for non UE-associated signalling (TS 38.422 section 7)
+pub const NON_UE_STREAM: u16 = 0;
+/// SCTP stream used for UE-associated signalling
+pub const UE_STREAM: u16 = 1;
+
+impl SctpProtocol for XnapProcedureCode {
+    const INTERFACE: &'static str = "Xn-C";
+    const PPID: u32 = XNAP_PPID;
+    const NUM_STREAMS: u16 = 2;
+}
+
+/// Event received on the Xn transport
+pub type XnTransportEvent = TransportEvent;
+/// Sending side of an Xn association
+pub type XnSender = ApSender<XnapProcedureCode>;
+/// Receiving side of an Xn association
+pub type XnReceiver = ApReceiver<XnapProcedureCode>;
+/// Listening socket for Xn associations opened by neighbours
+pub type XnListener = ApListener<XnapProcedureCode>;
+
+/// Open an Xn association towards a neighbour NG-RAN node
+pub async fn connect(
+    kind: NgTransportKind,
+    local_address: SocketAddr,
+    peer_address: SocketAddr,
+) -> Result<(XnSender, XnReceiver), LayerError> {
+    transport::connect(kind, local_address, peer_address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xn_sctp_parameters() {
+        // TS 38.422 section 7, UE-associated signalling on its own stream
+        assert_eq!(XnapProcedureCode::PPID, 61);
+        assert_eq!(XNAP_PORT, 38422);
+        assert_eq!(XnapProcedureCode::NUM_STREAMS, 2);
+        assert_ne!(NON_UE_STREAM, UE_STREAM);
+        assert!(UE_STREAM < XnapProcedureCode::NUM_STREAMS);
+    }
+}
@@ -0,0 +1,556 @@
+//! Scripted AMF
+//!
+//! Plays the AMF side of one gNB association: answers NG Setup, registers each
+//! UE with 5G AKA (Authentication, Security Mode, Registration Accept in Initial
+//! Context Setup) and sets up the PDU session the UE asks for. Every PDU from
+//! the gNB is checked against the script; anything unexpected ends the run
+//! with an error describing what was received.
+
+use crate::association::{AmfListener, GnbAssociation};
+use crate::keys::{self, AuthenticationVector};
+use crate::milenage::Milenage;
+use crate::nas::{self, MmMessage, NasSecurityContext, SecurityHeaderType, SmMessage};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bytes::Bytes;
+use common::types::{
+    AggregateMaximumBitRate, AllocationRetentionPriority, FiveQi, QosCharacteristics, QosFlowDescriptor, SNssai,
+};
+use layers::ngap::pdu::{
+    self, AllowedNssai, AmfUeNgapId, Criticality, GlobalGnbId, GtpTunnel, Guami, NgapPdu, NgapPduType, NodeName,
+    PduSessionResourceItem, PduSessionResourceSetupItem, PduSessionResourceSetupRequestTransfer,
+    PduSessionResourceSetupResponseTransfer, PduSessionType, PlmnSupportItem, RanUeNgapId, RelativeAmfCapacity,
+    SecurityKey, ServedGuamiItem, SupportedTaItem, UeSecurityCapabilities, UserLocationInformationNr,
+};
+use layers::ngap::transport::{NgTransportKind, NON_UE_STREAM};
+use layers::ngap::NgapProcedureCode;
+use layers::rrc::{CipheringAlgorithm, IntegrityAlgorithm};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tracing::{debug, info, warn};
+
+/// Subscriber provisioned in the AMF and on the USIM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscriber {
+    /// IMSI digits
+    pub imsi: String,
+    /// Permanent key K
+    pub k: [u8; 16],
+    /// Operator key OPc
+    pub opc: [u8; 16],
+    /// Next sequence number to use
+    pub sqn: u64,
+}
+
+impl Default for Subscriber {
+    /// Test subscriber with the keys of TS 35.208 test set 1
+    fn default() -> Self {
+        Self {
+            imsi: "999700000000001".to_string(),
+            k: [0x46, 0x5b, 0x5c, 0xe8, 0xb1, 0x99, 0xb4, 0x9f, 0xaa, 0x5f, 0x0a, 0x2e, 0xe2, 0x38, 0xa6, 0xbc],
+            opc: [0xcd, 0x63, 0xcb, 0x71, 0x95, 0x4a, 0x9f, 0x4e, 0x48, 0xa5, 0x99, 0x4e, 0x37, 0xa0, 0x2b, 0xaf],
+            sqn: 0x21,
+        }
+    }
+}
+
+/// Scripted AMF configuration
+#[derive(Debug, Clone)]
+pub struct MockAmfConfig {
+    /// Address to listen on
+    pub listen: SocketAddr,
+    /// SCTP or the TCP framing
+    pub transport: NgTransportKind,
+    /// AMF Name sent in NG Setup Response
+    pub amf_name: String,
+    /// Served GUAMI, its PLMN is the home network
+    pub guami: Guami,
+    /// Slices supported in the PLMN
+    pub slices: Vec<SNssai>,
+    /// Subscribers allowed to register
+    pub subscribers: Vec<Subscriber>,
+    /// UPF endpoint of the N3 tunnels
+    pub upf_address: IpAddr,
+    /// IPv4 address assigned to the first PDU session
+    pub first_ue_address: Ipv4Addr,
+}
+
+impl Default for MockAmfConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 38412)),
+            transport: NgTransportKind::Sctp,
+            amf_name: "mock-amf".to_string(),
+            guami: Guami { plmn_id: [0x99, 0xF9, 0x07], amf_region_id: 2, amf_set_id: 1, amf_pointer: 0 },
+            slices: vec![SNssai { sst: 1, sd: None }],
+            subscribers: vec![Subscriber::default()],
+            upf_address: IpAddr::from([127, 0, 0, 1]),
+            first_ue_address: Ipv4Addr::new(10, 45, 0, 2),
+        }
+    }
+}
+
+/// PDU session set up by the script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EstablishedSession {
+    pub pdu_session_id: u8,
+    pub s_nssai: SNssai,
+    /// UPF endpoint sent to the gNB
+    pub ul_tunnel: GtpTunnel,
+    /// gNB endpoint from the setup response
+    pub dl_tunnel: GtpTunnel,
+    pub ue_address: Ipv4Addr,
+}
+
+/// Outcome of the script for one UE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredUe {
+    pub supi: String,
+    pub ran_ue_ngap_id: u32,
+    pub amf_ue_ngap_id: u64,
+    /// K_gNB sent in Initial Context Setup Request, or NH once handed over
+    pub k_gnb: [u8; 32],
+    /// Registration Complete was received
+    pub registration_complete: bool,
+    pub sessions: Vec<EstablishedSession>,
+}
+
+/// What the gNB sent during a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    /// RAN Node Name from NG Setup Request
+    pub ran_node_name: Option<String>,
+    /// Global gNB ID from NG Setup Request
+    pub gnb_id: Option<GlobalGnbId>,
+    /// Procedures of the PDUs received, in order
+    pub received: Vec<(NgapPduType, NgapProcedureCode)>,
+    /// UEs in the order they were authenticated
+    pub ues: Vec<RegisteredUe>,
+}
+
+impl Transcript {
+    /// Number of PDU sessions set up
+    pub fn num_sessions(&self) -> usize {
+        self.ues.iter().map(|ue| ue.sessions.len()).sum()
+    }
+}
+
+/// Step of the registration a UE is in
+pub(crate) enum UeState {
+    Authenticating { vector: AuthenticationVector, supi: String, security_capability: Vec<u8> },
+    SecurityMode { k_amf: [u8; 32], security: NasSecurityContext },
+    Registered { k_amf: [u8; 32], security: NasSecurityContext },
+}
+
+/// UE known to the script
+pub(crate) struct UeEntry {
+    pub(crate) ran_ue_ngap_id: u32,
+    pub(crate) amf_ue_ngap_id: u64,
+    pub(crate) stream: u16,
+    pub(crate) state: UeState,
+    /// Index in the transcript once registered
+    pub(crate) registered: Option<usize>,
+    /// PDU sessions requested but not set up yet
+    pub(crate) pending_sessions: HashMap<u8, (SNssai, Ipv4Addr)>,
+}
+
+/// gNB served by the script, with what it sent and its UEs by RAN UE NGAP ID
+pub(crate) struct ServedGnb {
+    pub(crate) association: GnbAssociation,
+    pub(crate) transcript: Transcript,
+    pub(crate) ues: HashMap<u32, UeEntry>,
+}
+
+impl ServedGnb {
+    /// Receive and record the next PDU, None once the gNB closed the association
+    pub(crate) async fn recv(&mut self) -> Result<Option<(u16, NgapPdu)>> {
+        let Some((stream, payload)) = self.association.recv().await? else {
+            return Ok(None);
+        };
+        let pdu = NgapPdu::decode(&payload).context("Undecodable NGAP PDU from the gNB")?;
+        let procedure = pdu.procedure()
+            .ok_or_else(|| anyhow!("Unknown procedure code {}", pdu.procedure_code))?;
+        self.transcript.received.push((pdu.pdu_type, procedure));
+        debug!("Received {:?} {:?} on stream {} from {}", pdu.pdu_type, procedure, stream, self.association.peer);
+        Ok(Some((stream, pdu)))
+    }
+
+    /// Receive the next PDU, failing unless it is of the given procedure
+    pub(crate) async fn expect(&mut self, pdu_type: NgapPduType, procedure: NgapProcedureCode) -> Result<(u16, NgapPdu)> {
+        let Some((stream, pdu)) = self.recv().await? else {
+            bail!("gNB {} closed the association", self.association.peer);
+        };
+        if pdu.pdu_type != pdu_type || pdu.procedure() != Some(procedure) {
+            let cause = pdu.optional_ie::<pdu::Cause>(pdu::ID_CAUSE).ok().flatten();
+            bail!("Expected {:?} {:?}, got {:?} {:?} (cause {:?})", pdu_type, procedure, pdu.pdu_type,
+                  pdu.procedure(), cause);
+        }
+        Ok((stream, pdu))
+    }
+}
+
+/// Scripted AMF listening for gNBs
+pub struct MockAmf {
+    pub(crate) config: MockAmfConfig,
+    listener: AmfListener,
+    pub(crate) next_amf_ue_ngap_id: u64,
+    next_ue_address: u32,
+}
+
+impl MockAmf {
+    /// Start listening
+    pub async fn bind(config: MockAmfConfig) -> Result<Self> {
+        let listener = AmfListener::bind(config.transport, config.listen).await
+            .with_context(|| format!("Failed to listen on {}", config.listen))?;
+        info!("Mock AMF {} listening on {} ({:?})", config.amf_name, config.listen, config.transport);
+        let next_ue_address = u32::from(config.first_ue_address);
+        Ok(Self { config, listener, next_amf_ue_ngap_id: 1, next_ue_address })
+    }
+
+    /// Address gNBs connect to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap_or(self.config.listen)
+    }
+
+    /// Accept one gNB and run the script with it
+    ///
+    /// Returns once `sessions` PDU sessions are set up or, with `None`, once the gNB
+    /// closes the association.
+    pub async fn serve_gnb(&mut self, sessions: Option<usize>) -> Result<Transcript> {
+        let mut gnb = self.accept_gnb().await?;
+        self.register(&mut gnb, sessions).await?;
+        Ok(gnb.transcript)
+    }
+
+    /// Accept the next gNB and answer its NG Setup
+    pub(crate) async fn accept_gnb(&mut self) -> Result<ServedGnb> {
+        let association = self.listener.accept().await?;
+        info!("gNB associated from {}", association.peer);
+        let mut gnb = ServedGnb { association, transcript: Transcript::default(), ues: HashMap::new() };
+        self.ng_setup(&mut gnb.association, &mut gnb.transcript).await?;
+        Ok(gnb)
+    }
+
+    /// Register the UEs of a gNB until `sessions` PDU sessions are set up or, with
+    /// `None`, until the gNB closes the association
+    pub(crate) async fn register(&mut self, gnb: &mut ServedGnb, sessions: Option<usize>) -> Result<()> {
+        while sessions.is_none_or(|sessions| gnb.transcript.num_sessions() < sessions) {
+            let Some((stream, pdu)) = gnb.recv().await? else {
+                info!("gNB {} closed the association", gnb.association.peer);
+                break;
+            };
+            let procedure = pdu.procedure()
+                .ok_or_else(|| anyhow!("Unknown procedure code {}", pdu.procedure_code))?;
+            let ServedGnb { association, transcript, ues } = gnb;
+            match (pdu.pdu_type, procedure) {
+                (NgapPduType::InitiatingMessage, NgapProcedureCode::InitialUeMessage) => {
+                    self.handle_initial_ue_message(association, ues, stream, &pdu).await?
+                }
+                (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkNasTransport) => {
+                    let ue = ue_entry(ues, &pdu)?;
+                    let nas_pdu = pdu.ie::<Bytes>(pdu::ID_NAS_PDU)?;
+                    self.handle_uplink_nas(association, transcript, ue, &nas_pdu).await?
+                }
+                (NgapPduType::SuccessfulOutcome, NgapProcedureCode::InitialContextSetup) => {
+                    let ue = ue_entry(ues, &pdu)?;
+                    ensure!(ue.registered.is_some(), "Initial Context Setup Response before the Registration Accept");
+                    info!("Initial Context Setup complete for AMF UE NGAP ID {}", ue.amf_ue_ngap_id);
+                }
+                (NgapPduType::SuccessfulOutcome, NgapProcedureCode::PduSessionResourceSetup) => {
+                    let ue = ue_entry(ues, &pdu)?;
+                    handle_pdu_session_resource_setup_response(&self.config, transcript, ue, &pdu)?
+                }
+                (NgapPduType::InitiatingMessage, NgapProcedureCode::UeRadioCapabilityInfoIndication) => {
+                    debug!("UE Radio Capability Info Indication received");
+                }
+                (NgapPduType::UnsuccessfulOutcome, procedure) => {
+                    let cause = pdu.optional_ie::<pdu::Cause>(pdu::ID_CAUSE)?;
+                    bail!("gNB answered {:?} with a failure: {:?}", procedure, cause);
+                }
+                (NgapPduType::InitiatingMessage, NgapProcedureCode::ErrorIndication) => {
+                    let cause = pdu.optional_ie::<pdu::Cause>(pdu::ID_CAUSE)?;
+                    bail!("gNB sent Error Indication: {:?}", cause);
+                }
+                (pdu_type, procedure) => warn!("Ignoring {:?} {:?} from the gNB", pdu_type, procedure),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer the NG Setup Request, checking the gNB serves the AMF's PLMN
+    async fn ng_setup(&self, association: &mut GnbAssociation, transcript: &mut Transcript) -> Result<()> {
+        let (stream, payload) = association.expect().await?;
+        let request = NgapPdu::decode(&payload)?;
+        ensure!(request.pdu_type == NgapPduType::InitiatingMessage && request.procedure() == Some(NgapProcedureCode::NgSetup),
+                "Expected NG Setup Request, got {:?} {:?}", request.pdu_type, request.procedure());
+        ensure!(stream == NON_UE_STREAM, "NG Setup Request on UE-associated stream {}", stream);
+        transcript.received.push((request.pdu_type, NgapProcedureCode::NgSetup));
+
+        let gnb_id = request.ie::<GlobalGnbId>(pdu::ID_GLOBAL_RAN_NODE_ID)?;
+        let ran_node_name = request.optional_ie::<NodeName>(pdu::ID_RAN_NODE_NAME)?.map(|name| name.0);
+        let supported_tas = request.ie::<Vec<SupportedTaItem>>(pdu::ID_SUPPORTED_TA_LIST)?;
+        let plmn_id = self.config.guami.plmn_id;
+        ensure!(supported_tas.iter().flat_map(|ta| &ta.broadcast_plmns).any(|plmn| plmn.plmn_id == plmn_id),
+                "gNB does not serve PLMN {:02x?}", plmn_id);
+        info!("NG Setup Request from gNB {:#x} ({})", gnb_id.gnb_id, ran_node_name.as_deref().unwrap_or("unnamed"));
+        transcript.gnb_id = Some(gnb_id);
+        transcript.ran_node_name = ran_node_name;
+
+        let response = NgapPdu::successful(NgapProcedureCode::NgSetup)
+            .with_ie(pdu::ID_AMF_NAME, Criticality::Reject, &NodeName(self.config.amf_name.clone()))?
+            .with_ie(pdu::ID_SERVED_GUAMI_LIST, Criticality::Reject,
+                     &vec![ServedGuamiItem { guami: self.config.guami, backup_amf_name: None }])?
+            .with_ie(pdu::ID_RELATIVE_AMF_CAPACITY, Criticality::Ignore, &RelativeAmfCapacity(255))?
+            .with_ie(pdu::ID_PLMN_SUPPORT_LIST, Criticality::Reject,
+                     &vec![PlmnSupportItem { plmn_id, slices: self.config.slices.clone() }])?;
+        association.send(NON_UE_STREAM, &response.encode()?).await
+    }
+
+    /// Start authentication of a UE registering with its SUCI
+    async fn handle_initial_ue_message(
+        &mut self,
+        association: &mut GnbAssociation,
+        ues: &mut HashMap<u32, UeEntry>,
+        stream: u16,
+        pdu: &NgapPdu,
+    ) -> Result<()> {
+        let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let nas_pdu = pdu.ie::<Bytes>(pdu::ID_NAS_PDU)?;
+        let location = pdu.ie::<UserLocationInformationNr>(pdu::ID_USER_LOCATION_INFORMATION)?;
+        ensure!(location.tai.plmn_id == self.config.guami.plmn_id, "UE located in another PLMN: {:?}", location.tai);
+        ensure!(!ues.contains_key(&ran_ue_ngap_id), "RAN UE NGAP ID {} already in use", ran_ue_ngap_id);
+
+        let MmMessage::RegistrationRequest { suci, security_capability, .. } = MmMessage::decode(&nas_pdu)? else {
+            bail!("Initial UE Message does not carry a Registration Request");
+        };
+        let supi = suci.supi();
+        let subscriber = self.config.subscribers.iter_mut()
+            .find(|subscriber| subscriber.imsi == supi)
+            .ok_or_else(|| anyhow!("Unknown subscriber {}", supi))?;
+        let sqn = subscriber.sqn;
+        subscriber.sqn += 1;
+        let milenage = Milenage::new(&subscriber.k, &subscriber.opc);
+        let serving_network_name = keys::serving_network_name(&self.config.guami.plmn_id);
+        let rand: [u8; 16] = rand_challenge(self.next_amf_ue_ngap_id, sqn);
+        let vector = AuthenticationVector::generate(&milenage, rand, sqn, &serving_network_name);
+
+        let amf_ue_ngap_id = self.next_amf_ue_ngap_id;
+        self.next_amf_ue_ngap_id += 1;
+        info!("Registration Request from {} (RAN UE NGAP ID {}), AMF UE NGAP ID {}", supi, ran_ue_ngap_id, amf_ue_ngap_id);
+
+        let request = MmMessage::AuthenticationRequest { ngksi: 0, rand: vector.rand, autn: vector.autn }.encode();
+        let ue = UeEntry {
+            ran_ue_ngap_id,
+            amf_ue_ngap_id,
+            stream,
+            state: UeState::Authenticating { vector, supi, security_capability },
+            registered: None,
+            pending_sessions: HashMap::new(),
+        };
+        send_downlink_nas(association, &ue, request).await?;
+        ues.insert(ran_ue_ngap_id, ue);
+        Ok(())
+    }
+
+    /// Advance the registration of a UE with its next uplink NAS message
+    async fn handle_uplink_nas(
+        &mut self,
+        association: &mut GnbAssociation,
+        transcript: &mut Transcript,
+        ue: &mut UeEntry,
+        nas_pdu: &[u8],
+    ) -> Result<()> {
+        match &mut ue.state {
+            UeState::Authenticating { vector, supi, security_capability } => {
+                let MmMessage::AuthenticationResponse { res_star } = MmMessage::decode(nas_pdu)? else {
+                    bail!("Expected Authentication Response from {}", supi);
+                };
+                ensure!(res_star == vector.xres_star, "RES* of {} does not match XRES*", supi);
+                let serving_network_name = keys::serving_network_name(&self.config.guami.plmn_id);
+                let k_amf = keys::k_amf(&vector.k_ausf, &serving_network_name, supi);
+                let mut security = NasSecurityContext::new(&k_amf, CipheringAlgorithm::Nea0, IntegrityAlgorithm::Nia2);
+                let command = MmMessage::SecurityModeCommand {
+                    ngksi: 0,
+                    ciphering: security.ciphering,
+                    integrity: security.integrity,
+                    replayed_security_capability: security_capability.clone(),
+                }.encode();
+                let command = security.protect(SecurityHeaderType::IntegrityProtectedWithNewContext, &command, nas::DOWNLINK)?;
+                info!("{} authenticated, sending Security Mode Command", supi);
+                transcript.ues.push(RegisteredUe {
+                    supi: supi.clone(),
+                    ran_ue_ngap_id: ue.ran_ue_ngap_id,
+                    amf_ue_ngap_id: ue.amf_ue_ngap_id,
+                    k_gnb: [0; 32],
+                    registration_complete: false,
+                    sessions: Vec::new(),
+                });
+                ue.registered = Some(transcript.ues.len() - 1);
+                send_downlink_nas(association, ue, command).await?;
+                ue.state = UeState::SecurityMode { k_amf, security };
+            }
+            UeState::SecurityMode { k_amf, security } => {
+                // K_gNB is bound to the uplink NAS COUNT of the Security Mode Complete
+                let ul_count = security.ul_count;
+                let plain = security.unprotect(nas_pdu, nas::UPLINK)?;
+                ensure!(MmMessage::decode(&plain)? == MmMessage::SecurityModeComplete, "Expected Security Mode Complete");
+                let k_gnb = keys::k_gnb(k_amf, ul_count);
+                let k_amf = *k_amf;
+                let mut security = security.clone();
+                let accept = MmMessage::RegistrationAccept {
+                    guti: nas::Guti { guami: self.config.guami, tmsi: 0xC000_0000 | ue.amf_ue_ngap_id as u32 },
+                    allowed_nssai: self.config.slices.clone(),
+                }.encode();
+                let accept = security.protect(SecurityHeaderType::IntegrityProtectedAndCiphered, &accept, nas::DOWNLINK)?;
+                self.send_initial_context_setup_request(association, ue, k_gnb, accept).await?;
+                let index = ue.registered.ok_or_else(|| anyhow!("UE not in the transcript"))?;
+                transcript.ues[index].k_gnb = k_gnb;
+                ue.state = UeState::Registered { k_amf, security };
+            }
+            UeState::Registered { security, .. } => {
+                let plain = security.unprotect(nas_pdu, nas::UPLINK)?;
+                let index = ue.registered.ok_or_else(|| anyhow!("UE not in the transcript"))?;
+                match MmMessage::decode(&plain)? {
+                    MmMessage::RegistrationComplete => {
+                        info!("Registration complete for {}", transcript.ues[index].supi);
+                        transcript.ues[index].registration_complete = true;
+                    }
+                    MmMessage::UlNasTransport { pdu_session_id, payload, s_nssai, dnn } => {
+                        let SmMessage::PduSessionEstablishmentRequest { pti, .. } = SmMessage::decode(&payload)? else {
+                            bail!("Unexpected 5GSM message in UL NAS Transport");
+                        };
+                        let s_nssai = s_nssai.or_else(|| self.config.slices.first().cloned())
+                            .ok_or_else(|| anyhow!("No slice for PDU session {}", pdu_session_id))?;
+                        ensure!(self.config.slices.contains(&s_nssai), "Slice {:?} not supported", s_nssai);
+                        let ue_address = Ipv4Addr::from(self.next_ue_address);
+                        self.next_ue_address += 1;
+                        info!("PDU Session Establishment Request {} for DNN {:?}, assigning {}",
+                              pdu_session_id, dnn, ue_address);
+
+                        let accept = SmMessage::PduSessionEstablishmentAccept {
+                            pdu_session_id, pti, qfi: 1, session_ambr_mbps: (100, 100), address: ue_address,
+                        }.encode();
+                        let transport = MmMessage::DlNasTransport { pdu_session_id, payload: accept }.encode();
+                        let transport = security.protect(SecurityHeaderType::IntegrityProtectedAndCiphered, &transport, nas::DOWNLINK)?;
+                        ue.pending_sessions.insert(pdu_session_id, (s_nssai.clone(), ue_address));
+                        let item = PduSessionResourceSetupItem {
+                            pdu_session_id,
+                            nas_pdu: Some(Bytes::from(transport)),
+                            s_nssai,
+                            transfer: self.setup_request_transfer(ue.amf_ue_ngap_id, pdu_session_id),
+                        };
+                        let request = NgapPdu::initiating(NgapProcedureCode::PduSessionResourceSetup)
+                            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+                            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue.ran_ue_ngap_id))?
+                            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_REQ, Criticality::Reject, &vec![item])?;
+                        association.send(ue.stream, &request.encode()?).await?;
+                    }
+                    other => bail!("Unexpected NAS message from a registered UE: {:?}", other),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// UPF tunnel and default QoS flow of a PDU session
+    pub(crate) fn setup_request_transfer(&self, amf_ue_ngap_id: u64, pdu_session_id: u8) -> PduSessionResourceSetupRequestTransfer {
+        PduSessionResourceSetupRequestTransfer {
+            session_ambr: Some(AggregateMaximumBitRate { dl: 100_000_000, ul: 100_000_000 }),
+            ul_tunnel: ul_tunnel(&self.config, amf_ue_ngap_id, pdu_session_id),
+            pdu_session_type: PduSessionType::Ipv4,
+            qos_flows: vec![QosFlowDescriptor {
+                qfi: 1,
+                characteristics: QosCharacteristics::NonDynamic { five_qi: FiveQi::DEFAULT, priority_level: None },
+                arp: AllocationRetentionPriority { priority_level: 8, may_trigger_pre_emption: false, pre_emptable: false },
+                gbr: None,
+            }],
+        }
+    }
+
+    /// Send Initial Context Setup Request with K_gNB and the Registration Accept
+    async fn send_initial_context_setup_request(
+        &self,
+        association: &mut GnbAssociation,
+        ue: &UeEntry,
+        k_gnb: [u8; 32],
+        registration_accept: Vec<u8>,
+    ) -> Result<()> {
+        let request = NgapPdu::initiating(NgapProcedureCode::InitialContextSetup)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue.ran_ue_ngap_id))?
+            .with_ie(pdu::ID_GUAMI, Criticality::Reject, &self.config.guami)?
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(self.config.slices.clone()))?
+            .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Reject, &ue_security_capabilities())?
+            .with_ie(pdu::ID_SECURITY_KEY, Criticality::Reject, &SecurityKey(k_gnb))?
+            .with_ie(pdu::ID_NAS_PDU, Criticality::Ignore, &Bytes::from(registration_accept))?;
+        info!("Sending Initial Context Setup Request for AMF UE NGAP ID {}", ue.amf_ue_ngap_id);
+        association.send(ue.stream, &request.encode()?).await
+    }
+}
+
+/// Record the gNB tunnels of the PDU sessions set up for a UE
+fn handle_pdu_session_resource_setup_response(
+    config: &MockAmfConfig,
+    transcript: &mut Transcript,
+    ue: &mut UeEntry,
+    pdu: &NgapPdu,
+) -> Result<()> {
+    if let Some(failed) = pdu.optional_ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_FAILED_TO_SETUP_LIST_SU_RES)? {
+        bail!("gNB failed to set up PDU sessions {:?}", failed.iter().map(|item| item.pdu_session_id).collect::<Vec<_>>());
+    }
+    let index = ue.registered.ok_or_else(|| anyhow!("PDU Session Resource Setup Response for an unregistered UE"))?;
+    for item in pdu.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_SU_RES)? {
+        let transfer = item.decode_transfer::<PduSessionResourceSetupResponseTransfer>()?;
+        let (s_nssai, ue_address) = ue.pending_sessions.remove(&item.pdu_session_id)
+            .ok_or_else(|| anyhow!("PDU session {} was not requested", item.pdu_session_id))?;
+        ensure!(transfer.qos_flows == [1], "Unexpected QoS flows {:?} for PDU session {}", transfer.qos_flows, item.pdu_session_id);
+        info!("PDU session {} set up, gNB tunnel {}/{:#x}", item.pdu_session_id,
+              transfer.dl_tunnel.transport_layer_address, transfer.dl_tunnel.teid);
+        transcript.ues[index].sessions.push(EstablishedSession {
+            pdu_session_id: item.pdu_session_id,
+            s_nssai,
+            ul_tunnel: ul_tunnel(config, ue.amf_ue_ngap_id, item.pdu_session_id),
+            dl_tunnel: transfer.dl_tunnel,
+            ue_address,
+        });
+    }
+    Ok(())
+}
+
+/// NR algorithms of the UE handed to the gNB: NEA1-3 and NIA1-3
+pub(crate) fn ue_security_capabilities() -> UeSecurityCapabilities {
+    UeSecurityCapabilities { nr_encryption_algorithms: 0xE000, nr_integrity_algorithms: 0xE000, ..Default::default() }
+}
+
+/// UPF endpoint of a PDU session, one TEID per UE and session
+fn ul_tunnel(config: &MockAmfConfig, amf_ue_ngap_id: u64, pdu_session_id: u8) -> GtpTunnel {
+    GtpTunnel {
+        transport_layer_address: config.upf_address,
+        teid: ((amf_ue_ngap_id as u32) << 8) | pdu_session_id as u32,
+    }
+}
+
+/// UE entry addressed by a UE-associated PDU, checking both NGAP IDs
+pub(crate) fn ue_entry<'a>(ues: &'a mut HashMap<u32, UeEntry>, pdu: &NgapPdu) -> Result<&'a mut UeEntry> {
+    let amf_ue_ngap_id = pdu.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0;
+    let ran_ue_ngap_id = pdu.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+    ues.get_mut(&ran_ue_ngap_id)
+        .filter(|ue| ue.amf_ue_ngap_id == amf_ue_ngap_id)
+        .ok_or_else(|| anyhow!("Unknown UE (AMF UE NGAP ID {}, RAN UE NGAP ID {})", amf_ue_ngap_id, ran_ue_ngap_id))
+}
+
+async fn send_downlink_nas(association: &mut GnbAssociation, ue: &UeEntry, nas_pdu: Vec<u8>) -> Result<()> {
+    let pdu = NgapPdu::initiating(NgapProcedureCode::DownlinkNasTransport)
+        .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+        .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(ue.ran_ue_ngap_id))?
+        .with_ie(pdu::ID_NAS_PDU, Criticality::Reject, &Bytes::from(nas_pdu))?;
+    association.send(ue.stream, &pdu.encode()?).await
+}
+
+/// RAND of a challenge; a scripted run needs distinct challenges, not unpredictable ones
+fn rand_challenge(amf_ue_ngap_id: u64, sqn: u64) -> [u8; 16] {
+    let mut rand = [0u8; 16];
+    rand[..8].copy_from_slice(&amf_ue_ngap_id.to_be_bytes());
+    rand[8..].copy_from_slice(&sqn.to_be_bytes());
+    rand
+}
+
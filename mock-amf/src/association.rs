@@ -0,0 +1,129 @@
+//! AMF side of the NG-C transport
+//!
+//! Listens for gNB associations over kernel SCTP (PPID 60, TS 38.412) or over the
+//! TCP framing of `layers::ngap::transport`, and exchanges NGAP PDUs on them.
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use layers::ngap::transport::{encode_frame, read_frame, NgTransportKind, NGAP_PPID};
+use sctp_rs::{ConnectedSocket, Listener, NotificationOrData, SendData, SendInfo, Socket, SocketToAssociation};
+use std::net::SocketAddr;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+/// Number of SCTP streams offered to the gNB in each direction
+const NUM_STREAMS: u16 = 4;
+
+/// Listening socket of the AMF
+pub enum AmfListener {
+    Sctp(Listener),
+    Tcp(TcpListener),
+}
+
+impl AmfListener {
+    /// Listen for gNB associations
+    pub async fn bind(kind: NgTransportKind, address: SocketAddr) -> Result<Self> {
+        let listener = match kind {
+            NgTransportKind::Sctp => {
+                let socket = match address {
+                    SocketAddr::V4(_) => Socket::new_v4(SocketToAssociation::OneToOne)?,
+                    SocketAddr::V6(_) => Socket::new_v6(SocketToAssociation::OneToOne)?,
+                };
+                socket.sctp_setup_init_params(NUM_STREAMS, NUM_STREAMS, 0, 0)?;
+                socket.bind(address)?;
+                AmfListener::Sctp(socket.listen(8)?)
+            }
+            NgTransportKind::TcpFramed => AmfListener::Tcp(TcpListener::bind(address).await?),
+        };
+        Ok(listener)
+    }
+
+    /// Local address of a TCP listener; SCTP listeners report the bound address
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            AmfListener::Sctp(_) => None,
+            AmfListener::Tcp(listener) => listener.local_addr().ok(),
+        }
+    }
+
+    /// Wait for the next gNB association
+    pub async fn accept(&self) -> Result<GnbAssociation> {
+        let association = match self {
+            AmfListener::Sctp(listener) => {
+                let (socket, peer) = listener.accept().await.context("SCTP accept failed")?;
+                socket.sctp_request_rcvinfo(true)?;
+                GnbAssociation { peer, link: Link::Sctp(socket) }
+            }
+            AmfListener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await.context("TCP accept failed")?;
+                stream.set_nodelay(true)?;
+                GnbAssociation { peer, link: Link::Tcp(stream) }
+            }
+        };
+        Ok(association)
+    }
+}
+
+enum Link {
+    Sctp(ConnectedSocket),
+    Tcp(TcpStream),
+}
+
+/// Association with one gNB
+pub struct GnbAssociation {
+    /// Address of the gNB
+    pub peer: SocketAddr,
+    link: Link,
+}
+
+impl GnbAssociation {
+    /// Send an NGAP PDU on a stream
+    pub async fn send(&mut self, stream: u16, pdu: &[u8]) -> Result<()> {
+        match &mut self.link {
+            Link::Sctp(socket) => {
+                let snd_info = SendInfo { sid: stream, ppid: NGAP_PPID.to_be(), ..Default::default() };
+                socket.sctp_send(SendData { payload: pdu.to_vec(), snd_info: Some(snd_info) }).await?;
+            }
+            Link::Tcp(tcp) => tcp.write_all(&encode_frame(stream, NGAP_PPID, pdu)).await?,
+        }
+        debug!("Sent NGAP PDU ({} bytes) on stream {} to {}", pdu.len(), stream, self.peer);
+        Ok(())
+    }
+
+    /// Receive the next NGAP PDU with its stream, None once the gNB closed the association
+    pub async fn recv(&mut self) -> Result<Option<(u16, Bytes)>> {
+        loop {
+            let (stream, ppid, payload) = match &mut self.link {
+                Link::Sctp(socket) => match socket.sctp_recv().await? {
+                    NotificationOrData::Data(data) if data.payload.is_empty() => return Ok(None),
+                    NotificationOrData::Data(data) => {
+                        let (stream, ppid) = data.rcv_info.map_or((0, NGAP_PPID), |info| (info.sid, u32::from_be(info.ppid)));
+                        (stream, ppid, Bytes::from(data.payload))
+                    }
+                    NotificationOrData::Notification(notification) => {
+                        debug!("Ignoring SCTP notification {:?}", notification);
+                        continue;
+                    }
+                },
+                Link::Tcp(tcp) => match read_frame(tcp).await? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                },
+            };
+            if ppid != NGAP_PPID {
+                warn!("Dropping data with PPID {} on stream {}", ppid, stream);
+                continue;
+            }
+            return Ok(Some((stream, payload)));
+        }
+    }
+
+    /// Receive the next NGAP PDU, failing if the association is closed
+    pub async fn expect(&mut self) -> Result<(u16, Bytes)> {
+        match self.recv().await? {
+            Some(received) => Ok(received),
+            None => bail!("gNB {} closed the association", self.peer),
+        }
+    }
+}
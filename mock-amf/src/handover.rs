@@ -0,0 +1,298 @@
+//! Scripted handover between two gNBs
+//!
+//! Registers a UE with a PDU session at a source gNB, then follows it to a
+//! target gNB connected to the same AMF. Without Xn the script relays the N2
+//! handover between the two (Handover Preparation, Handover Resource
+//! Allocation, RAN status transfer, Handover Notification and the release of
+//! the UE at the source); after an Xn handover it switches the path of the PDU
+//! sessions to the target (TS 38.413 sections 8.4.1 to 8.4.4 and 8.4.6 to 8.4.7).
+
+use crate::amf::{
+    ue_entry, ue_security_capabilities, EstablishedSession, MockAmf, RegisteredUe, ServedGnb, Transcript, UeEntry,
+    UeState,
+};
+use crate::keys;
+use crate::nas::NasSecurityContext;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use common::types::AggregateMaximumBitRate;
+use layers::ngap::pdu::{
+    self, AllowedNssai, AmfUeNgapId, Cause, Criticality, HandoverCommandTransfer, HandoverRequestAcknowledgeTransfer,
+    HandoverType, NgapPdu, NgapPduType, PathSwitchRequestAcknowledgeTransfer, PathSwitchRequestTransfer,
+    PduSessionResourceItem, PduSessionResourceSetupItemHoReq, RanStatusTransferContainer, RanUeNgapId,
+    SecurityContext, SourceToTargetTransparentContainer, TargetRanNodeId, TargetToSourceTransparentContainer,
+    UeNgapIds, UeSecurityCapabilities,
+};
+use layers::ngap::NgapProcedureCode;
+use std::collections::HashMap;
+use tracing::info;
+
+/// UE-associated stream the Handover Request goes on, the target has no RAN
+/// UE NGAP ID for the UE yet
+const HANDOVER_REQUEST_STREAM: u16 = 1;
+
+/// How the UE reaches the target gNB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoverMode {
+    /// Through the AMF, the gNBs have no Xn between them
+    N2,
+    /// Over Xn, the AMF only switches the path
+    Xn,
+}
+
+/// What the two gNBs sent during a handover run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandoverTranscript {
+    /// The gNB the UE registered at
+    pub source: Transcript,
+    /// The gNB the UE was handed over to, its UE has the target tunnels and NH as K_gNB
+    pub target: Transcript,
+}
+
+/// UE registered at the source, as known before it moves
+struct SourceUe {
+    ran_ue_ngap_id: u32,
+    amf_ue_ngap_id: u64,
+    stream: u16,
+    k_amf: [u8; 32],
+    security: NasSecurityContext,
+    registered: RegisteredUe,
+}
+
+impl MockAmf {
+    /// Accept a source gNB and register a UE with one PDU session there, then
+    /// accept a target gNB and follow the UE to it
+    ///
+    /// The target gNB must connect once the PDU session is up. The run ends once
+    /// the source released the UE (N2) or the path is switched (Xn).
+    pub async fn serve_handover(&mut self, mode: HandoverMode) -> Result<HandoverTranscript> {
+        let mut source = self.accept_gnb().await?;
+        self.register(&mut source, Some(1)).await?;
+        let mut target = self.accept_gnb().await?;
+        match mode {
+            HandoverMode::N2 => self.relay_n2_handover(&mut source, &mut target).await?,
+            HandoverMode::Xn => self.switch_path(&mut source, &mut target).await?,
+        }
+        Ok(HandoverTranscript { source: source.transcript, target: target.transcript })
+    }
+
+    /// Relay the N2 handover of the UE from the source to the target
+    async fn relay_n2_handover(&mut self, source: &mut ServedGnb, target: &mut ServedGnb) -> Result<()> {
+        let (_, required) = source.expect(NgapPduType::InitiatingMessage, NgapProcedureCode::HandoverPreparation).await?;
+        let ue = source_ue(source, |ues| ue_entry(ues, &required).map(|ue| ue.ran_ue_ngap_id))?;
+        let handover_type = required.ie::<HandoverType>(pdu::ID_HANDOVER_TYPE)?;
+        let cause = required.ie::<Cause>(pdu::ID_CAUSE)?;
+        let target_id = required.ie::<TargetRanNodeId>(pdu::ID_TARGET_ID)?;
+        let items = required.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_LIST_HO_RQD)?;
+        let container = required.ie::<SourceToTargetTransparentContainer>(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER)?;
+        ensure!(handover_type == HandoverType::Intra5gs, "Unexpected handover type {:?}", handover_type);
+        info!("Handover Required for AMF UE NGAP ID {} towards gNB {:#x} ({:?})", ue.amf_ue_ngap_id,
+              target_id.global_gnb_id.gnb_id, cause);
+
+        if target.transcript.gnb_id != Some(target_id.global_gnb_id) {
+            let failure = NgapPdu::unsuccessful(NgapProcedureCode::HandoverPreparation)
+                .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+                .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ue.ran_ue_ngap_id))?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::UNKNOWN_TARGET_ID)?;
+            source.association.send(ue.stream, &failure.encode()?).await?;
+            bail!("Handover Required towards unknown gNB {:?}", target_id.global_gnb_id);
+        }
+
+        let sessions = items.iter()
+            .map(|item| {
+                let session = ue.session(item.pdu_session_id)?;
+                Ok(PduSessionResourceSetupItemHoReq {
+                    pdu_session_id: session.pdu_session_id,
+                    s_nssai: session.s_nssai.clone(),
+                    transfer: self.setup_request_transfer(ue.amf_ue_ngap_id, session.pdu_session_id),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let next_hop = keys::next_hop(&ue.k_amf, &ue.registered.k_gnb);
+        // The target gets a UE-associated signalling connection of its own
+        let target_amf_ue_ngap_id = self.next_amf_ue_ngap_id;
+        self.next_amf_ue_ngap_id += 1;
+        let request = NgapPdu::initiating(NgapProcedureCode::HandoverResourceAllocation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(target_amf_ue_ngap_id))?
+            .with_ie(pdu::ID_HANDOVER_TYPE, Criticality::Reject, &handover_type)?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?
+            .with_ie(pdu::ID_UE_AGGREGATE_MAXIMUM_BIT_RATE, Criticality::Reject,
+                     &AggregateMaximumBitRate { dl: 100_000_000, ul: 100_000_000 })?
+            .with_ie(pdu::ID_UE_SECURITY_CAPABILITIES, Criticality::Reject, &ue_security_capabilities())?
+            .with_ie(pdu::ID_SECURITY_CONTEXT, Criticality::Reject,
+                     &SecurityContext { next_hop_chaining_count: 1, next_hop })?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SETUP_LIST_HO_REQ, Criticality::Reject, &sessions)?
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(self.config.slices.clone()))?
+            .with_ie(pdu::ID_SOURCE_TO_TARGET_TRANSPARENT_CONTAINER, Criticality::Reject, &container)?
+            .with_ie(pdu::ID_GUAMI, Criticality::Reject, &self.config.guami)?;
+        target.association.send(HANDOVER_REQUEST_STREAM, &request.encode()?).await?;
+
+        let Some((target_stream, answer)) = target.recv().await? else {
+            bail!("Target gNB {} closed the association", target.association.peer);
+        };
+        ensure!(answer.procedure() == Some(NgapProcedureCode::HandoverResourceAllocation),
+                "Expected the answer to Handover Request, got {:?} {:?}", answer.pdu_type, answer.procedure());
+        if answer.pdu_type == NgapPduType::UnsuccessfulOutcome {
+            let cause = answer.ie::<Cause>(pdu::ID_CAUSE)?;
+            let failure = NgapPdu::unsuccessful(NgapProcedureCode::HandoverPreparation)
+                .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+                .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ue.ran_ue_ngap_id))?
+                .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &cause)?;
+            source.association.send(ue.stream, &failure.encode()?).await?;
+            bail!("Target gNB refused the handover: {:?}", cause);
+        }
+        ensure!(answer.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0 == target_amf_ue_ngap_id,
+                "Handover Request Acknowledge for another UE");
+        let target_ran_ue_ngap_id = answer.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let admitted = answer.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_ADMITTED_LIST)?;
+        let container = answer.ie::<TargetToSourceTransparentContainer>(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER)?;
+
+        let mut handover_list = Vec::new();
+        let mut target_sessions = Vec::new();
+        for item in &admitted {
+            let transfer = item.decode_transfer::<HandoverRequestAcknowledgeTransfer>()?;
+            handover_list.push(PduSessionResourceItem::new(item.pdu_session_id, &HandoverCommandTransfer {
+                dl_forwarding_tunnel: transfer.dl_forwarding_tunnel,
+                qos_flows_to_forward: transfer.qos_flows.clone(),
+            })?);
+            target_sessions.push(EstablishedSession { dl_tunnel: transfer.dl_tunnel, ..ue.session(item.pdu_session_id)?.clone() });
+        }
+        info!("Target admitted PDU sessions {:?} as RAN UE NGAP ID {}, sending Handover Command",
+              admitted.iter().map(|item| item.pdu_session_id).collect::<Vec<_>>(), target_ran_ue_ngap_id);
+        let command = NgapPdu::successful(NgapProcedureCode::HandoverPreparation)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ue.ran_ue_ngap_id))?
+            .with_ie(pdu::ID_HANDOVER_TYPE, Criticality::Reject, &handover_type)?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_HANDOVER_LIST, Criticality::Ignore, &handover_list)?
+            .with_ie(pdu::ID_TARGET_TO_SOURCE_TRANSPARENT_CONTAINER, Criticality::Reject, &container)?;
+        source.association.send(ue.stream, &command.encode()?).await?;
+
+        let (_, status) = source.expect(NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkRanStatusTransfer).await?;
+        let status = status.ie::<RanStatusTransferContainer>(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER)?;
+        let transfer = NgapPdu::initiating(NgapProcedureCode::DownlinkRanStatusTransfer)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Reject, &AmfUeNgapId(target_amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Reject, &RanUeNgapId(target_ran_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_STATUS_TRANSFER_TRANSPARENT_CONTAINER, Criticality::Reject, &status)?;
+        target.association.send(target_stream, &transfer.encode()?).await?;
+
+        let (_, notify) = target.expect(NgapPduType::InitiatingMessage, NgapProcedureCode::HandoverNotification).await?;
+        ensure!(notify.ie::<AmfUeNgapId>(pdu::ID_AMF_UE_NGAP_ID)?.0 == target_amf_ue_ngap_id
+                && notify.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0 == target_ran_ue_ngap_id,
+                "Handover Notify for another UE");
+        info!("AMF UE NGAP ID {} arrived at the target, releasing it at the source", target_amf_ue_ngap_id);
+        target.adopt(&ue, target_ran_ue_ngap_id, target_amf_ue_ngap_id, target_stream, next_hop, target_sessions);
+
+        let release = NgapPdu::initiating(NgapProcedureCode::UeContextRelease)
+            .with_ie(pdu::ID_UE_NGAP_IDS, Criticality::Reject, &UeNgapIds::Pair {
+                amf_ue_ngap_id: ue.amf_ue_ngap_id,
+                ran_ue_ngap_id: ue.ran_ue_ngap_id,
+            })?
+            .with_ie(pdu::ID_CAUSE, Criticality::Ignore, &Cause::SUCCESSFUL_HANDOVER)?;
+        source.association.send(ue.stream, &release.encode()?).await?;
+        let (_, complete) = source.expect(NgapPduType::SuccessfulOutcome, NgapProcedureCode::UeContextRelease).await?;
+        ue_entry(&mut source.ues, &complete)?;
+        source.ues.remove(&ue.ran_ue_ngap_id);
+        Ok(())
+    }
+
+    /// Switch the path of the UE handed over to the target over Xn
+    async fn switch_path(&mut self, source: &mut ServedGnb, target: &mut ServedGnb) -> Result<()> {
+        let (stream, request) = target.expect(NgapPduType::InitiatingMessage, NgapProcedureCode::PathSwitchRequest).await?;
+        let ran_ue_ngap_id = request.ie::<RanUeNgapId>(pdu::ID_RAN_UE_NGAP_ID)?.0;
+        let source_amf_ue_ngap_id = request.ie::<AmfUeNgapId>(pdu::ID_SOURCE_AMF_UE_NGAP_ID)?.0;
+        let security_capabilities = request.ie::<UeSecurityCapabilities>(pdu::ID_UE_SECURITY_CAPABILITIES)?;
+        let items = request.ie::<Vec<PduSessionResourceItem>>(pdu::ID_PDU_SESSION_RESOURCE_TO_BE_SWITCHED_DL_LIST)?;
+        ensure!(security_capabilities == ue_security_capabilities(),
+                "UE security capabilities changed in the handover: {:?}", security_capabilities);
+        let ue = source_ue(source, |ues| {
+            ues.values().find(|ue| ue.amf_ue_ngap_id == source_amf_ue_ngap_id)
+                .map(|ue| ue.ran_ue_ngap_id)
+                .ok_or_else(|| anyhow!("Path Switch Request for unknown AMF UE NGAP ID {}", source_amf_ue_ngap_id))
+        })?;
+
+        let mut switched = Vec::new();
+        let mut sessions = Vec::new();
+        for item in &items {
+            let transfer = item.decode_transfer::<PathSwitchRequestTransfer>()?;
+            ensure!(transfer.qos_flows == [1], "Unexpected QoS flows {:?} for PDU session {}", transfer.qos_flows,
+                    item.pdu_session_id);
+            // The UPF keeps its endpoint, only the downlink moves
+            switched.push(PduSessionResourceItem::new(item.pdu_session_id, &PathSwitchRequestAcknowledgeTransfer::default())?);
+            sessions.push(EstablishedSession { dl_tunnel: transfer.dl_tunnel, ..ue.session(item.pdu_session_id)?.clone() });
+        }
+        let next_hop = keys::next_hop(&ue.k_amf, &ue.registered.k_gnb);
+        info!("Switching PDU sessions {:?} of AMF UE NGAP ID {} to the target",
+              items.iter().map(|item| item.pdu_session_id).collect::<Vec<_>>(), ue.amf_ue_ngap_id);
+        let acknowledge = NgapPdu::successful(NgapProcedureCode::PathSwitchRequest)
+            .with_ie(pdu::ID_AMF_UE_NGAP_ID, Criticality::Ignore, &AmfUeNgapId(ue.amf_ue_ngap_id))?
+            .with_ie(pdu::ID_RAN_UE_NGAP_ID, Criticality::Ignore, &RanUeNgapId(ran_ue_ngap_id))?
+            .with_ie(pdu::ID_SECURITY_CONTEXT, Criticality::Reject,
+                     &SecurityContext { next_hop_chaining_count: 1, next_hop })?
+            .with_ie(pdu::ID_PDU_SESSION_RESOURCE_SWITCHED_LIST, Criticality::Ignore, &switched)?
+            .with_ie(pdu::ID_ALLOWED_NSSAI, Criticality::Reject, &AllowedNssai(self.config.slices.clone()))?;
+        target.association.send(stream, &acknowledge.encode()?).await?;
+        target.adopt(&ue, ran_ue_ngap_id, ue.amf_ue_ngap_id, stream, next_hop, sessions);
+        // The source releases the UE on the Xn UE Context Release, without the AMF
+        source.ues.remove(&ue.ran_ue_ngap_id);
+        Ok(())
+    }
+}
+
+impl SourceUe {
+    /// PDU session of the UE set up at the source
+    fn session(&self, pdu_session_id: u8) -> Result<&EstablishedSession> {
+        self.registered.sessions.iter()
+            .find(|session| session.pdu_session_id == pdu_session_id)
+            .ok_or_else(|| anyhow!("PDU session {} was not set up", pdu_session_id))
+    }
+}
+
+impl ServedGnb {
+    /// Take over a UE handed over from another gNB
+    fn adopt(
+        &mut self,
+        ue: &SourceUe,
+        ran_ue_ngap_id: u32,
+        amf_ue_ngap_id: u64,
+        stream: u16,
+        next_hop: [u8; 32],
+        sessions: Vec<EstablishedSession>,
+    ) {
+        self.transcript.ues.push(RegisteredUe {
+            ran_ue_ngap_id,
+            amf_ue_ngap_id,
+            k_gnb: next_hop,
+            sessions,
+            ..ue.registered.clone()
+        });
+        self.ues.insert(ran_ue_ngap_id, UeEntry {
+            ran_ue_ngap_id,
+            amf_ue_ngap_id,
+            stream,
+            // NAS security moves with the UE, the AMF keeps its NAS COUNTs
+            state: UeState::Registered { k_amf: ue.k_amf, security: ue.security.clone() },
+            registered: Some(self.transcript.ues.len() - 1),
+            pending_sessions: HashMap::new(),
+        });
+    }
+}
+
+/// Registered UE of the source picked by `find`
+fn source_ue<F>(source: &mut ServedGnb, find: F) -> Result<SourceUe>
+where
+    F: FnOnce(&mut HashMap<u32, UeEntry>) -> Result<u32>,
+{
+    let ran_ue_ngap_id = find(&mut source.ues)?;
+    let ue = &source.ues[&ran_ue_ngap_id];
+    let UeState::Registered { k_amf, security } = &ue.state else {
+        bail!("Handover of UE {} before its registration", ran_ue_ngap_id);
+    };
+    let index = ue.registered.context("Registered UE not in the transcript")?;
+    Ok(SourceUe {
+        ran_ue_ngap_id,
+        amf_ue_ngap_id: ue.amf_ue_ngap_id,
+        stream: ue.stream,
+        k_amf: *k_amf,
+        security: security.clone(),
+        registered: source.transcript.ues[index].clone(),
+    })
+}
@@ -0,0 +1,190 @@
+//! 5G AKA key derivation (3GPP TS 33.501 Annex A)
+//!
+//! The key hierarchy from CK/IK down to K_AMF, the NAS keys and K_gNB. Both the
+//! scripted AMF and the test UE derive the same keys from the same inputs.
+
+use crate::milenage::Milenage;
+use layers::rrc::security::{derive_algorithm_key, kdf};
+use layers::rrc::{CipheringAlgorithm, IntegrityAlgorithm};
+
+/// Authentication management field with the separation bit set (TS 33.102 Annex H)
+pub const AUTHENTICATION_MANAGEMENT_FIELD: [u8; 2] = [0x80, 0x00];
+/// ABBA parameter of this release (TS 33.501 Annex A.7.1)
+pub const ABBA: [u8; 2] = [0x00, 0x00];
+/// Access type distinguisher for 3GPP access (TS 33.501 Annex A.9)
+const ACCESS_TYPE_3GPP: u8 = 0x01;
+/// Algorithm type distinguishers of the NAS keys (TS 33.501 Annex A.8)
+const N_NAS_ENC_ALG: u8 = 0x01;
+const N_NAS_INT_ALG: u8 = 0x02;
+
+/// Serving network name of a PLMN, "5G:mnc<MNC>.mcc<MCC>.3gppnetwork.org" (TS 24.501 section 9.12.1)
+pub fn serving_network_name(plmn_id: &[u8; 3]) -> String {
+    let mcc = format!("{}{}{}", plmn_id[0] & 0x0F, plmn_id[0] >> 4, plmn_id[1] & 0x0F);
+    let mnc = if plmn_id[1] >> 4 == 0x0F {
+        format!("0{}{}", plmn_id[2] & 0x0F, plmn_id[2] >> 4)
+    } else {
+        format!("{}{}{}", plmn_id[2] & 0x0F, plmn_id[2] >> 4, plmn_id[1] >> 4)
+    };
+    format!("5G:mnc{}.mcc{}.3gppnetwork.org", mnc, mcc)
+}
+
+/// Keys and parameters of one 5G AKA run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationVector {
+    /// Random challenge
+    pub rand: [u8; 16],
+    /// Authentication token: SQN xor AK, AMF, MAC-A
+    pub autn: [u8; 16],
+    /// Expected RES* (TS 33.501 Annex A.4)
+    pub xres_star: [u8; 16],
+    /// K_AUSF (TS 33.501 Annex A.2)
+    pub k_ausf: [u8; 32],
+}
+
+impl AuthenticationVector {
+    /// Generate the vector for a RAND and sequence number on the home network side
+    pub fn generate(milenage: &Milenage, rand: [u8; 16], sqn: u64, serving_network_name: &str) -> Self {
+        let sqn = sqn_octets(sqn);
+        let (mac_a, _) = milenage.f1(&rand, &sqn, &AUTHENTICATION_MANAGEMENT_FIELD);
+        let keys = milenage.f2345(&rand);
+        let mut sqn_ak = [0u8; 6];
+        for (i, octet) in sqn_ak.iter_mut().enumerate() {
+            *octet = sqn[i] ^ keys.ak[i];
+        }
+
+        let mut autn = [0u8; 16];
+        autn[..6].copy_from_slice(&sqn_ak);
+        autn[6..8].copy_from_slice(&AUTHENTICATION_MANAGEMENT_FIELD);
+        autn[8..].copy_from_slice(&mac_a);
+        let ck_ik = [keys.ck, keys.ik].concat();
+        Self {
+            rand,
+            autn,
+            xres_star: res_star(&ck_ik, serving_network_name, &rand, &keys.res),
+            k_ausf: kdf(&ck_ik, 0x6A, &[serving_network_name.as_bytes(), &sqn_ak]),
+        }
+    }
+}
+
+/// Result of checking an authentication challenge on the USIM side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationResult {
+    /// RES* to return to the network
+    pub res_star: [u8; 16],
+    /// K_AUSF
+    pub k_ausf: [u8; 32],
+    /// Sequence number of the challenge
+    pub sqn: u64,
+}
+
+/// Check RAND/AUTN with the subscriber keys and compute RES* and K_AUSF, None
+/// when MAC-A does not match
+pub fn authenticate(milenage: &Milenage, rand: &[u8; 16], autn: &[u8; 16], serving_network_name: &str) -> Option<AuthenticationResult> {
+    let keys = milenage.f2345(rand);
+    let mut sqn = [0u8; 6];
+    for (i, octet) in sqn.iter_mut().enumerate() {
+        *octet = autn[i] ^ keys.ak[i];
+    }
+    let (mac_a, _) = milenage.f1(rand, &sqn, &[autn[6], autn[7]]);
+    if mac_a[..] != autn[8..] {
+        return None;
+    }
+
+    let ck_ik = [keys.ck, keys.ik].concat();
+    Some(AuthenticationResult {
+        res_star: res_star(&ck_ik, serving_network_name, rand, &keys.res),
+        k_ausf: kdf(&ck_ik, 0x6A, &[serving_network_name.as_bytes(), &autn[..6]]),
+        sqn: sqn.iter().fold(0, |sqn, octet| (sqn << 8) | *octet as u64),
+    })
+}
+
+/// RES* / XRES*, the 128 least significant bits of the KDF output (TS 33.501 Annex A.4)
+fn res_star(ck_ik: &[u8], serving_network_name: &str, rand: &[u8; 16], res: &[u8]) -> [u8; 16] {
+    let derived = kdf(ck_ik, 0x6B, &[serving_network_name.as_bytes(), rand, res]);
+    let mut res_star = [0u8; 16];
+    res_star.copy_from_slice(&derived[16..]);
+    res_star
+}
+
+fn sqn_octets(sqn: u64) -> [u8; 6] {
+    let mut octets = [0u8; 6];
+    octets.copy_from_slice(&sqn.to_be_bytes()[2..]);
+    octets
+}
+
+/// K_SEAF from K_AUSF (TS 33.501 Annex A.6)
+pub fn k_seaf(k_ausf: &[u8; 32], serving_network_name: &str) -> [u8; 32] {
+    kdf(k_ausf, 0x6C, &[serving_network_name.as_bytes()])
+}
+
+/// K_AMF from K_AUSF through K_SEAF (TS 33.501 Annex A.6 and A.7)
+///
+/// `supi` is the IMSI as a string of digits.
+pub fn k_amf(k_ausf: &[u8; 32], serving_network_name: &str, supi: &str) -> [u8; 32] {
+    kdf(&k_seaf(k_ausf, serving_network_name), 0x6D, &[supi.as_bytes(), &ABBA])
+}
+
+/// NAS ciphering and integrity keys (TS 33.501 Annex A.8)
+pub fn nas_keys(k_amf: &[u8; 32], ciphering: CipheringAlgorithm, integrity: IntegrityAlgorithm) -> ([u8; 16], [u8; 16]) {
+    (derive_algorithm_key(k_amf, N_NAS_ENC_ALG, ciphering as u8),
+     derive_algorithm_key(k_amf, N_NAS_INT_ALG, integrity as u8))
+}
+
+/// K_gNB for 3GPP access (TS 33.501 Annex A.9)
+pub fn k_gnb(k_amf: &[u8; 32], uplink_nas_count: u32) -> [u8; 32] {
+    kdf(k_amf, 0x6E, &[&uplink_nas_count.to_be_bytes(), &[ACCESS_TYPE_3GPP]])
+}
+
+/// NH from the previous NH, or from K_gNB for the first one (TS 33.501 Annex A.10)
+pub fn next_hop(k_amf: &[u8; 32], sync_input: &[u8; 32]) -> [u8; 32] {
+    kdf(k_amf, 0x6F, &[sync_input])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::milenage::parse_hex;
+
+    #[test]
+    fn test_5g_aka_keys() {
+        // Subscriber, RAND and SQN of TS 35.208 test set 1. TS 33.501 publishes no
+        // test data for Annex A: the expected keys are the Annex A KDF outputs of an
+        // independent HMAC-SHA-256 implementation over the TS 35.208 CK, IK and RES.
+        let milenage = Milenage::new(&parse_hex("465b5ce8b199b49faa5f0a2ee238a6bc").unwrap(),
+                                     &parse_hex("cd63cb71954a9f4e48a5994e37a02baf").unwrap());
+        let name = serving_network_name(&[0x99, 0xF9, 0x07]);
+        assert_eq!(name, "5G:mnc070.mcc999.3gppnetwork.org");
+        assert_eq!(serving_network_name(&[0x00, 0x21, 0x43]), "5G:mnc342.mcc001.3gppnetwork.org");
+
+        let rand = parse_hex("23553cbe9637a89d218ae64dae47bf35").unwrap();
+        let vector = AuthenticationVector::generate(&milenage, rand, 0xFF9B_B4D0_B607, &name);
+        assert_eq!(vector.autn[..6], parse_hex::<6>("55f328b43577").unwrap());
+        assert_eq!(vector.autn[6..8], AUTHENTICATION_MANAGEMENT_FIELD);
+        assert_eq!(vector.xres_star, parse_hex("dd7ccf2eb8c36ef1f67062c553788357").unwrap());
+        assert_eq!(vector.k_ausf,
+                   parse_hex("75e57ab670ad4d0c1ee03b6e68250af7bd0e66ab2f9d74f5faccd126dc25d69c").unwrap());
+
+        // Network and USIM agree on RES* and K_AUSF
+        let result = authenticate(&milenage, &vector.rand, &vector.autn, &name).unwrap();
+        assert_eq!(result.res_star, vector.xres_star);
+        assert_eq!(result.k_ausf, vector.k_ausf);
+        assert_eq!(result.sqn, 0xFF9B_B4D0_B607);
+
+        // A tampered AUTN fails the MAC check
+        let mut autn = vector.autn;
+        autn[15] ^= 1;
+        assert!(authenticate(&milenage, &vector.rand, &autn, &name).is_none());
+
+        assert_eq!(k_seaf(&vector.k_ausf, &name),
+                   parse_hex("5beb161059b19911976c78676691a98692312643257d3db7e07c6bb34dda59d9").unwrap());
+        let k_amf = k_amf(&vector.k_ausf, &name, "999700000000001");
+        assert_eq!(k_amf, parse_hex("e6898699d34d65a3ca1142394d456eb2fa1c79b5b2404ae75e558d8f9dbed7cb").unwrap());
+        let (k_nas_enc, k_nas_int) = nas_keys(&k_amf, CipheringAlgorithm::Nea0, IntegrityAlgorithm::Nia2);
+        assert_eq!(k_nas_enc, parse_hex("90aa35d639b6311e286904c9cc38d91f").unwrap());
+        assert_eq!(k_nas_int, parse_hex("336129bcfeef9ba5b630a6b2e296c4b5").unwrap());
+        let k_gnb = k_gnb(&k_amf, 0);
+        assert_eq!(k_gnb, parse_hex("a9debe71c1f8a9f620ec41c0ed7a5451836bb60f149e8fb5ade8900bfe56a1e1").unwrap());
+        assert_eq!(next_hop(&k_amf, &k_gnb),
+                   parse_hex("e0356fcdc3c0a09841e829d844b1189a5e9beb80e311c24d41874799c1d1195c").unwrap());
+    }
+}
@@ -0,0 +1,20 @@
+//! Scripted AMF for NGAP integration tests
+//!
+//! Stands in for a 5G core on the NG-C interface: answers NG Setup, registers a
+//! UE with 5G AKA (Milenage), activates NAS security, hands K_gNB to the gNB in
+//! Initial Context Setup and sets up a PDU session, checking every PDU the gNB
+//! sends along the way, and can follow the UE to a second gNB by N2 or Xn
+//! handover. Runs over kernel SCTP or the TCP framing of the NG transport, so CI
+//! can exercise NGAP end to end without a real core network.
+
+pub mod amf;
+pub mod association;
+pub mod handover;
+pub mod keys;
+pub mod milenage;
+pub mod nas;
+pub mod ue;
+
+pub use amf::{MockAmf, MockAmfConfig, Subscriber, Transcript};
+pub use handover::{HandoverMode, HandoverTranscript};
+pub use ue::MockUe;
@@ -0,0 +1,96 @@
+//! Mock AMF
+//!
+//! Serves gNBs one association at a time with the scripted registration of
+//! `mock_amf::amf`, logging what each gNB did.
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use layers::ngap::transport::NgTransportKind;
+use mock_amf::milenage::parse_hex;
+use mock_amf::{MockAmf, MockAmfConfig, Subscriber};
+use std::net::SocketAddr;
+use tracing::{error, info};
+use tracing_subscriber::{fmt, EnvFilter};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Transport {
+    Sctp,
+    Tcp,
+}
+
+/// Scripted AMF for NGAP testing
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:38412")]
+    listen: SocketAddr,
+
+    /// NG-C transport: kernel SCTP or the TCP framing
+    #[arg(short, long, value_enum, default_value = "sctp")]
+    transport: Transport,
+
+    /// IMSI of the test subscriber
+    #[arg(long, default_value = "999700000000001")]
+    imsi: String,
+
+    /// Permanent key K (hex)
+    #[arg(long, default_value = "465b5ce8b199b49faa5f0a2ee238a6bc")]
+    k: String,
+
+    /// Operator key OPc (hex)
+    #[arg(long, default_value = "cd63cb71954a9f4e48a5994e37a02baf")]
+    opc: String,
+
+    /// Stop after one gNB association
+    #[arg(long)]
+    once: bool,
+
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(long, default_value = "info")]
+    log_level: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let env_filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&args.log_level));
+    fmt().with_env_filter(env_filter).with_target(true).init();
+
+    let subscriber = Subscriber {
+        imsi: args.imsi.clone(),
+        k: parse_hex(&args.k).ok_or_else(|| anyhow!("K must be 32 hex digits"))?,
+        opc: parse_hex(&args.opc).ok_or_else(|| anyhow!("OPc must be 32 hex digits"))?,
+        ..Default::default()
+    };
+    let config = MockAmfConfig {
+        listen: args.listen,
+        transport: match args.transport {
+            Transport::Sctp => NgTransportKind::Sctp,
+            Transport::Tcp => NgTransportKind::TcpFramed,
+        },
+        subscribers: vec![subscriber],
+        ..Default::default()
+    };
+    let mut amf = MockAmf::bind(config).await?;
+
+    loop {
+        match amf.serve_gnb(None).await {
+            Ok(transcript) => {
+                info!("gNB {} done: {} UEs, {} PDU sessions, {} PDUs received",
+                      transcript.ran_node_name.as_deref().unwrap_or("unnamed"), transcript.ues.len(),
+                      transcript.num_sessions(), transcript.received.len());
+                if args.once {
+                    return Ok(());
+                }
+            }
+            Err(e) => {
+                error!("Script failed: {:#}", e);
+                if args.once {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
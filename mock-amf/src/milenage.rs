@@ -0,0 +1,190 @@
+//! Milenage authentication functions (3GPP TS 35.206)
+//!
+//! f1, f1*, f2, f3, f4, f5 and f5* built on AES-128, as used by the home
+//! network to generate authentication vectors and by the USIM to check them.
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+/// Rotation (in bytes) and constant of each output block, TS 35.206 section 4.1
+const R: [usize; 5] = [8, 0, 4, 8, 12];
+const C: [u8; 5] = [0, 1, 2, 4, 8];
+
+/// Milenage instance for one subscriber
+#[derive(Clone)]
+pub struct Milenage {
+    cipher: Aes128,
+    opc: [u8; 16],
+}
+
+/// Outputs of f2, f3, f4 and f5 for a RAND
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Milenage2345 {
+    /// f2: response RES
+    pub res: [u8; 8],
+    /// f3: cipher key CK
+    pub ck: [u8; 16],
+    /// f4: integrity key IK
+    pub ik: [u8; 16],
+    /// f5: anonymity key AK
+    pub ak: [u8; 6],
+}
+
+impl Milenage {
+    /// Subscriber with the derived operator key OPc
+    pub fn new(k: &[u8; 16], opc: &[u8; 16]) -> Self {
+        Self {
+            cipher: Aes128::new(GenericArray::from_slice(k)),
+            opc: *opc,
+        }
+    }
+
+    /// Subscriber with the operator variant key OP, OPc = E_K(OP) xor OP
+    pub fn with_op(k: &[u8; 16], op: &[u8; 16]) -> Self {
+        let mut milenage = Self::new(k, &[0u8; 16]);
+        milenage.opc = xor(&milenage.encrypt(op), op);
+        milenage
+    }
+
+    /// Operator key OPc
+    pub fn opc(&self) -> [u8; 16] {
+        self.opc
+    }
+
+    fn encrypt(&self, block: &[u8; 16]) -> [u8; 16] {
+        let mut block = GenericArray::from(*block);
+        self.cipher.encrypt_block(&mut block);
+        block.into()
+    }
+
+    /// OUTn = E_K(rot(input xor OPc, rn) xor cn) xor OPc
+    fn output(&self, n: usize, input: &[u8; 16]) -> [u8; 16] {
+        let masked = xor(input, &self.opc);
+        let mut block = [0u8; 16];
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = masked[(i + R[n]) % 16];
+        }
+        block[15] ^= C[n];
+        xor(&self.encrypt(&block), &self.opc)
+    }
+
+    fn temp(&self, rand: &[u8; 16]) -> [u8; 16] {
+        self.encrypt(&xor(rand, &self.opc))
+    }
+
+    /// f1 and f1*: network authentication code MAC-A and resynchronisation code MAC-S
+    pub fn f1(&self, rand: &[u8; 16], sqn: &[u8; 6], amf: &[u8; 2]) -> ([u8; 8], [u8; 8]) {
+        let temp = self.temp(rand);
+        let mut in1 = [0u8; 16];
+        in1[0..6].copy_from_slice(sqn);
+        in1[6..8].copy_from_slice(amf);
+        in1[8..14].copy_from_slice(sqn);
+        in1[14..16].copy_from_slice(amf);
+        // OUT1 = E_K(TEMP xor rot(IN1 xor OPc, r1) xor c1) xor OPc
+        let masked = xor(&in1, &self.opc);
+        let mut block = [0u8; 16];
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = temp[i] ^ masked[(i + R[0]) % 16] ^ if i == 15 { C[0] } else { 0 };
+        }
+        let out1 = xor(&self.encrypt(&block), &self.opc);
+
+        let mut mac_a = [0u8; 8];
+        let mut mac_s = [0u8; 8];
+        mac_a.copy_from_slice(&out1[..8]);
+        mac_s.copy_from_slice(&out1[8..]);
+        (mac_a, mac_s)
+    }
+
+    /// f2, f3, f4 and f5
+    pub fn f2345(&self, rand: &[u8; 16]) -> Milenage2345 {
+        let temp = self.temp(rand);
+        let out2 = self.output(1, &temp);
+        let mut res = [0u8; 8];
+        let mut ak = [0u8; 6];
+        res.copy_from_slice(&out2[8..]);
+        ak.copy_from_slice(&out2[..6]);
+        Milenage2345 {
+            res,
+            ck: self.output(2, &temp),
+            ik: self.output(3, &temp),
+            ak,
+        }
+    }
+
+    /// f5*: anonymity key for resynchronisation
+    pub fn f5_star(&self, rand: &[u8; 16]) -> [u8; 6] {
+        let out5 = self.output(4, &self.temp(rand));
+        let mut ak = [0u8; 6];
+        ak.copy_from_slice(&out5[..6]);
+        ak
+    }
+}
+
+fn xor<const N: usize>(a: &[u8; N], b: &[u8; N]) -> [u8; N] {
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = a[i] ^ b[i];
+    }
+    out
+}
+
+/// Parse a hex string of exactly N octets
+pub fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.trim();
+    if hex.len() != 2 * N || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_milenage_test_set_1() {
+        // TS 35.208 section 4.3, test set 1
+        let k = parse_hex::<16>("465b5ce8b199b49faa5f0a2ee238a6bc").unwrap();
+        let op = parse_hex::<16>("cdc202d5123e20f62b6d676ac72cb318").unwrap();
+        let rand = parse_hex::<16>("23553cbe9637a89d218ae64dae47bf35").unwrap();
+        let sqn = parse_hex::<6>("ff9bb4d0b607").unwrap();
+        let amf = parse_hex::<2>("b9b9").unwrap();
+
+        let milenage = Milenage::with_op(&k, &op);
+        assert_eq!(milenage.opc(), parse_hex("cd63cb71954a9f4e48a5994e37a02baf").unwrap());
+        assert_eq!(milenage.f1(&rand, &sqn, &amf),
+                   (parse_hex("4a9ffac354dfafb3").unwrap(), parse_hex("01cfaf9ec4e871e9").unwrap()));
+        assert_eq!(milenage.f2345(&rand), Milenage2345 {
+            res: parse_hex("a54211d5e3ba50bf").unwrap(),
+            ck: parse_hex("b40ba9a3c58b2a05bbf0d987b21bf8cb").unwrap(),
+            ik: parse_hex("f769bcd751044604127672711c6d3441").unwrap(),
+            ak: parse_hex("aa689c648370").unwrap(),
+        });
+        assert_eq!(milenage.f5_star(&rand), parse_hex("451e8beca43b").unwrap());
+        assert!(parse_hex::<2>("b9").is_none());
+    }
+
+    #[test]
+    fn test_parse_hex_errors() {
+        assert_eq!(parse_hex::<2>(" b9b9\n"), Some([0xB9, 0xB9]));
+        assert!(parse_hex::<2>("b9b9b9").is_none());
+        assert!(parse_hex::<2>("b9xz").is_none());
+        assert!(parse_hex::<2>("+9b9").is_none());
+        assert!(parse_hex::<2>("b9é").is_none());
+        assert!(parse_hex::<0>("").is_some());
+
+        // A USIM with another key rejects the challenge
+        let rand = parse_hex::<16>("23553cbe9637a89d218ae64dae47bf35").unwrap();
+        let opc = parse_hex::<16>("cd63cb71954a9f4e48a5994e37a02baf").unwrap();
+        let network = Milenage::new(&parse_hex("465b5ce8b199b49faa5f0a2ee238a6bc").unwrap(), &opc);
+        let usim = Milenage::new(&[0; 16], &opc);
+        let sqn = [0, 0, 0, 0, 0, 1];
+        assert_ne!(network.f1(&rand, &sqn, &[0x80, 0x00]).0, usim.f1(&rand, &sqn, &[0x80, 0x00]).0);
+        assert_ne!(network.f1(&rand, &sqn, &[0x80, 0x00]).0, network.f1(&rand, &sqn, &[0x80, 0x01]).0);
+    }
+}
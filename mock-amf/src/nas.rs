@@ -0,0 +1,741 @@
+//! 5GS NAS messages of the scripted registration (3GPP TS 24.501)
+//!
+//! Only the 5GMM and 5GSM messages and IEs of an initial registration followed
+//! by a PDU session establishment are handled. NAS integrity uses 128-NIA2; the
+//! scripted AMF always selects NEA0, so "ciphered" messages are sent in clear.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use common::types::SNssai;
+use layers::ngap::pdu::Guami;
+use layers::rrc::security::compute_mac_i;
+use layers::rrc::{CipheringAlgorithm, IntegrityAlgorithm};
+use std::net::Ipv4Addr;
+
+/// Extended protocol discriminators (TS 24.007 section 11.2.3.1.1A)
+pub const EPD_5GMM: u8 = 0x7E;
+pub const EPD_5GSM: u8 = 0x2E;
+
+const REGISTRATION_REQUEST: u8 = 0x41;
+const REGISTRATION_ACCEPT: u8 = 0x42;
+const REGISTRATION_COMPLETE: u8 = 0x43;
+const AUTHENTICATION_REQUEST: u8 = 0x56;
+const AUTHENTICATION_RESPONSE: u8 = 0x57;
+const SECURITY_MODE_COMMAND: u8 = 0x5D;
+const SECURITY_MODE_COMPLETE: u8 = 0x5E;
+const UL_NAS_TRANSPORT: u8 = 0x67;
+const DL_NAS_TRANSPORT: u8 = 0x68;
+const PDU_SESSION_ESTABLISHMENT_REQUEST: u8 = 0xC1;
+const PDU_SESSION_ESTABLISHMENT_ACCEPT: u8 = 0xC2;
+
+const IEI_AUTHENTICATION_PARAMETER_AUTN: u8 = 0x20;
+const IEI_AUTHENTICATION_PARAMETER_RAND: u8 = 0x21;
+const IEI_S_NSSAI: u8 = 0x22;
+const IEI_DNN: u8 = 0x25;
+const IEI_PDU_ADDRESS: u8 = 0x29;
+const IEI_AUTHENTICATION_RESPONSE_PARAMETER: u8 = 0x2D;
+const IEI_UE_SECURITY_CAPABILITY: u8 = 0x2E;
+const IEI_PDU_SESSION_ID: u8 = 0x12;
+const IEI_ALLOWED_NSSAI: u8 = 0x15;
+const IEI_5G_GUTI: u8 = 0x77;
+const IEI_REQUEST_TYPE: u8 = 0x80;
+
+/// Payload container type N1 SM information (TS 24.501 section 9.11.3.40)
+const PAYLOAD_N1_SM: u8 = 0x01;
+/// BEARER input of the NAS integrity algorithm: NAS connection identifier of 3GPP access
+const NAS_BEARER_3GPP: u8 = 0x01;
+/// Integrity protection direction bit (TS 33.501 section D.3)
+pub const UPLINK: u8 = 0;
+pub const DOWNLINK: u8 = 1;
+/// Key set identifier value meaning "no key is available" (TS 24.501 section 9.11.3.32)
+pub const NO_KEY_AVAILABLE: u8 = 0x07;
+
+/// Security header type of a 5GMM message (TS 24.501 section 9.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityHeaderType {
+    Plain = 0,
+    IntegrityProtected = 1,
+    IntegrityProtectedAndCiphered = 2,
+    IntegrityProtectedWithNewContext = 3,
+    IntegrityProtectedAndCipheredWithNewContext = 4,
+}
+
+/// Security header type of a received 5GMM message
+pub fn security_header_type(pdu: &[u8]) -> Result<SecurityHeaderType> {
+    ensure!(pdu.len() >= 3 && pdu[0] == EPD_5GMM, "Not a 5GMM message: {:02x?}", pdu);
+    Ok(match pdu[1] & 0x0F {
+        0 => SecurityHeaderType::Plain,
+        1 => SecurityHeaderType::IntegrityProtected,
+        2 => SecurityHeaderType::IntegrityProtectedAndCiphered,
+        3 => SecurityHeaderType::IntegrityProtectedWithNewContext,
+        4 => SecurityHeaderType::IntegrityProtectedAndCipheredWithNewContext,
+        other => bail!("Unknown security header type {}", other),
+    })
+}
+
+/// SUCI with the null protection scheme (TS 24.501 section 9.11.3.4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suci {
+    /// Home network PLMN
+    pub plmn_id: [u8; 3],
+    /// MSIN digits, sent in clear with the null scheme
+    pub msin: String,
+}
+
+impl Suci {
+    /// SUCI of an IMSI of the home network
+    pub fn from_imsi(plmn_id: [u8; 3], imsi: &str) -> Result<Self> {
+        let prefix = plmn_digits(&plmn_id);
+        let msin = imsi.strip_prefix(&prefix)
+            .filter(|msin| !msin.is_empty() && msin.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| anyhow!("IMSI {} is not in PLMN {}", imsi, prefix))?;
+        Ok(Self { plmn_id, msin: msin.to_string() })
+    }
+
+    /// SUPI (IMSI digits) the SUCI conceals
+    pub fn supi(&self) -> String {
+        format!("{}{}", plmn_digits(&self.plmn_id), self.msin)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        // SUPI format IMSI, type of identity SUCI
+        buf.push(0x01);
+        buf.extend_from_slice(&self.plmn_id);
+        // Routing indicator "0", null protection scheme, home network public key 0
+        buf.extend_from_slice(&[0xF0, 0xFF, 0x00, 0x00]);
+        let digits: Vec<u8> = self.msin.bytes().map(|b| b - b'0').collect();
+        for pair in digits.chunks(2) {
+            buf.push(pair[0] | (pair.get(1).copied().unwrap_or(0x0F) << 4));
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= 8 && data[0] & 0x77 == 0x01, "Only IMSI based SUCI is supported");
+        ensure!(data[6] & 0x0F == 0, "Only the null protection scheme is supported");
+        let mut msin = String::new();
+        for octet in &data[8..] {
+            for digit in [octet & 0x0F, octet >> 4] {
+                if digit <= 9 {
+                    msin.push((b'0' + digit) as char);
+                }
+            }
+        }
+        Ok(Self { plmn_id: [data[1], data[2], data[3]], msin })
+    }
+}
+
+/// MCC and MNC digits of a PLMN identity
+pub fn plmn_digits(plmn_id: &[u8; 3]) -> String {
+    let mut digits = String::new();
+    for digit in [plmn_id[0] & 0x0F, plmn_id[0] >> 4, plmn_id[1] & 0x0F, plmn_id[2] & 0x0F, plmn_id[2] >> 4, plmn_id[1] >> 4] {
+        if digit <= 9 {
+            digits.push((b'0' + digit) as char);
+        }
+    }
+    digits
+}
+
+/// 5G-GUTI (TS 23.003 section 2.10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guti {
+    /// GUAMI of the allocating AMF
+    pub guami: Guami,
+    /// 5G-TMSI
+    pub tmsi: u32,
+}
+
+/// 5GMM messages of the registration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MmMessage {
+    RegistrationRequest {
+        ngksi: u8,
+        suci: Suci,
+        /// UE security capability value, 5G-EA and 5G-IA octets first
+        security_capability: Vec<u8>,
+    },
+    AuthenticationRequest {
+        ngksi: u8,
+        rand: [u8; 16],
+        autn: [u8; 16],
+    },
+    AuthenticationResponse {
+        res_star: [u8; 16],
+    },
+    SecurityModeCommand {
+        ngksi: u8,
+        ciphering: CipheringAlgorithm,
+        integrity: IntegrityAlgorithm,
+        replayed_security_capability: Vec<u8>,
+    },
+    SecurityModeComplete,
+    RegistrationAccept {
+        guti: Guti,
+        allowed_nssai: Vec<SNssai>,
+    },
+    RegistrationComplete,
+    UlNasTransport {
+        pdu_session_id: u8,
+        /// 5GSM message
+        payload: Vec<u8>,
+        s_nssai: Option<SNssai>,
+        dnn: Option<String>,
+    },
+    DlNasTransport {
+        pdu_session_id: u8,
+        /// 5GSM message
+        payload: Vec<u8>,
+    },
+}
+
+impl MmMessage {
+    /// Encode as a plain 5GMM message
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![EPD_5GMM, SecurityHeaderType::Plain as u8];
+        match self {
+            MmMessage::RegistrationRequest { ngksi, suci, security_capability } => {
+                buf.push(REGISTRATION_REQUEST);
+                // ngKSI, follow-on request pending, initial registration
+                buf.push((ngksi << 4) | 0x09);
+                let mut identity = Vec::new();
+                suci.encode(&mut identity);
+                put_lv_e(&mut buf, &identity);
+                buf.push(IEI_UE_SECURITY_CAPABILITY);
+                put_lv(&mut buf, security_capability);
+            }
+            MmMessage::AuthenticationRequest { ngksi, rand, autn } => {
+                buf.extend_from_slice(&[AUTHENTICATION_REQUEST, *ngksi]);
+                put_lv(&mut buf, &crate::keys::ABBA);
+                buf.push(IEI_AUTHENTICATION_PARAMETER_RAND);
+                buf.extend_from_slice(rand);
+                buf.push(IEI_AUTHENTICATION_PARAMETER_AUTN);
+                put_lv(&mut buf, autn);
+            }
+            MmMessage::AuthenticationResponse { res_star } => {
+                buf.extend_from_slice(&[AUTHENTICATION_RESPONSE, IEI_AUTHENTICATION_RESPONSE_PARAMETER]);
+                put_lv(&mut buf, res_star);
+            }
+            MmMessage::SecurityModeCommand { ngksi, ciphering, integrity, replayed_security_capability } => {
+                buf.extend_from_slice(&[SECURITY_MODE_COMMAND, ((*ciphering as u8) << 4) | *integrity as u8, *ngksi]);
+                put_lv(&mut buf, replayed_security_capability);
+            }
+            MmMessage::SecurityModeComplete => buf.push(SECURITY_MODE_COMPLETE),
+            MmMessage::RegistrationAccept { guti, allowed_nssai } => {
+                // 5GS registration result: 3GPP access
+                buf.extend_from_slice(&[REGISTRATION_ACCEPT, 0x01, 0x01]);
+                let guami = &guti.guami;
+                buf.push(IEI_5G_GUTI);
+                let mut identity = vec![0xF2];
+                identity.extend_from_slice(&guami.plmn_id);
+                identity.push(guami.amf_region_id);
+                identity.extend_from_slice(&((guami.amf_set_id << 6) | (guami.amf_pointer as u16 & 0x3F)).to_be_bytes());
+                identity.extend_from_slice(&guti.tmsi.to_be_bytes());
+                put_lv_e(&mut buf, &identity);
+                buf.push(IEI_ALLOWED_NSSAI);
+                let mut nssai = Vec::new();
+                for s_nssai in allowed_nssai {
+                    put_lv(&mut nssai, &encode_s_nssai(s_nssai));
+                }
+                put_lv(&mut buf, &nssai);
+            }
+            MmMessage::RegistrationComplete => buf.push(REGISTRATION_COMPLETE),
+            MmMessage::UlNasTransport { pdu_session_id, payload, s_nssai, dnn } => {
+                buf.extend_from_slice(&[UL_NAS_TRANSPORT, PAYLOAD_N1_SM]);
+                put_lv_e(&mut buf, payload);
+                buf.extend_from_slice(&[IEI_PDU_SESSION_ID, *pdu_session_id]);
+                // Request type: initial request
+                buf.push(IEI_REQUEST_TYPE | 0x01);
+                if let Some(s_nssai) = s_nssai {
+                    buf.push(IEI_S_NSSAI);
+                    put_lv(&mut buf, &encode_s_nssai(s_nssai));
+                }
+                if let Some(dnn) = dnn {
+                    buf.push(IEI_DNN);
+                    put_lv(&mut buf, &encode_dnn(dnn));
+                }
+            }
+            MmMessage::DlNasTransport { pdu_session_id, payload } => {
+                buf.extend_from_slice(&[DL_NAS_TRANSPORT, PAYLOAD_N1_SM]);
+                put_lv_e(&mut buf, payload);
+                buf.extend_from_slice(&[IEI_PDU_SESSION_ID, *pdu_session_id]);
+            }
+        }
+        buf
+    }
+
+    /// Decode a plain 5GMM message
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        ensure!(security_header_type(data)? == SecurityHeaderType::Plain, "5GMM message is security protected");
+        let mut reader = Reader::new(&data[3..]);
+        let message = match data[2] {
+            REGISTRATION_REQUEST => {
+                let ngksi = reader.u8()? >> 4;
+                let suci = Suci::decode(reader.lv_e()?)?;
+                let security_capability = reader.optional_ies()?.into_iter()
+                    .find(|(iei, _)| *iei == IEI_UE_SECURITY_CAPABILITY)
+                    .map(|(_, value)| value.to_vec())
+                    .unwrap_or_default();
+                MmMessage::RegistrationRequest { ngksi, suci, security_capability }
+            }
+            AUTHENTICATION_REQUEST => {
+                let ngksi = reader.u8()? & 0x0F;
+                reader.lv()?;
+                let ies = reader.optional_ies()?;
+                let rand = find_ie(&ies, IEI_AUTHENTICATION_PARAMETER_RAND)?;
+                let autn = find_ie(&ies, IEI_AUTHENTICATION_PARAMETER_AUTN)?;
+                MmMessage::AuthenticationRequest { ngksi, rand: array(rand)?, autn: array(autn)? }
+            }
+            AUTHENTICATION_RESPONSE => {
+                let ies = reader.optional_ies()?;
+                MmMessage::AuthenticationResponse { res_star: array(find_ie(&ies, IEI_AUTHENTICATION_RESPONSE_PARAMETER)?)? }
+            }
+            SECURITY_MODE_COMMAND => {
+                let algorithms = reader.u8()?;
+                let ngksi = reader.u8()? & 0x0F;
+                MmMessage::SecurityModeCommand {
+                    ngksi,
+                    ciphering: ciphering_algorithm(algorithms >> 4)?,
+                    integrity: integrity_algorithm(algorithms & 0x0F)?,
+                    replayed_security_capability: reader.lv()?.to_vec(),
+                }
+            }
+            SECURITY_MODE_COMPLETE => MmMessage::SecurityModeComplete,
+            REGISTRATION_ACCEPT => {
+                reader.lv()?;
+                let ies = reader.optional_ies()?;
+                let identity = find_ie(&ies, IEI_5G_GUTI)?;
+                ensure!(identity.len() == 11 && identity[0] & 0x07 == 0x02, "Invalid 5G-GUTI");
+                let set_pointer = u16::from_be_bytes([identity[5], identity[6]]);
+                let guti = Guti {
+                    guami: Guami {
+                        plmn_id: [identity[1], identity[2], identity[3]],
+                        amf_region_id: identity[4],
+                        amf_set_id: set_pointer >> 6,
+                        amf_pointer: (set_pointer & 0x3F) as u8,
+                    },
+                    tmsi: u32::from_be_bytes([identity[7], identity[8], identity[9], identity[10]]),
+                };
+                let mut allowed_nssai = Vec::new();
+                if let Some((_, nssai)) = ies.iter().find(|(iei, _)| *iei == IEI_ALLOWED_NSSAI) {
+                    let mut nssai = Reader::new(nssai);
+                    while !nssai.is_empty() {
+                        allowed_nssai.push(decode_s_nssai(nssai.lv()?)?);
+                    }
+                }
+                MmMessage::RegistrationAccept { guti, allowed_nssai }
+            }
+            REGISTRATION_COMPLETE => MmMessage::RegistrationComplete,
+            UL_NAS_TRANSPORT => {
+                ensure!(reader.u8()? & 0x0F == PAYLOAD_N1_SM, "Only N1 SM payloads are supported");
+                let payload = reader.lv_e()?.to_vec();
+                let ies = reader.optional_ies()?;
+                let s_nssai = ies.iter().find(|(iei, _)| *iei == IEI_S_NSSAI)
+                    .map(|(_, value)| decode_s_nssai(value))
+                    .transpose()?;
+                let dnn = ies.iter().find(|(iei, _)| *iei == IEI_DNN).map(|(_, value)| decode_dnn(value));
+                MmMessage::UlNasTransport {
+                    pdu_session_id: find_ie(&ies, IEI_PDU_SESSION_ID)?[0],
+                    payload,
+                    s_nssai,
+                    dnn,
+                }
+            }
+            DL_NAS_TRANSPORT => {
+                ensure!(reader.u8()? & 0x0F == PAYLOAD_N1_SM, "Only N1 SM payloads are supported");
+                let payload = reader.lv_e()?.to_vec();
+                let ies = reader.optional_ies()?;
+                MmMessage::DlNasTransport { pdu_session_id: find_ie(&ies, IEI_PDU_SESSION_ID)?[0], payload }
+            }
+            other => bail!("Unsupported 5GMM message type {:#04x}", other),
+        };
+        Ok(message)
+    }
+}
+
+/// 5GSM messages of the PDU session establishment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmMessage {
+    PduSessionEstablishmentRequest {
+        pdu_session_id: u8,
+        /// Procedure transaction identity
+        pti: u8,
+    },
+    PduSessionEstablishmentAccept {
+        pdu_session_id: u8,
+        pti: u8,
+        /// QoS flow of the default QoS rule
+        qfi: u8,
+        /// Session-AMBR downlink and uplink in Mbit/s
+        session_ambr_mbps: (u16, u16),
+        /// IPv4 address of the UE
+        address: Ipv4Addr,
+    },
+}
+
+impl SmMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            SmMessage::PduSessionEstablishmentRequest { pdu_session_id, pti } => {
+                // Integrity protection maximum data rate: full data rate both ways
+                vec![EPD_5GSM, *pdu_session_id, *pti, PDU_SESSION_ESTABLISHMENT_REQUEST, 0xFF, 0xFF]
+            }
+            SmMessage::PduSessionEstablishmentAccept { pdu_session_id, pti, qfi, session_ambr_mbps, address } => {
+                // SSC mode 1, PDU session type IPv4
+                let mut buf = vec![EPD_5GSM, *pdu_session_id, *pti, PDU_SESSION_ESTABLISHMENT_ACCEPT, 0x11];
+                // Default QoS rule 1: create, DQR, one match-all packet filter, lowest precedence
+                let rule = [0x31, 0x31, 0x01, 0x01, 0xFF, qfi & 0x3F];
+                let mut rules = vec![0x01];
+                rules.extend_from_slice(&(rule.len() as u16).to_be_bytes());
+                rules.extend_from_slice(&rule);
+                put_lv_e(&mut buf, &rules);
+                // Session-AMBR in units of 1 Mbit/s
+                let (dl, ul) = session_ambr_mbps;
+                let mut ambr = vec![0x06];
+                ambr.extend_from_slice(&dl.to_be_bytes());
+                ambr.push(0x06);
+                ambr.extend_from_slice(&ul.to_be_bytes());
+                put_lv(&mut buf, &ambr);
+                buf.push(IEI_PDU_ADDRESS);
+                let mut pdu_address = vec![0x01];
+                pdu_address.extend_from_slice(&address.octets());
+                put_lv(&mut buf, &pdu_address);
+                buf
+            }
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= 4 && data[0] == EPD_5GSM, "Not a 5GSM message: {:02x?}", data);
+        let (pdu_session_id, pti) = (data[1], data[2]);
+        let mut reader = Reader::new(&data[4..]);
+        let message = match data[3] {
+            PDU_SESSION_ESTABLISHMENT_REQUEST => SmMessage::PduSessionEstablishmentRequest { pdu_session_id, pti },
+            PDU_SESSION_ESTABLISHMENT_ACCEPT => {
+                ensure!(reader.u8()? & 0x07 == 0x01, "Only IPv4 PDU sessions are supported");
+                let rules = reader.lv_e()?;
+                ensure!(rules.len() >= 4, "Invalid QoS rules");
+                let rule_len = u16::from_be_bytes([rules[1], rules[2]]) as usize;
+                let qfi = rules.get(2 + rule_len).ok_or_else(|| anyhow!("Invalid QoS rule"))? & 0x3F;
+                let ambr = reader.lv()?;
+                ensure!(ambr.len() == 6, "Invalid Session-AMBR");
+                let ies = reader.optional_ies()?;
+                let address = find_ie(&ies, IEI_PDU_ADDRESS)?;
+                ensure!(address.len() == 5 && address[0] & 0x07 == 0x01, "Invalid PDU address");
+                SmMessage::PduSessionEstablishmentAccept {
+                    pdu_session_id,
+                    pti,
+                    qfi,
+                    session_ambr_mbps: (u16::from_be_bytes([ambr[1], ambr[2]]), u16::from_be_bytes([ambr[4], ambr[5]])),
+                    address: Ipv4Addr::new(address[1], address[2], address[3], address[4]),
+                }
+            }
+            other => bail!("Unsupported 5GSM message type {:#04x}", other),
+        };
+        Ok(message)
+    }
+}
+
+/// 5G NAS security context of one side (TS 33.501 section 6.4)
+#[derive(Debug, Clone)]
+pub struct NasSecurityContext {
+    pub ciphering: CipheringAlgorithm,
+    pub integrity: IntegrityAlgorithm,
+    k_nas_int: [u8; 16],
+    /// Next uplink NAS COUNT
+    pub ul_count: u32,
+    /// Next downlink NAS COUNT
+    pub dl_count: u32,
+}
+
+impl NasSecurityContext {
+    /// New context from K_AMF, both NAS COUNTs starting at 0
+    pub fn new(k_amf: &[u8; 32], ciphering: CipheringAlgorithm, integrity: IntegrityAlgorithm) -> Self {
+        let (_, k_nas_int) = crate::keys::nas_keys(k_amf, ciphering, integrity);
+        Self { ciphering, integrity, k_nas_int, ul_count: 0, dl_count: 0 }
+    }
+
+    fn count_mut(&mut self, direction: u8) -> &mut u32 {
+        if direction == UPLINK { &mut self.ul_count } else { &mut self.dl_count }
+    }
+
+    /// Protect a plain 5GMM message sent in `direction`
+    pub fn protect(&mut self, header: SecurityHeaderType, plain: &[u8], direction: u8) -> Result<Vec<u8>> {
+        ensure!(header != SecurityHeaderType::Plain, "No security header to add");
+        ensure!(self.ciphering == CipheringAlgorithm::Nea0, "NAS ciphering with {:?} is not supported", self.ciphering);
+        let count = *self.count_mut(direction);
+        let mut sequenced = vec![count as u8];
+        sequenced.extend_from_slice(plain);
+        let mac = compute_mac_i(self.integrity, &self.k_nas_int, count, NAS_BEARER_3GPP, direction, &sequenced)?;
+        *self.count_mut(direction) = count.wrapping_add(1) & 0x00FF_FFFF;
+
+        let mut pdu = vec![EPD_5GMM, header as u8];
+        pdu.extend_from_slice(&mac);
+        pdu.extend_from_slice(&sequenced);
+        Ok(pdu)
+    }
+
+    /// Check the MAC of a protected 5GMM message received in `direction` and
+    /// return the plain message
+    pub fn unprotect(&mut self, pdu: &[u8], direction: u8) -> Result<Vec<u8>> {
+        ensure!(security_header_type(pdu)? != SecurityHeaderType::Plain, "5GMM message is not security protected");
+        ensure!(pdu.len() > 7, "Truncated security protected 5GMM message");
+        // Estimate the NAS COUNT from the received sequence number
+        let expected = *self.count_mut(direction);
+        let mut count = (expected & !0xFF) | pdu[6] as u32;
+        if count < expected {
+            count = count.wrapping_add(0x100);
+        }
+        let mac = compute_mac_i(self.integrity, &self.k_nas_int, count, NAS_BEARER_3GPP, direction, &pdu[6..])?;
+        ensure!(mac[..] == pdu[2..6], "NAS MAC check failed for NAS COUNT {}", count);
+        *self.count_mut(direction) = count.wrapping_add(1) & 0x00FF_FFFF;
+        Ok(pdu[7..].to_vec())
+    }
+}
+
+fn ciphering_algorithm(value: u8) -> Result<CipheringAlgorithm> {
+    Ok(match value {
+        0 => CipheringAlgorithm::Nea0,
+        1 => CipheringAlgorithm::Nea1,
+        2 => CipheringAlgorithm::Nea2,
+        3 => CipheringAlgorithm::Nea3,
+        other => bail!("Unknown 5G-EA{}", other),
+    })
+}
+
+fn integrity_algorithm(value: u8) -> Result<IntegrityAlgorithm> {
+    Ok(match value {
+        0 => IntegrityAlgorithm::Nia0,
+        1 => IntegrityAlgorithm::Nia1,
+        2 => IntegrityAlgorithm::Nia2,
+        3 => IntegrityAlgorithm::Nia3,
+        other => bail!("Unknown 5G-IA{}", other),
+    })
+}
+
+fn encode_s_nssai(s_nssai: &SNssai) -> Vec<u8> {
+    let mut value = vec![s_nssai.sst];
+    if let Some(sd) = s_nssai.sd {
+        value.extend_from_slice(&sd.to_be_bytes()[1..]);
+    }
+    value
+}
+
+fn decode_s_nssai(value: &[u8]) -> Result<SNssai> {
+    match value.len() {
+        // SST, optionally with the mapped HPLMN SST
+        1 | 2 => Ok(SNssai { sst: value[0], sd: None }),
+        _ if value.len() >= 4 => Ok(SNssai { sst: value[0], sd: Some(u32::from_be_bytes([0, value[1], value[2], value[3]])) }),
+        _ => bail!("Invalid S-NSSAI"),
+    }
+}
+
+/// DNN as length-prefixed labels (TS 23.003 section 9.1)
+fn encode_dnn(dnn: &str) -> Vec<u8> {
+    let mut value = Vec::new();
+    for label in dnn.split('.') {
+        put_lv(&mut value, label.as_bytes());
+    }
+    value
+}
+
+fn decode_dnn(value: &[u8]) -> String {
+    let mut labels = Vec::new();
+    let mut reader = Reader::new(value);
+    while let Ok(label) = reader.lv() {
+        labels.push(String::from_utf8_lossy(label).into_owned());
+    }
+    labels.join(".")
+}
+
+fn put_lv(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.push(value.len() as u8);
+    buf.extend_from_slice(value);
+}
+
+fn put_lv_e(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+fn find_ie<'a>(ies: &[(u8, &'a [u8])], iei: u8) -> Result<&'a [u8]> {
+    ies.iter().find(|(id, _)| *id == iei).map(|(_, value)| *value)
+        .ok_or_else(|| anyhow!("Missing NAS IE {:#04x}", iei))
+}
+
+fn array<const N: usize>(value: &[u8]) -> Result<[u8; N]> {
+    value.try_into().map_err(|_| anyhow!("NAS IE of {} octets, expected {}", value.len(), N))
+}
+
+/// Cursor over the IEs of a NAS message
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.data.len() >= len, "Truncated NAS message");
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn lv(&mut self) -> Result<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    fn lv_e(&mut self) -> Result<&'a [u8]> {
+        let len = u16::from_be_bytes([self.u8()?, self.u8()?]) as usize;
+        self.take(len)
+    }
+
+    /// Remaining optional IEs as (IEI, value); a type 1 IE keeps its whole octet
+    /// and is reported under the IEI in its upper half
+    fn optional_ies(&mut self) -> Result<Vec<(u8, &'a [u8])>> {
+        let mut ies = Vec::new();
+        while !self.is_empty() {
+            let iei = self.data[0];
+            let ie = match iei {
+                0x80..=0xFF => (iei & 0xF0, self.take(1)?),
+                IEI_AUTHENTICATION_PARAMETER_RAND => {
+                    self.take(1)?;
+                    (iei, self.take(16)?)
+                }
+                IEI_PDU_SESSION_ID => {
+                    self.take(1)?;
+                    (iei, self.take(1)?)
+                }
+                0x70..=0x7F => {
+                    self.take(1)?;
+                    (iei, self.lv_e()?)
+                }
+                _ => {
+                    self.take(1)?;
+                    (iei, self.lv()?)
+                }
+            };
+            ies.push(ie);
+        }
+        Ok(ies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nas_messages() {
+        let suci = Suci::from_imsi([0x99, 0xF9, 0x07], "999700000000001").unwrap();
+        assert_eq!(suci.msin, "0000000001");
+        let request = MmMessage::RegistrationRequest { ngksi: NO_KEY_AVAILABLE, suci: suci.clone(), security_capability: vec![0xF0, 0xF0] };
+        let encoded = request.encode();
+        assert_eq!(&encoded[..4], &[0x7E, 0x00, 0x41, 0x79]);
+        assert_eq!(&encoded[4..10], &[0x00, 0x0D, 0x01, 0x99, 0xF9, 0x07]);
+        assert_eq!(MmMessage::decode(&encoded).unwrap(), request);
+        assert_eq!(suci.supi(), "999700000000001");
+        assert!(Suci::from_imsi([0x99, 0xF9, 0x07], "00101123").is_err());
+
+        let accept = MmMessage::RegistrationAccept {
+            guti: Guti { guami: Guami { plmn_id: [0x99, 0xF9, 0x07], amf_region_id: 2, amf_set_id: 1, amf_pointer: 3 }, tmsi: 0x1234 },
+            allowed_nssai: vec![SNssai { sst: 1, sd: None }, SNssai { sst: 2, sd: Some(0x010203) }],
+        };
+        assert_eq!(MmMessage::decode(&accept.encode()).unwrap(), accept);
+
+        let transport = MmMessage::UlNasTransport {
+            pdu_session_id: 1,
+            payload: SmMessage::PduSessionEstablishmentRequest { pdu_session_id: 1, pti: 1 }.encode(),
+            s_nssai: Some(SNssai { sst: 1, sd: None }),
+            dnn: Some("internet".to_string()),
+        };
+        assert_eq!(MmMessage::decode(&transport.encode()).unwrap(), transport);
+        let accept = SmMessage::PduSessionEstablishmentAccept {
+            pdu_session_id: 1, pti: 1, qfi: 1, session_ambr_mbps: (100, 50), address: Ipv4Addr::new(10, 45, 0, 2),
+        };
+        assert_eq!(SmMessage::decode(&accept.encode()).unwrap(), accept);
+
+        // Integrity protection round trip, a modified message fails the MAC check
+        let mut gnb_side = NasSecurityContext::new(&[7; 32], CipheringAlgorithm::Nea0, IntegrityAlgorithm::Nia2);
+        let mut ue_side = gnb_side.clone();
+        let plain = MmMessage::RegistrationComplete.encode();
+        let protected = ue_side.protect(SecurityHeaderType::IntegrityProtectedAndCiphered, &plain, UPLINK).unwrap();
+        assert_eq!(gnb_side.unprotect(&protected, UPLINK).unwrap(), plain);
+        assert_eq!((gnb_side.ul_count, ue_side.ul_count), (1, 1));
+        let mut tampered = ue_side.protect(SecurityHeaderType::IntegrityProtected, &plain, UPLINK).unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(gnb_side.unprotect(&tampered, UPLINK).is_err());
+    }
+
+    #[test]
+    fn test_nas_decode_errors() {
+        // Header errors
+        assert!(security_header_type(&[0x7E, 0x00]).is_err());
+        assert!(security_header_type(&[EPD_5GSM, 0x00, 0x41]).is_err());
+        assert!(security_header_type(&[0x7E, 0x05, 0x41]).is_err());
+        assert!(MmMessage::decode(&[0x7E, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x43]).is_err());
+        assert!(MmMessage::decode(&[0x7E, 0x00, 0x44]).is_err());
+        assert!(SmMessage::decode(&[0x2E, 0x01, 0x01]).is_err());
+        assert!(SmMessage::decode(&[0x2E, 0x01, 0x01, 0xC3]).is_err());
+
+        // Unknown optional IEs are skipped, truncated ones are not
+        let suci = Suci::from_imsi([0x99, 0xF9, 0x07], "999700000000001").unwrap();
+        let request = MmMessage::RegistrationRequest { ngksi: 1, suci: suci.clone(), security_capability: Vec::new() };
+        let mut encoded = request.encode();
+        encoded.extend_from_slice(&[0x5A, 0x02, 0xAB, 0xCD, 0x90]);
+        assert_eq!(MmMessage::decode(&encoded).unwrap(), request);
+        encoded.extend_from_slice(&[0x5A, 0x02, 0xAB]);
+        assert!(MmMessage::decode(&encoded).is_err());
+        let encoded = request.encode();
+        assert!(MmMessage::decode(&encoded[..encoded.len() - 4]).is_err());
+
+        // SUCI other than IMSI with the null scheme
+        let mut encoded = request.encode();
+        encoded[6] = 0x11;
+        assert!(MmMessage::decode(&encoded).is_err());
+        let mut encoded = request.encode();
+        encoded[12] = 0x01;
+        assert!(MmMessage::decode(&encoded).is_err());
+
+        // Missing mandatory-in-practice IEs and wrong IE lengths
+        assert!(MmMessage::decode(&[0x7E, 0x00, AUTHENTICATION_RESPONSE]).is_err());
+        assert!(MmMessage::decode(&[0x7E, 0x00, AUTHENTICATION_RESPONSE, IEI_AUTHENTICATION_RESPONSE_PARAMETER, 0x02, 0x00, 0x00]).is_err());
+        assert!(MmMessage::decode(&[0x7E, 0x00, REGISTRATION_ACCEPT, 0x01, 0x01]).is_err());
+        assert!(MmMessage::decode(&[0x7E, 0x00, SECURITY_MODE_COMMAND, 0x42, 0x00, 0x00]).is_err());
+        let transport = MmMessage::DlNasTransport { pdu_session_id: 1, payload: vec![0x2E] };
+        let encoded = transport.encode();
+        assert!(MmMessage::decode(&encoded[..encoded.len() - 2]).is_err());
+        let mut encoded = transport.encode();
+        encoded[3] = 0x02;
+        assert!(MmMessage::decode(&encoded).is_err());
+
+        // Session establishment accept that is not IPv4, or with a bad Session-AMBR
+        let accept = SmMessage::PduSessionEstablishmentAccept {
+            pdu_session_id: 1, pti: 1, qfi: 1, session_ambr_mbps: (100, 50), address: Ipv4Addr::new(10, 45, 0, 2),
+        };
+        let mut encoded = accept.encode();
+        encoded[4] = 0x02;
+        assert!(SmMessage::decode(&encoded).is_err());
+        let encoded = accept.encode();
+        assert!(SmMessage::decode(&encoded[..encoded.len() - 7]).is_err());
+
+        // Protection errors: a plain header, NEA2, a truncated PDU
+        let mut context = NasSecurityContext::new(&[7; 32], CipheringAlgorithm::Nea0, IntegrityAlgorithm::Nia2);
+        let plain = MmMessage::RegistrationComplete.encode();
+        assert!(context.protect(SecurityHeaderType::Plain, &plain, DOWNLINK).is_err());
+        assert!(context.unprotect(&plain, UPLINK).is_err());
+        assert!(context.unprotect(&[0x7E, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00], UPLINK).is_err());
+        let mut ciphered = NasSecurityContext::new(&[7; 32], CipheringAlgorithm::Nea2, IntegrityAlgorithm::Nia2);
+        assert!(ciphered.protect(SecurityHeaderType::IntegrityProtectedAndCiphered, &plain, DOWNLINK).is_err());
+        assert_eq!((context.ul_count, context.dl_count), (0, 0));
+    }
+}
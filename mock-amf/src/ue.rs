@@ -0,0 +1,148 @@
+//! Test UE
+//!
+//! NAS side of a UE for driving the scripted AMF through a gNB without a radio:
+//! the USIM checks the challenge with Milenage and the UE answers each downlink
+//! NAS message of the registration and PDU session establishment.
+
+use crate::amf::Subscriber;
+use crate::keys;
+use crate::milenage::Milenage;
+use crate::nas::{self, Guti, MmMessage, NasSecurityContext, SecurityHeaderType, SmMessage, Suci};
+use anyhow::{anyhow, bail, ensure, Result};
+use common::types::SNssai;
+use std::net::Ipv4Addr;
+use tracing::info;
+
+/// UE security capability: 5G-EA0-3 and 5G-IA0-3
+const SECURITY_CAPABILITY: [u8; 2] = [0xF0, 0xF0];
+
+/// PDU session as seen by the UE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UeSession {
+    pub pdu_session_id: u8,
+    pub qfi: u8,
+    pub address: Ipv4Addr,
+}
+
+/// NAS side of a test UE
+pub struct MockUe {
+    suci: Suci,
+    milenage: Milenage,
+    serving_network_name: String,
+    k_ausf: Option<[u8; 32]>,
+    k_amf: Option<[u8; 32]>,
+    security: Option<NasSecurityContext>,
+    /// K_gNB derived when NAS security was activated
+    pub k_gnb: Option<[u8; 32]>,
+    /// 5G-GUTI from the Registration Accept
+    pub guti: Option<Guti>,
+    /// Allowed NSSAI from the Registration Accept
+    pub allowed_nssai: Vec<SNssai>,
+    /// Established PDU sessions
+    pub sessions: Vec<UeSession>,
+}
+
+impl MockUe {
+    /// UE with the USIM of a subscriber, registering in its home PLMN
+    pub fn new(subscriber: &Subscriber, plmn_id: [u8; 3]) -> Result<Self> {
+        Ok(Self {
+            suci: Suci::from_imsi(plmn_id, &subscriber.imsi)?,
+            milenage: Milenage::new(&subscriber.k, &subscriber.opc),
+            serving_network_name: keys::serving_network_name(&plmn_id),
+            k_ausf: None,
+            k_amf: None,
+            security: None,
+            k_gnb: None,
+            guti: None,
+            allowed_nssai: Vec::new(),
+            sessions: Vec::new(),
+        })
+    }
+
+    /// Initial registration request with the SUCI
+    pub fn registration_request(&self) -> Vec<u8> {
+        MmMessage::RegistrationRequest {
+            ngksi: nas::NO_KEY_AVAILABLE,
+            suci: self.suci.clone(),
+            security_capability: SECURITY_CAPABILITY.to_vec(),
+        }.encode()
+    }
+
+    /// UL NAS Transport with a PDU Session Establishment Request
+    pub fn pdu_session_establishment_request(&mut self, pdu_session_id: u8, s_nssai: SNssai, dnn: &str) -> Result<Vec<u8>> {
+        let request = SmMessage::PduSessionEstablishmentRequest { pdu_session_id, pti: pdu_session_id }.encode();
+        let transport = MmMessage::UlNasTransport {
+            pdu_session_id,
+            payload: request,
+            s_nssai: Some(s_nssai),
+            dnn: Some(dnn.to_string()),
+        }.encode();
+        self.protect(&transport)
+    }
+
+    /// NH with NCC 1, the key the UE takes to the target of its first handover
+    pub fn next_hop(&self) -> Option<[u8; 32]> {
+        Some(keys::next_hop(&self.k_amf?, &self.k_gnb?))
+    }
+
+    fn protect(&mut self, plain: &[u8]) -> Result<Vec<u8>> {
+        self.security.as_mut()
+            .ok_or_else(|| anyhow!("No NAS security context"))?
+            .protect(SecurityHeaderType::IntegrityProtectedAndCiphered, plain, nas::UPLINK)
+    }
+
+    /// Handle a downlink NAS message, returning the uplink answer if one is due
+    pub fn handle_downlink(&mut self, pdu: &[u8]) -> Result<Option<Vec<u8>>> {
+        let plain = match nas::security_header_type(pdu)? {
+            SecurityHeaderType::Plain => pdu.to_vec(),
+            SecurityHeaderType::IntegrityProtectedWithNewContext => {
+                // The Security Mode Command carries the algorithms of the new context
+                let MmMessage::SecurityModeCommand { ciphering, integrity, replayed_security_capability, .. }
+                    = MmMessage::decode(pdu.get(7..).ok_or_else(|| anyhow!("Truncated NAS message"))?)? else {
+                    bail!("New security context without Security Mode Command");
+                };
+                ensure!(replayed_security_capability == SECURITY_CAPABILITY, "Replayed UE security capability mismatch");
+                let k_ausf = self.k_ausf.ok_or_else(|| anyhow!("Security Mode Command before authentication"))?;
+                let k_amf = keys::k_amf(&k_ausf, &self.serving_network_name, &self.suci.supi());
+                self.k_amf = Some(k_amf);
+                self.security = Some(NasSecurityContext::new(&k_amf, ciphering, integrity));
+                self.security.as_mut().unwrap().unprotect(pdu, nas::DOWNLINK)?
+            }
+            _ => self.security.as_mut()
+                .ok_or_else(|| anyhow!("Protected NAS message without security context"))?
+                .unprotect(pdu, nas::DOWNLINK)?,
+        };
+
+        match MmMessage::decode(&plain)? {
+            MmMessage::AuthenticationRequest { rand, autn, .. } => {
+                let result = keys::authenticate(&self.milenage, &rand, &autn, &self.serving_network_name)
+                    .ok_or_else(|| anyhow!("Network authentication failed"))?;
+                self.k_ausf = Some(result.k_ausf);
+                Ok(Some(MmMessage::AuthenticationResponse { res_star: result.res_star }.encode()))
+            }
+            MmMessage::SecurityModeCommand { .. } => {
+                let k_amf = self.k_amf.ok_or_else(|| anyhow!("No K_AMF"))?;
+                let ul_count = self.security.as_ref().map_or(0, |security| security.ul_count);
+                self.k_gnb = Some(keys::k_gnb(&k_amf, ul_count));
+                let complete = MmMessage::SecurityModeComplete.encode();
+                let security = self.security.as_mut().ok_or_else(|| anyhow!("No NAS security context"))?;
+                Ok(Some(security.protect(SecurityHeaderType::IntegrityProtectedAndCipheredWithNewContext, &complete, nas::UPLINK)?))
+            }
+            MmMessage::RegistrationAccept { guti, allowed_nssai } => {
+                info!("Registered with 5G-GUTI TMSI {:#x}", guti.tmsi);
+                self.guti = Some(guti);
+                self.allowed_nssai = allowed_nssai;
+                Ok(Some(self.protect(&MmMessage::RegistrationComplete.encode())?))
+            }
+            MmMessage::DlNasTransport { payload, .. } => {
+                let SmMessage::PduSessionEstablishmentAccept { pdu_session_id, qfi, address, .. } = SmMessage::decode(&payload)? else {
+                    bail!("Unexpected 5GSM message");
+                };
+                info!("PDU session {} established with address {}", pdu_session_id, address);
+                self.sessions.push(UeSession { pdu_session_id, qfi, address });
+                Ok(None)
+            }
+            other => bail!("Unexpected downlink NAS message {:?}", other),
+        }
+    }
+}
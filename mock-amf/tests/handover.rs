@@ -0,0 +1,272 @@
+//! Handover of a registered UE between two NgapLayers connected to the scripted
+//! AMF, by N2 through the AMF and by Xn with a path switch. The test stands in
+//! for RRC, the UE and, for Xn, XnAP between the two gNBs.
+
+use bytes::Bytes;
+use common::types::SNssai;
+use layers::ngap::association::run_ng_association;
+use layers::ngap::handover::HandoverTarget;
+use layers::ngap::pdu::{BroadcastPlmnItem, NgapPduType, PagingDrx, SupportedTaItem};
+use layers::ngap::transport::{NgTransportConfig, NgTransportKind};
+use layers::ngap::{NgapConfig, NgapLayer, NgapProcedureCode};
+use layers::rrc::{
+    AmfSelectionInfo, DrbSnStatus, EstablishmentCause, NgapRrcMessage, PduSessionProcedure, RrcNgapMessage,
+};
+use layers::xnap::{NgapXnapMessage, XnapNgapMessage};
+use layers::ProtocolLayer;
+use mock_amf::{HandoverMode, MockAmf, MockAmfConfig, MockUe, Subscriber};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+/// gNB whose RRC is played by the test
+struct Gnb {
+    ngap: Arc<RwLock<NgapLayer>>,
+    rrc_rx: mpsc::Receiver<NgapRrcMessage>,
+    xnap_rx: mpsc::Receiver<NgapXnapMessage>,
+    association: JoinHandle<()>,
+}
+
+impl Gnb {
+    async fn start(amf_address: SocketAddr, gnb_id: u32, handover_targets: Vec<HandoverTarget>) -> Self {
+        let mut ngap = NgapLayer::new(NgapConfig {
+            amfs: vec![amf_address.into()],
+            local_address: "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            gnb_id,
+            gnb_id_bits: 22,
+            plmn_id: PLMN,
+            ran_node_name: format!("gNB-{}", gnb_id),
+            supported_tas: vec![SupportedTaItem {
+                tac: 1,
+                broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![SNssai { sst: 1, sd: None }] }],
+            }],
+            default_paging_drx: PagingDrx::V64,
+            nr_cell_identity: nr_cell_identity(gnb_id),
+            tac: 1,
+            gtpu_address: IpAddr::from([127, 0, 0, gnb_id as u8]),
+            transport: NgTransportConfig { kind: NgTransportKind::TcpFramed, ..Default::default() },
+            handover_targets,
+        });
+        let (rrc_tx, rrc_rx) = mpsc::channel(16);
+        let (xnap_tx, xnap_rx) = mpsc::channel(16);
+        ngap.set_rrc_channel(rrc_tx);
+        ngap.set_xnap_channel(xnap_tx);
+        ngap.initialize().await.unwrap();
+        let ngap = Arc::new(RwLock::new(ngap));
+        let association = tokio::spawn(run_ng_association(Arc::clone(&ngap)));
+        Self { ngap, rrc_rx, xnap_rx, association }
+    }
+
+    async fn recv(&mut self) -> NgapRrcMessage {
+        timeout(Duration::from_secs(5), self.rrc_rx.recv()).await.unwrap().unwrap()
+    }
+
+    async fn recv_xnap(&mut self) -> NgapXnapMessage {
+        timeout(Duration::from_secs(5), self.xnap_rx.recv()).await.unwrap().unwrap()
+    }
+
+    async fn send(&self, message: RrcNgapMessage) {
+        self.ngap.write().await.handle_rrc_message(message).await.unwrap();
+    }
+
+    /// Register the UE as RRC UE 1 and set up PDU session 1
+    async fn register(&mut self, ue: &mut MockUe) {
+        self.send(RrcNgapMessage::InitialUeMessage {
+            ue_id: 1,
+            nas_pdu: Bytes::from(ue.registration_request()),
+            establishment_cause: EstablishmentCause::MoSignalling,
+            amf_selection: AmfSelectionInfo::default(),
+        }).await;
+        while ue.sessions.is_empty() {
+            let uplink_nas = |nas_pdu: Vec<u8>| RrcNgapMessage::UplinkNasTransport { ue_id: 1, nas_pdu: Bytes::from(nas_pdu) };
+            let replies = match self.recv().await {
+                NgapRrcMessage::DownlinkNasTransport { ue_id: 1, nas_pdu } => {
+                    ue.handle_downlink(&nas_pdu).unwrap().map(uplink_nas).into_iter().collect()
+                }
+                NgapRrcMessage::InitialContextSetup { ue_id: 1, nas_pdu, .. } => {
+                    let complete = ue.handle_downlink(&nas_pdu.unwrap()).unwrap().unwrap();
+                    let request = ue.pdu_session_establishment_request(1, SNssai { sst: 1, sd: None }, "internet").unwrap();
+                    vec![
+                        RrcNgapMessage::PduSessionResourceResponse {
+                            ue_id: 1,
+                            procedure: PduSessionProcedure::InitialContextSetup,
+                            succeeded: Vec::new(),
+                            failed: Vec::new(),
+                        },
+                        uplink_nas(complete),
+                        uplink_nas(request),
+                    ]
+                }
+                NgapRrcMessage::PduSessionResourceSetup { ue_id: 1, sessions, .. } => {
+                    assert!(ue.handle_downlink(sessions[0].nas_pdu.as_ref().unwrap()).unwrap().is_none());
+                    vec![RrcNgapMessage::PduSessionResourceResponse {
+                        ue_id: 1,
+                        procedure: PduSessionProcedure::Setup,
+                        succeeded: vec![sessions[0].pdu_session_id],
+                        failed: Vec::new(),
+                    }]
+                }
+                other => panic!("unexpected message {:?}", other),
+            };
+            for reply in replies {
+                self.send(reply).await;
+            }
+        }
+    }
+
+    async fn stop(self) {
+        self.ngap.write().await.shutdown().await.unwrap();
+        self.association.abort();
+    }
+}
+
+fn nr_cell_identity(gnb_id: u32) -> u64 {
+    ((gnb_id as u64) << 14) | 1
+}
+
+async fn mock_amf(mode: HandoverMode) -> (SocketAddr, JoinHandle<anyhow::Result<mock_amf::HandoverTranscript>>) {
+    let mut amf = MockAmf::bind(MockAmfConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        ..Default::default()
+    }).await.unwrap();
+    let amf_address = amf.local_addr();
+    (amf_address, tokio::spawn(async move { amf.serve_handover(mode).await }))
+}
+
+#[tokio::test]
+async fn test_n2_handover() {
+    let (amf_address, amf_task) = mock_amf(HandoverMode::N2).await;
+    let target_cell = HandoverTarget { pci: 2, gnb_id: 2, gnb_id_bits: 22, nr_cell_identity: nr_cell_identity(2), tac: 1 };
+    let mut source = Gnb::start(amf_address, 1, vec![target_cell]).await;
+    let mut ue = MockUe::new(&Subscriber::default(), PLMN).unwrap();
+    source.register(&mut ue).await;
+    let mut target = Gnb::start(amf_address, 2, Vec::new()).await;
+
+    source.send(RrcNgapMessage::HandoverRequired {
+        ue_id: 1,
+        target_pci: 2,
+        security_key: [0; 32],
+        next_hop_chaining_count: 0,
+        rrc_container: Bytes::from_static(b"preparation"),
+    }).await;
+    let NgapRrcMessage::HandoverRequest { handover_id, security_key, next_hop_chaining_count, sessions, rrc_container, .. }
+        = target.recv().await else {
+        panic!("expected Handover Request at the target");
+    };
+    // The target starts from NH, the source's K_gNB* is not used on N2
+    assert_eq!(Some(security_key), ue.next_hop());
+    assert_eq!(next_hop_chaining_count, 1);
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].qos_flows, vec![1]);
+    assert_eq!(rrc_container, Bytes::from_static(b"preparation"));
+
+    target.send(RrcNgapMessage::HandoverRequestAcknowledge {
+        handover_id,
+        ue_id: 5,
+        admitted: vec![1],
+        failed: Vec::new(),
+        rrc_container: Bytes::from_static(b"reconfiguration"),
+    }).await;
+    let NgapRrcMessage::HandoverCommand { ue_id: 1, rrc_container } = source.recv().await else {
+        panic!("expected Handover Command at the source");
+    };
+    assert_eq!(rrc_container, Bytes::from_static(b"reconfiguration"));
+
+    let drbs = vec![DrbSnStatus { drb_id: 1, ul_count: 17, dl_count: 42 }];
+    source.send(RrcNgapMessage::SnStatusTransfer { ue_id: 1, drbs: drbs.clone() }).await;
+    let NgapRrcMessage::SnStatusTransfer { ue_id: 5, drbs: received } = target.recv().await else {
+        panic!("expected the SN status at the target");
+    };
+    assert_eq!(received, drbs);
+
+    target.send(RrcNgapMessage::HandoverNotify { ue_id: 5 }).await;
+    assert!(matches!(source.recv().await, NgapRrcMessage::UeContextRelease { ue_id: 1 }));
+    source.send(RrcNgapMessage::UeContextReleaseComplete { ue_id: 1, pdu_session_ids: vec![1] }).await;
+
+    let transcript = timeout(Duration::from_secs(5), amf_task).await.unwrap().unwrap().unwrap();
+    assert_eq!(transcript.source.received[transcript.source.received.len() - 3..], [
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::HandoverPreparation),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkRanStatusTransfer),
+        (NgapPduType::SuccessfulOutcome, NgapProcedureCode::UeContextRelease),
+    ]);
+    assert_eq!(transcript.target.received, vec![
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::NgSetup),
+        (NgapPduType::SuccessfulOutcome, NgapProcedureCode::HandoverResourceAllocation),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::HandoverNotification),
+    ]);
+    let (registered, handed_over) = (&transcript.source.ues[0], &transcript.target.ues[0]);
+    assert_eq!(handed_over.supi, registered.supi);
+    assert_eq!(handed_over.ran_ue_ngap_id, 5);
+    assert_ne!(handed_over.amf_ue_ngap_id, registered.amf_ue_ngap_id);
+    assert_eq!(Some(handed_over.k_gnb), ue.next_hop());
+    assert_eq!(handed_over.sessions[0].ul_tunnel, registered.sessions[0].ul_tunnel);
+    assert_eq!(handed_over.sessions[0].dl_tunnel.transport_layer_address, IpAddr::from([127, 0, 0, 2]));
+
+    source.stop().await;
+    target.stop().await;
+}
+
+#[tokio::test]
+async fn test_xn_handover_path_switch() {
+    let (amf_address, amf_task) = mock_amf(HandoverMode::Xn).await;
+    let mut source = Gnb::start(amf_address, 1, Vec::new()).await;
+    let mut ue = MockUe::new(&Subscriber::default(), PLMN).unwrap();
+    source.register(&mut ue).await;
+    let mut target = Gnb::start(amf_address, 2, Vec::new()).await;
+
+    let k_ng_ran_star = [0x5A; 32];
+    source.send(RrcNgapMessage::HandoverRequired {
+        ue_id: 1,
+        target_pci: 2,
+        security_key: k_ng_ran_star,
+        next_hop_chaining_count: 0,
+        rrc_container: Bytes::from_static(b"preparation"),
+    }).await;
+    let NgapXnapMessage::HandoverRequest { ue_id: 1, target_pci: 2, guami, context } = source.recv_xnap().await else {
+        panic!("expected an Xn Handover Request from the source");
+    };
+    target.ngap.write().await
+        .handle_xnap_message(XnapNgapMessage::HandoverRequest { handover_id: 9, guami, context }).await.unwrap();
+    let NgapRrcMessage::HandoverRequest { handover_id, security_key, .. } = target.recv().await else {
+        panic!("expected Handover Request at the target");
+    };
+    assert_eq!(security_key, k_ng_ran_star);
+
+    target.send(RrcNgapMessage::HandoverRequestAcknowledge {
+        handover_id,
+        ue_id: 5,
+        admitted: vec![1],
+        failed: Vec::new(),
+        rrc_container: Bytes::from_static(b"reconfiguration"),
+    }).await;
+    let NgapXnapMessage::HandoverRequestAcknowledge { handover_id: 9, admitted, rrc_container, .. } = target.recv_xnap().await else {
+        panic!("expected an Xn Handover Request Acknowledge from the target");
+    };
+    source.ngap.write().await
+        .handle_xnap_message(XnapNgapMessage::HandoverRequestAcknowledge { ue_id: 1, admitted, rrc_container }).await.unwrap();
+    assert!(matches!(source.recv().await, NgapRrcMessage::HandoverCommand { ue_id: 1, .. }));
+
+    // The UE arrives, the target switches the path and lets the source go
+    target.send(RrcNgapMessage::HandoverNotify { ue_id: 5 }).await;
+    assert_eq!(target.recv_xnap().await, NgapXnapMessage::UeContextRelease { ue_id: 5 });
+
+    let transcript = timeout(Duration::from_secs(5), amf_task).await.unwrap().unwrap().unwrap();
+    assert_eq!(transcript.target.received, vec![
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::NgSetup),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::PathSwitchRequest),
+    ]);
+    let (registered, handed_over) = (&transcript.source.ues[0], &transcript.target.ues[0]);
+    assert_eq!(handed_over.amf_ue_ngap_id, registered.amf_ue_ngap_id);
+    assert_eq!(handed_over.ran_ue_ngap_id, 5);
+    assert_eq!(Some(handed_over.k_gnb), ue.next_hop());
+    assert_eq!(handed_over.sessions[0].dl_tunnel.transport_layer_address, IpAddr::from([127, 0, 0, 2]));
+
+    source.stop().await;
+    target.stop().await;
+}
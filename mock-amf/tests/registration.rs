@@ -0,0 +1,130 @@
+//! End-to-end NGAP run: NgapLayer against the scripted AMF over the TCP framing,
+//! with the test standing in for RRC and the UE
+
+use bytes::Bytes;
+use common::types::SNssai;
+use layers::ngap::association::run_ng_association;
+use layers::ngap::pdu::{BroadcastPlmnItem, NgapPduType, PagingDrx, SupportedTaItem};
+use layers::ngap::transport::{NgTransportConfig, NgTransportKind};
+use layers::ngap::{NgapConfig, NgapLayer, NgapProcedureCode};
+use layers::rrc::{AmfSelectionInfo, EstablishmentCause, NgapRrcMessage, PduSessionProcedure, RrcNgapMessage};
+use layers::ProtocolLayer;
+use mock_amf::{MockAmf, MockAmfConfig, MockUe, Subscriber};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::timeout;
+
+const PLMN: [u8; 3] = [0x99, 0xF9, 0x07];
+
+#[tokio::test]
+async fn test_registration_and_pdu_session() {
+    let mut amf = MockAmf::bind(MockAmfConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        transport: NgTransportKind::TcpFramed,
+        ..Default::default()
+    }).await.unwrap();
+    let amf_address = amf.local_addr();
+    let amf_task = tokio::spawn(async move { amf.serve_gnb(Some(1)).await });
+
+    let slice = SNssai { sst: 1, sd: None };
+    let mut ngap = NgapLayer::new(NgapConfig {
+        amfs: vec![amf_address.into()],
+        local_address: "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+        gnb_id: 1,
+        gnb_id_bits: 22,
+        plmn_id: PLMN,
+        ran_node_name: "Albor-gNodeB".to_string(),
+        supported_tas: vec![SupportedTaItem {
+            tac: 1,
+            broadcast_plmns: vec![BroadcastPlmnItem { plmn_id: PLMN, slices: vec![slice.clone()] }],
+        }],
+        default_paging_drx: PagingDrx::V64,
+        nr_cell_identity: 0x000400001,
+        tac: 1,
+        gtpu_address: IpAddr::from([127, 0, 0, 1]),
+        transport: NgTransportConfig { kind: NgTransportKind::TcpFramed, ..Default::default() },
+        handover_targets: Vec::new(),
+    });
+    let (rrc_tx, mut rrc_rx) = mpsc::channel(16);
+    ngap.set_rrc_channel(rrc_tx);
+    ngap.initialize().await.unwrap();
+    let ngap = Arc::new(RwLock::new(ngap));
+    let association = tokio::spawn(run_ng_association(Arc::clone(&ngap)));
+
+    let mut ue = MockUe::new(&Subscriber::default(), PLMN).unwrap();
+    ngap.write().await.handle_rrc_message(RrcNgapMessage::InitialUeMessage {
+        ue_id: 1,
+        nas_pdu: Bytes::from(ue.registration_request()),
+        establishment_cause: EstablishmentCause::MoSignalling,
+        amf_selection: AmfSelectionInfo::default(),
+    }).await.unwrap();
+
+    // Play RRC and the UE until the PDU session is up
+    while ue.sessions.is_empty() {
+        let message = timeout(Duration::from_secs(5), rrc_rx.recv()).await.unwrap().unwrap();
+        let uplink_nas = |nas_pdu: Vec<u8>| RrcNgapMessage::UplinkNasTransport { ue_id: 1, nas_pdu: Bytes::from(nas_pdu) };
+        let replies = match message {
+            NgapRrcMessage::DownlinkNasTransport { ue_id: 1, nas_pdu } => {
+                ue.handle_downlink(&nas_pdu).unwrap().map(uplink_nas).into_iter().collect()
+            }
+            NgapRrcMessage::InitialContextSetup { ue_id: 1, security_key, nas_pdu, sessions, .. } => {
+                assert_eq!(Some(security_key), ue.k_gnb);
+                assert!(sessions.is_empty());
+                let complete = ue.handle_downlink(&nas_pdu.unwrap()).unwrap().unwrap();
+                let request = ue.pdu_session_establishment_request(1, slice.clone(), "internet").unwrap();
+                vec![
+                    RrcNgapMessage::PduSessionResourceResponse {
+                        ue_id: 1,
+                        procedure: PduSessionProcedure::InitialContextSetup,
+                        succeeded: Vec::new(),
+                        failed: Vec::new(),
+                    },
+                    uplink_nas(complete),
+                    uplink_nas(request),
+                ]
+            }
+            NgapRrcMessage::PduSessionResourceSetup { ue_id: 1, sessions, .. } => {
+                assert_eq!(sessions.len(), 1);
+                assert_eq!(sessions[0].qos_flows, vec![1]);
+                assert!(ue.handle_downlink(sessions[0].nas_pdu.as_ref().unwrap()).unwrap().is_none());
+                vec![RrcNgapMessage::PduSessionResourceResponse {
+                    ue_id: 1,
+                    procedure: PduSessionProcedure::Setup,
+                    succeeded: vec![sessions[0].pdu_session_id],
+                    failed: Vec::new(),
+                }]
+            }
+            other => panic!("unexpected message {:?}", other),
+        };
+        for reply in replies {
+            ngap.write().await.handle_rrc_message(reply).await.unwrap();
+        }
+    }
+    assert_eq!(ue.sessions[0].address, Ipv4Addr::new(10, 45, 0, 2));
+    assert_eq!(ue.allowed_nssai, vec![slice.clone()]);
+
+    let transcript = timeout(Duration::from_secs(5), amf_task).await.unwrap().unwrap().unwrap();
+    assert_eq!(transcript.ran_node_name.as_deref(), Some("Albor-gNodeB"));
+    assert_eq!(transcript.received, vec![
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::NgSetup),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::InitialUeMessage),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkNasTransport),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkNasTransport),
+        (NgapPduType::SuccessfulOutcome, NgapProcedureCode::InitialContextSetup),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkNasTransport),
+        (NgapPduType::InitiatingMessage, NgapProcedureCode::UplinkNasTransport),
+        (NgapPduType::SuccessfulOutcome, NgapProcedureCode::PduSessionResourceSetup),
+    ]);
+    let registered = &transcript.ues[0];
+    assert_eq!(registered.supi, "999700000000001");
+    assert_eq!(registered.ran_ue_ngap_id, 1);
+    assert!(registered.registration_complete);
+    assert_eq!(Some(registered.k_gnb), ue.k_gnb);
+    assert_eq!(registered.sessions[0].s_nssai, slice);
+    assert_eq!(registered.sessions[0].dl_tunnel.transport_layer_address, IpAddr::from([127, 0, 0, 1]));
+
+    ngap.write().await.shutdown().await.unwrap();
+    association.abort();
+}